@@ -0,0 +1 @@
+ALTER TABLE job_configs ADD COLUMN env_vars JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+ALTER TABLE job_configs ADD COLUMN failure_policy JSONB;
+ALTER TABLE job_statuses ADD COLUMN poison_pill JSONB;
@@ -0,0 +1,16 @@
+CREATE TABLE masking_policies (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+
+    name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    -- if set, the policy only applies to the sink with this name
+    sink_name TEXT,
+    -- json-serialized MaskingAction
+    action JSONB NOT NULL,
+
+    UNIQUE (organization_id, name)
+);
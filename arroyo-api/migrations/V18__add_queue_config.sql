@@ -0,0 +1 @@
+ALTER TABLE job_configs ADD COLUMN queue_config JSONB;
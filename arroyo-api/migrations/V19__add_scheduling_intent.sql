@@ -0,0 +1 @@
+ALTER TABLE job_statuses ADD COLUMN scheduling_intent JSONB;
@@ -0,0 +1,2 @@
+ALTER TABLE job_configs ADD COLUMN dependencies JSONB;
+ALTER TABLE job_statuses ADD COLUMN waiting_for TEXT;
@@ -0,0 +1,16 @@
+CREATE TABLE connection_profiles (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+
+    connection_id BIGINT NOT NULL REFERENCES connections(id) ON DELETE CASCADE,
+    environment TEXT NOT NULL,
+    -- replaces the connection's config for pipelines in this environment
+    config JSONB NOT NULL,
+
+    UNIQUE (connection_id, environment)
+);
+
+ALTER TABLE pipelines ADD COLUMN environment TEXT;
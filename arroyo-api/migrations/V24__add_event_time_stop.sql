@@ -0,0 +1,2 @@
+ALTER TYPE stop_mode ADD VALUE 'drain';
+ALTER TABLE job_configs ADD COLUMN stop_at_event_time_micros BIGINT;
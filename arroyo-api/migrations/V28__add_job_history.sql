@@ -0,0 +1,29 @@
+-- a summary of each finished run of a job, which is kept (for the retention period) after the job
+-- or its pipeline is deleted
+CREATE TABLE job_history (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    job_id VARCHAR(8) NOT NULL,
+    pipeline_id VARCHAR NOT NULL,
+    pipeline_name TEXT NOT NULL,
+    run_id BIGINT NOT NULL,
+    state TEXT NOT NULL,
+    start_time TIMESTAMPTZ,
+    finish_time TIMESTAMPTZ NOT NULL,
+    restarts INT NOT NULL DEFAULT 0,
+    failure_message TEXT,
+    -- the final progress of each operator
+    metrics JSONB,
+    -- the job's events during the run
+    timeline JSONB NOT NULL DEFAULT '[]',
+    -- the range of the job's log messages written during the run
+    first_log_message_id BIGINT,
+    last_log_message_id BIGINT,
+    UNIQUE (job_id, run_id, finish_time)
+);
+
+CREATE INDEX job_history_finish_time_idx ON job_history (organization_id, finish_time DESC);
+-- the index on job_log_messages (created_at) that pruning uses is built concurrently by the
+-- controller after migrating, as job_log_messages may already be large (see
+-- arroyo-controller/src/migrations.rs)
@@ -82,24 +82,83 @@ DELETE FROM connection_tables
 WHERE organization_id = :organization_id AND id = :id;
 
 
+----------- connection profiles ---------
+
+--: DbConnectionProfile ()
+
+--! create_connection_profile
+INSERT INTO connection_profiles (pub_id, organization_id, created_by, connection_id, environment, config)
+VALUES (:pub_id, :organization_id, :created_by, :connection_id, :environment, :config)
+RETURNING created_at;
+
+--! get_connection_profiles : DbConnectionProfile
+SELECT connection_profiles.pub_id, connections.name as connection_name, environment, connection_profiles.config, connection_profiles.created_at
+FROM connection_profiles
+    INNER JOIN connections ON connections.id = connection_profiles.connection_id
+WHERE connection_profiles.organization_id = :organization_id
+ORDER BY connections.name, environment;
+
+--! delete_connection_profile
+DELETE FROM connection_profiles
+WHERE pub_id = :pub_id AND organization_id = :organization_id;
+
+
+----------- masking policies ------------
+
+--: DbMaskingPolicy (sink_name?)
+
+--! create_masking_policy(sink_name?)
+INSERT INTO masking_policies (pub_id, organization_id, created_by, name, column_name, sink_name, action)
+VALUES (:pub_id, :organization_id, :created_by, :name, :column_name, :sink_name, :action)
+RETURNING created_at;
+
+--! get_masking_policies : DbMaskingPolicy
+SELECT pub_id, name, column_name, sink_name, action, created_at
+FROM masking_policies
+WHERE organization_id = :organization_id
+ORDER BY created_at DESC;
+
+--! delete_masking_policy
+DELETE FROM masking_policies
+WHERE pub_id = :pub_id AND organization_id = :organization_id;
+
+
+----------- job history ------------
+
+--! get_job_history(pipeline_id?, state?, search?, before?) : DbJobRun(start_time?, failure_message?, metrics?, first_log_message_id?, last_log_message_id?)
+SELECT pub_id, job_id, pipeline_id, pipeline_name, run_id, state, start_time, finish_time, restarts,
+    failure_message, metrics, timeline, first_log_message_id, last_log_message_id
+FROM job_history
+WHERE organization_id = :organization_id
+    AND (:pipeline_id::VARCHAR IS NULL OR pipeline_id = :pipeline_id)
+    AND (:state::TEXT IS NULL OR state = :state)
+    AND (:search::TEXT IS NULL
+        OR job_id = :search
+        OR pipeline_name ILIKE '%' || :search || '%'
+        OR failure_message ILIKE '%' || :search || '%')
+    AND (:before::TIMESTAMPTZ IS NULL OR finish_time < :before)
+ORDER BY finish_time DESC
+LIMIT :limit;
+
+
 ----------- pipelines -------------------
 
---: DbPipelineRest ()
+--: DbPipelineRest (environment?)
 
 --! get_pipelines_rest : DbPipelineRest
-SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at
+SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, env_vars, pipelines.environment, pipelines.created_at
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
 WHERE pipelines.organization_id = :organization_id AND pipelines.pub_id IS NOT NULL
 ORDER BY pipelines.created_at DESC;
 
---! create_pipeline(udfs?, textual_repr?)
-INSERT INTO pipelines (pub_id, organization_id, created_by, name, type, textual_repr, udfs, program)
-VALUES (:pub_id, :organization_id, :created_by, :name, :type, :textual_repr, :udfs, :program)
+--! create_pipeline(udfs?, textual_repr?, environment?)
+INSERT INTO pipelines (pub_id, organization_id, created_by, name, type, textual_repr, udfs, program, environment)
+VALUES (:pub_id, :organization_id, :created_by, :name, :type, :textual_repr, :udfs, :program, :environment)
 RETURNING id;
 
 --! get_pipeline_rest: DbPipelineRest
-SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at
+SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, env_vars, pipelines.environment, pipelines.created_at
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
 WHERE pipelines.pub_id = :pub_id AND pipelines.organization_id = :organization_id;
@@ -123,7 +182,7 @@ WHERE pub_id = :pub_id AND organization_id = :organization_id;
 
 ----------- jobs -----------------------
 
---! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?)
+--! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?, env_vars?, restore_overrides?, slo?, failure_policy?, queue_config?, stop_at_event_time_micros?, recovery_throttle?, state_storage?)
 UPDATE job_configs
 SET
    updated_at = :updated_at,
@@ -131,35 +190,53 @@ SET
 
    stop = COALESCE(:stop, stop),
    checkpoint_interval_micros = COALESCE(:checkpoint_interval_micros, checkpoint_interval_micros),
-   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides)
+   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides),
+   env_vars = COALESCE(:env_vars, env_vars),
+   restore_overrides = COALESCE(:restore_overrides, restore_overrides),
+   slo = COALESCE(:slo, slo),
+   failure_policy = COALESCE(:failure_policy, failure_policy),
+   queue_config = COALESCE(:queue_config, queue_config),
+   stop_at_event_time_micros = COALESCE(:stop_at_event_time_micros, stop_at_event_time_micros),
+   recovery_throttle = COALESCE(:recovery_throttle, recovery_throttle),
+   state_storage = COALESCE(:state_storage, state_storage)
 WHERE id = :job_id AND organization_id = :organization_id;
 
---! create_job(ttl_micros?)
+--! create_job(ttl_micros?, dependencies?, stop_at_event_time_micros?)
 INSERT INTO job_configs
-(pub_id, id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros)
-VALUES (:pub_id, :id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros);
+(pub_id, id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros, env_vars, dependencies, stop_at_event_time_micros)
+VALUES (:pub_id, :id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros, :env_vars, :dependencies, :stop_at_event_time_micros);
+
+--! set_job_fork
+UPDATE job_configs
+SET fork_from = :fork_from
+WHERE id = :job_id AND organization_id = :organization_id;
+
+--! set_job_restore_overrides
+UPDATE job_configs
+SET restore_overrides = :restore_overrides
+WHERE id = :job_id AND organization_id = :organization_id;
 
 --! create_job_status
 INSERT INTO job_statuses (pub_id, id, organization_id) VALUES (:pub_id, :id, :organization_id);
 
---! get_jobs: (start_time?, finish_time?, state?, tasks?, textual_repr?, failure_message?, run_id?, udfs)
-SELECT job_configs.id as id, pipeline_name, stop, textual_repr, start_time, finish_time, state, tasks, pipeline_id, failure_message, run_id, udfs
+--! get_jobs: (start_time?, finish_time?, state?, tasks?, textual_repr?, failure_message?, poison_pill?, run_id?, udfs, slo_violations?, waiting_for?, state_compaction?)
+SELECT job_configs.id as id, pipeline_name, stop, textual_repr, start_time, finish_time, state, tasks, pipeline_id, failure_message, poison_pill, run_id, udfs, slo_violations, waiting_for, state_compaction
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipeline_id = pipelines.id
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY COALESCE(job_configs.updated_at, job_configs.created_at) DESC;
 
---! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, job_configs.pub_id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, poison_pill?, run_id?, queue_config?, slo_violations?, waiting_for?, state_compaction?)
+SELECT job_configs.id, job_configs.pub_id, stop, start_time, finish_time, state, tasks, failure_message, poison_pill, run_id, checkpoint_interval_micros, queue_config, slo_violations, waiting_for, state_compaction, job_configs.created_at
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND pipelines.pub_id = :pub_id AND ttl_micros IS NULL
 ORDER BY job_configs.created_at DESC;
 
---! get_job_details: (start_time?, finish_time?, state?, tasks?, textual_repr?, udfs, failure_message?, run_id?)
-SELECT pipeline_name, stop, parallelism_overrides, state, start_time, finish_time, tasks, textual_repr, program, pipeline_id, udfs, failure_message, run_id
+--! get_job_details: (start_time?, finish_time?, state?, tasks?, textual_repr?, udfs, failure_message?, poison_pill?, run_id?, slo_violations?, waiting_for?, state_compaction?)
+SELECT pipeline_name, stop, parallelism_overrides, state, start_time, finish_time, tasks, textual_repr, program, pipeline_id, udfs, failure_message, poison_pill, run_id, slo_violations, waiting_for, state_compaction
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipeline_id = pipelines.id
@@ -173,6 +250,46 @@ WHERE job_id = :job_id
     AND state != 'failed'
 ORDER BY epoch;
 
+--! get_job_health_config: (state?, run_id?, start_time?, slo?)
+SELECT state, run_id, start_time, checkpoint_interval_micros, slo
+FROM job_configs
+         LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
+WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_id;
+
+--! get_last_checkpoint_time: (finish_time?)
+SELECT MAX(finish_time) as finish_time FROM checkpoints
+WHERE job_id = :job_id
+    AND organization_id = :organization_id
+    AND state != 'failed';
+
+--! get_last_checkpoint_operators: (operators?)
+SELECT operators FROM checkpoints
+WHERE job_id = :job_id
+    AND organization_id = :organization_id
+    AND finish_time IS NOT NULL
+    AND state != 'failed'
+ORDER BY epoch DESC
+LIMIT 1;
+
+--! get_forkable_checkpoint(epoch?)
+SELECT epoch FROM checkpoints
+WHERE job_id = :job_id
+    AND organization_id = :organization_id
+    AND state = 'ready'
+    AND (:epoch::INTEGER IS NULL OR epoch = :epoch)
+ORDER BY epoch DESC
+LIMIT 1;
+
+--! get_checkpoint_before(epoch?)
+SELECT epoch FROM checkpoints
+WHERE job_id = :job_id
+    AND organization_id = :organization_id
+    AND state = 'ready'
+    AND start_time <= :before
+    AND (:epoch::INTEGER IS NULL OR epoch = :epoch)
+ORDER BY epoch DESC
+LIMIT 1;
+
 --! get_checkpoint_details: (finish_time?, operators?)
 SELECT epoch, state_backend, start_time, finish_time, operators FROM checkpoints
 WHERE job_id = :job_id
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+
+use arroyo_connectors::connector_for_type;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::GenericClient;
+use http::StatusCode;
+use tonic::Status;
+
+use arroyo_rpc::grpc::api::Connection;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+
+use crate::queries::api_queries::{self, DbConnectionProfile};
+use crate::rest::AppState;
+use crate::rest_types::{ConnectionProfile, ConnectionProfileCollection, ConnectionProfilePost};
+use crate::rest_utils::{authenticate, client, log_and_map_rest, ApiError, BearerAuth, ErrorResp};
+use crate::{handle_db_error, log_and_map, to_micros, AuthData};
+
+impl From<DbConnectionProfile> for ConnectionProfile {
+    fn from(value: DbConnectionProfile) -> Self {
+        ConnectionProfile {
+            id: value.pub_id,
+            connection: value.connection_name,
+            environment: value.environment,
+            config: serde_json::to_string(&value.config).unwrap(),
+            created_at: to_micros(value.created_at),
+        }
+    }
+}
+
+/// The configs that connections use in the environment a pipeline is created for
+pub(crate) struct Profiles {
+    environment: Option<String>,
+    configs: HashMap<String, String>,
+    // connections that have a profile for some environment
+    profiled: HashSet<String>,
+}
+
+impl Profiles {
+    pub(crate) async fn for_environment<C: GenericClient>(
+        auth: &AuthData,
+        environment: Option<&str>,
+        client: &C,
+    ) -> Result<Self, Status> {
+        let mut configs = HashMap::new();
+        let mut profiled = HashSet::new();
+
+        if let Some(environment) = environment {
+            for profile in api_queries::get_connection_profiles()
+                .bind(client, &auth.organization_id)
+                .all()
+                .await
+                .map_err(log_and_map)?
+            {
+                if profile.environment == environment {
+                    configs.insert(
+                        profile.connection_name.clone(),
+                        serde_json::to_string(&profile.config).unwrap(),
+                    );
+                }
+                profiled.insert(profile.connection_name);
+            }
+        }
+
+        Ok(Self {
+            environment: environment.map(|e| e.to_string()),
+            configs,
+            profiled,
+        })
+    }
+
+    /// The connection's config in this environment; connections without any profiles use their
+    /// own config in every environment
+    pub(crate) fn config(&self, connection: &Connection) -> String {
+        self.configs
+            .get(&connection.name)
+            .cloned()
+            .unwrap_or_else(|| connection.config.clone())
+    }
+
+    /// Fails if the connection has profiles, but none for this environment, so that a pipeline
+    /// can't silently fall back to another environment's brokers or credentials
+    pub(crate) fn check(&self, connection: &Connection) -> Result<(), Status> {
+        match &self.environment {
+            Some(environment)
+                if self.profiled.contains(&connection.name)
+                    && !self.configs.contains_key(&connection.name) =>
+            {
+                Err(Status::failed_precondition(format!(
+                    "Connection '{}' has no profile for environment '{}'",
+                    connection.name, environment
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn bad_request(message: impl Into<String>) -> ErrorResp {
+    ErrorResp {
+        status_code: StatusCode::BAD_REQUEST,
+        message: message.into(),
+    }
+}
+
+/// Create a connection profile
+///
+/// Pipelines created for the profile's environment use its config for the connection in place of
+/// the connection's own config. Once a connection has a profile, pipelines can only use it in
+/// environments that it has a profile for.
+#[utoipa::path(
+    post,
+    path = "/v1/connection_profiles",
+    tag = "connection_profiles",
+    request_body = ConnectionProfilePost,
+    responses(
+        (status = 200, description = "Created connection profile", body = ConnectionProfile),
+    ),
+)]
+pub async fn post_connection_profile(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(profile_post), _): WithRejection<Json<ConnectionProfilePost>, ApiError>,
+) -> Result<Json<ConnectionProfile>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    if profile_post.environment.is_empty() {
+        return Err(bad_request("Environment must not be empty"));
+    }
+
+    let connection = api_queries::get_connection()
+        .bind(
+            &client,
+            &auth_data.organization_id,
+            &profile_post.connection,
+        )
+        .opt()
+        .await
+        .map_err(log_and_map_rest)?
+        .ok_or_else(|| ErrorResp {
+            status_code: StatusCode::NOT_FOUND,
+            message: format!("No connection with name {}", profile_post.connection),
+        })?;
+
+    let connector = connector_for_type(&connection.r#type)
+        .ok_or_else(|| bad_request(format!("Unknown connection type '{}'", connection.r#type)))?;
+
+    connector
+        .validate_config(&profile_post.config)
+        .map_err(|e| bad_request(format!("Failed to parse config: {:?}", e)))?;
+
+    let config: serde_json::Value = serde_json::from_str(&profile_post.config).unwrap();
+
+    let pub_id = generate_id(IdTypes::ConnectionProfile);
+    let created_at = api_queries::create_connection_profile()
+        .bind(
+            &client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &connection.id,
+            &profile_post.environment,
+            &config,
+        )
+        .one()
+        .await
+        .map_err(|e| handle_db_error("connection profile", e))?;
+
+    Ok(Json(ConnectionProfile {
+        id: pub_id,
+        connection: connection.name,
+        environment: profile_post.environment,
+        config: serde_json::to_string(&config).unwrap(),
+        created_at: to_micros(created_at),
+    }))
+}
+
+/// List all connection profiles
+#[utoipa::path(
+    get,
+    path = "/v1/connection_profiles",
+    tag = "connection_profiles",
+    responses(
+        (status = 200, description = "Got connection profiles collection", body = ConnectionProfileCollection),
+    ),
+)]
+pub async fn get_connection_profiles(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<ConnectionProfileCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let profiles = api_queries::get_connection_profiles()
+        .bind(&client, &auth_data.organization_id)
+        .all()
+        .await
+        .map_err(log_and_map_rest)?
+        .into_iter()
+        .map(|p| p.into())
+        .collect();
+
+    Ok(Json(ConnectionProfileCollection {
+        data: profiles,
+        has_more: false,
+    }))
+}
+
+/// Delete a connection profile
+///
+/// Existing pipelines keep the config they were created with.
+#[utoipa::path(
+    delete,
+    path = "/v1/connection_profiles/{id}",
+    tag = "connection_profiles",
+    params(
+        ("id" = String, Path, description = "Connection profile id")
+    ),
+    responses(
+        (status = 200, description = "Deleted connection profile"),
+    ),
+)]
+pub async fn delete_connection_profile(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(profile_pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let count = api_queries::delete_connection_profile()
+        .bind(&client, &profile_pub_id, &auth_data.organization_id)
+        .await
+        .map_err(log_and_map_rest)?;
+
+    if count != 1 {
+        return Err(ErrorResp {
+            status_code: StatusCode::NOT_FOUND,
+            message: "Connection profile not found".to_string(),
+        });
+    }
+
+    Ok(())
+}
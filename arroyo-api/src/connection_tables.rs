@@ -1,19 +1,23 @@
 use anyhow::anyhow;
 use arrow_schema::DataType;
-use arroyo_connectors::{connector_for_type, ErasedConnector};
+use arroyo_connectors::{check_format, connector_for_type, ErasedConnector};
 use arroyo_rpc::grpc::api::{
     connection_schema::Definition, ConfluentSchemaReq, ConfluentSchemaResp, Connection,
-    ConnectionSchema, ConnectionTable, CreateConnectionTableReq, DeleteConnectionTableReq,
+    ConnectionSchema, ConnectionTable, CreateConnectionTableReq, DeleteConnectionTableReq, Format,
     TableType, TestSchemaReq, TestSourceMessage,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_server_common::http::{HttpClient, HttpError};
 use arroyo_sql::{
+    avro,
     json_schema::{self, convert_json_schema},
+    protobuf,
     types::{StructField, TypeDef},
 };
 use cornucopia_async::GenericClient;
 use deadpool_postgres::Pool;
 use http::StatusCode;
+use once_cell::sync::Lazy;
 use tokio::sync::mpsc::{channel, Receiver};
 use tonic::Status;
 use tracing::warn;
@@ -24,6 +28,9 @@ use crate::{
     required_field, AuthData,
 };
 
+// shared so that lookups reuse connections and back off together while the registry is failing
+static SCHEMA_REGISTRY_CLIENT: Lazy<HttpClient> = Lazy::new(|| HttpClient::new("schema_registry"));
+
 async fn get_and_validate_connector<E: GenericClient>(
     req: &CreateConnectionTableReq,
     auth: &AuthData,
@@ -74,6 +81,8 @@ async fn get_and_validate_connector<E: GenericClient>(
         .map_err(|e| Status::invalid_argument(&format!("Failed to parse config: {:?}", e)))?;
 
     let schema = if let Some(schema) = &req.schema {
+        check_format(connector.name(), schema.format())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
         Some(expand_schema(&req.name, schema)?)
     } else {
         None
@@ -230,6 +239,24 @@ pub(crate) async fn get<C: GenericClient>(
         .collect())
 }
 
+// compiles the protobuf schema into a descriptor set, and converts the message that records are
+// encoded as into fields
+fn expand_protobuf_schema(
+    name: &str,
+    schema: &ConnectionSchema,
+    proto: &str,
+) -> Result<(String, Vec<StructField>), String> {
+    let message = schema
+        .format_options
+        .as_ref()
+        .and_then(|o| o.protobuf_message.as_ref())
+        .ok_or_else(|| "the message that records are encoded as must be set".to_string())?;
+
+    let descriptor_set = protobuf::encode_descriptor_set(&protobuf::descriptor_pool(proto)?);
+    let fields = protobuf::convert_protobuf_schema(name, &descriptor_set, message)?;
+    Ok((descriptor_set, fields))
+}
+
 // attempts to fill in the SQL schema from a schema object that may just have a json-schema or
 // other source schema. schemas stored in the database should always be expanded first.
 pub(crate) fn expand_schema(
@@ -239,23 +266,32 @@ pub(crate) fn expand_schema(
     let mut schema = schema.clone();
 
     if let Some(d) = &schema.definition {
+        let mut compiled = None;
         let fields = match d {
             Definition::JsonSchema(json) => json_schema::convert_json_schema(name, &json)
                 .map_err(|e| Status::invalid_argument(format!("Invalid json-schema: {}", e)))?,
-            Definition::ProtobufSchema(_) => {
-                return Err(Status::failed_precondition(
-                    "Protobuf schemas are not yet supported",
-                ))
-            }
-            Definition::AvroSchema(_) => {
-                return Err(Status::failed_precondition(
-                    "Avro schemas are not yet supported",
-                ))
+            Definition::ProtobufSchema(proto) => {
+                let (descriptor_set, fields) = expand_protobuf_schema(name, &schema, proto)
+                    .map_err(|e| {
+                        Status::invalid_argument(format!("Invalid protobuf schema: {}", e))
+                    })?;
+                // .proto sources are stored compiled, which is how the workers read them
+                compiled = Some(Definition::ProtobufSchema(descriptor_set));
+                fields
             }
+            Definition::AvroSchema(avro) => avro::convert_avro_schema(name, &avro)
+                .map_err(|e| Status::invalid_argument(format!("Invalid avro schema: {}", e)))?,
             Definition::RawSchema(_) => vec![StructField::new(
                 "value".to_string(),
                 None,
-                TypeDef::DataType(DataType::Utf8, false),
+                TypeDef::DataType(
+                    if schema.format() == Format::RawBytesFormat {
+                        DataType::Binary
+                    } else {
+                        DataType::Utf8
+                    },
+                    false,
+                ),
             )],
         };
 
@@ -263,18 +299,19 @@ pub(crate) fn expand_schema(
 
         schema.fields = fields
             .map_err(|e| Status::failed_precondition(format!("Failed to convert schema: {}", e)))?;
+        if compiled.is_some() {
+            schema.definition = compiled;
+        }
     }
 
     Ok(schema)
 }
 
 pub(crate) async fn test_schema(req: TestSchemaReq) -> Result<Vec<String>, Status> {
-    let Some(schema_def) = req
-        .schema
-        .ok_or_else(|| required_field("schema"))?
-        .definition else {
-            return Ok(vec![]);
-        };
+    let schema = req.schema.ok_or_else(|| required_field("schema"))?;
+    let Some(schema_def) = schema.definition.clone() else {
+        return Ok(vec![]);
+    };
 
     match schema_def {
         Definition::JsonSchema(schema) => {
@@ -284,6 +321,20 @@ pub(crate) async fn test_schema(req: TestSchemaReq) -> Result<Vec<String>, Statu
                 Ok(vec![])
             }
         }
+        Definition::AvroSchema(schema) => {
+            if let Err(e) = avro::convert_avro_schema(&"test", &schema) {
+                Ok(vec![e])
+            } else {
+                Ok(vec![])
+            }
+        }
+        Definition::ProtobufSchema(proto) => {
+            if let Err(e) = expand_protobuf_schema("test", &schema, &proto) {
+                Ok(vec![e])
+            } else {
+                Ok(vec![])
+            }
+        }
         _ => {
             // TODO: add testing for other schema types
             Ok(vec![])
@@ -299,28 +350,38 @@ pub(crate) async fn get_confluent_schema(
         "{}/subjects/{}-value/versions/latest",
         req.endpoint, req.topic
     );
-    let resp = reqwest::get(url).await.map_err(|e| {
-        warn!("Got error response from schema registry: {:?}", e);
-        match e.status() {
-            Some(StatusCode::NOT_FOUND) => Status::failed_precondition(format!(
-                "Could not find value schema for topic '{}'",
-                req.topic
+    let resp = SCHEMA_REGISTRY_CLIENT
+        .send(SCHEMA_REGISTRY_CLIENT.get(url))
+        .await
+        .map_err(|e| match e {
+            HttpError::CircuitOpen => Status::unavailable(format!(
+                "Schema registry at {} is failing; try again later",
+                req.endpoint
             )),
-            Some(code) => {
-                Status::failed_precondition(format!("Schema registry returned error: {}", code))
-            }
-            None => {
-                warn!(
-                    "Unknown error connecting to schema registry {}: {:?}",
-                    req.endpoint, e
-                );
-                Status::failed_precondition(format!(
-                    "Could not connect to Schema Registry at {}: unknown error",
-                    req.endpoint
-                ))
+            HttpError::Request(e) => {
+                warn!("Got error response from schema registry: {:?}", e);
+                match e.status() {
+                    Some(StatusCode::NOT_FOUND) => Status::failed_precondition(format!(
+                        "Could not find value schema for topic '{}'",
+                        req.topic
+                    )),
+                    Some(code) => Status::failed_precondition(format!(
+                        "Schema registry returned error: {}",
+                        code
+                    )),
+                    None => {
+                        warn!(
+                            "Unknown error connecting to schema registry {}: {:?}",
+                            req.endpoint, e
+                        );
+                        Status::failed_precondition(format!(
+                            "Could not connect to Schema Registry at {}: unknown error",
+                            req.endpoint
+                        ))
+                    }
+                }
             }
-        }
-    })?;
+        })?;
 
     if !resp.status().is_success() {
         return Err(Status::failed_precondition(format!(
@@ -0,0 +1,116 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use http::StatusCode;
+use time::OffsetDateTime;
+
+use crate::queries::api_queries::{self, DbJobRun};
+use crate::rest::AppState;
+use crate::rest_types::{
+    JobHistoryQueryParams, JobRun, JobRunCollection, JobRunEvent, OperatorRunMetrics,
+};
+use crate::rest_utils::{authenticate, client, log_and_map_rest, BearerAuth, ErrorResp};
+use crate::to_micros;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 1000;
+
+impl From<DbJobRun> for JobRun {
+    fn from(value: DbJobRun) -> Self {
+        let metrics: Vec<arroyo_types::OperatorRunMetrics> = value
+            .metrics
+            .and_then(|m| serde_json::from_value(m).ok())
+            .unwrap_or_default();
+
+        JobRun {
+            id: value.pub_id,
+            job_id: value.job_id,
+            pipeline_id: value.pipeline_id,
+            pipeline_name: value.pipeline_name,
+            run_id: value.run_id as u64,
+            state: value.state,
+            start_time: value.start_time.map(to_micros),
+            finish_time: to_micros(value.finish_time),
+            restarts: value.restarts as u32,
+            failure_message: value.failure_message,
+            metrics: metrics
+                .into_iter()
+                .map(|m| OperatorRunMetrics {
+                    operator_id: m.operator_id,
+                    records_in: m.records_in,
+                    records_out: m.records_out,
+                    state_bytes: m.state_bytes,
+                    watermark_micros: m.watermark_micros,
+                })
+                .collect(),
+            timeline: serde_json::from_value::<Vec<JobRunEvent>>(value.timeline)
+                .unwrap_or_default(),
+            first_log_message_id: value.first_log_message_id,
+            last_log_message_id: value.last_log_message_id,
+        }
+    }
+}
+
+/// List finished runs of jobs
+///
+/// Runs are archived when a job stops, finishes or fails, and are returned most recently finished
+/// first. They're kept for the configured retention period even after their pipeline is deleted.
+#[utoipa::path(
+    get,
+    path = "/v1/job_history",
+    tag = "job_history",
+    params(JobHistoryQueryParams),
+    responses(
+        (status = 200, description = "Got job history", body = JobRunCollection),
+    ),
+)]
+pub async fn get_job_history(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Query(params): Query<JobHistoryQueryParams>,
+) -> Result<Json<JobRunCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(ErrorResp {
+            status_code: StatusCode::BAD_REQUEST,
+            message: format!("limit must be between 1 and {}", MAX_LIMIT),
+        });
+    }
+
+    let before = params
+        .before_micros
+        .map(|micros| OffsetDateTime::from_unix_timestamp_nanos(micros as i128 * 1000))
+        .transpose()
+        .map_err(|_| ErrorResp {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "beforeMicros is not a valid timestamp".to_string(),
+        })?;
+
+    // fetch one more than requested to find out whether there are more runs
+    let mut runs: Vec<JobRun> = api_queries::get_job_history()
+        .bind(
+            &client,
+            &auth_data.organization_id,
+            &params.pipeline_id,
+            &params.state,
+            &params.search,
+            &before,
+            &(limit as i64 + 1),
+        )
+        .all()
+        .await
+        .map_err(log_and_map_rest)?
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+
+    let has_more = runs.len() > limit as usize;
+    runs.truncate(limit as usize);
+
+    Ok(Json(JobRunCollection {
+        data: runs,
+        has_more,
+    }))
+}
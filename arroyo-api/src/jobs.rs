@@ -1,9 +1,16 @@
-use arroyo_datastream::Program;
+use arroyo_datastream::{ConnectorOp, Operator, Program};
 use arroyo_rpc::grpc::api::{
-    CheckpointDetailsResp, CheckpointOverview, CreateJobReq, JobDetailsResp, JobStatus,
-    PipelineProgram, StopType,
+    operator, CheckpointDetailsResp, CheckpointOverview, CreateJobReq, DependencyCondition,
+    FailurePolicy, JobDependency, JobDetailsResp, JobEnv, JobStatus, PipelineProgram, PoisonPill,
+    PoisonPillAction, ProgramNode, QueueConfig, RecoveryThrottle, SloIndicator, SloViolation,
+    SourceOffsetOverride, SourceOffsetPosition, StateCompaction, StateCompactionState,
+    StateCompression, StateStorageConfig, StopType,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_types::{
+    u32_config, RestoreOverrides, PIPELINE_ENV_ALLOWLIST_ENV, PIPELINE_ENV_PREFIX,
+    PIPELINE_FEATURE_PREFIX, PREVIEW_TIMEOUT_SECS_ENV,
+};
 use cornucopia_async::GenericClient;
 use deadpool_postgres::{Pool, Transaction};
 use prost::Message;
@@ -12,10 +19,15 @@ use serde_json::from_str;
 use std::{collections::HashMap, time::Duration};
 use tonic::Status;
 
-const PREVIEW_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_PREVIEW_TIMEOUT_SECS: u32 = 60;
 
 use crate::{log_and_map, pipelines, queries::api_queries, to_micros, types::public, AuthData};
 
+/// How long preview runs may run before they're stopped
+fn preview_ttl() -> Duration {
+    Duration::from_secs(u32_config(PREVIEW_TIMEOUT_SECS_ENV, DEFAULT_PREVIEW_TIMEOUT_SECS) as u64)
+}
+
 fn gen_id() -> String {
     rand::thread_rng()
         .sample_iter(&Alphanumeric)
@@ -25,6 +37,435 @@ fn gen_id() -> String {
         .collect()
 }
 
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn env_var_allowed(name: &str) -> bool {
+    let Ok(allowlist) = std::env::var(PIPELINE_ENV_ALLOWLIST_ENV) else {
+        return true;
+    };
+
+    allowlist
+        .split(',')
+        .map(|entry| entry.trim().to_uppercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| match entry.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == entry,
+        })
+}
+
+/// Validates a job's environment and converts it into the variables that will be set on its
+/// workers (which is how it's stored)
+pub(crate) fn env_to_vars(env: &JobEnv) -> Result<HashMap<String, String>, Status> {
+    let mut vars = HashMap::new();
+
+    for (name, value) in &env.env_vars {
+        if !valid_name(name) {
+            return Err(Status::invalid_argument(format!(
+                "invalid environment variable name '{}'; names may only contain letters, \
+                numbers, and underscores",
+                name
+            )));
+        }
+
+        let name = name.to_uppercase();
+        if !env_var_allowed(&name) {
+            return Err(Status::invalid_argument(format!(
+                "environment variable '{}' is not in the allowlist for this cluster",
+                name
+            )));
+        }
+
+        vars.insert(format!("{}{}", PIPELINE_ENV_PREFIX, name), value.clone());
+    }
+
+    for (name, enabled) in &env.feature_flags {
+        if !valid_name(name) {
+            return Err(Status::invalid_argument(format!(
+                "invalid feature flag '{}'; names may only contain letters, numbers, and \
+                underscores",
+                name
+            )));
+        }
+
+        vars.insert(
+            format!("{}{}", PIPELINE_FEATURE_PREFIX, name.to_uppercase()),
+            enabled.to_string(),
+        );
+    }
+
+    Ok(vars)
+}
+
+/// The inverse of [`env_to_vars`]
+pub(crate) fn vars_to_env(vars: serde_json::Value) -> JobEnv {
+    let vars: HashMap<String, String> = serde_json::from_value(vars).unwrap_or_default();
+    let mut env = JobEnv::default();
+
+    for (name, value) in vars {
+        if let Some(name) = name.strip_prefix(PIPELINE_ENV_PREFIX) {
+            env.env_vars.insert(name.to_string(), value);
+        } else if let Some(name) = name.strip_prefix(PIPELINE_FEATURE_PREFIX) {
+            env.feature_flags.insert(name.to_string(), value == "true");
+        }
+    }
+
+    env
+}
+
+/// Validates that each override targets a source operator of the program, and converts them into
+/// the form that is stored for the controller
+pub(crate) fn restore_overrides(
+    overrides: &[SourceOffsetOverride],
+    program: &PipelineProgram,
+) -> Result<RestoreOverrides, Status> {
+    let mut sources = HashMap::new();
+
+    for o in overrides {
+        let Some(node) = program.nodes.iter().find(|n| n.node_id == o.operator_id) else {
+            return Err(Status::invalid_argument(format!(
+                "job has no operator '{}'",
+                o.operator_id
+            )));
+        };
+
+        if program
+            .edges
+            .iter()
+            .any(|e| e.downstream_node == node.node_index)
+        {
+            return Err(Status::invalid_argument(format!(
+                "operator '{}' is not a source",
+                o.operator_id
+            )));
+        }
+
+        let position = match (o.position(), o.timestamp_micros) {
+            (SourceOffsetPosition::Earliest, None) => arroyo_types::SourceOffsetOverride::Earliest,
+            (SourceOffsetPosition::Latest, None) => arroyo_types::SourceOffsetOverride::Latest,
+            (SourceOffsetPosition::Timestamp, Some(micros)) => {
+                arroyo_types::SourceOffsetOverride::Timestamp { micros }
+            }
+            (SourceOffsetPosition::Timestamp, None) => {
+                return Err(Status::invalid_argument(format!(
+                    "timestamp_micros must be set to start operator '{}' from a timestamp",
+                    o.operator_id
+                )));
+            }
+            (_, Some(_)) => {
+                return Err(Status::invalid_argument(format!(
+                    "timestamp_micros may only be set for the Timestamp position (operator '{}')",
+                    o.operator_id
+                )));
+            }
+        };
+
+        if sources.insert(o.operator_id.clone(), position).is_some() {
+            return Err(Status::invalid_argument(format!(
+                "operator '{}' has more than one override",
+                o.operator_id
+            )));
+        }
+    }
+
+    Ok(RestoreOverrides {
+        id: gen_id(),
+        sources,
+    })
+}
+
+// the sources that can start reading from a point in event time, by their operator
+const SEEKABLE_SOURCES: &[&str] = &[
+    "connectors::kafka::source::KafkaSourceFunc",
+    "connectors::nats::source::NatsSourceFunc",
+];
+
+/// Overrides that start every source of the program from `micros`, for runs that re-read a window
+/// of historical input without restoring a checkpoint
+pub(crate) fn seek_overrides(
+    program: &PipelineProgram,
+    micros: u64,
+) -> Result<RestoreOverrides, Status> {
+    let mut sources = HashMap::new();
+
+    for node in &program.nodes {
+        if program
+            .edges
+            .iter()
+            .any(|e| e.downstream_node == node.node_index)
+        {
+            continue;
+        }
+
+        let seekable = match node.operator.as_ref().and_then(|o| o.operator.as_ref()) {
+            Some(operator::Operator::ConnectorSource(op)) => {
+                SEEKABLE_SOURCES.iter().any(|s| op.operator.starts_with(s))
+            }
+            _ => false,
+        };
+
+        if !seekable {
+            return Err(Status::failed_precondition(format!(
+                "source '{}' can't be started from a timestamp; restore the run from a checkpoint \
+                instead",
+                node.node_id
+            )));
+        }
+
+        sources.insert(
+            node.node_id.clone(),
+            arroyo_types::SourceOffsetOverride::Timestamp { micros },
+        );
+    }
+
+    Ok(RestoreOverrides {
+        id: gen_id(),
+        sources,
+    })
+}
+
+pub(crate) fn failure_policy(policy: &FailurePolicy) -> arroyo_types::FailurePolicy {
+    arroyo_types::FailurePolicy {
+        restart_budget: policy.restart_budget,
+        on_poison_pill: match policy.on_poison_pill() {
+            PoisonPillAction::FailJob => arroyo_types::PoisonPillAction::Fail,
+            PoisonPillAction::SkipRecords => arroyo_types::PoisonPillAction::Skip,
+            PoisonPillAction::ParkJob => arroyo_types::PoisonPillAction::Park,
+        },
+    }
+}
+
+/// Validates a job's queue config and converts it into the form that is stored for the controller
+pub(crate) fn queue_config(config: &QueueConfig) -> Result<arroyo_types::QueueConfig, Status> {
+    if config.forward_queue_size == Some(0) || config.shuffle_queue_size == Some(0) {
+        return Err(Status::invalid_argument(
+            "queue sizes must be greater than 0",
+        ));
+    }
+
+    if config.network_flush_interval_micros == Some(0) {
+        return Err(Status::invalid_argument(
+            "network flush interval must be greater than 0",
+        ));
+    }
+
+    Ok(arroyo_types::QueueConfig {
+        forward_queue_size: config.forward_queue_size,
+        shuffle_queue_size: config.shuffle_queue_size,
+        network_flush_interval_micros: config.network_flush_interval_micros,
+    })
+}
+
+/// Validates a job's recovery throttle and converts it into the form that is stored for the
+/// controller
+pub(crate) fn recovery_throttle(
+    throttle: &RecoveryThrottle,
+) -> Result<arroyo_types::RecoveryThrottle, Status> {
+    if throttle.max_watermark_advance_micros == 0 {
+        return Err(Status::invalid_argument(
+            "max watermark advance must be greater than 0",
+        ));
+    }
+
+    Ok(arroyo_types::RecoveryThrottle {
+        max_watermark_advance_micros: throttle.max_watermark_advance_micros,
+        caught_up_lag_micros: throttle.caught_up_lag_micros,
+    })
+}
+
+/// Validates a job's state storage config and converts it into the form that is stored for the
+/// controller
+pub(crate) fn state_storage(
+    config: &StateStorageConfig,
+) -> Result<arroyo_types::StateStorageConfig, Status> {
+    let compression = config
+        .compression
+        .map(|c| match StateCompression::from_i32(c) {
+            Some(StateCompression::Zstd) => Ok(arroyo_types::StateCompression::Zstd),
+            Some(StateCompression::Uncompressed) => Ok(arroyo_types::StateCompression::None),
+            None => Err(Status::invalid_argument(format!(
+                "unknown state compression {}",
+                c
+            ))),
+        })
+        .transpose()?;
+
+    if let Some(level) = config.compression_level {
+        if compression == Some(arroyo_types::StateCompression::None) {
+            return Err(Status::invalid_argument(
+                "compression level can't be set when compression is disabled",
+            ));
+        }
+
+        if !(1..=22).contains(&level) {
+            return Err(Status::invalid_argument(
+                "compression level must be between 1 and 22",
+            ));
+        }
+    }
+
+    if config.kms_key_id.as_ref().map(|k| k.trim().is_empty()) == Some(true) {
+        return Err(Status::invalid_argument("KMS key id must not be empty"));
+    }
+
+    Ok(arroyo_types::StateStorageConfig {
+        compression,
+        compression_level: config.compression_level,
+        kms_key_id: config.kms_key_id.as_ref().map(|k| k.trim().to_string()),
+    })
+}
+
+/// Validates that the jobs a new job depends on exist, and converts the dependencies into the form
+/// that is stored for the controller
+async fn dependencies(
+    dependencies: &[JobDependency],
+    auth: &AuthData,
+    client: &impl GenericClient,
+) -> Result<Vec<arroyo_types::JobDependency>, Status> {
+    let mut result = vec![];
+    for dependency in dependencies {
+        api_queries::get_job_details()
+            .bind(client, &auth.organization_id, &dependency.job_id)
+            .opt()
+            .await
+            .map_err(log_and_map)?
+            .ok_or_else(|| {
+                Status::invalid_argument(format!(
+                    "job depends on job '{}', which does not exist",
+                    dependency.job_id
+                ))
+            })?;
+
+        if dependency.max_lag_micros == Some(0) {
+            return Err(Status::invalid_argument(
+                "dependency max lag must be greater than 0",
+            ));
+        }
+
+        result.push(arroyo_types::JobDependency {
+            job_id: dependency.job_id.clone(),
+            condition: match dependency.condition() {
+                DependencyCondition::Running => arroyo_types::DependencyCondition::Running,
+                DependencyCondition::CaughtUp => arroyo_types::DependencyCondition::CaughtUp,
+                DependencyCondition::Finished => arroyo_types::DependencyCondition::Finished,
+            },
+            max_lag_micros: dependency.max_lag_micros,
+        });
+    }
+
+    Ok(result)
+}
+
+/// The operators of a forked pipeline that are unchanged from the job it was forked from (the same
+/// operator, reading inputs of the same types), and so can be restored from that job's state
+pub(crate) fn unchanged_operators(source: &PipelineProgram, fork: &PipelineProgram) -> Vec<String> {
+    fn input_types(program: &PipelineProgram, node: &ProgramNode) -> Vec<(String, String)> {
+        let mut types: Vec<_> = program
+            .edges
+            .iter()
+            .filter(|e| e.downstream_node == node.node_index)
+            .map(|e| (e.key_type.clone(), e.value_type.clone()))
+            .collect();
+        types.sort();
+        types
+    }
+
+    fork.nodes
+        .iter()
+        .filter(|node| {
+            source.nodes.iter().any(|s| {
+                s.node_id == node.node_id
+                    && s.operator == node.operator
+                    && input_types(source, s) == input_types(fork, node)
+            })
+        })
+        .map(|node| node.node_id.clone())
+        .collect()
+}
+
+/// Checks that a forked pipeline doesn't write to any of the sinks of the job it was forked from,
+/// so that running it can't change that job's output
+pub(crate) fn validate_fork_sinks(source: &Program, fork: &Program) -> Result<(), Status> {
+    fn sinks(program: &Program) -> impl Iterator<Item = &ConnectorOp> {
+        program
+            .graph
+            .node_weights()
+            .filter_map(|node| match &node.operator {
+                Operator::ConnectorSink(op) if !op.operator.starts_with("GrpcSink") => Some(op),
+                _ => None,
+            })
+    }
+
+    for sink in sinks(fork) {
+        if sinks(source).any(|s| s.operator == sink.operator && s.config == sink.config) {
+            return Err(Status::invalid_argument(format!(
+                "the forked pipeline writes to the same sink ({}) as the original job; write to a \
+                different sink or set shadow_sinks to send its output to web sinks",
+                sink.description
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn poison_pill(value: serde_json::Value) -> Option<PoisonPill> {
+    let p: arroyo_types::PoisonPill = serde_json::from_value(value).ok()?;
+    Some(PoisonPill {
+        operator_id: p.operator_id,
+        subtask_index: p.subtask_index,
+        epoch: p.epoch,
+        failures: p.failures,
+        error: p.error,
+    })
+}
+
+pub(crate) fn slo_violations(value: serde_json::Value) -> Vec<SloViolation> {
+    let violations: Vec<arroyo_types::SloViolation> =
+        serde_json::from_value(value).unwrap_or_default();
+
+    violations
+        .into_iter()
+        .map(|v| SloViolation {
+            indicator: match v.indicator {
+                arroyo_types::SloIndicator::SourceLag => SloIndicator::SourceLag,
+                arroyo_types::SloIndicator::WatermarkLag => SloIndicator::WatermarkLag,
+                arroyo_types::SloIndicator::Idle => SloIndicator::Idle,
+            } as i32,
+            operator_id: v.operator_id,
+            value_micros: v.value_micros,
+            threshold_micros: v.threshold_micros,
+        })
+        .collect()
+}
+
+pub(crate) fn state_compaction(value: serde_json::Value) -> Option<StateCompaction> {
+    let c: arroyo_types::StateCompaction = serde_json::from_value(value).ok()?;
+    Some(StateCompaction {
+        state: match c.state {
+            arroyo_types::StateCompactionState::Running => StateCompactionState::CompactionRunning,
+            arroyo_types::StateCompactionState::Finished => {
+                StateCompactionState::CompactionFinished
+            }
+            arroyo_types::StateCompactionState::Failed => StateCompactionState::CompactionFailed,
+        } as i32,
+        epoch: c.epoch,
+        start_time: c.start_time_micros,
+        finish_time: c.finish_time_micros,
+        operators: c.operators,
+        operators_compacted: c.operators_compacted,
+        files_read: c.files_read,
+        files_written: c.files_written,
+        bytes_read: c.bytes_read,
+        bytes_written: c.bytes_written,
+        error: c.error,
+    })
+}
+
 pub(crate) async fn create_job<'a>(
     request: CreateJobReq,
     auth: AuthData,
@@ -49,7 +490,12 @@ pub(crate) async fn create_job<'a>(
     let running_jobs = get_jobs(&auth, client)
         .await?
         .iter()
-        .filter(|j| j.running_desired && j.state != "Failed" && j.state != "Finished")
+        .filter(|j| {
+            j.running_desired
+                && j.state != "Failed"
+                && j.state != "Finished"
+                && j.state != "NeedsAttention"
+        })
         .count();
 
     if running_jobs > auth.org_metadata.max_running_jobs as usize {
@@ -58,6 +504,9 @@ pub(crate) async fn create_job<'a>(
             an increase", auth.org_metadata.max_running_jobs)));
     }
 
+    let env_vars = env_to_vars(&request.env.unwrap_or_default())?;
+    let dependencies = dependencies(&request.dependencies, &auth, client).await?;
+
     let job_id = gen_id();
 
     // TODO: handle chance of collision in ids
@@ -72,10 +521,13 @@ pub(crate) async fn create_job<'a>(
             &from_str(&pipeline.pipeline_id).unwrap(),
             &(checkpoint_interval.as_micros() as i64),
             &(if request.preview {
-                Some(PREVIEW_TTL.as_micros() as i64)
+                Some(preview_ttl().as_micros() as i64)
             } else {
                 None
             }),
+            &serde_json::to_value(env_vars).unwrap(),
+            &(!dependencies.is_empty()).then(|| serde_json::to_value(&dependencies).unwrap()),
+            &request.stop_at_event_time_micros.map(|t| t as i64),
         )
         .await
         .map_err(log_and_map)?;
@@ -118,6 +570,10 @@ pub(crate) async fn get_jobs(
                 udfs: serde_json::from_value(rec.udfs).map_err(log_and_map)?,
                 pipeline_id: format!("{}", rec.pipeline_id),
                 failure_message: rec.failure_message,
+                poison_pill: rec.poison_pill.and_then(poison_pill),
+                slo_violations: rec.slo_violations.map(slo_violations).unwrap_or_default(),
+                waiting_for: rec.waiting_for,
+                state_compaction: rec.state_compaction.and_then(state_compaction),
             })
         })
         .collect()
@@ -197,6 +653,9 @@ pub(crate) async fn get_job_details(
         ("Failed", true) => ("Failed", Option::None, Stable),
         ("Failed", false) => ("Start", Some(None), Stable),
 
+        ("NeedsAttention", true) => ("Stop", Some(Immediate), Stable),
+        ("NeedsAttention", false) => ("Start", Some(None), Stable),
+
         _ => panic!("unhandled state {}", state),
     };
 
@@ -213,6 +672,10 @@ pub(crate) async fn get_job_details(
         pipeline_id: format!("{}", res.pipeline_id),
         udfs: serde_json::from_value(res.udfs).map_err(log_and_map)?,
         failure_message: res.failure_message,
+        poison_pill: res.poison_pill.and_then(poison_pill),
+        slo_violations: res.slo_violations.map(slo_violations).unwrap_or_default(),
+        waiting_for: res.waiting_for,
+        state_compaction: res.state_compaction.and_then(state_compaction),
     };
 
     Ok(JobDetailsResp {
@@ -225,6 +688,8 @@ pub(crate) async fn get_job_details(
             InProgress => true,
             Stable => false,
         },
+        // filled in from the controller by the API handler
+        connectors: vec![],
     })
 }
 
@@ -290,9 +755,13 @@ pub(crate) async fn delete_job(job_id: &str, auth: AuthData, pool: &Pool) -> Res
     let job_details = get_job_details(job_id, &auth, &transaction).await?;
 
     if let Some(status) = job_details.job_status {
-        if !(status.state == "Stopped" || status.state == "Finished" || status.state == "Failed") {
+        if !(status.state == "Stopped"
+            || status.state == "Finished"
+            || status.state == "Failed"
+            || status.state == "NeedsAttention")
+        {
             return Err(Status::failed_precondition(
-                "Job must be in a terminal state (stopped, finished, or failed)
+                "Job must be in a terminal state (stopped, finished, failed, or needs attention)
                 before it can be deleted",
             ));
         }
@@ -307,3 +776,144 @@ pub(crate) async fn delete_job(job_id: &str, auth: AuthData, pool: &Pool) -> Res
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use arroyo_rpc::grpc::api::{
+        operator, ConnectorOp, JobEnv, Operator, PipelineProgram, ProgramEdge, ProgramNode,
+        SourceOffsetOverride, SourceOffsetPosition,
+    };
+
+    use super::{env_to_vars, restore_overrides, seek_overrides, vars_to_env};
+
+    #[test]
+    fn test_job_env() {
+        let env = JobEnv {
+            env_vars: HashMap::from([("api_url".to_string(), "http://api:8000".to_string())]),
+            feature_flags: HashMap::from([("NEW_PARSER".to_string(), true)]),
+        };
+
+        let vars = env_to_vars(&env).unwrap();
+        assert_eq!(
+            vars.get("ARROYO_PIPELINE_ENV_API_URL").unwrap(),
+            "http://api:8000"
+        );
+        assert_eq!(
+            vars.get("ARROYO_PIPELINE_FEATURE_NEW_PARSER").unwrap(),
+            "true"
+        );
+
+        let env = vars_to_env(serde_json::to_value(vars).unwrap());
+        assert_eq!(env.env_vars.get("API_URL").unwrap(), "http://api:8000");
+        assert!(env.feature_flags.get("NEW_PARSER").unwrap());
+
+        let invalid = JobEnv {
+            env_vars: HashMap::from([("PATH=/bin; X".to_string(), "".to_string())]),
+            feature_flags: HashMap::new(),
+        };
+        assert!(env_to_vars(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_restore_overrides() {
+        let node = |index: i32| ProgramNode {
+            node_index: index,
+            node_id: format!("node_{}", index),
+            parallelism: 1,
+            operator: None,
+        };
+        let program = PipelineProgram {
+            nodes: vec![node(0), node(1)],
+            edges: vec![ProgramEdge {
+                upstream_node: 0,
+                downstream_node: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let o = |operator_id: &str, position: SourceOffsetPosition, timestamp_micros| {
+            SourceOffsetOverride {
+                operator_id: operator_id.to_string(),
+                position: position as i32,
+                timestamp_micros,
+            }
+        };
+
+        let overrides = restore_overrides(
+            &[o("node_0", SourceOffsetPosition::Timestamp, Some(1_000))],
+            &program,
+        )
+        .unwrap();
+        assert_eq!(
+            overrides.sources.get("node_0"),
+            Some(&arroyo_types::SourceOffsetOverride::Timestamp { micros: 1_000 })
+        );
+
+        // not a source
+        assert!(restore_overrides(
+            &[o("node_1", SourceOffsetPosition::Earliest, None)],
+            &program
+        )
+        .is_err());
+        // no such operator
+        assert!(
+            restore_overrides(&[o("node_2", SourceOffsetPosition::Latest, None)], &program)
+                .is_err()
+        );
+        // missing timestamp
+        assert!(restore_overrides(
+            &[o("node_0", SourceOffsetPosition::Timestamp, None)],
+            &program
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_seek_overrides() {
+        let source = |index: i32, operator: &str| ProgramNode {
+            node_index: index,
+            node_id: format!("node_{}", index),
+            parallelism: 1,
+            operator: Some(Operator {
+                operator: Some(operator::Operator::ConnectorSource(ConnectorOp {
+                    operator: operator.to_string(),
+                    ..Default::default()
+                })),
+            }),
+        };
+        let edge = |upstream_node, downstream_node| ProgramEdge {
+            upstream_node,
+            downstream_node,
+            ..Default::default()
+        };
+
+        let program = PipelineProgram {
+            nodes: vec![
+                source(0, "connectors::kafka::source::KafkaSourceFunc"),
+                source(1, "connectors::kafka::source::KafkaSourceFunc"),
+                ProgramNode {
+                    node_index: 2,
+                    node_id: "node_2".to_string(),
+                    parallelism: 1,
+                    operator: None,
+                },
+            ],
+            edges: vec![edge(0, 2), edge(1, 2)],
+            ..Default::default()
+        };
+
+        let overrides = seek_overrides(&program, 5_000).unwrap();
+        assert_eq!(overrides.sources.len(), 2);
+        assert_eq!(
+            overrides.sources.get("node_1"),
+            Some(&arroyo_types::SourceOffsetOverride::Timestamp { micros: 5_000 })
+        );
+
+        let mut program = program;
+        program.nodes[1] = source(1, "connectors::sse::SSESourceFunc");
+        assert!(seek_overrides(&program, 5_000).is_err());
+    }
+}
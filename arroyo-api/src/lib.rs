@@ -1,36 +1,66 @@
+use crate::connection_profiles::{
+    __path_delete_connection_profile, __path_get_connection_profiles,
+    __path_post_connection_profile,
+};
+use crate::job_history::__path_get_job_history;
+use crate::masking_policies::{
+    __path_delete_masking_policy, __path_get_masking_policies, __path_post_masking_policy,
+};
 use crate::pipelines::__path_get_pipelines;
 use crate::pipelines::__path_post_pipeline;
+use crate::pipelines::__path_post_pipeline_import;
+use crate::pipelines::__path_post_pipeline_schema;
 use crate::pipelines::{
-    __path_delete_pipeline, __path_get_jobs, __path_get_pipeline, __path_patch_pipeline,
+    __path_delete_pipeline, __path_get_jobs, __path_get_pipeline, __path_get_pipeline_health,
+    __path_get_pipeline_resources, __path_patch_pipeline, __path_promote_pipeline,
 };
 use crate::rest::__path_ping;
 use crate::rest_types::{
-    Job, JobCollection, Pipeline, PipelineCollection, PipelinePatch, PipelinePost,
-    StopType as StopTypeRest, Udf, UdfLanguage,
+    ConnectionProfile, ConnectionProfileCollection, ConnectionProfilePost, DependencyCondition,
+    EstimateBasis, FailurePolicy, HealthIndicator, HealthStatus, ImportDialect, ImportedConnection,
+    Instrumentation, Job, JobCollection, JobRun, JobRunCollection, JobRunEvent, MaskingAction,
+    MaskingPolicy, MaskingPolicyCollection, MaskingPolicyPost, OperatorResources,
+    OperatorRunMetrics, Pipeline, PipelineCollection, PipelineDependency, PipelineHealth,
+    PipelineImport, PipelineImportPost, PipelinePatch, PipelinePost, PipelinePromotePost,
+    PipelineResources, PipelineSchema, PipelineSchemaPost, PipelineSlo, PoisonPill,
+    PoisonPillAction, QueueConfig, RecoveryThrottle, SchemaField, SinkSchema, SloIndicator,
+    SloViolation, SourceOffsetPosition, SourceOverride, SqlWarning, StateCompaction,
+    StateCompactionState, StateCompression, StateStorageConfig, StopType as StopTypeRest, Udf,
+    UdfLanguage,
 };
 use arroyo_connectors::connectors;
+use arroyo_datastream::Program;
+use arroyo_rpc::column_stats::distinct_estimate;
 use arroyo_rpc::grpc::api::{
-    CreateConnectionTableReq, CreateConnectionTableResp, DeleteConnectionReq, DeleteConnectionResp,
-    DeleteConnectionTableReq, DeleteConnectionTableResp, DeleteJobReq, DeleteJobResp,
-    GetConnectionTablesReq, GetConnectionTablesResp, GetConnectorsReq, GetConnectorsResp,
-    PipelineProgram, TestSchemaReq, TestSchemaResp,
+    ColumnStats, CreateConnectionTableReq, CreateConnectionTableResp, DeleteConnectionReq,
+    DeleteConnectionResp, DeleteConnectionTableReq, DeleteConnectionTableResp, DeleteJobReq,
+    DeleteJobResp, GetConnectionTablesReq, GetConnectionTablesResp, GetConnectorsReq,
+    GetConnectorsResp, JobColumnStatsReq, JobColumnStatsResp, OperatorColumnStats, PipelineProgram,
+    TestSchemaReq, TestSchemaResp,
 };
 use arroyo_rpc::grpc::{
     self,
     api::{
-        api_grpc_server::ApiGrpc, CheckpointDetailsReq, CheckpointDetailsResp, ConfluentSchemaReq,
-        ConfluentSchemaResp, CreateConnectionReq, CreateConnectionResp, CreateJobReq,
-        CreateJobResp, CreatePipelineReq, CreatePipelineResp, GetConnectionsReq,
-        GetConnectionsResp, GetJobsReq, GetJobsResp, GetPipelineReq, GrpcOutputSubscription,
-        JobCheckpointsReq, JobCheckpointsResp, JobDetailsReq, JobDetailsResp, JobMetricsReq,
-        JobMetricsResp, OperatorErrorsReq, OperatorErrorsRes, OutputData, PipelineDef,
-        PipelineGraphReq, PipelineGraphResp, StopType, TestSourceMessage, UpdateJobReq,
-        UpdateJobResp,
+        api_grpc_server::ApiGrpc, create_pipeline_req, CheckpointDetailsReq, CheckpointDetailsResp,
+        CompactJobStateReq, CompactJobStateResp, ConfluentSchemaReq, ConfluentSchemaResp,
+        ConnectorStatus, CreateConnectionReq, CreateConnectionResp, CreateJobReq, CreateJobResp,
+        CreatePipelineReq, CreatePipelineResp, DebugRunReq, DebugRunResp, ForkJobReq, ForkJobResp,
+        GetConnectionsReq, GetConnectionsResp, GetJobsReq, GetJobsResp, GetPipelineReq,
+        GrpcOutputSubscription, InjectWatermarkProbeReq, InjectWatermarkProbeResp,
+        JobCheckpointsReq, JobCheckpointsResp, JobDetailsReq, JobDetailsResp, JobHealthReq,
+        JobHealthResp, JobMetricsReq, JobMetricsResp, JobProgressReq, JobProgressResp,
+        JobResourceEstimateReq, JobResourceEstimateResp, MaterializedRow, OperatorErrorsReq,
+        OperatorErrorsRes, OutputData, PipelineDef, PipelineGraphReq, PipelineGraphResp,
+        ProbeObservation, SampleSinkOutputReq, SampleSinkOutputResp, SinkOutputSample, StopType,
+        TaskProgressSample, TaskProgressWindow, TestSourceMessage, UpdateJobReq, UpdateJobResp,
+        UpdatingOutputStateReq, UpdatingOutputStateResp, WatermarkProbeReport, WatermarkProbesReq,
+        WatermarkProbesResp,
     },
     controller_grpc_client::ControllerGrpcClient,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
-use arroyo_server_common::log_event;
+use arroyo_server_common::{log_event, tls};
+use arroyo_types::JobFork;
 use cornucopia_async::GenericClient;
 use deadpool_postgres::{Object, Pool};
 use prost::Message;
@@ -41,15 +71,18 @@ use std::time::Duration;
 use time::OffsetDateTime;
 use tokio_postgres::error::SqlState;
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
-use tonic::{Request, Response, Status};
+use tonic::{transport::Channel, Request, Response, Status};
 use tracing::{error, info, warn};
 use utoipa::OpenApi;
 
 mod cloud;
+mod connection_profiles;
 mod connection_tables;
 mod connections;
+mod job_history;
 mod job_log;
 mod jobs;
+mod masking_policies;
 mod metrics;
 mod optimizations;
 mod pipelines;
@@ -169,6 +202,13 @@ impl ApiServer {
         self.pool.get().await.map_err(log_and_map)
     }
 
+    async fn controller(&self) -> Result<ControllerGrpcClient<Channel>, Status> {
+        tls::connect_addr(self.controller_addr.clone())
+            .await
+            .map(ControllerGrpcClient::new)
+            .map_err(log_and_map)
+    }
+
     async fn start_or_preview(
         &self,
         req: CreatePipelineReq,
@@ -183,12 +223,24 @@ impl ApiServer {
             .await
             .map_err(log_and_map)?;
 
-        let pipeline_id =
-            pipelines::create_pipeline(req, &pub_id, auth.clone(), &transaction).await?;
+        let (env, dependencies, stop_at_event_time_micros) = match &req.config {
+            Some(create_pipeline_req::Config::Sql(sql)) => (
+                sql.env.clone(),
+                sql.dependencies.clone(),
+                sql.stop_at_event_time_micros,
+            ),
+            _ => (None, vec![], None),
+        };
+
+        let (pipeline_id, warnings) =
+            pipelines::create_pipeline(req, &pub_id, false, auth.clone(), &transaction).await?;
         let create_job = CreateJobReq {
             pipeline_id: format!("{}", pipeline_id),
             checkpoint_interval_micros: DEFAULT_CHECKPOINT_INTERVAL.as_micros() as u64,
             preview,
+            env,
+            dependencies,
+            stop_at_event_time_micros,
         };
 
         let job_id = jobs::create_job(create_job, auth, &transaction).await?;
@@ -199,7 +251,43 @@ impl ApiServer {
             json!({"service": "api", "is_preview": preview, "job_id": job_id}),
         );
 
-        Ok(Response::new(CreateJobResp { job_id }))
+        Ok(Response::new(CreateJobResp { job_id, warnings }))
+    }
+
+    /// The health of the job's connectors, as last reported to the controller. This is best
+    /// effort: if the controller can't be reached, no connectors are returned.
+    async fn connector_health(&self, job_id: &str) -> Vec<ConnectorStatus> {
+        let progress = async {
+            self.controller()
+                .await?
+                .get_job_progress(Request::new(grpc::JobProgressReq {
+                    job_id: job_id.to_string(),
+                }))
+                .await
+        };
+
+        match progress.await {
+            Ok(progress) => progress
+                .into_inner()
+                .connectors
+                .into_iter()
+                .map(|c| ConnectorStatus {
+                    operator_id: c.operator_id,
+                    task_index: c.task_index,
+                    connected: c.connected,
+                    last_error: c.last_error,
+                    last_error_time: c.last_error_time,
+                    reconnects: c.reconnects,
+                    lag_millis: c.lag_millis,
+                    updated_time: c.updated_time,
+                })
+                .collect(),
+            Err(status) if status.code() == tonic::Code::NotFound => vec![],
+            Err(status) => {
+                warn!("failed to get connector health of {}: {:?}", job_id, status);
+                vec![]
+            }
+        }
     }
 }
 
@@ -336,9 +424,10 @@ impl ApiGrpc for ApiServer {
         let mut client = self.client().await?;
         let transaction = client.transaction().await.map_err(log_and_map)?;
 
-        let id = pipelines::create_pipeline(
+        let (id, warnings) = pipelines::create_pipeline(
             request.into_inner(),
             &generate_id(IdTypes::Pipeline),
+            false,
             auth,
             &transaction,
         )
@@ -350,6 +439,7 @@ impl ApiGrpc for ApiServer {
 
         Ok(Response::new(CreatePipelineResp {
             pipeline_id: format!("{}", id),
+            warnings,
         }))
     }
 
@@ -398,7 +488,10 @@ impl ApiGrpc for ApiServer {
 
         log_event("job_created", json!({"service": "api"}));
 
-        Ok(Response::new(CreateJobResp { job_id: id }))
+        Ok(Response::new(CreateJobResp {
+            job_id: id,
+            warnings: vec![],
+        }))
     }
 
     async fn delete_job(
@@ -459,9 +552,10 @@ impl ApiGrpc for ApiServer {
         let (request, auth) = self.authenticate(request).await?;
         let req = request.into_inner();
 
-        jobs::get_job_details(&req.job_id, &auth, &self.client().await?)
-            .await
-            .map(Response::new)
+        let mut details = jobs::get_job_details(&req.job_id, &auth, &self.client().await?).await?;
+        details.connectors = self.connector_health(&req.job_id).await;
+
+        Ok(Response::new(details))
     }
 
     async fn get_checkpoints(
@@ -515,6 +609,234 @@ impl ApiGrpc for ApiServer {
         ))
     }
 
+    async fn get_job_health(
+        &self,
+        request: Request<JobHealthReq>,
+    ) -> Result<Response<JobHealthResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+
+        Ok(Response::new(
+            metrics::get_health(request.into_inner().job_id, auth, &self.client().await?).await?,
+        ))
+    }
+
+    async fn get_job_progress(
+        &self,
+        request: Request<JobProgressReq>,
+    ) -> Result<Response<JobProgressResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let job_id = request.into_inner().job_id;
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        let progress = controller
+            .get_job_progress(Request::new(grpc::JobProgressReq { job_id }))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(JobProgressResp {
+            tasks: progress
+                .tasks
+                .into_iter()
+                .map(|task| TaskProgressWindow {
+                    operator_id: task.operator_id,
+                    task_index: task.task_index,
+                    samples: task
+                        .samples
+                        .into_iter()
+                        .map(|s| TaskProgressSample {
+                            time: s.time,
+                            records_in: s.records_in,
+                            records_out: s.records_out,
+                            watermark_micros: s.watermark_micros,
+                            state_bytes: s.state_bytes,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_job_resource_estimate(
+        &self,
+        request: Request<JobResourceEstimateReq>,
+    ) -> Result<Response<JobResourceEstimateResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+
+        Ok(Response::new(
+            metrics::get_resource_estimate(
+                request.into_inner().job_id,
+                auth,
+                &self.client().await?,
+            )
+            .await?,
+        ))
+    }
+
+    async fn sample_sink_output(
+        &self,
+        request: Request<SampleSinkOutputReq>,
+    ) -> Result<Response<SampleSinkOutputResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let req = request.into_inner();
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&req.job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        let resp = controller
+            .sample_sink_output(Request::new(grpc::SampleSinkOutputReq {
+                job_id: req.job_id,
+                operator_id: req.operator_id,
+                count: req.count,
+            }))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(SampleSinkOutputResp {
+            samples: resp
+                .samples
+                .into_iter()
+                .map(|s| SinkOutputSample {
+                    task_index: s.task_index,
+                    timestamp_micros: s.timestamp,
+                    value: s.value,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_job_column_stats(
+        &self,
+        request: Request<JobColumnStatsReq>,
+    ) -> Result<Response<JobColumnStatsResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let req = request.into_inner();
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&req.job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        let resp = controller
+            .get_job_column_stats(Request::new(grpc::JobColumnStatsReq { job_id: req.job_id }))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(JobColumnStatsResp {
+            operators: resp
+                .operators
+                .into_iter()
+                .map(|o| OperatorColumnStats {
+                    operator_id: o.operator_id,
+                    columns: o
+                        .columns
+                        .into_iter()
+                        .map(|c| ColumnStats {
+                            distinct_estimate: distinct_estimate(&c.distinct_sketch),
+                            column: c.column,
+                            count: c.count,
+                            nulls: c.nulls,
+                            min: c.min,
+                            max: c.max,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn inject_watermark_probe(
+        &self,
+        request: Request<InjectWatermarkProbeReq>,
+    ) -> Result<Response<InjectWatermarkProbeResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let req = request.into_inner();
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&req.job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        let resp = controller
+            .inject_watermark_probe(Request::new(grpc::InjectWatermarkProbeReq {
+                job_id: req.job_id,
+                label: req.label,
+                operator_id: req.operator_id,
+            }))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(InjectWatermarkProbeResp {
+            probe_id: resp.probe_id,
+            sources: resp.sources,
+        }))
+    }
+
+    async fn compact_job_state(
+        &self,
+        request: Request<CompactJobStateReq>,
+    ) -> Result<Response<CompactJobStateResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let job_id = request.into_inner().job_id;
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        controller
+            .compact_job_state(Request::new(grpc::CompactJobStateReq { job_id }))
+            .await?;
+
+        Ok(Response::new(CompactJobStateResp {}))
+    }
+
+    async fn get_watermark_probes(
+        &self,
+        request: Request<WatermarkProbesReq>,
+    ) -> Result<Response<WatermarkProbesResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let job_id = request.into_inner().job_id;
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        let resp = controller
+            .get_watermark_probes(Request::new(grpc::WatermarkProbesReq { job_id }))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(WatermarkProbesResp {
+            probes: resp
+                .probes
+                .into_iter()
+                .map(|p| WatermarkProbeReport {
+                    probe_id: p.probe_id,
+                    label: p.label,
+                    injected_time: p.injected_time,
+                    observations: p
+                        .observations
+                        .into_iter()
+                        .map(|o| ProbeObservation {
+                            operator_id: o.operator_id,
+                            task_index: o.task_index,
+                            time: o.time,
+                            delay_micros: o.time.saturating_sub(p.injected_time),
+                            watermark_micros: o.watermark_micros,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }))
+    }
+
     async fn update_job(
         &self,
         request: Request<UpdateJobReq>,
@@ -530,6 +852,7 @@ impl ApiGrpc for ApiServer {
             StopType::Immediate => types::public::StopMode::immediate,
             StopType::Checkpoint => types::public::StopMode::checkpoint,
             StopType::Force => types::public::StopMode::force,
+            StopType::Drain => types::public::StopMode::drain,
         });
 
         if let Some(interval) = interval {
@@ -540,7 +863,7 @@ impl ApiGrpc for ApiServer {
             }
         }
 
-        let parallelism_overrides = if let Some(parallelism) = req.parallelism {
+        let program = if req.parallelism.is_some() || !req.source_overrides.is_empty() {
             let res = queries::api_queries::get_job_details()
                 .bind(&self.client().await?, &auth.organization_id, &req.job_id)
                 .opt()
@@ -548,11 +871,18 @@ impl ApiGrpc for ApiServer {
                 .map_err(log_and_map)?
                 .ok_or_else(|| Status::not_found(format!("No job with id '{}'", req.job_id)))?;
 
-            let program = PipelineProgram::decode(&res.program[..]).map_err(log_and_map)?;
+            Some(PipelineProgram::decode(&res.program[..]).map_err(log_and_map)?)
+        } else {
+            None
+        };
+
+        let parallelism_overrides = if let Some(parallelism) = req.parallelism {
             let map: HashMap<String, u32> = program
+                .as_ref()
+                .unwrap()
                 .nodes
-                .into_iter()
-                .map(|node| (node.node_id, parallelism))
+                .iter()
+                .map(|node| (node.node_id.clone(), parallelism))
                 .collect();
 
             Some(serde_json::to_value(map).map_err(log_and_map)?)
@@ -560,6 +890,51 @@ impl ApiGrpc for ApiServer {
             None
         };
 
+        let restore_overrides = if !req.source_overrides.is_empty() {
+            let overrides =
+                jobs::restore_overrides(&req.source_overrides, program.as_ref().unwrap())?;
+            Some(serde_json::to_value(overrides).map_err(log_and_map)?)
+        } else {
+            None
+        };
+
+        let env_vars = req
+            .env
+            .as_ref()
+            .map(jobs::env_to_vars)
+            .transpose()?
+            .map(|vars| serde_json::to_value(vars).unwrap());
+
+        let slo = req
+            .slo
+            .map(|slo| serde_json::to_value(PipelineSlo::from(slo)).unwrap());
+
+        let failure_policy = req
+            .failure_policy
+            .as_ref()
+            .map(|p| serde_json::to_value(jobs::failure_policy(p)).unwrap());
+
+        let queue_config = req
+            .queue_config
+            .as_ref()
+            .map(jobs::queue_config)
+            .transpose()?
+            .map(|c| serde_json::to_value(c).unwrap());
+
+        let recovery_throttle = req
+            .recovery_throttle
+            .as_ref()
+            .map(jobs::recovery_throttle)
+            .transpose()?
+            .map(|t| serde_json::to_value(t).unwrap());
+
+        let state_storage = req
+            .state_storage
+            .as_ref()
+            .map(jobs::state_storage)
+            .transpose()?
+            .map(|c| serde_json::to_value(c).unwrap());
+
         let res = queries::api_queries::update_job()
             .bind(
                 &self.client().await?,
@@ -568,6 +943,14 @@ impl ApiGrpc for ApiServer {
                 &stop,
                 &interval.map(|i| i.as_micros() as i64),
                 &parallelism_overrides,
+                &env_vars,
+                &restore_overrides,
+                &slo,
+                &failure_policy,
+                &queue_config,
+                &req.stop_at_event_time_micros.map(|t| t as i64),
+                &recovery_throttle,
+                &state_storage,
                 &req.job_id,
                 &auth.organization_id,
             )
@@ -581,6 +964,285 @@ impl ApiGrpc for ApiServer {
         }
     }
 
+    async fn fork_job(
+        &self,
+        request: Request<ForkJobReq>,
+    ) -> Result<Response<ForkJobResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let req = request.into_inner();
+        let sql = req.sql.ok_or_else(|| required_field("sql"))?;
+
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(log_and_map)?;
+        transaction
+            .execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE", &[])
+            .await
+            .map_err(log_and_map)?;
+
+        let job = queries::api_queries::get_job_details()
+            .bind(&transaction, &auth.organization_id, &req.job_id)
+            .opt()
+            .await
+            .map_err(log_and_map)?
+            .ok_or_else(|| Status::not_found(format!("No job with id '{}'", req.job_id)))?;
+
+        let epoch = queries::api_queries::get_forkable_checkpoint()
+            .bind(
+                &transaction,
+                &req.job_id,
+                &auth.organization_id,
+                &req.epoch.map(|epoch| epoch as i32),
+            )
+            .opt()
+            .await
+            .map_err(log_and_map)?
+            .ok_or_else(|| {
+                Status::failed_precondition(match req.epoch {
+                    Some(epoch) => format!(
+                        "job '{}' has no completed checkpoint with epoch {}",
+                        req.job_id, epoch
+                    ),
+                    None => format!("job '{}' has no completed checkpoints", req.job_id),
+                })
+            })?;
+
+        let (env, dependencies, stop_at_event_time_micros) = (
+            sql.env.clone(),
+            sql.dependencies.clone(),
+            sql.stop_at_event_time_micros,
+        );
+        let (pipeline_id, warnings) = pipelines::create_pipeline(
+            CreatePipelineReq {
+                name: req.name,
+                config: Some(create_pipeline_req::Config::Sql(sql)),
+            },
+            &generate_id(IdTypes::Pipeline),
+            req.shadow_sinks,
+            auth.clone(),
+            &transaction,
+        )
+        .await?;
+
+        let pipeline = queries::api_queries::get_pipeline()
+            .bind(&transaction, &pipeline_id, &auth.organization_id)
+            .one()
+            .await
+            .map_err(log_and_map)?;
+
+        let source_program = PipelineProgram::decode(&job.program[..]).map_err(log_and_map)?;
+        let fork_program = PipelineProgram::decode(&pipeline.program[..]).map_err(log_and_map)?;
+
+        if !req.shadow_sinks {
+            let source: Program = source_program.clone().try_into().map_err(log_and_map)?;
+            let fork: Program = fork_program.clone().try_into().map_err(log_and_map)?;
+            jobs::validate_fork_sinks(&source, &fork)?;
+        }
+
+        let fork = JobFork {
+            job_id: req.job_id.clone(),
+            epoch: epoch as u32,
+            operators: jobs::unchanged_operators(&source_program, &fork_program),
+        };
+
+        let job_id = jobs::create_job(
+            CreateJobReq {
+                pipeline_id: format!("{}", pipeline_id),
+                checkpoint_interval_micros: DEFAULT_CHECKPOINT_INTERVAL.as_micros() as u64,
+                preview: false,
+                env,
+                dependencies,
+                stop_at_event_time_micros,
+            },
+            auth.clone(),
+            &transaction,
+        )
+        .await?;
+
+        queries::api_queries::set_job_fork()
+            .bind(
+                &transaction,
+                &serde_json::to_value(&fork).unwrap(),
+                &job_id,
+                &auth.organization_id,
+            )
+            .await
+            .map_err(log_and_map)?;
+
+        transaction.commit().await.map_err(log_and_map)?;
+        log_event(
+            "job_forked",
+            json!({"service": "api", "job_id": job_id, "forked_job_id": req.job_id}),
+        );
+
+        Ok(Response::new(ForkJobResp {
+            job_id,
+            warnings,
+            epoch: fork.epoch,
+            restored_operators: fork.operators,
+        }))
+    }
+
+    async fn debug_run_job(
+        &self,
+        request: Request<DebugRunReq>,
+    ) -> Result<Response<DebugRunResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let req = request.into_inner();
+        let mut sql = req.sql.ok_or_else(|| required_field("sql"))?;
+
+        if req.end_time_micros <= req.start_time_micros {
+            return Err(Status::invalid_argument(
+                "end_time_micros must be after start_time_micros",
+            ));
+        }
+        if req.seek_sources && req.epoch.is_some() {
+            return Err(Status::invalid_argument(
+                "epoch can't be set for runs that seek their sources",
+            ));
+        }
+        let start_time =
+            OffsetDateTime::from_unix_timestamp_nanos(req.start_time_micros as i128 * 1000)
+                .map_err(|_| Status::invalid_argument("start_time_micros is out of range"))?;
+
+        // the run finishes once it has processed the whole window
+        sql.stop_at_event_time_micros = Some(req.end_time_micros);
+
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(log_and_map)?;
+        transaction
+            .execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE", &[])
+            .await
+            .map_err(log_and_map)?;
+
+        let job = queries::api_queries::get_job_details()
+            .bind(&transaction, &auth.organization_id, &req.job_id)
+            .opt()
+            .await
+            .map_err(log_and_map)?
+            .ok_or_else(|| Status::not_found(format!("No job with id '{}'", req.job_id)))?;
+
+        let epoch = if req.seek_sources {
+            None
+        } else {
+            let epoch = queries::api_queries::get_checkpoint_before()
+                .bind(
+                    &transaction,
+                    &req.job_id,
+                    &auth.organization_id,
+                    &start_time,
+                    &req.epoch.map(|epoch| epoch as i32),
+                )
+                .opt()
+                .await
+                .map_err(log_and_map)?
+                .ok_or_else(|| {
+                    Status::failed_precondition(match req.epoch {
+                        Some(epoch) => format!(
+                            "job '{}' has no completed checkpoint with epoch {} taken before the \
+                            start of the window",
+                            req.job_id, epoch
+                        ),
+                        None => format!(
+                            "job '{}' has no completed checkpoints taken before the start of the \
+                            window; set seek_sources to start its sources from the window instead",
+                            req.job_id
+                        ),
+                    })
+                })?;
+            Some(epoch as u32)
+        };
+
+        let (env, dependencies) = (sql.env.clone(), sql.dependencies.clone());
+        let (pipeline_id, warnings) = pipelines::create_pipeline(
+            CreatePipelineReq {
+                name: req.name,
+                config: Some(create_pipeline_req::Config::Sql(sql)),
+            },
+            &generate_id(IdTypes::Pipeline),
+            true,
+            auth.clone(),
+            &transaction,
+        )
+        .await?;
+
+        let pipeline = queries::api_queries::get_pipeline()
+            .bind(&transaction, &pipeline_id, &auth.organization_id)
+            .one()
+            .await
+            .map_err(log_and_map)?;
+
+        let run_program = PipelineProgram::decode(&pipeline.program[..]).map_err(log_and_map)?;
+
+        let (fork, restore_overrides) = match epoch {
+            Some(epoch) => {
+                let source_program =
+                    PipelineProgram::decode(&job.program[..]).map_err(log_and_map)?;
+                let fork = JobFork {
+                    job_id: req.job_id.clone(),
+                    epoch,
+                    operators: jobs::unchanged_operators(&source_program, &run_program),
+                };
+                (Some(fork), None)
+            }
+            None => (
+                None,
+                Some(jobs::seek_overrides(&run_program, req.start_time_micros)?),
+            ),
+        };
+
+        // previews are temporary and sandboxed, which is what a debug run should be as well
+        let job_id = jobs::create_job(
+            CreateJobReq {
+                pipeline_id: format!("{}", pipeline_id),
+                checkpoint_interval_micros: DEFAULT_CHECKPOINT_INTERVAL.as_micros() as u64,
+                preview: true,
+                env,
+                dependencies,
+                stop_at_event_time_micros: Some(req.end_time_micros),
+            },
+            auth.clone(),
+            &transaction,
+        )
+        .await?;
+
+        if let Some(fork) = &fork {
+            queries::api_queries::set_job_fork()
+                .bind(
+                    &transaction,
+                    &serde_json::to_value(fork).unwrap(),
+                    &job_id,
+                    &auth.organization_id,
+                )
+                .await
+                .map_err(log_and_map)?;
+        }
+
+        if let Some(overrides) = &restore_overrides {
+            queries::api_queries::set_job_restore_overrides()
+                .bind(
+                    &transaction,
+                    &serde_json::to_value(overrides).unwrap(),
+                    &job_id,
+                    &auth.organization_id,
+                )
+                .await
+                .map_err(log_and_map)?;
+        }
+
+        transaction.commit().await.map_err(log_and_map)?;
+        log_event(
+            "job_debug_run",
+            json!({"service": "api", "job_id": job_id, "debugged_job_id": req.job_id}),
+        );
+
+        Ok(Response::new(DebugRunResp {
+            job_id,
+            warnings,
+            epoch,
+            restored_operators: fork.map(|f| f.operators).unwrap_or_default(),
+        }))
+    }
+
     type SubscribeToOutputStream = ReceiverStream<Result<OutputData, Status>>;
 
     async fn subscribe_to_output(
@@ -589,17 +1251,19 @@ impl ApiGrpc for ApiServer {
     ) -> Result<Response<Self::SubscribeToOutputStream>, Status> {
         let (request, auth) = self.authenticate(request).await?;
 
-        let job_id = request.into_inner().job_id;
+        let GrpcOutputSubscription { job_id, tag } = request.into_inner();
         // validate that the job exists, the user has access, and the graph has a GrpcSink
         let details = jobs::get_job_details(&job_id, &auth, &self.client().await?).await?;
 
-        if !details
+        let web_sinks = details
             .job_graph
             .unwrap()
             .nodes
             .iter()
-            .any(|n| n.operator.contains("WebSink"))
-        {
+            .filter(|n| n.operator.contains("WebSink"))
+            .count();
+
+        if web_sinks == 0 {
             // TODO: make this check more robust
             return Err(Status::invalid_argument(format!(
                 "Job {} does not have a web sink",
@@ -609,9 +1273,7 @@ impl ApiGrpc for ApiServer {
 
         let (tx, rx) = tokio::sync::mpsc::channel(32);
 
-        let mut controller = ControllerGrpcClient::connect(self.controller_addr.clone())
-            .await
-            .map_err(log_and_map)?;
+        let mut controller = self.controller().await?;
 
         info!("connected to controller");
 
@@ -626,10 +1288,22 @@ impl ApiGrpc for ApiServer {
         info!("subscribed to output");
         tokio::spawn(async move {
             let _controller = controller;
+            // the stream is finished once every sink that we're streaming has finished
+            let mut remaining = if tag.is_some() { 1 } else { web_sinks };
             while let Some(d) = stream.next().await {
+                if let (Ok(d), Some(tag)) = (&d, &tag) {
+                    if d.tag != *tag {
+                        continue;
+                    }
+                }
+
                 if d.as_ref().map(|t| t.done).unwrap_or(false) {
-                    info!("Stream done for {}", job_id);
-                    break;
+                    remaining -= 1;
+                    if remaining == 0 {
+                        info!("Stream done for {}", job_id);
+                        break;
+                    }
+                    continue;
                 }
 
                 let v = d.map(|d| OutputData {
@@ -637,6 +1311,7 @@ impl ApiGrpc for ApiServer {
                     timestamp: d.timestamp,
                     key: d.key,
                     value: d.value,
+                    tag: d.tag,
                 });
 
                 if tx.send(v).await.is_err() {
@@ -649,16 +1324,62 @@ impl ApiGrpc for ApiServer {
 
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    async fn get_updating_output_state(
+        &self,
+        request: Request<UpdatingOutputStateReq>,
+    ) -> Result<Response<UpdatingOutputStateResp>, Status> {
+        let (request, auth) = self.authenticate(request).await?;
+        let job_id = request.into_inner().job_id;
+
+        // validate that the job exists and user can access it
+        let _ = jobs::get_job_details(&job_id, &auth, &self.client().await?).await?;
+
+        let mut controller = self.controller().await?;
+
+        let state = controller
+            .get_updating_output_state(Request::new(grpc::UpdatingOutputStateReq { job_id }))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(UpdatingOutputStateResp {
+            rows: state
+                .rows
+                .into_iter()
+                .map(|row| MaterializedRow {
+                    key: row.key,
+                    value: row.value,
+                    count: row.count,
+                    tag: row.tag,
+                })
+                .collect(),
+            changes: state
+                .changes
+                .into_iter()
+                .map(|d| OutputData {
+                    operator_id: d.operator_id,
+                    timestamp: d.timestamp,
+                    key: d.key,
+                    value: d.value,
+                    tag: d.tag,
+                })
+                .collect(),
+            truncated: state.truncated,
+        }))
+    }
 }
 
 #[derive(OpenApi)]
 #[openapi(
     info(title = "Arroyo REST API", version = "1.0.0"),
     servers((url = "/api/")),
-    paths(ping, post_pipeline, patch_pipeline, get_pipeline, delete_pipeline, get_pipelines, get_jobs),
-    components(schemas(PipelinePost, PipelinePatch, Pipeline, Job, StopTypeRest, Udf, UdfLanguage, PipelineCollection, JobCollection)),
+    paths(ping, post_pipeline, post_pipeline_schema, post_pipeline_import, patch_pipeline, get_pipeline, delete_pipeline, get_pipelines, get_jobs, get_pipeline_health, get_pipeline_resources, promote_pipeline, post_masking_policy, get_masking_policies, delete_masking_policy, post_connection_profile, get_connection_profiles, delete_connection_profile, get_job_history),
+    components(schemas(PipelinePost, PipelineDependency, DependencyCondition, Instrumentation, PipelinePatch, PipelinePromotePost, SourceOverride, SourceOffsetPosition, PipelineSlo, SloIndicator, SloViolation, StateCompaction, StateCompactionState, PipelineHealth, HealthStatus, HealthIndicator, PipelineResources, OperatorResources, EstimateBasis, FailurePolicy, PoisonPillAction, PoisonPill, QueueConfig, RecoveryThrottle, StateCompression, StateStorageConfig, Pipeline, SqlWarning, PipelineSchemaPost, PipelineSchema, SinkSchema, SchemaField, PipelineImportPost, ImportDialect, PipelineImport, ImportedConnection, Job, StopTypeRest, Udf, UdfLanguage, PipelineCollection, JobCollection, MaskingPolicyPost, MaskingPolicy, MaskingAction, MaskingPolicyCollection, ConnectionProfilePost, ConnectionProfile, ConnectionProfileCollection, JobRun, JobRunEvent, OperatorRunMetrics, JobRunCollection)),
     tags(
         (name = "pipelines", description = "Pipeline management endpoints"),
+        (name = "masking_policies", description = "Masking policy management endpoints"),
+        (name = "connection_profiles", description = "Connection profile management endpoints"),
+        (name = "job_history", description = "Finished job run endpoints"),
         (name = "ping", description = "Ping endpoint"),
     )
 )]
@@ -16,7 +16,7 @@ use arroyo_api::ApiServer;
 use arroyo_rpc::grpc::api::api_grpc_server::ApiGrpcServer;
 use arroyo_server_common::{log_event, start_admin_server};
 use arroyo_types::{
-    grpc_port, ports, service_port, DatabaseConfig, CONTROLLER_ADDR_ENV, HTTP_PORT_ENV,
+    bind_addr, grpc_port, ports, service_port, DatabaseConfig, CONTROLLER_ADDR_ENV, HTTP_PORT_ENV,
 };
 
 const DEFAULT_EXPOSED_HEADERS: [&str; 3] =
@@ -87,7 +87,7 @@ async fn server(pool: Pool) {
         .unwrap_or_else(|_| format!("http://localhost:{}", ports::CONTROLLER_GRPC));
 
     let http_port = service_port("api", ports::API_HTTP, HTTP_PORT_ENV);
-    let addr = format!("0.0.0.0:{}", http_port).parse().unwrap();
+    let addr = bind_addr("api", http_port);
     let api_server_pool = pool.clone();
     let server = ApiServer {
         pool: api_server_pool,
@@ -109,9 +109,7 @@ async fn server(pool: Pool) {
         }
     });
 
-    let addr = format!("0.0.0.0:{}", grpc_port("api", ports::API_GRPC))
-        .parse()
-        .unwrap();
+    let addr = bind_addr("api", grpc_port("api", ports::API_GRPC));
     info!("Starting gRPC server on {:?}", addr);
 
     arroyo_server_common::grpc_server()
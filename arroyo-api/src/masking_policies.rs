@@ -0,0 +1,197 @@
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::GenericClient;
+use http::StatusCode;
+use tonic::Status;
+
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_sql::masking;
+
+use crate::queries::api_queries::{self, DbMaskingPolicy};
+use crate::rest::AppState;
+use crate::rest_types::{MaskingAction, MaskingPolicy, MaskingPolicyCollection, MaskingPolicyPost};
+use crate::rest_utils::{authenticate, client, log_and_map_rest, ApiError, BearerAuth, ErrorResp};
+use crate::{handle_db_error, log_and_map, to_micros, AuthData};
+
+impl From<MaskingAction> for masking::MaskingAction {
+    fn from(value: MaskingAction) -> Self {
+        match value {
+            MaskingAction::Redact => masking::MaskingAction::Redact,
+            MaskingAction::Hash => masking::MaskingAction::Hash,
+            MaskingAction::Truncate { length } => masking::MaskingAction::Truncate { length },
+        }
+    }
+}
+
+impl TryFrom<DbMaskingPolicy> for MaskingPolicy {
+    type Error = serde_json::Error;
+
+    fn try_from(value: DbMaskingPolicy) -> Result<Self, Self::Error> {
+        Ok(MaskingPolicy {
+            id: value.pub_id,
+            name: value.name,
+            column: value.column_name,
+            sink: value.sink_name,
+            action: serde_json::from_value(value.action)?,
+            created_at: to_micros(value.created_at),
+        })
+    }
+}
+
+/// Loads the organization's masking policies for planning a pipeline
+pub(crate) async fn get_policies<C: GenericClient>(
+    auth: &AuthData,
+    client: &C,
+) -> Result<Vec<masking::MaskingPolicy>, Status> {
+    api_queries::get_masking_policies()
+        .bind(client, &auth.organization_id)
+        .all()
+        .await
+        .map_err(log_and_map)?
+        .into_iter()
+        .map(|p| {
+            let policy: MaskingPolicy = p.try_into().map_err(log_and_map)?;
+            Ok(masking::MaskingPolicy {
+                name: policy.name,
+                column: policy.column,
+                sink: policy.sink,
+                action: policy.action.into(),
+            })
+        })
+        .collect()
+}
+
+fn bad_request(message: impl Into<String>) -> ErrorResp {
+    ErrorResp {
+        status_code: StatusCode::BAD_REQUEST,
+        message: message.into(),
+    }
+}
+
+/// Create a masking policy
+///
+/// The policy applies to pipelines created after it; existing pipelines are unaffected.
+#[utoipa::path(
+    post,
+    path = "/v1/masking_policies",
+    tag = "masking_policies",
+    request_body = MaskingPolicyPost,
+    responses(
+        (status = 200, description = "Created masking policy", body = MaskingPolicy),
+    ),
+)]
+pub async fn post_masking_policy(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(policy_post), _): WithRejection<Json<MaskingPolicyPost>, ApiError>,
+) -> Result<Json<MaskingPolicy>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    if policy_post.name.is_empty() {
+        return Err(bad_request("Masking policy name must not be empty"));
+    }
+
+    if policy_post.column.is_empty() {
+        return Err(bad_request("Masking policy column must not be empty"));
+    }
+
+    if let MaskingAction::Truncate { length: 0 } = policy_post.action {
+        return Err(bad_request("Truncation length must be greater than 0"));
+    }
+
+    let pub_id = generate_id(IdTypes::MaskingPolicy);
+    let created_at = api_queries::create_masking_policy()
+        .bind(
+            &client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &policy_post.name,
+            &policy_post.column,
+            &policy_post.sink,
+            &serde_json::to_value(&policy_post.action).unwrap(),
+        )
+        .one()
+        .await
+        .map_err(|e| handle_db_error("masking policy", e))?;
+
+    Ok(Json(MaskingPolicy {
+        id: pub_id,
+        name: policy_post.name,
+        column: policy_post.column,
+        sink: policy_post.sink,
+        action: policy_post.action,
+        created_at: to_micros(created_at),
+    }))
+}
+
+/// List all masking policies
+#[utoipa::path(
+    get,
+    path = "/v1/masking_policies",
+    tag = "masking_policies",
+    responses(
+        (status = 200, description = "Got masking policies collection", body = MaskingPolicyCollection),
+    ),
+)]
+pub async fn get_masking_policies(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<MaskingPolicyCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let policies = api_queries::get_masking_policies()
+        .bind(&client, &auth_data.organization_id)
+        .all()
+        .await
+        .map_err(log_and_map_rest)?
+        .into_iter()
+        .map(|p| p.try_into())
+        .collect::<Result<Vec<MaskingPolicy>, _>>()
+        .map_err(log_and_map_rest)?;
+
+    Ok(Json(MaskingPolicyCollection {
+        data: policies,
+        has_more: false,
+    }))
+}
+
+/// Delete a masking policy
+///
+/// Pipelines that were created while the policy existed continue to apply it.
+#[utoipa::path(
+    delete,
+    path = "/v1/masking_policies/{id}",
+    tag = "masking_policies",
+    params(
+        ("id" = String, Path, description = "Masking policy id")
+    ),
+    responses(
+        (status = 200, description = "Deleted masking policy"),
+    ),
+)]
+pub async fn delete_masking_policy(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(policy_pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let count = api_queries::delete_masking_policy()
+        .bind(&client, &policy_pub_id, &auth_data.organization_id)
+        .await
+        .map_err(log_and_map_rest)?;
+
+    if count != 1 {
+        return Err(ErrorResp {
+            status_code: StatusCode::NOT_FOUND,
+            message: "Masking policy not found".to_string(),
+        });
+    }
+
+    Ok(())
+}
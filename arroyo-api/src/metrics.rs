@@ -2,23 +2,46 @@ use base64::engine::general_purpose;
 use base64::Engine;
 use cornucopia_async::GenericClient;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{collections::HashMap, env, time::SystemTime};
 
 use arroyo_rpc::grpc::api::{job_metrics_resp::OperatorMetrics, JobMetricsResp};
-use arroyo_rpc::grpc::api::{Metric, SubtaskMetrics};
+use arroyo_rpc::grpc::api::{
+    HealthIndicator, JobHealthResp, JobHealthStatus, JobResourceEstimateResp, JoinSideMetrics,
+    Metric, OperatorCheckpointDetail, OperatorResourceEstimate, ResourceEstimateBasis,
+    SubtaskMetrics,
+};
 use arroyo_types::{
-    to_millis, API_METRICS_RATE_ENV, BYTES_RECV, BYTES_SENT, MESSAGES_RECV, MESSAGES_SENT,
-    TX_QUEUE_REM, TX_QUEUE_SIZE,
+    from_millis, to_millis, API_METRICS_RATE_ENV, BYTES_RECV, BYTES_SENT, JOIN_BUFFERED_KEYS,
+    JOIN_BUFFERED_ROWS, JOIN_EXPIRED_KEYS, JOIN_MATCHED_RECORDS, JOIN_ORPHANED_KEYS, JOIN_RECORDS,
+    MESSAGES_RECV, MESSAGES_SENT, SOURCE_LAG, TX_QUEUE_REM, TX_QUEUE_SIZE, WATERMARK,
 };
 use http::{header::AUTHORIZATION, HeaderMap, HeaderValue};
 use once_cell::sync::Lazy;
 use prometheus_http_query::Client;
+use time::OffsetDateTime;
 use tonic::Status;
 
-use crate::{jobs, AuthData};
+use crate::queries::api_queries;
+use crate::rest_types::PipelineSlo;
+use crate::{jobs, log_and_map, AuthData};
 
 const METRICS_GRANULARITY_SECS: f64 = 5.0;
 
+// thresholds for pipelines that don't set them in their SLO
+const DEFAULT_MAX_SOURCE_LAG: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_MAX_WATERMARK_LAG: Duration = Duration::from_secs(5 * 60);
+// by default, a job is behind on checkpointing once it has missed this many checkpoints
+const DEFAULT_MISSED_CHECKPOINTS: u32 = 3;
+
+// window over which throughput and backpressure are averaged for resource estimates
+const ESTIMATE_WINDOW: &str = "5m";
+// operators whose input queues are fuller than this on average are scaled up
+const MAX_BACKPRESSURE: f64 = 0.2;
+// a single subtask can comfortably process at least this many messages per second, so operators
+// that receive less than this per subtask without being backpressured are scaled down
+const MIN_SUBTASK_THROUGHPUT: f64 = 1000.0;
+
 static METRICS_CLIENT: Lazy<Client> = Lazy::new(|| {
     let mut headers = HeaderMap::new();
     if let Ok(basic_auth) = std::env::var("PROM_AUTH") {
@@ -175,6 +198,8 @@ pub(crate) async fn get_metrics(
                         messages_recv: vec![],
                         messages_sent: vec![],
                         backpressure: vec![],
+                        join_left: None,
+                        join_right: None,
                     });
 
                     match q {
@@ -186,6 +211,9 @@ pub(crate) async fn get_metrics(
                     };
                 }
             }
+
+            add_join_metrics(&mut metrics, &job_id, run_id, &rate, start, end).await?;
+
             Ok(JobMetricsResp {
                 job_id,
                 start_time: start as u64 * 1000,
@@ -199,3 +227,360 @@ pub(crate) async fn get_metrics(
         ))),
     }
 }
+
+#[derive(Copy, Clone)]
+enum JoinMetric {
+    BufferedRows,
+    BufferedKeys,
+    MatchRate,
+    ExpiredKeys,
+    OrphanedKeys,
+}
+
+/// Adds the metrics that join operators report for each side of the join to those of their
+/// subtasks
+async fn add_join_metrics(
+    metrics: &mut HashMap<String, OperatorMetrics>,
+    job_id: &str,
+    run_id: u64,
+    rate: &str,
+    start: i64,
+    end: i64,
+) -> Result<(), Status> {
+    use JoinMetric::*;
+
+    let labels = format!("job_id=\"{}\",run_id=\"{}\"", job_id, run_id);
+    let rate_of = |metric: &str| format!("rate({}{{{}}}[{}])", metric, labels, rate);
+
+    let queries = [
+        (
+            BufferedRows,
+            format!("{}{{{}}}", JOIN_BUFFERED_ROWS, labels),
+        ),
+        (
+            BufferedKeys,
+            format!("{}{{{}}}", JOIN_BUFFERED_KEYS, labels),
+        ),
+        (
+            MatchRate,
+            format!(
+                "{} / {}",
+                rate_of(JOIN_MATCHED_RECORDS),
+                rate_of(JOIN_RECORDS)
+            ),
+        ),
+        (ExpiredKeys, rate_of(JOIN_EXPIRED_KEYS)),
+        (OrphanedKeys, rate_of(JOIN_ORPHANED_KEYS)),
+    ];
+
+    let results = futures::future::try_join_all(queries.iter().map(|(_, query)| {
+        METRICS_CLIENT
+            .query_range(query.clone(), start, end, METRICS_GRANULARITY_SECS)
+            .get()
+    }))
+    .await
+    .map_err(|e| Status::internal(format!("Failed to query prometheus: {}", e)))?;
+
+    for ((metric, _), result) in queries.iter().zip(results) {
+        for v in result.data().as_matrix().into_iter().flatten() {
+            let (Some(operator_id), Some(subtask_idx), Some(side)) = (
+                v.metric().get("operator_id"),
+                v.metric()
+                    .get("subtask_idx")
+                    .and_then(|i| u32::from_str(i).ok()),
+                v.metric().get("side"),
+            ) else {
+                continue;
+            };
+
+            let subtask = metrics
+                .entry(operator_id.clone())
+                .or_insert(OperatorMetrics {
+                    subtasks: HashMap::new(),
+                })
+                .subtasks
+                .entry(subtask_idx)
+                .or_default();
+            let side = match side.as_str() {
+                "left" => subtask
+                    .join_left
+                    .get_or_insert_with(JoinSideMetrics::default),
+                "right" => subtask
+                    .join_right
+                    .get_or_insert_with(JoinSideMetrics::default),
+                _ => continue,
+            };
+
+            let data = v
+                .samples()
+                .iter()
+                // the match rate is undefined while a side receives no records
+                .filter(|s| s.value().is_finite())
+                .map(|s| Metric {
+                    time: (s.timestamp() * 1000.0 * 1000.0) as u64,
+                    value: s.value(),
+                })
+                .collect();
+
+            match metric {
+                BufferedRows => side.buffered_rows = data,
+                BufferedKeys => side.buffered_keys = data,
+                MatchRate => side.match_rate = data,
+                ExpiredKeys => side.expired_keys = data,
+                OrphanedKeys => side.orphaned_keys = data,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn query_instant(query: String) -> Result<Option<f64>, Status> {
+    let result = METRICS_CLIENT
+        .query(query)
+        .get()
+        .await
+        .map_err(|e| Status::internal(format!("Failed to query prometheus: {}", e)))?;
+
+    Ok(result
+        .data()
+        .as_vector()
+        .and_then(|v| v.first())
+        .map(|v| v.sample().value())
+        .filter(|v| v.is_finite()))
+}
+
+fn indicator(value: Option<Duration>, threshold: Duration) -> HealthIndicator {
+    HealthIndicator {
+        value_micros: value.map(|v| v.as_micros() as u64),
+        threshold_micros: threshold.as_micros() as u64,
+        violated: value.map(|v| v > threshold).unwrap_or(false),
+    }
+}
+
+/// Determines whether a job is keeping up with its inputs, by comparing how far behind its
+/// sources and watermarks are and how long it has been since it last checkpointed against the
+/// pipeline's SLO
+pub(crate) async fn get_health(
+    job_id: String,
+    auth: AuthData,
+    client: &impl GenericClient,
+) -> Result<JobHealthResp, Status> {
+    let config = api_queries::get_job_health_config()
+        .bind(client, &auth.organization_id, &job_id)
+        .opt()
+        .await
+        .map_err(log_and_map)?
+        .ok_or_else(|| Status::not_found(format!("There is no job with id '{}'", job_id)))?;
+
+    let slo: PipelineSlo = config
+        .slo
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(log_and_map)?
+        .unwrap_or_default();
+
+    let max_source_lag = slo
+        .max_source_lag_micros
+        .map(Duration::from_micros)
+        .unwrap_or(DEFAULT_MAX_SOURCE_LAG);
+    let max_watermark_lag = slo
+        .max_watermark_lag_micros
+        .map(Duration::from_micros)
+        .unwrap_or(DEFAULT_MAX_WATERMARK_LAG);
+    let max_checkpoint_age = slo
+        .max_checkpoint_age_micros
+        .map(Duration::from_micros)
+        .unwrap_or(
+            Duration::from_micros(config.checkpoint_interval_micros as u64)
+                * DEFAULT_MISSED_CHECKPOINTS,
+        );
+
+    if config.state.as_deref() != Some("Running") {
+        return Ok(JobHealthResp {
+            job_id,
+            status: JobHealthStatus::HealthUnknown as i32,
+            source_lag: Some(indicator(None, max_source_lag)),
+            watermark_lag: Some(indicator(None, max_watermark_lag)),
+            checkpoint_age: Some(indicator(None, max_checkpoint_age)),
+        });
+    }
+
+    let labels = format!(
+        "job_id=\"{}\",run_id=\"{}\"",
+        job_id,
+        config.run_id.unwrap_or_default()
+    );
+
+    let (source_lag, watermark) = tokio::try_join!(
+        query_instant(format!("max({}{{{}}})", SOURCE_LAG, labels)),
+        // operators that haven't received a watermark yet report 0
+        query_instant(format!("min({}{{{}}} > 0)", WATERMARK, labels)),
+    )?;
+
+    let now = SystemTime::now();
+    let source_lag = source_lag.map(|lag| Duration::from_millis(lag as u64));
+    let watermark_lag = watermark.map(|watermark| {
+        now.duration_since(from_millis(watermark as u64))
+            .unwrap_or_default()
+    });
+
+    // a job that has just started (or restored from an old checkpoint) hasn't fallen behind
+    // on checkpointing until it has been running for a while
+    let last_checkpoint = api_queries::get_last_checkpoint_time()
+        .bind(client, &job_id, &auth.organization_id)
+        .one()
+        .await
+        .map_err(log_and_map)?
+        .into_iter()
+        .chain(config.start_time)
+        .max();
+    let checkpoint_age = last_checkpoint.map(|t| {
+        (OffsetDateTime::now_utc() - t)
+            .try_into()
+            .unwrap_or_default()
+    });
+
+    let indicators = [
+        indicator(source_lag, max_source_lag),
+        indicator(watermark_lag, max_watermark_lag),
+        indicator(checkpoint_age, max_checkpoint_age),
+    ];
+
+    let status = if indicators.iter().any(|i| i.violated) {
+        JobHealthStatus::Behind
+    } else if indicators.iter().all(|i| i.value_micros.is_none()) {
+        JobHealthStatus::HealthUnknown
+    } else {
+        JobHealthStatus::Healthy
+    };
+
+    let [source_lag, watermark_lag, checkpoint_age] = indicators;
+
+    Ok(JobHealthResp {
+        job_id,
+        status: status as i32,
+        source_lag: Some(source_lag),
+        watermark_lag: Some(watermark_lag),
+        checkpoint_age: Some(checkpoint_age),
+    })
+}
+
+async fn query_by_operator(query: String) -> Result<HashMap<String, f64>, Status> {
+    let result = METRICS_CLIENT
+        .query(query)
+        .get()
+        .await
+        .map_err(|e| Status::internal(format!("Failed to query prometheus: {}", e)))?;
+
+    Ok(result
+        .data()
+        .as_vector()
+        .map(|v| {
+            v.iter()
+                .filter(|v| v.sample().value().is_finite())
+                .filter_map(|v| Some((v.metric().get("operator_id")?.clone(), v.sample().value())))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Estimates the slots and state a job needs and recommends a parallelism for each operator.
+/// Operators whose input queues are filling up are scaled up in proportion to how full they
+/// are, while operators receiving little data are scaled down; for jobs that aren't running,
+/// only the current plan and the state in the last checkpoint are reported.
+pub(crate) async fn get_resource_estimate(
+    job_id: String,
+    auth: AuthData,
+    client: &impl GenericClient,
+) -> Result<JobResourceEstimateResp, Status> {
+    let details = jobs::get_job_details(&job_id, &auth, client).await?;
+    let status = details.job_status.unwrap();
+    let graph = details.job_graph.unwrap();
+
+    let state_bytes: HashMap<String, u64> = api_queries::get_last_checkpoint_operators()
+        .bind(client, &job_id, &auth.organization_id)
+        .opt()
+        .await
+        .map_err(log_and_map)?
+        .flatten()
+        .map(serde_json::from_value::<HashMap<String, OperatorCheckpointDetail>>)
+        .transpose()
+        .map_err(log_and_map)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(operator_id, op)| (operator_id, op.tasks.values().filter_map(|t| t.bytes).sum()))
+        .collect();
+
+    let (messages, backpressure) = if status.state == "Running" {
+        let labels = format!("job_id=\"{}\",run_id=\"{}\"", job_id, status.run_id);
+        tokio::try_join!(
+            query_by_operator(format!(
+                "sum by (operator_id) (rate({}{{{}}}[{}]))",
+                MESSAGES_RECV, labels, ESTIMATE_WINDOW
+            )),
+            // as in get_metrics, add 1 to account for queues that haven't reported yet
+            query_by_operator(format!(
+                "max by (operator_id) (1 - (avg_over_time({}{{{}}}[{}]) + 1) / (avg_over_time({}{{{}}}[{}]) + 1))",
+                TX_QUEUE_REM, labels, ESTIMATE_WINDOW, TX_QUEUE_SIZE, labels, ESTIMATE_WINDOW
+            )),
+        )?
+    } else {
+        (HashMap::new(), HashMap::new())
+    };
+
+    let operators: Vec<_> = graph
+        .nodes
+        .iter()
+        .map(|node| {
+            // backpressure is reported by the sender, so an operator that can't keep up shows
+            // up as full queues on the operators that feed it
+            let input_backpressure = graph
+                .edges
+                .iter()
+                .filter(|e| e.dest_id == node.node_id)
+                .filter_map(|e| backpressure.get(&e.src_id).copied())
+                .reduce(f64::max);
+            let messages_per_second = messages.get(&node.node_id).copied();
+
+            let parallelism = node.parallelism as f64;
+            let recommended_parallelism = match (input_backpressure, messages_per_second) {
+                (Some(b), _) if b > MAX_BACKPRESSURE => (parallelism * (1.0 + b)).ceil(),
+                (Some(_), Some(m)) if m < parallelism * MIN_SUBTASK_THROUGHPUT => {
+                    (m / MIN_SUBTASK_THROUGHPUT).ceil().max(1.0)
+                }
+                _ => parallelism,
+            } as u32;
+
+            OperatorResourceEstimate {
+                operator_id: node.node_id.clone(),
+                parallelism: node.parallelism,
+                recommended_parallelism,
+                messages_per_second,
+                headroom: input_backpressure.map(|b| 1.0 - b),
+                state_bytes: state_bytes.get(&node.node_id).copied(),
+            }
+        })
+        .collect();
+
+    let basis = if messages.is_empty() && backpressure.is_empty() {
+        ResourceEstimateBasis::Plan
+    } else {
+        ResourceEstimateBasis::Metrics
+    };
+
+    Ok(JobResourceEstimateResp {
+        job_id,
+        basis: basis as i32,
+        // each slot runs one subtask of every operator
+        slots: operators.iter().map(|o| o.parallelism).max().unwrap_or(0),
+        recommended_slots: operators
+            .iter()
+            .map(|o| o.recommended_parallelism)
+            .max()
+            .unwrap_or(0),
+        state_bytes: (!state_bytes.is_empty()).then(|| state_bytes.values().sum()),
+        headroom: operators.iter().filter_map(|o| o.headroom).reduce(f64::min),
+        operators,
+    })
+}
@@ -446,6 +446,7 @@ mod tests {
                 operator: "NullSource".to_string(),
                 config: "".to_string(),
                 description: "Null".to_string(),
+                router: None,
             }),
             parallelism: 5,
         });
@@ -473,6 +474,7 @@ mod tests {
             operator: arroyo_datastream::Operator::Window {
                 typ: arroyo_datastream::WindowType::Tumbling {
                     width: Duration::from_secs(10),
+                    offset: Duration::ZERO,
                 },
                 agg: None,
                 flatten: false,
@@ -492,6 +494,7 @@ mod tests {
                 operator: "ConsoleSink".to_string(),
                 config: "".to_string(),
                 description: "ConsoleSink".to_string(),
+                router: None,
             }),
             parallelism: 5,
         });
@@ -562,7 +565,8 @@ mod tests {
         assert_eq!(
             arroyo_datastream::Operator::Window {
                 typ: arroyo_datastream::WindowType::Tumbling {
-                    width: Duration::from_secs(10)
+                    width: Duration::from_secs(10),
+                    offset: Duration::ZERO,
                 },
                 agg: Some(WindowAgg::Count),
                 flatten: false,
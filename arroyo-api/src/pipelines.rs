@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 use anyhow::Context;
@@ -13,24 +14,29 @@ use tonic::{Request, Status};
 use tracing::warn;
 
 use crate::rest_types::{
-    Job, JobCollection, Pipeline, PipelineCollection, PipelinePatch, PipelinePost,
+    Job, JobCollection, Pipeline, PipelineCollection, PipelineHealth, PipelineImport,
+    PipelineImportPost, PipelinePatch, PipelinePost, PipelinePromotePost, PipelineResources,
+    PipelineSchema, PipelineSchemaPost, SqlWarning as SqlWarningRest,
 };
 use arroyo_datastream::{ConnectorOp, Operator, Program};
 use arroyo_rpc::grpc::api::api_grpc_server::ApiGrpc;
 use arroyo_rpc::grpc::api::{
-    self, create_pipeline_req, CreatePipelineReq, CreateSqlJob, CreateUdf, PipelineDef,
-    PipelineGraphReq, PipelineGraphResp, PipelineProgram, SqlError, SqlErrors, Udf, UdfLanguage,
-    UpdateJobReq,
+    self, create_pipeline_req, CreatePipelineReq, CreateSqlJob, CreateUdf, JobDependency, JobEnv,
+    JobHealthReq, JobResourceEstimateReq, PipelineDef, PipelineGraphReq, PipelineGraphResp,
+    PipelineProgram, SqlError, SqlErrors, SqlWarning, Udf, UdfLanguage, UpdateJobReq,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
-use arroyo_sql::{ArroyoSchemaProvider, SqlConfig};
+use arroyo_sql::{ArroyoSchemaProvider, CompiledSql, SqlConfig};
+use arroyo_types::UdfSandbox;
 
 use crate::queries::api_queries;
 use crate::queries::api_queries::{DbPipeline, DbPipelineJob, DbPipelineRest};
 use crate::rest::AppState;
 use crate::rest_utils::{authenticate, client, log_and_map_rest, ApiError, BearerAuth, ErrorResp};
 use crate::types::public::{PipelineType, StopMode};
-use crate::{connection_tables, to_micros};
+use crate::{
+    connection_profiles, connection_tables, connections, jobs, masking_policies, to_micros,
+};
 use crate::{handle_db_error, log_and_map, optimizations, required_field, AuthData};
 use create_pipeline_req::Config::Sql;
 
@@ -38,7 +44,7 @@ async fn compile_sql<'e, E>(
     sql: &CreateSqlJob,
     auth_data: &AuthData,
     tx: &E,
-) -> Result<(Program, Vec<i64>), Status>
+) -> Result<CompiledSql, Status>
 where
     E: GenericClient,
 {
@@ -57,6 +63,24 @@ where
         }
     }
 
+    let profiles =
+        connection_profiles::Profiles::for_environment(auth_data, sql.environment.as_deref(), tx)
+            .await?;
+
+    for connection in connections::get_connections(auth_data, tx).await? {
+        // connections that can't be used in this environment are left out
+        if profiles.check(&connection).is_ok() {
+            schema_provider.add_saved_connection(
+                &connection.name,
+                &connection.connector,
+                &profiles.config(&connection),
+            );
+        }
+    }
+
+    // the connections of the tables the query uses are checked once it has been planned
+    let mut table_connections = HashMap::new();
+
     for table in connection_tables::get(auth_data, tx).await? {
         let Some(connector) = connector_for_type(&table.connector) else {
             warn!("Saved table found with unknown connector {}", table.connector);
@@ -69,7 +93,8 @@ where
                 &table.name,
                 &table
                     .connection
-                    .map(|c| c.config.clone())
+                    .as_ref()
+                    .map(|c| profiles.config(c))
                     .unwrap_or_else(|| "{}".to_string()),
                 &table.config,
                 table.schema.as_ref(),
@@ -77,13 +102,23 @@ where
             .map_err(log_and_map)?;
 
         schema_provider.add_connector_table(connection);
+        if let Some(connection) = table.connection {
+            table_connections.insert(table.id, connection);
+        }
+    }
+
+    for policy in masking_policies::get_policies(auth_data, tx).await? {
+        schema_provider.add_masking_policy(policy);
     }
 
-    let (program, connections) = arroyo_sql::parse_and_get_program(
+    let compiled = arroyo_sql::compile_sql(
         &sql.query,
         schema_provider,
         SqlConfig {
             default_parallelism: sql.parallelism as usize,
+            ordered: sql.ordered,
+            preserve_identifier_case: sql.preserve_identifier_case,
+            udf_sandbox: UdfSandbox::from_env(),
         },
     )
     .await
@@ -93,7 +128,23 @@ where
         Status::invalid_argument(format!("{}", err.root_cause()))
     })?;
 
-    Ok((program, connections))
+    for id in &compiled.connection_ids {
+        if let Some(connection) = table_connections.get(id) {
+            profiles.check(connection)?;
+        }
+    }
+
+    Ok(compiled)
+}
+
+fn to_proto_warnings(warnings: Vec<arroyo_sql::SqlWarning>) -> Vec<SqlWarning> {
+    warnings
+        .into_iter()
+        .map(|w| SqlWarning {
+            code: w.code.to_string(),
+            message: w.message,
+        })
+        .collect()
 }
 
 fn set_parallelism(program: &mut Program, parallelism: usize) {
@@ -102,18 +153,42 @@ fn set_parallelism(program: &mut Program, parallelism: usize) {
     }
 }
 
+/// The tag that identifies the output of a sink in a preview: the name of the table that it writes
+/// to (SQL sinks have ids like `sink_{table}_{index}`), made unique if several statements write to
+/// the same table
+fn preview_tag(operator_id: &str, used: &mut HashSet<String>) -> String {
+    let name = operator_id
+        .strip_prefix("sink_")
+        .and_then(|id| id.rsplit_once('_'))
+        .map(|(name, _)| name)
+        .unwrap_or(operator_id);
+
+    let mut tag = name.to_string();
+    let mut n = 1;
+    while !used.insert(tag.clone()) {
+        n += 1;
+        tag = format!("{}_{}", name, n);
+    }
+    tag
+}
+
+/// Compiles and stores a pipeline; with `shadow_sinks` (as for previews), its sinks are replaced
+/// with web sinks
 pub(crate) async fn create_pipeline<'a>(
     req: CreatePipelineReq,
     pub_id: &str,
+    shadow_sinks: bool,
     auth: AuthData,
     tx: &Transaction<'a>,
-) -> Result<i64, Status> {
+) -> Result<(i64, Vec<SqlWarning>), Status> {
     let pipeline_type;
     let mut program;
     let connections;
+    let warnings;
     let text;
     let udfs: Option<Vec<Udf>>;
     let is_preview;
+    let environment;
 
     match req.config.ok_or_else(|| required_field("config"))? {
         create_pipeline_req::Config::Program(bytes) => {
@@ -128,9 +203,11 @@ pub(crate) async fn create_pipeline<'a>(
                 .try_into()
                 .map_err(log_and_map)?;
             connections = vec![];
+            warnings = vec![];
             text = None;
             udfs = None;
             is_preview = false;
+            environment = None;
         }
         Sql(sql) => {
             if sql.parallelism > auth.org_metadata.max_parallelism as u64 {
@@ -142,7 +219,11 @@ pub(crate) async fn create_pipeline<'a>(
             }
 
             pipeline_type = PipelineType::sql;
-            (program, connections) = compile_sql(&sql, &auth, tx).await?;
+            let compiled = compile_sql(&sql, &auth, tx).await?;
+            program = compiled.program;
+            program.instrumentation = sql.instrumentation.map(Into::into);
+            connections = compiled.connection_ids;
+            warnings = to_proto_warnings(compiled.warnings);
             text = Some(sql.query);
             udfs = Some(
                 sql.udfs
@@ -154,9 +235,18 @@ pub(crate) async fn create_pipeline<'a>(
                     .collect(),
             );
             is_preview = sql.preview;
+            environment = sql.environment;
         }
     };
 
+    if let Some(instrumentation) = &program.instrumentation {
+        if !(0.0..=1.0).contains(&instrumentation.record_sample_rate) {
+            return Err(Status::invalid_argument(
+                "instrumentation record_sample_rate must be between 0 and 1",
+            ));
+        }
+    }
+
     optimizations::optimize(&mut program.graph);
 
     if program.graph.node_count() > auth.org_metadata.max_operators as usize {
@@ -177,11 +267,13 @@ pub(crate) async fn create_pipeline<'a>(
 
     set_parallelism(&mut program, 1);
 
-    if is_preview {
+    if is_preview || shadow_sinks {
+        let mut tags = HashSet::new();
         for node in program.graph.node_weights_mut() {
             // if it is a connector sink or switch to a web sink
             if let Operator::ConnectorSink { .. } = node.operator {
-                node.operator = Operator::ConnectorSink(ConnectorOp::web_sink());
+                let tag = preview_tag(&node.operator_id, &mut tags);
+                node.operator = Operator::ConnectorSink(ConnectorOp::web_sink(&tag));
             }
         }
     }
@@ -205,6 +297,7 @@ pub(crate) async fn create_pipeline<'a>(
             &text,
             &udfs.map(|t| serde_json::to_value(t).unwrap()),
             &program,
+            &environment,
         )
         .one()
         .await
@@ -224,7 +317,7 @@ pub(crate) async fn create_pipeline<'a>(
         }
     }
 
-    Ok(pipeline_id)
+    Ok((pipeline_id, warnings))
 }
 
 impl TryInto<PipelineDef> for DbPipeline {
@@ -250,6 +343,7 @@ impl TryInto<PipelineDef> for DbPipeline {
 impl Into<Pipeline> for DbPipelineRest {
     fn into(self) -> Pipeline {
         let udfs: Vec<Udf> = serde_json::from_value(self.udfs).unwrap();
+        let env = jobs::vars_to_env(self.env_vars);
         Pipeline {
             id: self.pub_id,
             name: self.name,
@@ -257,7 +351,11 @@ impl Into<Pipeline> for DbPipelineRest {
             udfs: udfs.into_iter().map(|v| v.into()).collect(),
             checkpoint_interval_micros: self.checkpoint_interval_micros as u64,
             stop: self.stop.into(),
+            env_vars: env.env_vars,
+            feature_flags: env.feature_flags,
+            environment: self.environment,
             created_at: to_micros(self.created_at),
+            warnings: vec![],
         }
     }
 }
@@ -273,6 +371,23 @@ impl Into<Job> for DbPipelineJob {
             finish_time: self.finish_time.map(to_micros),
             tasks: self.tasks.map(|t| t as u64),
             failure_message: self.failure_message,
+            poison_pill: self
+                .poison_pill
+                .and_then(|p| serde_json::from_value(p).ok()),
+            queue_config: self
+                .queue_config
+                .and_then(|c| serde_json::from_value::<arroyo_types::QueueConfig>(c).ok())
+                .unwrap_or_default()
+                .effective()
+                .into(),
+            slo_violations: self
+                .slo_violations
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default(),
+            waiting_for: self.waiting_for,
+            state_compaction: self
+                .state_compaction
+                .and_then(|c| serde_json::from_value(c).ok()),
             created_at: to_micros(self.created_at),
         }
     }
@@ -308,15 +423,27 @@ pub(crate) async fn sql_graph(
         parallelism: 1,
         udfs: req.udfs,
         preview: false,
+        env: None,
+        ordered: false,
+        preserve_identifier_case: req.preserve_identifier_case,
+        dependencies: vec![],
+        instrumentation: None,
+        environment: None,
+        stop_at_event_time_micros: None,
     };
 
     match compile_sql(&sql, &auth, client).await {
-        Ok((mut program, _)) => {
+        Ok(CompiledSql {
+            mut program,
+            warnings,
+            ..
+        }) => {
             optimizations::optimize(&mut program.graph);
             Ok(PipelineGraphResp {
                 result: Some(api::pipeline_graph_resp::Result::JobGraph(
                     program.as_job_graph(),
                 )),
+                warnings: to_proto_warnings(warnings),
             })
         }
         Err(err) => match err.code() {
@@ -326,6 +453,7 @@ pub(crate) async fn sql_graph(
                         message: err.message().to_string(),
                     }],
                 })),
+                warnings: vec![],
             }),
             _ => Err(err),
         },
@@ -352,6 +480,29 @@ pub async fn post_pipeline(
     let client = client(&state.pool).await?;
     let auth_data = authenticate(&state.pool, bearer_auth).await?;
 
+    // dependencies are on the pipeline's job; this assumes there is just one job per pipeline
+    let mut dependencies = vec![];
+    for dependency in pipeline_post.dependencies.unwrap_or_default() {
+        let job = api_queries::get_pipeline_jobs()
+            .bind(&client, &auth_data.organization_id, &dependency.pipeline_id)
+            .opt()
+            .await
+            .map_err(log_and_map_rest)?
+            .ok_or_else(|| ErrorResp {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!(
+                    "Pipeline depends on pipeline '{}', which does not exist",
+                    dependency.pipeline_id
+                ),
+            })?;
+
+        dependencies.push(JobDependency {
+            job_id: job.id,
+            condition: api::DependencyCondition::from(dependency.condition) as i32,
+            max_lag_micros: dependency.max_lag_micros,
+        });
+    }
+
     let create_pipeline_req = CreatePipelineReq {
         name: pipeline_post.name.to_string(),
         config: Some(Sql(CreateSqlJob {
@@ -366,12 +517,22 @@ pub async fn post_pipeline(
                 })
                 .collect(),
             preview: false,
+            env: Some(JobEnv {
+                env_vars: pipeline_post.env_vars.unwrap_or_default(),
+                feature_flags: pipeline_post.feature_flags.unwrap_or_default(),
+            }),
+            ordered: pipeline_post.ordered.unwrap_or_default(),
+            preserve_identifier_case: pipeline_post.preserve_identifier_case.unwrap_or_default(),
+            dependencies,
+            instrumentation: pipeline_post.instrumentation.map(Into::into),
+            environment: pipeline_post.environment,
+            stop_at_event_time_micros: pipeline_post.stop_at_event_time_micros,
         })),
     };
 
     let pipeline_pub_id = generate_id(IdTypes::Pipeline);
 
-    state
+    let warnings = state
         .grpc_api_server
         .start_or_preview(
             create_pipeline_req,
@@ -379,12 +540,211 @@ pub async fn post_pipeline(
             false,
             auth_data.clone(),
         )
-        .await?;
+        .await?
+        .into_inner()
+        .warnings;
 
-    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+    let mut pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+    pipeline.warnings = warnings
+        .into_iter()
+        .map(|w| SqlWarningRest {
+            code: w.code,
+            message: w.message,
+        })
+        .collect();
+    Ok(Json(pipeline))
+}
+
+/// Promote a pipeline to another environment
+///
+/// Creates a new pipeline with the same query, UDFs and environment variables, planned against
+/// its connections' profiles for the target environment, and starts a job for it. The promotion
+/// is atomic: if any connection the query uses has no profile for the environment, or the query
+/// fails to plan, nothing is created. The promoted pipeline is left running.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{id}/promote",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    request_body = PipelinePromotePost,
+    responses(
+        (status = 200, description = "Created pipeline and job in the target environment", body = Pipeline),
+    ),
+)]
+pub async fn promote_pipeline(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+    WithRejection(Json(promote_post), _): WithRejection<Json<PipelinePromotePost>, ApiError>,
+) -> Result<Json<Pipeline>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let source = api_queries::get_pipeline_rest()
+        .bind(&client, &pipeline_pub_id, &auth_data.organization_id)
+        .opt()
+        .await
+        .map_err(log_and_map_rest)?
+        .ok_or_else(|| ErrorResp {
+            status_code: StatusCode::NOT_FOUND,
+            message: "Pipeline not found".to_string(),
+        })?;
+
+    if source.r#type != PipelineType::sql {
+        return Err(ErrorResp {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "Only SQL pipelines can be promoted".to_string(),
+        });
+    }
+
+    if promote_post.environment.is_empty() {
+        return Err(ErrorResp {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "Environment must not be empty".to_string(),
+        });
+    }
+
+    if source.environment.as_ref() == Some(&promote_post.environment) {
+        return Err(ErrorResp {
+            status_code: StatusCode::BAD_REQUEST,
+            message: format!(
+                "Pipeline is already in environment '{}'",
+                promote_post.environment
+            ),
+        });
+    }
+
+    let udfs: Vec<Udf> = serde_json::from_value(source.udfs).map_err(log_and_map_rest)?;
+
+    let create_pipeline_req = CreatePipelineReq {
+        name: promote_post.name.unwrap_or(source.name),
+        config: Some(Sql(CreateSqlJob {
+            query: source.textual_repr,
+            parallelism: 1,
+            udfs: udfs
+                .into_iter()
+                .map(|u| CreateUdf {
+                    language: u.language,
+                    definition: u.definition,
+                })
+                .collect(),
+            preview: false,
+            env: Some(jobs::vars_to_env(source.env_vars)),
+            ordered: promote_post.ordered.unwrap_or_default(),
+            preserve_identifier_case: promote_post.preserve_identifier_case.unwrap_or_default(),
+            dependencies: vec![],
+            instrumentation: None,
+            environment: Some(promote_post.environment),
+            stop_at_event_time_micros: None,
+        })),
+    };
+
+    let promoted_pub_id = generate_id(IdTypes::Pipeline);
+
+    let warnings = state
+        .grpc_api_server
+        .start_or_preview(
+            create_pipeline_req,
+            promoted_pub_id.clone(),
+            false,
+            auth_data.clone(),
+        )
+        .await?
+        .into_inner()
+        .warnings;
+
+    let mut pipeline = query_pipeline_by_pub_id(&promoted_pub_id, &client, &auth_data).await?;
+    pipeline.warnings = warnings
+        .into_iter()
+        .map(|w| SqlWarningRest {
+            code: w.code,
+            message: w.message,
+        })
+        .collect();
     Ok(Json(pipeline))
 }
 
+/// Get the schema of the records each sink of a query would receive, without creating a pipeline
+///
+/// This can be used to check that a query's outputs match what its consumers expect before it is
+/// deployed.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/schema",
+    tag = "pipelines",
+    request_body = PipelineSchemaPost,
+    responses(
+        (status = 200, description = "Got sink schemas", body = PipelineSchema),
+    ),
+)]
+pub async fn post_pipeline_schema(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(schema_post), _): WithRejection<Json<PipelineSchemaPost>, ApiError>,
+) -> Result<Json<PipelineSchema>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let sql = CreateSqlJob {
+        query: schema_post.query,
+        parallelism: 1,
+        udfs: schema_post
+            .udfs
+            .into_iter()
+            .map(|u| CreateUdf {
+                language: 0,
+                definition: u.definition,
+            })
+            .collect(),
+        preview: false,
+        env: None,
+        ordered: false,
+        preserve_identifier_case: schema_post.preserve_identifier_case.unwrap_or_default(),
+        dependencies: vec![],
+        instrumentation: None,
+        environment: None,
+        stop_at_event_time_micros: None,
+    };
+
+    let compiled = compile_sql(&sql, &auth_data, &client).await?;
+
+    Ok(Json(PipelineSchema {
+        sinks: compiled.sinks.into_iter().map(|s| s.into()).collect(),
+    }))
+}
+
+/// Translate Flink SQL or ksqlDB statements to Arroyo SQL
+///
+/// Kafka tables are translated along with the queries over them, and the Kafka clusters they read
+/// from become connections, which must be created before the query is run. Statements and
+/// constructs that can't be translated are reported in `unsupported`.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/import",
+    tag = "pipelines",
+    request_body = PipelineImportPost,
+    responses(
+        (status = 200, description = "Translated query", body = PipelineImport),
+    ),
+)]
+pub async fn post_pipeline_import(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(import_post), _): WithRejection<Json<PipelineImportPost>, ApiError>,
+) -> Result<Json<PipelineImport>, ErrorResp> {
+    authenticate(&state.pool, bearer_auth).await?;
+
+    let imported = arroyo_sql::import::import(import_post.dialect.into(), &import_post.query)
+        .map_err(|e| ErrorResp {
+            status_code: StatusCode::BAD_REQUEST,
+            message: e.to_string(),
+        })?;
+
+    Ok(Json(imported.into()))
+}
+
 /// Update a pipeline
 #[utoipa::path(
     patch,
@@ -422,6 +782,24 @@ pub async fn patch_pipeline(
         checkpoint_interval_micros: pipeline_patch.checkpoint_interval_micros,
         stop: stop.map(|v| v as i32),
         parallelism: pipeline_patch.parallelism.map(|v| v as u32),
+        env: (pipeline_patch.env_vars.is_some() || pipeline_patch.feature_flags.is_some()).then(
+            || JobEnv {
+                env_vars: pipeline_patch.env_vars.unwrap_or_default(),
+                feature_flags: pipeline_patch.feature_flags.unwrap_or_default(),
+            },
+        ),
+        source_overrides: pipeline_patch
+            .source_overrides
+            .unwrap_or_default()
+            .into_iter()
+            .map(|o| o.into())
+            .collect(),
+        slo: pipeline_patch.slo.map(|slo| slo.into()),
+        failure_policy: pipeline_patch.failure_policy.map(|p| p.into()),
+        queue_config: pipeline_patch.queue_config.map(|c| c.into()),
+        stop_at_event_time_micros: pipeline_patch.stop_at_event_time_micros,
+        recovery_throttle: pipeline_patch.recovery_throttle.map(|t| t.into()),
+        state_storage: pipeline_patch.state_storage.map(|c| c.into()),
     };
 
     state
@@ -514,13 +892,15 @@ pub async fn delete_pipeline(
         .map(|j| j.into())
         .collect();
 
-    if jobs
-        .iter()
-        .any(|job| job.state != "Stopped" && job.state != "Finished" && job.state != "Failed")
-    {
+    if jobs.iter().any(|job| {
+        job.state != "Stopped"
+            && job.state != "Finished"
+            && job.state != "Failed"
+            && job.state != "NeedsAttention"
+    }) {
         return Err(ErrorResp {
             status_code: StatusCode::BAD_REQUEST,
-            message: "Pipeline's jobs must be in a terminal state (stopped, finished, or failed) before it can be deleted"
+            message: "Pipeline's jobs must be in a terminal state (stopped, finished, failed, or needs attention) before it can be deleted"
                 .to_string(),
         });
     }
@@ -574,6 +954,94 @@ pub async fn get_jobs(
     }))
 }
 
+/// Get the health of a pipeline's current job
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{id}/health",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    responses(
+        (status = 200, description = "Got pipeline health", body = PipelineHealth),
+    ),
+)]
+pub async fn get_pipeline_health(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+) -> Result<Json<PipelineHealth>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+
+    // jobs are ordered by creation time, so the first is the current one
+    let job = api_queries::get_pipeline_jobs()
+        .bind(&client, &auth_data.organization_id, &pipeline_pub_id)
+        .all()
+        .await
+        .map_err(log_and_map_rest)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ErrorResp {
+            status_code: StatusCode::NOT_FOUND,
+            message: "Pipeline has no jobs".to_string(),
+        })?;
+
+    let health = state
+        .grpc_api_server
+        .get_job_health(Request::new(JobHealthReq { job_id: job.id }))
+        .await?
+        .into_inner();
+
+    Ok(Json(health.into()))
+}
+
+/// Estimate the resources needed by a pipeline's current job and recommend a parallelism for each
+/// of its operators
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{id}/resources",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    responses(
+        (status = 200, description = "Got resource estimate", body = PipelineResources),
+    ),
+)]
+pub async fn get_pipeline_resources(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+) -> Result<Json<PipelineResources>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+
+    let job = api_queries::get_pipeline_jobs()
+        .bind(&client, &auth_data.organization_id, &pipeline_pub_id)
+        .all()
+        .await
+        .map_err(log_and_map_rest)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ErrorResp {
+            status_code: StatusCode::NOT_FOUND,
+            message: "Pipeline has no jobs".to_string(),
+        })?;
+
+    let estimate = state
+        .grpc_api_server
+        .get_job_resource_estimate(Request::new(JobResourceEstimateReq { job_id: job.id }))
+        .await?
+        .into_inner();
+
+    Ok(Json(estimate.into()))
+}
+
 async fn query_pipeline_by_pub_id(
     pipeline_pub_id: &String,
     client: &Object,
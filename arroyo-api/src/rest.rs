@@ -17,8 +17,15 @@ use tower_http::services::ServeDir;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::connection_profiles::{
+    delete_connection_profile, get_connection_profiles, post_connection_profile,
+};
+use crate::job_history::get_job_history;
+use crate::masking_policies::{delete_masking_policy, get_masking_policies, post_masking_policy};
 use crate::pipelines::{
-    delete_pipeline, get_jobs, get_pipeline, get_pipelines, patch_pipeline, post_pipeline,
+    delete_pipeline, get_jobs, get_pipeline, get_pipeline_health, get_pipeline_resources,
+    get_pipelines, patch_pipeline, post_pipeline, post_pipeline_import, post_pipeline_schema,
+    promote_pipeline,
 };
 use crate::rest_utils::ErrorResp;
 use crate::ApiDoc;
@@ -84,10 +91,25 @@ pub fn create_rest_app(server: ApiServer, pool: Pool) -> Router {
         .route("/ping", get(ping))
         .route("/pipelines", post(post_pipeline))
         .route("/pipelines", get(get_pipelines))
+        .route("/pipelines/schema", post(post_pipeline_schema))
+        .route("/pipelines/import", post(post_pipeline_import))
         .route("/pipelines/:id", patch(patch_pipeline))
         .route("/pipelines/:id", get(get_pipeline))
         .route("/pipelines/:id", delete(delete_pipeline))
         .route("/pipelines/:id/jobs", get(get_jobs))
+        .route("/pipelines/:id/health", get(get_pipeline_health))
+        .route("/pipelines/:id/resources", get(get_pipeline_resources))
+        .route("/pipelines/:id/promote", post(promote_pipeline))
+        .route("/masking_policies", post(post_masking_policy))
+        .route("/masking_policies", get(get_masking_policies))
+        .route("/masking_policies/:id", delete(delete_masking_policy))
+        .route("/connection_profiles", post(post_connection_profile))
+        .route("/connection_profiles", get(get_connection_profiles))
+        .route(
+            "/connection_profiles/:id",
+            delete(delete_connection_profile),
+        )
+        .route("/job_history", get(get_job_history))
         .fallback(api_fallback);
 
     Router::new()
@@ -1,7 +1,8 @@
 use crate::types::public::StopMode;
 use arroyo_rpc::grpc::api;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -11,6 +12,193 @@ pub struct PipelinePost {
     pub udfs: Vec<Udf>,
     pub preview: Option<bool>,
     pub parallelism: u64,
+    /// Environment variables available to the pipeline's UDFs
+    pub env_vars: Option<HashMap<String, String>>,
+    pub feature_flags: Option<HashMap<String, bool>>,
+    /// Preserve the event-time order of records with the same key through shuffles, at the cost
+    /// of buffering them until the watermark passes
+    pub ordered: Option<bool>,
+    /// Keep the case of unquoted identifiers in the query, rather than folding them to lowercase
+    pub preserve_identifier_case: Option<bool>,
+    /// Other pipelines that must meet a condition before this pipeline is first started; until
+    /// then, its job waits in the Created state
+    pub dependencies: Option<Vec<PipelineDependency>>,
+    /// Compile the pipeline with debug instrumentation, which is otherwise left out of its binary
+    pub instrumentation: Option<Instrumentation>,
+    /// The environment the pipeline runs in, like `staging`; its connections use their profiles
+    /// for this environment
+    pub environment: Option<String>,
+    /// Finish the pipeline once its watermark passes this event time (in microseconds since the
+    /// epoch), for backfills that should cover an exact time range; records after it are dropped
+    pub stop_at_event_time_micros: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Instrumentation {
+    /// The fraction of the records received by each operator that are logged, from 0 to 1
+    pub record_sample_rate: Option<f64>,
+    /// Log each access to operator state
+    pub trace_state: Option<bool>,
+}
+
+impl From<Instrumentation> for api::Instrumentation {
+    fn from(value: Instrumentation) -> Self {
+        api::Instrumentation {
+            record_sample_rate: value.record_sample_rate.unwrap_or_default(),
+            trace_state: value.trace_state.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DependencyCondition {
+    /// The pipeline has started running (or has finished)
+    Running,
+    /// The pipeline is running with a watermark within `maxLagMicros` of now, for example once it
+    /// has finished backfilling (or it has finished)
+    CaughtUp,
+    /// The pipeline has finished
+    Finished,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineDependency {
+    pub pipeline_id: String,
+    pub condition: DependencyCondition,
+    /// For the caughtUp condition, how far the pipeline's watermark may be behind now (defaults
+    /// to a minute)
+    pub max_lag_micros: Option<u64>,
+}
+
+impl From<DependencyCondition> for api::DependencyCondition {
+    fn from(value: DependencyCondition) -> Self {
+        match value {
+            DependencyCondition::Running => api::DependencyCondition::Running,
+            DependencyCondition::CaughtUp => api::DependencyCondition::CaughtUp,
+            DependencyCondition::Finished => api::DependencyCondition::Finished,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSchemaPost {
+    pub query: String,
+    pub udfs: Vec<Udf>,
+    pub preserve_identifier_case: Option<bool>,
+}
+
+/// The schema of the records each sink of a query receives
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSchema {
+    pub sinks: Vec<SinkSchema>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkSchema {
+    /// The name of the sink table, or `web` if the query has no sink
+    pub name: String,
+    /// Whether the sink receives Debezium-style updates rather than appends
+    pub updating: bool,
+    pub fields: Vec<SchemaField>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaField {
+    pub name: String,
+    /// The SQL type of the field, like `BIGINT`; struct fields have the type `STRUCT`
+    pub sql_type: String,
+    pub nullable: bool,
+    /// The fields of a struct field
+    pub fields: Vec<SchemaField>,
+}
+
+impl From<arroyo_sql::SinkSchema> for SinkSchema {
+    fn from(value: arroyo_sql::SinkSchema) -> Self {
+        SinkSchema {
+            name: value.name,
+            updating: value.updating,
+            fields: value.fields.into_iter().map(|f| f.into()).collect(),
+        }
+    }
+}
+
+impl From<arroyo_sql::SchemaField> for SchemaField {
+    fn from(value: arroyo_sql::SchemaField) -> Self {
+        SchemaField {
+            name: value.name,
+            sql_type: value.sql_type,
+            nullable: value.nullable,
+            fields: value.fields.into_iter().map(|f| f.into()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineImportPost {
+    pub dialect: ImportDialect,
+    pub query: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportDialect {
+    FlinkSql,
+    KsqlDb,
+}
+
+impl From<ImportDialect> for arroyo_sql::import::ImportDialect {
+    fn from(value: ImportDialect) -> Self {
+        match value {
+            ImportDialect::FlinkSql => arroyo_sql::import::ImportDialect::FlinkSql,
+            ImportDialect::KsqlDb => arroyo_sql::import::ImportDialect::KsqlDb,
+        }
+    }
+}
+
+/// A query translated to Arroyo SQL, along with the connections it needs
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineImport {
+    pub query: String,
+    /// Connections the query's tables refer to, which must be created before it can run
+    pub connections: Vec<ImportedConnection>,
+    /// Statements and constructs that couldn't be translated, or whose behavior changed
+    pub unsupported: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedConnection {
+    pub name: String,
+    pub connector: String,
+    /// The connection config as JSON
+    pub config: String,
+}
+
+impl From<arroyo_sql::import::ImportedQuery> for PipelineImport {
+    fn from(value: arroyo_sql::import::ImportedQuery) -> Self {
+        PipelineImport {
+            query: value.query,
+            connections: value
+                .connections
+                .into_iter()
+                .map(|c| ImportedConnection {
+                    name: c.name,
+                    connector: c.connector,
+                    config: c.config,
+                })
+                .collect(),
+            unsupported: value.unsupported,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -19,6 +207,414 @@ pub struct PipelinePatch {
     pub parallelism: Option<u64>,
     pub checkpoint_interval_micros: Option<u64>,
     pub stop: Option<StopType>,
+    /// Replaces the pipeline's environment variables and feature flags; changes take effect
+    /// the next time the pipeline is started
+    pub env_vars: Option<HashMap<String, String>>,
+    pub feature_flags: Option<HashMap<String, bool>>,
+    /// Restarts the pipeline from its latest checkpoint with the given sources reading from new
+    /// positions, ignoring their checkpointed offsets; all other state is preserved
+    pub source_overrides: Option<Vec<SourceOverride>>,
+    /// Thresholds used to determine the pipeline's health
+    pub slo: Option<PipelineSlo>,
+    /// How the pipeline responds to repeated failures
+    pub failure_policy: Option<FailurePolicy>,
+    /// Replaces the pipeline's queue config; changes take effect the next time the pipeline is
+    /// started
+    pub queue_config: Option<QueueConfig>,
+    /// Finish the pipeline once its watermark passes this event time (in microseconds since the
+    /// epoch); a running pipeline is restarted from its latest checkpoint to apply it
+    pub stop_at_event_time_micros: Option<u64>,
+    /// Replaces the pipeline's recovery throttle; changes take effect the next time the pipeline
+    /// is started
+    pub recovery_throttle: Option<RecoveryThrottle>,
+    /// Replaces the pipeline's state storage config; changes apply to state written after the
+    /// pipeline is next started
+    pub state_storage: Option<StateStorageConfig>,
+}
+
+/// Thresholds above which a pipeline is considered behind; unset thresholds use the defaults
+/// (5 minutes of source and watermark lag, and 3 missed checkpoints) when reporting its health.
+///
+/// The lag and idle thresholds that are set are also checked continuously while the pipeline
+/// runs; a job that exceeds any of them is degraded until it is back within them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSlo {
+    pub max_source_lag_micros: Option<u64>,
+    pub max_watermark_lag_micros: Option<u64>,
+    pub max_checkpoint_age_micros: Option<u64>,
+    /// How long each operator (by id) may go without processing a record, for example to require
+    /// that a sink writes data at least every minute
+    #[serde(default)]
+    pub max_idle_micros: HashMap<String, u64>,
+    /// Sent a POST request with the job's violations when it becomes degraded, when the
+    /// thresholds it exceeds change, and when it recovers
+    pub webhook_url: Option<String>,
+}
+
+impl From<PipelineSlo> for api::PipelineSlo {
+    fn from(value: PipelineSlo) -> Self {
+        api::PipelineSlo {
+            max_source_lag_micros: value.max_source_lag_micros,
+            max_watermark_lag_micros: value.max_watermark_lag_micros,
+            max_checkpoint_age_micros: value.max_checkpoint_age_micros,
+            max_idle_micros: value.max_idle_micros,
+            webhook_url: value.webhook_url,
+        }
+    }
+}
+
+impl From<api::PipelineSlo> for PipelineSlo {
+    fn from(value: api::PipelineSlo) -> Self {
+        PipelineSlo {
+            max_source_lag_micros: value.max_source_lag_micros,
+            max_watermark_lag_micros: value.max_watermark_lag_micros,
+            max_checkpoint_age_micros: value.max_checkpoint_age_micros,
+            max_idle_micros: value.max_idle_micros,
+            webhook_url: value.webhook_url,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SloIndicator {
+    SourceLag,
+    WatermarkLag,
+    /// The operator hasn't processed a record within its idle threshold
+    Idle,
+}
+
+/// A threshold of the pipeline's SLO that its running job exceeds
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SloViolation {
+    pub indicator: SloIndicator,
+    /// Set for idle thresholds
+    pub operator_id: Option<String>,
+    pub value_micros: u64,
+    pub threshold_micros: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum StateCompactionState {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// The progress of a compaction of a job's state, which rewrites the small files written by each
+/// checkpoint into larger ones while the job keeps running
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateCompaction {
+    pub state: StateCompactionState,
+    /// The checkpoint whose files are compacted
+    pub epoch: u32,
+    pub start_time_micros: u64,
+    pub finish_time_micros: Option<u64>,
+    pub operators: u32,
+    pub operators_compacted: u32,
+    pub files_read: u64,
+    pub files_written: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub error: Option<String>,
+}
+
+/// What to do when the pipeline keeps failing in the same operator each time it is restored from
+/// the same checkpoint, which usually means that a record reliably crashes the operator
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PoisonPillAction {
+    /// Keep restarting until the restart budget is exhausted, then fail the pipeline
+    #[default]
+    Fail,
+    /// Restart with the failing operator skipping records that cause it to fail; skipped records
+    /// are reported in the pipeline's error log
+    Skip,
+    /// Stop the pipeline in the NeedsAttention state until it is stopped and restarted
+    Park,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FailurePolicy {
+    /// How many times the pipeline may restart without becoming healthy before it is failed
+    /// (defaults to 10)
+    pub restart_budget: Option<u32>,
+    #[serde(default)]
+    pub on_poison_pill: PoisonPillAction,
+}
+
+impl From<FailurePolicy> for api::FailurePolicy {
+    fn from(value: FailurePolicy) -> Self {
+        let on_poison_pill = match value.on_poison_pill {
+            PoisonPillAction::Fail => api::PoisonPillAction::FailJob,
+            PoisonPillAction::Skip => api::PoisonPillAction::SkipRecords,
+            PoisonPillAction::Park => api::PoisonPillAction::ParkJob,
+        };
+
+        api::FailurePolicy {
+            restart_budget: value.restart_budget,
+            on_poison_pill: on_poison_pill as i32,
+        }
+    }
+}
+
+/// Sizes of the queues between the pipeline's operators and how long data sent between workers
+/// is buffered. The defaults (4096 messages and 100ms) favor throughput; smaller queues and a
+/// flush interval of a few milliseconds lower latency at the cost of throughput.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueConfig {
+    /// Capacity, in messages, of queues between operators with the same parallelism
+    pub forward_queue_size: Option<u32>,
+    /// Capacity, in messages, of queues between operators that repartition data
+    pub shuffle_queue_size: Option<u32>,
+    /// How long data sent to other workers may be buffered before it is flushed
+    pub network_flush_interval_micros: Option<u64>,
+}
+
+impl From<QueueConfig> for api::QueueConfig {
+    fn from(value: QueueConfig) -> Self {
+        api::QueueConfig {
+            forward_queue_size: value.forward_queue_size,
+            shuffle_queue_size: value.shuffle_queue_size,
+            network_flush_interval_micros: value.network_flush_interval_micros,
+        }
+    }
+}
+
+impl From<arroyo_types::QueueConfig> for QueueConfig {
+    fn from(value: arroyo_types::QueueConfig) -> Self {
+        QueueConfig {
+            forward_queue_size: value.forward_queue_size,
+            shuffle_queue_size: value.shuffle_queue_size,
+            network_flush_interval_micros: value.network_flush_interval_micros,
+        }
+    }
+}
+
+/// Limits how quickly the pipeline catches up when it starts behind, for example after being
+/// restored from an old checkpoint, so that its backlog doesn't overwhelm the systems its sinks
+/// write to. Sources that get ahead are paused so that the pipeline's watermark advances at most
+/// `maxWatermarkAdvanceMicros` per second, a limit that is raised as the pipeline's lag shrinks
+/// and lifted once its watermark is within `caughtUpLagMicros` (a minute by default) of now.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryThrottle {
+    pub max_watermark_advance_micros: u64,
+    pub caught_up_lag_micros: Option<u64>,
+}
+
+impl From<RecoveryThrottle> for api::RecoveryThrottle {
+    fn from(value: RecoveryThrottle) -> Self {
+        api::RecoveryThrottle {
+            max_watermark_advance_micros: value.max_watermark_advance_micros,
+            caught_up_lag_micros: value.caught_up_lag_micros,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum StateCompression {
+    #[default]
+    Zstd,
+    None,
+}
+
+/// How the pipeline's state and checkpoint files are written. Files are compressed with zstd by
+/// default. If `kmsKeyId` is set, each file is also encrypted (with AES-256-GCM) under a data key
+/// generated by that KMS key, and the key is recorded in the checkpoint so that the files can be
+/// decrypted when the pipeline is restored; the pipeline's workers and the controller must be
+/// allowed to generate data keys with and decrypt using the KMS key.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateStorageConfig {
+    pub compression: Option<StateCompression>,
+    /// The zstd compression level, from 1 to 22
+    pub compression_level: Option<i32>,
+    /// The id or ARN of the KMS key used to encrypt state files
+    pub kms_key_id: Option<String>,
+}
+
+impl From<StateStorageConfig> for api::StateStorageConfig {
+    fn from(value: StateStorageConfig) -> Self {
+        api::StateStorageConfig {
+            compression: value.compression.map(|c| match c {
+                StateCompression::Zstd => api::StateCompression::Zstd as i32,
+                StateCompression::None => api::StateCompression::Uncompressed as i32,
+            }),
+            compression_level: value.compression_level,
+            kms_key_id: value.kms_key_id,
+        }
+    }
+}
+
+/// A failure that repeated each time the job was restored from the same checkpoint
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PoisonPill {
+    pub operator_id: String,
+    pub subtask_index: u32,
+    /// The checkpoint the job was restored from before each failure
+    pub epoch: u32,
+    pub failures: u32,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum HealthStatus {
+    /// The pipeline isn't running, or hasn't reported any metrics yet
+    Unknown,
+    Healthy,
+    /// At least one indicator is over its threshold
+    Behind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthIndicator {
+    pub value_micros: Option<u64>,
+    pub threshold_micros: u64,
+    pub violated: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineHealth {
+    pub status: HealthStatus,
+    /// How far behind the slowest source is from the data it is reading
+    pub source_lag: HealthIndicator,
+    /// How far the lowest watermark of any operator is behind the current time
+    pub watermark_lag: HealthIndicator,
+    /// Time since the last successful checkpoint
+    pub checkpoint_age: HealthIndicator,
+}
+
+impl From<api::JobHealthResp> for PipelineHealth {
+    fn from(value: api::JobHealthResp) -> Self {
+        let status = match value.status() {
+            api::JobHealthStatus::HealthUnknown => HealthStatus::Unknown,
+            api::JobHealthStatus::Healthy => HealthStatus::Healthy,
+            api::JobHealthStatus::Behind => HealthStatus::Behind,
+        };
+
+        let indicator = |i: Option<api::HealthIndicator>| {
+            let i = i.unwrap_or_default();
+            HealthIndicator {
+                value_micros: i.value_micros,
+                threshold_micros: i.threshold_micros,
+                violated: i.violated,
+            }
+        };
+
+        PipelineHealth {
+            status,
+            source_lag: indicator(value.source_lag),
+            watermark_lag: indicator(value.watermark_lag),
+            checkpoint_age: indicator(value.checkpoint_age),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum EstimateBasis {
+    /// The pipeline isn't running, so only its plan and last checkpoint were used
+    Plan,
+    /// The pipeline's recent throughput and backpressure were used
+    Metrics,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorResources {
+    pub operator_id: String,
+    pub parallelism: u32,
+    pub recommended_parallelism: u32,
+    pub messages_per_second: Option<f64>,
+    /// Fraction of the operator's capacity that is unused
+    pub headroom: Option<f64>,
+    pub state_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineResources {
+    pub basis: EstimateBasis,
+    pub slots: u32,
+    pub recommended_slots: u32,
+    pub state_bytes: Option<u64>,
+    /// The lowest headroom of any operator
+    pub headroom: Option<f64>,
+    pub operators: Vec<OperatorResources>,
+}
+
+impl From<api::JobResourceEstimateResp> for PipelineResources {
+    fn from(value: api::JobResourceEstimateResp) -> Self {
+        let basis = match value.basis() {
+            api::ResourceEstimateBasis::Plan => EstimateBasis::Plan,
+            api::ResourceEstimateBasis::Metrics => EstimateBasis::Metrics,
+        };
+
+        PipelineResources {
+            basis,
+            slots: value.slots,
+            recommended_slots: value.recommended_slots,
+            state_bytes: value.state_bytes,
+            headroom: value.headroom,
+            operators: value
+                .operators
+                .into_iter()
+                .map(|o| OperatorResources {
+                    operator_id: o.operator_id,
+                    parallelism: o.parallelism,
+                    recommended_parallelism: o.recommended_parallelism,
+                    messages_per_second: o.messages_per_second,
+                    headroom: o.headroom,
+                    state_bytes: o.state_bytes,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceOverride {
+    pub operator_id: String,
+    pub position: SourceOffsetPosition,
+    /// Required for the `timestamp` position
+    pub timestamp_micros: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SourceOffsetPosition {
+    Earliest,
+    Latest,
+    Timestamp,
+}
+
+impl From<SourceOverride> for api::SourceOffsetOverride {
+    fn from(value: SourceOverride) -> Self {
+        let position = match value.position {
+            SourceOffsetPosition::Earliest => api::SourceOffsetPosition::Earliest,
+            SourceOffsetPosition::Latest => api::SourceOffsetPosition::Latest,
+            SourceOffsetPosition::Timestamp => api::SourceOffsetPosition::Timestamp,
+        };
+
+        api::SourceOffsetOverride {
+            operator_id: value.operator_id,
+            position: position as i32,
+            timestamp_micros: value.timestamp_micros,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -30,7 +626,33 @@ pub struct Pipeline {
     pub udfs: Vec<Udf>,
     pub checkpoint_interval_micros: u64,
     pub stop: StopType,
+    pub env_vars: HashMap<String, String>,
+    pub feature_flags: HashMap<String, bool>,
+    pub environment: Option<String>,
     pub created_at: u64,
+    /// Patterns in the query likely to cause unbounded state growth; only set when the pipeline
+    /// is created
+    pub warnings: Vec<SqlWarning>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelinePromotePost {
+    /// The environment to promote the pipeline to
+    pub environment: String,
+    /// The name of the new pipeline; defaults to the name of the promoted pipeline
+    pub name: Option<String>,
+    /// Query options aren't stored with a pipeline, so those it was created with must be given
+    /// again
+    pub ordered: Option<bool>,
+    pub preserve_identifier_case: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlWarning {
+    pub code: String,
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -41,6 +663,9 @@ pub enum StopType {
     Graceful,
     Immediate,
     Force,
+    /// Sources finish as though their input had ended, flushing all windows before the pipeline
+    /// stops
+    Drain,
 }
 
 impl From<StopMode> for StopType {
@@ -51,6 +676,7 @@ impl From<StopMode> for StopType {
             StopMode::graceful => StopType::Graceful,
             StopMode::immediate => StopType::Immediate,
             StopMode::force => StopType::Force,
+            StopMode::drain => StopType::Drain,
         }
     }
 }
@@ -63,6 +689,7 @@ impl Into<arroyo_rpc::grpc::api::StopType> for StopType {
             StopType::Graceful => arroyo_rpc::grpc::api::StopType::Graceful,
             StopType::Immediate => arroyo_rpc::grpc::api::StopType::Immediate,
             StopType::Force => arroyo_rpc::grpc::api::StopType::Force,
+            StopType::Drain => arroyo_rpc::grpc::api::StopType::Drain,
         }
     }
 }
@@ -78,6 +705,16 @@ pub struct Job {
     pub finish_time: Option<u64>,
     pub tasks: Option<u64>,
     pub failure_message: Option<String>,
+    /// Set when the job repeatedly failed on the same input
+    pub poison_pill: Option<PoisonPill>,
+    /// The queue config the job runs with, including defaults for unset values
+    pub queue_config: QueueConfig,
+    /// Thresholds of the pipeline's SLO that the job exceeds; the job is degraded if any are set
+    pub slo_violations: Vec<SloViolation>,
+    /// Set while the job is waiting for the pipelines it depends on before it is first started
+    pub waiting_for: Option<String>,
+    /// The most recent compaction of the job's state files
+    pub state_compaction: Option<StateCompaction>,
     pub created_at: u64,
 }
 
@@ -111,6 +748,120 @@ pub struct Udf {
     pub definition: String,
 }
 
+/// How a masking policy hides the values of a column
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum MaskingAction {
+    /// Replace values with null
+    Redact,
+    /// Replace values with the hex-encoded SHA-256 hash of their text representation
+    Hash,
+    /// Keep only the first `length` characters of text values
+    Truncate { length: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MaskingPolicyPost {
+    pub name: String,
+    /// The name of the column to mask, as written to sinks
+    pub column: String,
+    /// Only mask the column when it's written to the sink with this name; if unset, the column is
+    /// masked in every sink
+    pub sink: Option<String>,
+    pub action: MaskingAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MaskingPolicy {
+    pub id: String,
+    pub name: String,
+    pub column: String,
+    pub sink: Option<String>,
+    pub action: MaskingAction,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionProfilePost {
+    /// The name of the connection the profile applies to
+    pub connection: String,
+    pub environment: String,
+    /// The JSON config to use for the connection in this environment, in place of its own
+    pub config: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionProfile {
+    pub id: String,
+    pub connection: String,
+    pub environment: String,
+    pub config: String,
+    pub created_at: u64,
+}
+
+/// The final progress of an operator in a finished run
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorRunMetrics {
+    pub operator_id: String,
+    pub records_in: u64,
+    pub records_out: u64,
+    pub state_bytes: u64,
+    pub watermark_micros: Option<u64>,
+}
+
+/// An event in the timeline of a run, such as a state transition
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRunEvent {
+    pub time_micros: u64,
+    pub level: String,
+    pub message: String,
+    pub details: String,
+}
+
+/// A summary of a finished run of a job, which is kept after the job is restarted or deleted
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRun {
+    pub id: String,
+    pub job_id: String,
+    pub pipeline_id: String,
+    pub pipeline_name: String,
+    pub run_id: u64,
+    pub state: String,
+    pub start_time: Option<u64>,
+    pub finish_time: u64,
+    pub restarts: u32,
+    pub failure_message: Option<String>,
+    pub metrics: Vec<OperatorRunMetrics>,
+    pub timeline: Vec<JobRunEvent>,
+    /// The range of ids of the job's log messages written during the run, if they haven't been
+    /// pruned
+    pub first_log_message_id: Option<i64>,
+    pub last_log_message_id: Option<i64>,
+}
+
+#[derive(Deserialize, Clone, Debug, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query, rename_all = "camelCase")]
+pub struct JobHistoryQueryParams {
+    /// Only return runs of this pipeline
+    pub pipeline_id: Option<String>,
+    /// Only return runs that finished in this state
+    pub state: Option<String>,
+    /// Only return runs with this job id, or whose pipeline name or failure message contain this
+    pub search: Option<String>,
+    /// Only return runs that finished before this time, for paging through older runs
+    pub before_micros: Option<u64>,
+    /// The maximum number of runs to return (50 by default)
+    pub limit: Option<u32>,
+}
+
 // Collections need to be created with this macro rather than a generic type
 // because utoipa::ToSchema (and the OpenAPI spec) don't support generics natively
 macro_rules! collection_type {
@@ -126,3 +877,6 @@ macro_rules! collection_type {
 
 collection_type!(JobCollection, Job);
 collection_type!(PipelineCollection, Pipeline);
+collection_type!(MaskingPolicyCollection, MaskingPolicy);
+collection_type!(ConnectionProfileCollection, ConnectionProfile);
+collection_type!(JobRunCollection, JobRun);
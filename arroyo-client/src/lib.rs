@@ -0,0 +1,275 @@
+//! A typed client for the Arroyo API, for services that manage pipelines programmatically.
+//!
+//! [`ArroyoClient`] wraps the generated gRPC client with helpers for the common workflow of
+//! submitting a SQL pipeline, waiting for its job to start running, and reading its metrics. The
+//! full API remains available through [`ArroyoClient::grpc`], and its request and response types
+//! are re-exported as [`api`].
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use arroyo_client::{ArroyoClient, SqlPipeline};
+//! use std::time::Duration;
+//!
+//! let mut client = ArroyoClient::connect("http://localhost:8001").await?;
+//! let job = client
+//!     .submit_sql(SqlPipeline::new("counts", "select count(*) from nexmark").parallelism(2))
+//!     .await?;
+//! client.wait_for_running(&job.job_id, Duration::from_secs(120)).await?;
+//! let metrics = client.job_metrics(&job.job_id).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use arroyo_rpc::grpc::api::api_grpc_client::ApiGrpcClient;
+use arroyo_rpc::grpc::api::{
+    create_pipeline_req, CreateJobResp, CreatePipelineReq, CreateSqlJob, CreateUdf, JobDetailsReq,
+    JobMetricsReq, JobMetricsResp, JobStatus, StopType, UpdateJobReq,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status};
+use tracing::debug;
+
+pub use arroyo_rpc::grpc::api;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Adds the bearer token, if any, to each request
+#[derive(Clone)]
+pub struct Auth {
+    token: Option<MetadataValue<Ascii>>,
+}
+
+impl Interceptor for Auth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.token {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+pub type GrpcClient = ApiGrpcClient<InterceptedService<Channel, Auth>>;
+
+/// A SQL pipeline to submit with [`ArroyoClient::submit_sql`]
+#[derive(Clone, Debug)]
+pub struct SqlPipeline {
+    name: String,
+    job: CreateSqlJob,
+}
+
+impl SqlPipeline {
+    pub fn new(name: impl Into<String>, query: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            job: CreateSqlJob {
+                query: query.into(),
+                parallelism: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn parallelism(mut self, parallelism: u64) -> Self {
+        self.job.parallelism = parallelism;
+        self
+    }
+
+    /// Adds a Rust UDF definition
+    pub fn udf(mut self, definition: impl Into<String>) -> Self {
+        self.job.udfs.push(CreateUdf {
+            language: api::UdfLanguage::Rust as i32,
+            definition: definition.into(),
+        });
+        self
+    }
+
+    /// Environment variables available to the pipeline's UDFs
+    pub fn env_vars(mut self, env_vars: HashMap<String, String>) -> Self {
+        self.job.env.get_or_insert_with(Default::default).env_vars = env_vars;
+        self
+    }
+
+    /// Preserve the event-time order of records with the same key through shuffles
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.job.ordered = ordered;
+        self
+    }
+
+    /// The environment to run the pipeline in, which determines which profile its connections use
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.job.environment = Some(environment.into());
+        self
+    }
+
+    /// Finish the job once its watermark passes `time`, dropping records after it
+    pub fn stop_at_event_time(mut self, time: SystemTime) -> Self {
+        self.job.stop_at_event_time_micros = Some(
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as u64,
+        );
+        self
+    }
+
+    /// Escape hatch for options that don't have a setter here
+    pub fn job_mut(&mut self) -> &mut CreateSqlJob {
+        &mut self.job
+    }
+}
+
+impl From<SqlPipeline> for CreatePipelineReq {
+    fn from(pipeline: SqlPipeline) -> Self {
+        CreatePipelineReq {
+            name: pipeline.name,
+            config: Some(create_pipeline_req::Config::Sql(pipeline.job)),
+        }
+    }
+}
+
+pub struct ArroyoClient {
+    client: GrpcClient,
+}
+
+impl ArroyoClient {
+    /// Connects to the API server's gRPC endpoint, e.g., `http://localhost:8001`
+    pub async fn connect(endpoint: impl Into<String>) -> Result<Self> {
+        Self::connect_with_token(endpoint, None).await
+    }
+
+    /// Connects to the API server, sending `token` as a bearer token with each request
+    pub async fn connect_with_token(
+        endpoint: impl Into<String>,
+        token: Option<&str>,
+    ) -> Result<Self> {
+        let endpoint = endpoint.into();
+        let token = token
+            .map(|t| format!("Bearer {}", t).parse())
+            .transpose()
+            .map_err(|_| anyhow!("token is not a valid header value"))?;
+
+        let channel = Endpoint::from_shared(endpoint.clone())
+            .with_context(|| format!("invalid endpoint '{}'", endpoint))?
+            .connect()
+            .await
+            .with_context(|| format!("failed to connect to {}", endpoint))?;
+
+        Ok(Self {
+            client: ApiGrpcClient::with_interceptor(channel, Auth { token }),
+        })
+    }
+
+    /// The underlying gRPC client, for the parts of the API without a helper here
+    pub fn grpc(&mut self) -> &mut GrpcClient {
+        &mut self.client
+    }
+
+    /// Creates the pipeline and starts a job for it
+    pub async fn submit(&mut self, req: impl Into<CreatePipelineReq>) -> Result<CreateJobResp> {
+        Ok(self
+            .client
+            .start_pipeline(req.into())
+            .await
+            .map_err(|s| anyhow!("failed to submit pipeline: {}", s.message()))?
+            .into_inner())
+    }
+
+    pub async fn submit_sql(&mut self, pipeline: SqlPipeline) -> Result<CreateJobResp> {
+        self.submit(pipeline).await
+    }
+
+    pub async fn job_status(&mut self, job_id: &str) -> Result<JobStatus> {
+        self.client
+            .get_job_details(JobDetailsReq {
+                job_id: job_id.to_string(),
+            })
+            .await
+            .map_err(|s| anyhow!("failed to get job {}: {}", job_id, s.message()))?
+            .into_inner()
+            .job_status
+            .ok_or_else(|| anyhow!("no status returned for job {}", job_id))
+    }
+
+    /// Polls the job until it reaches `state`, returning an error if it fails, stops in another
+    /// state, or doesn't get there within `timeout`
+    pub async fn wait_for_state(
+        &mut self,
+        job_id: &str,
+        state: &str,
+        timeout: Duration,
+    ) -> Result<JobStatus> {
+        let start = Instant::now();
+        let mut last_state = String::new();
+
+        loop {
+            let status = self.job_status(job_id).await?;
+            if status.state == state {
+                return Ok(status);
+            }
+
+            if status.state != last_state {
+                debug!("job {} transitioned to {}", job_id, status.state);
+                last_state = status.state.clone();
+            }
+
+            match status.state.as_str() {
+                "Failed" => bail!(
+                    "job {} failed: {}",
+                    job_id,
+                    status.failure_message.as_deref().unwrap_or("unknown error")
+                ),
+                "Finished" | "Stopped" => {
+                    bail!("job {} is {}, not {}", job_id, status.state, state)
+                }
+                _ => {}
+            }
+
+            if start.elapsed() > timeout {
+                bail!(
+                    "timed out after {:?} waiting for job {} to be {} (currently {})",
+                    timeout,
+                    job_id,
+                    state,
+                    status.state
+                );
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    pub async fn wait_for_running(&mut self, job_id: &str, timeout: Duration) -> Result<JobStatus> {
+        self.wait_for_state(job_id, "Running", timeout).await
+    }
+
+    /// Recent metrics for each subtask of the job
+    pub async fn job_metrics(&mut self, job_id: &str) -> Result<JobMetricsResp> {
+        Ok(self
+            .client
+            .get_job_metrics(JobMetricsReq {
+                job_id: job_id.to_string(),
+            })
+            .await
+            .map_err(|s| anyhow!("failed to get metrics for job {}: {}", job_id, s.message()))?
+            .into_inner())
+    }
+
+    pub async fn stop_job(&mut self, job_id: &str, stop: StopType) -> Result<()> {
+        self.client
+            .update_job(UpdateJobReq {
+                job_id: job_id.to_string(),
+                stop: Some(stop as i32),
+                ..Default::default()
+            })
+            .await
+            .map_err(|s| anyhow!("failed to stop job {}: {}", job_id, s.message()))?;
+        Ok(())
+    }
+}
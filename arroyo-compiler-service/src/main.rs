@@ -10,12 +10,13 @@ use arroyo_rpc::grpc::{
     CompileQueryReq, CompileQueryResp,
 };
 
-use arroyo_server_common::start_admin_server;
-use arroyo_types::{grpc_port, ports, S3_BUCKET_ENV, S3_REGION_ENV};
+use arroyo_server_common::{start_admin_server, tls};
+use arroyo_types::{bind_addr, grpc_port, ports, S3_BUCKET_ENV, S3_REGION_ENV};
 use object_store::aws::AmazonS3Builder;
 use object_store::local::LocalFileSystem;
 use object_store::ObjectStore;
 use prost::Message;
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 use tokio::{process::Command, sync::Mutex};
 use tonic::{transport::Server, Request, Response, Status};
@@ -122,7 +123,7 @@ pub async fn start_service(service: CompileService) {
 
     let grpc = grpc_port("compiler", ports::COMPILER_GRPC);
 
-    let addr = format!("0.0.0.0:{}", grpc).parse().unwrap();
+    let addr = bind_addr("compiler", grpc);
 
     info!("Starting compiler service at {}", addr);
 
@@ -149,7 +150,7 @@ pub async fn start_service(service: CompileService) {
     Server::builder()
         .max_frame_size(Some((1 << 24) - 1)) // 16MB
         .add_service(CompilerGrpcServer::new(service))
-        .serve(addr)
+        .serve_with_incoming(tls::incoming(TcpListener::bind(addr).await.unwrap()))
         .await
         .unwrap();
 
@@ -166,7 +167,14 @@ pub struct CompileService {
 }
 
 impl CompileService {
-    async fn get_output(&self) -> io::Result<Output> {
+    async fn get_output(&self, instrumented: bool) -> io::Result<Output> {
+        // instrumentation is only compiled into pipelines that ask for it
+        let features: &[&str] = if instrumented {
+            &["--features", "pipeline/instrumentation"]
+        } else {
+            &[]
+        };
+
         if self.debug {
             let args = if std::env::var("VERBOSE").is_ok() {
                 vec!["build", "--verbose"]
@@ -177,6 +185,7 @@ impl CompileService {
             Command::new("cargo")
                 .current_dir(&self.build_dir)
                 .args(&args)
+                .args(features)
                 .output()
                 .await
         } else {
@@ -184,6 +193,7 @@ impl CompileService {
                 .current_dir(&self.build_dir)
                 .arg("build")
                 .arg("--release")
+                .args(features)
                 .output()
                 .await
         }
@@ -207,7 +217,7 @@ impl CompileService {
 
         tokio::fs::write(build_dir.join("wasm-fns/src/lib.rs"), &req.wasm_fns).await?;
 
-        let result = self.get_output().await?;
+        let result = self.get_output(req.instrumented).await?;
 
         if !result.status.success() {
             return Err(io::Error::new(
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use arroyo_rpc::tls;
+use scylla::{Session, SessionBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/cassandra/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/cassandra/table.json");
+const ICON: &str = include_str!("../resources/cassandra.svg");
+
+import_types!(schema = "../connector-schemas/cassandra/connection.json");
+import_types!(schema = "../connector-schemas/cassandra/table.json");
+
+pub struct CassandraConnector {}
+
+impl Connector for CassandraConnector {
+    type ConfigT = CassandraConfig;
+    type TableT = CassandraTable;
+
+    fn name(&self) -> &'static str {
+        "cassandra"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "cassandra".to_string(),
+            name: "Cassandra".to_string(),
+            icon: ICON.to_string(),
+            description: "Write to Apache Cassandra or ScyllaDB tables".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        (*config.hosts).clone()
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        CassandraTester {
+            connection: config,
+            table,
+            tx,
+        }
+        .start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if table.batch_size.map(|s| s <= 0).unwrap_or(false) {
+            bail!("batchSize must be positive");
+        }
+
+        if let Some(t) = &config.tls {
+            tls::TlsConfig::from_generated(t)
+                .openssl_context()
+                .map_err(|e| anyhow!("invalid TLS config: {}", e))?;
+        }
+
+        let description = format!("CassandraSink<{}.{}>", table.keyspace, table.table);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: schema
+                .map(|s| s.to_owned())
+                .ok_or_else(|| anyhow!("No schema defined for Cassandra sink"))?,
+            operator: "connectors::cassandra::CassandraSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let auth = opts.remove("auth.type");
+        let authentication = match auth.as_deref() {
+            Some("none") | None => Authentication::None {},
+            Some("password") => Authentication::Password {
+                username: pull_opt("auth.username", opts)?,
+                password: pull_opt("auth.password", opts)?,
+            },
+            Some(other) => bail!("unknown auth type '{}'", other),
+        };
+
+        let connection = CassandraConfig {
+            hosts: Hosts(pull_opt("hosts", opts)?),
+            authentication,
+            tls: tls::TlsConfig::from_options(opts)
+                .map_err(|e| anyhow!(e))?
+                .map(|t| t.into_generated()),
+        };
+
+        let consistency = opts
+            .remove("consistency")
+            .map(|c| {
+                ConsistencyLevel::try_from(c.as_str())
+                    .map_err(|_| anyhow!("invalid value for consistency '{}'", c))
+            })
+            .transpose()?;
+
+        let batch_size = opts
+            .remove("batch_size")
+            .map(|s| {
+                s.parse::<i64>()
+                    .map_err(|_| anyhow!("invalid value for batch_size '{}'", s))
+            })
+            .transpose()?;
+
+        let table = CassandraTable {
+            keyspace: pull_opt("keyspace", opts)?,
+            table: pull_opt("table", opts)?,
+            consistency,
+            batch_size,
+        };
+
+        self.from_config(None, name, connection, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("hosts".to_string(), config.hosts.to_string());
+
+        match config.authentication {
+            Authentication::None {} => {
+                opts.insert("auth.type".to_string(), "none".to_string());
+            }
+            Authentication::Password { username, password } => {
+                opts.insert("auth.type".to_string(), "password".to_string());
+                opts.insert("auth.username".to_string(), username);
+                opts.insert("auth.password".to_string(), password);
+            }
+        }
+
+        if let Some(t) = &config.tls {
+            opts.extend(tls::TlsConfig::from_generated(t).to_options());
+        }
+
+        opts
+    }
+}
+
+pub async fn connect(config: &CassandraConfig) -> anyhow::Result<Session> {
+    let mut builder = SessionBuilder::new().known_nodes(config.hosts.split(','));
+
+    if let Authentication::Password { username, password } = &config.authentication {
+        builder = builder.user(username, password);
+    }
+
+    if let Some(t) = &config.tls {
+        let context = tls::TlsConfig::from_generated(t)
+            .openssl_context()
+            .map_err(|e| anyhow!("invalid TLS config: {}", e))?;
+        builder = builder.ssl_context(Some(context));
+    }
+
+    builder
+        .build()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", *config.hosts, e))
+}
+
+struct CassandraTester {
+    connection: CassandraConfig,
+    table: CassandraTable,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl CassandraTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let message = match self.test_internal().await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully validated Cassandra table".to_string(),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            self.send(message).await;
+        });
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self.tx.send(Ok(msg)).await.is_err() {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<()> {
+        let session = connect(&self.connection).await?;
+
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: "Connected to cluster".to_string(),
+        })
+        .await;
+
+        let rows = session
+            .query(
+                "SELECT column_name FROM system_schema.columns \
+                WHERE keyspace_name = ? AND table_name = ?",
+                (&self.table.keyspace, &self.table.table),
+            )
+            .await?
+            .rows
+            .unwrap_or_default();
+
+        if rows.is_empty() {
+            bail!(
+                "Table '{}.{}' does not exist",
+                self.table.keyspace,
+                self.table.table
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use rusoto_core::Region;
+use rusoto_dynamodb::{DescribeTableInput, DynamoDb, DynamoDbClient};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/dynamodb/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/dynamodb/table.json");
+const ICON: &str = include_str!("../resources/dynamodb.svg");
+
+// BatchWriteItem accepts at most 25 requests per call
+pub const MAX_BATCH_SIZE: i64 = 25;
+
+import_types!(schema = "../connector-schemas/dynamodb/connection.json");
+import_types!(schema = "../connector-schemas/dynamodb/table.json");
+
+pub struct DynamoDbConnector {}
+
+impl Connector for DynamoDbConnector {
+    type ConfigT = DynamoDbConfig;
+    type TableT = DynamoDbTable;
+
+    fn name(&self) -> &'static str {
+        "dynamodb"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "dynamodb".to_string(),
+            name: "DynamoDB".to_string(),
+            icon: ICON.to_string(),
+            description: "Write results to Amazon DynamoDB tables".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        match config.endpoint {
+            Some(endpoint) => format!("{} ({})", config.region, endpoint),
+            None => config.region,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        DynamoDbTester { config, table, tx }.start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        region(&config)?;
+
+        if let Some(batch_size) = table.batch_size {
+            if !(1..=MAX_BATCH_SIZE).contains(&batch_size) {
+                bail!("batchSize must be between 1 and {}", MAX_BATCH_SIZE);
+            }
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for DynamoDB sink"))?;
+
+        for key in std::iter::once(&table.partition_key).chain(table.sort_key.iter()) {
+            if !schema.fields.is_empty() && !schema.fields.iter().any(|f| &f.field_name == key) {
+                bail!(
+                    "key attribute '{}' is not a field of the sink's schema",
+                    key
+                );
+            }
+        }
+
+        let description = format!("DynamoDbSink<{}>", table.table_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(&schema)),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::dynamodb::DynamoDbSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let config = DynamoDbConfig {
+            region: pull_opt("region", opts)?,
+            endpoint: opts.remove("endpoint"),
+        };
+
+        let write_mode = match opts.remove("write_mode").as_deref() {
+            None | Some("put") => WriteMode::Put,
+            Some("update") => WriteMode::Update,
+            Some(other) => bail!("invalid value for write_mode '{}'", other),
+        };
+
+        let batch_size = opts
+            .remove("batch_size")
+            .map(|s| {
+                s.parse::<i64>()
+                    .map_err(|_| anyhow!("invalid value for batch_size '{}'", s))
+            })
+            .transpose()?;
+
+        let table = DynamoDbTable {
+            table_name: pull_opt("table_name", opts)?,
+            partition_key: pull_opt("partition_key", opts)?,
+            sort_key: opts.remove("sort_key"),
+            write_mode,
+            condition_expression: opts.remove("condition_expression"),
+            batch_size,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("region".to_string(), config.region);
+        if let Some(endpoint) = config.endpoint {
+            opts.insert("endpoint".to_string(), endpoint);
+        }
+
+        opts
+    }
+}
+
+pub fn region(config: &DynamoDbConfig) -> anyhow::Result<Region> {
+    match &config.endpoint {
+        Some(endpoint) => Ok(Region::Custom {
+            name: config.region.clone(),
+            endpoint: endpoint.clone(),
+        }),
+        None => Region::from_str(&config.region)
+            .map_err(|_| anyhow!("'{}' is not a valid AWS region", config.region)),
+    }
+}
+
+struct DynamoDbTester {
+    config: DynamoDbConfig,
+    table: DynamoDbTable,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl DynamoDbTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let message = match self.test_internal().await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully validated DynamoDB table".to_string(),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if self.tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<()> {
+        let client = DynamoDbClient::new(region(&self.config)?);
+
+        let description = client
+            .describe_table(DescribeTableInput {
+                table_name: self.table.table_name.clone(),
+            })
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to describe table '{}': {}",
+                    self.table.table_name,
+                    e
+                )
+            })?
+            .table
+            .ok_or_else(|| anyhow!("Table '{}' does not exist", self.table.table_name))?;
+
+        let key_schema = description.key_schema.unwrap_or_default();
+        let key_for = |key_type: &str| {
+            key_schema
+                .iter()
+                .find(|k| k.key_type == key_type)
+                .map(|k| k.attribute_name.clone())
+        };
+
+        if key_for("HASH").as_ref() != Some(&self.table.partition_key) {
+            bail!(
+                "Table '{}' has partition key {:?}, but '{}' was configured",
+                self.table.table_name,
+                key_for("HASH"),
+                self.table.partition_key
+            );
+        }
+
+        if key_for("RANGE") != self.table.sort_key {
+            bail!(
+                "Table '{}' has sort key {:?}, but {:?} was configured",
+                self.table.table_name,
+                key_for("RANGE"),
+                self.table.sort_key
+            );
+        }
+
+        Ok(())
+    }
+}
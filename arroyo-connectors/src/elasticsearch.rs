@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig,
+    OperatorConfigSerializationMode,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/elasticsearch/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/elasticsearch/table.json");
+const ICON: &str = include_str!("../resources/elasticsearch.svg");
+
+import_types!(schema = "../connector-schemas/elasticsearch/connection.json");
+import_types!(schema = "../connector-schemas/elasticsearch/table.json");
+
+pub struct ElasticsearchConnector {}
+
+impl Connector for ElasticsearchConnector {
+    type ConfigT = ElasticsearchConfig;
+    type TableT = ElasticsearchTable;
+
+    fn name(&self) -> &'static str {
+        "elasticsearch"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "elasticsearch".to_string(),
+            name: "Elasticsearch".to_string(),
+            icon: ICON.to_string(),
+            description: "Index documents into Elasticsearch or OpenSearch".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        config.endpoint
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_internal(&config).await {
+                Ok(version) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: format!("Successfully connected to {}", version),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if !config.endpoint.starts_with("http://") && !config.endpoint.starts_with("https://") {
+            bail!("'{}' is not an http or https url", config.endpoint);
+        }
+
+        validate_index(&table.index)?;
+
+        for (name, value) in [
+            ("batchSize", table.batch_size),
+            ("timeoutMs", table.timeout_ms),
+            ("maxAttempts", table.max_attempts),
+        ] {
+            if value.map(|v| v <= 0).unwrap_or(false) {
+                bail!("{} must be positive", name);
+            }
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Elasticsearch sink"))?;
+
+        let serialization_mode = serialization_mode(&schema);
+        match serialization_mode {
+            OperatorConfigSerializationMode::Json => {}
+            OperatorConfigSerializationMode::DebeziumJson => {
+                // deletes are applied to the document with the row's id
+                if table.id_fields.is_none() {
+                    bail!("idFields must be set to write updates with the debezium_json format");
+                }
+            }
+            _ => bail!("Elasticsearch sinks only support the json and debezium_json formats"),
+        }
+
+        for field in id_fields(&table) {
+            if !schema.fields.is_empty() && !schema.fields.iter().any(|f| f.field_name == field) {
+                bail!("id field '{}' is not a field of the sink's schema", field);
+            }
+        }
+
+        let description = format!("ElasticsearchSink<{}>", table.index);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::elasticsearch::ElasticsearchSinkFunc::<#in_k, #in_t>"
+                .to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let authentication = match opts.remove("auth.type").as_deref() {
+            Some("none") | None => ElasticsearchConfigAuthentication::None {},
+            Some("basic") => ElasticsearchConfigAuthentication::Basic {
+                username: pull_opt("auth.username", opts)?,
+                password: pull_opt("auth.password", opts)?,
+            },
+            Some("api_key") => ElasticsearchConfigAuthentication::ApiKey {
+                api_key: pull_opt("auth.api_key", opts)?,
+            },
+            Some(other) => bail!("unknown auth type '{}'", other),
+        };
+
+        let config = ElasticsearchConfig {
+            endpoint: pull_opt("endpoint", opts)?,
+            authentication,
+        };
+
+        let int_opt = |opts: &mut HashMap<String, String>, name: &str| {
+            opts.remove(name)
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for {} '{}'", name, s))
+                })
+                .transpose()
+        };
+
+        let table = ElasticsearchTable {
+            index: pull_opt("index", opts)?,
+            id_fields: opts.remove("id_fields"),
+            batch_size: int_opt(opts, "batch_size")?,
+            timeout_ms: int_opt(opts, "timeout_ms")?,
+            max_attempts: int_opt(opts, "max_attempts")?,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("endpoint".to_string(), config.endpoint);
+
+        match config.authentication {
+            ElasticsearchConfigAuthentication::None {} => {
+                opts.insert("auth.type".to_string(), "none".to_string());
+            }
+            ElasticsearchConfigAuthentication::Basic { username, password } => {
+                opts.insert("auth.type".to_string(), "basic".to_string());
+                opts.insert("auth.username".to_string(), username);
+                opts.insert("auth.password".to_string(), password);
+            }
+            ElasticsearchConfigAuthentication::ApiKey { api_key } => {
+                opts.insert("auth.type".to_string(), "api_key".to_string());
+                opts.insert("auth.api_key".to_string(), api_key);
+            }
+        }
+
+        opts
+    }
+}
+
+pub fn id_fields(table: &ElasticsearchTable) -> Vec<String> {
+    table
+        .id_fields
+        .as_ref()
+        .map(|fields| {
+            fields
+                .split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks that the date patterns in the index name are closed, and that the rest of it is
+/// lowercase as Elasticsearch requires
+fn validate_index(index: &str) -> anyhow::Result<()> {
+    let mut in_pattern = false;
+    for c in index.chars() {
+        match c {
+            '{' if in_pattern => bail!("index '{}' contains a nested '{{'", index),
+            '{' => in_pattern = true,
+            '}' if !in_pattern => bail!("index '{}' contains an unmatched '}}'", index),
+            '}' => in_pattern = false,
+            c if !in_pattern && c.is_uppercase() => {
+                bail!("index '{}' must be lowercase", index)
+            }
+            _ => {}
+        }
+    }
+
+    if in_pattern {
+        bail!("index '{}' contains an unclosed '{{'", index);
+    }
+
+    Ok(())
+}
+
+/// Fetches the cluster's info, returning its distribution and version
+async fn test_internal(config: &ElasticsearchConfig) -> anyhow::Result<String> {
+    let request = reqwest::Client::new()
+        .get(&config.endpoint)
+        .timeout(Duration::from_secs(10));
+
+    let request = match &config.authentication {
+        ElasticsearchConfigAuthentication::None {} => request,
+        ElasticsearchConfigAuthentication::Basic { username, password } => {
+            request.basic_auth(username, Some(password))
+        }
+        ElasticsearchConfigAuthentication::ApiKey { api_key } => {
+            request.header("Authorization", format!("ApiKey {}", api_key))
+        }
+    };
+
+    let info: serde_json::Value = request
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", config.endpoint, e))?
+        .error_for_status()
+        .map_err(|e| anyhow!("Failed to fetch cluster info: {}", e))?
+        .json()
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "{} did not respond with cluster info: {}",
+                config.endpoint,
+                e
+            )
+        })?;
+
+    let distribution = match info
+        .pointer("/version/distribution")
+        .and_then(|d| d.as_str())
+    {
+        Some("opensearch") => "OpenSearch",
+        _ => "Elasticsearch",
+    };
+    let version = info
+        .pointer("/version/number")
+        .and_then(|v| v.as_str())
+        .unwrap_or("(unknown version)");
+
+    Ok(format!("{} {}", distribution, version))
+}
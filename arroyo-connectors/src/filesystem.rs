@@ -5,11 +5,15 @@ use arroyo_rpc::grpc::{
     self,
     api::{ConnectionSchema, Format, TestSourceMessage},
 };
+use arroyo_rpc::proxy::ProxyConfig;
 use typify::import_types;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{serialization_mode, Connection, ConnectionType, EmptyConfig, OperatorConfig};
+use crate::{
+    csv_config, serialization_mode, Connection, ConnectionType, CsvConfig, CsvQuoting, EmptyConfig,
+    OperatorConfig,
+};
 
 use super::Connector;
 
@@ -78,6 +82,12 @@ impl Connector for FileSystemConnector {
         table: Self::TableT,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<crate::Connection> {
+        if let Some(proxy) = &table.proxy {
+            ProxyConfig::from_generated(proxy)
+                .validate()
+                .map_err(|e| anyhow!(e))?;
+        }
+
         let is_local = match &table.write_target {
             Destination::FolderUri { path } => path.starts_with("file://"),
             Destination::S3Bucket { .. } => false,
@@ -88,6 +98,8 @@ impl Connector for FileSystemConnector {
             (Some(FormatSettings::Parquet { .. }), false) => ("FileSystem<Parquet>".to_string(), "connectors::filesystem::ParquetFileSystemSink::<#in_k, #in_t, #in_tRecordBatchBuilder>"),
             (Some(FormatSettings::Json {  }), true) => ("LocalFileSystem<JSON>".to_string(), "connectors::filesystem::LocalJsonFileSystemSink::<#in_k, #in_t>"),
             (Some(FormatSettings::Json {  }), false) => ("FileSystem<JSON>".to_string(), "connectors::filesystem::JsonFileSystemSink::<#in_k, #in_t>"),
+            (Some(FormatSettings::Csv { .. }), true) => ("LocalFileSystem<CSV>".to_string(), "connectors::filesystem::LocalCsvFileSystemSink::<#in_k, #in_t>"),
+            (Some(FormatSettings::Csv { .. }), false) => ("FileSystem<CSV>".to_string(), "connectors::filesystem::CsvFileSystemSink::<#in_k, #in_t>"),
             (None, _) => bail!("have to have some format settings"),
         };
 
@@ -95,7 +107,13 @@ impl Connector for FileSystemConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            batching: None,
+            connection_pool: None,
             serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
         };
 
         Ok(Connection {
@@ -165,6 +183,25 @@ impl Connector for FileSystemConnector {
                 })
             }
             Format::JsonFormat => Some(FormatSettings::Json {}),
+            Format::CsvFormat => {
+                let CsvConfig {
+                    delimiter,
+                    quote,
+                    has_header,
+                    quoting,
+                } = csv_config(schema.unwrap())?.context("csv table without csv options")?;
+                Some(FormatSettings::Csv {
+                    delimiter,
+                    quote,
+                    has_header: has_header.unwrap_or(false),
+                    quoting: quoting.map(|q| match q {
+                        CsvQuoting::Necessary => Quoting::Necessary,
+                        CsvQuoting::Always => Quoting::Always,
+                        CsvQuoting::NonNumeric => Quoting::NonNumeric,
+                        CsvQuoting::Never => Quoting::Never,
+                    }),
+                })
+            }
             other => bail!("Unsupported format: {:?}", other),
         };
 
@@ -176,6 +213,9 @@ impl Connector for FileSystemConnector {
                 write_target,
                 file_settings,
                 format_settings,
+                proxy: ProxyConfig::from_options(opts)
+                    .map_err(|e| anyhow!(e))?
+                    .map(|p| p.into_generated()),
             },
             schema,
         )
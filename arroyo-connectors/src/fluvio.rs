@@ -7,8 +7,8 @@ use serde::{Deserialize, Serialize};
 use typify::import_types;
 
 use crate::{
-    pull_opt, serialization_mode, Connection, ConnectionType, Connector, EmptyConfig,
-    OperatorConfig,
+    bad_data, csv_config, pull_opt, serialization_mode, Connection, ConnectionType, Connector,
+    EmptyConfig, OperatorConfig,
 };
 
 pub struct FluvioConnector {}
@@ -141,7 +141,13 @@ impl Connector for FluvioConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            batching: None,
+            connection_pool: None,
             serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: csv_config(schema.as_ref().unwrap())?,
+            bad_data: bad_data(schema.as_ref().unwrap()),
+            lineage: None,
         };
 
         Ok(Connection {
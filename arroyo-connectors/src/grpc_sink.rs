@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use arroyo_rpc::tls;
+use arroyo_types::string_to_map;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use prost_reflect::{DescriptorPool, MethodDescriptor};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/grpc/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/grpc/table.json");
+const ICON: &str = include_str!("../resources/grpc.svg");
+
+import_types!(schema = "../connector-schemas/grpc/connection.json");
+import_types!(schema = "../connector-schemas/grpc/table.json");
+
+pub struct GrpcConnector {}
+
+impl Connector for GrpcConnector {
+    type ConfigT = GrpcConfig;
+    type TableT = GrpcTable;
+
+    fn name(&self) -> &'static str {
+        "grpc"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "grpc".to_string(),
+            name: "gRPC".to_string(),
+            icon: ICON.to_string(),
+            description: "Send records to a gRPC service".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        config.endpoint
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_internal(&config, &table).await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully connected to gRPC service".to_string(),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let method = method_descriptor(&table)?;
+
+        if let Some(headers) = &config.headers {
+            string_to_map(&headers.0).ok_or_else(|| anyhow!("invalid headers '{}'", **headers))?;
+        }
+
+        tls_config(&config)?;
+
+        for (name, value) in [
+            ("batchSize", table.batch_size),
+            ("maxConcurrency", table.max_concurrency),
+            ("timeoutMs", table.timeout_ms),
+            ("maxAttempts", table.max_attempts),
+        ] {
+            if value.map(|v| v <= 0).unwrap_or(false) {
+                bail!("{} must be positive", name);
+            }
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for gRPC sink"))?;
+
+        let input = method.input();
+        for field in &schema.fields {
+            if input.get_field_by_name(&field.field_name).is_none() {
+                bail!(
+                    "field '{}' does not exist in message {}",
+                    field.field_name,
+                    input.full_name()
+                );
+            }
+        }
+
+        let description = format!("GrpcSink<{}>", *table.method);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(&schema)),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::grpc_sink::GrpcSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let int_opt = |opts: &mut HashMap<String, String>, name: &str| {
+            opts.remove(name)
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for {} '{}'", name, s))
+                })
+                .transpose()
+        };
+
+        let config = GrpcConfig {
+            endpoint: pull_opt("endpoint", opts)?,
+            headers: opts.remove("headers").map(Headers),
+            tls: tls::TlsConfig::from_options(opts)
+                .map_err(|e| anyhow!(e))?
+                .map(|t| t.into_generated()),
+        };
+
+        let table = GrpcTable {
+            descriptor_set: pull_opt("descriptor_set", opts)?,
+            method: Method(pull_opt("method", opts)?),
+            batch_size: int_opt(opts, "batch_size")?,
+            max_concurrency: int_opt(opts, "max_concurrency")?,
+            timeout_ms: int_opt(opts, "timeout_ms")?,
+            max_attempts: int_opt(opts, "max_attempts")?,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("endpoint".to_string(), config.endpoint);
+        if let Some(headers) = config.headers {
+            opts.insert("headers".to_string(), headers.0);
+        }
+        if let Some(t) = &config.tls {
+            opts.extend(tls::TlsConfig::from_generated(t).to_options());
+        }
+
+        opts
+    }
+}
+
+/// Finds the configured method in the descriptor set, checking that it's one we can call
+pub fn method_descriptor(table: &GrpcTable) -> anyhow::Result<MethodDescriptor> {
+    let bytes = STANDARD
+        .decode(&table.descriptor_set)
+        .map_err(|e| anyhow!("descriptorSet is not valid base64: {}", e))?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .map_err(|e| anyhow!("descriptorSet is not a valid FileDescriptorSet: {}", e))?;
+
+    let (service, method) = table
+        .method
+        .split_once('/')
+        .ok_or_else(|| anyhow!("method must have the form package.Service/Method"))?;
+
+    let method = pool
+        .get_service_by_name(service)
+        .ok_or_else(|| anyhow!("service '{}' not found in descriptor set", service))?
+        .methods()
+        .find(|m| m.name() == method)
+        .ok_or_else(|| anyhow!("method '{}' not found in service '{}'", method, service))?;
+
+    if method.is_server_streaming() {
+        bail!("server-streaming methods are not supported");
+    }
+
+    Ok(method)
+}
+
+/// The TLS config for the endpoint, if it uses https
+pub fn tls_config(config: &GrpcConfig) -> anyhow::Result<Option<ClientTlsConfig>> {
+    if !config.endpoint.starts_with("https") {
+        if config.tls.is_some() {
+            bail!("tls is configured, but the endpoint is not an https url");
+        }
+        return Ok(None);
+    }
+
+    match &config.tls {
+        Some(t) => {
+            let t = tls::TlsConfig::from_generated(t);
+            t.validate()
+                .and_then(|_| t.tonic_config())
+                .map(Some)
+                .map_err(|e| anyhow!("invalid TLS config: {}", e))
+        }
+        None => Ok(Some(ClientTlsConfig::new())),
+    }
+}
+
+async fn test_internal(config: &GrpcConfig, table: &GrpcTable) -> anyhow::Result<()> {
+    method_descriptor(table)?;
+
+    let mut endpoint = Endpoint::from_shared(config.endpoint.clone())
+        .map_err(|e| anyhow!("invalid endpoint: {}", e))?;
+    if let Some(tls) = tls_config(config)? {
+        endpoint = endpoint.tls_config(tls)?;
+    }
+
+    endpoint
+        .connect()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", config.endpoint, e))?;
+
+    Ok(())
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use rusoto_core::Region;
+use rusoto_glue::{GetTableRequest, Glue, GlueClient};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/iceberg/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/iceberg/table.json");
+const ICON: &str = include_str!("../resources/iceberg.svg");
+
+import_types!(schema = "../connector-schemas/iceberg/connection.json");
+import_types!(schema = "../connector-schemas/iceberg/table.json");
+
+pub struct IcebergConnector {}
+
+impl Connector for IcebergConnector {
+    type ConfigT = IcebergConfig;
+    type TableT = IcebergTable;
+
+    fn name(&self) -> &'static str {
+        "iceberg"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "iceberg".to_string(),
+            name: "Apache Iceberg".to_string(),
+            icon: ICON.to_string(),
+            description: "Append to Apache Iceberg tables through a REST or Glue catalog"
+                .to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        match config.catalog {
+            Catalog::Rest { url, .. } => url,
+            Catalog::Glue { region, .. } => format!("Glue ({})", region),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_internal(&config, &table).await {
+                Ok(location) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: format!(
+                        "Successfully loaded table {}.{}, located at {}",
+                        table.namespace, table.table_name, location
+                    ),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Iceberg sink"))?;
+
+        if table.namespace.is_empty() || table.table_name.is_empty() {
+            bail!("Iceberg sinks require a namespace and a table name");
+        }
+
+        let description = format!("IcebergSink<{}.{}>", table.namespace, table.table_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(&schema)),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::iceberg::IcebergSink::<#in_k, #in_t, #in_tRecordBatchBuilder>"
+                .to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let catalog = match pull_opt("catalog.type", opts)?.as_str() {
+            "rest" => Catalog::Rest {
+                url: pull_opt("catalog.url", opts)?,
+                warehouse: opts.remove("catalog.warehouse"),
+                token: opts.remove("catalog.token"),
+            },
+            "glue" => Catalog::Glue {
+                region: pull_opt("catalog.region", opts)?,
+                catalog_id: opts.remove("catalog.catalog_id"),
+            },
+            other => bail!(
+                "unknown catalog.type '{}'; expected 'rest' or 'glue'",
+                other
+            ),
+        };
+
+        let compression = opts
+            .remove("compression")
+            .map(|c| Compression::from_str(&c).map_err(|_| anyhow!("invalid compression '{}'", c)))
+            .transpose()?;
+
+        let target_file_size = opts
+            .remove("target_file_size")
+            .map(|s| {
+                s.parse::<i64>()
+                    .map_err(|_| anyhow!("invalid target_file_size '{}'", s))
+            })
+            .transpose()?;
+
+        let table = IcebergTable {
+            namespace: pull_opt("namespace", opts)?,
+            table_name: pull_opt("table_name", opts)?,
+            compression,
+            target_file_size,
+        };
+
+        self.from_config(None, name, IcebergConfig { catalog }, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        match config.catalog {
+            Catalog::Rest {
+                url,
+                warehouse,
+                token,
+            } => {
+                opts.insert("catalog.type".to_string(), "rest".to_string());
+                opts.insert("catalog.url".to_string(), url);
+                if let Some(warehouse) = warehouse {
+                    opts.insert("catalog.warehouse".to_string(), warehouse);
+                }
+                if let Some(token) = token {
+                    opts.insert("catalog.token".to_string(), token);
+                }
+            }
+            Catalog::Glue { region, catalog_id } => {
+                opts.insert("catalog.type".to_string(), "glue".to_string());
+                opts.insert("catalog.region".to_string(), region);
+                if let Some(catalog_id) = catalog_id {
+                    opts.insert("catalog.catalog_id".to_string(), catalog_id);
+                }
+            }
+        }
+
+        opts
+    }
+}
+
+/// Loads the table from the catalog, returning its location
+async fn test_internal(config: &IcebergConfig, table: &IcebergTable) -> anyhow::Result<String> {
+    match &config.catalog {
+        Catalog::Rest {
+            url,
+            warehouse,
+            token,
+        } => {
+            let client = reqwest::Client::new();
+            let with_auth = |req: reqwest::RequestBuilder| match token {
+                Some(token) => req.bearer_auth(token),
+                None => req,
+            };
+
+            let mut config_url = rest_url(url, &["v1", "config"])?;
+            if let Some(warehouse) = warehouse {
+                config_url
+                    .query_pairs_mut()
+                    .append_pair("warehouse", warehouse);
+            }
+
+            let catalog_config: serde_json::Value = with_auth(client.get(config_url))
+                .send()
+                .await
+                .map_err(|e| anyhow!("Failed to connect to catalog at {}: {}", url, e))?
+                .error_for_status()
+                .map_err(|e| anyhow!("Failed to fetch catalog config: {}", e))?
+                .json()
+                .await?;
+
+            // nested namespaces are joined with the unit separator in REST catalog paths
+            let namespace = table.namespace.replace('.', "\u{1f}");
+            let mut segments = vec!["v1"];
+            if let Some(prefix) = catalog_config
+                .pointer("/overrides/prefix")
+                .and_then(|p| p.as_str())
+            {
+                segments.extend(prefix.split('/'));
+            }
+            segments.extend([
+                "namespaces",
+                namespace.as_str(),
+                "tables",
+                table.table_name.as_str(),
+            ]);
+            let table_url = rest_url(url, &segments)?;
+
+            let response = with_auth(client.get(table_url)).send().await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                bail!(
+                    "Table {}.{} does not exist",
+                    table.namespace,
+                    table.table_name
+                );
+            }
+
+            let loaded: serde_json::Value = response
+                .error_for_status()
+                .map_err(|e| anyhow!("Failed to load table: {}", e))?
+                .json()
+                .await?;
+
+            check_unpartitioned(&loaded["metadata"])?;
+
+            loaded
+                .pointer("/metadata/location")
+                .and_then(|l| l.as_str())
+                .map(|l| l.to_string())
+                .ok_or_else(|| anyhow!("catalog returned table without a location"))
+        }
+        Catalog::Glue { region, catalog_id } => {
+            let region = Region::from_str(region)
+                .map_err(|_| anyhow!("'{}' is not a valid AWS region", region))?;
+
+            let glue_table = GlueClient::new(region)
+                .get_table(GetTableRequest {
+                    catalog_id: catalog_id.clone(),
+                    database_name: table.namespace.clone(),
+                    name: table.table_name.clone(),
+                })
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to fetch table {}.{} from Glue: {}",
+                        table.namespace,
+                        table.table_name,
+                        e
+                    )
+                })?
+                .table
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Table {}.{} does not exist",
+                        table.namespace,
+                        table.table_name
+                    )
+                })?;
+
+            let parameters = glue_table.parameters.unwrap_or_default();
+            if !parameters
+                .get("table_type")
+                .map(|t| t.eq_ignore_ascii_case("iceberg"))
+                .unwrap_or(false)
+            {
+                bail!(
+                    "Table {}.{} is not an Iceberg table",
+                    table.namespace,
+                    table.table_name
+                );
+            }
+
+            if glue_table
+                .partition_keys
+                .map(|k| !k.is_empty())
+                .unwrap_or(false)
+            {
+                bail!("Only unpartitioned Iceberg tables are supported");
+            }
+
+            parameters
+                .get("metadata_location")
+                .cloned()
+                .ok_or_else(|| anyhow!("Glue table has no Iceberg metadata location"))
+        }
+    }
+}
+
+fn rest_url(base: &str, segments: &[&str]) -> anyhow::Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse(base)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("invalid catalog URL {}", base))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+fn check_unpartitioned(metadata: &serde_json::Value) -> anyhow::Result<()> {
+    let default_spec = metadata["default-spec-id"].as_i64().unwrap_or(0);
+    let partitioned = metadata["partition-specs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|s| s["spec-id"].as_i64() == Some(default_spec))
+        .any(|s| {
+            s["fields"]
+                .as_array()
+                .map(|f| !f.is_empty())
+                .unwrap_or(false)
+        });
+
+    if partitioned {
+        bail!("Only unpartitioned Iceberg tables are supported");
+    }
+
+    Ok(())
+}
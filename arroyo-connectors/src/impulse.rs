@@ -159,7 +159,13 @@ impl Connector for ImpulseConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            batching: None,
+            connection_pool: None,
             serialization_mode: None,
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
         };
 
         Ok(Connection {
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use mysql_async::prelude::Queryable;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tokio_postgres::NoTls;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig,
+    OperatorConfigSerializationMode,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/jdbc/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/jdbc/table.json");
+const ICON: &str = include_str!("../resources/jdbc.svg");
+
+import_types!(schema = "../connector-schemas/jdbc/connection.json");
+import_types!(schema = "../connector-schemas/jdbc/table.json");
+
+pub struct JdbcConnector {}
+
+impl Connector for JdbcConnector {
+    type ConfigT = JdbcConfig;
+    type TableT = JdbcTable;
+
+    fn name(&self) -> &'static str {
+        "jdbc"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "jdbc".to_string(),
+            name: "JDBC".to_string(),
+            icon: ICON.to_string(),
+            description: "Write rows to Postgres or MySQL, appending or upserting them".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        // don't leak credentials embedded in the connection string
+        match config.connection_string.split_once('@') {
+            Some((_, hosts)) => hosts.to_string(),
+            None => (*config.connection_string).clone(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        JdbcTester {
+            config,
+            table,
+            fields: schema
+                .map(|s| s.fields.iter().map(|f| f.field_name.clone()).collect())
+                .unwrap_or_default(),
+            tx,
+        }
+        .start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for JDBC sink"))?;
+        let serialization_mode = serialization_mode(&schema);
+
+        let primary_key = primary_key(&table);
+        match serialization_mode {
+            OperatorConfigSerializationMode::Json => {}
+            OperatorConfigSerializationMode::DebeziumJson => {
+                if primary_key.is_empty() {
+                    bail!("primaryKey must be set to write updates with the debezium_json format");
+                }
+            }
+            _ => bail!("JDBC sinks only support the json and debezium_json formats"),
+        }
+
+        for key in &primary_key {
+            if !schema.fields.is_empty() && !schema.fields.iter().any(|f| &f.field_name == key) {
+                bail!(
+                    "primary key column '{}' is not a field of the sink's schema",
+                    key
+                );
+            }
+        }
+
+        if table.batch_size.map(|s| s <= 0).unwrap_or(false) {
+            bail!("batchSize must be positive");
+        }
+
+        let description = format!("JdbcSink<{}>", table.table_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::jdbc::JdbcSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let config = JdbcConfig {
+            connection_string: ConnectionString(pull_opt("connection_string", opts)?),
+        };
+
+        let table = JdbcTable {
+            table_name: pull_opt("table_name", opts)?,
+            primary_key: opts.remove("primary_key"),
+            batch_size: opts
+                .remove("batch_size")
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for batch_size '{}'", s))
+                })
+                .transpose()?,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        HashMap::from([(
+            "connection_string".to_string(),
+            config.connection_string.to_string(),
+        )])
+    }
+}
+
+pub fn primary_key(table: &JdbcTable) -> Vec<String> {
+    table
+        .primary_key
+        .iter()
+        .flat_map(|k| k.split(','))
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+struct JdbcTester {
+    config: JdbcConfig,
+    table: JdbcTable,
+    fields: Vec<String>,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl JdbcTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let message = match self.test_internal().await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully validated JDBC table".to_string(),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if self.tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<()> {
+        let columns = if self.config.connection_string.starts_with("mysql://") {
+            self.mysql_columns().await?
+        } else {
+            self.postgres_columns().await?
+        };
+
+        if columns.is_empty() {
+            bail!("Table '{}' does not exist", self.table.table_name);
+        }
+
+        for field in primary_key(&self.table).iter().chain(&self.fields) {
+            if !columns.contains(field) {
+                bail!(
+                    "Table '{}' has no column '{}'",
+                    self.table.table_name,
+                    field
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn postgres_columns(&self) -> anyhow::Result<Vec<String>> {
+        let (client, connection) = tokio_postgres::connect(&*self.config.connection_string, NoTls)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Postgres: {}", e))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Postgres connection closed: {}", e);
+            }
+        });
+
+        Ok(client
+            .query(
+                "SELECT attname::text FROM pg_attribute \
+                WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped",
+                &[&self.table.table_name],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn mysql_columns(&self) -> anyhow::Result<Vec<String>> {
+        let mut conn = mysql_async::Conn::from_url(&*self.config.connection_string)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to MySQL: {}", e))?;
+
+        let (database, table) = match self.table.table_name.split_once('.') {
+            Some((database, table)) => (Some(database.to_string()), table.to_string()),
+            None => (None, self.table.table_name.clone()),
+        };
+
+        let columns = conn
+            .exec(
+                "SELECT COLUMN_NAME FROM information_schema.COLUMNS \
+                WHERE TABLE_SCHEMA = COALESCE(?, DATABASE()) AND TABLE_NAME = ?",
+                (database, table),
+            )
+            .await?;
+        conn.disconnect().await?;
+
+        Ok(columns)
+    }
+}
@@ -2,12 +2,15 @@ use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
 use typify::import_types;
 
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
 use arroyo_rpc::grpc::{
     self,
-    api::{ConnectionSchema, TestSourceMessage},
+    api::{source_field_type, ConnectionSchema, PrimitiveType, TestSourceMessage},
 };
+use arroyo_rpc::tls;
+use arroyo_types::string_to_map;
 use rdkafka::{
     consumer::{BaseConsumer, Consumer},
     message::BorrowedMessage,
@@ -17,7 +20,10 @@ use tokio::sync::mpsc::Sender;
 use tonic::Status;
 use tracing::{error, info, warn};
 
-use crate::{pull_opt, serialization_mode, Connection, ConnectionType};
+use crate::{
+    bad_data, csv_config, lineage, protobuf_config, pull_opt, serialization_mode, Connection,
+    ConnectionType, OperatorConfigSerializationMode,
+};
 
 use super::{Connector, OperatorConfig};
 
@@ -67,6 +73,12 @@ impl Connector for KafkaConnector {
         table: KafkaTable,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection> {
+        if let Some(t) = &config.tls {
+            tls::TlsConfig::from_generated(t)
+                .validate()
+                .map_err(|e| anyhow!("invalid TLS config: {}", e))?;
+        }
+
         let (typ, operator, desc) = match table.type_ {
             TableType::Source { .. } => (
                 ConnectionType::Source,
@@ -80,11 +92,34 @@ impl Connector for KafkaConnector {
             ),
         };
 
+        let serialization_mode = serialization_mode(schema.as_ref().unwrap());
+        if matches!(serialization_mode, OperatorConfigSerializationMode::Avro)
+            && config.schema_registry.is_none()
+        {
+            bail!("a schema registry must be configured to read or write avro");
+        }
+
+        if matches!(
+            serialization_mode,
+            OperatorConfigSerializationMode::Protobuf
+        ) && matches!(table.type_, TableType::Sink { .. })
+        {
+            bail!("protobuf is only supported for kafka sources");
+        }
+
+        validate_header_columns(&table, schema.as_ref().unwrap(), &serialization_mode)?;
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
-            serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode),
+            protobuf: protobuf_config(schema.as_ref().unwrap())?,
+            csv: csv_config(schema.as_ref().unwrap())?,
+            bad_data: bad_data(schema.as_ref().unwrap()),
+            lineage: lineage(schema.as_ref().unwrap()),
         };
 
         Ok(Connection {
@@ -127,7 +162,7 @@ impl Connector for KafkaConnector {
     fn from_options(
         &self,
         name: &str,
-        opts: &mut std::collections::HashMap<String, String>,
+        opts: &mut HashMap<String, String>,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection> {
         let auth = opts.remove("auth.type");
@@ -145,6 +180,16 @@ impl Connector for KafkaConnector {
         let connection = KafkaConfig {
             authentication: auth,
             bootstrap_servers: BootstrapServers(pull_opt("bootstrap_servers", opts)?),
+            tls: tls::TlsConfig::from_options(opts)
+                .map_err(|e| anyhow!(e))?
+                .map(|t| t.into_generated()),
+            schema_registry: opts.remove("schema_registry.endpoint").map(|endpoint| {
+                SchemaRegistry {
+                    endpoint,
+                    api_key: opts.remove("schema_registry.api_key"),
+                    api_secret: opts.remove("schema_registry.api_secret"),
+                }
+            }),
         };
 
         let typ = pull_opt("type", opts)?;
@@ -159,7 +204,14 @@ impl Connector for KafkaConnector {
                     },
                 }
             }
-            "sink" => TableType::Sink {},
+            "sink" => TableType::Sink {
+                commit_mode: match opts.remove("sink.commit_mode").as_deref() {
+                    None => None,
+                    Some("at_least_once") => Some(CommitMode::AtLeastOnce),
+                    Some("exactly_once") => Some(CommitMode::ExactlyOnce),
+                    Some(other) => bail!("invalid value for sink.commit_mode '{}'", other),
+                },
+            },
             _ => {
                 bail!("type must be one of 'source' or 'sink")
             }
@@ -167,11 +219,127 @@ impl Connector for KafkaConnector {
 
         let table = KafkaTable {
             topic: pull_opt("topic", opts)?,
+            header_columns: opts.remove("header_columns").map(HeaderColumns),
+            binary_header_columns: opts
+                .remove("binary_header_columns")
+                .map(BinaryHeaderColumns),
             type_: table_type,
         };
 
         Self::from_config(&self, None, name, connection, table, schema)
     }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert(
+            "bootstrap_servers".to_string(),
+            config.bootstrap_servers.to_string(),
+        );
+
+        match config.authentication {
+            KafkaConfigAuthentication::None {} => {
+                opts.insert("auth.type".to_string(), "none".to_string());
+            }
+            KafkaConfigAuthentication::Sasl {
+                mechanism,
+                protocol,
+                username,
+                password,
+            } => {
+                opts.insert("auth.type".to_string(), "sasl".to_string());
+                opts.insert("auth.mechanism".to_string(), mechanism);
+                opts.insert("auth.protocol".to_string(), protocol);
+                opts.insert("auth.username".to_string(), username);
+                opts.insert("auth.password".to_string(), password);
+            }
+        }
+
+        if let Some(t) = &config.tls {
+            opts.extend(tls::TlsConfig::from_generated(t).to_options());
+        }
+
+        if let Some(registry) = config.schema_registry {
+            opts.insert("schema_registry.endpoint".to_string(), registry.endpoint);
+            if let Some(api_key) = registry.api_key {
+                opts.insert("schema_registry.api_key".to_string(), api_key);
+            }
+            if let Some(api_secret) = registry.api_secret {
+                opts.insert("schema_registry.api_secret".to_string(), api_secret);
+            }
+        }
+
+        opts
+    }
+}
+
+/// Checks that header columns are TEXT or BYTEA columns of the schema, as set by the property
+/// they're listed in. Sinks may not have a schema yet, in which case the columns are checked when
+/// records are written.
+fn validate_header_columns(
+    table: &KafkaTable,
+    schema: &ConnectionSchema,
+    serialization_mode: &OperatorConfigSerializationMode,
+) -> anyhow::Result<()> {
+    let text = table.header_columns.as_ref().map(|c| (&c.0, false));
+    let binary = table.binary_header_columns.as_ref().map(|c| (&c.0, true));
+
+    let mut seen = vec![];
+    for (pairs, binary) in text.into_iter().chain(binary) {
+        let columns = string_to_map(pairs).ok_or_else(|| {
+            anyhow!(
+                "invalid header columns '{}'; expected a comma separated list of column: header pairs",
+                pairs
+            )
+        })?;
+
+        for column in columns.into_keys() {
+            if seen.contains(&column) {
+                bail!("column '{}' is mapped to more than one header", column);
+            }
+
+            if let Some(field) = schema.fields.iter().find(|f| f.field_name == column) {
+                let primitive = field
+                    .field_type
+                    .as_ref()
+                    .and_then(|t| t.r#type.as_ref())
+                    .and_then(|t| match t {
+                        source_field_type::Type::Primitive(p) => PrimitiveType::from_i32(*p),
+                        source_field_type::Type::Struct(_) => None,
+                    });
+
+                match (primitive, binary) {
+                    (Some(PrimitiveType::String), false) | (Some(PrimitiveType::Bytes), true) => {}
+                    (_, false) => bail!("header column '{}' must be TEXT", column),
+                    (_, true) => bail!("binary header column '{}' must be BYTEA", column),
+                }
+            } else if !schema.fields.is_empty() {
+                bail!("header column '{}' is not a column of the table", column);
+            }
+
+            seen.push(column);
+        }
+    }
+
+    if !seen.is_empty()
+        && matches!(table.type_, TableType::Source { .. })
+        && !matches!(
+            serialization_mode,
+            OperatorConfigSerializationMode::Json
+                | OperatorConfigSerializationMode::JsonSchemaRegistry
+                | OperatorConfigSerializationMode::Avro
+                | OperatorConfigSerializationMode::Protobuf
+        )
+    {
+        bail!("headers can only be read into the columns of json, avro and protobuf sources");
+    }
+
+    // csv records are written from the columns by position, which taking out the header columns
+    // would reorder
+    if !seen.is_empty() && matches!(serialization_mode, OperatorConfigSerializationMode::Csv) {
+        bail!("headers can't be written from the columns of csv sinks");
+    }
+
+    Ok(())
 }
 
 struct KafkaTester {
@@ -211,6 +379,19 @@ impl KafkaTester {
             }
         };
 
+        if let Some(t) = &self.connection.tls {
+            if matches!(
+                self.connection.authentication,
+                KafkaConfigAuthentication::None {}
+            ) {
+                client_config.set("security.protocol", "ssl");
+            }
+
+            for (k, v) in tls::TlsConfig::from_generated(t).kafka_configs() {
+                client_config.set(k, v);
+            }
+        }
+
         let client: BaseConsumer = client_config
             .create()
             .map_err(|e| format!("Failed to connect: {:?}", e))?;
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use rusoto_core::Region;
+use rusoto_kinesis::{Kinesis, KinesisClient, ListShardsInput};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    bad_data, csv_config, pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/kinesis/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/kinesis/table.json");
+const ICON: &str = include_str!("../resources/kinesis.svg");
+
+// PutRecords accepts at most 500 records per call
+pub const MAX_BATCH_SIZE: i64 = 500;
+
+import_types!(schema = "../connector-schemas/kinesis/connection.json");
+import_types!(schema = "../connector-schemas/kinesis/table.json");
+
+pub struct KinesisConnector {}
+
+impl Connector for KinesisConnector {
+    type ConfigT = KinesisConfig;
+    type TableT = KinesisTable;
+
+    fn name(&self) -> &'static str {
+        "kinesis"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "kinesis".to_string(),
+            name: "Kinesis".to_string(),
+            icon: ICON.to_string(),
+            description: "Read from and write to Amazon Kinesis data streams".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        match config.endpoint {
+            Some(endpoint) => format!("{} ({})", config.region, endpoint),
+            None => config.region,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        KinesisTester { config, table, tx }.start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, table: Self::TableT) -> grpc::api::TableType {
+        match table.type_ {
+            TableType::Source { .. } => grpc::api::TableType::Source,
+            TableType::Sink { .. } => grpc::api::TableType::Sink,
+        }
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        region(&config)?;
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Kinesis connection"))?;
+
+        let (typ, operator, description) = match &table.type_ {
+            TableType::Source { .. } => (
+                ConnectionType::Source,
+                "connectors::kinesis::source::KinesisSourceFunc",
+                format!("KinesisSource<{}>", table.stream_name),
+            ),
+            TableType::Sink { batch_size } => {
+                if let Some(batch_size) = batch_size {
+                    if !(1..=MAX_BATCH_SIZE).contains(batch_size) {
+                        bail!("batchSize must be between 1 and {}", MAX_BATCH_SIZE);
+                    }
+                }
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::kinesis::sink::KinesisSinkFunc::<#in_k, #in_t>",
+                    format!("KinesisSink<{}>", table.stream_name),
+                )
+            }
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(&schema)),
+            protobuf: None,
+            csv: csv_config(&schema)?,
+            bad_data: bad_data(&schema),
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let config = KinesisConfig {
+            region: pull_opt("region", opts)?,
+            endpoint: opts.remove("endpoint"),
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {
+                offset: match opts.remove("source.offset").as_deref() {
+                    Some("earliest") => SourceOffset::Earliest,
+                    None | Some("latest") => SourceOffset::Latest,
+                    Some(other) => bail!("invalid value for source.offset '{}'", other),
+                },
+            },
+            "sink" => TableType::Sink {
+                batch_size: opts
+                    .remove("sink.batch_size")
+                    .map(|s| {
+                        s.parse::<i64>()
+                            .map_err(|_| anyhow!("invalid value for sink.batch_size '{}'", s))
+                    })
+                    .transpose()?,
+            },
+            _ => bail!("type must be one of 'source' or 'sink'"),
+        };
+
+        let table = KinesisTable {
+            stream_name: pull_opt("stream_name", opts)?,
+            type_: table_type,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("region".to_string(), config.region);
+        if let Some(endpoint) = config.endpoint {
+            opts.insert("endpoint".to_string(), endpoint);
+        }
+
+        opts
+    }
+}
+
+pub fn region(config: &KinesisConfig) -> anyhow::Result<Region> {
+    match &config.endpoint {
+        Some(endpoint) => Ok(Region::Custom {
+            name: config.region.clone(),
+            endpoint: endpoint.clone(),
+        }),
+        None => Region::from_str(&config.region)
+            .map_err(|_| anyhow!("'{}' is not a valid AWS region", config.region)),
+    }
+}
+
+struct KinesisTester {
+    config: KinesisConfig,
+    table: KinesisTable,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl KinesisTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let message = match self.test_internal().await {
+                Ok(shards) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: format!(
+                        "Successfully connected to Kinesis stream with {} shards",
+                        shards
+                    ),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if self.tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<usize> {
+        let client = KinesisClient::new(region(&self.config)?);
+
+        let shards = client
+            .list_shards(ListShardsInput {
+                stream_name: Some(self.table.stream_name.clone()),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to list shards of stream '{}': {}",
+                    self.table.stream_name,
+                    e
+                )
+            })?
+            .shards
+            .unwrap_or_default();
+
+        Ok(shards.len())
+    }
+}
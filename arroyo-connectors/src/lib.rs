@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arroyo_datastream::SerializationMode;
 use arroyo_rpc::{
     grpc::{
@@ -12,26 +12,56 @@ use arroyo_rpc::{
     },
     primitive_to_sql,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
 use blackhole::BlackholeConnector;
+use cassandra::CassandraConnector;
+use dynamodb::DynamoDbConnector;
+use elasticsearch::ElasticsearchConnector;
 use fluvio::FluvioConnector;
+use grpc_sink::GrpcConnector;
+use iceberg::IcebergConnector;
 use impulse::ImpulseConnector;
+use jdbc::JdbcConnector;
+use kinesis::KinesisConnector;
+use mongodb::MongoDbConnector;
+use nats::NatsConnector;
 use nexmark::NexmarkConnector;
+use object_store::ObjectStoreConnector;
+use postgres_cdc::PostgresCdcConnector;
+use prost_reflect::DescriptorPool;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sftp::SftpConnector;
+use smtp::SmtpConnector;
 use sse::SSEConnector;
 use tokio::sync::mpsc::Sender;
 use tonic::Status;
 use typify::import_types;
+use webhook::WebhookConnector;
 use websocket::WebsocketConnector;
 
 use self::kafka::KafkaConnector;
 
 pub mod blackhole;
+pub mod cassandra;
+pub mod dynamodb;
+pub mod elasticsearch;
 pub mod filesystem;
 pub mod fluvio;
+pub mod grpc_sink;
+pub mod iceberg;
 pub mod impulse;
+pub mod jdbc;
 pub mod kafka;
+pub mod kinesis;
+pub mod mongodb;
+pub mod nats;
 pub mod nexmark;
+pub mod object_store;
+pub mod postgres_cdc;
+pub mod sftp;
+pub mod smtp;
 pub mod sse;
+pub mod webhook;
 pub mod websocket;
 
 import_types!(schema = "../connector-schemas/common.json",);
@@ -46,6 +76,20 @@ pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
     m.insert("websocket", Box::new(WebsocketConnector {}));
     m.insert("fluvio", Box::new(FluvioConnector {}));
     m.insert("filesystem", Box::new(filesystem::FileSystemConnector {}));
+    m.insert("dynamodb", Box::new(DynamoDbConnector {}));
+    m.insert("cassandra", Box::new(CassandraConnector {}));
+    m.insert("mongodb", Box::new(MongoDbConnector {}));
+    m.insert("sftp", Box::new(SftpConnector {}));
+    m.insert("smtp", Box::new(SmtpConnector {}));
+    m.insert("grpc", Box::new(GrpcConnector {}));
+    m.insert("kinesis", Box::new(KinesisConnector {}));
+    m.insert("object_store", Box::new(ObjectStoreConnector {}));
+    m.insert("postgres_cdc", Box::new(PostgresCdcConnector {}));
+    m.insert("jdbc", Box::new(JdbcConnector {}));
+    m.insert("webhook", Box::new(WebhookConnector {}));
+    m.insert("nats", Box::new(NatsConnector {}));
+    m.insert("iceberg", Box::new(IcebergConnector {}));
+    m.insert("elasticsearch", Box::new(ElasticsearchConnector {}));
 
     m
 }
@@ -119,6 +163,14 @@ pub trait Connector: Send {
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection>;
 
+    /// The inverse of the connection half of `from_options`: produces the WITH options that would
+    /// configure this connection. These are used as defaults for tables that reference a saved
+    /// connection by name.
+    #[allow(unused)]
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
     fn from_config(
         &self,
         id: Option<i64>,
@@ -165,6 +217,8 @@ pub trait ErasedConnector: Send {
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection>;
 
+    fn config_options(&self, config: &str) -> Result<HashMap<String, String>, serde_json::Error>;
+
     fn from_config(
         &self,
         id: Option<i64>,
@@ -237,6 +291,10 @@ impl<C: Connector> ErasedConnector for C {
         self.from_options(name, options, schema)
     }
 
+    fn config_options(&self, config: &str) -> Result<HashMap<String, String>, serde_json::Error> {
+        Ok(self.config_options(self.parse_config(config)?))
+    }
+
     fn from_config(
         &self,
         id: Option<i64>,
@@ -264,6 +322,46 @@ pub fn connector_for_type(t: &str) -> Option<Box<dyn ErasedConnector>> {
     connectors().remove(t)
 }
 
+// connectors that can read and write avro, which requires a schema registry
+pub const AVRO_CONNECTORS: &[&str] = &["kafka"];
+
+// sources that can read protobuf messages
+pub const PROTOBUF_CONNECTORS: &[&str] = &["kafka"];
+
+// connectors that can read or write csv records
+pub const CSV_CONNECTORS: &[&str] = &[
+    "kafka",
+    "filesystem",
+    "fluvio",
+    "kinesis",
+    "nats",
+    "object_store",
+    "sftp",
+    "sse",
+    "websocket",
+];
+
+/// Checks that the connector can read or write the format, which is checked when tables are
+/// created rather than when their workers start
+pub fn check_format(connector: &str, format: grpc::api::Format) -> anyhow::Result<()> {
+    let (name, connectors) = match format {
+        grpc::api::Format::AvroFormat => ("avro", AVRO_CONNECTORS),
+        grpc::api::Format::ProtobufFormat => ("protobuf", PROTOBUF_CONNECTORS),
+        grpc::api::Format::CsvFormat => ("csv", CSV_CONNECTORS),
+        _ => return Ok(()),
+    };
+
+    if !connectors.contains(&connector) {
+        bail!(
+            "the '{}' connector does not support the {} format",
+            connector,
+            name
+        );
+    }
+
+    Ok(())
+}
+
 pub fn serialization_mode(schema: &ConnectionSchema) -> OperatorConfigSerializationMode {
     let confluent = schema
         .format_options
@@ -280,8 +378,8 @@ pub fn serialization_mode(schema: &ConnectionSchema) -> OperatorConfigSerializat
                 OperatorConfigSerializationMode::Json
             }
         }
-        grpc::api::Format::ProtobufFormat => todo!(),
-        grpc::api::Format::AvroFormat => todo!(),
+        grpc::api::Format::ProtobufFormat => OperatorConfigSerializationMode::Protobuf,
+        grpc::api::Format::AvroFormat => OperatorConfigSerializationMode::Avro,
         grpc::api::Format::RawStringFormat => {
             if confluent {
                 todo!("support raw json schemas with confluent schema registry decoding")
@@ -289,11 +387,106 @@ pub fn serialization_mode(schema: &ConnectionSchema) -> OperatorConfigSerializat
                 OperatorConfigSerializationMode::RawJson
             }
         }
+        grpc::api::Format::RawBytesFormat => OperatorConfigSerializationMode::RawBytes,
         grpc::api::Format::DebeziumJsonFormat => OperatorConfigSerializationMode::DebeziumJson,
         grpc::api::Format::ParquetFormat => OperatorConfigSerializationMode::Parquet,
+        grpc::api::Format::CsvFormat => OperatorConfigSerializationMode::Csv,
     }
 }
 
+pub fn bad_data(schema: &ConnectionSchema) -> Option<OperatorConfigBadData> {
+    schema
+        .format_options
+        .as_ref()
+        .filter(|t| t.permissive)
+        .map(|_| OperatorConfigBadData::Permissive)
+}
+
+pub fn lineage(schema: &ConnectionSchema) -> Option<bool> {
+    schema
+        .format_options
+        .as_ref()
+        .filter(|t| t.lineage)
+        .map(|_| true)
+}
+
+/// The message that the records of a protobuf schema are encoded as, which sources decode them
+/// with. The schema's definition must already have been compiled into a descriptor set.
+pub fn protobuf_config(schema: &ConnectionSchema) -> anyhow::Result<Option<ProtobufConfig>> {
+    if schema.format() != grpc::api::Format::ProtobufFormat {
+        return Ok(None);
+    }
+
+    let Some(Definition::ProtobufSchema(descriptor_set)) = &schema.definition else {
+        bail!("protobuf tables must have a protobuf schema");
+    };
+
+    let message = schema
+        .format_options
+        .as_ref()
+        .and_then(|o| o.protobuf_message.clone())
+        .ok_or_else(|| {
+            anyhow!("protobuf tables must set the message that records are encoded as")
+        })?;
+
+    let bytes = STANDARD
+        .decode(descriptor_set)
+        .map_err(|e| anyhow!("protobuf schema is not valid base64: {}", e))?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .map_err(|e| anyhow!("protobuf schema is not a valid FileDescriptorSet: {}", e))?;
+    if pool.get_message_by_name(&message).is_none() {
+        bail!("message '{}' not found in the protobuf schema", message);
+    }
+
+    Ok(Some(ProtobufConfig {
+        descriptor_set: descriptor_set.clone(),
+        message,
+    }))
+}
+
+/// How the records of a csv table are laid out, with the delimiter and quote checked to be single
+/// ASCII characters
+pub fn csv_config(schema: &ConnectionSchema) -> anyhow::Result<Option<CsvConfig>> {
+    if schema.format() != grpc::api::Format::CsvFormat {
+        return Ok(None);
+    }
+
+    let options = schema
+        .format_options
+        .as_ref()
+        .and_then(|o| o.csv.clone())
+        .unwrap_or_default();
+
+    for (name, c) in [("delimiter", &options.delimiter), ("quote", &options.quote)] {
+        if let Some(c) = c {
+            // a one-byte string is an ASCII character
+            if c.len() != 1 {
+                bail!("csv {} must be a single ASCII character, not '{}'", name, c);
+            }
+        }
+    }
+
+    let quoting = options
+        .quoting
+        .as_ref()
+        .map(|q| {
+            CsvQuoting::try_from(q.as_str()).map_err(|_| {
+                anyhow!(
+                    "invalid csv quoting '{}'; expected one of necessary, always, non_numeric or never",
+                    q
+                )
+            })
+        })
+        .transpose()?;
+
+    Ok(Some(CsvConfig {
+        delimiter: options.delimiter,
+        quote: options.quote,
+        has_header: Some(options.has_header),
+        quoting,
+    }))
+}
+
 impl From<OperatorConfigSerializationMode> for SerializationMode {
     fn from(value: OperatorConfigSerializationMode) -> Self {
         match value {
@@ -302,8 +495,12 @@ impl From<OperatorConfigSerializationMode> for SerializationMode {
                 SerializationMode::JsonSchemaRegistry
             }
             OperatorConfigSerializationMode::RawJson => SerializationMode::RawJson,
+            OperatorConfigSerializationMode::RawBytes => SerializationMode::RawBytes,
             OperatorConfigSerializationMode::DebeziumJson => SerializationMode::DebeziumJson,
             OperatorConfigSerializationMode::Parquet => SerializationMode::Parquet,
+            OperatorConfigSerializationMode::Avro => SerializationMode::Avro,
+            OperatorConfigSerializationMode::Protobuf => SerializationMode::Protobuf,
+            OperatorConfigSerializationMode::Csv => SerializationMode::Csv,
         }
     }
 }
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use arroyo_rpc::tls;
+use mongodb::{
+    bson::doc,
+    options::{ClientOptions, Tls, TlsOptions},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig,
+    OperatorConfigSerializationMode,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/mongodb/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/mongodb/table.json");
+const ICON: &str = include_str!("../resources/mongodb.svg");
+
+import_types!(schema = "../connector-schemas/mongodb/connection.json");
+import_types!(schema = "../connector-schemas/mongodb/table.json");
+
+pub struct MongoDbConnector {}
+
+impl Connector for MongoDbConnector {
+    type ConfigT = MongoDbConfig;
+    type TableT = MongoDbTable;
+
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "mongodb".to_string(),
+            name: "MongoDB".to_string(),
+            icon: ICON.to_string(),
+            description: "Read change streams from and write documents to MongoDB".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        // don't leak credentials embedded in the connection string
+        match config.connection_string.split_once('@') {
+            Some((_, hosts)) => hosts.to_string(),
+            None => (*config.connection_string).clone(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        MongoDbTester { config, table, tx }.start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, table: Self::TableT) -> grpc::api::TableType {
+        match table.type_ {
+            TableType::Source { .. } => grpc::api::TableType::Source,
+            TableType::Sink { .. } => grpc::api::TableType::Sink,
+        }
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for MongoDB connection"))?;
+        let serialization_mode = serialization_mode(&schema);
+
+        if let Some(t) = &config.tls {
+            tls::TlsConfig::from_generated(t)
+                .validate()
+                .map_err(|e| anyhow!("invalid TLS config: {}", e))?;
+        }
+
+        let (typ, operator, desc) = match &table.type_ {
+            TableType::Source {} => {
+                if !matches!(
+                    serialization_mode,
+                    OperatorConfigSerializationMode::Json
+                        | OperatorConfigSerializationMode::DebeziumJson
+                ) {
+                    bail!("MongoDB sources only support the json and debezium_json formats");
+                }
+
+                (
+                    ConnectionType::Source,
+                    "connectors::mongodb::source::MongoDbSourceFunc",
+                    format!("MongoDbSource<{}.{}>", table.database, table.collection),
+                )
+            }
+            TableType::Sink { batch_size, .. } => {
+                if batch_size.map(|s| s <= 0).unwrap_or(false) {
+                    bail!("batchSize must be positive");
+                }
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::mongodb::sink::MongoDbSinkFunc::<#in_k, #in_t>",
+                    format!("MongoDbSink<{}.{}>", table.database, table.collection),
+                )
+            }
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let config = MongoDbConfig {
+            connection_string: ConnectionString(pull_opt("connection_string", opts)?),
+            tls: tls::TlsConfig::from_options(opts)
+                .map_err(|e| anyhow!(e))?
+                .map(|t| t.into_generated()),
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {},
+            "sink" => TableType::Sink {
+                write_mode: match opts.remove("sink.write_mode").as_deref() {
+                    None | Some("insert") => WriteMode::Insert,
+                    Some("replace") => WriteMode::Replace,
+                    Some("upsert") => WriteMode::Upsert,
+                    Some(other) => bail!("invalid value for sink.write_mode '{}'", other),
+                },
+                batch_size: opts
+                    .remove("sink.batch_size")
+                    .map(|s| {
+                        s.parse::<i64>()
+                            .map_err(|_| anyhow!("invalid value for sink.batch_size '{}'", s))
+                    })
+                    .transpose()?,
+            },
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = MongoDbTable {
+            database: pull_opt("database", opts)?,
+            collection: pull_opt("collection", opts)?,
+            type_: table_type,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::from([(
+            "connection_string".to_string(),
+            config.connection_string.to_string(),
+        )]);
+        if let Some(t) = &config.tls {
+            opts.extend(tls::TlsConfig::from_generated(t).to_options());
+        }
+        opts
+    }
+}
+
+struct MongoDbTester {
+    config: MongoDbConfig,
+    table: MongoDbTable,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl MongoDbTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let message = match self.test_internal().await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully validated MongoDB collection".to_string(),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if self.tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<()> {
+        let mut options = ClientOptions::parse(&*self.config.connection_string)
+            .await
+            .map_err(|e| anyhow!("Invalid connection string: {}", e))?;
+
+        if let Some(t) = &self.config.tls {
+            let t = tls::TlsConfig::from_generated(t);
+            let (ca, client) =
+                t.write_pem_files(&std::env::temp_dir().join("arroyo-mongodb-tls"))?;
+            options.tls = Some(Tls::Enabled(
+                TlsOptions::builder()
+                    .ca_file_path(ca)
+                    .cert_key_file_path(client)
+                    .allow_invalid_certificates(t.insecure_skip_verify)
+                    .build(),
+            ));
+        }
+
+        let client = Client::with_options(options)?;
+
+        let database = client.database(&self.table.database);
+        database
+            .run_command(doc! { "ping": 1 }, None)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to MongoDB: {}", e))?;
+
+        let collections = database
+            .list_collection_names(doc! { "name": &self.table.collection })
+            .await?;
+
+        if collections.is_empty() && matches!(self.table.type_, TableType::Source { .. }) {
+            bail!(
+                "Collection '{}.{}' does not exist",
+                self.table.database,
+                self.table.collection
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use async_nats::ConnectOptions;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    bad_data, csv_config, pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/nats/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/nats/table.json");
+const ICON: &str = include_str!("../resources/nats.svg");
+
+import_types!(schema = "../connector-schemas/nats/connection.json");
+import_types!(schema = "../connector-schemas/nats/table.json");
+
+pub struct NatsConnector {}
+
+impl Connector for NatsConnector {
+    type ConfigT = NatsConfig;
+    type TableT = NatsTable;
+
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "nats".to_string(),
+            name: "NATS JetStream".to_string(),
+            icon: ICON.to_string(),
+            description: "Read from and write to NATS JetStream streams".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        (*config.servers).clone()
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_internal(&config, &table).await {
+                Ok(messages) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: format!(
+                        "Successfully connected to stream '{}', which holds {} messages",
+                        table.stream, messages
+                    ),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, table: Self::TableT) -> grpc::api::TableType {
+        match table.type_ {
+            TableType::Source { .. } => grpc::api::TableType::Source,
+            TableType::Sink { .. } => grpc::api::TableType::Sink,
+        }
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for NATS connection"))?;
+
+        let (typ, operator, desc) = match &table.type_ {
+            TableType::Source { .. } => (
+                ConnectionType::Source,
+                "connectors::nats::source::NatsSourceFunc",
+                format!("NatsSource<{}>", table.stream),
+            ),
+            TableType::Sink {} => {
+                let Some(subject) = &table.subject else {
+                    bail!("NATS sinks require a subject to publish to");
+                };
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::nats::sink::NatsSinkFunc::<#in_k, #in_t>",
+                    format!("NatsSink<{}>", subject),
+                )
+            }
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(&schema)),
+            protobuf: None,
+            csv: csv_config(&schema)?,
+            bad_data: bad_data(&schema),
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let auth = opts.remove("auth.type");
+        let auth = match auth.as_deref() {
+            Some("none") | None => NatsConfigAuthentication::None {},
+            Some("credentials") => NatsConfigAuthentication::Credentials {
+                username: pull_opt("auth.username", opts)?,
+                password: pull_opt("auth.password", opts)?,
+            },
+            Some("token") => NatsConfigAuthentication::Token {
+                token: pull_opt("auth.token", opts)?,
+            },
+            Some(other) => bail!("unknown auth type '{}'", other),
+        };
+
+        let connection = NatsConfig {
+            servers: Servers(pull_opt("servers", opts)?),
+            authentication: auth,
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {
+                offset: match opts.remove("source.offset").as_deref() {
+                    Some("earliest") => SourceOffset::Earliest,
+                    None | Some("latest") => SourceOffset::Latest,
+                    Some(other) => bail!("invalid value for source.offset '{}'", other),
+                },
+            },
+            "sink" => TableType::Sink {},
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = NatsTable {
+            stream: pull_opt("stream", opts)?,
+            subject: opts.remove("subject"),
+            type_: table_type,
+        };
+
+        self.from_config(None, name, connection, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("servers".to_string(), config.servers.to_string());
+
+        match config.authentication {
+            NatsConfigAuthentication::None {} => {
+                opts.insert("auth.type".to_string(), "none".to_string());
+            }
+            NatsConfigAuthentication::Credentials { username, password } => {
+                opts.insert("auth.type".to_string(), "credentials".to_string());
+                opts.insert("auth.username".to_string(), username);
+                opts.insert("auth.password".to_string(), password);
+            }
+            NatsConfigAuthentication::Token { token } => {
+                opts.insert("auth.type".to_string(), "token".to_string());
+                opts.insert("auth.token".to_string(), token);
+            }
+        }
+
+        opts
+    }
+}
+
+/// Connects to the servers and checks that the stream exists, returning the number of messages it
+/// currently holds
+async fn test_internal(config: &NatsConfig, table: &NatsTable) -> anyhow::Result<u64> {
+    let options = match &config.authentication {
+        NatsConfigAuthentication::None {} => ConnectOptions::new(),
+        NatsConfigAuthentication::Credentials { username, password } => {
+            ConnectOptions::with_user_and_password(username.clone(), password.clone())
+        }
+        NatsConfigAuthentication::Token { token } => ConnectOptions::with_token(token.clone()),
+    };
+
+    let client = tokio::time::timeout(
+        Duration::from_secs(10),
+        options.connect(config.servers.as_str()),
+    )
+    .await
+    .map_err(|_| anyhow!("Timed out connecting to {}", *config.servers))?
+    .map_err(|e| anyhow!("Failed to connect to {}: {}", *config.servers, e))?;
+
+    let mut stream = async_nats::jetstream::new(client)
+        .get_stream(&table.stream)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch stream '{}': {}", table.stream, e))?;
+
+    let info = stream.info().await?;
+
+    if let Some(subject) = &table.subject {
+        if matches!(table.type_, TableType::Sink {})
+            && !info
+                .config
+                .subjects
+                .iter()
+                .any(|s| subject_matches(s, subject))
+        {
+            bail!(
+                "Subject '{}' is not bound to stream '{}'; messages published to it won't be stored",
+                subject,
+                table.stream
+            );
+        }
+    }
+
+    Ok(info.state.messages)
+}
+
+/// Whether `subject` matches the NATS subject `pattern`, which may contain `*` (matching a single
+/// token) and a trailing `>` (matching one or more tokens)
+fn subject_matches(pattern: &str, subject: &str) -> bool {
+    let mut subject = subject.split('.');
+    for token in pattern.split('.') {
+        match (token, subject.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (t, Some(s)) if t == s => {}
+            _ => return false,
+        }
+    }
+    subject.next().is_none()
+}
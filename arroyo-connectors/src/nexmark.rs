@@ -183,7 +183,13 @@ impl Connector for NexmarkConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            batching: None,
+            connection_pool: None,
             serialization_mode: None,
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
         };
 
         Ok(Connection {
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, Format, TestSourceMessage},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    bad_data, csv_config, lineage, pull_opt, serialization_mode, Connection, ConnectionType,
+    EmptyConfig, OperatorConfig,
+};
+
+use super::Connector;
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/object_store/table.json");
+const ICON: &str = include_str!("../resources/object_store.svg");
+
+import_types!(schema = "../connector-schemas/object_store/table.json");
+
+pub struct ObjectStoreConnector {}
+
+impl Connector for ObjectStoreConnector {
+    type ConfigT = EmptyConfig;
+    type TableT = ObjectStoreTable;
+
+    fn name(&self) -> &'static str {
+        "object_store"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "object_store".to_string(),
+            name: "Object Store".to_string(),
+            icon: ICON.to_string(),
+            description: "Read JSON, CSV or Parquet files from S3 or a local directory".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_table(&table) {
+                Ok(message) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message,
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Source
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        validate_path(&table.path)?;
+        file_pattern(&table)?;
+
+        if table.poll_interval_seconds.map(|s| s <= 0).unwrap_or(false) {
+            bail!("pollIntervalSeconds must be positive");
+        }
+
+        if matches!(table.file_format, FileFormat::Csv) && schema.and_then(lineage).is_some() {
+            bail!("lineage is not supported for csv files");
+        }
+
+        let description = format!("ObjectStoreSource<{}>", table.path);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: csv_config(schema.as_ref().unwrap())?,
+            bad_data: bad_data(schema.as_ref().unwrap()),
+            lineage: lineage(schema.as_ref().unwrap()),
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema: schema
+                .map(|s| s.to_owned())
+                .ok_or_else(|| anyhow!("No schema defined for object store source"))?,
+            operator: "connectors::object_store::ObjectStoreSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let file_format = match opts.remove("file_format").as_deref() {
+            Some("json") => FileFormat::Json,
+            Some("csv") => FileFormat::Csv,
+            Some("parquet") => FileFormat::Parquet,
+            // parquet tables don't need to say so twice
+            None if schema.map(|s| s.format()) == Some(Format::ParquetFormat) => {
+                FileFormat::Parquet
+            }
+            None => FileFormat::Json,
+            Some(other) => bail!("invalid value for file_format '{}'", other),
+        };
+
+        let table = ObjectStoreTable {
+            path: pull_opt("path", opts)?,
+            region: opts.remove("region"),
+            file_pattern: opts.remove("file_pattern"),
+            file_format,
+            poll_interval_seconds: opts
+                .remove("poll_interval_seconds")
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for poll_interval_seconds '{}'", s))
+                })
+                .transpose()?,
+        };
+
+        self.from_config(None, name, EmptyConfig {}, table, schema)
+    }
+}
+
+/// The pattern must match the whole file name
+pub fn file_pattern(table: &ObjectStoreTable) -> anyhow::Result<Option<Regex>> {
+    table
+        .file_pattern
+        .as_ref()
+        .map(|p| Regex::new(&format!("^(?:{})$", p)))
+        .transpose()
+        .map_err(|e| anyhow!("invalid filePattern: {}", e))
+}
+
+/// The bucket and prefix of an S3 path like s3://bucket/prefix, or None if it's a local path
+pub fn s3_location(path: &str) -> Option<(&str, &str)> {
+    let location = path.strip_prefix("s3://")?;
+    Some(location.split_once('/').unwrap_or((location, "")))
+}
+
+/// The directory of a local path, which may be given as a file:// URI
+pub fn local_directory(path: &str) -> &str {
+    path.strip_prefix("file://").unwrap_or(path)
+}
+
+fn validate_path(path: &str) -> anyhow::Result<()> {
+    match s3_location(path) {
+        Some(("", _)) => bail!("path '{}' does not name a bucket", path),
+        Some(_) => Ok(()),
+        None if Path::new(local_directory(path)).is_absolute() => Ok(()),
+        None => bail!(
+            "path must be an S3 prefix like s3://bucket/prefix or an absolute local directory, not '{}'",
+            path
+        ),
+    }
+}
+
+// S3 paths can only be checked by the workers, which have the credentials to list them
+fn test_table(table: &ObjectStoreTable) -> anyhow::Result<String> {
+    validate_path(&table.path)?;
+    let pattern = file_pattern(table)?;
+
+    if s3_location(&table.path).is_some() {
+        return Ok("Successfully validated table".to_string());
+    }
+
+    let directory = local_directory(&table.path);
+    let files = std::fs::read_dir(directory)
+        .map_err(|e| anyhow!("Failed to list {}: {}", directory, e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map(|n| pattern.as_ref().map(|p| p.is_match(n)).unwrap_or(true))
+                .unwrap_or(false)
+        })
+        .count();
+
+    Ok(format!(
+        "Successfully listed {}; found {} matching files",
+        directory, files
+    ))
+}
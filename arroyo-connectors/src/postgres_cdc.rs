@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tokio_postgres::NoTls;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig,
+    OperatorConfigSerializationMode,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/postgres_cdc/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/postgres_cdc/table.json");
+const ICON: &str = include_str!("../resources/postgres_cdc.svg");
+
+import_types!(schema = "../connector-schemas/postgres_cdc/connection.json");
+import_types!(schema = "../connector-schemas/postgres_cdc/table.json");
+
+pub struct PostgresCdcConnector {}
+
+impl Connector for PostgresCdcConnector {
+    type ConfigT = PostgresCdcConfig;
+    type TableT = PostgresCdcTable;
+
+    fn name(&self) -> &'static str {
+        "postgres_cdc"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "postgres_cdc".to_string(),
+            name: "Postgres CDC".to_string(),
+            icon: ICON.to_string(),
+            description: "Read changes to a Postgres table from a logical replication slot"
+                .to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        // don't leak credentials embedded in the connection string
+        match config.connection_string.split_once('@') {
+            Some((_, hosts)) => hosts.to_string(),
+            None => (*config.connection_string).clone(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        PostgresCdcTester {
+            config,
+            table,
+            updating: schema
+                .map(|s| {
+                    matches!(
+                        serialization_mode(s),
+                        OperatorConfigSerializationMode::DebeziumJson
+                    )
+                })
+                .unwrap_or(false),
+            tx,
+        }
+        .start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Source
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Postgres CDC source"))?;
+        let serialization_mode = serialization_mode(&schema);
+
+        if !matches!(
+            serialization_mode,
+            OperatorConfigSerializationMode::Json | OperatorConfigSerializationMode::DebeziumJson
+        ) {
+            bail!("Postgres CDC sources only support the json and debezium_json formats");
+        }
+
+        if matches!(table.plugin, Plugin::Pgoutput) && table.publication.is_none() {
+            bail!("a publication is required to read changes with pgoutput");
+        }
+
+        let description = format!("PostgresCdcSource<{}>", table.table);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            operator: "connectors::postgres_cdc::PostgresCdcSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let config = PostgresCdcConfig {
+            connection_string: ConnectionString(pull_opt("connection_string", opts)?),
+        };
+
+        let table = PostgresCdcTable {
+            table: pull_opt("table", opts)?,
+            slot: pull_opt("slot", opts)?,
+            plugin: match opts.remove("plugin").as_deref() {
+                None | Some("pgoutput") => Plugin::Pgoutput,
+                Some("wal2json") => Plugin::Wal2json,
+                Some(other) => bail!("invalid value for plugin '{}'", other),
+            },
+            publication: opts.remove("publication"),
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        HashMap::from([(
+            "connection_string".to_string(),
+            config.connection_string.to_string(),
+        )])
+    }
+}
+
+struct PostgresCdcTester {
+    config: PostgresCdcConfig,
+    table: PostgresCdcTable,
+    updating: bool,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl PostgresCdcTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let message = match self.test_internal().await {
+                Ok(message) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message,
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if self.tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<String> {
+        let (client, connection) = tokio_postgres::connect(&*self.config.connection_string, NoTls)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Postgres: {}", e))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Postgres connection closed: {}", e);
+            }
+        });
+
+        let wal_level: String = client.query_one("SHOW wal_level", &[]).await?.get(0);
+        if wal_level != "logical" {
+            bail!(
+                "wal_level must be 'logical' to read changes, but it is '{}'",
+                wal_level
+            );
+        }
+
+        let replica_identity: i8 = client
+            .query_opt(
+                "SELECT relreplident FROM pg_class WHERE oid = to_regclass($1)",
+                &[&self.table.table],
+            )
+            .await?
+            .ok_or_else(|| anyhow!("Table '{}' does not exist", self.table.table))?
+            .get(0);
+
+        if let (Plugin::Pgoutput, Some(publication)) = (&self.table.plugin, &self.table.publication)
+        {
+            if client
+                .query_opt(
+                    "SELECT 1 FROM pg_publication WHERE pubname = $1",
+                    &[publication],
+                )
+                .await?
+                .is_none()
+            {
+                bail!("Publication '{}' does not exist", publication);
+            }
+        }
+
+        // without the complete old rows, updates and deletes can't be turned into Debezium changes
+        if self.updating && replica_identity != b'f' as i8 {
+            bail!(
+                "Table '{}' must have REPLICA IDENTITY FULL to read updates and deletes in the \
+                debezium_json format",
+                self.table.table
+            );
+        }
+
+        Ok("Successfully validated Postgres table".to_string())
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use ssh2::Session;
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    bad_data, csv_config, lineage, pull_opt, serialization_mode, Connection, ConnectionType,
+    OperatorConfig,
+};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/sftp/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/sftp/table.json");
+const ICON: &str = include_str!("../resources/sftp.svg");
+
+import_types!(schema = "../connector-schemas/sftp/connection.json");
+import_types!(schema = "../connector-schemas/sftp/table.json");
+
+pub struct SftpConnector {}
+
+impl Connector for SftpConnector {
+    type ConfigT = SftpConfig;
+    type TableT = SftpTable;
+
+    fn name(&self) -> &'static str {
+        "sftp"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "sftp".to_string(),
+            name: "SFTP".to_string(),
+            icon: ICON.to_string(),
+            description: "Read files dropped on an SFTP server".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        format!(
+            "{}@{}:{}",
+            config.username,
+            config.host,
+            config.port.unwrap_or(22)
+        )
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        SftpTester { config, table, tx }.start();
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Source
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        file_pattern(&table)?;
+
+        if table.poll_interval_seconds.map(|s| s <= 0).unwrap_or(false) {
+            bail!("pollIntervalSeconds must be positive");
+        }
+
+        if matches!(table.file_format, FileFormat::Csv) && schema.and_then(lineage).is_some() {
+            bail!("lineage is not supported for csv files");
+        }
+
+        let description = format!("SftpSource<{}:{}>", config.host, table.directory);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: csv_config(schema.as_ref().unwrap())?,
+            bad_data: bad_data(schema.as_ref().unwrap()),
+            lineage: lineage(schema.as_ref().unwrap()),
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema: schema
+                .map(|s| s.to_owned())
+                .ok_or_else(|| anyhow!("No schema defined for SFTP source"))?,
+            operator: "connectors::sftp::SftpSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let authentication = match pull_opt("auth.type", opts)?.as_str() {
+            "password" => Authentication::Password {
+                password: pull_opt("auth.password", opts)?,
+            },
+            "private_key" => Authentication::PrivateKey {
+                private_key: pull_opt("auth.private_key", opts)?,
+                passphrase: opts.remove("auth.passphrase"),
+            },
+            other => bail!("unknown auth type '{}'", other),
+        };
+
+        let config = SftpConfig {
+            host: pull_opt("host", opts)?,
+            port: opts
+                .remove("port")
+                .map(|p| {
+                    p.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for port '{}'", p))
+                })
+                .transpose()?,
+            username: pull_opt("username", opts)?,
+            authentication,
+        };
+
+        let post_processing = match opts.remove("post_processing").as_deref() {
+            None | Some("none") => PostProcessing::None {},
+            Some("delete") => PostProcessing::Delete {},
+            Some("move") => PostProcessing::Move {
+                destination: pull_opt("post_processing.destination", opts)?,
+            },
+            Some(other) => bail!("invalid value for post_processing '{}'", other),
+        };
+
+        let file_format = match opts.remove("file_format").as_deref() {
+            None | Some("lines") => FileFormat::Lines,
+            Some("csv") => FileFormat::Csv,
+            Some(other) => bail!("invalid value for file_format '{}'", other),
+        };
+
+        let table = SftpTable {
+            directory: pull_opt("directory", opts)?,
+            file_pattern: opts.remove("file_pattern"),
+            file_format,
+            poll_interval_seconds: opts
+                .remove("poll_interval_seconds")
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for poll_interval_seconds '{}'", s))
+                })
+                .transpose()?,
+            post_processing,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("host".to_string(), config.host);
+        if let Some(port) = config.port {
+            opts.insert("port".to_string(), port.to_string());
+        }
+        opts.insert("username".to_string(), config.username);
+
+        match config.authentication {
+            Authentication::Password { password } => {
+                opts.insert("auth.type".to_string(), "password".to_string());
+                opts.insert("auth.password".to_string(), password);
+            }
+            Authentication::PrivateKey {
+                private_key,
+                passphrase,
+            } => {
+                opts.insert("auth.type".to_string(), "private_key".to_string());
+                opts.insert("auth.private_key".to_string(), private_key);
+                if let Some(passphrase) = passphrase {
+                    opts.insert("auth.passphrase".to_string(), passphrase);
+                }
+            }
+        }
+
+        opts
+    }
+}
+
+/// The pattern must match the whole file name
+pub fn file_pattern(table: &SftpTable) -> anyhow::Result<Option<Regex>> {
+    table
+        .file_pattern
+        .as_ref()
+        .map(|p| Regex::new(&format!("^(?:{})$", p)))
+        .transpose()
+        .map_err(|e| anyhow!("invalid filePattern: {}", e))
+}
+
+pub fn connect(config: &SftpConfig) -> anyhow::Result<Session> {
+    let tcp = TcpStream::connect((config.host.as_str(), config.port.unwrap_or(22) as u16))
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", config.host, e))?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.handshake()?;
+
+    match &config.authentication {
+        Authentication::Password { password } => {
+            session.userauth_password(&config.username, password)?
+        }
+        Authentication::PrivateKey {
+            private_key,
+            passphrase,
+        } => session.userauth_pubkey_memory(
+            &config.username,
+            None,
+            private_key,
+            passphrase.as_deref(),
+        )?,
+    }
+
+    if !session.authenticated() {
+        bail!("Failed to authenticate as {}", config.username);
+    }
+
+    Ok(session)
+}
+
+struct SftpTester {
+    config: SftpConfig,
+    table: SftpTable,
+    tx: Sender<Result<TestSourceMessage, Status>>,
+}
+
+impl SftpTester {
+    pub fn start(self) {
+        tokio::task::spawn(async move {
+            let config = self.config.clone();
+            let table = self.table.clone();
+            let message =
+                match tokio::task::spawn_blocking(move || Self::test_internal(&config, &table))
+                    .await
+                    .map_err(|e| anyhow!("{}", e))
+                    .and_then(|r| r)
+                {
+                    Ok(files) => TestSourceMessage {
+                        error: false,
+                        done: true,
+                        message: format!(
+                            "Successfully connected; found {} matching files in {}",
+                            files, self.table.directory
+                        ),
+                    },
+                    Err(e) => TestSourceMessage {
+                        error: true,
+                        done: true,
+                        message: e.to_string(),
+                    },
+                };
+
+            if self.tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn test_internal(config: &SftpConfig, table: &SftpTable) -> anyhow::Result<usize> {
+        let session = connect(config)?;
+        let sftp = session.sftp()?;
+
+        let pattern = file_pattern(table)?;
+
+        let files = sftp
+            .readdir(Path::new(&table.directory))
+            .map_err(|e| anyhow!("Failed to list {}: {}", table.directory, e))?;
+
+        Ok(files
+            .iter()
+            .filter(|(path, stat)| {
+                stat.is_file()
+                    && path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .map(|n| pattern.as_ref().map(|p| p.is_match(n)).unwrap_or(true))
+                        .unwrap_or(false)
+            })
+            .count())
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use lettre::{
+    message::{Mailbox, Mailboxes},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{pull_opt, serialization_mode, Connection, ConnectionType, OperatorConfig};
+
+use super::Connector;
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/smtp/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/smtp/table.json");
+const ICON: &str = include_str!("../resources/smtp.svg");
+
+import_types!(schema = "../connector-schemas/smtp/connection.json");
+import_types!(schema = "../connector-schemas/smtp/table.json");
+
+pub struct SmtpConnector {}
+
+impl Connector for SmtpConnector {
+    type ConfigT = SmtpConfig;
+    type TableT = SmtpTable;
+
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "smtp".to_string(),
+            name: "Email (SMTP)".to_string(),
+            icon: ICON.to_string(),
+            description: "Send records as emails for low-volume alerting".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ConfigT) -> String {
+        match config.port {
+            Some(port) => format!("{}:{}", config.host, port),
+            None => config.host,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ConfigT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_connection(&config).await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully connected to SMTP server".to_string(),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        config
+            .from
+            .parse::<Mailbox>()
+            .map_err(|e| anyhow!("invalid from address '{}': {}", config.from, e))?;
+        table
+            .to
+            .parse::<Mailboxes>()
+            .map_err(|e| anyhow!("invalid recipients '{}': {}", table.to, e))?;
+
+        if table.digest_size.map(|s| s <= 0).unwrap_or(false) {
+            bail!("digestSize must be positive");
+        }
+
+        let description = format!("SmtpSink<{}>", table.to);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: schema
+                .map(|s| s.to_owned())
+                .ok_or_else(|| anyhow!("No schema defined for SMTP sink"))?,
+            operator: "connectors::smtp::SmtpSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let config = SmtpConfig {
+            host: pull_opt("host", opts)?,
+            port: opts
+                .remove("port")
+                .map(|p| {
+                    p.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for port '{}'", p))
+                })
+                .transpose()?,
+            tls: match opts.remove("tls").as_deref() {
+                None | Some("tls") => TlsMode::Tls,
+                Some("starttls") => TlsMode::Starttls,
+                Some("none") => TlsMode::None,
+                Some(other) => bail!("invalid value for tls '{}'", other),
+            },
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+            from: pull_opt("from", opts)?,
+        };
+
+        let table = SmtpTable {
+            to: pull_opt("to", opts)?,
+            subject: pull_opt("subject", opts)?,
+            body: pull_opt("body", opts)?,
+            digest_size: opts
+                .remove("digest_size")
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for digest_size '{}'", s))
+                })
+                .transpose()?,
+        };
+
+        self.from_config(None, name, config, table, schema)
+    }
+
+    fn config_options(&self, config: Self::ConfigT) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("host".to_string(), config.host);
+        if let Some(port) = config.port {
+            opts.insert("port".to_string(), port.to_string());
+        }
+        let tls = match config.tls {
+            TlsMode::Tls => "tls",
+            TlsMode::Starttls => "starttls",
+            TlsMode::None => "none",
+        };
+        opts.insert("tls".to_string(), tls.to_string());
+        if let Some(username) = config.username {
+            opts.insert("username".to_string(), username);
+        }
+        if let Some(password) = config.password {
+            opts.insert("password".to_string(), password);
+        }
+        opts.insert("from".to_string(), config.from);
+
+        opts
+    }
+}
+
+async fn test_connection(config: &SmtpConfig) -> anyhow::Result<()> {
+    let transport = match config.tls {
+        TlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+        TlsMode::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+        TlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+    };
+
+    let transport = match config.port {
+        Some(port) => transport.port(port as u16),
+        None => transport,
+    };
+
+    let transport = match (&config.username, &config.password) {
+        (Some(username), Some(password)) => {
+            transport.credentials(Credentials::new(username.clone(), password.clone()))
+        }
+        _ => transport,
+    };
+
+    if !transport
+        .build()
+        .test_connection()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", config.host, e))?
+    {
+        bail!("SMTP server at {} is not responding", config.host);
+    }
+
+    Ok(())
+}
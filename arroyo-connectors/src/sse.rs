@@ -5,6 +5,8 @@ use arroyo_rpc::grpc::{
     self,
     api::{ConnectionSchema, TestSourceMessage},
 };
+use arroyo_rpc::proxy::{self, ProxyConfig};
+use arroyo_rpc::tls;
 use arroyo_types::string_to_map;
 use eventsource_client::Client;
 use futures::StreamExt;
@@ -15,7 +17,8 @@ use typify::import_types;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    pull_opt, serialization_mode, Connection, ConnectionType, EmptyConfig, OperatorConfig,
+    bad_data, csv_config, pull_opt, serialization_mode, Connection, ConnectionType, EmptyConfig,
+    OperatorConfig,
 };
 
 use super::Connector;
@@ -87,11 +90,29 @@ impl Connector for SSEConnector {
             })?;
         }
 
+        if let Some(t) = &table.tls {
+            tls::TlsConfig::from_generated(t)
+                .validate()
+                .map_err(|e| anyhow!("invalid TLS config: {}", e))?;
+        }
+
+        if let Some(p) = &table.proxy {
+            ProxyConfig::from_generated(p)
+                .validate()
+                .map_err(|e| anyhow!(e))?;
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            batching: None,
+            connection_pool: None,
             serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: csv_config(schema.as_ref().unwrap())?,
+            bad_data: bad_data(schema.as_ref().unwrap()),
+            lineage: None,
         };
 
         Ok(Connection {
@@ -116,6 +137,8 @@ impl Connector for SSEConnector {
         let endpoint = pull_opt("endpoint", opts)?;
         let headers = opts.remove("headers");
         let events = opts.remove("events");
+        let tls = tls::TlsConfig::from_options(opts).map_err(|e| anyhow!(e))?;
+        let proxy = ProxyConfig::from_options(opts).map_err(|e| anyhow!(e))?;
 
         self.from_config(
             None,
@@ -125,6 +148,8 @@ impl Connector for SSEConnector {
                 endpoint,
                 events,
                 headers: headers.map(Headers),
+                tls: tls.map(|t| t.into_generated()),
+                proxy: proxy.map(|p| p.into_generated()),
             },
             schema,
         )
@@ -176,7 +201,20 @@ impl SseTester {
                 .map_err(|_| anyhow!("Invalid header '{}: {}'", k, v))?;
         }
 
-        let mut stream = client.build().stream();
+        let tls = self.config.tls.as_ref().map(tls::TlsConfig::from_generated);
+        let proxy =
+            ProxyConfig::resolve(self.config.proxy.as_ref().map(ProxyConfig::from_generated));
+
+        let mut stream = if tls.is_some() || proxy.is_some() {
+            client
+                .build_with_conn(
+                    proxy::https_connector(proxy.as_ref(), tls.as_ref())
+                        .map_err(|e| anyhow!("Invalid TLS or proxy config: {}", e))?,
+                )
+                .stream()
+        } else {
+            client.build().stream()
+        };
 
         let timeout = Duration::from_secs(30);
 
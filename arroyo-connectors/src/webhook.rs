@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::grpc::{
+    self,
+    api::{ConnectionSchema, TestSourceMessage},
+};
+use arroyo_types::string_to_map;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    pull_opt, serialization_mode, Connection, ConnectionType, EmptyConfig, OperatorConfig,
+    OperatorConfigSerializationMode,
+};
+
+use super::Connector;
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/webhook/table.json");
+const ICON: &str = include_str!("../resources/webhook.svg");
+
+import_types!(schema = "../connector-schemas/webhook/table.json");
+
+pub struct WebhookConnector {}
+
+impl Connector for WebhookConnector {
+    type ConfigT = EmptyConfig;
+    type TableT = WebhookTable;
+
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn metadata(&self) -> grpc::api::Connector {
+        grpc::api::Connector {
+            id: "webhook".to_string(),
+            name: "Webhook".to_string(),
+            icon: ICON.to_string(),
+            description: "POST records to an HTTP endpoint".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ConfigT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<TestSourceMessage, Status>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match test_internal(&table).await {
+                Ok(status) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: format!(
+                        "Successfully connected to {} (status {})",
+                        table.endpoint, status
+                    ),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            if tx.send(Ok(message)).await.is_err() {
+                warn!("Test API rx closed while sending message");
+            }
+        });
+    }
+
+    fn table_type(&self, _: Self::ConfigT, _: Self::TableT) -> grpc::api::TableType {
+        grpc::api::TableType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ConfigT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        for url in std::iter::once(&table.endpoint).chain(&table.dead_letter_endpoint) {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                bail!("'{}' is not an http or https url", url);
+            }
+        }
+
+        if let Some(headers) = &table.headers {
+            string_to_map(headers).ok_or_else(|| {
+                anyhow!(
+                    "Invalid format for headers; should be a \
+                    comma-separated list of colon-separated key value pairs"
+                )
+            })?;
+        }
+
+        for (name, value) in [
+            ("batchSize", table.batch_size),
+            ("maxConcurrency", table.max_concurrency),
+            ("timeoutMs", table.timeout_ms),
+            ("maxAttempts", table.max_attempts),
+        ] {
+            if value.map(|v| v <= 0).unwrap_or(false) {
+                bail!("{} must be positive", name);
+            }
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for webhook sink"))?;
+        let serialization_mode = serialization_mode(&schema);
+        if !matches!(
+            serialization_mode,
+            OperatorConfigSerializationMode::Json | OperatorConfigSerializationMode::DebeziumJson
+        ) {
+            bail!("Webhook sinks only support the json and debezium_json formats");
+        }
+
+        let description = format!("WebhookSink<{}>", table.endpoint);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            batching: None,
+            connection_pool: None,
+            serialization_mode: Some(serialization_mode),
+            protobuf: None,
+            csv: None,
+            bad_data: None,
+            lineage: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::webhook::WebhookSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let int_opt = |opts: &mut HashMap<String, String>, name: &str| {
+            opts.remove(name)
+                .map(|s| {
+                    s.parse::<i64>()
+                        .map_err(|_| anyhow!("invalid value for {} '{}'", name, s))
+                })
+                .transpose()
+        };
+
+        let body_format = opts
+            .remove("body_format")
+            .map(|f| match f.as_str() {
+                "single" => Ok(BodyFormat::Single),
+                "array" => Ok(BodyFormat::Array),
+                _ => Err(anyhow!(
+                    "invalid value for body_format '{}'; expected 'single' or 'array'",
+                    f
+                )),
+            })
+            .transpose()?;
+
+        let table = WebhookTable {
+            endpoint: pull_opt("endpoint", opts)?,
+            headers: opts.remove("headers").map(Headers),
+            body_format,
+            batch_size: int_opt(opts, "batch_size")?,
+            max_concurrency: int_opt(opts, "max_concurrency")?,
+            timeout_ms: int_opt(opts, "timeout_ms")?,
+            max_attempts: int_opt(opts, "max_attempts")?,
+            dead_letter_endpoint: opts.remove("dead_letter_endpoint"),
+        };
+
+        self.from_config(None, name, EmptyConfig {}, table, schema)
+    }
+}
+
+/// Checks that the endpoint can be reached, returning the status it responded with. Any response
+/// is accepted, as many endpoints only allow POSTs of the records they expect.
+async fn test_internal(table: &WebhookTable) -> anyhow::Result<u16> {
+    let headers = string_to_map(table.headers.as_ref().map(|h| h.0.as_str()).unwrap_or(""))
+        .ok_or_else(|| anyhow!("Headers are invalid; should be comma-separated pairs"))?;
+
+    let mut request = reqwest::Client::new()
+        .head(&table.endpoint)
+        .timeout(Duration::from_secs(10));
+    for (k, v) in headers {
+        request = request.header(k, v);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", table.endpoint, e))?;
+
+    Ok(response.status().as_u16())
+}
@@ -5,16 +5,19 @@ use arroyo_rpc::grpc::{
     self,
     api::{ConnectionSchema, TestSourceMessage},
 };
+use arroyo_rpc::proxy::{self, ProxyConfig};
+use arroyo_rpc::tls;
 use futures::{SinkExt, StreamExt};
 use tokio::sync::mpsc::Sender;
-use tokio_tungstenite::{connect_async, tungstenite};
+use tokio_tungstenite::{client_async_tls_with_config, tungstenite, Connector};
 use tonic::Status;
 use typify::import_types;
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    pull_opt, serialization_mode, Connection, ConnectionType, EmptyConfig, OperatorConfig,
+    bad_data, csv_config, pull_opt, serialization_mode, Connection, ConnectionType, EmptyConfig,
+    OperatorConfig,
 };
 
 use super::Connector;
@@ -74,7 +77,30 @@ impl Connector for WebsocketConnector {
                 }
             };
 
-            let ws_stream = match connect_async(&table.endpoint).await {
+            let connector = match table
+                .tls
+                .as_ref()
+                .map(|t| tls::TlsConfig::from_generated(t).native_tls_connector())
+                .transpose()
+            {
+                Ok(connector) => connector.map(Connector::NativeTls),
+                Err(e) => {
+                    send(true, true, format!("Invalid TLS config: {}", e)).await;
+                    return;
+                }
+            };
+
+            let proxy = ProxyConfig::resolve(table.proxy.as_ref().map(ProxyConfig::from_generated));
+
+            let result = match proxy::tcp_connect(&table.endpoint, proxy.as_ref()).await {
+                Ok(stream) => {
+                    client_async_tls_with_config(table.endpoint.as_str(), stream, None, connector)
+                        .await
+                }
+                Err(e) => Err(tungstenite::Error::Io(e)),
+            };
+
+            let ws_stream = match result {
                 Ok((ws_stream, _)) => ws_stream,
                 Err(e) => {
                     send(
@@ -161,11 +187,29 @@ impl Connector for WebsocketConnector {
     ) -> anyhow::Result<crate::Connection> {
         let description = format!("WebsocketSource<{}>", table.endpoint);
 
+        if let Some(t) = &table.tls {
+            tls::TlsConfig::from_generated(t)
+                .validate()
+                .map_err(|e| anyhow!("invalid TLS config: {}", e))?;
+        }
+
+        if let Some(p) = &table.proxy {
+            ProxyConfig::from_generated(p)
+                .validate()
+                .map_err(|e| anyhow!(e))?;
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            batching: None,
+            connection_pool: None,
             serialization_mode: Some(serialization_mode(schema.as_ref().unwrap())),
+            protobuf: None,
+            csv: csv_config(schema.as_ref().unwrap())?,
+            bad_data: bad_data(schema.as_ref().unwrap()),
+            lineage: None,
         };
 
         Ok(Connection {
@@ -189,6 +233,8 @@ impl Connector for WebsocketConnector {
     ) -> anyhow::Result<crate::Connection> {
         let endpoint = pull_opt("endpoint", opts)?;
         let subscription_message = opts.remove("subscription_message");
+        let tls = tls::TlsConfig::from_options(opts).map_err(|e| anyhow!(e))?;
+        let proxy = ProxyConfig::from_options(opts).map_err(|e| anyhow!(e))?;
 
         self.from_config(
             None,
@@ -197,6 +243,8 @@ impl Connector for WebsocketConnector {
             WebsocketTable {
                 endpoint,
                 subscription_message: subscription_message.map(SubscriptionMessage),
+                tls: tls.map(|t| t.into_generated()),
+                proxy: proxy.map(|p| p.into_generated()),
             },
             schema,
         )
@@ -1,4 +1,4 @@
---! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?)
+--! all_jobs : Job(ttl_micros?, restore_overrides?, failure_policy?, queue_config?, dependencies?, fork_from?, stop_at_event_time_micros?, recovery_throttle?, state_storage?, state?, start_time?, finish_time?, tasks?, failure_message?, poison_pill?, run_id?, pipeline_path?, wasm_path?, scheduling_intent?, waiting_for?)
 SELECT
     job_configs.id as id,
     job_configs.organization_id as org_id,
@@ -7,32 +7,51 @@ SELECT
     checkpoint_interval_micros,
     ttl_micros,
     parallelism_overrides,
+    env_vars,
+    restore_overrides,
+    failure_policy,
+    queue_config,
+    dependencies,
+    fork_from,
+    stop_at_event_time_micros,
+    recovery_throttle,
+    state_storage,
     stop,
     state,
     start_time,
     finish_time,
     tasks,
     failure_message,
+    poison_pill,
     restarts,
     run_id,
     pipeline_path,
-    wasm_path
+    wasm_path,
+    scheduling_intent,
+    waiting_for
 FROM job_configs
 LEFT JOIN job_statuses ON job_configs.id = job_statuses.id;
 
---! update_job_status (start_time?, finish_time?, tasks?, failure_message?, pipeline_path?, wasm_path?)
+--! update_job_status (start_time?, finish_time?, tasks?, failure_message?, poison_pill?, pipeline_path?, wasm_path?, scheduling_intent?)
 UPDATE job_statuses
 SET state = :state,
     start_time = :start_time,
     finish_time = :finish_time,
     tasks = :tasks,
     failure_message = :failure_message,
+    poison_pill = :poison_pill,
     restarts = :restarts,
     pipeline_path = :pipeline_path,
     wasm_path = :wasm_path,
+    scheduling_intent = :scheduling_intent,
     run_id = :run_id
 WHERE id = :job_id;
 
+--! clear_restore_overrides
+UPDATE job_configs
+SET restore_overrides = NULL
+WHERE id = :job_id AND restore_overrides->>'id' = :overrides_id;
+
 --! get_program
 SELECT program FROM pipelines WHERE id = :id;
 
@@ -85,3 +104,81 @@ LIMIT 1;
 INSERT INTO job_log_messages (pub_id, job_id, operator_id, task_index, log_level, message, details)
 VALUES (:pub_id, :job_id, :operator_id, :task_index, :log_level, :message, :details)
 RETURNING id;
+
+--! create_job_event
+INSERT INTO job_log_messages (pub_id, job_id, log_level, message, details)
+VALUES (:pub_id, :job_id, :log_level, :message, :details)
+RETURNING id;
+
+--! running_job_slos: (run_id?)
+SELECT job_configs.id as id, pipeline_name, run_id, slo
+FROM job_configs
+INNER JOIN job_statuses ON job_configs.id = job_statuses.id
+WHERE state = 'Running' AND slo IS NOT NULL;
+
+--! update_slo_violations (slo_violations?)
+UPDATE job_statuses
+SET slo_violations = :slo_violations
+WHERE id = :job_id;
+
+--! update_waiting_for (waiting_for?)
+UPDATE job_statuses
+SET waiting_for = :waiting_for
+WHERE id = :job_id;
+
+--! update_state_compaction
+UPDATE job_statuses
+SET state_compaction = :state_compaction
+WHERE id = :job_id;
+
+--! archive_job_run (start_time?, failure_message?, metrics?)
+INSERT INTO job_history (pub_id, organization_id, job_id, pipeline_id, pipeline_name, run_id, state, start_time, finish_time, restarts, failure_message, metrics, timeline, first_log_message_id, last_log_message_id)
+SELECT
+    :pub_id::VARCHAR,
+    job_configs.organization_id,
+    job_configs.id,
+    pipelines.pub_id,
+    job_configs.pipeline_name,
+    :run_id::BIGINT,
+    :state::TEXT,
+    :start_time::TIMESTAMPTZ,
+    :finish_time::TIMESTAMPTZ,
+    :restarts::INTEGER,
+    :failure_message::TEXT,
+    :metrics::JSONB,
+    COALESCE((
+        SELECT jsonb_agg(jsonb_build_object(
+            'timeMicros', (EXTRACT(EPOCH FROM created_at) * 1000000)::BIGINT,
+            'level', log_level,
+            'message', message,
+            'details', details) ORDER BY created_at)
+        FROM job_log_messages
+        WHERE job_id = :job_id AND operator_id IS NULL
+            AND created_at >= COALESCE(:start_time, '-infinity') AND created_at <= :finish_time
+    ), '[]'),
+    (SELECT MIN(id) FROM job_log_messages
+        WHERE job_id = :job_id AND created_at >= COALESCE(:start_time, '-infinity') AND created_at <= :finish_time),
+    (SELECT MAX(id) FROM job_log_messages
+        WHERE job_id = :job_id AND created_at >= COALESCE(:start_time, '-infinity') AND created_at <= :finish_time)
+FROM job_configs
+INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
+WHERE job_configs.id = :job_id
+ON CONFLICT (job_id, run_id, finish_time) DO NOTHING;
+
+--! prune_job_history
+DELETE FROM job_history
+WHERE finish_time < :before;
+
+--! prune_job_log_messages
+DELETE FROM job_log_messages
+WHERE created_at < :before;
+
+--! prune_previews
+DELETE FROM pipelines
+WHERE id IN (
+    SELECT pipeline_id
+    FROM job_configs
+    INNER JOIN job_statuses ON job_configs.id = job_statuses.id
+    WHERE ttl_micros IS NOT NULL
+        AND state IN ('Stopped', 'Finished', 'Failed')
+        AND finish_time < :before);
@@ -0,0 +1,434 @@
+//! Garbage collects compiled pipeline artifacts (the pipeline binary and wasm functions), which
+//! would otherwise accumulate forever. Artifacts live in three places:
+//! * the object store that the compiler service writes to, under `{job_id}/artifacts/{id}`
+//! * the output directory used when compiling locally, under a directory per program
+//! * the process scheduler's cache of the binaries of the jobs it runs, under a directory per job
+//!
+//! An artifact is removed once it is older than the retention period and isn't referenced by any
+//! active job or by any job that finished within the retention period. Because only old artifacts
+//! are considered, artifacts from compilations that haven't yet been recorded in a job's status are
+//! never removed. If a stopped job whose artifacts were removed is restarted, it is recompiled.
+//!
+//! The behavior is controlled by the `ARTIFACT_GC` environment variable:
+//! * `apply` (the default) removes orphaned artifacts
+//! * `dry-run` logs and counts the artifacts that would be removed without removing them
+//! * `disabled` doesn't run the garbage collector
+//!
+//! and the retention period by `ARTIFACT_RETENTION_HOURS` (7 days by default).
+
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail};
+use arroyo_types::{ARTIFACT_GC_ENV, ARTIFACT_RETENTION_HOURS_ENV};
+use deadpool_postgres::Pool;
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use prometheus::{register_int_counter, register_int_counter_vec, IntCounter, IntCounterVec};
+use regex::Regex;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::compiler::OUTPUT_PATH;
+use crate::queries::controller_queries;
+use crate::schedulers::PROCESS_BINARIES_PATH;
+
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// states in which a job no longer needs its artifacts
+const FINISHED_STATES: [&str; 3] = ["Stopped", "Finished", "Failed"];
+
+lazy_static! {
+    static ref ARTIFACTS_DELETED: IntCounterVec = register_int_counter_vec!(
+        "arroyo_controller_artifacts_deleted",
+        "number of orphaned pipeline artifacts deleted (or, in dry-run mode, that would have been)",
+        &["dry_run"]
+    )
+    .unwrap();
+    static ref ARTIFACT_BYTES_DELETED: IntCounterVec = register_int_counter_vec!(
+        "arroyo_controller_artifact_bytes_deleted",
+        "bytes of orphaned pipeline artifacts deleted (or, in dry-run mode, that would have been)",
+        &["dry_run"]
+    )
+    .unwrap();
+    static ref ARTIFACT_GC_ERRORS: IntCounter = register_int_counter!(
+        "arroyo_controller_artifact_gc_errors",
+        "number of errors encountered while garbage collecting pipeline artifacts"
+    )
+    .unwrap();
+    // paths written by the compiler service: {root}/{job_id}/artifacts/{id}/{file}
+    static ref ARTIFACT_PATH_REGEX: Regex =
+        Regex::new(r"^(?P<root>.+)/[^/]+/artifacts/[^/]+/[^/]+$").unwrap();
+    static ref S3_REGEX: Regex = Regex::new(
+        r"^s3://(?P<bucket>[^/\.]*)\.s3-(?P<region>[^\.]*).amazonaws.com(/(?P<path>.*))?$"
+    )
+    .unwrap();
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GcMode {
+    Apply,
+    DryRun,
+    Disabled,
+}
+
+impl GcMode {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env::var(ARTIFACT_GC_ENV).as_deref() {
+            Err(_) | Ok("apply") => Ok(GcMode::Apply),
+            Ok("dry-run") => Ok(GcMode::DryRun),
+            Ok("disabled") => Ok(GcMode::Disabled),
+            Ok(other) => bail!(
+                "invalid {} '{}'; expected apply, dry-run, or disabled",
+                ARTIFACT_GC_ENV,
+                other
+            ),
+        }
+    }
+}
+
+fn retention_from_env() -> anyhow::Result<Duration> {
+    match env::var(ARTIFACT_RETENTION_HOURS_ENV) {
+        Err(_) => Ok(DEFAULT_RETENTION),
+        Ok(hours) => hours
+            .parse::<u64>()
+            .map(|hours| Duration::from_secs(hours * 60 * 60))
+            .map_err(|_| {
+                anyhow!(
+                    "invalid {} '{}'; expected a number of hours",
+                    ARTIFACT_RETENTION_HOURS_ENV,
+                    hours
+                )
+            }),
+    }
+}
+
+// file urls are written with varying numbers of slashes (file:///tmp/.. and file:////tmp/..)
+fn normalize(url: &str) -> String {
+    match url.strip_prefix("file://") {
+        Some(path) => format!("file:///{}", path.trim_start_matches('/')),
+        None => url.to_string(),
+    }
+}
+
+/// The artifacts that are still needed
+#[derive(Default)]
+struct References {
+    // normalized paths of the artifacts of active and recently finished jobs
+    paths: HashSet<String>,
+    // ids of active and recently finished jobs
+    jobs: HashSet<String>,
+    // normalized roots of the object stores the compiler service has written artifacts to
+    roots: HashSet<String>,
+}
+
+impl References {
+    async fn load(pool: &Pool, retention: Duration) -> anyhow::Result<Self> {
+        let client = pool.get().await?;
+        let jobs = controller_queries::all_jobs().bind(&client).all().await?;
+        let cutoff = OffsetDateTime::now_utc() - retention;
+
+        let mut refs = Self::default();
+        for job in jobs {
+            let paths: Vec<_> = [&job.pipeline_path, &job.wasm_path]
+                .into_iter()
+                .flatten()
+                .map(|p| normalize(p))
+                .collect();
+
+            for path in &paths {
+                if let Some(m) = ARTIFACT_PATH_REGEX.captures(path) {
+                    refs.roots
+                        .insert(m.name("root").unwrap().as_str().to_string());
+                }
+            }
+
+            let finished = job
+                .state
+                .as_deref()
+                .map(|s| FINISHED_STATES.contains(&s))
+                .unwrap_or(false);
+            let recent = job.finish_time.map(|t| t > cutoff).unwrap_or(true);
+
+            if !finished || recent {
+                refs.paths.extend(paths);
+                refs.jobs.insert(job.id);
+            }
+        }
+
+        Ok(refs)
+    }
+}
+
+enum Location {
+    Local(PathBuf),
+    ObjectStore(Arc<dyn ObjectStore>, Vec<Path>),
+}
+
+struct Artifact {
+    // normalized url of the directory containing the artifact's files
+    url: String,
+    // set for the process scheduler's cache, which is used by whichever job it is for
+    job_id: Option<String>,
+    location: Location,
+    modified: SystemTime,
+    bytes: u64,
+}
+
+impl Artifact {
+    fn referenced(&self, refs: &References) -> bool {
+        match &self.job_id {
+            Some(job_id) => refs.jobs.contains(job_id),
+            None => {
+                let prefix = format!("{}/", self.url);
+                refs.paths.iter().any(|p| p.starts_with(&prefix))
+            }
+        }
+    }
+
+    async fn delete(&self) -> anyhow::Result<()> {
+        match &self.location {
+            Location::Local(dir) => tokio::fs::remove_dir_all(dir).await?,
+            Location::ObjectStore(store, objects) => {
+                for object in objects {
+                    store.delete(object).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn subdirectories(dir: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(dirs),
+        Err(e) => return Err(e.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+async fn local_artifact(dir: PathBuf, job_id: Option<String>) -> anyhow::Result<Artifact> {
+    let mut modified = tokio::fs::metadata(&dir).await?.modified()?;
+    let mut bytes = 0;
+
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        modified = modified.max(metadata.modified()?);
+        bytes += metadata.len();
+    }
+
+    Ok(Artifact {
+        url: normalize(&format!("file://{}", dir.to_string_lossy())),
+        job_id,
+        location: Location::Local(dir),
+        modified,
+        bytes,
+    })
+}
+
+/// Lists the artifacts the compiler service has written under a local output directory
+async fn local_store_artifacts(root: PathBuf) -> anyhow::Result<Vec<Artifact>> {
+    let mut artifacts = vec![];
+    for job_dir in subdirectories(root).await? {
+        for dir in subdirectories(job_dir.join("artifacts")).await? {
+            artifacts.push(local_artifact(dir, None).await?);
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Lists the artifacts the compiler service has written to an S3 bucket
+async fn s3_artifacts(root: &str) -> anyhow::Result<Vec<Artifact>> {
+    let m = S3_REGEX
+        .captures(root)
+        .ok_or_else(|| anyhow!("unsupported artifact store {}", root))?;
+    let bucket = m.name("bucket").unwrap().as_str();
+    let region = m.name("region").unwrap().as_str();
+    let base = m.name("path").map(|p| Path::from(p.as_str()));
+
+    let store: Arc<dyn ObjectStore> = Arc::new(
+        AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .with_region(region)
+            .build()?,
+    );
+
+    let mut artifacts = vec![];
+    for job_dir in store
+        .list_with_delimiter(base.as_ref())
+        .await?
+        .common_prefixes
+    {
+        let objects: Vec<_> = store
+            .list(Some(&job_dir.child("artifacts")))
+            .await?
+            .try_collect()
+            .await?;
+
+        // group the files by the artifact directory that contains them
+        let mut dirs: BTreeMap<String, Vec<_>> = BTreeMap::new();
+        for object in objects {
+            if let Some((dir, _)) = object.location.as_ref().rsplit_once('/') {
+                dirs.entry(dir.to_string()).or_default().push(object);
+            }
+        }
+
+        for (dir, objects) in dirs {
+            artifacts.push(Artifact {
+                url: format!("s3://{}.s3-{}.amazonaws.com/{}", bucket, region, dir),
+                job_id: None,
+                modified: objects
+                    .iter()
+                    .map(|o| SystemTime::from(o.last_modified))
+                    .max()
+                    .unwrap(),
+                bytes: objects.iter().map(|o| o.size as u64).sum(),
+                location: Location::ObjectStore(
+                    store.clone(),
+                    objects.into_iter().map(|o| o.location).collect(),
+                ),
+            });
+        }
+    }
+
+    Ok(artifacts)
+}
+
+async fn list_artifacts(refs: &References) -> anyhow::Result<Vec<Artifact>> {
+    let mut artifacts = vec![];
+
+    for dir in subdirectories(PathBuf::from(OUTPUT_PATH)).await? {
+        artifacts.push(local_artifact(dir, None).await?);
+    }
+
+    for dir in subdirectories(PathBuf::from(PROCESS_BINARIES_PATH)).await? {
+        let job_id = dir.file_name().unwrap().to_string_lossy().to_string();
+        artifacts.push(local_artifact(dir, Some(job_id)).await?);
+    }
+
+    for root in &refs.roots {
+        let result = match root.strip_prefix("file://") {
+            Some(path) => local_store_artifacts(PathBuf::from(path)).await,
+            None => s3_artifacts(root).await,
+        };
+
+        match result {
+            Ok(a) => artifacts.extend(a),
+            Err(e) => {
+                ARTIFACT_GC_ERRORS.inc();
+                warn!(
+                    message = "failed to list artifacts",
+                    root,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+    }
+
+    Ok(artifacts)
+}
+
+/// Removes the artifacts that are no longer needed, returning the urls of those that were removed
+/// (or, in dry-run mode, that would have been)
+pub async fn collect_garbage(
+    pool: &Pool,
+    mode: GcMode,
+    retention: Duration,
+) -> anyhow::Result<Vec<String>> {
+    let refs = References::load(pool, retention).await?;
+    let cutoff = SystemTime::now() - retention;
+    let dry_run = if mode == GcMode::DryRun {
+        "true"
+    } else {
+        "false"
+    };
+
+    let mut removed = vec![];
+    for artifact in list_artifacts(&refs).await? {
+        if artifact.modified > cutoff || artifact.referenced(&refs) {
+            continue;
+        }
+
+        if mode == GcMode::DryRun {
+            info!(
+                message = "would remove orphaned artifact",
+                url = artifact.url,
+                bytes = artifact.bytes
+            );
+        } else if let Err(e) = artifact.delete().await {
+            ARTIFACT_GC_ERRORS.inc();
+            warn!(
+                message = "failed to remove orphaned artifact",
+                url = artifact.url,
+                error = format!("{:?}", e)
+            );
+            continue;
+        } else {
+            info!(
+                message = "removed orphaned artifact",
+                url = artifact.url,
+                bytes = artifact.bytes
+            );
+        }
+
+        ARTIFACTS_DELETED.with_label_values(&[dry_run]).inc();
+        ARTIFACT_BYTES_DELETED
+            .with_label_values(&[dry_run])
+            .inc_by(artifact.bytes);
+        removed.push(artifact.url);
+    }
+
+    Ok(removed)
+}
+
+/// Starts garbage collecting artifacts periodically, according to the environment
+pub fn start_gc(pool: Pool) -> anyhow::Result<()> {
+    let mode = GcMode::from_env()?;
+    let retention = retention_from_env()?;
+
+    if mode == GcMode::Disabled {
+        info!("artifact garbage collection is disabled");
+        return Ok(());
+    }
+
+    tokio::spawn(async move {
+        loop {
+            match collect_garbage(&pool, mode, retention).await {
+                Ok(removed) => {
+                    info!(
+                        message = "finished artifact garbage collection",
+                        dry_run = mode == GcMode::DryRun,
+                        removed = removed.len()
+                    );
+                }
+                Err(e) => {
+                    ARTIFACT_GC_ERRORS.inc();
+                    warn!(
+                        message = "artifact garbage collection failed",
+                        error = format!("{:?}", e)
+                    );
+                }
+            }
+
+            tokio::time::sleep(GC_INTERVAL).await;
+        }
+    });
+
+    Ok(())
+}
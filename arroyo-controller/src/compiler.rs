@@ -3,6 +3,7 @@ use anyhow::{anyhow, Result};
 use arroyo_datastream::{parse_type, Operator, Program, WasmBehavior};
 use arroyo_rpc::grpc::compiler_grpc_client::CompilerGrpcClient;
 use arroyo_rpc::grpc::CompileQueryReq;
+use arroyo_server_common::tls;
 use arroyo_types::REMOTE_COMPILER_ENDPOINT_ENV;
 use petgraph::Direction;
 use proc_macro2::TokenStream;
@@ -16,7 +17,7 @@ use tokio::process::Command;
 use tonic::{Code, Request};
 use tracing::info;
 
-const OUTPUT_PATH: &str = "/tmp/arroyo_binaries";
+pub(crate) const OUTPUT_PATH: &str = "/tmp/arroyo_binaries";
 
 #[derive(Debug, Clone)]
 pub struct CompiledProgram {
@@ -60,10 +61,12 @@ impl ProgramCompiler {
             types: self.compile_types().to_string(),
             pipeline: self.compile_pipeline_main(&self.name, &self.program.get_hash()),
             wasm_fns: self.compile_wasm_lib().to_string(),
+            instrumented: self.program.instrumentation.is_some(),
         };
 
-        let mut client = CompilerGrpcClient::connect(endpoint)
+        let mut client = tls::connect_addr(endpoint)
             .await
+            .map(CompilerGrpcClient::new)
             .map_err(|e| io::Error::new(ErrorKind::Other, format!("{}", e)))?;
 
         let req = Request::new(req);
@@ -172,11 +175,7 @@ arroyo-worker = {{ path = "{}/arroyo-worker"{}}}
 "#,
             arroyo_dir.to_string_lossy(),
             arroyo_dir.to_string_lossy(),
-            if cfg!(feature = "kafka-sasl") {
-                ", features = [\"kafka-sasl\"]"
-            } else {
-                ""
-            }
+            self.worker_features()
         );
         Self::create_subproject(&dir, "pipeline", &pipeline_toml, "main.rs", main).await?;
 
@@ -290,6 +289,22 @@ wasm-opt = false
         }
     }
 
+    fn worker_features(&self) -> String {
+        let mut features = vec![];
+        if cfg!(feature = "kafka-sasl") {
+            features.push("\"kafka-sasl\"");
+        }
+        if self.program.instrumentation.is_some() {
+            features.push("\"instrumentation\"");
+        }
+
+        if features.is_empty() {
+            String::new()
+        } else {
+            format!(", features = [{}]", features.join(", "))
+        }
+    }
+
     fn compile_pipeline_main(&self, name: &str, hash: &str) -> String {
         let imports = quote! {
             #![allow(warnings)]
@@ -319,12 +334,22 @@ wasm-opt = false
 
         let make_graph_function = self.program.make_graph_function();
 
+        let configure_instrumentation = self.program.instrumentation.as_ref().map(|i| {
+            let record_sample_rate = i.record_sample_rate;
+            let trace_state = i.trace_state;
+            quote! {
+                arroyo_worker::instrumentation::configure(#record_sample_rate, #trace_state);
+            }
+        });
+
         prettyplease::unparse(&parse_quote! {
             #imports
 
             #make_graph_function
 
             pub fn main() {
+                #configure_instrumentation
+
                 let graph = make_graph();
 
                 arroyo_worker::WorkerServer::new(#name, #hash, graph).start().unwrap();
@@ -462,6 +487,13 @@ wasm-opt = false
             })
             .collect();
 
+        let wasm_defs: Vec<TokenStream> = self
+            .program
+            .wasm_defs
+            .iter()
+            .map(|t| parse_str(t).unwrap())
+            .collect();
+
         parse_quote! {
             use types::*;
             use std::time::SystemTime;
@@ -484,7 +516,20 @@ wasm-opt = false
                 unsafe { send(encoded.as_ptr(), encoded.len() as u32) };
             }
 
+            fn get_value<T: bincode::Decode>(len: u32) -> T {
+                let mut data = vec![0u8; len as usize];
+                unsafe { fetch_data(data.as_mut_ptr()) };
+                bincode::decode_from_slice(&data[..], bincode::config::standard()).unwrap().0
+            }
+
+            fn send_value<T: bincode::Encode>(value: T) {
+                let encoded = bincode::encode_to_vec(value, bincode::config::standard()).unwrap();
+                unsafe { send(encoded.as_ptr(), encoded.len() as u32) };
+            }
+
             #(#wasm_fns )*
+
+            #(#wasm_defs )*
         }
     }
 }
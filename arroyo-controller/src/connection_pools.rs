@@ -0,0 +1,75 @@
+//! Limits the connections that jobs open to external systems across the whole cluster, so that
+//! many pipelines writing to the same database can't together exhaust its connections.
+//!
+//! Each subtask that opens connections to a pooled system holds a permit for each of them, which
+//! it acquires before connecting. Permits are leases that the subtask renews by acquiring them
+//! again; a lease that isn't renewed (because the worker died or the job was stopped without
+//! releasing it) expires, so permits can't leak. Pools are only kept in memory: if the controller
+//! restarts, they're rebuilt as the running subtasks renew their leases.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use arroyo_rpc::grpc::{
+    AcquireConnectionPermitsReq, AcquireConnectionPermitsResp, ReleaseConnectionPermitsReq,
+};
+use arroyo_types::CONNECTION_PERMIT_LEASE;
+
+// (job id, operator id, subtask index)
+type Holder = (String, String, u32);
+
+struct Lease {
+    permits: u32,
+    renewed: Instant,
+}
+
+#[derive(Default)]
+pub struct ConnectionPools {
+    pools: HashMap<String, HashMap<Holder, Lease>>,
+}
+
+impl ConnectionPools {
+    /// Grants the permits requested by a subtask (replacing any it already holds) if that keeps
+    /// the pool within the requested limit
+    pub fn acquire(&mut self, req: AcquireConnectionPermitsReq) -> AcquireConnectionPermitsResp {
+        let now = Instant::now();
+        let leases = self.pools.entry(req.pool).or_default();
+        leases.retain(|_, lease| now.duration_since(lease.renewed) < CONNECTION_PERMIT_LEASE);
+
+        let holder = (req.job_id, req.operator_id, req.task_index);
+        let held_by_others: u32 = leases
+            .iter()
+            .filter(|(h, _)| **h != holder)
+            .map(|(_, lease)| lease.permits)
+            .sum();
+
+        if held_by_others + req.permits > req.max_connections {
+            return AcquireConnectionPermitsResp {
+                granted: false,
+                in_use: held_by_others + leases.get(&holder).map(|l| l.permits).unwrap_or(0),
+            };
+        }
+
+        leases.insert(
+            holder,
+            Lease {
+                permits: req.permits,
+                renewed: now,
+            },
+        );
+
+        AcquireConnectionPermitsResp {
+            granted: true,
+            in_use: held_by_others + req.permits,
+        }
+    }
+
+    pub fn release(&mut self, req: ReleaseConnectionPermitsReq) {
+        if let Some(leases) = self.pools.get_mut(&req.pool) {
+            leases.remove(&(req.job_id, req.operator_id, req.task_index));
+            if leases.is_empty() {
+                self.pools.remove(&req.pool);
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Defers the first start of jobs that depend on other jobs (see [`JobDependency`]) until each of
+//! their dependencies meets its condition. While a job is waiting, the reason is recorded in its
+//! status so that it can be shown through the API.
+//!
+//! Dependencies are only checked before a job is first started; once it has been started, it is
+//! restarted and recovered regardless of the state of the jobs it depends on.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use arroyo_types::{to_micros, DependencyCondition, JobDependency};
+
+use crate::task_progress::JobProgress;
+
+const DEFAULT_MAX_LAG: Duration = Duration::from_secs(60);
+
+/// What the job is waiting for before it can be started, or None if all of its dependencies have
+/// met their conditions. `states` holds the current state of every job by id.
+pub(crate) fn waiting_for(
+    dependencies: &[JobDependency],
+    states: &HashMap<String, String>,
+    progress: &JobProgress,
+    now: SystemTime,
+) -> Option<String> {
+    dependencies
+        .iter()
+        .find_map(|dependency| unmet(dependency, states, progress, now))
+}
+
+fn unmet(
+    dependency: &JobDependency,
+    states: &HashMap<String, String>,
+    progress: &JobProgress,
+    now: SystemTime,
+) -> Option<String> {
+    let job_id = &dependency.job_id;
+    let Some(state) = states.get(job_id) else {
+        return Some(format!("job {} does not exist", job_id));
+    };
+
+    let max_lag = dependency
+        .max_lag_micros
+        .map(Duration::from_micros)
+        .unwrap_or(DEFAULT_MAX_LAG);
+
+    let reason = match (dependency.condition, state.as_str()) {
+        // a job that finished has met every condition
+        (_, "Finished") => return None,
+        (DependencyCondition::Running, "Running") => return None,
+        (DependencyCondition::CaughtUp, "Running") => {
+            // the job's watermark is that of its furthest behind subtask
+            let watermark = progress
+                .latest(job_id)
+                .iter()
+                .map(|(_, task)| task.watermark_micros)
+                .min()
+                .flatten();
+
+            match watermark {
+                Some(watermark) => {
+                    let lag = Duration::from_micros(to_micros(now).saturating_sub(watermark));
+                    if lag <= max_lag {
+                        return None;
+                    }
+                    format!(
+                        "job {} to catch up to within {:?} of now (it is {:?} behind)",
+                        job_id,
+                        max_lag,
+                        Duration::from_secs(lag.as_secs())
+                    )
+                }
+                None => format!("job {} to report a watermark", job_id),
+            }
+        }
+        (DependencyCondition::Running, state) => {
+            format!("job {} to be running (it is {})", job_id, state)
+        }
+        (DependencyCondition::CaughtUp, state) => format!(
+            "job {} to be running and caught up to within {:?} of now (it is {})",
+            job_id, max_lag, state
+        ),
+        (DependencyCondition::Finished, state) => {
+            format!("job {} to finish (it is {})", job_id, state)
+        }
+    };
+
+    Some(format!("Waiting for {}", reason))
+}
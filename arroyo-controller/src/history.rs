@@ -0,0 +1,181 @@
+//! Keeps a summary of each finished run of a job in the `job_history` table (its final state, the
+//! final progress of its operators, its events, and the range of its log messages), so that runs
+//! remain visible after the job is restarted or deleted. Events are added to the job's log for each
+//! state transition, which makes up the timeline of the run.
+//!
+//! Finished jobs would otherwise leave their metadata behind forever, so it is pruned periodically
+//! according to the environment:
+//! * run summaries are kept for `JOB_HISTORY_RETENTION_DAYS` (90 by default)
+//! * job log messages are kept for `JOB_LOG_RETENTION_DAYS` (30 by default)
+//! * finished previews and debug runs, with their pipelines, are kept for `PREVIEW_RETENTION_HOURS`
+//!   (24 by default)
+//!
+//! Setting any of them to 0 keeps that data forever.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use arroyo_rpc::grpc::TaskProgressSample;
+use arroyo_types::{
+    OperatorRunMetrics, JOB_HISTORY_RETENTION_DAYS_ENV, JOB_LOG_RETENTION_DAYS_ENV,
+    PREVIEW_RETENTION_HOURS_ENV,
+};
+use deadpool_postgres::Pool;
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::job_store::JobStore;
+use crate::queries::controller_queries;
+use crate::task_progress::JobProgress;
+use crate::types::public::LogLevel;
+use crate::JobStatus;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+/// How long the metadata of finished jobs is kept; `None` keeps it forever
+#[derive(Copy, Clone, Debug)]
+pub struct Retention {
+    pub history: Option<Duration>,
+    pub logs: Option<Duration>,
+    pub previews: Option<Duration>,
+}
+
+fn duration_from_env(var: &str, unit: u64, default: u64) -> anyhow::Result<Option<Duration>> {
+    let count = match env::var(var) {
+        Err(_) => default,
+        Ok(count) => count
+            .parse::<u64>()
+            .map_err(|_| anyhow!("invalid {} '{}'; expected a whole number", var, count))?,
+    };
+
+    Ok((count > 0).then(|| Duration::from_secs(count * unit)))
+}
+
+impl Retention {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            history: duration_from_env(JOB_HISTORY_RETENTION_DAYS_ENV, DAY, 90)?,
+            logs: duration_from_env(JOB_LOG_RETENTION_DAYS_ENV, DAY, 30)?,
+            previews: duration_from_env(PREVIEW_RETENTION_HOURS_ENV, HOUR, 24)?,
+        })
+    }
+}
+
+/// Adds an event to the job's log, which is shown in the timeline of the run it happened in
+pub(crate) async fn record_event(
+    store: &dyn JobStore,
+    job_id: &str,
+    level: LogLevel,
+    message: &str,
+    details: &str,
+) {
+    if let Err(e) = store.create_event(job_id, level, message, details).await {
+        warn!(
+            message = "failed to record job event",
+            job_id,
+            error = format!("{:?}", e)
+        );
+    }
+}
+
+/// Sums the latest progress of each subtask into that of its operator
+fn operator_metrics(progress: Vec<(String, TaskProgressSample)>) -> Vec<OperatorRunMetrics> {
+    let mut operators: BTreeMap<String, OperatorRunMetrics> = BTreeMap::new();
+    for (operator_id, sample) in progress {
+        let metrics = operators
+            .entry(operator_id.clone())
+            .or_insert_with(|| OperatorRunMetrics {
+                operator_id,
+                watermark_micros: sample.watermark_micros,
+                ..Default::default()
+            });
+
+        metrics.records_in += sample.records_in;
+        metrics.records_out += sample.records_out;
+        metrics.state_bytes += sample.state_bytes;
+        metrics.watermark_micros = metrics.watermark_micros.min(sample.watermark_micros);
+    }
+
+    operators.into_values().collect()
+}
+
+/// Archives the summary of a run that has just finished; runs that were already archived (for
+/// example when a job's terminal state is re-entered after the controller restarts) are ignored
+pub(crate) async fn archive_run(
+    store: &dyn JobStore,
+    status: &JobStatus,
+    progress: &Mutex<JobProgress>,
+) {
+    let metrics = operator_metrics(progress.lock().await.latest(&status.id));
+    let metrics = (!metrics.is_empty()).then(|| serde_json::to_value(&metrics).unwrap());
+    let finish_time = status.finish_time.unwrap_or_else(OffsetDateTime::now_utc);
+
+    match store.archive_run(status, metrics, finish_time).await {
+        Ok(_) => info!(
+            message = "archived job run",
+            job_id = status.id,
+            run_id = status.run_id,
+            state = status.state
+        ),
+        Err(e) => warn!(
+            message = "failed to archive job run",
+            job_id = status.id,
+            run_id = status.run_id,
+            error = format!("{:?}", e)
+        ),
+    }
+}
+
+async fn prune(pool: &Pool, retention: Retention) -> anyhow::Result<()> {
+    let client = pool.get().await?;
+    let now = OffsetDateTime::now_utc();
+
+    if let Some(r) = retention.history {
+        let removed = controller_queries::prune_job_history()
+            .bind(&client, &(now - r))
+            .await?;
+        info!(message = "pruned job history", removed);
+    }
+
+    if let Some(r) = retention.logs {
+        let removed = controller_queries::prune_job_log_messages()
+            .bind(&client, &(now - r))
+            .await?;
+        info!(message = "pruned job log messages", removed);
+    }
+
+    if let Some(r) = retention.previews {
+        let removed = controller_queries::prune_previews()
+            .bind(&client, &(now - r))
+            .await?;
+        info!(message = "pruned finished previews", removed);
+    }
+
+    Ok(())
+}
+
+/// Starts pruning the metadata of finished jobs periodically, according to the environment
+pub fn start_pruner(pool: Pool) -> anyhow::Result<()> {
+    let retention = Retention::from_env()?;
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = prune(&pool, retention).await {
+                warn!(
+                    message = "failed to prune job metadata",
+                    error = format!("{:?}", e)
+                );
+            }
+
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    });
+
+    Ok(())
+}
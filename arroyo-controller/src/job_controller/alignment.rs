@@ -0,0 +1,150 @@
+//! Coordinates watermark alignment groups. Aligned watermark generators report their watermarks
+//! to the controller; sources whose watermark is more than the group's max drift ahead of the
+//! slowest member of the group are paused until the rest of the group catches up.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use arroyo_datastream::{EdgeType, Operator, Program};
+use arroyo_rpc::grpc::SourcePause;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Direction};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Members that haven't reported a watermark in this long (for example because their partitions
+/// are idle) don't hold back the rest of the group
+pub(super) const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Member {
+    group: String,
+    source_operator_id: String,
+}
+
+struct SubtaskWatermark {
+    watermark: SystemTime,
+    updated: Instant,
+}
+
+#[derive(Default)]
+pub struct WatermarkAligner {
+    max_drift: HashMap<String, Duration>,
+    // watermark operator id -> member
+    members: HashMap<String, Member>,
+    // (watermark operator id, subtask) -> last reported watermark
+    watermarks: HashMap<(String, u32), SubtaskWatermark>,
+}
+
+impl WatermarkAligner {
+    pub fn new(program: &Program) -> Self {
+        let mut aligner = Self::default();
+
+        for idx in program.graph.node_indices() {
+            let node = &program.graph[idx];
+            let Operator::Watermark(watermark) = &node.operator else {
+                continue;
+            };
+            let Some(alignment) = watermark.alignment() else {
+                continue;
+            };
+
+            let Some(source) = upstream_source(program, idx) else {
+                warn!(
+                    message = "aligned watermark is not forward-connected to a source; ignoring",
+                    operator_id = node.operator_id
+                );
+                continue;
+            };
+
+            aligner
+                .max_drift
+                .entry(alignment.group.clone())
+                .and_modify(|d| *d = (*d).min(alignment.max_drift))
+                .or_insert(alignment.max_drift);
+
+            aligner.members.insert(
+                node.operator_id.clone(),
+                Member {
+                    group: alignment.group.clone(),
+                    source_operator_id: program.graph[source].operator_id.clone(),
+                },
+            );
+        }
+
+        aligner
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn is_member(&self, operator_id: &str) -> bool {
+        self.members.contains_key(operator_id)
+    }
+
+    pub fn update(&mut self, operator_id: String, subtask: u32, watermark: SystemTime) {
+        if !self.members.contains_key(&operator_id) {
+            warn!(
+                message = "received watermark for operator that isn't aligned",
+                operator_id
+            );
+            return;
+        }
+
+        let entry = self
+            .watermarks
+            .entry((operator_id, subtask))
+            .or_insert(SubtaskWatermark {
+                watermark,
+                updated: Instant::now(),
+            });
+        entry.watermark = entry.watermark.max(watermark);
+        entry.updated = Instant::now();
+    }
+
+    /// Whether each source subtask that has reported a watermark should be paused
+    pub fn pauses(&self) -> Vec<SourcePause> {
+        let mut min_watermarks: HashMap<&str, SystemTime> = HashMap::new();
+        for ((operator_id, _), w) in &self.watermarks {
+            if w.updated.elapsed() > IDLE_TIMEOUT {
+                continue;
+            }
+            let group = self.members[operator_id].group.as_str();
+            let min = min_watermarks.entry(group).or_insert(w.watermark);
+            *min = (*min).min(w.watermark);
+        }
+
+        self.watermarks
+            .iter()
+            .map(|((operator_id, subtask), w)| {
+                let member = &self.members[operator_id];
+                SourcePause {
+                    operator_id: member.source_operator_id.clone(),
+                    operator_subtask: *subtask as u64,
+                    paused: min_watermarks
+                        .get(member.group.as_str())
+                        .map(|min| w.watermark > *min + self.max_drift[&member.group])
+                        .unwrap_or(false),
+                }
+            })
+            .collect()
+    }
+}
+
+// Follows forward edges back from the watermark to the source that feeds it; the subtasks of
+// operators connected by forward edges correspond one-to-one
+pub(super) fn upstream_source(program: &Program, mut idx: NodeIndex) -> Option<NodeIndex> {
+    loop {
+        let mut inputs = program.graph.edges_directed(idx, Direction::Incoming);
+        let Some(edge) = inputs.next() else {
+            return Some(idx);
+        };
+
+        if inputs.next().is_some() || edge.weight().typ != EdgeType::Forward {
+            return None;
+        }
+
+        idx = edge.source();
+    }
+}
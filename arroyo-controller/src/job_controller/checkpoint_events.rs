@@ -0,0 +1,221 @@
+//! Exports a structured event for each checkpoint once all of its operators have finished, so that
+//! checkpoint behavior can be analyzed outside of Arroyo over longer periods than the database
+//! retains. Events are sent to a webhook (`CHECKPOINT_EVENTS_WEBHOOK_URL`) and/or a Kafka topic
+//! (`CHECKPOINT_EVENTS_KAFKA_BOOTSTRAP_SERVERS` and `CHECKPOINT_EVENTS_KAFKA_TOPIC`) in addition to
+//! being recorded in the database.
+//!
+//! Exporting is best-effort: failures are logged and never hold up or fail the checkpoint.
+
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use arroyo_rpc::grpc::api::{self, OperatorCheckpointDetail};
+use arroyo_server_common::http::{HttpClient, HttpError};
+use arroyo_types::{
+    CHECKPOINT_EVENTS_KAFKA_BOOTSTRAP_SERVERS_ENV, CHECKPOINT_EVENTS_KAFKA_TOPIC_ENV,
+    CHECKPOINT_EVENTS_WEBHOOK_URL_ENV,
+};
+use lazy_static::lazy_static;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use rdkafka::ClientConfig;
+use serde::Serialize;
+use tracing::{info, warn};
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref EXPORTER: Option<Exporter> = Exporter::from_env();
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointEvent {
+    pub job_id: String,
+    pub epoch: u32,
+    pub min_epoch: u32,
+    pub start_time: u64,
+    pub finish_time: u64,
+    pub duration_micros: u64,
+    pub bytes: u64,
+    /// whether the checkpoint has a commit phase that follows, for operators with transactional
+    /// writes
+    pub needs_commit: bool,
+    pub operators: Vec<OperatorCheckpointEvent>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorCheckpointEvent {
+    pub operator_id: String,
+    pub start_time: u64,
+    pub finish_time: Option<u64>,
+    pub duration_micros: Option<u64>,
+    pub bytes: u64,
+    /// the longest time any of the operator's subtasks spent aligning barriers from its inputs
+    /// before it started checkpointing
+    pub alignment_micros: u64,
+}
+
+impl OperatorCheckpointEvent {
+    pub fn new(detail: &OperatorCheckpointDetail) -> Self {
+        let bytes = detail.tasks.values().filter_map(|t| t.bytes).sum();
+
+        let alignment_micros = detail
+            .tasks
+            .values()
+            .filter_map(alignment_micros)
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            operator_id: detail.operator_id.clone(),
+            start_time: detail.start_time,
+            finish_time: detail.finish_time,
+            duration_micros: detail
+                .finish_time
+                .map(|finish| finish.saturating_sub(detail.start_time)),
+            bytes,
+            alignment_micros,
+        }
+    }
+}
+
+fn alignment_micros(task: &api::TaskCheckpointDetail) -> Option<u64> {
+    let time_of = |event_type: api::TaskCheckpointEventType| {
+        task.events
+            .iter()
+            .find(|e| e.event_type == event_type as i32)
+            .map(|e| e.time)
+    };
+
+    let started = time_of(api::TaskCheckpointEventType::AlignmentStarted)?;
+    let finished = time_of(api::TaskCheckpointEventType::CheckpointStarted)?;
+    Some(finished.saturating_sub(started))
+}
+
+/// Builds the per-operator events for a checkpoint, ordered by operator id
+pub fn operator_events(
+    details: &HashMap<String, OperatorCheckpointDetail>,
+) -> Vec<OperatorCheckpointEvent> {
+    let mut operators: Vec<_> = details.values().map(OperatorCheckpointEvent::new).collect();
+    operators.sort_by(|a, b| a.operator_id.cmp(&b.operator_id));
+    operators
+}
+
+struct Exporter {
+    webhook_url: Option<String>,
+    http: HttpClient,
+    kafka: Option<(FutureProducer, String)>,
+}
+
+impl Exporter {
+    fn from_env() -> Option<Self> {
+        let webhook_url = env::var(CHECKPOINT_EVENTS_WEBHOOK_URL_ENV).ok();
+
+        let kafka = match (
+            env::var(CHECKPOINT_EVENTS_KAFKA_BOOTSTRAP_SERVERS_ENV),
+            env::var(CHECKPOINT_EVENTS_KAFKA_TOPIC_ENV),
+        ) {
+            (Ok(servers), Ok(topic)) => {
+                match ClientConfig::new()
+                    .set("bootstrap.servers", &servers)
+                    .create::<FutureProducer>()
+                {
+                    Ok(producer) => Some((producer, topic)),
+                    Err(e) => {
+                        warn!(
+                            message = "Failed to create Kafka producer for checkpoint events",
+                            error = format!("{:?}", e)
+                        );
+                        None
+                    }
+                }
+            }
+            (Ok(_), Err(_)) | (Err(_), Ok(_)) => {
+                warn!(
+                    "Both {} and {} must be set to export checkpoint events to Kafka",
+                    CHECKPOINT_EVENTS_KAFKA_BOOTSTRAP_SERVERS_ENV,
+                    CHECKPOINT_EVENTS_KAFKA_TOPIC_ENV
+                );
+                None
+            }
+            _ => None,
+        };
+
+        if webhook_url.is_none() && kafka.is_none() {
+            return None;
+        }
+
+        info!(
+            message = "Exporting checkpoint events",
+            webhook_url = ?webhook_url,
+            kafka_topic = ?kafka.as_ref().map(|(_, topic)| topic)
+        );
+
+        Some(Self {
+            webhook_url,
+            http: HttpClient::new("checkpoint_events"),
+            kafka,
+        })
+    }
+
+    fn publish(&'static self, event: CheckpointEvent) {
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!(
+                    message = "Failed to serialize checkpoint event",
+                    error = format!("{:?}", e)
+                );
+                return;
+            }
+        };
+
+        if let Some(url) = &self.webhook_url {
+            let request = self
+                .http
+                .post(url)
+                .timeout(SEND_TIMEOUT)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+
+            tokio::spawn(async move {
+                let result = match self.http.send(request).await {
+                    Ok(response) => response.error_for_status().map_err(HttpError::Request),
+                    Err(e) => Err(e),
+                };
+
+                if let Err(e) = result {
+                    warn!(
+                        message = "Failed to send checkpoint event webhook",
+                        url,
+                        error = format!("{}", e)
+                    );
+                }
+            });
+        }
+
+        if let Some((producer, topic)) = &self.kafka {
+            let job_id = event.job_id;
+            tokio::spawn(async move {
+                let record = FutureRecord::to(topic).key(&job_id).payload(&body);
+                if let Err((e, _)) = producer.send(record, Timeout::After(SEND_TIMEOUT)).await {
+                    warn!(
+                        message = "Failed to send checkpoint event to Kafka",
+                        topic,
+                        error = format!("{:?}", e)
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Exports the event to the configured destinations, if any
+pub fn publish(event: CheckpointEvent) {
+    if let Some(exporter) = EXPORTER.as_ref() {
+        exporter.publish(event);
+    }
+}
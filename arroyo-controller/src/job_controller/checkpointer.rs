@@ -3,20 +3,19 @@ use std::{
     time::SystemTime,
 };
 
-use crate::queries::controller_queries;
+use super::checkpoint_events::{self, CheckpointEvent};
+use crate::job_store::JobStore;
 use anyhow::bail;
 use arroyo_datastream::Program;
 use arroyo_rpc::grpc::{
     self,
     api::{self, OperatorCheckpointDetail},
     backend_data, BackendData, CheckpointMetadata, OperatorCheckpointMetadata,
-    SubtaskCheckpointMetadata, TableDescriptor, TableWriteBehavior, TaskCheckpointCompletedReq,
-    TaskCheckpointEventReq, TaskCheckpointEventType,
+    SubtaskCheckpointMetadata, TableDescriptor, TableSchema, TableWriteBehavior,
+    TaskCheckpointCompletedReq, TaskCheckpointEventReq, TaskCheckpointEventType,
 };
-use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_state::{BackingStore, StateBackend};
-use arroyo_types::{from_micros, to_micros};
-use deadpool_postgres::Pool;
+use arroyo_types::{from_micros, to_micros, STATE_VERSION};
 use time::OffsetDateTime;
 use tracing::{debug, info, warn};
 
@@ -101,15 +100,41 @@ impl CommittingState {
         self.subtasks_to_commit.is_empty()
     }
 
-    pub async fn finish(self, pool: &Pool) -> anyhow::Result<()> {
-        let finish_time = SystemTime::now();
+    /// The operators that have subtasks left to commit
+    pub fn operators(&self) -> Vec<String> {
+        let operators: HashSet<_> = self
+            .subtasks_to_commit
+            .iter()
+            .map(|(operator_id, _)| operator_id.clone())
+            .collect();
+        operators.into_iter().collect()
+    }
 
-        let c = pool.get().await?;
-        controller_queries::commit_checkpoint()
-            .bind(&c, &finish_time.into(), &self.checkpoint_id)
-            .await?;
+    fn export_event(&self, finish_time: SystemTime, needs_commit: bool) {
+        let operators = checkpoint_events::operator_events(&self.operator_details);
+        checkpoint_events::publish(CheckpointEvent {
+            job_id: self.job_id.clone(),
+            epoch: self.epoch,
+            min_epoch: self.min_epoch,
+            start_time: to_micros(self.start_time),
+            finish_time: to_micros(finish_time),
+            duration_micros: finish_time
+                .duration_since(self.start_time)
+                .unwrap_or_default()
+                .as_micros() as u64,
+            bytes: operators.iter().map(|op| op.bytes).sum(),
+            needs_commit,
+            operators,
+        });
+    }
 
-        Ok(())
+    pub async fn finish(self, store: &dyn JobStore) -> anyhow::Result<()> {
+        let finish_time = SystemTime::now();
+        self.export_event(finish_time, false);
+
+        store
+            .commit_checkpoint(self.checkpoint_id, finish_time.into())
+            .await
     }
 }
 
@@ -129,7 +154,7 @@ impl CheckpointState {
         epoch: u32,
         min_epoch: u32,
         program: &Program,
-        pool: &Pool,
+        store: &dyn JobStore,
     ) -> anyhow::Result<Self> {
         StateBackend::initialize_checkpoint(
             &job_id,
@@ -152,22 +177,9 @@ impl CheckpointState {
 
         let start = OffsetDateTime::now_utc();
 
-        let checkpoint_id = {
-            let c = pool.get().await?;
-            controller_queries::create_checkpoint()
-                .bind(
-                    &c,
-                    &generate_id(IdTypes::Checkpoint),
-                    &organization_id,
-                    &job_id,
-                    &StateBackend::name().to_string(),
-                    &(epoch as i32),
-                    &(min_epoch as i32),
-                    &start,
-                )
-                .one()
-                .await?
-        };
+        let checkpoint_id = store
+            .create_checkpoint(organization_id, &job_id, epoch, min_epoch, start)
+            .await?;
 
         Ok(Self {
             job_id,
@@ -368,6 +380,12 @@ impl CheckpointState {
             .values()
             .fold(0, |size, s| size + s.metadata.as_ref().unwrap().bytes);
 
+        let table_schemas: HashMap<(String, u64), TableSchema> = subtasks
+            .values()
+            .flat_map(|t| t.metadata.as_ref().unwrap().table_schemas.clone())
+            .map(|s| ((s.table.clone(), s.fingerprint), s))
+            .collect();
+
         StateBackend::complete_operator_checkpoint(OperatorCheckpointMetadata {
             job_id: self.job_id.to_string(),
             operator_id: operator_id.clone(),
@@ -380,6 +398,7 @@ impl CheckpointState {
             tables: tables.into_values().collect(),
             backend_data: backend_data.into_values().collect(),
             bytes: size,
+            table_schemas: table_schemas.into_values().collect(),
         })
         .await;
 
@@ -409,24 +428,20 @@ impl CheckpointState {
         CommittingState::new(self.checkpoint_id, self.subtasks_to_commit.clone())
     }
 
-    pub async fn update_db(&self, pool: &Pool) -> anyhow::Result<()> {
-        let c = pool.get().await?;
-
-        controller_queries::update_checkpoint()
-            .bind(
-                &c,
-                &serde_json::to_value(&self.operator_details).unwrap(),
-                &None,
-                &crate::types::public::CheckpointState::inprogress,
-                &self.checkpoint_id,
+    pub async fn update_db(&self, store: &dyn JobStore) -> anyhow::Result<()> {
+        store
+            .update_checkpoint(
+                self.checkpoint_id,
+                serde_json::to_value(&self.operator_details).unwrap(),
+                None,
+                crate::types::public::CheckpointState::inprogress,
             )
-            .await?;
-
-        Ok(())
+            .await
     }
 
-    pub async fn finish(self, pool: &Pool) -> anyhow::Result<()> {
+    pub async fn finish(self, store: &dyn JobStore) -> anyhow::Result<()> {
         let finish_time = SystemTime::now();
+        self.export_event(finish_time, false);
         StateBackend::complete_checkpoint(CheckpointMetadata {
             job_id: self.job_id,
             epoch: self.epoch,
@@ -434,26 +449,24 @@ impl CheckpointState {
             finish_time: to_micros(finish_time),
             min_epoch: self.min_epoch,
             operator_ids: self.completed_operators.iter().cloned().collect(),
+            state_version: STATE_VERSION,
         })
         .await;
 
         let operator_state = serde_json::to_value(&self.operator_details).unwrap();
 
-        let c = pool.get().await?;
-        controller_queries::update_checkpoint()
-            .bind(
-                &c,
-                &operator_state,
-                &Some(finish_time.into()),
-                &crate::types::public::CheckpointState::ready,
-                &self.checkpoint_id,
+        store
+            .update_checkpoint(
+                self.checkpoint_id,
+                operator_state,
+                Some(finish_time.into()),
+                crate::types::public::CheckpointState::ready,
             )
-            .await?;
-
-        Ok(())
+            .await
     }
-    pub async fn pre_commit_finish(self, pool: &Pool) -> anyhow::Result<()> {
+    pub async fn pre_commit_finish(self, store: &dyn JobStore) -> anyhow::Result<()> {
         let finish_time = SystemTime::now();
+        self.export_event(finish_time, true);
         StateBackend::complete_checkpoint(CheckpointMetadata {
             job_id: self.job_id,
             epoch: self.epoch,
@@ -461,22 +474,19 @@ impl CheckpointState {
             finish_time: to_micros(finish_time),
             min_epoch: self.min_epoch,
             operator_ids: self.completed_operators.iter().cloned().collect(),
+            state_version: STATE_VERSION,
         })
         .await;
 
         let operator_state = serde_json::to_value(&self.operator_details).unwrap();
 
-        let c = pool.get().await?;
-        controller_queries::update_checkpoint()
-            .bind(
-                &c,
-                &operator_state,
-                &None,
-                &crate::types::public::CheckpointState::committing,
-                &self.checkpoint_id,
+        store
+            .update_checkpoint(
+                self.checkpoint_id,
+                operator_state,
+                None,
+                crate::types::public::CheckpointState::committing,
             )
-            .await?;
-
-        Ok(())
+            .await
     }
 }
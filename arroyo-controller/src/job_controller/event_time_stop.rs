@@ -0,0 +1,56 @@
+//! Stops a job once it has processed its input up to a point in event time. Watermark generators
+//! drop records after the stop time and report their watermarks once they pass it; when every
+//! watermark subtask has, the sources are drained, finishing as though their input had ended so
+//! that all windows are flushed and the job finishes.
+
+use std::{collections::HashSet, time::SystemTime};
+
+use arroyo_datastream::{Operator, Program};
+
+pub struct EventTimeStop {
+    stop_at: SystemTime,
+    // (watermark operator id, subtask) that haven't passed the stop time yet
+    pending: HashSet<(String, u32)>,
+    draining: bool,
+}
+
+impl EventTimeStop {
+    /// Returns None if the program has no watermarks, in which case its event time never advances
+    pub fn new(program: &Program, stop_at: SystemTime) -> Option<Self> {
+        let pending: HashSet<_> = program
+            .graph
+            .node_weights()
+            .filter(|node| matches!(node.operator, Operator::Watermark(_)))
+            .flat_map(|node| {
+                (0..node.parallelism).map(|subtask| (node.operator_id.clone(), subtask as u32))
+            })
+            .collect();
+
+        (!pending.is_empty()).then_some(Self {
+            stop_at,
+            pending,
+            draining: false,
+        })
+    }
+
+    pub fn stop_at(&self) -> SystemTime {
+        self.stop_at
+    }
+
+    pub fn update(&mut self, operator_id: &str, subtask: u32, watermark: SystemTime) {
+        if watermark >= self.stop_at {
+            self.pending.remove(&(operator_id.to_string(), subtask));
+        }
+    }
+
+    /// Whether the sources should be drained now; true only once, after every watermark has
+    /// passed the stop time
+    pub fn should_drain(&mut self) -> bool {
+        if self.draining || !self.pending.is_empty() {
+            return false;
+        }
+
+        self.draining = true;
+        true
+    }
+}
@@ -1,34 +1,76 @@
 use std::{
     collections::HashMap,
-    time::{Duration, Instant, SystemTime},
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 use crate::types::public::StopMode as SqlStopMode;
 use anyhow::bail;
 use arroyo_datastream::Program;
+use arroyo_rpc::column_stats::merge_operators;
 use arroyo_rpc::grpc::{
-    worker_grpc_client::WorkerGrpcClient, CheckpointReq, JobFinishedReq, StopExecutionReq,
-    StopMode, TaskCheckpointEventType,
+    worker_grpc_client::WorkerGrpcClient, AlignSourcesReq, CheckpointReq, ColumnStatsReq,
+    InjectProbeReq, JobFinishedReq, LoadCompactedStateReq, OperatorColumnStats, SampleOutputReq,
+    SetLogFilterReq, SinkOutputSample, SourcePause, StopExecutionReq, StopMode,
+    TaskCheckpointEventType,
 };
+use arroyo_state::parquet::CompactedOperatorState;
 use arroyo_state::{BackingStore, StateBackend};
-use arroyo_types::{to_micros, WorkerId};
+use arroyo_types::{
+    to_micros, to_millis, RestoreOverrides, StateCompaction, StateCompactionState, WorkerId,
+    STATE_COMPACTION_EVERY_ENV,
+};
 
-use deadpool_postgres::Pool;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec};
 
-use tokio::{sync::mpsc::Receiver, task::JoinHandle};
-use tonic::{transport::Channel, Request};
+use tokio::{
+    sync::mpsc::Receiver,
+    task::{JoinError, JoinHandle},
+    time::Instant,
+};
+use tonic::{transport::Channel, Code, Request, Status};
 use tracing::{error, info, warn};
 
-use crate::{queries::controller_queries, JobConfig, JobMessage, RunningMessage};
+use crate::job_store::JobStore;
+use crate::{JobConfig, JobMessage, RunningMessage};
 
+use self::alignment::WatermarkAligner;
 use self::checkpointer::{CheckpointState, CheckpointingOrCommittingState, CommittingState};
+use self::event_time_stop::EventTimeStop;
+use self::recovery_throttle::RecoveryThrottler;
 
+mod alignment;
+mod checkpoint_events;
 mod checkpointer;
+mod event_time_stop;
+mod recovery_throttle;
 
 const CHECKPOINTS_TO_KEEP: u32 = 4;
 const COMPACT_EVERY: u32 = 2;
 const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
 
+lazy_static! {
+    static ref LAST_CHECKPOINT: IntGaugeVec = register_int_gauge_vec!(
+        "arroyo_controller_last_checkpoint_ms",
+        "time that each job last completed a checkpoint, in milliseconds since the epoch",
+        &["job_id"]
+    )
+    .unwrap();
+    static ref STATE_COMPACTION_FILES: IntCounterVec = register_int_counter_vec!(
+        "arroyo_controller_state_compaction_files",
+        "number of state files read and written by state compaction",
+        &["job_id", "direction"]
+    )
+    .unwrap();
+    static ref STATE_COMPACTION_BYTES: IntCounterVec = register_int_counter_vec!(
+        "arroyo_controller_state_compaction_bytes",
+        "bytes of state files read and written by state compaction",
+        &["job_id", "direction"]
+    )
+    .unwrap();
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkerState {
     Running,
@@ -79,6 +121,13 @@ pub struct RunningJobModel {
     workers: HashMap<WorkerId, WorkerStatus>,
     tasks: HashMap<(String, u32), TaskStatus>,
     operator_parallelism: HashMap<String, usize>,
+    aligner: WatermarkAligner,
+    event_time_stop: Option<EventTimeStop>,
+    recovery_throttle: Option<RecoveryThrottler>,
+    // the pause state last sent for each (source operator id, subtask)
+    source_pauses: HashMap<(String, u64), bool>,
+    // set when state compaction is requested through the API, until it starts
+    state_compaction_requested: bool,
 }
 
 impl std::fmt::Debug for RunningJobModel {
@@ -95,7 +144,11 @@ impl std::fmt::Debug for RunningJobModel {
 }
 
 impl RunningJobModel {
-    pub async fn handle_message(&mut self, msg: RunningMessage, pool: &Pool) -> anyhow::Result<()> {
+    pub async fn handle_message(
+        &mut self,
+        msg: RunningMessage,
+        store: &dyn JobStore,
+    ) -> anyhow::Result<()> {
         match msg {
             RunningMessage::TaskCheckpointEvent(c) => {
                 if let Some(checkpoint_state) = &mut self.checkpoint_state {
@@ -110,7 +163,7 @@ impl RunningJobModel {
                         match checkpoint_state {
                             CheckpointingOrCommittingState::Checkpointing(checkpoint_state) => {
                                 checkpoint_state.checkpoint_event(c)?;
-                                checkpoint_state.update_db(pool).await?
+                                checkpoint_state.update_db(store).await?
                             }
                             CheckpointingOrCommittingState::Committing(committing_state) => {
                                 if matches!(c.event_type(), TaskCheckpointEventType::FinishedCommit)
@@ -145,7 +198,7 @@ impl RunningJobModel {
                             bail!("Received checkpoint finished but not checkpointing");
                         };
                         checkpoint_state.checkpoint_finished(c).await;
-                        checkpoint_state.update_db(pool).await?;
+                        checkpoint_state.update_db(store).await?;
                     }
                 } else {
                     warn!(
@@ -191,6 +244,25 @@ impl RunningJobModel {
                     );
                 }
             }
+            RunningMessage::TaskWatermark {
+                operator_id,
+                subtask_index,
+                watermark,
+            } => {
+                if let Some(stop) = &mut self.event_time_stop {
+                    stop.update(&operator_id, subtask_index, watermark);
+                }
+
+                if let Some(throttle) = &mut self.recovery_throttle {
+                    throttle.update(&operator_id, subtask_index, watermark);
+                }
+
+                // with a stop time or recovery throttle, watermarks that aren't aligned are
+                // reported as well
+                if self.aligner.is_member(&operator_id) {
+                    self.aligner.update(operator_id, subtask_index, watermark);
+                }
+            }
             RunningMessage::WorkerHeartbeat { worker_id, time } => {
                 if let Some(worker) = self.workers.get_mut(&worker_id) {
                     worker.last_heartbeat = time;
@@ -213,6 +285,41 @@ impl RunningJobModel {
                     );
                 }
             }
+            RunningMessage::SetLogFilter {
+                directives,
+                worker_id,
+                operator_id,
+                applied,
+            } => {
+                let result = self
+                    .set_log_filter(&directives, worker_id, operator_id)
+                    .await;
+                // the requester may have gone away
+                let _ = applied.send(result);
+            }
+            RunningMessage::SampleOutput {
+                operator_id,
+                count,
+                samples,
+            } => {
+                let result = self.sample_output(&operator_id, count).await;
+                let _ = samples.send(result);
+            }
+            RunningMessage::ColumnStats { stats } => {
+                let _ = stats.send(self.column_stats().await);
+            }
+            RunningMessage::InjectProbe {
+                probe_id,
+                operator_id,
+                injected,
+            } => {
+                let sources = self.inject_probe(probe_id, operator_id).await;
+                let _ = injected.send(sources);
+            }
+            RunningMessage::CompactState { requested } => {
+                self.state_compaction_requested = true;
+                let _ = requested.send(());
+            }
         }
 
         if self.state == JobState::Running
@@ -238,7 +345,7 @@ impl RunningJobModel {
     pub async fn start_checkpoint(
         &mut self,
         organization_id: &str,
-        pool: &Pool,
+        store: &dyn JobStore,
         then_stop: bool,
     ) -> anyhow::Result<()> {
         self.epoch += 1;
@@ -260,6 +367,7 @@ impl RunningJobModel {
                     min_epoch: self.min_epoch,
                     then_stop,
                     is_commit: false,
+                    committing_operators: vec![],
                 }))
                 .await?;
         }
@@ -271,7 +379,7 @@ impl RunningJobModel {
                 self.epoch,
                 self.min_epoch,
                 &self.program,
-                pool,
+                store,
             )
             .await?,
         ));
@@ -279,7 +387,15 @@ impl RunningJobModel {
         Ok(())
     }
 
-    pub async fn finish_checkpoint_if_done(&mut self, pool: &Pool) -> anyhow::Result<()> {
+    fn checkpoint_completed(&mut self) {
+        self.last_checkpoint = Instant::now();
+        self.checkpoint_state = None;
+        LAST_CHECKPOINT
+            .with_label_values(&[&self.job_id])
+            .set(to_millis(SystemTime::now()) as i64);
+    }
+
+    pub async fn finish_checkpoint_if_done(&mut self, store: &dyn JobStore) -> anyhow::Result<()> {
         if self.checkpoint_state.as_ref().unwrap().done() {
             let state = self.checkpoint_state.take().unwrap();
             match state {
@@ -292,9 +408,8 @@ impl RunningJobModel {
                         .as_secs_f32();
                     // shortcut if committing is unnecessary
                     if committing_state.done() {
-                        checkpointing.finish(pool).await?;
-                        self.last_checkpoint = Instant::now();
-                        self.checkpoint_state = None;
+                        checkpointing.finish(store).await?;
+                        self.checkpoint_completed();
                         info!(
                             message = "Finished checkpointing",
                             job_id = self.job_id,
@@ -302,7 +417,8 @@ impl RunningJobModel {
                             duration
                         );
                     } else {
-                        checkpointing.pre_commit_finish(pool).await?;
+                        checkpointing.pre_commit_finish(store).await?;
+                        let committing_operators = committing_state.operators();
                         self.checkpoint_state =
                             Some(CheckpointingOrCommittingState::Committing(committing_state));
                         info!(
@@ -319,15 +435,15 @@ impl RunningJobModel {
                                     epoch: self.epoch,
                                     then_stop: false,
                                     is_commit: true,
+                                    committing_operators: committing_operators.clone(),
                                 }))
                                 .await?;
                         }
                     }
                 }
                 CheckpointingOrCommittingState::Committing(committing) => {
-                    committing.finish(pool).await?;
-                    self.last_checkpoint = Instant::now();
-                    self.checkpoint_state = None;
+                    committing.finish(store).await?;
+                    self.checkpoint_completed();
                     info!(
                         message = "Finished committing checkpointing",
                         job_id = self.job_id,
@@ -375,6 +491,27 @@ impl RunningJobModel {
         false
     }
 
+    // the first task that has failed, along with the reason it failed
+    pub fn failed_task(&self) -> Option<(&str, u32, &str)> {
+        self.tasks
+            .iter()
+            .find_map(|((operator_id, subtask), status)| match &status.state {
+                TaskState::Failed(reason) => {
+                    Some((operator_id.as_str(), *subtask, reason.as_str()))
+                }
+                _ => None,
+            })
+    }
+
+    // the epoch of the most recent checkpoint that the job could be restored from
+    pub fn last_completed_epoch(&self) -> u32 {
+        if self.checkpoint_state.is_some() {
+            self.epoch.saturating_sub(1)
+        } else {
+            self.epoch
+        }
+    }
+
     pub fn any_finished_sources(&self) -> bool {
         let source_tasks = self.program.sources();
 
@@ -388,21 +525,226 @@ impl RunningJobModel {
             .iter()
             .all(|(_, t)| t.state == TaskState::Finished)
     }
+
+    // pauses or resumes sources in watermark alignment groups and those held back by the
+    // recovery throttle; workers ignore sources that they aren't running, so changes are sent
+    // to all of them
+    pub async fn align_sources(&mut self) {
+        if self.aligner.is_empty() && self.recovery_throttle.is_none() {
+            return;
+        }
+
+        // a source is paused if either wants it to be; anything paused before is resumed unless
+        // one of them still does
+        let mut desired: HashMap<(String, u64), bool> = self
+            .source_pauses
+            .keys()
+            .map(|k| (k.clone(), false))
+            .collect();
+        let throttled = self
+            .recovery_throttle
+            .as_mut()
+            .map(|t| t.pauses())
+            .unwrap_or_default();
+        for p in self.aligner.pauses().into_iter().chain(throttled) {
+            *desired
+                .entry((p.operator_id, p.operator_subtask))
+                .or_default() |= p.paused;
+        }
+
+        let sources: Vec<_> = desired
+            .into_iter()
+            .filter(|(k, paused)| self.source_pauses.get(k).copied().unwrap_or(false) != *paused)
+            .map(|((operator_id, operator_subtask), paused)| SourcePause {
+                operator_id,
+                operator_subtask,
+                paused,
+            })
+            .collect();
+        if sources.is_empty() {
+            return;
+        }
+
+        for s in &sources {
+            self.source_pauses
+                .insert((s.operator_id.clone(), s.operator_subtask), s.paused);
+        }
+
+        for w in self.workers.values_mut() {
+            if let Err(e) = w
+                .connect
+                .align_sources(AlignSourcesReq {
+                    sources: sources.clone(),
+                })
+                .await
+            {
+                warn!(
+                    message = "Failed to send source alignment to worker",
+                    job_id = self.job_id,
+                    worker_id = w.id.0,
+                    error = format!("{:?}", e),
+                )
+            }
+        }
+    }
+
+    // sends new log filter directives to the job's workers (or only to `worker_id`), returning the
+    // workers that applied them; workers that can't be reached are skipped
+    async fn set_log_filter(
+        &mut self,
+        directives: &str,
+        worker_id: Option<WorkerId>,
+        operator_id: Option<String>,
+    ) -> Result<Vec<WorkerId>, Status> {
+        let mut applied = vec![];
+
+        for w in self
+            .workers
+            .values_mut()
+            .filter(|w| worker_id.map(|id| id == w.id).unwrap_or(true))
+        {
+            match w
+                .connect
+                .set_log_filter(SetLogFilterReq {
+                    directives: directives.to_string(),
+                    operator_id: operator_id.clone(),
+                })
+                .await
+            {
+                Ok(resp) => {
+                    if resp.into_inner().applied {
+                        applied.push(w.id);
+                    }
+                }
+                // the directives are invalid, so every worker would reject them
+                Err(e) if e.code() == Code::InvalidArgument => return Err(e),
+                Err(e) => {
+                    warn!(
+                        message = "Failed to set log filter on worker",
+                        job_id = self.job_id,
+                        worker_id = w.id.0,
+                        error = format!("{:?}", e),
+                    )
+                }
+            }
+        }
+
+        Ok(applied)
+    }
+
+    // collects the most recent records written by the operator's subtasks from each of the job's
+    // workers, newest first
+    async fn sample_output(
+        &mut self,
+        operator_id: &str,
+        count: u32,
+    ) -> Result<Vec<SinkOutputSample>, Status> {
+        let mut samples = vec![];
+
+        for w in self.workers.values_mut() {
+            match w
+                .connect
+                .sample_output(SampleOutputReq {
+                    operator_id: operator_id.to_string(),
+                    count,
+                })
+                .await
+            {
+                Ok(resp) => samples.extend(resp.into_inner().samples),
+                Err(e) => {
+                    warn!(
+                        message = "Failed to sample output from worker",
+                        job_id = self.job_id,
+                        worker_id = w.id.0,
+                        error = format!("{:?}", e),
+                    )
+                }
+            }
+        }
+
+        samples.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        samples.truncate(count as usize);
+        Ok(samples)
+    }
+
+    // collects the column stats of the job's sources and sinks from each of its workers
+    async fn column_stats(&mut self) -> Vec<OperatorColumnStats> {
+        let mut stats = vec![];
+
+        for w in self.workers.values_mut() {
+            match w.connect.get_column_stats(ColumnStatsReq {}).await {
+                Ok(resp) => merge_operators(&mut stats, resp.into_inner().operators),
+                Err(e) => {
+                    warn!(
+                        message = "Failed to get column stats from worker",
+                        job_id = self.job_id,
+                        worker_id = w.id.0,
+                        error = format!("{:?}", e),
+                    )
+                }
+            }
+        }
+
+        stats
+    }
+
+    // injects a watermark probe at the source subtasks on each of the job's workers, returning the
+    // number of subtasks it was injected at; workers that can't be reached are skipped
+    async fn inject_probe(&mut self, probe_id: u64, operator_id: Option<String>) -> u32 {
+        let mut sources = 0;
+
+        for w in self.workers.values_mut() {
+            match w
+                .connect
+                .inject_probe(InjectProbeReq {
+                    probe_id,
+                    operator_id: operator_id.clone(),
+                })
+                .await
+            {
+                Ok(resp) => sources += resp.into_inner().sources,
+                Err(e) => {
+                    warn!(
+                        message = "Failed to inject watermark probe on worker",
+                        job_id = self.job_id,
+                        worker_id = w.id.0,
+                        error = format!("{:?}", e),
+                    )
+                }
+            }
+        }
+
+        sources
+    }
 }
 
 pub struct JobController {
-    pool: Pool,
+    store: Arc<dyn JobStore>,
     config: JobConfig,
     model: RunningJobModel,
     compacting_task: Option<JoinHandle<anyhow::Result<u32>>>,
+    // rewrites the state files of a checkpoint into larger ones, returning the progress and the
+    // files that each operator's subtasks should swap in
+    state_compaction_task: Option<StateCompactionTask>,
+    // the progress of the running state compaction
+    state_compaction: Option<StateCompaction>,
+    // the number of checkpoints between scheduled state compactions
+    state_compaction_every: Option<u32>,
+    last_state_compaction_epoch: u32,
+    // whether the restore overrides the job was scheduled with have been checkpointed past
+    restore_overrides_cleared: bool,
 }
 
+type StateCompactionTask =
+    JoinHandle<anyhow::Result<(StateCompaction, Vec<CompactedOperatorState>)>>;
+
 impl std::fmt::Debug for JobController {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("JobController")
             .field("config", &self.config)
             .field("model", &self.model)
             .field("compacting", &self.compacting_task.is_some())
+            .field("compacting_state", &self.state_compaction_task.is_some())
             .finish()
     }
 }
@@ -414,7 +756,7 @@ pub enum ControllerProgress {
 
 impl JobController {
     pub fn new(
-        pool: Pool,
+        store: Arc<dyn JobStore>,
         config: JobConfig,
         program: Program,
         epoch: u32,
@@ -423,7 +765,7 @@ impl JobController {
         commit_state: Option<CommittingState>,
     ) -> Self {
         Self {
-            pool,
+            store,
             model: RunningJobModel {
                 job_id: config.id.clone(),
                 state: JobState::Running,
@@ -465,15 +807,46 @@ impl JobController {
                     .node_weights()
                     .map(|node| (node.operator_id.clone(), node.parallelism))
                     .collect(),
+                aligner: WatermarkAligner::new(&program),
+                event_time_stop: config.stop_at_event_time.and_then(|t| {
+                    let stop = EventTimeStop::new(&program, t);
+                    if stop.is_none() {
+                        warn!(
+                            message = "job has a stop time but no watermarks; ignoring it",
+                            job_id = config.id
+                        );
+                    }
+                    stop
+                }),
+                recovery_throttle: config.recovery_throttle.as_ref().and_then(|t| {
+                    let throttle = RecoveryThrottler::new(&program, t);
+                    if throttle.is_none() {
+                        warn!(
+                            message = "job has a recovery throttle but no watermarks; ignoring it",
+                            job_id = config.id
+                        );
+                    }
+                    throttle
+                }),
+                source_pauses: HashMap::new(),
+                state_compaction_requested: false,
                 program,
             },
+            restore_overrides_cleared: config.restore_overrides.is_none(),
             config,
             compacting_task: None,
+            state_compaction_task: None,
+            state_compaction: None,
+            state_compaction_every: std::env::var(STATE_COMPACTION_EVERY_ENV)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|every| *every > 0),
+            last_state_compaction_epoch: epoch,
         }
     }
 
     pub async fn handle_message(&mut self, msg: RunningMessage) -> anyhow::Result<()> {
-        self.model.handle_message(msg, &self.pool).await
+        self.model.handle_message(msg, &*self.store).await
     }
 
     pub async fn progress(&mut self) -> anyhow::Result<ControllerProgress> {
@@ -518,14 +891,40 @@ impl JobController {
         }
 
         if let Some(new_epoch) = self.model.compaction_needed() {
-            if self.compacting_task.is_none() && self.model.checkpoint_state.is_none() {
+            if self.compacting_task.is_none()
+                && self.state_compaction_task.is_none()
+                && self.model.checkpoint_state.is_none()
+            {
                 self.compacting_task = Some(self.start_compaction(new_epoch));
             }
         }
 
+        // check on state compaction; the compacted files are sent to the workers between
+        // checkpoints, so that all subtasks swap them in at the same one
+        if self.model.checkpoint_state.is_none()
+            && self
+                .state_compaction_task
+                .as_ref()
+                .map(|task| task.is_finished())
+                .unwrap_or(false)
+        {
+            let task = self.state_compaction_task.take().unwrap();
+            self.finish_state_compaction(task.await).await?;
+        }
+
+        if let Some(epoch) = self.state_compaction_needed() {
+            if self.state_compaction_task.is_none()
+                && self.compacting_task.is_none()
+                && self.model.checkpoint_state.is_none()
+            {
+                self.state_compaction_task = Some(self.start_state_compaction(epoch).await);
+            }
+        }
+
         // check on checkpointing
         if self.model.checkpoint_state.is_some() {
-            self.model.finish_checkpoint_if_done(&self.pool).await?;
+            self.model.finish_checkpoint_if_done(&*self.store).await?;
+            self.clear_restore_overrides_if_checkpointed().await;
         } else if self.model.last_checkpoint.elapsed() > self.config.checkpoint_interval
             && self.compacting_task.is_none()
         {
@@ -533,6 +932,19 @@ impl JobController {
             self.checkpoint(false).await?;
         }
 
+        self.model.align_sources().await;
+
+        if let Some(stop) = &mut self.model.event_time_stop {
+            if stop.should_drain() {
+                info!(
+                    message = "watermarks have passed the stop time; draining job",
+                    job_id = self.config.id,
+                    stop_at = to_micros(stop.stop_at())
+                );
+                self.stop_job(StopMode::Drain).await?;
+            }
+        }
+
         Ok(ControllerProgress::Continue)
     }
 
@@ -551,7 +963,7 @@ impl JobController {
     pub async fn checkpoint(&mut self, then_stop: bool) -> anyhow::Result<bool> {
         if self.model.checkpoint_state.is_none() {
             self.model
-                .start_checkpoint(&self.config.organization_id, &self.pool, then_stop)
+                .start_checkpoint(&self.config.organization_id, &*self.store, then_stop)
                 .await?;
             Ok(true)
         } else {
@@ -565,15 +977,63 @@ impl JobController {
 
     pub async fn checkpoint_finished(&mut self) -> anyhow::Result<bool> {
         if self.model.checkpoint_state.is_some() {
-            self.model.finish_checkpoint_if_done(&self.pool).await?;
+            self.model.finish_checkpoint_if_done(&*self.store).await?;
+            self.clear_restore_overrides_if_checkpointed().await;
         }
         Ok(self.model.checkpoint_state.is_none())
     }
 
+    /// The restore overrides that this run of the job was started with
+    pub fn restore_overrides(&self) -> Option<&RestoreOverrides> {
+        self.config.restore_overrides.as_ref()
+    }
+
+    /// The stop time that this run of the job was started with
+    pub fn stop_at_event_time(&self) -> Option<SystemTime> {
+        self.config.stop_at_event_time
+    }
+
+    // Once a checkpoint has completed, the overridden sources have stored their new offsets and
+    // future restores should use them. Overrides that were changed since this run started are
+    // left in place to be applied.
+    async fn clear_restore_overrides_if_checkpointed(&mut self) {
+        if self.restore_overrides_cleared || self.model.checkpoint_state.is_some() {
+            return;
+        }
+
+        let Some(overrides) = &self.config.restore_overrides else {
+            return;
+        };
+
+        match self
+            .store
+            .clear_restore_overrides(&self.config.id, &overrides.id)
+            .await
+        {
+            Ok(_) => {
+                info!(
+                    message = "cleared restore overrides",
+                    job_id = self.config.id,
+                    epoch = self.model.epoch
+                );
+                self.restore_overrides_cleared = true;
+            }
+            Err(e) => {
+                // we'll try again after the next checkpoint
+                warn!(
+                    message = "failed to clear restore overrides",
+                    job_id = self.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+    }
+
     pub async fn send_commit_messages(&mut self) -> anyhow::Result<()> {
-        let Some(CheckpointingOrCommittingState::Committing(_committing)) = &self.model.checkpoint_state else {
+        let Some(CheckpointingOrCommittingState::Committing(committing)) = &self.model.checkpoint_state else {
             bail!("should be committing")
         };
+        let committing_operators = committing.operators();
         for worker in self.model.workers.values_mut() {
             worker
                 .connect
@@ -583,6 +1043,7 @@ impl JobController {
                     epoch: self.model.epoch,
                     then_stop: false,
                     is_commit: true,
+                    committing_operators: committing_operators.clone(),
                 }))
                 .await?;
         }
@@ -601,7 +1062,7 @@ impl JobController {
                 .ok_or_else(|| anyhow::anyhow!("channel closed while receiving"))?
             {
                 JobMessage::RunningMessage(msg) => {
-                    self.model.handle_message(msg, &self.pool).await?;
+                    self.model.handle_message(msg, &*self.store).await?;
                 }
                 JobMessage::ConfigUpdate(c) => {
                     if c.stop_mode == SqlStopMode::immediate {
@@ -623,10 +1084,18 @@ impl JobController {
         self.model.operator_parallelism.get(op).cloned()
     }
 
+    pub fn failed_task(&self) -> Option<(&str, u32, &str)> {
+        self.model.failed_task()
+    }
+
+    pub fn last_completed_epoch(&self) -> u32 {
+        self.model.last_completed_epoch()
+    }
+
     fn start_compaction(&self, new_min: u32) -> JoinHandle<anyhow::Result<u32>> {
         let min_epoch = self.model.min_epoch;
         let job_id = self.config.id.clone();
-        let pool = self.pool.clone();
+        let store = self.store.clone();
 
         info!(message = "Starting compaction", job_id, min_epoch, new_min);
         let start = Instant::now();
@@ -639,16 +1108,11 @@ impl JobController {
                     anyhow::anyhow!("Couldn't find checkpoint for job during compaction")
                 })?;
 
-            let c = pool.get().await?;
-            controller_queries::mark_compacting()
-                .bind(&c, &job_id, &(min_epoch as i32), &(new_min as i32))
-                .await?;
+            store.mark_compacting(&job_id, min_epoch, new_min).await?;
 
             StateBackend::compact_checkpoint(checkpoint, min_epoch, new_min).await?;
 
-            controller_queries::mark_checkpoints_compacted()
-                .bind(&c, &job_id, &(new_min as i32))
-                .await?;
+            store.mark_checkpoints_compacted(&job_id, new_min).await?;
 
             info!(
                 message = "Finished compaction",
@@ -661,4 +1125,191 @@ impl JobController {
             Ok(new_min)
         })
     }
+
+    // the checkpoint to compact the state of, if it's been requested or scheduled and there's
+    // one since the last state compaction
+    fn state_compaction_needed(&self) -> Option<u32> {
+        let epoch = self.model.last_completed_epoch();
+        if epoch == 0 || epoch <= self.last_state_compaction_epoch {
+            return None;
+        }
+
+        let scheduled = self
+            .state_compaction_every
+            .map(|every| epoch - self.last_state_compaction_epoch >= every)
+            .unwrap_or(false);
+
+        (self.model.state_compaction_requested || scheduled).then_some(epoch)
+    }
+
+    async fn start_state_compaction(&mut self, epoch: u32) -> StateCompactionTask {
+        self.model.state_compaction_requested = false;
+        self.last_state_compaction_epoch = epoch;
+
+        let job_id = self.config.id.clone();
+        let storage_config = self.config.state_storage.clone();
+        let store = self.store.clone();
+        let mut progress = StateCompaction {
+            state: StateCompactionState::Running,
+            epoch,
+            start_time_micros: to_micros(SystemTime::now()),
+            finish_time_micros: None,
+            operators: 0,
+            operators_compacted: 0,
+            files_read: 0,
+            files_written: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            error: None,
+        };
+        self.state_compaction = Some(progress.clone());
+        update_state_compaction(&*store, &job_id, &progress).await;
+
+        info!(message = "Starting state compaction", job_id, epoch);
+
+        tokio::spawn(async move {
+            let checkpoint = StateBackend::load_checkpoint_metadata(&job_id, epoch)
+                .await
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Couldn't find checkpoint {} for state compaction", epoch)
+                })?;
+            progress.operators = checkpoint.operator_ids.len() as u32;
+
+            let mut results = vec![];
+            for operator_id in &checkpoint.operator_ids {
+                let result = StateBackend::compact_operator_state(
+                    &job_id,
+                    operator_id,
+                    epoch,
+                    &storage_config,
+                )
+                .await?;
+
+                progress.operators_compacted += 1;
+                progress.files_read += result.replaced_files.len() as u64;
+                progress.files_written += result.files.len() as u64;
+                progress.bytes_read += result.bytes_read;
+                progress.bytes_written += result.bytes_written;
+                update_state_compaction(&*store, &job_id, &progress).await;
+
+                if !result.replaced_files.is_empty() {
+                    results.push(result);
+                }
+            }
+
+            Ok((progress, results))
+        })
+    }
+
+    async fn finish_state_compaction(
+        &mut self,
+        result: Result<anyhow::Result<(StateCompaction, Vec<CompactedOperatorState>)>, JoinError>,
+    ) -> anyhow::Result<()> {
+        let mut compaction = self
+            .state_compaction
+            .take()
+            .expect("state compaction should be running");
+
+        let results = match result {
+            Ok(Ok((progress, results))) => {
+                compaction = progress;
+                results
+            }
+            Ok(Err(e)) => {
+                error!(
+                    message = "state compaction failed",
+                    job_id = self.config.id,
+                    error = format!("{:?}", e)
+                );
+                compaction.error = Some(e.to_string());
+                vec![]
+            }
+            Err(e) => {
+                error!(
+                    message = "state compaction panicked",
+                    job_id = self.config.id,
+                    error = format!("{:?}", e)
+                );
+                compaction.error = Some("state compaction panicked".to_string());
+                vec![]
+            }
+        };
+
+        // no checkpoint is in progress, so the next one is the first that the workers can
+        // swap the compacted files in at
+        let apply_epoch = self.model.epoch + 1;
+        let mut send_error = None;
+        'send: for result in &results {
+            for worker in self.model.workers.values_mut() {
+                if let Err(e) = worker
+                    .connect
+                    .load_compacted_state(LoadCompactedStateReq {
+                        operator_id: result.operator_id.clone(),
+                        epoch: result.epoch,
+                        apply_epoch,
+                        replaced_files: result.replaced_files.clone(),
+                        files: result.files.clone(),
+                    })
+                    .await
+                {
+                    send_error = Some(e);
+                    break 'send;
+                }
+            }
+        }
+
+        compaction.state = if compaction.error.is_some() || send_error.is_some() {
+            StateCompactionState::Failed
+        } else {
+            StateCompactionState::Finished
+        };
+        if let Some(e) = &send_error {
+            compaction.error = Some(format!("failed to send compacted state to workers: {}", e));
+        }
+        compaction.finish_time_micros = Some(to_micros(SystemTime::now()));
+        update_state_compaction(&*self.store, &self.config.id, &compaction).await;
+
+        for (direction, files, bytes) in [
+            ("read", compaction.files_read, compaction.bytes_read),
+            (
+                "written",
+                compaction.files_written,
+                compaction.bytes_written,
+            ),
+        ] {
+            STATE_COMPACTION_FILES
+                .with_label_values(&[&self.config.id, direction])
+                .inc_by(files);
+            STATE_COMPACTION_BYTES
+                .with_label_values(&[&self.config.id, direction])
+                .inc_by(bytes);
+        }
+
+        // workers that were sent the files would swap them in while the others kept the files
+        // they replace, so the job is restarted from its last checkpoint instead
+        if let Some(e) = send_error {
+            bail!("failed to send compacted state to workers: {}", e);
+        }
+
+        info!(
+            message = "Finished state compaction",
+            job_id = self.config.id,
+            epoch = compaction.epoch,
+            apply_epoch,
+            files_read = compaction.files_read,
+            files_written = compaction.files_written
+        );
+
+        Ok(())
+    }
+}
+
+async fn update_state_compaction(store: &dyn JobStore, job_id: &str, compaction: &StateCompaction) {
+    if let Err(e) = store.update_state_compaction(job_id, compaction).await {
+        warn!(
+            message = "failed to update state compaction progress",
+            job_id,
+            error = format!("{:?}", e)
+        );
+    }
 }
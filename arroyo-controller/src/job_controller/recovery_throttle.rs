@@ -0,0 +1,149 @@
+//! Limits how quickly a job that starts behind catches up, so that replaying a backlog doesn't
+//! overwhelm the systems its sinks write to. Watermark generators report their watermarks, and an
+//! allowed watermark starts at the slowest of them and advances by the configured amount per
+//! second of wall-clock time; sources whose watermark is ahead of it are paused. The rate scales
+//! up as the lag shrinks so that the limit is lifted gradually, and once the job is within the
+//! caught-up lag of the current time the throttle is removed entirely.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+use arroyo_datastream::{Operator, Program};
+use arroyo_rpc::grpc::SourcePause;
+use arroyo_types::RecoveryThrottle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::alignment::{upstream_source, IDLE_TIMEOUT};
+
+pub struct RecoveryThrottler {
+    max_advance: Duration,
+    caught_up_lag: Duration,
+    // watermark operator id -> source operator id
+    sources: HashMap<String, String>,
+    // (watermark operator id, subtask) -> last reported watermark and when it was reported
+    watermarks: HashMap<(String, u32), (SystemTime, Instant)>,
+    paused: HashSet<(String, u32)>,
+    allowed: Option<SystemTime>,
+    initial_lag: Option<Duration>,
+    last_update: Instant,
+    lifted: bool,
+}
+
+impl RecoveryThrottler {
+    /// Returns None if none of the program's watermarks can be traced back to a source
+    pub fn new(program: &Program, config: &RecoveryThrottle) -> Option<Self> {
+        let mut sources = HashMap::new();
+
+        for idx in program.graph.node_indices() {
+            let node = &program.graph[idx];
+            if !matches!(node.operator, Operator::Watermark(_)) {
+                continue;
+            }
+
+            let Some(source) = upstream_source(program, idx) else {
+                warn!(
+                    message = "watermark is not forward-connected to a source; not throttling it",
+                    operator_id = node.operator_id
+                );
+                continue;
+            };
+
+            sources.insert(
+                node.operator_id.clone(),
+                program.graph[source].operator_id.clone(),
+            );
+        }
+
+        (!sources.is_empty()).then(|| Self {
+            max_advance: config.max_watermark_advance(),
+            caught_up_lag: config.caught_up_lag(),
+            sources,
+            watermarks: HashMap::new(),
+            paused: HashSet::new(),
+            allowed: None,
+            initial_lag: None,
+            last_update: Instant::now(),
+            lifted: false,
+        })
+    }
+
+    pub fn update(&mut self, operator_id: &str, subtask: u32, watermark: SystemTime) {
+        if self.lifted || !self.sources.contains_key(operator_id) {
+            return;
+        }
+
+        let entry = self
+            .watermarks
+            .entry((operator_id.to_string(), subtask))
+            .or_insert((watermark, Instant::now()));
+        entry.0 = entry.0.max(watermark);
+        entry.1 = Instant::now();
+    }
+
+    /// Advances the allowed watermark and returns whether each source subtask that has reported a
+    /// watermark should be paused; empty once the job has caught up
+    pub fn pauses(&mut self) -> Vec<SourcePause> {
+        if self.lifted {
+            return vec![];
+        }
+
+        // subtasks that we've paused stop reporting, but are still behind the throttle
+        let Some(min) = self
+            .watermarks
+            .iter()
+            .filter(|(k, (_, updated))| {
+                updated.elapsed() <= IDLE_TIMEOUT || self.paused.contains(*k)
+            })
+            .map(|(_, (w, _))| *w)
+            .min()
+        else {
+            return vec![];
+        };
+
+        let lag = SystemTime::now().duration_since(min).unwrap_or_default();
+        if lag <= self.caught_up_lag {
+            info!(
+                message = "job has caught up; lifting recovery throttle",
+                lag_secs = lag.as_secs()
+            );
+            self.lifted = true;
+            self.paused.clear();
+            return vec![];
+        }
+
+        let initial_lag = *self.initial_lag.get_or_insert(lag);
+        let elapsed = self.last_update.elapsed();
+        self.last_update = Instant::now();
+
+        // the further the job has caught up, the faster it's allowed to advance
+        let speedup = (initial_lag.as_secs_f64() / lag.as_secs_f64()).max(1.0);
+        let allowed = match self.allowed {
+            Some(allowed) => {
+                (allowed + self.max_advance.mul_f64(elapsed.as_secs_f64() * speedup)).max(min)
+            }
+            None => min,
+        };
+        self.allowed = Some(allowed);
+
+        self.watermarks
+            .iter()
+            .map(|((operator_id, subtask), (w, _))| {
+                let paused = *w > allowed;
+                if paused {
+                    self.paused.insert((operator_id.clone(), *subtask));
+                } else {
+                    self.paused.remove(&(operator_id.clone(), *subtask));
+                }
+
+                SourcePause {
+                    operator_id: self.sources[operator_id].clone(),
+                    operator_subtask: *subtask as u64,
+                    paused,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,322 @@
+//! Storage for what a job's state machine persists while it runs: the job's status, the events in
+//! its log and the summaries of its finished runs, and its checkpoints. The controller stores jobs
+//! in Postgres; the state machine tests run against an in-memory store instead (see
+//! [`crate::testing`]).
+
+use anyhow::bail;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_state::{BackingStore, StateBackend};
+use arroyo_types::StateCompaction;
+use deadpool_postgres::Pool;
+use time::OffsetDateTime;
+
+use crate::queries::controller_queries;
+use crate::types::public::{CheckpointState, LogLevel};
+use crate::JobStatus;
+
+/// The last checkpoint of a job that can be restored from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredCheckpoint {
+    pub id: i64,
+    pub epoch: u32,
+    pub min_epoch: u32,
+    // the checkpoint completed, but its commits may not have been
+    pub needs_commits: bool,
+}
+
+#[async_trait::async_trait]
+pub trait JobStore: Send + Sync {
+    /// The encoded program of a pipeline
+    async fn get_program(&self, pipeline_id: i64) -> anyhow::Result<Vec<u8>>;
+
+    async fn update_status(&self, status: &JobStatus) -> anyhow::Result<()>;
+
+    /// Adds an event to the job's log
+    async fn create_event(
+        &self,
+        job_id: &str,
+        level: LogLevel,
+        message: &str,
+        details: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Stores the summary of a finished run, ignoring runs that were already archived
+    async fn archive_run(
+        &self,
+        status: &JobStatus,
+        metrics: Option<serde_json::Value>,
+        finish_time: OffsetDateTime,
+    ) -> anyhow::Result<()>;
+
+    async fn last_successful_checkpoint(
+        &self,
+        job_id: &str,
+    ) -> anyhow::Result<Option<StoredCheckpoint>>;
+
+    /// Records a checkpoint that has started, returning its id
+    async fn create_checkpoint(
+        &self,
+        organization_id: &str,
+        job_id: &str,
+        epoch: u32,
+        min_epoch: u32,
+        start_time: OffsetDateTime,
+    ) -> anyhow::Result<i64>;
+
+    async fn update_checkpoint(
+        &self,
+        checkpoint_id: i64,
+        operators: serde_json::Value,
+        finish_time: Option<OffsetDateTime>,
+        state: CheckpointState,
+    ) -> anyhow::Result<()>;
+
+    /// Marks a checkpoint whose commits have finished as ready
+    async fn commit_checkpoint(
+        &self,
+        checkpoint_id: i64,
+        finish_time: OffsetDateTime,
+    ) -> anyhow::Result<()>;
+
+    /// Marks the checkpoints from `epoch` on as failed
+    async fn mark_failed(&self, job_id: &str, epoch: u32) -> anyhow::Result<()>;
+
+    async fn mark_compacting(&self, job_id: &str, min_epoch: u32, epoch: u32)
+        -> anyhow::Result<()>;
+
+    async fn mark_checkpoints_compacted(&self, job_id: &str, epoch: u32) -> anyhow::Result<()>;
+
+    /// Clears the job's restore overrides, if they're still the ones with `overrides_id`
+    async fn clear_restore_overrides(&self, job_id: &str, overrides_id: &str)
+        -> anyhow::Result<()>;
+
+    async fn update_state_compaction(
+        &self,
+        job_id: &str,
+        compaction: &StateCompaction,
+    ) -> anyhow::Result<()>;
+}
+
+#[async_trait::async_trait]
+impl JobStore for Pool {
+    async fn get_program(&self, pipeline_id: i64) -> anyhow::Result<Vec<u8>> {
+        let c = self.get().await?;
+        Ok(controller_queries::get_program()
+            .bind(&c, &pipeline_id)
+            .one()
+            .await?)
+    }
+
+    async fn update_status(&self, status: &JobStatus) -> anyhow::Result<()> {
+        let poison_pill = status
+            .poison_pill
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+        let scheduling_intent = status
+            .scheduling_intent
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+
+        let c = self.get().await?;
+        let res = controller_queries::update_job_status()
+            .bind(
+                &c,
+                &status.state,
+                &status.start_time,
+                &status.finish_time,
+                &status.tasks,
+                &status.failure_message,
+                &poison_pill,
+                &status.restarts,
+                &status.pipeline_path,
+                &status.wasm_path,
+                &scheduling_intent,
+                &status.run_id,
+                &status.id,
+            )
+            .await?;
+
+        if res == 0 {
+            bail!("Job status does not exist");
+        }
+
+        Ok(())
+    }
+
+    async fn create_event(
+        &self,
+        job_id: &str,
+        level: LogLevel,
+        message: &str,
+        details: &str,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::create_job_event()
+            .bind(
+                &c,
+                &generate_id(IdTypes::JobLogMessage),
+                &job_id,
+                &level,
+                &message,
+                &details,
+            )
+            .one()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn archive_run(
+        &self,
+        status: &JobStatus,
+        metrics: Option<serde_json::Value>,
+        finish_time: OffsetDateTime,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::archive_job_run()
+            .bind(
+                &c,
+                &generate_id(IdTypes::JobRun),
+                &status.run_id,
+                &status.state,
+                &status.start_time,
+                &finish_time,
+                &status.restarts,
+                &status.failure_message,
+                &metrics,
+                &status.id,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn last_successful_checkpoint(
+        &self,
+        job_id: &str,
+    ) -> anyhow::Result<Option<StoredCheckpoint>> {
+        let c = self.get().await?;
+        Ok(controller_queries::last_successful_checkpoint()
+            .bind(&c, &job_id)
+            .opt()
+            .await?
+            .map(|r| StoredCheckpoint {
+                id: r.id,
+                epoch: r.epoch as u32,
+                min_epoch: r.min_epoch as u32,
+                needs_commits: r.needs_commits,
+            }))
+    }
+
+    async fn create_checkpoint(
+        &self,
+        organization_id: &str,
+        job_id: &str,
+        epoch: u32,
+        min_epoch: u32,
+        start_time: OffsetDateTime,
+    ) -> anyhow::Result<i64> {
+        let c = self.get().await?;
+        Ok(controller_queries::create_checkpoint()
+            .bind(
+                &c,
+                &generate_id(IdTypes::Checkpoint),
+                &organization_id,
+                &job_id,
+                &StateBackend::name().to_string(),
+                &(epoch as i32),
+                &(min_epoch as i32),
+                &start_time,
+            )
+            .one()
+            .await?)
+    }
+
+    async fn update_checkpoint(
+        &self,
+        checkpoint_id: i64,
+        operators: serde_json::Value,
+        finish_time: Option<OffsetDateTime>,
+        state: CheckpointState,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::update_checkpoint()
+            .bind(&c, &operators, &finish_time, &state, &checkpoint_id)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn commit_checkpoint(
+        &self,
+        checkpoint_id: i64,
+        finish_time: OffsetDateTime,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::commit_checkpoint()
+            .bind(&c, &finish_time, &checkpoint_id)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, job_id: &str, epoch: u32) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::mark_failed()
+            .bind(&c, &job_id, &(epoch as i32))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_compacting(
+        &self,
+        job_id: &str,
+        min_epoch: u32,
+        epoch: u32,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::mark_compacting()
+            .bind(&c, &job_id, &(min_epoch as i32), &(epoch as i32))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_checkpoints_compacted(&self, job_id: &str, epoch: u32) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::mark_checkpoints_compacted()
+            .bind(&c, &job_id, &(epoch as i32))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_restore_overrides(
+        &self,
+        job_id: &str,
+        overrides_id: &str,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::clear_restore_overrides()
+            .bind(&c, &job_id, &overrides_id)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_state_compaction(
+        &self,
+        job_id: &str,
+        compaction: &StateCompaction,
+    ) -> anyhow::Result<()> {
+        let c = self.get().await?;
+        controller_queries::update_state_compaction()
+            .bind(&c, &serde_json::to_value(compaction).unwrap(), &job_id)
+            .await?;
+
+        Ok(())
+    }
+}
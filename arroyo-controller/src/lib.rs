@@ -4,56 +4,99 @@
 
 use anyhow::bail;
 use arroyo_rpc::grpc::controller_grpc_server::{ControllerGrpc, ControllerGrpcServer};
+use arroyo_rpc::grpc::{
+    AcquireConnectionPermitsReq, AcquireConnectionPermitsResp, JobColumnStatsReq,
+    JobColumnStatsResp, OperatorColumnStats, ReleaseConnectionPermitsReq,
+    ReleaseConnectionPermitsResp, SampleSinkOutputReq, SampleSinkOutputResp, SinkOutputSample,
+};
+use arroyo_rpc::grpc::{
+    CompactJobStateReq, CompactJobStateResp, ConnectorHealthReq, ConnectorHealthResp, DrainNodeReq,
+    DrainNodeResp, JobUpgradeResult, SetJobLogFilterReq, SetJobLogFilterResp, SinkDataReq,
+    SinkDataResp, TaskCheckpointEventReq, TaskCheckpointEventResp, UpgradeJobsReq, UpgradeJobsResp,
+    WorkerErrorReq, WorkerErrorRes,
+};
 use arroyo_rpc::grpc::{
     GrpcOutputSubscription, HeartbeatNodeReq, HeartbeatNodeResp, HeartbeatReq, HeartbeatResp,
-    OutputData, RegisterNodeReq, RegisterNodeResp, RegisterWorkerReq, RegisterWorkerResp,
-    TaskCheckpointCompletedReq, TaskCheckpointCompletedResp, TaskFailedReq, TaskFailedResp,
-    TaskFinishedReq, TaskFinishedResp, TaskStartedReq, TaskStartedResp, WorkerFinishedReq,
-    WorkerFinishedResp,
+    JobProgressReq, JobProgressResp, OutputData, RegisterNodeReq, RegisterNodeResp,
+    RegisterWorkerReq, RegisterWorkerResp, TaskCheckpointCompletedReq, TaskCheckpointCompletedResp,
+    TaskFailedReq, TaskFailedResp, TaskFinishedReq, TaskFinishedResp, TaskStartedReq,
+    TaskStartedResp, TaskWatermarkReq, TaskWatermarkResp, UpdatingOutputStateReq,
+    UpdatingOutputStateResp, WorkerFinishedReq, WorkerFinishedResp, WorkerIdleReq, WorkerIdleResp,
 };
 use arroyo_rpc::grpc::{
-    SinkDataReq, SinkDataResp, TaskCheckpointEventReq, TaskCheckpointEventResp, WorkerErrorReq,
-    WorkerErrorRes,
+    InjectWatermarkProbeReq, InjectWatermarkProbeResp, TaskProbeReq, TaskProbeResp,
+    WatermarkProbesReq, WatermarkProbesResp,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
-use arroyo_server_common::log_event;
-use arroyo_types::{from_micros, ports, DatabaseConfig, NodeId, WorkerId};
+use arroyo_server_common::{log_event, tls};
+use arroyo_state::{BackingStore, StateBackend};
+use arroyo_types::{
+    from_micros, ports, state_version_supported, worker_protocol_compatible, DatabaseConfig,
+    FailurePolicy, JobDependency, JobFork, NodeId, PoisonPill, QueueConfig, RecoveryThrottle,
+    RestoreOverrides, StateStorageConfig, WorkerId, WORKER_PROTOCOL_VERSION,
+};
 use deadpool_postgres::{ManagerConfig, Pool, RecyclingMethod};
 use lazy_static::lazy_static;
+use migrations::MigrationMode;
 use object_store::aws::AmazonS3Builder;
 use object_store::ObjectStore;
 use prometheus::{register_gauge, Gauge};
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde_json::json;
-use states::{Created, State, StateMachine};
+use states::{Created, SchedulingIntent, State, StateMachine};
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, SystemTime};
 use time::OffsetDateTime;
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
 use tokio_postgres::NoTls;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+mod artifacts;
 pub mod compiler;
+mod connection_pools;
+mod dependencies;
+mod history;
 mod job_controller;
+mod job_store;
+pub mod migrations;
+mod output_state;
 pub mod schedulers;
+mod slo;
 mod states;
+mod task_progress;
+#[cfg(any(test, feature = "test-harness"))]
+pub mod testing;
 
 include!(concat!(env!("OUT_DIR"), "/controller-sql.rs"));
 
+use crate::connection_pools::ConnectionPools;
+use crate::job_store::JobStore;
+use crate::output_state::UpdatingOutputs;
 use crate::schedulers::{nomad::NomadScheduler, NodeScheduler, ProcessScheduler, Scheduler};
+use crate::task_progress::JobProgress;
 use types::public::LogLevel;
 use types::public::StopMode;
 
 pub const CHECKPOINTS_TO_KEEP: u32 = 5;
 
+// how long to wait for an upgraded job to be running again before giving up on the upgrade
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// the most records that can be sampled from a sink in one request
+const MAX_OUTPUT_SAMPLES: u32 = 1000;
+
 lazy_static! {
     static ref ACTIVE_PIPELINES: Gauge = register_gauge!(
         "arroyo_controller_active_pipelines",
@@ -101,6 +144,23 @@ pub struct JobConfig {
     checkpoint_interval: Duration,
     ttl: Option<Duration>,
     parallelism_overrides: HashMap<String, usize>,
+    // per-job environment set through the API, already namespaced for the workers
+    env_vars: HashMap<String, String>,
+    // starting positions for sources that override their checkpointed offsets the next time the
+    // job is restored; cleared once a checkpoint has been taken after applying them
+    restore_overrides: Option<RestoreOverrides>,
+    failure_policy: FailurePolicy,
+    queue_config: QueueConfig,
+    // other jobs that must meet a condition before this job is first started
+    dependencies: Vec<JobDependency>,
+    // the checkpoint of another job that this job's state is copied from when it is first started
+    fork_from: Option<JobFork>,
+    // the event time that the job is drained at once all of its watermarks have passed it
+    stop_at_event_time: Option<SystemTime>,
+    // limits how quickly the job catches up when it starts behind
+    recovery_throttle: Option<RecoveryThrottle>,
+    // how the job's state files are compressed and encrypted
+    state_storage: StateStorageConfig,
 }
 
 #[derive(Clone, Debug)]
@@ -112,39 +172,113 @@ pub struct JobStatus {
     finish_time: Option<OffsetDateTime>,
     tasks: Option<i32>,
     failure_message: Option<String>,
+    // the repeated failure that caused the job to be parked or to start skipping records
+    poison_pill: Option<PoisonPill>,
     restarts: i32,
     pipeline_path: Option<String>,
     wasm_path: Option<String>,
+    // the workers requested for the current run while the job is scheduling, so that scheduling
+    // can be resumed if the controller restarts
+    scheduling_intent: Option<SchedulingIntent>,
 }
 
 impl JobStatus {
-    pub async fn update_db(&self, pool: &Pool) -> Result<(), String> {
-        let c = pool.get().await.map_err(|e| format!("{:?}", e))?;
-        let res = queries::controller_queries::update_job_status()
-            .bind(
-                &c,
-                &self.state,
-                &self.start_time,
-                &self.finish_time,
-                &self.tasks,
-                &self.failure_message,
-                &self.restarts,
-                &self.pipeline_path,
-                &self.wasm_path,
-                &self.run_id,
-                &self.id,
-            )
+    pub async fn update_db(&self, store: &dyn JobStore) -> Result<(), String> {
+        store
+            .update_status(self)
             .await
-            .map_err(|e| format!("{:?}", e))?;
-
-        if res == 0 {
-            Err("Job status does not exist".to_string())
-        } else {
-            Ok(())
-        }
+            .map_err(|e| format!("{:?}", e))
     }
 }
 
+// parses a json column of a job's row; a malformed value is logged and treated as unset, so that
+// the job can still be run (with the defaults) rather than being stuck
+fn parse_column<T: DeserializeOwned>(
+    job_id: &str,
+    column: &str,
+    value: serde_json::Value,
+) -> Option<T> {
+    serde_json::from_value(value)
+        .map_err(|e| {
+            warn!(
+                message = "ignoring malformed job configuration",
+                job_id,
+                column,
+                error = format!("{:?}", e)
+            );
+        })
+        .ok()
+}
+
+// builds the configuration and status that the state machine runs a job with from its database row
+fn job_from_row(p: queries::controller_queries::Job) -> (JobConfig, JobStatus) {
+    let job_id = p.id.clone();
+    let config = JobConfig {
+        id: p.id.clone(),
+        organization_id: p.org_id,
+        pipeline_name: p.pipeline_name,
+        pipeline_id: p.pipeline_id,
+        stop_mode: p.stop,
+        checkpoint_interval: Duration::from_micros(p.checkpoint_interval_micros as u64),
+        ttl: p.ttl_micros.map(|t| Duration::from_micros(t as u64)),
+        parallelism_overrides: p
+            .parallelism_overrides
+            .as_object()
+            .unwrap()
+            .into_iter()
+            .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as usize))
+            .collect(),
+        env_vars: serde_json::from_value(p.env_vars).unwrap_or_default(),
+        restore_overrides: p
+            .restore_overrides
+            .and_then(|o| parse_column(&job_id, "restore_overrides", o)),
+        failure_policy: p
+            .failure_policy
+            .and_then(|o| parse_column(&job_id, "failure_policy", o))
+            .unwrap_or_default(),
+        queue_config: p
+            .queue_config
+            .and_then(|c| parse_column(&job_id, "queue_config", c))
+            .unwrap_or_default(),
+        dependencies: p
+            .dependencies
+            .and_then(|d| parse_column(&job_id, "dependencies", d))
+            .unwrap_or_default(),
+        fork_from: p
+            .fork_from
+            .and_then(|f| parse_column(&job_id, "fork_from", f)),
+        stop_at_event_time: p.stop_at_event_time_micros.map(|t| from_micros(t as u64)),
+        recovery_throttle: p
+            .recovery_throttle
+            .and_then(|t| parse_column(&job_id, "recovery_throttle", t)),
+        state_storage: p
+            .state_storage
+            .and_then(|c| parse_column(&job_id, "state_storage", c))
+            .unwrap_or_default(),
+    };
+
+    let status = JobStatus {
+        id: p.id,
+        run_id: p.run_id.unwrap_or(0),
+        state: p.state.unwrap_or_else(|| Created {}.name().to_string()),
+        start_time: p.start_time,
+        finish_time: p.finish_time,
+        tasks: p.tasks,
+        failure_message: p.failure_message,
+        poison_pill: p
+            .poison_pill
+            .and_then(|pill| parse_column(&job_id, "poison_pill", pill)),
+        restarts: p.restarts,
+        pipeline_path: p.pipeline_path,
+        wasm_path: p.wasm_path,
+        scheduling_intent: p
+            .scheduling_intent
+            .and_then(|i| parse_column(&job_id, "scheduling_intent", i)),
+    };
+
+    (config, status)
+}
+
 #[derive(Debug)]
 pub enum RunningMessage {
     TaskCheckpointEvent(TaskCheckpointEventReq),
@@ -161,6 +295,11 @@ pub enum RunningMessage {
         subtask_index: u32,
         reason: String,
     },
+    TaskWatermark {
+        operator_id: String,
+        subtask_index: u32,
+        watermark: SystemTime,
+    },
     WorkerHeartbeat {
         worker_id: WorkerId,
         time: Instant,
@@ -168,6 +307,36 @@ pub enum RunningMessage {
     WorkerFinished {
         worker_id: WorkerId,
     },
+    // replaces the log filter directives of the job's workers; replies with the workers that
+    // applied them
+    SetLogFilter {
+        directives: String,
+        worker_id: Option<WorkerId>,
+        operator_id: Option<String>,
+        applied: oneshot::Sender<Result<Vec<WorkerId>, Status>>,
+    },
+    // replies with the most recent records written by a sink, newest first
+    SampleOutput {
+        operator_id: String,
+        count: u32,
+        samples: oneshot::Sender<Result<Vec<SinkOutputSample>, Status>>,
+    },
+    // replies with the column stats of the job's sources and sinks, merged across its workers
+    ColumnStats {
+        stats: oneshot::Sender<Vec<OperatorColumnStats>>,
+    },
+    // injects a watermark probe at the job's sources (or only those of `operator_id`); replies
+    // with the number of source subtasks it was injected at
+    InjectProbe {
+        probe_id: u64,
+        operator_id: Option<String>,
+        injected: oneshot::Sender<u32>,
+    },
+    // compacts the job's state files once it has a checkpoint that they haven't been compacted
+    // at; replies once the compaction is scheduled
+    CompactState {
+        requested: oneshot::Sender<()>,
+    },
 }
 
 #[derive(Debug)]
@@ -180,6 +349,7 @@ pub enum JobMessage {
         data_address: String,
         slots: usize,
         job_hash: String,
+        run_id: i64,
     },
     TaskStarted {
         worker_id: WorkerId,
@@ -187,12 +357,21 @@ pub enum JobMessage {
         operator_subtask: u64,
     },
     RunningMessage(RunningMessage),
+    // asks a running job to move its workers, by restarting from a checkpoint onto the nodes
+    // that can currently be scheduled; sent when a node that it's running on is drained
+    Reschedule,
+    // asks a running job to restart from a checkpoint with its pipeline recompiled by this
+    // version of arroyo
+    Upgrade,
 }
 
 #[derive(Clone)]
 pub struct ControllerServer {
     job_state: Arc<tokio::sync::Mutex<HashMap<String, StateMachine>>>,
     data_txs: Arc<tokio::sync::Mutex<HashMap<String, Vec<Sender<Result<OutputData, Status>>>>>>,
+    updating_outputs: Arc<tokio::sync::Mutex<UpdatingOutputs>>,
+    job_progress: Arc<tokio::sync::Mutex<JobProgress>>,
+    connection_pools: Arc<std::sync::Mutex<ConnectionPools>>,
     scheduler: Arc<dyn Scheduler>,
     db: Pool,
 }
@@ -207,6 +386,19 @@ impl ControllerGrpc for ControllerServer {
 
         let req = request.into_inner();
 
+        if !worker_protocol_compatible(req.protocol_version) {
+            warn!(
+                message = "rejecting worker with incompatible protocol version",
+                job_id = req.job_id,
+                worker_id = req.worker_id,
+                protocol_version = req.protocol_version
+            );
+            return Err(Status::failed_precondition(format!(
+                "worker protocol version {} is not compatible with controller version {}",
+                req.protocol_version, WORKER_PROTOCOL_VERSION
+            )));
+        }
+
         self.send_to_job_queue(
             &req.job_id,
             JobMessage::WorkerConnect {
@@ -216,6 +408,7 @@ impl ControllerGrpc for ControllerServer {
                 data_address: req.data_address,
                 slots: req.slots as usize,
                 job_hash: req.job_hash,
+                run_id: req.run_id,
             },
         )
         .await?;
@@ -229,6 +422,11 @@ impl ControllerGrpc for ControllerServer {
     ) -> Result<Response<HeartbeatResp>, Status> {
         let req = request.into_inner();
 
+        self.job_progress
+            .lock()
+            .await
+            .apply(&req.job_id, req.time, req.tasks);
+
         self.send_to_job_queue(
             &req.job_id,
             JobMessage::RunningMessage(RunningMessage::WorkerHeartbeat {
@@ -322,6 +520,14 @@ impl ControllerGrpc for ControllerServer {
     ) -> Result<Response<TaskFailedResp>, Status> {
         let req = request.into_inner();
 
+        self.job_progress.lock().await.task_failed(
+            &req.job_id,
+            &req.operator_id,
+            req.operator_subtask as u32,
+            req.time,
+            &req.error,
+        );
+
         self.send_to_job_queue(
             &req.job_id,
             JobMessage::RunningMessage(RunningMessage::TaskFailed {
@@ -336,6 +542,25 @@ impl ControllerGrpc for ControllerServer {
         Ok(Response::new(TaskFailedResp {}))
     }
 
+    async fn task_watermark(
+        &self,
+        request: Request<TaskWatermarkReq>,
+    ) -> Result<Response<TaskWatermarkResp>, Status> {
+        let req = request.into_inner();
+
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::RunningMessage(RunningMessage::TaskWatermark {
+                operator_id: req.operator_id,
+                subtask_index: req.operator_subtask as u32,
+                watermark: from_micros(req.watermark_micros),
+            }),
+        )
+        .await?;
+
+        Ok(Response::new(TaskWatermarkResp {}))
+    }
+
     async fn register_node(
         &self,
         request: Request<RegisterNodeReq>,
@@ -367,21 +592,87 @@ impl ControllerGrpc for ControllerServer {
         Ok(Response::new(WorkerFinishedResp {}))
     }
 
+    async fn worker_idle(
+        &self,
+        request: Request<WorkerIdleReq>,
+    ) -> Result<Response<WorkerIdleResp>, Status> {
+        self.scheduler.worker_idle(request.into_inner()).await;
+        Ok(Response::new(WorkerIdleResp {}))
+    }
+
+    async fn drain_node(
+        &self,
+        request: Request<DrainNodeReq>,
+    ) -> Result<Response<DrainNodeResp>, Status> {
+        let node_id = NodeId(request.into_inner().node_id);
+        let job_ids = self.scheduler.drain_node(node_id).await?;
+
+        for job_id in &job_ids {
+            // jobs that aren't running either don't have workers to move, or will be rescheduled
+            // away from the node by the state they're in
+            if let Err(e) = self.send_to_job_queue(job_id, JobMessage::Reschedule).await {
+                warn!(
+                    message = "failed to reschedule job off of draining node",
+                    job_id,
+                    node_id = node_id.0,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+
+        Ok(Response::new(DrainNodeResp { job_ids }))
+    }
+
+    async fn upgrade_jobs(
+        &self,
+        request: Request<UpgradeJobsReq>,
+    ) -> Result<Response<UpgradeJobsResp>, Status> {
+        let mut results = vec![];
+        let mut failed = false;
+
+        for job_id in request.into_inner().job_ids {
+            let result = if failed {
+                Err("not attempted because an earlier upgrade failed".to_string())
+            } else {
+                self.upgrade_job(&job_id).await
+            };
+
+            if let Err(e) = &result {
+                warn!(message = "failed to upgrade job", job_id, error = e);
+                failed = true;
+            }
+
+            results.push(JobUpgradeResult {
+                job_id,
+                upgraded: result.is_ok(),
+                error: result.err(),
+            });
+        }
+
+        Ok(Response::new(UpgradeJobsResp { results }))
+    }
+
     async fn send_sink_data(
         &self,
         request: Request<SinkDataReq>,
     ) -> Result<Response<SinkDataResp>, Status> {
         let req = request.into_inner();
+        let output = OutputData {
+            operator_id: req.operator_id,
+            timestamp: req.timestamp,
+            key: req.key,
+            value: req.value,
+            done: req.done,
+            tag: req.tag,
+        };
+
+        self.updating_outputs
+            .lock()
+            .await
+            .apply(&req.job_id, &output);
+
         let mut data_txs = self.data_txs.lock().await;
         if let Some(v) = data_txs.get_mut(&req.job_id) {
-            let output = OutputData {
-                operator_id: req.operator_id,
-                timestamp: req.timestamp,
-                key: req.key,
-                value: req.value,
-                done: req.done,
-            };
-
             let mut remove = HashSet::new();
             for (i, tx) in v.iter().enumerate() {
                 match tx.try_send(Ok(output.clone())) {
@@ -421,6 +712,41 @@ impl ControllerGrpc for ControllerServer {
         Ok(Response::new(ReceiverStream::new(rx)))
     }
 
+    async fn get_updating_output_state(
+        &self,
+        request: Request<UpdatingOutputStateReq>,
+    ) -> Result<Response<UpdatingOutputStateResp>, Status> {
+        let job_id = request.into_inner().job_id;
+
+        self.updating_outputs
+            .lock()
+            .await
+            .get(&job_id)
+            .map(Response::new)
+            .ok_or_else(|| {
+                Status::not_found(format!(
+                    "No updating output has been received for job {}",
+                    job_id
+                ))
+            })
+    }
+
+    async fn get_job_progress(
+        &self,
+        request: Request<JobProgressReq>,
+    ) -> Result<Response<JobProgressResp>, Status> {
+        let job_id = request.into_inner().job_id;
+
+        self.job_progress
+            .lock()
+            .await
+            .get(&job_id)
+            .map(Response::new)
+            .ok_or_else(|| {
+                Status::not_found(format!("No progress has been reported for job {}", job_id))
+            })
+    }
+
     async fn worker_error(
         &self,
         request: Request<WorkerErrorReq>,
@@ -446,6 +772,246 @@ impl ControllerGrpc for ControllerServer {
             Err(err) => Err(Status::from_error(Box::new(err))),
         }
     }
+
+    async fn connector_health(
+        &self,
+        request: Request<ConnectorHealthReq>,
+    ) -> Result<Response<ConnectorHealthResp>, Status> {
+        self.job_progress
+            .lock()
+            .await
+            .connector_health(request.into_inner());
+
+        Ok(Response::new(ConnectorHealthResp {}))
+    }
+
+    async fn acquire_connection_permits(
+        &self,
+        request: Request<AcquireConnectionPermitsReq>,
+    ) -> Result<Response<AcquireConnectionPermitsResp>, Status> {
+        let req = request.into_inner();
+        if req.permits > req.max_connections {
+            return Err(Status::invalid_argument(format!(
+                "requested {} permits from pool '{}', which only allows {}",
+                req.permits, req.pool, req.max_connections
+            )));
+        }
+
+        Ok(Response::new(
+            self.connection_pools.lock().unwrap().acquire(req),
+        ))
+    }
+
+    async fn release_connection_permits(
+        &self,
+        request: Request<ReleaseConnectionPermitsReq>,
+    ) -> Result<Response<ReleaseConnectionPermitsResp>, Status> {
+        self.connection_pools
+            .lock()
+            .unwrap()
+            .release(request.into_inner());
+
+        Ok(Response::new(ReleaseConnectionPermitsResp {}))
+    }
+
+    async fn set_job_log_filter(
+        &self,
+        request: Request<SetJobLogFilterReq>,
+    ) -> Result<Response<SetJobLogFilterResp>, Status> {
+        let req = request.into_inner();
+        let (tx, rx) = oneshot::channel();
+
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::RunningMessage(RunningMessage::SetLogFilter {
+                directives: req.directives,
+                worker_id: req.worker_id.map(WorkerId),
+                operator_id: req.operator_id,
+                applied: tx,
+            }),
+        )
+        .await?;
+
+        // the message is dropped if the job isn't running
+        let applied = rx.await.map_err(|_| {
+            Status::failed_precondition(format!("Job {} is not running", req.job_id))
+        })??;
+
+        info!(
+            message = "Changed log filter",
+            job_id = req.job_id,
+            workers = format!("{:?}", applied)
+        );
+
+        Ok(Response::new(SetJobLogFilterResp {
+            worker_ids: applied.into_iter().map(|w| w.0).collect(),
+        }))
+    }
+
+    async fn sample_sink_output(
+        &self,
+        request: Request<SampleSinkOutputReq>,
+    ) -> Result<Response<SampleSinkOutputResp>, Status> {
+        let req = request.into_inner();
+        if req.count == 0 || req.count > MAX_OUTPUT_SAMPLES {
+            return Err(Status::invalid_argument(format!(
+                "count must be between 1 and {}",
+                MAX_OUTPUT_SAMPLES
+            )));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::RunningMessage(RunningMessage::SampleOutput {
+                operator_id: req.operator_id,
+                count: req.count,
+                samples: tx,
+            }),
+        )
+        .await?;
+
+        let samples = rx.await.map_err(|_| {
+            Status::failed_precondition(format!("Job {} is not running", req.job_id))
+        })??;
+
+        Ok(Response::new(SampleSinkOutputResp { samples }))
+    }
+
+    async fn get_job_column_stats(
+        &self,
+        request: Request<JobColumnStatsReq>,
+    ) -> Result<Response<JobColumnStatsResp>, Status> {
+        let req = request.into_inner();
+
+        let (tx, rx) = oneshot::channel();
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::RunningMessage(RunningMessage::ColumnStats { stats: tx }),
+        )
+        .await?;
+
+        let operators = rx.await.map_err(|_| {
+            Status::failed_precondition(format!("Job {} is not running", req.job_id))
+        })?;
+
+        Ok(Response::new(JobColumnStatsResp { operators }))
+    }
+
+    async fn inject_watermark_probe(
+        &self,
+        request: Request<InjectWatermarkProbeReq>,
+    ) -> Result<Response<InjectWatermarkProbeResp>, Status> {
+        let req = request.into_inner();
+
+        // the probe is recorded before it's injected, as subtasks may report it before the
+        // workers have all replied
+        let probe_id = self
+            .job_progress
+            .lock()
+            .await
+            .start_probe(&req.job_id, req.label.clone());
+
+        let (tx, rx) = oneshot::channel();
+        let result = match self
+            .send_to_job_queue(
+                &req.job_id,
+                JobMessage::RunningMessage(RunningMessage::InjectProbe {
+                    probe_id,
+                    operator_id: req.operator_id.clone(),
+                    injected: tx,
+                }),
+            )
+            .await
+        {
+            // the message is dropped if the job isn't running
+            Ok(()) => rx.await.map_err(|_| {
+                Status::failed_precondition(format!("Job {} is not running", req.job_id))
+            }),
+            Err(e) => Err(e),
+        }
+        .and_then(|sources| {
+            if sources == 0 {
+                Err(Status::failed_precondition(match &req.operator_id {
+                    Some(operator_id) => format!(
+                        "Operator {} has no running source subtasks in job {}",
+                        operator_id, req.job_id
+                    ),
+                    None => format!("Job {} has no running source subtasks", req.job_id),
+                }))
+            } else {
+                Ok(sources)
+            }
+        });
+
+        match result {
+            Ok(sources) => {
+                info!(
+                    message = "Injected watermark probe",
+                    job_id = req.job_id,
+                    probe_id,
+                    label = req.label,
+                    sources
+                );
+
+                Ok(Response::new(InjectWatermarkProbeResp {
+                    probe_id,
+                    sources,
+                }))
+            }
+            Err(e) => {
+                self.job_progress
+                    .lock()
+                    .await
+                    .cancel_probe(&req.job_id, probe_id);
+                Err(e)
+            }
+        }
+    }
+
+    async fn task_probe(
+        &self,
+        request: Request<TaskProbeReq>,
+    ) -> Result<Response<TaskProbeResp>, Status> {
+        self.job_progress
+            .lock()
+            .await
+            .probe_observed(request.into_inner());
+
+        Ok(Response::new(TaskProbeResp {}))
+    }
+
+    async fn get_watermark_probes(
+        &self,
+        request: Request<WatermarkProbesReq>,
+    ) -> Result<Response<WatermarkProbesResp>, Status> {
+        let job_id = request.into_inner().job_id;
+
+        Ok(Response::new(WatermarkProbesResp {
+            probes: self.job_progress.lock().await.probes(&job_id),
+        }))
+    }
+
+    async fn compact_job_state(
+        &self,
+        request: Request<CompactJobStateReq>,
+    ) -> Result<Response<CompactJobStateResp>, Status> {
+        let job_id = request.into_inner().job_id;
+
+        let (tx, rx) = oneshot::channel();
+        self.send_to_job_queue(
+            &job_id,
+            JobMessage::RunningMessage(RunningMessage::CompactState { requested: tx }),
+        )
+        .await?;
+
+        // the message is dropped if the job isn't running
+        rx.await
+            .map_err(|_| Status::failed_precondition(format!("Job {} is not running", job_id)))?;
+
+        info!(message = "Requested state compaction", job_id);
+        Ok(Response::new(CompactJobStateResp {}))
+    }
 }
 
 impl ControllerServer {
@@ -500,6 +1066,19 @@ impl ControllerServer {
             );
         });
 
+        let migration_mode = MigrationMode::from_env().unwrap_or_else(|e| panic!("{}", e));
+        let migrations = migrations::migrate(&pool, migration_mode)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to migrate database: {:?}", e));
+
+        if migration_mode == MigrationMode::DryRun {
+            info!(
+                "Dry run complete; {} migrations would be applied",
+                migrations.len()
+            );
+            std::process::exit(0);
+        }
+
         match pool
             .get()
             .await
@@ -527,6 +1106,9 @@ impl ControllerServer {
         Self {
             scheduler,
             data_txs: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            updating_outputs: Arc::new(tokio::sync::Mutex::new(UpdatingOutputs::default())),
+            job_progress: Arc::new(tokio::sync::Mutex::new(JobProgress::default())),
+            connection_pools: Arc::new(std::sync::Mutex::new(ConnectionPools::default())),
             job_state: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             db: pool,
         }
@@ -553,9 +1135,83 @@ impl ControllerServer {
         }
     }
 
+    async fn job_status(&self, job_id: &str) -> Result<JobStatus, String> {
+        let c = self.db.get().await.map_err(|e| format!("{:?}", e))?;
+        queries::controller_queries::all_jobs()
+            .bind(&c)
+            .all()
+            .await
+            .map_err(|e| format!("{:?}", e))?
+            .into_iter()
+            .find(|p| p.id == job_id)
+            .map(|p| job_from_row(p).1)
+            .ok_or_else(|| format!("No job with id {}", job_id))
+    }
+
+    // restarts a running job from a checkpoint onto a pipeline built by this version of arroyo,
+    // and waits for it to be running again
+    async fn upgrade_job(&self, job_id: &str) -> Result<(), String> {
+        let status = self.job_status(job_id).await?;
+        if status.state != "Running" {
+            return Err(format!("job is {}, not Running", status.state));
+        }
+
+        let c = self.db.get().await.map_err(|e| format!("{:?}", e))?;
+        let checkpoint = queries::controller_queries::last_successful_checkpoint()
+            .bind(&c, &job_id)
+            .opt()
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+
+        if let Some(checkpoint) = checkpoint {
+            let epoch = checkpoint.epoch as u32;
+            let metadata = StateBackend::load_checkpoint_metadata(job_id, epoch)
+                .await
+                .ok_or_else(|| format!("metadata for epoch {} not found", epoch))?;
+            if !state_version_supported(metadata.state_version) {
+                return Err(format!(
+                    "state version {} of epoch {} can't be restored by this version",
+                    metadata.state_version, epoch
+                ));
+            }
+        }
+
+        info!(message = "upgrading job", job_id, run_id = status.run_id);
+        self.send_to_job_queue(job_id, JobMessage::Upgrade)
+            .await
+            .map_err(|e| e.message().to_string())?;
+
+        let start = Instant::now();
+        while start.elapsed() < UPGRADE_TIMEOUT {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            let current = self.job_status(job_id).await?;
+            match current.state.as_str() {
+                "Running" if current.run_id > status.run_id => {
+                    info!(message = "upgraded job", job_id, run_id = current.run_id);
+                    return Ok(());
+                }
+                "Failed" | "NeedsAttention" | "Stopped" => {
+                    return Err(format!(
+                        "job is {} after upgrade: {}",
+                        current.state,
+                        current.failure_message.unwrap_or_default()
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        Err(format!(
+            "job was not running again after {:?}",
+            UPGRADE_TIMEOUT
+        ))
+    }
+
     fn start_updater(&self) {
         let db = self.db.clone();
         let jobs = Arc::clone(&self.job_state);
+        let job_progress = Arc::clone(&self.job_progress);
         let scheduler = Arc::clone(&self.scheduler);
 
         tokio::spawn(async move {
@@ -566,47 +1222,70 @@ impl ControllerServer {
                     .all()
                     .await
                     .unwrap();
+
+                let states: HashMap<_, _> = res
+                    .iter()
+                    .map(|p| {
+                        let state = p.state.as_deref().unwrap_or(Created.name());
+                        (p.id.clone(), state.to_string())
+                    })
+                    .collect();
+
                 for p in res {
-                    let config = JobConfig {
-                        id: p.id.clone(),
-                        organization_id: p.org_id,
-                        pipeline_name: p.pipeline_name,
-                        pipeline_id: p.pipeline_id,
-                        stop_mode: p.stop,
-                        checkpoint_interval: Duration::from_micros(
-                            p.checkpoint_interval_micros as u64,
-                        ),
-                        ttl: p.ttl_micros.map(|t| Duration::from_micros(t as u64)),
-                        parallelism_overrides: p
-                            .parallelism_overrides
-                            .as_object()
-                            .unwrap()
-                            .into_iter()
-                            .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as usize))
-                            .collect(),
-                    };
+                    let waiting_for = p.waiting_for.clone();
+                    let (config, status) = job_from_row(p);
 
                     let mut jobs = jobs.lock().await;
 
-                    let status = JobStatus {
-                        id: p.id,
-                        run_id: p.run_id.unwrap_or(0),
-                        state: p.state.unwrap_or_else(|| Created {}.name().to_string()),
-                        start_time: p.start_time,
-                        finish_time: p.finish_time,
-                        tasks: p.tasks,
-                        failure_message: p.failure_message,
-                        restarts: p.restarts,
-                        pipeline_path: p.pipeline_path,
-                        wasm_path: p.wasm_path,
-                    };
-
                     if let Some(sm) = jobs.get_mut(&config.id) {
                         sm.update(config, status).await;
                     } else {
+                        // jobs that haven't been started yet wait for their dependencies (unless
+                        // they've been stopped in the meantime)
+                        if status.state == Created.name() && !config.dependencies.is_empty() {
+                            let waiting = if config.stop_mode == StopMode::none {
+                                dependencies::waiting_for(
+                                    &config.dependencies,
+                                    &states,
+                                    &*job_progress.lock().await,
+                                    SystemTime::now(),
+                                )
+                            } else {
+                                None
+                            };
+
+                            if waiting != waiting_for {
+                                if let Some(reason) = &waiting {
+                                    info!(message = "job is waiting", job_id = config.id, reason);
+                                }
+
+                                if let Err(e) = queries::controller_queries::update_waiting_for()
+                                    .bind(&client, &waiting, &config.id)
+                                    .await
+                                {
+                                    warn!(
+                                        message = "failed to update job waiting reason",
+                                        job_id = config.id,
+                                        error = format!("{:?}", e)
+                                    );
+                                }
+                            }
+
+                            if config.stop_mode != StopMode::none || waiting.is_some() {
+                                continue;
+                            }
+                        }
+
                         jobs.insert(
                             config.id.clone(),
-                            StateMachine::new(config, status, db.clone(), scheduler.clone()).await,
+                            StateMachine::new(
+                                config,
+                                status,
+                                Arc::new(db.clone()),
+                                scheduler.clone(),
+                                job_progress.clone(),
+                            )
+                            .await,
                         );
                     }
                 }
@@ -632,12 +1311,15 @@ impl ControllerServer {
         );
 
         self.start_updater();
+        artifacts::start_gc(self.db.clone())?;
+        history::start_pruner(self.db.clone())?;
+        slo::start_monitor(self.db.clone(), Arc::clone(&self.job_progress));
 
         arroyo_server_common::grpc_server()
             .accept_http1(true)
             .add_service(ControllerGrpcServer::new(self.clone()))
             .add_service(reflection)
-            .serve(addr)
+            .serve_with_incoming(tls::incoming(TcpListener::bind(addr).await?))
             .await?;
 
         shutdown_tx.send(0).unwrap();
@@ -0,0 +1,203 @@
+//! Applies the database migrations (which live with the API, in `arroyo-api/migrations`) when the
+//! controller starts, so that upgrading the control plane doesn't require migrating the database
+//! by hand. Migrations are tracked in the same history table that the refinery CLI uses, so
+//! databases that were previously migrated by hand are picked up where they left off.
+//!
+//! Upgrades are zero-downtime because every migration must be backwards compatible: they only add
+//! tables, nullable columns, and indexes, so controllers and APIs from the previous release keep
+//! working while and after the new one migrates. Columns and tables are only dropped once no
+//! supported release uses them.
+//!
+//! Indexes on tables that may already be large are built with `CREATE INDEX CONCURRENTLY`, so
+//! that building them doesn't block writes to the table for as long as it takes. As that can't run
+//! inside a transaction, these indexes aren't created by migrations, but by the controller after
+//! it has applied them (see [`CONCURRENT_INDEXES`]).
+//!
+//! There are no down migrations. To downgrade, deploy the previous release: it finds the database
+//! at a newer version than it knows about, logs a warning, and runs against the newer schema.
+//!
+//! The behavior is controlled by the `MIGRATIONS` environment variable:
+//! * `apply` (the default) applies pending migrations in a single transaction, then builds any
+//!   missing concurrent indexes
+//! * `dry-run` logs the pending migrations and exits without applying them
+//! * `skip` doesn't touch the database, for deployments that migrate it separately (which must also
+//!   create the concurrent indexes)
+
+use std::env;
+
+use anyhow::{anyhow, bail, Context};
+use arroyo_types::MIGRATIONS_ENV;
+use deadpool_postgres::Pool;
+use refinery::Migration;
+use tracing::{info, warn};
+
+mod embedded {
+    use refinery::embed_migrations;
+    embed_migrations!("../arroyo-api/migrations");
+}
+
+// key of the postgres advisory lock held while migrating, so that controllers starting at the same
+// time don't apply migrations concurrently
+const MIGRATION_LOCK_KEY: i64 = 0x6172_726f_796f;
+
+/// An index that's built without locking its table against writes
+pub struct ConcurrentIndex {
+    pub name: &'static str,
+    // the table and columns, as they follow ON in CREATE INDEX
+    pub on: &'static str,
+}
+
+/// Indexes on existing tables, which are built after the migrations that need them have been
+/// applied. Once added here, an index must stay here, so that databases migrated by earlier
+/// releases get it too.
+pub const CONCURRENT_INDEXES: &[ConcurrentIndex] = &[
+    // used to prune old log messages (added with V28__add_job_history)
+    ConcurrentIndex {
+        name: "job_log_messages_created_at_idx",
+        on: "job_log_messages (created_at)",
+    },
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MigrationMode {
+    Apply,
+    DryRun,
+    Skip,
+}
+
+impl MigrationMode {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env::var(MIGRATIONS_ENV).as_deref() {
+            Err(_) | Ok("apply") => Ok(MigrationMode::Apply),
+            Ok("dry-run") => Ok(MigrationMode::DryRun),
+            Ok("skip") => Ok(MigrationMode::Skip),
+            Ok(other) => bail!(
+                "invalid {} '{}'; expected apply, dry-run, or skip",
+                MIGRATIONS_ENV,
+                other
+            ),
+        }
+    }
+}
+
+/// Brings the database up to date according to `mode`, returning the migrations that were applied
+/// (or, in dry-run mode, that would have been)
+pub async fn migrate(pool: &Pool, mode: MigrationMode) -> anyhow::Result<Vec<Migration>> {
+    if mode == MigrationMode::Skip {
+        info!("skipping database migrations");
+        return Ok(vec![]);
+    }
+
+    let mut client = pool.get().await?;
+    let client: &mut tokio_postgres::Client = &mut client;
+
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await
+        .context("failed to acquire migration lock")?;
+
+    let result = match run_locked(client, mode).await {
+        Ok(applied) if mode == MigrationMode::Apply => {
+            create_concurrent_indexes(client).await.map(|_| applied)
+        }
+        result => result,
+    };
+
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY])
+        .await
+        .context("failed to release migration lock")?;
+
+    result
+}
+
+async fn run_locked(
+    client: &mut tokio_postgres::Client,
+    mode: MigrationMode,
+) -> anyhow::Result<Vec<Migration>> {
+    let mut runner = embedded::migrations::runner();
+    runner.set_grouped(true);
+
+    let latest_known = runner.get_migrations().iter().map(|m| m.version()).max();
+    let current = runner
+        .get_last_applied_migration_async(client)
+        .await
+        .map_err(|e| anyhow!("failed to read migration history: {}", e))?
+        .map(|m| m.version());
+
+    if let (Some(current), Some(latest_known)) = (current, latest_known) {
+        if current > latest_known {
+            warn!(
+                "database schema is at version {}, which is newer than this release's latest \
+                migration ({}); continuing, as migrations are backwards compatible",
+                current, latest_known
+            );
+            return Ok(vec![]);
+        }
+    }
+
+    let pending: Vec<Migration> = runner
+        .get_migrations()
+        .iter()
+        .filter(|m| current.map(|c| m.version() > c).unwrap_or(true))
+        .cloned()
+        .collect();
+
+    if pending.is_empty() {
+        info!("database schema is up to date at version {:?}", current);
+        return Ok(vec![]);
+    }
+
+    if mode == MigrationMode::DryRun {
+        for migration in &pending {
+            info!("would apply migration {}", migration);
+        }
+        return Ok(pending);
+    }
+
+    let report = runner
+        .run_async(client)
+        .await
+        .map_err(|e| anyhow!("failed to apply migrations: {}", e))?;
+
+    for migration in report.applied_migrations() {
+        info!("applied migration {}", migration);
+    }
+
+    Ok(report.applied_migrations().clone())
+}
+
+/// Builds the indexes in [`CONCURRENT_INDEXES`] that don't exist yet. A concurrent build that fails
+/// (for example, because the controller was stopped) leaves behind an invalid index, which is
+/// dropped and rebuilt.
+async fn create_concurrent_indexes(client: &mut tokio_postgres::Client) -> anyhow::Result<()> {
+    for index in CONCURRENT_INDEXES {
+        let invalid = client
+            .query_opt(
+                "SELECT 1 FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid \
+                WHERE c.relname = $1 AND NOT i.indisvalid",
+                &[&index.name],
+            )
+            .await
+            .with_context(|| format!("failed to check index {}", index.name))?
+            .is_some();
+
+        if invalid {
+            warn!("rebuilding invalid index {}", index.name);
+            client
+                .batch_execute(&format!("DROP INDEX CONCURRENTLY IF EXISTS {}", index.name))
+                .await
+                .with_context(|| format!("failed to drop invalid index {}", index.name))?;
+        }
+
+        client
+            .batch_execute(&format!(
+                "CREATE INDEX CONCURRENTLY IF NOT EXISTS {} ON {}",
+                index.name, index.on
+            ))
+            .await
+            .with_context(|| format!("failed to create index {}", index.name))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Materializes the output of updating queries that is sent to the web sink, so that the console
+//! can show the current contents of the result table and the most recent changes to it.
+//!
+//! Updating outputs are serialized as Debezium changes (`{"before": .., "after": .., "op": ..}`);
+//! each create adds the `after` row, each delete retracts the `before` row, and each update does
+//! both. Outputs that aren't Debezium changes are ignored.
+//!
+//! Rows are materialized separately for each tag, so that the outputs of the statements of a
+//! query with several sinks aren't mixed.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Instant;
+
+use arroyo_rpc::grpc::{MaterializedRow, OutputData, UpdatingOutputStateResp};
+use serde_json::Value;
+
+/// Maximum number of distinct rows materialized per job
+const MAX_ROWS: usize = 10_000;
+/// Number of changes retained per job
+const MAX_CHANGES: usize = 1_000;
+/// Number of jobs whose output is retained; the least recently updated job is dropped first
+const MAX_JOBS: usize = 32;
+
+#[derive(Default)]
+pub struct UpdatingOutputState {
+    // (tag, key, serialized row) -> count
+    rows: BTreeMap<(String, String, String), u64>,
+    changes: VecDeque<OutputData>,
+    truncated: bool,
+}
+
+impl UpdatingOutputState {
+    /// Applies a record from the web sink, returning false if it is not a Debezium change
+    pub fn apply(&mut self, output: &OutputData) -> bool {
+        let Ok(Value::Object(change)) = serde_json::from_str::<Value>(&output.value) else {
+            return false;
+        };
+
+        let row = |field: &str| {
+            change
+                .get(field)
+                .filter(|v| !v.is_null())
+                .map(|v| (output.tag.clone(), output.key.clone(), v.to_string()))
+        };
+
+        let (before, after) = match change.get("op").and_then(|op| op.as_str()) {
+            Some("c") => (None, row("after")),
+            Some("u") => (row("before"), row("after")),
+            Some("d") => (row("before"), None),
+            _ => return false,
+        };
+
+        if let Some(before) = before {
+            // retractions for rows we never saw (because they were dropped) are ignored
+            if let Some(count) = self.rows.get_mut(&before) {
+                *count -= 1;
+                if *count == 0 {
+                    self.rows.remove(&before);
+                }
+            }
+        }
+
+        if let Some(after) = after {
+            if self.rows.len() < MAX_ROWS || self.rows.contains_key(&after) {
+                *self.rows.entry(after).or_default() += 1;
+            } else {
+                self.truncated = true;
+            }
+        }
+
+        if self.changes.len() == MAX_CHANGES {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(output.clone());
+
+        true
+    }
+
+    pub fn to_resp(&self) -> UpdatingOutputStateResp {
+        UpdatingOutputStateResp {
+            rows: self
+                .rows
+                .iter()
+                .map(|((tag, key, value), count)| MaterializedRow {
+                    key: key.clone(),
+                    value: value.clone(),
+                    count: *count,
+                    tag: tag.clone(),
+                })
+                .collect(),
+            changes: self.changes.iter().cloned().collect(),
+            truncated: self.truncated,
+        }
+    }
+}
+
+/// The materialized outputs of recent jobs
+#[derive(Default)]
+pub struct UpdatingOutputs {
+    jobs: HashMap<String, (Instant, UpdatingOutputState)>,
+}
+
+impl UpdatingOutputs {
+    pub fn apply(&mut self, job_id: &str, output: &OutputData) {
+        if output.done {
+            return;
+        }
+
+        if let Some((updated, state)) = self.jobs.get_mut(job_id) {
+            if state.apply(output) {
+                *updated = Instant::now();
+            }
+            return;
+        }
+
+        // only start tracking jobs once we see that their output is updating
+        let mut state = UpdatingOutputState::default();
+        if !state.apply(output) {
+            return;
+        }
+
+        if self.jobs.len() == MAX_JOBS {
+            let oldest = self
+                .jobs
+                .iter()
+                .min_by_key(|(_, (updated, _))| *updated)
+                .map(|(job_id, _)| job_id.clone())
+                .unwrap();
+            self.jobs.remove(&oldest);
+        }
+
+        self.jobs
+            .insert(job_id.to_string(), (Instant::now(), state));
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<UpdatingOutputStateResp> {
+        self.jobs.get(job_id).map(|(_, state)| state.to_resp())
+    }
+}
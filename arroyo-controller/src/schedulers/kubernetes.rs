@@ -1,12 +1,14 @@
 use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
 use anyhow::bail;
-use arroyo_rpc::grpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq};
+use arroyo_rpc::grpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq, WorkerIdleReq};
 use arroyo_types::{
-    string_config, u32_config, WorkerId, ADMIN_PORT_ENV, CONTROLLER_ADDR_ENV, GRPC_PORT_ENV,
-    JOB_ID_ENV, K8S_NAMESPACE_ENV, K8S_WORKER_ANNOTATIONS_ENV, K8S_WORKER_IMAGE_ENV,
-    K8S_WORKER_IMAGE_PULL_POLICY_ENV, K8S_WORKER_LABELS_ENV, K8S_WORKER_NAME_ENV,
-    K8S_WORKER_RESOURCES_ENV, K8S_WORKER_SERVICE_ACCOUNT_NAME_ENV, K8S_WORKER_SLOTS_ENV,
-    K8S_WORKER_VOLUMES_ENV, K8S_WORKER_VOLUME_MOUNTS_ENV, NODE_ID_ENV, RUN_ID_ENV, TASK_SLOTS_ENV,
+    string_config, u32_config, WorkerId, ADMIN_PORT_ENV, CLUSTER_TLS_CA_PATH_ENV,
+    CLUSTER_TLS_CERT_PATH_ENV, CLUSTER_TLS_KEY_PATH_ENV, CLUSTER_TLS_SERVER_NAME_ENV,
+    CONTROLLER_ADDR_ENV, GRPC_PORT_ENV, JOB_ID_ENV, K8S_NAMESPACE_ENV, K8S_WORKER_ANNOTATIONS_ENV,
+    K8S_WORKER_IMAGE_ENV, K8S_WORKER_IMAGE_PULL_POLICY_ENV, K8S_WORKER_LABELS_ENV,
+    K8S_WORKER_NAME_ENV, K8S_WORKER_RESOURCES_ENV, K8S_WORKER_SERVICE_ACCOUNT_NAME_ENV,
+    K8S_WORKER_SLOTS_ENV, K8S_WORKER_VOLUMES_ENV, K8S_WORKER_VOLUME_MOUNTS_ENV, NODE_ID_ENV,
+    PIPELINE_SECRETS_DIR_ENV, RUN_ID_ENV, TASK_SLOTS_ENV,
 };
 use async_trait::async_trait;
 use k8s_openapi::api::apps::v1::ReplicaSet;
@@ -154,6 +156,23 @@ impl Scheduler for KubernetesScheduler {
             }));
         }
 
+        // workers use the same certificate and pipeline secret paths as the controller; the
+        // secrets holding them need to be mounted into the worker pods via the worker volume config
+        for var in [
+            CLUSTER_TLS_CERT_PATH_ENV,
+            CLUSTER_TLS_KEY_PATH_ENV,
+            CLUSTER_TLS_CA_PATH_ENV,
+            CLUSTER_TLS_SERVER_NAME_ENV,
+            PIPELINE_SECRETS_DIR_ENV,
+        ] {
+            if let Ok(value) = std::env::var(var) {
+                env.as_array_mut().unwrap().push(json!({
+                    "name": var,
+                    "value": value,
+                }));
+            }
+        }
+
         for (key, value) in req.env_vars.into_iter() {
             env.as_array_mut().unwrap().push(json!({
                 "name": key,
@@ -232,6 +251,10 @@ impl Scheduler for KubernetesScheduler {
         // n/a
     }
 
+    async fn worker_idle(&self, _req: WorkerIdleReq) {
+        // n/a
+    }
+
     async fn stop_workers(
         &self,
         job_id: &str,
@@ -1,11 +1,14 @@
 use anyhow::bail;
 use arroyo_rpc::grpc::node_grpc_client::NodeGrpcClient;
+use arroyo_rpc::grpc::worker_grpc_client::WorkerGrpcClient;
 use arroyo_rpc::grpc::{
-    HeartbeatNodeReq, RegisterNodeReq, StartWorkerData, StartWorkerHeader, StartWorkerReq,
-    StopWorkerReq, StopWorkerStatus, WorkerFinishedReq,
+    AssignWorkerReq, HeartbeatNodeReq, RegisterNodeReq, StartWorkerData, StartWorkerHeader,
+    StartWorkerReq, StopWorkerReq, StopWorkerStatus, WorkerFinishedReq, WorkerIdleReq,
 };
+use arroyo_server_common::tls;
 use arroyo_types::{
-    NodeId, WorkerId, JOB_ID_ENV, NODE_ID_ENV, RUN_ID_ENV, TASK_SLOTS_ENV, WORKER_ID_ENV,
+    NodeId, WorkerId, JOB_ID_ENV, NODE_ID_ENV, PROCESS_SLOTS_ENV, PROCESS_SLOTS_PER_WORKER_ENV,
+    PROCESS_WORKER_REUSE_ENV, RUN_ID_ENV, TASK_SLOTS_ENV, WORKER_ID_ENV, WORKER_REUSE_ENV,
 };
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
@@ -18,6 +21,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::process::Command;
 use tokio::sync::{oneshot, Mutex};
+use tonic::transport::Channel;
 use tonic::{Request, Status};
 use tracing::{info, warn};
 
@@ -45,6 +49,10 @@ lazy_static! {
 
 const NODE_PART_SIZE: usize = 2 * 1024 * 1024;
 
+// where the process scheduler caches the binaries of the jobs it runs, in a directory per job and
+// pipeline hash
+pub(crate) const PROCESS_BINARIES_PATH: &str = "/tmp/arroyo-process";
+
 #[async_trait::async_trait]
 pub trait Scheduler: Send + Sync {
     async fn start_workers(
@@ -55,6 +63,16 @@ pub trait Scheduler: Send + Sync {
     async fn register_node(&self, req: RegisterNodeReq);
     async fn heartbeat_node(&self, req: HeartbeatNodeReq) -> Result<(), Status>;
     async fn worker_finished(&self, req: WorkerFinishedReq);
+    /// Called when a reusable worker's job finishes; schedulers that don't reuse workers ignore it
+    async fn worker_idle(&self, req: WorkerIdleReq);
+    /// Stops scheduling new workers on the node, returning the jobs that still have workers on it;
+    /// only supported by schedulers that manage nodes
+    async fn drain_node(&self, node_id: NodeId) -> Result<Vec<String>, Status> {
+        Err(Status::unimplemented(format!(
+            "can't drain node {}; the scheduler doesn't manage nodes",
+            node_id.0
+        )))
+    }
     async fn stop_workers(
         &self,
         job_id: &str,
@@ -68,9 +86,22 @@ pub trait Scheduler: Send + Sync {
     ) -> anyhow::Result<Vec<WorkerId>>;
 }
 
+#[derive(Debug)]
+enum ProcessWorkerState {
+    Running,
+    // the worker's job finished, and it's waiting to be assigned another run of the pipeline
+    Idle { rpc_address: String, since: Instant },
+}
+
 pub struct ProcessWorker {
     job_id: String,
     run_id: i64,
+    // the pipeline and environment the worker was started with, which must match for it to be reused
+    hash: String,
+    env_vars: HashMap<String, String>,
+    slots: usize,
+    dir: PathBuf,
+    state: ProcessWorkerState,
     shutdown_tx: oneshot::Sender<()>,
 }
 
@@ -78,18 +109,224 @@ pub struct ProcessWorker {
 pub struct ProcessScheduler {
     workers: Arc<Mutex<HashMap<WorkerId, ProcessWorker>>>,
     worker_counter: AtomicU64,
+    // the number of slots that running workers may use in total
+    slots: usize,
+    slots_per_worker: usize,
+    // whether workers are kept once their job finishes to be reused by later runs of the pipeline
+    reuse_workers: bool,
 }
 
 impl ProcessScheduler {
     pub fn new() -> Self {
+        let slots = env_usize(PROCESS_SLOTS_ENV).unwrap_or_else(default_process_slots);
+        let slots_per_worker =
+            env_usize(PROCESS_SLOTS_PER_WORKER_ENV).unwrap_or(DEFAULT_SLOTS_PER_WORKER);
+        let reuse_workers = std::env::var(PROCESS_WORKER_REUSE_ENV)
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
+        info!(
+            "process scheduler running up to {} slots with {} slots per worker{}",
+            slots,
+            slots_per_worker,
+            if reuse_workers {
+                ", reusing finished workers"
+            } else {
+                ""
+            }
+        );
+
         Self {
             workers: Arc::new(Mutex::new(HashMap::new())),
             worker_counter: AtomicU64::new(100),
+            slots,
+            slots_per_worker,
+            reuse_workers,
+        }
+    }
+
+    /// Tries to assign the run to an idle worker started for the same pipeline, returning whether
+    /// one took it
+    async fn reuse_idle_worker(
+        &self,
+        workers: &mut HashMap<WorkerId, ProcessWorker>,
+        req: &StartPipelineReq,
+        slots: usize,
+    ) -> bool {
+        let Some((worker_id, rpc_address)) = workers.iter().find_map(|(id, w)| match &w.state {
+            ProcessWorkerState::Idle { rpc_address, .. }
+                if w.hash == req.hash && w.env_vars == req.env_vars && w.slots == slots =>
+            {
+                Some((*id, rpc_address.clone()))
+            }
+            _ => None,
+        }) else {
+            return false;
+        };
+
+        match assign_worker(rpc_address, &req.job_id, req.run_id).await {
+            Ok(()) => {
+                info!(
+                    message = "Reusing idle worker",
+                    worker_id = worker_id.0,
+                    job_id = req.job_id,
+                    run_id = req.run_id
+                );
+                let worker = workers.get_mut(&worker_id).unwrap();
+                worker.job_id = req.job_id.clone();
+                worker.run_id = req.run_id;
+                worker.state = ProcessWorkerState::Running;
+                true
+            }
+            Err(e) => {
+                warn!(
+                    "failed to assign idle worker {} to job {}, stopping it: {:?}",
+                    worker_id.0, req.job_id, e
+                );
+                if let Some(worker) = workers.remove(&worker_id) {
+                    let _ = worker.shutdown_tx.send(());
+                }
+                false
+            }
         }
     }
+
+    fn spawn_worker(
+        &self,
+        workers: &mut HashMap<WorkerId, ProcessWorker>,
+        req: &StartPipelineReq,
+        path: PathBuf,
+        slots: usize,
+    ) {
+        let worker_id = self.worker_counter.fetch_add(1, Ordering::SeqCst);
+
+        let (tx, rx) = oneshot::channel();
+
+        workers.insert(
+            WorkerId(worker_id),
+            ProcessWorker {
+                job_id: req.job_id.clone(),
+                run_id: req.run_id,
+                hash: req.hash.clone(),
+                env_vars: req.env_vars.clone(),
+                slots,
+                dir: path.clone(),
+                state: ProcessWorkerState::Running,
+                shutdown_tx: tx,
+            },
+        );
+
+        let job_id = req.job_id.clone();
+        let run_id = req.run_id;
+        let reuse_workers = self.reuse_workers;
+        println!("Starting in path {:?}", path);
+        let workers = self.workers.clone();
+        let env_map = req.env_vars.clone();
+        tokio::spawn(async move {
+            let mut command = Command::new("./pipeline");
+            for (env, value) in env_map {
+                command.env(env, value);
+            }
+            if reuse_workers {
+                command.env(WORKER_REUSE_ENV, "true");
+            }
+            let mut child = command
+                .current_dir(&path)
+                .env("RUST_LOG", "info")
+                .env(TASK_SLOTS_ENV, format!("{}", slots))
+                .env(WORKER_ID_ENV, format!("{}", worker_id)) // start at 100 to make same length
+                .env(JOB_ID_ENV, &job_id)
+                .env(NODE_ID_ENV, format!("{}", 1))
+                .env(RUN_ID_ENV, format!("{}", run_id))
+                .kill_on_drop(true)
+                .spawn()
+                .unwrap();
+
+            tokio::select! {
+                status = child.wait() => {
+                    info!("Child ({:?}) exited with status {:?}", path, status);
+                }
+                _ = rx => {
+                    info!(message = "Killing child", worker_id = worker_id, job_id = job_id);
+                    child.kill().await.unwrap();
+                }
+            }
+
+            let mut state = workers.lock().await;
+            state.remove(&WorkerId(worker_id));
+
+            // clean up the job's binaries once no workers are using them
+            if !state.values().any(|w| w.dir == path) {
+                if let Err(e) = tokio::fs::remove_dir_all(&path).await {
+                    warn!("failed to clean up {:?}: {:?}", path, e);
+                }
+                if let Some(job_dir) = path.parent() {
+                    // only succeeds if no other versions of the job's pipeline are in use
+                    let _ = tokio::fs::remove_dir(job_dir).await;
+                }
+            }
+        });
+    }
+}
+
+// by default, the process scheduler allows this many slots per core...
+const SLOTS_PER_CORE: usize = 4;
+// ...as long as each slot has at least this much memory
+const MEMORY_PER_SLOT: u64 = 256 * 1024 * 1024;
+const DEFAULT_SLOTS_PER_WORKER: usize = 16;
+// how long an idle worker is kept for reuse before it's stopped
+const IDLE_WORKER_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+fn env_usize(var: &str) -> Option<usize> {
+    let value = std::env::var(var).ok()?;
+    match usize::from_str(&value) {
+        Ok(n) if n > 0 => Some(n),
+        _ => panic!("{} must be a positive integer, not '{}'", var, value),
+    }
 }
 
-const SLOTS_PER_NODE: usize = 16;
+/// The slot capacity of the host, based on its cores and memory; always enough for at least one
+/// full worker
+fn default_process_slots() -> usize {
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let mut slots = cores * SLOTS_PER_CORE;
+
+    if let Some(memory) = total_memory() {
+        slots = slots.min((memory / MEMORY_PER_SLOT) as usize);
+    }
+
+    slots.max(DEFAULT_SLOTS_PER_WORKER)
+}
+
+/// The host's total memory in bytes, where it can be determined
+fn total_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kb = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim();
+
+    u64::from_str(kb).ok().map(|kb| kb * 1024)
+}
+
+async fn assign_worker(rpc_address: String, job_id: &str, run_id: i64) -> anyhow::Result<()> {
+    let channel =
+        tls::connect_channel(Channel::from_shared(rpc_address)?.timeout(Duration::from_secs(5)))
+            .await?;
+
+    WorkerGrpcClient::new(channel)
+        .assign_worker(Request::new(AssignWorkerReq {
+            job_id: job_id.to_string(),
+            run_id,
+        }))
+        .await?;
+
+    Ok(())
+}
 
 pub struct StartPipelineReq {
     pub name: String,
@@ -115,21 +352,34 @@ impl Scheduler for ProcessScheduler {
         &self,
         start_pipeline_req: StartPipelineReq,
     ) -> Result<(), SchedulerError> {
-        let workers = (start_pipeline_req.slots as f32 / SLOTS_PER_NODE as f32).ceil() as usize;
-
-        let mut slots_scheduled = 0;
-
-        let base_path = PathBuf::from_str(&format!(
-            "/tmp/arroyo-process/{}",
-            start_pipeline_req.job_id
-        ))
-        .unwrap();
-        tokio::fs::create_dir_all(&base_path).await.unwrap();
-
         let (pipeline, wasm) = get_binaries(&start_pipeline_req)
             .await
             .map_err(|_| SchedulerError::CompilationNeeded)?;
 
+        // held until the workers are started, so that concurrently scheduled jobs don't race for
+        // slots or directories
+        let mut workers = self.workers.lock().await;
+
+        let slots_in_use: usize = workers
+            .values()
+            .filter(|w| matches!(w.state, ProcessWorkerState::Running))
+            .map(|w| w.slots)
+            .sum();
+
+        if slots_in_use + start_pipeline_req.slots > self.slots {
+            return Err(SchedulerError::NotEnoughSlots {
+                slots_needed: slots_in_use + start_pipeline_req.slots - self.slots,
+            });
+        }
+
+        // binaries are kept per pipeline hash, so that a job whose pipeline changed doesn't run
+        // the binaries of its previous version
+        let base_path = PathBuf::from_str(PROCESS_BINARIES_PATH)
+            .unwrap()
+            .join(&start_pipeline_req.job_id)
+            .join(&start_pipeline_req.hash);
+        tokio::fs::create_dir_all(&base_path).await.unwrap();
+
         let pipeline_path = base_path.join("pipeline");
 
         if !pipeline_path.exists() {
@@ -145,62 +395,27 @@ impl Scheduler for ProcessScheduler {
                 .unwrap();
         }
 
-        for _ in 0..workers {
-            let path = base_path.clone();
-
-            let slots_here = (start_pipeline_req.slots - slots_scheduled).min(SLOTS_PER_NODE);
-
-            let worker_id = self.worker_counter.fetch_add(1, Ordering::SeqCst);
+        let mut slots_scheduled = 0;
 
-            let (tx, rx) = oneshot::channel();
+        while slots_scheduled < start_pipeline_req.slots {
+            let slots_here =
+                (start_pipeline_req.slots - slots_scheduled).min(self.slots_per_worker);
+            slots_scheduled += slots_here;
 
+            if self.reuse_workers
+                && self
+                    .reuse_idle_worker(&mut workers, &start_pipeline_req, slots_here)
+                    .await
             {
-                let mut workers = self.workers.lock().await;
-                workers.insert(
-                    WorkerId(worker_id),
-                    ProcessWorker {
-                        job_id: start_pipeline_req.job_id.clone(),
-                        run_id: start_pipeline_req.run_id,
-                        shutdown_tx: tx,
-                    },
-                );
+                continue;
             }
 
-            slots_scheduled += slots_here;
-            let job_id = start_pipeline_req.job_id.clone();
-            println!("Starting in path {:?}", path);
-            let workers = self.workers.clone();
-            let env_map = start_pipeline_req.env_vars.clone();
-            tokio::spawn(async move {
-                let mut command = Command::new("./pipeline");
-                for (env, value) in env_map {
-                    command.env(env, value);
-                }
-                let mut child = command
-                    .current_dir(&path)
-                    .env("RUST_LOG", "info")
-                    .env(TASK_SLOTS_ENV, format!("{}", slots_here))
-                    .env(WORKER_ID_ENV, format!("{}", worker_id)) // start at 100 to make same length
-                    .env(JOB_ID_ENV, &job_id)
-                    .env(NODE_ID_ENV, format!("{}", 1))
-                    .env(RUN_ID_ENV, format!("{}", start_pipeline_req.run_id))
-                    .kill_on_drop(true)
-                    .spawn()
-                    .unwrap();
-
-                tokio::select! {
-                    status = child.wait() => {
-                        info!("Child ({:?}) exited with status {:?}", path, status);
-                    }
-                    _ = rx => {
-                        info!(message = "Killing child", worker_id = worker_id, job_id = job_id);
-                        child.kill().await.unwrap();
-                    }
-                }
-
-                let mut state = workers.lock().await;
-                state.remove(&WorkerId(worker_id));
-            });
+            self.spawn_worker(
+                &mut workers,
+                &start_pipeline_req,
+                base_path.clone(),
+                slots_here,
+            );
         }
 
         Ok(())
@@ -212,6 +427,50 @@ impl Scheduler for ProcessScheduler {
     }
     async fn worker_finished(&self, _: WorkerFinishedReq) {}
 
+    async fn worker_idle(&self, req: WorkerIdleReq) {
+        let worker_id = WorkerId(req.worker_id);
+        let mut workers = self.workers.lock().await;
+        let Some(worker) = workers.get_mut(&worker_id) else {
+            warn!("Got worker idle message for unknown worker {}", worker_id.0);
+            return;
+        };
+
+        if !self.reuse_workers {
+            let worker = workers.remove(&worker_id).unwrap();
+            let _ = worker.shutdown_tx.send(());
+            return;
+        }
+
+        let since = Instant::now();
+        worker.state = ProcessWorkerState::Idle {
+            rpc_address: req.rpc_address,
+            since,
+        };
+        info!(
+            message = "Worker is idle",
+            worker_id = worker_id.0,
+            job_id = req.job_id
+        );
+
+        // stop the worker if it isn't reused in time
+        let workers = self.workers.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(IDLE_WORKER_TIMEOUT).await;
+
+            let mut workers = workers.lock().await;
+            let still_idle = matches!(
+                workers.get(&worker_id).map(|w| &w.state),
+                Some(ProcessWorkerState::Idle { since: idle_since, .. }) if *idle_since == since
+            );
+
+            if still_idle {
+                info!(message = "Stopping idle worker", worker_id = worker_id.0);
+                let worker = workers.remove(&worker_id).unwrap();
+                let _ = worker.shutdown_tx.send(());
+            }
+        });
+    }
+
     async fn workers_for_job(
         &self,
         job_id: &str,
@@ -223,7 +482,9 @@ impl Scheduler for ProcessScheduler {
             .await
             .iter()
             .filter(|(_, w)| {
-                w.job_id == job_id && (run_id.is_none() || w.run_id == run_id.unwrap())
+                matches!(w.state, ProcessWorkerState::Running)
+                    && w.job_id == job_id
+                    && (run_id.is_none() || w.run_id == run_id.unwrap())
             })
             .map(|(k, _)| *k)
             .collect())
@@ -258,6 +519,8 @@ struct NodeStatus {
     scheduled_slots: HashMap<WorkerId, usize>,
     addr: String,
     last_heartbeat: Instant,
+    // draining nodes aren't assigned new workers
+    draining: bool,
 }
 
 impl NodeStatus {
@@ -271,6 +534,15 @@ impl NodeStatus {
             scheduled_slots: HashMap::new(),
             addr,
             last_heartbeat: Instant::now(),
+            draining: false,
+        }
+    }
+
+    fn schedulable_slots(&self) -> usize {
+        if self.draining {
+            0
+        } else {
+            self.free_slots
         }
     }
 
@@ -386,7 +658,10 @@ impl NodeScheduler {
             worker_id = worker_id.0
         );
 
-        let Ok(mut client) = NodeGrpcClient::connect(format!("http://{}", node.addr)).await else {
+        let Ok(mut client) = tls::connect_addr(format!("http://{}", node.addr))
+            .await
+            .map(NodeGrpcClient::new)
+        else {
             warn!("Failed to connect to worker to stop; this likely means it is dead");
             return Ok(Some(worker_id));
         };
@@ -464,6 +739,40 @@ impl Scheduler for NodeScheduler {
         }
     }
 
+    async fn worker_idle(&self, _: WorkerIdleReq) {
+        // workers started by nodes aren't reused
+    }
+
+    async fn drain_node(&self, node_id: NodeId) -> Result<Vec<String>, Status> {
+        let mut state = self.state.lock().await;
+        let Some(node) = state.nodes.get_mut(&node_id) else {
+            return Err(Status::not_found(format!(
+                "node {} not in scheduler's collection of nodes",
+                node_id.0
+            )));
+        };
+
+        if !node.draining {
+            info!(
+                message = "draining node",
+                node_id = node_id.0,
+                node_addr = node.addr
+            );
+            node.draining = true;
+        }
+
+        let mut job_ids: Vec<_> = state
+            .workers
+            .values()
+            .filter(|w| w.node_id == node_id)
+            .map(|w| w.job_id.clone())
+            .collect();
+        job_ids.sort();
+        job_ids.dedup();
+
+        Ok(job_ids)
+    }
+
     async fn workers_for_job(
         &self,
         job_id: &str,
@@ -495,7 +804,11 @@ impl Scheduler for NodeScheduler {
 
         state.expire_nodes(Instant::now() - Duration::from_secs(30));
 
-        let free_slots = state.nodes.values().map(|n| n.free_slots).sum::<usize>();
+        let free_slots = state
+            .nodes
+            .values()
+            .map(|n| n.schedulable_slots())
+            .sum::<usize>();
         let slots = start_pipeline_req.slots;
         if slots > free_slots {
             return Err(SchedulerError::NotEnoughSlots {
@@ -512,9 +825,10 @@ impl Scheduler for NodeScheduler {
                     .nodes
                     .values()
                     .filter(|n| {
-                        n.free_slots > 0 && n.last_heartbeat.elapsed() < Duration::from_secs(30)
+                        n.schedulable_slots() > 0
+                            && n.last_heartbeat.elapsed() < Duration::from_secs(30)
                     })
-                    .max_by_key(|n| n.free_slots)
+                    .max_by_key(|n| n.schedulable_slots())
                     .cloned()
                 {
                     status
@@ -529,8 +843,9 @@ impl Scheduler for NodeScheduler {
                 slots_for_this_one, node.addr
             );
 
-            let mut client = NodeGrpcClient::connect(format!("http://{}", node.addr))
+            let mut client = tls::connect_addr(format!("http://{}", node.addr))
                 .await
+                .map(NodeGrpcClient::new)
                 // TODO: handle this issue more gracefully by moving trying other nodes
                 .map_err(|e| {
                     // release back slots already scheduled.
@@ -1,5 +1,5 @@
 use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
-use arroyo_rpc::grpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq};
+use arroyo_rpc::grpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq, WorkerIdleReq};
 use arroyo_types::{
     WorkerId, CONTROLLER_ADDR_ENV, JOB_ID_ENV, NODE_ID_ENV, NOMAD_DC_ENV, NOMAD_ENDPOINT_ENV,
     RUN_ID_ENV, TASK_SLOTS_ENV, WORKER_ID_ENV,
@@ -254,6 +254,10 @@ impl Scheduler for NomadScheduler {
         // ignore
     }
 
+    async fn worker_idle(&self, _req: WorkerIdleReq) {
+        // ignore
+    }
+
     async fn stop_workers(
         &self,
         job_id: &str,
@@ -0,0 +1,310 @@
+//! Checks running jobs against the expectations of their SLO (see [`JobSlo`]), using the progress
+//! that workers report with their heartbeats. A job that isn't meeting them is degraded: its
+//! violations are recorded in its status (and cleared once it recovers or stops running), and each
+//! time the set of expectations it violates changes, an event is added to the job's log and the
+//! SLO's webhook (if any) is sent the job's current violations.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use arroyo_rpc::grpc::TaskProgressSample;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_server_common::http::{HttpClient, HttpError};
+use arroyo_types::{to_micros, JobSlo, SloIndicator, SloViolation};
+use deadpool_postgres::Pool;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::queries::controller_queries;
+use crate::task_progress::JobProgress;
+use crate::types::public::LogLevel;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct JobSloState {
+    run_id: i64,
+    // when the run was first checked; operators count as active from then
+    started: SystemTime,
+    // operator id -> (records received and emitted by its subtasks, when that last increased)
+    activity: HashMap<String, (u64, SystemTime)>,
+    violations: Vec<SloViolation>,
+}
+
+impl JobSloState {
+    fn new(run_id: i64, now: SystemTime) -> Self {
+        Self {
+            run_id,
+            started: now,
+            activity: HashMap::new(),
+            violations: vec![],
+        }
+    }
+
+    fn evaluate(
+        &mut self,
+        slo: &JobSlo,
+        tasks: &[(String, TaskProgressSample)],
+        now: SystemTime,
+    ) -> Vec<SloViolation> {
+        let mut violations = vec![];
+        let mut check = |indicator, operator_id: Option<&String>, value, threshold| {
+            if value > threshold {
+                violations.push(SloViolation {
+                    indicator,
+                    operator_id: operator_id.cloned(),
+                    value_micros: value,
+                    threshold_micros: threshold,
+                });
+            }
+        };
+
+        if let Some(threshold) = slo.max_source_lag_micros {
+            if let Some(lag) = tasks.iter().filter_map(|(_, t)| t.source_lag_millis).max() {
+                check(SloIndicator::SourceLag, None, lag * 1000, threshold);
+            }
+        }
+
+        if let Some(threshold) = slo.max_watermark_lag_micros {
+            // the job's watermark is that of its furthest behind subtask
+            if let Some(watermark) = tasks.iter().filter_map(|(_, t)| t.watermark_micros).min() {
+                let lag = to_micros(now).saturating_sub(watermark);
+                check(SloIndicator::WatermarkLag, None, lag, threshold);
+            }
+        }
+
+        let mut records: HashMap<&str, u64> = HashMap::new();
+        for (operator_id, task) in tasks {
+            *records.entry(operator_id).or_default() += task.records_in + task.records_out;
+        }
+
+        let mut operators: Vec<_> = slo.max_idle_micros.iter().collect();
+        operators.sort();
+        for (operator_id, threshold) in operators {
+            let count = records.get(operator_id.as_str()).copied().unwrap_or(0);
+            let (last_count, last_active) = self
+                .activity
+                .entry(operator_id.clone())
+                .or_insert((count, self.started));
+
+            // counts reset when subtasks restart, which isn't activity
+            if count > *last_count {
+                *last_active = now;
+            }
+            *last_count = count;
+
+            let idle = now.duration_since(*last_active).unwrap_or_default();
+            check(
+                SloIndicator::Idle,
+                Some(operator_id),
+                idle.as_micros() as u64,
+                *threshold,
+            );
+        }
+
+        violations
+    }
+}
+
+fn describe(violation: &SloViolation) -> String {
+    let value = Duration::from_secs(violation.value_micros / 1_000_000);
+    let threshold = Duration::from_secs(violation.threshold_micros / 1_000_000);
+
+    match violation.indicator {
+        SloIndicator::SourceLag => {
+            format!("source lag of {:?} exceeds {:?}", value, threshold)
+        }
+        SloIndicator::WatermarkLag => {
+            format!("watermark lag of {:?} exceeds {:?}", value, threshold)
+        }
+        SloIndicator::Idle => format!(
+            "operator {} has not processed a record in {:?} (expected at least every {:?})",
+            violation.operator_id.as_deref().unwrap_or_default(),
+            value,
+            threshold
+        ),
+    }
+}
+
+// which expectations are violated, ignoring by how much
+fn violated(violations: &[SloViolation]) -> Vec<(SloIndicator, Option<&str>)> {
+    violations
+        .iter()
+        .map(|v| (v.indicator, v.operator_id.as_deref()))
+        .collect()
+}
+
+struct SloMonitor {
+    jobs: HashMap<String, JobSloState>,
+    http: HttpClient,
+}
+
+impl SloMonitor {
+    fn new() -> Self {
+        Self {
+            jobs: HashMap::new(),
+            http: HttpClient::new("slo_webhooks"),
+        }
+    }
+
+    async fn check_jobs(
+        &mut self,
+        pool: &Pool,
+        job_progress: &tokio::sync::Mutex<JobProgress>,
+    ) -> anyhow::Result<()> {
+        let client = pool.get().await?;
+        let jobs = controller_queries::running_job_slos()
+            .bind(&client)
+            .all()
+            .await?;
+
+        let now = SystemTime::now();
+        let mut checked = HashSet::new();
+
+        for job in jobs {
+            let slo: JobSlo = match serde_json::from_value(job.slo) {
+                Ok(slo) => slo,
+                Err(e) => {
+                    warn!(
+                        message = "Invalid SLO for job",
+                        job_id = job.id,
+                        error = format!("{:?}", e)
+                    );
+                    continue;
+                }
+            };
+
+            if slo.is_empty() {
+                continue;
+            }
+
+            checked.insert(job.id.clone());
+
+            let run_id = job.run_id.unwrap_or_default();
+            let state = self
+                .jobs
+                .entry(job.id.clone())
+                .or_insert_with(|| JobSloState::new(run_id, now));
+
+            if state.run_id != run_id {
+                // the job restarted; keep its violations so that a recovery is reported
+                let violations = std::mem::take(&mut state.violations);
+                *state = JobSloState::new(run_id, now);
+                state.violations = violations;
+            }
+
+            let tasks = job_progress.lock().await.latest(&job.id);
+            let violations = state.evaluate(&slo, &tasks, now);
+            if violations == state.violations {
+                continue;
+            }
+
+            controller_queries::update_slo_violations()
+                .bind(
+                    &client,
+                    &(!violations.is_empty()).then(|| serde_json::to_value(&violations).unwrap()),
+                    &job.id,
+                )
+                .await?;
+
+            if violated(&violations) != violated(&state.violations) {
+                let (log_level, message) = if violations.is_empty() {
+                    (LogLevel::info, "Job is meeting its SLO again".to_string())
+                } else {
+                    (LogLevel::warn, "Job is not meeting its SLO".to_string())
+                };
+
+                info!(
+                    message = message.as_str(),
+                    job_id = job.id,
+                    violations = format!("{:?}", violations)
+                );
+
+                let details: Vec<_> = violations.iter().map(describe).collect();
+                controller_queries::create_job_event()
+                    .bind(
+                        &client,
+                        &generate_id(IdTypes::JobLogMessage),
+                        &job.id,
+                        &log_level,
+                        &message,
+                        &details.join("\n"),
+                    )
+                    .one()
+                    .await?;
+
+                if let Some(url) = slo.webhook_url {
+                    send_webhook(
+                        &self.http,
+                        url,
+                        json!({
+                            "jobId": job.id,
+                            "pipelineName": job.pipeline_name,
+                            "degraded": !violations.is_empty(),
+                            "violations": &violations,
+                        }),
+                    );
+                }
+            }
+
+            state.violations = violations;
+        }
+
+        // jobs that stopped running (or no longer have an SLO) are no longer degraded
+        let stopped: Vec<_> = self
+            .jobs
+            .keys()
+            .filter(|job_id| !checked.contains(*job_id))
+            .cloned()
+            .collect();
+
+        for job_id in stopped {
+            let state = self.jobs.remove(&job_id).unwrap();
+            if !state.violations.is_empty() {
+                controller_queries::update_slo_violations()
+                    .bind(&client, &None::<serde_json::Value>, &job_id)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn send_webhook(http: &HttpClient, url: String, body: serde_json::Value) {
+    let http = http.clone();
+    let request = http.post(&url).timeout(WEBHOOK_TIMEOUT).json(&body);
+
+    tokio::spawn(async move {
+        let result = match http.send(request).await {
+            Ok(response) => response.error_for_status().map_err(HttpError::Request),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            warn!(
+                message = "Failed to send SLO webhook",
+                url,
+                error = format!("{}", e)
+            );
+        }
+    });
+}
+
+pub fn start_monitor(pool: Pool, job_progress: Arc<tokio::sync::Mutex<JobProgress>>) {
+    tokio::spawn(async move {
+        let mut monitor = SloMonitor::new();
+
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+
+            if let Err(e) = monitor.check_jobs(&pool, &job_progress).await {
+                warn!(
+                    message = "Failed to check job SLOs",
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+    });
+}
@@ -21,7 +21,7 @@ impl State for Compiling {
                 message = "Pipeline already compiled",
                 job_id = ctx.config.id,
             );
-            return Ok(Transition::next(*self, Scheduling {}));
+            return Ok(Transition::next(*self, Scheduling::default()));
         }
 
         info!(
@@ -46,7 +46,7 @@ impl State for Compiling {
                     Ok(res) => {
                         ctx.status.pipeline_path = Some(res.pipeline_path);
                         ctx.status.wasm_path = Some(res.wasm_path);
-                        return Ok(Transition::next(*self, Scheduling {}));
+                        return Ok(Transition::next(*self, Scheduling::default()));
                     }
                     Err(e) => return Err(e
                         .downcast::<StateError>()
@@ -1,39 +1,47 @@
+use std::collections::HashSet;
 use std::sync::RwLock;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::{fmt::Debug, sync::Arc};
 
 use arroyo_datastream::Program;
 use arroyo_rpc::grpc::api::PipelineProgram;
 
 use arroyo_server_common::log_event;
-use deadpool_postgres::Pool;
+use arroyo_types::PoisonPillAction;
 use serde_json::json;
 use thiserror::Error;
 use time::OffsetDateTime;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::Instant;
 
 use tracing::{error, info, warn};
 
 use anyhow::Result;
 
+use crate::history;
 use crate::job_controller::JobController;
-use crate::queries::controller_queries;
-use crate::types::public::StopMode;
+use crate::job_store::JobStore;
+use crate::task_progress::JobProgress;
+use crate::types::public::{LogLevel, StopMode};
 use crate::{schedulers::Scheduler, JobConfig, JobMessage, JobStatus};
 use prost::Message;
 
 use self::checkpoint_stopping::CheckpointStopping;
 use self::compiling::Compiling;
 use self::finishing::Finishing;
+use self::poison_pill::PoisonPillDetector;
 use self::recovering::Recovering;
 use self::rescaling::Rescaling;
 use self::running::Running;
 use self::scheduling::Scheduling;
 use self::stopping::Stopping;
 
+pub(crate) use self::scheduling::SchedulingIntent;
+
 mod checkpoint_stopping;
 mod compiling;
 mod finishing;
+mod poison_pill;
 mod recovering;
 mod rescaling;
 mod running;
@@ -111,6 +119,38 @@ impl State for Failed {
     }
 }
 
+#[derive(Debug)]
+pub struct NeedsAttention;
+
+// The job repeatedly failed on the same input and its failure policy is to park it; workers are
+// torn down and the job stays here until it is stopped and restarted
+#[async_trait::async_trait]
+impl State for NeedsAttention {
+    fn name(&self) -> &'static str {
+        "NeedsAttention"
+    }
+
+    async fn next(self: Box<Self>, ctx: &mut Context) -> Result<Transition, StateError> {
+        if let Err(e) = ctx
+            .scheduler
+            .stop_workers(&ctx.config.id, Some(ctx.status.run_id), true)
+            .await
+        {
+            warn!(
+                message = "Failed to clean up cluster",
+                error = format!("{:?}", e),
+                job_id = ctx.config.id
+            );
+        }
+
+        Ok(Transition::Stop)
+    }
+
+    fn is_terminal(&self) -> bool {
+        true
+    }
+}
+
 #[derive(Debug)]
 pub struct Finished;
 
@@ -160,9 +200,17 @@ impl State for Stopped {
 }
 
 // State transitions
-impl TransitionTo<Compiling> for Created {}
+impl TransitionTo<Compiling> for Created {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(clear_poison_pill)
+    }
+}
 
-impl TransitionTo<Compiling> for Stopped {}
+impl TransitionTo<Compiling> for Stopped {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(clear_poison_pill)
+    }
+}
 
 impl TransitionTo<Compiling> for Scheduling {}
 
@@ -201,6 +249,11 @@ impl TransitionTo<Recovering> for Running {
     }
 }
 impl TransitionTo<Rescaling> for Running {}
+impl TransitionTo<NeedsAttention> for Running {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(done_transition)
+    }
+}
 
 impl TransitionTo<Scheduling> for Rescaling {
     fn update_status(&self) -> TransitionFn {
@@ -211,6 +264,14 @@ impl TransitionTo<Scheduling> for Rescaling {
 }
 
 impl TransitionTo<Compiling> for Recovering {}
+impl TransitionTo<Compiling> for Rescaling {}
+
+// a fresh start of the job gives it a chance to process the records it was failing on again
+fn clear_poison_pill(ctx: &mut Context) {
+    ctx.status.poison_pill = None;
+    ctx.skip_operators.clear();
+    ctx.poison_pills.reset();
+}
 
 fn done_transition(ctx: &mut Context) {
     ctx.status.finish_time = Some(OffsetDateTime::now_utc());
@@ -265,6 +326,14 @@ macro_rules! stop_if_desired_running {
                     },
                 ));
             }
+            StopMode::drain => {
+                return Ok(Transition::next(
+                    *$self,
+                    Stopping {
+                        stop_mode: StopBehavior::StopJob(grpc::StopMode::Drain),
+                    },
+                ));
+            }
             StopMode::force => {
                 return Ok(Transition::next(
                     *$self,
@@ -290,7 +359,7 @@ macro_rules! stop_if_desired_non_running {
         use crate::types::public::StopMode;
         use arroyo_rpc::grpc;
         match $config.stop_mode {
-            StopMode::checkpoint | StopMode::graceful | StopMode::immediate => {
+            StopMode::checkpoint | StopMode::graceful | StopMode::immediate | StopMode::drain => {
                 return Ok(Transition::next(
                     *$self,
                     Stopping {
@@ -320,12 +389,16 @@ pub struct Context<'a> {
     config: JobConfig,
     status: &'a mut JobStatus,
     program: &'a mut Program,
-    pool: Pool,
+    store: Arc<dyn JobStore>,
     scheduler: Arc<dyn Scheduler>,
     rx: &'a mut Receiver<JobMessage>,
     retries_attempted: usize,
     job_controller: Option<JobController>,
     last_transitioned_at: Instant,
+    poison_pills: PoisonPillDetector,
+    // operators that skip records that cause them to fail, from the job's failure policy
+    skip_operators: HashSet<String>,
+    job_progress: Arc<tokio::sync::Mutex<JobProgress>>,
 }
 
 impl<'a> Context<'a> {
@@ -391,6 +464,7 @@ async fn execute_state<'a>(
     mut ctx: Context<'a>,
 ) -> (Option<Box<dyn State>>, Context<'a>) {
     let state_name = state.name();
+    let was_terminal = state.is_terminal();
 
     let next: Option<Box<dyn State>> = match state.next(&mut ctx).await {
         Ok(Transition::Advance(s)) => {
@@ -413,6 +487,19 @@ async fn execute_state<'a>(
                 }),
             );
 
+            history::record_event(
+                &*ctx.store,
+                &ctx.config.id,
+                LogLevel::info,
+                &format!("Job is {}", s.state.name()),
+                &format!(
+                    "Transitioned from {} after {}ms",
+                    state_name,
+                    ctx.last_transitioned_at.elapsed().as_millis()
+                ),
+            )
+            .await;
+
             (s.update_fn)(&mut ctx);
             ctx.retries_attempted = 0;
             ctx.last_transitioned_at = Instant::now();
@@ -445,6 +532,14 @@ async fn execute_state<'a>(
                     "retries": 0,
                 }),
             );
+            history::record_event(
+                &*ctx.store,
+                &ctx.config.id,
+                LogLevel::error,
+                "Job failed",
+                &format!("Fatal error in {}: {}", state_name, message),
+            )
+            .await;
             ctx.status.failure_message = Some(message);
             ctx.status.finish_time = Some(OffsetDateTime::now_utc());
             let s: Box<dyn State> = Box::new(Failed {});
@@ -484,11 +579,19 @@ async fn execute_state<'a>(
 
     if let Some(s) = &next {
         ctx.status.state = s.name().to_string();
+        if s.name() != "Scheduling" {
+            ctx.status.scheduling_intent = None;
+        }
 
         ctx.status
-            .update_db(&ctx.pool)
+            .update_db(&*ctx.store)
             .await
             .expect("Failed to update status");
+
+        // previews aren't kept, so their runs aren't archived
+        if s.is_terminal() && !was_terminal && ctx.config.ttl.is_none() {
+            history::archive_run(&*ctx.store, ctx.status, &ctx.job_progress).await;
+        }
     }
 
     (next, ctx)
@@ -498,18 +601,14 @@ pub async fn run_to_completion(
     config: Arc<RwLock<JobConfig>>,
     mut status: JobStatus,
     mut state: Box<dyn State>,
-    pool: Pool,
+    store: Arc<dyn JobStore>,
     mut rx: Receiver<JobMessage>,
     scheduler: Arc<dyn Scheduler>,
+    job_progress: Arc<tokio::sync::Mutex<JobProgress>>,
 ) {
-    let c = pool.get().await.unwrap();
     let id = config.read().unwrap().pipeline_id;
     let mut program: Program = {
-        let res = controller_queries::get_program()
-            .bind(&c, &id)
-            .one()
-            .await
-            .unwrap();
+        let res = store.get_program(id).await.unwrap();
 
         PipelineProgram::decode(&res[..])
             .unwrap()
@@ -517,16 +616,31 @@ pub async fn run_to_completion(
             .unwrap()
     };
 
+    let config_snapshot = config.read().unwrap().clone();
+
+    // keep skipping records in the operator that was detected as failing before the controller
+    // restarted
+    let skip_operators = status
+        .poison_pill
+        .as_ref()
+        .filter(|_| config_snapshot.failure_policy.on_poison_pill == PoisonPillAction::Skip)
+        .map(|p| p.operator_id.clone())
+        .into_iter()
+        .collect();
+
     let mut ctx = Context {
-        config: config.read().unwrap().clone(),
+        config: config_snapshot,
         status: &mut status,
         program: &mut program,
-        pool: pool.clone(),
+        store,
         scheduler,
         rx: &mut rx,
         retries_attempted: 0,
         job_controller: None,
         last_transitioned_at: Instant::now(),
+        poison_pills: PoisonPillDetector::default(),
+        skip_operators,
+        job_progress,
     };
 
     loop {
@@ -545,22 +659,25 @@ pub async fn run_to_completion(
 pub struct StateMachine {
     tx: Option<Sender<JobMessage>>,
     config: Arc<RwLock<JobConfig>>,
-    pool: Pool,
+    store: Arc<dyn JobStore>,
     scheduler: Arc<dyn Scheduler>,
+    job_progress: Arc<tokio::sync::Mutex<JobProgress>>,
 }
 
 impl StateMachine {
     pub async fn new(
         config: JobConfig,
         status: JobStatus,
-        pool: Pool,
+        store: Arc<dyn JobStore>,
         scheduler: Arc<dyn Scheduler>,
+        job_progress: Arc<tokio::sync::Mutex<JobProgress>>,
     ) -> Self {
         let mut this = Self {
             tx: None,
             config: Arc::new(RwLock::new(config)),
-            pool,
+            store,
             scheduler,
+            job_progress,
         };
 
         this.start(status).await;
@@ -580,7 +697,18 @@ impl StateMachine {
             "Stopped" => Some(Box::new(Stopped {})),
             "Finished" => Some(Box::new(Finished {})),
             "Failed" => Some(Box::new(Failed {})),
-            "Compiling" | "Scheduling" | "Running" | "Recovering" => Some(Box::new(Compiling {})),
+            "NeedsAttention" => {
+                if self.config.read().unwrap().stop_mode == StopMode::none {
+                    Some(Box::new(NeedsAttention {}))
+                } else {
+                    Some(Box::new(Stopped {}))
+                }
+            }
+            "Scheduling" => match self.resumable_intent(&status).await {
+                Some(intent) => Some(Box::new(Scheduling::resume(intent))),
+                None => Some(Box::new(Compiling {})),
+            },
+            "Compiling" | "Running" | "Recovering" => Some(Box::new(Compiling {})),
             "Stopping" | "CheckpointStopping" => {
                 // TODO: do we need to handle a failure in CheckpointStopping specially?
                 if status.finish_time.is_none() {
@@ -601,16 +729,26 @@ impl StateMachine {
 
         if let Some(initial_state) = initial_state {
             status.state = initial_state.name().to_string();
-            status.update_db(&self.pool).await.unwrap();
+            status.update_db(&*self.store).await.unwrap();
             let (tx, rx) = channel(1024);
             {
                 let config = self.config.clone();
-                let pool = self.pool.clone();
+                let store = self.store.clone();
                 let scheduler = self.scheduler.clone();
+                let job_progress = self.job_progress.clone();
                 tokio::spawn(async move {
                     let id = { config.read().unwrap().id.clone() };
                     info!(message = "starting state machine", job_id = id);
-                    run_to_completion(config, status, initial_state, pool, rx, scheduler).await;
+                    run_to_completion(
+                        config,
+                        status,
+                        initial_state,
+                        store,
+                        rx,
+                        scheduler,
+                        job_progress,
+                    )
+                    .await;
                     info!(message = "finished state machine", job_id = id);
                 });
             }
@@ -619,6 +757,58 @@ impl StateMachine {
         }
     }
 
+    /// Reconciles the scheduling intent of a job that was scheduling when the controller stopped
+    /// against the workers the scheduler is running for it. Scheduling can be resumed if execution
+    /// hadn't yet been started and every worker that registered is still running; otherwise the job
+    /// is restarted from compilation, which moves it to a new run.
+    async fn resumable_intent(&self, status: &JobStatus) -> Option<SchedulingIntent> {
+        let intent = status.scheduling_intent.as_ref()?;
+
+        if intent.started || intent.run_id != status.run_id || status.pipeline_path.is_none() {
+            info!(
+                message = "not resuming scheduling",
+                job_id = status.id,
+                run_id = status.run_id,
+                started = intent.started
+            );
+            return None;
+        }
+
+        let running: HashSet<_> = match self
+            .scheduler
+            .workers_for_job(&status.id, Some(intent.run_id))
+            .await
+        {
+            Ok(workers) => workers.into_iter().collect(),
+            Err(e) => {
+                warn!(
+                    message = "failed to list workers for job, not resuming scheduling",
+                    job_id = status.id,
+                    error = format!("{:?}", e)
+                );
+                return None;
+            }
+        };
+
+        let missing = intent
+            .worker_ids()
+            .filter(|id| !running.contains(id))
+            .count();
+
+        if running.is_empty() || missing > 0 {
+            info!(
+                message = "workers for scheduled run are gone, not resuming scheduling",
+                job_id = status.id,
+                run_id = intent.run_id,
+                running = running.len(),
+                missing
+            );
+            return None;
+        }
+
+        Some(intent.clone())
+    }
+
     pub async fn update(&mut self, config: JobConfig, status: JobStatus) {
         if *self.config.read().unwrap() != config {
             let update = JobMessage::ConfigUpdate(config.clone());
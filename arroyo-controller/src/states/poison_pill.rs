@@ -0,0 +1,52 @@
+use arroyo_types::PoisonPill;
+
+// how many times in a row a task must fail after being restored from the same checkpoint before
+// we consider the failure to be caused by a poison pill
+const POISON_PILL_FAILURES: u32 = 3;
+
+#[derive(Default)]
+pub struct PoisonPillDetector {
+    last: Option<PoisonPill>,
+}
+
+impl PoisonPillDetector {
+    /// Records a task failure, returning the poison pill once the same task has failed enough
+    /// times in a row without the job completing a checkpoint in between
+    pub fn record(
+        &mut self,
+        operator_id: &str,
+        subtask_index: u32,
+        epoch: u32,
+        error: &str,
+    ) -> Option<&PoisonPill> {
+        match &mut self.last {
+            Some(last)
+                if last.operator_id == operator_id
+                    && last.subtask_index == subtask_index
+                    && last.epoch == epoch =>
+            {
+                last.failures += 1;
+                last.error = error.to_string();
+            }
+            _ => {
+                self.last = Some(PoisonPill {
+                    operator_id: operator_id.to_string(),
+                    subtask_index,
+                    epoch,
+                    failures: 1,
+                    error: error.to_string(),
+                });
+            }
+        }
+
+        self.last
+            .as_ref()
+            .filter(|p| p.failures >= POISON_PILL_FAILURES)
+    }
+
+    /// Failures that aren't caused by a task (like a worker failing to heartbeat) break up a run
+    /// of task failures
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
@@ -1,8 +1,8 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use anyhow::bail;
 use arroyo_rpc::grpc::StopMode;
-use tokio::time::timeout;
+use tokio::time::{timeout, Instant};
 use tracing::{info, warn};
 
 use super::{compiling::Compiling, Context, State, StateError, Transition};
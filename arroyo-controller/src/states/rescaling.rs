@@ -1,6 +1,6 @@
 use crate::{states::stop_if_desired_non_running, JobMessage};
 
-use super::{scheduling::Scheduling, Context, State, StateError, Transition};
+use super::{compiling::Compiling, scheduling::Scheduling, Context, State, StateError, Transition};
 
 #[derive(Debug)]
 pub struct Rescaling {}
@@ -22,7 +22,11 @@ impl State for Rescaling {
             match job_controller.checkpoint_finished().await {
                 Ok(done) => {
                     if done && job_controller.finished() {
-                        return Ok(Transition::next(*self, Scheduling {}));
+                        // jobs being upgraded need their pipeline to be recompiled
+                        if ctx.status.pipeline_path.is_none() {
+                            return Ok(Transition::next(*self, Compiling {}));
+                        }
+                        return Ok(Transition::next(*self, Scheduling::default()));
                     }
                 }
                 Err(e) => {
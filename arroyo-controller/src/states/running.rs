@@ -1,13 +1,15 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
+use arroyo_types::PoisonPillAction;
 use time::OffsetDateTime;
+use tokio::time::Instant;
 
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::states::finishing::Finishing;
 use crate::states::recovering::Recovering;
 use crate::states::rescaling::Rescaling;
-use crate::states::{fatal, stop_if_desired_running};
+use crate::states::{fatal, stop_if_desired_running, NeedsAttention};
 use crate::JobMessage;
 use crate::{job_controller::ControllerProgress, states::StateError};
 
@@ -16,8 +18,9 @@ use super::{Context, State, Transition};
 // after this amount of time, we consider the job to be healthy and reset the restarts counter
 const HEALTHY_DURATION: Duration = Duration::from_secs(2 * 60);
 
-// how many times we allow the job to restart before moving it to failed
-const RESTARTS_ALLOWED: usize = 10;
+// how many times we allow the job to restart before moving it to failed, unless the job's failure
+// policy sets its own restart budget
+const RESTARTS_ALLOWED: u32 = 10;
 
 #[derive(Debug)]
 pub struct Running {}
@@ -50,6 +53,24 @@ impl State for Running {
                             stop_if_desired_running!(self, &c);
 
                             let job_controller = ctx.job_controller.as_ref().unwrap();
+
+                            // new restore overrides are applied by restarting from a checkpoint
+                            if c.restore_overrides.is_some()
+                                && c.restore_overrides.as_ref() != job_controller.restore_overrides() {
+                                return Ok(Transition::next(
+                                    *self,
+                                    Rescaling {}
+                                ));
+                            }
+
+                            // as is a new stop time, which the watermark generators read on start
+                            if c.stop_at_event_time != job_controller.stop_at_event_time() {
+                                return Ok(Transition::next(
+                                    *self,
+                                    Rescaling {}
+                                ));
+                            }
+
                             for (op, p) in &c.parallelism_overrides {
                                 if let Some(actual) = job_controller.operator_parallelism(op){
                                     if actual != *p {
@@ -61,6 +82,22 @@ impl State for Running {
                                 }
                             }
                         }
+                        Some(JobMessage::Reschedule) => {
+                            return Ok(Transition::next(
+                                *self,
+                                Rescaling {}
+                            ));
+                        }
+                        Some(JobMessage::Upgrade) => {
+                            // the pipeline is rebuilt by this version of arroyo once the job has
+                            // stopped at its checkpoint
+                            ctx.status.pipeline_path = None;
+                            ctx.status.wasm_path = None;
+                            return Ok(Transition::next(
+                                *self,
+                                Rescaling {}
+                            ));
+                        }
                         Some(JobMessage::RunningMessage(msg)) => {
                             if let Err(e) = ctx.job_controller.as_mut().unwrap().handle_message(msg).await {
                                 return Err(ctx.retryable(self, "job encountered an error", e, 10));
@@ -78,7 +115,7 @@ impl State for Running {
                     if ctx.status.restarts > 0 && running_start.elapsed() > HEALTHY_DURATION {
                         let restarts = ctx.status.restarts;
                         ctx.status.restarts = 0;
-                        if let Err(e) = ctx.status.update_db(&ctx.pool).await {
+                        if let Err(e) = ctx.status.update_db(&*ctx.store).await {
                             error!(message = "Failed to update status", error = format!("{:?}", e),
                                 job_id = ctx.config.id);
                             ctx.status.restarts = restarts;
@@ -98,7 +135,53 @@ impl State for Running {
                         },
                         Err(err) => {
                             error!(message = "error while running", error = format!("{:?}", err), job_id = ctx.config.id);
-                            if ctx.status.restarts >= RESTARTS_ALLOWED as i32 {
+
+                            let job_controller = ctx.job_controller.as_ref().unwrap();
+                            let poison_pill = match job_controller.failed_task() {
+                                Some((operator_id, subtask, reason)) => ctx.poison_pills.record(
+                                    operator_id,
+                                    subtask,
+                                    job_controller.last_completed_epoch(),
+                                    reason,
+                                ).cloned(),
+                                None => {
+                                    ctx.poison_pills.reset();
+                                    None
+                                }
+                            };
+
+                            if let Some(poison_pill) = poison_pill {
+                                warn!(message = "detected poison pill", job_id = ctx.config.id,
+                                    operator_id = poison_pill.operator_id, subtask = poison_pill.subtask_index,
+                                    epoch = poison_pill.epoch, failures = poison_pill.failures);
+
+                                let operator_id = poison_pill.operator_id.clone();
+                                ctx.status.poison_pill = Some(poison_pill);
+
+                                match ctx.config.failure_policy.on_poison_pill {
+                                    PoisonPillAction::Fail => {}
+                                    PoisonPillAction::Skip => {
+                                        // if we're already skipping records in this operator, the
+                                        // failures aren't caused by a record and we treat them as usual
+                                        if ctx.skip_operators.insert(operator_id) {
+                                            ctx.poison_pills.reset();
+                                            return Ok(Transition::next(
+                                                *self,
+                                                Recovering {}
+                                            ));
+                                        }
+                                    }
+                                    PoisonPillAction::Park => {
+                                        return Ok(Transition::next(
+                                            *self,
+                                            NeedsAttention {}
+                                        ));
+                                    }
+                                }
+                            }
+
+                            let restarts_allowed = ctx.config.failure_policy.restart_budget.unwrap_or(RESTARTS_ALLOWED);
+                            if ctx.status.restarts >= restarts_allowed as i32 {
                                 return Err(fatal(
                                     "too many job failures",
                                     err
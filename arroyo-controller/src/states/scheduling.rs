@@ -1,15 +1,21 @@
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use arroyo_datastream::Program;
 use arroyo_rpc::grpc::{
     worker_grpc_client::WorkerGrpcClient, StartExecutionReq, TableWriteBehavior, TaskAssignment,
 };
-use arroyo_types::WorkerId;
-use tokio::{sync::Mutex, task::JoinHandle};
+use arroyo_server_common::tls;
+use arroyo_types::{
+    state_version_supported, to_micros, SandboxLimits, WorkerId, REPORT_WATERMARKS_ENV,
+    SKIP_FAILING_RECORDS_ENV, STOP_AT_EVENT_TIME_ENV,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
 use tonic::{transport::Channel, Request};
 use tracing::{error, info, warn};
 
@@ -18,7 +24,7 @@ use arroyo_state::{parquet::StorageClient, BackingStore, StateBackend};
 
 use crate::{
     job_controller::JobController,
-    queries::controller_queries,
+    job_store::StoredCheckpoint,
     states::{compiling::Compiling, stop_if_desired_non_running},
 };
 use crate::{schedulers::SchedulerError, JobMessage};
@@ -38,8 +44,47 @@ struct WorkerStatus {
     slots: usize,
 }
 
-#[derive(Debug)]
-pub struct Scheduling {}
+/// A worker that registered with the controller for the run being scheduled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RegisteredWorker {
+    id: u64,
+    rpc_address: String,
+    data_address: String,
+    slots: usize,
+}
+
+/// The scheduling decisions made for a run of the job, which are persisted with the job status
+/// while it is scheduling. If the controller restarts before execution is started, the intent is
+/// reconciled against the workers the scheduler still has for the run: if they are all still
+/// there, scheduling resumes with them rather than tearing them down and starting over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SchedulingIntent {
+    pub run_id: i64,
+    pub slots: usize,
+    pub workers: Vec<RegisteredWorker>,
+    /// Set once execution has been sent to the workers, after which the run can no longer be
+    /// resumed
+    pub started: bool,
+}
+
+impl SchedulingIntent {
+    pub fn worker_ids(&self) -> impl Iterator<Item = WorkerId> + '_ {
+        self.workers.iter().map(|w| WorkerId(w.id))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduling {
+    resume: Option<SchedulingIntent>,
+}
+
+impl Scheduling {
+    pub(crate) fn resume(intent: SchedulingIntent) -> Self {
+        Self {
+            resume: Some(intent),
+        }
+    }
+}
 
 fn slots_for_job(job: &Program) -> usize {
     job.graph
@@ -74,6 +119,67 @@ fn compute_assignments(workers: Vec<&WorkerStatus>, program: &Program) -> Vec<Ta
     assignments
 }
 
+fn connect_worker(
+    job_id: String,
+    worker: RegisteredWorker,
+    workers: &mut HashMap<WorkerId, WorkerStatus>,
+    worker_connects: Arc<Mutex<HashMap<WorkerId, WorkerGrpcClient<Channel>>>>,
+    handles: &mut Vec<JoinHandle<()>>,
+) {
+    let worker_id = WorkerId(worker.id);
+    let rpc_address = worker.rpc_address;
+
+    workers.insert(
+        worker_id,
+        WorkerStatus {
+            id: worker_id,
+            data_address: worker.data_address,
+            slots: worker.slots,
+        },
+    );
+
+    let connects = worker_connects;
+
+    handles.push(tokio::spawn(async move {
+        info!(
+            message = "connecting to worker",
+            job_id,
+            worker_id = worker_id.0,
+            rpc_address
+        );
+
+        for i in 0..10 {
+            match tls::connect_channel(
+                Channel::from_shared(rpc_address.clone())
+                    .unwrap()
+                    .timeout(Duration::from_secs(10)),
+            )
+            .await
+            {
+                Ok(channel) => {
+                    {
+                        let mut connects = connects.lock().await;
+                        connects.insert(worker_id, WorkerGrpcClient::new(channel));
+                    }
+                    return;
+                }
+                Err(e) => {
+                    error!(
+                        message = "Failed to connect to worker",
+                        job_id,
+                        worker_id = worker_id.0,
+                        error = format!("{:?}", e),
+                        rpc_address,
+                        retry = i
+                    );
+                    tokio::time::sleep(Duration::from_millis((i + 1) * 100)).await;
+                }
+            }
+        }
+        panic!("Failed to connect to worker {}", rpc_address);
+    }));
+}
+
 async fn handle_worker_connect<'a>(
     msg: JobMessage,
     workers: &mut HashMap<WorkerId, WorkerStatus>,
@@ -82,6 +188,16 @@ async fn handle_worker_connect<'a>(
     ctx: &mut Context<'a>,
 ) -> Result<(), StateError> {
     match msg {
+        JobMessage::WorkerConnect { run_id, .. } if run_id != ctx.status.run_id => {
+            // a worker from a previous run (for example, one started before the controller
+            // restarted) that hasn't been cleaned up yet
+            warn!(
+                message = "ignoring worker registration from a previous run",
+                job_id = ctx.config.id,
+                run_id,
+                current_run_id = ctx.status.run_id
+            );
+        }
         JobMessage::WorkerConnect {
             worker_id,
             rpc_address,
@@ -89,56 +205,31 @@ async fn handle_worker_connect<'a>(
             slots,
             ..
         } => {
-            workers.insert(
-                worker_id,
-                WorkerStatus {
-                    id: worker_id,
-                    data_address,
-                    slots,
-                },
-            );
-
-            let connects = worker_connects;
-
-            let job_id = ctx.config.id.clone();
-
-            handles.push(tokio::spawn(async move {
-                info!(
-                    message = "connecting to worker",
-                    job_id,
-                    worker_id = worker_id.0,
-                    rpc_address
-                );
-
-                for i in 0..10 {
-                    match Channel::from_shared(rpc_address.clone())
-                        .unwrap()
-                        .timeout(Duration::from_secs(10))
-                        .connect()
-                        .await
-                    {
-                        Ok(channel) => {
-                            {
-                                let mut connects = connects.lock().await;
-                                connects.insert(worker_id, WorkerGrpcClient::new(channel));
-                            }
-                            return;
-                        }
-                        Err(e) => {
-                            error!(
-                                message = "Failed to connect to worker",
-                                job_id,
-                                worker_id = worker_id.0,
-                                error = format!("{:?}", e),
-                                rpc_address,
-                                retry = i
-                            );
-                            tokio::time::sleep(Duration::from_millis((i + 1) * 100)).await;
-                        }
-                    }
+            let worker = RegisteredWorker {
+                id: worker_id.0,
+                rpc_address,
+                data_address,
+                slots,
+            };
+
+            if let Some(intent) = &mut ctx.status.scheduling_intent {
+                intent.workers.push(worker.clone());
+                if let Err(e) = ctx.status.update_db(&*ctx.store).await {
+                    warn!(
+                        message = "failed to persist scheduling intent",
+                        job_id = ctx.config.id,
+                        error = format!("{:?}", e)
+                    );
                 }
-                panic!("Failed to connect to worker {}", rpc_address);
-            }));
+            }
+
+            connect_worker(
+                ctx.config.id.clone(),
+                worker,
+                workers,
+                worker_connects,
+                handles,
+            );
         }
         other => {
             ctx.handle(other)?;
@@ -171,7 +262,44 @@ impl Scheduling {
                     name: ctx.config.pipeline_name.clone(),
                     hash: ctx.program.get_hash(),
                     slots: slots_needed,
-                    env_vars: StorageClient::get_storage_environment_variables(),
+                    env_vars: StorageClient::get_storage_environment_variables()
+                        .into_iter()
+                        .chain(ctx.config.env_vars.clone())
+                        .chain(ctx.config.queue_config.to_env_vars())
+                        .chain(ctx.config.state_storage.to_env_vars())
+                        .chain(
+                            ctx.config
+                                .restore_overrides
+                                .iter()
+                                .flat_map(|o| o.to_env_vars()),
+                        )
+                        .chain((!ctx.skip_operators.is_empty()).then(|| {
+                            (
+                                SKIP_FAILING_RECORDS_ENV.to_string(),
+                                ctx.skip_operators
+                                    .iter()
+                                    .cloned()
+                                    .collect::<Vec<_>>()
+                                    .join(","),
+                            )
+                        }))
+                        .chain(ctx.config.stop_at_event_time.map(|t| {
+                            (STOP_AT_EVENT_TIME_ENV.to_string(), to_micros(t).to_string())
+                        }))
+                        .chain(
+                            ctx.config
+                                .recovery_throttle
+                                .as_ref()
+                                .map(|_| (REPORT_WATERMARKS_ENV.to_string(), "true".to_string())),
+                        )
+                        // jobs with a TTL are previews, which run sandboxed
+                        .chain(
+                            ctx.config
+                                .ttl
+                                .iter()
+                                .flat_map(|_| SandboxLimits::for_previews().to_env_vars()),
+                        )
+                        .collect(),
                 })
                 .await
             {
@@ -230,33 +358,77 @@ impl State for Scheduling {
     }
 
     async fn next(mut self: Box<Self>, ctx: &mut Context) -> Result<Transition, StateError> {
-        // clear out any existing workers for this job
-        if let Err(e) = ctx.scheduler.stop_workers(&ctx.config.id, None, true).await {
-            warn!(
-                message = "failed to clean cluster prior to scheduling",
-                job_id = ctx.config.id,
-                error = format!("{:?}", e)
-            )
-        }
-
         ctx.program
             .update_parallelism(&ctx.config.parallelism_overrides);
 
         let slots_needed: usize = slots_for_job(ctx.program);
-        self = match self.start_workers(ctx, slots_needed).await? {
-            Either::Left(t) => {
-                return Ok(t);
-            }
-            Either::Right(s) => s,
-        };
 
-        // wait for them to connect and make outbound RPC connections
         let mut workers = HashMap::new();
         let worker_connects = Arc::new(Mutex::new(HashMap::new()));
         let mut handles = vec![];
 
+        // if we're retrying, start over rather than resuming again
+        let resume = self
+            .resume
+            .take()
+            .filter(|intent| intent.slots == slots_needed && intent.run_id == ctx.status.run_id);
+
+        if let Some(intent) = resume {
+            info!(
+                message = "resuming scheduling",
+                job_id = ctx.config.id,
+                run_id = intent.run_id,
+                registered_workers = intent.workers.len()
+            );
+
+            // reconnect to the workers that registered before the controller restarted; the rest
+            // will register with us as they start up
+            for worker in intent.workers.clone() {
+                connect_worker(
+                    ctx.config.id.clone(),
+                    worker,
+                    &mut workers,
+                    worker_connects.clone(),
+                    &mut handles,
+                );
+            }
+
+            ctx.status.scheduling_intent = Some(intent);
+        } else {
+            // clear out any existing workers for this job
+            if let Err(e) = ctx.scheduler.stop_workers(&ctx.config.id, None, true).await {
+                warn!(
+                    message = "failed to clean cluster prior to scheduling",
+                    job_id = ctx.config.id,
+                    error = format!("{:?}", e)
+                )
+            }
+
+            ctx.status.scheduling_intent = Some(SchedulingIntent {
+                run_id: ctx.status.run_id,
+                slots: slots_needed,
+                workers: vec![],
+                started: false,
+            });
+            if let Err(e) = ctx.status.update_db(&*ctx.store).await {
+                warn!(
+                    message = "failed to persist scheduling intent",
+                    job_id = ctx.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+
+            self = match self.start_workers(ctx, slots_needed).await? {
+                Either::Left(t) => {
+                    return Ok(t);
+                }
+                Either::Right(s) => s,
+            };
+        }
+
+        // wait for them to connect and make outbound RPC connections
         let start = Instant::now();
-        loop {
+        while workers.values().map(|w| w.slots).sum::<usize>() < slots_needed {
             let timeout = STARTUP_TIME
                 .checked_sub(start.elapsed())
                 .unwrap_or(Duration::ZERO);
@@ -281,10 +453,6 @@ impl State for Scheduling {
                         anyhow!("timed out after {:?} while waiting for worker startup", STARTUP_TIME), 3));
                 }
             }
-
-            if workers.values().map(|w| w.slots).sum::<usize>() >= slots_needed {
-                break;
-            }
         }
 
         for h in handles {
@@ -296,36 +464,74 @@ impl State for Scheduling {
         // Compute assignments and send to workers
 
         // TODO: better error handling
-        let c = ctx.pool.get().await.unwrap();
-
-        #[derive(Clone)]
-        struct CheckpointInfo {
-            epoch: u32,
-            min_epoch: u32,
-            id: i64,
-            needs_commits: bool,
+        let checkpoint_info = ctx
+            .store
+            .last_successful_checkpoint(&ctx.config.id)
+            .await
+            .unwrap();
+        if let Some(checkpoint) = &checkpoint_info {
+            info!(
+                message = "restoring checkpoint",
+                job_id = ctx.config.id,
+                epoch = checkpoint.epoch,
+                min_epoch = checkpoint.min_epoch
+            );
         }
 
-        let checkpoint_info = controller_queries::last_successful_checkpoint()
-            .bind(&c, &ctx.config.id)
-            .opt()
-            .await
-            .unwrap()
-            .map(|r| {
+        // a forked job starts from a copy of a checkpoint of the job it was forked from, which is
+        // recorded as the job's own first checkpoint so that it's only copied once
+        let checkpoint_info = match (checkpoint_info, &ctx.config.fork_from) {
+            (None, Some(fork)) => {
                 info!(
-                    message = "restoring checkpoint",
+                    message = "copying checkpoint of forked job",
                     job_id = ctx.config.id,
-                    epoch = r.epoch,
-                    min_epoch = r.min_epoch
+                    forked_job_id = fork.job_id,
+                    epoch = fork.epoch
                 );
 
-                CheckpointInfo {
-                    epoch: r.epoch as u32,
-                    min_epoch: r.min_epoch as u32,
-                    id: r.id,
-                    needs_commits: r.needs_commits,
+                let operators: Vec<String> = ctx
+                    .program
+                    .graph
+                    .node_weights()
+                    .map(|node| node.operator_id.clone())
+                    .collect();
+                if let Err(e) = StateBackend::fork_checkpoint(
+                    &fork.job_id,
+                    fork.epoch,
+                    &ctx.config.id,
+                    &operators,
+                    &fork.operators,
+                )
+                .await
+                {
+                    return Err(fatal("failed to copy checkpoint of forked job", e));
                 }
-            });
+
+                let id = ctx
+                    .store
+                    .create_checkpoint(
+                        &ctx.config.organization_id,
+                        &ctx.config.id,
+                        fork.epoch,
+                        fork.epoch,
+                        OffsetDateTime::now_utc(),
+                    )
+                    .await
+                    .unwrap();
+                ctx.store
+                    .commit_checkpoint(id, OffsetDateTime::now_utc())
+                    .await
+                    .unwrap();
+
+                Some(StoredCheckpoint {
+                    epoch: fork.epoch,
+                    min_epoch: fork.epoch,
+                    id,
+                    needs_commits: false,
+                })
+            }
+            (checkpoint_info, _) => checkpoint_info,
+        };
 
         {
             // mark in-progress checkpoints as failed
@@ -333,8 +539,8 @@ impl State for Scheduling {
                 .as_ref()
                 .map(|checkpoint_info| checkpoint_info.epoch)
                 .unwrap_or(0);
-            controller_queries::mark_failed()
-                .bind(&c, &ctx.config.id, &(last_epoch as i32 + 1))
+            ctx.store
+                .mark_failed(&ctx.config.id, last_epoch + 1)
                 .await
                 .unwrap();
         }
@@ -342,7 +548,7 @@ impl State for Scheduling {
         let mut committing_state = None;
 
         // clear all of the epochs after the one we're loading so that we don't read in-progress data
-        if let Some(CheckpointInfo {
+        if let Some(StoredCheckpoint {
             epoch,
             min_epoch,
             id,
@@ -352,6 +558,16 @@ impl State for Scheduling {
             let mut metadata = StateBackend::load_checkpoint_metadata(&ctx.config.id, epoch)
                 .await
                 .unwrap_or_else(|| panic!("epoch {} not found for job {}", epoch, ctx.config.id));
+            if !state_version_supported(metadata.state_version) {
+                return Err(fatal(
+                    "checkpoint was written by an incompatible version of arroyo",
+                    anyhow!(
+                        "state version {} of epoch {} is not supported",
+                        metadata.state_version,
+                        epoch
+                    ),
+                ));
+            }
             if let Err(e) = StateBackend::prepare_checkpoint_load(&metadata).await {
                 return Err(ctx.retryable(self, "failed to prepare checkpoint for loading", e, 10));
             }
@@ -388,6 +604,19 @@ impl State for Scheduling {
             StateBackend::complete_checkpoint(metadata).await;
         }
 
+        // once execution has been sent to the workers, the run can't be resumed
+        if let Some(intent) = &mut ctx.status.scheduling_intent {
+            intent.started = true;
+            if let Err(e) = ctx.status.update_db(&*ctx.store).await {
+                return Err(ctx.retryable(
+                    self,
+                    "failed to persist scheduling intent",
+                    anyhow!(e),
+                    10,
+                ));
+            }
+        }
+
         let assignments = compute_assignments(workers.values().collect(), ctx.program);
         let worker_connects = Arc::try_unwrap(worker_connects).unwrap().into_inner();
         let tasks: Vec<_> = worker_connects
@@ -472,7 +701,7 @@ impl State for Scheduling {
         let needs_commit = committing_state.is_some();
 
         let mut controller = JobController::new(
-            ctx.pool.clone(),
+            ctx.store.clone(),
             ctx.config.clone(),
             ctx.program.clone(),
             checkpoint_info.as_ref().map(|info| info.epoch).unwrap_or(0),
@@ -0,0 +1,257 @@
+//! Keeps a rolling window of the progress that workers report for each of their subtasks with
+//! their heartbeats (records in and out, watermark, state size, and source lag), so that the
+//! console can chart recent progress without a separate metrics stack.
+//!
+//! Records in and out are cumulative counts since the subtask started, so they reset when the job
+//! is restarted.
+//!
+//! The health of each source and sink that connects to an external system is tracked alongside,
+//! from the reports of the connectors and the failures of their subtasks. Unlike progress, it is
+//! kept across restarts so that the last error and number of reconnects remain visible.
+//!
+//! The job's most recent watermark probes are also kept here, with the time that each subtask
+//! reported observing them and its watermark at that time.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Instant, SystemTime};
+
+use arroyo_rpc::grpc::{
+    ConnectorHealthReq, ConnectorStatus, JobProgressResp, ProbeObservation, TaskProbeReq,
+    TaskProgress, TaskProgressSample, TaskProgressWindow, WatermarkProbeReport,
+};
+use arroyo_types::to_micros;
+
+/// Number of samples retained per subtask; with the worker's 5 second heartbeat this covers the
+/// last 5 minutes
+const MAX_SAMPLES: usize = 60;
+/// Number of jobs whose progress is retained; the least recently updated job is dropped first
+const MAX_JOBS: usize = 256;
+/// Number of watermark probes retained per job
+const MAX_PROBES: usize = 16;
+
+#[derive(Default)]
+struct JobProgressState {
+    // (operator id, subtask index) -> samples, oldest first
+    tasks: BTreeMap<(String, u32), VecDeque<TaskProgressSample>>,
+    // (operator id, subtask index) -> health, for connectors that have reported it
+    connectors: BTreeMap<(String, u32), ConnectorStatus>,
+    // oldest first
+    probes: VecDeque<WatermarkProbeReport>,
+}
+
+impl JobProgressState {
+    fn apply(&mut self, time: u64, tasks: Vec<TaskProgress>) {
+        for task in tasks {
+            if let Some(status) = self
+                .connectors
+                .get_mut(&(task.operator_id.clone(), task.task_index))
+            {
+                status.lag_millis = task.source_lag_millis;
+            }
+
+            let samples = self
+                .tasks
+                .entry((task.operator_id, task.task_index))
+                .or_default();
+
+            if samples.len() == MAX_SAMPLES {
+                samples.pop_front();
+            }
+
+            samples.push_back(TaskProgressSample {
+                time,
+                records_in: task.records_in,
+                records_out: task.records_out,
+                watermark_micros: task.watermark_micros,
+                state_bytes: task.state_bytes,
+                source_lag_millis: task.source_lag_millis,
+            });
+        }
+    }
+
+    fn connector_health(&mut self, req: ConnectorHealthReq) {
+        let status = self
+            .connectors
+            .entry((req.operator_id.clone(), req.task_index))
+            .or_insert_with(|| ConnectorStatus {
+                operator_id: req.operator_id,
+                task_index: req.task_index,
+                ..Default::default()
+            });
+
+        // any connection after the first (including after the subtask restarts) is a reconnect
+        if req.connected && status.updated_time > 0 {
+            status.reconnects += 1;
+        }
+
+        status.connected = req.connected;
+        status.updated_time = req.time;
+        if let Some(error) = req.error {
+            status.last_error = Some(error);
+            status.last_error_time = Some(req.time);
+        }
+    }
+
+    fn task_failed(&mut self, operator_id: &str, task_index: u32, time: u64, error: &str) {
+        if let Some(status) = self
+            .connectors
+            .get_mut(&(operator_id.to_string(), task_index))
+        {
+            status.connected = false;
+            status.updated_time = time;
+            status.last_error = Some(error.to_string());
+            status.last_error_time = Some(time);
+        }
+    }
+
+    fn start_probe(&mut self, label: String) -> u64 {
+        let injected_time = to_micros(SystemTime::now());
+
+        // subtasks only handle probes newer than the last one they saw, so ids must increase
+        let probe_id = self
+            .probes
+            .back()
+            .map(|p| p.probe_id + 1)
+            .unwrap_or_default()
+            .max(injected_time);
+
+        if self.probes.len() == MAX_PROBES {
+            self.probes.pop_front();
+        }
+
+        self.probes.push_back(WatermarkProbeReport {
+            probe_id,
+            label,
+            injected_time,
+            observations: vec![],
+        });
+
+        probe_id
+    }
+
+    fn probe_observed(&mut self, req: TaskProbeReq) {
+        if let Some(probe) = self.probes.iter_mut().find(|p| p.probe_id == req.probe_id) {
+            probe.observations.push(ProbeObservation {
+                operator_id: req.operator_id,
+                task_index: req.task_index,
+                time: req.time,
+                watermark_micros: req.watermark_micros,
+            });
+        }
+    }
+
+    fn to_resp(&self) -> JobProgressResp {
+        JobProgressResp {
+            tasks: self
+                .tasks
+                .iter()
+                .map(|((operator_id, task_index), samples)| TaskProgressWindow {
+                    operator_id: operator_id.clone(),
+                    task_index: *task_index,
+                    samples: samples.iter().cloned().collect(),
+                })
+                .collect(),
+            connectors: self.connectors.values().cloned().collect(),
+        }
+    }
+}
+
+/// The recent progress and connector health of running jobs
+#[derive(Default)]
+pub struct JobProgress {
+    jobs: HashMap<String, (Instant, JobProgressState)>,
+}
+
+impl JobProgress {
+    /// Records the progress reported by a worker's heartbeat at `time` (in micros)
+    pub fn apply(&mut self, job_id: &str, time: u64, tasks: Vec<TaskProgress>) {
+        if tasks.is_empty() {
+            return;
+        }
+
+        self.state(job_id).apply(time, tasks);
+    }
+
+    /// Records a connector's report that it connected or lost its connection
+    pub fn connector_health(&mut self, req: ConnectorHealthReq) {
+        let job_id = req.job_id.clone();
+        self.state(&job_id).connector_health(req);
+    }
+
+    /// Marks the connector run by a failed subtask (if any) as disconnected
+    pub fn task_failed(
+        &mut self,
+        job_id: &str,
+        operator_id: &str,
+        task_index: u32,
+        time: u64,
+        error: &str,
+    ) {
+        if let Some((_, state)) = self.jobs.get_mut(job_id) {
+            state.task_failed(operator_id, task_index, time, error);
+        }
+    }
+
+    /// Records a new watermark probe for the job, returning its id
+    pub fn start_probe(&mut self, job_id: &str, label: String) -> u64 {
+        self.state(job_id).start_probe(label)
+    }
+
+    /// Forgets a probe that couldn't be injected
+    pub fn cancel_probe(&mut self, job_id: &str, probe_id: u64) {
+        if let Some((_, state)) = self.jobs.get_mut(job_id) {
+            state.probes.retain(|p| p.probe_id != probe_id);
+        }
+    }
+
+    /// Records a subtask's report that it observed a watermark probe
+    pub fn probe_observed(&mut self, req: TaskProbeReq) {
+        if let Some((_, state)) = self.jobs.get_mut(&req.job_id) {
+            state.probe_observed(req);
+        }
+    }
+
+    /// The job's recent watermark probes, oldest first
+    pub fn probes(&self, job_id: &str) -> Vec<WatermarkProbeReport> {
+        self.jobs
+            .get(job_id)
+            .map(|(_, state)| state.probes.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn state(&mut self, job_id: &str) -> &mut JobProgressState {
+        if !self.jobs.contains_key(job_id) && self.jobs.len() == MAX_JOBS {
+            let oldest = self
+                .jobs
+                .iter()
+                .min_by_key(|(_, (updated, _))| *updated)
+                .map(|(job_id, _)| job_id.clone())
+                .unwrap();
+            self.jobs.remove(&oldest);
+        }
+
+        let (updated, state) = self.jobs.entry(job_id.to_string()).or_default();
+        *updated = Instant::now();
+        state
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<JobProgressResp> {
+        self.jobs.get(job_id).map(|(_, state)| state.to_resp())
+    }
+
+    /// The most recent progress of each of the job's subtasks, with its operator id
+    pub fn latest(&self, job_id: &str) -> Vec<(String, TaskProgressSample)> {
+        self.jobs
+            .get(job_id)
+            .map(|(_, state)| {
+                state
+                    .tasks
+                    .iter()
+                    .filter_map(|((operator_id, _), samples)| {
+                        Some((operator_id.clone(), samples.back()?.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,243 @@
+//! Support for testing the job state machine without a cluster, enabled by the `test-harness`
+//! feature.
+//!
+//! [`JobHarness`] runs the state machine for a single job against a [`VirtualScheduler`], whose
+//! workers acknowledge the controller's requests and report task events back to the job without
+//! running anything. Tests drive the job through scheduling, checkpointing, failure recovery, and
+//! rescaling by changing its configuration, injecting task failures, and moving the clock forward.
+//!
+//! Jobs are kept in a [`MemoryJobStore`] rather than in the database, so the harness runs without
+//! one. Tests run with the clock paused (`#[tokio::test(start_paused = true)]`), so that timers
+//! fire in order whenever the job is waiting rather than in real time, and check each state the
+//! job transitions to as it happens.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_datastream::Program;
+use arroyo_rpc::grpc::api::PipelineProgram;
+use prost::Message;
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::sync::Mutex;
+
+use crate::states::{Created, State, StateMachine};
+use crate::task_progress::JobProgress;
+use crate::types::public::StopMode;
+use crate::{JobConfig, JobMessage, JobStatus, RunningMessage};
+
+mod scheduler;
+mod store;
+#[cfg(test)]
+mod tests;
+
+pub use scheduler::{SchedulerEvent, VirtualScheduler};
+pub use store::{CheckpointRecord, EventRecord, MemoryJobStore};
+
+const TEST_ORGANIZATION: &str = "test-organization";
+
+pub struct JobHarness {
+    job_id: String,
+    store: Arc<MemoryJobStore>,
+    jobs: Arc<Mutex<HashMap<String, StateMachine>>>,
+    scheduler: Arc<VirtualScheduler>,
+    // how many of the job's transitions have been checked
+    transitions_seen: AtomicUsize,
+}
+
+impl JobHarness {
+    /// Creates a job that runs `program` and starts its state machine
+    pub async fn start(program: Program, checkpoint_interval: Duration) -> anyhow::Result<Self> {
+        let job_id: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect();
+
+        let program = PipelineProgram::try_from(program)?.encode_to_vec();
+
+        let config = JobConfig {
+            id: job_id.clone(),
+            organization_id: TEST_ORGANIZATION.to_string(),
+            pipeline_name: job_id.clone(),
+            pipeline_id: 1,
+            stop_mode: StopMode::none,
+            checkpoint_interval,
+            ttl: None,
+            parallelism_overrides: HashMap::new(),
+            env_vars: HashMap::new(),
+            restore_overrides: None,
+            failure_policy: Default::default(),
+            queue_config: Default::default(),
+            dependencies: vec![],
+            fork_from: None,
+            stop_at_event_time: None,
+            recovery_throttle: None,
+            state_storage: Default::default(),
+        };
+
+        let status = JobStatus {
+            id: job_id.clone(),
+            run_id: 0,
+            state: Created {}.name().to_string(),
+            start_time: None,
+            finish_time: None,
+            tasks: None,
+            failure_message: None,
+            poison_pill: None,
+            restarts: 0,
+            // nothing is compiled for virtual workers; setting the binary paths skips compilation
+            pipeline_path: Some("virtual".to_string()),
+            wasm_path: Some("virtual".to_string()),
+            scheduling_intent: None,
+        };
+
+        let store = Arc::new(MemoryJobStore::default());
+        store.add_job(program, config, status);
+
+        let jobs = Arc::new(Mutex::new(HashMap::new()));
+        let scheduler = Arc::new(VirtualScheduler::new(jobs.clone()));
+
+        let harness = Self {
+            job_id,
+            store,
+            jobs,
+            scheduler,
+            transitions_seen: AtomicUsize::new(0),
+        };
+        harness.sync().await?;
+
+        Ok(harness)
+    }
+
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    pub fn scheduler(&self) -> &VirtualScheduler {
+        &self.scheduler
+    }
+
+    pub fn store(&self) -> &MemoryJobStore {
+        &self.store
+    }
+
+    // reads the job from the store and passes it to its state machine, as the controller does
+    // when it polls for changes
+    async fn sync(&self) -> anyhow::Result<()> {
+        let (config, status) = self
+            .store
+            .job(&self.job_id)
+            .ok_or_else(|| anyhow!("job {} not found", self.job_id))?;
+
+        let mut jobs = self.jobs.lock().await;
+        if let Some(sm) = jobs.get_mut(&self.job_id) {
+            sm.update(config, status).await;
+        } else {
+            let sm = StateMachine::new(
+                config,
+                status,
+                self.store.clone(),
+                self.scheduler.clone(),
+                Arc::new(Mutex::new(JobProgress::default())),
+            )
+            .await;
+            jobs.insert(self.job_id.clone(), sm);
+        }
+
+        Ok(())
+    }
+
+    /// The state that the job's state machine last transitioned to
+    pub async fn state(&self) -> anyhow::Result<String> {
+        self.store
+            .state(&self.job_id)
+            .ok_or_else(|| anyhow!("job {} not found", self.job_id))
+    }
+
+    /// Waits for the job's next transition, failing if it isn't to `state` or doesn't happen
+    /// within `timeout`
+    pub async fn expect_transition(&self, state: &str, timeout: Duration) -> anyhow::Result<()> {
+        let index = self.transitions_seen.fetch_add(1, Ordering::SeqCst);
+        let next = tokio::time::timeout(timeout, self.store.transition(&self.job_id, index))
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "timed out waiting for job to transition to {}; it is {:?}",
+                    state,
+                    self.store.state(&self.job_id)
+                )
+            })?;
+
+        if next != state {
+            bail!(
+                "expected job to transition to {}, but it transitioned to {}",
+                state,
+                next
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Sets the job's stop mode; `StopMode::none` starts a stopped job
+    pub async fn set_stop_mode(&self, stop_mode: StopMode) -> anyhow::Result<()> {
+        self.store
+            .update_config(&self.job_id, |c| c.stop_mode = stop_mode);
+
+        self.sync().await
+    }
+
+    /// Changes the parallelism of an operator, which rescales a running job
+    pub async fn set_parallelism(
+        &self,
+        operator_id: &str,
+        parallelism: usize,
+    ) -> anyhow::Result<()> {
+        self.store.update_config(&self.job_id, |c| {
+            c.parallelism_overrides
+                .insert(operator_id.to_string(), parallelism);
+        });
+
+        self.sync().await
+    }
+
+    /// Reports that a subtask of the running job has failed
+    pub async fn fail_task(
+        &self,
+        operator_id: &str,
+        subtask_index: u32,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let Some(worker_id) = self
+            .scheduler
+            .workers_for_job(&self.job_id, None)
+            .await?
+            .into_iter()
+            .next()
+        else {
+            bail!("job {} has no workers", self.job_id);
+        };
+
+        let mut jobs = self.jobs.lock().await;
+        jobs.get_mut(&self.job_id)
+            .unwrap()
+            .send(JobMessage::RunningMessage(RunningMessage::TaskFailed {
+                worker_id,
+                operator_id: operator_id.to_string(),
+                subtask_index,
+                reason: reason.to_string(),
+            }))
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+
+    /// Lets the job run for `duration`. The clock is paused, so it only moves forward while the
+    /// job and its workers are waiting, and timers (checkpoint intervals, heartbeat timeouts, and
+    /// restart backoffs) fire in order.
+    pub async fn advance(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use arroyo_rpc::grpc::worker_grpc_server::{WorkerGrpc, WorkerGrpcServer};
+use arroyo_rpc::grpc::{
+    AlignSourcesReq, AlignSourcesResp, AssignWorkerReq, AssignWorkerResp, CheckpointReq,
+    CheckpointResp, ColumnStatsReq, ColumnStatsResp, HeartbeatNodeReq, InjectProbeReq,
+    InjectProbeResp, JobFinishedReq, JobFinishedResp, LoadCompactedStateReq,
+    LoadCompactedStateResp, RegisterNodeReq, SampleOutputReq, SampleOutputResp, SetLogFilterReq,
+    SetLogFilterResp, StartExecutionReq, StartExecutionResp, StopExecutionReq, StopExecutionResp,
+    SubtaskCheckpointMetadata, TaskCheckpointCompletedReq, TaskCheckpointEventReq,
+    TaskCheckpointEventType, WorkerFinishedReq, WorkerIdleReq,
+};
+use arroyo_types::{to_micros, NodeId, WorkerId};
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, Mutex};
+use tokio::time::Instant;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status};
+
+use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
+use crate::states::StateMachine;
+use crate::{JobMessage, RunningMessage};
+
+// how often virtual workers heartbeat to their job
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A call made to the scheduler by a job's state machine
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchedulerEvent {
+    StartWorkers {
+        job_id: String,
+        run_id: i64,
+        slots: usize,
+    },
+    StopWorkers {
+        job_id: String,
+        run_id: Option<i64>,
+        force: bool,
+    },
+}
+
+struct VirtualWorker {
+    job_id: String,
+    run_id: i64,
+    slots: usize,
+    shutdown_tx: oneshot::Sender<()>,
+}
+
+/// A scheduler that runs each worker as an in-process gRPC server rather than starting a
+/// process. Virtual workers don't run any tasks; they acknowledge the controller's requests and
+/// report the task events that real workers would (tasks starting, completing checkpoints, and
+/// finishing) back to the job.
+pub struct VirtualScheduler {
+    jobs: Arc<Mutex<HashMap<String, StateMachine>>>,
+    workers: Mutex<HashMap<WorkerId, VirtualWorker>>,
+    events: Mutex<Vec<SchedulerEvent>>,
+    capacity: Mutex<Option<usize>>,
+    worker_counter: AtomicU64,
+}
+
+impl VirtualScheduler {
+    pub(crate) fn new(jobs: Arc<Mutex<HashMap<String, StateMachine>>>) -> Self {
+        Self {
+            jobs,
+            workers: Mutex::new(HashMap::new()),
+            events: Mutex::new(vec![]),
+            capacity: Mutex::new(None),
+            worker_counter: AtomicU64::new(100),
+        }
+    }
+
+    /// Limits the number of slots that can be scheduled across all jobs; scheduling beyond it
+    /// fails with `NotEnoughSlots`. `None` removes the limit.
+    pub async fn set_capacity(&self, slots: Option<usize>) {
+        *self.capacity.lock().await = slots;
+    }
+
+    /// The calls made to the scheduler so far, in order
+    pub async fn events(&self) -> Vec<SchedulerEvent> {
+        self.events.lock().await.clone()
+    }
+}
+
+async fn send_to_job(
+    jobs: &Mutex<HashMap<String, StateMachine>>,
+    job_id: &str,
+    msg: JobMessage,
+) -> Result<(), Status> {
+    let mut jobs = jobs.lock().await;
+    let Some(sm) = jobs.get_mut(job_id) else {
+        return Err(Status::not_found(format!("no job with id {}", job_id)));
+    };
+
+    sm.send(msg).await.map_err(Status::failed_precondition)
+}
+
+#[async_trait::async_trait]
+impl Scheduler for VirtualScheduler {
+    async fn start_workers(
+        &self,
+        start_pipeline_req: StartPipelineReq,
+    ) -> Result<(), SchedulerError> {
+        self.events.lock().await.push(SchedulerEvent::StartWorkers {
+            job_id: start_pipeline_req.job_id.clone(),
+            run_id: start_pipeline_req.run_id,
+            slots: start_pipeline_req.slots,
+        });
+
+        let mut workers = self.workers.lock().await;
+        if let Some(capacity) = *self.capacity.lock().await {
+            let free = capacity.saturating_sub(workers.values().map(|w| w.slots).sum());
+            if free < start_pipeline_req.slots {
+                return Err(SchedulerError::NotEnoughSlots {
+                    slots_needed: start_pipeline_req.slots - free,
+                });
+            }
+        }
+
+        let worker_id = WorkerId(self.worker_counter.fetch_add(1, Ordering::SeqCst));
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let rpc_address = VirtualWorkerServer::start(
+            worker_id,
+            start_pipeline_req.job_id.clone(),
+            self.jobs.clone(),
+            shutdown_rx,
+        )
+        .await
+        .map_err(|e| SchedulerError::Other(format!("failed to start virtual worker: {:?}", e)))?;
+
+        workers.insert(
+            worker_id,
+            VirtualWorker {
+                job_id: start_pipeline_req.job_id.clone(),
+                run_id: start_pipeline_req.run_id,
+                slots: start_pipeline_req.slots,
+                shutdown_tx,
+            },
+        );
+        drop(workers);
+
+        send_to_job(
+            &self.jobs,
+            &start_pipeline_req.job_id,
+            JobMessage::WorkerConnect {
+                worker_id,
+                node_id: NodeId(0),
+                rpc_address,
+                data_address: "virtual".to_string(),
+                slots: start_pipeline_req.slots,
+                job_hash: start_pipeline_req.hash,
+                run_id: start_pipeline_req.run_id,
+            },
+        )
+        .await
+        .map_err(|e| SchedulerError::Other(e.message().to_string()))
+    }
+
+    async fn register_node(&self, _: RegisterNodeReq) {}
+    async fn heartbeat_node(&self, _: HeartbeatNodeReq) -> Result<(), Status> {
+        Ok(())
+    }
+    async fn worker_finished(&self, _: WorkerFinishedReq) {}
+    async fn worker_idle(&self, _: WorkerIdleReq) {}
+
+    async fn stop_workers(
+        &self,
+        job_id: &str,
+        run_id: Option<i64>,
+        force: bool,
+    ) -> anyhow::Result<()> {
+        self.events.lock().await.push(SchedulerEvent::StopWorkers {
+            job_id: job_id.to_string(),
+            run_id,
+            force,
+        });
+
+        for worker_id in self.workers_for_job(job_id, run_id).await? {
+            if let Some(worker) = self.workers.lock().await.remove(&worker_id) {
+                let _ = worker.shutdown_tx.send(());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn workers_for_job(
+        &self,
+        job_id: &str,
+        run_id: Option<i64>,
+    ) -> anyhow::Result<Vec<WorkerId>> {
+        Ok(self
+            .workers
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, w)| {
+                w.job_id == job_id && (run_id.is_none() || w.run_id == run_id.unwrap())
+            })
+            .map(|(k, _)| *k)
+            .collect())
+    }
+}
+
+struct VirtualWorkerServer {
+    worker_id: WorkerId,
+    job_id: String,
+    jobs: Arc<Mutex<HashMap<String, StateMachine>>>,
+    tasks: Mutex<Vec<(String, u32)>>,
+}
+
+impl VirtualWorkerServer {
+    // starts serving on a local port, returning the worker's rpc address
+    async fn start(
+        worker_id: WorkerId,
+        job_id: String,
+        jobs: Arc<Mutex<HashMap<String, StateMachine>>>,
+        shutdown_rx: oneshot::Receiver<()>,
+    ) -> anyhow::Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let local_addr = listener.local_addr()?;
+
+        let heartbeats = {
+            let job_id = job_id.clone();
+            let jobs = jobs.clone();
+            tokio::spawn(async move {
+                loop {
+                    let heartbeat = RunningMessage::WorkerHeartbeat {
+                        worker_id,
+                        time: Instant::now(),
+                    };
+                    if send_to_job(&jobs, &job_id, JobMessage::RunningMessage(heartbeat))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                    tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+                }
+            })
+        };
+
+        let server = VirtualWorkerServer {
+            worker_id,
+            job_id,
+            jobs,
+            tasks: Mutex::new(vec![]),
+        };
+
+        tokio::spawn(async move {
+            let _ = arroyo_server_common::grpc_server()
+                .add_service(WorkerGrpcServer::new(server))
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            heartbeats.abort();
+        });
+
+        Ok(format!("http://{}", local_addr))
+    }
+
+    async fn send(&self, msg: JobMessage) -> Result<(), Status> {
+        send_to_job(&self.jobs, &self.job_id, msg).await
+    }
+
+    async fn finish_tasks(&self) -> Result<(), Status> {
+        for (operator_id, subtask_index) in self.tasks.lock().await.iter() {
+            self.send(JobMessage::RunningMessage(RunningMessage::TaskFinished {
+                worker_id: self.worker_id,
+                time: SystemTime::now(),
+                operator_id: operator_id.clone(),
+                subtask_index: *subtask_index,
+            }))
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl WorkerGrpc for VirtualWorkerServer {
+    async fn start_execution(
+        &self,
+        request: Request<StartExecutionReq>,
+    ) -> Result<Response<StartExecutionResp>, Status> {
+        let tasks: Vec<_> = request
+            .into_inner()
+            .tasks
+            .into_iter()
+            .filter(|t| t.worker_id == self.worker_id.0)
+            .map(|t| (t.operator_id, t.operator_subtask as u32))
+            .collect();
+
+        for (operator_id, subtask_index) in &tasks {
+            self.send(JobMessage::TaskStarted {
+                worker_id: self.worker_id,
+                operator_id: operator_id.clone(),
+                operator_subtask: *subtask_index as u64,
+            })
+            .await?;
+        }
+
+        *self.tasks.lock().await = tasks;
+
+        Ok(Response::new(StartExecutionResp {}))
+    }
+
+    async fn checkpoint(
+        &self,
+        request: Request<CheckpointReq>,
+    ) -> Result<Response<CheckpointResp>, Status> {
+        let req = request.into_inner();
+        if req.is_commit {
+            return Ok(Response::new(CheckpointResp {}));
+        }
+
+        // virtual tasks have no state, so they finish checkpointing as soon as they start
+        for (operator_id, subtask_index) in self.tasks.lock().await.iter() {
+            let now = to_micros(SystemTime::now());
+
+            self.send(JobMessage::RunningMessage(
+                RunningMessage::TaskCheckpointEvent(TaskCheckpointEventReq {
+                    worker_id: self.worker_id.0,
+                    time: now,
+                    job_id: self.job_id.clone(),
+                    operator_id: operator_id.clone(),
+                    subtask_index: *subtask_index,
+                    epoch: req.epoch,
+                    event_type: TaskCheckpointEventType::StartedCheckpointing as i32,
+                }),
+            ))
+            .await?;
+
+            self.send(JobMessage::RunningMessage(
+                RunningMessage::TaskCheckpointFinished(TaskCheckpointCompletedReq {
+                    worker_id: self.worker_id.0,
+                    time: now,
+                    job_id: self.job_id.clone(),
+                    operator_id: operator_id.clone(),
+                    epoch: req.epoch,
+                    metadata: Some(SubtaskCheckpointMetadata {
+                        subtask_index: *subtask_index,
+                        start_time: now,
+                        finish_time: now,
+                        has_state: false,
+                        ..Default::default()
+                    }),
+                    needs_commit: false,
+                }),
+            ))
+            .await?;
+        }
+
+        if req.then_stop {
+            self.finish_tasks().await?;
+        }
+
+        Ok(Response::new(CheckpointResp {}))
+    }
+
+    async fn stop_execution(
+        &self,
+        _: Request<StopExecutionReq>,
+    ) -> Result<Response<StopExecutionResp>, Status> {
+        self.finish_tasks().await?;
+        Ok(Response::new(StopExecutionResp {}))
+    }
+
+    async fn job_finished(
+        &self,
+        _: Request<JobFinishedReq>,
+    ) -> Result<Response<JobFinishedResp>, Status> {
+        Ok(Response::new(JobFinishedResp {}))
+    }
+
+    async fn align_sources(
+        &self,
+        _: Request<AlignSourcesReq>,
+    ) -> Result<Response<AlignSourcesResp>, Status> {
+        Ok(Response::new(AlignSourcesResp {}))
+    }
+
+    async fn set_log_filter(
+        &self,
+        _: Request<SetLogFilterReq>,
+    ) -> Result<Response<SetLogFilterResp>, Status> {
+        Ok(Response::new(SetLogFilterResp { applied: true }))
+    }
+
+    async fn sample_output(
+        &self,
+        _: Request<SampleOutputReq>,
+    ) -> Result<Response<SampleOutputResp>, Status> {
+        Ok(Response::new(SampleOutputResp { samples: vec![] }))
+    }
+
+    async fn get_column_stats(
+        &self,
+        _: Request<ColumnStatsReq>,
+    ) -> Result<Response<ColumnStatsResp>, Status> {
+        Ok(Response::new(ColumnStatsResp { operators: vec![] }))
+    }
+
+    async fn assign_worker(
+        &self,
+        _: Request<AssignWorkerReq>,
+    ) -> Result<Response<AssignWorkerResp>, Status> {
+        // virtual workers are never reused
+        Err(Status::failed_precondition("Worker is not reusable"))
+    }
+
+    async fn inject_probe(
+        &self,
+        _: Request<InjectProbeReq>,
+    ) -> Result<Response<InjectProbeResp>, Status> {
+        Ok(Response::new(InjectProbeResp { sources: 0 }))
+    }
+
+    async fn load_compacted_state(
+        &self,
+        _: Request<LoadCompactedStateReq>,
+    ) -> Result<Response<LoadCompactedStateResp>, Status> {
+        Ok(Response::new(LoadCompactedStateResp {}))
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use arroyo_types::StateCompaction;
+use time::OffsetDateTime;
+use tokio::sync::Notify;
+
+use crate::job_store::{JobStore, StoredCheckpoint};
+use crate::types::public::{CheckpointState, LogLevel};
+use crate::{JobConfig, JobStatus};
+
+/// A checkpoint as the job's state machine has recorded it
+#[derive(Clone, Debug)]
+pub struct CheckpointRecord {
+    pub id: i64,
+    pub job_id: String,
+    pub epoch: u32,
+    pub min_epoch: u32,
+    pub state: CheckpointState,
+    pub finish_time: Option<OffsetDateTime>,
+}
+
+/// An event added to a job's log
+#[derive(Clone, Debug)]
+pub struct EventRecord {
+    pub job_id: String,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct Jobs {
+    programs: HashMap<i64, Vec<u8>>,
+    configs: HashMap<String, JobConfig>,
+    statuses: HashMap<String, JobStatus>,
+    // the states that each job has transitioned to, in order
+    transitions: HashMap<String, Vec<String>>,
+    compactions: HashMap<String, StateCompaction>,
+    events: Vec<EventRecord>,
+    runs: Vec<JobStatus>,
+    checkpoints: Vec<CheckpointRecord>,
+}
+
+/// Keeps jobs in memory rather than in the database, so that state machine tests don't need one
+#[derive(Default)]
+pub struct MemoryJobStore {
+    jobs: Mutex<Jobs>,
+    // notified whenever a job transitions to a new state
+    transitioned: Notify,
+}
+
+impl MemoryJobStore {
+    pub(crate) fn add_job(&self, program: Vec<u8>, config: JobConfig, status: JobStatus) {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.programs.insert(config.pipeline_id, program);
+        jobs.statuses.insert(status.id.clone(), status);
+        jobs.configs.insert(config.id.clone(), config);
+    }
+
+    /// Reads a job as the controller does when it polls for changes
+    pub(crate) fn job(&self, job_id: &str) -> Option<(JobConfig, JobStatus)> {
+        let jobs = self.jobs.lock().unwrap();
+        Some((
+            jobs.configs.get(job_id)?.clone(),
+            jobs.statuses.get(job_id)?.clone(),
+        ))
+    }
+
+    pub(crate) fn update_config(&self, job_id: &str, f: impl FnOnce(&mut JobConfig)) {
+        f(self.jobs.lock().unwrap().configs.get_mut(job_id).unwrap());
+    }
+
+    /// The state that the job's state machine last transitioned to
+    pub fn state(&self, job_id: &str) -> Option<String> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.statuses.get(job_id).map(|s| s.state.clone())
+    }
+
+    /// The states that the job has transitioned to, in order
+    pub fn transitions(&self, job_id: &str) -> Vec<String> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.transitions.get(job_id).cloned().unwrap_or_default()
+    }
+
+    /// Waits for the job's `index`th transition, returning the state it transitioned to
+    pub async fn transition(&self, job_id: &str, index: usize) -> String {
+        loop {
+            // registered before checking, so that a transition in between isn't missed
+            let transitioned = self.transitioned.notified();
+            if let Some(state) = self
+                .jobs
+                .lock()
+                .unwrap()
+                .transitions
+                .get(job_id)
+                .and_then(|t| t.get(index))
+            {
+                return state.clone();
+            }
+            transitioned.await;
+        }
+    }
+
+    pub fn checkpoints(&self, job_id: &str) -> Vec<CheckpointRecord> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.checkpoints
+            .iter()
+            .filter(|c| c.job_id == job_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn events(&self, job_id: &str) -> Vec<EventRecord> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.events
+            .iter()
+            .filter(|e| e.job_id == job_id)
+            .cloned()
+            .collect()
+    }
+
+    /// The ids of the job's runs that have been archived
+    pub fn archived_runs(&self, job_id: &str) -> Vec<i64> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.runs
+            .iter()
+            .filter(|r| r.id == job_id)
+            .map(|r| r.run_id)
+            .collect()
+    }
+
+    // applies `f` to the job's checkpoints that match `filter`
+    fn update_checkpoints(
+        &self,
+        filter: impl Fn(&CheckpointRecord) -> bool,
+        f: impl Fn(&mut CheckpointRecord),
+    ) {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.checkpoints
+            .iter_mut()
+            .filter(|c| filter(c))
+            .for_each(f);
+    }
+}
+
+#[async_trait::async_trait]
+impl JobStore for MemoryJobStore {
+    async fn get_program(&self, pipeline_id: i64) -> anyhow::Result<Vec<u8>> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.programs
+            .get(&pipeline_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("pipeline {} does not exist", pipeline_id))
+    }
+
+    async fn update_status(&self, status: &JobStatus) -> anyhow::Result<()> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(s) = jobs.statuses.get_mut(&status.id) else {
+            return Err(anyhow!("Job status does not exist"));
+        };
+        let transitioned = s.state != status.state;
+        *s = status.clone();
+
+        if transitioned {
+            jobs.transitions
+                .entry(status.id.clone())
+                .or_default()
+                .push(status.state.clone());
+            self.transitioned.notify_waiters();
+        }
+
+        Ok(())
+    }
+
+    async fn create_event(
+        &self,
+        job_id: &str,
+        level: LogLevel,
+        message: &str,
+        _details: &str,
+    ) -> anyhow::Result<()> {
+        self.jobs.lock().unwrap().events.push(EventRecord {
+            job_id: job_id.to_string(),
+            level,
+            message: message.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn archive_run(
+        &self,
+        status: &JobStatus,
+        _metrics: Option<serde_json::Value>,
+        _finish_time: OffsetDateTime,
+    ) -> anyhow::Result<()> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if !jobs
+            .runs
+            .iter()
+            .any(|r| r.id == status.id && r.run_id == status.run_id)
+        {
+            jobs.runs.push(status.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn last_successful_checkpoint(
+        &self,
+        job_id: &str,
+    ) -> anyhow::Result<Option<StoredCheckpoint>> {
+        Ok(self
+            .checkpoints(job_id)
+            .into_iter()
+            .filter(|c| {
+                matches!(
+                    c.state,
+                    CheckpointState::ready | CheckpointState::committing
+                )
+            })
+            .max_by_key(|c| c.epoch)
+            .map(|c| StoredCheckpoint {
+                id: c.id,
+                epoch: c.epoch,
+                min_epoch: c.min_epoch,
+                needs_commits: c.state == CheckpointState::committing,
+            }))
+    }
+
+    async fn create_checkpoint(
+        &self,
+        _organization_id: &str,
+        job_id: &str,
+        epoch: u32,
+        min_epoch: u32,
+        _start_time: OffsetDateTime,
+    ) -> anyhow::Result<i64> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = jobs.checkpoints.len() as i64 + 1;
+        jobs.checkpoints.push(CheckpointRecord {
+            id,
+            job_id: job_id.to_string(),
+            epoch,
+            min_epoch,
+            state: CheckpointState::inprogress,
+            finish_time: None,
+        });
+
+        Ok(id)
+    }
+
+    async fn update_checkpoint(
+        &self,
+        checkpoint_id: i64,
+        _operators: serde_json::Value,
+        finish_time: Option<OffsetDateTime>,
+        state: CheckpointState,
+    ) -> anyhow::Result<()> {
+        self.update_checkpoints(
+            |c| c.id == checkpoint_id,
+            |c| {
+                c.finish_time = finish_time;
+                c.state = state;
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn commit_checkpoint(
+        &self,
+        checkpoint_id: i64,
+        finish_time: OffsetDateTime,
+    ) -> anyhow::Result<()> {
+        self.update_checkpoints(
+            |c| c.id == checkpoint_id,
+            |c| {
+                c.finish_time = Some(finish_time);
+                c.state = CheckpointState::ready;
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, job_id: &str, epoch: u32) -> anyhow::Result<()> {
+        self.update_checkpoints(
+            |c| c.job_id == job_id && c.epoch >= epoch,
+            |c| c.state = CheckpointState::failed,
+        );
+
+        Ok(())
+    }
+
+    async fn mark_compacting(
+        &self,
+        job_id: &str,
+        min_epoch: u32,
+        epoch: u32,
+    ) -> anyhow::Result<()> {
+        self.update_checkpoints(
+            |c| c.job_id == job_id && c.epoch >= min_epoch && c.epoch < epoch,
+            |c| c.state = CheckpointState::compacting,
+        );
+
+        Ok(())
+    }
+
+    async fn mark_checkpoints_compacted(&self, job_id: &str, epoch: u32) -> anyhow::Result<()> {
+        self.update_checkpoints(
+            |c| c.job_id == job_id && c.epoch < epoch,
+            |c| c.state = CheckpointState::compacted,
+        );
+
+        Ok(())
+    }
+
+    async fn clear_restore_overrides(
+        &self,
+        job_id: &str,
+        overrides_id: &str,
+    ) -> anyhow::Result<()> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(config) = jobs.configs.get_mut(job_id) {
+            if matches!(&config.restore_overrides, Some(o) if o.id == overrides_id) {
+                config.restore_overrides = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_state_compaction(
+        &self,
+        job_id: &str,
+        compaction: &StateCompaction,
+    ) -> anyhow::Result<()> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .compactions
+            .insert(job_id.to_string(), compaction.clone());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use arroyo_datastream::{ConnectorOp, EdgeType, Operator, Program, StreamEdge, StreamNode};
+use petgraph::graph::DiGraph;
+
+use super::{JobHarness, SchedulerEvent};
+use crate::types::public::CheckpointState;
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// a source feeding a sink, each with a single subtask
+fn program() -> Program {
+    let mut graph = DiGraph::new();
+    let source = graph.add_node(StreamNode {
+        operator_id: "source".to_string(),
+        operator: Operator::ConnectorSource(ConnectorOp {
+            operator: "NullSource".to_string(),
+            config: "".to_string(),
+            description: "Null".to_string(),
+            router: None,
+        }),
+        parallelism: 1,
+    });
+    let sink = graph.add_node(StreamNode {
+        operator_id: "sink".to_string(),
+        operator: Operator::ConnectorSink(ConnectorOp {
+            operator: "NullSink".to_string(),
+            config: "".to_string(),
+            description: "Null".to_string(),
+            router: None,
+        }),
+        parallelism: 1,
+    });
+    graph.add_edge(
+        source,
+        sink,
+        StreamEdge::unkeyed_edge("()", EdgeType::Forward),
+    );
+
+    Program {
+        types: vec![],
+        other_defs: vec![],
+        wasm_defs: vec![],
+        graph,
+        instrumentation: None,
+    }
+}
+
+async fn running_job() -> JobHarness {
+    let harness = JobHarness::start(program(), CHECKPOINT_INTERVAL)
+        .await
+        .unwrap();
+    expect_transitions(&harness, &["Compiling", "Scheduling", "Running"]).await;
+    harness
+}
+
+async fn expect_transitions(harness: &JobHarness, states: &[&str]) {
+    for state in states {
+        harness.expect_transition(state, TIMEOUT).await.unwrap();
+    }
+}
+
+// the states the job has transitioned to, in order
+fn transitions(harness: &JobHarness) -> Vec<String> {
+    harness.store().transitions(harness.job_id())
+}
+
+fn start_workers(harness: &JobHarness, events: Vec<SchedulerEvent>) -> Vec<(i64, usize)> {
+    events
+        .into_iter()
+        .filter_map(|e| match e {
+            SchedulerEvent::StartWorkers {
+                job_id,
+                run_id,
+                slots,
+            } if job_id == harness.job_id() => Some((run_id, slots)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_scheduling() {
+    let harness = running_job().await;
+
+    assert_eq!(
+        transitions(&harness),
+        vec!["Compiling", "Scheduling", "Running"]
+    );
+    assert_eq!(
+        start_workers(&harness, harness.scheduler().events().await),
+        vec![(1, 1)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_checkpoint() {
+    let harness = running_job().await;
+
+    harness.advance(CHECKPOINT_INTERVAL * 2).await;
+
+    let checkpoints = harness.store().checkpoints(harness.job_id());
+    assert!(!checkpoints.is_empty(), "no checkpoints were taken");
+    let first = &checkpoints[0];
+    assert_eq!(first.epoch, 1);
+    assert_eq!(first.state, CheckpointState::ready);
+    assert!(first.finish_time.is_some());
+    assert_eq!(harness.state().await.unwrap(), "Running");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_failure_recovers() {
+    let harness = running_job().await;
+    harness.advance(CHECKPOINT_INTERVAL * 2).await;
+
+    harness
+        .fail_task("source", 0, "test failure")
+        .await
+        .unwrap();
+    expect_transitions(
+        &harness,
+        &["Recovering", "Compiling", "Scheduling", "Running"],
+    )
+    .await;
+
+    assert_eq!(
+        transitions(&harness),
+        vec![
+            "Compiling",
+            "Scheduling",
+            "Running",
+            "Recovering",
+            "Compiling",
+            "Scheduling",
+            "Running"
+        ]
+    );
+    // the job is restarted as a new run
+    assert_eq!(
+        start_workers(&harness, harness.scheduler().events().await),
+        vec![(1, 1), (2, 1)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_rescale() {
+    let harness = running_job().await;
+
+    harness.set_parallelism("source", 2).await.unwrap();
+    // the job is stopped with a checkpoint before it's rescheduled, which happens once the
+    // paused clock reaches it
+    expect_transitions(&harness, &["Rescaling", "Scheduling", "Running"]).await;
+
+    assert_eq!(
+        transitions(&harness),
+        vec![
+            "Compiling",
+            "Scheduling",
+            "Running",
+            "Rescaling",
+            "Scheduling",
+            "Running"
+        ]
+    );
+    // the job is restarted on enough slots for the new parallelism
+    assert_eq!(
+        start_workers(&harness, harness.scheduler().events().await),
+        vec![(1, 1), (2, 2)]
+    );
+}
@@ -15,7 +15,7 @@ use std::time::{Duration, SystemTime};
 use arroyo_rpc::grpc::api::create_pipeline_req::Config;
 use arroyo_rpc::grpc::api::operator::Operator as GrpcOperator;
 use arroyo_rpc::grpc::api::{self as GrpcApi, ExpressionAggregator, Flatten, ProgramEdge};
-use arroyo_types::{Data, GlobalKey, JoinType, Key};
+use arroyo_types::{CalendarUnit, Data, GlobalKey, JoinType, Key};
 use bincode::{Decode, Encode};
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
@@ -106,8 +106,22 @@ pub struct WasmUDF {
 
 #[derive(Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
 pub enum WindowType {
-    Tumbling { width: Duration },
-    Sliding { width: Duration, slide: Duration },
+    /// `offset` shifts window boundaries away from multiples of the width (e.g., hourly windows
+    /// starting at :15)
+    Tumbling {
+        width: Duration,
+        offset: Duration,
+    },
+    Sliding {
+        width: Duration,
+        slide: Duration,
+        offset: Duration,
+    },
+    /// Windows aligned to days, weeks, or months in an IANA timezone (like `America/New_York`)
+    Calendar {
+        unit: CalendarUnit,
+        timezone: String,
+    },
     Instant,
 }
 
@@ -132,10 +146,22 @@ fn format_duration(duration: Duration) -> String {
 impl Debug for WindowType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Tumbling { width } => {
+            Self::Tumbling { width, offset } if offset.is_zero() => {
                 write!(f, "TumblingWindow({})", format_duration(*width))
             }
-            Self::Sliding { width, slide } => {
+            Self::Tumbling { width, offset } => {
+                write!(
+                    f,
+                    "TumblingWindow({}, offset: {})",
+                    format_duration(*width),
+                    format_duration(*offset)
+                )
+            }
+            Self::Sliding {
+                width,
+                slide,
+                offset,
+            } if offset.is_zero() => {
                 write!(
                     f,
                     "SlidingWindow(size: {}, slide: {})",
@@ -143,6 +169,22 @@ impl Debug for WindowType {
                     format_duration(*slide)
                 )
             }
+            Self::Sliding {
+                width,
+                slide,
+                offset,
+            } => {
+                write!(
+                    f,
+                    "SlidingWindow(size: {}, slide: {}, offset: {})",
+                    format_duration(*width),
+                    format_duration(*slide),
+                    format_duration(*offset)
+                )
+            }
+            Self::Calendar { unit, timezone } => {
+                write!(f, "CalendarWindow({:?}, {})", unit, timezone)
+            }
             Self::Instant => {
                 write!(f, "InstantWindow")
             }
@@ -155,13 +197,257 @@ pub enum WatermarkType {
     FixedLateness {
         period: Duration,
         max_lateness: Duration,
+        alignment: Option<WatermarkAlignment>,
     },
     Expression {
         period: Duration,
         expression: String,
+        alignment: Option<WatermarkAlignment>,
     },
 }
 
+impl WatermarkType {
+    pub fn alignment(&self) -> Option<&WatermarkAlignment> {
+        match self {
+            WatermarkType::FixedLateness { alignment, .. }
+            | WatermarkType::Expression { alignment, .. } => alignment.as_ref(),
+        }
+    }
+}
+
+/// Sources whose watermarks are in the same alignment group are paused while their watermark is
+/// more than `max_drift` ahead of the slowest source in the group, which bounds the state that
+/// downstream joins and windows must buffer when one source is far behind the others
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WatermarkAlignment {
+    pub group: String,
+    pub max_drift: Duration,
+}
+
+impl From<GrpcApi::WatermarkAlignment> for WatermarkAlignment {
+    fn from(value: GrpcApi::WatermarkAlignment) -> Self {
+        WatermarkAlignment {
+            group: value.group,
+            max_drift: Duration::from_micros(value.max_drift_micros),
+        }
+    }
+}
+
+impl From<WatermarkAlignment> for GrpcApi::WatermarkAlignment {
+    fn from(value: WatermarkAlignment) -> Self {
+        GrpcApi::WatermarkAlignment {
+            group: value.group,
+            max_drift_micros: value.max_drift.as_micros() as u64,
+        }
+    }
+}
+
+/// Bounds on how far the timestamps of records read from a source may be from the current time;
+/// records outside of them are dropped, or if `clamp` is set, moved to the nearest bound
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimestampBounds {
+    pub max_future: Option<Duration>,
+    pub max_age: Option<Duration>,
+    pub clamp: bool,
+}
+
+impl From<GrpcApi::TimestampBounds> for TimestampBounds {
+    fn from(value: GrpcApi::TimestampBounds) -> Self {
+        TimestampBounds {
+            max_future: value.max_future_micros.map(Duration::from_micros),
+            max_age: value.max_age_micros.map(Duration::from_micros),
+            clamp: value.clamp,
+        }
+    }
+}
+
+impl From<TimestampBounds> for GrpcApi::TimestampBounds {
+    fn from(value: TimestampBounds) -> Self {
+        GrpcApi::TimestampBounds {
+            max_future_micros: value.max_future.map(|d| d.as_micros() as u64),
+            max_age_micros: value.max_age.map(|d| d.as_micros() as u64),
+            clamp: value.clamp,
+        }
+    }
+}
+
+/// A join against a table of JSON rows that every subtask loads from `location` and reloads every
+/// `refresh_interval`; records without a matching row are dropped if `inner` is set
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LookupJoin {
+    pub location: String,
+    pub refresh_interval: Duration,
+    pub inner: bool,
+    pub row_type: String,
+    // fn(&R) -> K
+    pub row_key: String,
+    // fn(&T, Option<&R>) -> OutT
+    pub merge: String,
+}
+
+impl From<GrpcApi::LookupJoin> for LookupJoin {
+    fn from(value: GrpcApi::LookupJoin) -> Self {
+        LookupJoin {
+            location: value.location,
+            refresh_interval: Duration::from_micros(value.refresh_interval_micros),
+            inner: value.inner,
+            row_type: value.row_type,
+            row_key: value.row_key,
+            merge: value.merge,
+        }
+    }
+}
+
+impl From<LookupJoin> for GrpcApi::LookupJoin {
+    fn from(value: LookupJoin) -> Self {
+        GrpcApi::LookupJoin {
+            location: value.location,
+            refresh_interval_micros: value.refresh_interval.as_micros() as u64,
+            inner: value.inner,
+            row_type: value.row_type,
+            row_key: value.row_key,
+            merge: value.merge,
+        }
+    }
+}
+
+/// Maintains the first `max_elements` records of its input, ordered by the key computed by
+/// `extractor`, emitting the changes to them as updates. Each subtask of a partial top-N keeps the
+/// top of its own input, and a `global` top-N (which always runs as a single subtask) merges them.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TopN {
+    pub max_elements: usize,
+    // fn(&T) -> SK
+    pub extractor: String,
+    pub sort_key_type: String,
+    // whether the input is a stream of updates rather than of appended records
+    pub input_updating: bool,
+    pub global: bool,
+}
+
+impl From<GrpcApi::TopN> for TopN {
+    fn from(value: GrpcApi::TopN) -> Self {
+        TopN {
+            max_elements: value.max_elements as usize,
+            extractor: value.extractor,
+            sort_key_type: value.sort_key_type,
+            input_updating: value.input_updating,
+            global: value.global,
+        }
+    }
+}
+
+impl From<TopN> for GrpcApi::TopN {
+    fn from(value: TopN) -> Self {
+        GrpcApi::TopN {
+            max_elements: value.max_elements as u64,
+            extractor: value.extractor,
+            sort_key_type: value.sort_key_type,
+            input_updating: value.input_updating,
+            global: value.global,
+        }
+    }
+}
+
+/// A custom operator called from SQL with PROCESS, which runs the process function `function` (a
+/// type defined with the pipeline's UDFs) as a single subtask
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessFunction {
+    pub function: String,
+    pub config: String,
+    // fn(&T) -> F::Input
+    pub input: String,
+    // fn(F::Output) -> OutT
+    pub output: String,
+}
+
+impl From<GrpcApi::ProcessFunction> for ProcessFunction {
+    fn from(value: GrpcApi::ProcessFunction) -> Self {
+        ProcessFunction {
+            function: value.function,
+            config: value.config,
+            input: value.input,
+            output: value.output,
+        }
+    }
+}
+
+impl From<ProcessFunction> for GrpcApi::ProcessFunction {
+    fn from(value: ProcessFunction) -> Self {
+        GrpcApi::ProcessFunction {
+            function: value.function,
+            config: value.config,
+            input: value.input,
+            output: value.output,
+        }
+    }
+}
+
+/// A data quality assertion declared in SQL with ASSERT, checked against each record read from a
+/// source. Violations are dropped, or if `fail` is set, fail the pipeline.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Assertion {
+    // the assertion's SQL, which labels its metrics
+    pub assertion: String,
+    pub fail: bool,
+    // evaluates to whether `record` passes
+    pub predicate: String,
+}
+
+impl From<GrpcApi::Assertion> for Assertion {
+    fn from(value: GrpcApi::Assertion) -> Self {
+        Assertion {
+            assertion: value.assertion,
+            fail: value.fail,
+            predicate: value.predicate,
+        }
+    }
+}
+
+impl From<Assertion> for GrpcApi::Assertion {
+    fn from(value: Assertion) -> Self {
+        GrpcApi::Assertion {
+            assertion: value.assertion,
+            fail: value.fail,
+            predicate: value.predicate,
+        }
+    }
+}
+
+/// Per-tenant rate limits on the records read from a source, which are keyed by their tenant.
+/// Records over a tenant's rate are dropped, or queued if `max_queued` is set.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TenantLimit {
+    // records per second per tenant
+    pub max_rate: u32,
+    pub burst: u32,
+    pub max_queued: Option<u32>,
+    // fn(&T) -> String, the tenant that labels its metrics
+    pub tenant: String,
+}
+
+impl From<GrpcApi::TenantLimit> for TenantLimit {
+    fn from(value: GrpcApi::TenantLimit) -> Self {
+        TenantLimit {
+            max_rate: value.max_rate,
+            burst: value.burst,
+            max_queued: value.max_queued,
+            tenant: value.tenant,
+        }
+    }
+}
+
+impl From<TenantLimit> for GrpcApi::TenantLimit {
+    fn from(value: TenantLimit) -> Self {
+        GrpcApi::TenantLimit {
+            max_rate: value.max_rate,
+            burst: value.burst,
+            max_queued: value.max_queued,
+            tenant: value.tenant,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Encode, Decode, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub enum OffsetMode {
     Earliest,
@@ -223,7 +509,7 @@ pub struct SlidingWindowAggregator {
 #[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TumblingWindowAggregator {
     pub width: Duration,
-    // fn(&MemA) -> OutT
+    // fn(&BinA) -> Option<OutT>
     pub aggregator: String,
     // fn(&T, Option<&BinA>) -> BinA
     pub bin_merger: String,
@@ -284,8 +570,16 @@ pub enum SerializationMode {
     // https://docs.confluent.io/platform/current/schema-registry/serdes-develop/index.html#wire-format
     JsonSchemaRegistry,
     RawJson,
+    // bytes are passed through as-is, without any decoding
+    RawBytes,
     DebeziumJson,
     Parquet,
+    // confluent schema registry wire format, with the schemas read from the registry
+    Avro,
+    // with the message descriptor from the operator's config
+    Protobuf,
+    // with the delimiter, quoting and header row from the operator's config
+    Csv,
 }
 impl SerializationMode {
     pub fn from_has_registry_flag(has_registry: bool) -> Self {
@@ -300,7 +594,11 @@ impl SerializationMode {
             Some("json") => Self::Json,
             Some("json_schema_registry") => Self::JsonSchemaRegistry,
             Some("raw_json") => Self::RawJson,
+            Some("raw_bytes") => Self::RawBytes,
             Some("debezium_json") => Self::DebeziumJson,
+            Some("avro") => Self::Avro,
+            Some("protobuf") => Self::Protobuf,
+            Some("csv") => Self::Csv,
             _ => Self::Json,
         }
     }
@@ -322,10 +620,26 @@ impl ToTokens for SerializationMode {
             SerializationMode::RawJson => {
                 quote::quote!(arroyo_worker::operators::SerializationMode::RawJson)
             }
+            SerializationMode::RawBytes => {
+                quote::quote!(arroyo_worker::operators::SerializationMode::RawBytes)
+            }
             SerializationMode::DebeziumJson => {
                 quote::quote!(arroyo_worker::operators::SerializationMode::Json)
             }
             SerializationMode::Parquet => unimplemented!(),
+            SerializationMode::Avro => {
+                quote::quote!(arroyo_worker::operators::SerializationMode::Avro)
+            }
+            SerializationMode::Protobuf => {
+                quote::quote!(arroyo_worker::operators::SerializationMode::Protobuf)
+            }
+            // operators that read csv build their options from the connector's config; this is the
+            // mode with the default options
+            SerializationMode::Csv => {
+                quote::quote!(arroyo_worker::operators::SerializationMode::Csv(
+                    arroyo_worker::formats::csv::CsvOptions::default()
+                ))
+            }
         };
 
         tokens.append_all(serialization_mode);
@@ -338,7 +652,11 @@ impl From<GrpcApi::SerializationMode> for SerializationMode {
             GrpcApi::SerializationMode::Json => Self::Json,
             GrpcApi::SerializationMode::JsonSchemaRegistry => Self::JsonSchemaRegistry,
             GrpcApi::SerializationMode::Raw => Self::RawJson,
+            GrpcApi::SerializationMode::RawBytes => Self::RawBytes,
             GrpcApi::SerializationMode::Parquet => Self::Parquet,
+            GrpcApi::SerializationMode::Avro => Self::Avro,
+            GrpcApi::SerializationMode::Protobuf => Self::Protobuf,
+            GrpcApi::SerializationMode::Csv => Self::Csv,
         }
     }
 }
@@ -351,14 +669,19 @@ pub struct ConnectorOp {
     pub config: String,
     // description to be rendered in the pipeline graph
     pub description: String,
+    // for sinks, a closure that computes the destination (like a topic) of each record
+    pub router: Option<String>,
 }
 
 impl ConnectorOp {
-    pub fn web_sink() -> Self {
+    /// A sink that sends its output to the console; `tag` identifies its output when a preview
+    /// has several sinks
+    pub fn web_sink(tag: &str) -> Self {
         ConnectorOp {
             operator: "GrpcSink::<#in_k, #in_t>".to_string(),
-            config: "{}".to_string(),
+            config: serde_json::json!({ "tag": tag }).to_string(),
             description: "WebSink".to_string(),
+            router: None,
         }
     }
 }
@@ -369,6 +692,7 @@ impl From<GrpcApi::ConnectorOp> for ConnectorOp {
             operator: c.operator,
             config: c.config,
             description: c.description,
+            router: c.router,
         }
     }
 }
@@ -379,6 +703,7 @@ impl From<ConnectorOp> for GrpcApi::ConnectorOp {
             operator: c.operator,
             config: c.config,
             description: c.description,
+            router: c.router,
         }
     }
 }
@@ -434,6 +759,16 @@ pub enum Operator {
         name: String,
         expression: String,
     },
+    ReorderBuffer,
+    IntervalJoin {
+        window: Duration,
+    },
+    TimestampBounds(TimestampBounds),
+    LookupJoin(LookupJoin),
+    TopN(TopN),
+    ProcessFunction(ProcessFunction),
+    Assertion(Assertion),
+    TenantLimit(TenantLimit),
 }
 
 #[derive(Clone, Encode, Decode, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -508,14 +843,18 @@ impl Debug for Operator {
                     "SlidingWindowAggregator<{:?}>",
                     WindowType::Sliding {
                         width: *width,
-                        slide: *slide
+                        slide: *slide,
+                        offset: Duration::ZERO,
                     }
                 )
             }
             Operator::TumblingWindowAggregator(TumblingWindowAggregator { width, .. }) => write!(
                 f,
                 "TumblingWindowAggregator<{:?}>",
-                WindowType::Tumbling { width: *width }
+                WindowType::Tumbling {
+                    width: *width,
+                    offset: Duration::ZERO
+                }
             ),
             Operator::TumblingTopN(TumblingTopN {
                 width,
@@ -524,7 +863,10 @@ impl Debug for Operator {
             }) => write!(
                 f,
                 "TumblingTopN<{:?}, {:?}>",
-                WindowType::Tumbling { width: *width },
+                WindowType::Tumbling {
+                    width: *width,
+                    offset: Duration::ZERO
+                },
                 *max_elements
             ),
             Operator::SlidingAggregatingTopN(SlidingAggregatingTopN { width, slide, .. }) => {
@@ -533,7 +875,8 @@ impl Debug for Operator {
                     "SlidingAggregatingTopN<{:?}>",
                     WindowType::Sliding {
                         width: *width,
-                        slide: *slide
+                        slide: *slide,
+                        offset: Duration::ZERO,
                     }
                 )
             }
@@ -555,6 +898,32 @@ impl Debug for Operator {
                 name,
                 expression: _,
             } => write!(f, "updating_key<{}>", name),
+            Operator::ReorderBuffer => write!(f, "ReorderBuffer"),
+            Operator::IntervalJoin { window } => write!(f, "IntervalJoin<{:?}>", window),
+            Operator::TimestampBounds(_) => write!(f, "TimestampBounds"),
+            Operator::LookupJoin(LookupJoin { location, .. }) => {
+                write!(f, "LookupJoin<{}>", location)
+            }
+            Operator::TopN(TopN {
+                max_elements,
+                global,
+                ..
+            }) => {
+                if *global {
+                    write!(f, "GlobalTopN<{}>", max_elements)
+                } else {
+                    write!(f, "TopN<{}>", max_elements)
+                }
+            }
+            Operator::ProcessFunction(ProcessFunction { function, .. }) => {
+                write!(f, "Process<{}>", function)
+            }
+            Operator::Assertion(Assertion { assertion, .. }) => {
+                write!(f, "Assertion<{}>", assertion)
+            }
+            Operator::TenantLimit(TenantLimit { max_rate, .. }) => {
+                write!(f, "TenantLimit<{}/s>", max_rate)
+            }
         }
     }
 }
@@ -766,7 +1135,9 @@ impl<T: Data> Stream<T> {
         Program {
             types: vec![],
             other_defs: vec![],
+            wasm_defs: vec![],
             graph: self.graph.take(),
+            instrumentation: None,
         }
     }
 }
@@ -799,7 +1170,10 @@ impl<K: Key, T: Data> TumblingWindow<K, T> {
 impl<K: Key, T: Data> KeyedWindowFun<K, T> for TumblingWindow<K, T> {
     fn as_operator(&self) -> Operator {
         Operator::Window {
-            typ: WindowType::Tumbling { width: self.width },
+            typ: WindowType::Tumbling {
+                width: self.width,
+                offset: Duration::ZERO,
+            },
             agg: None,
             flatten: false,
         }
@@ -828,6 +1202,7 @@ impl<K: Key, T: Data> KeyedWindowFun<K, T> for SlidingWindow<K, T> {
             typ: WindowType::Sliding {
                 width: self.width,
                 slide: self.slide,
+                offset: Duration::ZERO,
             },
             agg: None,
             flatten: false,
@@ -985,6 +1360,31 @@ impl<K: Key, T: Data> KeyedStream<K, T> {
         other: KeyedStream<K, T2>,
         window: W,
     ) -> KeyedStream<K, (Vec<T>, Vec<T2>)> {
+        let join_op = if let Operator::Window { typ, .. } = window.as_operator() {
+            Operator::WindowJoin { window: typ }
+        } else {
+            unreachable!()
+        };
+
+        self.add_join_node(other, join_op)
+    }
+
+    /// Joins each record with the first record of `other` for the same key whose timestamp is
+    /// within `window` after it; records without a match are emitted with `None` once the window
+    /// has passed
+    pub fn interval_join<T2: Data>(
+        &mut self,
+        other: KeyedStream<K, T2>,
+        window: Duration,
+    ) -> KeyedStream<K, (T, Option<T2>)> {
+        self.add_join_node(other, Operator::IntervalJoin { window })
+    }
+
+    fn add_join_node<T2: Data, Out: Data>(
+        &mut self,
+        other: KeyedStream<K, T2>,
+        join_op: Operator,
+    ) -> KeyedStream<K, Out> {
         let idx_map = if Rc::ptr_eq(&self.graph, &other.graph) {
             // TODO: handle the case that the indexes are potentially out of order
             assert!(self.last_node.unwrap().index() < other.last_node.unwrap().index());
@@ -996,12 +1396,6 @@ impl<K: Key, T: Data> KeyedStream<K, T> {
             ))
         };
 
-        let join_op = if let Operator::Window { typ, .. } = window.as_operator() {
-            Operator::WindowJoin { window: typ }
-        } else {
-            unreachable!()
-        };
-
         let join_node = StreamNode {
             operator_id: format!("node_{}", (*self.graph).borrow().node_count()),
             operator: join_op,
@@ -1045,7 +1439,9 @@ impl<K: Key, T: Data> KeyedStream<K, T> {
         Program {
             types: vec![],
             other_defs: vec![],
+            wasm_defs: vec![],
             graph: self.graph.take(),
+            instrumentation: None,
         }
     }
 }
@@ -1134,8 +1530,39 @@ impl<K: Key, T1: Data, T2: Data, F: Fn(&Option<K>, &T1) -> T2> BiFunc<K, T1, T2>
 pub struct Program {
     pub types: Vec<String>,
     pub other_defs: Vec<String>,
+    /// Definitions compiled into the pipeline's WebAssembly module rather than its binary, like
+    /// the bodies of SQL UDFs that are run in a sandbox
+    pub wasm_defs: Vec<String>,
     #[bincode(with_serde)]
     pub graph: DiGraph<StreamNode, StreamEdge>,
+    /// Debug instrumentation to compile into the pipeline, if any
+    pub instrumentation: Option<Instrumentation>,
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+pub struct Instrumentation {
+    /// The fraction of the records received by each operator that are logged
+    pub record_sample_rate: f64,
+    /// Whether each access to operator state is logged
+    pub trace_state: bool,
+}
+
+impl From<GrpcApi::Instrumentation> for Instrumentation {
+    fn from(value: GrpcApi::Instrumentation) -> Self {
+        Instrumentation {
+            record_sample_rate: value.record_sample_rate,
+            trace_state: value.trace_state,
+        }
+    }
+}
+
+impl From<Instrumentation> for GrpcApi::Instrumentation {
+    fn from(value: Instrumentation) -> Self {
+        GrpcApi::Instrumentation {
+            record_sample_rate: value.record_sample_rate,
+            trace_state: value.trace_state,
+        }
+    }
 }
 
 impl Program {
@@ -1143,7 +1570,9 @@ impl Program {
         Program {
             types: vec![],
             other_defs: vec![],
+            wasm_defs: vec![],
             graph: s.graph.take(),
+            instrumentation: None,
         }
     }
 
@@ -1187,6 +1616,88 @@ impl Program {
                 node.parallelism = *p;
             }
         }
+        self.enforce_single_parallelism();
+
+        // reorder buffers are connected to the operator they feed by a forward edge, so they
+        // must always be rescaled along with it
+        let buffers: Vec<_> = self
+            .graph
+            .node_indices()
+            .filter(|idx| self.graph[*idx].operator == Operator::ReorderBuffer)
+            .collect();
+        for idx in buffers {
+            if let Some(target) = self
+                .graph
+                .neighbors_directed(idx, Direction::Outgoing)
+                .next()
+            {
+                self.graph[idx].parallelism = self.graph[target].parallelism;
+            }
+        }
+    }
+
+    /// Global top-Ns and process functions see all of their input in a single subtask, as must
+    /// the operators that they forward records to
+    pub fn enforce_single_parallelism(&mut self) {
+        let mut nodes: Vec<_> = self
+            .graph
+            .node_indices()
+            .filter(|idx| {
+                matches!(
+                    self.graph[*idx].operator,
+                    Operator::TopN(TopN { global: true, .. }) | Operator::ProcessFunction(_)
+                )
+            })
+            .collect();
+
+        while let Some(idx) = nodes.pop() {
+            self.graph[idx].parallelism = 1;
+            nodes.extend(
+                self.graph
+                    .edges_directed(idx, Direction::Outgoing)
+                    .filter(|e| e.weight().typ == EdgeType::Forward)
+                    .map(|e| e.target()),
+            );
+        }
+    }
+
+    /// Inserts a reorder buffer after each shuffle, so that records for a key reach the
+    /// downstream operator in timestamp order even when they were produced by several upstream
+    /// subtasks. Shuffles route records by the hash of their key, and the buffers are keyed state
+    /// that is redistributed along with those key ranges on restore, so ordering is preserved
+    /// when parallelism is changed. Records without a key and the inputs of joins are not
+    /// reordered, nor are those of tenant limits, which come before the source's watermarks are
+    /// generated.
+    pub fn enforce_ordering(&mut self) {
+        let shuffles: Vec<_> = self
+            .graph
+            .edge_references()
+            .filter(|e| e.weight().typ == EdgeType::Shuffle)
+            .filter(|e| !matches!(self.graph[e.target()].operator, Operator::TenantLimit(_)))
+            .map(|e| (e.source(), e.target()))
+            .collect();
+
+        for (source, target) in shuffles {
+            let edge = self.graph.find_edge(source, target).unwrap();
+            let edge = self.graph.remove_edge(edge).unwrap();
+
+            let buffer = self.graph.add_node(StreamNode {
+                operator_id: format!("reorder_buffer_{}", self.graph.node_count()),
+                operator: Operator::ReorderBuffer,
+                parallelism: self.graph.node_weight(target).unwrap().parallelism,
+            });
+
+            self.graph.add_edge(
+                buffer,
+                target,
+                StreamEdge {
+                    key: edge.key.clone(),
+                    value: edge.value.clone(),
+                    typ: EdgeType::Forward,
+                },
+            );
+            self.graph.add_edge(source, buffer, edge);
+        }
     }
 
     pub fn task_count(&self) -> usize {
@@ -1234,6 +1745,14 @@ impl Program {
                     stop: Some(StopType::None as i32),
                     checkpoint_interval_micros: Some(checkpoint_interval_micros),
                     parallelism: None,
+                    env: None,
+                    source_overrides: vec![],
+                    slo: None,
+                    failure_policy: None,
+                    queue_config: None,
+                    stop_at_event_time_micros: None,
+                    recovery_throttle: None,
+                    state_storage: None,
                 }))
                 .await?;
             Ok(restore_from)
@@ -1254,6 +1773,9 @@ impl Program {
                     pipeline_id: res.into_inner().pipeline_id,
                     checkpoint_interval_micros,
                     preview: false,
+                    env: None,
+                    dependencies: vec![],
+                    stop_at_event_time_micros: None,
                 }))
                 .await?;
 
@@ -1320,8 +1842,16 @@ impl Program {
 
                     let strukt = parse_type(&replaced_type);
                     let config = &c.config;
-                    quote! {
-                        Box::new(#strukt::from_config(#config))
+                    match &c.router {
+                        Some(router) => {
+                            let router: syn::ExprClosure = parse_str(router).unwrap();
+                            quote! {
+                                Box::new(#strukt::from_config(#config).with_router(#router))
+                            }
+                        }
+                        None => quote! {
+                            Box::new(#strukt::from_config(#config))
+                        },
                     }
                 }
                 Operator::FusedWasmUDFs { name, udfs: _ } => {
@@ -1366,21 +1896,31 @@ impl Program {
                     };
 
                     match typ {
-                        WindowType::Tumbling { width } => {
+                        WindowType::Tumbling { width, offset } => {
                             let width = duration_to_syn_expr(*width);
+                            let offset = duration_to_syn_expr(*offset);
 
                             quote! {
                                 Box::new(KeyedWindowFunc::<#in_k, #in_t, #out_t, TumblingWindowAssigner>::
-                                    tumbling_window(#width, #agg))
+                                    tumbling_window(#width, #offset, #agg))
                             }
                         }
-                        WindowType::Sliding { width, slide } => {
+                        WindowType::Sliding { width, slide, offset } => {
                             let width = duration_to_syn_expr(*width);
                             let slide = duration_to_syn_expr(*slide);
+                            let offset = duration_to_syn_expr(*offset);
 
                             quote! {
                                 Box::new(KeyedWindowFunc::<#in_k, #in_t, #out_t, SlidingWindowAssigner>::
-                                    sliding_window(#width, #slide, #agg))
+                                    sliding_window(#width, #slide, #offset, #agg))
+                            }
+                        }
+                        WindowType::Calendar { unit, timezone } => {
+                            let unit = format_ident!("{:?}", unit);
+
+                            quote! {
+                                Box::new(KeyedWindowFunc::<#in_k, #in_t, #out_t, CalendarWindowAssigner>::
+                                    calendar_window(arroyo_types::CalendarUnit::#unit, #timezone, #agg))
                             }
                         }
                         WindowType::Instant => {
@@ -1395,24 +1935,26 @@ impl Program {
                     let in_k = parse_type(&input.unwrap().weight().key);
                     let in_t = parse_type(&input.unwrap().weight().value);
 
+                    // aligned generators report their watermarks to the controller
+                    let aligned = watermark.alignment().is_some();
                     match watermark {
-                        WatermarkType::FixedLateness { period, max_lateness } => {
+                        WatermarkType::FixedLateness { period, max_lateness, .. } => {
                             let period = duration_to_syn_expr(*period);
                             let max_lateness = duration_to_syn_expr(*max_lateness);
                             quote! {
                                 Box::new(
                                     PeriodicWatermarkGenerator::<#in_k, #in_t>::
-                                    fixed_lateness(#period,#max_lateness))
+                                    fixed_lateness(#period,#max_lateness).aligned(#aligned))
                             }
                         }
-                        WatermarkType::Expression { period, expression } => {
+                        WatermarkType::Expression { period, expression, .. } => {
                             let expr: syn::Expr = parse_str(expression).unwrap();
                             let watermark_function : syn::ExprClosure = parse_quote!(|record| {#expr});
                             let period = duration_to_syn_expr(*period);
                             quote! {
                                 Box::new(
                                     PeriodicWatermarkGenerator::<#in_k, #in_t>::
-                                    watermark_function(#period, Box::new(#watermark_function)))
+                                    watermark_function(#period, Box::new(#watermark_function)).aligned(#aligned))
                             }
                         }
                     }
@@ -1424,6 +1966,94 @@ impl Program {
                         Box::new(ToGlobalOperator::<#in_k, #in_t>::new())
                     }
                 }
+                Operator::ReorderBuffer => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    quote! {
+                        Box::new(arroyo_worker::operators::reorder_buffer::ReorderBuffer::<#in_k, #in_t>::new())
+                    }
+                }
+                Operator::TimestampBounds(TimestampBounds { max_future, max_age, clamp }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let optional = |d: &Option<Duration>| match d.map(duration_to_syn_expr) {
+                        Some(d) => quote!(Some(#d)),
+                        None => quote!(None),
+                    };
+                    let max_future = optional(max_future);
+                    let max_age = optional(max_age);
+                    quote! {
+                        Box::new(arroyo_worker::operators::timestamp_bounds::TimestampBounds::<#in_k, #in_t>::new(#max_future, #max_age, #clamp))
+                    }
+                }
+                Operator::LookupJoin(LookupJoin { location, refresh_interval, inner, row_type, row_key, merge }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let row_t = parse_type(row_type);
+                    let refresh_interval = duration_to_syn_expr(*refresh_interval);
+                    let row_key: syn::ExprClosure = parse_str(row_key).unwrap();
+                    let merge: syn::ExprClosure = parse_str(merge).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::lookup_join::LookupJoin::<#in_k, #in_t, #row_t, #out_t>::new(
+                            #location.to_string(), #refresh_interval, #inner, #row_key, #merge))
+                    }
+                }
+                Operator::TopN(TopN { max_elements, extractor, sort_key_type, input_updating, global }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let updating_out_t = parse_type(&output.unwrap().weight().value);
+                    let out_t = extract_container_type("UpdatingData", &updating_out_t).unwrap();
+                    let sk_t = parse_type(sort_key_type);
+                    let extractor: syn::ExprClosure = parse_str(extractor).unwrap();
+                    let updates = if *input_updating {
+                        quote!(|arg| arg.clone())
+                    } else {
+                        quote!(|arg| arroyo_types::UpdatingData::Append(arg.clone()))
+                    };
+                    let retain_all = *input_updating || *global;
+                    quote! {
+                        Box::new(arroyo_worker::operators::top_n::TopN::<#in_k, #in_t, #out_t, #sk_t>::new(
+                            #max_elements, #retain_all, #extractor, #updates))
+                    }
+                }
+                Operator::ProcessFunction(ProcessFunction { function, config, input: input_fn, output: output_fn }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let function = parse_type(&format!("udfs::{}", function));
+                    let input_fn: syn::ExprClosure = parse_str(input_fn).unwrap();
+                    let output_fn: syn::ExprClosure = parse_str(output_fn).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::process::ProcessOperator::<#in_k, #in_t, #out_t, #function>::new(
+                            #config, #input_fn, #output_fn))
+                    }
+                }
+                Operator::Assertion(Assertion { assertion, fail, predicate }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let predicate: syn::Expr = parse_str(predicate).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::assertion::Assertion::<#in_k, #in_t>::new(
+                            #assertion, #fail, |record| {#predicate}))
+                    }
+                }
+                Operator::TenantLimit(TenantLimit { max_rate, burst, max_queued, tenant }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let max_queued = match max_queued {
+                        Some(max_queued) => {
+                            let max_queued = *max_queued as usize;
+                            quote!(Some(#max_queued))
+                        }
+                        None => quote!(None),
+                    };
+                    let tenant: syn::ExprClosure = parse_str(tenant).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::tenant_limit::TenantLimit::<#in_k, #in_t>::new(
+                            #max_rate, #burst, #max_queued, #tenant))
+                    }
+                }
                 Operator::WindowJoin { window } => {
                     let mut inputs: Vec<_> = self.graph.edges_directed(idx, Direction::Incoming)
                         .collect();
@@ -1436,19 +2066,28 @@ impl Program {
                     let in_t2 = parse_type(&inputs[1].weight().value);
 
                     match window {
-                        WindowType::Tumbling { width } => {
+                        WindowType::Tumbling { width, offset } => {
                             let width = duration_to_syn_expr(*width);
+                            let offset = duration_to_syn_expr(*offset);
                             quote! {
                                 Box::new(WindowedHashJoin::<#in_k, #in_t1, #in_t2, TumblingWindowAssigner, TumblingWindowAssigner>::
-                                    tumbling_window(#width))
+                                    tumbling_window(#width, #offset))
                             }
                         }
-                        WindowType::Sliding { width, slide } => {
+                        WindowType::Sliding { width, slide, offset } => {
                             let width = duration_to_syn_expr(*width);
                             let slide = duration_to_syn_expr(*slide);
+                            let offset = duration_to_syn_expr(*offset);
                             quote! {
                                 Box::new(WindowedHashJoin::<#in_k, #in_t1, #in_t2, SlidingWindowAssigner, SlidingWindowAssigner>::
-                                    sliding_window(#width, #slide))
+                                    sliding_window(#width, #slide, #offset))
+                            }
+                        }
+                        WindowType::Calendar { unit, timezone } => {
+                            let unit = format_ident!("{:?}", unit);
+                            quote! {
+                                Box::new(WindowedHashJoin::<#in_k, #in_t1, #in_t2, CalendarWindowAssigner, CalendarWindowAssigner>::
+                                    calendar_window(arroyo_types::CalendarUnit::#unit, #timezone))
                             }
                         }
                         WindowType::Instant => {
@@ -1712,6 +2351,21 @@ impl Program {
                         },
                     }
                 },
+                Operator::IntervalJoin { window } => {
+                    let mut inputs: Vec<_> = self.graph.edges_directed(idx, Direction::Incoming)
+                        .collect();
+                    inputs.sort_by_key(|e| e.weight().typ.clone());
+                    assert_eq!(2, inputs.len(), "IntervalJoin should have 2 inputs, but has {}", inputs.len());
+                    assert_eq!(inputs[0].weight().key, inputs[1].weight().key, "IntervalJoin inputs must have the same key type");
+
+                    let in_k = parse_type(&inputs[0].weight().key);
+                    let in_t1 = parse_type(&inputs[0].weight().value);
+                    let in_t2 = parse_type(&inputs[1].weight().value);
+                    let window = duration_to_syn_expr(*window);
+                    quote! {
+                        Box::new(arroyo_worker::operators::interval_join::IntervalJoin::<#in_k, #in_t1, #in_t2>::new(#window))
+                    }
+                },
                 Operator::UpdatingOperator { name, expression } => {
                     let expr : syn::Expr = parse_str(expression).expect(expression);
                     let in_k = parse_type(&input.unwrap().weight().key);
@@ -1867,8 +2521,10 @@ impl TryFrom<Program> for PipelineProgram {
         Ok(PipelineProgram {
             types: program.types,
             other_defs: program.other_defs,
+            wasm_defs: program.wasm_defs,
             nodes,
             edges,
+            instrumentation: program.instrumentation.map(Into::into),
         })
     }
 }
@@ -1917,16 +2573,21 @@ impl From<Operator> for GrpcApi::operator::Operator {
             Operator::Watermark(WatermarkType::FixedLateness {
                 period,
                 max_lateness,
+                alignment,
             }) => GrpcOperator::PeriodicWatermark(GrpcApi::PeriodicWatermark {
                 period_micros: period.as_micros() as u64,
                 max_lateness_micros: max_lateness.as_micros() as u64,
+                alignment: alignment.map(|a| a.into()),
+            }),
+            Operator::Watermark(WatermarkType::Expression {
+                period,
+                expression,
+                alignment,
+            }) => GrpcOperator::ExpressionWatermark(GrpcApi::ExpressionWatermark {
+                period_micros: period.as_micros() as u64,
+                expression,
+                alignment: alignment.map(|a| a.into()),
             }),
-            Operator::Watermark(WatermarkType::Expression { period, expression }) => {
-                GrpcOperator::ExpressionWatermark(GrpcApi::ExpressionWatermark {
-                    period_micros: period.as_micros() as u64,
-                    expression,
-                })
-            }
             Operator::GlobalKey => todo!(),
             Operator::WindowJoin { window } => GrpcOperator::WindowJoin(GrpcApi::Window {
                 window: Some(window.into()),
@@ -2052,6 +2713,18 @@ impl From<Operator> for GrpcApi::operator::Operator {
             Operator::UpdatingKeyOperator { name, expression } => {
                 GrpcOperator::UpdatingKeyOperator(GrpcApi::UpdatingKeyOperator { name, expression })
             }
+            Operator::ReorderBuffer => GrpcOperator::ReorderBuffer(GrpcApi::ReorderBuffer {}),
+            Operator::TimestampBounds(bounds) => GrpcOperator::TimestampBounds(bounds.into()),
+            Operator::LookupJoin(lookup) => GrpcOperator::LookupJoin(lookup.into()),
+            Operator::TopN(top_n) => GrpcOperator::TopN(top_n.into()),
+            Operator::ProcessFunction(process) => GrpcOperator::ProcessFunction(process.into()),
+            Operator::Assertion(assertion) => GrpcOperator::Assertion(assertion.into()),
+            Operator::TenantLimit(limit) => GrpcOperator::TenantLimit(limit.into()),
+            Operator::IntervalJoin { window } => {
+                GrpcOperator::IntervalJoin(GrpcApi::IntervalJoin {
+                    window_micros: window.as_micros() as u64,
+                })
+            }
         }
     }
 }
@@ -2062,8 +2735,12 @@ impl From<SerializationMode> for GrpcApi::SerializationMode {
             SerializationMode::Json => GrpcApi::SerializationMode::Json,
             SerializationMode::JsonSchemaRegistry => GrpcApi::SerializationMode::JsonSchemaRegistry,
             SerializationMode::RawJson => GrpcApi::SerializationMode::Raw,
+            SerializationMode::RawBytes => GrpcApi::SerializationMode::RawBytes,
             SerializationMode::DebeziumJson => GrpcApi::SerializationMode::Json,
             SerializationMode::Parquet => GrpcApi::SerializationMode::Parquet,
+            SerializationMode::Avro => GrpcApi::SerializationMode::Avro,
+            SerializationMode::Protobuf => GrpcApi::SerializationMode::Protobuf,
+            SerializationMode::Csv => GrpcApi::SerializationMode::Csv,
         }
     }
 }
@@ -2110,15 +2787,30 @@ impl From<WasmBehavior> for i32 {
 impl From<WindowType> for GrpcApi::window::Window {
     fn from(window_type: WindowType) -> Self {
         match window_type {
-            WindowType::Tumbling { width } => {
+            WindowType::Tumbling { width, offset } => {
                 GrpcApi::window::Window::TumblingWindow(GrpcApi::TumblingWindow {
                     size_micros: width.as_micros() as u64,
+                    offset_micros: offset.as_micros() as u64,
                 })
             }
-            WindowType::Sliding { width, slide } => {
-                GrpcApi::window::Window::SlidingWindow(GrpcApi::SlidingWindow {
-                    size_micros: width.as_micros() as u64,
-                    slide_micros: slide.as_micros() as u64,
+            WindowType::Sliding {
+                width,
+                slide,
+                offset,
+            } => GrpcApi::window::Window::SlidingWindow(GrpcApi::SlidingWindow {
+                size_micros: width.as_micros() as u64,
+                slide_micros: slide.as_micros() as u64,
+                offset_micros: offset.as_micros() as u64,
+            }),
+            WindowType::Calendar { unit, timezone } => {
+                GrpcApi::window::Window::CalendarWindow(GrpcApi::CalendarWindow {
+                    unit: match unit {
+                        CalendarUnit::Day => GrpcApi::CalendarUnit::Day,
+                        CalendarUnit::Week => GrpcApi::CalendarUnit::Week,
+                        CalendarUnit::Month => GrpcApi::CalendarUnit::Month,
+                    }
+                    .into(),
+                    timezone,
                 })
             }
             WindowType::Instant => {
@@ -2135,6 +2827,7 @@ impl TryFrom<PipelineProgram> for Program {
         let mut graph = DiGraph::with_capacity(program.nodes.len(), program.edges.len());
         let types = program.types;
         let other_defs = program.other_defs;
+        let wasm_defs = program.wasm_defs;
         let mut nodes: Vec<_> = vec![];
         for node in program.nodes {
             let node_pair = (
@@ -2165,7 +2858,9 @@ impl TryFrom<PipelineProgram> for Program {
         Ok(Program {
             types,
             other_defs,
+            wasm_defs,
             graph,
+            instrumentation: program.instrumentation.map(Into::into),
         })
     }
 }
@@ -2223,6 +2918,7 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                     Operator::Watermark(WatermarkType::FixedLateness {
                         period: Duration::from_micros(watermark.period_micros),
                         max_lateness: Duration::from_micros(watermark.max_lateness_micros),
+                        alignment: watermark.alignment.map(|a| a.into()),
                     })
                 }
                 GrpcOperator::WindowJoin(window) => Operator::WindowJoin {
@@ -2333,9 +3029,11 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                 GrpcOperator::ExpressionWatermark(GrpcApi::ExpressionWatermark {
                     period_micros,
                     expression,
+                    alignment,
                 }) => Operator::Watermark(WatermarkType::Expression {
                     period: Duration::from_micros(period_micros),
                     expression,
+                    alignment: alignment.map(|a| a.into()),
                 }),
                 GrpcOperator::UpdatingOperator(GrpcApi::UpdatingOperator { name, expression }) => {
                     Operator::UpdatingOperator { name, expression }
@@ -2355,6 +3053,18 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                     name,
                     expression,
                 }) => Operator::UpdatingKeyOperator { name, expression },
+                GrpcOperator::ReorderBuffer(_) => Operator::ReorderBuffer,
+                GrpcOperator::TimestampBounds(bounds) => Operator::TimestampBounds(bounds.into()),
+                GrpcOperator::LookupJoin(lookup) => Operator::LookupJoin(lookup.into()),
+                GrpcOperator::TopN(top_n) => Operator::TopN(top_n.into()),
+                GrpcOperator::ProcessFunction(process) => Operator::ProcessFunction(process.into()),
+                GrpcOperator::Assertion(assertion) => Operator::Assertion(assertion.into()),
+                GrpcOperator::TenantLimit(limit) => Operator::TenantLimit(limit.into()),
+                GrpcOperator::IntervalJoin(GrpcApi::IntervalJoin { window_micros }) => {
+                    Operator::IntervalJoin {
+                        window: Duration::from_micros(window_micros),
+                    }
+                }
             },
             None => bail!("unset on operator {:?}", operator),
         };
@@ -2397,11 +3107,23 @@ impl From<arroyo_rpc::grpc::api::Window> for WindowType {
                 WindowType::Sliding {
                     width: Duration::from_micros(sliding_window.size_micros),
                     slide: Duration::from_micros(sliding_window.slide_micros),
+                    offset: Duration::from_micros(sliding_window.offset_micros),
                 }
             }
             Some(arroyo_rpc::grpc::api::window::Window::TumblingWindow(tumbling_window)) => {
                 WindowType::Tumbling {
                     width: Duration::from_micros(tumbling_window.size_micros),
+                    offset: Duration::from_micros(tumbling_window.offset_micros),
+                }
+            }
+            Some(arroyo_rpc::grpc::api::window::Window::CalendarWindow(calendar_window)) => {
+                WindowType::Calendar {
+                    unit: match calendar_window.unit() {
+                        arroyo_rpc::grpc::api::CalendarUnit::Day => CalendarUnit::Day,
+                        arroyo_rpc::grpc::api::CalendarUnit::Week => CalendarUnit::Week,
+                        arroyo_rpc::grpc::api::CalendarUnit::Month => CalendarUnit::Month,
+                    },
+                    timezone: calendar_window.timezone,
                 }
             }
             Some(arroyo_rpc::grpc::api::window::Window::InstantWindow(_)) => WindowType::Instant,
@@ -400,10 +400,9 @@ fn impl_stream_node_type(
                         *datum.downcast().expect(&format!("failed to downcast data in {}", self.name()))
                     }
                     crate::engine::QueueItem::Bytes(bs) => {
-                        ctx.counters
-                            .get("arroyo_worker_bytes_recv")
-                            .expect("bytes received")
-                            .inc_by(bs.len() as u64);
+                        if let Some(c) = &ctx.metrics.bytes_recv {
+                            c.inc_by(bs.len() as u64);
+                        }
 
                         bincode::decode_from_slice(&bs, config::standard())
                             .expect(#deserialize_error)
@@ -415,15 +414,33 @@ fn impl_stream_node_type(
                 tracing::debug!("[{}] Received message {}-{}, {:?} [{:?}]", ctx.task_info.operator_name, #i, local_idx, message, stacker::remaining_stack());
 
                 if let arroyo_types::Message::Record(record) = &message {
-                    ctx.counters
-                        .get("arroyo_worker_messages_recv")
-                        .expect("msg received")
-                        .inc();
-
-                    Self::#handle_fn(&mut (*self), record, &mut ctx)
-                      .instrument(tracing::trace_span!("handle_fn",
-                        name, operator_id=task_info.operator_id, subtask_idx=task_info.task_index))
-                      .await;
+                    if let Some(c) = &ctx.metrics.messages_recv {
+                        c.inc();
+                    }
+
+                    #[cfg(feature = "instrumentation")]
+                    crate::instrumentation::log_record(&ctx.task_info, #i, record);
+
+                    if ctx.skip_failing_records {
+                        let result = futures::FutureExt::catch_unwind(std::panic::AssertUnwindSafe(
+                            Self::#handle_fn(&mut (*self), record, &mut ctx)
+                              .instrument(tracing::trace_span!("handle_fn",
+                                name, operator_id=task_info.operator_id, subtask_idx=task_info.task_index))
+                        )).await;
+
+                        if let Err(e) = result {
+                            let panic = e.downcast_ref::<&str>().map(|s| s.to_string())
+                                .or_else(|| e.downcast_ref::<String>().cloned())
+                                .unwrap_or_else(|| "unknown panic".to_string());
+                            ctx.report_error("Skipped record that caused the operator to fail".to_string(),
+                                format!("{}\n\nrecord: {:?}", panic, record)).await;
+                        }
+                    } else {
+                        Self::#handle_fn(&mut (*self), record, &mut ctx)
+                          .instrument(tracing::trace_span!("handle_fn",
+                            name, operator_id=task_info.operator_id, subtask_idx=task_info.task_index))
+                          .await;
+                    }
                 } else {
                     match Self::handle_control_message(&mut (*self), idx, &message, &mut counter, &mut closed, in_partitions, &mut ctx).await {
                         crate::ControlOutcome::Continue => {
@@ -492,11 +509,20 @@ fn impl_stream_node_type(
 
             let mut blocked = vec![];
 
+            let mut ticks = self.tick_interval().map(|d| {
+                let mut interval = tokio::time::interval(d);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                interval
+            });
+
             loop {
                 tokio::select! {
                     Some(control_message) = ctx.control_rx.recv() => {
                         self.handle_raw_control_message(control_message, &mut ctx).await;
                     }
+                    _ = async { ticks.as_mut().unwrap().tick().await }, if ticks.is_some() => {
+                        self.handle_tick(&mut ctx).await;
+                    }
                     Some(((idx, item), s)) = sel.next() => {
                         match idx / (in_partitions / #handler_count) {
                             #(#handle_matchers
@@ -552,13 +578,22 @@ fn impl_stream_node_type(
                     tables,
                 ).await;
 
-                Self::on_start(&mut (*self), &mut ctx).await;
+                // UDFs and crypto functions called by the operator find its caches and keys
+                // through these task-locals
+                let udf_caches = ctx.udf_caches.clone();
+                let crypto_keys = crate::operators::functions::crypto::CryptoKeys::new(
+                    ctx.task_info.clone(), ctx.control_tx.clone());
+                crate::udf_cache::UDF_CACHES.scope(udf_caches,
+                    crate::operators::functions::crypto::CRYPTO_KEYS.scope(crypto_keys, async {
+                    Self::on_start(&mut (*self), &mut ctx).await;
+
+                    let task_info = ctx.task_info.clone();
+                    let name = self.name();
+                    #handle_body
 
-                let task_info = ctx.task_info.clone();
-                let name = self.name();
-                #handle_body
+                    Self::on_close(&mut (*self), &mut ctx).await;
+                })).await;
 
-                Self::on_close(&mut (*self), &mut ctx).await;
                 tracing::info!("Task finished {}-{}", ctx.task_info.operator_name, ctx.task_info.task_index);
 
                 ctx.control_tx
@@ -626,7 +661,7 @@ fn impl_stream_node_type(
 
                         trace!("received watermark {:?} in {}-{}", watermark, self.name(), ctx.task_info.task_index);
                         if let Some(watermark) = ctx.watermark() {
-                            ctx.state.handle_watermark(watermark);
+                            ctx.handle_watermark(watermark);
                             self.handle_watermark_int(watermark, ctx).await;
                         }
                     }
@@ -644,6 +679,9 @@ fn impl_stream_node_type(
                             return crate::ControlOutcome::Finish;
                         }
                     }
+                    Message::Probe(probe_id) => {
+                        ctx.handle_probe(*probe_id).await;
+                    }
                 }
                 crate::ControlOutcome::Continue
             }
@@ -670,6 +708,8 @@ fn impl_stream_node_type(
 
             crate::process_fn::ProcessFnUtils::send_event(checkpoint_barrier, ctx, arroyo_rpc::grpc::TaskCheckpointEventType::FinishedOperatorSetup).await;
 
+            ctx.checkpoint_udf_caches().await;
+
             let watermark = ctx.watermark();
             ctx.state.checkpoint(checkpoint_barrier, watermark).await;
 
@@ -744,6 +784,20 @@ fn impl_stream_node_type(
         });
     }
 
+    if !methods.contains("tick_interval") {
+        defs.push(quote! {
+            fn tick_interval(&self) -> Option<std::time::Duration> {
+                None
+            }
+        });
+    }
+
+    if !methods.contains("handle_tick") {
+        defs.push(quote! {
+            async fn handle_tick(&mut self, ctx: &mut crate::engine::Context<#out_k, #out_t>) {}
+        });
+    }
+
     if !methods.contains("handle_raw_control_message") {
         defs.push(quote! {
             async fn handle_raw_control_message(&mut self, control_message: arroyo_rpc::ControlMessage, ctx: &mut Context<#out_k, #out_t>) {
@@ -1,11 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 
 use arroyo_types::TaskInfo;
+use prometheus::core::Collector;
 use prometheus::{
     register_histogram, register_int_counter, register_int_gauge, Histogram, HistogramOpts,
     IntCounter, IntGauge, Opts,
 };
 
+// collectors registered for each subtask running in this worker, keyed by operator id and subtask
+// index, so that they can be removed once the subtask finishes
+static TASK_COLLECTORS: Mutex<BTreeMap<(String, usize), Vec<Box<dyn Collector>>>> =
+    Mutex::new(BTreeMap::new());
+
+fn track<C: Collector + Clone + 'static>(task_info: &TaskInfo, collector: C) -> C {
+    TASK_COLLECTORS
+        .lock()
+        .unwrap()
+        .entry((task_info.operator_id.clone(), task_info.task_index))
+        .or_default()
+        .push(Box::new(collector.clone()));
+    collector
+}
+
 pub fn counter_for_task(
     task_info: &TaskInfo,
     name: &'static str,
@@ -17,7 +34,9 @@ pub fn counter_for_task(
 
     opts.const_labels = labels;
 
-    register_int_counter!(opts).ok()
+    register_int_counter!(opts)
+        .ok()
+        .map(|c| track(task_info, c))
 }
 
 pub fn gauge_for_task(
@@ -31,7 +50,7 @@ pub fn gauge_for_task(
 
     opts.const_labels = labels;
 
-    register_int_gauge!(opts).ok()
+    register_int_gauge!(opts).ok().map(|g| track(task_info, g))
 }
 
 pub fn histogram_for_task(
@@ -46,5 +65,19 @@ pub fn histogram_for_task(
         .const_labels(labels)
         .buckets(buckets);
 
-    register_histogram!(opts).ok()
+    register_histogram!(opts).ok().map(|h| track(task_info, h))
+}
+
+/// Unregisters every metric registered for the subtask, so that a finished subtask stops being
+/// reported and its metrics can be registered again if it is restarted in this worker
+pub fn unregister_task_metrics(task_info: &TaskInfo) {
+    let collectors = TASK_COLLECTORS
+        .lock()
+        .unwrap()
+        .remove(&(task_info.operator_id.clone(), task_info.task_index))
+        .unwrap_or_default();
+
+    for collector in collectors {
+        let _ = prometheus::unregister(collector);
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    net::SocketAddr,
     path::{Path, PathBuf},
     str::FromStr,
     sync::{Arc, Mutex},
@@ -9,19 +10,22 @@ use std::{
 use anyhow::{anyhow, bail};
 use arroyo_rpc::grpc::{
     controller_grpc_client::ControllerGrpcClient, node_grpc_server::NodeGrpc,
-    node_grpc_server::NodeGrpcServer, start_worker_req, GetWorkersReq, GetWorkersResp,
-    HeartbeatNodeReq, RegisterNodeReq, StartWorkerReq, StartWorkerResp, StopWorkerReq,
-    StopWorkerResp, StopWorkerStatus, WorkerFinishedReq,
+    node_grpc_server::NodeGrpcServer, start_worker_req, DrainNodeReq, GetWorkersReq,
+    GetWorkersResp, HeartbeatNodeReq, RegisterNodeReq, StartWorkerReq, StartWorkerResp,
+    StopWorkerReq, StopWorkerResp, StopWorkerStatus, WorkerFinishedReq,
 };
+use arroyo_server_common::tls;
 use arroyo_types::{
-    grpc_port, ports, to_millis, NodeId, WorkerId, CONTROLLER_ADDR_ENV, JOB_ID_ENV, NODE_ID_ENV,
-    RUN_ID_ENV, TASK_SLOTS_ENV, WORKER_ID_ENV,
+    bind_addr, grpc_port, ports, to_millis, NodeId, WorkerId, CONTROLLER_ADDR_ENV, JOB_ID_ENV,
+    NODE_ID_ENV, RUN_ID_ENV, TASK_SLOTS_ENV, WORKER_ID_ENV,
 };
 use lazy_static::lazy_static;
 use prometheus::{register_gauge, Gauge};
 use rand::Rng;
 use std::os::unix::fs::PermissionsExt;
 use std::process::exit;
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::{
     broadcast,
     mpsc::{channel, Sender},
@@ -330,7 +334,7 @@ pub async fn main() {
         worker_finished_tx,
     };
 
-    let bind_addr = format!("0.0.0.0:{}", grpc);
+    let bind_addr = bind_addr("node", grpc);
     info!(
         "Starting node server on {} with {} slots",
         bind_addr, task_slots
@@ -339,10 +343,19 @@ pub async fn main() {
     let (stop_tx, mut stop_rx) = broadcast::channel(1);
 
     tokio::spawn(async move {
+        let listener = match TcpListener::bind(bind_addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Node server failed to bind: {:?}...exiting", e);
+                stop_tx.send(1).unwrap();
+                return;
+            }
+        };
+
         if let Err(e) = arroyo_server_common::grpc_server()
             .max_frame_size(Some((1 << 24) - 1)) // 16MB
             .add_service(NodeGrpcServer::new(server))
-            .serve(bind_addr.parse().unwrap())
+            .serve_with_incoming(tls::incoming(listener))
             .await
         {
             eprintln!("Node server failed: {:?}...exiting", e);
@@ -352,7 +365,8 @@ pub async fn main() {
 
     arroyo_server_common::start_admin_server("node", ports::NODE_ADMIN, stop_rx.resubscribe());
 
-    let req_addr = format!("{}:{}", local_ip_address::local_ip().unwrap(), grpc);
+    // formatting as a socket address brackets IPv6 addresses so that the controller can dial them
+    let req_addr = SocketAddr::new(arroyo_server_common::advertise_ip("node"), grpc).to_string();
 
     // TODO: replace this with some sort of hook on server startup
     tokio::time::sleep(Duration::from_secs(1)).await;
@@ -362,9 +376,18 @@ pub async fn main() {
         exit(code);
     }
 
+    // on SIGTERM the node is drained before exiting: the controller stops scheduling workers on it
+    // and moves the jobs running here to other nodes, so that the host can be taken down for
+    // maintenance without failing them
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    let mut draining = false;
+
     let mut attempts = 0;
     loop {
-        match ControllerGrpcClient::connect(controller_addr.clone()).await {
+        match tls::connect_addr(controller_addr.clone())
+            .await
+            .map(ControllerGrpcClient::new)
+        {
             Ok(mut controller) => {
                 controller
                     .register_node(Request::new(RegisterNodeReq {
@@ -389,6 +412,31 @@ pub async fn main() {
                         _ = stop_rx.recv() => {
                             return;
                         }
+                        _ = sigterm.recv(), if !draining => {
+                            info!("received SIGTERM; draining node");
+                            draining = true;
+                        }
+                    }
+
+                    if draining {
+                        match controller
+                            .drain_node(Request::new(DrainNodeReq { node_id: node_id.0 }))
+                            .await
+                        {
+                            Ok(resp) if resp.get_ref().job_ids.is_empty() => {
+                                info!("node drained; shutting down");
+                                return;
+                            }
+                            Ok(resp) => {
+                                info!(
+                                    "waiting for jobs to move off of node: {:?}",
+                                    resp.get_ref().job_ids
+                                );
+                            }
+                            Err(e) => {
+                                error!("failed to drain node: {:?}", e);
+                            }
+                        }
                     }
 
                     if let Err(e) = controller
@@ -0,0 +1,170 @@
+//! Merging of the column stats that sources and sinks compute over a sample of their records.
+//!
+//! Each subtask computes its own stats, which are merged on its worker and then across workers by
+//! the controller, so they're kept in a form that can be combined: counts, the smallest and
+//! largest values, and a k-minimum-values sketch of the hashes of the values, from which the
+//! number of distinct values is estimated.
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde_json::Value;
+
+use crate::grpc::{ColumnStats, OperatorColumnStats};
+
+/// The number of hashes kept in a distinct sketch
+pub const DISTINCT_SKETCH_SIZE: usize = 256;
+
+/// Orders JSON values of the same kind: numbers numerically, strings lexicographically and
+/// booleans with false first. Other values, or values of different kinds, aren't ordered.
+pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// The hash of a value, as it's kept in a distinct sketch; the hasher has fixed keys, so every
+/// worker hashes a value the same way
+pub fn value_hash(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Adds a hash to a sketch, which holds the smallest hashes seen in ascending order
+pub fn add_to_sketch(sketch: &mut Vec<u64>, hash: u64) {
+    if let Err(i) = sketch.binary_search(&hash) {
+        if i < DISTINCT_SKETCH_SIZE {
+            sketch.insert(i, hash);
+            sketch.truncate(DISTINCT_SKETCH_SIZE);
+        }
+    }
+}
+
+/// Estimates the number of distinct values hashed into a sketch. Until the sketch is full it
+/// holds every hash, so the count is exact.
+pub fn distinct_estimate(sketch: &[u64]) -> u64 {
+    match sketch.last() {
+        Some(&largest) if sketch.len() == DISTINCT_SKETCH_SIZE => {
+            ((DISTINCT_SKETCH_SIZE - 1) as f64 * (u64::MAX as f64 / largest.max(1) as f64)) as u64
+        }
+        _ => sketch.len() as u64,
+    }
+}
+
+/// Merges the stats of `other` into `stats`, matching operators by id
+pub fn merge_operators(stats: &mut Vec<OperatorColumnStats>, other: Vec<OperatorColumnStats>) {
+    for operator in other {
+        match stats
+            .iter_mut()
+            .find(|o| o.operator_id == operator.operator_id)
+        {
+            Some(existing) => merge_columns(&mut existing.columns, operator.columns),
+            None => stats.push(operator),
+        }
+    }
+}
+
+/// Merges the stats of `other` into `stats`, matching columns by name
+pub fn merge_columns(stats: &mut Vec<ColumnStats>, other: Vec<ColumnStats>) {
+    for column in other {
+        let Some(existing) = stats.iter_mut().find(|c| c.column == column.column) else {
+            stats.push(column);
+            continue;
+        };
+
+        existing.count += column.count;
+        existing.nulls += column.nulls;
+        existing.min = extreme(existing.min.take(), column.min, Ordering::Less);
+        existing.max = extreme(existing.max.take(), column.max, Ordering::Greater);
+        for hash in column.distinct_sketch {
+            add_to_sketch(&mut existing.distinct_sketch, hash);
+        }
+    }
+}
+
+// the value, of two given as JSON, that's ordered first by `keep`; if they can't be compared, the
+// first is kept
+fn extreme(a: Option<String>, b: Option<String>, keep: Ordering) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let ordering = serde_json::from_str::<Value>(&a)
+                .ok()
+                .zip(serde_json::from_str::<Value>(&b).ok())
+                .and_then(|(a, b)| compare_values(&b, &a));
+
+            Some(if ordering == Some(keep) { b } else { a })
+        }
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn column(name: &str, count: u64, nulls: u64, min: &str, max: &str) -> ColumnStats {
+        ColumnStats {
+            column: name.to_string(),
+            count,
+            nulls,
+            min: Some(min.to_string()),
+            max: Some(max.to_string()),
+            distinct_sketch: vec![],
+        }
+    }
+
+    #[test]
+    fn test_merge_columns() {
+        let mut stats = vec![column("price", 10, 1, "5", "20")];
+        merge_columns(
+            &mut stats,
+            vec![
+                column("price", 5, 5, "2.5", "8"),
+                column("name", 3, 0, "\"a\"", "\"c\""),
+            ],
+        );
+
+        assert_eq!(2, stats.len());
+        assert_eq!(15, stats[0].count);
+        assert_eq!(6, stats[0].nulls);
+        assert_eq!(Some("2.5"), stats[0].min.as_deref());
+        assert_eq!(Some("20"), stats[0].max.as_deref());
+        assert_eq!(Some("\"a\""), stats[1].min.as_deref());
+
+        // values of different kinds can't be compared, so the existing extremes are kept
+        merge_columns(&mut stats, vec![column("price", 1, 0, "\"0\"", "\"z\"")]);
+        assert_eq!(Some("2.5"), stats[0].min.as_deref());
+        assert_eq!(Some("20"), stats[0].max.as_deref());
+    }
+
+    #[test]
+    fn test_distinct_estimate() {
+        let mut sketch = vec![];
+        for i in 0..100 {
+            add_to_sketch(&mut sketch, value_hash(&Value::from(i % 50)));
+        }
+        assert_eq!(50, distinct_estimate(&sketch));
+
+        // sketches merge to the sketch of the combined values
+        let mut a = vec![];
+        let mut b = vec![];
+        for i in 0..100_000 {
+            add_to_sketch(
+                if i % 2 == 0 { &mut a } else { &mut b },
+                value_hash(&Value::from(i)),
+            );
+        }
+        for hash in b {
+            add_to_sketch(&mut a, hash);
+        }
+
+        let estimate = distinct_estimate(&a) as f64;
+        assert_eq!(DISTINCT_SKETCH_SIZE, a.len());
+        assert!((80_000.0..120_000.0).contains(&estimate), "{}", estimate);
+    }
+}
@@ -1,4 +1,7 @@
+pub mod column_stats;
 pub mod public_ids;
+pub mod proxy;
+pub mod tls;
 
 use std::{fs, time::SystemTime};
 
@@ -32,6 +35,11 @@ pub enum ControlMessage {
     Checkpoint(CheckpointBarrier),
     Stop { mode: StopMode },
     Commit { epoch: u32 },
+    // sent to sources in a watermark alignment group; paused sources stop reading new data but
+    // continue to handle other control messages
+    SetPaused { paused: bool },
+    // sent to sources to emit a watermark probe downstream
+    InjectProbe { probe_id: u64 },
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +77,27 @@ pub enum ControlResp {
         message: String,
         details: String,
     },
+    Watermark {
+        operator_id: String,
+        task_index: usize,
+        watermark: SystemTime,
+    },
+    /// Reported by sources and sinks when they connect to or lose their connection to the
+    /// external system
+    ConnectorHealth {
+        operator_id: String,
+        task_index: usize,
+        connected: bool,
+        error: Option<String>,
+    },
+    /// Reported by each subtask the first time it observes a watermark probe
+    ProbeObserved {
+        operator_id: String,
+        task_index: usize,
+        probe_id: u64,
+        time: SystemTime,
+        watermark: Option<SystemTime>,
+    },
 }
 
 pub struct FileAuthInterceptor {
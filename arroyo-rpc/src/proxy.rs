@@ -0,0 +1,248 @@
+//! HTTP proxy settings for connectors that make outbound HTTP, SSE, or WebSocket connections.
+//!
+//! The global proxy comes from the standard `HTTPS_PROXY`/`HTTP_PROXY` and `NO_PROXY` environment
+//! variables (which the object store clients also read); connectors may override it with a
+//! `proxy` object in their table config.
+
+use std::collections::HashMap;
+use std::env;
+use std::io;
+
+use arroyo_types::{check_egress, host_matches, HTTPS_PROXY_ENV, HTTP_PROXY_ENV, NO_PROXY_ENV};
+use hyper::client::HttpConnector;
+use hyper::Uri;
+use hyper_proxy::{Intercept, Proxy, ProxyConnector};
+use hyper_tls::HttpsConnector;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::tls::TlsConfig;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyConfig {
+    /// URL of the proxy, like `http://proxy.internal:3128`
+    pub url: Option<String>,
+    /// Comma-separated hosts that should be connected to directly; `.example.com` or
+    /// `example.com` match the domain and all of its subdomains, and `*` matches everything
+    pub no_proxy: Option<String>,
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+impl ProxyConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = env_var(HTTPS_PROXY_ENV).or_else(|| env_var(HTTP_PROXY_ENV))?;
+        Some(ProxyConfig {
+            url: Some(url),
+            no_proxy: env_var(NO_PROXY_ENV),
+        })
+    }
+
+    /// Combines a connector's proxy settings with the global ones; fields set on the connector
+    /// take precedence
+    pub fn resolve(connector: Option<Self>) -> Option<Self> {
+        let global = Self::from_env();
+
+        let config = match (connector, global) {
+            (Some(connector), Some(global)) => ProxyConfig {
+                url: connector.url.or(global.url),
+                no_proxy: connector.no_proxy.or(global.no_proxy),
+            },
+            (Some(config), None) | (None, Some(config)) => config,
+            (None, None) => return None,
+        };
+
+        config.url.is_some().then_some(config)
+    }
+
+    /// Converts from the type generated from a connector's json-schema, which has the same
+    /// serialized form
+    pub fn from_generated<T: Serialize>(config: &T) -> Self {
+        serde_json::from_value(serde_json::to_value(config).unwrap())
+            .expect("proxy config does not match the shared schema")
+    }
+
+    pub fn into_generated<T: DeserializeOwned>(self) -> T {
+        serde_json::from_value(serde_json::to_value(self).unwrap())
+            .expect("proxy config does not match the connector's schema")
+    }
+
+    /// Reads the `proxy.url` and `proxy.no_proxy` options of a CREATE TABLE statement
+    pub fn from_options(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        let config = ProxyConfig {
+            url: opts.remove("proxy.url"),
+            no_proxy: opts.remove("proxy.no_proxy"),
+        };
+
+        if config.url.is_none() && config.no_proxy.is_none() {
+            return Ok(None);
+        }
+
+        config.validate()?;
+        Ok(Some(config))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.url {
+            let uri: Uri = url
+                .parse()
+                .map_err(|e| format!("invalid proxy url '{}': {}", url, e))?;
+            if uri.scheme_str() != Some("http") || uri.host().is_none() {
+                return Err(format!(
+                    "invalid proxy url '{}': must be of the form http://host:port",
+                    url
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn uri(&self) -> Option<Uri> {
+        self.url.as_ref().and_then(|u| u.parse().ok())
+    }
+
+    /// Whether connections to `host` should skip the proxy, according to the no-proxy list
+    pub fn bypass(&self, host: &str) -> bool {
+        self.no_proxy
+            .as_ref()
+            .map(|no_proxy| host_matches(no_proxy, host))
+            .unwrap_or(false)
+    }
+
+    /// The proxy to use for `host`, if any
+    pub fn for_host(&self, host: &str) -> Option<Uri> {
+        if self.bypass(host) {
+            None
+        } else {
+            self.uri()
+        }
+    }
+}
+
+/// A hyper connector that goes through the proxy (for hosts that aren't excluded) and uses the
+/// given TLS settings for https urls
+pub fn https_connector(
+    proxy: Option<&ProxyConfig>,
+    tls: Option<&TlsConfig>,
+) -> Result<ProxyConnector<HttpsConnector<HttpConnector>>, String> {
+    let connector = match tls {
+        Some(tls) => tls.https_connector()?,
+        None => HttpsConnector::new(),
+    };
+
+    let mut proxy_connector =
+        ProxyConnector::new(connector).map_err(|e| format!("failed to configure TLS: {}", e))?;
+
+    if let Some(tls) = tls {
+        proxy_connector.set_tls(Some(tls.native_tls_connector()?));
+    }
+
+    if let Some(proxy) = proxy {
+        let uri = proxy
+            .uri()
+            .ok_or_else(|| "proxy url must be set".to_string())?;
+        let config = proxy.clone();
+        let intercept = Intercept::Custom(
+            (move |_: Option<&str>, host: Option<&str>, _: Option<u16>| {
+                host.map(|h| !config.bypass(h)).unwrap_or(true)
+            })
+            .into(),
+        );
+        proxy_connector.add_proxy(Proxy::new(intercept, uri));
+    }
+
+    Ok(proxy_connector)
+}
+
+/// Opens a TCP connection to the host and port of `url`, tunneling through the proxy with
+/// HTTP CONNECT unless the host is excluded. This is used for protocols like WebSocket that
+/// perform their own handshake over the returned stream.
+pub async fn tcp_connect(url: &str, proxy: Option<&ProxyConfig>) -> io::Result<TcpStream> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let host = uri
+        .host()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "url has no host"))?;
+    check_egress(host).map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, e))?;
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("https") | Some("wss") => 443,
+        _ => 80,
+    });
+
+    let Some(proxy) = proxy.and_then(|p| p.for_host(host)) else {
+        return TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port))
+            .await;
+    };
+
+    let proxy_host = proxy.host().unwrap();
+    let mut stream = TcpStream::connect((
+        proxy_host.trim_start_matches('[').trim_end_matches(']'),
+        proxy.port_u16().unwrap_or(80),
+    ))
+    .await?;
+
+    let target = format!("{}:{}", host, port);
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target).as_bytes())
+        .await?;
+
+    // read the response headers byte-by-byte so we don't consume any of the tunneled stream
+    let mut response = vec![];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "proxy response headers too large",
+            ));
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("proxy refused to connect to {}: {}", target, status_line),
+        ));
+    }
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod test {
+    use super::ProxyConfig;
+
+    #[test]
+    fn test_bypass() {
+        let config = ProxyConfig {
+            url: Some("http://proxy:3128".to_string()),
+            no_proxy: Some("localhost, .internal,example.com,10.0.0.1".to_string()),
+        };
+
+        assert!(config.bypass("localhost"));
+        assert!(config.bypass("kafka.internal"));
+        assert!(config.bypass("Example.com"));
+        assert!(config.bypass("api.example.com"));
+        assert!(config.bypass("10.0.0.1"));
+
+        assert!(!config.bypass("internal.net"));
+        assert!(!config.bypass("notexample.com"));
+        assert!(!config.bypass("10.0.0.10"));
+
+        assert!(ProxyConfig {
+            url: None,
+            no_proxy: Some("*".to_string())
+        }
+        .bypass("anything"));
+    }
+}
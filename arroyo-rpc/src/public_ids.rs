@@ -21,6 +21,9 @@ pub enum IdTypes {
     JobLogMessage,
     ConnectionTable,
     ConnectionTablePipeline,
+    MaskingPolicy,
+    ConnectionProfile,
+    JobRun,
 }
 
 pub fn generate_id(id_type: IdTypes) -> String {
@@ -36,6 +39,9 @@ pub fn generate_id(id_type: IdTypes) -> String {
         IdTypes::JobLogMessage => "jlm",
         IdTypes::ConnectionTable => "ct",
         IdTypes::ConnectionTablePipeline => "ctp",
+        IdTypes::MaskingPolicy => "mp",
+        IdTypes::ConnectionProfile => "cpr",
+        IdTypes::JobRun => "jr",
     };
     let id = nanoid!(ID_LENGTH, &ALPHABET);
     format!("{}_{}", prefix, id)
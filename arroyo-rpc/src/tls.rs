@@ -0,0 +1,287 @@
+//! TLS settings shared by all connectors that talk to external systems over the network.
+//!
+//! Connectors include a `tls` object in their connection (or table) json-schema with the fields
+//! of [`TlsConfig`]; the generated config type is converted with [`TlsConfig::from_generated`]
+//! and then turned into whatever the client library for that connector expects.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use native_tls::{Certificate, Identity, TlsConnector};
+use openssl::pkey::PKey;
+use openssl::ssl::{SslContext, SslContextBuilder, SslMethod, SslVerifyMode};
+use openssl::x509::X509;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tonic::transport::ClientTlsConfig;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    /// PEM-encoded CA certificates to trust, in addition to the system roots
+    pub ca_cert: Option<String>,
+    /// PEM-encoded client certificate, for servers that require mutual TLS
+    pub client_cert: Option<String>,
+    /// PEM-encoded (PKCS#8) private key for the client certificate
+    pub client_key: Option<String>,
+    /// Disables verification of the server's certificate and hostname
+    pub insecure_skip_verify: Option<bool>,
+}
+
+impl TlsConfig {
+    /// Converts from the type generated from a connector's json-schema, which has the same
+    /// serialized form
+    pub fn from_generated<T: Serialize>(config: &T) -> Self {
+        serde_json::from_value(serde_json::to_value(config).unwrap())
+            .expect("TLS config does not match the shared schema")
+    }
+
+    pub fn into_generated<T: DeserializeOwned>(self) -> T {
+        serde_json::from_value(serde_json::to_value(self).unwrap())
+            .expect("TLS config does not match the connector's schema")
+    }
+
+    /// Reads the `tls.*` options of a CREATE TABLE statement. Setting any of them (including just
+    /// `tls.enabled = 'true'`) enables TLS.
+    pub fn from_options(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        let enabled = match opts.remove("tls.enabled").as_deref() {
+            None => None,
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(other) => return Err(format!("invalid value for tls.enabled '{}'", other)),
+        };
+
+        let config = TlsConfig {
+            ca_cert: opts.remove("tls.ca_cert"),
+            client_cert: opts.remove("tls.client_cert"),
+            client_key: opts.remove("tls.client_key"),
+            insecure_skip_verify: match opts.remove("tls.insecure_skip_verify").as_deref() {
+                None => None,
+                Some("true") => Some(true),
+                Some("false") => Some(false),
+                Some(other) => {
+                    return Err(format!(
+                        "invalid value for tls.insecure_skip_verify '{}'",
+                        other
+                    ))
+                }
+            },
+        };
+
+        let configured = config.ca_cert.is_some()
+            || config.client_cert.is_some()
+            || config.client_key.is_some()
+            || config.insecure_skip_verify.is_some();
+
+        match enabled {
+            Some(false) if configured => {
+                Err("tls options were set but tls.enabled is 'false'".to_string())
+            }
+            Some(false) => Ok(None),
+            Some(true) => Ok(Some(config)),
+            None => Ok(configured.then_some(config)),
+        }
+    }
+
+    /// The inverse of [`TlsConfig::from_options`]
+    pub fn to_options(&self) -> HashMap<String, String> {
+        let mut opts = HashMap::new();
+        opts.insert("tls.enabled".to_string(), "true".to_string());
+        if let Some(ca) = &self.ca_cert {
+            opts.insert("tls.ca_cert".to_string(), ca.clone());
+        }
+        if let Some(cert) = &self.client_cert {
+            opts.insert("tls.client_cert".to_string(), cert.clone());
+        }
+        if let Some(key) = &self.client_key {
+            opts.insert("tls.client_key".to_string(), key.clone());
+        }
+        if let Some(insecure) = self.insecure_skip_verify {
+            opts.insert("tls.insecure_skip_verify".to_string(), insecure.to_string());
+        }
+        opts
+    }
+
+    pub fn insecure(&self) -> bool {
+        self.insecure_skip_verify.unwrap_or(false)
+    }
+
+    /// Checks that the certificates and key can be parsed, so that misconfigurations are
+    /// reported when the connection is created rather than when the pipeline runs
+    pub fn validate(&self) -> Result<(), String> {
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err("clientCert and clientKey must be set together".to_string());
+        }
+
+        self.native_tls_connector().map(|_| ())
+    }
+
+    fn identity(&self) -> Result<Option<Identity>, String> {
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Identity::from_pkcs8(cert.as_bytes(), key.as_bytes())
+                .map(Some)
+                .map_err(|e| format!("invalid client certificate or key: {}", e)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn native_tls_connector(&self) -> Result<TlsConnector, String> {
+        let mut builder = TlsConnector::builder();
+
+        if let Some(ca) = &self.ca_cert {
+            builder.add_root_certificate(
+                Certificate::from_pem(ca.as_bytes())
+                    .map_err(|e| format!("invalid CA certificate: {}", e))?,
+            );
+        }
+
+        if let Some(identity) = self.identity()? {
+            builder.identity(identity);
+        }
+
+        if self.insecure() {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+
+        builder
+            .build()
+            .map_err(|e| format!("failed to configure TLS: {}", e))
+    }
+
+    /// A hyper connector for HTTP clients (which will still accept plain http urls)
+    pub fn https_connector(&self) -> Result<HttpsConnector<HttpConnector>, String> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        Ok(HttpsConnector::from((
+            http,
+            self.native_tls_connector()?.into(),
+        )))
+    }
+
+    pub fn tonic_config(&self) -> Result<ClientTlsConfig, String> {
+        if self.insecure() {
+            return Err("insecureSkipVerify is not supported for gRPC connections".to_string());
+        }
+
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca) = &self.ca_cert {
+            config = config.ca_certificate(tonic::transport::Certificate::from_pem(ca));
+        }
+
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            config = config.identity(tonic::transport::Identity::from_pem(cert, key));
+        }
+
+        Ok(config)
+    }
+
+    /// An OpenSSL context, for clients built on it (like the Cassandra driver)
+    pub fn openssl_context(&self) -> Result<SslContext, String> {
+        let mut builder = SslContextBuilder::new(SslMethod::tls_client())
+            .map_err(|e| format!("failed to configure TLS: {}", e))?;
+        builder
+            .set_default_verify_paths()
+            .map_err(|e| format!("failed to load system CA certificates: {}", e))?;
+
+        if let Some(ca) = &self.ca_cert {
+            for cert in X509::stack_from_pem(ca.as_bytes())
+                .map_err(|e| format!("invalid CA certificate: {}", e))?
+            {
+                builder
+                    .cert_store_mut()
+                    .add_cert(cert)
+                    .map_err(|e| format!("invalid CA certificate: {}", e))?;
+            }
+        }
+
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            let cert = X509::from_pem(cert.as_bytes())
+                .map_err(|e| format!("invalid client certificate: {}", e))?;
+            let key = PKey::private_key_from_pem(key.as_bytes())
+                .map_err(|e| format!("invalid client key: {}", e))?;
+            builder
+                .set_certificate(&cert)
+                .and_then(|_| builder.set_private_key(&key))
+                .and_then(|_| builder.check_private_key())
+                .map_err(|e| format!("invalid client certificate or key: {}", e))?;
+        }
+
+        builder.set_verify(if self.insecure() {
+            SslVerifyMode::NONE
+        } else {
+            SslVerifyMode::PEER
+        });
+
+        Ok(builder.build())
+    }
+
+    /// librdkafka configuration; it accepts PEM contents directly
+    pub fn kafka_configs(&self) -> HashMap<String, String> {
+        let mut configs = HashMap::new();
+
+        if let Some(ca) = &self.ca_cert {
+            configs.insert("ssl.ca.pem".to_string(), ca.clone());
+        }
+
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            configs.insert("ssl.certificate.pem".to_string(), cert.clone());
+            configs.insert("ssl.key.pem".to_string(), key.clone());
+        }
+
+        if self.insecure() {
+            configs.insert(
+                "enable.ssl.certificate.verification".to_string(),
+                "false".to_string(),
+            );
+            configs.insert(
+                "ssl.endpoint.identification.algorithm".to_string(),
+                "none".to_string(),
+            );
+        }
+
+        configs
+    }
+
+    /// Some clients (like the MongoDB driver) can only read certificates from files; this writes
+    /// the CA and the combined client certificate and key to `dir`, returning their paths. Files
+    /// are named by the hash of their contents, so connections with different settings can share
+    /// a directory.
+    pub fn write_pem_files(
+        &self,
+        dir: &Path,
+    ) -> std::io::Result<(Option<PathBuf>, Option<PathBuf>)> {
+        fs::create_dir_all(dir)?;
+
+        let ca = match &self.ca_cert {
+            Some(ca) => {
+                let path = dir.join(format!("ca-{}.pem", hash(ca)));
+                fs::write(&path, ca)?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        let client = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let pem = format!("{}\n{}", cert.trim_end(), key);
+                let path = dir.join(format!("client-{}.pem", hash(&pem)));
+                fs::write(&path, pem)?;
+                Some(path)
+            }
+            _ => None,
+        };
+
+        Ok((ca, client))
+    }
+}
+
+fn hash(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
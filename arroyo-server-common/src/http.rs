@@ -0,0 +1,289 @@
+//! An HTTP client for calling external services, shared so that connectors and services behave
+//! the same way when an upstream is degraded. Failed requests (connection errors, timeouts, 5xx
+//! and 429 responses) are retried with jittered exponential backoff, and after repeated failures
+//! a circuit breaker fails requests immediately until the upstream has had time to recover.
+
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec};
+use rand::Rng;
+use reqwest::{IntoUrl, RequestBuilder, Response, StatusCode};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "arroyo_http_client_requests",
+        "requests made by shared HTTP clients, by outcome",
+        &["client", "outcome"]
+    )
+    .unwrap();
+    static ref HTTP_CIRCUIT_OPEN: IntGaugeVec = register_int_gauge_vec!(
+        "arroyo_http_client_circuit_open",
+        "whether the client's circuit breaker is open",
+        &["client"]
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retrying after the given (0-indexed) attempt: exponential, capped at
+    /// `max_backoff`, with up to half of it randomized so that clients don't retry in lockstep
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // a single trial request is allowed through to check if the upstream has recovered
+    HalfOpen,
+}
+
+/// Opens after `failure_threshold` consecutive failures, rejecting requests for `reset_timeout`
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        Self {
+            failure_threshold,
+            reset_timeout,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a request may be made now
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                *state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::Closed { failures: 0 };
+    }
+
+    /// Records a failure, returning true if it opened the circuit
+    pub fn record_failure(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::HalfOpen => self.failure_threshold,
+            BreakerState::Open { .. } => return false,
+        };
+
+        if failures >= self.failure_threshold {
+            *state = BreakerState::Open {
+                until: Instant::now() + self.reset_timeout,
+            };
+            true
+        } else {
+            *state = BreakerState::Closed { failures };
+            false
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), BreakerState::Closed { .. })
+    }
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// The request wasn't made because the upstream has been failing
+    CircuitOpen,
+    Request(reqwest::Error),
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::CircuitOpen => {
+                write!(f, "circuit breaker is open after repeated failures")
+            }
+            HttpError::Request(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+fn retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// A pooled HTTP client with retries and a circuit breaker. Clones share the connection pool and
+/// the breaker, so a client should be created once per upstream and cloned where it's needed.
+#[derive(Clone)]
+pub struct HttpClient {
+    name: Arc<str>,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl HttpClient {
+    /// Creates a client with the default policies; `name` labels its metrics and logs
+    pub fn new(name: &str) -> Self {
+        Self::with_client(
+            name,
+            reqwest::Client::builder()
+                .timeout(DEFAULT_TIMEOUT)
+                .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Creates a client around an existing reqwest client, e.g., one with custom TLS config
+    pub fn with_client(name: &str, client: reqwest::Client) -> Self {
+        Self {
+            name: name.into(),
+            client,
+            retry: RetryPolicy::default(),
+            breaker: Arc::new(CircuitBreaker::new(5, Duration::from_secs(30))),
+        }
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn circuit_breaker(mut self, failure_threshold: u32, reset_timeout: Duration) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new(failure_threshold, reset_timeout));
+        self
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    fn record(&self, outcome: &str) {
+        HTTP_REQUESTS
+            .with_label_values(&[&*self.name, outcome])
+            .inc();
+    }
+
+    fn record_failure(&self) {
+        if self.breaker.record_failure() {
+            warn!(
+                message = "Opening HTTP circuit breaker after repeated failures",
+                client = &*self.name
+            );
+            HTTP_CIRCUIT_OPEN.with_label_values(&[&*self.name]).set(1);
+        }
+    }
+
+    fn record_success(&self) {
+        if self.breaker.is_open() {
+            info!(
+                message = "Closing HTTP circuit breaker",
+                client = &*self.name
+            );
+            HTTP_CIRCUIT_OPEN.with_label_values(&[&*self.name]).set(0);
+        }
+        self.breaker.record_success();
+    }
+
+    /// Sends the request, retrying failures. Responses with non-retryable statuses (including
+    /// 4xx errors) are returned as-is, as is the last response if retries are exhausted. Requests
+    /// with streaming bodies can't be cloned, so they are only attempted once.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, HttpError> {
+        let mut attempt = 0;
+        let mut request = Some(request);
+
+        loop {
+            if !self.breaker.allow() {
+                self.record("circuit_open");
+                return Err(HttpError::CircuitOpen);
+            }
+
+            let current = request.take().unwrap();
+            request = current.try_clone();
+            let last_attempt = request.is_none() || attempt + 1 >= self.retry.max_attempts;
+
+            match current.send().await {
+                Ok(response) if !retryable_status(response.status()) => {
+                    self.record("success");
+                    self.record_success();
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    self.record("retryable_error");
+                    self.record_failure();
+                    if last_attempt {
+                        return Ok(response);
+                    }
+                    warn!(
+                        message = "Retrying HTTP request",
+                        client = &*self.name,
+                        status = response.status().as_u16(),
+                        attempt
+                    );
+                }
+                Err(e) => {
+                    self.record("error");
+                    self.record_failure();
+                    if last_attempt {
+                        return Err(HttpError::Request(e));
+                    }
+                    warn!(
+                        message = "Retrying HTTP request",
+                        client = &*self.name,
+                        error = format!("{}", e),
+                        attempt
+                    );
+                }
+            }
+
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
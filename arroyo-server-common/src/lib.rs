@@ -1,5 +1,8 @@
 #![allow(clippy::type_complexity)]
-use arroyo_types::{admin_port, telemetry_enabled, POSTHOG_KEY};
+use arroyo_types::{
+    admin_port, bind_addr, bind_ip, parse_ip, service_env, telemetry_enabled,
+    ADVERTISE_ADDRESS_ENV, POSTHOG_KEY,
+};
 use axum::body::Bytes;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -16,7 +19,8 @@ use pyroscope_pprofrs::{pprof_backend, PprofConfig};
 use reqwest::Client;
 use serde_json::{json, Value};
 use std::fs;
-use std::sync::Arc;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use tokio::select;
 use tokio::sync::broadcast::Receiver;
@@ -32,11 +36,15 @@ use tracing::metadata::LevelFilter;
 use tracing::{debug, info, span, warn, Level};
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::reload;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Registry;
 
 use tracing_appender::non_blocking::WorkerGuard;
 
+pub mod http;
+pub mod tls;
+
 pub const BUILD_TIMESTAMP: &str = env!("VERGEN_BUILD_TIMESTAMP");
 pub const GIT_SHA: &str = env!("VERGEN_GIT_SHA");
 pub const GIT_DESCRIBE: &str = env!("VERGEN_GIT_DESCRIBE");
@@ -46,16 +54,57 @@ const PYROSCOPE_SERVER_ADDRESS_ENV: &str = "PYROSCOPE_SERVER_ADDRESS";
 
 static CLUSTER_ID: OnceCell<String> = OnceCell::new();
 
+// replace the filters of the log layers installed by `init_logging`, for `set_log_filter`
+static LOG_FILTER_RELOADERS: Mutex<Vec<Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>>> =
+    Mutex::new(Vec::new());
+
+// the filter for a log layer: the directives from RUST_LOG followed by `directives`, which take
+// precedence over them
+fn log_filter(default: LevelFilter, directives: &str) -> EnvFilter {
+    let env = std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_default();
+    let directives: Vec<_> = [env.as_str(), directives]
+        .into_iter()
+        .filter(|d| !d.is_empty())
+        .collect();
+
+    EnvFilter::builder()
+        .with_default_directive(default.into())
+        .parse_lossy(directives.join(","))
+}
+
+/// Changes what this process logs without restarting it. `directives` use the `RUST_LOG` syntax
+/// (like `arroyo_worker::connectors::kafka=debug`) and are applied on top of the directives from
+/// `RUST_LOG`, replacing those set by previous calls; an empty string restores the original filter.
+pub fn set_log_filter(directives: &str) -> Result<(), String> {
+    // the filters are parsed leniently so that invalid directives in RUST_LOG are skipped as they
+    // are at startup, so validate the new directives on their own first
+    EnvFilter::builder()
+        .parse(directives)
+        .map_err(|e| format!("Invalid log filter '{}': {}", directives, e))?;
+
+    for reload in LOG_FILTER_RELOADERS.lock().unwrap().iter() {
+        reload(directives)?;
+    }
+
+    info!("Log filter directives set to '{}'", directives);
+    Ok(())
+}
+
 pub fn init_logging(name: &str) -> Option<WorkerGuard> {
+    let mut reloaders = LOG_FILTER_RELOADERS.lock().unwrap();
+
+    let (stdout_filter, stdout_reload) = reload::Layer::new(log_filter(LevelFilter::INFO, ""));
+    reloaders.push(Box::new(move |directives| {
+        stdout_reload
+            .reload(log_filter(LevelFilter::INFO, directives))
+            .map_err(|e| e.to_string())
+    }));
+
     let stdout_log = tracing_subscriber::fmt::layer()
         .with_line_number(false)
         .with_file(false)
         .with_span_events(FmtSpan::NONE)
-        .with_filter(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
-        );
+        .with_filter(stdout_filter);
 
     let subscriber = Registry::default().with(stdout_log);
 
@@ -70,16 +119,25 @@ pub fn init_logging(name: &str) -> Option<WorkerGuard> {
         let (non_blocking, g) = tracing_appender::non_blocking(file_appender);
         guard = Some(g);
 
+        let (json_filter, json_reload) = reload::Layer::new(log_filter(LevelFilter::ERROR, ""));
+        reloaders.push(Box::new(move |directives| {
+            json_reload
+                .reload(log_filter(LevelFilter::ERROR, directives))
+                .map_err(|e| e.to_string())
+        }));
+
         let json_log = tracing_subscriber::fmt::layer()
             .event_format(tracing_logfmt::EventsFormatter)
             .fmt_fields(tracing_logfmt::FieldsFormatter)
             .with_writer(non_blocking)
-            .with_filter(EnvFilter::from_default_env());
+            .with_filter(json_filter);
         Some(json_log)
     } else {
         None
     };
 
+    drop(reloaders);
+
     let subscriber = subscriber.with(json_log);
 
     tracing::subscriber::set_global_default(subscriber).expect("Unable to set global subscriber");
@@ -181,7 +239,9 @@ async fn details<'a>(State(state): State<Arc<AdminState>>) -> String {
 pub fn start_admin_server(service: &str, default_port: u16, mut shutdown: Receiver<i32>) {
     let port = admin_port(service, default_port);
 
-    info!("Starting {} admin server on 0.0.0.0:{}", service, port);
+    let addr = bind_addr(service, port);
+
+    info!("Starting {} admin server on {}", service, addr);
 
     let serve_dir = ServeDir::new("arroyo-console/dist")
         .not_found_service(ServeFile::new("arroyo-console/dist/index.html"));
@@ -199,8 +259,6 @@ pub fn start_admin_server(service: &str, default_port: u16, mut shutdown: Receiv
         .fallback_service(serve_dir)
         .with_state(state);
 
-    let addr = format!("0.0.0.0:{}", port).parse().unwrap();
-
     tokio::spawn(async move {
         select! {
             result = axum::Server::bind(&addr)
@@ -214,6 +272,27 @@ pub fn start_admin_server(service: &str, default_port: u16, mut shutdown: Receiv
     });
 }
 
+/// The IP address that a service advertises to its peers. This is taken from
+/// `{SERVICE}_ADVERTISE_ADDRESS` or `ADVERTISE_ADDRESS` if set; otherwise we use the
+/// address of the local interface, preferring IPv6 if the service is bound to an IPv6 address
+/// and falling back to the other family on hosts that only have one.
+pub fn advertise_ip(service: &str) -> IpAddr {
+    if let Some(addr) = service_env(service, ADVERTISE_ADDRESS_ENV) {
+        return parse_ip(&addr)
+            .unwrap_or_else(|| panic!("Invalid setting for {}", ADVERTISE_ADDRESS_ENV));
+    }
+
+    let (preferred, fallback) = if bind_ip(service).is_ipv6() {
+        (local_ip_address::local_ipv6(), local_ip_address::local_ip())
+    } else {
+        (local_ip_address::local_ip(), local_ip_address::local_ipv6())
+    };
+
+    preferred
+        .or(fallback)
+        .unwrap_or_else(|e| panic!("Failed to determine local IP address: {:?}", e))
+}
+
 #[cfg(not(target_os = "freebsd"))]
 pub fn try_profile_start(
     application_name: impl ToString,
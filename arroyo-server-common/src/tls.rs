@@ -0,0 +1,382 @@
+//! Mutual TLS for traffic within the cluster: the gRPC control plane between the API, controller,
+//! compiler, nodes and workers, and the data plane between workers.
+//!
+//! TLS is enabled by setting `CLUSTER_TLS_CERT_PATH`, `CLUSTER_TLS_KEY_PATH` and
+//! `CLUSTER_TLS_CA_PATH` to PEM files (for example, from a mounted Kubernetes secret). Every
+//! service presents its certificate both when accepting and when opening connections, and only
+//! talks to peers whose certificates are signed by the CA. Certificates are verified against the
+//! host that is being connected to, unless `CLUSTER_TLS_SERVER_NAME` is set, in which case all
+//! peers must present a certificate for that name.
+//!
+//! The files are checked for changes whenever a connection is opened or accepted, so rotated
+//! certificates are used for new connections without restarting services or the jobs running on
+//! them; connections that are already established are unaffected.
+
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use arroyo_types::{
+    CLUSTER_TLS_CA_PATH_ENV, CLUSTER_TLS_CERT_PATH_ENV, CLUSTER_TLS_KEY_PATH_ENV,
+    CLUSTER_TLS_SERVER_NAME_ENV,
+};
+use once_cell::sync::Lazy;
+use rustls_pemfile::Item;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{
+    Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector, TlsStream};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tonic::transport::{Channel, Endpoint, Uri};
+use tracing::{info, warn};
+
+static CLUSTER_TLS: Lazy<Option<ClusterTls>> = Lazy::new(ClusterTls::from_env);
+
+/// The cluster TLS configuration, if it's enabled
+pub fn cluster_tls() -> Option<&'static ClusterTls> {
+    CLUSTER_TLS.as_ref()
+}
+
+pub struct ClusterTls {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    ca_path: PathBuf,
+    server_name: Option<String>,
+    loaded: Mutex<Option<LoadedConfigs>>,
+}
+
+struct LoadedConfigs {
+    modified: Vec<SystemTime>,
+    server: Arc<ServerConfig>,
+    client: Arc<ClientConfig>,
+}
+
+impl ClusterTls {
+    fn from_env() -> Option<Self> {
+        let env = |var: &str| std::env::var(var).ok().filter(|s| !s.is_empty());
+
+        let paths = (
+            env(CLUSTER_TLS_CERT_PATH_ENV),
+            env(CLUSTER_TLS_KEY_PATH_ENV),
+            env(CLUSTER_TLS_CA_PATH_ENV),
+        );
+
+        let (cert_path, key_path, ca_path) = match paths {
+            (None, None, None) => return None,
+            (Some(cert), Some(key), Some(ca)) => (cert, key, ca),
+            _ => panic!(
+                "{}, {} and {} must be set together to enable cluster TLS",
+                CLUSTER_TLS_CERT_PATH_ENV, CLUSTER_TLS_KEY_PATH_ENV, CLUSTER_TLS_CA_PATH_ENV
+            ),
+        };
+
+        let tls = ClusterTls {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            ca_path: ca_path.into(),
+            server_name: env(CLUSTER_TLS_SERVER_NAME_ENV),
+            loaded: Mutex::new(None),
+        };
+
+        // fail on startup rather than on the first connection if the certificates are invalid
+        if let Err(e) = tls.configs() {
+            panic!("Failed to load cluster TLS certificates: {}", e);
+        }
+
+        info!(
+            "Cluster TLS enabled with certificate {}",
+            tls.cert_path.display()
+        );
+
+        Some(tls)
+    }
+
+    /// Returns the current server and client configs, reloading them if the files have changed
+    fn configs(&self) -> io::Result<(Arc<ServerConfig>, Arc<ClientConfig>)> {
+        let modified = [&self.cert_path, &self.key_path, &self.ca_path]
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()))
+            .collect::<io::Result<Vec<_>>>();
+
+        let mut loaded = self.loaded.lock().unwrap();
+        if let (Ok(modified), Some(current)) = (&modified, &*loaded) {
+            if *modified == current.modified {
+                return Ok((current.server.clone(), current.client.clone()));
+            }
+        }
+
+        match modified.and_then(|modified| self.load(modified)) {
+            Ok(configs) => {
+                if loaded.is_some() {
+                    info!(
+                        "Reloaded cluster TLS certificate {}",
+                        self.cert_path.display()
+                    );
+                }
+                let configs = loaded.insert(configs);
+                Ok((configs.server.clone(), configs.client.clone()))
+            }
+            // the files may be in the middle of being rotated, so keep using the previous ones
+            Err(e) => match &*loaded {
+                Some(current) => {
+                    warn!("Failed to reload cluster TLS certificates, continuing to use the previous ones: {}", e);
+                    Ok((current.server.clone(), current.client.clone()))
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn load(&self, modified: Vec<SystemTime>) -> io::Result<LoadedConfigs> {
+        let certs = read_certs(&self.cert_path)?;
+        let key = read_key(&self.key_path)?;
+
+        let mut roots = RootCertStore::empty();
+        for ca in read_certs(&self.ca_path)? {
+            roots.add(&ca).map_err(|e| {
+                invalid_data(format!(
+                    "invalid CA certificate in {}: {}",
+                    self.ca_path.display(),
+                    e
+                ))
+            })?;
+        }
+
+        let server = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(Arc::new(AllowAnyAuthenticatedClient::new(roots.clone())))
+            .with_single_cert(certs.clone(), key.clone())
+            .map_err(invalid_data)?;
+
+        let client = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(certs, key)
+            .map_err(invalid_data)?;
+
+        Ok(LoadedConfigs {
+            modified,
+            server: Arc::new(server),
+            client: Arc::new(client),
+        })
+    }
+
+    fn server_name(&self, host: &str) -> io::Result<ServerName> {
+        let name = self.server_name.as_deref().unwrap_or(host);
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        ServerName::try_from(name)
+            .map_err(|_| invalid_data(format!("invalid TLS server name '{}'", name)))
+    }
+
+    async fn accept(&self, stream: TcpStream) -> io::Result<ClusterStream> {
+        let (server, _) = self.configs()?;
+        let stream = TlsAcceptor::from(server).accept(stream).await?;
+        Ok(ClusterStream::Tls(Box::new(stream.into())))
+    }
+
+    async fn connect(&self, host: &str, stream: TcpStream) -> io::Result<ClusterStream> {
+        let (_, client) = self.configs()?;
+        let stream = TlsConnector::from(client)
+            .connect(self.server_name(host)?, stream)
+            .await?;
+        Ok(ClusterStream::Tls(Box::new(stream.into())))
+    }
+}
+
+fn invalid_data(e: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn read_certs(path: &Path) -> io::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &Path) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        if let Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) = item {
+            return Ok(PrivateKey(key));
+        }
+    }
+
+    Err(invalid_data(format!(
+        "no private key found in {}",
+        path.display()
+    )))
+}
+
+/// A connection within the cluster, which is encrypted if cluster TLS is enabled
+pub enum ClusterStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl ClusterStream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            ClusterStream::Plain(stream) => stream,
+            ClusterStream::Tls(stream) => stream.get_ref().0,
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().local_addr()
+    }
+}
+
+impl AsyncRead for ClusterStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClusterStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            ClusterStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ClusterStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ClusterStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            ClusterStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ClusterStream::Plain(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            ClusterStream::Tls(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            ClusterStream::Plain(stream) => stream.is_write_vectored(),
+            ClusterStream::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClusterStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            ClusterStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClusterStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            ClusterStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+impl Connected for ClusterStream {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.tcp().connect_info()
+    }
+}
+
+/// Performs the server side of the TLS handshake on an accepted connection, if TLS is enabled
+pub async fn accept(stream: TcpStream) -> io::Result<ClusterStream> {
+    match cluster_tls() {
+        Some(tls) => tls.accept(stream).await,
+        None => Ok(ClusterStream::Plain(stream)),
+    }
+}
+
+/// Opens a connection to `addr` (a `host:port` pair)
+pub async fn connect(addr: &str) -> io::Result<ClusterStream> {
+    let stream = TcpStream::connect(addr).await?;
+    match cluster_tls() {
+        Some(tls) => {
+            let host = addr.rsplit_once(':').map(|(host, _)| host).unwrap_or(addr);
+            tls.connect(host, stream).await
+        }
+        None => Ok(ClusterStream::Plain(stream)),
+    }
+}
+
+/// Accepts connections on `listener`, for use with tonic's `serve_with_incoming`. Handshakes are
+/// performed off of the accept loop, so that a slow or misbehaving peer can't block others, and
+/// connections that fail them are dropped rather than taking down the server.
+pub fn incoming(listener: TcpListener) -> ReceiverStream<io::Result<ClusterStream>> {
+    let (tx, rx) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        while !tx.is_closed() {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match accept(stream).await {
+                    Ok(stream) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Err(e) => warn!("Failed to accept connection: {}", e),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Connects a gRPC channel to an endpoint within the cluster
+pub async fn connect_channel(endpoint: Endpoint) -> Result<Channel, tonic::transport::Error> {
+    let Some(tls) = cluster_tls() else {
+        return endpoint.connect().await;
+    };
+
+    endpoint
+        .connect_with_connector(tower::service_fn(move |uri: Uri| async move {
+            let host = uri.host().unwrap_or_default().to_string();
+            let port = uri.port_u16().unwrap_or(80);
+            let stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+            tls.connect(&host, stream).await
+        }))
+        .await
+}
+
+/// Connects a gRPC channel to `addr` (e.g., `http://host:port`)
+pub async fn connect_addr(addr: impl Into<String>) -> Result<Channel, tonic::transport::Error> {
+    connect_channel(Endpoint::from_shared(addr.into())?).await
+}
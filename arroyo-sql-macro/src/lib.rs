@@ -1,8 +1,15 @@
+use std::fmt::Write;
+
 use arroyo_connectors::nexmark::{NexmarkConnector, NexmarkTable};
 use arroyo_connectors::{Connector, EmptyConfig};
+use arroyo_datastream::{ExpressionReturnType, Operator, Program, WindowAgg};
 use arroyo_sql::{
-    get_test_expression, parse_and_get_program_sync, ArroyoSchemaProvider, SqlConfig,
+    get_test_expression, get_test_projection, parse_and_get_program_sync, test_schema_provider,
+    test_struct_def, ArroyoSchemaProvider, SqlConfig,
 };
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse::{Parse, ParseStream};
@@ -58,6 +65,49 @@ pub fn single_test_codegen(input: proc_macro::TokenStream) -> proc_macro::TokenS
     tokens.into()
 }
 
+/// This macro is used to compile a projection over `TestStruct`s both with and without the
+/// expression optimizations, for benchmarking the code that each generates.
+/// Used in the `arroyo-sql-testing` crate.
+///
+/// # Arguments
+///
+/// * `name` - The name of the module to generate.
+/// * `projection` - The fields of the projection, as they would be written in a SELECT.
+///
+/// # Returns
+///
+/// A module named `name`, containing `optimized` and `unoptimized` functions that each map a
+/// `TestStruct` to a tuple of the projection's fields.
+///
+/// # Example
+///
+/// ```
+/// use arroyo_sql_testing::projection_codegen;
+///
+/// projection_codegen!(
+///    "squares",
+///   "nullable_i64 * nullable_i64, nullable_i64 * nullable_i64 + 1"
+/// );
+/// ```
+#[proc_macro]
+pub fn projection_codegen(input: TokenStream) -> TokenStream {
+    let case = parse_macro_input!(input as PipelineCase);
+    let projection = case.query.value();
+
+    let mod_name: syn::Ident = parse_str(&case.test_name.value()).unwrap();
+    let optimized = get_test_projection("optimized", &projection, true);
+    let unoptimized = get_test_projection("unoptimized", &projection, false);
+
+    quote!(
+    #[allow(clippy::all)]
+    pub mod #mod_name {
+        #optimized
+
+        #unoptimized
+    })
+    .into()
+}
+
 struct TestCase {
     test_name: LitStr,
     calculation_string: LitStr,
@@ -95,7 +145,8 @@ impl Parse for TestCase {
 /// * `query` - The query to compile. Assumes a nexmark source named "nexmark" is available.
 /// # Returns
 ///
-/// A module named `test_name`, containing the generated code.
+/// A module named `test_name`, containing the generated code and a test that checks the
+/// program's plan against its golden file (see [`operator_run_codegen`]).
 ///
 /// # Example
 ///
@@ -138,6 +189,9 @@ pub fn full_pipeline_codegen(input: TokenStream) -> TokenStream {
 
     let mod_name: syn::Ident = parse_str(test_name).unwrap();
 
+    let plan_path = format!("plans/{}.txt", test_name);
+    let plan = render_plan(&program);
+
     let function = program.make_graph_function();
     let other_defs: Vec<proc_macro2::TokenStream> = program
         .other_defs
@@ -163,10 +217,394 @@ pub fn full_pipeline_codegen(input: TokenStream) -> TokenStream {
         #function
 
         #(#other_defs)*
+
+        #[test]
+        fn golden_plan() {
+            crate::golden::check_golden(#plan_path, #plan);
+        }
     })
     .into()
 }
 
+/// This macro is used to run the stateless operators of a query over canned inputs.
+/// The query's plan and its outputs are checked against golden files, so that changes
+/// to the generated code and its behavior show up as diffs.
+/// Used in the `arroyo-sql-testing` crate.
+///
+/// # Arguments
+///
+/// * `test_name` - The name of the test.
+/// * `query` - The query to run. Assumes a source named "test_source" whose rows are
+///   `TestStruct`s, and that every operator between it and the sink is a filter or map.
+///
+/// # Returns
+///
+/// A module named `test_name`, containing a `run` function that applies the query's
+/// operators to a list of inputs, and tests that compare the plan to `golden/plans/<test_name>.txt`
+/// and the outputs for `golden/inputs/test_struct.json` to `golden/outputs/<test_name>.json`.
+///
+/// # Example
+///
+/// ```
+/// use arroyo_sql_testing::operator_run_codegen;
+///
+/// operator_run_codegen!{
+///    "filter_nulls",
+///   "SELECT nullable_i32 + 1 FROM test_source WHERE nullable_i32 IS NOT NULL",
+/// }
+/// ```
+#[proc_macro]
+pub fn operator_run_codegen(input: TokenStream) -> TokenStream {
+    let pipeline_case = parse_macro_input!(input as PipelineCase);
+
+    let test_name = &pipeline_case.test_name.value();
+    let query_string = &pipeline_case.query;
+
+    let (program, _) = parse_and_get_program_sync(
+        query_string.value(),
+        test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .unwrap();
+
+    let mod_name: syn::Ident = parse_str(test_name).unwrap();
+
+    let plan_path = format!("plans/{}.txt", test_name);
+    let plan = render_plan(&program);
+
+    let (source_key, steps, last) = record_transforms(&program, source(&program));
+    assert!(
+        matches!(program.graph[last].operator, Operator::ConnectorSink(_)),
+        "operator runs only support filters and maps, but the plan contains {:?}",
+        program.graph[last]
+    );
+    let test_struct: proc_macro2::TokenStream = parse_str(&test_struct_def().def(false)).unwrap();
+    let other_defs: Vec<proc_macro2::TokenStream> = program
+        .other_defs
+        .iter()
+        .map(|t| parse_str(t).unwrap())
+        .collect();
+
+    quote!(
+    #[allow(dead_code, unused_imports, unused_variables)]
+    mod #mod_name {
+        use arroyo_sql::types;
+        use types::*;
+        use chrono;
+        use std::time::SystemTime;
+        use std::str::FromStr;
+        use serde::{Deserialize, Serialize};
+
+        #test_struct
+
+        #(#other_defs)*
+
+        pub fn run(inputs: Vec<TestStruct>) -> Vec<serde_json::Value> {
+            let task_info = arroyo_types::TaskInfo::for_test("test-job", "test-operator");
+            inputs
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, value)| {
+                    let record: arroyo_types::Record<#source_key, TestStruct> = arroyo_types::Record {
+                        timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(i as u64),
+                        key: None,
+                        value,
+                    };
+                    #(#steps)*
+                    Some(serde_json::to_value(&record.value).unwrap())
+                })
+                .collect()
+        }
+
+        #[test]
+        fn golden_plan() {
+            crate::golden::check_golden(#plan_path, #plan);
+        }
+
+        #[test]
+        fn golden_outputs() {
+            crate::golden::check_outputs(#test_name, run);
+        }
+    })
+    .into()
+}
+
+/// This macro is used to run a query's window function over canned inputs, along with the
+/// stateless operators before and after it. All of the inputs are treated as falling in the same
+/// window, so the outputs are those of the window function over each partition of them.
+/// Used in the `arroyo-sql-testing` crate.
+///
+/// # Arguments
+///
+/// * `test_name` - The name of the test.
+/// * `query` - The query to run. Assumes a source named "test_source" whose rows are
+///   `TestStruct`s, and that every operator between it and the sink other than the window
+///   function is a filter or map.
+///
+/// # Returns
+///
+/// A module named `test_name`, containing a `run` function that applies the query's
+/// operators to a list of inputs, returning the outputs of each partition in the order that the
+/// partitions first appear in the inputs, and tests that compare the plan to
+/// `golden/plans/<test_name>.txt` and the outputs for `golden/inputs/test_struct.json` to
+/// `golden/outputs/<test_name>.json`.
+///
+/// # Example
+///
+/// ```
+/// use arroyo_sql_testing::window_function_run_codegen;
+///
+/// window_function_run_codegen!{
+///    "row_number",
+///   "SELECT non_nullable_i32, ROW_NUMBER() OVER (
+///     PARTITION BY tumble(interval '1 hour') ORDER BY non_nullable_i32) AS row_num
+///   FROM test_source"
+/// }
+/// ```
+#[proc_macro]
+pub fn window_function_run_codegen(input: TokenStream) -> TokenStream {
+    let pipeline_case = parse_macro_input!(input as PipelineCase);
+
+    let test_name = &pipeline_case.test_name.value();
+    let query_string = &pipeline_case.query;
+
+    let (program, _) = parse_and_get_program_sync(
+        query_string.value(),
+        test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .unwrap();
+    let graph = &program.graph;
+
+    let mod_name: syn::Ident = parse_str(test_name).unwrap();
+
+    let plan_path = format!("plans/{}.txt", test_name);
+    let plan = render_plan(&program);
+
+    let (source_key, before, window) = record_transforms(&program, source(&program));
+    let Operator::Window {
+        agg: Some(WindowAgg::Expression { expression, .. }),
+        flatten: true,
+        ..
+    } = &graph[window].operator
+    else {
+        panic!(
+            "window function runs require a window function, but the plan contains {:?}",
+            graph[window]
+        );
+    };
+    let window_function: syn::Expr = parse_str(expression).expect(expression);
+
+    let in_edge = graph
+        .edges_directed(window, Direction::Incoming)
+        .next()
+        .unwrap();
+    let window_k: syn::Type = parse_str(&in_edge.weight().key).unwrap();
+    let window_t: syn::Type = parse_str(&in_edge.weight().value).unwrap();
+    let out_edge = graph
+        .edges_directed(window, Direction::Outgoing)
+        .next()
+        .unwrap();
+    let out_t: syn::Type = parse_str(&out_edge.weight().value).unwrap();
+
+    let (out_k, after, sink) = record_transforms(&program, window);
+    assert!(
+        matches!(graph[sink].operator, Operator::ConnectorSink(_)),
+        "window function runs only support a single window, but the plan contains {:?}",
+        graph[sink]
+    );
+
+    let test_struct: proc_macro2::TokenStream = parse_str(&test_struct_def().def(false)).unwrap();
+    let other_defs: Vec<proc_macro2::TokenStream> = program
+        .other_defs
+        .iter()
+        .map(|t| parse_str(t).unwrap())
+        .collect();
+
+    quote!(
+    #[allow(dead_code, unused_imports, unused_mut, unused_variables)]
+    mod #mod_name {
+        use arroyo_sql::types;
+        use types::*;
+        use chrono;
+        use std::time::SystemTime;
+        use std::str::FromStr;
+        use serde::{Deserialize, Serialize};
+
+        #test_struct
+
+        #(#other_defs)*
+
+        pub fn run(inputs: Vec<TestStruct>) -> Vec<serde_json::Value> {
+            let task_info = arroyo_types::TaskInfo::for_test("test-job", "test-operator");
+
+            let mut partitions: Vec<(Option<#window_k>, Vec<#window_t>)> = vec![];
+            for (i, value) in inputs.into_iter().enumerate() {
+                let record = (|| {
+                    let record: arroyo_types::Record<#source_key, TestStruct> = arroyo_types::Record {
+                        timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(i as u64),
+                        key: None,
+                        value,
+                    };
+                    #(#before)*
+                    Some(record)
+                })();
+                let Some(record) = record else {
+                    continue;
+                };
+
+                match partitions.iter_mut().find(|(key, _)| *key == record.key) {
+                    Some((_, values)) => values.push(record.value),
+                    None => partitions.push((record.key, vec![record.value])),
+                }
+            }
+
+            let mut outputs = vec![];
+            for (_, values) in partitions {
+                let results = (|mut arg: Vec<#window_t>| -> Vec<#out_t> { #window_function })(values);
+                for value in results {
+                    let output = (|| {
+                        let record: arroyo_types::Record<#out_k, #out_t> = arroyo_types::Record {
+                            timestamp: std::time::UNIX_EPOCH,
+                            key: None,
+                            value,
+                        };
+                        #(#after)*
+                        Some(serde_json::to_value(&record.value).unwrap())
+                    })();
+                    outputs.extend(output);
+                }
+            }
+            outputs
+        }
+
+        #[test]
+        fn golden_plan() {
+            crate::golden::check_golden(#plan_path, #plan);
+        }
+
+        #[test]
+        fn golden_outputs() {
+            crate::golden::check_outputs(#test_name, run);
+        }
+    })
+    .into()
+}
+
+fn source(program: &Program) -> NodeIndex {
+    let mut sources = program.graph.externals(Direction::Incoming);
+    let idx = sources.next().expect("query has no source");
+    assert!(
+        sources.next().is_none(),
+        "operator runs only support queries with a single source"
+    );
+    idx
+}
+
+/// Compiles the operators after `idx`, up to the program's sink or its first window, into
+/// statements that transform a `record` in a closure returning an `Option`. Returns the key type
+/// of the records they take, along with the sink or window that they stop at.
+fn record_transforms(
+    program: &Program,
+    mut idx: NodeIndex,
+) -> (syn::Type, Vec<proc_macro2::TokenStream>, NodeIndex) {
+    let graph = &program.graph;
+
+    let mut in_key = None;
+    let mut steps = vec![];
+    loop {
+        let mut edges = graph.edges_directed(idx, Direction::Outgoing);
+        let edge = edges.next().expect("query has no sink");
+        assert!(
+            edges.next().is_none(),
+            "operator runs only support queries without fan-out"
+        );
+
+        let in_k: syn::Type = parse_str(&edge.weight().key).unwrap();
+        let in_t: syn::Type = parse_str(&edge.weight().value).unwrap();
+        in_key.get_or_insert_with(|| in_k.clone());
+        idx = edge.target();
+
+        match &graph[idx].operator {
+            Operator::ExpressionOperator {
+                expression,
+                return_type,
+                ..
+            } => {
+                let expr: syn::Expr = parse_str(expression).expect(expression);
+                let func = quote!(
+                    |record: &arroyo_types::Record<#in_k, #in_t>, _: &arroyo_types::TaskInfo| {#expr}
+                );
+                steps.push(match return_type {
+                    ExpressionReturnType::Predicate => quote! {
+                        if !(#func)(&record, &task_info) {
+                            return None;
+                        }
+                    },
+                    ExpressionReturnType::Record => quote! {
+                        let record = (#func)(&record, &task_info);
+                    },
+                    ExpressionReturnType::OptionalRecord => quote! {
+                        let record = (#func)(&record, &task_info)?;
+                    },
+                });
+            }
+            // watermarks are irrelevant to stateless operators
+            Operator::Watermark(_) => {}
+            Operator::ConnectorSink(_) | Operator::Window { .. } => {
+                return (in_key.unwrap(), steps, idx)
+            }
+            _ => panic!(
+                "operator runs only support filters and maps, but the plan contains {:?}",
+                graph[idx]
+            ),
+        }
+    }
+}
+
+/// Renders the program's nodes, edges and generated code. Definitions are sorted, as they are
+/// collected from sets and so aren't generated in a stable order.
+fn render_plan(program: &Program) -> String {
+    let graph = &program.graph;
+    let mut plan = String::new();
+
+    writeln!(plan, "nodes:").unwrap();
+    for node in graph.node_weights() {
+        writeln!(plan, "  {:?} (parallelism {})", node, node.parallelism).unwrap();
+    }
+
+    writeln!(plan, "edges:").unwrap();
+    for edge in graph.edge_references() {
+        let weight = edge.weight();
+        writeln!(
+            plan,
+            "  {} -> {} {:?} <{}, {}>",
+            graph[edge.source()].operator_id,
+            graph[edge.target()].operator_id,
+            weight.typ,
+            weight.key,
+            weight.value
+        )
+        .unwrap();
+    }
+
+    let mut defs: Vec<syn::Item> = program
+        .other_defs
+        .iter()
+        .flat_map(|def| parse_str::<syn::File>(def).unwrap().items)
+        .collect();
+    defs.sort_by_cached_key(|item| quote!(#item).to_string());
+    defs.insert(0, syn::Item::Fn(program.make_graph_function()));
+
+    writeln!(plan).unwrap();
+    plan.push_str(&prettyplease::unparse(&syn::File {
+        shebang: None,
+        attrs: vec![],
+        items: defs,
+    }));
+    plan
+}
+
 struct PipelineCase {
     test_name: LitStr,
     query: LitStr,
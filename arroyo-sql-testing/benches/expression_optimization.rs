@@ -0,0 +1,81 @@
+//! Compares the code generated for a wide projection whose fields share subexpressions, as it's
+//! compiled with the expression optimizations (see `arroyo_sql::expressions::optimize_expressions`)
+//! and without them.
+//!
+//! Run with `cargo bench -p arroyo-sql-testing --bench expression_optimization`.
+
+use arroyo_sql::TestStruct;
+use arroyo_sql_macro::projection_codegen;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+projection_codegen!(
+    "wide_projection",
+    "lower(non_nullable_string) = 'value-0' AS string_0,
+    lower(non_nullable_string) = 'value-1' AS string_1,
+    lower(non_nullable_string) = 'value-2' AS string_2,
+    lower(non_nullable_string) = 'value-3' AS string_3,
+    lower(non_nullable_string) = 'value-4' AS string_4,
+    lower(non_nullable_string) = 'value-5' AS string_5,
+    lower(non_nullable_string) = 'value-6' AS string_6,
+    lower(non_nullable_string) = 'value-7' AS string_7,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 0 AS square_0,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 10 AS square_1,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 20 AS square_2,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 30 AS square_3,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 40 AS square_4,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 50 AS square_5,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 60 AS square_6,
+    nullable_i64 IS NOT NULL AND (nullable_i64 + 1) * (nullable_i64 + 1) > 70 AS square_7,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 1.0) AS ratio_0,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 2.0) AS ratio_1,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 3.0) AS ratio_2,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 4.0) AS ratio_3,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 5.0) AS ratio_4,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 6.0) AS ratio_5,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 7.0) AS ratio_6,
+    (non_nullable_f64 * 2.0 + 1.0) / (non_nullable_f64 * 2.0 + 1.0 + 8.0) AS ratio_7"
+);
+
+fn inputs() -> Vec<TestStruct> {
+    (0..1_000)
+        .map(|i| TestStruct {
+            nullable_i64: (i % 10 != 0).then_some(i),
+            non_nullable_f64: i as f64,
+            non_nullable_string: format!("VALUE-{}", i % 8),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn bench_projection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wide_projection");
+
+    group.bench_function("unoptimized", |b| {
+        b.iter_batched(
+            inputs,
+            |inputs| {
+                for input in inputs {
+                    black_box(wide_projection::unoptimized(input));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("optimized", |b| {
+        b.iter_batched(
+            inputs,
+            |inputs| {
+                for input in inputs {
+                    black_box(wide_projection::optimized(input));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_projection);
+criterion_main!(benches);
@@ -100,6 +100,25 @@ INSERT INTO kafka_raw_sink
 SELECT bid.price FROM nexmark;
 "}
 
+full_pipeline_codegen! {"raw_bytes_mirror", "
+CREATE TABLE source WITH (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  type = 'source',
+  topic = 'events',
+  format = 'raw_bytes'
+);
+CREATE TABLE mirror WITH (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  type = 'sink',
+  topic = 'events_mirror',
+  format = 'raw_bytes'
+);
+INSERT INTO mirror
+SELECT value FROM source;
+"}
+
 full_pipeline_codegen! {"filter_on_updating_aggregates", "
 SELECT auction  / 2 as half_auction
 FROM (
@@ -0,0 +1,101 @@
+//! Golden files for the SQL tests, under `golden/`. Plans and outputs are compared to the
+//! checked-in files, so that changes to the generated code show up as diffs. A missing file fails
+//! the test; running the tests with `UPDATE_GOLDEN=1` writes missing files and rewrites the rest.
+use std::fs;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+
+const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+fn golden_path(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(path)
+}
+
+pub fn check_golden(path: &str, actual: &str) {
+    let file = golden_path(path);
+
+    if std::env::var(UPDATE_ENV).is_ok() {
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, actual).unwrap();
+        println!("wrote golden file {}", file.display());
+        return;
+    }
+
+    let expected = fs::read_to_string(&file).unwrap_or_else(|e| {
+        panic!(
+            "failed to read golden file {} ({}); rerun with {}=1 to create it",
+            file.display(),
+            e,
+            UPDATE_ENV
+        )
+    });
+
+    if expected != actual {
+        panic!(
+            "{} does not match (rerun with {}=1 to update it):\n{}",
+            file.display(),
+            UPDATE_ENV,
+            diff(&expected, actual)
+        );
+    }
+}
+
+/// Runs the compiled operators over the canned inputs, and checks their outputs
+pub fn check_outputs<T: DeserializeOwned>(name: &str, run: fn(Vec<T>) -> Vec<serde_json::Value>) {
+    let inputs = fs::read_to_string(golden_path("inputs/test_struct.json")).unwrap();
+    let inputs = inputs
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", line, e)))
+        .collect();
+
+    let outputs: Vec<_> = run(inputs)
+        .iter()
+        .map(|output| serde_json::to_string(output).unwrap())
+        .collect();
+
+    check_golden(
+        &format!("outputs/{}.json", name),
+        &format!("{}\n", outputs.join("\n")),
+    );
+}
+
+/// A line diff of the two strings, which only lists lines that differ after their common prefix
+/// and suffix
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+
+    let prefix = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(e, a)| e == a)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+
+    let mut diff = format!("@@ line {} @@\n", prefix + 1);
+    for line in &expected[prefix..expected.len() - suffix] {
+        diff.push_str(&format!("-{}\n", line));
+    }
+    for line in &actual[prefix..actual.len() - suffix] {
+        diff.push_str(&format!("+{}\n", line));
+    }
+    diff
+}
+
+#[test]
+fn test_diff() {
+    assert_eq!(
+        diff("a\nb\nc\n", "a\nx\ny\nc\n"),
+        "@@ line 2 @@\n-b\n+x\n+y\n"
+    );
+    assert_eq!(diff("a\nb\n", "a\nb\nc\n"), "@@ line 3 @@\n+c\n");
+}
@@ -1,5 +1,8 @@
 mod full_query_tests;
 #[cfg(test)]
+mod golden;
+mod operator_run_tests;
+#[cfg(test)]
 mod tests {
     use arroyo_sql_macro::single_test_codegen;
     use arroyo_types;
@@ -830,6 +833,47 @@ mod tests {
         None
     );
 
+    // Encoding
+    single_test_codegen!(
+        "encode_hex",
+        "encode(non_nullable_bytes, 'hex')",
+        arroyo_sql::TestStruct {
+            non_nullable_bytes: "asdf".as_bytes().to_vec(),
+            ..Default::default()
+        },
+        "61736466".to_string()
+    );
+
+    single_test_codegen!(
+        "encode_base64_null",
+        "encode(nullable_string, 'base64')",
+        arroyo_sql::TestStruct {
+            nullable_string: None,
+            ..Default::default()
+        },
+        None
+    );
+
+    single_test_codegen!(
+        "decode_base64",
+        "decode(non_nullable_string, 'base64')",
+        arroyo_sql::TestStruct {
+            non_nullable_string: "YXNkZg==".into(),
+            ..Default::default()
+        },
+        Some("asdf".as_bytes().to_vec())
+    );
+
+    single_test_codegen!(
+        "decode_invalid_hex",
+        "decode(non_nullable_string, 'hex')",
+        arroyo_sql::TestStruct {
+            non_nullable_string: "not hex".into(),
+            ..Default::default()
+        },
+        None
+    );
+
     single_test_codegen!(
         "float_literal",
         "100.0",
@@ -1243,4 +1287,47 @@ mod tests {
         },
         arroyo_types::from_millis(168565954000)
     );
+
+    // Expression optimizations
+    single_test_codegen!(
+        "folded_constants",
+        "non_nullable_i64 + (1 + 2 * 3)",
+        arroyo_sql::TestStruct {
+            non_nullable_i64: 1,
+            ..Default::default()
+        },
+        8i64
+    );
+
+    single_test_codegen!(
+        "shared_subexpression",
+        "(nullable_i64 + 1) * (nullable_i64 + 1) - (nullable_i64 + 1)",
+        arroyo_sql::TestStruct {
+            nullable_i64: Some(3),
+            ..Default::default()
+        },
+        Some(12i64)
+    );
+
+    single_test_codegen!(
+        "repeated_null_check",
+        "nullable_i64 IS NOT NULL AND nullable_i64 > 1 AND nullable_i64 IS NOT NULL",
+        arroyo_sql::TestStruct {
+            nullable_i64: Some(5),
+            ..Default::default()
+        },
+        Some(true)
+    );
+
+    // the repeated division is only evaluated when the first condition is false, so it can't be
+    // computed up front
+    single_test_codegen!(
+        "conditional_subexpression",
+        "non_nullable_i64 = 0 OR (10 / non_nullable_i64 > 1 AND 10 / non_nullable_i64 < 100)",
+        arroyo_sql::TestStruct {
+            non_nullable_i64: 0,
+            ..Default::default()
+        },
+        true
+    );
 }
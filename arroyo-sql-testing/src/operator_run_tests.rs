@@ -0,0 +1,82 @@
+use arroyo_sql_macro::{operator_run_codegen, window_function_run_codegen};
+
+operator_run_codegen! {"project_arithmetic",
+"SELECT non_nullable_i32 + 1 AS plus_one, nullable_i64 * 2 AS doubled,
+  non_nullable_f64 / 2 AS halved, nullable_f32 - non_nullable_f32 AS difference
+FROM test_source"}
+
+operator_run_codegen! {"filter_nullable",
+"SELECT nullable_i32, nullable_string FROM test_source WHERE nullable_i32 > 5"}
+
+operator_run_codegen! {"string_functions",
+"SELECT lower(non_nullable_string) AS lower, upper(nullable_string) AS upper,
+  concat(non_nullable_string, '!') AS exclaimed, btrim(nullable_string) AS trimmed,
+  coalesce(nullable_string, non_nullable_string) AS coalesced
+FROM test_source"}
+
+operator_run_codegen! {"case_and_boolean_filter",
+"SELECT CASE WHEN non_nullable_bool THEN non_nullable_i64 ELSE nullable_i64 END AS picked,
+  coalesce(nullable_f64, 0.0) AS f
+FROM test_source
+WHERE non_nullable_string = 'hello' OR nullable_bool IS NULL"}
+
+operator_run_codegen! {"timestamp_functions",
+"SELECT date_trunc('month', non_nullable_timestamp) AS month,
+  date_part('month', nullable_timestamp) AS month_part,
+  extract(MONTH from non_nullable_timestamp) AS extracted
+FROM test_source
+WHERE nullable_timestamp IS NOT NULL OR non_nullable_i32 < 0"}
+
+// a nullable float sort key, in a partition computed from an expression
+window_function_run_codegen! {"row_number_nullable_float",
+"SELECT non_nullable_i32, nullable_f64, ROW_NUMBER() OVER (
+    PARTITION BY tumble(interval '1 hour'), non_nullable_i32 % 2
+    ORDER BY nullable_f64 DESC NULLS LAST) AS row_num
+FROM test_source"}
+
+#[test]
+fn test_row_number_nullable_float_order() {
+    let inputs = [
+        (1, Some(1.5)),
+        (2, None),
+        (3, Some(-2.0)),
+        (4, Some(7.25)),
+        (5, None),
+        (6, Some(0.5)),
+        (7, Some(3.0)),
+    ]
+    .into_iter()
+    .map(|(i, f)| {
+        serde_json::from_value(serde_json::json!({
+            "non_nullable_i32": i,
+            "non_nullable_bool": false,
+            "non_nullable_f32": 0.0,
+            "non_nullable_f64": 0.0,
+            "non_nullable_i64": 0,
+            "non_nullable_string": "",
+            "non_nullable_timestamp": "1970-01-01T00:00:00Z",
+            "non_nullable_bytes": [],
+            "nullable_f64": f,
+        }))
+        .unwrap()
+    })
+    .collect();
+
+    let outputs: Vec<_> = row_number_nullable_float::run(inputs)
+        .iter()
+        .map(|output| {
+            // columns selected without an alias are qualified by their table
+            (
+                output["test_source_non_nullable_i32"].as_i64().unwrap(),
+                output["row_num"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+
+    // the odd partition comes first, as it appears first in the inputs; within each, rows are
+    // numbered by descending value, with nulls last
+    assert_eq!(
+        outputs,
+        vec![(7, 1), (1, 2), (3, 3), (5, 4), (4, 1), (6, 2), (2, 3)]
+    );
+}
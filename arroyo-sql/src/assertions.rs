@@ -0,0 +1,267 @@
+//! Data quality assertions on the records read from source tables, declared with statements like
+//! `ASSERT orders.amount >= 0 ON VIOLATION DROP;`. An assertion's condition may only read the
+//! columns of one source table, qualified with its name. Records for which the condition is false
+//! violate the assertion, while (as with CHECK constraints) those for which it's null don't.
+//!
+//! Each assertion is checked by an operator placed after its source, which counts violations in
+//! the `arroyo_worker_assertion_violations` metric and then handles them per the assertion's
+//! action: `FAIL` (the default) fails the pipeline, `DROP` drops them, and `DLQ <sink>` drops them
+//! from the stream but writes them, as they were read, to the sink table `sink`.
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_connectors::ConnectionType;
+use datafusion::sql::sqlparser::{
+    ast::Ident,
+    dialect::PostgreSqlDialect,
+    parser::Parser,
+    tokenizer::{Token, Word},
+};
+use datafusion_expr::{Expr, LogicalPlan};
+
+use crate::{
+    tables::{produce_optimized_plan, Table},
+    ArroyoSchemaProvider,
+};
+
+const USAGE: &str = "assertions are written as \
+    ASSERT <condition> [ON VIOLATION DROP | FAIL | DLQ <sink>], where the condition's columns are \
+    qualified with the name of their table, as in ASSERT orders.amount >= 0";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ViolationAction {
+    Fail,
+    Drop,
+    /// Writes the violating records to the named sink table
+    DeadLetter(String),
+}
+
+/// An ASSERT statement, before the tables it refers to have been defined
+#[derive(Clone, Debug)]
+pub(crate) struct AssertStatement {
+    condition: Vec<Token>,
+    action: ViolationAction,
+}
+
+/// A planned assertion on the records of a source table
+#[derive(Clone, Debug)]
+pub struct TableAssertion {
+    pub table: String,
+    /// The condition as written, which identifies the assertion in metrics and errors
+    pub text: String,
+    pub action: ViolationAction,
+    // a query that reads the columns of the table that the assertion needs, and the condition as
+    // it's planned within it
+    pub(crate) plan: LogicalPlan,
+    pub(crate) condition: Expr,
+}
+
+fn is_word(token: &Token, word: &str) -> bool {
+    matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word))
+}
+
+fn is_whitespace(token: &Token) -> bool {
+    matches!(token, Token::Whitespace(_))
+}
+
+/// Removes the ASSERT statements from a query's tokens, as sqlparser can't parse them, returning
+/// the tokens of the remaining statements along with the assertions
+pub(crate) fn extract_assertions(
+    tokens: Vec<Token>,
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<(Vec<Token>, Vec<AssertStatement>)> {
+    let mut statements = vec![vec![]];
+    for token in tokens {
+        let end = token == Token::SemiColon;
+        statements.last_mut().unwrap().push(token);
+        if end {
+            statements.push(vec![]);
+        }
+    }
+
+    let mut rest = vec![];
+    let mut assertions = vec![];
+    for statement in statements {
+        let is_assert = statement
+            .iter()
+            .find(|t| !is_whitespace(t))
+            .map_or(false, |t| is_word(t, "assert"));
+        if is_assert {
+            assertions.push(parse_assertion(&statement, schema_provider)?);
+        } else {
+            rest.extend(statement);
+        }
+    }
+
+    Ok((rest, assertions))
+}
+
+fn parse_assertion(
+    statement: &[Token],
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<AssertStatement> {
+    let start = statement.iter().position(|t| is_word(t, "assert")).unwrap() + 1;
+    let tokens: Vec<_> = statement[start..]
+        .iter()
+        .filter(|t| **t != Token::SemiColon)
+        .collect();
+    let words: Vec<_> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| !is_whitespace(t))
+        .collect();
+
+    // the condition ends at the first ON VIOLATION that's not within parentheses
+    let mut depth = 0;
+    let mut on_violation = None;
+    for (i, (index, token)) in words.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            t if depth == 0
+                && is_word(t, "on")
+                && words
+                    .get(i + 1)
+                    .map_or(false, |(_, t)| is_word(t, "violation")) =>
+            {
+                on_violation = Some((*index, i + 2));
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let (condition, action) = match on_violation {
+        Some((end, action_start)) => {
+            let action: Vec<_> = words[action_start..].iter().map(|(_, t)| **t).collect();
+            let action = match action.as_slice() {
+                [t] if is_word(t, "fail") => ViolationAction::Fail,
+                [t] if is_word(t, "drop") => ViolationAction::Drop,
+                [t, Token::Word(sink)] if is_word(t, "dlq") => {
+                    ViolationAction::DeadLetter(schema_provider.ident_name(&ident(sink)))
+                }
+                _ => bail!(USAGE),
+            };
+            (&tokens[..end], action)
+        }
+        None => (&tokens[..], ViolationAction::Fail),
+    };
+
+    if condition.iter().all(|t| is_whitespace(t)) {
+        bail!(USAGE);
+    }
+
+    Ok(AssertStatement {
+        condition: condition.iter().map(|t| (*t).clone()).collect(),
+        action,
+    })
+}
+
+fn ident(word: &Word) -> Ident {
+    Ident {
+        value: word.value.clone(),
+        quote_style: word.quote_style,
+    }
+}
+
+/// Plans an assertion against the tables defined by the query
+pub(crate) fn plan_assertion(
+    statement: &AssertStatement,
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<TableAssertion> {
+    let text = statement
+        .condition
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<String>()
+        .trim()
+        .to_string();
+
+    // the tables that qualify columns; a word followed by a period is a table, unless it's itself
+    // a field of a column, as in orders.customer.id
+    let mut tables: Vec<(String, &Word)> = vec![];
+    let words: Vec<_> = statement
+        .condition
+        .iter()
+        .filter(|t| !is_whitespace(t))
+        .collect();
+    for (i, token) in words.iter().enumerate() {
+        if let Token::Word(word) = token {
+            let qualifier = words.get(i + 1) == Some(&&Token::Period)
+                && (i == 0 || words[i - 1] != &Token::Period);
+            let name = schema_provider.ident_name(&ident(word));
+            if qualifier && !tables.iter().any(|(n, _)| *n == name) {
+                tables.push((name, word));
+            }
+        }
+    }
+
+    let [(table_name, table)] = tables.as_slice() else {
+        bail!(
+            "the columns of assertion {} must all be qualified with the name of the table it's \
+            on; {}",
+            text,
+            USAGE
+        );
+    };
+
+    match schema_provider.get_table(table_name) {
+        Some(Table::ConnectorTable(t)) if matches!(t.connection_type, ConnectionType::Source) => {}
+        Some(_) => bail!(
+            "assertion {} is on {}, but assertions can only be declared on source tables",
+            text,
+            table_name
+        ),
+        None => bail!("assertion {} is on unknown table {}", text, table_name),
+    }
+
+    if let ViolationAction::DeadLetter(sink) = &statement.action {
+        match schema_provider.get_table(sink) {
+            Some(Table::ConnectorTable(t)) if matches!(t.connection_type, ConnectionType::Sink) => {
+            }
+            _ => bail!(
+                "the dead-letter table {} of assertion {} must be a sink table",
+                sink,
+                text
+            ),
+        }
+    }
+
+    // dead-letter sinks write every column of the violating records, so none can be pruned
+    let columns = match statement.action {
+        ViolationAction::DeadLetter(_) => "*",
+        _ => "1",
+    };
+    let query = format!("SELECT {} FROM {} WHERE {}", columns, table, text);
+    let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, &query)
+        .map_err(|e| anyhow!("invalid assertion {}: {}", text, e))?;
+    if statements.len() != 1 {
+        bail!(USAGE);
+    }
+
+    let plan = produce_optimized_plan(&statements.remove(0), schema_provider)
+        .map_err(|e| anyhow!("failed to plan assertion {}: {}", text, e))?;
+    let condition = find_condition(&plan)
+        .ok_or_else(|| {
+            anyhow!(
+                "assertion {} doesn't check the records of {}",
+                text,
+                table_name
+            )
+        })?
+        .clone();
+
+    Ok(TableAssertion {
+        table: table_name.clone(),
+        text,
+        action: statement.action.clone(),
+        plan,
+        condition,
+    })
+}
+
+fn find_condition(plan: &LogicalPlan) -> Option<&Expr> {
+    match plan {
+        LogicalPlan::Filter(filter) => Some(&filter.predicate),
+        _ => plan.inputs().into_iter().find_map(find_condition),
+    }
+}
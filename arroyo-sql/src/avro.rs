@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use arrow_schema::{DataType, TimeUnit};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::types::{StructDef, StructField, TypeDef};
+
+/// Converts an avro schema, whose top level must be a record, into the fields of a table. Unions
+/// of null and another type become nullable fields; arrays, maps and other unions aren't
+/// supported and are left out of the table.
+pub fn convert_avro_schema(name: &str, schema: &str) -> Result<Vec<StructField>, String> {
+    let schema: Value =
+        serde_json::from_str(schema).map_err(|e| format!("Invalid avro schema: {}", e))?;
+
+    let mut names = HashMap::new();
+    collect_names(&schema, None, &mut names);
+
+    match to_type_def(&schema, &names, &mut vec![])? {
+        Some(TypeDef::StructDef(StructDef { fields, .. }, _)) => Ok(fields),
+        _ => Err(format!("The avro schema for {} is not a record", name)),
+    }
+}
+
+// named types, by both their full and short names
+fn collect_names<'a>(
+    schema: &'a Value,
+    namespace: Option<&'a str>,
+    names: &mut HashMap<String, &'a Value>,
+) {
+    match schema {
+        Value::Array(branches) => {
+            for branch in branches {
+                collect_names(branch, namespace, names);
+            }
+        }
+        Value::Object(o) => {
+            let namespace = o.get("namespace").and_then(|n| n.as_str()).or(namespace);
+            let named = matches!(
+                o.get("type").and_then(|t| t.as_str()),
+                Some("record" | "error" | "enum" | "fixed")
+            );
+
+            if let (true, Some(name)) = (named, o.get("name").and_then(|n| n.as_str())) {
+                names.insert(name.to_string(), schema);
+                match (name.rsplit_once('.'), namespace) {
+                    (Some((_, short)), _) => {
+                        names.insert(short.to_string(), schema);
+                    }
+                    (None, Some(namespace)) => {
+                        names.insert(format!("{}.{}", namespace, name), schema);
+                    }
+                    (None, None) => {}
+                }
+            }
+
+            for child in ["type", "items", "values"] {
+                if let Some(child) = o.get(child) {
+                    collect_names(child, namespace, names);
+                }
+            }
+
+            for field in o
+                .get("fields")
+                .and_then(|f| f.as_array())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(typ) = field.get("type") {
+                    collect_names(typ, namespace, names);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The type of the schema, or None if it's a type that tables can't represent. `expanding` holds
+/// the named types that are being converted, to reject recursive types.
+fn to_type_def(
+    schema: &Value,
+    names: &HashMap<String, &Value>,
+    expanding: &mut Vec<String>,
+) -> Result<Option<TypeDef>, String> {
+    let data_type = match schema {
+        Value::String(typ) => match typ.as_str() {
+            "boolean" => DataType::Boolean,
+            "int" => DataType::Int32,
+            "long" => DataType::Int64,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "bytes" => DataType::Binary,
+            "string" => DataType::Utf8,
+            "null" => return Err("null is only supported as part of a union".to_string()),
+            name => {
+                let named = names
+                    .get(name)
+                    .or_else(|| names.get(name.rsplit('.').next().unwrap()))
+                    .ok_or_else(|| format!("Unknown avro type '{}'", name))?;
+
+                if expanding.iter().any(|n| n == name) {
+                    return Err(format!("Recursive avro type '{}' is not supported", name));
+                }
+                expanding.push(name.to_string());
+                let t = to_type_def(named, names, expanding);
+                expanding.pop();
+                return t;
+            }
+        },
+        Value::Array(branches) => {
+            let non_null: Vec<_> = branches
+                .iter()
+                .filter(|b| b.as_str() != Some("null"))
+                .collect();
+
+            if non_null.len() != 1 {
+                warn!("Unhandled avro union {}", schema);
+                return Ok(None);
+            }
+
+            let t = to_type_def(non_null[0], names, expanding)?;
+            return Ok(if non_null.len() < branches.len() {
+                t.map(|t| t.to_optional())
+            } else {
+                t
+            });
+        }
+        Value::Object(o) => {
+            let typ = o
+                .get("type")
+                .ok_or_else(|| format!("Avro schema {} is missing a type", schema))?;
+
+            match (typ.as_str(), o.get("logicalType").and_then(|t| t.as_str())) {
+                (Some("long"), Some("timestamp-millis")) => {
+                    DataType::Timestamp(TimeUnit::Millisecond, None)
+                }
+                (Some("long"), Some("timestamp-micros")) => {
+                    DataType::Timestamp(TimeUnit::Microsecond, None)
+                }
+                (Some("record" | "error"), _) => {
+                    let mut fields = vec![];
+                    for field in o
+                        .get("fields")
+                        .and_then(|f| f.as_array())
+                        .ok_or_else(|| format!("Avro record {} has no fields", schema))?
+                    {
+                        let name = field
+                            .get("name")
+                            .and_then(|n| n.as_str())
+                            .ok_or_else(|| format!("Avro field {} has no name", field))?;
+                        let typ = field
+                            .get("type")
+                            .ok_or_else(|| format!("Avro field '{}' has no type", name))?;
+
+                        match to_type_def(typ, names, expanding)? {
+                            Some(t) => fields.push(StructField::new(name.to_string(), None, t)),
+                            None => warn!("Leaving out avro field '{}' of unhandled type", name),
+                        }
+                    }
+
+                    return Ok(Some(TypeDef::StructDef(
+                        StructDef { name: None, fields },
+                        false,
+                    )));
+                }
+                (Some("enum"), _) => DataType::Utf8,
+                (Some("fixed"), _) => DataType::Binary,
+                (Some("array" | "map"), _) => {
+                    warn!("Unhandled avro type {}", schema);
+                    return Ok(None);
+                }
+                // a primitive with a logical type we don't handle, or a nested type definition
+                _ => return to_type_def(typ, names, expanding),
+            }
+        }
+        _ => return Err(format!("Invalid avro schema {}", schema)),
+    };
+
+    Ok(Some(TypeDef::DataType(data_type, false)))
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_schema::{DataType, TimeUnit};
+
+    use super::convert_avro_schema;
+    use crate::types::TypeDef;
+
+    #[test]
+    fn test_convert() {
+        let fields = convert_avro_schema(
+            "orders",
+            r#"
+            {
+                "type": "record",
+                "name": "Order",
+                "namespace": "com.example",
+                "fields": [
+                    {"name": "id", "type": "long"},
+                    {"name": "customer", "type": ["null", "string"]},
+                    {"name": "created", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                    {"name": "status", "type": {"type": "enum", "name": "Status", "symbols": ["NEW", "PAID"]}},
+                    {"name": "previous_status", "type": ["null", "com.example.Status"]},
+                    {"name": "address", "type": ["null", {
+                        "type": "record",
+                        "name": "Address",
+                        "fields": [{"name": "city", "type": "string"}]
+                    }]},
+                    {"name": "tags", "type": {"type": "array", "items": "string"}}
+                ]
+            }
+            "#,
+        )
+        .unwrap();
+
+        let types: Vec<_> = fields
+            .iter()
+            .map(|f| (f.name.as_str(), &f.data_type))
+            .collect();
+
+        assert_eq!(types.len(), 6);
+        assert_eq!(types[0], ("id", &TypeDef::DataType(DataType::Int64, false)));
+        assert_eq!(
+            types[1],
+            ("customer", &TypeDef::DataType(DataType::Utf8, true))
+        );
+        assert_eq!(
+            types[2],
+            (
+                "created",
+                &TypeDef::DataType(DataType::Timestamp(TimeUnit::Millisecond, None), false)
+            )
+        );
+        assert_eq!(
+            types[4],
+            ("previous_status", &TypeDef::DataType(DataType::Utf8, true))
+        );
+        assert!(
+            matches!(types[5], ("address", TypeDef::StructDef(s, true)) if s.fields.len() == 1)
+        );
+    }
+
+    #[test]
+    fn test_recursive() {
+        let err = convert_avro_schema(
+            "list",
+            r#"
+            {
+                "type": "record",
+                "name": "Node",
+                "fields": [
+                    {"name": "value", "type": "long"},
+                    {"name": "next", "type": ["null", "Node"]}
+                ]
+            }
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(err.contains("Recursive"), "{}", err);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    extensions::FunctionImplementation,
     operators::TwoPhaseAggregation,
     pipeline::SortDirection,
     types::{StructDef, StructField, TypeDef},
@@ -7,7 +8,7 @@ use crate::{
 use anyhow::{anyhow, bail, Ok, Result};
 use arrow::datatypes::DataType;
 use arrow_schema::{Field, TimeUnit};
-use arroyo_types::{DatePart, DateTruncPrecision};
+use arroyo_types::{valid_secret_name, DatePart, DateTruncPrecision};
 use datafusion_common::ScalarValue;
 use datafusion_expr::{
     aggregate_function,
@@ -18,7 +19,7 @@ use datafusion_expr::{
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use regex::Regex;
-use std::{fmt::Debug, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 use syn::{parse_quote, parse_str, Ident, Path};
 
 #[derive(Debug, Clone)]
@@ -38,11 +39,15 @@ pub enum Expression {
     Date(DateTimeFunction),
     String(StringFunction),
     Hash(HashExpression),
+    Crypto(CryptoExpression),
     DataStructure(DataStructureFunction),
     Json(JsonExpression),
+    Network(NetworkExpression),
     RustUdf(RustUdfExpression),
+    Extension(ExtensionExpression),
     WrapType(WrapTypeExpression),
     Case(CaseExpression),
+    Binding(BindingExpression),
 }
 
 impl Expression {
@@ -67,13 +72,17 @@ impl Expression {
             Expression::Numeric(numeric_expression) => numeric_expression.to_syn_expression(),
             Expression::String(string_function) => string_function.to_syn_expression(),
             Expression::Hash(hash_expression) => hash_expression.to_syn_expression(),
+            Expression::Crypto(crypto_expression) => crypto_expression.to_syn_expression(),
             Expression::DataStructure(data_structure_expression) => {
                 data_structure_expression.to_syn_expression()
             }
             Expression::Json(json_function) => json_function.to_syn_expression(),
+            Expression::Network(network_expression) => network_expression.to_syn_expression(),
             Expression::RustUdf(t) => t.to_syn_expression(),
+            Expression::Extension(t) => t.to_syn_expression(),
             Expression::WrapType(t) => t.to_syn_expression(),
             Expression::Case(case_expression) => case_expression.to_syn_expression(),
+            Expression::Binding(binding_expression) => binding_expression.to_syn_expression(),
             Expression::Date(datetime_expr) => datetime_expr.to_syn_expression(),
         }
     }
@@ -109,13 +118,17 @@ impl Expression {
             Expression::Date(date_function) => date_function.return_type(),
             Expression::String(string_function) => string_function.return_type(),
             Expression::Hash(hash_expression) => hash_expression.return_type(),
+            Expression::Crypto(crypto_expression) => crypto_expression.return_type(),
             Expression::DataStructure(data_structure_expression) => {
                 data_structure_expression.return_type()
             }
             Expression::Json(json_function) => json_function.return_type(),
+            Expression::Network(network_expression) => network_expression.return_type(),
             Expression::RustUdf(t) => t.return_type(),
+            Expression::Extension(t) => t.return_type(),
             Expression::WrapType(t) => t.return_type(),
             Expression::Case(case_statement) => case_statement.return_type(),
+            Expression::Binding(binding_expression) => binding_expression.return_type(),
         }
     }
 
@@ -496,6 +509,14 @@ impl<'a> ExpressionContext<'a> {
                 }
             }
             Expr::ScalarUDF(ScalarUDF { fun, args }) => match fun.name.as_str() {
+                "tumble_start" | "hop_start" => StructFieldExpression::new(
+                    Box::new(self.compile_expr(&args[0])?),
+                    &ScalarValue::Utf8(Some("start_time".to_string())),
+                ),
+                "tumble_end" | "hop_end" => StructFieldExpression::new(
+                    Box::new(self.compile_expr(&args[0])?),
+                    &ScalarValue::Utf8(Some("end_time".to_string())),
+                ),
                 "get_first_json_object" => {
                     let json_string = Box::new(self.compile_expr(&args[0])?);
                     let path = Box::new(self.compile_expr(&args[1])?);
@@ -523,6 +544,44 @@ impl<'a> ExpressionContext<'a> {
                         path,
                     }))
                 }
+                "encode" | "decode" | "hmac_sha256" | "aes_gcm_encrypt" | "aes_gcm_decrypt" => {
+                    let mut args = args
+                        .iter()
+                        .map(|e| self.compile_expr(e))
+                        .collect::<Result<Vec<_>>>()?;
+                    CryptoExpression::new(&fun.name, Box::new(args.remove(0)), args.remove(0))
+                }
+                "ip_version" | "normalize_ip" | "cidr_contains" | "geoip_country" | "geoip_asn"
+                | "geoip_asn_org" => {
+                    let args = args
+                        .iter()
+                        .map(|e| self.compile_expr(e))
+                        .collect::<Result<_>>()?;
+                    NetworkExpression::new(&fun.name, args)
+                }
+                name if self.schema_provider.extensions.contains_key(name) => {
+                    let extension = &self.schema_provider.extensions[name];
+                    match extension.implement(args.clone())? {
+                        FunctionImplementation::Rewrite(expr) => self.compile_expr(&expr),
+                        FunctionImplementation::Rust(body) => {
+                            parse_str::<syn::Expr>(&body).map_err(|e| {
+                                anyhow!("invalid implementation of function '{}': {}", name, e)
+                            })?;
+
+                            let args = args
+                                .iter()
+                                .map(|e| self.compile_expr(e))
+                                .collect::<Result<_>>()?;
+
+                            Ok(Expression::Extension(ExtensionExpression {
+                                name: name.to_string(),
+                                args,
+                                body,
+                                ret_type: TypeDef::DataType(extension.return_type(), false),
+                            }))
+                        }
+                    }
+                }
                 udf => {
                     // get udf from context
                     let def = self
@@ -1426,11 +1485,18 @@ impl SortExpression {
     }
 
     fn tuple_type(&self) -> syn::Type {
-        let value_type = if self.value.return_type().is_float() {
-            let t = self.value.return_type().return_type();
-            parse_quote! { arroyo_worker::OrderedFloat<#t> }
-        } else {
-            self.value.return_type().return_type()
+        let return_type = self.value.return_type();
+        let value_type = match return_type.as_datatype() {
+            // nullable floats are wrapped inside of their option (see to_syn_expr)
+            Some(data_type) if return_type.is_float() => {
+                let t = TypeDef::DataType(data_type.clone(), false).return_type();
+                if return_type.is_optional() {
+                    parse_quote! { Option<arroyo_worker::OrderedFloat<#t>> }
+                } else {
+                    parse_quote! { arroyo_worker::OrderedFloat<#t> }
+                }
+            }
+            _ => return_type.return_type(),
         };
 
         match (self.value.nullable(), &self.direction, self.nulls_first) {
@@ -1631,8 +1697,169 @@ impl HashExpression {
     }
 
     fn return_type(&self) -> TypeDef {
+        // md5 returns a hex string, while the sha functions return the raw digest
+        let data_type = match self.function {
+            HashFunction::MD5 => DataType::Utf8,
+            _ => DataType::Binary,
+        };
         // this *can* be null because in SQL - MD5(NULL) = NULL
-        TypeDef::DataType(DataType::Utf8, self.input.nullable())
+        TypeDef::DataType(data_type, self.input.nullable())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub enum Encoding {
+    Base64,
+    Hex,
+}
+
+impl TryFrom<&str> for Encoding {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "base64" => Ok(Self::Base64),
+            "hex" => Ok(Self::Hex),
+            _ => bail!(
+                "unsupported encoding '{}'; expected 'base64' or 'hex'",
+                value
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub enum CryptoFunction {
+    Encode(Encoding),
+    Decode(Encoding),
+    // the keys are the names of the pipeline secrets that hold them
+    HmacSha256 { key: String },
+    AesGcmEncrypt { key: String },
+    AesGcmDecrypt { key: String },
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct CryptoExpression {
+    function: CryptoFunction,
+    input: Box<Expression>,
+}
+
+impl CryptoExpression {
+    fn new(name: &str, input: Box<Expression>, arg: Expression) -> Result<Expression> {
+        let arg = extract_literal_string(arg)
+            .map_err(|_| anyhow!("the second argument to {} must be a string literal", name))?;
+
+        let function = match name {
+            "encode" => CryptoFunction::Encode(arg.as_str().try_into()?),
+            "decode" => CryptoFunction::Decode(arg.as_str().try_into()?),
+            "hmac_sha256" => CryptoFunction::HmacSha256 { key: arg },
+            "aes_gcm_encrypt" => CryptoFunction::AesGcmEncrypt { key: arg },
+            "aes_gcm_decrypt" => CryptoFunction::AesGcmDecrypt { key: arg },
+            _ => bail!("unknown crypto function {}", name),
+        };
+
+        if let CryptoFunction::HmacSha256 { key }
+        | CryptoFunction::AesGcmEncrypt { key }
+        | CryptoFunction::AesGcmDecrypt { key } = &function
+        {
+            if !valid_secret_name(key) {
+                bail!(
+                    "invalid key name '{}' for {}; keys are named by the secret that holds them, \
+                    which may only contain letters, numbers, underscores, dashes, and dots",
+                    key,
+                    name
+                );
+            }
+        }
+
+        let input_type = match input.return_type() {
+            TypeDef::DataType(t @ (DataType::Utf8 | DataType::Binary), _) => t,
+            _ => bail!("the first argument to {} must be a string or bytes", name),
+        };
+
+        match (&function, input_type) {
+            (CryptoFunction::Decode(_), DataType::Binary) => {
+                bail!("the first argument to decode must be a string")
+            }
+            (CryptoFunction::AesGcmDecrypt { .. }, DataType::Utf8) => {
+                bail!("the first argument to aes_gcm_decrypt must be bytes")
+            }
+            _ => {}
+        }
+
+        Ok(Expression::Crypto(CryptoExpression { function, input }))
+    }
+
+    // whether the function returns null for some non-null inputs
+    fn fallible(&self) -> bool {
+        matches!(
+            self.function,
+            CryptoFunction::Decode(_) | CryptoFunction::AesGcmDecrypt { .. }
+        )
+    }
+
+    fn to_syn_expression(&self) -> syn::Expr {
+        let input = self.input.to_syn_expression();
+
+        let coerce = match self.input.return_type() {
+            TypeDef::DataType(DataType::Utf8, _) => quote!(.into_bytes()),
+            _ => quote!(),
+        };
+
+        let call: syn::Expr = match &self.function {
+            CryptoFunction::Encode(Encoding::Base64) => parse_quote!(
+                arroyo_worker::operators::functions::crypto::base64_encode(value #coerce)
+            ),
+            CryptoFunction::Encode(Encoding::Hex) => parse_quote!(
+                arroyo_worker::operators::functions::crypto::hex_encode(value #coerce)
+            ),
+            CryptoFunction::Decode(Encoding::Base64) => {
+                parse_quote!(arroyo_worker::operators::functions::crypto::base64_decode(
+                    value
+                ))
+            }
+            CryptoFunction::Decode(Encoding::Hex) => {
+                parse_quote!(arroyo_worker::operators::functions::crypto::hex_decode(
+                    value
+                ))
+            }
+            CryptoFunction::HmacSha256 { key } => parse_quote!(
+                arroyo_worker::operators::functions::crypto::hmac_sha256(value #coerce, #key)
+            ),
+            CryptoFunction::AesGcmEncrypt { key } => parse_quote!(
+                arroyo_worker::operators::functions::crypto::aes_gcm_encrypt(value #coerce, #key)
+            ),
+            CryptoFunction::AesGcmDecrypt { key } => parse_quote!(
+                arroyo_worker::operators::functions::crypto::aes_gcm_decrypt(value, #key)
+            ),
+        };
+
+        match (self.input.nullable(), self.fallible()) {
+            (true, true) => parse_quote!({
+                match #input {
+                    Some(value) => #call,
+                    None => None,
+                }
+            }),
+            (true, false) => parse_quote!({
+                match #input {
+                    Some(value) => Some(#call),
+                    None => None,
+                }
+            }),
+            (false, _) => parse_quote!({
+                let value = #input;
+                #call
+            }),
+        }
+    }
+
+    fn return_type(&self) -> TypeDef {
+        let data_type = match self.function {
+            CryptoFunction::Encode(_) => DataType::Utf8,
+            _ => DataType::Binary,
+        };
+        TypeDef::DataType(data_type, self.input.nullable() || self.fallible())
     }
 }
 
@@ -2415,6 +2642,153 @@ impl JsonExpression {
     }
 }
 
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub enum NetworkFunction {
+    IpVersion,
+    NormalizeIp,
+    CidrContains,
+    GeoIpCountry,
+    GeoIpAsn,
+    GeoIpAsnOrg,
+}
+
+/// IP address functions, which return null for arguments that aren't valid addresses or
+/// networks, and GeoIP lookups (see `arroyo_worker::operators::functions::geoip`)
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct NetworkExpression {
+    function: NetworkFunction,
+    args: Vec<Expression>,
+}
+
+impl NetworkExpression {
+    fn new(name: &str, args: Vec<Expression>) -> Result<Expression> {
+        let function = match name {
+            "ip_version" => NetworkFunction::IpVersion,
+            "normalize_ip" => NetworkFunction::NormalizeIp,
+            "cidr_contains" => NetworkFunction::CidrContains,
+            "geoip_country" => NetworkFunction::GeoIpCountry,
+            "geoip_asn" => NetworkFunction::GeoIpAsn,
+            "geoip_asn_org" => NetworkFunction::GeoIpAsnOrg,
+            _ => bail!("unknown network function {}", name),
+        };
+
+        for arg in &args {
+            if !matches!(arg.return_type(), TypeDef::DataType(DataType::Utf8, _)) {
+                bail!("arguments to {} must be strings", name);
+            }
+        }
+
+        Ok(Expression::Network(NetworkExpression { function, args }))
+    }
+
+    fn to_syn_expression(&self) -> syn::Expr {
+        let function: syn::Path = match self.function {
+            NetworkFunction::IpVersion => {
+                parse_quote!(arroyo_worker::operators::functions::ip::ip_version)
+            }
+            NetworkFunction::NormalizeIp => {
+                parse_quote!(arroyo_worker::operators::functions::ip::normalize_ip)
+            }
+            NetworkFunction::CidrContains => {
+                parse_quote!(arroyo_worker::operators::functions::ip::cidr_contains)
+            }
+            NetworkFunction::GeoIpCountry => {
+                parse_quote!(arroyo_worker::operators::functions::geoip::geoip_country)
+            }
+            NetworkFunction::GeoIpAsn => {
+                parse_quote!(arroyo_worker::operators::functions::geoip::geoip_asn)
+            }
+            NetworkFunction::GeoIpAsnOrg => {
+                parse_quote!(arroyo_worker::operators::functions::geoip::geoip_asn_org)
+            }
+        };
+
+        let (defs, args): (Vec<_>, Vec<_>) = self
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, expr)| {
+                let t = expr.to_syn_expression();
+                let id = format_ident!("__{}", i);
+                let def = if expr.nullable() {
+                    quote!(let #id = (#t)?)
+                } else {
+                    quote!(let #id = #t)
+                };
+                (def, quote!(#id))
+            })
+            .unzip();
+
+        parse_quote!({
+            (|| {
+                #(#defs; )*
+                #function(#(#args, )*)
+            })()
+        })
+    }
+
+    fn return_type(&self) -> TypeDef {
+        let data_type = match self.function {
+            NetworkFunction::IpVersion => DataType::Int32,
+            NetworkFunction::CidrContains => DataType::Boolean,
+            NetworkFunction::GeoIpAsn => DataType::Int64,
+            NetworkFunction::NormalizeIp
+            | NetworkFunction::GeoIpCountry
+            | NetworkFunction::GeoIpAsnOrg => DataType::Utf8,
+        };
+        TypeDef::DataType(data_type, true)
+    }
+}
+
+/// A call to a deployment-provided function implemented in Rust (see [`crate::extensions`])
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct ExtensionExpression {
+    name: String,
+    args: Vec<Expression>,
+    body: String,
+    ret_type: TypeDef,
+}
+
+impl ExtensionExpression {
+    fn to_syn_expression(&self) -> syn::Expr {
+        let body: syn::Expr = parse_str(&self.body).unwrap();
+
+        let defs: Vec<_> = self
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, expr)| {
+                let t = expr.to_syn_expression();
+                let id = format_ident!("__{}", i);
+                if expr.nullable() {
+                    quote!(let #id = (#t)?)
+                } else {
+                    quote!(let #id = #t)
+                }
+            })
+            .collect();
+
+        if self.return_type().is_optional() {
+            parse_quote!({
+                (|| {
+                    #(#defs; )*
+                    Some(#body)
+                })()
+            })
+        } else {
+            parse_quote!({
+                #(#defs; )*
+                #body
+            })
+        }
+    }
+
+    fn return_type(&self) -> TypeDef {
+        self.ret_type
+            .with_nullity(self.args.iter().any(|e| e.nullable()))
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
 pub struct RustUdfExpression {
     name: String,
@@ -2738,3 +3112,671 @@ impl DateTimeFunction {
         }
     }
 }
+
+/// A reference to the value of a subexpression that's computed once, before the expressions that
+/// share it (see [`optimize_expressions`])
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct BindingExpression {
+    name: String,
+    ret_type: TypeDef,
+}
+
+impl BindingExpression {
+    fn to_syn_expression(&self) -> syn::Expr {
+        let ident = format_ident!("{}", self.name);
+        parse_quote!(#ident.clone())
+    }
+
+    fn return_type(&self) -> TypeDef {
+        self.ret_type.clone()
+    }
+}
+
+/// Optimizes expressions that are all evaluated for each record, like the fields of a projection,
+/// returning the statements that compute the subexpressions they share followed by the optimized
+/// expressions, which must be compiled after them in the same scope.
+///
+/// The expressions are optimized by
+///  * folding operations on literals, and boolean operations whose result doesn't depend on one
+///    of their operands,
+///  * removing repeated terms from chains of ANDs and ORs (e.g., the same null check ANDed with
+///    several conditions), and
+///  * computing subexpressions that occur more than once (within an expression or across all of
+///    them) a single time, binding them to variables that each occurrence is replaced by.
+///
+/// Only subexpressions that are compiled directly into the code of their parents (operators,
+/// casts, struct fields, and numeric functions) are optimized; function calls and CASE
+/// expressions are shared as a whole, but what's inside them is left as is. Expressions that
+/// call UDFs or extensions are never removed, shared, or reordered, as they may have side effects.
+pub(crate) fn optimize_expressions(
+    expressions: &[Expression],
+) -> (Vec<syn::Stmt>, Vec<Expression>) {
+    let mut expressions: Vec<_> = expressions.iter().cloned().map(Expression::fold).collect();
+    let bindings = bind_common_subexpressions(&mut expressions);
+
+    let statements = bindings
+        .into_iter()
+        .map(|(name, expression)| {
+            let ident = format_ident!("{}", name);
+            let ty = expression.return_type().return_type();
+            let expr = expression.to_syn_expression();
+            parse_quote!(let #ident: #ty = #expr;)
+        })
+        .collect();
+
+    (statements, expressions)
+}
+
+/// Compiles a single expression with [`optimize_expressions`]
+pub(crate) fn to_optimized_syn_expression(expression: &Expression) -> syn::Expr {
+    let (statements, expressions) = optimize_expressions(std::slice::from_ref(expression));
+    let expr = expressions[0].to_syn_expression();
+    if statements.is_empty() {
+        expr
+    } else {
+        parse_quote!(({
+            #(#statements)*
+            #expr
+        }))
+    }
+}
+
+/// Replaces subexpressions that occur more than once in `expressions` with bindings, returning the
+/// names and values of the bindings in the order they need to be computed
+fn bind_common_subexpressions(expressions: &mut [Expression]) -> Vec<(String, Expression)> {
+    let mut bindings: Vec<(String, Expression)> = vec![];
+
+    loop {
+        let mut occurrences = Occurrences::default();
+        for expression in expressions.iter() {
+            occurrences.add(expression, true);
+        }
+
+        // sharing the smallest expressions first means that the value of a binding can only
+        // reference the bindings before it
+        let Some(shared) = occurrences
+            .order
+            .iter()
+            .copied()
+            .filter(|e| {
+                let (count, unconditional) = occurrences.counts[*e];
+                // a subexpression is only computed up front if it would have been anyway, so that
+                // this doesn't introduce panics (e.g., dividing by zero) in branches not taken
+                count > 1 && unconditional
+            })
+            .min_by_key(|e| e.size())
+            .cloned()
+        else {
+            break;
+        };
+
+        let name = format!("__cse_{}", bindings.len());
+        let binding = Expression::Binding(BindingExpression {
+            name: name.clone(),
+            ret_type: shared.return_type(),
+        });
+        for expression in expressions.iter_mut() {
+            expression.replace(&shared, &binding);
+        }
+        bindings.push((name, shared));
+    }
+
+    // a subexpression that's only shared as part of a larger shared subexpression ends up being
+    // used once, by that one's value, so there's no need for it to have its own binding
+    for i in (0..bindings.len()).rev() {
+        let (name, value) = bindings[i].clone();
+        let binding = Expression::Binding(BindingExpression {
+            name,
+            ret_type: value.return_type(),
+        });
+
+        let uses: usize = expressions
+            .iter()
+            .chain(bindings[i + 1..].iter().map(|(_, e)| e))
+            .map(|e| e.occurrences_of(&binding))
+            .sum();
+
+        if uses == 1 {
+            for expression in expressions.iter_mut() {
+                expression.replace(&binding, &value);
+            }
+            for (_, expression) in bindings[i + 1..].iter_mut() {
+                expression.replace(&binding, &value);
+            }
+            bindings.remove(i);
+        }
+    }
+
+    bindings
+}
+
+/// The number of times each shareable subexpression occurs, and whether any of those occurrences
+/// is always evaluated, in the order they were first found
+#[derive(Default)]
+struct Occurrences<'a> {
+    counts: HashMap<&'a Expression, (usize, bool)>,
+    order: Vec<&'a Expression>,
+}
+
+impl<'a> Occurrences<'a> {
+    fn add(&mut self, expression: &'a Expression, unconditional: bool) {
+        if expression.is_shareable() {
+            if !self.counts.contains_key(expression) {
+                self.order.push(expression);
+            }
+            let (count, always) = self.counts.entry(expression).or_insert((0, false));
+            *count += 1;
+            *always |= unconditional;
+        }
+
+        for (child, evaluated) in expression.inlined_children() {
+            self.add(child, unconditional && evaluated);
+        }
+    }
+}
+
+impl Expression {
+    /// Folds constant operations in the expression, and removes repeated terms from its chains of
+    /// ANDs and ORs
+    fn fold(self) -> Expression {
+        match self {
+            Expression::UnaryBoolean(UnaryBooleanExpression { operator, input }) => {
+                fold_unary(operator, (*input).fold())
+            }
+            Expression::BinaryComparison(BinaryComparisonExpression { left, op, right }) => {
+                fold_comparison((*left).fold(), op, (*right).fold())
+            }
+            Expression::BinaryMath(BinaryMathExpression { left, op, right }) => {
+                fold_math((*left).fold(), op, (*right).fold())
+            }
+            Expression::StructField(StructFieldExpression {
+                struct_expression,
+                struct_field,
+            }) => Expression::StructField(StructFieldExpression {
+                struct_expression: Box::new((*struct_expression).fold()),
+                struct_field,
+            }),
+            Expression::Cast(CastExpression { input, data_type }) => {
+                Expression::Cast(CastExpression {
+                    input: Box::new((*input).fold()),
+                    data_type,
+                })
+            }
+            Expression::Numeric(NumericExpression { function, input }) => {
+                Expression::Numeric(NumericExpression {
+                    function,
+                    input: Box::new((*input).fold()),
+                })
+            }
+            expression => expression,
+        }
+    }
+
+    /// The expression's children that are compiled directly into its code, each with whether it's
+    /// evaluated whenever the expression is. This mirrors how each of these expressions is
+    /// compiled: a non-null operand of an operator whose other operand is nullable is only
+    /// evaluated within a `map` over the other, and `&&` and `||` short-circuit.
+    fn inlined_children(&self) -> Vec<(&Expression, bool)> {
+        fn operands<'a>(
+            left: &'a Expression,
+            right: &'a Expression,
+            short_circuits: bool,
+        ) -> Vec<(&'a Expression, bool)> {
+            match (left.nullable(), right.nullable()) {
+                (true, true) => vec![(left, true), (right, true)],
+                (true, false) => vec![(left, true), (right, false)],
+                (false, true) => vec![(left, false), (right, true)],
+                (false, false) => vec![(left, true), (right, !short_circuits)],
+            }
+        }
+
+        match self {
+            Expression::UnaryBoolean(UnaryBooleanExpression { operator, input }) => {
+                // null checks of non-nullable inputs are compiled to constants
+                let evaluated = input.nullable()
+                    || !matches!(
+                        operator,
+                        UnaryOperator::IsNull
+                            | UnaryOperator::IsNotNull
+                            | UnaryOperator::IsUnknown
+                            | UnaryOperator::IsNotUnknown
+                    );
+                vec![(input.as_ref(), evaluated)]
+            }
+            Expression::BinaryComparison(BinaryComparisonExpression { left, op, right }) => {
+                match op {
+                    BinaryComparison::IsDistinctFrom | BinaryComparison::IsNotDistinctFrom => {
+                        vec![(left.as_ref(), true), (right.as_ref(), true)]
+                    }
+                    BinaryComparison::And | BinaryComparison::Or => operands(left, right, true),
+                    _ => operands(left, right, false),
+                }
+            }
+            Expression::BinaryMath(BinaryMathExpression { left, right, .. }) => {
+                operands(left, right, false)
+            }
+            Expression::StructField(StructFieldExpression {
+                struct_expression, ..
+            }) => vec![(struct_expression.as_ref(), true)],
+            Expression::Cast(CastExpression { input, .. })
+            | Expression::Numeric(NumericExpression { input, .. }) => vec![(input.as_ref(), true)],
+            _ => vec![],
+        }
+    }
+
+    fn inlined_children_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::UnaryBoolean(UnaryBooleanExpression { input, .. })
+            | Expression::Cast(CastExpression { input, .. })
+            | Expression::Numeric(NumericExpression { input, .. }) => vec![input.as_mut()],
+            Expression::BinaryComparison(BinaryComparisonExpression { left, right, .. })
+            | Expression::BinaryMath(BinaryMathExpression { left, right, .. }) => {
+                vec![left.as_mut(), right.as_mut()]
+            }
+            Expression::StructField(StructFieldExpression {
+                struct_expression, ..
+            }) => vec![struct_expression.as_mut()],
+            _ => vec![],
+        }
+    }
+
+    /// All of the expression's children
+    fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Column(_) | Expression::Literal(_) | Expression::Binding(_) => vec![],
+            Expression::UnaryBoolean(_)
+            | Expression::BinaryComparison(_)
+            | Expression::BinaryMath(_)
+            | Expression::StructField(_)
+            | Expression::Cast(_)
+            | Expression::Numeric(_) => self
+                .inlined_children()
+                .into_iter()
+                .map(|(child, _)| child)
+                .collect(),
+            Expression::Aggregation(AggregationExpression {
+                producing_expression,
+                ..
+            }) => vec![producing_expression.as_ref()],
+            Expression::Date(function) => match function {
+                DateTimeFunction::DatePart(_, input)
+                | DateTimeFunction::DateTrunc(_, input)
+                | DateTimeFunction::FromUnixTime(input) => vec![input.as_ref()],
+            },
+            Expression::String(function) => function.children(),
+            Expression::Hash(HashExpression { input, .. })
+            | Expression::Crypto(CryptoExpression { input, .. }) => vec![input.as_ref()],
+            Expression::DataStructure(function) => match function {
+                DataStructureFunction::Coalesce(args) | DataStructureFunction::MakeArray(args) => {
+                    args.iter().collect()
+                }
+                DataStructureFunction::NullIf { left, right } => {
+                    vec![left.as_ref(), right.as_ref()]
+                }
+            },
+            Expression::Json(JsonExpression {
+                json_string, path, ..
+            }) => vec![json_string.as_ref(), path.as_ref()],
+            Expression::Network(NetworkExpression { args, .. })
+            | Expression::Extension(ExtensionExpression { args, .. }) => args.iter().collect(),
+            Expression::RustUdf(RustUdfExpression { args, .. }) => {
+                args.iter().map(|(_, arg)| arg).collect()
+            }
+            Expression::WrapType(WrapTypeExpression { arg, .. }) => vec![arg.as_ref()],
+            Expression::Case(case) => {
+                let (value, pairs, default) = match case {
+                    CaseExpression::Match {
+                        value,
+                        matches,
+                        default,
+                    } => (Some(value), matches, default),
+                    CaseExpression::When {
+                        condition_pairs,
+                        default,
+                    } => (None, condition_pairs, default),
+                };
+                value
+                    .into_iter()
+                    .chain(pairs.iter().flat_map(|(when, then)| [when, then]))
+                    .chain(default.iter())
+                    .map(|e| e.as_ref())
+                    .collect()
+            }
+        }
+    }
+
+    /// The fields of the input that the expression reads
+    pub(crate) fn referenced_fields(&self) -> Vec<&StructField> {
+        match self {
+            Expression::Column(ColumnExpression { column_field }) => vec![column_field],
+            _ => self
+                .children()
+                .into_iter()
+                .flat_map(|c| c.referenced_fields())
+                .collect(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        1 + self.children().iter().map(|c| c.size()).sum::<usize>()
+    }
+
+    /// Whether evaluating the expression has no effect besides computing its value, which doesn't
+    /// depend on where it's evaluated, so that it can be computed once for all of its occurrences
+    /// or not at all if the value isn't needed
+    fn is_pure(&self) -> bool {
+        match self {
+            // UDFs and extensions may have side effects, and aggregations are evaluated over
+            // the records of their group
+            Expression::RustUdf(_) | Expression::Extension(_) | Expression::Aggregation(_) => false,
+            _ => self.children().iter().all(|c| c.is_pure()),
+        }
+    }
+
+    fn is_shareable(&self) -> bool {
+        !matches!(
+            self,
+            Expression::Column(_) | Expression::Literal(_) | Expression::Binding(_)
+        ) && self.is_pure()
+    }
+
+    fn replace(&mut self, target: &Expression, replacement: &Expression) {
+        if self == target {
+            *self = replacement.clone();
+            return;
+        }
+
+        for child in self.inlined_children_mut() {
+            child.replace(target, replacement);
+        }
+    }
+
+    fn occurrences_of(&self, target: &Expression) -> usize {
+        if self == target {
+            return 1;
+        }
+
+        self.inlined_children()
+            .into_iter()
+            .map(|(child, _)| child.occurrences_of(target))
+            .sum()
+    }
+
+    fn non_null_literal(&self) -> Option<&ScalarValue> {
+        match self {
+            Expression::Literal(LiteralExpression { literal }) if !literal.is_null() => {
+                Some(literal)
+            }
+            _ => None,
+        }
+    }
+
+    fn bool_literal(&self) -> Option<bool> {
+        match self.non_null_literal() {
+            Some(ScalarValue::Boolean(Some(value))) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl StringFunction {
+    fn children(&self) -> Vec<&Expression> {
+        let (required, optional): (Vec<&Expression>, Option<&Expression>) = match self {
+            StringFunction::Concat(args) => return args.iter().collect(),
+            StringFunction::ConcatWithSeparator(separator, args) => {
+                return std::iter::once(separator.as_ref()).chain(args).collect()
+            }
+            StringFunction::Ascii(input)
+            | StringFunction::BitLength(input)
+            | StringFunction::CharacterLength(input)
+            | StringFunction::Chr(input)
+            | StringFunction::InitCap(input)
+            | StringFunction::Lower(input)
+            | StringFunction::OctetLength(input)
+            | StringFunction::Upper(input)
+            | StringFunction::Reverse(input)
+            | StringFunction::RegexpMatch(input, _) => (vec![input.as_ref()], None),
+            StringFunction::Btrim(input, trim)
+            | StringFunction::Ltrim(input, trim)
+            | StringFunction::Trim(input, trim)
+            | StringFunction::Rtrim(input, trim) => (vec![input.as_ref()], trim.as_deref()),
+            StringFunction::StartsWith(a, b)
+            | StringFunction::Strpos(a, b)
+            | StringFunction::Left(a, b)
+            | StringFunction::Repeat(a, b)
+            | StringFunction::Right(a, b) => (vec![a.as_ref(), b.as_ref()], None),
+            StringFunction::SplitPart(a, b, c)
+            | StringFunction::Translate(a, b, c)
+            | StringFunction::Replace(a, b, c) => (vec![a.as_ref(), b.as_ref(), c.as_ref()], None),
+            StringFunction::Substr(a, b, c)
+            | StringFunction::Lpad(a, b, c)
+            | StringFunction::Rpad(a, b, c) => (vec![a.as_ref(), b.as_ref()], c.as_deref()),
+            StringFunction::RegexpReplace(input, _, replacement, flags) => {
+                (vec![input.as_ref(), replacement.as_ref()], flags.as_deref())
+            }
+        };
+
+        required.into_iter().chain(optional).collect()
+    }
+}
+
+fn fold_unary(operator: UnaryOperator, input: Expression) -> Expression {
+    let value = match (&operator, input.nullable()) {
+        // these are compiled to constants without evaluating the input, which can't be null
+        (UnaryOperator::IsNotNull | UnaryOperator::IsNotUnknown, false) => {
+            Some(ScalarValue::Boolean(Some(true)))
+        }
+        (UnaryOperator::IsNull | UnaryOperator::IsUnknown, false) => {
+            Some(ScalarValue::Boolean(Some(false)))
+        }
+        (UnaryOperator::Negative, _) => input.non_null_literal().and_then(negate),
+        (UnaryOperator::IsTrue | UnaryOperator::IsNotFalse, _) => {
+            input.bool_literal().map(|b| ScalarValue::Boolean(Some(b)))
+        }
+        (UnaryOperator::IsFalse | UnaryOperator::IsNotTrue, _) => {
+            input.bool_literal().map(|b| ScalarValue::Boolean(Some(!b)))
+        }
+        _ => None,
+    };
+
+    match value {
+        Some(value) => LiteralExpression::new(value),
+        None => UnaryBooleanExpression::new(operator, Box::new(input)),
+    }
+}
+
+fn negate(value: &ScalarValue) -> Option<ScalarValue> {
+    match value {
+        ScalarValue::Int8(Some(v)) => v.checked_neg().map(|v| ScalarValue::Int8(Some(v))),
+        ScalarValue::Int16(Some(v)) => v.checked_neg().map(|v| ScalarValue::Int16(Some(v))),
+        ScalarValue::Int32(Some(v)) => v.checked_neg().map(|v| ScalarValue::Int32(Some(v))),
+        ScalarValue::Int64(Some(v)) => v.checked_neg().map(|v| ScalarValue::Int64(Some(v))),
+        ScalarValue::Float32(Some(v)) => Some(ScalarValue::Float32(Some(-v))),
+        ScalarValue::Float64(Some(v)) => Some(ScalarValue::Float64(Some(-v))),
+        _ => None,
+    }
+}
+
+fn fold_comparison(left: Expression, op: BinaryComparison, right: Expression) -> Expression {
+    if let (Some(l), Some(r)) = (left.non_null_literal(), right.non_null_literal()) {
+        if let Some(value) = compare(l, &op, r) {
+            return LiteralExpression::new(ScalarValue::Boolean(Some(value)));
+        }
+    }
+
+    let (identity, absorbing) = match op {
+        BinaryComparison::And => (true, false),
+        BinaryComparison::Or => (false, true),
+        _ => {
+            return Expression::BinaryComparison(BinaryComparisonExpression {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            })
+        }
+    };
+
+    if left.bool_literal() == Some(identity) {
+        return right;
+    }
+    if right.bool_literal() == Some(identity) {
+        return left;
+    }
+
+    // the other operand can only be dropped if the result doesn't become non-nullable, and it
+    // doesn't need to be evaluated
+    for (literal, other) in [(&left, &right), (&right, &left)] {
+        if literal.bool_literal() == Some(absorbing) && !other.nullable() && other.is_pure() {
+            return LiteralExpression::new(ScalarValue::Boolean(Some(absorbing)));
+        }
+    }
+
+    // AND and OR, as compiled, are associative, commutative and idempotent (a null operand makes
+    // the result null regardless of the other), so repeated terms can be removed as long as
+    // evaluating them has no side effects
+    let mut terms = vec![];
+    flatten(left.clone(), &op, &mut terms);
+    flatten(right.clone(), &op, &mut terms);
+
+    if terms.iter().all(|t| t.is_pure()) {
+        let count = terms.len();
+        let mut unique: Vec<Expression> = vec![];
+        for term in terms {
+            if !unique.contains(&term) {
+                unique.push(term);
+            }
+        }
+
+        if unique.len() < count {
+            return unique
+                .into_iter()
+                .reduce(|left, right| {
+                    Expression::BinaryComparison(BinaryComparisonExpression {
+                        left: Box::new(left),
+                        op: op.clone(),
+                        right: Box::new(right),
+                    })
+                })
+                .unwrap();
+        }
+    }
+
+    Expression::BinaryComparison(BinaryComparisonExpression {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    })
+}
+
+fn flatten(expression: Expression, op: &BinaryComparison, terms: &mut Vec<Expression>) {
+    match expression {
+        Expression::BinaryComparison(BinaryComparisonExpression {
+            left,
+            op: term_op,
+            right,
+        }) if term_op == *op => {
+            flatten(*left, op, terms);
+            flatten(*right, op, terms);
+        }
+        expression => terms.push(expression),
+    }
+}
+
+fn compare(left: &ScalarValue, op: &BinaryComparison, right: &ScalarValue) -> Option<bool> {
+    use std::cmp::Ordering;
+
+    // other types (e.g., floats and timestamps) don't necessarily compare the same way as literals
+    // as they do once compiled
+    let comparable = |v: &ScalarValue| {
+        matches!(
+            v,
+            ScalarValue::Boolean(_)
+                | ScalarValue::Int8(_)
+                | ScalarValue::Int16(_)
+                | ScalarValue::Int32(_)
+                | ScalarValue::Int64(_)
+                | ScalarValue::UInt8(_)
+                | ScalarValue::UInt16(_)
+                | ScalarValue::UInt32(_)
+                | ScalarValue::UInt64(_)
+                | ScalarValue::Utf8(_)
+        )
+    };
+    if !comparable(left) || !comparable(right) {
+        return None;
+    }
+
+    let ordering = left.partial_cmp(right)?;
+    let value = match op {
+        BinaryComparison::Eq | BinaryComparison::IsNotDistinctFrom => ordering == Ordering::Equal,
+        BinaryComparison::NotEq | BinaryComparison::IsDistinctFrom => ordering != Ordering::Equal,
+        BinaryComparison::Lt => ordering == Ordering::Less,
+        BinaryComparison::LtEq => ordering != Ordering::Greater,
+        BinaryComparison::Gt => ordering == Ordering::Greater,
+        BinaryComparison::GtEq => ordering != Ordering::Less,
+        BinaryComparison::And | BinaryComparison::Or => return None,
+    };
+    Some(value)
+}
+
+fn fold_math(left: Expression, op: BinaryMathOperator, right: Expression) -> Expression {
+    if let (Some(l), Some(r)) = (left.non_null_literal(), right.non_null_literal()) {
+        if let Some(value) = calculate(l, &op, r) {
+            return LiteralExpression::new(value);
+        }
+    }
+
+    Expression::BinaryMath(BinaryMathExpression {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    })
+}
+
+/// Computes the operation on two literals, if it's between numbers of the same type and doesn't
+/// overflow, divide by zero, or produce a non-finite float (which can't be a literal)
+fn calculate(
+    left: &ScalarValue,
+    op: &BinaryMathOperator,
+    right: &ScalarValue,
+) -> Option<ScalarValue> {
+    macro_rules! checked {
+        ($variant:ident, $l:expr, $r:expr) => {
+            match op {
+                BinaryMathOperator::Plus => $l.checked_add(*$r),
+                BinaryMathOperator::Minus => $l.checked_sub(*$r),
+                BinaryMathOperator::Multiply => $l.checked_mul(*$r),
+                BinaryMathOperator::Divide => $l.checked_div(*$r),
+                BinaryMathOperator::Modulo => $l.checked_rem(*$r),
+            }
+            .map(|v| ScalarValue::$variant(Some(v)))
+        };
+    }
+
+    macro_rules! float {
+        ($variant:ident, $l:expr, $r:expr) => {{
+            let v = match op {
+                BinaryMathOperator::Plus => $l + $r,
+                BinaryMathOperator::Minus => $l - $r,
+                BinaryMathOperator::Multiply => $l * $r,
+                BinaryMathOperator::Divide => $l / $r,
+                BinaryMathOperator::Modulo => $l % $r,
+            };
+            v.is_finite().then_some(ScalarValue::$variant(Some(v)))
+        }};
+    }
+
+    match (left, right) {
+        (ScalarValue::Int8(Some(l)), ScalarValue::Int8(Some(r))) => checked!(Int8, l, r),
+        (ScalarValue::Int16(Some(l)), ScalarValue::Int16(Some(r))) => checked!(Int16, l, r),
+        (ScalarValue::Int32(Some(l)), ScalarValue::Int32(Some(r))) => checked!(Int32, l, r),
+        (ScalarValue::Int64(Some(l)), ScalarValue::Int64(Some(r))) => checked!(Int64, l, r),
+        (ScalarValue::UInt8(Some(l)), ScalarValue::UInt8(Some(r))) => checked!(UInt8, l, r),
+        (ScalarValue::UInt16(Some(l)), ScalarValue::UInt16(Some(r))) => checked!(UInt16, l, r),
+        (ScalarValue::UInt32(Some(l)), ScalarValue::UInt32(Some(r))) => checked!(UInt32, l, r),
+        (ScalarValue::UInt64(Some(l)), ScalarValue::UInt64(Some(r))) => checked!(UInt64, l, r),
+        (ScalarValue::Float32(Some(l)), ScalarValue::Float32(Some(r))) => float!(Float32, l, r),
+        (ScalarValue::Float64(Some(l)), ScalarValue::Float64(Some(r))) => float!(Float64, l, r),
+        _ => None,
+    }
+}
@@ -0,0 +1,43 @@
+//! Scalar functions provided by a deployment rather than by users, such as a company-standard
+//! `anonymize_ip()`. A crate linked into the API registers its functions with [`register`] at
+//! startup, and every [`ArroyoSchemaProvider`](crate::ArroyoSchemaProvider) created afterwards
+//! makes them available to SQL queries alongside the built-in functions.
+
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use arrow::datatypes::DataType;
+use datafusion_expr::Expr;
+
+static EXTENSIONS: RwLock<Vec<Arc<dyn ScalarFunctionExtension>>> = RwLock::new(Vec::new());
+
+/// How a call to an extension function is compiled
+pub enum FunctionImplementation {
+    /// An equivalent expression, usually over built-in functions, that replaces the call
+    Rewrite(Expr),
+    /// Rust code that computes the result. The arguments are bound to `__0`, `__1`, and so on,
+    /// and are never null; if any argument is null, the result is null.
+    Rust(String),
+}
+
+pub trait ScalarFunctionExtension: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The types of the function's arguments, none of which are nullable
+    fn arg_types(&self) -> Vec<DataType>;
+
+    fn return_type(&self) -> DataType;
+
+    /// Compiles a call to the function with the given arguments
+    fn implement(&self, args: Vec<Expr>) -> Result<FunctionImplementation>;
+}
+
+/// Makes `function` available to SQL queries planned by schema providers created after this call
+pub fn register(function: impl ScalarFunctionExtension + 'static) {
+    EXTENSIONS.write().unwrap().push(Arc::new(function));
+}
+
+pub(crate) fn registered() -> Vec<Arc<dyn ScalarFunctionExtension>> {
+    EXTENSIONS.read().unwrap().clone()
+}
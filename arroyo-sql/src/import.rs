@@ -0,0 +1,1097 @@
+//! Translates job definitions written for Flink SQL or ksqlDB into Arroyo SQL, to make it easier
+//! to migrate existing jobs. Only a subset of each dialect is supported: tables (or streams) that
+//! read from and write to Kafka, and the queries over them, including windowed aggregations and
+//! joins. The Kafka clusters that tables use become connections, which the translated tables
+//! refer to by name.
+//!
+//! Translation is done on tokens rather than on a parsed AST, as neither dialect can be parsed as
+//! is. Anything that can't be translated is reported rather than silently dropped, and each
+//! translated statement is planned to check that it's valid Arroyo SQL.
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use arrow_schema::DataType;
+use datafusion::sql::sqlparser::{
+    dialect::{GenericDialect, PostgreSqlDialect},
+    keywords::Keyword,
+    parser::Parser,
+    tokenizer::{Token, Tokenizer, Word},
+};
+
+use crate::{
+    tables::{produce_optimized_plan, Table},
+    ArroyoSchemaProvider,
+};
+
+const KAFKA_CONNECTOR: &str = "kafka";
+// ksqlDB statements don't say which cluster they use, as it's configured on the server
+const KSQL_BOOTSTRAP_SERVERS: &str = "localhost:9092";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportDialect {
+    FlinkSql,
+    KsqlDb,
+}
+
+/// A connection that the translated query uses, which needs to be created before it can run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedConnection {
+    pub name: String,
+    pub connector: String,
+    pub config: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportedQuery {
+    pub query: String,
+    pub connections: Vec<ImportedConnection>,
+    /// Statements and constructs that couldn't be translated, or whose meaning changed
+    pub unsupported: Vec<String>,
+}
+
+pub fn import(dialect: ImportDialect, sql: &str) -> Result<ImportedQuery> {
+    let statements = split_statements(sql)?;
+    let mut importer = Importer::new(dialect, &statements);
+
+    for (i, statement) in statements.into_iter().enumerate() {
+        let n = i + 1;
+        match importer.statement(n, statement) {
+            Ok(Some(sql)) => {
+                importer.check(n, &sql);
+                importer.statements.push(sql);
+            }
+            Ok(None) => {}
+            Err(e) => importer.note(n, format!("not translated: {}", e)),
+        }
+    }
+
+    Ok(ImportedQuery {
+        query: importer
+            .statements
+            .iter()
+            .map(|s| format!("{};", s))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        connections: importer.connections,
+        unsupported: importer.unsupported,
+    })
+}
+
+struct Importer {
+    dialect: ImportDialect,
+    // the tables that are inserted into, which become sinks
+    sinks: HashSet<String>,
+    // the tables that each statement reads from
+    reads: Vec<HashSet<String>>,
+    connections: Vec<ImportedConnection>,
+    // the translated tables, for checking the statements that use them
+    planner: ArroyoSchemaProvider,
+    statements: Vec<String>,
+    unsupported: Vec<String>,
+}
+
+/// A CREATE TABLE or CREATE STREAM statement
+struct Create {
+    kind: String,
+    name: String,
+    elements: Vec<Vec<Token>>,
+    options: BTreeMap<String, String>,
+    query: Option<Vec<Token>>,
+}
+
+impl Importer {
+    fn new(dialect: ImportDialect, statements: &[Vec<Token>]) -> Self {
+        let mut sinks = HashSet::new();
+        let mut reads = vec![];
+
+        for statement in statements {
+            let mut read = HashSet::new();
+            for (i, token) in statement.iter().enumerate() {
+                let Some(Token::Word(next)) = statement.get(i + 1) else {
+                    continue;
+                };
+
+                if is_word(token, "FROM") || is_word(token, "JOIN") {
+                    read.insert(next.value.to_lowercase());
+                } else if is_word(token, "INTO") || is_word(token, "OVERWRITE") {
+                    sinks.insert(next.value.to_lowercase());
+                }
+            }
+            reads.push(read);
+        }
+
+        Self {
+            dialect,
+            sinks,
+            reads,
+            connections: vec![],
+            planner: ArroyoSchemaProvider::new(),
+            statements: vec![],
+            unsupported: vec![],
+        }
+    }
+
+    fn note(&mut self, n: usize, message: impl Into<String>) {
+        self.unsupported
+            .push(format!("statement {}: {}", n, message.into()));
+    }
+
+    /// Plans the translated statement, registering the tables it creates
+    fn check(&mut self, n: usize, sql: &str) {
+        let result = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .map_err(|e| anyhow!(e))
+            .and_then(|statements| {
+                for statement in statements {
+                    if let Some(table) = Table::try_from_statement(&statement, &self.planner)? {
+                        self.planner.insert_table(table);
+                    }
+                }
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            self.note(n, format!("the translated statement is invalid: {}", e));
+        }
+    }
+
+    fn statement(&mut self, n: usize, mut tokens: Vec<Token>) -> Result<Option<String>> {
+        // Flink statement sets are just a group of inserts, which is what every Arroyo query is
+        if starts_with(&tokens, &["EXECUTE", "STATEMENT", "SET", "BEGIN"]) {
+            tokens.drain(..4);
+        }
+        if starts_with(&tokens, &["BEGIN", "STATEMENT", "SET"]) || starts_with(&tokens, &["END"]) {
+            return Ok(None);
+        }
+
+        let first = match tokens.first() {
+            Some(Token::Word(w)) => w.value.to_uppercase(),
+            Some(other) => bail!("unexpected '{}'", other),
+            None => return Ok(None),
+        };
+
+        match first.as_str() {
+            "SET" | "RESET" | "UNSET" | "USE" | "LOAD" | "SHOW" | "DESCRIBE" | "EXPLAIN"
+            | "PRINT" | "DROP" | "TERMINATE" => {
+                self.note(
+                    n,
+                    format!("{} statements have no equivalent and were skipped", first),
+                );
+                Ok(None)
+            }
+            "CREATE" => {
+                let create = parse_create(&tokens)?;
+                match (self.dialect, create.kind.as_str()) {
+                    (_, "VIEW") => {
+                        let query = self.query(n, create.query.unwrap_or_default())?;
+                        Ok(Some(format!(
+                            "CREATE VIEW {} AS {}",
+                            create.name, query.sql
+                        )))
+                    }
+                    (ImportDialect::FlinkSql, "TABLE") if create.query.is_none() => {
+                        self.flink_table(n, create).map(Some)
+                    }
+                    (ImportDialect::KsqlDb, "STREAM") if create.query.is_none() => {
+                        self.ksql_stream(n, create).map(Some)
+                    }
+                    (ImportDialect::KsqlDb, "TABLE") if create.query.is_none() => bail!(
+                        "ksqlDB tables over changelog topics are not supported; declare {} as a \
+                        stream instead",
+                        create.name
+                    ),
+                    (ImportDialect::KsqlDb, "STREAM" | "TABLE") => {
+                        self.ksql_create_as(n, create).map(Some)
+                    }
+                    (_, kind) => bail!("CREATE {} is not supported", kind),
+                }
+            }
+            "INSERT" => {
+                let (Some(Token::Word(name)), true) = (
+                    tokens.get(2),
+                    is_word(&tokens[1], "INTO") || is_word(&tokens[1], "OVERWRITE"),
+                ) else {
+                    bail!("expected INSERT INTO <table>");
+                };
+                let name = render(&[Token::Word(name.clone())]);
+                let query = self.query(n, tokens[3..].to_vec())?;
+                Ok(Some(format!("INSERT INTO {} {}", name, query.sql)))
+            }
+            "SELECT" | "WITH" => Ok(Some(self.query(n, tokens)?.sql)),
+            other => bail!("{} statements are not supported", other),
+        }
+    }
+
+    /// The connection for a Kafka cluster, which is created the first time it's used
+    fn connection(&mut self, bootstrap_servers: &str) -> String {
+        let config = serde_json::json!({
+            "bootstrapServers": bootstrap_servers,
+            "authentication": {},
+        })
+        .to_string();
+
+        if let Some(c) = self.connections.iter().find(|c| c.config == config) {
+            return c.name.clone();
+        }
+
+        let name = match self.connections.len() {
+            0 => KAFKA_CONNECTOR.to_string(),
+            n => format!("{}_{}", KAFKA_CONNECTOR, n + 1),
+        };
+        self.planner
+            .add_saved_connection(&name, KAFKA_CONNECTOR, &config);
+        self.connections.push(ImportedConnection {
+            name: name.clone(),
+            connector: KAFKA_CONNECTOR.to_string(),
+            config,
+        });
+
+        name
+    }
+
+    fn table_type(&mut self, n: usize, name: &str) -> &'static str {
+        let name = unquoted(name);
+        if !self.sinks.contains(&name) {
+            return "source";
+        }
+
+        if self.reads.iter().any(|r| r.contains(&name)) {
+            self.note(
+                n,
+                format!(
+                    "{} is both read from and inserted into; Arroyo tables are either sources or \
+                    sinks, so it was translated as a sink",
+                    name
+                ),
+            );
+        }
+        "sink"
+    }
+
+    fn flink_table(&mut self, n: usize, mut create: Create) -> Result<String> {
+        let options = &mut create.options;
+        match options.remove("connector").as_deref() {
+            Some(KAFKA_CONNECTOR) => {}
+            Some(other) => bail!("the '{}' connector is not supported", other),
+            None => bail!("tables without a connector are not supported"),
+        }
+
+        let topic = options.remove("topic").ok_or_else(|| {
+            anyhow!("Kafka tables must have a topic ('topic-pattern' is not supported)")
+        })?;
+        let bootstrap_servers = options
+            .remove("properties.bootstrap.servers")
+            .ok_or_else(|| anyhow!("Kafka tables must set 'properties.bootstrap.servers'"))?;
+
+        let format = options
+            .remove("format")
+            .or_else(|| options.remove("value.format"));
+        let format = match format.as_deref() {
+            Some("json") => "json",
+            Some("raw") => "raw_string",
+            Some("debezium-json") => "debezium_json",
+            Some(other) => bail!("the '{}' format is not supported", other),
+            None => bail!("Kafka tables must have a format"),
+        };
+        let permissive = options.remove("json.ignore-parse-errors").as_deref() == Some("true");
+
+        let offset = match options.remove("scan.startup.mode").as_deref() {
+            Some("earliest-offset") => Some("earliest"),
+            Some("latest-offset") => Some("latest"),
+            Some(other) => {
+                self.note(
+                    n,
+                    format!(
+                        "scan.startup.mode '{}' is not supported; {} will start from the latest \
+                        offset",
+                        other, create.name
+                    ),
+                );
+                None
+            }
+            None => None,
+        };
+
+        if !create.options.is_empty() {
+            let ignored: Vec<_> = create.options.keys().cloned().collect();
+            self.note(
+                n,
+                format!(
+                    "the options {} of {} were ignored",
+                    ignored.join(", "),
+                    create.name
+                ),
+            );
+        }
+
+        let typ = self.table_type(n, &create.name);
+        let mut columns = vec![];
+        let mut event_time = None;
+        for element in &create.elements {
+            if is_word(&element[0], "WATERMARK") {
+                event_time = Some(self.flink_watermark(element)?);
+            } else if is_word(&element[0], "PRIMARY") || is_word(&element[0], "CONSTRAINT") {
+                self.note(n, format!("the primary key of {} was ignored", create.name));
+            } else if let Some(column) = self.column(n, &create.name, element) {
+                columns.push(column);
+            }
+        }
+
+        let mut with = vec![
+            ("connection", self.connection(&bootstrap_servers)),
+            ("type", typ.to_string()),
+            ("topic", topic),
+            ("format", format.to_string()),
+        ];
+        if let Some(offset) = offset.filter(|_| typ == "source") {
+            with.push(("source.offset", offset.to_string()));
+        }
+        if permissive && typ == "source" {
+            with.push(("bad_data", "permissive".to_string()));
+        }
+
+        match event_time {
+            Some(_) if typ == "sink" => {
+                self.note(
+                    n,
+                    format!("the watermark of sink {} was ignored", create.name),
+                );
+            }
+            Some((field, watermark)) => {
+                with.push(("event_time_field", field));
+                if let Some(expr) = watermark {
+                    let mut name = "watermark".to_string();
+                    while columns.iter().any(|c| c.starts_with(&format!("{} ", name))) {
+                        name.insert(0, '_');
+                    }
+                    columns.push(format!("{} TIMESTAMP GENERATED ALWAYS AS ({})", name, expr));
+                    with.push(("watermark_field", name));
+                }
+            }
+            None => {}
+        }
+
+        create_table(&create.name, &columns, &with)
+    }
+
+    /// The event time field of `WATERMARK FOR field AS expr`, and the expression if the
+    /// watermark isn't the field itself
+    fn flink_watermark(&self, element: &[Token]) -> Result<(String, Option<String>)> {
+        let (Some(Token::Word(field)), true, true) = (
+            element.get(2),
+            element.get(1).map_or(false, |t| is_word(t, "FOR")),
+            element.get(3).map_or(false, |t| is_word(t, "AS")),
+        ) else {
+            bail!("expected WATERMARK FOR <column> AS <expression>");
+        };
+
+        let expr = render(&element[4..]);
+        let is_field = expr == render(&[Token::Word(field.clone())]);
+        // unquoted identifiers are lowercased, as they are in the column definitions
+        let field = match field.quote_style {
+            Some(_) => field.value.clone(),
+            None => field.value.to_lowercase(),
+        };
+        Ok((field, (!is_field).then_some(expr)))
+    }
+
+    fn ksql_stream(&mut self, n: usize, mut create: Create) -> Result<String> {
+        let options = &mut create.options;
+        let topic = options
+            .remove("kafka_topic")
+            .ok_or_else(|| anyhow!("streams must set KAFKA_TOPIC"))?;
+        let format = ksql_format(options)?;
+        let timestamp = options.remove("timestamp");
+        options.remove("partitions");
+        options.remove("replicas");
+        options.remove("key_format");
+
+        if !create.options.is_empty() {
+            let ignored: Vec<_> = create.options.keys().cloned().collect();
+            self.note(
+                n,
+                format!(
+                    "the options {} of {} were ignored",
+                    ignored.join(", "),
+                    create.name
+                ),
+            );
+        }
+
+        let mut columns = vec![];
+        for element in &create.elements {
+            if element
+                .iter()
+                .any(|t| is_word(t, "KEY") || is_word(t, "HEADERS"))
+            {
+                self.note(
+                    n,
+                    format!(
+                        "key and header columns are not supported, so {} was dropped from {}",
+                        render(&element[..1]),
+                        create.name
+                    ),
+                );
+            } else if let Some(column) = self.column(n, &create.name, element) {
+                columns.push(column);
+            }
+        }
+
+        let typ = self.table_type(n, &create.name);
+        let connection = self.ksql_connection(n);
+        let mut with = vec![
+            ("connection", connection),
+            ("type", typ.to_string()),
+            ("topic", topic),
+            ("format", format.to_string()),
+        ];
+
+        if let Some(timestamp) = timestamp.filter(|_| typ == "source") {
+            let is_timestamp = columns
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(&format!("{} TIMESTAMP", timestamp)));
+            if is_timestamp {
+                with.push(("event_time_field", timestamp.to_lowercase()));
+            } else {
+                self.note(
+                    n,
+                    format!(
+                        "the TIMESTAMP column of {} must have type TIMESTAMP to be used as its \
+                        event time",
+                        create.name
+                    ),
+                );
+            }
+        }
+
+        create_table(&create.name, &columns, &with)
+    }
+
+    /// CREATE STREAM or CREATE TABLE AS SELECT, which writes the query's results to a topic
+    fn ksql_create_as(&mut self, n: usize, mut create: Create) -> Result<String> {
+        let query = self.query(n, create.query.take().unwrap())?;
+
+        // streams that are read by later queries become views, as Arroyo sinks can't be read
+        let name = unquoted(&create.name);
+        if self.reads[n..].iter().any(|r| r.contains(&name)) {
+            self.note(
+                n,
+                format!(
+                    "{} is read by other queries, so it was translated as a view, which isn't \
+                    written to Kafka",
+                    create.name
+                ),
+            );
+            return Ok(format!("CREATE VIEW {} AS {}", create.name, query.sql));
+        }
+
+        let options = &mut create.options;
+        let topic = options
+            .remove("kafka_topic")
+            .unwrap_or_else(|| create.name.to_uppercase());
+        // aggregations that aren't windowed produce updates
+        let format = if create.kind == "TABLE" && !query.windowed {
+            "debezium_json"
+        } else {
+            ksql_format(options)?
+        };
+
+        let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, &query.sql)?;
+        let plan = produce_optimized_plan(&statements.remove(0), &self.planner)
+            .map_err(|e| anyhow!("failed to plan the query of {}: {}", create.name, e))?;
+        let mut columns = vec![];
+        for field in plan.schema().fields() {
+            let Some(typ) = sql_type(field.data_type()) else {
+                bail!(
+                    "column {} of {} has type {}, which is not supported",
+                    field.name(),
+                    create.name,
+                    field.data_type()
+                );
+            };
+            columns.push(format!("{} {}", ident(field.name()), typ));
+        }
+
+        let with = vec![
+            ("connection", self.ksql_connection(n)),
+            ("type", "sink".to_string()),
+            ("topic", topic),
+            ("format", format.to_string()),
+        ];
+
+        Ok(format!(
+            "{};\n\nINSERT INTO {} {}",
+            create_table(&create.name, &columns, &with)?,
+            create.name,
+            query.sql
+        ))
+    }
+
+    fn ksql_connection(&mut self, n: usize) -> String {
+        if self.connections.is_empty() {
+            self.note(
+                n,
+                format!(
+                    "ksqlDB statements don't include the Kafka cluster, so connection '{}' uses \
+                    {}",
+                    KAFKA_CONNECTOR, KSQL_BOOTSTRAP_SERVERS
+                ),
+            );
+        }
+        self.connection(KSQL_BOOTSTRAP_SERVERS)
+    }
+
+    /// A column definition, or None if its type isn't supported
+    fn column(&mut self, n: usize, table: &str, element: &[Token]) -> Option<String> {
+        let name = render(&element[..1]);
+        let rest = &element[1..];
+
+        if rest.first().map_or(true, |t| is_word(t, "AS"))
+            || rest.iter().any(|t| is_word(t, "METADATA"))
+        {
+            self.note(
+                n,
+                format!(
+                    "computed and metadata columns are not supported, so {} was dropped from {}",
+                    name, table
+                ),
+            );
+            return None;
+        }
+
+        let typ = match rest[0].to_string().to_uppercase().as_str() {
+            "STRING" | "VARCHAR" | "CHAR" => "TEXT",
+            "BIGINT" => "BIGINT",
+            // connection tables don't support smaller integers
+            "INT" | "INTEGER" | "SMALLINT" | "TINYINT" => "INT",
+            "BOOLEAN" => "BOOLEAN",
+            "DOUBLE" => "DOUBLE",
+            "FLOAT" | "REAL" => "FLOAT",
+            "TIMESTAMP" | "TIMESTAMP_LTZ" => "TIMESTAMP",
+            "BYTES" | "VARBINARY" | "BINARY" => "BYTEA",
+            "DECIMAL" | "NUMERIC" => {
+                self.note(
+                    n,
+                    format!(
+                        "{} of {} was translated as DOUBLE, as decimals are not supported",
+                        name, table
+                    ),
+                );
+                "DOUBLE"
+            }
+            _ => {
+                self.note(
+                    n,
+                    format!(
+                        "{} of {} has type {}, which is not supported, so it was dropped",
+                        name,
+                        table,
+                        render(rest)
+                    ),
+                );
+                return None;
+            }
+        };
+
+        let not_null = rest
+            .windows(2)
+            .any(|w| is_word(&w[0], "NOT") && is_word(&w[1], "NULL"));
+        Some(if not_null {
+            format!("{} {} NOT NULL", name, typ)
+        } else {
+            format!("{} {}", name, typ)
+        })
+    }
+
+    fn query(&mut self, n: usize, tokens: Vec<Token>) -> Result<TranslatedQuery> {
+        match self.dialect {
+            ImportDialect::FlinkSql => flink_query(tokens),
+            ImportDialect::KsqlDb => self.ksql_query(n, tokens),
+        }
+    }
+
+    fn ksql_query(&mut self, n: usize, tokens: Vec<Token>) -> Result<TranslatedQuery> {
+        let mut result = vec![];
+        let mut window = None;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let next = tokens.get(i + 1);
+
+            if *token == Token::Arrow {
+                // struct fields are accessed with ->
+                result.push(Token::Period);
+            } else if is_word(token, "EMIT") {
+                i += 1;
+            } else if is_word(token, "WINDOW") && tokens.get(i + 2) == Some(&Token::LParen) {
+                let kind = next.unwrap().to_string().to_uppercase();
+                let (args, end) = group(&tokens, i + 2)?;
+                window = Some(self.ksql_window(n, &kind, &args)?);
+                i = end;
+                continue;
+            } else if is_word(token, "PARTITION") && next.map_or(false, |t| is_word(t, "BY")) {
+                self.note(n, "PARTITION BY was ignored");
+                i += 2;
+                while i < tokens.len() && !is_word(&tokens[i], "EMIT") {
+                    i += 1;
+                }
+                continue;
+            } else if is_word(token, "WITHIN") {
+                self.note(
+                    n,
+                    "join windows are not supported; the join matches records at any distance in \
+                    time",
+                );
+                i = if next == Some(&Token::LParen) {
+                    group(&tokens, i + 1)?.1
+                } else {
+                    i + 3
+                };
+                if tokens.get(i).map_or(false, |t| is_word(t, "GRACE")) {
+                    i += 4;
+                }
+                continue;
+            } else if [
+                "WINDOWSTART",
+                "WINDOWEND",
+                "ROWTIME",
+                "ROWPARTITION",
+                "ROWOFFSET",
+            ]
+            .iter()
+            .any(|w| is_word(token, w))
+            {
+                bail!("{} is not supported", token);
+            } else {
+                result.push(token.clone());
+            }
+
+            i += 1;
+        }
+
+        let windowed = window.is_some();
+        if let Some(mut window) = window {
+            let group_by = top_level(&result)
+                .find(|i| {
+                    is_word(&result[*i], "GROUP")
+                        && result.get(*i + 1).map_or(false, |t| is_word(t, "BY"))
+                })
+                .ok_or_else(|| anyhow!("windows are only supported in aggregations"))?;
+            window.push(Token::Comma);
+            result.splice(group_by + 2..group_by + 2, window);
+        }
+
+        Ok(TranslatedQuery {
+            sql: render(&result),
+            windowed,
+        })
+    }
+
+    /// The window function for a ksqlDB WINDOW clause
+    fn ksql_window(&mut self, n: usize, kind: &str, args: &[Token]) -> Result<Vec<Token>> {
+        if kind != "TUMBLING" && kind != "HOPPING" {
+            bail!("{} windows are not supported", kind.to_lowercase());
+        }
+
+        let mut size = None;
+        let mut advance = None;
+        for arg in split(args, &Token::Comma) {
+            let words: Vec<_> = arg.iter().map(|t| t.to_string().to_uppercase()).collect();
+            match words
+                .iter()
+                .map(|w| w.as_str())
+                .collect::<Vec<_>>()
+                .as_slice()
+            {
+                ["SIZE", count, unit] => size = Some(interval(count, unit)?),
+                ["ADVANCE", "BY", count, unit] => advance = Some(interval(count, unit)?),
+                ["RETENTION", ..] | ["GRACE", "PERIOD", ..] => self.note(
+                    n,
+                    "window retention and grace periods were ignored; Arroyo windows close once \
+                    the watermark passes them",
+                ),
+                _ => bail!("unexpected window argument '{}'", render(arg)),
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("windows must have a SIZE"))?;
+        let mut call = match (kind, advance) {
+            ("TUMBLING", _) => vec![Token::make_word("tumble", None), Token::LParen],
+            (_, Some(mut advance)) => {
+                let mut call = vec![Token::make_word("hop", None), Token::LParen];
+                call.append(&mut advance);
+                call.push(Token::Comma);
+                call
+            }
+            (_, None) => bail!("hopping windows must have an ADVANCE BY"),
+        };
+        call.extend(size);
+        call.push(Token::RParen);
+
+        Ok(call)
+    }
+}
+
+struct TranslatedQuery {
+    sql: String,
+    windowed: bool,
+}
+
+fn flink_query(tokens: Vec<Token>) -> Result<TranslatedQuery> {
+    let mut result = vec![];
+    let mut windowed = false;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let next = tokens.get(i + 1);
+
+        if let (Token::Word(w), Some(Token::LParen)) = (token, next) {
+            match w.value.to_uppercase().as_str() {
+                // the first argument is the time attribute, but Arroyo windows are always over
+                // event time
+                "TUMBLE" | "HOP" => {
+                    let (args, _) = group(&tokens, i + 1)?;
+                    let time = split(&args, &Token::Comma)[0].len();
+                    if time == args.len() {
+                        bail!("expected {}(<time>, ...)", w.value);
+                    }
+                    result.push(Token::make_word(&w.value.to_lowercase(), None));
+                    result.push(Token::LParen);
+                    windowed = true;
+                    // skip the time argument and the comma after it
+                    i += time + 3;
+                    continue;
+                }
+                "TABLE" => bail!(
+                    "window table-valued functions are not supported; use GROUP BY TUMBLE(...) or \
+                    HOP(...) instead"
+                ),
+                f @ ("SESSION" | "CUMULATE" | "TUMBLE_START" | "TUMBLE_END" | "TUMBLE_ROWTIME"
+                | "HOP_START" | "HOP_END" | "HOP_ROWTIME" | "SESSION_START"
+                | "SESSION_END" | "PROCTIME" | "CURRENT_WATERMARK") => {
+                    bail!("{} is not supported", f)
+                }
+                _ => {}
+            }
+        }
+
+        if is_word(token, "MATCH_RECOGNIZE") {
+            bail!("MATCH_RECOGNIZE is not supported");
+        }
+        if is_word(token, "FOR") && next.map_or(false, |t| is_word(t, "SYSTEM_TIME")) {
+            bail!("temporal joins are not supported");
+        }
+
+        result.push(token.clone());
+        i += 1;
+    }
+
+    Ok(TranslatedQuery {
+        sql: render(&result),
+        windowed,
+    })
+}
+
+fn parse_create(tokens: &[Token]) -> Result<Create> {
+    let mut i = 1;
+    while ["OR", "REPLACE", "TEMPORARY", "SOURCE"]
+        .iter()
+        .any(|w| tokens.get(i).map_or(false, |t| is_word(t, w)))
+    {
+        i += 1;
+    }
+
+    let kind = tokens
+        .get(i)
+        .ok_or_else(|| anyhow!("expected CREATE TABLE"))?
+        .to_string()
+        .to_uppercase();
+    i += 1;
+
+    if starts_with(&tokens[i..], &["IF", "NOT", "EXISTS"]) {
+        i += 3;
+    }
+
+    // catalog and database qualifiers are dropped
+    let mut name = None;
+    while let Some(Token::Word(w)) = tokens.get(i) {
+        if ["WITH", "AS", "COMMENT"]
+            .iter()
+            .any(|k| w.value.eq_ignore_ascii_case(k))
+        {
+            break;
+        }
+        name = Some(render(&[Token::Word(w.clone())]));
+        i += 1;
+        if tokens.get(i) == Some(&Token::Period) {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    let name = name.ok_or_else(|| anyhow!("expected a name after CREATE {}", kind))?;
+
+    let mut create = Create {
+        kind,
+        name,
+        elements: vec![],
+        options: BTreeMap::new(),
+        query: None,
+    };
+
+    if tokens.get(i) == Some(&Token::LParen) {
+        let (elements, end) = group(tokens, i)?;
+        create.elements = split_elements(&elements);
+        i = end;
+    }
+
+    while let Some(token) = tokens.get(i) {
+        if is_word(token, "COMMENT") {
+            i += 2;
+        } else if is_word(token, "WITH") {
+            let (options, end) = group(tokens, i + 1)?;
+            for option in split(&options, &Token::Comma) {
+                let [key, Token::Eq, value] = option else {
+                    bail!("invalid option '{}'", render(option));
+                };
+                create
+                    .options
+                    .insert(unquote(key).to_lowercase(), unquote(value));
+            }
+            i = end;
+        } else if is_word(token, "AS") {
+            create.query = Some(tokens[i + 1..].to_vec());
+            break;
+        } else {
+            bail!("'{}' is not supported in CREATE {}", token, create.kind);
+        }
+    }
+
+    Ok(create)
+}
+
+fn ksql_format(options: &mut BTreeMap<String, String>) -> Result<&'static str> {
+    let format = options
+        .remove("value_format")
+        .or_else(|| options.remove("format"));
+    match format.map(|f| f.to_uppercase()).as_deref() {
+        Some("JSON") | None => Ok("json"),
+        Some(other) => bail!("the {} format is not supported", other),
+    }
+}
+
+fn create_table(name: &str, columns: &[String], with: &[(&str, String)]) -> Result<String> {
+    if columns.is_empty() {
+        bail!("none of the columns of {} are supported", name);
+    }
+
+    let columns: Vec<_> = columns.iter().map(|c| format!("  {}", c)).collect();
+    let with: Vec<_> = with
+        .iter()
+        .map(|(k, v)| format!("  {} = '{}'", k, v.replace('\'', "''")))
+        .collect();
+
+    Ok(format!(
+        "CREATE TABLE {} (\n{}\n) WITH (\n{}\n)",
+        name,
+        columns.join(",\n"),
+        with.join(",\n")
+    ))
+}
+
+/// The SQL type to declare a column of the given type with
+fn sql_type(data_type: &DataType) -> Option<&'static str> {
+    Some(match data_type {
+        DataType::Boolean => "BOOLEAN",
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => "INT",
+        DataType::Int64 | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 => "BIGINT",
+        DataType::Float32 => "FLOAT",
+        DataType::Float64 => "DOUBLE",
+        DataType::Utf8 => "TEXT",
+        DataType::Binary => "BYTEA",
+        DataType::Timestamp(_, None) => "TIMESTAMP",
+        _ => return None,
+    })
+}
+
+fn interval(count: &str, unit: &str) -> Result<Vec<Token>> {
+    let count: u64 = count
+        .parse()
+        .map_err(|_| anyhow!("invalid window size '{} {}'", count, unit))?;
+    let unit = unit.trim_end_matches('S').to_lowercase();
+    if !["millisecond", "second", "minute", "hour", "day"].contains(&unit.as_str()) {
+        bail!("invalid window unit '{}'", unit);
+    }
+
+    Ok(vec![
+        Token::make_keyword("INTERVAL"),
+        Token::SingleQuotedString(format!("{} {}", count, unit)),
+    ])
+}
+
+/// Splits the statements, dropping whitespace and comments
+fn split_statements(sql: &str) -> Result<Vec<Vec<Token>>> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql)
+        .tokenize()
+        .map_err(|e| anyhow!("failed to parse SQL: {}", e))?;
+
+    let tokens: Vec<_> = tokens
+        .into_iter()
+        .filter(|t| !matches!(t, Token::Whitespace(_) | Token::EOF))
+        .collect();
+
+    Ok(split(&tokens, &Token::SemiColon)
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_vec())
+        .collect())
+}
+
+/// The indices of the tokens that aren't inside parentheses
+fn top_level(tokens: &[Token]) -> impl Iterator<Item = usize> + '_ {
+    let mut depth = 0;
+    tokens.iter().enumerate().filter_map(move |(i, t)| {
+        match t {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            _ => return (depth == 0).then_some(i),
+        }
+        None
+    })
+}
+
+/// Splits the tokens on a separator that isn't inside parentheses
+fn split<'a>(tokens: &'a [Token], separator: &Token) -> Vec<&'a [Token]> {
+    let mut parts = vec![];
+    let mut start = 0;
+    for i in top_level(tokens) {
+        if tokens[i] == *separator {
+            parts.push(&tokens[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+/// Splits column definitions, which may have generic types like `MAP<STRING, INT>`
+fn split_elements(tokens: &[Token]) -> Vec<Vec<Token>> {
+    let mut elements = vec![vec![]];
+    let mut depth = 0;
+    for token in tokens {
+        match token {
+            Token::LParen | Token::Lt => depth += 1,
+            Token::RParen | Token::Gt => depth -= 1,
+            Token::ShiftRight => depth -= 2,
+            Token::Comma if depth == 0 => {
+                elements.push(vec![]);
+                continue;
+            }
+            _ => {}
+        }
+        elements.last_mut().unwrap().push(token.clone());
+    }
+
+    elements.retain(|e| !e.is_empty());
+    elements
+}
+
+/// The contents of the parentheses that open at `start`, and the index after they close
+fn group(tokens: &[Token], start: usize) -> Result<(Vec<Token>, usize)> {
+    if tokens.get(start) != Some(&Token::LParen) {
+        bail!("expected '('");
+    }
+
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((tokens[start + 1..i].to_vec(), i + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    bail!("unbalanced parentheses")
+}
+
+fn is_word(token: &Token, word: &str) -> bool {
+    matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word))
+}
+
+fn starts_with(tokens: &[Token], words: &[&str]) -> bool {
+    tokens.len() >= words.len() && tokens.iter().zip(words).all(|(t, w)| is_word(t, w))
+}
+
+/// A table name as it's looked up in `Importer::sinks` and `Importer::reads`
+fn unquoted(name: &str) -> String {
+    name.trim_matches('"').to_lowercase()
+}
+
+fn unquote(token: &Token) -> String {
+    match token {
+        Token::SingleQuotedString(s) => s.clone(),
+        Token::Word(w) => w.value.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// An identifier for a column name, quoted if necessary
+fn ident(name: &str) -> String {
+    let plain = name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// Formats tokens as SQL, with identifiers quoted as Postgres quotes them
+fn render(tokens: &[Token]) -> String {
+    let mut sql = String::new();
+    let mut previous: Option<&Token> = None;
+
+    for token in tokens {
+        let space = match (previous, token) {
+            (None, _) => false,
+            (_, Token::Comma | Token::RParen | Token::Period) => false,
+            (Some(Token::LParen | Token::Period), _) => false,
+            // function calls
+            (Some(Token::Word(w)), Token::LParen) => {
+                w.quote_style.is_some() || (w.keyword != Keyword::NoKeyword && !is_function(w))
+            }
+            _ => true,
+        };
+        if space {
+            sql.push(' ');
+        }
+
+        match token {
+            Token::Word(w) if w.quote_style.is_some() => {
+                sql.push_str(&Token::make_word(&w.value, Some('"')).to_string())
+            }
+            token => sql.push_str(&token.to_string()),
+        }
+        previous = Some(token);
+    }
+
+    sql
+}
+
+fn is_function(word: &Word) -> bool {
+    ![
+        "AS", "IN", "ON", "AND", "OR", "NOT", "EXISTS", "FROM", "JOIN", "WHERE", "SELECT", "BY",
+        "VALUES", "USING", "OVER", "WITH", "INTO", "TABLE",
+    ]
+    .iter()
+    .any(|k| word.value.eq_ignore_ascii_case(k))
+}
@@ -10,6 +10,7 @@ use syn::{parse_str, Type};
 use tracing::warn;
 use typify::{TypeDetails, TypeSpace, TypeSpaceSettings};
 
+use crate::tables::source_fields;
 use crate::types::{StructDef, StructField, TypeDef};
 
 pub const ROOT_NAME: &str = "ArroyoJsonRoot";
@@ -71,7 +72,12 @@ pub fn convert_json_schema(name: &str, schema: &str) -> Result<Vec<StructField>,
     }
 }
 
-pub fn get_defs(source_name: &str, schema: &str) -> Result<String, String> {
+pub fn get_defs(
+    source_name: &str,
+    schema: &str,
+    permissive: bool,
+    lineage: bool,
+) -> Result<String, String> {
     fn add_defs(name: &str, fields: &Vec<StructField>, defs: &mut Vec<String>) {
         let struct_fields: Vec<_> = fields.iter().map(|f| {
             let mut serde_opts = vec![];
@@ -106,11 +112,11 @@ pub fn get_defs(source_name: &str, schema: &str) -> Result<String, String> {
                 serde_opts.push(opt);
             };
 
-            if let Some(rename) = &f.renamed_from {
+            if let Some(rename) = f.serde_rename() {
                 serde_opts.push(quote!(#[serde(rename = #rename)]));
             }
 
-            let name = format_ident!("{}", f.name);
+            let name = f.field_ident();
             let typ = match &f.data_type {
                 TypeDef::DataType(dt, _) => StructField::data_type_name(dt),
                 TypeDef::StructDef(sd, _) => {
@@ -147,7 +153,11 @@ pub fn get_defs(source_name: &str, schema: &str) -> Result<String, String> {
         }.to_string());
     }
 
-    let fields = convert_json_schema(source_name, schema)?;
+    let fields = source_fields(
+        convert_json_schema(source_name, schema)?,
+        permissive,
+        lineage,
+    );
 
     let mut defs: Vec<String> = vec![];
 
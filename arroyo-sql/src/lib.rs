@@ -7,23 +7,38 @@ use arroyo_connectors::kafka::{KafkaConfig, KafkaConnector, KafkaTable};
 use arroyo_connectors::{Connection, Connector};
 use arroyo_datastream::Program;
 use arroyo_rpc::grpc::api::{ConnectionSchema, Format, FormatOptions};
+use arroyo_types::UdfSandbox;
+use assertions::TableAssertion;
 use datafusion::physical_plan::functions::make_scalar_function;
 
+mod assertions;
+pub mod avro;
 mod expressions;
+pub mod extensions;
 pub mod external;
+pub mod import;
 pub mod json_schema;
+mod lints;
+pub mod masking;
 mod operators;
 mod optimizations;
+mod output_schemas;
 mod pipeline;
 mod plan_graph;
+mod process;
+pub mod protobuf;
 pub mod schemas;
 mod tables;
 pub mod types;
+mod udfs;
 
 use datafusion::prelude::create_udf;
 
+use datafusion::sql::planner::{ParserOptions, SqlToRel};
+use datafusion::sql::sqlparser::ast::{Ident, ObjectName};
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::sqlparser::parser::Parser;
+use datafusion::sql::sqlparser::tokenizer::Tokenizer;
 use datafusion::sql::{planner::ContextProvider, TableReference};
 
 use datafusion_expr::{
@@ -33,17 +48,25 @@ use datafusion_expr::{
     AccumulatorFunctionImplementation, LogicalPlan, ReturnTypeFunction, Signature,
     StateTypeFunction, TypeSignature, Volatility,
 };
-use expressions::{Expression, ExpressionContext};
+use expressions::{
+    optimize_expressions, to_optimized_syn_expression, Expression, ExpressionContext,
+};
+use extensions::ScalarFunctionExtension;
+pub use lints::SqlWarning;
+use masking::MaskingPolicy;
+pub use output_schemas::{SchemaField, SinkSchema};
 use pipeline::{SqlOperator, SqlPipelineBuilder};
 use plan_graph::{get_program, PlanGraph};
+use process::ProcessFunctionDef;
 use schemas::window_arrow_struct;
-use tables::{schema_defs, ConnectorTable, Insert, Table};
+use tables::{is_lineage, is_permissive, schema_defs, ConnectorTable, Insert, Table};
 
 use crate::types::{StructDef, StructField, TypeDef};
 use quote::ToTokens;
 use std::time::SystemTime;
 use std::{collections::HashMap, sync::Arc};
 use syn::{parse_quote, parse_str, FnArg, Item, ReturnType, Visibility};
+use tracing::warn;
 
 #[cfg(test)]
 mod test;
@@ -55,13 +78,29 @@ pub struct UdfDef {
     def: String,
 }
 
+/// A saved connection (e.g., a Kafka cluster and its credentials) that can be referenced from
+/// the `connection` option of CREATE TABLE
+#[derive(Clone, Debug)]
+pub struct SavedConnection {
+    pub name: String,
+    pub connector: String,
+    pub config: String,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ArroyoSchemaProvider {
     pub source_defs: HashMap<String, String>,
     tables: HashMap<String, Table>,
     pub functions: HashMap<String, Arc<ScalarUDF>>,
     pub connections: HashMap<String, Connection>,
+    saved_connections: HashMap<String, SavedConnection>,
     pub udf_defs: HashMap<String, UdfDef>,
+    // process functions, and the other items defined with the UDFs that they use
+    process_functions: HashMap<String, ProcessFunctionDef>,
+    udf_items: Vec<String>,
+    extensions: HashMap<String, Arc<dyn ScalarFunctionExtension>>,
+    masking_policies: Vec<MaskingPolicy>,
+    assertions: Vec<TableAssertion>,
     config_options: datafusion::config::ConfigOptions,
 }
 
@@ -72,30 +111,69 @@ impl ArroyoSchemaProvider {
 
         let fn_impl = |args: &[ArrayRef]| Ok(Arc::new(args[0].clone()) as ArrayRef);
 
-        let window_return_type = Arc::new(window_arrow_struct());
+        let interval = DataType::Interval(datatypes::IntervalUnit::MonthDayNano);
+        let window_return_type: ReturnTypeFunction =
+            Arc::new(|_| Ok(Arc::new(window_arrow_struct())));
+        // hop(slide, width[, offset]) and tumble(width[, offset]); the offset shifts window
+        // boundaries away from the epoch
         functions.insert(
             "hop".to_string(),
-            Arc::new(create_udf(
+            Arc::new(ScalarUDF::new(
                 "hop",
-                vec![
-                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
-                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
-                ],
-                window_return_type.clone(),
-                Volatility::Volatile,
-                make_scalar_function(fn_impl),
+                &Signature::one_of(
+                    vec![
+                        TypeSignature::Exact(vec![interval.clone(), interval.clone()]),
+                        TypeSignature::Exact(vec![
+                            interval.clone(),
+                            interval.clone(),
+                            interval.clone(),
+                        ]),
+                    ],
+                    Volatility::Volatile,
+                ),
+                &window_return_type,
+                &make_scalar_function(fn_impl),
             )),
         );
         functions.insert(
             "tumble".to_string(),
-            Arc::new(create_udf(
+            Arc::new(ScalarUDF::new(
                 "tumble",
-                vec![DataType::Interval(datatypes::IntervalUnit::MonthDayNano)],
-                window_return_type,
+                &Signature::one_of(
+                    vec![
+                        TypeSignature::Exact(vec![interval.clone()]),
+                        TypeSignature::Exact(vec![interval.clone(), interval]),
+                    ],
+                    Volatility::Volatile,
+                ),
+                &window_return_type,
+                &make_scalar_function(fn_impl),
+            )),
+        );
+        // calendar(unit, timezone), for day, week, or month windows in a timezone
+        functions.insert(
+            "calendar".to_string(),
+            Arc::new(create_udf(
+                "calendar",
+                vec![DataType::Utf8, DataType::Utf8],
+                Arc::new(window_arrow_struct()),
                 Volatility::Volatile,
                 make_scalar_function(fn_impl),
             )),
         );
+        // accessors for the bounds of the window column of a windowed aggregate
+        for name in ["tumble_start", "tumble_end", "hop_start", "hop_end"] {
+            functions.insert(
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    vec![window_arrow_struct()],
+                    Arc::new(DataType::Timestamp(TimeUnit::Millisecond, None)),
+                    Volatility::Immutable,
+                    make_scalar_function(fn_impl),
+                )),
+            );
+        }
         functions.insert(
             "get_first_json_object".to_string(),
             Arc::new(create_udf(
@@ -131,18 +209,128 @@ impl ArroyoSchemaProvider {
             )),
         );
 
-        Self {
+        // encoding, keyed hashing, and encryption of strings or bytes; the second argument is a
+        // literal encoding or the name of the pipeline environment variable holding the key
+        for (name, inputs, ret) in [
+            (
+                "encode",
+                vec![DataType::Utf8, DataType::Binary],
+                DataType::Utf8,
+            ),
+            ("decode", vec![DataType::Utf8], DataType::Binary),
+            (
+                "hmac_sha256",
+                vec![DataType::Utf8, DataType::Binary],
+                DataType::Binary,
+            ),
+            (
+                "aes_gcm_encrypt",
+                vec![DataType::Utf8, DataType::Binary],
+                DataType::Binary,
+            ),
+            ("aes_gcm_decrypt", vec![DataType::Binary], DataType::Binary),
+        ] {
+            let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(ret.clone())));
+            functions.insert(
+                name.to_string(),
+                Arc::new(ScalarUDF::new(
+                    name,
+                    &Signature::one_of(
+                        inputs
+                            .into_iter()
+                            .map(|t| TypeSignature::Exact(vec![t, DataType::Utf8]))
+                            .collect(),
+                        Volatility::Volatile,
+                    ),
+                    &return_type,
+                    &make_scalar_function(fn_impl),
+                )),
+            );
+        }
+
+        // functions over IP addresses in their string representations
+        for (name, args, ret) in [
+            ("ip_version", vec![DataType::Utf8], DataType::Int32),
+            ("normalize_ip", vec![DataType::Utf8], DataType::Utf8),
+            (
+                "cidr_contains",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Boolean,
+            ),
+            ("geoip_country", vec![DataType::Utf8], DataType::Utf8),
+            ("geoip_asn", vec![DataType::Utf8], DataType::Int64),
+            ("geoip_asn_org", vec![DataType::Utf8], DataType::Utf8),
+        ] {
+            functions.insert(
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    args,
+                    Arc::new(ret),
+                    Volatility::Volatile,
+                    make_scalar_function(fn_impl),
+                )),
+            );
+        }
+
+        let mut provider = Self {
             tables,
             functions,
             source_defs: HashMap::new(),
             connections: HashMap::new(),
+            saved_connections: HashMap::new(),
             udf_defs: HashMap::new(),
+            process_functions: HashMap::new(),
+            udf_items: vec![],
+            extensions: HashMap::new(),
+            masking_policies: vec![],
+            assertions: vec![],
             config_options: datafusion::config::ConfigOptions::new(),
+        };
+
+        for extension in extensions::registered() {
+            provider.add_function_extension(extension);
         }
+
+        provider
+    }
+
+    fn add_function_extension(&mut self, extension: Arc<dyn ScalarFunctionExtension>) {
+        let name = extension.name().to_string();
+        if self.functions.contains_key(&name) {
+            warn!(
+                "Not registering extension function '{}', as there is already a built-in function with that name",
+                name
+            );
+            return;
+        }
+
+        let fn_impl = |args: &[ArrayRef]| Ok(Arc::new(args[0].clone()) as ArrayRef);
+
+        self.functions.insert(
+            name.clone(),
+            Arc::new(create_udf(
+                &name,
+                extension.arg_types(),
+                Arc::new(extension.return_type()),
+                Volatility::Volatile,
+                make_scalar_function(fn_impl),
+            )),
+        );
+        self.extensions.insert(name, extension);
+    }
+
+    pub fn add_masking_policy(&mut self, policy: MaskingPolicy) {
+        self.masking_policies.push(policy);
     }
 
     pub fn add_connector_table(&mut self, connection: Connection) {
-        if let Some(def) = schema_defs(&connection.name, &connection.schema) {
+        if let Some(def) = schema_defs(
+            &connection.name,
+            &connection.schema,
+            is_permissive(&connection),
+            is_lineage(&connection),
+        ) {
             self.source_defs.insert(connection.name.clone(), def);
         }
 
@@ -152,6 +340,21 @@ impl ArroyoSchemaProvider {
         );
     }
 
+    pub fn add_saved_connection(&mut self, name: &str, connector: &str, config: &str) {
+        self.saved_connections.insert(
+            name.to_string(),
+            SavedConnection {
+                name: name.to_string(),
+                connector: connector.to_string(),
+                config: config.to_string(),
+            },
+        );
+    }
+
+    fn get_saved_connection(&self, name: &str) -> Option<&SavedConnection> {
+        self.saved_connections.get(name)
+    }
+
     fn insert_table(&mut self, table: Table) {
         self.tables.insert(table.name().to_string(), table);
     }
@@ -162,10 +365,18 @@ impl ArroyoSchemaProvider {
 
     pub fn add_rust_udf(&mut self, body: &str) -> Result<()> {
         let file = syn::parse_file(body)?;
+        let mut items = vec![];
 
         for item in file.items {
-            let Item::Fn(mut function) = item else {
-                bail!("not a function");
+            let mut function = match item {
+                Item::Fn(function) => function,
+                // process functions, and the types, imports, and constants (like UDF caches)
+                // that they need
+                Item::Struct(_) | Item::Enum(_) | Item::Impl(_) | Item::Use(_) | Item::Const(_) => {
+                    items.push(item);
+                    continue;
+                }
+                _ => bail!("not a function"),
             };
 
             let mut args: Vec<TypeDef> = vec![];
@@ -221,8 +432,51 @@ impl ArroyoSchemaProvider {
             );
         }
 
+        for def in process::process_functions(&mut items)? {
+            if self.process_functions.contains_key(&def.name) {
+                bail!("process function '{}' is defined twice", def.name);
+            }
+            self.process_functions.insert(def.name.clone(), def);
+        }
+        self.udf_items
+            .extend(items.iter().map(|item| item.to_token_stream().to_string()));
+
         Ok(())
     }
+
+    fn parser_options(&self) -> ParserOptions {
+        ParserOptions {
+            parse_float_as_decimal: self.config_options.sql_parser.parse_float_as_decimal,
+            enable_ident_normalization: self.config_options.sql_parser.enable_ident_normalization,
+        }
+    }
+
+    /// A planner for queries against this schema, which resolves identifiers per the options the
+    /// query is compiled with (see [`SqlConfig::preserve_identifier_case`])
+    pub(crate) fn sql_to_rel(&self) -> SqlToRel<'_, Self> {
+        SqlToRel::new_with_options(self, self.parser_options())
+    }
+
+    /// The name an identifier refers to. As in Postgres, unquoted identifiers are folded to
+    /// lowercase (unless the query preserves identifier case) while quoted identifiers, which may
+    /// contain any character, are used as written.
+    pub(crate) fn ident_name(&self, ident: &Ident) -> String {
+        if ident.quote_style.is_none() && self.config_options.sql_parser.enable_ident_normalization
+        {
+            ident.value.to_ascii_lowercase()
+        } else {
+            ident.value.clone()
+        }
+    }
+
+    /// The name of a table, with each of its parts resolved as by [`Self::ident_name`]
+    pub(crate) fn object_name(&self, name: &ObjectName) -> String {
+        name.0
+            .iter()
+            .map(|ident| self.ident_name(ident))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
 }
 
 fn create_table_source(fields: Vec<Field>) -> Arc<dyn TableSource> {
@@ -300,40 +554,92 @@ impl ContextProvider for ArroyoSchemaProvider {
 #[derive(Clone, Debug)]
 pub struct SqlConfig {
     pub default_parallelism: usize,
+    /// Whether records with the same key must be delivered in event-time order through
+    /// shuffles (see [`Program::enforce_ordering`])
+    pub ordered: bool,
+    /// Whether unquoted identifiers keep their case, rather than being folded to lowercase as in
+    /// Postgres. Either way, quoted identifiers are case-sensitive.
+    pub preserve_identifier_case: bool,
+    /// If set, UDFs are run in a WebAssembly sandbox with these limits rather than compiled into
+    /// the pipeline
+    pub udf_sandbox: Option<UdfSandbox>,
 }
 
 impl Default for SqlConfig {
     fn default() -> Self {
         Self {
             default_parallelism: 4,
+            ordered: false,
+            preserve_identifier_case: false,
+            udf_sandbox: None,
         }
     }
 }
 
+/// The result of compiling a SQL query
+pub struct CompiledSql {
+    pub program: Program,
+    /// Ids of the saved connections used by the query
+    pub connection_ids: Vec<i64>,
+    /// Patterns in the query that are valid but likely to cause unbounded state growth
+    pub warnings: Vec<SqlWarning>,
+    /// The schema of the records written to each sink
+    pub sinks: Vec<SinkSchema>,
+}
+
 pub async fn parse_and_get_program(
     query: &str,
     schema_provider: ArroyoSchemaProvider,
     config: SqlConfig,
 ) -> Result<(Program, Vec<i64>)> {
+    let compiled = compile_sql(query, schema_provider, config).await?;
+    Ok((compiled.program, compiled.connection_ids))
+}
+
+pub fn parse_and_get_program_sync(
+    query: String,
+    schema_provider: ArroyoSchemaProvider,
+    config: SqlConfig,
+) -> Result<(Program, Vec<i64>)> {
+    let compiled = compile_sql_sync(query, schema_provider, config)?;
+    Ok((compiled.program, compiled.connection_ids))
+}
+
+pub async fn compile_sql(
+    query: &str,
+    schema_provider: ArroyoSchemaProvider,
+    config: SqlConfig,
+) -> Result<CompiledSql> {
     let query = query.to_string();
 
     if query.trim().is_empty() {
         bail!("Query is empty");
     }
 
-    tokio::spawn(async move { parse_and_get_program_sync(query, schema_provider, config) })
+    tokio::spawn(async move { compile_sql_sync(query, schema_provider, config) })
         .await
         .map_err(|_| anyhow!("Something went wrong"))?
 }
 
-pub fn parse_and_get_program_sync(
+pub fn compile_sql_sync(
     query: String,
     mut schema_provider: ArroyoSchemaProvider,
     config: SqlConfig,
-) -> Result<(Program, Vec<i64>)> {
+) -> Result<CompiledSql> {
+    schema_provider
+        .config_options
+        .sql_parser
+        .enable_ident_normalization = !config.preserve_identifier_case;
+
     let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, &query).tokenize()?;
+    let (tokens, assert_statements) = assertions::extract_assertions(tokens, &schema_provider)?;
     let mut inserts = vec![];
-    for statement in Parser::parse_sql(&dialect, &query)? {
+    for mut statement in Parser::new(&dialect)
+        .with_tokens(process::rewrite_process_tokens(tokens))
+        .parse_statements()?
+    {
+        process::plan_process_calls(&mut statement, &mut schema_provider)?;
         if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
             schema_provider.insert_table(table);
         } else {
@@ -341,7 +647,14 @@ pub fn parse_and_get_program_sync(
         };
     }
 
+    // assertions may be declared before the tables they're on
+    for statement in &assert_statements {
+        let assertion = assertions::plan_assertion(statement, &schema_provider)?;
+        schema_provider.assertions.push(assertion);
+    }
+
     let mut sql_pipeline_builder = SqlPipelineBuilder::new(&mut schema_provider);
+    sql_pipeline_builder.plan_source_fields(&inserts);
     for insert in inserts {
         sql_pipeline_builder.add_insert(insert)?;
     }
@@ -353,6 +666,8 @@ pub fn parse_and_get_program_sync(
         bail!("The provided SQL does not contain a query");
     }
 
+    let mut sinks = vec![];
+
     // If there isn't a sink, add a web sink to the last insert
     if !sql_pipeline_builder
         .insert_nodes
@@ -368,7 +683,7 @@ pub fn parse_and_get_program_sync(
             fields: struct_def.fields.clone(),
             type_name: None,
             operator: "GrpcSink::<#in_k, #in_t>".to_string(),
-            config: "{}".to_string(),
+            config: r#"{"tag": "web"}"#.to_string(),
             description: "WebSink".to_string(),
             serialization_mode: if insert.is_updating() {
                 arroyo_datastream::SerializationMode::DebeziumJson
@@ -377,16 +692,40 @@ pub fn parse_and_get_program_sync(
             },
             event_time_field: None,
             watermark_field: None,
+            watermark_alignment: None,
+            timestamp_bounds: None,
+            tenant_limit: None,
+            route: None,
         });
 
-        plan_graph.add_sql_operator(sink.as_sql_sink(insert)?);
+        let web_sink = sink.as_sql_sink(insert, sql_pipeline_builder.schema_provider)?;
+        sinks.extend(SinkSchema::for_operator(&web_sink));
+        plan_graph.add_sql_operator(web_sink);
     }
 
     for output in sql_pipeline_builder.insert_nodes.into_iter() {
+        sinks.extend(SinkSchema::for_operator(&output));
         plan_graph.add_sql_operator(output);
     }
 
-    get_program(plan_graph, sql_pipeline_builder.schema_provider.clone())
+    for dead_letters in sql_pipeline_builder.dead_letter_nodes.into_iter() {
+        sinks.extend(SinkSchema::for_operator(&dead_letters));
+        plan_graph.add_sql_operator(dead_letters);
+    }
+
+    let (mut program, connection_ids, warnings) =
+        get_program(plan_graph, sql_pipeline_builder.schema_provider.clone())?;
+
+    if config.ordered {
+        program.enforce_ordering();
+    }
+
+    Ok(CompiledSql {
+        program,
+        connection_ids,
+        warnings,
+        sinks,
+    })
 }
 
 #[derive(Clone)]
@@ -432,7 +771,8 @@ impl Default for TestStruct {
     }
 }
 
-fn test_struct_def() -> StructDef {
+/// The definition of [`TestStruct`], the type of `test_source`'s rows
+pub fn test_struct_def() -> StructDef {
     StructDef {
         name: Some("TestStruct".to_string()),
         fields: vec![
@@ -526,7 +866,7 @@ pub fn generate_test_code(
     struct_tokens: &syn::Expr,
     result_expression: &syn::Expr,
 ) -> syn::ItemFn {
-    let syn_expr = generating_expression.to_syn_expression();
+    let syn_expr = to_optimized_syn_expression(generating_expression);
     let function_name: syn::Ident =
         parse_str(&format!("generated_test_{}", function_suffix)).unwrap();
     parse_quote!(
@@ -535,12 +875,8 @@ pub fn generate_test_code(
     })
 }
 
-pub fn get_test_expression(
-    test_name: &str,
-    calculation_string: &str,
-    input_value: &syn::Expr,
-    expected_result: &syn::Expr,
-) -> syn::ItemFn {
+/// A schema provider with a source table named `test_source` whose rows are [`TestStruct`]s
+pub fn test_schema_provider() -> ArroyoSchemaProvider {
     let struct_def = test_struct_def();
     let schema = ConnectionSchema {
         format: Some(Format::JsonFormat as i32),
@@ -562,9 +898,13 @@ pub fn get_test_expression(
             KafkaConfig {
                 authentication: arroyo_connectors::kafka::KafkaConfigAuthentication::None {},
                 bootstrap_servers: "localhost:9092".to_string().try_into().unwrap(),
+                tls: None,
+                schema_registry: None,
             },
             KafkaTable {
                 topic: "test_topic".to_string(),
+                header_columns: None,
+                binary_header_columns: None,
                 type_: arroyo_connectors::kafka::TableType::Source {
                     offset: arroyo_connectors::kafka::SourceOffset::Latest,
                 },
@@ -574,6 +914,17 @@ pub fn get_test_expression(
         .unwrap();
 
     schema_provider.add_connector_table(kafka);
+    schema_provider
+}
+
+pub fn get_test_expression(
+    test_name: &str,
+    calculation_string: &str,
+    input_value: &syn::Expr,
+    expected_result: &syn::Expr,
+) -> syn::ItemFn {
+    let struct_def = test_struct_def();
+    let mut schema_provider = test_schema_provider();
 
     let mut inserts = vec![];
     for statement in Parser::parse_sql(
@@ -604,3 +955,47 @@ pub fn get_test_expression(
         expected_result,
     )
 }
+
+/// Compiles the fields of `SELECT <projection> FROM test_source` into a function from a
+/// [`TestStruct`] to a tuple of their values, either as they are or optimized together as
+/// projections are (see [`optimize_expressions`]), so that the two can be compared
+pub fn get_test_projection(function_name: &str, projection: &str, optimized: bool) -> syn::ItemFn {
+    let struct_def = test_struct_def();
+    let schema_provider = test_schema_provider();
+
+    let statement = Parser::parse_sql(
+        &PostgreSqlDialect {},
+        &format!("SELECT {} FROM test_source", projection),
+    )
+    .unwrap()
+    .remove(0);
+
+    let Insert::Anonymous{logical_plan: LogicalPlan::Projection(projection)} = Insert::try_from_statement(&statement, &schema_provider).unwrap() else {panic!("expect projection")};
+    let ctx = ExpressionContext {
+        schema_provider: &schema_provider,
+        input_struct: &struct_def,
+    };
+
+    let expressions: Vec<_> = projection
+        .expr
+        .iter()
+        .map(|expr| ctx.compile_expr(expr).unwrap())
+        .collect();
+    let (statements, expressions) = if optimized {
+        optimize_expressions(&expressions)
+    } else {
+        (vec![], expressions)
+    };
+
+    let function_name: syn::Ident = parse_str(function_name).unwrap();
+    let types = expressions
+        .iter()
+        .map(|expr| expr.return_type().return_type());
+    let exprs = expressions.iter().map(|expr| expr.to_syn_expression());
+    parse_quote!(
+        pub fn #function_name(arg: arroyo_sql::TestStruct) -> (#(#types,)*) {
+            #(#statements)*
+            (#(#exprs,)*)
+        }
+    )
+}
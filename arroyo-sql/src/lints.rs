@@ -0,0 +1,90 @@
+//! Lints over the optimized plan that flag patterns likely to cause unbounded state growth. These
+//! queries are valid and will run, but their state is only bounded by the expiration of the
+//! operator (24 hours), which can be far more than intended.
+
+use std::time::Duration;
+
+use petgraph::graph::DiGraph;
+
+use crate::plan_graph::{PlanEdge, PlanNode, PlanOperator, PlanType};
+/// A warning about a query that is valid but likely to hold much more state than intended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqlWarning {
+    /// Identifies the pattern that was flagged, like `unwindowed_join`
+    pub code: &'static str,
+    pub message: String,
+}
+
+pub(crate) fn lint(graph: &DiGraph<PlanNode, PlanEdge>) -> Vec<SqlWarning> {
+    graph.node_weights().filter_map(lint_node).collect()
+}
+
+fn lint_node(node: &PlanNode) -> Option<SqlWarning> {
+    match &node.operator {
+        PlanOperator::JoinWithExpiration {
+            left_expiration,
+            right_expiration,
+            ..
+        } => Some(SqlWarning {
+            code: "unwindowed_join",
+            message: format!(
+                "The join on {} is not windowed, so every record from both sides is kept for {} \
+                waiting for matches. Join windowed aggregates (with TUMBLE or HOP) instead.",
+                key_fields(&node.output_type)?,
+                format_duration(*left_expiration.max(right_expiration))
+            ),
+        }),
+        PlanOperator::NonWindowAggregate { expiration, .. } => Some(SqlWarning {
+            code: "unwindowed_aggregate",
+            message: format!(
+                "GROUP BY {} is not windowed, so state is kept for every distinct key for {}. \
+                If there are many keys, aggregate over a window (with TUMBLE or HOP) instead.",
+                key_fields(&node.output_type)?,
+                format_duration(*expiration)
+            ),
+        }),
+        // window functions with a limit on the row number are fused into a top-n operator by the
+        // optimizer, so any that remain keep every record of the window
+        PlanOperator::WindowFunction(function) if !function.order_by.is_empty() => {
+            Some(SqlWarning {
+                code: "unlimited_order_by",
+                message: format!(
+                    "{0} is computed with ORDER BY but no limit, so every record of each window \
+                    is kept. Filter on it (like WHERE {0} <= 10) to keep only the top rows.",
+                    function.field_name
+                ),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The key columns of the operator, or None if it has no key (like a global aggregate)
+fn key_fields(output_type: &PlanType) -> Option<String> {
+    let key = match output_type {
+        PlanType::Keyed { key, .. } | PlanType::KeyedPair { key, .. } => key,
+        PlanType::Updating(inner) => return key_fields(inner),
+        _ => return None,
+    };
+
+    if key.fields.is_empty() {
+        return None;
+    }
+
+    Some(
+        key.fields
+            .iter()
+            .map(|f| f.name())
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs % (60 * 60) == 0 {
+        format!("{} hours", secs / (60 * 60))
+    } else {
+        format!("{} seconds", secs)
+    }
+}
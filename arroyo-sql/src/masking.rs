@@ -0,0 +1,152 @@
+//! Masking policies hide sensitive columns (for example, PII) from a pipeline's outputs. A
+//! projection that masks every covered column is inserted immediately before each sink, so the
+//! masked values are what is written regardless of how the query computes them.
+
+use anyhow::{anyhow, bail, Result};
+use arrow_schema::DataType;
+use datafusion_common::ScalarValue;
+use datafusion_expr::expr::{ScalarFunction, ScalarUDF};
+use datafusion_expr::{lit, BuiltinScalarFunction, Cast, Expr};
+
+use crate::expressions::{Column, ExpressionContext};
+use crate::operators::Projection;
+use crate::pipeline::{RecordTransform, SqlOperator};
+use crate::types::StructField;
+use crate::ArroyoSchemaProvider;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MaskingAction {
+    /// Replaces the value with null
+    Redact,
+    /// Replaces the value with the hex-encoded SHA-256 hash of its string representation
+    Hash,
+    /// Keeps only the first `length` characters of a string
+    Truncate { length: u32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct MaskingPolicy {
+    pub name: String,
+    pub column: String,
+    /// Restricts the policy to the sink with this name; if unset, the policy applies to the
+    /// column in every sink
+    pub sink: Option<String>,
+    pub action: MaskingAction,
+}
+
+impl MaskingPolicy {
+    fn applies_to(&self, sink: &str, field: &StructField) -> bool {
+        self.column == field.name() && self.sink.as_ref().map(|s| s == sink).unwrap_or(true)
+    }
+}
+
+/// Wraps the input to `sink` in a projection that applies the masking policies covering its
+/// columns. Policies that name the sink take precedence over those that apply to every sink.
+pub(crate) fn mask_sink_input(
+    sink: &str,
+    input: SqlOperator,
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<SqlOperator> {
+    let struct_def = input.return_type();
+
+    let policies: Vec<_> = struct_def
+        .fields
+        .iter()
+        .map(|field| {
+            let mut policies = schema_provider
+                .masking_policies
+                .iter()
+                .filter(|p| p.applies_to(sink, field));
+            policies
+                .clone()
+                .find(|p| p.sink.is_some())
+                .or_else(|| policies.next())
+        })
+        .collect();
+
+    if policies.iter().all(|p| p.is_none()) {
+        return Ok(input);
+    }
+
+    let ctx = ExpressionContext {
+        schema_provider,
+        input_struct: &struct_def,
+    };
+
+    let mut field_names = vec![];
+    let mut field_computations = vec![];
+    for (field, policy) in struct_def.fields.iter().zip(policies) {
+        let column = Expr::Column(datafusion_common::Column::new(
+            field.alias.clone(),
+            field.name(),
+        ));
+
+        let expr = match policy {
+            Some(policy) => masking_expr(policy, field, column, schema_provider)
+                .map_err(|e| anyhow!("could not apply masking policy '{}': {}", policy.name, e))?,
+            None => column,
+        };
+
+        field_names.push(Column {
+            relation: field.alias.clone(),
+            name: field.name(),
+        });
+        field_computations.push(ctx.compile_expr(&expr)?);
+    }
+
+    Ok(SqlOperator::RecordTransform(
+        Box::new(input),
+        RecordTransform::ValueProjection(Projection {
+            field_names,
+            field_computations,
+        }),
+    ))
+}
+
+fn masking_expr(
+    policy: &MaskingPolicy,
+    field: &StructField,
+    column: Expr,
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<Expr> {
+    let Some(data_type) = field.data_type.as_datatype() else {
+        bail!(
+            "column '{}' is a struct, which cannot be masked",
+            field.name()
+        );
+    };
+
+    Ok(match &policy.action {
+        MaskingAction::Redact => Expr::Literal(ScalarValue::try_from(data_type)?),
+        MaskingAction::Hash => {
+            let value = match data_type {
+                DataType::Utf8 | DataType::Binary => column,
+                _ => Expr::Cast(Cast::new(Box::new(column), DataType::Utf8)),
+            };
+
+            let hash = Expr::ScalarFunction(ScalarFunction::new(
+                BuiltinScalarFunction::SHA256,
+                vec![value],
+            ));
+
+            Expr::ScalarUDF(ScalarUDF::new(
+                schema_provider.functions["encode"].clone(),
+                vec![hash, lit("hex")],
+            ))
+        }
+        MaskingAction::Truncate { length } => {
+            if *data_type != DataType::Utf8 {
+                bail!(
+                    "only text columns can be truncated, but '{}' is {:?}",
+                    field.name(),
+                    data_type
+                );
+            }
+
+            Expr::ScalarFunction(ScalarFunction::new(
+                BuiltinScalarFunction::Left,
+                vec![column, lit(*length as i64)],
+            ))
+        }
+    })
+}
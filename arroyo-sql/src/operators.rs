@@ -1,8 +1,7 @@
 #![allow(clippy::comparison_chain)]
-use std::time::Duration;
 
 use crate::{
-    expressions::{AggregationExpression, Aggregator, Column, Expression},
+    expressions::{optimize_expressions, AggregationExpression, Aggregator, Column, Expression},
     schemas::window_type_def,
     types::{StructDef, StructField, TypeDef},
 };
@@ -55,11 +54,11 @@ impl Projection {
         StructDef { name: None, fields }
     }
     pub fn to_truncated_syn_expression(&self, terms: usize) -> syn::Expr {
-        let assignments: Vec<_> = self
-            .field_computations
+        let computations = &self.field_computations[..terms.min(self.field_computations.len())];
+        let (bindings, computations) = optimize_expressions(computations);
+        let assignments: Vec<_> = computations
             .iter()
             .enumerate()
-            .take(terms)
             .map(|(i, field)| {
                 let field_name = self.field_names[i].clone();
                 let name = field_name.name;
@@ -71,12 +70,13 @@ impl Projection {
             })
             .collect();
         let output_type = self.truncated_return_type(terms).get_type();
-        parse_quote!(
+        parse_quote!({
+                #(#bindings)*
                 #output_type {
                     #(#assignments)
                     ,*
                 }
-        )
+        })
     }
 
     pub fn truncated_return_type(&self, terms: usize) -> StructDef {
@@ -95,8 +95,8 @@ impl Projection {
     }
 
     pub fn to_syn_expression(&self) -> syn::Expr {
-        let assignments: Vec<_> = self
-            .field_computations
+        let (bindings, computations) = optimize_expressions(&self.field_computations);
+        let assignments: Vec<_> = computations
             .iter()
             .enumerate()
             .map(|(i, field)| {
@@ -110,12 +110,13 @@ impl Projection {
             })
             .collect();
         let output_type = self.return_type().return_type();
-        parse_quote!(
+        parse_quote!({
+                #(#bindings)*
                 #output_type {
                     #(#assignments)
                     ,*
                 }
-        )
+        })
     }
 
     fn return_type(&self) -> TypeDef {
@@ -246,11 +247,11 @@ impl GroupByKind {
         let mut assignments: Vec<_> = vec![];
 
         key_struct.fields.iter().for_each(|field| {
-            let field_name: Ident = format_ident!("{}", field.field_name());
+            let field_name: Ident = field.field_ident();
             assignments.push(quote!(#field_name : arg.key.#field_name.clone()));
         });
         aggregate_struct.fields.iter().for_each(|field| {
-            let field_name: Ident = format_ident!("{}", field.field_name());
+            let field_name: Ident = field.field_ident();
             assignments.push(quote!(#field_name : arg.aggregate.#field_name.clone()));
         });
         let return_struct = self.output_struct(key_struct, aggregate_struct);
@@ -260,15 +261,25 @@ impl GroupByKind {
             window_type,
         } = self
         {
-            let width = match window_type {
-                WindowType::Tumbling { width } | WindowType::Sliding { width, .. } => width,
-                WindowType::Instant => &Duration::ZERO,
-            };
-            let field_name = format_ident!("{}", return_struct.fields[*index].field_name());
-            let width_literal: LitInt = parse_str(&width.as_millis().to_string()).unwrap();
-            assignments.push(quote!(#field_name: arroyo_types::Window{
+            let field_name = return_struct.fields[*index].field_ident();
+            let window = match window_type {
+                // calendar windows vary in length, so find the one containing the result
+                WindowType::Calendar { unit, timezone } => {
+                    let unit = format_ident!("{:?}", unit);
+                    quote!(arroyo_types::CalendarUnit::#unit.window(arg.timestamp, #timezone.parse().unwrap()))
+                }
+                WindowType::Tumbling { width, .. } | WindowType::Sliding { width, .. } => {
+                    let width_literal: LitInt = parse_str(&width.as_millis().to_string()).unwrap();
+                    quote!(arroyo_types::Window{
                         start_time: arg.timestamp - std::time::Duration::from_millis(#width_literal) + std::time::Duration::from_nanos(1),
-                        end_time: arg.timestamp + std::time::Duration::from_nanos(1)}));
+                        end_time: arg.timestamp + std::time::Duration::from_nanos(1)})
+                }
+                WindowType::Instant => quote!(arroyo_types::Window {
+                    start_time: arg.timestamp + std::time::Duration::from_nanos(1),
+                    end_time: arg.timestamp + std::time::Duration::from_nanos(1)
+                }),
+            };
+            assignments.push(quote!(#field_name: #window));
         }
         let return_type = return_struct.get_type();
         let struct_expression = parse_quote!(
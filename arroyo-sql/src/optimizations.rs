@@ -13,7 +13,7 @@ use petgraph::Direction::{self, Incoming, Outgoing};
 use quote::quote;
 
 use crate::operators::{AggregateProjection, GroupByKind, Projection, TwoPhaseAggregateProjection};
-use crate::pipeline::RecordTransform;
+use crate::pipeline::{RecordTransform, WindowFunction};
 use crate::plan_graph::{
     FusedRecordTransform, PlanEdge, PlanNode, PlanOperator, PlanType, WindowFunctionOperator,
 };
@@ -246,11 +246,21 @@ impl Optimizer for TwoPhaseOptimization {
         graph: &mut DiGraph<PlanNode, PlanEdge>,
     ) -> bool {
         let PlanOperator::WindowAggregate { window, projection } = node.operator else { return false };
-        let (width, slide) = match window {
-            WindowType::Tumbling { width } => (width, width),
-            WindowType::Sliding { width, slide } => (width, slide),
-            WindowType::Instant => (Duration::ZERO, Duration::ZERO),
+        let (width, slide, offset) = match window {
+            WindowType::Tumbling { width, offset } => (width, width, offset),
+            WindowType::Sliding {
+                width,
+                slide,
+                offset,
+            } => (width, slide, offset),
+            WindowType::Instant => (Duration::ZERO, Duration::ZERO, Duration::ZERO),
+            WindowType::Calendar { .. } => return false,
         };
+        // the two-phase aggregators bin by epoch-aligned slides, so offset windows use the
+        // general window operator
+        if !offset.is_zero() {
+            return false;
+        }
         if !slide.is_zero() && width.as_micros() % slide.as_micros() != 0 {
             return false;
         }
@@ -261,6 +271,7 @@ impl Optimizer for TwoPhaseOptimization {
             PlanOperator::TumblingWindowTwoPhaseAggregator {
                 tumble_width: width,
                 projection,
+                input_updating: false,
             }
         } else {
             PlanOperator::SlidingWindowTwoPhaseAggregator {
@@ -367,7 +378,14 @@ impl Optimizer for WindowTopNOptimization {
                 }
             }
             SearchTarget::WindowFunctionOperator => {
-                if let PlanOperator::WindowFunction(window_function_operator) = node.operator {
+                // only row numbers can be computed from the top N rows of each window
+                if let PlanOperator::WindowFunction(
+                    window_function_operator @ WindowFunctionOperator {
+                        window_function: WindowFunction::RowNumber,
+                        ..
+                    },
+                ) = node.operator
+                {
                     let _field_name = window_function_operator.field_name.clone();
                     self.window_function_operator = Some(window_function_operator);
                     self.nodes.push(node_index);
@@ -411,12 +429,20 @@ impl Optimizer for WindowTopNOptimization {
                         let mut additional_nodes = vec![];
                         // Non-shuffle slide-width tumbling aggregator.
                         let (window, projection) = self.window_aggregate.take().unwrap();
-                        let (width, slide) = match window {
-                            WindowType::Tumbling { width } => (width, width),
-                            WindowType::Sliding { width, slide } => (width, slide),
-                            WindowType::Instant => (Duration::ZERO, Duration::ZERO),
+                        let (width, slide, offset) = match window {
+                            WindowType::Tumbling { width, offset } => (width, width, offset),
+                            WindowType::Sliding {
+                                width,
+                                slide,
+                                offset,
+                            } => (width, slide, offset),
+                            WindowType::Instant => (Duration::ZERO, Duration::ZERO, Duration::ZERO),
+                            WindowType::Calendar { .. } => {
+                                self.clear();
+                                return false;
+                            }
                         };
-                        if width.as_micros() % slide.as_micros() != 0 {
+                        if !offset.is_zero() || width.as_micros() % slide.as_micros() != 0 {
                             self.clear();
                             return false;
                         }
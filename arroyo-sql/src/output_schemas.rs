@@ -0,0 +1,84 @@
+//! The resolved schemas of the records a query writes to each of its sinks, so that consumers of
+//! those sinks can check their expectations against a query before it is deployed.
+
+use arrow_schema::DataType;
+
+use crate::pipeline::SqlOperator;
+use crate::types::{StructField, TypeDef};
+
+/// The schema of the records written to a sink
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SinkSchema {
+    /// The name of the sink table, or `web` for the preview sink
+    pub name: String,
+    /// Whether the sink receives updates (as Debezium-style before/after records) rather than
+    /// appends
+    pub updating: bool,
+    pub fields: Vec<SchemaField>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaField {
+    pub name: String,
+    /// The SQL type of the field, like `BIGINT`; struct fields have the type `STRUCT` and list
+    /// their own fields
+    pub sql_type: String,
+    pub nullable: bool,
+    pub fields: Vec<SchemaField>,
+}
+
+impl SinkSchema {
+    pub(crate) fn for_operator(operator: &SqlOperator) -> Option<Self> {
+        let SqlOperator::Sink(name, sink, input) = operator else {
+            return None;
+        };
+
+        Some(SinkSchema {
+            name: name.clone(),
+            updating: input.is_updating(),
+            fields: sink.struct_def.fields.iter().map(|f| f.into()).collect(),
+        })
+    }
+}
+
+impl From<&StructField> for SchemaField {
+    fn from(f: &StructField) -> Self {
+        let (sql_type, fields) = match &f.data_type {
+            TypeDef::StructDef(def, _) => (
+                "STRUCT".to_string(),
+                def.fields.iter().map(|f| f.into()).collect(),
+            ),
+            TypeDef::DataType(dt, _) => (sql_type(dt), vec![]),
+        };
+
+        SchemaField {
+            name: f.name(),
+            sql_type,
+            nullable: f.nullable(),
+            fields,
+        }
+    }
+}
+
+fn sql_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Boolean => "BOOLEAN".to_string(),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => "INTEGER".to_string(),
+        DataType::Int64 => "BIGINT".to_string(),
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 => "INTEGER UNSIGNED".to_string(),
+        DataType::UInt64 => "BIGINT UNSIGNED".to_string(),
+        DataType::Float16 | DataType::Float32 => "FLOAT".to_string(),
+        DataType::Float64 => "DOUBLE".to_string(),
+        DataType::Utf8 | DataType::LargeUtf8 => "TEXT".to_string(),
+        DataType::Binary | DataType::LargeBinary => "BINARY".to_string(),
+        DataType::Timestamp(_, _) => "TIMESTAMP".to_string(),
+        DataType::Date32 | DataType::Date64 => "DATE".to_string(),
+        DataType::Time32(_) | DataType::Time64(_) => "TIME".to_string(),
+        DataType::Interval(_) | DataType::Duration(_) => "INTERVAL".to_string(),
+        DataType::Decimal128(precision, scale) => format!("DECIMAL({}, {})", precision, scale),
+        DataType::List(field) | DataType::LargeList(field) => {
+            format!("{}[]", sql_type(field.data_type()))
+        }
+        dt => format!("{:?}", dt),
+    }
+}
@@ -1,5 +1,5 @@
 #![allow(clippy::comparison_chain)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::time::Duration;
 use std::unreachable;
@@ -7,18 +7,20 @@ use std::unreachable;
 use anyhow::Result;
 use anyhow::{anyhow, bail};
 use arrow_schema::DataType;
-use arroyo_datastream::{Operator, WindowType};
+use arroyo_datastream::{Operator, TenantLimit, TimestampBounds, WatermarkAlignment, WindowType};
+use arroyo_types::{CalendarUnit, Tz};
 
 use datafusion_common::{DFField, ScalarValue};
 use datafusion_expr::expr::ScalarUDF;
 use datafusion_expr::{BuiltInWindowFunction, Expr, JoinConstraint, LogicalPlan, Window, WriteOp};
 
-use quote::{format_ident, quote};
+use quote::quote;
 use syn::{parse_quote, Type};
 
-use crate::expressions::ExpressionContext;
+use crate::assertions::ViolationAction;
+use crate::expressions::{to_optimized_syn_expression, ExpressionContext};
 use crate::external::{ProcessingMode, SqlSink, SqlSource};
-use crate::tables::{Insert, Table};
+use crate::tables::{Insert, ReferenceTable, Table};
 use crate::{
     expressions::{AggregationExpression, Column, ColumnExpression, Expression, SortExpression},
     operators::{AggregateProjection, GroupByKind, Projection},
@@ -31,7 +33,10 @@ pub enum SqlOperator {
     Source(SourceOperator),
     Aggregator(Box<SqlOperator>, AggregateOperator),
     JoinOperator(Box<SqlOperator>, Box<SqlOperator>, JoinOperator),
+    LookupJoin(Box<SqlOperator>, LookupJoinOperator),
     Window(Box<SqlOperator>, SqlWindowOperator),
+    TopN(Box<SqlOperator>, TopNOperator),
+    Process(Box<SqlOperator>, ProcessOperator),
     RecordTransform(Box<SqlOperator>, RecordTransform),
     Sink(String, SqlSink, Box<SqlOperator>),
     NamedTable(String, Box<SqlOperator>),
@@ -52,6 +57,12 @@ pub struct SourceOperator {
     pub virtual_field_projection: Option<Projection>,
     pub timestamp_override: Option<Expression>,
     pub watermark_column: Option<Expression>,
+    pub watermark_alignment: Option<WatermarkAlignment>,
+    pub timestamp_bounds: Option<TimestampBounds>,
+    pub assertions: Vec<SourceAssertion>,
+    pub tenant_limit: Option<SourceTenantLimit>,
+    // set on the source read by dead-letter sinks, which see its records before they're checked
+    pub unchecked: bool,
 }
 impl SourceOperator {
     fn return_type(&self) -> StructDef {
@@ -63,6 +74,38 @@ impl SourceOperator {
     }
 }
 
+/// A data quality assertion on a source's records (see [`crate::assertions`]), whose predicate
+/// is true unless the record violates it
+#[derive(Debug, Clone)]
+pub struct SourceAssertion {
+    pub text: String,
+    pub predicate: Expression,
+    pub fail: bool,
+}
+
+impl SourceAssertion {
+    pub fn as_operator(&self) -> Operator {
+        let predicate = to_optimized_syn_expression(&self.predicate);
+        let predicate: syn::Expr = parse_quote!({
+            let arg = &record.value;
+            #predicate
+        });
+        Operator::Assertion(arroyo_datastream::Assertion {
+            assertion: self.text.clone(),
+            fail: self.fail,
+            predicate: quote!(#predicate).to_string(),
+        })
+    }
+}
+
+/// Per-tenant rate limits on a source's records, which are partitioned by `key` (their tenant)
+/// before being limited
+#[derive(Debug, Clone)]
+pub struct SourceTenantLimit {
+    pub key: Projection,
+    pub limit: TenantLimit,
+}
+
 impl RecordTransform {
     pub fn output_struct(&self, input_struct: StructDef) -> StructDef {
         match self {
@@ -95,7 +138,7 @@ impl RecordTransform {
                 }
             }
             RecordTransform::Filter(expression) => {
-                let filter_method = expression.to_syn_expression();
+                let filter_method = to_optimized_syn_expression(expression);
                 if is_updating {
                     MethodCompiler::updating_filter_operator(
                         "updating_filter",
@@ -141,9 +184,36 @@ impl AggregateOperator {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
 pub enum WindowFunction {
     RowNumber,
+    Rank,
+    DenseRank,
+    // the value of the row `offset` rows before (LAG) or after (LEAD) the current one in the
+    // window's partition, or `default` if there is no such row
+    Lag {
+        value: Expression,
+        offset: usize,
+        default: Option<Expression>,
+    },
+    Lead {
+        value: Expression,
+        offset: usize,
+        default: Option<Expression>,
+    },
+}
+
+impl WindowFunction {
+    pub fn return_type(&self) -> TypeDef {
+        match self {
+            WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => {
+                TypeDef::DataType(DataType::UInt64, false)
+            }
+            WindowFunction::Lag { value, .. } | WindowFunction::Lead { value, .. } => {
+                value.return_type().to_optional()
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -161,11 +231,52 @@ pub struct SqlWindowOperator {
     pub window: WindowType,
 }
 
+/// The first `limit` records of the whole output of a query, as sorted by its ORDER BY
+#[derive(Debug, Clone)]
+pub struct TopNOperator {
+    pub order_by: Vec<SortExpression>,
+    pub limit: usize,
+}
+
+/// A call to a process function, along with the closures that convert rows to its input and
+/// from its output
+#[derive(Debug, Clone)]
+pub struct ProcessOperator {
+    pub function: String,
+    pub config: String,
+    pub input_fn: String,
+    pub output_fn: String,
+    pub output_struct: StructDef,
+}
+
 #[derive(Debug, Clone)]
 pub struct JoinOperator {
     pub left_key: Projection,
     pub right_key: Projection,
     pub join_type: JoinType,
+    // the tumbling window shared by both sides, if any, which lets the join buffer each window's
+    // records until it closes rather than matching records instant by instant
+    pub window: Option<WindowType>,
+}
+
+/// A join of a stream against a reference table, which every subtask of the join loads for
+/// itself rather than it being read as a stream
+#[derive(Debug, Clone)]
+pub struct LookupJoinOperator {
+    pub table: ReferenceTable,
+    // the rows of the table, with only the fields that the query reads
+    pub row_struct: StructDef,
+    // turns a row into the right side of the join, with its fields qualified by the table's alias
+    pub row_projection: Projection,
+    pub key: Projection,
+    pub row_key: Projection,
+    pub join_type: JoinType,
+}
+
+impl LookupJoinOperator {
+    pub fn right_struct(&self) -> StructDef {
+        self.row_projection.output_struct()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -271,7 +382,7 @@ impl JoinType {
         let mut assignments: Vec<_> = vec![];
 
         left_struct.fields.iter().for_each(|field| {
-                let field_name = format_ident!("{}",field.field_name());
+                let field_name = field.field_ident();
                 if self.left_nullable() {
                     if field.data_type.is_optional() {
                         assignments.push(quote!(#field_name : arg.left.as_ref().map(|inner| inner.#field_name.clone()).flatten()));
@@ -283,7 +394,7 @@ impl JoinType {
                 }
             });
         right_struct.fields.iter().for_each(|field| {
-                let field_name = format_ident!("{}",field.field_name());
+                let field_name = field.field_ident();
                 if self.right_nullable() {
                     if field.data_type.is_optional() {
                         assignments.push(quote!(#field_name : arg.right.as_ref().map(|inner| inner.#field_name.clone()).flatten()));
@@ -319,15 +430,20 @@ impl SqlOperator {
             SqlOperator::JoinOperator(left, right, operator) => operator
                 .join_type
                 .output_struct(&left.return_type(), &right.return_type()),
+            SqlOperator::LookupJoin(input, operator) => operator
+                .join_type
+                .output_struct(&input.return_type(), &operator.right_struct()),
             SqlOperator::Window(input, window) => {
                 let mut input_struct = input.return_type();
                 input_struct.fields.push(StructField::new(
                     window.field_name.clone(),
                     None,
-                    TypeDef::DataType(DataType::UInt64, false),
+                    window.window_fn.return_type(),
                 ));
                 input_struct
             }
+            SqlOperator::TopN(input, _) => input.return_type(),
+            SqlOperator::Process(_, process) => process.output_struct.clone(),
             SqlOperator::RecordTransform(input, record_transform) => {
                 record_transform.output_struct(input.return_type())
             }
@@ -369,19 +485,43 @@ impl SqlOperator {
                 !matches!(aggregator.window, WindowType::Instant) || input.has_window()
             }
             SqlOperator::JoinOperator(left, right, _) => left.has_window() || right.has_window(),
+            SqlOperator::LookupJoin(input, _) => input.has_window(),
             SqlOperator::Window(_, _) => true,
+            // the top records are chosen from all of the windows
+            SqlOperator::TopN(_, _) => false,
+            SqlOperator::Process(_, _) => false,
             SqlOperator::RecordTransform(input, _) => input.has_window(),
             SqlOperator::Sink(_, _, input) => input.has_window(),
             SqlOperator::NamedTable(_, input) => input.has_window(),
         }
     }
 
+    /// The window of the closest windowed operator upstream of this one
+    pub fn window(&self) -> Option<WindowType> {
+        match self {
+            SqlOperator::Source(_) => None,
+            SqlOperator::Aggregator(input, aggregator) => match aggregator.window {
+                WindowType::Instant => input.window(),
+                _ => Some(aggregator.window.clone()),
+            },
+            SqlOperator::JoinOperator(left, _, _) => left.window(),
+            SqlOperator::LookupJoin(input, _) => input.window(),
+            SqlOperator::Window(_, window_operator) => Some(window_operator.window.clone()),
+            SqlOperator::TopN(_, _) | SqlOperator::Process(_, _) => None,
+            SqlOperator::RecordTransform(input, _)
+            | SqlOperator::Sink(_, _, input)
+            | SqlOperator::NamedTable(_, input) => input.window(),
+        }
+    }
+
     pub fn is_updating(&self) -> bool {
         match self {
             SqlOperator::Source(source) => source.source.processing_mode == ProcessingMode::Update,
+            // windowed aggregates apply retractions to their bins, and only emit once the window
+            // closes
             SqlOperator::Aggregator(input, aggregate_operator) => {
-                input.is_updating()
-                    || (!input.has_window() && aggregate_operator.window == WindowType::Instant)
+                aggregate_operator.window == WindowType::Instant
+                    && (input.is_updating() || !input.has_window())
             }
             SqlOperator::JoinOperator(left, right, join_operator) => {
                 // the join will be updating if one of the sides is updating or if a non-window side is nullable.
@@ -393,6 +533,13 @@ impl SqlOperator {
             SqlOperator::Window(input, sql_window_operator) => {
                 input.is_updating() || sql_window_operator.window == WindowType::Instant
             }
+            // rows missing from the table are emitted immediately, rather than being retracted
+            // if they show up later
+            SqlOperator::LookupJoin(input, _) => input.is_updating(),
+            // records are retracted as they're displaced from the top
+            SqlOperator::TopN(_, _) => true,
+            // the function's input isn't updating, and its output is whatever it emits
+            SqlOperator::Process(_, _) => false,
             SqlOperator::RecordTransform(input, _) => input.is_updating(),
             SqlOperator::Sink(_, _, input) => input.is_updating(),
             SqlOperator::NamedTable(_, table_operator) => table_operator.is_updating(),
@@ -405,6 +552,13 @@ pub struct SqlPipelineBuilder<'a> {
     pub schema_provider: &'a ArroyoSchemaProvider,
     pub planned_tables: HashMap<String, SqlOperator>,
     pub insert_nodes: Vec<SqlOperator>,
+    // the sinks that assertions write their violations to, and the tables they're on
+    pub dead_letter_nodes: Vec<SqlOperator>,
+    dead_letter_tables: HashSet<String>,
+    pub source_fields: HashMap<String, HashSet<String>>,
+    // set while planning the end of a query (its last operators, before any but projections),
+    // which is the only place it can be sorted; holds the query's LIMIT, if any
+    query_limit: Option<Option<usize>>,
 }
 
 impl<'a> SqlPipelineBuilder<'a> {
@@ -413,9 +567,128 @@ impl<'a> SqlPipelineBuilder<'a> {
             schema_provider,
             planned_tables: HashMap::new(),
             insert_nodes: vec![],
+            dead_letter_nodes: vec![],
+            dead_letter_tables: HashSet::new(),
+            source_fields: HashMap::new(),
+            query_limit: None,
+        }
+    }
+
+    /// DataFusion pushes the columns each query reads down into its table scans. As a source is
+    /// shared by every query that reads from it, it needs to produce the union of those columns.
+    pub(crate) fn plan_source_fields(&mut self, inserts: &[Insert]) {
+        for insert in inserts {
+            let (Insert::InsertQuery { logical_plan, .. } | Insert::Anonymous { logical_plan }) =
+                insert;
+            self.add_source_fields(logical_plan);
+        }
+
+        let schema_provider = self.schema_provider;
+        for assertion in &schema_provider.assertions {
+            self.add_source_fields(&assertion.plan);
+        }
+    }
+
+    fn add_source_fields(&mut self, plan: &LogicalPlan) {
+        if let LogicalPlan::TableScan(table_scan) = plan {
+            let schema_provider = self.schema_provider;
+            match schema_provider.get_table(&table_scan.table_name.to_string()) {
+                Some(Table::ConnectorTable(table)) => {
+                    let fields = self.source_fields.entry(table.name.clone()).or_default();
+                    match &table_scan.projection {
+                        Some(projection) => fields.extend(
+                            projection
+                                .iter()
+                                .filter_map(|i| table.fields.get(*i))
+                                .map(|f| f.name.clone()),
+                        ),
+                        None => fields.extend(table.fields.iter().map(|f| f.name.clone())),
+                    }
+                }
+                Some(Table::TableFromQuery { logical_plan, .. }) => {
+                    self.add_source_fields(logical_plan);
+                }
+                _ => {}
+            }
+        }
+
+        for input in plan.inputs() {
+            self.add_source_fields(input);
         }
     }
 
+    /// Checks the records of a source against the assertions declared on its table. Violations of
+    /// those that route them to a dead-letter sink are read from the source before it's checked.
+    pub(crate) fn add_assertions(
+        &mut self,
+        table: &str,
+        source: SqlOperator,
+    ) -> Result<SqlOperator> {
+        let mut source_operator = match source {
+            SqlOperator::Source(source_operator) => source_operator,
+            source => return Ok(source),
+        };
+        let schema_provider = self.schema_provider;
+        let assertions: Vec<_> = schema_provider
+            .assertions
+            .iter()
+            .filter(|a| a.table == table)
+            .collect();
+        if assertions.is_empty() {
+            return Ok(SqlOperator::Source(source_operator));
+        }
+        if source_operator.source.processing_mode == ProcessingMode::Update {
+            bail!("assertions can't be declared on update-mode sources");
+        }
+
+        let struct_def = source_operator.return_type();
+        let ctx = ExpressionContext {
+            schema_provider,
+            input_struct: &struct_def,
+        };
+        let mut dead_letters = vec![];
+        for assertion in assertions {
+            let compile = |expr: Expr| {
+                ctx.compile_expr(&expr)
+                    .map_err(|e| anyhow!("failed to plan assertion {}: {}", assertion.text, e))
+            };
+
+            // as with CHECK constraints, records for which the condition is null pass
+            let condition = Box::new(assertion.condition.clone());
+            source_operator.assertions.push(SourceAssertion {
+                text: assertion.text.clone(),
+                predicate: compile(Expr::IsNotFalse(condition.clone()))?,
+                fail: assertion.action == ViolationAction::Fail,
+            });
+            if let ViolationAction::DeadLetter(sink) = &assertion.action {
+                dead_letters.push((sink, compile(Expr::IsFalse(condition))?));
+            }
+        }
+
+        // the source is planned for every query that reads it, but its violations are only
+        // written once
+        if self.dead_letter_tables.insert(table.to_string()) {
+            for (sink, violations) in dead_letters {
+                let unchecked = SqlOperator::Source(SourceOperator {
+                    assertions: vec![],
+                    unchecked: true,
+                    ..source_operator.clone()
+                });
+                let input = SqlOperator::RecordTransform(
+                    Box::new(unchecked),
+                    RecordTransform::Filter(violations),
+                );
+                let sink = schema_provider
+                    .get_table(sink)
+                    .ok_or_else(|| anyhow!("dead-letter table {} not found", sink))?
+                    .as_sql_sink(input, schema_provider)?;
+                self.dead_letter_nodes.push(sink);
+            }
+        }
+
+        Ok(SqlOperator::Source(source_operator))
+    }
+
     fn ctx(&'a self, input_struct: &'a StructDef) -> ExpressionContext<'a> {
         ExpressionContext {
             schema_provider: self.schema_provider,
@@ -424,11 +697,20 @@ impl<'a> SqlPipelineBuilder<'a> {
     }
 
     pub fn insert_sql_plan(&mut self, plan: &LogicalPlan) -> Result<SqlOperator> {
+        let query_limit = match plan {
+            // left for the projected plan
+            LogicalPlan::Projection(_) | LogicalPlan::SubqueryAlias(_) => None,
+            _ => self.query_limit.take(),
+        };
+        if matches!(query_limit, Some(Some(_))) && !matches!(plan, LogicalPlan::Sort(_)) {
+            bail!("LIMIT is only supported after an ORDER BY");
+        }
+
         match plan {
             LogicalPlan::Projection(projection) => self.insert_projection(projection),
             LogicalPlan::Filter(filter) => self.insert_filter(filter),
             LogicalPlan::Aggregate(aggregate) => self.insert_aggregation(aggregate),
-            LogicalPlan::Sort(_) => bail!("sorting is not currently supported"),
+            LogicalPlan::Sort(sort) => self.insert_sort(sort, query_limit),
             LogicalPlan::Join(join) => self.insert_join(join),
             LogicalPlan::CrossJoin(_) => bail!("cross joins are not currently supported"),
             LogicalPlan::Repartition(_) => bail!("repartitions are not currently supported"),
@@ -439,7 +721,9 @@ impl<'a> SqlPipelineBuilder<'a> {
             LogicalPlan::SubqueryAlias(subquery_alias) => {
                 self.insert_subquery_alias(subquery_alias)
             }
-            LogicalPlan::Limit(_) => bail!("limit not currently supported"),
+            LogicalPlan::Limit(_) => {
+                bail!("LIMIT is only supported at the end of a query, after an ORDER BY")
+            }
             LogicalPlan::Ddl(ddl_statement) => match ddl_statement {
                 datafusion_expr::DdlStatement::CreateExternalTable(_) => {
                     bail!("creating external tables is not currently supported")
@@ -484,6 +768,62 @@ impl<'a> SqlPipelineBuilder<'a> {
         }
     }
 
+    // plans a whole query, which (unlike its subqueries) may end with an ORDER BY and LIMIT
+    fn insert_query(&mut self, plan: &LogicalPlan) -> Result<SqlOperator> {
+        let (plan, limit) = match plan {
+            LogicalPlan::Limit(limit) => {
+                if limit.skip != 0 {
+                    bail!("OFFSET is not supported");
+                }
+                (limit.input.as_ref(), limit.fetch)
+            }
+            _ => (plan, None),
+        };
+
+        self.query_limit = Some(limit);
+        let result = self.insert_sql_plan(plan);
+        self.query_limit = None;
+        result
+    }
+
+    fn insert_sort(
+        &mut self,
+        sort: &datafusion_expr::logical_plan::Sort,
+        query_limit: Option<Option<usize>>,
+    ) -> Result<SqlOperator> {
+        let Some(limit) = query_limit else {
+            bail!("ORDER BY is only supported at the end of a query");
+        };
+        // the optimizer may have pushed the limit into the sort
+        let Some(limit) = limit.or(sort.fetch) else {
+            bail!("ORDER BY requires a LIMIT, as a stream's whole output can't be sorted");
+        };
+        if limit == 0 {
+            bail!("LIMIT must be positive");
+        }
+
+        let input = self.insert_sql_plan(&sort.input)?;
+        let input_struct = input.return_type();
+        let mut ctx = self.ctx(&input_struct);
+
+        let order_by = sort
+            .expr
+            .iter()
+            .map(|expr| {
+                if let Expr::Sort(sort) = expr {
+                    SortExpression::from_expression(&mut ctx, sort)
+                } else {
+                    bail!("expected sort expression, found {:?}", expr);
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SqlOperator::TopN(
+            Box::new(input),
+            TopNOperator { order_by, limit },
+        ))
+    }
+
     fn insert_dml(
         &mut self,
         dml_statement: &datafusion_expr::logical_plan::DmlStatement,
@@ -491,7 +831,7 @@ impl<'a> SqlPipelineBuilder<'a> {
         if !matches!(dml_statement.op, WriteOp::Insert) {
             bail!("only insert statements are currently supported")
         }
-        let input = self.insert_sql_plan(&dml_statement.input)?;
+        let input = self.insert_query(&dml_statement.input)?;
         self.schema_provider
             .get_table(&dml_statement.table_name.to_string())
             .ok_or_else(|| {
@@ -500,7 +840,7 @@ impl<'a> SqlPipelineBuilder<'a> {
                     dml_statement.table_name
                 )
             })?
-            .as_sql_sink(input)
+            .as_sql_sink(input, self.schema_provider)
     }
 
     fn insert_filter(
@@ -588,6 +928,15 @@ impl<'a> SqlPipelineBuilder<'a> {
         {
             bail!("updating aggregates only support two phase aggregations. Currently count distinct is not supported");
         }
+        if source.is_updating() && !matches!(window, WindowType::Instant) {
+            match &window {
+                WindowType::Tumbling { offset, .. } if offset.is_zero() => {}
+                _ => bail!("windowed aggregates over updating inputs only support tumbling windows without an offset"),
+            }
+            if !aggregating.supports_two_phase() {
+                bail!("windowed aggregates over updating inputs only support two phase aggregations. Currently count distinct is not supported");
+            }
+        }
         let merge = self.window_field(&aggregate.group_expr, aggregate.schema.fields())?;
         Ok(SqlOperator::Aggregator(
             Box::new(source),
@@ -675,7 +1024,7 @@ impl<'a> SqlPipelineBuilder<'a> {
     fn is_window(expression: &Expr) -> bool {
         match expression {
             Expr::ScalarUDF(ScalarUDF { fun, args: _ }) => {
-                matches!(fun.name.as_str(), "hop" | "tumble")
+                matches!(fun.name.as_str(), "hop" | "tumble" | "calendar")
             }
             Expr::Alias(exp, _) => Self::is_window(exp),
             _ => false,
@@ -686,19 +1035,37 @@ impl<'a> SqlPipelineBuilder<'a> {
         match expression {
             Expr::ScalarUDF(ScalarUDF { fun, args }) => match fun.name.as_str() {
                 "hop" => {
-                    if args.len() != 2 {
+                    if args.len() != 2 && args.len() != 3 {
                         unreachable!();
                     }
                     let slide = Self::get_duration(&args[0])?;
                     let width = Self::get_duration(&args[1])?;
-                    Ok(Some(WindowType::Sliding { width, slide }))
+                    let offset = Self::get_offset(args.get(2), slide)?;
+                    Ok(Some(WindowType::Sliding {
+                        width,
+                        slide,
+                        offset,
+                    }))
                 }
                 "tumble" => {
-                    if args.len() != 1 {
-                        unreachable!("wrong number of arguments for tumble(), expect one");
+                    if args.len() != 1 && args.len() != 2 {
+                        unreachable!("wrong number of arguments for tumble(), expect one or two");
                     }
                     let width = Self::get_duration(&args[0])?;
-                    Ok(Some(WindowType::Tumbling { width }))
+                    let offset = Self::get_offset(args.get(1), width)?;
+                    Ok(Some(WindowType::Tumbling { width, offset }))
+                }
+                "calendar" => {
+                    if args.len() != 2 {
+                        unreachable!("wrong number of arguments for calendar(), expect two");
+                    }
+                    let unit = Self::get_string(&args[0])?;
+                    let unit = CalendarUnit::try_from(unit.as_str()).map_err(|e| anyhow!(e))?;
+                    let timezone = Self::get_string(&args[1])?;
+                    timezone
+                        .parse::<Tz>()
+                        .map_err(|e| anyhow!("invalid timezone '{}': {}", timezone, e))?;
+                    Ok(Some(WindowType::Calendar { unit, timezone }))
                 }
                 _ => Ok(None),
             },
@@ -706,6 +1073,33 @@ impl<'a> SqlPipelineBuilder<'a> {
             _ => Ok(None),
         }
     }
+    /// The optional alignment offset of a window, which must be smaller than the interval that
+    /// window boundaries are aligned to
+    fn get_offset(expression: Option<&Expr>, alignment: Duration) -> Result<Duration> {
+        let Some(expression) = expression else {
+            return Ok(Duration::ZERO);
+        };
+        let offset = Self::get_duration(expression)?;
+        if offset >= alignment {
+            bail!(
+                "window offset ({:?}) must be smaller than the window slide ({:?})",
+                offset,
+                alignment
+            );
+        }
+        Ok(offset)
+    }
+
+    fn get_string(expression: &Expr) -> Result<String> {
+        match expression {
+            Expr::Literal(ScalarValue::Utf8(Some(val))) => Ok(val.clone()),
+            _ => bail!(
+                "unsupported window argument, expect string literal, not {}",
+                expression
+            ),
+        }
+    }
+
     fn get_duration(expression: &Expr) -> Result<Duration> {
         match expression {
             Expr::Literal(ScalarValue::IntervalDayTime(Some(val))) => {
@@ -722,6 +1116,16 @@ impl<'a> SqlPipelineBuilder<'a> {
     }
 
     fn insert_join(&mut self, join: &datafusion_expr::logical_plan::Join) -> Result<SqlOperator> {
+        if let Some((table, row_struct, alias)) = self.reference_table_scan(&join.right) {
+            return self.insert_lookup_join(join, table, row_struct, alias);
+        }
+        if let Some((table, _, _)) = self.reference_table_scan(&join.left) {
+            bail!(
+                "reference table '{}' must be on the right side of the join",
+                table.name
+            );
+        }
+
         let left_input = self.insert_sql_plan(&join.left)?;
         let right_input = self.insert_sql_plan(&join.right)?;
         if left_input.is_updating() || right_input.is_updating() {
@@ -771,6 +1175,16 @@ impl<'a> SqlPipelineBuilder<'a> {
         if right_key.output_struct() != left_key.output_struct() {
             bail!("join key types must match. Try casting?");
         }
+
+        let window = match (left_input.window(), right_input.window()) {
+            (Some(left), Some(right))
+                if left == right && matches!(left, WindowType::Tumbling { .. }) =>
+            {
+                Some(left)
+            }
+            _ => None,
+        };
+
         let join_operator = SqlOperator::JoinOperator(
             Box::new(left_input),
             Box::new(right_input),
@@ -778,6 +1192,7 @@ impl<'a> SqlPipelineBuilder<'a> {
                 left_key,
                 right_key,
                 join_type,
+                window,
             },
         );
         let Some(join_filter) = &join.filter else {
@@ -792,24 +1207,168 @@ impl<'a> SqlPipelineBuilder<'a> {
         ))
     }
 
+    /// If `plan` scans a reference table, returns the table along with the struct of the fields
+    /// that are read from it and the alias they're qualified by
+    fn reference_table_scan(
+        &self,
+        plan: &LogicalPlan,
+    ) -> Option<(ReferenceTable, StructDef, Option<String>)> {
+        match plan {
+            LogicalPlan::SubqueryAlias(subquery_alias) => {
+                let (table, row_struct, _) = self.reference_table_scan(&subquery_alias.input)?;
+                let alias = subquery_alias
+                    .schema
+                    .fields()
+                    .first()
+                    .and_then(|field| Column::convert(&field.qualified_column()).relation);
+                Some((table, row_struct, alias))
+            }
+            LogicalPlan::TableScan(table_scan) => {
+                match self
+                    .schema_provider
+                    .get_table(&table_scan.table_name.to_string())?
+                {
+                    Table::ReferenceTable(table) => Some((
+                        table.clone(),
+                        table.row_struct(table_scan.projection.as_ref()),
+                        None,
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn insert_lookup_join(
+        &mut self,
+        join: &datafusion_expr::logical_plan::Join,
+        table: ReferenceTable,
+        row_struct: StructDef,
+        alias: Option<String>,
+    ) -> Result<SqlOperator> {
+        let input = self.insert_sql_plan(&join.left)?;
+        if input.is_updating() {
+            bail!("don't support joins with updating inputs");
+        }
+        match join.join_constraint {
+            JoinConstraint::On => {}
+            JoinConstraint::Using => bail!("don't support 'using' in joins"),
+        };
+        let join_type: JoinType = join.join_type.try_into()?;
+        if !matches!(join_type, JoinType::Inner | JoinType::Left) {
+            bail!(
+                "only inner and left joins are supported against reference table '{}'",
+                table.name
+            );
+        }
+
+        let field_names: Vec<_> = join
+            .on
+            .iter()
+            .map(|(left, _right)| Column::convert_expr(left))
+            .collect::<Result<Vec<_>>>()?;
+        let (key_computations, row_key_computations): (Vec<_>, Vec<_>) = join
+            .on
+            .iter()
+            .map(|(left, right)| {
+                Ok((
+                    self.ctx(&input.return_type()).compile_expr(left)?,
+                    self.ctx(&row_struct).compile_expr(right)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        let key = Projection {
+            field_names: field_names.clone(),
+            field_computations: key_computations,
+        };
+        let row_key = Projection {
+            field_names,
+            field_computations: row_key_computations,
+        };
+
+        if key.output_struct() != row_key.output_struct() {
+            bail!("join key types must match. Try casting?");
+        }
+
+        let row_projection = Projection {
+            field_names: row_struct
+                .fields
+                .iter()
+                .map(|f| Column {
+                    relation: alias.clone(),
+                    name: f.name.clone(),
+                })
+                .collect(),
+            field_computations: row_struct
+                .fields
+                .iter()
+                .map(|f| Expression::Column(ColumnExpression::new(f.clone())))
+                .collect(),
+        };
+
+        let lookup_join = SqlOperator::LookupJoin(
+            Box::new(input),
+            LookupJoinOperator {
+                table,
+                row_struct,
+                row_projection,
+                key,
+                row_key,
+                join_type,
+            },
+        );
+        let Some(join_filter) = &join.filter else {
+            return Ok(lookup_join);
+        };
+        let join_filter = self
+            .ctx(&lookup_join.return_type())
+            .compile_expr(join_filter)?;
+        Ok(SqlOperator::RecordTransform(
+            Box::new(lookup_join),
+            RecordTransform::Filter(join_filter),
+        ))
+    }
+
     fn insert_table_scan(
         &mut self,
         table_scan: &datafusion::logical_expr::TableScan,
     ) -> Result<SqlOperator> {
         let table_name = table_scan.table_name.to_string();
-        let source = self
+        let table = self
             .schema_provider
             .get_table(&table_name)
             .ok_or_else(|| anyhow!("table {} not found", table_scan.table_name))?;
-        let source = source
+        let source = table
             .as_sql_source(self)
             .map_err(|e| anyhow!("failed to plan {}: {}", table_scan.table_name, e))?;
 
         if let Some(projection) = table_scan.projection.as_ref() {
-            let fields: Vec<StructField> = projection
-                .iter()
-                .map(|i| source.return_type().fields[*i].clone())
-                .collect();
+            let source_type = source.return_type();
+            let fields: Vec<StructField> = match table {
+                // the source may have been pruned, so fields have to be found by name
+                Table::ConnectorTable(table) => projection
+                    .iter()
+                    .map(|i| {
+                        let name = &table.fields[*i].name;
+                        source_type
+                            .fields
+                            .iter()
+                            .find(|f| &f.name == name)
+                            .cloned()
+                            .ok_or_else(|| {
+                                anyhow!("field {} was pruned from source {}", name, table.name)
+                            })
+                    })
+                    .collect::<Result<_>>()?,
+                _ => projection
+                    .iter()
+                    .map(|i| source_type.fields[*i].clone())
+                    .collect(),
+            };
 
             let field_names = fields
                 .iter()
@@ -842,6 +1401,9 @@ impl<'a> SqlPipelineBuilder<'a> {
         if let Some(expr) = window.window_expr.get(0) {
             match expr {
                 Expr::WindowFunction(w) => {
+                    let input_struct = input.return_type();
+                    let mut ctx = self.ctx(&input_struct);
+
                     let window_fn = match &w.fun {
                         datafusion_expr::WindowFunction::AggregateFunction(_) => {
                             bail!("window aggregate functions not yet supported")
@@ -849,6 +1411,30 @@ impl<'a> SqlPipelineBuilder<'a> {
                         datafusion_expr::WindowFunction::BuiltInWindowFunction(
                             BuiltInWindowFunction::RowNumber,
                         ) => WindowFunction::RowNumber,
+                        datafusion_expr::WindowFunction::BuiltInWindowFunction(
+                            BuiltInWindowFunction::Rank,
+                        ) => WindowFunction::Rank,
+                        datafusion_expr::WindowFunction::BuiltInWindowFunction(
+                            BuiltInWindowFunction::DenseRank,
+                        ) => WindowFunction::DenseRank,
+                        datafusion_expr::WindowFunction::BuiltInWindowFunction(
+                            f @ (BuiltInWindowFunction::Lag | BuiltInWindowFunction::Lead),
+                        ) => {
+                            let (value, offset, default) = Self::offset_args(&mut ctx, &w.args)?;
+                            if *f == BuiltInWindowFunction::Lag {
+                                WindowFunction::Lag {
+                                    value,
+                                    offset,
+                                    default,
+                                }
+                            } else {
+                                WindowFunction::Lead {
+                                    value,
+                                    offset,
+                                    default,
+                                }
+                            }
+                        }
                         datafusion_expr::WindowFunction::BuiltInWindowFunction(w) => {
                             bail!("Window function {} not yet supported", w);
                         }
@@ -857,9 +1443,6 @@ impl<'a> SqlPipelineBuilder<'a> {
                         }
                     };
 
-                    let input_struct = input.return_type();
-                    let mut ctx = self.ctx(&input_struct);
-
                     let order_by: Vec<_> = w
                         .order_by
                         .iter()
@@ -922,6 +1505,47 @@ impl<'a> SqlPipelineBuilder<'a> {
         bail!("no expression for window");
     }
 
+    // the arguments of LAG and LEAD: the value, an optional literal offset (defaulting to 1) and an
+    // optional default for rows without a row at that offset
+    fn offset_args(
+        ctx: &mut ExpressionContext,
+        args: &[Expr],
+    ) -> Result<(Expression, usize, Option<Expression>)> {
+        let Some(value) = args.get(0) else {
+            bail!("LAG and LEAD require an argument");
+        };
+        let value = ctx.compile_expr(value)?;
+
+        let offset = match args.get(1) {
+            None => 1,
+            Some(Expr::Literal(ScalarValue::Int64(Some(offset)))) if *offset >= 0 => {
+                *offset as usize
+            }
+            Some(Expr::Literal(ScalarValue::UInt64(Some(offset)))) => *offset as usize,
+            Some(offset) => bail!(
+                "the offset of LAG and LEAD must be a non-negative integer literal, not {}",
+                offset
+            ),
+        };
+
+        // a NULL default is the same as no default
+        let default = args
+            .get(2)
+            .filter(|default| !matches!(default, Expr::Literal(ScalarValue::Null)))
+            .map(|default| ctx.compile_expr(default))
+            .transpose()?;
+        if let Some(default) = &default {
+            if default.return_type().as_datatype() != value.return_type().as_datatype() {
+                bail!(
+                    "the default of LAG and LEAD must have the same type as its value ({:?})",
+                    value.return_type().as_datatype()
+                );
+            }
+        }
+
+        Ok((value, offset, default))
+    }
+
     fn insert_subquery_alias(
         &mut self,
         subquery_alias: &datafusion_expr::logical_plan::SubqueryAlias,
@@ -981,7 +1605,7 @@ impl<'a> SqlPipelineBuilder<'a> {
                 sink_name,
                 logical_plan,
             } => {
-                let input = self.insert_sql_plan(&logical_plan)?;
+                let input = self.insert_query(&logical_plan)?;
                 let sink = self.schema_provider.get_table(&sink_name).ok_or_else(|| {
                     anyhow!("Could not find sink {} in schema provider", sink_name)
                 })?;
@@ -1013,7 +1637,7 @@ impl<'a> SqlPipelineBuilder<'a> {
                     }
                     Table::ConnectorTable(c) => {
                         self.insert_nodes.push(
-                            c.as_sql_sink(input)
+                            c.as_sql_sink(input, self.schema_provider)
                                 .map_err(|e| anyhow!("failed to plan {}: {}", c.name, e))?,
                         );
                     }
@@ -1021,10 +1645,14 @@ impl<'a> SqlPipelineBuilder<'a> {
                         name: _,
                         logical_plan: _,
                     } => todo!(),
+                    Table::ReferenceTable(r) => {
+                        bail!("can't insert into reference table '{}'", r.name)
+                    }
+                    Table::ProcessTable(_) => bail!("can't insert into the output of PROCESS"),
                 }
             }
             Insert::Anonymous { logical_plan } => {
-                let operator = self.insert_sql_plan(&logical_plan)?;
+                let operator = self.insert_query(&logical_plan)?;
                 self.insert_nodes.push(operator);
             }
         }
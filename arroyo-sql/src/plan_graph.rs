@@ -3,26 +3,30 @@ use std::{
     time::Duration,
 };
 
-use arrow_schema::DataType;
 use arroyo_datastream::{
-    EdgeType, ExpressionReturnType, NonWindowAggregator, Operator, Program, SlidingAggregatingTopN,
-    SlidingWindowAggregator, StreamEdge, StreamNode, TumblingTopN, TumblingWindowAggregator,
-    WatermarkType, WindowAgg, WindowType,
+    EdgeType, ExpressionReturnType, LookupJoin, NonWindowAggregator, Operator, ProcessFunction,
+    Program, SlidingAggregatingTopN, SlidingWindowAggregator, StreamEdge, StreamNode, TenantLimit,
+    TimestampBounds, TopN, TumblingTopN, TumblingWindowAggregator, WatermarkType, WindowAgg,
+    WindowType,
 };
 
 use petgraph::graph::{DiGraph, NodeIndex};
+use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{parse_quote, parse_str};
 
 use crate::{
-    expressions::SortExpression,
+    expressions::{to_optimized_syn_expression, Expression, SortExpression},
     external::{ProcessingMode, SinkUpdateType, SqlSink, SqlSource},
+    lints::{lint, SqlWarning},
     operators::{AggregateProjection, GroupByKind, Projection, TwoPhaseAggregateProjection},
     optimizations::optimize,
     pipeline::{
-        JoinType, MethodCompiler, RecordTransform, SourceOperator, SqlOperator, WindowFunction,
+        JoinType, LookupJoinOperator, MethodCompiler, ProcessOperator, RecordTransform,
+        SourceAssertion, SourceOperator, SqlOperator, TopNOperator, WindowFunction,
     },
-    types::{StructDef, StructField, StructPair, TypeDef},
+    types::{StructDef, StructField, StructPair},
+    udfs::udf_defs,
     ArroyoSchemaProvider, SqlConfig,
 };
 use anyhow::Result;
@@ -31,6 +35,9 @@ use anyhow::Result;
 pub enum PlanOperator {
     Source(String, SqlSource),
     Watermark(WatermarkType),
+    TimestampBounds(TimestampBounds),
+    Assertion(SourceAssertion),
+    TenantLimit(TenantLimit),
     RecordTransform(RecordTransform),
     FusedRecordTransform(FusedRecordTransform),
     Unkey,
@@ -51,6 +58,7 @@ pub enum PlanOperator {
     TumblingWindowTwoPhaseAggregator {
         tumble_width: Duration,
         projection: TwoPhaseAggregateProjection,
+        input_updating: bool,
     },
     SlidingWindowTwoPhaseAggregator {
         width: Duration,
@@ -58,6 +66,7 @@ pub enum PlanOperator {
         projection: TwoPhaseAggregateProjection,
     },
     InstantJoin,
+    WindowJoin(WindowType),
     JoinWithExpiration {
         left_expiration: Duration,
         right_expiration: Duration,
@@ -65,6 +74,11 @@ pub enum PlanOperator {
     },
     JoinListMerge(JoinType, StructPair),
     JoinPairMerge(JoinType, StructPair),
+    LookupJoin {
+        lookup: LookupJoinOperator,
+        // the records of the stream that's joined against the table
+        input_struct: StructDef,
+    },
     Flatten,
     // TODO: figure out naming of various things called 'window'
     WindowFunction(WindowFunctionOperator),
@@ -88,6 +102,14 @@ pub enum PlanOperator {
         max_elements: usize,
         window_function: WindowFunctionOperator,
     },
+    // the top records of a query's output, kept by each subtask for its own input and then, if
+    // `global`, merged by a single subtask
+    TopN {
+        order_by: Vec<SortExpression>,
+        max_elements: usize,
+        input_updating: bool,
+        global: bool,
+    },
     // for external nodes, mainly sinks.
     StreamOperator(String, Operator),
     ToDebezium,
@@ -104,6 +126,75 @@ pub struct WindowFunctionOperator {
     pub field_name: String,
 }
 
+impl WindowFunctionOperator {
+    // the statements run before visiting the sorted rows of a partition, and the expression
+    // computing the window function for the row `arg` at position `index` of `rows`
+    fn window_field_computation(&self) -> (TokenStream, TokenStream) {
+        match &self.window_function {
+            WindowFunction::RowNumber => (quote!(), quote!((index + 1) as u64)),
+            WindowFunction::Rank | WindowFunction::DenseRank => {
+                let sort_key = SortExpression::sort_tuple_expression(&self.order_by);
+                let next_rank = if self.window_function == WindowFunction::Rank {
+                    quote!(rank = (index + 1) as u64)
+                } else {
+                    quote!(rank += 1)
+                };
+                (
+                    quote! {
+                        let mut rank = 0u64;
+                        let mut last_key = None;
+                    },
+                    quote!({
+                        let key = #sort_key;
+                        if last_key.as_ref() != Some(&key) {
+                            #next_rank;
+                            last_key = Some(key);
+                        }
+                        rank
+                    }),
+                )
+            }
+            WindowFunction::Lag {
+                value,
+                offset,
+                default,
+            }
+            | WindowFunction::Lead {
+                value,
+                offset,
+                default,
+            } => {
+                let row = if matches!(self.window_function, WindowFunction::Lag { .. }) {
+                    quote!(index.checked_sub(#offset).and_then(|j| rows.get(j)))
+                } else {
+                    quote!(rows.get(index + #offset))
+                };
+                let value = nullable_expression(value);
+                let default = match default {
+                    Some(default) => nullable_expression(default),
+                    None => quote!(None),
+                };
+                (
+                    quote!(),
+                    quote!(match #row {
+                        Some(arg) => #value,
+                        None => #default,
+                    }),
+                )
+            }
+        }
+    }
+}
+
+fn nullable_expression(expression: &Expression) -> TokenStream {
+    let expr = expression.to_syn_expression();
+    if expression.nullable() {
+        quote!(#expr)
+    } else {
+        quote!(Some(#expr))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FusedRecordTransform {
     pub expressions: Vec<RecordTransform>,
@@ -128,7 +219,7 @@ impl FusedRecordTransform {
                 panic!("FusedRecordTransform.to_predicate_operator() called on non-predicate expression");
             };
             names.push("filter");
-            predicates.push(predicate.to_syn_expression());
+            predicates.push(to_optimized_syn_expression(predicate));
         }
         let predicate: syn::Expr = parse_quote!( {
             let arg = &record.value;
@@ -265,7 +356,7 @@ impl FusedRecordTransform {
                 }
                 (RecordTransform::Filter(predicate), false) => {
                     names.push("filter");
-                    let expr = predicate.to_syn_expression();
+                    let expr = to_optimized_syn_expression(predicate);
                     let unwrap = if predicate.nullable() {
                         quote!(.unwrap_or(false))
                     } else {
@@ -279,7 +370,7 @@ impl FusedRecordTransform {
                 }
                 (RecordTransform::Filter(predicate), true) => {
                     names.push("updating_filter");
-                    let expr = predicate.to_syn_expression();
+                    let expr = to_optimized_syn_expression(predicate);
                     let record_type = output_type.record_type();
                     let unwrap = if predicate.nullable() {
                         quote!(.unwrap_or(false))
@@ -371,6 +462,9 @@ impl PlanNode {
         match &self.operator {
             PlanOperator::Source(name, _) => name.to_string(),
             PlanOperator::Watermark(_) => "watermark".to_string(),
+            PlanOperator::TimestampBounds(_) => "timestamp_bounds".to_string(),
+            PlanOperator::Assertion(_) => "assertion".to_string(),
+            PlanOperator::TenantLimit(_) => "tenant_limit".to_string(),
             PlanOperator::RecordTransform(record_transform) => record_transform.name(),
             PlanOperator::FusedRecordTransform(_) => "fused".to_string(),
             PlanOperator::Unkey => "unkey".to_string(),
@@ -383,14 +477,18 @@ impl PlanNode {
                 "sliding_window_two_phase_aggregator".to_string()
             }
             PlanOperator::InstantJoin => "instant_join".to_string(),
+            PlanOperator::WindowJoin(_) => "window_join".to_string(),
             PlanOperator::JoinWithExpiration { .. } => "join_with_expiration".to_string(),
             PlanOperator::JoinListMerge(_, _) => "join_list_merge".to_string(),
             PlanOperator::JoinPairMerge(_, _) => "join_pair_merge".to_string(),
+            PlanOperator::LookupJoin { .. } => "lookup_join".to_string(),
             PlanOperator::Flatten => "flatten".to_string(),
             PlanOperator::WindowFunction { .. } => "window_function".to_string(),
             PlanOperator::StreamOperator(name, _) => name.to_string(),
             PlanOperator::TumblingLocalAggregator { .. } => "tumbling_local_aggregator".to_string(),
             PlanOperator::SlidingAggregatingTopN { .. } => "sliding_aggregating_top_n".to_string(),
+            PlanOperator::TopN { global: false, .. } => "top_n".to_string(),
+            PlanOperator::TopN { global: true, .. } => "global_top_n".to_string(),
             PlanOperator::TumblingTopN { .. } => "tumbling_top_n".to_string(),
             PlanOperator::Sink(name, _) => format!("sink_{}", name),
             PlanOperator::ToDebezium => "to_debezium".to_string(),
@@ -403,6 +501,9 @@ impl PlanNode {
         match &self.operator {
             PlanOperator::Source(_name, source) => source.operator.clone(),
             PlanOperator::Watermark(watermark) => Operator::Watermark(watermark.clone()),
+            PlanOperator::TimestampBounds(bounds) => Operator::TimestampBounds(bounds.clone()),
+            PlanOperator::Assertion(assertion) => assertion.as_operator(),
+            PlanOperator::TenantLimit(limit) => Operator::TenantLimit(limit.clone()),
             PlanOperator::RecordTransform(record_transform) => {
                 record_transform.as_operator(self.output_type.is_updating())
             }
@@ -473,16 +574,71 @@ impl PlanNode {
             PlanOperator::TumblingWindowTwoPhaseAggregator {
                 tumble_width,
                 projection,
+                input_updating,
             } => {
-                let aggregate_expr = projection.tumbling_aggregation_syn_expression();
-                let bin_merger = projection.bin_merger_syn_expression();
-                let bin_type = projection.bin_type();
-                arroyo_datastream::Operator::TumblingWindowAggregator(TumblingWindowAggregator {
-                    width: *tumble_width,
-                    aggregator: quote!(|arg| {#aggregate_expr}).to_string(),
-                    bin_merger: quote!(|arg, current_bin| {#bin_merger}).to_string(),
-                    bin_type: quote!(#bin_type).to_string(),
-                })
+                if *input_updating {
+                    // each bin holds the memory of the rows in it, so that retractions can be
+                    // removed from it; bins whose rows have all been retracted are empty
+                    let aggregate_expr = projection.sliding_aggregation_syn_expression();
+                    let bin_merger = projection.bin_merger_syn_expression();
+                    let bin_type = projection.bin_type();
+                    let memory_type = projection.memory_type();
+                    let memory_add = projection.memory_add_syn_expression();
+                    let memory_remove = projection.memory_remove_syn_expression();
+
+                    arroyo_datastream::Operator::TumblingWindowAggregator(
+                        TumblingWindowAggregator {
+                            width: *tumble_width,
+                            aggregator: quote!(|arg| {
+                                let arg = arg.as_ref()?;
+                                Some(#aggregate_expr)
+                            })
+                            .to_string(),
+                            bin_merger: quote!(|arg, current| {
+                                let current: Option<#memory_type> = current.cloned().flatten();
+                                let current_bin: Option<#bin_type> = None;
+                                match arg.map_over_inner(|arg| #bin_merger) {
+                                    // rows that the window hasn't seen can't be retracted from it
+                                    Some(arroyo_types::UpdatingData::Retract(retract)) => {
+                                        let current = current?;
+                                        let bin_value = retract;
+                                        #memory_remove
+                                    },
+                                    Some(arroyo_types::UpdatingData::Update { old, new }) => {
+                                        let current = match current {
+                                            Some(current) => {
+                                                let bin_value = old;
+                                                #memory_remove
+                                            },
+                                            None => None,
+                                        };
+                                        let bin_value = new;
+                                        Some(#memory_add)
+                                    },
+                                    Some(arroyo_types::UpdatingData::Append(append)) => {
+                                        let bin_value = append;
+                                        Some(#memory_add)
+                                    },
+                                    None => current,
+                                }
+                            })
+                            .to_string(),
+                            bin_type: quote!(Option<#memory_type>).to_string(),
+                        },
+                    )
+                } else {
+                    let aggregate_expr = projection.tumbling_aggregation_syn_expression();
+                    let bin_merger = projection.bin_merger_syn_expression();
+                    let bin_type = projection.bin_type();
+                    arroyo_datastream::Operator::TumblingWindowAggregator(
+                        TumblingWindowAggregator {
+                            width: *tumble_width,
+                            aggregator: quote!(|arg| {Some(#aggregate_expr)}).to_string(),
+                            bin_merger: quote!(|arg, current_bin| {#bin_merger}).to_string(),
+                            bin_type: quote!(#bin_type).to_string(),
+                        },
+                    )
+                }
             }
             PlanOperator::SlidingWindowTwoPhaseAggregator {
                 width,
@@ -509,6 +665,9 @@ impl PlanNode {
             PlanOperator::InstantJoin => Operator::WindowJoin {
                 window: WindowType::Instant,
             },
+            PlanOperator::WindowJoin(window) => Operator::WindowJoin {
+                window: window.clone(),
+            },
             PlanOperator::JoinWithExpiration {
                 left_expiration,
                 right_expiration,
@@ -553,13 +712,48 @@ impl PlanNode {
                     }
                 }
             }
-            PlanOperator::WindowFunction(WindowFunctionOperator {
-                window_function,
-                order_by,
-                window_type,
-                result_struct,
-                field_name: _,
-            }) => {
+            PlanOperator::LookupJoin {
+                lookup,
+                input_struct,
+            } => {
+                let right_struct = lookup.right_struct();
+                let merge_struct = lookup
+                    .join_type
+                    .join_struct_type(input_struct, &right_struct)
+                    .get_type();
+                let merge_expr = lookup
+                    .join_type
+                    .merge_syn_expression(input_struct, &right_struct);
+                let row_projection = lookup.row_projection.to_syn_expression();
+                let row_key = lookup.row_key.to_syn_expression();
+                let row_type = lookup.row_struct.get_type();
+                // inner joins are only merged with rows that were found
+                let right = match lookup.join_type {
+                    JoinType::Inner => quote!(right.unwrap()),
+                    _ => quote!(right),
+                };
+
+                Operator::LookupJoin(LookupJoin {
+                    location: lookup.table.location.clone(),
+                    refresh_interval: lookup.table.refresh_interval,
+                    inner: lookup.join_type == JoinType::Inner,
+                    row_type: quote!(#row_type).to_string(),
+                    row_key: quote!(|arg| {#row_key}).to_string(),
+                    merge: quote!(|left, right| {
+                        let right = right.map(|arg| #row_projection);
+                        let arg = #merge_struct { left: left.clone(), right: #right };
+                        #merge_expr
+                    })
+                    .to_string(),
+                })
+            }
+            PlanOperator::WindowFunction(window_function_operator) => {
+                let WindowFunctionOperator {
+                    order_by,
+                    window_type,
+                    result_struct,
+                    ..
+                } = window_function_operator;
                 let window_field = result_struct.fields.last().unwrap().field_ident();
                 let result_struct_name = result_struct.get_type();
                 let mut field_assignments: Vec<_> = result_struct
@@ -572,13 +766,10 @@ impl PlanNode {
                     })
                     .collect();
 
-                match window_function {
-                    WindowFunction::RowNumber => {
-                        field_assignments.push(quote! {
-                            #window_field: i as u64
-                        });
-                    }
-                }
+                let (preamble, window_value) = window_function_operator.window_field_computation();
+                field_assignments.push(quote! {
+                    #window_field: #window_value
+                });
 
                 let output_expression = quote!(#result_struct_name {
                     #(#field_assignments, )*
@@ -597,9 +788,10 @@ impl PlanNode {
                         expression: quote! {
                             {
                                 #sort
+                                let rows = arg;
+                                #preamble
                                 let mut result = vec![];
-                                for (index, arg) in arg.iter().enumerate() {
-                                    let i = index + 1;
+                                for (index, arg) in rows.iter().enumerate() {
                                     result.push(#output_expression);
                                 }
                                 result
@@ -735,6 +927,7 @@ impl PlanNode {
                             #window_field: i as u64
                         });
                     }
+                    _ => unreachable!("only ROW_NUMBER windows are optimized into top-n"),
                 }
                 let output_expression = quote!(#output_struct {
                     #(#field_assignments, )*
@@ -761,6 +954,22 @@ impl PlanNode {
                     converter,
                 })
             }
+            PlanOperator::TopN {
+                order_by,
+                max_elements,
+                input_updating,
+                global,
+            } => {
+                let sort_key = SortExpression::sort_tuple_expression(order_by);
+                let sort_key_type = SortExpression::sort_tuple_type(order_by);
+                Operator::TopN(TopN {
+                    max_elements: *max_elements,
+                    extractor: quote!(|arg| #sort_key).to_string(),
+                    sort_key_type: quote!(#sort_key_type).to_string(),
+                    input_updating: *input_updating,
+                    global: *global,
+                })
+            }
             PlanOperator::Flatten => arroyo_datastream::Operator::FlattenOperator {
                 name: "flatten".into(),
             },
@@ -866,6 +1075,19 @@ impl PlanNode {
             | PlanOperator::JoinListMerge(join_type, StructPair { left, right }) => {
                 output_types.insert(join_type.join_struct_type(left, right));
             }
+            PlanOperator::LookupJoin {
+                lookup,
+                input_struct,
+            } => {
+                let right_struct = lookup.right_struct();
+                output_types.extend(lookup.row_struct.all_structs());
+                output_types.extend(right_struct.all_structs());
+                output_types.insert(
+                    lookup
+                        .join_type
+                        .join_struct_type(input_struct, &right_struct),
+                );
+            }
             PlanOperator::FusedRecordTransform(fused_record_transform) => {
                 fused_record_transform.output_types.iter().for_each(|t| {
                     output_types.extend(t.get_all_types());
@@ -1144,6 +1366,8 @@ pub struct PlanGraph {
     pub types: HashSet<StructDef>,
     pub key_structs: HashSet<String>,
     pub sources: HashMap<String, NodeIndex>,
+    // the nodes of sources before their records are checked against assertions
+    pub unchecked_sources: HashMap<String, NodeIndex>,
     pub named_tables: HashMap<String, NodeIndex>,
     pub sql_config: SqlConfig,
     pub saved_sources_used: Vec<i64>,
@@ -1156,6 +1380,7 @@ impl PlanGraph {
             types: HashSet::new(),
             key_structs: HashSet::new(),
             sources: HashMap::new(),
+            unchecked_sources: HashMap::new(),
             named_tables: HashMap::new(),
             sql_config,
             saved_sources_used: vec![],
@@ -1169,7 +1394,10 @@ impl PlanGraph {
             SqlOperator::JoinOperator(left, right, join_operator) => {
                 self.add_join(left, right, join_operator)
             }
+            SqlOperator::LookupJoin(input, lookup) => self.add_lookup_join(input, lookup),
             SqlOperator::Window(input, window_operator) => self.add_window(input, window_operator),
+            SqlOperator::TopN(input, top_n) => self.add_top_n(input, top_n),
+            SqlOperator::Process(input, process) => self.add_process(input, process),
             SqlOperator::RecordTransform(input, transform) => {
                 self.add_record_transform(input, transform)
             }
@@ -1215,6 +1443,14 @@ impl PlanGraph {
     }
 
     fn add_sql_source(&mut self, source_operator: SourceOperator) -> NodeIndex {
+        if source_operator.unchecked {
+            let name = source_operator.name.clone();
+            self.add_sql_source(SourceOperator {
+                unchecked: false,
+                ..source_operator
+            });
+            return self.unchecked_sources[&name];
+        }
         if let Some(node_index) = self.sources.get(&source_operator.name) {
             return *node_index;
         }
@@ -1256,6 +1492,54 @@ impl PlanGraph {
                 .add_edge(current_index, timestamp_index, timestamp_edge);
             current_index = timestamp_index;
         }
+        if let Some(bounds) = source_operator.timestamp_bounds {
+            let bounds_index = self.insert_operator(
+                PlanOperator::TimestampBounds(bounds),
+                self.get_plan_node(current_index).output_type.clone(),
+            );
+            let bounds_edge = PlanEdge {
+                edge_type: EdgeType::Forward,
+            };
+            self.graph
+                .add_edge(current_index, bounds_index, bounds_edge);
+            current_index = bounds_index;
+        }
+        self.unchecked_sources
+            .insert(source_operator.name.clone(), current_index);
+        for assertion in source_operator.assertions {
+            let assertion_index = self.insert_operator(
+                PlanOperator::Assertion(assertion),
+                self.get_plan_node(current_index).output_type.clone(),
+            );
+            let assertion_edge = PlanEdge {
+                edge_type: EdgeType::Forward,
+            };
+            self.graph
+                .add_edge(current_index, assertion_index, assertion_edge);
+            current_index = assertion_index;
+        }
+        if let Some(tenant_limit) = source_operator.tenant_limit {
+            // records are partitioned by tenant, so that each tenant's rate is limited by a
+            // single subtask
+            let output_type = self.get_plan_node(current_index).output_type.clone();
+            let key_index = self.insert_operator(
+                PlanOperator::RecordTransform(RecordTransform::KeyProjection(
+                    tenant_limit.key.clone(),
+                )),
+                output_type.with_key(tenant_limit.key.output_struct()),
+            );
+            let key_edge = PlanEdge {
+                edge_type: EdgeType::Forward,
+            };
+            self.graph.add_edge(current_index, key_index, key_edge);
+            let limit_index =
+                self.insert_operator(PlanOperator::TenantLimit(tenant_limit.limit), output_type);
+            let limit_edge = PlanEdge {
+                edge_type: EdgeType::Shuffle,
+            };
+            self.graph.add_edge(key_index, limit_index, limit_edge);
+            current_index = limit_index;
+        }
         let watermark = if let Some(watermark_expression) = source_operator.watermark_column {
             let expression = watermark_expression.to_syn_expression();
             let null_checked_expression = if watermark_expression.nullable() {
@@ -1271,11 +1555,13 @@ impl PlanGraph {
                    #null_checked_expression
                 })
                 .to_string(),
+                alignment: source_operator.watermark_alignment,
             }
         } else {
             arroyo_datastream::WatermarkType::FixedLateness {
                 period: Duration::from_secs(1),
                 max_lateness: Duration::from_secs(1),
+                alignment: source_operator.watermark_alignment,
             }
         };
         let watermark_operator = PlanOperator::Watermark(watermark);
@@ -1309,6 +1595,7 @@ impl PlanGraph {
             return self.add_updating_aggregator(input, aggregate);
         }
         let input_index = self.add_sql_operator(*input);
+        let input_updating = self.get_plan_node(input_index).output_type.is_updating();
 
         let output_type = aggregate.output_struct();
         let key_struct = aggregate.key.output_struct();
@@ -1326,9 +1613,20 @@ impl PlanGraph {
         self.graph.add_edge(input_index, key_index, key_edge);
         let aggregate_projection = aggregate.aggregating;
         let aggregate_struct = aggregate_projection.output_struct();
-        let aggregate_operator = PlanOperator::WindowAggregate {
-            window: aggregate.window,
-            projection: aggregate_projection,
+        let aggregate_operator = match (input_updating, &aggregate.window) {
+            // retractions are applied to the bins of a tumbling window, which only emits once it
+            // closes (the pipeline builder rejects other windows over updating inputs)
+            (true, WindowType::Tumbling { width, .. }) => {
+                PlanOperator::TumblingWindowTwoPhaseAggregator {
+                    tumble_width: *width,
+                    projection: aggregate_projection.try_into().unwrap(),
+                    input_updating: true,
+                }
+            }
+            _ => PlanOperator::WindowAggregate {
+                window: aggregate.window,
+                projection: aggregate_projection,
+            },
         };
         let aggregate_index = self.insert_operator(
             aggregate_operator,
@@ -1374,6 +1672,7 @@ impl PlanGraph {
         // right now left and right either both have or don't have windows.
         let has_window = left.has_window();
         let join_type = join_operator.join_type;
+        let window = join_operator.window;
         let left_index = self.add_sql_operator(*left);
         let right_index = self.add_sql_operator(*right);
 
@@ -1415,9 +1714,12 @@ impl PlanGraph {
                 left_key_index,
                 right_key_index,
                 key_struct,
-                left_type,
-                right_type,
+                StructPair {
+                    left: left_type,
+                    right: right_type,
+                },
                 join_type,
+                window,
             )
         } else {
             self.add_join_with_expiration(
@@ -1431,20 +1733,131 @@ impl PlanGraph {
         }
     }
 
+    fn add_lookup_join(
+        &mut self,
+        input: Box<SqlOperator>,
+        lookup: LookupJoinOperator,
+    ) -> NodeIndex {
+        let input_struct = input.return_type();
+        let output_struct = lookup
+            .join_type
+            .output_struct(&input_struct, &lookup.right_struct());
+        let input_index = self.add_sql_operator(*input);
+
+        // every subtask loads the whole table, so records are keyed without being shuffled
+        let key_index = self.insert_operator(
+            PlanOperator::RecordTransform(RecordTransform::KeyProjection(lookup.key.clone())),
+            PlanType::Keyed {
+                key: lookup.key.output_struct(),
+                value: input_struct.clone(),
+            },
+        );
+        let key_edge = PlanEdge {
+            edge_type: EdgeType::Forward,
+        };
+        self.graph.add_edge(input_index, key_index, key_edge);
+
+        let lookup_index = self.insert_operator(
+            PlanOperator::LookupJoin {
+                lookup,
+                input_struct,
+            },
+            PlanType::Unkeyed(output_struct),
+        );
+        let lookup_edge = PlanEdge {
+            edge_type: EdgeType::Forward,
+        };
+        self.graph.add_edge(key_index, lookup_index, lookup_edge);
+
+        lookup_index
+    }
+
+    fn add_top_n(&mut self, input: Box<SqlOperator>, top_n: TopNOperator) -> NodeIndex {
+        let input_struct = input.return_type();
+        let input_index = self.add_sql_operator(*input);
+        let input_updating = self.graph[input_index].output_type.is_updating();
+        let output_type = PlanType::Updating(Box::new(PlanType::Unkeyed(input_struct)));
+
+        // each subtask only sends on the records that are in the top of its own input, so the
+        // single subtask that merges them only has to hold the top of each of them
+        let partial_index = self.insert_operator(
+            PlanOperator::TopN {
+                order_by: top_n.order_by.clone(),
+                max_elements: top_n.limit,
+                input_updating,
+                global: false,
+            },
+            output_type.clone(),
+        );
+        let partial_edge = PlanEdge {
+            edge_type: EdgeType::Forward,
+        };
+        self.graph
+            .add_edge(input_index, partial_index, partial_edge);
+
+        let global_index = self.insert_operator(
+            PlanOperator::TopN {
+                order_by: top_n.order_by,
+                max_elements: top_n.limit,
+                input_updating: true,
+                global: true,
+            },
+            output_type,
+        );
+        let global_edge = PlanEdge {
+            edge_type: EdgeType::Shuffle,
+        };
+        self.graph
+            .add_edge(partial_index, global_index, global_edge);
+
+        global_index
+    }
+
+    fn add_process(&mut self, input: Box<SqlOperator>, process: ProcessOperator) -> NodeIndex {
+        let input_index = self.add_sql_operator(*input);
+
+        // the process function runs as a single subtask (see Program::enforce_single_parallelism),
+        // which all of its input is shuffled to
+        let process_index = self.insert_operator(
+            PlanOperator::StreamOperator(
+                "process".to_string(),
+                Operator::ProcessFunction(ProcessFunction {
+                    function: process.function,
+                    config: process.config,
+                    input: process.input_fn,
+                    output: process.output_fn,
+                }),
+            ),
+            PlanType::Unkeyed(process.output_struct),
+        );
+        let process_edge = PlanEdge {
+            edge_type: EdgeType::Shuffle,
+        };
+        self.graph
+            .add_edge(input_index, process_index, process_edge);
+
+        process_index
+    }
+
     fn add_post_window_join(
         &mut self,
         left_index: NodeIndex,
         right_index: NodeIndex,
         key_struct: StructDef,
-        left_struct: StructDef,
-        right_struct: StructDef,
+        structs: StructPair,
         join_type: JoinType,
+        window: Option<WindowType>,
     ) -> NodeIndex {
-        let join_node = PlanOperator::InstantJoin;
+        // when both sides share a tumbling window, each window's records are joined together once
+        // it closes, after which its state is dropped
+        let join_node = match window {
+            Some(window) => PlanOperator::WindowJoin(window),
+            None => PlanOperator::InstantJoin,
+        };
         let join_node_output_type = PlanType::KeyedListPair {
             key: key_struct,
-            left_value: left_struct.clone(),
-            right_value: right_struct.clone(),
+            left_value: structs.left.clone(),
+            right_value: structs.right.clone(),
         };
         let join_node_index = self.insert_operator(join_node, join_node_output_type);
 
@@ -1459,14 +1872,8 @@ impl PlanGraph {
         self.graph
             .add_edge(right_index, join_node_index, right_join_edge);
 
-        let merge_type = join_type.output_struct(&left_struct, &right_struct);
-        let merge_operator = PlanOperator::JoinListMerge(
-            join_type,
-            StructPair {
-                left: left_struct,
-                right: right_struct,
-            },
-        );
+        let merge_type = join_type.output_struct(&structs.left, &structs.right);
+        let merge_operator = PlanOperator::JoinListMerge(join_type, structs);
         let merge_index =
             self.insert_operator(merge_operator, PlanType::UnkeyedList(merge_type.clone()));
 
@@ -1559,7 +1966,7 @@ impl PlanGraph {
         result_type.fields.push(StructField::new(
             window_operator.field_name.clone(),
             None,
-            TypeDef::DataType(DataType::UInt64, false),
+            window_operator.window_fn.return_type(),
         ));
         let partition_struct = window_operator.partition.output_struct();
 
@@ -1643,62 +2050,50 @@ impl PlanGraph {
     ) -> NodeIndex {
         let input_index = self.add_sql_operator(*input);
         let input_node = self.get_plan_node(input_index);
-        if let PlanType::Updating(inner) = &input_node.output_type {
-            let value_type = inner.as_syn_type();
-            let debezium_type = PlanType::KeyedLiteralTypeValue {
-                key: None,
-                value: quote!(arroyo_types::Debezium<#value_type>).to_string(),
-            };
-            let debezium_index =
-                self.insert_operator(PlanOperator::ToDebezium, debezium_type.clone());
 
-            let edge = PlanEdge {
-                edge_type: EdgeType::Forward,
-            };
-            self.graph.add_edge(input_index, debezium_index, edge);
+        // the type of the rows the sink's changes are made of, if it's written changes
+        let value_type = match (&input_node.output_type, &sql_sink.updating_type) {
+            (PlanType::Updating(inner), SinkUpdateType::Allow | SinkUpdateType::Force) => {
+                Some(inner.as_syn_type())
+            }
+            (PlanType::Updating(_), SinkUpdateType::Disallow) => {
+                unreachable!("updating input to append-only sink {}", name)
+            }
+            (output_type, SinkUpdateType::Force) => Some(output_type.as_syn_type()),
+            (_, SinkUpdateType::Allow | SinkUpdateType::Disallow) => None,
+        };
 
+        let Some(value_type) = value_type else {
             let plan_node = PlanOperator::Sink(name, sql_sink);
-            let plan_node_index = self.insert_operator(plan_node, debezium_type);
-
-            let debezium_edge = PlanEdge {
-                edge_type: EdgeType::Forward,
-            };
-
-            self.graph
-                .add_edge(debezium_index, plan_node_index, debezium_edge);
-            plan_node_index
-        } else if matches!(sql_sink.updating_type, SinkUpdateType::Force) {
-            let value_type = input_node.output_type.as_syn_type();
-            let debezium_type = PlanType::KeyedLiteralTypeValue {
-                key: None,
-                value: quote!(arroyo_types::Debezium<#value_type>).to_string(),
-            };
-            let debezium_index =
-                self.insert_operator(PlanOperator::ToDebezium, debezium_type.clone());
+            let plan_node_index = self.insert_operator(plan_node, input_node.output_type.clone());
             let edge = PlanEdge {
                 edge_type: EdgeType::Forward,
             };
-            self.graph.add_edge(input_index, debezium_index, edge);
+            self.graph.add_edge(input_index, plan_node_index, edge);
+            return plan_node_index;
+        };
 
-            let plan_node = PlanOperator::Sink(name, sql_sink);
-            let plan_node_index = self.insert_operator(plan_node, debezium_type);
+        let debezium_type = PlanType::KeyedLiteralTypeValue {
+            key: None,
+            value: quote!(arroyo_types::Debezium<#value_type>).to_string(),
+        };
+        let debezium_index = self.insert_operator(PlanOperator::ToDebezium, debezium_type.clone());
 
-            let debezium_edge = PlanEdge {
-                edge_type: EdgeType::Forward,
-            };
+        let edge = PlanEdge {
+            edge_type: EdgeType::Forward,
+        };
+        self.graph.add_edge(input_index, debezium_index, edge);
 
-            self.graph
-                .add_edge(debezium_index, plan_node_index, debezium_edge);
-            plan_node_index
-        } else {
-            let plan_node = PlanOperator::Sink(name, sql_sink);
-            let plan_node_index = self.insert_operator(plan_node, input_node.output_type.clone());
-            let edge = PlanEdge {
-                edge_type: EdgeType::Forward,
-            };
-            self.graph.add_edge(input_index, plan_node_index, edge);
-            plan_node_index
-        }
+        let plan_node = PlanOperator::Sink(name, sql_sink);
+        let plan_node_index = self.insert_operator(plan_node, debezium_type);
+
+        let debezium_edge = PlanEdge {
+            edge_type: EdgeType::Forward,
+        };
+
+        self.graph
+            .add_edge(debezium_index, plan_node_index, debezium_edge);
+        plan_node_index
     }
 
     fn add_updating_aggregator(
@@ -1782,8 +2177,9 @@ impl From<PlanGraph> for DiGraph<StreamNode, StreamEdge> {
 pub fn get_program(
     mut plan_graph: PlanGraph,
     schema_provider: ArroyoSchemaProvider,
-) -> Result<(Program, Vec<i64>)> {
+) -> Result<(Program, Vec<i64>, Vec<SqlWarning>)> {
     optimize(&mut plan_graph.graph);
+    let warnings = lint(&plan_graph.graph);
 
     let mut key_structs = HashSet::new();
     let sources = plan_graph.saved_sources_used.clone();
@@ -1821,26 +2217,25 @@ pub fn get_program(
             .map(|(_, v)| v),
     );
 
-    other_defs.push(format!(
-        "mod udfs {{ {} }}",
-        schema_provider
-            .udf_defs
-            .values()
-            .map(|u| u.def.as_str())
-            .collect::<Vec<_>>()
-            .join("\n\n")
-    ));
+    let (udfs, wasm_defs) = udf_defs(
+        &schema_provider.udf_defs,
+        &schema_provider.udf_items,
+        plan_graph.sql_config.udf_sandbox,
+    )?;
+    other_defs.push(udfs);
 
     let graph: DiGraph<StreamNode, StreamEdge> = plan_graph.into();
 
-    Ok((
-        Program {
-            // For now, we don't export any types from SQL into WASM, as there is a problem with doing serde
-            // in wasm
-            types: vec![],
-            other_defs,
-            graph,
-        },
-        sources,
-    ))
+    let mut program = Program {
+        // For now, we don't export any types from SQL into WASM, as there is a problem with doing serde
+        // in wasm
+        types: vec![],
+        other_defs,
+        wasm_defs,
+        graph,
+        instrumentation: None,
+    };
+    program.enforce_single_parallelism();
+
+    Ok((program, sources, warnings))
 }
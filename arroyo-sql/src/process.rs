@@ -0,0 +1,423 @@
+//! Custom stateful operators, which are called from SQL as
+//! `SELECT * FROM PROCESS(my_function, TABLE input, 'config')`. Process functions are defined
+//! along with the pipeline's UDFs, as types that implement
+//! `arroyo_worker::operators::process::ProcessFunction`; each call is planned as a table whose
+//! rows are the function's output.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use datafusion::sql::sqlparser::{
+    ast::{
+        Expr as SqlExpr, FunctionArg, FunctionArgExpr, ObjectName, Query, SetExpr, Statement,
+        TableFactor, TableWithJoins, Value,
+    },
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    parser::Parser,
+    tokenizer::Token,
+};
+use datafusion_expr::LogicalPlan;
+use quote::quote;
+use syn::{ext::IdentExt, Fields, ImplItem, Item, Type, Visibility};
+
+use crate::{
+    pipeline::{ProcessOperator, SqlOperator, SqlPipelineBuilder},
+    tables::{produce_optimized_plan, Table},
+    types::{StructDef, StructField, TypeDef},
+    ArroyoSchemaProvider,
+};
+
+const PROCESS_FUNCTION_TRAIT: &str = "ProcessFunction";
+
+/// A process function defined with the UDFs: a type whose `Input` and `Output` are structs whose
+/// fields have SQL types
+#[derive(Clone, Debug)]
+pub struct ProcessFunctionDef {
+    pub name: String,
+    input_type: String,
+    input: Vec<StructField>,
+    output_type: String,
+    output: Vec<StructField>,
+}
+
+impl ProcessFunctionDef {
+    pub fn output_struct(&self) -> StructDef {
+        StructDef {
+            name: None,
+            fields: self.output.clone(),
+        }
+    }
+
+    /// The closure that builds the function's input from a row of `input_struct`. Each field of
+    /// the input is read from the column with the same name, which must have the same type
+    /// (although nullable fields may be read from columns that aren't).
+    fn input_fn(&self, input_struct: &StructDef) -> Result<String> {
+        let input_type: Type = syn::parse_str(&format!("udfs::{}", self.input_type))?;
+        let fields = self
+            .input
+            .iter()
+            .map(|field| {
+                let column = input_struct
+                    .fields
+                    .iter()
+                    .find(|f| f.name == field.name)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "the input of process function {} has no column '{}'",
+                            self.name,
+                            field.name
+                        )
+                    })?;
+
+                let name = field.field_ident();
+                let column_name = column.field_ident();
+                let field_type = field.get_type();
+                let column_type = column.get_type();
+                if quote!(#field_type).to_string() == quote!(#column_type).to_string() {
+                    Ok(quote!(#name: arg.#column_name.clone()))
+                } else if field.data_type.is_optional()
+                    && field.data_type.as_datatype() == column.data_type.as_datatype()
+                    && !column.data_type.is_optional()
+                {
+                    Ok(quote!(#name: Some(arg.#column_name.clone())))
+                } else {
+                    bail!(
+                        "column '{}' has type {}, but process function {} expects {}",
+                        field.name,
+                        quote!(#column_type),
+                        self.name,
+                        quote!(#field_type)
+                    )
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(quote!(|arg| #input_type { #(#fields),* }).to_string())
+    }
+
+    /// The closure that converts the function's output into a row of the output struct
+    fn output_fn(&self) -> String {
+        let function_output: Type = syn::parse_str(&format!("udfs::{}", self.output_type)).unwrap();
+        let output_type: Type = syn::parse_str(&self.output_struct().struct_name()).unwrap();
+        let fields = self.output.iter().map(|field| {
+            let name = field.field_ident();
+            quote!(#name: out.#name)
+        });
+
+        quote!(|out: #function_output| #output_type { #(#fields),* }).to_string()
+    }
+}
+
+/// Finds the process functions among the types defined with the UDFs, which are made public so
+/// that they can be used by the pipeline
+pub(crate) fn process_functions(items: &mut [Item]) -> Result<Vec<ProcessFunctionDef>> {
+    let mut structs = HashMap::new();
+    for item in items.iter_mut() {
+        match item {
+            Item::Struct(s) => {
+                s.vis = Visibility::Public(Default::default());
+                for field in s.fields.iter_mut() {
+                    field.vis = Visibility::Public(Default::default());
+                }
+                structs.insert(s.ident.to_string(), s.fields.clone());
+            }
+            Item::Enum(e) => e.vis = Visibility::Public(Default::default()),
+            _ => {}
+        }
+    }
+
+    let struct_fields = |function: &str, typ: &Type| -> Result<(String, Vec<StructField>)> {
+        let name = match typ {
+            Type::Path(path) if path.path.segments.len() == 1 => {
+                path.path.segments[0].ident.to_string()
+            }
+            _ => bail!(
+                "the Input and Output of process function {} must be structs defined with it",
+                function
+            ),
+        };
+
+        let Some(Fields::Named(fields)) = structs.get(&name) else {
+            bail!(
+                "the Input and Output of process function {} must be structs with named fields \
+                defined with it, but {} isn't",
+                function,
+                name
+            );
+        };
+
+        let fields = fields
+            .named
+            .iter()
+            .map(|field| {
+                let field_name = field.ident.as_ref().unwrap().unraw().to_string();
+                let data_type = TypeDef::try_from(&field.ty).map_err(|_| {
+                    anyhow!(
+                        "field '{}' of {} has a type that can't be used in SQL",
+                        field_name,
+                        name
+                    )
+                })?;
+                Ok(StructField::new(field_name, None, data_type))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((name, fields))
+    };
+
+    let mut defs = vec![];
+    for item in items.iter() {
+        let Item::Impl(item_impl) = item else {
+            continue;
+        };
+
+        let Some((_, path, _)) = &item_impl.trait_ else {
+            continue;
+        };
+        if path.segments.last().unwrap().ident != PROCESS_FUNCTION_TRAIT {
+            continue;
+        }
+
+        let Type::Path(self_type) = &*item_impl.self_ty else {
+            bail!("process functions must be structs");
+        };
+        let name = self_type.path.segments.last().unwrap().ident.to_string();
+
+        let associated_type = |ident: &str| {
+            item_impl
+                .items
+                .iter()
+                .find_map(|item| match item {
+                    ImplItem::Type(t) if t.ident == ident => Some(&t.ty),
+                    _ => None,
+                })
+                .ok_or_else(|| anyhow!("process function {} has no {} type", name, ident))
+        };
+
+        let (input_type, input) = struct_fields(&name, associated_type("Input")?)?;
+        let (output_type, output) = struct_fields(&name, associated_type("Output")?)?;
+        if output.is_empty() {
+            bail!("the Output of process function {} has no fields", name);
+        }
+
+        defs.push(ProcessFunctionDef {
+            name,
+            input_type,
+            input,
+            output_type,
+            output,
+        });
+    }
+
+    Ok(defs)
+}
+
+/// A call to a process function, planned as a table
+#[derive(Clone, Debug)]
+pub struct ProcessTable {
+    pub name: String,
+    pub function: ProcessFunctionDef,
+    pub config: String,
+    pub input: LogicalPlan,
+}
+
+impl ProcessTable {
+    pub fn as_sql_source(&self, builder: &mut SqlPipelineBuilder) -> Result<SqlOperator> {
+        let input = builder.insert_sql_plan(&self.input)?;
+        if input.is_updating() {
+            bail!(
+                "process function {} can't be applied to an updating table",
+                self.function.name
+            );
+        }
+
+        let input_fn = self.function.input_fn(&input.return_type())?;
+
+        Ok(SqlOperator::Process(
+            Box::new(input),
+            ProcessOperator {
+                function: self.function.name.clone(),
+                config: self.config.clone(),
+                input_fn,
+                output_fn: self.function.output_fn(),
+                output_struct: self.function.output_struct(),
+            },
+        ))
+    }
+}
+
+/// sqlparser can't parse `TABLE input` as a function argument, so the TABLE keyword is dropped
+/// from the arguments of PROCESS calls, leaving just the table's name
+pub(crate) fn rewrite_process_tokens(tokens: Vec<Token>) -> Vec<Token> {
+    let mut result = Vec::with_capacity(tokens.len());
+    // how deeply nested in parentheses we are within the arguments of a PROCESS call
+    let mut depth = 0;
+    let mut after_process = false;
+    let mut argument_start = false;
+
+    for token in tokens {
+        if matches!(token, Token::Whitespace(_)) {
+            result.push(token);
+            continue;
+        }
+
+        if depth > 0 {
+            match &token {
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                Token::Word(w) if depth == 1 && argument_start && w.keyword == Keyword::TABLE => {
+                    argument_start = false;
+                    continue;
+                }
+                _ => {}
+            }
+            argument_start = depth == 1 && token == Token::Comma;
+        } else if after_process && token == Token::LParen {
+            depth = 1;
+            argument_start = true;
+        }
+
+        after_process = matches!(&token, Token::Word(w)
+            if w.quote_style.is_none() && w.value.eq_ignore_ascii_case("process"));
+        result.push(token);
+    }
+
+    result
+}
+
+/// Replaces each PROCESS call in the statement with a reference to a table that plans it
+pub(crate) fn plan_process_calls(
+    statement: &mut Statement,
+    schema_provider: &mut ArroyoSchemaProvider,
+) -> Result<()> {
+    match statement {
+        Statement::Query(query) => plan_query(query, schema_provider),
+        Statement::Insert { source, .. } => plan_query(source, schema_provider),
+        Statement::CreateView { query, .. } => plan_query(query, schema_provider),
+        Statement::CreateTable {
+            query: Some(query), ..
+        } => plan_query(query, schema_provider),
+        _ => Ok(()),
+    }
+}
+
+fn plan_query(query: &mut Query, schema_provider: &mut ArroyoSchemaProvider) -> Result<()> {
+    if let Some(with) = &mut query.with {
+        for cte in &mut with.cte_tables {
+            plan_query(&mut cte.query, schema_provider)?;
+        }
+    }
+
+    plan_set_expr(&mut query.body, schema_provider)
+}
+
+fn plan_set_expr(set_expr: &mut SetExpr, schema_provider: &mut ArroyoSchemaProvider) -> Result<()> {
+    match set_expr {
+        SetExpr::Select(select) => {
+            for table in &mut select.from {
+                plan_table_with_joins(table, schema_provider)?;
+            }
+            Ok(())
+        }
+        SetExpr::Query(query) => plan_query(query, schema_provider),
+        SetExpr::SetOperation { left, right, .. } => {
+            plan_set_expr(left, schema_provider)?;
+            plan_set_expr(right, schema_provider)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn plan_table_with_joins(
+    table: &mut TableWithJoins,
+    schema_provider: &mut ArroyoSchemaProvider,
+) -> Result<()> {
+    plan_table_factor(&mut table.relation, schema_provider)?;
+    for join in &mut table.joins {
+        plan_table_factor(&mut join.relation, schema_provider)?;
+    }
+    Ok(())
+}
+
+fn plan_table_factor(
+    factor: &mut TableFactor,
+    schema_provider: &mut ArroyoSchemaProvider,
+) -> Result<()> {
+    match factor {
+        TableFactor::Table { name, args, .. }
+            if args.is_some()
+                && name.0.len() == 1
+                && name.0[0].value.eq_ignore_ascii_case("process") =>
+        {
+            let table = process_table(args.as_ref().unwrap(), schema_provider)?;
+            *name = ObjectName(vec![table.name.as_str().into()]);
+            *args = None;
+            schema_provider.insert_table(Table::ProcessTable(table));
+            Ok(())
+        }
+        TableFactor::Derived { subquery, .. } => plan_query(subquery, schema_provider),
+        TableFactor::NestedJoin {
+            table_with_joins, ..
+        } => plan_table_with_joins(table_with_joins, schema_provider),
+        _ => Ok(()),
+    }
+}
+
+fn process_table(
+    args: &[FunctionArg],
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<ProcessTable> {
+    let usage = "PROCESS takes a process function, the TABLE to process, and an optional config \
+        string, as in PROCESS(my_function, TABLE input, '{}')";
+
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Ok(expr),
+            _ => bail!(usage),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (function, input, config) = match args.as_slice() {
+        [SqlExpr::Identifier(function), input] => (function, *input, String::new()),
+        [SqlExpr::Identifier(function), input, SqlExpr::Value(Value::SingleQuotedString(config))] => {
+            (function, *input, config.clone())
+        }
+        _ => bail!(usage),
+    };
+
+    let input = match input {
+        SqlExpr::Identifier(ident) => ObjectName(vec![ident.clone()]),
+        SqlExpr::CompoundIdentifier(idents) => ObjectName(idents.clone()),
+        _ => bail!(usage),
+    };
+
+    let function = schema_provider
+        .process_functions
+        .values()
+        .find(|def| def.name.eq_ignore_ascii_case(&function.value))
+        .ok_or_else(|| {
+            anyhow!(
+                "no process function named '{}'; process functions are types defined with the \
+                UDFs that implement ProcessFunction",
+                function.value
+            )
+        })?;
+
+    let mut statements =
+        Parser::parse_sql(&PostgreSqlDialect {}, &format!("SELECT * FROM {}", input))?;
+    let input = produce_optimized_plan(&statements.remove(0), schema_provider)?;
+
+    let name = (0..)
+        .map(|i| format!("__process_{}", i))
+        .find(|name| schema_provider.get_table(name).is_none())
+        .unwrap();
+
+    Ok(ProcessTable {
+        name,
+        function: function.clone(),
+        config,
+        input,
+    })
+}
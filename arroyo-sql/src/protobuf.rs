@@ -0,0 +1,251 @@
+use arrow_schema::{DataType, TimeUnit};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use prost::Message;
+use prost_reflect::{Cardinality, DescriptorPool, FieldDescriptor, Kind, MessageDescriptor};
+use prost_types::FileDescriptorSet;
+use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+use protox::Compiler;
+use tracing::warn;
+
+use crate::types::{StructDef, StructField, TypeDef};
+
+// the name that .proto sources are compiled under, which appears in their errors
+const SOURCE_FILE: &str = "schema.proto";
+
+pub const TIMESTAMP_MESSAGE: &str = "google.protobuf.Timestamp";
+
+struct SourceResolver {
+    source: String,
+}
+
+impl FileResolver for SourceResolver {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        if name == SOURCE_FILE {
+            File::from_source(name, &self.source)
+        } else {
+            Err(protox::Error::file_not_found(name))
+        }
+    }
+}
+
+/// Reads a protobuf schema, which is either a base64-encoded FileDescriptorSet (as produced by
+/// `protoc --include_imports --descriptor_set_out`) or the source of a .proto file. Sources may
+/// import the well-known types, but no other files.
+pub fn descriptor_pool(schema: &str) -> Result<DescriptorPool, String> {
+    if let Ok(bytes) = STANDARD.decode(schema.trim()) {
+        return DescriptorPool::decode(bytes.as_slice())
+            .map_err(|e| format!("not a valid FileDescriptorSet: {}", e));
+    }
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(SourceResolver {
+        source: schema.to_string(),
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_imports(true);
+    compiler
+        .open_file(SOURCE_FILE)
+        .map_err(|e| format!("could not compile .proto: {}", e))?;
+
+    Ok(compiler.descriptor_pool())
+}
+
+/// The base64-encoded FileDescriptorSet of the pool, which is how schemas are stored and passed
+/// to the workers whatever form they were defined in
+pub fn encode_descriptor_set(pool: &DescriptorPool) -> String {
+    let set = FileDescriptorSet {
+        file: pool.file_descriptor_protos().cloned().collect(),
+    };
+    STANDARD.encode(set.encode_to_vec())
+}
+
+pub fn message_descriptor(
+    pool: &DescriptorPool,
+    message: &str,
+) -> Result<MessageDescriptor, String> {
+    pool.get_message_by_name(message)
+        .ok_or_else(|| format!("message '{}' not found in the protobuf schema", message))
+}
+
+/// Converts the protobuf message `message` (by its fully-qualified name) from the schema into the
+/// fields of a table. Message fields and scalars with explicit presence become nullable fields,
+/// enums become their names and google.protobuf.Timestamps become timestamps; repeated and map
+/// fields aren't supported and are left out of the table.
+pub fn convert_protobuf_schema(
+    name: &str,
+    schema: &str,
+    message: &str,
+) -> Result<Vec<StructField>, String> {
+    let pool = descriptor_pool(schema)
+        .map_err(|e| format!("Invalid protobuf schema for {}: {}", name, e))?;
+    let descriptor = message_descriptor(&pool, message)?;
+
+    message_fields(&descriptor, &mut vec![])
+}
+
+/// The fields of the message. `expanding` holds the messages that are being converted, to reject
+/// recursive types.
+fn message_fields(
+    descriptor: &MessageDescriptor,
+    expanding: &mut Vec<String>,
+) -> Result<Vec<StructField>, String> {
+    if expanding.iter().any(|n| n == descriptor.full_name()) {
+        return Err(format!(
+            "Recursive protobuf message '{}' is not supported",
+            descriptor.full_name()
+        ));
+    }
+    expanding.push(descriptor.full_name().to_string());
+
+    let mut fields = vec![];
+    for field in descriptor.fields() {
+        match field_type(&field, expanding)? {
+            Some(t) => fields.push(StructField::new(field.name().to_string(), None, t)),
+            None => warn!(
+                "Leaving out protobuf field '{}' of unhandled type",
+                field.full_name()
+            ),
+        }
+    }
+
+    expanding.pop();
+    Ok(fields)
+}
+
+/// The type of the field, or None if it's a type that tables can't represent
+fn field_type(
+    field: &FieldDescriptor,
+    expanding: &mut Vec<String>,
+) -> Result<Option<TypeDef>, String> {
+    if field.is_list() || field.is_map() {
+        return Ok(None);
+    }
+
+    let nullable = field.supports_presence() && field.cardinality() != Cardinality::Required;
+
+    let data_type = match field.kind() {
+        Kind::Bool => DataType::Boolean,
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => DataType::Int32,
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => DataType::Int64,
+        Kind::Uint32 | Kind::Fixed32 => DataType::UInt32,
+        Kind::Uint64 | Kind::Fixed64 => DataType::UInt64,
+        Kind::Float => DataType::Float32,
+        Kind::Double => DataType::Float64,
+        Kind::String | Kind::Enum(_) => DataType::Utf8,
+        Kind::Bytes => DataType::Binary,
+        Kind::Message(m) if m.full_name() == TIMESTAMP_MESSAGE => {
+            DataType::Timestamp(TimeUnit::Nanosecond, None)
+        }
+        Kind::Message(m) => {
+            return Ok(Some(TypeDef::StructDef(
+                StructDef {
+                    name: None,
+                    fields: message_fields(&m, expanding)?,
+                },
+                nullable,
+            )));
+        }
+    };
+
+    Ok(Some(TypeDef::DataType(data_type, nullable)))
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_schema::{DataType, TimeUnit};
+
+    use super::{convert_protobuf_schema, descriptor_pool, encode_descriptor_set};
+    use crate::types::TypeDef;
+
+    const SCHEMA: &str = r#"
+        syntax = "proto3";
+        package com.example;
+
+        import "google/protobuf/timestamp.proto";
+
+        message Order {
+            enum Status {
+                NEW = 0;
+                PAID = 1;
+            }
+
+            message Address {
+                string city = 1;
+            }
+
+            int64 id = 1;
+            optional string customer = 2;
+            google.protobuf.Timestamp created = 3;
+            Status status = 4;
+            Address address = 5;
+            uint32 quantity = 6;
+            repeated string tags = 7;
+        }
+    "#;
+
+    #[test]
+    fn test_convert() {
+        let fields = convert_protobuf_schema("orders", SCHEMA, "com.example.Order").unwrap();
+
+        let types: Vec<_> = fields
+            .iter()
+            .map(|f| (f.name.as_str(), &f.data_type))
+            .collect();
+
+        assert_eq!(types.len(), 6);
+        assert_eq!(types[0], ("id", &TypeDef::DataType(DataType::Int64, false)));
+        assert_eq!(
+            types[1],
+            ("customer", &TypeDef::DataType(DataType::Utf8, true))
+        );
+        assert_eq!(
+            types[2],
+            (
+                "created",
+                &TypeDef::DataType(DataType::Timestamp(TimeUnit::Nanosecond, None), true)
+            )
+        );
+        assert_eq!(
+            types[3],
+            ("status", &TypeDef::DataType(DataType::Utf8, false))
+        );
+        assert!(
+            matches!(types[4], ("address", TypeDef::StructDef(s, true)) if s.fields.len() == 1)
+        );
+        assert_eq!(
+            types[5],
+            ("quantity", &TypeDef::DataType(DataType::UInt32, false))
+        );
+    }
+
+    #[test]
+    fn test_descriptor_set() {
+        let encoded = encode_descriptor_set(&descriptor_pool(SCHEMA).unwrap());
+
+        let fields = convert_protobuf_schema("orders", &encoded, "com.example.Order").unwrap();
+        assert_eq!(fields.len(), 6);
+
+        let err = convert_protobuf_schema("orders", &encoded, "com.example.Missing").unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_recursive() {
+        let err = convert_protobuf_schema(
+            "list",
+            r#"
+            syntax = "proto3";
+            message Node {
+                int64 value = 1;
+                Node next = 2;
+            }
+            "#,
+            "Node",
+        )
+        .unwrap_err();
+
+        assert!(err.contains("Recursive"), "{}", err);
+    }
+}
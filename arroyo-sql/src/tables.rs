@@ -1,33 +1,49 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 use anyhow::{anyhow, bail, Result};
 use arrow_schema::{DataType, Field};
-use arroyo_connectors::{connector_for_type, serialization_mode, Connection, ConnectionType};
-use arroyo_datastream::{ConnectorOp, Operator, SerializationMode};
+use arroyo_connectors::{
+    check_format, connector_for_type, serialization_mode, Connection, ConnectionType,
+};
+use arroyo_datastream::{
+    ConnectorOp, Operator, SerializationMode, TenantLimit, TimestampBounds, WatermarkAlignment,
+};
 use arroyo_rpc::grpc::{
     self,
-    api::{ConnectionSchema, Format, FormatOptions, SourceField},
+    api::{ConnectionSchema, CsvOptions, Format, FormatOptions, SourceField},
+};
+use arroyo_types::{
+    CORRUPT_RECORD_FIELD, LINEAGE_OFFSET_FIELD, LINEAGE_PARTITION_FIELD, LINEAGE_SOURCE_FIELD,
 };
 use datafusion::{
     optimizer::{analyzer::Analyzer, optimizer::Optimizer, OptimizerContext},
     sql::{
-        planner::{PlannerContext, SqlToRel},
-        sqlparser::ast::{ColumnDef, ColumnOption, Statement, Value},
+        planner::PlannerContext,
+        sqlparser::{
+            ast::{ColumnDef, ColumnOption, Expr as SqlExpr, Statement, Value},
+            dialect::PostgreSqlDialect,
+            parser::Parser,
+        },
     },
 };
 use datafusion_common::{config::ConfigOptions, DFField, DFSchema};
 use datafusion_expr::{
     CreateMemoryTable, CreateView, DdlStatement, DmlStatement, LogicalPlan, WriteOp,
 };
+use quote::quote;
 
 use crate::{
     expressions::{Column, ColumnExpression, Expression, ExpressionContext},
-    external::{ProcessingMode, SqlSink, SqlSource},
+    external::{ProcessingMode, SinkUpdateType, SqlSink, SqlSource},
     json_schema,
+    masking::mask_sink_input,
     operators::Projection,
-    pipeline::{SourceOperator, SqlOperator, SqlPipelineBuilder},
+    pipeline::{SourceOperator, SourceTenantLimit, SqlOperator, SqlPipelineBuilder},
+    process::ProcessTable,
+    protobuf,
     types::{convert_data_type, StructDef, StructField, TypeDef},
-    ArroyoSchemaProvider,
+    ArroyoSchemaProvider, SavedConnection,
 };
 
 #[derive(Debug, Clone)]
@@ -43,8 +59,33 @@ pub struct ConnectorTable {
     pub serialization_mode: SerializationMode,
     pub event_time_field: Option<String>,
     pub watermark_field: Option<String>,
+    pub watermark_alignment: Option<WatermarkAlignment>,
+    pub timestamp_bounds: Option<TimestampBounds>,
+    pub tenant_limit: Option<TenantLimitOptions>,
+    pub route: Option<SqlExpr>,
 }
 
+/// Per-tenant rate limits on the records of a source, set with its `tenant.*` options
+#[derive(Debug, Clone)]
+pub struct TenantLimitOptions {
+    // the column that identifies the tenant of each record
+    pub key: String,
+    pub max_rate: u32,
+    pub burst: u32,
+    // set if records over a tenant's rate are queued rather than shed
+    pub max_queued: Option<u32>,
+}
+
+// the records a tenant may have queued under the 'queue' policy, unless tenant.max_queued is set
+const DEFAULT_TENANT_MAX_QUEUED: u32 = 1000;
+
+// connectors whose sinks can route each record to a destination computed from it
+const ROUTING_CONNECTORS: &[&str] = &["kafka"];
+
+// sinks that write each record as a row, which can't represent the retractions of an updating
+// stream unless they're written in the debezium_json format
+const APPEND_ONLY_SINKS: &[&str] = &["connectors::jdbc::JdbcSinkFunc"];
+
 fn schema_type(name: &str, schema: &ConnectionSchema) -> Option<String> {
     schema.struct_name.as_ref().cloned().or_else(|| {
         let def = schema.definition.as_ref()?;
@@ -52,33 +93,44 @@ fn schema_type(name: &str, schema: &ConnectionSchema) -> Option<String> {
             grpc::api::connection_schema::Definition::JsonSchema(_) => {
                 Some(format!("{}::{}", name, json_schema::ROOT_NAME))
             }
-            grpc::api::connection_schema::Definition::ProtobufSchema(_) => todo!(),
-            grpc::api::connection_schema::Definition::AvroSchema(_) => todo!(),
+            // avro and protobuf schemas are expanded into the connection's fields, which the type
+            // is generated from
+            grpc::api::connection_schema::Definition::ProtobufSchema(_) => None,
+            grpc::api::connection_schema::Definition::AvroSchema(_) => None,
             grpc::api::connection_schema::Definition::RawSchema(_) => {
-                Some("arroyo_types::RawJson".to_string())
+                if schema.format() == Format::RawBytesFormat {
+                    Some("arroyo_types::RawBytes".to_string())
+                } else {
+                    Some("arroyo_types::RawJson".to_string())
+                }
             }
         }
     })
 }
 
-pub fn schema_defs(name: &str, schema: &ConnectionSchema) -> Option<String> {
+pub fn schema_defs(
+    name: &str,
+    schema: &ConnectionSchema,
+    permissive: bool,
+    lineage: bool,
+) -> Option<String> {
     let def = schema.definition.as_ref()?;
 
     match def {
         grpc::api::connection_schema::Definition::JsonSchema(s) => {
-            Some(json_schema::get_defs(&name, &s).unwrap())
+            Some(json_schema::get_defs(&name, &s, permissive, lineage).unwrap())
         }
-        grpc::api::connection_schema::Definition::ProtobufSchema(_) => todo!(),
-        grpc::api::connection_schema::Definition::AvroSchema(_) => todo!(),
+        grpc::api::connection_schema::Definition::ProtobufSchema(_) => None,
+        grpc::api::connection_schema::Definition::AvroSchema(_) => None,
         grpc::api::connection_schema::Definition::RawSchema(_) => None,
     }
 }
 
-fn produce_optimized_plan(
+pub(crate) fn produce_optimized_plan(
     statement: &Statement,
     schema_provider: &ArroyoSchemaProvider,
 ) -> Result<LogicalPlan> {
-    let sql_to_rel = SqlToRel::new(schema_provider);
+    let sql_to_rel = schema_provider.sql_to_rel();
     let plan = sql_to_rel.sql_statement_to_plan(statement.clone())?;
 
     let optimizer_config = OptimizerContext::default();
@@ -90,18 +142,391 @@ fn produce_optimized_plan(
     Ok(plan)
 }
 
+/// Whether the connection is a source that reads records that don't match its schema permissively
+pub(crate) fn is_permissive(connection: &Connection) -> bool {
+    matches!(connection.connection_type, ConnectionType::Source)
+        && connection
+            .schema
+            .format_options
+            .as_ref()
+            .filter(|o| o.permissive)
+            .is_some()
+}
+
+/// Permissive sources read fields that can't be deserialized as nulls and put the raw payload of
+/// the record in its `_corrupt_record` column, so all of their fields are nullable and they always
+/// have that column
+pub(crate) fn permissive_fields(fields: Vec<StructField>) -> Vec<StructField> {
+    let mut fields: Vec<_> = fields
+        .into_iter()
+        .filter(|f| f.name != CORRUPT_RECORD_FIELD)
+        .map(|mut f| {
+            if f.expression.is_none() {
+                f.data_type = f.data_type.as_nullable();
+            }
+            f
+        })
+        .collect();
+
+    fields.push(StructField::new(
+        CORRUPT_RECORD_FIELD.to_string(),
+        None,
+        TypeDef::DataType(DataType::Utf8, true),
+    ));
+
+    fields
+}
+
+/// Whether the connection is a source that records where each of its records was read from
+pub(crate) fn is_lineage(connection: &Connection) -> bool {
+    matches!(connection.connection_type, ConnectionType::Source)
+        && connection
+            .schema
+            .format_options
+            .as_ref()
+            .filter(|o| o.lineage)
+            .is_some()
+}
+
+/// Sources with lineage enabled fill in the `_lineage_*` columns for each record they read; these
+/// are ordinary columns, so they flow through projections to sinks like any other
+pub(crate) fn lineage_fields(fields: Vec<StructField>) -> Vec<StructField> {
+    let lineage = [
+        (LINEAGE_SOURCE_FIELD, DataType::Utf8, false),
+        (LINEAGE_PARTITION_FIELD, DataType::Int32, true),
+        (LINEAGE_OFFSET_FIELD, DataType::Int64, false),
+    ];
+
+    let mut fields: Vec<_> = fields
+        .into_iter()
+        .filter(|f| !lineage.iter().any(|(name, _, _)| f.name == *name))
+        .collect();
+
+    fields.extend(lineage.into_iter().map(|(name, data_type, nullable)| {
+        StructField::new(
+            name.to_string(),
+            None,
+            TypeDef::DataType(data_type, nullable),
+        )
+    }));
+
+    fields
+}
+
+/// Adds the columns that the source fills in itself to the fields declared in its schema
+pub(crate) fn source_fields(
+    fields: Vec<StructField>,
+    permissive: bool,
+    lineage: bool,
+) -> Vec<StructField> {
+    let fields = if permissive {
+        permissive_fields(fields)
+    } else {
+        fields
+    };
+
+    if lineage {
+        lineage_fields(fields)
+    } else {
+        fields
+    }
+}
+
+fn raw_bytes_fields(fields: Vec<StructField>) -> Result<Vec<StructField>> {
+    let value = StructField::new(
+        "value".to_string(),
+        None,
+        TypeDef::DataType(DataType::Binary, false),
+    );
+
+    match fields.as_slice() {
+        [] => Ok(vec![value]),
+        [field] if field.name == "value" && field.data_type == value.data_type => Ok(fields),
+        _ => {
+            bail!("tables with format 'raw_bytes' must have a single column 'value BYTEA NOT NULL'")
+        }
+    }
+}
+
+/// Parses durations like `30s`, `500 ms`, `5 minutes`, or `7 days`
+fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("duration '{}' is missing a unit", s))?;
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n.parse().map_err(|_| anyhow!("invalid duration '{}'", s))?;
+
+    Ok(match unit.trim() {
+        "ms" | "millisecond" | "milliseconds" => Duration::from_millis(n),
+        "s" | "second" | "seconds" => Duration::from_secs(n),
+        "m" | "minute" | "minutes" => Duration::from_secs(n * 60),
+        "h" | "hour" | "hours" => Duration::from_secs(n * 60 * 60),
+        "d" | "day" | "days" => Duration::from_secs(n * 24 * 60 * 60),
+        unit => bail!("invalid unit '{}' in duration '{}'", unit, s),
+    })
+}
+
+fn watermark_alignment(
+    options: &mut HashMap<String, String>,
+) -> Result<Option<WatermarkAlignment>> {
+    let group = options.remove("watermark_alignment.group");
+    let max_drift = options.remove("watermark_alignment.max_drift");
+
+    match (group, max_drift) {
+        (Some(group), Some(max_drift)) => Ok(Some(WatermarkAlignment {
+            group,
+            max_drift: parse_duration(&max_drift)?,
+        })),
+        (None, None) => Ok(None),
+        _ => bail!(
+            "watermark_alignment.group and watermark_alignment.max_drift must be set together"
+        ),
+    }
+}
+
+fn timestamp_bounds(options: &mut HashMap<String, String>) -> Result<Option<TimestampBounds>> {
+    let max_future = options.remove("timestamp_bounds.max_future");
+    let max_age = options.remove("timestamp_bounds.max_age");
+    let action = options.remove("timestamp_bounds.action");
+
+    if max_future.is_none() && max_age.is_none() {
+        if action.is_some() {
+            bail!("timestamp_bounds.action requires a max_future or max_age bound");
+        }
+        return Ok(None);
+    }
+
+    let clamp = match action.as_deref() {
+        None | Some("reject") => false,
+        Some("clamp") => true,
+        Some(other) => bail!(
+            "invalid timestamp_bounds.action '{}'; expected 'reject' or 'clamp'",
+            other
+        ),
+    };
+
+    Ok(Some(TimestampBounds {
+        max_future: max_future.as_deref().map(parse_duration).transpose()?,
+        max_age: max_age.as_deref().map(parse_duration).transpose()?,
+        clamp,
+    }))
+}
+
+fn tenant_limit(options: &mut HashMap<String, String>) -> Result<Option<TenantLimitOptions>> {
+    let key = options.remove("tenant.key");
+    let max_rate = options.remove("tenant.max_rate");
+    let burst = options.remove("tenant.burst");
+    let policy = options.remove("tenant.policy");
+    let max_queued = options.remove("tenant.max_queued");
+
+    let (key, max_rate) = match (key, max_rate) {
+        (Some(key), Some(max_rate)) => (key, max_rate),
+        (None, None) => {
+            if burst.is_some() || policy.is_some() || max_queued.is_some() {
+                bail!("tenant limits require tenant.key and tenant.max_rate");
+            }
+            return Ok(None);
+        }
+        _ => bail!("tenant.key and tenant.max_rate must be set together"),
+    };
+
+    let positive = |name: &str, value: &str| match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(anyhow!(
+            "invalid {} '{}'; expected a positive integer",
+            name,
+            value
+        )),
+    };
+
+    let max_rate = positive("tenant.max_rate", &max_rate)?;
+    let max_queued = match (policy.as_deref(), max_queued) {
+        (None | Some("shed"), None) => None,
+        (None | Some("shed"), Some(_)) => {
+            bail!("tenant.max_queued can only be set when tenant.policy is 'queue'")
+        }
+        (Some("queue"), max_queued) => Some(
+            max_queued
+                .map(|q| positive("tenant.max_queued", &q))
+                .transpose()?
+                .unwrap_or(DEFAULT_TENANT_MAX_QUEUED),
+        ),
+        (Some(other), _) => bail!(
+            "invalid tenant.policy '{}'; expected 'shed' or 'queue'",
+            other
+        ),
+    };
+
+    Ok(Some(TenantLimitOptions {
+        key,
+        max_rate,
+        burst: burst
+            .map(|b| positive("tenant.burst", &b))
+            .transpose()?
+            .unwrap_or(max_rate),
+        max_queued,
+    }))
+}
+
+// options shared by all sinks that control how records are batched before being written; they
+// are passed to the sink operator in its config
+fn batching_options(options: &mut HashMap<String, String>) -> Result<Option<serde_json::Value>> {
+    let mut batching = serde_json::Map::new();
+
+    for name in ["max_records", "max_bytes"] {
+        if let Some(value) = options.remove(&format!("batch.{}", name)) {
+            let value: u64 = value
+                .parse()
+                .ok()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| anyhow!("batch.{} must be a positive integer", name))?;
+            batching.insert(name.to_string(), value.into());
+        }
+    }
+
+    if let Some(linger) = options.remove("batch.max_linger") {
+        batching.insert(
+            "max_linger_micros".to_string(),
+            (parse_duration(&linger)?.as_micros() as u64).into(),
+        );
+    }
+
+    Ok((!batching.is_empty()).then_some(serde_json::Value::Object(batching)))
+}
+
+// connectors that hold a permit from the controller for their connection while they run
+const POOLED_CONNECTORS: &[&str] = &["cassandra", "jdbc", "mongodb"];
+
+// sources that know where each of their records was read from
+const LINEAGE_CONNECTORS: &[&str] = &["kafka", "object_store", "sftp"];
+
+// the layout of a csv table's records; the connector checks the delimiter, quote and quoting.
+// Fields of csv records can't hold structs, so neither can the table's columns.
+fn csv_options(
+    options: &mut HashMap<String, String>,
+    fields: &[StructField],
+) -> Result<CsvOptions> {
+    if let Some(f) = fields
+        .iter()
+        .find(|f| f.expression.is_none() && matches!(f.data_type, TypeDef::StructDef(..)))
+    {
+        bail!(
+            "column '{}' is a struct, which csv tables can't have",
+            f.name
+        );
+    }
+
+    let has_header = match options.remove("format_options.csv_has_header").as_deref() {
+        None | Some("false") => false,
+        Some("true") => true,
+        Some(other) => bail!(
+            "invalid value '{}' for format_options.csv_has_header; expected 'true' or 'false'",
+            other
+        ),
+    };
+
+    Ok(CsvOptions {
+        delimiter: options.remove("format_options.csv_delimiter"),
+        quote: options.remove("format_options.csv_quote"),
+        has_header,
+        quoting: options.remove("format_options.csv_quoting"),
+    })
+}
+
+// the schema and message that a protobuf table's records are read with. The schema may be a
+// base64-encoded FileDescriptorSet or the source of a .proto file, and is returned as a descriptor
+// set. Columns that the message has must match the types of its fields.
+fn protobuf_options(
+    name: &str,
+    options: &mut HashMap<String, String>,
+    fields: &[StructField],
+) -> Result<(String, String)> {
+    let schema = options
+        .remove("format_options.protobuf_schema")
+        .ok_or_else(|| anyhow!("protobuf tables require format_options.protobuf_schema"))?;
+    let message = options
+        .remove("format_options.protobuf_message")
+        .ok_or_else(|| anyhow!("protobuf tables require format_options.protobuf_message"))?;
+
+    let pool = protobuf::descriptor_pool(&schema)
+        .map_err(|e| anyhow!("invalid protobuf schema: {}", e))?;
+    let descriptor_set = protobuf::encode_descriptor_set(&pool);
+    let message_fields = protobuf::convert_protobuf_schema(name, &descriptor_set, &message)
+        .map_err(|e| anyhow!(e))?;
+
+    for field in fields.iter().filter(|f| f.expression.is_none()) {
+        let Some(message_field) = message_fields.iter().find(|m| m.name == field.name) else {
+            continue;
+        };
+
+        match (
+            field.data_type.as_datatype(),
+            message_field.data_type.as_datatype(),
+        ) {
+            // timestamps of any unit are read from the same RFC3339 strings
+            (Some(DataType::Timestamp(_, _)), Some(DataType::Timestamp(_, _))) => {}
+            (Some(column), Some(expected)) if column != expected => bail!(
+                "column '{}' has type {:?}, but field '{}' of {} has type {:?}",
+                field.name,
+                column,
+                message_field.name,
+                message,
+                expected
+            ),
+            _ => {}
+        }
+    }
+
+    Ok((descriptor_set, message))
+}
+
+// options that limit the connections that all jobs in the cluster hold to the table's system;
+// tables that use a saved connection share a pool named after it unless they choose another
+fn connection_pool_options(
+    options: &mut HashMap<String, String>,
+    saved_connection: Option<&SavedConnection>,
+) -> Result<Option<serde_json::Value>> {
+    let name = options.remove("connection_pool.name");
+    let Some(max_connections) = options.remove("connection_pool.max_connections") else {
+        if name.is_some() {
+            bail!("connection_pool.name requires connection_pool.max_connections");
+        }
+        return Ok(None);
+    };
+
+    let max_connections: u32 = max_connections
+        .parse()
+        .ok()
+        .filter(|v| *v > 0)
+        .ok_or_else(|| anyhow!("connection_pool.max_connections must be a positive integer"))?;
+
+    let name = name
+        .or_else(|| saved_connection.map(|c| c.name.clone()))
+        .ok_or_else(|| {
+            anyhow!("connection_pool.name must be set for tables that don't use a saved connection")
+        })?;
+
+    Ok(Some(serde_json::json!({
+        "name": name,
+        "max_connections": max_connections,
+    })))
+}
+
 impl From<Connection> for ConnectorTable {
     fn from(value: Connection) -> Self {
+        let fields = value
+            .schema
+            .fields
+            .iter()
+            .map(|f| f.clone().into())
+            .collect();
+
         ConnectorTable {
             id: value.id,
             name: value.name.clone(),
             connection_type: value.connection_type,
-            fields: value
-                .schema
-                .fields
-                .iter()
-                .map(|f| f.clone().into())
-                .collect(),
+            fields: source_fields(fields, is_permissive(&value), is_lineage(&value)),
             type_name: schema_type(&value.name, &value.schema),
             operator: value.operator,
             config: value.config,
@@ -109,6 +534,10 @@ impl From<Connection> for ConnectorTable {
             serialization_mode: serialization_mode(&value.schema).into(),
             event_time_field: None,
             watermark_field: None,
+            watermark_alignment: None,
+            timestamp_bounds: None,
+            tenant_limit: None,
+            route: None,
         }
     }
 }
@@ -118,11 +547,60 @@ impl ConnectorTable {
         name: &str,
         connector: &str,
         fields: Vec<StructField>,
+        saved_connection: Option<&SavedConnection>,
         options: &mut HashMap<String, String>,
     ) -> Result<Self> {
+        let route = options
+            .remove("route")
+            .map(|route| {
+                if !ROUTING_CONNECTORS.contains(&connector) {
+                    bail!("the '{}' connector does not support routing", connector);
+                }
+                Parser::new(&PostgreSqlDialect {})
+                    .try_with_sql(&route)
+                    .and_then(|mut parser| parser.parse_expr())
+                    .map_err(|e| anyhow!("invalid route expression '{}': {}", route, e))
+            })
+            .transpose()?;
+
+        let connection_pool = connection_pool_options(options, saved_connection)?;
+        if connection_pool.is_some() && !POOLED_CONNECTORS.contains(&connector) {
+            bail!(
+                "the '{}' connector does not support connection pools",
+                connector
+            );
+        }
+
+        let lineage = match options.remove("lineage").as_deref() {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(other) => bail!(
+                "invalid value '{}' for lineage; expected 'true' or 'false'",
+                other
+            ),
+        };
+
+        if lineage && !LINEAGE_CONNECTORS.contains(&connector) {
+            bail!("the '{}' connector does not support lineage", connector);
+        }
+
         let connector = connector_for_type(connector)
             .ok_or_else(|| anyhow!("Unknown connector '{}'", connector))?;
 
+        // settings from the saved connection are defaults, which may be overridden by the table
+        let mut defaults = vec![];
+        if let Some(saved) = saved_connection {
+            for (k, v) in connector
+                .config_options(&saved.config)
+                .map_err(|e| anyhow!("invalid config for saved connection: {}", e))?
+            {
+                if !options.contains_key(&k) {
+                    options.insert(k.clone(), v);
+                    defaults.push(k);
+                }
+            }
+        }
+
         let mut format = None;
         if let Some(f) = options.remove("format") {
             format = Some(match f.as_str() {
@@ -131,16 +609,68 @@ impl ConnectorTable {
                 "protobuf" => Format::ProtobufFormat,
                 "avro" => Format::AvroFormat,
                 "raw_string" => Format::RawStringFormat,
+                "raw_bytes" => Format::RawBytesFormat,
                 "parquet" => Format::ParquetFormat,
+                "csv" => Format::CsvFormat,
                 f => bail!("Unknown format '{}'", f),
             });
         }
 
+        if let Some(format) = format {
+            check_format(connector.name(), format)?;
+        }
+
         let schema_registry = options
             .remove("format_options.confluent_schema_registry")
             .map(|f| f == "true")
             .unwrap_or(false);
 
+        let permissive = match options.remove("bad_data").as_deref() {
+            None | Some("fail") => false,
+            Some("permissive") => true,
+            Some(other) => bail!(
+                "invalid value '{}' for bad_data; expected 'fail' or 'permissive'",
+                other
+            ),
+        };
+
+        if permissive {
+            if format != Some(Format::JsonFormat) {
+                bail!("bad_data = 'permissive' is only supported for tables with format 'json'");
+            }
+
+            if let Some(f) = fields.iter().find(|f| f.name == CORRUPT_RECORD_FIELD) {
+                if !matches!(f.data_type, TypeDef::DataType(DataType::Utf8, _)) {
+                    bail!("the {} column must have type TEXT", CORRUPT_RECORD_FIELD);
+                }
+            }
+        }
+
+        if lineage && format != Some(Format::JsonFormat) {
+            bail!("lineage is only supported for tables with format 'json'");
+        }
+
+        // raw_bytes tables always have a single bytes column, so they can be used to move data
+        // between systems without declaring (or generating code for) a schema
+        let raw_bytes = format == Some(Format::RawBytesFormat);
+        let fields = if raw_bytes {
+            raw_bytes_fields(fields)?
+        } else {
+            fields
+        };
+
+        let protobuf = if format == Some(Format::ProtobufFormat) {
+            Some(protobuf_options(name, options, &fields)?)
+        } else {
+            None
+        };
+
+        let csv = if format == Some(Format::CsvFormat) {
+            Some(csv_options(options, &fields)?)
+        } else {
+            None
+        };
+
         let schema_fields: Result<Vec<SourceField>> = fields
             .iter()
             .map(|f| {
@@ -158,18 +688,73 @@ impl ConnectorTable {
             format: format.map(|f| f as i32),
             format_options: Some(FormatOptions {
                 confluent_schema_registry: schema_registry,
+                permissive,
+                lineage,
+                protobuf_message: protobuf.as_ref().map(|(_, message)| message.clone()),
+                csv,
             }),
-            struct_name: None,
+            struct_name: raw_bytes.then(|| "arroyo_types::RawBytes".to_string()),
             fields: schema_fields?,
-            definition: None,
+            definition: protobuf.map(|(descriptor_set, _)| {
+                grpc::api::connection_schema::Definition::ProtobufSchema(descriptor_set)
+            }),
         };
 
         let connection = connector.from_options(name, options, Some(&schema))?;
 
+        // defaults that weren't needed (for example, credentials for a different auth type than
+        // the table chose) shouldn't be reported as unknown options
+        for k in defaults {
+            options.remove(&k);
+        }
+
+        if permissive && !matches!(connection.connection_type, ConnectionType::Source) {
+            bail!("bad_data can only be set on sources");
+        }
+
+        if lineage && !matches!(connection.connection_type, ConnectionType::Source) {
+            bail!("lineage can only be set on sources");
+        }
+
         let mut table: ConnectorTable = connection.into();
-        table.fields = fields;
+        table.fields = source_fields(fields, permissive, lineage);
         table.event_time_field = options.remove("event_time_field");
         table.watermark_field = options.remove("watermark_field");
+        table.watermark_alignment = watermark_alignment(options)?;
+        table.timestamp_bounds = timestamp_bounds(options)?;
+
+        if table.timestamp_bounds.is_some()
+            && !matches!(table.connection_type, ConnectionType::Source)
+        {
+            bail!("timestamp bounds can only be set on sources");
+        }
+
+        table.tenant_limit = tenant_limit(options)?;
+        if table.tenant_limit.is_some() && !matches!(table.connection_type, ConnectionType::Source)
+        {
+            bail!("tenant limits can only be set on sources");
+        }
+
+        if route.is_some() && !matches!(table.connection_type, ConnectionType::Sink) {
+            bail!("route can only be set on sinks");
+        }
+        table.route = route;
+
+        if let Some(batching) = batching_options(options)? {
+            if !matches!(table.connection_type, ConnectionType::Sink) {
+                bail!("batch options can only be set on sinks");
+            }
+
+            let mut config: serde_json::Value = serde_json::from_str(&table.config)?;
+            config["batching"] = batching;
+            table.config = serde_json::to_string(&config)?;
+        }
+
+        if let Some(connection_pool) = connection_pool {
+            let mut config: serde_json::Value = serde_json::from_str(&table.config)?;
+            config["connection_pool"] = connection_pool;
+            table.config = serde_json::to_string(&config)?;
+        }
 
         if !options.is_empty() {
             let keys: Vec<String> = options.keys().map(|s| format!("'{}'", s)).collect();
@@ -275,11 +860,191 @@ impl ConnectorTable {
         }
     }
 
+    fn tenant_limit(&self) -> Result<Option<SourceTenantLimit>> {
+        let Some(options) = &self.tenant_limit else {
+            return Ok(None);
+        };
+        if self.is_update() {
+            bail!("tenant limits can't be used with update mode.")
+        }
+
+        // the tenant labels the limit's metrics, so it must have a readable string form
+        let field = self
+            .fields
+            .iter()
+            .find(|f| {
+                f.name == options.key
+                    && matches!(
+                        f.data_type,
+                        TypeDef::DataType(
+                            DataType::Utf8
+                                | DataType::Int8
+                                | DataType::Int16
+                                | DataType::Int32
+                                | DataType::Int64
+                                | DataType::UInt8
+                                | DataType::UInt16
+                                | DataType::UInt32
+                                | DataType::UInt64,
+                            _
+                        )
+                    )
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "tenant.key {} not found or not a TEXT or integer column",
+                    options.key
+                )
+            })?;
+
+        let expr = Expression::Column(ColumnExpression::new(field.clone()));
+        let tenant = expr.to_syn_expression();
+        let tenant = if expr.nullable() {
+            quote!(|arg| #tenant.map(|t| t.to_string()).unwrap_or_else(|| "null".to_string()))
+        } else {
+            quote!(|arg| #tenant.to_string())
+        };
+
+        Ok(Some(SourceTenantLimit {
+            key: Projection {
+                field_names: vec![Column {
+                    relation: None,
+                    name: field.name.clone(),
+                }],
+                field_computations: vec![expr],
+            },
+            limit: TenantLimit {
+                max_rate: options.max_rate,
+                burst: options.burst,
+                max_queued: options.max_queued,
+                tenant: tenant.to_string(),
+            },
+        }))
+    }
+
+    /// Whether the source can be read with only some of its fields, which is the case when it
+    /// deserializes JSON into a struct generated from its fields; the fields it doesn't have are
+    /// then skipped over by the deserializer rather than materialized.
+    fn supports_field_pruning(&self) -> bool {
+        matches!(self.connection_type, ConnectionType::Source)
+            && self.type_name.is_none()
+            && matches!(
+                self.serialization_mode,
+                SerializationMode::Json | SerializationMode::JsonSchemaRegistry
+            )
+    }
+
+    /// The table with only the fields that are `referenced` by the query, along with those its
+    /// virtual fields, event time and watermark are computed from. At least one non-virtual
+    /// field is always kept.
+    ///
+    /// Records are no longer rejected for bad values in fields that were dropped, as those fields
+    /// are never read.
+    fn with_referenced_fields(&self, referenced: &HashSet<String>) -> ConnectorTable {
+        let mut keep: HashSet<&str> = referenced.iter().map(|f| f.as_str()).collect();
+        keep.extend(self.event_time_field.as_deref());
+        keep.extend(self.watermark_field.as_deref());
+        keep.extend(self.tenant_limit.as_ref().map(|t| t.key.as_str()));
+
+        for field in &self.fields {
+            if let Some(expression) = &field.expression {
+                if keep.contains(field.name.as_str()) {
+                    keep.extend(
+                        expression
+                            .referenced_fields()
+                            .into_iter()
+                            .map(|f| f.name.as_str()),
+                    );
+                }
+            }
+        }
+
+        let physical = self.fields.iter().filter(|f| f.expression.is_none());
+        if !physical.clone().any(|f| keep.contains(f.name.as_str())) {
+            keep.extend(physical.take(1).map(|f| f.name.as_str()));
+        }
+
+        let mut table = self.clone();
+        table.fields.retain(|f| keep.contains(f.name.as_str()));
+        table
+    }
+
     fn connector_op(&self) -> ConnectorOp {
         ConnectorOp {
             operator: self.operator.clone(),
             config: self.config.clone(),
             description: self.description.clone(),
+            router: None,
+        }
+    }
+
+    /// Compiles the route expression into a closure that computes the destination of each
+    /// record written by the sink, or `None` to write it to the sink's own destination
+    fn router(
+        &self,
+        input_struct: &StructDef,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Option<String>> {
+        let Some(route) = &self.route else {
+            return Ok(None);
+        };
+
+        let schema = DFSchema::new_with_metadata(
+            input_struct
+                .fields
+                .iter()
+                .map(|f| {
+                    let field: Field = f.clone().into();
+                    DFField::new_unqualified(
+                        field.name(),
+                        field.data_type().clone(),
+                        field.is_nullable(),
+                    )
+                })
+                .collect(),
+            HashMap::new(),
+        )?;
+
+        let expr = schema_provider.sql_to_rel().sql_to_expr(
+            route.clone(),
+            &schema,
+            &mut PlannerContext::default(),
+        )?;
+        let expr = ExpressionContext {
+            input_struct,
+            schema_provider,
+        }
+        .compile_expr(&expr)?;
+
+        let TypeDef::DataType(DataType::Utf8, nullable) = expr.return_type() else {
+            bail!(
+                "route expression must return TEXT, but it returns {:?}",
+                expr.return_type()
+            );
+        };
+
+        let expr = expr.to_syn_expression();
+        let expr = if nullable {
+            quote!(#expr)
+        } else {
+            quote!(Some(#expr))
+        };
+        Ok(Some(quote!(|arg| { #expr }).to_string()))
+    }
+
+    /// Sinks in the debezium_json format are always written changes, even if their input is
+    /// append-only; other sinks are written the changes of updating input as they are, unless they
+    /// can only append rows.
+    fn sink_update_type(&self) -> SinkUpdateType {
+        if self.is_update() {
+            SinkUpdateType::Force
+        } else if APPEND_ONLY_SINKS
+            .iter()
+            .any(|op| self.operator.starts_with(op))
+        {
+            SinkUpdateType::Disallow
+        } else {
+            SinkUpdateType::Allow
         }
     }
 
@@ -305,6 +1070,11 @@ impl ConnectorTable {
         let virtual_field_projection = self.virtual_field_projection();
         let timestamp_override = self.timestamp_override()?;
         let watermark_column = self.watermark_column()?;
+        let tenant_limit = self.tenant_limit()?;
+
+        if self.watermark_alignment.is_some() && self.is_update() {
+            bail!("watermark alignment can't be used with update mode.")
+        }
 
         let source = SqlSource {
             id: self.id,
@@ -322,10 +1092,19 @@ impl ConnectorTable {
             virtual_field_projection,
             timestamp_override,
             watermark_column,
+            watermark_alignment: self.watermark_alignment.clone(),
+            timestamp_bounds: self.timestamp_bounds.clone(),
+            assertions: vec![],
+            tenant_limit,
+            unchecked: false,
         }))
     }
 
-    pub fn as_sql_sink(&self, input: SqlOperator) -> Result<SqlOperator> {
+    pub fn as_sql_sink(
+        &self,
+        input: SqlOperator,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<SqlOperator> {
         match self.connection_type {
             ConnectionType::Source => {
                 bail!("Inserting into a source is not allowed")
@@ -338,22 +1117,119 @@ impl ConnectorTable {
             bail!("Virtual fields are not currently supported in sinks");
         }
 
+        let input = mask_sink_input(&self.name, input, schema_provider)?;
+
+        let updating_type = self.sink_update_type();
+        if matches!(updating_type, SinkUpdateType::Disallow) && input.is_updating() {
+            bail!(
+                "sink '{}' can only append rows, but the query writes updates; use \
+                format = 'debezium_json' to write them",
+                self.name
+            );
+        }
+
+        let mut connector_op = self.connector_op();
+        connector_op.router = self
+            .router(&input.return_type(), schema_provider)
+            .map_err(|e| anyhow!("invalid route for sink '{}': {}", self.name, e))?;
+
         Ok(SqlOperator::Sink(
             self.name.clone(),
             SqlSink {
                 id: self.id,
                 struct_def: input.return_type(),
-                updating_type: crate::external::SinkUpdateType::Disallow,
-                operator: Operator::ConnectorSink(self.connector_op()),
+                updating_type,
+                operator: Operator::ConnectorSink(connector_op),
             },
             Box::new(input),
         ))
     }
 }
 
+// how often a reference table is reloaded if it doesn't set a refresh_interval
+const DEFAULT_REFERENCE_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// A small table of newline-delimited JSON rows loaded from a url (s3://, http(s)://, or
+/// file://) and reloaded periodically. Rather than being read as a stream, it's loaded by every
+/// subtask of the operator that joins against it, so the joined stream doesn't need to be shuffled.
+#[derive(Debug, Clone)]
+pub struct ReferenceTable {
+    pub name: String,
+    pub fields: Vec<StructField>,
+    pub location: String,
+    pub refresh_interval: Duration,
+}
+
+impl ReferenceTable {
+    fn from_options(
+        name: &str,
+        fields: Vec<StructField>,
+        options: &mut HashMap<String, String>,
+    ) -> Result<Self> {
+        if fields.iter().any(|f| f.expression.is_some()) {
+            bail!("Virtual fields are not supported in reference tables");
+        }
+
+        let location = options
+            .remove("location")
+            .ok_or_else(|| anyhow!("reference tables require a 'location' option"))?;
+        if !["s3://", "http://", "https://", "file://"]
+            .iter()
+            .any(|scheme| location.starts_with(scheme))
+        {
+            bail!(
+                "invalid location '{}'; expected an s3://, http(s)://, or file:// url",
+                location
+            );
+        }
+
+        match options.remove("format").as_deref() {
+            None | Some("json") => {}
+            Some(other) => bail!(
+                "unsupported format '{}' for reference table; only 'json' is supported",
+                other
+            ),
+        }
+
+        let refresh_interval = options
+            .remove("refresh_interval")
+            .map(|d| parse_duration(&d))
+            .transpose()?
+            .unwrap_or(DEFAULT_REFERENCE_REFRESH_INTERVAL);
+        if refresh_interval.is_zero() {
+            bail!("refresh_interval must be greater than zero");
+        }
+
+        if !options.is_empty() {
+            let keys: Vec<String> = options.keys().map(|s| format!("'{}'", s)).collect();
+            bail!(
+                "unknown options provided in WITH clause: {}",
+                keys.join(", ")
+            );
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            fields,
+            location,
+            refresh_interval,
+        })
+    }
+
+    /// The struct that rows with the fields at `projection` are loaded into
+    pub fn row_struct(&self, projection: Option<&Vec<usize>>) -> StructDef {
+        let fields = match projection {
+            Some(projection) => projection.iter().map(|i| self.fields[*i].clone()).collect(),
+            None => self.fields.clone(),
+        };
+        StructDef { name: None, fields }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Table {
     ConnectorTable(ConnectorTable),
+    ReferenceTable(ReferenceTable),
     MemoryTable {
         name: String,
         fields: Vec<StructField>,
@@ -362,6 +1238,7 @@ pub enum Table {
         name: String,
         logical_plan: LogicalPlan,
     },
+    ProcessTable(ProcessTable),
 }
 
 fn value_to_inner_string(value: &Value) -> Result<String> {
@@ -381,7 +1258,7 @@ impl Table {
         let struct_field_pairs = columns
             .iter()
             .map(|column| {
-                let name = column.name.value.to_string();
+                let name = schema_provider.ident_name(&column.name);
                 let data_type = convert_data_type(&column.data_type)?;
                 let nullable = !column
                     .options
@@ -438,7 +1315,7 @@ impl Table {
             schema_provider,
         };
 
-        let sql_to_rel = SqlToRel::new(schema_provider);
+        let sql_to_rel = schema_provider.sql_to_rel();
         struct_field_pairs
             .into_iter()
             .map(|(mut struct_field, generating_expression)| {
@@ -472,7 +1349,7 @@ impl Table {
             ..
         } = statement
         {
-            let name: String = name.to_string();
+            let name = schema_provider.object_name(name);
             let mut with_map = HashMap::new();
             for option in with_options {
                 with_map.insert(
@@ -483,7 +1360,26 @@ impl Table {
 
             let fields = Self::schema_from_columns(columns, schema_provider)?;
 
-            let connector = with_map.remove("connector");
+            let saved_connection = with_map
+                .remove("connection")
+                .map(|c| {
+                    schema_provider
+                        .get_saved_connection(&c)
+                        .ok_or_else(|| anyhow!("no connection named '{}' exists", c))
+                })
+                .transpose()?;
+
+            let connector = match (with_map.remove("connector"), saved_connection) {
+                (Some(connector), Some(saved)) if connector != saved.connector => {
+                    bail!(
+                        "table '{}' has connector '{}' but its connection is for '{}'",
+                        name,
+                        connector,
+                        saved.connector
+                    );
+                }
+                (connector, saved) => connector.or_else(|| saved.map(|s| s.connector.clone())),
+            };
 
             match connector.as_ref().map(|c| c.as_str()) {
                 Some("memory") | None => {
@@ -501,9 +1397,25 @@ impl Table {
 
                     Ok(Some(Table::MemoryTable { name, fields }))
                 }
+                Some("reference") => {
+                    if saved_connection.is_some() {
+                        bail!("reference tables can't use a connection");
+                    }
+                    Ok(Some(Table::ReferenceTable(
+                        ReferenceTable::from_options(&name, fields, &mut with_map).map_err(
+                            |e| anyhow!("Failed to construct table '{}': {:?}", name, e),
+                        )?,
+                    )))
+                }
                 Some(connector) => Ok(Some(Table::ConnectorTable(
-                    ConnectorTable::from_options(&name, connector, fields, &mut with_map)
-                        .map_err(|e| anyhow!("Failed to construct table '{}': {:?}", name, e))?,
+                    ConnectorTable::from_options(
+                        &name,
+                        connector,
+                        fields,
+                        saved_connection,
+                        &mut with_map,
+                    )
+                    .map_err(|e| anyhow!("Failed to construct table '{}': {:?}", name, e))?,
                 ))),
             }
         } else {
@@ -530,13 +1442,16 @@ impl Table {
         match self {
             Table::MemoryTable { name, .. } | Table::TableFromQuery { name, .. } => name.as_str(),
             Table::ConnectorTable(c) => c.name.as_str(),
+            Table::ReferenceTable(r) => r.name.as_str(),
+            Table::ProcessTable(p) => p.name.as_str(),
         }
     }
 
     pub fn get_fields(&self) -> Result<Vec<Field>> {
         match self {
             Table::MemoryTable { fields, .. }
-            | Table::ConnectorTable(ConnectorTable { fields, .. }) => fields
+            | Table::ConnectorTable(ConnectorTable { fields, .. })
+            | Table::ReferenceTable(ReferenceTable { fields, .. }) => fields
                 .iter()
                 .map(|field| {
                     let field: Field = field.clone().into();
@@ -552,12 +1467,27 @@ impl Table {
                     Ok(field)
                 })
                 .collect::<Result<Vec<_>>>(),
+            Table::ProcessTable(p) => Ok(p
+                .function
+                .output_struct()
+                .fields
+                .into_iter()
+                .map(|field| field.into())
+                .collect()),
         }
     }
 
     pub fn as_sql_source(&self, builder: &mut SqlPipelineBuilder) -> Result<SqlOperator> {
         match self {
-            Table::ConnectorTable(cn) => cn.as_sql_source(),
+            Table::ConnectorTable(cn) => {
+                let source = match builder.source_fields.get(&cn.name) {
+                    Some(referenced) if cn.supports_field_pruning() => {
+                        cn.with_referenced_fields(referenced).as_sql_source()
+                    }
+                    _ => cn.as_sql_source(),
+                }?;
+                builder.add_assertions(&cn.name, source)
+            }
             Table::MemoryTable { name, .. } => Ok(builder
                 .planned_tables
                 .get(name)
@@ -571,16 +1501,27 @@ impl Table {
             Table::TableFromQuery { logical_plan, .. } => {
                 builder.insert_sql_plan(&logical_plan.clone())
             }
+            Table::ReferenceTable(r) => bail!(
+                "reference table '{}' can only be read by joining a stream against it",
+                r.name
+            ),
+            Table::ProcessTable(p) => p.as_sql_source(builder),
         }
     }
 
-    pub fn as_sql_sink(&self, input: SqlOperator) -> Result<SqlOperator> {
+    pub fn as_sql_sink(
+        &self,
+        input: SqlOperator,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<SqlOperator> {
         match self {
-            Table::ConnectorTable(c) => c.as_sql_sink(input),
+            Table::ConnectorTable(c) => c.as_sql_sink(input, schema_provider),
             Table::MemoryTable { name, .. } => {
                 Ok(SqlOperator::NamedTable(name.clone(), Box::new(input)))
             }
             Table::TableFromQuery { .. } => todo!(),
+            Table::ReferenceTable(r) => bail!("can't insert into reference table '{}'", r.name),
+            Table::ProcessTable(_) => bail!("can't insert into the output of PROCESS"),
         }
     }
 }
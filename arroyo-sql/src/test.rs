@@ -1,10 +1,20 @@
+use anyhow::Result;
 use arrow_schema::DataType;
 use arroyo_connectors::{
     nexmark::{NexmarkConnector, NexmarkTable},
     Connector, EmptyConfig,
 };
+use arroyo_datastream::{EdgeType, Operator, TopN, WindowAgg, WindowType};
+use arroyo_types::UdfSandbox;
+use datafusion_expr::{lit, Expr};
+use petgraph::{visit::EdgeRef, Direction};
+use std::collections::HashSet;
+use std::time::Duration;
 
-use crate::{parse_and_get_program, types::TypeDef, ArroyoSchemaProvider, SqlConfig};
+use crate::extensions::{self, FunctionImplementation, ScalarFunctionExtension};
+use crate::masking::{MaskingAction, MaskingPolicy};
+use crate::types::{rust_field_name, StructField};
+use crate::{compile_sql, parse_and_get_program, types::TypeDef, ArroyoSchemaProvider, SqlConfig};
 
 #[tokio::test]
 async fn test_parse() {
@@ -47,6 +57,59 @@ async fn test_parse() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_tumbling_window_join() {
+    let query = |window: &str| {
+        format!(
+            "
+    WITH bids as (SELECT bid.auction as auction, bid.price as price
+      FROM nexmark where bid is not null)
+    SELECT counts.auction, counts.num, totals.total
+    FROM (
+      SELECT auction, {window} as window, count(*) AS num
+      FROM bids
+      GROUP BY 1, 2
+    ) AS counts
+    JOIN (
+      SELECT auction, {window} as window, sum(price) AS total
+      FROM bids
+      GROUP BY 1, 2
+    ) AS totals
+    ON counts.auction = totals.auction AND counts.window = totals.window"
+        )
+    };
+
+    let join_window = |sql: String| async move {
+        let (program, _) =
+            parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+                .await
+                .unwrap();
+        program
+            .graph
+            .node_weights()
+            .find_map(|n| match &n.operator {
+                Operator::WindowJoin { window } => Some(window.clone()),
+                _ => None,
+            })
+            .unwrap()
+    };
+
+    // aggregates over the same tumbling window are joined by that window
+    assert_eq!(
+        join_window(query("TUMBLE(INTERVAL '1' HOUR)")).await,
+        WindowType::Tumbling {
+            width: Duration::from_secs(60 * 60),
+            offset: Duration::ZERO,
+        }
+    );
+
+    // other windows are still joined instant by instant
+    assert_eq!(
+        join_window(query("HOP(INTERVAL '10' MINUTE, INTERVAL '1' HOUR)")).await,
+        WindowType::Instant
+    );
+}
+
 #[tokio::test]
 async fn test_program_compilation() {
     let schema_provider = get_test_schema_provider();
@@ -117,6 +180,287 @@ async fn test_window_function() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_rank_and_offset_window_functions() {
+    let schema_provider = get_test_schema_provider();
+
+    for function in ["RANK()", "DENSE_RANK()", "LAG(count)", "LEAD(count, 2, 0)"] {
+        let sql = format!(
+            "SELECT * FROM (
+            SELECT *, {} OVER (PARTITION BY window ORDER BY count DESC) as value
+            FROM (SELECT bid.auction as auction, count(*) as count,
+                tumble(interval '10 seconds') as window
+                    FROM nexmark
+                    WHERE bid is not null
+                    group by 1, window)) WHERE auction > 10",
+            function
+        );
+
+        parse_and_get_program(&sql, schema_provider.clone(), SqlConfig::default())
+            .await
+            .unwrap();
+    }
+
+    let sql = "SELECT *, LAG(count, count) OVER (PARTITION BY window ORDER BY count) as previous
+    FROM (SELECT count(*) as count, tumble(interval '10 seconds') as window
+        FROM nexmark group by window)";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("must be a non-negative integer literal"));
+}
+
+#[tokio::test]
+async fn test_window_function_expression_partitions() {
+    for order_by in [
+        "avg_price",
+        "avg_price DESC NULLS LAST",
+        "avg_price NULLS FIRST, auction % 7 DESC",
+    ] {
+        let sql = format!(
+            "SELECT *, ROW_NUMBER() OVER (
+                PARTITION BY window, auction % 10, bidder IS NULL
+                ORDER BY {}) as row_num
+            FROM (SELECT bid.auction as auction, bid.bidder as bidder, avg(bid.price) as avg_price,
+                tumble(interval '10 seconds') as window
+                FROM nexmark
+                GROUP BY 1, 2, window)",
+            order_by
+        );
+
+        let (program, _) =
+            parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+                .await
+                .unwrap();
+
+        let window_expression = program
+            .graph
+            .node_weights()
+            .find_map(|n| match &n.operator {
+                Operator::Window {
+                    agg: Some(WindowAgg::Expression { name, expression }),
+                    ..
+                } if name == "sql_window" => Some(expression.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert!(window_expression.contains("OrderedFloat"), "{}", order_by);
+    }
+}
+
+#[tokio::test]
+async fn test_global_top_n() {
+    let sql = "SELECT bid.auction as auction, count(*) as count
+        FROM nexmark WHERE bid is not null GROUP BY 1
+        ORDER BY count DESC LIMIT 10";
+
+    let (program, _) = parse_and_get_program(
+        sql,
+        get_test_schema_provider(),
+        SqlConfig {
+            default_parallelism: 4,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let top_ns: Vec<_> = program
+        .graph
+        .node_weights()
+        .filter_map(|n| match &n.operator {
+            Operator::TopN(top_n) => Some((top_n.clone(), n.parallelism)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(top_ns.len(), 2);
+
+    for (top_n, parallelism) in top_ns {
+        assert_eq!(top_n.max_elements, 10);
+        assert!(top_n.input_updating);
+        assert_eq!(parallelism, if top_n.global { 1 } else { 4 });
+    }
+
+    // everything after the final merge runs on a single subtask
+    let sink = program
+        .graph
+        .node_weights()
+        .find(|n| matches!(n.operator, Operator::ConnectorSink(_)))
+        .unwrap();
+    assert_eq!(sink.parallelism, 1);
+
+    for sql in [
+        "SELECT bid.price FROM nexmark ORDER BY bid.price",
+        "SELECT bid.price FROM nexmark LIMIT 10",
+        "SELECT bid.price FROM nexmark ORDER BY bid.price LIMIT 10 OFFSET 5",
+        "SELECT * FROM (SELECT bid.price as price FROM nexmark ORDER BY price LIMIT 10) WHERE price > 5",
+    ] {
+        parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+            .await
+            .unwrap_err();
+    }
+}
+
+async fn warning_codes(sql: &str) -> Vec<&'static str> {
+    compile_sql(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap()
+        .warnings
+        .into_iter()
+        .map(|w| w.code)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_lint_unbounded_state() {
+    let join = "SELECT * FROM
+    (SELECT bid.auction as auction, bid.price as price FROM nexmark WHERE bid is not null) bids
+    JOIN (SELECT auction.id as id FROM nexmark where auction is not null) auctions
+    ON bids.auction = auctions.id";
+    assert_eq!(warning_codes(join).await, vec!["unwindowed_join"]);
+
+    let aggregate = "SELECT bid.auction as auction, count(*) as count
+        FROM nexmark where bid is not null group by 1";
+    assert_eq!(warning_codes(aggregate).await, vec!["unwindowed_aggregate"]);
+
+    let order_by = "SELECT *, ROW_NUMBER() OVER (
+        PARTITION BY window
+        ORDER BY count DESC) as row_num
+    FROM (SELECT count(*) as count,
+        tumble(interval '10 seconds') as window
+            FROM nexmark
+            group by window)";
+    assert_eq!(warning_codes(order_by).await, vec!["unlimited_order_by"]);
+}
+
+#[tokio::test]
+async fn test_sink_schemas() {
+    let sql = "SELECT bid.auction as auction, bid.url as url, count(*) as count
+        FROM nexmark where bid is not null group by 1, 2";
+    let compiled = compile_sql(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    assert_eq!(compiled.sinks.len(), 1);
+    let sink = &compiled.sinks[0];
+    assert_eq!(sink.name, "web");
+    assert!(sink.updating);
+
+    let fields: Vec<_> = sink
+        .fields
+        .iter()
+        .map(|f| (f.name.as_str(), f.sql_type.as_str()))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![("auction", "BIGINT"), ("url", "TEXT"), ("count", "BIGINT")]
+    );
+}
+
+#[tokio::test]
+async fn test_lint_windowed_queries() {
+    let aggregate = "SELECT bid.auction as auction, count(*) as count,
+        tumble(interval '1 minute') as window
+        FROM nexmark where bid is not null group by 1, 3";
+    assert!(warning_codes(aggregate).await.is_empty());
+
+    let top_n = "SELECT * FROM (
+    SELECT *, ROW_NUMBER() OVER (
+        PARTITION BY window
+        ORDER BY count DESC) as row_num
+    FROM (SELECT count(*) as count,
+        tumble(interval '10 seconds') as window
+            FROM nexmark
+            group by window)) WHERE row_num <= 5";
+    assert!(warning_codes(top_n).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_window_offset_and_accessors() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT tumble_start(window) as start, tumble_end(window) as end, count
+    FROM (SELECT count(*) as count,
+        tumble(interval '1 hour', interval '15 minutes') as window
+            FROM nexmark
+            group by window)";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT count(*) as count, hop(interval '1 minute', interval '5 minutes', interval '1 minute') as window
+        FROM nexmark group by window";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("window offset"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_calendar_window() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT count(*) as count, calendar('day', 'America/New_York') as window
+        FROM nexmark group by window";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT count(*) as count, calendar('month', 'Mars/Olympus_Mons') as window
+        FROM nexmark group by window";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid timezone"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_ordered_pipeline() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT bid.auction as auction, count(*) as count,
+        tumble(interval '1 minute') as window
+        FROM nexmark where bid is not null group by 1, 3";
+
+    let (program, _) = parse_and_get_program(
+        sql,
+        schema_provider,
+        SqlConfig {
+            ordered: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let shuffles: Vec<_> = program
+        .graph
+        .edge_references()
+        .filter(|e| e.weight().typ == EdgeType::Shuffle)
+        .collect();
+    assert!(!shuffles.is_empty());
+
+    // every shuffle feeds a reorder buffer, which forwards to the original target
+    for shuffle in shuffles {
+        assert_eq!(
+            Operator::ReorderBuffer,
+            program.graph[shuffle.target()].operator
+        );
+        let outgoing: Vec<_> = program
+            .graph
+            .edges_directed(shuffle.target(), Direction::Outgoing)
+            .collect();
+        assert_eq!(1, outgoing.len());
+        assert_eq!(EdgeType::Forward, outgoing[0].weight().typ);
+    }
+}
+
 #[tokio::test]
 async fn test_no_updating_window_functions() {
     let schema_provider = get_test_schema_provider();
@@ -155,6 +499,538 @@ async fn test_no_virtual_fields_updating() {
         .unwrap_err();
 }
 
+#[tokio::test]
+async fn test_window_aggregate_over_updating() {
+    let sql = |query: &str| {
+        format!(
+            "CREATE TABLE debezium_source (
+            id int,
+            price int
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'source',
+            topic = 'updating',
+            format = 'debezium_json'
+          );
+          CREATE TABLE totals (
+            id int,
+            total bigint
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'sink',
+            topic = 'totals',
+            format = 'json'
+          );
+          INSERT INTO totals {}",
+            query
+        )
+    };
+
+    // windows emit once they close, so they can be written to append-only sinks
+    let (program, _) = parse_and_get_program(
+        &sql("SELECT id, sum(price) as total FROM debezium_source
+            GROUP BY id, tumble(interval '1 minute')"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let aggregator = program
+        .graph
+        .node_weights()
+        .find_map(|n| match &n.operator {
+            Operator::TumblingWindowAggregator(aggregator) => Some(aggregator),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(aggregator.width, Duration::from_secs(60));
+    assert!(aggregator.bin_type.starts_with("Option <"));
+
+    let err = parse_and_get_program(
+        &sql("SELECT id, sum(price) as total FROM debezium_source
+            GROUP BY id, hop(interval '10 seconds', interval '1 minute')"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("only support tumbling windows"));
+}
+
+#[tokio::test]
+async fn test_watermark_alignment() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE orders (
+        id bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json',
+        'watermark_alignment.group' = 'backfill',
+        'watermark_alignment.max_drift' = '5 minutes'
+      );
+      SELECT * FROM orders";
+    let (program, _) = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    let alignment = program
+        .graph
+        .node_weights()
+        .find_map(|n| match &n.operator {
+            Operator::Watermark(w) => w.alignment().cloned(),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!("backfill", alignment.group);
+    assert_eq!(Duration::from_secs(5 * 60), alignment.max_drift);
+
+    let sql = "CREATE TABLE orders (
+        id bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json',
+        'watermark_alignment.group' = 'backfill'
+      );
+      SELECT * FROM orders";
+    assert!(
+        parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_timestamp_bounds() {
+    let sql = |options: &str| {
+        format!(
+            "CREATE TABLE orders (
+            id bigint
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'source',
+            topic = 'orders',
+            format = 'json',
+            {}
+          );
+          SELECT * FROM orders",
+            options
+        )
+    };
+
+    let (program, _) = parse_and_get_program(
+        &sql(
+            "'timestamp_bounds.max_future' = '30s', 'timestamp_bounds.max_age' = '7 days', \
+            'timestamp_bounds.action' = 'clamp'",
+        ),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let bounds = program
+        .graph
+        .node_weights()
+        .find_map(|n| match &n.operator {
+            Operator::TimestampBounds(bounds) => Some(bounds.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(Some(Duration::from_secs(30)), bounds.max_future);
+    assert_eq!(Some(Duration::from_secs(7 * 24 * 60 * 60)), bounds.max_age);
+    assert!(bounds.clamp);
+
+    // out of bounds records are rejected by default
+    let (program, _) = parse_and_get_program(
+        &sql("'timestamp_bounds.max_future' = '1 minute'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(program.graph.node_weights().any(|n| matches!(
+        &n.operator,
+        Operator::TimestampBounds(bounds) if !bounds.clamp && bounds.max_age.is_none()
+    )));
+
+    parse_and_get_program(
+        &sql("'timestamp_bounds.action' = 'clamp'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+
+    parse_and_get_program(
+        &sql("'timestamp_bounds.max_future' = '1 minute', 'timestamp_bounds.action' = 'drop'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_tenant_limits() {
+    let sql = |options: &str| {
+        format!(
+            "CREATE TABLE orders (
+            id bigint,
+            tenant text,
+            created timestamp
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'source',
+            topic = 'orders',
+            format = 'json',
+            {}
+          );
+          SELECT id FROM orders",
+            options
+        )
+    };
+
+    // the tenant column is read even though the query doesn't select it
+    let (program, _) = parse_and_get_program(
+        &sql(
+            "'tenant.key' = 'tenant', 'tenant.max_rate' = '100', 'tenant.policy' = 'queue', \
+            'tenant.max_queued' = '50'",
+        ),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let limit_index = program
+        .graph
+        .node_indices()
+        .find(|idx| matches!(program.graph[*idx].operator, Operator::TenantLimit(_)))
+        .unwrap();
+    let Operator::TenantLimit(limit) = &program.graph[limit_index].operator else {
+        unreachable!();
+    };
+    assert_eq!(100, limit.max_rate);
+    assert_eq!(100, limit.burst);
+    assert_eq!(Some(50), limit.max_queued);
+
+    // each tenant is limited by a single subtask
+    let inputs: Vec<_> = program
+        .graph
+        .edges_directed(limit_index, Direction::Incoming)
+        .map(|e| e.weight().typ.clone())
+        .collect();
+    assert_eq!(vec![EdgeType::Shuffle], inputs);
+
+    // records over the rate are shed by default
+    let (program, _) = parse_and_get_program(
+        &sql("'tenant.key' = 'tenant', 'tenant.max_rate' = '100', 'tenant.burst' = '500'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(program.graph.node_weights().any(|n| matches!(
+        &n.operator,
+        Operator::TenantLimit(limit) if limit.burst == 500 && limit.max_queued.is_none()
+    )));
+
+    for (options, error) in [
+        ("'tenant.key' = 'tenant'", "must be set together"),
+        (
+            "'tenant.key' = 'created', 'tenant.max_rate' = '100'",
+            "not a TEXT or integer column",
+        ),
+        (
+            "'tenant.key' = 'tenant', 'tenant.max_rate' = '0'",
+            "expected a positive integer",
+        ),
+        (
+            "'tenant.key' = 'tenant', 'tenant.max_rate' = '100', 'tenant.max_queued' = '10'",
+            "when tenant.policy is 'queue'",
+        ),
+    ] {
+        let err = parse_and_get_program(
+            &sql(options),
+            get_test_schema_provider(),
+            SqlConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains(error), "{}: {}", options, err);
+    }
+}
+
+#[tokio::test]
+async fn test_assertions() {
+    let sql = |assertions: &str| {
+        format!(
+            "{}
+          CREATE TABLE orders (
+            id bigint,
+            amount double
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'source',
+            topic = 'orders',
+            format = 'json'
+          );
+          CREATE TABLE bad_orders (
+            id bigint,
+            amount double
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'sink',
+            topic = 'bad_orders',
+            format = 'json'
+          );
+          SELECT id FROM orders",
+            assertions
+        )
+    };
+
+    let (program, _) = parse_and_get_program(
+        &sql("ASSERT orders.amount >= 0 ON VIOLATION DLQ bad_orders;
+            ASSERT orders.id IS NOT NULL ON VIOLATION DROP;
+            ASSERT (orders.id > 0 OR orders.amount = 0);"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let mut assertions: Vec<_> = program
+        .graph
+        .node_weights()
+        .filter_map(|n| match &n.operator {
+            Operator::Assertion(assertion) => Some((assertion.assertion.clone(), assertion.fail)),
+            _ => None,
+        })
+        .collect();
+    assertions.sort();
+    assert_eq!(
+        vec![
+            ("(orders.id > 0 OR orders.amount = 0)".to_string(), true),
+            ("orders.amount >= 0".to_string(), false),
+            ("orders.id IS NOT NULL".to_string(), false),
+        ],
+        assertions
+    );
+
+    // violations are written to the dead-letter sink, in addition to the query's web sink
+    let sinks = program
+        .graph
+        .node_weights()
+        .filter(|n| matches!(n.operator, Operator::ConnectorSink(_)))
+        .count();
+    assert_eq!(2, sinks);
+
+    // assertions must qualify their columns with the source table they're on, and may only write
+    // violations to sinks
+    for invalid in [
+        "ASSERT amount >= 0;",
+        "ASSERT orders.amount >= 0 ON VIOLATION IGNORE;",
+        "ASSERT bad_orders.amount >= 0;",
+        "ASSERT orders.amount >= 0 ON VIOLATION DLQ orders;",
+    ] {
+        parse_and_get_program(
+            &sql(invalid),
+            get_test_schema_provider(),
+            SqlConfig::default(),
+        )
+        .await
+        .unwrap_err();
+    }
+}
+
+#[tokio::test]
+async fn test_sink_batching_options() {
+    let sql = "CREATE TABLE orders_sink (
+        id bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'sink',
+        topic = 'orders',
+        format = 'json',
+        'batch.max_records' = '500',
+        'batch.max_linger' = '100 ms'
+      );
+      INSERT INTO orders_sink SELECT bid.auction FROM nexmark";
+    let (program, _) = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let config = program
+        .graph
+        .node_weights()
+        .find_map(|n| match &n.operator {
+            Operator::ConnectorSink(c) => Some(c.config.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let config: serde_json::Value = serde_json::from_str(&config).unwrap();
+    assert_eq!(
+        serde_json::json!({"max_records": 500, "max_linger_micros": 100_000}),
+        config["batching"]
+    );
+
+    let sql = "CREATE TABLE orders (
+        id bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json',
+        'batch.max_records' = '500'
+      );
+      SELECT * FROM orders";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("batch options can only be set on sinks"));
+}
+
+#[tokio::test]
+async fn test_jdbc_sink_update_types() {
+    let sql = |format: &str, options: &str, query: &str| {
+        format!(
+            "CREATE TABLE totals (
+            auction bigint,
+            total bigint
+          ) WITH (
+            connector = 'jdbc',
+            connection_string = 'postgresql://localhost:5432/arroyo',
+            table_name = 'totals',
+            format = '{}'
+            {}
+          );
+          INSERT INTO totals {}",
+            format, options, query
+        )
+    };
+    let updating = "SELECT bid.auction AS auction, count(*) AS total FROM nexmark GROUP BY 1";
+    let append = "SELECT bid.auction AS auction, bid.price AS total FROM nexmark";
+
+    let debezium_nodes = |sql: String| async move {
+        let (program, _) =
+            parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+                .await
+                .unwrap();
+        program
+            .graph
+            .node_weights()
+            .filter(|n| match &n.operator {
+                Operator::ExpressionOperator { name, .. } => name == "to_debezium",
+                _ => false,
+            })
+            .count()
+    };
+
+    // upserting sinks are written changes whether or not the query is updating
+    assert_eq!(
+        1,
+        debezium_nodes(sql("debezium_json", ", primary_key = 'auction'", updating)).await
+    );
+    assert_eq!(
+        1,
+        debezium_nodes(sql("debezium_json", ", primary_key = 'auction'", append)).await
+    );
+    assert_eq!(0, debezium_nodes(sql("json", "", append)).await);
+
+    let err = parse_and_get_program(
+        &sql("json", "", updating),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("can only append rows"));
+
+    let err = parse_and_get_program(
+        &sql("debezium_json", "", updating),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("primaryKey must be set"));
+}
+
+#[tokio::test]
+async fn test_raw_bytes_columns() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE raw_source (
+        value text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'raw_bytes'
+      );
+      SELECT * FROM raw_source";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("single column 'value BYTEA NOT NULL'"));
+}
+
+#[tokio::test]
+async fn test_saved_connection() {
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider.add_saved_connection(
+        "local_kafka",
+        "kafka",
+        r#"{"bootstrapServers": "localhost:9092", "authentication": {}}"#,
+    );
+
+    let sql = "CREATE TABLE events (
+        value text
+      ) WITH (
+        connection = 'local_kafka',
+        type = 'source',
+        topic = 'events',
+        format = 'raw_string'
+      );
+      SELECT * FROM events";
+    parse_and_get_program(sql, schema_provider.clone(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "CREATE TABLE events (
+        value text
+      ) WITH (
+        connection = 'local_kafka',
+        connector = 'sse',
+        endpoint = 'http://localhost:9091/events',
+        format = 'raw_string'
+      );
+      SELECT * FROM events";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("its connection is for 'kafka'"));
+}
+
 #[tokio::test]
 async fn test_udf() {
     let mut schema_provider = get_test_schema_provider();
@@ -171,3 +1047,1135 @@ async fn test_udf() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_udf_cache() {
+    let mut schema_provider = get_test_schema_provider();
+
+    schema_provider
+        .add_rust_udf(
+            "use std::time::Duration;
+            use arroyo_worker::udf_cache::UdfCache;
+
+            const SQUARES: UdfCache<i64, i64> = UdfCache::new(\"squares\", Duration::from_secs(60), 100);
+
+            fn cached_sqr(x: i64) -> i64 {
+                SQUARES.get_or_insert_with(&x, || x * x)
+            }",
+        )
+        .unwrap();
+
+    let sql = "SELECT cached_sqr(bid.auction) FROM nexmark";
+    let (program, _) = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    let defs = program.other_defs.join("\n");
+    assert!(defs.contains("const SQUARES"), "{}", defs);
+}
+
+#[tokio::test]
+async fn test_sandboxed_udf() {
+    let mut schema_provider = get_test_schema_provider();
+
+    schema_provider
+        .add_rust_udf("fn my_sqr(x: i64) -> i64 { x * x }")
+        .unwrap();
+
+    let sql = "SELECT my_sqr(bid.auction) FROM nexmark";
+    let (program, _) = parse_and_get_program(
+        sql,
+        schema_provider,
+        SqlConfig {
+            udf_sandbox: Some(UdfSandbox {
+                memory_bytes: 1 << 20,
+                fuel: 1000,
+            }),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    // the body is compiled into the wasm module, and the pipeline calls it in the sandbox
+    let defs = program.other_defs.join("\n");
+    assert!(!defs.contains("x * x"), "{}", defs);
+    assert!(defs.contains("arroyo_worker :: udfs :: call"), "{}", defs);
+
+    let wasm_defs = program.wasm_defs.join("\n");
+    assert!(wasm_defs.contains("x * x"), "{}", wasm_defs);
+    assert!(wasm_defs.contains("__udf_my_sqr"), "{}", wasm_defs);
+}
+
+#[tokio::test]
+async fn test_process_function() {
+    let mut schema_provider = get_test_schema_provider();
+
+    schema_provider
+        .add_rust_udf(
+            "use std::collections::HashMap;
+            use std::time::SystemTime;
+            use arroyo_worker::operators::process::ProcessFunction;
+
+            struct Bid { auction: Option<i64>, price: Option<i64> }
+            struct NewMax { auction: i64, max_price: i64 }
+
+            struct MaxPrices { prices: HashMap<i64, i64> }
+
+            impl ProcessFunction for MaxPrices {
+                type Input = Bid;
+                type Output = NewMax;
+
+                fn new(_: &str) -> Self {
+                    MaxPrices { prices: HashMap::new() }
+                }
+
+                fn process(&mut self, bid: Bid, _: SystemTime) -> Vec<NewMax> {
+                    let (Some(auction), Some(price)) = (bid.auction, bid.price) else {
+                        return vec![];
+                    };
+                    let max = self.prices.entry(auction).or_insert(price);
+                    *max = (*max).max(price);
+                    vec![NewMax { auction, max_price: *max }]
+                }
+            }",
+        )
+        .unwrap();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price FROM nexmark WHERE bid is not null;
+
+        SELECT auction, max_price FROM PROCESS(MaxPrices, TABLE bids, '{}') WHERE max_price > 100";
+    let (program, _) = parse_and_get_program(
+        sql,
+        schema_provider,
+        SqlConfig {
+            default_parallelism: 4,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let process = program
+        .graph
+        .node_weights()
+        .find(|n| matches!(n.operator, Operator::ProcessFunction(_)))
+        .unwrap();
+    assert_eq!(process.parallelism, 1);
+
+    let defs = program.other_defs.join("\n");
+    assert!(
+        defs.contains("impl ProcessFunction for MaxPrices"),
+        "{}",
+        defs
+    );
+
+    let err = parse_and_get_program(
+        "SELECT * FROM PROCESS(MinPrices, TABLE nexmark)",
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("no process function named"));
+}
+
+#[tokio::test]
+async fn test_shared_subexpressions() {
+    let schema_provider = get_test_schema_provider();
+
+    // a wide projection where every field uses the same value, filtered with a repeated null check
+    let fields: Vec<_> = (0..20)
+        .map(|i| format!("lower(bid.url) = 'url-{}' as url_{}", i, i))
+        .collect();
+    let sql = format!(
+        "SELECT {} FROM nexmark WHERE bid IS NOT NULL AND bid.price > 10 AND bid IS NOT NULL",
+        fields.join(", ")
+    );
+    let (program, _) = parse_and_get_program(&sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    let code = program
+        .graph
+        .node_weights()
+        .filter_map(|n| match &n.operator {
+            Operator::ExpressionOperator { expression, .. } => Some(expression.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // each is computed once, rather than once per use
+    assert_eq!(code.matches("to_lowercase").count(), 1, "{}", code);
+    assert_eq!(code.matches("is_some").count(), 1, "{}", code);
+}
+
+#[tokio::test]
+async fn test_network_functions() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "
+    SELECT ip_version(bid.url), normalize_ip(bid.url), cidr_contains('10.0.0.0/8', bid.url),
+        geoip_country(bid.url), geoip_asn(bid.url), geoip_asn_org(bid.url)
+    FROM nexmark";
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_crypto_functions() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "
+    SELECT encode(sha256(bid.url), 'hex'), decode(bid.extra, 'base64'),
+        encode(hmac_sha256(bid.url, 'url_key'), 'base64'),
+        aes_gcm_decrypt(aes_gcm_encrypt(bid.extra, 'extra_key'), 'extra_key')
+    FROM nexmark";
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_crypto_function_key_must_be_literal() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT hmac_sha256(bid.url, bid.extra) FROM nexmark";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("must be a string literal"));
+}
+
+#[tokio::test]
+async fn test_crypto_function_key_must_name_secret() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT hmac_sha256(bid.url, '../url_key') FROM nexmark";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid key name '../url_key'"));
+}
+
+fn masking_policy(column: &str, sink: Option<&str>, action: MaskingAction) -> MaskingPolicy {
+    MaskingPolicy {
+        name: format!("mask_{}", column),
+        column: column.to_string(),
+        sink: sink.map(|s| s.to_string()),
+        action,
+    }
+}
+
+#[tokio::test]
+async fn test_masking_policies() {
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider.add_masking_policy(masking_policy("url", None, MaskingAction::Hash));
+    schema_provider.add_masking_policy(masking_policy(
+        "extra",
+        Some("web"),
+        MaskingAction::Truncate { length: 4 },
+    ));
+    schema_provider.add_masking_policy(masking_policy("auction", None, MaskingAction::Redact));
+    schema_provider.add_masking_policy(masking_policy(
+        "bidder",
+        Some("other"),
+        MaskingAction::Redact,
+    ));
+
+    let sql = "
+    SELECT bid.url as url, bid.extra as extra, bid.auction as auction, bid.bidder as bidder
+    FROM nexmark";
+    let (program, _) = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    let expressions: Vec<_> = program
+        .graph
+        .node_weights()
+        .filter_map(|n| match &n.operator {
+            Operator::ExpressionOperator { expression, .. } => Some(expression.clone()),
+            _ => None,
+        })
+        .collect();
+    assert!(expressions.iter().any(|e| e.contains("hex_encode")));
+
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider.add_masking_policy(masking_policy(
+        "auction",
+        None,
+        MaskingAction::Truncate { length: 4 },
+    ));
+    let sql = "SELECT bid.auction as auction FROM nexmark";
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("only text columns can be truncated"));
+}
+
+#[derive(Debug)]
+struct DoubleIt;
+
+impl ScalarFunctionExtension for DoubleIt {
+    fn name(&self) -> &str {
+        "double_it"
+    }
+
+    fn arg_types(&self) -> Vec<DataType> {
+        vec![DataType::Int64]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Int64
+    }
+
+    fn implement(&self, _: Vec<Expr>) -> Result<FunctionImplementation> {
+        Ok(FunctionImplementation::Rust("__0 * 2".to_string()))
+    }
+}
+
+#[derive(Debug)]
+struct IsHigh;
+
+impl ScalarFunctionExtension for IsHigh {
+    fn name(&self) -> &str {
+        "is_high"
+    }
+
+    fn arg_types(&self) -> Vec<DataType> {
+        vec![DataType::Int64]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Boolean
+    }
+
+    fn implement(&self, mut args: Vec<Expr>) -> Result<FunctionImplementation> {
+        Ok(FunctionImplementation::Rewrite(
+            args.remove(0).gt(lit(1000i64)),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test_function_extensions() {
+    extensions::register(DoubleIt);
+    extensions::register(IsHigh);
+
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT double_it(bid.auction), is_high(bid.auction) FROM nexmark";
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_quoted_identifiers() {
+    let schema_provider = get_test_schema_provider();
+    let sql = r#"CREATE TABLE "user-events" (
+        "user-id" TEXT,
+        user_id TEXT,
+        "userId" TEXT,
+        "type" TEXT,
+        "1st seen" BIGINT
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json'
+      );
+      SELECT "user-id", user_id, "userId", "type", "1st seen" FROM "user-events""#;
+    let (program, _) = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    // fields whose names aren't valid Rust identifiers are still (de)serialized by their names
+    let defs = program.other_defs.join("\n");
+    assert!(defs.contains(r#"rename = "user-id""#), "{}", defs);
+    assert!(defs.contains(r#"rename = "1st seen""#), "{}", defs);
+}
+
+#[tokio::test]
+async fn test_source_field_pruning() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE orders (
+        customer_id BIGINT,
+        notes TEXT,
+        shipping_address TEXT
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json'
+      );
+      SELECT customer_id FROM orders WHERE notes IS NOT NULL";
+    let (program, _) = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    // only the fields the query reads are deserialized from the source
+    let defs = program.other_defs.join("\n");
+    assert!(defs.contains("customer_id"), "{}", defs);
+    assert!(defs.contains("notes"), "{}", defs);
+    assert!(!defs.contains("shipping_address"), "{}", defs);
+}
+
+#[tokio::test]
+async fn test_identifier_case() {
+    let sql = "CREATE TABLE events (
+        userId TEXT,
+        \"eventType\" TEXT
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json'
+      );";
+
+    // by default, unquoted identifiers are folded to lowercase
+    let query = format!("{} SELECT USERID, \"eventType\" FROM Events", sql);
+    parse_and_get_program(&query, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let query = format!("{} SELECT eventType FROM events", sql);
+    parse_and_get_program(&query, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+
+    // or they can be matched as written
+    let config = SqlConfig {
+        preserve_identifier_case: true,
+        ..Default::default()
+    };
+
+    let query = format!("{} SELECT userId, eventType FROM events", sql);
+    parse_and_get_program(&query, get_test_schema_provider(), config.clone())
+        .await
+        .unwrap();
+
+    let query = format!("{} SELECT userid FROM events", sql);
+    parse_and_get_program(&query, get_test_schema_provider(), config)
+        .await
+        .unwrap_err();
+}
+
+#[test]
+fn test_rust_field_names() {
+    assert_eq!(rust_field_name("user_id"), "user_id");
+    assert_eq!(rust_field_name("userId"), "userId");
+    assert_eq!(rust_field_name("type"), "type");
+    assert_eq!(rust_field_name("user-id"), rust_field_name("user-id"));
+
+    let names = [
+        "user-id", "user_id", "user.id", "user id", "1st", "self", "_", "",
+    ];
+    let idents: HashSet<_> = names
+        .iter()
+        .map(|name| {
+            StructField::new(
+                name.to_string(),
+                None,
+                TypeDef::DataType(DataType::Utf8, false),
+            )
+            .field_ident()
+            .to_string()
+        })
+        .collect();
+    assert_eq!(names.len(), idents.len());
+}
+
+#[tokio::test]
+async fn test_permissive_source() {
+    let sql = "CREATE TABLE orders (
+        id bigint NOT NULL,
+        customer text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json',
+        bad_data = 'permissive'
+      );
+      SELECT id, _corrupt_record FROM orders WHERE _corrupt_record IS NOT NULL";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "CREATE TABLE orders (
+        value text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'raw_string',
+        bad_data = 'permissive'
+      );
+      SELECT * FROM orders";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("only supported for tables with format 'json'"));
+}
+
+#[tokio::test]
+async fn test_lineage_source() {
+    let sql = "CREATE TABLE orders (
+        id bigint NOT NULL,
+        customer text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json',
+        lineage = 'true'
+      );
+      SELECT id, _lineage_source, _lineage_partition, _lineage_offset FROM orders";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "CREATE TABLE orders (
+        value text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'raw_string',
+        lineage = 'true'
+      );
+      SELECT * FROM orders";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("lineage is only supported for tables with format 'json'"));
+
+    let sql = "CREATE TABLE events (
+        id bigint
+      ) WITH (
+        connector = 'sse',
+        endpoint = 'http://localhost:9000',
+        format = 'json',
+        lineage = 'true'
+      );
+      SELECT * FROM events";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("the 'sse' connector does not support lineage"));
+}
+
+#[tokio::test]
+async fn test_reference_table_join() {
+    let sql = |join: &str| {
+        format!(
+            "CREATE TABLE orders (
+            id bigint,
+            currency text,
+            amount double
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'source',
+            topic = 'orders',
+            format = 'json'
+          );
+          CREATE TABLE rates (
+            currency text,
+            rate double
+          ) WITH (
+            connector = 'reference',
+            location = 's3://my-bucket/rates.json',
+            refresh_interval = '1 minute'
+          );
+          SELECT o.id, o.amount * r.rate as usd FROM orders o {} rates r ON o.currency = r.currency",
+            join
+        )
+    };
+
+    let (program, _) = parse_and_get_program(
+        &sql("JOIN"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let lookup = program
+        .graph
+        .node_weights()
+        .find_map(|n| match &n.operator {
+            Operator::LookupJoin(lookup) => Some(lookup.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!("s3://my-bucket/rates.json", lookup.location);
+    assert_eq!(Duration::from_secs(60), lookup.refresh_interval);
+    assert!(lookup.inner);
+
+    // the stream is joined where it is, rather than being shuffled to the table
+    assert!(program
+        .graph
+        .edge_weights()
+        .all(|e| e.typ != EdgeType::Shuffle));
+
+    parse_and_get_program(
+        &sql("LEFT JOIN"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    parse_and_get_program(
+        &sql("FULL OUTER JOIN"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_sink_routing() {
+    let sql = |route: &str| {
+        format!(
+            "CREATE TABLE events (
+            tenant text,
+            value bigint
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = 'sink',
+            topic = 'events',
+            format = 'json',
+            {}
+          );
+          INSERT INTO events SELECT bid.url as tenant, bid.price as value FROM nexmark",
+            route
+        )
+    };
+
+    let (program, _) = parse_and_get_program(
+        &sql("route = 'concat(''events-'', tenant)'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let router = program
+        .graph
+        .node_weights()
+        .find_map(|n| match &n.operator {
+            Operator::ConnectorSink(c) => Some(c.router.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert!(router.unwrap().contains("events-"));
+
+    // routes must be text
+    parse_and_get_program(
+        &sql("route = 'value + 1'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+
+    // and can only refer to fields that are written to the sink
+    parse_and_get_program(
+        &sql("route = 'concat(''events-'', region)'"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_connection_pool_options() {
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider.add_saved_connection(
+        "orders_db",
+        "mongodb",
+        r#"{"connectionString": "mongodb://localhost:27017"}"#,
+    );
+
+    let sql = |options: &str| {
+        format!(
+            "CREATE TABLE orders_sink (
+            id bigint
+          ) WITH (
+            {},
+            type = 'sink',
+            database = 'shop',
+            collection = 'orders',
+            format = 'json'
+          );
+          INSERT INTO orders_sink SELECT bid.auction FROM nexmark",
+            options
+        )
+    };
+
+    let pool = |options: &str| {
+        let schema_provider = schema_provider.clone();
+        let sql = sql(options);
+        async move {
+            let (program, _) = parse_and_get_program(&sql, schema_provider, SqlConfig::default())
+                .await
+                .unwrap();
+            let config = program
+                .graph
+                .node_weights()
+                .find_map(|n| match &n.operator {
+                    Operator::ConnectorSink(c) => Some(c.config.clone()),
+                    _ => None,
+                })
+                .unwrap();
+            let config: serde_json::Value = serde_json::from_str(&config).unwrap();
+            config["connection_pool"].clone()
+        }
+    };
+
+    // tables that use a saved connection share a pool named after it
+    assert_eq!(
+        serde_json::json!({"name": "orders_db", "max_connections": 20}),
+        pool("connection = 'orders_db', 'connection_pool.max_connections' = '20'").await
+    );
+
+    assert_eq!(
+        serde_json::json!({"name": "shop", "max_connections": 5}),
+        pool(
+            "connector = 'mongodb', connection_string = 'mongodb://localhost:27017', \
+            'connection_pool.name' = 'shop', 'connection_pool.max_connections' = '5'"
+        )
+        .await
+    );
+
+    assert_eq!(
+        serde_json::Value::Null,
+        pool("connection = 'orders_db'").await
+    );
+
+    // without a saved connection, the pool must be named
+    parse_and_get_program(
+        &sql(
+            "connector = 'mongodb', connection_string = 'mongodb://localhost:27017', \
+            'connection_pool.max_connections' = '5'",
+        ),
+        schema_provider.clone(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+
+    parse_and_get_program(
+        &sql("connection = 'orders_db', 'connection_pool.max_connections' = '0'"),
+        schema_provider.clone(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_import_flink_sql() {
+    let flink = "
+    CREATE TABLE orders (
+        order_id BIGINT,
+        customer STRING NOT NULL,
+        amount DECIMAL(10, 2),
+        tags MAP<STRING, STRING>,
+        `ts` TIMESTAMP(3),
+        proc_time AS PROCTIME(),
+        WATERMARK FOR `ts` AS `ts` - INTERVAL '5' SECOND
+    ) WITH (
+        'connector' = 'kafka',
+        'topic' = 'orders',
+        'properties.bootstrap.servers' = 'broker:9092',
+        'properties.group.id' = 'orders-job',
+        'scan.startup.mode' = 'earliest-offset',
+        'format' = 'json'
+    );
+
+    CREATE TABLE totals (
+        customer STRING,
+        total DECIMAL(10, 2)
+    ) WITH (
+        'connector' = 'kafka',
+        'topic' = 'totals',
+        'properties.bootstrap.servers' = 'broker:9092',
+        'format' = 'json'
+    );
+
+    SET 'pipeline.name' = 'totals';
+
+    INSERT INTO totals
+    SELECT customer, SUM(amount) AS total
+    FROM orders
+    GROUP BY TUMBLE(`ts`, INTERVAL '1' MINUTE), customer;
+    ";
+
+    let imported = crate::import::import(crate::import::ImportDialect::FlinkSql, flink).unwrap();
+
+    assert_eq!(1, imported.connections.len());
+    assert_eq!("kafka", imported.connections[0].name);
+    assert!(imported.query.contains("type = 'sink'"));
+    assert!(imported.query.contains("source.offset = 'earliest'"));
+    assert!(imported.query.contains("event_time_field = 'ts'"));
+    assert!(imported.query.contains("tumble(INTERVAL '1' MINUTE)"));
+
+    let unsupported = imported.unsupported.join("\n");
+    assert!(unsupported.contains("tags"), "{}", unsupported);
+    assert!(unsupported.contains("proc_time"), "{}", unsupported);
+    assert!(
+        unsupported.contains("properties.group.id"),
+        "{}",
+        unsupported
+    );
+    assert!(unsupported.contains("SET"), "{}", unsupported);
+    assert!(!unsupported.contains("invalid"), "{}", unsupported);
+
+    let mut schema_provider = ArroyoSchemaProvider::new();
+    for c in &imported.connections {
+        schema_provider.add_saved_connection(&c.name, &c.connector, &c.config);
+    }
+    parse_and_get_program(&imported.query, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    // unsupported statements are reported rather than translated
+    let imported = crate::import::import(
+        crate::import::ImportDialect::FlinkSql,
+        "CREATE TABLE files (line STRING) WITH ('connector' = 'filesystem', 'path' = '/tmp')",
+    )
+    .unwrap();
+    assert_eq!("", imported.query);
+    assert!(imported.unsupported[0].contains("filesystem"));
+}
+
+#[tokio::test]
+async fn test_import_ksql() {
+    let ksql = "
+    CREATE STREAM pageviews (
+        user_id VARCHAR KEY,
+        page VARCHAR,
+        viewtime TIMESTAMP
+    ) WITH (KAFKA_TOPIC = 'pageviews', VALUE_FORMAT = 'JSON', TIMESTAMP = 'viewtime');
+
+    CREATE TABLE views_per_page WITH (KAFKA_TOPIC = 'views_per_page') AS
+    SELECT page, COUNT(*) AS views
+    FROM pageviews
+    WINDOW HOPPING (SIZE 10 MINUTES, ADVANCE BY 1 MINUTE)
+    GROUP BY page
+    EMIT CHANGES;
+
+    CREATE STREAM session_starts AS
+    SELECT page FROM pageviews WINDOW SESSION (30 MINUTES) GROUP BY page;
+    ";
+
+    let imported = crate::import::import(crate::import::ImportDialect::KsqlDb, ksql).unwrap();
+
+    assert!(imported
+        .query
+        .contains("hop(INTERVAL '1 minute', INTERVAL '10 minute'), page"));
+    assert!(imported.query.contains("topic = 'views_per_page'"));
+    assert!(imported.query.contains("views BIGINT"));
+    assert!(!imported.query.contains("EMIT"));
+
+    let unsupported = imported.unsupported.join("\n");
+    assert!(unsupported.contains("user_id"), "{}", unsupported);
+    assert!(unsupported.contains("session windows"), "{}", unsupported);
+    assert!(!unsupported.contains("invalid"), "{}", unsupported);
+
+    let mut schema_provider = ArroyoSchemaProvider::new();
+    for c in &imported.connections {
+        schema_provider.add_saved_connection(&c.name, &c.connector, &c.config);
+    }
+    parse_and_get_program(&imported.query, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_avro_source() {
+    let sql = "CREATE TABLE orders (
+        id bigint NOT NULL,
+        customer text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'avro',
+        'schema_registry.endpoint' = 'http://localhost:8081'
+      );
+      SELECT id, customer FROM orders";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "CREATE TABLE orders (
+        id bigint NOT NULL
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'avro'
+      );
+      SELECT * FROM orders";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("a schema registry must be configured"));
+
+    let sql = "CREATE TABLE events (
+        id bigint
+      ) WITH (
+        connector = 'sse',
+        endpoint = 'http://localhost:9000',
+        format = 'avro'
+      );
+      SELECT * FROM events";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("the 'sse' connector does not support the avro format"));
+}
+
+#[tokio::test]
+async fn test_kafka_header_columns() {
+    let sql = "CREATE TABLE events (
+        id bigint NOT NULL,
+        tenant_id text,
+        trace_id bytea
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json',
+        header_columns = 'tenant_id: x-tenant-id',
+        binary_header_columns = 'trace_id: x-trace-id'
+      );
+      SELECT id, tenant_id, trace_id FROM events";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "CREATE TABLE events (
+        id bigint NOT NULL,
+        trace_id bytea
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json',
+        header_columns = 'trace_id: x-trace-id'
+      );
+      SELECT * FROM events";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("header column 'trace_id' must be TEXT"));
+}
+
+#[tokio::test]
+async fn test_protobuf_source() {
+    let table = |columns: &str, typ: &str| {
+        format!(
+            "CREATE TABLE orders (
+            {}
+          ) WITH (
+            connector = 'kafka',
+            bootstrap_servers = 'localhost:9092',
+            type = '{}',
+            topic = 'orders',
+            format = 'protobuf',
+            'format_options.protobuf_message' = 'com.example.Order',
+            'format_options.protobuf_schema' = '
+                syntax = \"proto3\";
+                package com.example;
+                import \"google/protobuf/timestamp.proto\";
+                message Order {{
+                    int64 id = 1;
+                    optional string customer = 2;
+                    google.protobuf.Timestamp created = 3;
+                }}'
+          );",
+            columns, typ
+        )
+    };
+
+    let sql = format!(
+        "{}
+        SELECT id, customer, created FROM orders",
+        table(
+            "id bigint NOT NULL, customer text, created timestamp",
+            "source"
+        )
+    );
+    parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = format!(
+        "{}
+        SELECT * FROM orders",
+        table("id text", "source")
+    );
+    let err = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("column 'id' has type Utf8, but field 'id' of com.example.Order has type Int64"));
+
+    let sql = format!(
+        "{}
+        INSERT INTO orders SELECT 1",
+        table("id bigint NOT NULL", "sink")
+    );
+    let err = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("protobuf is only supported for kafka sources"));
+
+    let sql = "CREATE TABLE events (
+        id bigint
+      ) WITH (
+        connector = 'sse',
+        endpoint = 'http://localhost:9000',
+        format = 'protobuf'
+      );
+      SELECT * FROM events";
+    let err = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("the 'sse' connector does not support the protobuf format"));
+}
+
+#[tokio::test]
+async fn test_csv() {
+    let table = |connector: &str, options: &str| {
+        format!(
+            "CREATE TABLE orders (
+            id bigint NOT NULL,
+            customer text,
+            created timestamp
+          ) WITH (
+            connector = '{}',
+            {}
+            format = 'csv',
+            'format_options.csv_delimiter' = ';',
+            'format_options.csv_has_header' = 'true'
+          );",
+            connector, options
+        )
+    };
+    let kafka = |typ: &str| {
+        table(
+            "kafka",
+            &format!(
+                "bootstrap_servers = 'localhost:9092', type = '{}', topic = 'orders',",
+                typ
+            ),
+        )
+    };
+
+    let sql = format!(
+        "{}
+        SELECT id, customer, created FROM orders",
+        kafka("source")
+    );
+    parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = format!(
+        "{}
+        INSERT INTO orders SELECT 1, 'acme', CAST('2023-07-22 04:26:40' AS TIMESTAMP)",
+        kafka("sink")
+    );
+    parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = format!(
+        "{}
+        INSERT INTO orders SELECT 1, 'acme', CAST('2023-07-22 04:26:40' AS TIMESTAMP)",
+        table("filesystem", "path = 'file:///tmp/orders',")
+    );
+    parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = format!(
+        "{}
+        SELECT * FROM orders",
+        table(
+            "kafka",
+            "bootstrap_servers = 'localhost:9092', type = 'source', topic = 'orders',
+            'format_options.csv_quote' = '||',"
+        )
+    );
+    let err = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("csv quote must be a single ASCII character"));
+
+    let sql = format!(
+        "{}
+        SELECT id, customer, created FROM orders",
+        table("sse", "endpoint = 'http://localhost:9091/sse',")
+    );
+    parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = format!(
+        "{}
+        INSERT INTO orders SELECT 1, 'acme', CAST('2023-07-22 04:26:40' AS TIMESTAMP)",
+        table("webhook", "endpoint = 'http://localhost:9091/orders',")
+    );
+    let err = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("the 'webhook' connector does not support the csv format"));
+}
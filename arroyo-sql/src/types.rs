@@ -22,7 +22,6 @@ use datafusion::sql::sqlparser::ast::{DataType as SQLDataType, ExactNumberInfo,
 use datafusion_common::ScalarValue;
 use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote};
-use regex::Regex;
 use syn::PathArguments::AngleBracketed;
 use syn::{parse_quote, parse_str, GenericArgument, Type};
 
@@ -304,6 +303,37 @@ impl StructField {
     }
 }
 
+/// Identifiers that are reserved in Rust and can't be used as raw identifiers either
+const NON_RAW_IDENTS: &[&str] = &["_", "crate", "self", "Self", "super"];
+
+/// Converts a SQL identifier, which may contain any character (e.g., `"user-id"`), into the name
+/// of a Rust field. Names that are already valid Rust identifiers are kept as they are (keywords
+/// are used as raw identifiers). Other names have their invalid characters replaced with `_` and
+/// a hash of the SQL name appended, so that distinct SQL names (like `user-id` and `user_id`) never
+/// map to the same field, and the same name always maps to the same field.
+pub fn rust_field_name(name: &str) -> String {
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !NON_RAW_IDENTS.contains(&name);
+
+    if valid {
+        return name.to_string();
+    }
+
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    // FNV-1a, which unlike the std hashers is guaranteed to be the same across Rust versions
+    let hash = name.bytes().fold(0x811c9dc5u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x01000193)
+    });
+
+    format!("_{}_{:08x}", sanitized.trim_matches('_'), hash)
+}
+
 /* this returns a duration with the same length as the postgres interval. */
 pub fn interval_month_day_nanos_to_duration(serialized_value: i128) -> Duration {
     let (month, day, nanos) = IntervalMonthDayNanoType::to_parts(serialized_value);
@@ -540,9 +570,9 @@ impl StructField {
         self.name.to_string()
     }
 
+    /// The name of the field in generated structs; see [`rust_field_name`]
     pub fn field_name(&self) -> String {
-        let re = Regex::new("[^a-zA-Z0-9_]").unwrap();
-        re.replace_all(&self.qualified_name(), "_").to_string()
+        rust_field_name(&self.qualified_name())
     }
 
     pub fn qualified_name(&self) -> String {
@@ -557,32 +587,46 @@ impl StructField {
     }
 
     pub fn field_ident(&self) -> Ident {
-        match parse_str(&self.field_name()) {
+        let field_name = self.field_name();
+        match parse_str(&field_name) {
             Ok(ident) => ident,
-            Err(_) => {
-                format_ident!("r#{}", self.field_name())
-            }
+            // keywords that aren't in NON_RAW_IDENTS
+            Err(_) => format_ident!("r#{}", field_name),
         }
     }
 
+    /// The name the field is (de)serialized with, if it isn't the name of its Rust field
+    pub fn serde_rename(&self) -> Option<String> {
+        if self.renamed_from.is_some() {
+            return self.renamed_from.clone();
+        }
+
+        (self.alias.is_none() && self.field_name() != self.name).then(|| self.name.clone())
+    }
+
     fn def(&self) -> TokenStream {
         let name: Ident = self.field_ident();
         let type_string = self.get_type();
+        let rename = self
+            .serde_rename()
+            .map(|rename| quote!(#[serde(rename = #rename)]));
         // special case time fields
         if let TypeDef::DataType(DataType::Timestamp(_, _), nullable) = self.data_type {
             if nullable {
                 return quote!(
+                #rename
                 #[serde(default)]
                 #[serde(deserialize_with = "arroyo_worker::deserialize_rfc3339_datetime_opt")]
                 pub #name: #type_string
                 );
             } else {
                 return quote!(
+                #rename
                 #[serde(deserialize_with = "arroyo_worker::deserialize_rfc3339_datetime")]
                 pub #name: #type_string);
             }
         }
-        quote!(pub #name: #type_string)
+        quote!(#rename pub #name: #type_string)
     }
 
     pub fn get_type(&self) -> Type {
@@ -649,7 +693,7 @@ impl StructField {
             DataType::Time64(_) => todo!(),
             DataType::Duration(_) => todo!(),
             DataType::Interval(_) => todo!(),
-            DataType::Binary => todo!(),
+            DataType::Binary => quote!(arrow::datatypes::DataType::Binary),
             DataType::FixedSizeBinary(_) => todo!(),
             DataType::LargeBinary => todo!(),
             DataType::Utf8 => quote!(arrow::datatypes::DataType::Utf8),
@@ -756,7 +800,7 @@ impl StructField {
             DataType::Time32(_) => todo!(),
             DataType::Time64(_) => todo!(),
             DataType::Duration(_) | DataType::Interval(_) => "std::time::Duration".to_string(),
-            DataType::Binary => todo!(),
+            DataType::Binary => "Vec<u8>".to_string(),
             DataType::FixedSizeBinary(_) => todo!(),
             DataType::LargeBinary => todo!(),
             DataType::Utf8 => "String".to_string(),
@@ -928,7 +972,7 @@ impl StructField {
     }
 
     pub fn get_return_expression(&self, parent_ident: TokenStream) -> TokenStream {
-        let ident: Ident = parse_str(&self.field_name()).unwrap();
+        let ident = self.field_ident();
         quote!(#parent_ident.#ident.clone())
     }
 
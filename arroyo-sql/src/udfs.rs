@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_types::UdfSandbox;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{FnArg, ItemFn, ReturnType};
+
+use crate::UdfDef;
+
+/// The definitions of the query's UDFs: the `udfs` module for the pipeline, and the definitions to
+/// compile into its WebAssembly module, if any.
+///
+/// UDFs are normally defined in the pipeline itself. When they're sandboxed, their bodies are
+/// instead compiled into the WebAssembly module, each behind an export that decodes its arguments
+/// and encodes its result; the pipeline's `udfs` module then has a function with the same signature
+/// as each UDF that calls its export in the sandbox (see `arroyo_worker::udfs`).
+///
+/// `items` are the other items defined with the UDFs, like process functions and their types,
+/// which always run in the pipeline.
+pub(crate) fn udf_defs(
+    defs: &HashMap<String, UdfDef>,
+    items: &[String],
+    sandbox: Option<UdfSandbox>,
+) -> Result<(String, Vec<String>)> {
+    let mut defs: Vec<_> = defs.iter().collect();
+    defs.sort_by_key(|(name, _)| *name);
+
+    let bodies = defs
+        .iter()
+        .map(|(_, def)| def.def.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let Some(sandbox) = sandbox else {
+        let items = items.join("\n\n");
+        return Ok((format!("mod udfs {{ {}\n\n{} }}", bodies, items), vec![]));
+    };
+
+    if !items.is_empty() {
+        bail!("process functions and other types can't be defined when UDFs are sandboxed");
+    }
+
+    let mut shims = vec![];
+    let mut exports = vec![];
+    for (name, def) in defs {
+        let function: ItemFn = syn::parse_str(&def.def)
+            .map_err(|e| anyhow!("failed to parse UDF '{}': {}", name, e))?;
+        let (shim, export) = sandboxed(&function);
+        shims.push(shim);
+        exports.push(export);
+    }
+
+    let memory_bytes = sandbox.memory_bytes;
+    let fuel = sandbox.fuel;
+    let pipeline_def = quote! {
+        mod udfs {
+            const SANDBOX: arroyo_types::UdfSandbox = arroyo_types::UdfSandbox {
+                memory_bytes: #memory_bytes,
+                fuel: #fuel,
+            };
+
+            #(#shims)*
+        }
+    };
+
+    let wasm_def = format!("mod udfs {{ {} }}", bodies);
+
+    Ok((
+        pipeline_def.to_string(),
+        vec![wasm_def, quote!(#(#exports)*).to_string()],
+    ))
+}
+
+/// The function that calls the UDF in the sandbox, and the export that it calls
+fn sandboxed(function: &ItemFn) -> (TokenStream, TokenStream) {
+    let name = &function.sig.ident;
+    let export = format_ident!("__udf_{}", name);
+    let export_name = export.to_string();
+
+    let types: Vec<_> = function
+        .sig
+        .inputs
+        .iter()
+        .map(|arg| match arg {
+            FnArg::Typed(t) => &t.ty,
+            FnArg::Receiver(_) => unreachable!("self types are rejected when UDFs are added"),
+        })
+        .collect();
+    let args: Vec<_> = (0..types.len())
+        .map(|i| format_ident!("arg_{}", i))
+        .collect();
+
+    let ret = match &function.sig.output {
+        ReturnType::Type(_, t) => t,
+        ReturnType::Default => unreachable!("return types are required when UDFs are added"),
+    };
+
+    let shim = quote! {
+        pub fn #name(#(#args: #types),*) -> #ret {
+            arroyo_worker::udfs::call(&SANDBOX, #export_name, (#(#args,)*))
+        }
+    };
+
+    let export = quote! {
+        #[no_mangle]
+        pub extern "C" fn #export(len: u32) {
+            let (#(#args,)*): (#(#types,)*) = get_value(len);
+            send_value(udfs::#name(#(#args),*));
+        }
+    };
+
+    (shim, export)
+}
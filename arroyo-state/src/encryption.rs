@@ -0,0 +1,253 @@
+//! Envelope encryption of state files. Each file is encrypted with AES-256-GCM under a data key
+//! generated by KMS; the data key, encrypted by the KMS key, is stored in the file's header, so
+//! decrypting a file only requires access to the KMS key that it was written with.
+//!
+//! Data keys are reused for an hour, rather than generated for every file, to avoid a KMS request
+//! for each table of each subtask on every checkpoint.
+
+use aes_gcm::aead::{Aead, OsRng, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit, Nonce};
+use anyhow::{anyhow, bail, Result};
+use arroyo_types::S3_REGION_ENV;
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use rusoto_core::Region;
+use rusoto_kms::{DecryptRequest, GenerateDataKeyRequest, Kms, KmsClient};
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"ARYE";
+const VERSION: u8 = 1;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+const DATA_KEY_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+struct DataKey {
+    key: Vec<u8>,
+    encrypted_key: Vec<u8>,
+    key_arn: String,
+    created: Instant,
+}
+
+// the current data key for each KMS key that files are encrypted with
+static DATA_KEYS: Lazy<Mutex<HashMap<String, DataKey>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// data keys that have been decrypted, by their encrypted form
+static DECRYPTED_KEYS: Lazy<Mutex<HashMap<Vec<u8>, Vec<u8>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// KMS keys are regional, so use the region from the key's ARN if it has one
+fn kms_client(key_id: &str) -> KmsClient {
+    let region = key_id
+        .strip_prefix("arn:")
+        .and_then(|arn| arn.split(':').nth(2))
+        .map(|region| region.to_string())
+        .or_else(|| env::var(S3_REGION_ENV).ok())
+        .and_then(|region| Region::from_str(&region).ok())
+        .unwrap_or_default();
+
+    KmsClient::new(region)
+}
+
+async fn data_key(kms_key_id: &str) -> Result<DataKey> {
+    if let Some(key) = DATA_KEYS.lock().unwrap().get(kms_key_id) {
+        if key.created.elapsed() < DATA_KEY_TTL {
+            return Ok(key.clone());
+        }
+    }
+
+    let response = kms_client(kms_key_id)
+        .generate_data_key(GenerateDataKeyRequest {
+            key_id: kms_key_id.to_string(),
+            key_spec: Some("AES_256".to_string()),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "failed to generate data key with KMS key {}: {}",
+                kms_key_id,
+                e
+            )
+        })?;
+
+    let (Some(key), Some(encrypted_key)) = (response.plaintext, response.ciphertext_blob) else {
+        bail!("KMS did not return a data key for {}", kms_key_id);
+    };
+
+    let key = DataKey {
+        key: key.to_vec(),
+        encrypted_key: encrypted_key.to_vec(),
+        key_arn: response.key_id.unwrap_or_else(|| kms_key_id.to_string()),
+        created: Instant::now(),
+    };
+
+    DECRYPTED_KEYS
+        .lock()
+        .unwrap()
+        .insert(key.encrypted_key.clone(), key.key.clone());
+    DATA_KEYS
+        .lock()
+        .unwrap()
+        .insert(kms_key_id.to_string(), key.clone());
+
+    Ok(key)
+}
+
+async fn decrypt_data_key(key_id: &str, encrypted_key: &[u8]) -> Result<Vec<u8>> {
+    if let Some(key) = DECRYPTED_KEYS.lock().unwrap().get(encrypted_key) {
+        return Ok(key.clone());
+    }
+
+    let response = kms_client(key_id)
+        .decrypt(DecryptRequest {
+            ciphertext_blob: Bytes::copy_from_slice(encrypted_key),
+            key_id: Some(key_id.to_string()),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow!("failed to decrypt data key with KMS key {}: {}", key_id, e))?;
+
+    let key = response
+        .plaintext
+        .ok_or_else(|| anyhow!("KMS did not return the decrypted data key"))?
+        .to_vec();
+
+    DECRYPTED_KEYS
+        .lock()
+        .unwrap()
+        .insert(encrypted_key.to_vec(), key.clone());
+
+    Ok(key)
+}
+
+/// Encrypts a state file with a data key of the KMS key `kms_key_id`, returning the encrypted file
+/// and the ARN of the KMS key, which is needed to decrypt it
+pub async fn encrypt(kms_key_id: &str, data: Vec<u8>) -> Result<(Vec<u8>, String)> {
+    let key = data_key(kms_key_id).await?;
+    let encrypted = seal(&key.key, &key.encrypted_key, &data)?;
+    Ok((encrypted, key.key_arn))
+}
+
+/// Decrypts a state file written by [`encrypt`] with the KMS key `key_id`
+pub async fn decrypt(key_id: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+    let (encrypted_key, _) = parse_header(&data)?;
+    let key = decrypt_data_key(key_id, encrypted_key).await?;
+    open(&key, &data)
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256Gcm> {
+    if key.len() != KEY_LEN {
+        bail!("invalid data key length {}", key.len());
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)))
+}
+
+// files are laid out as the magic bytes, the format version, the length of the encrypted data key
+// as a big-endian u16, the encrypted data key, the nonce and then the ciphertext. Everything before
+// the ciphertext is authenticated along with it.
+fn seal(key: &[u8], encrypted_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key_len = u16::try_from(encrypted_key.len())
+        .map_err(|_| anyhow!("encrypted data key is too long"))?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let mut result =
+        Vec::with_capacity(MAGIC.len() + 3 + encrypted_key.len() + NONCE_LEN + data.len() + 16);
+    result.extend_from_slice(MAGIC);
+    result.push(VERSION);
+    result.extend_from_slice(&key_len.to_be_bytes());
+    result.extend_from_slice(encrypted_key);
+    result.extend_from_slice(&nonce);
+
+    let ciphertext = cipher(key)?
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: data,
+                aad: &result,
+            },
+        )
+        .map_err(|_| anyhow!("failed to encrypt state file"))?;
+    result.extend_from_slice(&ciphertext);
+
+    Ok(result)
+}
+
+// returns the encrypted data key and the length of the header
+fn parse_header(data: &[u8]) -> Result<(&[u8], usize)> {
+    if data.len() < MAGIC.len() + 3 || &data[..MAGIC.len()] != MAGIC {
+        bail!("state file is not encrypted");
+    }
+    if data[MAGIC.len()] != VERSION {
+        bail!("unsupported encryption version {}", data[MAGIC.len()]);
+    }
+
+    let key_start = MAGIC.len() + 3;
+    let key_len = u16::from_be_bytes([data[MAGIC.len() + 1], data[MAGIC.len() + 2]]) as usize;
+    let header_len = key_start + key_len + NONCE_LEN;
+    if data.len() < header_len {
+        bail!("encrypted state file is truncated");
+    }
+
+    Ok((&data[key_start..key_start + key_len], header_len))
+}
+
+fn open(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let (_, header_len) = parse_header(data)?;
+    let (header, ciphertext) = data.split_at(header_len);
+    let nonce = Nonce::from_slice(&header[header_len - NONCE_LEN..]);
+
+    cipher(key)?
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| anyhow!("failed to decrypt state file; it may be corrupted"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{open, parse_header, seal};
+
+    #[test]
+    fn test_round_trip() {
+        let key = [7u8; 32];
+        let data = b"some state".to_vec();
+
+        let sealed = seal(&key, b"encrypted key", &data).unwrap();
+        assert!(!sealed.windows(data.len()).any(|w| w == &data[..]));
+        assert_eq!(parse_header(&sealed).unwrap().0, b"encrypted key");
+        assert_eq!(open(&key, &sealed).unwrap(), data);
+
+        // each file gets its own nonce
+        assert_ne!(seal(&key, b"encrypted key", &data).unwrap(), sealed);
+    }
+
+    #[test]
+    fn test_rejects_tampering() {
+        let key = [7u8; 32];
+        let sealed = seal(&key, b"encrypted key", b"some state").unwrap();
+
+        assert!(open(&[8u8; 32], &sealed).is_err());
+
+        let mut modified = sealed.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(open(&key, &modified).is_err());
+
+        // the header is authenticated along with the data
+        let mut modified = sealed.clone();
+        modified[8] ^= 1;
+        assert!(open(&key, &modified).is_err());
+
+        assert!(open(&key, &sealed[..10]).is_err());
+        assert!(open(&key, b"PAR1").is_err());
+    }
+}
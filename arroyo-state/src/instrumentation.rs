@@ -0,0 +1,25 @@
+//! Tracing of state accesses for pipelines compiled with the `instrumentation` feature. Tracing
+//! is off until enabled by the pipeline's generated `main`.
+
+use arroyo_types::TaskInfo;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+static TRACE_STATE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_trace_state(enabled: bool) {
+    TRACE_STATE.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn trace_access(task_info: &TaskInfo, table: char, table_type: &str) {
+    if TRACE_STATE.load(Ordering::Relaxed) {
+        info!(
+            target: "arroyo_state::access",
+            operator_id = task_info.operator_id,
+            subtask_idx = task_info.task_index,
+            table = %table,
+            table_type,
+            "state access"
+        );
+    }
+}
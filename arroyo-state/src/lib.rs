@@ -7,6 +7,7 @@ use arroyo_rpc::ControlResp;
 use arroyo_types::{CheckpointBarrier, Data, Key, TaskInfo};
 use async_trait::async_trait;
 use bincode::config::Configuration;
+use schema::StateMigration;
 use std::any::Any;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
@@ -18,7 +19,11 @@ use tables::{
 };
 use tokio::sync::mpsc::Sender;
 
+mod encryption;
+#[cfg(feature = "instrumentation")]
+pub mod instrumentation;
 pub mod parquet;
+pub mod schema;
 pub mod tables;
 
 pub const BINCODE_CONFIG: Configuration = bincode::config::standard();
@@ -88,6 +93,17 @@ pub trait BackingStore {
         Ok(())
     }
 
+    // copies the checkpoint at `epoch` of another job into the same epoch of `job_id`, so that
+    // it can be restored from. Of the job's `operators`, only those in `restored_operators` keep
+    // their state; the rest are restored without any.
+    async fn fork_checkpoint(
+        from_job_id: &str,
+        epoch: u32,
+        job_id: &str,
+        operators: &[String],
+        restored_operators: &[String],
+    ) -> Result<CheckpointMetadata>;
+
     async fn complete_operator_checkpoint(metadata: OperatorCheckpointMetadata);
 
     async fn complete_checkpoint(metadata: CheckpointMetadata);
@@ -119,6 +135,8 @@ pub trait BackingStore {
 
     async fn get_global_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)>;
     async fn get_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)>;
+
+    fn register_migration(&mut self, table: char, migration: StateMigration);
 }
 
 pub struct StateStore<S: BackingStore> {
@@ -175,6 +193,12 @@ impl<S: BackingStore> StateStore<S> {
         }
     }
 
+    /// Registers a migration for restoring a table that was written with an older value type.
+    /// This must be called before the table is first accessed, e.g., in `on_start`.
+    pub fn register_migration(&mut self, table: char, migration: StateMigration) {
+        self.backend.register_migration(table, migration);
+    }
+
     // We now handle this in the individual tables. Don't love it, but they have different behaviors.
     pub fn handle_watermark(&mut self, _watermark: SystemTime) {}
 
@@ -183,6 +207,9 @@ impl<S: BackingStore> StateStore<S> {
         table: char,
         watermark: Option<SystemTime>,
     ) -> TimeKeyMap<K, V, S> {
+        #[cfg(feature = "instrumentation")]
+        instrumentation::trace_access(&self.task_info, table, "time_key_map");
+
         // this is done because populating it is async, so can't use or_insert().
         if let std::collections::hash_map::Entry::Vacant(e) = self.caches.entry(table) {
             let cache: Box<dyn Any + Send> = match &self.restore_from {
@@ -218,6 +245,9 @@ impl<S: BackingStore> StateStore<S> {
         &mut self,
         table: char,
     ) -> KeyTimeMultiMap<K, V, S> {
+        #[cfg(feature = "instrumentation")]
+        instrumentation::trace_access(&self.task_info, table, "key_time_multi_map");
+
         // this is done because populating it is async, so can't use or_insert().
         if let std::collections::hash_map::Entry::Vacant(e) = self.caches.entry(table) {
             let cache: Box<dyn Any + Send> = match &self.restore_from {
@@ -253,6 +283,9 @@ impl<S: BackingStore> StateStore<S> {
         &mut self,
         table: char,
     ) -> GlobalKeyedState<K, V, S> {
+        #[cfg(feature = "instrumentation")]
+        instrumentation::trace_access(&self.task_info, table, "global_keyed_state");
+
         // this is done because populating it is async, so can't use or_insert().
         if let std::collections::hash_map::Entry::Vacant(e) = self.caches.entry(table) {
             let cache: Box<dyn Any + Send> = match &self.restore_from {
@@ -279,6 +312,9 @@ impl<S: BackingStore> StateStore<S> {
     }
 
     pub async fn get_key_state<K: Key, V: Data>(&mut self, table: char) -> KeyedState<K, V, S> {
+        #[cfg(feature = "instrumentation")]
+        instrumentation::trace_access(&self.task_info, table, "keyed_state");
+
         if let std::collections::hash_map::Entry::Vacant(e) = self.caches.entry(table) {
             let cache: Box<dyn Any + Send> = match &self.restore_from {
                 Some(_restore_from) => {
@@ -437,6 +473,40 @@ mod test {
         );
     }
 
+    #[test_case(parquet_for_test().await; "parquet store")]
+    #[tokio::test]
+    async fn test_key_time_multi_map_expiration(
+        p: (StateStore<impl BackingStore>, Receiver<ControlResp>),
+    ) {
+        let (mut ss, _rx) = p;
+        let mut ks: KeyTimeMultiMap<String, i32, _> = ss.get_key_time_multi_map('t').await;
+
+        let t1 = SystemTime::now();
+        let t2 = t1 + Duration::from_secs(1);
+        let t3 = t1 + Duration::from_secs(2);
+
+        ks.insert(t1, "k1".into(), 1).await;
+        ks.insert(t1, "k1".into(), 2).await;
+        ks.insert(t3, "k1".into(), 3).await;
+        ks.insert(t2, "k2".into(), 4).await;
+        assert_eq!((ks.key_count(), ks.row_count()), (2, 4));
+
+        // k1 keeps its later value
+        assert!(ks.expire_entries_before(t2).is_empty());
+        assert_eq!((ks.key_count(), ks.row_count()), (2, 2));
+
+        assert_eq!(ks.expire_entries_before(t3), vec!["k2".to_string()]);
+        assert_eq!((ks.key_count(), ks.row_count()), (1, 1));
+        assert!(!ks.contains_key(&"k2".into()));
+
+        // expiring again doesn't find the keys that were already removed
+        assert!(ks.expire_entries_before(t3).is_empty());
+
+        ks.clear_time_range(&mut "k1".into(), t1, t3 + Duration::from_secs(1))
+            .await;
+        assert_eq!((ks.key_count(), ks.row_count()), (0, 0));
+    }
+
     #[test_case(parquet_for_test().await; "parquet store")]
     #[tokio::test]
     async fn test_time_key_map(p: (StateStore<impl BackingStore>, Receiver<ControlResp>)) {
@@ -1,24 +1,32 @@
-use crate::{hash_key, BackingStore, BINCODE_CONFIG};
-use anyhow::Result;
+use crate::schema::{table_schema, StateMigration};
+use crate::{encryption, hash_key, BackingStore, BINCODE_CONFIG};
+use anyhow::{anyhow, Result};
 use arrow_array::RecordBatch;
 use arroyo_rpc::grpc::backend_data::BackendData;
 use arroyo_rpc::grpc::{
-    backend_data, CheckpointMetadata, OperatorCheckpointMetadata, ParquetStoreData,
-    SubtaskCheckpointMetadata, TableDeleteBehavior, TableDescriptor, TableType,
+    backend_data, CheckpointMetadata, LoadCompactedStateReq, OperatorCheckpointMetadata,
+    ParquetStoreData, SubtaskCheckpointMetadata, TableDeleteBehavior, TableDescriptor, TableSchema,
+    TableType,
 };
 use arroyo_rpc::{CheckpointCompleted, ControlResp};
 use arroyo_types::{
-    from_micros, to_micros, CheckpointBarrier, Data, Key, TaskInfo, OUTPUT_DIR_ENV, S3_BUCKET_ENV,
-    S3_REGION_ENV,
+    from_micros, to_micros, CheckpointBarrier, Data, Key, StateCompression, StateStorageConfig,
+    TaskInfo, OUTPUT_DIR_ENV, S3_BUCKET_ENV, S3_REGION_ENV, STATE_BLOOM_FILTERS_ENV,
 };
 use bincode::config;
 use bytes::Bytes;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
+use once_cell::sync::Lazy;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::arrow::ArrowWriter;
 use parquet::basic::ZstdLevel;
-use parquet::file::properties::{EnabledStatistics, WriterProperties};
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::properties::{EnabledStatistics, ReaderProperties, WriterProperties};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::serialized_reader::ReadOptionsBuilder;
+use parquet::file::statistics::Statistics;
+use parquet::schema::types::ColumnPath;
 use prost::Message;
 use rusoto_core::{ByteStream, Region, RusotoError};
 use rusoto_s3::{
@@ -30,6 +38,7 @@ use std::io::ErrorKind;
 use std::ops::RangeInclusive;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
 use tokio::fs::{remove_file, DirBuilder};
 use tokio::io::AsyncReadExt;
@@ -38,6 +47,56 @@ use tokio::sync::oneshot;
 use tracing::warn;
 use tracing::{debug, info};
 
+// files are sorted by key hash and split into small row groups, so that readers can use the
+// row group statistics to skip data outside of their key range
+const ROW_GROUP_SIZE: usize = 8 * 1024;
+
+// tables with fewer files than this in a checkpoint aren't worth compacting
+const MIN_FILES_TO_COMPACT: usize = 16;
+// the most rows written to each file by state compaction
+const COMPACTED_FILE_ROWS: usize = 128 * ROW_GROUP_SIZE;
+
+// the compacted state that this process has been sent for each (job id, operator id)
+static COMPACTED_STATE: Lazy<Mutex<HashMap<(String, String), LoadCompactedStateReq>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records the compacted state files of an operator, which each of its subtasks in this process
+/// swaps in for the files they replace when it takes the checkpoint at `apply_epoch`
+pub fn load_compacted_state(job_id: &str, compacted: LoadCompactedStateReq) {
+    COMPACTED_STATE.lock().unwrap().insert(
+        (job_id.to_string(), compacted.operator_id.clone()),
+        compacted,
+    );
+}
+
+/// Drops the compacted state recorded for a job, which a new run of it must not apply
+pub fn clear_compacted_state(job_id: &str) {
+    COMPACTED_STATE
+        .lock()
+        .unwrap()
+        .retain(|(job, _), _| job != job_id);
+}
+
+fn compacted_state(task_info: &TaskInfo, epoch: u32) -> Option<LoadCompactedStateReq> {
+    COMPACTED_STATE
+        .lock()
+        .unwrap()
+        .get(&(task_info.job_id.clone(), task_info.operator_id.clone()))
+        .filter(|compacted| compacted.apply_epoch == epoch)
+        .cloned()
+}
+
+/// The files written by compacting an operator's state at a checkpoint, and those they replace
+#[derive(Debug, Default)]
+pub struct CompactedOperatorState {
+    pub operator_id: String,
+    pub epoch: u32,
+    pub replaced_files: Vec<String>,
+    pub files: Vec<ParquetStoreData>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
 pub struct ParquetBackend {
     epoch: u32,
     min_epoch: u32,
@@ -47,6 +106,11 @@ pub struct ParquetBackend {
     task_info: TaskInfo,
     tables: HashMap<char, TableDescriptor>,
     storage_client: StorageClient,
+    // the state types written by this task, and those of the files it was restored from
+    schemas: HashMap<char, TableSchema>,
+    restored_schemas: HashMap<(char, u64), TableSchema>,
+    migrations: HashMap<(char, u64), StateMigration>,
+    control_tx: Sender<ControlResp>,
 }
 
 fn base_path(job_id: &str, epoch: u32) -> String {
@@ -61,6 +125,14 @@ fn operator_path(job_id: &str, epoch: u32, operator: &str) -> String {
     format!("{}/operator-{}", base_path(job_id, epoch), operator)
 }
 
+// where a file of a checkpoint that is copied into another job is written
+fn forked_file_path(file: &str, from_job_id: &str, job_id: &str) -> String {
+    match file.strip_prefix(from_job_id) {
+        Some(path) => format!("{}{}", job_id, path),
+        None => format!("{}/forked/{}", job_id, file),
+    }
+}
+
 fn table_checkpoint_path(task_info: &TaskInfo, table: char, epoch: u32) -> String {
     format!(
         "{}/table-{}-{:0>3}",
@@ -142,6 +214,79 @@ impl BackingStore for ParquetBackend {
             .unwrap();
     }
 
+    async fn fork_checkpoint(
+        from_job_id: &str,
+        epoch: u32,
+        job_id: &str,
+        operators: &[String],
+        restored_operators: &[String],
+    ) -> Result<CheckpointMetadata> {
+        let storage_client = StorageClient::new();
+        let mut metadata = Self::load_checkpoint_metadata(from_job_id, epoch)
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "checkpoint {} of job {} no longer exists",
+                    epoch,
+                    from_job_id
+                )
+            })?;
+
+        let operator_ids: Vec<&str> = operators.iter().map(|o| o.as_str()).collect();
+        Self::initialize_checkpoint(job_id, epoch, &operator_ids).await?;
+
+        for operator_id in operators {
+            let restored = if restored_operators.contains(operator_id) {
+                Self::load_operator_metadata(from_job_id, operator_id, epoch).await
+            } else {
+                None
+            };
+
+            let operator_metadata = match restored {
+                Some(mut operator_metadata) => {
+                    for backend_data in &mut operator_metadata.backend_data {
+                        let Some(BackendData::ParquetStore(parquet_store)) = &mut backend_data.backend_data else {
+                            unreachable!("expect parquet backends")
+                        };
+                        let bytes = storage_client
+                            .get_bytes(&parquet_store.file)
+                            .await
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "file {} of checkpoint {} of job {} no longer exists",
+                                    parquet_store.file,
+                                    epoch,
+                                    from_job_id
+                                )
+                            })?;
+                        let file = forked_file_path(&parquet_store.file, from_job_id, job_id);
+                        storage_client.write(&file, bytes).await?;
+                        parquet_store.file = file;
+                    }
+                    operator_metadata.job_id = job_id.to_string();
+                    operator_metadata
+                }
+                None => OperatorCheckpointMetadata {
+                    job_id: job_id.to_string(),
+                    operator_id: operator_id.clone(),
+                    epoch,
+                    start_time: metadata.start_time,
+                    finish_time: metadata.finish_time,
+                    has_state: false,
+                    ..Default::default()
+                },
+            };
+            Self::complete_operator_checkpoint(operator_metadata).await;
+        }
+
+        metadata.job_id = job_id.to_string();
+        metadata.min_epoch = epoch;
+        metadata.operator_ids = operators.to_vec();
+        Self::complete_checkpoint(metadata.clone()).await;
+
+        Ok(metadata)
+    }
+
     async fn new(
         task_info: &TaskInfo,
         tables: Vec<TableDescriptor>,
@@ -153,10 +298,11 @@ impl BackingStore for ParquetBackend {
             current_files: HashMap::new(),
             writer: ParquetWriter::new(
                 task_info.clone(),
-                tx,
+                tx.clone(),
                 tables.clone(),
                 StorageClient::new(),
                 HashMap::new(),
+                HashMap::new(),
             ),
             task_info: task_info.clone(),
             tables: tables
@@ -164,6 +310,10 @@ impl BackingStore for ParquetBackend {
                 .map(|table| (table.name.clone().chars().next().unwrap(), table))
                 .collect(),
             storage_client: StorageClient::new(),
+            schemas: HashMap::new(),
+            restored_schemas: HashMap::new(),
+            migrations: HashMap::new(),
+            control_tx: tx,
         }
     }
 
@@ -213,20 +363,36 @@ impl BackingStore for ParquetBackend {
 
         let writer_current_files = current_files.clone();
 
+        let restored_schemas: HashMap<(char, u64), TableSchema> = operator_metadata
+            .table_schemas
+            .into_iter()
+            .map(|schema| {
+                (
+                    (schema.table.chars().next().unwrap(), schema.fingerprint),
+                    schema,
+                )
+            })
+            .collect();
+
         Self {
             epoch: metadata.epoch + 1,
             min_epoch: metadata.min_epoch,
             current_files,
             writer: ParquetWriter::new(
                 task_info.clone(),
-                control_tx,
+                control_tx.clone(),
                 tables.values().cloned().collect(),
                 StorageClient::new(),
                 writer_current_files,
+                restored_schemas.clone(),
             ),
             task_info: task_info.clone(),
             tables,
             storage_client: StorageClient::new(),
+            schemas: HashMap::new(),
+            restored_schemas,
+            migrations: HashMap::new(),
+            control_tx,
         }
     }
 
@@ -294,7 +460,13 @@ impl BackingStore for ParquetBackend {
     ) -> u32 {
         assert_eq!(barrier.epoch, self.epoch);
         self.writer
-            .checkpoint(self.epoch, barrier.timestamp, watermark, barrier.then_stop)
+            .checkpoint(
+                self.epoch,
+                barrier.timestamp,
+                watermark,
+                barrier.then_stop,
+                self.schemas.clone(),
+            )
             .await;
         self.epoch += 1;
         self.min_epoch = barrier.min_epoch;
@@ -310,16 +482,8 @@ impl BackingStore for ParquetBackend {
                     return vec![];
                 };
                 for file in files.values().flatten() {
-                    let bytes = self
-                        .storage_client
-                        .get_bytes(&file.file)
-                        .await
-                        .unwrap_or_else(|| {
-                            panic!("unable to find file {} in checkpoint", file.file)
-                        });
-                    result.append(
-                        &mut self.triples_from_parquet_bytes(bytes, &self.task_info.key_range),
-                    );
+                    result
+                        .append(&mut self.read_file(table, file, &self.task_info.key_range).await);
                 }
             }
         }
@@ -334,6 +498,10 @@ impl BackingStore for ParquetBackend {
         key: &mut K,
         value: &mut V,
     ) {
+        self.schemas
+            .entry(table)
+            .or_insert_with(|| table_schema::<K, V>(table));
+
         let (key_hash, key_bytes, value_bytes) = {
             (
                 hash_key(key),
@@ -357,13 +525,7 @@ impl BackingStore for ParquetBackend {
         };
         let mut state_map = HashMap::new();
         for file in files.values().flatten() {
-            let bytes = self
-                .storage_client
-                .get_bytes(&file.file)
-                .await
-                .unwrap_or_else(|| panic!("unable to find file {} in checkpoint", file.file));
-            for (_timestamp, key, value) in self.triples_from_parquet_bytes(bytes, &(0..=u64::MAX))
-            {
+            for (_timestamp, key, value) in self.read_file(table, file, &(0..=u64::MAX)).await {
                 state_map.insert(key, value);
             }
         }
@@ -376,19 +538,18 @@ impl BackingStore for ParquetBackend {
         };
         let mut state_map = HashMap::new();
         for file in files.values().flatten() {
-            let bytes = self
-                .storage_client
-                .get_bytes(&file.file)
-                .await
-                .unwrap_or_else(|| panic!("unable to find file {} in checkpoint", file.file));
             for (_timestamp, key, value) in
-                self.triples_from_parquet_bytes(bytes, &self.task_info.key_range)
+                self.read_file(table, file, &self.task_info.key_range).await
             {
                 state_map.insert(key, value);
             }
         }
         state_map.into_iter().collect()
     }
+
+    fn register_migration(&mut self, table: char, migration: StateMigration) {
+        self.migrations.insert((table, migration.from), migration);
+    }
 }
 
 impl ParquetBackend {
@@ -434,13 +595,211 @@ impl ParquetBackend {
         }
         Ok(operator)
     }
-    fn triples_from_parquet_bytes<K: Key, V: Data>(
+
+    /// Rewrites the files of each of the operator's tables in checkpoint `epoch` that has
+    /// accumulated at least MIN_FILES_TO_COMPACT of them into a few large files sorted by key
+    /// hash. The checkpoint itself is unchanged; the new files are used once the operator's
+    /// subtasks are sent them, and the replaced files are deleted by checkpoint compaction once
+    /// no retained checkpoint references them. The new files are written according to the job's
+    /// `storage_config`.
+    pub async fn compact_operator_state(
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+        storage_config: &StateStorageConfig,
+    ) -> Result<CompactedOperatorState> {
+        let metadata = Self::load_operator_metadata(job_id, operator_id, epoch)
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "missing metadata for operator {}, epoch {}",
+                    operator_id,
+                    epoch
+                )
+            })?;
+
+        let mut table_files: BTreeMap<char, Vec<ParquetStoreData>> = BTreeMap::new();
+        for backend_data in metadata.backend_data {
+            let Some(BackendData::ParquetStore(parquet_store)) = backend_data.backend_data else {
+                unreachable!("expect parquet backends")
+            };
+            table_files
+                .entry(parquet_store.table.chars().next().unwrap())
+                .or_default()
+                .push(parquet_store);
+        }
+
+        let mut result = CompactedOperatorState {
+            operator_id: operator_id.to_string(),
+            epoch,
+            ..Default::default()
+        };
+        let storage_client = StorageClient::new();
+
+        for (table, mut files) in table_files {
+            let Some(descriptor) = metadata.tables.iter().find(|t| t.name.starts_with(table)) else {
+                continue;
+            };
+
+            // subtasks may share files, which appear once for each of them
+            files.sort_by(|a, b| (a.epoch, &a.file).cmp(&(b.epoch, &b.file)));
+            files.dedup_by(|a, b| a.file == b.file);
+
+            if descriptor.table_type() == TableType::Global
+                || files.len() < MIN_FILES_TO_COMPACT
+                || files
+                    .iter()
+                    .any(|f| f.schema_fingerprint != files[0].schema_fingerprint)
+            {
+                continue;
+            }
+
+            let mut batches = vec![];
+            for file in &files {
+                let bytes = storage_client.get_bytes(&file.file).await.ok_or_else(|| {
+                    anyhow!("unable to find file {} in checkpoint {}", file.file, epoch)
+                })?;
+                result.bytes_read += bytes.len() as u64;
+                let bytes = decrypt_state_file(file, bytes).await?;
+                for batch in
+                    ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))?.build()?
+                {
+                    batches.push(batch?);
+                }
+            }
+
+            let expires_before = match (descriptor.delete_behavior(), metadata.min_watermark) {
+                (TableDeleteBehavior::NoReadsBeforeWatermark, Some(watermark)) => {
+                    Some(watermark.saturating_sub(descriptor.retention_micros))
+                }
+                _ => None,
+            };
+
+            // the files are ordered by epoch, so the compacted files take the place of the most
+            // recent ones when the state is read
+            let file_epoch = files.last().unwrap().epoch;
+            if let Some(batch) = merge_state_batches(&batches, expires_before)? {
+                let mut offset = 0;
+                while offset < batch.num_rows() {
+                    let chunk =
+                        batch.slice(offset, COMPACTED_FILE_ROWS.min(batch.num_rows() - offset));
+                    let stats = batch_stats(&chunk);
+                    let key = format!(
+                        "{}/compacted-table-{}-{:0>3}",
+                        operator_path(job_id, epoch, operator_id),
+                        table,
+                        offset / COMPACTED_FILE_ROWS
+                    );
+                    offset += chunk.num_rows();
+
+                    let (bytes, encryption_key_id) =
+                        encode_state_file(chunk, storage_config).await?;
+                    result.bytes_written += bytes.len() as u64;
+                    storage_client.write(&key, bytes).await?;
+
+                    result.files.push(ParquetStoreData {
+                        epoch: file_epoch,
+                        file: key,
+                        table: table.to_string(),
+                        min_routing_key: stats.min_routing_key,
+                        max_routing_key: stats.max_routing_key,
+                        max_timestamp_micros: to_micros(stats.max_timestamp),
+                        min_required_timestamp_micros: None,
+                        schema_fingerprint: files[0].schema_fingerprint,
+                        encryption_key_id,
+                    });
+                }
+            }
+
+            result
+                .replaced_files
+                .extend(files.into_iter().map(|file| file.file));
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the triples in `range` from a checkpointed file, migrating the values if the file
+    /// was written with an older value type
+    async fn read_file<K: Key, V: Data>(
+        &self,
+        table: char,
+        file: &ParquetStoreData,
+        range: &RangeInclusive<u64>,
+    ) -> Vec<(SystemTime, K, V)> {
+        let migration = self.check_schema::<K, V>(table, file).await;
+        let bytes = self
+            .storage_client
+            .get_bytes(&file.file)
+            .await
+            .unwrap_or_else(|| panic!("unable to find file {} in checkpoint", file.file));
+        let bytes = decrypt_state_file(file, bytes)
+            .await
+            .unwrap_or_else(|e| panic!("unable to read file {}: {:?}", file.file, e));
+        Self::triples_from_parquet_bytes(bytes, range, migration)
+    }
+
+    /// Checks that a file was written with the types we're reading it as, returning the
+    /// migration to apply if it was written with a registered older value type. Files from
+    /// before schemas were recorded are assumed to be compatible.
+    async fn check_schema<K: Key, V: Data>(
         &self,
+        table: char,
+        file: &ParquetStoreData,
+    ) -> Option<&StateMigration> {
+        let written = file.schema_fingerprint?;
+        let expected = table_schema::<K, V>(table);
+        if written == expected.fingerprint {
+            return None;
+        }
+
+        if let Some(migration) = self.migrations.get(&(table, written)) {
+            return Some(migration);
+        }
+
+        let written = self
+            .restored_schemas
+            .get(&(table, written))
+            .map(|s| format!("key type {} and value type {}", s.key_type, s.value_type))
+            .unwrap_or_else(|| "unknown types".to_string());
+        let message = format!(
+            "Incompatible state for table '{}' in checkpoint {}",
+            table,
+            self.epoch - 1
+        );
+        let details = format!(
+            "The checkpoint was written with {}, but the pipeline now uses key type {} and \
+            value type {}. Restart the pipeline without state or from a compatible checkpoint, or \
+            register a state migration for the table in the operator.",
+            written, expected.key_type, expected.value_type
+        );
+
+        self.control_tx
+            .send(ControlResp::Error {
+                operator_id: self.task_info.operator_id.clone(),
+                task_index: self.task_info.task_index,
+                message: message.clone(),
+                details: details.clone(),
+            })
+            .await
+            .ok();
+        panic!("{}: {}", message, details);
+    }
+
+    fn triples_from_parquet_bytes<K: Key, V: Data>(
         bytes: Vec<u8>,
         range: &RangeInclusive<u64>,
+        migration: Option<&StateMigration>,
     ) -> Vec<(SystemTime, K, V)> {
-        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::copy_from_slice(&bytes))
+        let bytes = Bytes::from(bytes);
+        let row_groups = row_groups_for_range(&bytes, range);
+        if row_groups.is_empty() {
+            return vec![];
+        }
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
             .unwrap()
+            .with_row_groups(row_groups)
             .build()
             .unwrap();
 
@@ -479,9 +838,15 @@ impl ParquetBackend {
                 let key: K = bincode::decode_from_slice(key_array.value(index), BINCODE_CONFIG)
                     .unwrap()
                     .0;
-                let value: V = bincode::decode_from_slice(value_array.value(index), BINCODE_CONFIG)
-                    .unwrap()
-                    .0;
+                let value: V = match migration {
+                    Some(migration) => bincode::decode_from_slice(
+                        &migration.apply(value_array.value(index)),
+                        BINCODE_CONFIG,
+                    ),
+                    None => bincode::decode_from_slice(value_array.value(index), BINCODE_CONFIG),
+                }
+                .unwrap()
+                .0;
                 result.push((timestamp, key, value));
             }
         }
@@ -489,6 +854,117 @@ impl ParquetBackend {
     }
 }
 
+/// The row groups of a state file that may contain keys in `range`, based on the key hash
+/// statistics and, for single-key reads, the bloom filters. Files written without statistics
+/// have all of their row groups read.
+fn row_groups_for_range(bytes: &Bytes, range: &RangeInclusive<u64>) -> Vec<usize> {
+    let options = ReadOptionsBuilder::new()
+        .with_reader_properties(
+            ReaderProperties::builder()
+                .set_read_bloom_filter(range.start() == range.end())
+                .build(),
+        )
+        .build();
+    let reader = SerializedFileReader::new_with_options(bytes.clone(), options).unwrap();
+
+    (0..reader.num_row_groups())
+        .filter(|i| {
+            if let Some((min, max)) = key_hash_bounds(reader.metadata().row_group(*i)) {
+                if max < *range.start() || *range.end() < min {
+                    return false;
+                }
+            }
+
+            if range.start() != range.end() {
+                return true;
+            }
+
+            reader
+                .get_row_group(*i)
+                .unwrap()
+                .get_column_bloom_filter(0)
+                .map(|filter| filter.check(range.start()))
+                .unwrap_or(true)
+        })
+        .collect()
+}
+
+/// Merges the rows of an operator's state files into a single batch sorted by key hash, dropping
+/// those with timestamps before `expires_before`. The batches must be in the order they were
+/// written; the sort is stable, so later writes for a key still come after earlier ones.
+fn merge_state_batches(
+    batches: &[RecordBatch],
+    expires_before: Option<u64>,
+) -> Result<Option<RecordBatch>> {
+    let Some(first) = batches.first() else {
+        return Ok(None);
+    };
+    let batch = arrow::compute::concat_batches(&first.schema(), batches)?;
+
+    let key_hash_array = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<arrow_array::UInt64Array>()
+        .unwrap();
+    let time_array = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<arrow_array::TimestampMicrosecondArray>()
+        .unwrap();
+
+    let mut indices: Vec<u32> = (0..batch.num_rows() as u32)
+        .filter(|i| {
+            expires_before
+                .map(|t| time_array.value(*i as usize) >= t as i64)
+                .unwrap_or(true)
+        })
+        .collect();
+    if indices.is_empty() {
+        return Ok(None);
+    }
+    indices.sort_by_key(|i| key_hash_array.value(*i as usize));
+    let indices = arrow_array::UInt32Array::from(indices);
+
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| arrow::compute::take(column, &indices, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(RecordBatch::try_new(batch.schema(), columns)?))
+}
+
+/// The stats of a batch of state rows that is sorted by key hash
+fn batch_stats(batch: &RecordBatch) -> ParquetStats {
+    let key_hash_array = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<arrow_array::UInt64Array>()
+        .unwrap();
+    let time_array = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<arrow_array::TimestampMicrosecondArray>()
+        .unwrap();
+
+    ParquetStats {
+        max_timestamp: arrow::compute::max(time_array)
+            .map(|t| from_micros(t as u64))
+            .unwrap_or(SystemTime::UNIX_EPOCH),
+        min_routing_key: key_hash_array.value(0),
+        max_routing_key: key_hash_array.value(batch.num_rows() - 1),
+    }
+}
+
+fn key_hash_bounds(row_group: &RowGroupMetaData) -> Option<(u64, u64)> {
+    match row_group.column(0).statistics()? {
+        // the unsigned key hashes are stored as INT64
+        Statistics::Int64(stats) if stats.has_min_max_set() => {
+            Some((*stats.min() as u64, *stats.max() as u64))
+        }
+        _ => None,
+    }
+}
+
 struct ParquetWriter {
     sender: Sender<ParquetQueueItem>,
     finish_rx: Option<oneshot::Receiver<()>>,
@@ -501,6 +977,7 @@ impl ParquetWriter {
         tables: Vec<TableDescriptor>,
         storage_client: StorageClient,
         current_files: HashMap<char, BTreeMap<u32, Vec<ParquetStoreData>>>,
+        table_schemas: HashMap<(char, u64), TableSchema>,
     ) -> Self {
         let (tx, rx) = mpsc::channel(1024 * 1024);
         let (finish_tx, finish_rx) = oneshot::channel();
@@ -517,6 +994,8 @@ impl ParquetWriter {
                 .collect(),
             builders: HashMap::new(),
             current_files,
+            table_schemas,
+            storage_config: StateStorageConfig::from_env(),
         })
         .start();
 
@@ -552,6 +1031,7 @@ impl ParquetWriter {
         time: SystemTime,
         watermark: Option<SystemTime>,
         then_stop: bool,
+        table_schemas: HashMap<char, TableSchema>,
     ) {
         self.sender
             .send(ParquetQueueItem::Checkpoint(ParquetCheckpoint {
@@ -559,6 +1039,7 @@ impl ParquetWriter {
                 time,
                 watermark,
                 then_stop,
+                table_schemas,
             }))
             .await
             .unwrap();
@@ -592,6 +1073,7 @@ struct ParquetCheckpoint {
     time: SystemTime,
     watermark: Option<SystemTime>,
     then_stop: bool,
+    table_schemas: HashMap<char, TableSchema>,
 }
 struct RecordBatchBuilder {
     key_hash_builder: arrow_array::builder::PrimitiveBuilder<arrow_array::types::UInt64Type>,
@@ -642,15 +1124,22 @@ impl RecordBatchBuilder {
         > = self.start_time_array.finish();
         let key_array: arrow_array::BinaryArray = self.key_bytes.finish();
         let data_array: arrow_array::BinaryArray = self.data_bytes.finish();
+
+        // sort by key hash so that row groups cover narrow key ranges; the sort is stable, so
+        // later writes for a key still come after earlier ones
+        let mut indices: Vec<u32> = (0..key_hash_array.len() as u32).collect();
+        indices.sort_by_key(|i| key_hash_array.value(*i as usize));
+        let indices = arrow_array::UInt32Array::from(indices);
+
+        let columns: [&dyn arrow_array::Array; 4] =
+            [&key_hash_array, &start_time_array, &key_array, &data_array];
         Some((
             arrow_array::RecordBatch::try_new(
                 self.schema(),
-                vec![
-                    std::sync::Arc::new(key_hash_array),
-                    std::sync::Arc::new(start_time_array),
-                    std::sync::Arc::new(key_array),
-                    std::sync::Arc::new(data_array),
-                ],
+                columns
+                    .into_iter()
+                    .map(|column| arrow::compute::take(column, &indices, None).unwrap())
+                    .collect(),
             )
             .unwrap(),
             self.parquet_stats,
@@ -699,6 +1188,8 @@ struct ParquetFlusher {
     table_descriptors: HashMap<char, TableDescriptor>,
     builders: HashMap<char, RecordBatchBuilder>,
     current_files: HashMap<char, BTreeMap<u32, Vec<ParquetStoreData>>>,
+    table_schemas: HashMap<(char, u64), TableSchema>,
+    storage_config: StateStorageConfig,
 }
 
 #[derive(Clone)]
@@ -865,6 +1356,56 @@ impl StorageClient {
     }
 }
 
+fn writer_properties(storage_config: &StateStorageConfig) -> WriterProperties {
+    let compression = match storage_config.compression() {
+        StateCompression::None => parquet::basic::Compression::UNCOMPRESSED,
+        StateCompression::Zstd => parquet::basic::Compression::ZSTD(
+            storage_config
+                .compression_level
+                .and_then(|level| ZstdLevel::try_new(level).ok())
+                .unwrap_or_default(),
+        ),
+    };
+
+    let mut props = WriterProperties::builder()
+        .set_compression(compression)
+        .set_statistics_enabled(EnabledStatistics::None)
+        .set_column_statistics_enabled(ColumnPath::from("key_hash"), EnabledStatistics::Chunk)
+        .set_column_statistics_enabled(ColumnPath::from("start_time"), EnabledStatistics::Chunk)
+        .set_max_row_group_size(ROW_GROUP_SIZE);
+
+    if env::var(STATE_BLOOM_FILTERS_ENV).as_deref() == Ok("true") {
+        props = props.set_column_bloom_filter_enabled(ColumnPath::from("key_hash"), true);
+    }
+
+    props.build()
+}
+
+/// Writes a batch as a state file, encrypting it if the job has a KMS key configured; returns the
+/// file and the ARN of the key it was encrypted with
+async fn encode_state_file(
+    record_batch: RecordBatch,
+    storage_config: &StateStorageConfig,
+) -> Result<(Vec<u8>, Option<String>)> {
+    let bytes =
+        ParquetFlusher::write_parquet_bytes(record_batch, writer_properties(storage_config));
+    match &storage_config.kms_key_id {
+        Some(kms_key_id) => {
+            let (bytes, key_arn) = encryption::encrypt(kms_key_id, bytes).await?;
+            Ok((bytes, Some(key_arn)))
+        }
+        None => Ok((bytes, None)),
+    }
+}
+
+/// Decrypts the contents of a state file if it was written encrypted
+async fn decrypt_state_file(file: &ParquetStoreData, bytes: Vec<u8>) -> Result<Vec<u8>> {
+    match &file.encryption_key_id {
+        Some(key_id) => encryption::decrypt(key_id, bytes).await,
+        None => Ok(bytes),
+    }
+}
+
 impl ParquetFlusher {
     fn start(mut self) {
         tokio::spawn(async move {
@@ -875,23 +1416,27 @@ impl ParquetFlusher {
             }
         });
     }
-    async fn upload_record_batch(
-        &self,
-        key: &str,
+    fn write_parquet_bytes(
         record_batch: arrow_array::RecordBatch,
-    ) -> Result<usize> {
-        let props = WriterProperties::builder()
-            .set_compression(parquet::basic::Compression::ZSTD(ZstdLevel::default()))
-            .set_statistics_enabled(EnabledStatistics::None)
-            .build();
+        props: WriterProperties,
+    ) -> Vec<u8> {
         let cursor = Vec::new();
         let mut writer = ArrowWriter::try_new(cursor, record_batch.schema(), Some(props)).unwrap();
         writer.write(&record_batch).expect("Writing batch");
         writer.flush().unwrap();
-        let parquet_bytes = writer.into_inner().unwrap();
+        writer.into_inner().unwrap()
+    }
+
+    async fn upload_record_batch(
+        &self,
+        key: &str,
+        record_batch: arrow_array::RecordBatch,
+    ) -> Result<(usize, Option<String>)> {
+        let (parquet_bytes, encryption_key_id) =
+            encode_state_file(record_batch, &self.storage_config).await?;
         let bytes = parquet_bytes.len();
         self.storage_client.write(key, parquet_bytes).await?;
-        Ok(bytes)
+        Ok((bytes, encryption_key_id))
     }
 
     async fn flush_iteration(&mut self) -> Result<bool> {
@@ -919,6 +1464,11 @@ impl ParquetFlusher {
         let mut backend_data = vec![];
 
         if let Some(cp) = checkpoint_epoch {
+            for (table, schema) in &cp.table_schemas {
+                self.table_schemas
+                    .insert((*table, schema.fingerprint), schema.clone());
+            }
+
             let mut bytes = 0;
             let mut to_write = vec![];
             for (table, builder) in self.builders.drain() {
@@ -930,7 +1480,9 @@ impl ParquetFlusher {
             }
 
             for (record_batch, s3_key, table, stats) in to_write {
-                bytes += self.upload_record_batch(&s3_key, record_batch).await?;
+                let (file_bytes, encryption_key_id) =
+                    self.upload_record_batch(&s3_key, record_batch).await?;
+                bytes += file_bytes;
                 self.current_files
                     .entry(table)
                     .or_default()
@@ -944,8 +1496,14 @@ impl ParquetFlusher {
                         max_routing_key: stats.max_routing_key,
                         max_timestamp_micros: to_micros(stats.max_timestamp),
                         min_required_timestamp_micros: None,
+                        schema_fingerprint: cp.table_schemas.get(&table).map(|s| s.fingerprint),
+                        encryption_key_id,
                     });
             }
+            if let Some(compacted) = compacted_state(&self.task_info, cp.epoch) {
+                self.load_compacted_state(compacted);
+            }
+
             let mut new_file_map: HashMap<char, BTreeMap<u32, Vec<ParquetStoreData>>> =
                 HashMap::new();
             for (table, epoch_files) in self.current_files.drain() {
@@ -983,6 +1541,22 @@ impl ParquetFlusher {
 
             self.current_files = new_file_map;
 
+            // record the types of all of the files the checkpoint references
+            let fingerprints: HashSet<(char, u64)> = self
+                .current_files
+                .iter()
+                .flat_map(|(table, epochs)| {
+                    epochs
+                        .values()
+                        .flatten()
+                        .filter_map(|file| Some((*table, file.schema_fingerprint?)))
+                })
+                .collect();
+            let table_schemas = fingerprints
+                .iter()
+                .filter_map(|key| self.table_schemas.get(key).cloned())
+                .collect();
+
             // write checkpoint metadata
             let subtask_metadata = SubtaskCheckpointMetadata {
                 subtask_index: self.task_info.task_index as u32,
@@ -993,6 +1567,7 @@ impl ParquetFlusher {
                 watermark: cp.watermark.map(to_micros),
                 backend_data,
                 bytes: bytes as u64,
+                table_schemas,
             };
             self.control_tx
                 .send(ControlResp::CheckpointCompleted(CheckpointCompleted {
@@ -1009,4 +1584,150 @@ impl ParquetFlusher {
         }
         Ok(true)
     }
+
+    // every subtask of the operator swaps in the compacted files at the same checkpoint, so no
+    // checkpoint references both the compacted files and those they replace
+    fn load_compacted_state(&mut self, compacted: LoadCompactedStateReq) {
+        let replaced: HashSet<&String> = compacted.replaced_files.iter().collect();
+        for files in self
+            .current_files
+            .values_mut()
+            .flat_map(|epochs| epochs.values_mut())
+        {
+            files.retain(|file| !replaced.contains(&file.file));
+        }
+
+        for file in compacted.files {
+            if file.max_routing_key < *self.task_info.key_range.start()
+                || *self.task_info.key_range.end() < file.min_routing_key
+            {
+                continue;
+            }
+            self.current_files
+                .entry(file.table.chars().next().unwrap())
+                .or_default()
+                .entry(file.epoch)
+                .or_default()
+                .push(file);
+        }
+
+        info!(
+            message = "loaded compacted state",
+            operator_id = self.task_info.operator_id,
+            task_index = self.task_info.task_index,
+            epoch = compacted.epoch
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        batch_stats, merge_state_batches, row_groups_for_range, ParquetBackend, ParquetFlusher,
+        RecordBatchBuilder, ROW_GROUP_SIZE,
+    };
+    use crate::schema::StateMigration;
+    use crate::{hash_key, BINCODE_CONFIG};
+    use arroyo_types::{from_micros, to_micros};
+    use bytes::Bytes;
+    use parquet::file::properties::WriterProperties;
+    use parquet::schema::types::ColumnPath;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_row_group_pruning() {
+        let mut builder = RecordBatchBuilder::default();
+        for i in 0..(4 * ROW_GROUP_SIZE as u64) {
+            builder.insert(hash_key(&i), SystemTime::now(), vec![], vec![]);
+        }
+        let (batch, _) = builder.flush().unwrap();
+
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .set_column_bloom_filter_enabled(ColumnPath::from("key_hash"), true)
+            .build();
+        let bytes = Bytes::from(ParquetFlusher::write_parquet_bytes(batch, props));
+
+        assert_eq!(row_groups_for_range(&bytes, &(0..=u64::MAX)).len(), 4);
+
+        // the rows are sorted by key hash, so each key is in exactly one row group
+        assert_eq!(
+            row_groups_for_range(&bytes, &(hash_key(&7u64)..=hash_key(&7u64))).len(),
+            1
+        );
+        assert!(row_groups_for_range(&bytes, &(0..=u64::MAX / 8)).len() < 4);
+    }
+
+    #[test]
+    fn test_migration() {
+        let mut builder = RecordBatchBuilder::default();
+        let time = SystemTime::UNIX_EPOCH;
+        builder.insert(
+            hash_key(&1u64),
+            time,
+            bincode::encode_to_vec(1u64, BINCODE_CONFIG).unwrap(),
+            bincode::encode_to_vec(5u32, BINCODE_CONFIG).unwrap(),
+        );
+        let (batch, _) = builder.flush().unwrap();
+        let bytes = ParquetFlusher::write_parquet_bytes(batch, WriterProperties::default());
+
+        let migration = StateMigration::new::<u64, u32, (u32, String)>(|v| (v, "new".to_string()));
+        assert_eq!(
+            ParquetBackend::triples_from_parquet_bytes::<u64, (u32, String)>(
+                bytes,
+                &(0..=u64::MAX),
+                Some(&migration)
+            ),
+            vec![(time, 1, (5, "new".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_merge_state_batches() {
+        let write = |rows: &[(u64, u64, u32)]| {
+            let mut builder = RecordBatchBuilder::default();
+            for (key, time, value) in rows {
+                builder.insert(
+                    hash_key(key),
+                    from_micros(*time),
+                    bincode::encode_to_vec(key, BINCODE_CONFIG).unwrap(),
+                    bincode::encode_to_vec(value, BINCODE_CONFIG).unwrap(),
+                );
+            }
+            builder.flush().unwrap().0
+        };
+
+        let batches = vec![
+            write(&[(1, 100, 1), (2, 10, 1), (3, 200, 1)]),
+            write(&[(1, 150, 2), (3, 300, 2)]),
+        ];
+
+        // the row for key 2 has expired
+        let batch = merge_state_batches(&batches, Some(100)).unwrap().unwrap();
+        let stats = batch_stats(&batch);
+        assert_eq!(to_micros(stats.max_timestamp), 300);
+
+        let bytes = ParquetFlusher::write_parquet_bytes(batch, WriterProperties::default());
+        let triples =
+            ParquetBackend::triples_from_parquet_bytes::<u64, u32>(bytes, &(0..=u64::MAX), None);
+        assert_eq!(triples.len(), 4);
+        assert!(triples
+            .windows(2)
+            .all(|w| hash_key(&w[0].1) <= hash_key(&w[1].1)));
+
+        // later writes for a key still come after earlier ones
+        let values = |key: u64| -> Vec<u32> {
+            triples
+                .iter()
+                .filter(|(_, k, _)| *k == key)
+                .map(|(_, _, v)| *v)
+                .collect()
+        };
+        assert_eq!(values(1), vec![1, 2]);
+        assert_eq!(values(2), Vec::<u32>::new());
+        assert_eq!(values(3), vec![1, 2]);
+        assert_eq!(stats.min_routing_key, hash_key(&triples[0].1));
+
+        assert!(merge_state_batches(&batches, Some(1000)).unwrap().is_none());
+    }
 }
@@ -0,0 +1,51 @@
+//! Tracks the key and value types that state tables are written with, so that restoring a
+//! checkpoint into a pipeline whose state types have changed fails with a clear error instead of
+//! a decoding panic. Operators whose value types change can register a [`StateMigration`] to
+//! upgrade values written by the previous version.
+
+use crate::{hash_key, BINCODE_CONFIG};
+use arroyo_rpc::grpc::TableSchema;
+use arroyo_types::{Data, Key};
+
+pub fn table_schema<K: Key, V: Data>(table: char) -> TableSchema {
+    let key_type = std::any::type_name::<K>().to_string();
+    let value_type = std::any::type_name::<V>().to_string();
+
+    TableSchema {
+        table: table.to_string(),
+        fingerprint: hash_key(&(&key_type, &value_type)),
+        key_type,
+        value_type,
+    }
+}
+
+/// Converts values written with an older value type into the current one when state is
+/// restored. The key type must be unchanged, as it determines how state is partitioned.
+pub struct StateMigration {
+    pub(crate) from: u64,
+    migrate: Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>,
+}
+
+impl StateMigration {
+    pub fn new<K: Key, OldV: Data, V: Data>(f: impl Fn(OldV) -> V + Send + Sync + 'static) -> Self {
+        Self {
+            from: table_schema::<K, OldV>(' ').fingerprint,
+            migrate: Box::new(move |bytes| {
+                let old: OldV = bincode::decode_from_slice(bytes, BINCODE_CONFIG)
+                    .unwrap_or_else(|e| {
+                        panic!(
+                            "failed to decode state as {} for migration: {:?}",
+                            std::any::type_name::<OldV>(),
+                            e
+                        )
+                    })
+                    .0;
+                bincode::encode_to_vec(f(old), BINCODE_CONFIG).unwrap()
+            }),
+        }
+    }
+
+    pub(crate) fn apply(&self, value: &[u8]) -> Vec<u8> {
+        (self.migrate)(value)
+    }
+}
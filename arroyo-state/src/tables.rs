@@ -260,13 +260,33 @@ impl<'a, K: Key, V: Data, S: BackingStore> KeyTimeMultiMap<'a, K, V, S> {
             let times_to_remove = key_map.range(start..end);
             let times: Vec<_> = times_to_remove.map(|(time, _values)| *time).collect();
             for time in times {
-                key_map.remove(&time);
+                if let Some(values) = key_map.remove(&time) {
+                    self.cache.rows -= values.len();
+                }
+            }
+            if key_map.is_empty() {
+                self.cache.values.remove(key);
             }
         };
     }
 
-    pub fn expire_entries_before(&mut self, expiration_time: SystemTime) {
-        self.cache.expire_entries_before(expiration_time);
+    /// Expires all values before `expiration_time`, returning the keys that no longer have any
+    pub fn expire_entries_before(&mut self, expiration_time: SystemTime) -> Vec<K> {
+        self.cache.expire_entries_before(expiration_time)
+    }
+
+    /// The number of keys with values in the table
+    pub fn key_count(&self) -> usize {
+        self.cache.values.len()
+    }
+
+    /// The number of values in the table, across all keys and times
+    pub fn row_count(&self) -> usize {
+        self.cache.rows
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.values.contains_key(key)
     }
 
     pub async fn get_all_values_with_timestamps(
@@ -280,6 +300,8 @@ impl<'a, K: Key, V: Data, S: BackingStore> KeyTimeMultiMap<'a, K, V, S> {
 pub struct KeyTimeMultiMapCache<K: Key, V: Data> {
     values: HashMap<K, BTreeMap<SystemTime, Vec<V>>>,
     expirations: BTreeMap<SystemTime, HashSet<K>>,
+    // the total number of values, kept so that it can be reported without walking every key
+    rows: usize,
 }
 impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
     pub async fn from_checkpoint<S: BackingStore>(
@@ -290,6 +312,7 @@ impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
         checkpoint_metadata: &CheckpointMetadata,
     ) -> Self {
         let mut values: HashMap<K, BTreeMap<SystemTime, Vec<V>>> = HashMap::new();
+        let mut rows = 0;
         // TODO: there may be a race here, as the initial checkpoint_metadata might get stale.
         // This is unlikely as this method is only called on start, but should probably be the domain of the backing store.
         let operator_metadata = StateBackend::load_operator_metadata(
@@ -309,6 +332,7 @@ impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
             if timestamp < min_valid_time {
                 continue;
             }
+            rows += 1;
             values
                 .entry(key)
                 .or_default()
@@ -326,6 +350,7 @@ impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
         Self {
             values,
             expirations,
+            rows,
         }
     }
 
@@ -343,17 +368,31 @@ impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
         }
     }
 
-    fn expire_entries_before(&mut self, time: SystemTime) {
-        let times_to_remove = self.expirations.range(..time);
-        let keys_to_remove: HashSet<_> = times_to_remove
-            .flat_map(|(_time, keys)| keys.clone())
-            .collect();
+    fn expire_entries_before(&mut self, time: SystemTime) -> Vec<K> {
+        let retained_expirations = self.expirations.split_off(&time);
+        let keys_to_remove: HashSet<_> =
+            std::mem::replace(&mut self.expirations, retained_expirations)
+                .into_values()
+                .flatten()
+                .collect();
+
+        let mut expired_keys = vec![];
         for key in keys_to_remove {
-            let key_data = self.values.get_mut(&key).unwrap();
-            if *key_data.last_key_value().unwrap().0 <= time {
+            // the key may have been cleared or already expired
+            let Some(key_data) = self.values.get_mut(&key) else {
+                continue;
+            };
+            if key_data
+                .last_key_value()
+                .map(|(last, _)| *last <= time)
+                .unwrap_or(true)
+            {
+                self.rows -= key_data.values().map(Vec::len).sum::<usize>();
                 self.values.remove(&key);
+                expired_keys.push(key);
             } else {
                 let retained_data = key_data.split_off(&time);
+                self.rows -= key_data.values().map(Vec::len).sum::<usize>();
                 let earliest_key = retained_data.first_key_value().unwrap().0;
                 self.expirations
                     .entry(*earliest_key)
@@ -362,11 +401,13 @@ impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
                 *key_data = retained_data;
             }
         }
+        expired_keys
     }
 
     // Insert a new value for a key at a given timestamp.
     // This potentially updates the earliest timestamp for the key.
     fn insert(&mut self, timestamp: SystemTime, key: K, value: V) {
+        self.rows += 1;
         let current_entries = self.values.entry(key.clone()).or_default();
         // If there are no entries for this key, insert the new value.
         // the expiration is the timestamp of the new value.
@@ -398,6 +439,7 @@ impl<K: Key, V: Data> Default for KeyTimeMultiMapCache<K, V> {
         Self {
             values: Default::default(),
             expirations: Default::default(),
+            rows: 0,
         }
     }
 }
@@ -1,12 +1,15 @@
 use arrow::datatypes::SchemaRef;
 use arrow_array::RecordBatch;
 use bincode::{config, Decode, Encode};
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeZone, Utc};
+pub use chrono_tz::Tz;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -31,6 +34,81 @@ impl Serialize for Window {
     }
 }
 
+/// The unit of a calendar window. Calendar windows start at local midnight (on Mondays for weeks,
+/// and on the first of the month for months) in a given timezone, so their length varies with DST
+/// transitions and the lengths of months.
+#[derive(Copy, Hash, Debug, Clone, Eq, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub enum CalendarUnit {
+    Day,
+    Week,
+    Month,
+}
+
+impl TryFrom<&str> for CalendarUnit {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "day" => Ok(CalendarUnit::Day),
+            "week" => Ok(CalendarUnit::Week),
+            "month" => Ok(CalendarUnit::Month),
+            _ => Err(format!(
+                "'{}' is not a valid calendar window unit; expected day, week, or month",
+                value
+            )),
+        }
+    }
+}
+
+impl CalendarUnit {
+    /// The calendar window containing `ts`, with boundaries computed in `timezone`
+    pub fn window(&self, ts: SystemTime, timezone: Tz) -> Window {
+        let date = DateTime::<Utc>::from(ts)
+            .with_timezone(&timezone)
+            .date_naive();
+
+        let (start, end) = match self {
+            CalendarUnit::Day => (date, date + chrono::Duration::days(1)),
+            CalendarUnit::Week => {
+                let start =
+                    date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64);
+                (start, start + chrono::Duration::days(7))
+            }
+            CalendarUnit::Month => {
+                let start = date.with_day(1).unwrap();
+                (start, start + Months::new(1))
+            }
+        };
+
+        Window {
+            start_time: local_midnight(start, timezone),
+            end_time: local_midnight(end, timezone),
+        }
+    }
+
+    /// An upper bound on the length of a window, accounting for DST transitions
+    pub fn max_width(&self) -> Duration {
+        let days = match self {
+            CalendarUnit::Day => 1,
+            CalendarUnit::Week => 7,
+            CalendarUnit::Month => 31,
+        };
+        Duration::from_secs(days * 24 * 60 * 60) + Duration::from_secs(2 * 60 * 60)
+    }
+}
+
+fn local_midnight(date: NaiveDate, timezone: Tz) -> SystemTime {
+    // in some timezones DST transitions happen at midnight, in which case the day starts at the
+    // first valid local time after it
+    let mut time = date.and_hms_opt(0, 0, 0).unwrap();
+    loop {
+        if let Some(t) = timezone.from_local_datetime(&time).earliest() {
+            return t.with_timezone(&Utc).into();
+        }
+        time += chrono::Duration::minutes(15);
+    }
+}
+
 static BINCODE_CONF: config::Configuration = config::standard();
 
 pub const TASK_SLOTS_ENV: &str = "TASK_SLOTS";
@@ -49,10 +127,40 @@ pub const DATABASE_HOST_ENV: &str = "DATABASE_HOST";
 pub const DATABASE_PORT_ENV: &str = "DATABASE_PORT";
 pub const DATABASE_USER_ENV: &str = "DATABASE_USER";
 pub const DATABASE_PASSWORD_ENV: &str = "DATABASE_PASSWORD";
+// how the controller applies database migrations at startup: "apply" (the default), "dry-run",
+// or "skip"
+pub const MIGRATIONS_ENV: &str = "MIGRATIONS";
+// how the controller garbage collects compiled pipeline artifacts: "apply" (the default),
+// "dry-run", or "disabled"
+pub const ARTIFACT_GC_ENV: &str = "ARTIFACT_GC";
+// how long artifacts are kept after they were written, and after the jobs that use them finished
+pub const ARTIFACT_RETENTION_HOURS_ENV: &str = "ARTIFACT_RETENTION_HOURS";
+// how long the summaries of finished job runs are kept, how long job log messages are kept, and
+// how long finished previews (and debug runs) are kept before they are deleted
+pub const JOB_HISTORY_RETENTION_DAYS_ENV: &str = "JOB_HISTORY_RETENTION_DAYS";
+pub const JOB_LOG_RETENTION_DAYS_ENV: &str = "JOB_LOG_RETENTION_DAYS";
+pub const PREVIEW_RETENTION_HOURS_ENV: &str = "PREVIEW_RETENTION_HOURS";
+// where the controller exports an event for each completed checkpoint, as JSON: a webhook that is
+// POSTed each event, and/or a Kafka topic (which requires both the bootstrap servers and topic)
+pub const CHECKPOINT_EVENTS_WEBHOOK_URL_ENV: &str = "CHECKPOINT_EVENTS_WEBHOOK_URL";
+pub const CHECKPOINT_EVENTS_KAFKA_BOOTSTRAP_SERVERS_ENV: &str =
+    "CHECKPOINT_EVENTS_KAFKA_BOOTSTRAP_SERVERS";
+pub const CHECKPOINT_EVENTS_KAFKA_TOPIC_ENV: &str = "CHECKPOINT_EVENTS_KAFKA_TOPIC";
 
 pub const ADMIN_PORT_ENV: &str = "ADMIN_PORT";
 pub const GRPC_PORT_ENV: &str = "GRPC_PORT";
 pub const HTTP_PORT_ENV: &str = "HTTP_PORT";
+// Address that services bind their listeners to; "::" binds dual-stack on IPv6 hosts
+pub const BIND_ADDRESS_ENV: &str = "BIND_ADDRESS";
+// Address that nodes and workers advertise to the controller when they register
+pub const ADVERTISE_ADDRESS_ENV: &str = "ADVERTISE_ADDRESS";
+
+// PEM files for mutual TLS between the services of the cluster; see arroyo_server_common::tls
+pub const CLUSTER_TLS_CERT_PATH_ENV: &str = "CLUSTER_TLS_CERT_PATH";
+pub const CLUSTER_TLS_KEY_PATH_ENV: &str = "CLUSTER_TLS_KEY_PATH";
+pub const CLUSTER_TLS_CA_PATH_ENV: &str = "CLUSTER_TLS_CA_PATH";
+// Name that peers' certificates are verified against, instead of the host being connected to
+pub const CLUSTER_TLS_SERVER_NAME_ENV: &str = "CLUSTER_TLS_SERVER_NAME";
 
 pub const ASSET_DIR_ENV: &str = "ASSET_DIR";
 // Endpoint that the frontend should query for the API
@@ -64,6 +172,17 @@ pub const API_METRICS_RATE_ENV: &str = "API_METRICS_RATE";
 pub const S3_REGION_ENV: &str = "S3_REGION";
 pub const S3_BUCKET_ENV: &str = "S3_BUCKET";
 pub const OUTPUT_DIR_ENV: &str = "OUTPUT_DIR";
+// set to "true" to write bloom filters on the key column of state files, which speeds up
+// point lookups at the cost of larger files
+pub const STATE_BLOOM_FILTERS_ENV: &str = "STATE_BLOOM_FILTERS";
+// the number of checkpoints between compactions of each running job's state files into larger
+// ones; if unset, state is only compacted when requested through the API
+pub const STATE_COMPACTION_EVERY_ENV: &str = "STATE_COMPACTION_EVERY";
+
+// proxy for outbound connector traffic; the lowercase forms are also accepted
+pub const HTTP_PROXY_ENV: &str = "HTTP_PROXY";
+pub const HTTPS_PROXY_ENV: &str = "HTTPS_PROXY";
+pub const NO_PROXY_ENV: &str = "NO_PROXY";
 
 // kubernetes scheduler configuration
 pub const K8S_NAMESPACE_ENV: &str = "K8S_NAMESPACE";
@@ -78,6 +197,17 @@ pub const K8S_WORKER_SLOTS_ENV: &str = "K8S_WORKER_SLOTS";
 pub const K8S_WORKER_VOLUMES_ENV: &str = "K8S_WORKER_VOLUMES";
 pub const K8S_WORKER_VOLUME_MOUNTS_ENV: &str = "K8S_WORKER_VOLUME_MOUNTS";
 
+// process scheduler configuration: the total number of task slots its workers may use at once
+// (by default derived from the host's cores and memory), and the number of slots per worker
+pub const PROCESS_SLOTS_ENV: &str = "PROCESS_SLOTS";
+pub const PROCESS_SLOTS_PER_WORKER_ENV: &str = "PROCESS_SLOTS_PER_WORKER";
+// set to "true" to keep workers running once their job finishes so that they can be reused by
+// later runs of the same pipeline, which speeds up previews
+pub const PROCESS_WORKER_REUSE_ENV: &str = "PROCESS_WORKER_REUSE";
+// set on workers that should wait to be assigned another run once their job finishes, rather than
+// exiting
+pub const WORKER_REUSE_ENV: &str = "ARROYO_WORKER_REUSE";
+
 // telemetry configuration
 pub const DISABLE_TELEMETRY_ENV: &str = "DISABLE_TELEMETRY";
 pub const POSTHOG_KEY: &str = "phc_ghJo7Aa9QOo4inoWFYZP7o2aKszllEUyH77QeFgznUe";
@@ -88,6 +218,720 @@ pub fn telemetry_enabled() -> bool {
     }
 }
 
+// per-pipeline configuration set through the API; user-defined variables and flags are passed to
+// workers under these prefixes so they can't clobber any of the variables above
+pub const PIPELINE_ENV_PREFIX: &str = "ARROYO_PIPELINE_ENV_";
+pub const PIPELINE_FEATURE_PREFIX: &str = "ARROYO_PIPELINE_FEATURE_";
+// comma-separated names of the environment variables pipelines may define; names ending in `*`
+// allow any variable with that prefix. If unset, any valid name is allowed.
+pub const PIPELINE_ENV_ALLOWLIST_ENV: &str = "PIPELINE_ENV_ALLOWLIST";
+
+/// Returns the value of an environment variable defined for this pipeline, for use in UDFs
+pub fn pipeline_env(name: &str) -> Option<String> {
+    env::var(format!("{}{}", PIPELINE_ENV_PREFIX, name.to_uppercase())).ok()
+}
+
+/// Returns whether a feature flag is enabled for this pipeline, for use in UDFs
+pub fn pipeline_feature_enabled(name: &str) -> bool {
+    env::var(format!(
+        "{}{}",
+        PIPELINE_FEATURE_PREFIX,
+        name.to_uppercase()
+    ))
+    .map(|v| v == "true")
+    .unwrap_or(false)
+}
+
+// directory (on the workers) holding the secrets that pipelines can use, such as the keys for the
+// crypto SQL functions, with one file per secret named after it; typically a mounted Kubernetes
+// secret. Unlike pipeline environment variables, secrets are never stored with the job.
+pub const PIPELINE_SECRETS_DIR_ENV: &str = "PIPELINE_SECRETS_DIR";
+
+/// Whether `name` can name a secret: letters, numbers, underscores, dashes, and dots, not
+/// starting with a dot
+pub fn valid_secret_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Returns the value of a secret available to this pipeline, or None if it isn't set
+pub fn pipeline_secret(name: &str) -> Option<Vec<u8>> {
+    if !valid_secret_name(name) {
+        return None;
+    }
+
+    let dir = env::var(PIPELINE_SECRETS_DIR_ENV).ok()?;
+    std::fs::read(std::path::Path::new(&dir).join(name)).ok()
+}
+
+// pipeline feature flag that enables the column stats computed by sources and sinks
+pub const COLUMN_STATS_FEATURE: &str = "COLUMN_STATS";
+
+// pipeline environment variable with comma-separated paths (on the workers) of the MaxMind
+// databases used by the GeoIP SQL functions; a country or city database and an ASN database may
+// be given
+pub const GEOIP_DATABASES_VAR: &str = "GEOIP_DATABASES";
+
+// set on workers for each source whose starting position should be overridden on restore,
+// followed by the source's operator id
+pub const RESTORE_OVERRIDE_PREFIX: &str = "ARROYO_RESTORE_OVERRIDE_";
+
+// version of the messages exchanged between the controller and workers, bumped on incompatible
+// changes so that workers built from a different version of arroyo can't join a job
+pub const WORKER_PROTOCOL_VERSION: u32 = 1;
+
+// version of the format that operator state is checkpointed in; checkpoints written with versions
+// from MIN_STATE_VERSION up to STATE_VERSION can be restored
+pub const STATE_VERSION: u32 = 1;
+pub const MIN_STATE_VERSION: u32 = 1;
+
+/// Returns whether a worker that registered with `version` can run alongside this controller.
+/// Workers from before the version was reported send 0, which is treated as version 1.
+pub fn worker_protocol_compatible(version: u32) -> bool {
+    version.max(1) == WORKER_PROTOCOL_VERSION
+}
+
+/// Returns whether state checkpointed with `version` can be restored. Checkpoints from before the
+/// version was recorded have version 0, which is treated as version 1.
+pub fn state_version_supported(version: u32) -> bool {
+    (MIN_STATE_VERSION..=STATE_VERSION).contains(&version.max(1))
+}
+
+// how long a permit for a connection in a shared connection pool is held by the controller
+// without being renewed; workers renew their permits well within it
+pub const CONNECTION_PERMIT_LEASE: Duration = Duration::from_secs(60);
+
+/// Where a source should start reading when a job is restored, ignoring any offsets in its
+/// checkpointed state
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SourceOffsetOverride {
+    Earliest,
+    Latest,
+    Timestamp { micros: u64 },
+}
+
+impl std::fmt::Display for SourceOffsetOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceOffsetOverride::Earliest => write!(f, "earliest"),
+            SourceOffsetOverride::Latest => write!(f, "latest"),
+            SourceOffsetOverride::Timestamp { micros } => write!(f, "timestamp:{}", micros),
+        }
+    }
+}
+
+impl FromStr for SourceOffsetOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "earliest" => Ok(SourceOffsetOverride::Earliest),
+            None if s == "latest" => Ok(SourceOffsetOverride::Latest),
+            Some(("timestamp", micros)) => micros
+                .parse()
+                .map(|micros| SourceOffsetOverride::Timestamp { micros })
+                .map_err(|_| format!("invalid timestamp in source offset override '{}'", s)),
+            _ => Err(format!("invalid source offset override '{}'", s)),
+        }
+    }
+}
+
+/// Overrides for the starting positions of some of a job's sources, applied the next time the job
+/// is restored. State for all other operators (and the non-offset state of the overridden sources)
+/// is restored as usual.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RestoreOverrides {
+    // unique per request, so that overrides are only cleared once the ones that were applied
+    // have been checkpointed past
+    pub id: String,
+    pub sources: HashMap<String, SourceOffsetOverride>,
+}
+
+impl RestoreOverrides {
+    pub fn to_env_vars(&self) -> HashMap<String, String> {
+        self.sources
+            .iter()
+            .map(|(operator_id, o)| {
+                (
+                    format!("{}{}", RESTORE_OVERRIDE_PREFIX, operator_id),
+                    o.to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Returns the starting position override for a source operator, if the job is being restored
+/// with one
+pub fn restore_override(operator_id: &str) -> Option<SourceOffsetOverride> {
+    let value = env::var(format!("{}{}", RESTORE_OVERRIDE_PREFIX, operator_id)).ok()?;
+    Some(value.parse().unwrap_or_else(|e| panic!("{}", e)))
+}
+
+// set on workers to a comma-separated list of operators that should skip records that cause
+// them to fail, reporting them to the job's error log instead of failing the job
+pub const SKIP_FAILING_RECORDS_ENV: &str = "ARROYO_SKIP_FAILING_RECORDS";
+
+pub fn skip_failing_records(operator_id: &str) -> bool {
+    env::var(SKIP_FAILING_RECORDS_ENV)
+        .map(|ops| ops.split(',').any(|op| op == operator_id))
+        .unwrap_or(false)
+}
+
+// set on workers to the event time (in micros) that the job's watermark generators drop records
+// after; the controller drains the job once all of their watermarks have passed it
+pub const STOP_AT_EVENT_TIME_ENV: &str = "ARROYO_STOP_AT_EVENT_TIME_MICROS";
+
+pub fn stop_at_event_time() -> Option<SystemTime> {
+    env::var(STOP_AT_EVENT_TIME_ENV).ok().map(|t| {
+        from_micros(
+            t.parse()
+                .unwrap_or_else(|e| panic!("invalid {}: {}", STOP_AT_EVENT_TIME_ENV, e)),
+        )
+    })
+}
+
+/// What to do when a job keeps failing in the same operator each time it is restored from the
+/// same checkpoint, which usually means a record it reads reliably crashes the operator
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PoisonPillAction {
+    /// Keep restarting until the restart budget is exhausted, then fail the job
+    #[default]
+    Fail,
+    /// Restart with the failing operator skipping the records that cause it to fail
+    Skip,
+    /// Stop the job and leave it in the NeedsAttention state until it is stopped and restarted
+    Park,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailurePolicy {
+    /// How many times the job may restart without becoming healthy before it is failed
+    pub restart_budget: Option<u32>,
+    pub on_poison_pill: PoisonPillAction,
+}
+
+/// A failure that repeated each time the job was restored from the same checkpoint
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoisonPill {
+    pub operator_id: String,
+    pub subtask_index: u32,
+    /// The checkpoint the job was restored from; its source offsets are the position that the
+    /// failing record is read after
+    pub epoch: u32,
+    pub failures: u32,
+    pub error: String,
+}
+
+/// Expectations of a running job that the controller checks continuously; only the thresholds
+/// that are set are checked. This is the part of the pipeline's SLO that the controller uses.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSlo {
+    pub max_source_lag_micros: Option<u64>,
+    pub max_watermark_lag_micros: Option<u64>,
+    /// How long each operator (by id) may go without receiving or emitting a record
+    #[serde(default)]
+    pub max_idle_micros: HashMap<String, u64>,
+    /// Sent a POST request when the job becomes degraded or recovers
+    pub webhook_url: Option<String>,
+}
+
+impl JobSlo {
+    pub fn is_empty(&self) -> bool {
+        self.max_source_lag_micros.is_none()
+            && self.max_watermark_lag_micros.is_none()
+            && self.max_idle_micros.is_empty()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SloIndicator {
+    SourceLag,
+    WatermarkLag,
+    Idle,
+}
+
+/// An expectation of its SLO that a running job isn't meeting; a job with violations is degraded
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SloViolation {
+    pub indicator: SloIndicator,
+    /// Set for expectations of a single operator
+    pub operator_id: Option<String>,
+    pub value_micros: u64,
+    pub threshold_micros: u64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StateCompactionState {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// The progress of the most recent compaction of a job's state, which rewrites the small files
+/// written by each checkpoint into larger ones while the job keeps running
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateCompaction {
+    pub state: StateCompactionState,
+    /// The checkpoint whose files are compacted
+    pub epoch: u32,
+    pub start_time_micros: u64,
+    pub finish_time_micros: Option<u64>,
+    pub operators: u32,
+    pub operators_compacted: u32,
+    pub files_read: u64,
+    pub files_written: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub error: Option<String>,
+}
+
+/// The progress an operator had made when a run of its job finished, summed over its subtasks;
+/// records are counted from the last time the run was restored
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorRunMetrics {
+    pub operator_id: String,
+    pub records_in: u64,
+    pub records_out: u64,
+    pub state_bytes: u64,
+    /// The watermark of the operator's furthest behind subtask
+    pub watermark_micros: Option<u64>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DependencyCondition {
+    /// The job has started running (or has already finished)
+    Running,
+    /// The job is running with a watermark within the dependency's max lag of now, for example
+    /// once it has finished backfilling (or it has already finished)
+    CaughtUp,
+    /// The job has finished
+    Finished,
+}
+
+/// Another job that must reach a condition before a job is first started
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobDependency {
+    pub job_id: String,
+    pub condition: DependencyCondition,
+    /// For [`DependencyCondition::CaughtUp`], how far the job's watermark may be behind now;
+    /// defaults to a minute
+    pub max_lag_micros: Option<u64>,
+}
+
+/// A checkpoint of another job that a job's state is copied from before it is first started, so
+/// that a modified version of a pipeline can be run from the same point in its inputs
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobFork {
+    pub job_id: String,
+    pub epoch: u32,
+    /// The operators whose state is copied, which are unchanged from the forked job; all other
+    /// operators start without state
+    pub operators: Vec<String>,
+}
+
+// set on workers when the job has a recovery throttle, which needs the watermarks of all of the
+// job's watermark generators to be reported to the controller
+pub const REPORT_WATERMARKS_ENV: &str = "ARROYO_REPORT_WATERMARKS";
+
+pub fn report_watermarks() -> bool {
+    env::var(REPORT_WATERMARKS_ENV)
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+pub const DEFAULT_CAUGHT_UP_LAG: Duration = Duration::from_secs(60);
+
+/// Limits how quickly a job catches up when it starts behind, for example after being restored
+/// from an old checkpoint, so that replaying its backlog doesn't overwhelm the systems its sinks
+/// write to. Sources whose watermarks get ahead of an allowed watermark are paused; it advances
+/// by `max_watermark_advance_micros` each second, and faster as the job's lag shrinks, until the
+/// job's watermark is within `caught_up_lag_micros` of now and the throttle is lifted.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryThrottle {
+    pub max_watermark_advance_micros: u64,
+    pub caught_up_lag_micros: Option<u64>,
+}
+
+impl RecoveryThrottle {
+    pub fn max_watermark_advance(&self) -> Duration {
+        Duration::from_micros(self.max_watermark_advance_micros)
+    }
+
+    pub fn caught_up_lag(&self) -> Duration {
+        self.caught_up_lag_micros
+            .map(Duration::from_micros)
+            .unwrap_or(DEFAULT_CAUGHT_UP_LAG)
+    }
+}
+
+// set on workers to override the default queue config
+pub const FORWARD_QUEUE_SIZE_ENV: &str = "ARROYO_FORWARD_QUEUE_SIZE";
+pub const SHUFFLE_QUEUE_SIZE_ENV: &str = "ARROYO_SHUFFLE_QUEUE_SIZE";
+pub const NETWORK_FLUSH_INTERVAL_MICROS_ENV: &str = "ARROYO_NETWORK_FLUSH_INTERVAL_MICROS";
+
+pub const DEFAULT_QUEUE_SIZE: u32 = 4 * 1024;
+pub const DEFAULT_NETWORK_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sizes of the queues between a job's operators and how long data sent between workers is
+/// buffered, which trade latency for throughput. Smaller queues limit how long a record can wait
+/// behind others (and how much memory is used) but absorb less of a burst before applying
+/// backpressure; a shorter flush interval sends records to other workers sooner at the cost of
+/// more, smaller writes. The defaults favor throughput; latency-sensitive jobs like alerting
+/// typically want a flush interval of a few milliseconds and queues of a few hundred messages.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueConfig {
+    /// Capacity, in messages, of each queue on a forward edge (between corresponding subtasks of
+    /// operators with the same parallelism)
+    pub forward_queue_size: Option<u32>,
+    /// Capacity, in messages, of each queue on a shuffle edge (between every pair of subtasks of
+    /// two operators)
+    pub shuffle_queue_size: Option<u32>,
+    /// How long data sent to other workers may be buffered before it is flushed
+    pub network_flush_interval_micros: Option<u64>,
+}
+
+impl QueueConfig {
+    pub fn forward_queue_size(&self) -> usize {
+        self.forward_queue_size.unwrap_or(DEFAULT_QUEUE_SIZE) as usize
+    }
+
+    pub fn shuffle_queue_size(&self) -> usize {
+        self.shuffle_queue_size.unwrap_or(DEFAULT_QUEUE_SIZE) as usize
+    }
+
+    pub fn network_flush_interval(&self) -> Duration {
+        self.network_flush_interval_micros
+            .map(Duration::from_micros)
+            .unwrap_or(DEFAULT_NETWORK_FLUSH_INTERVAL)
+    }
+
+    /// The config with the defaults filled in for unset values
+    pub fn effective(&self) -> Self {
+        Self {
+            forward_queue_size: Some(self.forward_queue_size() as u32),
+            shuffle_queue_size: Some(self.shuffle_queue_size() as u32),
+            network_flush_interval_micros: Some(self.network_flush_interval().as_micros() as u64),
+        }
+    }
+
+    pub fn to_env_vars(&self) -> HashMap<String, String> {
+        let mut vars = HashMap::new();
+        if let Some(size) = self.forward_queue_size {
+            vars.insert(FORWARD_QUEUE_SIZE_ENV.to_string(), size.to_string());
+        }
+        if let Some(size) = self.shuffle_queue_size {
+            vars.insert(SHUFFLE_QUEUE_SIZE_ENV.to_string(), size.to_string());
+        }
+        if let Some(interval) = self.network_flush_interval_micros {
+            vars.insert(
+                NETWORK_FLUSH_INTERVAL_MICROS_ENV.to_string(),
+                interval.to_string(),
+            );
+        }
+        vars
+    }
+
+    /// The queue config that this worker was started with
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+
+        Self {
+            forward_queue_size: var(FORWARD_QUEUE_SIZE_ENV),
+            shuffle_queue_size: var(SHUFFLE_QUEUE_SIZE_ENV),
+            network_flush_interval_micros: var(NETWORK_FLUSH_INTERVAL_MICROS_ENV),
+        }
+    }
+}
+
+// set on workers and the controller to configure how the job's state files are written
+pub const STATE_COMPRESSION_ENV: &str = "ARROYO_STATE_COMPRESSION";
+pub const STATE_COMPRESSION_LEVEL_ENV: &str = "ARROYO_STATE_COMPRESSION_LEVEL";
+pub const STATE_KMS_KEY_ID_ENV: &str = "ARROYO_STATE_KMS_KEY_ID";
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateCompression {
+    None,
+    #[default]
+    Zstd,
+}
+
+impl FromStr for StateCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(StateCompression::None),
+            "zstd" => Ok(StateCompression::Zstd),
+            _ => Err(format!("unknown state compression '{}'", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for StateCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateCompression::None => write!(f, "none"),
+            StateCompression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// How a job's state and checkpoint files are written to the checkpoint storage. Files are
+/// compressed with zstd by default. If a KMS key is set, each file is also encrypted with
+/// AES-256-GCM under a data key generated by KMS, which is stored (encrypted by the KMS key)
+/// alongside the file; the key id is recorded in the checkpoint metadata so that the files can be
+/// decrypted when the job is restored, even after the key is changed. Checkpoint metadata itself
+/// is not encrypted.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateStorageConfig {
+    pub compression: Option<StateCompression>,
+    /// The zstd compression level, from 1 to 22; higher levels produce smaller files at the cost
+    /// of more CPU when checkpointing
+    pub compression_level: Option<i32>,
+    /// The id or ARN of the KMS key used to encrypt state files; if unset, they are not encrypted
+    pub kms_key_id: Option<String>,
+}
+
+impl StateStorageConfig {
+    pub fn compression(&self) -> StateCompression {
+        self.compression.unwrap_or_default()
+    }
+
+    pub fn to_env_vars(&self) -> HashMap<String, String> {
+        let mut vars = HashMap::new();
+        if let Some(compression) = self.compression {
+            vars.insert(STATE_COMPRESSION_ENV.to_string(), compression.to_string());
+        }
+        if let Some(level) = self.compression_level {
+            vars.insert(STATE_COMPRESSION_LEVEL_ENV.to_string(), level.to_string());
+        }
+        if let Some(key_id) = &self.kms_key_id {
+            vars.insert(STATE_KMS_KEY_ID_ENV.to_string(), key_id.clone());
+        }
+        vars
+    }
+
+    /// The state storage config that this worker was started with
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+
+        Self {
+            compression: var(STATE_COMPRESSION_ENV),
+            compression_level: var(STATE_COMPRESSION_LEVEL_ENV),
+            kms_key_id: env::var(STATE_KMS_KEY_ID_ENV)
+                .ok()
+                .filter(|key| !key.is_empty()),
+        }
+    }
+}
+
+// set on the controller to limit the resources of preview runs; 0 disables a limit
+pub const PREVIEW_MEMORY_MB_ENV: &str = "PREVIEW_MEMORY_MB";
+pub const PREVIEW_CPU_SECONDS_ENV: &str = "PREVIEW_CPU_SECONDS";
+pub const PREVIEW_MAX_OUTPUT_RECORDS_ENV: &str = "PREVIEW_MAX_OUTPUT_RECORDS";
+// comma-separated hosts that the sources of preview runs may connect to, in the same form as
+// NO_PROXY; if unset, they may connect to any host
+pub const PREVIEW_ALLOWED_HOSTS_ENV: &str = "PREVIEW_ALLOWED_HOSTS";
+// set on the API: how long preview runs may run before they're stopped
+pub const PREVIEW_TIMEOUT_SECS_ENV: &str = "PREVIEW_TIMEOUT_SECS";
+
+// set on the workers of sandboxed (preview) runs
+pub const SANDBOX_MEMORY_BYTES_ENV: &str = "ARROYO_SANDBOX_MEMORY_BYTES";
+pub const SANDBOX_CPU_SECONDS_ENV: &str = "ARROYO_SANDBOX_CPU_SECONDS";
+pub const SANDBOX_MAX_OUTPUT_RECORDS_ENV: &str = "ARROYO_SANDBOX_MAX_OUTPUT_RECORDS";
+pub const SANDBOX_ALLOWED_HOSTS_ENV: &str = "ARROYO_SANDBOX_ALLOWED_HOSTS";
+
+pub const DEFAULT_PREVIEW_MEMORY_MB: u64 = 2 * 1024;
+pub const DEFAULT_PREVIEW_CPU_SECONDS: u64 = 2 * 60;
+pub const DEFAULT_PREVIEW_MAX_OUTPUT_RECORDS: u64 = 10_000;
+
+/// Limits on the resources a worker may use. They're applied to the workers of preview runs, where
+/// queries are still being iterated on and an accidental cross join can easily exhaust a shared
+/// environment.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SandboxLimits {
+    /// Memory the worker may allocate, in bytes
+    pub memory_bytes: Option<u64>,
+    /// CPU time each run may use in the worker, in seconds
+    pub cpu_seconds: Option<u64>,
+    /// Records each subtask of a preview sink may output; further output is dropped
+    pub max_output_records: Option<u64>,
+    /// Hosts that sources may connect to (see [`host_matches`]); any host if unset
+    pub allowed_hosts: Option<String>,
+}
+
+impl SandboxLimits {
+    /// The limits for preview runs, as configured on the controller
+    pub fn for_previews() -> Self {
+        fn limit(name: &str, default: u64) -> Option<u64> {
+            let value = env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default);
+            (value > 0).then_some(value)
+        }
+
+        Self {
+            memory_bytes: limit(PREVIEW_MEMORY_MB_ENV, DEFAULT_PREVIEW_MEMORY_MB)
+                .map(|mb| mb * 1024 * 1024),
+            cpu_seconds: limit(PREVIEW_CPU_SECONDS_ENV, DEFAULT_PREVIEW_CPU_SECONDS),
+            max_output_records: limit(
+                PREVIEW_MAX_OUTPUT_RECORDS_ENV,
+                DEFAULT_PREVIEW_MAX_OUTPUT_RECORDS,
+            ),
+            allowed_hosts: env::var(PREVIEW_ALLOWED_HOSTS_ENV).ok(),
+        }
+    }
+
+    pub fn to_env_vars(&self) -> HashMap<String, String> {
+        let mut vars = HashMap::new();
+        if let Some(bytes) = self.memory_bytes {
+            vars.insert(SANDBOX_MEMORY_BYTES_ENV.to_string(), bytes.to_string());
+        }
+        if let Some(seconds) = self.cpu_seconds {
+            vars.insert(SANDBOX_CPU_SECONDS_ENV.to_string(), seconds.to_string());
+        }
+        if let Some(records) = self.max_output_records {
+            vars.insert(
+                SANDBOX_MAX_OUTPUT_RECORDS_ENV.to_string(),
+                records.to_string(),
+            );
+        }
+        if let Some(hosts) = &self.allowed_hosts {
+            vars.insert(SANDBOX_ALLOWED_HOSTS_ENV.to_string(), hosts.clone());
+        }
+        vars
+    }
+
+    /// The limits that this worker was started with
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+
+        Self {
+            memory_bytes: var(SANDBOX_MEMORY_BYTES_ENV),
+            cpu_seconds: var(SANDBOX_CPU_SECONDS_ENV),
+            max_output_records: var(SANDBOX_MAX_OUTPUT_RECORDS_ENV),
+            allowed_hosts: env::var(SANDBOX_ALLOWED_HOSTS_ENV).ok(),
+        }
+    }
+
+    pub fn allows_host(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .as_ref()
+            .map(|hosts| host_matches(hosts, host))
+            .unwrap_or(true)
+    }
+}
+
+/// Checks that this worker's sandbox allows its sources to connect to `host`
+pub fn check_egress(host: &str) -> Result<(), String> {
+    if SandboxLimits::from_env().allows_host(host) {
+        Ok(())
+    } else {
+        Err(format!(
+            "connecting to {} is not allowed in preview runs; the hosts previews may connect to are \
+            configured with {}",
+            host, PREVIEW_ALLOWED_HOSTS_ENV
+        ))
+    }
+}
+
+/// Whether `host` matches the comma-separated list of `patterns`, where `.example.com` or
+/// `example.com` match the domain and all of its subdomains, and `*` matches everything
+pub fn host_matches(patterns: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    patterns
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host.eq_ignore_ascii_case(entry)
+                || (host.len() > entry.len()
+                    && host.as_bytes()[host.len() - entry.len() - 1] == b'.'
+                    && host[host.len() - entry.len()..].eq_ignore_ascii_case(entry))
+        })
+}
+
+// set on the API: how SQL UDFs are run. "native" (the default) compiles them into the pipeline;
+// any other value (e.g., "wasm") runs them in a WebAssembly sandbox, so that a misspelled policy
+// doesn't run untrusted code natively
+pub const UDF_SANDBOX_ENV: &str = "UDF_SANDBOX";
+// limits on sandboxed UDFs; see [`UdfSandbox`]
+pub const UDF_SANDBOX_MEMORY_MB_ENV: &str = "UDF_SANDBOX_MEMORY_MB";
+pub const UDF_SANDBOX_FUEL_ENV: &str = "UDF_SANDBOX_FUEL";
+
+pub const DEFAULT_UDF_SANDBOX_MEMORY_MB: u64 = 64;
+pub const DEFAULT_UDF_SANDBOX_FUEL: u64 = 10_000_000;
+
+/// Limits on SQL UDFs that are run in a WebAssembly sandbox rather than compiled into the
+/// pipeline, for deployments where users' UDFs can't be trusted. Sandboxed UDFs have no access to
+/// the filesystem, network, clock, or any other I/O, and a call that exceeds either limit fails the
+/// job.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UdfSandbox {
+    /// Memory each sandbox may use, in bytes; each thread of a worker that calls UDFs has its own
+    pub memory_bytes: u64,
+    /// Fuel each call may consume, which is roughly the number of WebAssembly instructions it may
+    /// execute
+    pub fuel: u64,
+}
+
+impl UdfSandbox {
+    /// The deployment's policy for running UDFs, or None if they're run natively
+    pub fn from_env() -> Option<Self> {
+        if env::var(UDF_SANDBOX_ENV)
+            .map(|v| v.eq_ignore_ascii_case("native"))
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        fn limit(name: &str, default: u64) -> u64 {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        }
+
+        Some(Self {
+            memory_bytes: limit(UDF_SANDBOX_MEMORY_MB_ENV, DEFAULT_UDF_SANDBOX_MEMORY_MB)
+                * 1024
+                * 1024,
+            fuel: limit(UDF_SANDBOX_FUEL_ENV, DEFAULT_UDF_SANDBOX_FUEL),
+        })
+    }
+}
+
 pub fn string_config(var: &str, default: &str) -> String {
     env::var(var).unwrap_or_else(|_| default.to_string())
 }
@@ -147,11 +991,37 @@ pub fn admin_port(service: &str, default: u16) -> u16 {
 }
 
 pub fn service_port(service: &str, default: u16, env_var: &str) -> u16 {
+    service_env(service, env_var)
+        .map(|s| u16::from_str(&s).unwrap_or_else(|_| panic!("Invalid setting for {}", env_var)))
+        .unwrap_or(default)
+}
+
+/// Reads `{SERVICE}_{env_var}`, falling back to `env_var`
+pub fn service_env(service: &str, env_var: &str) -> Option<String> {
     env::var(format!("{}_{}", service.to_uppercase(), env_var))
         .ok()
         .or(env::var(env_var).ok())
-        .map(|s| u16::from_str(&s).unwrap_or_else(|_| panic!("Invalid setting for {}", env_var)))
-        .unwrap_or(default)
+}
+
+/// Parses an IP address, accepting IPv6 addresses wrapped in brackets (e.g., `[::]`)
+pub fn parse_ip(s: &str) -> Option<IpAddr> {
+    let s = s.trim();
+    s.strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s)
+        .parse()
+        .ok()
+}
+
+/// The IP address that the service's listeners bind to, defaulting to all IPv4 interfaces
+pub fn bind_ip(service: &str) -> IpAddr {
+    service_env(service, BIND_ADDRESS_ENV)
+        .map(|s| parse_ip(&s).unwrap_or_else(|| panic!("Invalid setting for {}", BIND_ADDRESS_ENV)))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+pub fn bind_addr(service: &str, port: u16) -> SocketAddr {
+    SocketAddr::new(bind_ip(service), port)
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Copy, Clone)]
@@ -229,6 +1099,9 @@ pub enum Message<K: Key, T: Data> {
     Watermark(SystemTime),
     Stop,
     EndOfData,
+    // a watermark probe, identified by its id, which each subtask reports when it first sees it
+    // before passing it on
+    Probe(u64),
 }
 
 impl<K: Key, T: Data> Message<K, T> {
@@ -506,6 +1379,59 @@ pub struct RawJson {
     pub value: String,
 }
 
+#[derive(Encode, Decode, Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RawBytes {
+    #[serde(deserialize_with = "deserialize_raw_bytes")]
+    pub value: Vec<u8>,
+}
+
+// accepts the bytes themselves as well as a sequence of them, so that sources can deserialize
+// messages into RawBytes without encoding them as json first
+fn deserialize_raw_bytes<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    struct BytesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("bytes")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(b) = seq.next_element()? {
+                bytes.push(b);
+            }
+            Ok(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(BytesVisitor)
+}
+
+/// The column in which permissive sources put the raw payload of records that don't match their
+/// schema
+pub const CORRUPT_RECORD_FIELD: &str = "_corrupt_record";
+
+/// The columns in which sources with lineage enabled record where each record was read from: the
+/// topic or file, the partition (if the source has them), and the offset or line number
+pub const LINEAGE_SOURCE_FIELD: &str = "_lineage_source";
+pub const LINEAGE_PARTITION_FIELD: &str = "_lineage_partition";
+pub const LINEAGE_OFFSET_FIELD: &str = "_lineage_offset";
+
 pub mod nexmark {
     use bincode::{Decode, Encode};
 
@@ -633,6 +1559,29 @@ pub static BYTES_RECV: &str = "arroyo_worker_bytes_recv";
 pub static BYTES_SENT: &str = "arroyo_worker_bytes_sent";
 pub static TX_QUEUE_SIZE: &str = "arroyo_worker_tx_queue_size";
 pub static TX_QUEUE_REM: &str = "arroyo_worker_tx_queue_rem";
+pub static SOURCE_LAG: &str = "arroyo_worker_source_lag_ms";
+pub static WATERMARK: &str = "arroyo_worker_watermark_ms";
+pub static SINK_BATCH_RECORDS: &str = "arroyo_worker_sink_batch_records";
+pub static SINK_BATCH_BYTES: &str = "arroyo_worker_sink_batch_bytes";
+pub static SINK_FLUSHES: &str = "arroyo_worker_sink_flushes";
+pub static TIMESTAMPS_ADJUSTED: &str = "arroyo_worker_timestamps_adjusted";
+pub static TIMESTAMPS_REJECTED: &str = "arroyo_worker_timestamps_rejected";
+pub static ASSERTION_VIOLATIONS: &str = "arroyo_worker_assertion_violations";
+pub static REFERENCE_TABLE_REFRESHED: &str = "arroyo_worker_reference_table_refreshed_seconds";
+pub static REFERENCE_TABLE_ROWS: &str = "arroyo_worker_reference_table_rows";
+pub static REFERENCE_TABLE_REFRESH_FAILURES: &str =
+    "arroyo_worker_reference_table_refresh_failures";
+pub static SINK_ROUTED_MESSAGES: &str = "arroyo_worker_sink_routed_messages";
+pub static SINK_ROUTING_ERRORS: &str = "arroyo_worker_sink_routing_errors";
+pub static TENANT_RECORDS: &str = "arroyo_worker_tenant_records";
+pub static TENANT_RECORDS_QUEUED: &str = "arroyo_worker_tenant_records_queued";
+pub static TENANT_RECORDS_SHED: &str = "arroyo_worker_tenant_records_shed";
+pub static JOIN_RECORDS: &str = "arroyo_worker_join_records";
+pub static JOIN_MATCHED_RECORDS: &str = "arroyo_worker_join_matched_records";
+pub static JOIN_BUFFERED_ROWS: &str = "arroyo_worker_join_buffered_rows";
+pub static JOIN_BUFFERED_KEYS: &str = "arroyo_worker_join_buffered_keys";
+pub static JOIN_EXPIRED_KEYS: &str = "arroyo_worker_join_expired_keys";
+pub static JOIN_ORPHANED_KEYS: &str = "arroyo_worker_join_orphaned_keys";
 
 #[derive(Debug, Copy, Clone, Encode, Decode)]
 pub struct CheckpointBarrier {
@@ -0,0 +1,190 @@
+//! Measures the tradeoff between latency and throughput that a pipeline's queue config makes:
+//! how quickly records pass through queues of different sizes, and, for different network flush
+//! intervals, how long data sent to another worker takes to arrive and how quickly a stream of it
+//! is delivered.
+//!
+//! Run with `cargo bench -p arroyo-worker --bench queue_config`.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use arroyo_types::{
+    Message, QueueConfig, Record, DEFAULT_QUEUE_SIZE, NETWORK_FLUSH_INTERVAL_MICROS_ENV,
+};
+use arroyo_worker::engine::QueueItem;
+use arroyo_worker::network_manager::{NetworkManager, Quad, Senders};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+const RECORDS: usize = 100_000;
+
+const MESSAGES: usize = 10_000;
+const MESSAGE_SIZE: usize = 100;
+
+const QUEUE_SIZES: [u32; 4] = [16, 256, 1024, DEFAULT_QUEUE_SIZE];
+
+const FLUSH_INTERVALS: [Duration; 3] = [
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+];
+
+async fn produce(tx: Sender<QueueItem>) {
+    for i in 0..RECORDS {
+        let message: Message<(), u64> = Message::Record(Record {
+            timestamp: SystemTime::UNIX_EPOCH,
+            key: None,
+            value: i as u64,
+        });
+        tx.send(QueueItem::Data(Box::new(message))).await.unwrap();
+    }
+}
+
+async fn consume(mut rx: Receiver<QueueItem>) {
+    while let Some(item) = rx.recv().await {
+        black_box(item);
+    }
+}
+
+fn bench_queue_size(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("queue_size");
+    group.throughput(Throughput::Elements(RECORDS as u64));
+
+    for size in QUEUE_SIZES {
+        let config = QueueConfig {
+            forward_queue_size: Some(size),
+            ..Default::default()
+        };
+
+        // a subtask sending to the next one over a forward edge, as fast as it can
+        group.bench_with_input(BenchmarkId::from_parameter(size), &config, |b, config| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let (tx, rx) = channel(config.forward_queue_size());
+                        let start = Instant::now();
+                        let consumer = tokio::spawn(consume(rx));
+                        produce(tx).await;
+                        consumer.await.unwrap();
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// a link from this worker to itself, as data is sent between workers
+async fn network_link(config: &QueueConfig) -> (Sender<QueueItem>, Receiver<QueueItem>) {
+    // the link reads its flush interval from the environment, as workers are configured
+    std::env::set_var(
+        NETWORK_FLUSH_INTERVAL_MICROS_ENV,
+        config.network_flush_interval().as_micros().to_string(),
+    );
+
+    let quad = Quad {
+        src_id: 0,
+        src_idx: 0,
+        dst_id: 1,
+        dst_idx: 0,
+    };
+
+    let (server_tx, mut server_rx) = channel(config.shuffle_queue_size());
+    let mut senders = Senders::new();
+    senders.add(quad, server_tx);
+
+    let mut nm = NetworkManager::new(0);
+    let port = nm.open_listener().await;
+
+    let (client_tx, client_rx) = channel(config.shuffle_queue_size());
+    nm.connect(format!("localhost:{}", port), quad, client_rx)
+        .await;
+    nm.start(senders).await;
+
+    // waits for the link to have started, and so to have read its flush interval
+    client_tx.send(QueueItem::Bytes(vec![])).await.unwrap();
+    server_rx.recv().await.unwrap();
+
+    (client_tx, server_rx)
+}
+
+fn bench_network_flush_interval(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut links: Vec<_> = FLUSH_INTERVALS
+        .iter()
+        .map(|interval| {
+            let config = QueueConfig {
+                network_flush_interval_micros: Some(interval.as_micros() as u64),
+                ..Default::default()
+            };
+            (
+                BenchmarkId::from_parameter(format!("{:?}", interval)),
+                rt.block_on(network_link(&config)),
+            )
+        })
+        .collect();
+
+    // how long a single message takes to arrive, which is bounded by the interval
+    let mut group = c.benchmark_group("network_flush_interval/latency");
+    group.sample_size(10);
+    for (id, (tx, rx)) in &mut links {
+        group.bench_function(id.clone(), |b| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let start = Instant::now();
+                        tx.send(QueueItem::Bytes(vec![0; MESSAGE_SIZE]))
+                            .await
+                            .unwrap();
+                        black_box(rx.recv().await.unwrap());
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            })
+        });
+    }
+    group.finish();
+
+    // how quickly a steady stream of messages is delivered
+    let mut group = c.benchmark_group("network_flush_interval/throughput");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((MESSAGES * MESSAGE_SIZE) as u64));
+    for (id, (tx, rx)) in &mut links {
+        group.bench_function(id.clone(), |b| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let start = Instant::now();
+                        let send = async {
+                            for _ in 0..MESSAGES {
+                                tx.send(QueueItem::Bytes(vec![0; MESSAGE_SIZE]))
+                                    .await
+                                    .unwrap();
+                            }
+                        };
+                        let receive = async {
+                            for _ in 0..MESSAGES {
+                                black_box(rx.recv().await.unwrap());
+                            }
+                        };
+                        tokio::join!(send, receive);
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_queue_size, bench_network_flush_interval);
+criterion_main!(benches);
@@ -0,0 +1,124 @@
+//! Compares routing records across shuffles by hashing their keys for every downstream operator,
+//! as the collector used to, with the collector's routing, which hashes each key once (and not
+//! at all for runs of the same key).
+//!
+//! Run with `cargo bench -p arroyo-worker --bench shuffle_routing`.
+
+use std::time::SystemTime;
+
+use arroyo_state::hash_key;
+use arroyo_types::Record;
+use arroyo_worker::engine::{Collector, OutQueue, QueueItem, Router};
+use arroyo_worker::metrics::OutputMetrics;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use tokio::sync::mpsc::{channel, Receiver};
+
+const RECORDS: usize = 10_000;
+
+// the parallelism of each downstream operator
+const FAN_OUTS: [&[usize]; 3] = [&[32], &[32, 32], &[32, 32, 32, 32]];
+
+// how routing was done before the collector hashed keys once per record
+fn server_for_hash(x: u64, n: usize) -> usize {
+    let range_size = u64::MAX / (n as u64);
+    (n - 1).min((x / range_size) as usize)
+}
+
+// keys that change on every record, and keys that come in runs as the results of a window do
+fn keys(run_length: usize) -> Vec<String> {
+    (0..RECORDS)
+        .map(|i| format!("key-{}", i / run_length))
+        .collect()
+}
+
+fn collector(fan_out: &[usize]) -> (Collector<String, u64>, Vec<Receiver<QueueItem>>) {
+    let (out_qs, rxs): (Vec<Vec<_>>, Vec<Vec<_>>) = fan_out
+        .iter()
+        .map(|n| {
+            (0..*n)
+                .map(|_| {
+                    let (tx, rx) = channel(RECORDS);
+                    (OutQueue::new(tx, false), rx)
+                })
+                .unzip()
+        })
+        .unzip();
+
+    // without any metrics registered, so that only routing and sending are measured
+    let queues = || fan_out.iter().map(|n| vec![None; *n]).collect();
+    let metrics = OutputMetrics {
+        messages_sent: None,
+        bytes_sent: None,
+        tx_queue_size: queues(),
+        tx_queue_rem: queues(),
+    };
+
+    (
+        Collector::new(out_qs, metrics),
+        rxs.into_iter().flatten().collect(),
+    )
+}
+
+fn bench_routing(c: &mut Criterion) {
+    for (name, run_length) in [("distinct_keys", 1), ("key_runs", 100)] {
+        let mut group = c.benchmark_group(format!("shuffle_routing/{}", name));
+        let keys = keys(run_length);
+
+        for fan_out in FAN_OUTS {
+            let id = format!("{:?}", fan_out);
+
+            group.bench_with_input(
+                BenchmarkId::new("hash_per_edge", &id),
+                fan_out,
+                |b, fan_out| {
+                    b.iter(|| {
+                        for key in &keys {
+                            for n in fan_out.iter() {
+                                black_box(server_for_hash(hash_key(key), *n));
+                            }
+                        }
+                    })
+                },
+            );
+
+            group.bench_with_input(BenchmarkId::new("hash_once", &id), fan_out, |b, fan_out| {
+                let routers: Vec<_> = fan_out.iter().map(|n| Router::new(*n)).collect();
+                b.iter(|| {
+                    for key in &keys {
+                        let hash = hash_key(key);
+                        for router in &routers {
+                            black_box(router.route(hash));
+                        }
+                    }
+                })
+            });
+
+            // the collector end to end, including sending to its queues
+            group.bench_with_input(BenchmarkId::new("collector", &id), fan_out, |b, fan_out| {
+                b.iter_batched(
+                    || (collector(fan_out), keys.clone()),
+                    |((mut collector, rxs), keys)| {
+                        futures::executor::block_on(async {
+                            for (i, key) in keys.into_iter().enumerate() {
+                                collector
+                                    .collect(Record {
+                                        timestamp: SystemTime::UNIX_EPOCH,
+                                        key: Some(key),
+                                        value: i as u64,
+                                    })
+                                    .await;
+                            }
+                        });
+                        rxs
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_routing);
+criterion_main!(benches);
@@ -0,0 +1,219 @@
+//! Data-quality stats for the columns of the records read by sources and written by sinks: how
+//! often each is null or missing, its smallest and largest values, and an estimate of how many
+//! distinct values it has. These make upstream schema changes and bad deployments (like a field
+//! that is suddenly always null) quick to spot.
+//!
+//! Stats are only computed when the pipeline's COLUMN_STATS feature flag is set, and then over one
+//! in every `SAMPLE_RATE` records. Records are profiled as JSON; nested fields are columns named by
+//! their dotted path.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use arroyo_rpc::column_stats::{add_to_sketch, compare_values, merge_columns, value_hash};
+use arroyo_rpc::grpc::{ColumnStats, OperatorColumnStats};
+use arroyo_types::{pipeline_feature_enabled, TaskInfo, COLUMN_STATS_FEATURE};
+use lazy_static::lazy_static;
+use serde_json::{Map, Value};
+
+/// One in this many records is profiled
+pub const SAMPLE_RATE: u64 = 10;
+
+// columns beyond this many (which are usually from unexpectedly nested data) aren't tracked
+const MAX_COLUMNS: usize = 200;
+
+#[derive(Default)]
+struct Column {
+    count: u64,
+    nulls: u64,
+    min: Option<Value>,
+    max: Option<Value>,
+    distinct_sketch: Vec<u64>,
+}
+
+impl Column {
+    fn add(&mut self, value: &Value) {
+        self.count += 1;
+        if value.is_null() {
+            self.nulls += 1;
+            return;
+        }
+
+        add_to_sketch(&mut self.distinct_sketch, value_hash(value));
+
+        if compare_values(value, value).is_some() {
+            if self.min.as_ref().map_or(true, |min| {
+                compare_values(value, min) == Some(Ordering::Less)
+            }) {
+                self.min = Some(value.clone());
+            }
+            if self.max.as_ref().map_or(true, |max| {
+                compare_values(value, max) == Some(Ordering::Greater)
+            }) {
+                self.max = Some(value.clone());
+            }
+        }
+    }
+
+    fn stats(&self, name: &str) -> ColumnStats {
+        ColumnStats {
+            column: name.to_string(),
+            count: self.count,
+            nulls: self.nulls,
+            min: self.min.as_ref().map(|v| v.to_string()),
+            max: self.max.as_ref().map(|v| v.to_string()),
+            distinct_sketch: self.distinct_sketch.clone(),
+        }
+    }
+}
+
+type Columns = Arc<Mutex<BTreeMap<String, Column>>>;
+
+lazy_static! {
+    static ref ENABLED: bool = pipeline_feature_enabled(COLUMN_STATS_FEATURE);
+    // (operator id, subtask index) -> stats of its columns
+    static ref PROFILES: Mutex<HashMap<(String, u32), Columns>> = Mutex::new(HashMap::new());
+}
+
+/// Whether column stats are enabled for the pipeline this worker is running
+pub fn enabled() -> bool {
+    *ENABLED
+}
+
+pub struct ColumnProfiler {
+    columns: Columns,
+    records: u64,
+}
+
+impl ColumnProfiler {
+    /// Creates the stats for a subtask, replacing those of any previous run of it
+    pub fn register(task_info: &TaskInfo) -> Self {
+        let columns = Columns::default();
+        PROFILES.lock().unwrap().insert(
+            (task_info.operator_id.clone(), task_info.task_index as u32),
+            columns.clone(),
+        );
+        Self {
+            columns,
+            records: 0,
+        }
+    }
+
+    /// Counts a record, returning whether it's one that should be profiled
+    pub fn sample(&mut self) -> bool {
+        self.records += 1;
+        self.records % SAMPLE_RATE == 1
+    }
+
+    /// Profiles a record; records that aren't JSON objects are ignored
+    pub fn add(&self, record: &Value) {
+        let Value::Object(fields) = record else {
+            return;
+        };
+
+        let mut values = HashMap::new();
+        flatten("", fields, &mut values);
+
+        let mut columns = self.columns.lock().unwrap();
+        for (name, column) in columns.iter_mut() {
+            if !values.contains_key(name) {
+                column.add(&Value::Null);
+            }
+        }
+
+        for (name, value) in values {
+            if !columns.contains_key(&name) && columns.len() >= MAX_COLUMNS {
+                continue;
+            }
+            columns.entry(name).or_default().add(value);
+        }
+    }
+}
+
+fn flatten<'a>(
+    prefix: &str,
+    fields: &'a Map<String, Value>,
+    values: &mut HashMap<String, &'a Value>,
+) {
+    for (name, value) in fields {
+        let name = format!("{}{}", prefix, name);
+        match value {
+            Value::Object(fields) => flatten(&format!("{}.", name), fields, values),
+            value => {
+                values.insert(name, value);
+            }
+        }
+    }
+}
+
+/// The stats of the sources and sinks running on this worker, merged across their subtasks
+pub fn stats() -> Vec<OperatorColumnStats> {
+    let profiles: Vec<_> = PROFILES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((operator_id, _), columns)| (operator_id.clone(), columns.clone()))
+        .collect();
+
+    let mut operators: BTreeMap<String, Vec<ColumnStats>> = BTreeMap::new();
+    for (operator_id, columns) in profiles {
+        let columns: Vec<_> = columns
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, column)| column.stats(name))
+            .collect();
+        merge_columns(operators.entry(operator_id).or_default(), columns);
+    }
+
+    operators
+        .into_iter()
+        .map(|(operator_id, columns)| OperatorColumnStats {
+            operator_id,
+            columns,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_rpc::column_stats::distinct_estimate;
+    use arroyo_types::get_test_task_info;
+    use serde_json::json;
+
+    #[test]
+    fn test_profile_columns() {
+        let mut task_info = get_test_task_info();
+        task_info.operator_id = "profiled_source".to_string();
+        let profiler = ColumnProfiler::register(&task_info);
+
+        profiler.add(&json!({"id": 1, "user": {"name": "a"}}));
+        profiler.add(&json!({"id": 7, "user": {"name": null}}));
+        profiler.add(&json!({"id": 3, "user": {"name": "c"}, "extra": true}));
+        profiler.add(&json!({"id": 3}));
+        profiler.add(&json!("not an object"));
+
+        let stats = stats();
+        let operator = stats
+            .iter()
+            .find(|o| o.operator_id == "profiled_source")
+            .unwrap();
+        let column = |name: &str| operator.columns.iter().find(|c| c.column == name).unwrap();
+
+        let id = column("id");
+        assert_eq!((4, 0), (id.count, id.nulls));
+        assert_eq!(Some("1"), id.min.as_deref());
+        assert_eq!(Some("7"), id.max.as_deref());
+        assert_eq!(3, distinct_estimate(&id.distinct_sketch));
+
+        // missing fields count as nulls, from when the column was first seen
+        let name = column("user.name");
+        assert_eq!((4, 2), (name.count, name.nulls));
+        assert_eq!(Some("\"c\""), name.max.as_deref());
+
+        let extra = column("extra");
+        assert_eq!((2, 1), (extra.count, extra.nulls));
+    }
+}
@@ -0,0 +1,253 @@
+//! Shared batching policy for sinks that buffer records and write them in batches. Each sink keeps
+//! its own pending writes (which may be deduplicated or grouped in connector-specific ways), and
+//! uses a [`Batcher`] to decide when to flush them and to report batch metrics.
+//!
+//! A batch is flushed when it reaches the maximum number of records or bytes, when its oldest
+//! record has waited for the maximum linger time, on every checkpoint barrier, and when the
+//! operator closes.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use arroyo_metrics::{counter_for_task, histogram_for_task};
+use arroyo_types::{TaskInfo, SINK_BATCH_BYTES, SINK_BATCH_RECORDS, SINK_FLUSHES};
+use prometheus::{Histogram, IntCounter};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::BatchingConfig;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FlushCause {
+    MaxRecords,
+    MaxBytes,
+    Linger,
+    Checkpoint,
+    Close,
+}
+
+impl FlushCause {
+    const ALL: [FlushCause; 5] = [
+        FlushCause::MaxRecords,
+        FlushCause::MaxBytes,
+        FlushCause::Linger,
+        FlushCause::Checkpoint,
+        FlushCause::Close,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            FlushCause::MaxRecords => "max_records",
+            FlushCause::MaxBytes => "max_bytes",
+            FlushCause::Linger => "linger",
+            FlushCause::Checkpoint => "checkpoint",
+            FlushCause::Close => "close",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchPolicy {
+    pub max_records: usize,
+    /// Records are measured by the size of their JSON encoding, which is only computed if this
+    /// is set
+    pub max_bytes: Option<usize>,
+    pub max_linger: Option<Duration>,
+}
+
+impl BatchPolicy {
+    /// The policy configured for the operator, falling back to `default_max_records` (usually the
+    /// connector's own batch size setting) if the maximum number of records isn't set
+    pub fn from_config(batching: Option<BatchingConfig>, default_max_records: usize) -> Self {
+        let batching = batching.as_ref();
+        let positive = |v: Option<i64>| v.filter(|v| *v > 0).map(|v| v as usize);
+
+        BatchPolicy {
+            max_records: positive(batching.and_then(|b| b.max_records))
+                .unwrap_or(default_max_records)
+                .max(1),
+            max_bytes: positive(batching.and_then(|b| b.max_bytes)),
+            max_linger: positive(batching.and_then(|b| b.max_linger_micros))
+                .map(|micros| Duration::from_micros(micros as u64)),
+        }
+    }
+}
+
+struct BatchMetrics {
+    records: Option<Histogram>,
+    bytes: Option<Histogram>,
+    flushes: HashMap<FlushCause, IntCounter>,
+}
+
+pub struct Batcher {
+    policy: BatchPolicy,
+    records: usize,
+    bytes: usize,
+    oldest: Option<Instant>,
+    metrics: Option<BatchMetrics>,
+}
+
+impl Batcher {
+    pub fn new(policy: BatchPolicy) -> Self {
+        Self {
+            policy,
+            records: 0,
+            bytes: 0,
+            oldest: None,
+            metrics: None,
+        }
+    }
+
+    /// Registers the batch metrics for the subtask; called from the sink's `on_start`
+    pub fn register_metrics(&mut self, task_info: &TaskInfo) {
+        let exponential = |start: f64| prometheus::exponential_buckets(start, 4.0, 10).unwrap();
+
+        self.metrics = Some(BatchMetrics {
+            records: histogram_for_task(
+                task_info,
+                SINK_BATCH_RECORDS,
+                "Number of records in each batch written by this sink",
+                HashMap::new(),
+                exponential(1.0),
+            ),
+            bytes: self.policy.max_bytes.and_then(|_| {
+                histogram_for_task(
+                    task_info,
+                    SINK_BATCH_BYTES,
+                    "Size in bytes of each batch written by this sink",
+                    HashMap::new(),
+                    exponential(256.0),
+                )
+            }),
+            flushes: FlushCause::ALL
+                .iter()
+                .filter_map(|cause| {
+                    let mut labels = HashMap::new();
+                    labels.insert("cause".to_string(), cause.as_str().to_string());
+                    Some((
+                        *cause,
+                        counter_for_task(
+                            task_info,
+                            SINK_FLUSHES,
+                            "Count of batches flushed by this sink, by what caused the flush",
+                            labels,
+                        )?,
+                    ))
+                })
+                .collect(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    /// Adds a record to the current batch, returning the cause if the batch should now be
+    /// flushed
+    pub fn add<V: Serialize>(&mut self, value: &V) -> Option<FlushCause> {
+        if self.records == 0 {
+            self.oldest = Some(Instant::now());
+        }
+
+        self.records += 1;
+        if self.policy.max_bytes.is_some() {
+            self.bytes += serde_json::to_vec(value).map(|v| v.len()).unwrap_or(0);
+        }
+
+        if self.records >= self.policy.max_records {
+            Some(FlushCause::MaxRecords)
+        } else if self.policy.max_bytes.map(|max| self.bytes >= max) == Some(true) {
+            Some(FlushCause::MaxBytes)
+        } else {
+            None
+        }
+    }
+
+    /// How often the sink should check whether the current batch has lingered for too long
+    pub fn tick_interval(&self) -> Option<Duration> {
+        self.policy
+            .max_linger
+            .map(|linger| (linger / 4).max(Duration::from_millis(1)))
+    }
+
+    /// Whether the oldest record of the current batch has waited for the maximum linger time
+    pub fn linger_expired(&self) -> bool {
+        match (self.oldest, self.policy.max_linger) {
+            (Some(oldest), Some(linger)) => oldest.elapsed() >= linger,
+            _ => false,
+        }
+    }
+
+    /// Records that the current batch has been flushed, and starts a new one
+    pub fn flushed(&mut self, cause: FlushCause) {
+        if self.records == 0 {
+            return;
+        }
+
+        if let Some(metrics) = &self.metrics {
+            if let Some(h) = &metrics.records {
+                h.observe(self.records as f64);
+            }
+            if let Some(h) = &metrics.bytes {
+                h.observe(self.bytes as f64);
+            }
+            if let Some(c) = metrics.flushes.get(&cause) {
+                c.inc();
+            }
+        }
+
+        self.records = 0;
+        self.bytes = 0;
+        self.oldest = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_records: usize, max_bytes: Option<usize>) -> BatchPolicy {
+        BatchPolicy {
+            max_records,
+            max_bytes,
+            max_linger: Some(Duration::from_millis(100)),
+        }
+    }
+
+    #[test]
+    fn test_flush_on_max_records() {
+        let mut batcher = Batcher::new(policy(3, None));
+        assert_eq!(None, batcher.add(&1));
+        assert_eq!(None, batcher.add(&2));
+        assert_eq!(Some(FlushCause::MaxRecords), batcher.add(&3));
+
+        batcher.flushed(FlushCause::MaxRecords);
+        assert!(batcher.is_empty());
+        assert_eq!(None, batcher.add(&4));
+    }
+
+    #[test]
+    fn test_flush_on_max_bytes() {
+        let mut batcher = Batcher::new(policy(100, Some(10)));
+        assert_eq!(None, batcher.add(&"abc"));
+        assert_eq!(Some(FlushCause::MaxBytes), batcher.add(&"abcdef"));
+    }
+
+    #[test]
+    fn test_linger() {
+        let mut batcher = Batcher::new(policy(100, None));
+        assert!(!batcher.linger_expired());
+
+        batcher.add(&1);
+        std::thread::sleep(Duration::from_millis(60));
+        batcher.add(&2);
+        assert!(!batcher.linger_expired());
+
+        // linger is measured from the oldest record in the batch
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(batcher.linger_expired());
+
+        batcher.flushed(FlushCause::Linger);
+        assert!(!batcher.linger_expired());
+    }
+}
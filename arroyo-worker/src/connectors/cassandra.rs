@@ -0,0 +1,549 @@
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::tls;
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use scylla::batch::{Batch, BatchType};
+use scylla::frame::response::result::{ColumnType, CqlValue};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::statement::Consistency;
+use scylla::{Session, SessionBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+
+use super::batching::{BatchPolicy, Batcher, FlushCause};
+use super::connection_pool::ConnectionPermit;
+use super::{ConnectionPoolConfig, OperatorConfig, OperatorConfigSerializationMode};
+
+import_types!(schema = "../connector-schemas/cassandra/connection.json");
+import_types!(schema = "../connector-schemas/cassandra/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+type Row = Vec<Option<CqlValue>>;
+
+enum Write {
+    Upsert(Row),
+    Delete(Row),
+}
+
+struct Statements {
+    // the columns bound by the insert statement, in order
+    columns: Vec<String>,
+    insert: PreparedStatement,
+    delete: PreparedStatement,
+}
+
+#[derive(StreamNode)]
+pub struct CassandraSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    connection: CassandraConfig,
+    connection_pool: Option<ConnectionPoolConfig>,
+    permit: Option<ConnectionPermit>,
+    table: CassandraTable,
+    updating: bool,
+    batcher: Batcher,
+    session: Option<Session>,
+    // (column name, is partition key) for each primary key column, in key order
+    primary_key: Vec<(String, bool)>,
+    table_columns: HashSet<String>,
+    statements: Option<Statements>,
+    // pending writes, grouped by partition and then by primary key so that each partition
+    // can be written as a single token-aware batch and later writes to a row replace earlier ones
+    pending: HashMap<String, HashMap<String, Write>>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> CassandraSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for CassandraSink");
+        let connection: CassandraConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for CassandraSink");
+        let table: CassandraTable =
+            serde_json::from_value(config.table).expect("Invalid table config for CassandraSink");
+
+        Self {
+            connection,
+            connection_pool: config.connection_pool,
+            permit: None,
+            batcher: Batcher::new(BatchPolicy::from_config(
+                config.batching,
+                table
+                    .batch_size
+                    .map(|s| s.max(1) as usize)
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+            )),
+            table,
+            updating: matches!(
+                config.serialization_mode,
+                Some(OperatorConfigSerializationMode::DebeziumJson)
+            ),
+            session: None,
+            primary_key: vec![],
+            table_columns: HashSet::new(),
+            statements: None,
+            pending: HashMap::new(),
+            _t: PhantomData,
+        }
+    }
+
+    fn qualified_table(&self) -> String {
+        format!("\"{}\".\"{}\"", self.table.keyspace, self.table.table)
+    }
+
+    fn consistency(&self) -> Consistency {
+        match self.table.consistency {
+            None | Some(ConsistencyLevel::LocalQuorum) => Consistency::LocalQuorum,
+            Some(ConsistencyLevel::Any) => Consistency::Any,
+            Some(ConsistencyLevel::One) => Consistency::One,
+            Some(ConsistencyLevel::Two) => Consistency::Two,
+            Some(ConsistencyLevel::Three) => Consistency::Three,
+            Some(ConsistencyLevel::Quorum) => Consistency::Quorum,
+            Some(ConsistencyLevel::All) => Consistency::All,
+            Some(ConsistencyLevel::EachQuorum) => Consistency::EachQuorum,
+            Some(ConsistencyLevel::LocalOne) => Consistency::LocalOne,
+        }
+    }
+
+    async fn load_table(&mut self) {
+        let session = self.session.as_ref().unwrap();
+        let rows = session
+            .query(
+                "SELECT column_name, kind, position FROM system_schema.columns \
+                WHERE keyspace_name = ? AND table_name = ?",
+                (&self.table.keyspace, &self.table.table),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to read schema of {}: {}", self.qualified_table(), e)
+            })
+            .rows_typed::<(String, String, i32)>()
+            .expect("Unexpected schema for system_schema.columns")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Unexpected schema for system_schema.columns");
+
+        if rows.is_empty() {
+            panic!("Table {} does not exist", self.qualified_table());
+        }
+
+        let mut key: Vec<_> = rows
+            .iter()
+            .filter(|(_, kind, _)| kind == "partition_key" || kind == "clustering")
+            .map(|(name, kind, position)| (kind == "clustering", *position, name.clone()))
+            .collect();
+        key.sort();
+
+        self.primary_key = key
+            .into_iter()
+            .map(|(clustering, _, name)| (name, !clustering))
+            .collect();
+        self.table_columns = rows.into_iter().map(|(name, _, _)| name).collect();
+    }
+
+    /// Prepares the insert and delete statements, using the columns of the first record that
+    /// exist in the table
+    async fn prepare(&mut self, fields: &serde_json::Map<String, Value>) -> Result<(), String> {
+        let mut columns: Vec<String> = fields
+            .keys()
+            .filter(|k| self.table_columns.contains(*k))
+            .cloned()
+            .collect();
+        columns.sort();
+
+        for (key, _) in &self.primary_key {
+            if !columns.contains(key) {
+                return Err(format!(
+                    "primary key column '{}' is not a field of the record",
+                    key
+                ));
+            }
+        }
+
+        let quoted: Vec<_> = columns.iter().map(|c| format!("\"{}\"", c)).collect();
+        let insert = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.qualified_table(),
+            quoted.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+
+        let delete = format!(
+            "DELETE FROM {} WHERE {}",
+            self.qualified_table(),
+            self.primary_key
+                .iter()
+                .map(|(c, _)| format!("\"{}\" = ?", c))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        );
+
+        let session = self.session.as_ref().unwrap();
+        let mut insert = session
+            .prepare(insert)
+            .await
+            .map_err(|e| format!("failed to prepare insert: {}", e))?;
+        let mut delete = session
+            .prepare(delete)
+            .await
+            .map_err(|e| format!("failed to prepare delete: {}", e))?;
+
+        insert.set_consistency(self.consistency());
+        delete.set_consistency(self.consistency());
+
+        self.statements = Some(Statements {
+            columns,
+            insert,
+            delete,
+        });
+
+        Ok(())
+    }
+
+    /// Builds the write for this record; fields of the record key (if it is a struct)
+    /// take precedence over fields of the same name in the value
+    async fn to_write(&mut self, record: &Record<K, T>) -> Result<(String, String, Write), String> {
+        let mut value = serde_json::to_value(&record.value).map_err(|e| e.to_string())?;
+
+        let (fields, delete) = if self.updating {
+            let delete = value.get("op").and_then(|op| op.as_str()) == Some("d");
+            let field = if delete { "before" } else { "after" };
+            (
+                value.get_mut(field).map(Value::take).unwrap_or(Value::Null),
+                delete,
+            )
+        } else {
+            (value, false)
+        };
+
+        let mut fields = match fields {
+            Value::Object(fields) => fields,
+            other => {
+                return Err(format!(
+                    "expected a struct to write to Cassandra, found {}",
+                    other
+                ))
+            }
+        };
+
+        if let Some(key) = &record.key {
+            if let Ok(Value::Object(key_fields)) = serde_json::to_value(key) {
+                fields.extend(key_fields);
+            }
+        }
+
+        if self.statements.is_none() {
+            self.prepare(&fields).await?;
+        }
+        let statements = self.statements.as_ref().unwrap();
+
+        let mut partition = vec![];
+        let mut primary_key = vec![];
+        let mut key_values = vec![];
+        for (i, (column, is_partition)) in self.primary_key.iter().enumerate() {
+            let value = fields.get(column).unwrap_or(&Value::Null);
+            if value.is_null() {
+                return Err(format!("record is missing primary key column '{}'", column));
+            }
+
+            let typ = &statements.delete.get_prepared_metadata().col_specs[i].typ;
+            let value = to_cql_value(value, typ)
+                .map_err(|e| format!("invalid value for column '{}': {}", column, e))?;
+
+            if *is_partition {
+                partition.push(format!("{:?}", value));
+            }
+            primary_key.push(format!("{:?}", value));
+            key_values.push(value);
+        }
+
+        let write = if delete {
+            Write::Delete(key_values)
+        } else {
+            let metadata = statements.insert.get_prepared_metadata();
+            Write::Upsert(
+                statements
+                    .columns
+                    .iter()
+                    .zip(metadata.col_specs.iter())
+                    .map(|(column, spec)| {
+                        to_cql_value(fields.get(column).unwrap_or(&Value::Null), &spec.typ)
+                            .map_err(|e| format!("invalid value for column '{}': {}", column, e))
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        };
+
+        Ok((partition.join("\u{0}"), primary_key.join("\u{0}"), write))
+    }
+
+    /// Writes all pending rows, as one unlogged batch per partition. As every statement in a
+    /// batch targets the same partition, the driver routes it directly to a replica.
+    async fn flush(&mut self, cause: FlushCause) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let pending = std::mem::take(&mut self.pending);
+        let statements = self.statements.as_ref().unwrap();
+        let session = self.session.as_ref().unwrap();
+
+        for (_, rows) in pending {
+            let mut batch = Batch::new(BatchType::Unlogged);
+            batch.set_consistency(self.consistency());
+            let mut values = Vec::with_capacity(rows.len());
+
+            for (_, write) in rows {
+                match write {
+                    Write::Upsert(row) => {
+                        batch.append_statement(statements.insert.clone());
+                        values.push(row);
+                    }
+                    Write::Delete(key) => {
+                        batch.append_statement(statements.delete.clone());
+                        values.push(key);
+                    }
+                }
+            }
+
+            let mut attempt = 0;
+            while let Err(e) = session.batch(&batch, &values).await {
+                attempt += 1;
+                if attempt >= MAX_ATTEMPTS {
+                    panic!(
+                        "Failed to write to {} after {} attempts: {}",
+                        self.qualified_table(),
+                        attempt,
+                        e
+                    );
+                }
+
+                warn!("Batch write to Cassandra failed, retrying: {}", e);
+                tokio::time::sleep(
+                    (Duration::from_millis(50) * 2u32.pow(attempt.min(10))).min(MAX_BACKOFF),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> CassandraSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!(
+            "cassandra-sink-{}.{}",
+            self.table.keyspace, self.table.table
+        )
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        for node in self.connection.hosts.split(',') {
+            let host = node.rsplit_once(':').map(|(h, _)| h).unwrap_or(node);
+            if let Err(e) = check_egress(host) {
+                ctx.report_error("Connection not allowed".to_string(), e.clone())
+                    .await;
+                panic!("{}", e);
+            }
+        }
+
+        self.permit = ConnectionPermit::acquire(&self.connection_pool, 1, &ctx.task_info).await;
+        info!("Connecting to Cassandra at {}", *self.connection.hosts);
+        let mut builder = SessionBuilder::new().known_nodes(self.connection.hosts.split(','));
+        if let Authentication::Password { username, password } = &self.connection.authentication {
+            builder = builder.user(username, password);
+        }
+
+        if let Some(t) = &self.connection.tls {
+            let context = tls::TlsConfig::from_generated(t)
+                .openssl_context()
+                .unwrap_or_else(|e| panic!("Invalid TLS config for Cassandra: {}", e));
+            builder = builder.ssl_context(Some(context));
+        }
+
+        self.session = Some(
+            builder
+                .build()
+                .await
+                .unwrap_or_else(|e| panic!("Failed to connect to Cassandra: {}", e)),
+        );
+
+        self.load_table().await;
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match self.to_write(record).await {
+            Ok((partition, key, write)) => {
+                self.pending
+                    .entry(partition)
+                    .or_default()
+                    .insert(key, write);
+
+                if let Some(cause) = self.batcher.add(&record.value) {
+                    self.flush(cause).await;
+                }
+            }
+            Err(e) => {
+                ctx.report_error("Could not write record to Cassandra".to_string(), e)
+                    .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, _: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint).await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close).await;
+    }
+}
+
+fn to_cql_value(value: &Value, typ: &ColumnType) -> Result<Option<CqlValue>, String> {
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    let mismatch = || format!("cannot convert {} to {:?}", value, typ);
+
+    let int = || value.as_i64().ok_or_else(mismatch);
+
+    Ok(Some(match typ {
+        ColumnType::Ascii => CqlValue::Ascii(value.as_str().ok_or_else(mismatch)?.to_string()),
+        ColumnType::Text => CqlValue::Text(match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }),
+        ColumnType::Boolean => CqlValue::Boolean(value.as_bool().ok_or_else(mismatch)?),
+        ColumnType::TinyInt => CqlValue::TinyInt(int()?.try_into().map_err(|_| mismatch())?),
+        ColumnType::SmallInt => CqlValue::SmallInt(int()?.try_into().map_err(|_| mismatch())?),
+        ColumnType::Int => CqlValue::Int(int()?.try_into().map_err(|_| mismatch())?),
+        ColumnType::BigInt => CqlValue::BigInt(int()?),
+        ColumnType::Counter => CqlValue::Counter(scylla::frame::value::Counter(int()?)),
+        ColumnType::Float => CqlValue::Float(value.as_f64().ok_or_else(mismatch)? as f32),
+        ColumnType::Double => CqlValue::Double(value.as_f64().ok_or_else(mismatch)?),
+        ColumnType::Timestamp => CqlValue::Timestamp(chrono::Duration::milliseconds(
+            timestamp_millis(value).ok_or_else(mismatch)?,
+        )),
+        ColumnType::Uuid => CqlValue::Uuid(
+            value
+                .as_str()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(mismatch)?,
+        ),
+        ColumnType::Blob => CqlValue::Blob(match value {
+            Value::Array(bytes) => bytes
+                .iter()
+                .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(mismatch)?,
+            Value::String(s) => s.as_bytes().to_vec(),
+            _ => return Err(mismatch()),
+        }),
+        ColumnType::List(inner) | ColumnType::Set(inner) => {
+            let values = value
+                .as_array()
+                .ok_or_else(mismatch)?
+                .iter()
+                .map(|v| to_cql_value(v, inner)?.ok_or_else(mismatch))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if matches!(typ, ColumnType::List(_)) {
+                CqlValue::List(values)
+            } else {
+                CqlValue::Set(values)
+            }
+        }
+        ColumnType::Map(key, inner) => CqlValue::Map(
+            value
+                .as_object()
+                .ok_or_else(mismatch)?
+                .iter()
+                .map(|(k, v)| {
+                    Ok((
+                        to_cql_value(&Value::String(k.clone()), key)?.ok_or_else(mismatch)?,
+                        to_cql_value(v, inner)?.ok_or_else(mismatch)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        ),
+        _ => return Err(format!("unsupported column type {:?}", typ)),
+    }))
+}
+
+/// Timestamps are serialized either as a `SystemTime` or as an RFC3339 string
+fn timestamp_millis(value: &Value) -> Option<i64> {
+    match value {
+        Value::Object(fields) => {
+            let secs = fields.get("secs_since_epoch")?.as_i64()?;
+            let nanos = fields.get("nanos_since_epoch")?.as_i64()?;
+            Some(secs * 1000 + nanos / 1_000_000)
+        }
+        Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.timestamp_millis()),
+        Value::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+    use serde_json::json;
+
+    use super::to_cql_value;
+
+    #[test]
+    fn test_cql_values() {
+        assert_eq!(
+            Some(CqlValue::Int(5)),
+            to_cql_value(&json!(5), &ColumnType::Int).unwrap()
+        );
+        assert!(to_cql_value(&json!(1u64 << 40), &ColumnType::Int).is_err());
+        assert_eq!(None, to_cql_value(&json!(null), &ColumnType::Text).unwrap());
+
+        assert_eq!(
+            Some(CqlValue::Timestamp(chrono::Duration::milliseconds(1500))),
+            to_cql_value(
+                &json!({"secs_since_epoch": 1, "nanos_since_epoch": 500_000_000}),
+                &ColumnType::Timestamp
+            )
+            .unwrap()
+        );
+
+        assert_eq!(
+            Some(CqlValue::List(vec![
+                CqlValue::Text("a".to_string()),
+                CqlValue::Text("b".to_string())
+            ])),
+            to_cql_value(
+                &json!(["a", "b"]),
+                &ColumnType::List(Box::new(ColumnType::Text))
+            )
+            .unwrap()
+        );
+    }
+}
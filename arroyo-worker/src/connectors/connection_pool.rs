@@ -0,0 +1,118 @@
+//! Permits for connections to external systems whose connections are limited across all of the
+//! jobs in the cluster. Connectors acquire a permit from the controller before connecting, and
+//! hold it (renewing its lease in the background) until they're dropped.
+
+use std::time::Duration;
+
+use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
+use arroyo_rpc::grpc::{AcquireConnectionPermitsReq, ReleaseConnectionPermitsReq};
+use arroyo_server_common::tls;
+use arroyo_types::{TaskInfo, CONNECTION_PERMIT_LEASE};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use super::ConnectionPoolConfig;
+
+const MAX_WAIT_BACKOFF: Duration = Duration::from_secs(30);
+
+pub struct ConnectionPermit {
+    release: Option<oneshot::Sender<()>>,
+}
+
+impl ConnectionPermit {
+    /// Waits until `permits` connections may be opened to the pool's system, or returns
+    /// immediately if the table isn't in a pool
+    pub async fn acquire(
+        pool: &Option<ConnectionPoolConfig>,
+        permits: u32,
+        task_info: &TaskInfo,
+    ) -> Option<Self> {
+        let pool = pool.as_ref()?;
+
+        let controller_addr = std::env::var(arroyo_types::CONTROLLER_ADDR_ENV)
+            .unwrap_or_else(|_| crate::LOCAL_CONTROLLER_ADDR.to_string());
+        let mut client = ControllerGrpcClient::new(
+            tls::connect_addr(controller_addr)
+                .await
+                .expect("Failed to connect to controller"),
+        );
+
+        let req = AcquireConnectionPermitsReq {
+            pool: pool.name.clone(),
+            max_connections: pool.max_connections as u32,
+            job_id: task_info.job_id.clone(),
+            operator_id: task_info.operator_id.clone(),
+            task_index: task_info.task_index as u32,
+            permits,
+        };
+
+        let mut backoff = Duration::from_millis(500);
+        loop {
+            let resp = client
+                .acquire_connection_permits(req.clone())
+                .await
+                .unwrap_or_else(|e| panic!("Failed to acquire connection permits: {}", e))
+                .into_inner();
+
+            if resp.granted {
+                info!(
+                    "Acquired {} connection permits from pool '{}' ({}/{} in use)",
+                    permits, pool.name, resp.in_use, pool.max_connections
+                );
+                break;
+            }
+
+            warn!(
+                "Waiting for connection permits from pool '{}' ({}/{} in use)",
+                pool.name, resp.in_use, pool.max_connections
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_WAIT_BACKOFF);
+        }
+
+        let (tx, mut rx) = oneshot::channel();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(CONNECTION_PERMIT_LEASE / 3) => {
+                        // the connections are already open, so there's nothing to do if the
+                        // controller (for example after a restart) no longer has room for them
+                        match client.acquire_connection_permits(req.clone()).await {
+                            Ok(resp) if !resp.get_ref().granted => {
+                                warn!("Connection pool '{}' is over its limit", req.pool);
+                            }
+                            Ok(_) => {}
+                            Err(e) => {
+                                warn!("Failed to renew connection permits from pool '{}': {}", req.pool, e);
+                            }
+                        }
+                    }
+                    _ = &mut rx => {
+                        if let Err(e) = client
+                            .release_connection_permits(ReleaseConnectionPermitsReq {
+                                pool: req.pool.clone(),
+                                job_id: req.job_id.clone(),
+                                operator_id: req.operator_id.clone(),
+                                task_index: req.task_index,
+                            })
+                            .await
+                        {
+                            warn!("Failed to release connection permits from pool '{}': {}", req.pool, e);
+                        }
+                        return;
+                    }
+                }
+            }
+        });
+
+        Some(Self { release: Some(tx) })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            let _ = release.send(());
+        }
+    }
+}
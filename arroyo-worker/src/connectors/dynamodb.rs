@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use lazy_static::lazy_static;
+use regex::Regex;
+use rusoto_core::{Region, RusotoError};
+use rusoto_dynamodb::{
+    AttributeValue, BatchWriteItemInput, DeleteItemError, DeleteItemInput, DeleteRequest, DynamoDb,
+    DynamoDbClient, PutItemError, PutItemInput, PutRequest, UpdateItemError, UpdateItemInput,
+    WriteRequest,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info, warn};
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+
+use super::batching::{BatchPolicy, Batcher, FlushCause};
+use super::{aws_host, OperatorConfig, OperatorConfigSerializationMode};
+
+import_types!(schema = "../connector-schemas/dynamodb/connection.json");
+import_types!(schema = "../connector-schemas/dynamodb/table.json");
+
+const MAX_BATCH_SIZE: usize = 25;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r":([A-Za-z0-9_]+)").unwrap();
+}
+
+type Item = HashMap<String, AttributeValue>;
+
+enum Write {
+    Upsert(Item),
+    Delete(Item),
+}
+
+#[derive(StreamNode)]
+pub struct DynamoDbSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    table: DynamoDbTable,
+    region: Region,
+    updating: bool,
+    batcher: Batcher,
+    client: Option<DynamoDbClient>,
+    // pending batched writes, keyed by the item's primary key; DynamoDB rejects batches that
+    // contain the same key twice, so later writes to a key replace earlier ones
+    pending: HashMap<String, WriteRequest>,
+    conditions_failed: u64,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> DynamoDbSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for DynamoDbSink");
+        let connection: DynamoDbConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for DynamoDbSink");
+        let table: DynamoDbTable =
+            serde_json::from_value(config.table).expect("Invalid table config for DynamoDbSink");
+
+        let region = match connection.endpoint {
+            Some(endpoint) => Region::Custom {
+                name: connection.region,
+                endpoint,
+            },
+            None => Region::from_str(&connection.region).expect("Invalid AWS region"),
+        };
+
+        let mut batch_policy = BatchPolicy::from_config(
+            config.batching,
+            table
+                .batch_size
+                .map(|s| s as usize)
+                .unwrap_or(MAX_BATCH_SIZE),
+        );
+        // BatchWriteItem accepts at most 25 items
+        batch_policy.max_records = batch_policy.max_records.min(MAX_BATCH_SIZE);
+
+        Self {
+            batcher: Batcher::new(batch_policy),
+            table,
+            region,
+            updating: matches!(
+                config.serialization_mode,
+                Some(OperatorConfigSerializationMode::DebeziumJson)
+            ),
+            client: None,
+            pending: HashMap::new(),
+            conditions_failed: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn key_attributes(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.table.partition_key).chain(self.table.sort_key.iter())
+    }
+
+    /// Builds the item to write from the record; fields of the record key (if it is a struct)
+    /// take precedence over fields of the same name in the value
+    fn to_write(&self, record: &Record<K, T>) -> Result<Write, String> {
+        let mut value = serde_json::to_value(&record.value).map_err(|e| e.to_string())?;
+
+        let (fields, delete) = if self.updating {
+            let delete = value.get("op").and_then(|op| op.as_str()) == Some("d");
+            let field = if delete { "before" } else { "after" };
+            (
+                value.get_mut(field).map(Value::take).unwrap_or(Value::Null),
+                delete,
+            )
+        } else {
+            (value, false)
+        };
+
+        let mut fields = match fields {
+            Value::Object(fields) => fields,
+            other => {
+                return Err(format!(
+                    "expected a struct to write to DynamoDB, found {}",
+                    other
+                ))
+            }
+        };
+
+        if let Some(key) = &record.key {
+            if let Ok(Value::Object(key_fields)) = serde_json::to_value(key) {
+                fields.extend(key_fields);
+            }
+        }
+
+        for key in self.key_attributes() {
+            match fields.get(key) {
+                None | Some(Value::Null) => {
+                    return Err(format!("record is missing key attribute '{}'", key));
+                }
+                _ => {}
+            }
+        }
+
+        let item: Item = fields
+            .iter()
+            .map(|(k, v)| (k.clone(), to_attribute_value(v)))
+            .collect();
+
+        Ok(if delete {
+            Write::Delete(self.key_of(&item))
+        } else {
+            Write::Upsert(item)
+        })
+    }
+
+    fn key_of(&self, item: &Item) -> Item {
+        self.key_attributes()
+            .filter_map(|k| Some((k.clone(), item.get(k)?.clone())))
+            .collect()
+    }
+
+    fn pending_key(&self, item: &Item) -> String {
+        self.key_attributes()
+            .map(|k| format!("{:?}", item.get(k)))
+            .collect::<Vec<_>>()
+            .join("\u{0}")
+    }
+
+    /// Binds `:field` placeholders in the condition expression to the corresponding attributes
+    /// of the item being written
+    fn condition_values(&self, item: &Item) -> Option<Item> {
+        let condition = self.table.condition_expression.as_ref()?;
+        let values: Item = PLACEHOLDER
+            .captures_iter(condition)
+            .map(|c| {
+                let value = item.get(&c[1]).cloned().unwrap_or_else(|| AttributeValue {
+                    null: Some(true),
+                    ..Default::default()
+                });
+                (c[0].to_string(), value)
+            })
+            .collect();
+
+        (!values.is_empty()).then_some(values)
+    }
+
+    async fn write(&mut self, write: Write, value: &T) {
+        if self.table.condition_expression.is_some() || self.table.write_mode == WriteMode::Update {
+            // conditional writes and updates are not supported by BatchWriteItem
+            let applied = match write {
+                Write::Upsert(item) if self.table.write_mode == WriteMode::Update => {
+                    self.update_item(item).await
+                }
+                Write::Upsert(item) => self.put_item(item).await,
+                Write::Delete(key) => self.delete_item(key).await,
+            };
+
+            if !applied {
+                self.conditions_failed += 1;
+            }
+            return;
+        }
+
+        let (key, request) = match write {
+            Write::Upsert(item) => (
+                self.pending_key(&item),
+                WriteRequest {
+                    put_request: Some(PutRequest { item }),
+                    delete_request: None,
+                },
+            ),
+            Write::Delete(key) => (
+                self.pending_key(&key),
+                WriteRequest {
+                    put_request: None,
+                    delete_request: Some(DeleteRequest { key }),
+                },
+            ),
+        };
+
+        self.pending.insert(key, request);
+
+        if let Some(cause) = self.batcher.add(value) {
+            self.flush(cause).await;
+        }
+    }
+
+    async fn put_item(&mut self, item: Item) -> bool {
+        let input = PutItemInput {
+            table_name: self.table.table_name.clone(),
+            condition_expression: self.table.condition_expression.clone(),
+            expression_attribute_values: self.condition_values(&item),
+            item,
+            ..Default::default()
+        };
+
+        let client = self.client.as_ref().unwrap();
+        let mut attempt = 0;
+        loop {
+            match client.put_item(input.clone()).await {
+                Ok(_) => return true,
+                Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => return false,
+                Err(e) => retry_or_panic("PutItem", &mut attempt, e).await,
+            }
+        }
+    }
+
+    async fn update_item(&mut self, item: Item) -> bool {
+        let key = self.key_of(&item);
+        let mut names = HashMap::new();
+        let mut values = self.condition_values(&item).unwrap_or_default();
+        let mut assignments = vec![];
+
+        for (i, (name, value)) in item
+            .into_iter()
+            .filter(|(name, _)| !key.contains_key(name))
+            .enumerate()
+        {
+            names.insert(format!("#__a{}", i), name);
+            values.insert(format!(":__v{}", i), value);
+            assignments.push(format!("#__a{} = :__v{}", i, i));
+        }
+
+        let input = UpdateItemInput {
+            table_name: self.table.table_name.clone(),
+            key,
+            update_expression: (!assignments.is_empty())
+                .then(|| format!("SET {}", assignments.join(", "))),
+            condition_expression: self.table.condition_expression.clone(),
+            expression_attribute_names: (!names.is_empty()).then_some(names),
+            expression_attribute_values: (!values.is_empty()).then_some(values),
+            ..Default::default()
+        };
+
+        let client = self.client.as_ref().unwrap();
+        let mut attempt = 0;
+        loop {
+            match client.update_item(input.clone()).await {
+                Ok(_) => return true,
+                Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => {
+                    return false
+                }
+                Err(e) => retry_or_panic("UpdateItem", &mut attempt, e).await,
+            }
+        }
+    }
+
+    async fn delete_item(&mut self, key: Item) -> bool {
+        let input = DeleteItemInput {
+            table_name: self.table.table_name.clone(),
+            condition_expression: self.table.condition_expression.clone(),
+            expression_attribute_values: self.condition_values(&key),
+            key,
+            ..Default::default()
+        };
+
+        let client = self.client.as_ref().unwrap();
+        let mut attempt = 0;
+        loop {
+            match client.delete_item(input.clone()).await {
+                Ok(_) => return true,
+                Err(RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_))) => {
+                    return false
+                }
+                Err(e) => retry_or_panic("DeleteItem", &mut attempt, e).await,
+            }
+        }
+    }
+
+    /// Writes all pending items with BatchWriteItem, retrying any unprocessed items with
+    /// exponential backoff until they have all been accepted
+    async fn flush(&mut self, cause: FlushCause) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let mut requests: Vec<WriteRequest> = self.pending.drain().map(|(_, r)| r).collect();
+        let client = self.client.as_ref().unwrap();
+        let mut attempt = 0;
+
+        while !requests.is_empty() {
+            let mut request_items = HashMap::new();
+            request_items.insert(self.table.table_name.clone(), requests.clone());
+
+            match client
+                .batch_write_item(BatchWriteItemInput {
+                    request_items,
+                    ..Default::default()
+                })
+                .await
+            {
+                Ok(output) => {
+                    requests = output
+                        .unprocessed_items
+                        .and_then(|mut items| items.remove(&self.table.table_name))
+                        .unwrap_or_default();
+
+                    if !requests.is_empty() {
+                        debug!(
+                            "{} items were unprocessed by DynamoDB, retrying",
+                            requests.len()
+                        );
+                        attempt += 1;
+                        if attempt >= MAX_ATTEMPTS {
+                            panic!(
+                                "Failed to write {} items to DynamoDB after {} attempts",
+                                requests.len(),
+                                attempt
+                            );
+                        }
+                        tokio::time::sleep(backoff(attempt)).await;
+                    }
+                }
+                Err(e) => retry_or_panic("BatchWriteItem", &mut attempt, e).await,
+            }
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> DynamoDbSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("dynamodb-sink-{}", self.table.table_name)
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        if let Err(e) = check_egress(&aws_host("dynamodb", &self.region)) {
+            ctx.report_error("Connection not allowed".to_string(), e.clone())
+                .await;
+            panic!("{}", e);
+        }
+
+        info!("Creating DynamoDB client for {:?}", self.region);
+        self.client = Some(DynamoDbClient::new(self.region.clone()));
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match self.to_write(record) {
+            Ok(write) => self.write(write, &record.value).await,
+            Err(e) => {
+                ctx.report_error("Could not write record to DynamoDB".to_string(), e)
+                    .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, _: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint).await;
+
+        if self.conditions_failed > 0 {
+            debug!(
+                "{} writes to {} were skipped because their condition did not hold",
+                self.conditions_failed, self.table.table_name
+            );
+            self.conditions_failed = 0;
+        }
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close).await;
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    (Duration::from_millis(50) * 2u32.pow(attempt.min(10))).min(MAX_BACKOFF)
+}
+
+async fn retry_or_panic<E: std::error::Error>(op: &str, attempt: &mut u32, e: RusotoError<E>) {
+    *attempt += 1;
+    if *attempt >= MAX_ATTEMPTS {
+        panic!(
+            "{} to DynamoDB failed after {} attempts: {}",
+            op, attempt, e
+        );
+    }
+
+    warn!("{} to DynamoDB failed, retrying: {}", op, e);
+    tokio::time::sleep(backoff(*attempt)).await;
+}
+
+fn to_attribute_value(value: &Value) -> AttributeValue {
+    match value {
+        Value::Null => AttributeValue {
+            null: Some(true),
+            ..Default::default()
+        },
+        Value::Bool(b) => AttributeValue {
+            bool: Some(*b),
+            ..Default::default()
+        },
+        Value::Number(n) => AttributeValue {
+            n: Some(n.to_string()),
+            ..Default::default()
+        },
+        Value::String(s) => AttributeValue {
+            s: Some(s.clone()),
+            ..Default::default()
+        },
+        Value::Array(values) => AttributeValue {
+            l: Some(values.iter().map(to_attribute_value).collect()),
+            ..Default::default()
+        },
+        Value::Object(fields) => AttributeValue {
+            m: Some(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), to_attribute_value(v)))
+                    .collect(),
+            ),
+            ..Default::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{to_attribute_value, PLACEHOLDER};
+
+    #[test]
+    fn test_attribute_values() {
+        let value = to_attribute_value(&json!({
+            "id": "a",
+            "count": 5,
+            "tags": ["x"],
+            "missing": null,
+        }));
+
+        let fields = value.m.unwrap();
+        assert_eq!(Some("a".to_string()), fields["id"].s);
+        assert_eq!(Some("5".to_string()), fields["count"].n);
+        assert_eq!(
+            Some("x".to_string()),
+            fields["tags"].l.as_ref().unwrap()[0].s
+        );
+        assert_eq!(Some(true), fields["missing"].null);
+    }
+
+    #[test]
+    fn test_condition_placeholders() {
+        let placeholders: Vec<_> = PLACEHOLDER
+            .captures_iter("attribute_not_exists(id) OR updated_at < :updated_at")
+            .map(|c| c[1].to_string())
+            .collect();
+
+        assert_eq!(vec!["updated_at".to_string()], placeholders);
+    }
+}
@@ -0,0 +1,542 @@
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+use arroyo_macro::process_fn;
+use arroyo_server_common::http::{HttpClient, RetryPolicy};
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+
+use super::batching::{BatchPolicy, Batcher, FlushCause};
+use super::{OperatorConfig, OperatorConfigSerializationMode};
+
+import_types!(schema = "../connector-schemas/elasticsearch/connection.json");
+import_types!(schema = "../connector-schemas/elasticsearch/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+
+/// A part of an index name: either literal text, or a date pattern that's formatted with the
+/// record's event time
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IndexPart {
+    Literal(String),
+    Date(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexName {
+    parts: Vec<IndexPart>,
+}
+
+impl IndexName {
+    fn parse(index: &str) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut rest = index;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("index '{}' contains an unclosed '{{'", index))?;
+            let pattern = &rest[start + 1..start + end];
+            if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+                return Err(format!("invalid date pattern '{}' in index", pattern));
+            }
+            if start > 0 {
+                parts.push(IndexPart::Literal(rest[..start].to_string()));
+            }
+            parts.push(IndexPart::Date(pattern.to_string()));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(IndexPart::Literal(rest.to_string()));
+        }
+
+        Ok(Self { parts })
+    }
+
+    fn for_time(&self, timestamp: SystemTime) -> String {
+        let time: DateTime<Utc> = timestamp.into();
+        self.parts
+            .iter()
+            .map(|part| match part {
+                IndexPart::Literal(s) => s.clone(),
+                IndexPart::Date(pattern) => time.format(pattern).to_string(),
+            })
+            .collect()
+    }
+}
+
+/// A single operation of a bulk request
+#[derive(Debug, Clone, PartialEq)]
+struct BulkAction {
+    index: String,
+    id: Option<String>,
+    // the document to index, or None to delete the document with the id
+    document: Option<Value>,
+}
+
+impl BulkAction {
+    fn write_to(&self, body: &mut Vec<u8>) {
+        let mut metadata = json!({ "_index": self.index });
+        if let Some(id) = &self.id {
+            metadata["_id"] = Value::String(id.clone());
+        }
+        let action = if self.document.is_some() {
+            "index"
+        } else {
+            "delete"
+        };
+
+        serde_json::to_writer(&mut *body, &json!({ action: metadata })).unwrap();
+        body.push(b'\n');
+        if let Some(document) = &self.document {
+            serde_json::to_writer(&mut *body, document).unwrap();
+            body.push(b'\n');
+        }
+    }
+}
+
+/// The outcome of an action of a bulk request
+#[derive(Debug, PartialEq)]
+enum ActionResult {
+    Ok,
+    // the cluster was overloaded, so the action should be retried
+    Retry,
+    Rejected(String),
+}
+
+#[derive(StreamNode)]
+pub struct ElasticsearchSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    endpoint: String,
+    headers: HeaderMap,
+    index: IndexName,
+    id_fields: Vec<String>,
+    updating: bool,
+    batcher: Batcher,
+    client: HttpClient,
+    retry: RetryPolicy,
+    pending: Vec<BulkAction>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> ElasticsearchSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for ElasticsearchSink");
+        let connection: ElasticsearchConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for ElasticsearchSink");
+        let table: ElasticsearchTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for ElasticsearchSink");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-ndjson"),
+        );
+        let authorization = match &connection.authentication {
+            ElasticsearchConfigAuthentication::None {} => None,
+            ElasticsearchConfigAuthentication::Basic { username, password } => Some(format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            )),
+            ElasticsearchConfigAuthentication::ApiKey { api_key } => {
+                Some(format!("ApiKey {}", api_key))
+            }
+        };
+        if let Some(authorization) = authorization {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::try_from(authorization).expect("Invalid credentials"),
+            );
+        }
+
+        let retry = RetryPolicy {
+            max_attempts: table
+                .max_attempts
+                .map(|a| a as u32)
+                .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        };
+        let timeout = table
+            .timeout_ms
+            .map(|t| Duration::from_millis(t as u64))
+            .unwrap_or(DEFAULT_TIMEOUT);
+
+        Self {
+            endpoint: connection.endpoint.trim_end_matches('/').to_string(),
+            headers,
+            index: IndexName::parse(&table.index).expect("Invalid index for ElasticsearchSink"),
+            id_fields: table
+                .id_fields
+                .as_deref()
+                .unwrap_or("")
+                .split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect(),
+            updating: matches!(
+                config.serialization_mode,
+                Some(OperatorConfigSerializationMode::DebeziumJson)
+            ),
+            batcher: Batcher::new(BatchPolicy::from_config(
+                config.batching,
+                table
+                    .batch_size
+                    .map(|s| s as usize)
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+            )),
+            client: HttpClient::with_client(
+                "elasticsearch_sink",
+                reqwest::Client::builder().timeout(timeout).build().unwrap(),
+            )
+            .retry_policy(retry.clone()),
+            retry,
+            pending: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    /// Builds the bulk action for the record; in the debezium format, deletes remove the
+    /// document of the deleted row
+    fn to_action(&self, record: &Record<K, T>) -> Result<BulkAction, String> {
+        let mut value = serde_json::to_value(&record.value).map_err(|e| e.to_string())?;
+
+        let (document, delete) = if self.updating {
+            let delete = value.get("op").and_then(|op| op.as_str()) == Some("d");
+            let field = if delete { "before" } else { "after" };
+            (
+                value.get_mut(field).map(Value::take).unwrap_or(Value::Null),
+                delete,
+            )
+        } else {
+            (value, false)
+        };
+
+        let key = record
+            .key
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+
+        Ok(BulkAction {
+            index: self.index.for_time(record.timestamp),
+            id: document_id(&self.id_fields, key.as_ref(), &document)?,
+            document: if delete { None } else { Some(document) },
+        })
+    }
+
+    /// Sends the pending actions, retrying those that the cluster rejects because it's overloaded
+    async fn flush(&mut self, cause: FlushCause, ctx: &mut Context<(), ()>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let mut actions = std::mem::take(&mut self.pending);
+        let mut attempt = 0;
+        while !actions.is_empty() {
+            let results = match self.bulk(&actions).await {
+                Ok(results) => results,
+                // the pipeline is restarted from its last checkpoint, which resends the records
+                Err(e) => panic!("Bulk request to {} failed: {}", self.endpoint, e),
+            };
+
+            let mut retries = vec![];
+            for (action, result) in actions.into_iter().zip(results) {
+                match result {
+                    ActionResult::Ok => {}
+                    ActionResult::Retry => retries.push(action),
+                    ActionResult::Rejected(reason) => {
+                        ctx.report_error(
+                            format!("Document rejected by index {}", action.index),
+                            reason,
+                        )
+                        .await;
+                    }
+                }
+            }
+
+            if retries.is_empty() {
+                break;
+            }
+
+            attempt += 1;
+            if attempt >= self.retry.max_attempts {
+                panic!(
+                    "{} documents were still rejected by {} after {} attempts",
+                    retries.len(),
+                    self.endpoint,
+                    attempt
+                );
+            }
+
+            warn!(
+                "{} documents were rejected by {} because it is overloaded; retrying",
+                retries.len(),
+                self.endpoint
+            );
+            tokio::time::sleep(self.retry.backoff(attempt - 1)).await;
+            actions = retries;
+        }
+    }
+
+    /// Sends a bulk request, returning the result of each of its actions
+    async fn bulk(&self, actions: &[BulkAction]) -> Result<Vec<ActionResult>, String> {
+        let mut body = vec![];
+        for action in actions {
+            action.write_to(&mut body);
+        }
+
+        let request = self
+            .client
+            .post(format!("{}/_bulk", self.endpoint))
+            .headers(self.headers.clone())
+            .body(body);
+
+        let response = self.client.send(request).await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("{}: {}", status, text));
+        }
+
+        let response: Value = response.json().await.map_err(|e| e.to_string())?;
+        bulk_results(&response, actions.len())
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> ElasticsearchSinkFunc<K, T> {
+    fn name(&self) -> String {
+        "elasticsearch-sink".to_string()
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        let host = url::Url::parse(&self.endpoint)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+        if let Err(e) = check_egress(&host) {
+            ctx.report_error("Connection not allowed".to_string(), e.clone())
+                .await;
+            panic!("{}", e);
+        }
+
+        info!("Indexing documents into {}", self.endpoint);
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match self.to_action(record) {
+            Ok(action) => {
+                self.pending.push(action);
+                if let Some(cause) = self.batcher.add(&record.value) {
+                    self.flush(cause, ctx).await;
+                }
+            }
+            Err(e) => {
+                ctx.report_error("Could not build document".to_string(), e)
+                    .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, ctx: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger, ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint, ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close, ctx).await;
+    }
+}
+
+/// The id of a row's document: the values of the id fields (read from the key if it has them,
+/// otherwise from the document) joined with underscores, or of all fields of the key if there
+/// are no id fields. Returns None if the cluster should generate the id.
+fn document_id(
+    id_fields: &[String],
+    key: Option<&Value>,
+    document: &Value,
+) -> Result<Option<String>, String> {
+    let values: Vec<&Value> = if id_fields.is_empty() {
+        match key {
+            Some(Value::Object(fields)) => fields.values().collect(),
+            Some(Value::Null) | None => return Ok(None),
+            Some(other) => vec![other],
+        }
+    } else {
+        id_fields
+            .iter()
+            .map(|field| {
+                key.and_then(|k| k.get(field))
+                    .or_else(|| document.get(field))
+                    .filter(|v| !v.is_null())
+                    .ok_or_else(|| format!("record is missing id field '{}'", field))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    Ok(Some(
+        values
+            .into_iter()
+            .map(|v| match v {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("_"),
+    ))
+}
+
+/// Parses the response to a bulk request into the results of its actions, which are listed in
+/// the order they were sent
+fn bulk_results(response: &Value, count: usize) -> Result<Vec<ActionResult>, String> {
+    let items = response
+        .get("items")
+        .and_then(|i| i.as_array())
+        .ok_or_else(|| "bulk response is missing items".to_string())?;
+    if items.len() != count {
+        return Err(format!(
+            "bulk response has {} items, but {} actions were sent",
+            items.len(),
+            count
+        ));
+    }
+
+    Ok(items
+        .iter()
+        .map(|item| {
+            // each item is keyed by its action type, like {"index": {"status": 201, ...}}
+            let Some((action, result)) = item.as_object().and_then(|o| o.iter().next()) else {
+                return ActionResult::Rejected(format!("invalid bulk response item {}", item));
+            };
+            let status = result.get("status").and_then(|s| s.as_u64()).unwrap_or(0);
+            match status {
+                200..=299 => ActionResult::Ok,
+                // deleting a document that doesn't exist leaves the index as it should be
+                404 if action == "delete" => ActionResult::Ok,
+                429 => ActionResult::Retry,
+                _ => ActionResult::Rejected(
+                    result
+                        .get("error")
+                        .map(|e| e.to_string())
+                        .unwrap_or_else(|| format!("status {}", status)),
+                ),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use super::{bulk_results, document_id, ActionResult, BulkAction, IndexName};
+
+    #[test]
+    fn test_index_name() {
+        let index = IndexName::parse("logs-{%Y.%m.%d}").unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(index.for_time(time), "logs-2023.11.14");
+
+        assert_eq!(IndexName::parse("events").unwrap().for_time(time), "events");
+        assert!(IndexName::parse("logs-{%Y").is_err());
+    }
+
+    #[test]
+    fn test_document_id() {
+        let document = json!({"user_id": 5, "event_id": "abc", "value": 1.5});
+        let fields = vec!["user_id".to_string(), "event_id".to_string()];
+
+        assert_eq!(
+            document_id(&fields, None, &document).unwrap(),
+            Some("5_abc".to_string())
+        );
+
+        // fields of the key take precedence
+        let key = json!({"user_id": 7});
+        assert_eq!(
+            document_id(&fields, Some(&key), &document).unwrap(),
+            Some("7_abc".to_string())
+        );
+
+        assert_eq!(
+            document_id(&[], Some(&key), &document).unwrap(),
+            Some("7".to_string())
+        );
+        assert_eq!(document_id(&[], None, &document).unwrap(), None);
+
+        assert!(document_id(&["missing".to_string()], None, &document).is_err());
+    }
+
+    #[test]
+    fn test_bulk_body() {
+        let mut body = vec![];
+        BulkAction {
+            index: "events".to_string(),
+            id: Some("1".to_string()),
+            document: Some(json!({"a": 1})),
+        }
+        .write_to(&mut body);
+        BulkAction {
+            index: "events".to_string(),
+            id: Some("2".to_string()),
+            document: None,
+        }
+        .write_to(&mut body);
+
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "{\"index\":{\"_index\":\"events\",\"_id\":\"1\"}}\n{\"a\":1}\n\
+            {\"delete\":{\"_index\":\"events\",\"_id\":\"2\"}}\n"
+        );
+    }
+
+    #[test]
+    fn test_bulk_results() {
+        let response = json!({
+            "errors": true,
+            "items": [
+                {"index": {"status": 201}},
+                {"index": {"status": 429, "error": {"type": "es_rejected_execution_exception"}}},
+                {"index": {"status": 400, "error": {"type": "mapper_parsing_exception"}}},
+                {"delete": {"status": 404}},
+            ]
+        });
+
+        let results = bulk_results(&response, 4).unwrap();
+        assert_eq!(results[0], ActionResult::Ok);
+        assert_eq!(results[1], ActionResult::Retry);
+        assert!(matches!(&results[2], ActionResult::Rejected(e) if e.contains("mapper_parsing")));
+        assert_eq!(results[3], ActionResult::Ok);
+
+        assert!(bulk_results(&response, 3).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{fs::File, io::Write, marker::PhantomData};
+
+use anyhow::anyhow;
+use arroyo_types::Data;
+use serde::Serialize;
+
+use crate::connectors::{CsvConfig, CsvQuoting};
+use crate::formats::csv::CsvOptions;
+
+use super::{
+    local::{CurrentFileRecovery, LocalWriter},
+    BatchBufferingWriter, FileSettings, FileSystemTable, FormatSettings, Quoting,
+};
+
+/// Formats records as lines of a csv file, starting the file with a header row if the table has
+/// one
+struct CsvLines {
+    options: CsvOptions,
+    header_written: bool,
+}
+
+impl CsvLines {
+    fn new(config: &FileSystemTable) -> Self {
+        let Some(FormatSettings::Csv {
+            delimiter,
+            quote,
+            has_header,
+            quoting,
+        }) = &config.format_settings
+        else {
+            unreachable!("csv writer for a table without csv format settings");
+        };
+
+        let quoting = quoting.as_ref().map(|q| match q {
+            Quoting::Necessary => CsvQuoting::Necessary,
+            Quoting::Always => CsvQuoting::Always,
+            Quoting::NonNumeric => CsvQuoting::NonNumeric,
+            Quoting::Never => CsvQuoting::Never,
+        });
+
+        Self {
+            options: CsvOptions::from_config(Some(&CsvConfig {
+                delimiter: delimiter.clone(),
+                quote: quote.clone(),
+                has_header: Some(*has_header),
+                quoting,
+            })),
+            header_written: false,
+        }
+    }
+
+    fn lines<D: Serialize>(&mut self, value: &D) -> anyhow::Result<Vec<u8>> {
+        let (header, record) = self
+            .options
+            .header_and_record(value)
+            .map_err(|e| anyhow!("{}: {}", e.name, e.details))?;
+
+        let mut lines = vec![];
+        if self.options.has_header() && !self.header_written {
+            lines.extend(header);
+            lines.push(b'\n');
+            self.header_written = true;
+        }
+        lines.extend(record);
+        lines.push(b'\n');
+        Ok(lines)
+    }
+}
+
+pub struct CsvWriter<D: Data + Serialize> {
+    lines: CsvLines,
+    current_buffer: Vec<u8>,
+    target_part_size: usize,
+    phantom: PhantomData<D>,
+}
+
+impl<D: Data + Serialize> BatchBufferingWriter for CsvWriter<D> {
+    type BatchData = D;
+
+    fn new(config: &FileSystemTable) -> Self {
+        let target_part_size = if let Some(FileSettings {
+            target_part_size: Some(target_part_size),
+            ..
+        }) = config.file_settings
+        {
+            target_part_size as usize
+        } else {
+            5 * 1024 * 1024
+        };
+        Self {
+            lines: CsvLines::new(config),
+            current_buffer: Vec::new(),
+            target_part_size,
+            phantom: PhantomData,
+        }
+    }
+
+    fn suffix() -> String {
+        "csv".to_string()
+    }
+
+    fn add_batch_data(&mut self, data: Self::BatchData) -> Option<Vec<u8>> {
+        self.current_buffer.extend(self.lines.lines(&data).unwrap());
+        if self.buffer_length() > self.target_part_size {
+            Some(self.evict_current_buffer())
+        } else {
+            None
+        }
+    }
+
+    fn buffer_length(&self) -> usize {
+        self.current_buffer.len()
+    }
+
+    fn evict_current_buffer(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.current_buffer)
+    }
+
+    fn get_trailing_bytes_for_checkpoint(&mut self) -> Option<Vec<u8>> {
+        if self.current_buffer.is_empty() {
+            None
+        } else {
+            Some(self.current_buffer.clone())
+        }
+    }
+
+    fn close(&mut self, final_batch: Option<Self::BatchData>) -> Option<Vec<u8>> {
+        if let Some(final_batch) = final_batch {
+            if let Some(final_batch) = self.add_batch_data(final_batch) {
+                return Some(final_batch);
+            }
+        }
+        if self.current_buffer.is_empty() {
+            None
+        } else {
+            Some(self.evict_current_buffer())
+        }
+    }
+}
+
+pub struct CsvLocalWriter {
+    tmp_path: String,
+    final_path: String,
+    file: File,
+    lines: CsvLines,
+}
+
+impl<D: Data + Serialize> LocalWriter<D> for CsvLocalWriter {
+    fn new(tmp_path: String, final_path: String, table_properties: &FileSystemTable) -> Self {
+        let file = File::create(&tmp_path).unwrap();
+        CsvLocalWriter {
+            tmp_path,
+            final_path,
+            file,
+            lines: CsvLines::new(table_properties),
+        }
+    }
+
+    fn file_suffix() -> &'static str {
+        "csv"
+    }
+
+    fn write(&mut self, value: D) -> anyhow::Result<()> {
+        self.file.write_all(&self.lines.lines(&value)?)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> anyhow::Result<usize> {
+        self.file.flush()?;
+        let size = self.file.metadata()?.len() as usize;
+        Ok(size)
+    }
+
+    fn close(&mut self) -> anyhow::Result<super::local::FilePreCommit> {
+        LocalWriter::<D>::sync(self)?;
+        Ok(super::local::FilePreCommit {
+            tmp_file: self.tmp_path.clone(),
+            destination: self.final_path.clone(),
+        })
+    }
+
+    fn checkpoint(&mut self) -> anyhow::Result<Option<super::local::CurrentFileRecovery>> {
+        let bytes_written = LocalWriter::<D>::sync(self)?;
+        if bytes_written > 0 {
+            Ok(Some(CurrentFileRecovery {
+                tmp_file: self.tmp_path.clone(),
+                bytes_written,
+                suffix: None,
+                destination: self.final_path.clone(),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
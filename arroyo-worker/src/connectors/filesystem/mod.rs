@@ -8,6 +8,7 @@ use std::{
 };
 
 use anyhow::{bail, Result};
+use arroyo_rpc::proxy::ProxyConfig;
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use futures::{stream::FuturesUnordered, Future};
@@ -16,7 +17,7 @@ use object_store::{
     aws::{AmazonS3Builder, AwsCredential},
     local::LocalFileSystem,
     path::Path,
-    CredentialProvider, MultipartId, ObjectStore, UploadPart,
+    ClientOptions, CredentialProvider, MultipartId, ObjectStore, UploadPart,
 };
 use rusoto_core::credential::{DefaultCredentialsProvider, ProvideAwsCredentials};
 use serde::{Deserialize, Serialize};
@@ -27,11 +28,13 @@ use typify::import_types;
 import_types!(schema = "../connector-schemas/filesystem/table.json");
 
 use arroyo_types::*;
+pub mod csv;
 pub mod json;
 pub mod local;
 pub mod parquet;
 
 use self::{
+    csv::{CsvLocalWriter, CsvWriter},
     json::{JsonLocalWriter, JsonWriter, PassThrough},
     local::{LocalFileSystemWriter, LocalWriter},
     parquet::{FixedSizeRecordBatchBuilder, ParquetLocalWriter, RecordBatchBufferingWriter},
@@ -65,6 +68,11 @@ pub type LocalParquetFileSystemSink<K, T, R> = LocalFileSystemWriter<K, T, Parqu
 
 pub type LocalJsonFileSystemSink<K, T> = LocalFileSystemWriter<K, T, JsonLocalWriter>;
 
+pub type CsvFileSystemSink<K, T> =
+    FileSystemSink<K, T, BatchMultipartWriter<PassThrough<T>, CsvWriter<T>>>;
+
+pub type LocalCsvFileSystemSink<K, T> = LocalFileSystemWriter<K, T, CsvLocalWriter>;
+
 impl<K: Key, T: Data + Sync, V: LocalWriter<T>> LocalFileSystemWriter<K, T, V> {
     pub fn from_config(config_str: &str) -> TwoPhaseCommitterOperator<K, T, Self> {
         let config: OperatorConfig =
@@ -79,7 +87,13 @@ impl<K: Key, T: Data + Sync, V: LocalWriter<T>> LocalFileSystemWriter<K, T, V> {
                 unreachable!("shouldn't be using local writer for S3");
             }
             Destination::FolderUri { path } => {
-                object_store::parse_url(&url::Url::parse(&path).unwrap()).unwrap()
+                let url = url::Url::parse(&path).unwrap();
+                if let Some(host) = url.host_str() {
+                    if let Err(e) = check_egress(host) {
+                        panic!("{}", e);
+                    }
+                }
+                object_store::parse_url(&url).unwrap()
             }
         };
         let writer = LocalFileSystemWriter::new(path.to_string(), table);
@@ -104,6 +118,22 @@ impl<K: Key, T: Data + Sync, R: MultiPartWriter<InputType = T> + Send + 'static>
                 s3_directory,
                 aws_region,
             } => {
+                let host = format!("{}.s3.{}.amazonaws.com", s3_bucket, aws_region);
+                if let Err(e) = check_egress(&host) {
+                    panic!("{}", e);
+                }
+
+                // the S3 client reads the global proxy from the environment itself, so we only
+                // need to configure it when the table overrides it
+                let mut client_options = ClientOptions::new();
+                if let Some(proxy) = table.proxy.as_ref().map(ProxyConfig::from_generated) {
+                    if let Some(url) =
+                        ProxyConfig::resolve(Some(proxy)).and_then(|p| p.for_host(&host))
+                    {
+                        client_options = client_options.with_proxy_url(url.to_string());
+                    }
+                }
+
                 (
                     Box::new(
                         // use default credentials
@@ -111,6 +141,7 @@ impl<K: Key, T: Data + Sync, R: MultiPartWriter<InputType = T> + Send + 'static>
                             .with_bucket_name(s3_bucket)
                             .with_credentials(Arc::new(S3Credentialing::try_new().unwrap()))
                             .with_region(aws_region)
+                            .with_client_options(client_options)
                             .build()
                             .unwrap(),
                     ),
@@ -201,7 +232,7 @@ pub enum InFlightPartCheckpoint {
     InProgressPart { part: usize, data: Vec<u8> },
 }
 
-struct S3Credentialing {
+pub(crate) struct S3Credentialing {
     credentials_provider: DefaultCredentialsProvider,
 }
 
@@ -212,7 +243,7 @@ impl Debug for S3Credentialing {
 }
 
 impl S3Credentialing {
-    fn try_new() -> Result<Self> {
+    pub(crate) fn try_new() -> Result<Self> {
         Ok(Self {
             credentials_provider: DefaultCredentialsProvider::new()?,
         })
@@ -1,5 +1,8 @@
-use crate::connectors::OperatorConfig;
+use crate::connectors::{OperatorConfig, OperatorConfigSerializationMode};
 use crate::engine::{Context, StreamNode};
+use crate::formats::csv::CsvOptions;
+use crate::operators::SerializationMode;
+use anyhow::anyhow;
 use arroyo_macro::process_fn;
 use arroyo_types::*;
 use fluvio::{Fluvio, FluvioConfig, TopicProducer};
@@ -17,6 +20,7 @@ pub struct FluvioSinkFunc<K: Key + Serialize, T: Data + Serialize> {
     topic: String,
     endpoint: Option<String>,
     producer: Option<TopicProducer>,
+    serialization_mode: SerializationMode,
     _t: PhantomData<(K, T)>,
 }
 
@@ -34,6 +38,13 @@ impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
             topic: table.topic,
             endpoint: table.endpoint,
             producer: None,
+            serialization_mode: match config.serialization_mode {
+                Some(OperatorConfigSerializationMode::RawBytes) => SerializationMode::RawBytes,
+                Some(OperatorConfigSerializationMode::Csv) => {
+                    SerializationMode::Csv(CsvOptions::from_config(config.csv.as_ref()))
+                }
+                _ => SerializationMode::Json,
+            },
             _t: PhantomData,
         }
     }
@@ -47,6 +58,13 @@ impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
 
     async fn get_producer(&mut self) -> anyhow::Result<TopicProducer> {
         info!("Creating fluvio producer for {:?}", self.endpoint);
+        if let Some(endpoint) = &self.endpoint {
+            let host = endpoint
+                .rsplit_once(':')
+                .map(|(h, _)| h)
+                .unwrap_or(endpoint);
+            check_egress(host).map_err(|e| anyhow!(e))?;
+        }
 
         let config: Option<FluvioConfig> = self
             .endpoint
@@ -66,8 +84,10 @@ impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
         match self.get_producer().await {
             Ok(producer) => {
                 self.producer = Some(producer);
+                ctx.report_connected().await;
             }
             Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
                 ctx.report_error(
                     "Failed to construct Fluvio producer".to_string(),
                     e.to_string(),
@@ -82,12 +102,20 @@ impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
         self.producer.as_mut().unwrap().flush().await.unwrap();
     }
 
-    async fn process_element(&mut self, record: &Record<K, T>, _ctx: &mut Context<(), ()>) {
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
         let k = record
             .key
             .as_ref()
             .map(|k| serde_json::to_string(k).unwrap());
-        let v = serde_json::to_string(&record.value).unwrap();
+        let v = match self.serialization_mode.serialize(&record.value) {
+            Ok(v) => v,
+            Err(e) => {
+                ctx.report_error(e.name, e.details).await;
+                return;
+            }
+        };
 
         self.producer
             .as_mut()
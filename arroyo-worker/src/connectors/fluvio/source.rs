@@ -1,5 +1,6 @@
 use crate::connectors::{OperatorConfig, OperatorConfigSerializationMode};
 use crate::engine::{Context, StreamNode};
+use crate::formats::csv::CsvOptions;
 use crate::SourceFinishType;
 use anyhow::anyhow;
 use arroyo_macro::source_fn;
@@ -19,7 +20,7 @@ use tokio::select;
 use tokio_stream::{Stream, StreamExt, StreamMap};
 use tracing::{debug, error, info};
 
-use crate::operators::{SerializationMode, UserError};
+use crate::operators::{BadData, SerializationMode, UserError};
 
 use super::{FluvioTable, SourceOffset, TableType};
 
@@ -33,6 +34,8 @@ where
     endpoint: Option<String>,
     offset_mode: SourceOffset,
     serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
     _t: PhantomData<(K, T)>,
 }
 
@@ -63,6 +66,8 @@ where
             endpoint: endpoint.map(|e| e.to_string()),
             offset_mode,
             serialization_mode,
+            format_error: None,
+            bad_data: BadData::default(),
             _t: PhantomData,
         }
     }
@@ -76,21 +81,37 @@ where
             panic!("found non-source Fluvio config in source operator");
         };
 
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => {
+                Ok(SerializationMode::JsonSchemaRegistry)
+            }
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "Fluvio"))
+            }
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "Fluvio"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "Fluvio"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
         Self {
             topic: table.topic,
             endpoint: table.endpoint.clone(),
             offset_mode: *offset,
-            serialization_mode: match config.serialization_mode.unwrap() {
-                OperatorConfigSerializationMode::Json => SerializationMode::Json,
-                OperatorConfigSerializationMode::JsonSchemaRegistry => {
-                    SerializationMode::JsonSchemaRegistry
-                }
-                OperatorConfigSerializationMode::RawJson => SerializationMode::RawJson,
-                OperatorConfigSerializationMode::DebeziumJson => SerializationMode::Json,
-                OperatorConfigSerializationMode::Parquet => {
-                    unreachable!("Parquet in Fluvio doesn't make sense")
-                }
-            },
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
             _t: PhantomData,
         }
     }
@@ -109,6 +130,13 @@ where
     ) -> anyhow::Result<StreamMap<u32, impl Stream<Item = Result<ConsumerRecord, ErrorCode>>>> {
         // anyhow::Result<Vec<impl Stream<Item = >>> {
         info!("Creating Fluvio consumer for {:?}", self.endpoint);
+        if let Some(endpoint) = &self.endpoint {
+            let host = endpoint
+                .rsplit_once(':')
+                .map(|(h, _)| h)
+                .unwrap_or(endpoint);
+            check_egress(host).map_err(|e| anyhow!(e))?;
+        }
 
         let config: Option<FluvioConfig> = self
             .endpoint
@@ -177,6 +205,11 @@ where
     }
 
     async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
@@ -188,26 +221,47 @@ where
     }
 
     async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
-        let mut streams = self
-            .get_consumer(ctx)
-            .await
-            .map_err(|e| UserError::new("Could not create Fluvio consumer", format!("{:?}", e)))?;
+        let mut streams = match self.get_consumer(ctx).await {
+            Ok(streams) => streams,
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                return Err(UserError::new(
+                    "Could not create Fluvio consumer",
+                    format!("{:?}", e),
+                ));
+            }
+        };
+        ctx.report_connected().await;
+        let mut connected = true;
 
         let mut offsets = HashMap::new();
+        // set while watermark alignment is holding this source back
+        let mut paused = false;
         loop {
             select! {
-                message = streams.next() => {
+                message = streams.next(), if !paused => {
                     match message {
                         Some((_, Ok(msg))) => {
+                            if !connected {
+                                ctx.report_connected().await;
+                                connected = true;
+                            }
+                            let timestamp = from_millis(msg.timestamp().max(0) as u64);
+                            ctx.report_source_lag(timestamp);
+                            ctx.profile_source_record(msg.value());
                             ctx.collector.collect(Record {
-                                timestamp: from_millis(msg.timestamp().max(0) as u64),
+                                timestamp,
                                 key: None,
-                                value: self.serialization_mode.deserialize_slice(msg.value())?,
+                                value: self.serialization_mode.deserialize_slice(msg.value(), self.bad_data)?,
                             }).await;
                             offsets.insert(msg.partition(), msg.offset());
                         },
                         Some((p, Err(e))) => {
                             error!("encountered error {:?} while reading partition {}", e, p);
+                            if connected {
+                                ctx.report_disconnected(e.to_string()).await;
+                                connected = false;
+                            }
                         }
                         None => {
                             panic!("Stream closed");
@@ -241,11 +295,21 @@ where
                                 StopMode::Immediate => {
                                     return Ok(SourceFinishType::Immediate);
                                 }
+                                StopMode::Drain => {
+                                    return Ok(SourceFinishType::Final);
+                                }
                             }
                         }
                         Some(ControlMessage::Commit{..}) => {
                             return Err(UserError::new("Fluvio source does not support committing", ""));
                         }
+                        Some(ControlMessage::SetPaused { paused: p }) => {
+                            debug!("fluvio source {} paused: {}", ctx.task_info.task_index, p);
+                            paused = p;
+                        }
+                        Some(ControlMessage::InjectProbe { probe_id }) => {
+                            ctx.handle_probe(probe_id).await;
+                        }
                         None => {
 
                         }
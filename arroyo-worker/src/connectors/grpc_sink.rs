@@ -0,0 +1,427 @@
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::tls;
+use arroyo_types::{string_to_map, CheckpointBarrier, Data, Key, Record};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DeserializeOptions, DynamicMessage, MessageDescriptor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tonic::client::Grpc;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::{MetadataKey, MetadataValue};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Code, Request, Status};
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+
+use super::batching::{BatchPolicy, Batcher, FlushCause};
+use super::OperatorConfig;
+
+import_types!(schema = "../connector-schemas/grpc/connection.json");
+import_types!(schema = "../connector-schemas/grpc/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+struct Call {
+    path: PathAndQuery,
+    client_streaming: bool,
+    output: MessageDescriptor,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    max_attempts: u32,
+}
+
+#[derive(StreamNode)]
+pub struct GrpcSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    endpoint: String,
+    tls: Option<tls::TlsConfig>,
+    method: String,
+    input: MessageDescriptor,
+    call: Call,
+    batcher: Batcher,
+    max_concurrency: usize,
+    client: Option<Grpc<Channel>>,
+    pending: Vec<DynamicMessage>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> GrpcSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for GrpcSink");
+        let connection: GrpcConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for GrpcSink");
+        let table: GrpcTable =
+            serde_json::from_value(config.table).expect("Invalid table config for GrpcSink");
+
+        let pool = DescriptorPool::decode(
+            STANDARD
+                .decode(&table.descriptor_set)
+                .expect("Invalid descriptor set")
+                .as_slice(),
+        )
+        .expect("Invalid descriptor set");
+
+        let (service, method_name) = table.method.split_once('/').expect("Invalid method");
+        let method = pool
+            .get_service_by_name(service)
+            .and_then(|s| s.methods().find(|m| m.name() == method_name))
+            .unwrap_or_else(|| panic!("Method {} not found in descriptor set", *table.method));
+
+        Self {
+            endpoint: connection.endpoint,
+            tls: connection.tls.as_ref().map(tls::TlsConfig::from_generated),
+            method: (*table.method).clone(),
+            input: method.input(),
+            call: Call {
+                path: PathAndQuery::from_str(&format!("/{}", *table.method))
+                    .expect("Invalid method"),
+                client_streaming: method.is_client_streaming(),
+                output: method.output(),
+                headers: string_to_map(
+                    connection
+                        .headers
+                        .as_ref()
+                        .map(|h| h.0.as_str())
+                        .unwrap_or(""),
+                )
+                .expect("Invalid header map")
+                .into_iter()
+                .collect(),
+                timeout: table
+                    .timeout_ms
+                    .map(|t| Duration::from_millis(t as u64))
+                    .unwrap_or(DEFAULT_TIMEOUT),
+                max_attempts: table
+                    .max_attempts
+                    .map(|a| a as u32)
+                    .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            },
+            batcher: Batcher::new(BatchPolicy::from_config(
+                config.batching,
+                table
+                    .batch_size
+                    .map(|s| s as usize)
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+            )),
+            max_concurrency: table
+                .max_concurrency
+                .map(|c| c as usize)
+                .unwrap_or(DEFAULT_CONCURRENCY),
+            client: None,
+            pending: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    fn to_message(&self, record: &Record<K, T>) -> Result<DynamicMessage, String> {
+        let mut value = serde_json::to_value(&record.value).map_err(|e| e.to_string())?;
+        timestamps_to_rfc3339(&mut value);
+
+        DynamicMessage::deserialize_with_options(
+            self.input.clone(),
+            value,
+            &DeserializeOptions::new().deny_unknown_fields(false),
+        )
+        .map_err(|e| {
+            format!(
+                "could not convert record to {}: {}",
+                self.input.full_name(),
+                e
+            )
+        })
+    }
+
+    /// Sends all pending messages, returning the errors for any calls that were rejected with
+    /// a non-retryable status
+    async fn flush(&mut self, cause: FlushCause) -> Vec<Status> {
+        if self.pending.is_empty() {
+            return vec![];
+        }
+        self.batcher.flushed(cause);
+
+        let messages = std::mem::take(&mut self.pending);
+        let client = self.client.clone().unwrap();
+
+        if self.call.client_streaming {
+            return call(client, self.call.clone(), messages)
+                .await
+                .err()
+                .into_iter()
+                .collect();
+        }
+
+        futures::stream::iter(messages)
+            .map(|message| call(client.clone(), self.call.clone(), vec![message]))
+            .buffer_unordered(self.max_concurrency)
+            .filter_map(|r| async move { r.err() })
+            .collect()
+            .await
+    }
+
+    async fn flush_and_report(&mut self, cause: FlushCause, ctx: &mut Context<(), ()>) {
+        for status in self.flush(cause).await {
+            ctx.report_error(
+                format!("Call to {} failed", self.method),
+                format!("{:?}: {}", status.code(), status.message()),
+            )
+            .await;
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> GrpcSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("grpc-sink-{}", self.method)
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        info!("Connecting to gRPC service at {}", self.endpoint);
+        let mut endpoint = Endpoint::from_shared(self.endpoint.clone()).expect("Invalid endpoint");
+        if self.endpoint.starts_with("https") {
+            let tls = match &self.tls {
+                Some(tls) => tls.tonic_config().expect("Invalid TLS config"),
+                None => ClientTlsConfig::new(),
+            };
+            endpoint = endpoint.tls_config(tls).expect("Invalid TLS config");
+        }
+
+        // connect lazily so that an unavailable service is handled by the retry policy
+        self.client = Some(Grpc::new(endpoint.connect_lazy()));
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match self.to_message(record) {
+            Ok(message) => {
+                self.pending.push(message);
+                if let Some(cause) = self.batcher.add(&record.value) {
+                    self.flush_and_report(cause, ctx).await;
+                }
+            }
+            Err(e) => {
+                ctx.report_error("Could not convert record to protobuf".to_string(), e)
+                    .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, ctx: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush_and_report(FlushCause::Linger, ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush_and_report(FlushCause::Checkpoint, ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush_and_report(FlushCause::Close, ctx).await;
+    }
+}
+
+/// Makes a unary call (for a single message) or a client-streaming call, retrying retryable
+/// failures with exponential backoff; panics if the call still fails after the maximum attempts
+async fn call(
+    mut client: Grpc<Channel>,
+    call: Call,
+    messages: Vec<DynamicMessage>,
+) -> Result<(), Status> {
+    let mut attempt = 0;
+    loop {
+        let result = async {
+            client
+                .ready()
+                .await
+                .map_err(|e| Status::unavailable(e.to_string()))?;
+
+            let codec = DynamicCodec {
+                output: call.output.clone(),
+            };
+
+            if !call.client_streaming {
+                let mut request = Request::new(messages[0].clone());
+                prepare(&mut request, &call);
+                client
+                    .unary(request, call.path.clone(), codec)
+                    .await
+                    .map(|_| ())
+            } else {
+                let mut request = Request::new(futures::stream::iter(messages.clone()));
+                prepare(&mut request, &call);
+                client
+                    .client_streaming(request, call.path.clone(), codec)
+                    .await
+                    .map(|_| ())
+            }
+        }
+        .await;
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(status) if is_retryable(&status) => {
+                attempt += 1;
+                if attempt >= call.max_attempts {
+                    panic!(
+                        "Call to {} failed after {} attempts: {:?}: {}",
+                        call.path,
+                        attempt,
+                        status.code(),
+                        status.message()
+                    );
+                }
+
+                warn!(
+                    "Call to {} failed, retrying: {:?}: {}",
+                    call.path,
+                    status.code(),
+                    status.message()
+                );
+                tokio::time::sleep(
+                    (Duration::from_millis(100) * 2u32.pow(attempt.min(10))).min(MAX_BACKOFF),
+                )
+                .await;
+            }
+            Err(status) => return Err(status),
+        }
+    }
+}
+
+fn prepare<M>(request: &mut Request<M>, call: &Call) {
+    request.set_timeout(call.timeout);
+    for (k, v) in &call.headers {
+        match (
+            MetadataKey::from_bytes(k.to_lowercase().as_bytes()),
+            MetadataValue::try_from(v.as_str()),
+        ) {
+            (Ok(k), Ok(v)) => {
+                request.metadata_mut().insert(k, v);
+            }
+            _ => warn!("Skipping invalid gRPC header {}", k),
+        }
+    }
+}
+
+fn is_retryable(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted | Code::Aborted
+    )
+}
+
+/// Serialized `SystemTime`s are replaced by RFC3339 strings, which is how protobuf's JSON
+/// mapping represents `google.protobuf.Timestamp`
+pub(crate) fn timestamps_to_rfc3339(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            if let (Some(secs), Some(nanos), 2) = (
+                fields.get("secs_since_epoch").and_then(Value::as_u64),
+                fields.get("nanos_since_epoch").and_then(Value::as_u64),
+                fields.len(),
+            ) {
+                let time = UNIX_EPOCH + Duration::new(secs, nanos as u32);
+                *value = Value::String(
+                    chrono::DateTime::<chrono::Utc>::from(time)
+                        .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+                );
+            } else {
+                fields.values_mut().for_each(timestamps_to_rfc3339);
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(timestamps_to_rfc3339),
+        _ => {}
+    }
+}
+
+struct DynamicCodec {
+    output: MessageDescriptor,
+}
+
+impl Codec for DynamicCodec {
+    type Encode = DynamicMessage;
+    type Decode = DynamicMessage;
+    type Encoder = DynamicEncoder;
+    type Decoder = DynamicDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        DynamicEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        DynamicDecoder(self.output.clone())
+    }
+}
+
+struct DynamicEncoder;
+
+impl Encoder for DynamicEncoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(dst)
+            .map_err(|e| Status::internal(format!("failed to encode message: {}", e)))
+    }
+}
+
+struct DynamicDecoder(MessageDescriptor);
+
+impl Decoder for DynamicDecoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        DynamicMessage::decode(self.0.clone(), src)
+            .map(Some)
+            .map_err(|e| Status::internal(format!("failed to decode response: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::timestamps_to_rfc3339;
+
+    #[test]
+    fn test_timestamps() {
+        let mut value = json!({
+            "id": 1,
+            "at": {"secs_since_epoch": 1, "nanos_since_epoch": 0},
+            "nested": [{"secs_since_epoch": 2, "nanos_since_epoch": 0, "other": 3}],
+        });
+
+        timestamps_to_rfc3339(&mut value);
+
+        assert_eq!(
+            json!({
+                "id": 1,
+                "at": "1970-01-01T00:00:01Z",
+                "nested": [{"secs_since_epoch": 2, "nanos_since_epoch": 0, "other": 3}],
+            }),
+            value
+        );
+    }
+}
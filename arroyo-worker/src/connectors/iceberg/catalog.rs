@@ -0,0 +1,571 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_types::check_egress;
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use reqwest::{StatusCode, Url};
+use rusoto_core::Region;
+use rusoto_glue::{GetTableRequest, Glue, GlueClient, TableInput, UpdateTableRequest};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::connectors::filesystem::S3Credentialing;
+
+use super::{Catalog, IcebergTable};
+
+/// The parts of an Iceberg table's metadata that the sink needs to append to it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TableMetadata {
+    pub format_version: u8,
+    pub location: String,
+    #[serde(default)]
+    pub last_sequence_number: i64,
+    pub current_schema_id: Option<i32>,
+    #[serde(default)]
+    pub schemas: Vec<serde_json::Value>,
+    // v1 tables may only have a single schema
+    pub schema: Option<serde_json::Value>,
+    #[serde(default)]
+    pub default_spec_id: i32,
+    #[serde(default)]
+    pub partition_specs: Vec<PartitionSpec>,
+    pub current_snapshot_id: Option<i64>,
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PartitionSpec {
+    pub spec_id: i32,
+    pub fields: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Snapshot {
+    pub snapshot_id: i64,
+    pub manifest_list: Option<String>,
+    #[serde(default)]
+    pub summary: HashMap<String, String>,
+}
+
+impl TableMetadata {
+    /// The snapshot that new snapshots are based on; older tables use -1 when there is none
+    pub fn current_snapshot(&self) -> Option<&Snapshot> {
+        let id = self.current_snapshot_id.filter(|id| *id != -1)?;
+        self.snapshots.iter().find(|s| s.snapshot_id == id)
+    }
+
+    pub fn current_schema(&self) -> Result<&serde_json::Value> {
+        match self.current_schema_id {
+            Some(id) => self
+                .schemas
+                .iter()
+                .find(|s| s["schema-id"].as_i64() == Some(id as i64)),
+            None => self.schema.as_ref(),
+        }
+        .ok_or_else(|| anyhow!("table metadata is missing its current schema"))
+    }
+
+    pub fn is_partitioned(&self) -> bool {
+        self.partition_specs
+            .iter()
+            .any(|s| s.spec_id == self.default_spec_id && !s.fields.is_empty())
+    }
+
+    /// Whether a snapshot was committed with the given commit id, which happens when a commit is
+    /// retried after the job is restored
+    pub fn has_commit(&self, commit_id: &str) -> bool {
+        self.snapshots
+            .iter()
+            .any(|s| s.summary.get(COMMIT_ID_PROPERTY).map(|c| c.as_str()) == Some(commit_id))
+    }
+}
+
+/// The snapshot summary property holding the id of the commit that created the snapshot
+pub const COMMIT_ID_PROPERTY: &str = "arroyo.commit-id";
+
+/// A loaded version of a table, which a new snapshot is committed on top of
+pub struct LoadedTable {
+    pub metadata: TableMetadata,
+    // the full metadata, which is rewritten when committing to Glue
+    raw: serde_json::Value,
+    metadata_location: Option<String>,
+    glue_table: Option<rusoto_glue::Table>,
+    /// Properties for accessing the table's files, as understood by [`Storage::new`]
+    pub storage_config: HashMap<String, String>,
+}
+
+/// A new snapshot, whose manifest list has already been written
+pub struct NewSnapshot {
+    pub snapshot_id: i64,
+    pub parent_snapshot_id: Option<i64>,
+    pub sequence_number: i64,
+    pub manifest_list: String,
+    pub summary: HashMap<String, String>,
+    pub schema_id: Option<i32>,
+}
+
+impl NewSnapshot {
+    fn to_json(&self, format_version: u8) -> serde_json::Value {
+        let mut snapshot = json!({
+            "snapshot-id": self.snapshot_id,
+            "timestamp-ms": now_millis(),
+            "manifest-list": self.manifest_list,
+            "summary": self.summary,
+        });
+        if let Some(parent) = self.parent_snapshot_id {
+            snapshot["parent-snapshot-id"] = json!(parent);
+        }
+        if format_version > 1 {
+            snapshot["sequence-number"] = json!(self.sequence_number);
+        }
+        if let Some(schema_id) = self.schema_id {
+            snapshot["schema-id"] = json!(schema_id);
+        }
+        snapshot
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// The object store holding a table's files, which are referred to by full URIs in its metadata
+pub struct Storage {
+    store: Arc<dyn ObjectStore>,
+    // the part of the URIs identifying the store, like s3://bucket
+    root: String,
+}
+
+impl Storage {
+    pub fn path(&self, uri: &str) -> Result<Path> {
+        let path = uri
+            .strip_prefix(&self.root)
+            .ok_or_else(|| anyhow!("{} is not in the table's storage {}", uri, self.root))?;
+        Ok(Path::from(path))
+    }
+
+    pub async fn put(&self, uri: &str, data: Vec<u8>) -> Result<()> {
+        self.store.put(&self.path(uri)?, data.into()).await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, uri: &str) -> Result<Vec<u8>> {
+        Ok(self
+            .store
+            .get(&self.path(uri)?)
+            .await?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+}
+
+pub enum IcebergCatalog {
+    Rest {
+        client: reqwest::Client,
+        // the URL of the table's endpoint
+        table_url: Url,
+        token: Option<String>,
+        namespace: Vec<String>,
+        table_name: String,
+    },
+    Glue {
+        client: GlueClient,
+        region: String,
+        catalog_id: Option<String>,
+        database: String,
+        table_name: String,
+    },
+}
+
+fn rest_url(base: &str, segments: &[&str]) -> Result<Url> {
+    let mut url = Url::parse(base)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("invalid catalog URL {}", base))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+impl IcebergCatalog {
+    pub async fn new(catalog: &Catalog, table: &IcebergTable) -> Result<Self> {
+        match catalog {
+            Catalog::Rest {
+                url,
+                warehouse,
+                token,
+            } => {
+                let host = Url::parse(url)?
+                    .host_str()
+                    .ok_or_else(|| anyhow!("invalid catalog URL {}", url))?
+                    .to_string();
+                check_egress(&host).map_err(|e| anyhow!(e))?;
+
+                let client = reqwest::Client::new();
+                let mut config_url = rest_url(url, &["v1", "config"])?;
+                if let Some(warehouse) = warehouse {
+                    config_url
+                        .query_pairs_mut()
+                        .append_pair("warehouse", warehouse);
+                }
+
+                let mut req = client.get(config_url);
+                if let Some(token) = token {
+                    req = req.bearer_auth(token);
+                }
+                let config: serde_json::Value =
+                    req.send().await?.error_for_status()?.json().await?;
+
+                // nested namespaces are joined with the unit separator in REST catalog paths
+                let namespace = table.namespace.replace('.', "\u{1f}");
+                let mut segments = vec!["v1"];
+                if let Some(prefix) = config.pointer("/overrides/prefix").and_then(|p| p.as_str()) {
+                    segments.extend(prefix.split('/'));
+                }
+                segments.extend([
+                    "namespaces",
+                    namespace.as_str(),
+                    "tables",
+                    table.table_name.as_str(),
+                ]);
+
+                Ok(IcebergCatalog::Rest {
+                    client,
+                    table_url: rest_url(url, &segments)?,
+                    token: token.clone(),
+                    namespace: table.namespace.split('.').map(|s| s.to_string()).collect(),
+                    table_name: table.table_name.clone(),
+                })
+            }
+            Catalog::Glue { region, catalog_id } => Ok(IcebergCatalog::Glue {
+                client: GlueClient::new(
+                    Region::from_str(region)
+                        .map_err(|_| anyhow!("'{}' is not a valid AWS region", region))?,
+                ),
+                region: region.clone(),
+                catalog_id: catalog_id.clone(),
+                database: table.namespace.clone(),
+                table_name: table.table_name.clone(),
+            }),
+        }
+    }
+
+    /// Loads the current version of the table
+    pub async fn load(&self) -> Result<LoadedTable> {
+        match self {
+            IcebergCatalog::Rest {
+                client,
+                table_url,
+                token,
+                ..
+            } => {
+                let mut req = client.get(table_url.clone());
+                if let Some(token) = token {
+                    req = req.bearer_auth(token);
+                }
+                let resp = req.send().await?;
+                if resp.status() == StatusCode::NOT_FOUND {
+                    bail!("table {} does not exist", table_url);
+                }
+                let mut loaded: serde_json::Value = resp.error_for_status()?.json().await?;
+
+                let raw = loaded["metadata"].take();
+                Ok(LoadedTable {
+                    metadata: serde_json::from_value(raw.clone())?,
+                    raw,
+                    metadata_location: None,
+                    glue_table: None,
+                    // the catalog may vend the configuration for accessing the table's files
+                    storage_config: serde_json::from_value(loaded["config"].take())
+                        .unwrap_or_default(),
+                })
+            }
+            IcebergCatalog::Glue { region, .. } => {
+                let table = self.get_glue_table().await?;
+                let metadata_location = table
+                    .parameters
+                    .as_ref()
+                    .and_then(|p| p.get("metadata_location"))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Glue table has no Iceberg metadata location"))?;
+
+                let storage_config = HashMap::from([("s3.region".to_string(), region.clone())]);
+                let raw: serde_json::Value = serde_json::from_slice(
+                    &Storage::new(&metadata_location, &storage_config)?
+                        .get(&metadata_location)
+                        .await?,
+                )?;
+
+                Ok(LoadedTable {
+                    metadata: serde_json::from_value(raw.clone())?,
+                    raw,
+                    metadata_location: Some(metadata_location),
+                    glue_table: Some(table),
+                    storage_config,
+                })
+            }
+        }
+    }
+
+    async fn get_glue_table(&self) -> Result<rusoto_glue::Table> {
+        let IcebergCatalog::Glue {
+            client,
+            catalog_id,
+            database,
+            table_name,
+            ..
+        } = self
+        else {
+            unreachable!("not a Glue catalog");
+        };
+
+        client
+            .get_table(GetTableRequest {
+                catalog_id: catalog_id.clone(),
+                database_name: database.clone(),
+                name: table_name.clone(),
+            })
+            .await
+            .map_err(|e| anyhow!("failed to fetch {}.{}: {}", database, table_name, e))?
+            .table
+            .ok_or_else(|| anyhow!("table {}.{} does not exist", database, table_name))
+    }
+
+    /// Commits the snapshot as the table's current snapshot, returning false if the table was
+    /// changed since it was loaded, in which case the commit should be retried on its new version
+    pub async fn commit(
+        &self,
+        table: LoadedTable,
+        snapshot: &NewSnapshot,
+        storage: &Storage,
+    ) -> Result<bool> {
+        let format_version = table.metadata.format_version;
+        match self {
+            IcebergCatalog::Rest {
+                client,
+                table_url,
+                token,
+                namespace,
+                table_name,
+            } => {
+                let body = json!({
+                    "identifier": {
+                        "namespace": namespace,
+                        "name": table_name,
+                    },
+                    "requirements": [{
+                        "type": "assert-ref-snapshot-id",
+                        "ref": "main",
+                        "snapshot-id": snapshot.parent_snapshot_id,
+                    }],
+                    "updates": [
+                        {
+                            "action": "add-snapshot",
+                            "snapshot": snapshot.to_json(format_version),
+                        },
+                        {
+                            "action": "set-snapshot-ref",
+                            "ref-name": "main",
+                            "type": "branch",
+                            "snapshot-id": snapshot.snapshot_id,
+                        }
+                    ],
+                });
+
+                let mut req = client.post(table_url.clone()).json(&body);
+                if let Some(token) = token {
+                    req = req.bearer_auth(token);
+                }
+                let resp = req.send().await?;
+                if resp.status() == StatusCode::CONFLICT {
+                    return Ok(false);
+                }
+                if !resp.status().is_success() {
+                    let status = resp.status();
+                    bail!(
+                        "catalog rejected commit with {}: {}",
+                        status,
+                        resp.text().await.unwrap_or_default()
+                    );
+                }
+                Ok(true)
+            }
+            IcebergCatalog::Glue {
+                client,
+                catalog_id,
+                database,
+                ..
+            } => {
+                let previous_location = table.metadata_location.unwrap();
+                let glue_table = table.glue_table.unwrap();
+                let now = now_millis();
+
+                let mut raw = table.raw;
+                let previous_updated = raw["last-updated-ms"].clone();
+                push(&mut raw, "snapshots", snapshot.to_json(format_version));
+                push(
+                    &mut raw,
+                    "snapshot-log",
+                    json!({"timestamp-ms": now, "snapshot-id": snapshot.snapshot_id}),
+                );
+                push(
+                    &mut raw,
+                    "metadata-log",
+                    json!({"timestamp-ms": previous_updated, "metadata-file": previous_location}),
+                );
+                raw["current-snapshot-id"] = json!(snapshot.snapshot_id);
+                raw["last-updated-ms"] = json!(now);
+                raw["refs"]["main"] = json!({
+                    "snapshot-id": snapshot.snapshot_id,
+                    "type": "branch",
+                });
+                if format_version > 1 {
+                    raw["last-sequence-number"] = json!(snapshot.sequence_number);
+                }
+
+                let new_location = format!(
+                    "{}/metadata/{:05}-{}.metadata.json",
+                    table.metadata.location,
+                    metadata_version(&previous_location) + 1,
+                    uuid::Uuid::new_v4()
+                );
+                storage
+                    .put(&new_location, serde_json::to_vec(&raw)?)
+                    .await?;
+
+                // Glue has no conditional update, so check that the table wasn't changed
+                // concurrently as close to the update as we can
+                let current = self.get_glue_table().await?;
+                if current
+                    .parameters
+                    .as_ref()
+                    .and_then(|p| p.get("metadata_location"))
+                    != Some(&previous_location)
+                {
+                    return Ok(false);
+                }
+
+                let mut parameters = glue_table.parameters.unwrap_or_default();
+                parameters.insert("metadata_location".to_string(), new_location.clone());
+                parameters.insert("previous_metadata_location".to_string(), previous_location);
+
+                client
+                    .update_table(UpdateTableRequest {
+                        catalog_id: catalog_id.clone(),
+                        database_name: database.clone(),
+                        table_input: TableInput {
+                            name: glue_table.name,
+                            description: glue_table.description,
+                            owner: glue_table.owner,
+                            retention: glue_table.retention,
+                            storage_descriptor: glue_table.storage_descriptor,
+                            partition_keys: glue_table.partition_keys,
+                            table_type: glue_table.table_type,
+                            parameters: Some(parameters),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|e| anyhow!("failed to update Glue table: {}", e))?;
+
+                info!("Updated Glue table metadata to {}", new_location);
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn push(raw: &mut serde_json::Value, key: &str, value: serde_json::Value) {
+    match raw[key].as_array_mut() {
+        Some(values) => values.push(value),
+        None => raw[key] = json!([value]),
+    }
+}
+
+/// The version of a metadata file named like 00003-<uuid>.metadata.json
+fn metadata_version(location: &str) -> u64 {
+    location
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.split('-').next())
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(0)
+}
+
+impl Storage {
+    /// Creates the store for files under the location, using any of the `s3.*` properties that
+    /// a catalog can provide for accessing them
+    pub fn new(location: &str, config: &HashMap<String, String>) -> Result<Self> {
+        let url = Url::parse(location)?;
+        match url.scheme() {
+            "s3" | "s3a" | "s3n" => {
+                let bucket = url
+                    .host_str()
+                    .ok_or_else(|| anyhow!("invalid table location {}", location))?;
+
+                let region = config.get("s3.region");
+                let host = match (config.get("s3.endpoint"), region) {
+                    (Some(endpoint), _) => Url::parse(endpoint)?
+                        .host_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    (None, Some(region)) => format!("{}.s3.{}.amazonaws.com", bucket, region),
+                    (None, None) => format!("{}.s3.amazonaws.com", bucket),
+                };
+                check_egress(&host).map_err(|e| anyhow!(e))?;
+
+                let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+                if let Some(region) = region {
+                    builder = builder.with_region(region);
+                }
+                if let Some(endpoint) = config.get("s3.endpoint") {
+                    builder = builder.with_endpoint(endpoint).with_allow_http(true);
+                }
+                if let Some(path_style) = config.get("s3.path-style-access") {
+                    builder = builder.with_virtual_hosted_style_request(path_style != "true");
+                }
+                builder = match (
+                    config.get("s3.access-key-id"),
+                    config.get("s3.secret-access-key"),
+                ) {
+                    (Some(key_id), Some(secret)) => {
+                        let mut builder = builder
+                            .with_access_key_id(key_id)
+                            .with_secret_access_key(secret);
+                        if let Some(token) = config.get("s3.session-token") {
+                            builder = builder.with_token(token);
+                        }
+                        builder
+                    }
+                    // use default credentials
+                    _ => builder.with_credentials(Arc::new(S3Credentialing::try_new()?)),
+                };
+
+                Ok(Storage {
+                    store: Arc::new(builder.build()?),
+                    root: format!("{}://{}", url.scheme(), bucket),
+                })
+            }
+            "file" => Ok(Storage {
+                store: Arc::new(LocalFileSystem::new()),
+                root: "file://".to_string(),
+            }),
+            other => bail!("unsupported table location scheme '{}'", other),
+        }
+    }
+}
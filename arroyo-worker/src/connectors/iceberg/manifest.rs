@@ -0,0 +1,370 @@
+use anyhow::Result;
+use apache_avro::types::Value;
+use apache_avro::{Reader, Schema, Writer};
+use bincode::{Decode, Encode};
+
+/// A data file written by the sink, which is added to the table when its checkpoint is committed
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct DataFile {
+    pub path: String,
+    pub record_count: u64,
+    pub file_size: u64,
+}
+
+// the block size v1 manifests require, which is unused by readers; this is the value the Java
+// implementation writes
+const V1_BLOCK_SIZE: i64 = 64 * 1024 * 1024;
+
+const STATUS_ADDED: i32 = 1;
+
+fn manifest_entry_schema(format_version: u8) -> Schema {
+    let schema = if format_version == 1 {
+        r#"{
+            "type": "record",
+            "name": "manifest_entry",
+            "fields": [
+                {"name": "status", "type": "int", "field-id": 0},
+                {"name": "snapshot_id", "type": "long", "field-id": 1},
+                {"name": "data_file", "field-id": 2, "type": {
+                    "type": "record",
+                    "name": "r2",
+                    "fields": [
+                        {"name": "file_path", "type": "string", "field-id": 100},
+                        {"name": "file_format", "type": "string", "field-id": 101},
+                        {"name": "partition", "field-id": 102, "type": {
+                            "type": "record", "name": "r102", "fields": []
+                        }},
+                        {"name": "record_count", "type": "long", "field-id": 103},
+                        {"name": "file_size_in_bytes", "type": "long", "field-id": 104},
+                        {"name": "block_size_in_bytes", "type": "long", "field-id": 105}
+                    ]
+                }}
+            ]
+        }"#
+    } else {
+        r#"{
+            "type": "record",
+            "name": "manifest_entry",
+            "fields": [
+                {"name": "status", "type": "int", "field-id": 0},
+                {"name": "snapshot_id", "type": ["null", "long"], "default": null, "field-id": 1},
+                {"name": "sequence_number", "type": ["null", "long"], "default": null, "field-id": 3},
+                {"name": "file_sequence_number", "type": ["null", "long"], "default": null, "field-id": 4},
+                {"name": "data_file", "field-id": 2, "type": {
+                    "type": "record",
+                    "name": "r2",
+                    "fields": [
+                        {"name": "content", "type": "int", "field-id": 134},
+                        {"name": "file_path", "type": "string", "field-id": 100},
+                        {"name": "file_format", "type": "string", "field-id": 101},
+                        {"name": "partition", "field-id": 102, "type": {
+                            "type": "record", "name": "r102", "fields": []
+                        }},
+                        {"name": "record_count", "type": "long", "field-id": 103},
+                        {"name": "file_size_in_bytes", "type": "long", "field-id": 104}
+                    ]
+                }}
+            ]
+        }"#
+    };
+
+    Schema::parse_str(schema).expect("invalid manifest entry schema")
+}
+
+fn manifest_file_schema(format_version: u8) -> Schema {
+    let schema = if format_version == 1 {
+        r#"{
+            "type": "record",
+            "name": "manifest_file",
+            "fields": [
+                {"name": "manifest_path", "type": "string", "field-id": 500},
+                {"name": "manifest_length", "type": "long", "field-id": 501},
+                {"name": "partition_spec_id", "type": "int", "field-id": 502},
+                {"name": "added_snapshot_id", "type": ["null", "long"], "default": null, "field-id": 503},
+                {"name": "added_data_files_count", "type": ["null", "int"], "default": null, "field-id": 504},
+                {"name": "existing_data_files_count", "type": ["null", "int"], "default": null, "field-id": 505},
+                {"name": "deleted_data_files_count", "type": ["null", "int"], "default": null, "field-id": 506},
+                {"name": "added_rows_count", "type": ["null", "long"], "default": null, "field-id": 512},
+                {"name": "existing_rows_count", "type": ["null", "long"], "default": null, "field-id": 513},
+                {"name": "deleted_rows_count", "type": ["null", "long"], "default": null, "field-id": 514}
+            ]
+        }"#
+    } else {
+        r#"{
+            "type": "record",
+            "name": "manifest_file",
+            "fields": [
+                {"name": "manifest_path", "type": "string", "field-id": 500},
+                {"name": "manifest_length", "type": "long", "field-id": 501},
+                {"name": "partition_spec_id", "type": "int", "field-id": 502},
+                {"name": "content", "type": "int", "field-id": 517},
+                {"name": "sequence_number", "type": "long", "field-id": 515},
+                {"name": "min_sequence_number", "type": "long", "field-id": 516},
+                {"name": "added_snapshot_id", "type": "long", "field-id": 503},
+                {"name": "added_files_count", "type": "int", "field-id": 504},
+                {"name": "existing_files_count", "type": "int", "field-id": 505},
+                {"name": "deleted_files_count", "type": "int", "field-id": 506},
+                {"name": "added_rows_count", "type": "long", "field-id": 512},
+                {"name": "existing_rows_count", "type": "long", "field-id": 513},
+                {"name": "deleted_rows_count", "type": "long", "field-id": 514}
+            ]
+        }"#
+    };
+
+    Schema::parse_str(schema).expect("invalid manifest file schema")
+}
+
+fn optional(value: Value) -> Value {
+    Value::Union(1, Box::new(value))
+}
+
+/// Identifies the table and snapshot that manifests are written for
+pub struct ManifestContext<'a> {
+    pub format_version: u8,
+    pub snapshot_id: i64,
+    pub parent_snapshot_id: Option<i64>,
+    pub sequence_number: i64,
+    pub spec_id: i32,
+    pub schema: &'a serde_json::Value,
+}
+
+/// Writes a manifest adding the files to the table, returning its contents
+pub fn write_manifest(ctx: &ManifestContext, files: &[DataFile]) -> Result<Vec<u8>> {
+    let schema = manifest_entry_schema(ctx.format_version);
+    let mut writer = Writer::new(&schema, Vec::new());
+
+    writer.add_user_metadata("schema".to_string(), serde_json::to_string(ctx.schema)?)?;
+    if let Some(schema_id) = ctx.schema["schema-id"].as_i64() {
+        writer.add_user_metadata("schema-id".to_string(), schema_id.to_string())?;
+    }
+    writer.add_user_metadata("partition-spec".to_string(), "[]")?;
+    writer.add_user_metadata("partition-spec-id".to_string(), ctx.spec_id.to_string())?;
+    writer.add_user_metadata("format-version".to_string(), ctx.format_version.to_string())?;
+    if ctx.format_version > 1 {
+        writer.add_user_metadata("content".to_string(), "data")?;
+    }
+
+    for file in files {
+        let mut data_file = vec![];
+        if ctx.format_version > 1 {
+            data_file.push(("content".to_string(), Value::Int(0)));
+        }
+        data_file.extend([
+            ("file_path".to_string(), Value::String(file.path.clone())),
+            (
+                "file_format".to_string(),
+                Value::String("PARQUET".to_string()),
+            ),
+            ("partition".to_string(), Value::Record(vec![])),
+            (
+                "record_count".to_string(),
+                Value::Long(file.record_count as i64),
+            ),
+            (
+                "file_size_in_bytes".to_string(),
+                Value::Long(file.file_size as i64),
+            ),
+        ]);
+
+        let entry = if ctx.format_version > 1 {
+            // the sequence numbers are inherited from the manifest's entry in the manifest list
+            vec![
+                ("status".to_string(), Value::Int(STATUS_ADDED)),
+                (
+                    "snapshot_id".to_string(),
+                    optional(Value::Long(ctx.snapshot_id)),
+                ),
+                (
+                    "sequence_number".to_string(),
+                    Value::Union(0, Box::new(Value::Null)),
+                ),
+                (
+                    "file_sequence_number".to_string(),
+                    Value::Union(0, Box::new(Value::Null)),
+                ),
+                ("data_file".to_string(), Value::Record(data_file)),
+            ]
+        } else {
+            data_file.push((
+                "block_size_in_bytes".to_string(),
+                Value::Long(V1_BLOCK_SIZE),
+            ));
+            vec![
+                ("status".to_string(), Value::Int(STATUS_ADDED)),
+                ("snapshot_id".to_string(), Value::Long(ctx.snapshot_id)),
+                ("data_file".to_string(), Value::Record(data_file)),
+            ]
+        };
+
+        writer.append(Value::Record(entry))?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Writes the manifest list of a new snapshot, which holds the manifests of its parent (read from
+/// `parent_list`) along with the new manifest
+pub fn write_manifest_list(
+    ctx: &ManifestContext,
+    parent_list: Option<&[u8]>,
+    manifest_path: &str,
+    manifest_length: usize,
+    files: &[DataFile],
+) -> Result<Vec<u8>> {
+    let schema = manifest_file_schema(ctx.format_version);
+    let mut writer = Writer::new(&schema, Vec::new());
+
+    writer.add_user_metadata("snapshot-id".to_string(), ctx.snapshot_id.to_string())?;
+    writer.add_user_metadata(
+        "parent-snapshot-id".to_string(),
+        ctx.parent_snapshot_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| "null".to_string()),
+    )?;
+    writer.add_user_metadata("format-version".to_string(), ctx.format_version.to_string())?;
+    if ctx.format_version > 1 {
+        writer.add_user_metadata(
+            "sequence-number".to_string(),
+            ctx.sequence_number.to_string(),
+        )?;
+    }
+
+    if let Some(parent_list) = parent_list {
+        // reading with our schema resolves the manifests into it, so they can be written as-is
+        for manifest in Reader::with_schema(&schema, parent_list)? {
+            writer.append(manifest?)?;
+        }
+    }
+
+    let added_files = files.len() as i32;
+    let added_rows: i64 = files.iter().map(|f| f.record_count as i64).sum();
+
+    let manifest = if ctx.format_version > 1 {
+        vec![
+            (
+                "manifest_path".to_string(),
+                Value::String(manifest_path.to_string()),
+            ),
+            (
+                "manifest_length".to_string(),
+                Value::Long(manifest_length as i64),
+            ),
+            ("partition_spec_id".to_string(), Value::Int(ctx.spec_id)),
+            ("content".to_string(), Value::Int(0)),
+            (
+                "sequence_number".to_string(),
+                Value::Long(ctx.sequence_number),
+            ),
+            (
+                "min_sequence_number".to_string(),
+                Value::Long(ctx.sequence_number),
+            ),
+            (
+                "added_snapshot_id".to_string(),
+                Value::Long(ctx.snapshot_id),
+            ),
+            ("added_files_count".to_string(), Value::Int(added_files)),
+            ("existing_files_count".to_string(), Value::Int(0)),
+            ("deleted_files_count".to_string(), Value::Int(0)),
+            ("added_rows_count".to_string(), Value::Long(added_rows)),
+            ("existing_rows_count".to_string(), Value::Long(0)),
+            ("deleted_rows_count".to_string(), Value::Long(0)),
+        ]
+    } else {
+        vec![
+            (
+                "manifest_path".to_string(),
+                Value::String(manifest_path.to_string()),
+            ),
+            (
+                "manifest_length".to_string(),
+                Value::Long(manifest_length as i64),
+            ),
+            ("partition_spec_id".to_string(), Value::Int(ctx.spec_id)),
+            (
+                "added_snapshot_id".to_string(),
+                optional(Value::Long(ctx.snapshot_id)),
+            ),
+            (
+                "added_data_files_count".to_string(),
+                optional(Value::Int(added_files)),
+            ),
+            (
+                "existing_data_files_count".to_string(),
+                optional(Value::Int(0)),
+            ),
+            (
+                "deleted_data_files_count".to_string(),
+                optional(Value::Int(0)),
+            ),
+            (
+                "added_rows_count".to_string(),
+                optional(Value::Long(added_rows)),
+            ),
+            ("existing_rows_count".to_string(), optional(Value::Long(0))),
+            ("deleted_rows_count".to_string(), optional(Value::Long(0))),
+        ]
+    };
+
+    writer.append(Value::Record(manifest))?;
+
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::types::Value;
+    use apache_avro::Reader;
+    use serde_json::json;
+
+    use super::{write_manifest, write_manifest_list, DataFile, ManifestContext};
+
+    fn manifest_paths(list: &[u8]) -> Vec<String> {
+        Reader::new(list)
+            .unwrap()
+            .map(|m| match m.unwrap() {
+                Value::Record(fields) => match &fields[0] {
+                    (name, Value::String(path)) if name == "manifest_path" => path.clone(),
+                    other => panic!("unexpected field {:?}", other),
+                },
+                other => panic!("unexpected manifest {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest_list_keeps_parent_manifests() {
+        for format_version in [1, 2] {
+            let schema = json!({"type": "struct", "schema-id": 0, "fields": []});
+            let files = vec![DataFile {
+                path: "s3://bucket/table/data/a.parquet".to_string(),
+                record_count: 10,
+                file_size: 1024,
+            }];
+
+            let first = ManifestContext {
+                format_version,
+                snapshot_id: 1,
+                parent_snapshot_id: None,
+                sequence_number: 1,
+                spec_id: 0,
+                schema: &schema,
+            };
+            let manifest = write_manifest(&first, &files).unwrap();
+            assert_eq!(Reader::new(manifest.as_slice()).unwrap().count(), 1);
+
+            let list =
+                write_manifest_list(&first, None, "m1.avro", manifest.len(), &files).unwrap();
+
+            let second = ManifestContext {
+                snapshot_id: 2,
+                parent_snapshot_id: Some(1),
+                sequence_number: 2,
+                ..first
+            };
+            let list = write_manifest_list(&second, Some(&list), "m2.avro", 100, &files).unwrap();
+
+            assert_eq!(manifest_paths(&list), vec!["m1.avro", "m2.avro"]);
+        }
+    }
+}
@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow_array::{make_array, Array, RecordBatch};
+use arroyo_types::{Data, Key, Record, RecordBatchBuilder, TaskInfo};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use parquet::arrow::{ArrowWriter, PARQUET_FIELD_ID_META_KEY};
+use parquet::basic::{GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use typify::import_types;
+use uuid::Uuid;
+
+use crate::connectors::filesystem::parquet::SharedBuffer;
+use crate::connectors::two_phase_committer::{TwoPhaseCommitter, TwoPhaseCommitterOperator};
+use crate::connectors::OperatorConfig;
+
+use self::catalog::{IcebergCatalog, NewSnapshot, Storage, COMMIT_ID_PROPERTY};
+use self::manifest::{write_manifest, write_manifest_list, DataFile, ManifestContext};
+
+mod catalog;
+mod manifest;
+
+import_types!(schema = "../connector-schemas/iceberg/connection.json");
+import_types!(schema = "../connector-schemas/iceberg/table.json");
+
+// the number of rows that are buffered before they're written to the current data file
+const BATCH_SIZE: usize = 10_000;
+
+const DEFAULT_TARGET_FILE_SIZE: usize = 128 * 1024 * 1024;
+
+// commits are retried when another writer changes the table concurrently
+const MAX_COMMIT_ATTEMPTS: u32 = 10;
+
+/// The data files written by a subtask between two checkpoints, which are appended to the table
+/// in a single snapshot once the checkpoint completes
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct IcebergCommit {
+    // recorded in the snapshot's summary, so that commits that are retried after a restore can be
+    // skipped if they already happened
+    commit_id: String,
+    files: Vec<DataFile>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct IcebergDataRecovery {
+    next_file_index: usize,
+}
+
+struct LoadedSink {
+    catalog: IcebergCatalog,
+    storage: Storage,
+    location: String,
+    // the schema of our data, with the field ids of the table's columns
+    schema: SchemaRef,
+    writer_properties: WriterProperties,
+}
+
+impl LoadedSink {
+    /// Appends the files to the table in a new snapshot, retrying if the table is changed
+    /// concurrently
+    async fn commit_files(&self, commit: &IcebergCommit) -> Result<()> {
+        for attempt in 1..=MAX_COMMIT_ATTEMPTS {
+            let table = self.catalog.load().await?;
+
+            if table.metadata.has_commit(&commit.commit_id) {
+                info!(
+                    "Files of commit {} were already appended to the table",
+                    commit.commit_id
+                );
+                return Ok(());
+            }
+
+            let snapshot = {
+                let metadata = &table.metadata;
+                let parent = metadata.current_snapshot();
+                let schema = metadata.current_schema()?;
+                let ctx = ManifestContext {
+                    format_version: metadata.format_version,
+                    // snapshot ids are random positive numbers
+                    snapshot_id: rand::random::<i64>() & i64::MAX,
+                    parent_snapshot_id: parent.map(|p| p.snapshot_id),
+                    sequence_number: metadata.last_sequence_number + 1,
+                    spec_id: metadata.default_spec_id,
+                    schema,
+                };
+
+                let manifest = write_manifest(&ctx, &commit.files)?;
+                let manifest_length = manifest.len();
+                let manifest_path =
+                    format!("{}/metadata/{}-m0.avro", metadata.location, Uuid::new_v4());
+                self.storage.put(&manifest_path, manifest).await?;
+
+                let parent_list = match parent {
+                    Some(parent) => Some(
+                        self.storage
+                            .get(parent.manifest_list.as_ref().ok_or_else(|| {
+                                anyhow!("snapshots without manifest lists are not supported")
+                            })?)
+                            .await?,
+                    ),
+                    None => None,
+                };
+
+                let manifest_list = write_manifest_list(
+                    &ctx,
+                    parent_list.as_deref(),
+                    &manifest_path,
+                    manifest_length,
+                    &commit.files,
+                )?;
+                let manifest_list_path = format!(
+                    "{}/metadata/snap-{}-{}-{}.avro",
+                    metadata.location,
+                    ctx.snapshot_id,
+                    attempt,
+                    Uuid::new_v4()
+                );
+                self.storage.put(&manifest_list_path, manifest_list).await?;
+
+                NewSnapshot {
+                    snapshot_id: ctx.snapshot_id,
+                    parent_snapshot_id: ctx.parent_snapshot_id,
+                    sequence_number: ctx.sequence_number,
+                    manifest_list: manifest_list_path,
+                    summary: HashMap::from([
+                        ("operation".to_string(), "append".to_string()),
+                        (
+                            "added-data-files".to_string(),
+                            commit.files.len().to_string(),
+                        ),
+                        (
+                            "added-records".to_string(),
+                            commit
+                                .files
+                                .iter()
+                                .map(|f| f.record_count)
+                                .sum::<u64>()
+                                .to_string(),
+                        ),
+                        (
+                            "added-files-size".to_string(),
+                            commit
+                                .files
+                                .iter()
+                                .map(|f| f.file_size)
+                                .sum::<u64>()
+                                .to_string(),
+                        ),
+                        (COMMIT_ID_PROPERTY.to_string(), commit.commit_id.clone()),
+                    ]),
+                    schema_id: schema["schema-id"].as_i64().map(|id| id as i32),
+                }
+            };
+
+            if self.catalog.commit(table, &snapshot, &self.storage).await? {
+                info!(
+                    "Committed snapshot {} appending {} files to {}",
+                    snapshot.snapshot_id,
+                    commit.files.len(),
+                    self.location
+                );
+                return Ok(());
+            }
+
+            warn!(
+                "Table {} was changed concurrently; retrying commit {} (attempt {})",
+                self.location, commit.commit_id, attempt
+            );
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt.min(6)))).await;
+        }
+
+        bail!(
+            "failed to commit to {} after {} attempts",
+            self.location,
+            MAX_COMMIT_ATTEMPTS
+        )
+    }
+}
+
+/// Appends data to an Iceberg table. Each subtask writes Parquet data files until a checkpoint,
+/// and then commits them to the table in a new snapshot once the checkpoint has completed.
+pub struct IcebergSink<K: Key, T: Data + Sync, R: RecordBatchBuilder<Data = T>> {
+    config: IcebergConfig,
+    table: IcebergTable,
+    sink: Option<LoadedSink>,
+    builder: R,
+    buffered_rows: usize,
+    writer: Option<ArrowWriter<SharedBuffer>>,
+    buffer: SharedBuffer,
+    file_rows: u64,
+    // files that have been written since the last checkpoint
+    data_files: Vec<DataFile>,
+    next_file_index: usize,
+    task_info: Option<TaskInfo>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key, T: Data + Sync, R: RecordBatchBuilder<Data = T>> IcebergSink<K, T, R> {
+    pub fn from_config(config: &str) -> TwoPhaseCommitterOperator<K, T, Self> {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for IcebergSink");
+        let connection: IcebergConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for IcebergSink");
+        let table: IcebergTable =
+            serde_json::from_value(config.table).expect("Invalid table config for IcebergSink");
+
+        TwoPhaseCommitterOperator::new(Self {
+            config: connection,
+            table,
+            sink: None,
+            builder: R::default(),
+            buffered_rows: 0,
+            writer: None,
+            buffer: SharedBuffer::new(0),
+            file_rows: 0,
+            data_files: vec![],
+            next_file_index: 0,
+            task_info: None,
+            _t: PhantomData,
+        })
+    }
+
+    fn writer_properties(&self) -> WriterProperties {
+        let compression = match self.table.compression {
+            Some(Compression::None) => parquet::basic::Compression::UNCOMPRESSED,
+            Some(Compression::Gzip) => parquet::basic::Compression::GZIP(GzipLevel::default()),
+            Some(Compression::Zstd) => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
+            Some(Compression::Snappy) | None => parquet::basic::Compression::SNAPPY,
+        };
+
+        WriterProperties::builder()
+            .set_compression(compression)
+            .build()
+    }
+
+    fn sink(&self) -> &LoadedSink {
+        self.sink
+            .as_ref()
+            .expect("iceberg sink was not initialized")
+    }
+
+    /// Writes the buffered rows to the current data file, which is closed if it has reached its
+    /// target size
+    async fn write_batch(&mut self) -> Result<()> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+
+        let sink = self.sink.as_ref().unwrap();
+        let batch = self.builder.flush();
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(sink.schema.fields())
+            .map(|(column, field)| Ok(make_array(with_type(column.to_data(), field.data_type())?)))
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(sink.schema.clone(), columns)?;
+
+        if self.writer.is_none() {
+            self.buffer = SharedBuffer::new(0);
+            self.writer = Some(ArrowWriter::try_new(
+                self.buffer.clone(),
+                sink.schema.clone(),
+                Some(sink.writer_properties.clone()),
+            )?);
+        }
+
+        let writer = self.writer.as_mut().unwrap();
+        writer.write(&batch)?;
+        writer.flush()?;
+        self.file_rows += self.buffered_rows as u64;
+        self.buffered_rows = 0;
+
+        let target_size = self
+            .table
+            .target_file_size
+            .map(|s| s as usize)
+            .unwrap_or(DEFAULT_TARGET_FILE_SIZE);
+        if self.buffer.buffer.try_lock().unwrap().len() >= target_size {
+            self.finish_file().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes the current data file and uploads it to the table's data directory
+    async fn finish_file(&mut self) -> Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        writer.close()?;
+        let data = std::mem::take(&mut *self.buffer.buffer.try_lock().unwrap());
+
+        let task_info = self.task_info.as_ref().unwrap();
+        let path = format!(
+            "{}/data/{}-{}-{:0>3}-{:0>5}-{}.parquet",
+            self.sink().location,
+            task_info.job_id,
+            task_info.operator_id,
+            task_info.task_index,
+            self.next_file_index,
+            Uuid::new_v4()
+        );
+
+        let file = DataFile {
+            path: path.clone(),
+            record_count: self.file_rows,
+            file_size: data.len() as u64,
+        };
+        self.sink().storage.put(&path, data).await?;
+
+        self.data_files.push(file);
+        self.file_rows = 0;
+        self.next_file_index += 1;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<K: Key, T: Data + Sync, R: RecordBatchBuilder<Data = T>> TwoPhaseCommitter<K, T>
+    for IcebergSink<K, T, R>
+{
+    type DataRecovery = IcebergDataRecovery;
+    type PreCommit = IcebergCommit;
+
+    fn name(&self) -> String {
+        "iceberg_sink".to_string()
+    }
+
+    async fn init(
+        &mut self,
+        task_info: &TaskInfo,
+        data_recovery: Vec<Self::DataRecovery>,
+    ) -> Result<()> {
+        let catalog = IcebergCatalog::new(&self.config.catalog, &self.table).await?;
+        let table = catalog.load().await?;
+        let metadata = &table.metadata;
+
+        if metadata.format_version > 2 {
+            bail!(
+                "Iceberg format version {} is not supported",
+                metadata.format_version
+            );
+        }
+        if metadata.is_partitioned() {
+            bail!("only unpartitioned Iceberg tables are supported");
+        }
+
+        let schema = with_field_ids(&self.builder.schema(), metadata.current_schema()?)?;
+
+        self.sink = Some(LoadedSink {
+            storage: Storage::new(&metadata.location, &table.storage_config)?,
+            location: metadata.location.trim_end_matches('/').to_string(),
+            schema: Arc::new(schema),
+            writer_properties: self.writer_properties(),
+            catalog,
+        });
+
+        self.next_file_index = data_recovery
+            .iter()
+            .map(|r| r.next_file_index)
+            .max()
+            .unwrap_or(0);
+        self.task_info = Some(task_info.clone());
+        Ok(())
+    }
+
+    async fn insert_record(&mut self, record: &Record<K, T>) -> Result<()> {
+        self.builder.add_data(Some(record.value.clone()));
+        self.buffered_rows += 1;
+        if self.buffered_rows >= BATCH_SIZE {
+            self.write_batch().await?;
+        }
+        Ok(())
+    }
+
+    async fn commit(
+        &mut self,
+        _task_info: &TaskInfo,
+        pre_commit: Vec<Self::PreCommit>,
+    ) -> Result<()> {
+        for commit in &pre_commit {
+            self.sink().commit_files(commit).await?;
+        }
+        Ok(())
+    }
+
+    async fn checkpoint(
+        &mut self,
+        _task_info: &TaskInfo,
+        _stopping: bool,
+    ) -> Result<(Self::DataRecovery, HashMap<String, Self::PreCommit>)> {
+        // data files are closed at every checkpoint, so there's never a partial file to recover
+        self.write_batch().await?;
+        self.finish_file().await?;
+
+        let mut pre_commits = HashMap::new();
+        let files = std::mem::take(&mut self.data_files);
+        if let Some(first) = files.first() {
+            let commit_id = first
+                .path
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .trim_end_matches(".parquet")
+                .to_string();
+            pre_commits.insert(commit_id.clone(), IcebergCommit { commit_id, files });
+        }
+
+        Ok((
+            IcebergDataRecovery {
+                next_file_index: self.next_file_index,
+            },
+            pre_commits,
+        ))
+    }
+}
+
+/// Adds the ids of the table's columns to our schema, which readers use to match the columns of
+/// data files to those of the table
+fn with_field_ids(schema: &Schema, table_schema: &serde_json::Value) -> Result<Schema> {
+    let fields = struct_fields_with_ids(
+        schema.fields().iter().map(|f| f.as_ref()),
+        &table_schema["fields"],
+    )?;
+    Ok(Schema::new(fields))
+}
+
+fn struct_fields_with_ids<'a>(
+    fields: impl Iterator<Item = &'a Field>,
+    table_fields: &serde_json::Value,
+) -> Result<Vec<Field>> {
+    let table_fields = table_fields.as_array().cloned().unwrap_or_default();
+
+    fields
+        .map(|field| {
+            let table_field = table_fields
+                .iter()
+                .find(|f| f["name"].as_str() == Some(field.name()))
+                .or_else(|| {
+                    table_fields.iter().find(|f| {
+                        f["name"]
+                            .as_str()
+                            .map(|n| n.eq_ignore_ascii_case(field.name()))
+                            .unwrap_or(false)
+                    })
+                })
+                .ok_or_else(|| anyhow!("column '{}' is not in the table's schema", field.name()))?;
+
+            with_field_id(field, table_field["id"].as_i64(), &table_field["type"])
+        })
+        .collect()
+}
+
+fn with_field_id(field: &Field, id: Option<i64>, table_type: &serde_json::Value) -> Result<Field> {
+    let id = id.ok_or_else(|| anyhow!("column '{}' has no field id", field.name()))?;
+
+    let data_type = match (field.data_type(), table_type["type"].as_str()) {
+        (DataType::Struct(fields), Some("struct")) => DataType::Struct(
+            struct_fields_with_ids(fields.iter().map(|f| f.as_ref()), &table_type["fields"])?
+                .into(),
+        ),
+        (DataType::List(element), Some("list")) => DataType::List(Arc::new(with_field_id(
+            element,
+            table_type["element-id"].as_i64(),
+            &table_type["element"],
+        )?)),
+        (data_type, _) => data_type.clone(),
+    };
+
+    let mut metadata = field.metadata().clone();
+    metadata.insert(PARQUET_FIELD_ID_META_KEY.to_string(), id.to_string());
+    Ok(Field::new(field.name(), data_type, field.is_nullable()).with_metadata(metadata))
+}
+
+/// Rebuilds array data with an equivalent type whose nested fields carry field ids
+fn with_type(
+    data: arrow::array::ArrayData,
+    data_type: &DataType,
+) -> Result<arrow::array::ArrayData> {
+    let child_types: Vec<&DataType> = match data_type {
+        DataType::Struct(fields) => fields.iter().map(|f| f.data_type()).collect(),
+        DataType::List(element) => vec![element.data_type()],
+        _ => return Ok(data),
+    };
+
+    let child_data = data
+        .child_data()
+        .iter()
+        .cloned()
+        .zip(child_types)
+        .map(|(child, child_type)| with_type(child, child_type))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(data
+        .into_builder()
+        .data_type(data_type.clone())
+        .child_data(child_data)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::datatypes::{DataType, Field, Schema};
+    use parquet::arrow::PARQUET_FIELD_ID_META_KEY;
+    use serde_json::json;
+
+    use super::with_field_ids;
+
+    #[test]
+    fn test_with_field_ids() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new(
+                "tags",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+        ]);
+
+        let table_schema = json!({
+            "type": "struct",
+            "schema-id": 0,
+            "fields": [
+                {"id": 1, "name": "id", "required": true, "type": "long"},
+                {"id": 2, "name": "tags", "required": false, "type": {
+                    "type": "list", "element-id": 3, "element": "string", "element-required": false
+                }},
+            ]
+        });
+
+        let schema = with_field_ids(&schema, &table_schema).unwrap();
+        let id = |f: &Field| f.metadata()[PARQUET_FIELD_ID_META_KEY].clone();
+
+        assert_eq!(id(schema.field(0)), "1");
+        assert_eq!(id(schema.field(1)), "2");
+        let DataType::List(element) = schema.field(1).data_type() else {
+            panic!("expected a list");
+        };
+        assert_eq!(id(element), "3");
+
+        let missing = json!({"fields": [{"id": 1, "name": "id", "type": "long"}]});
+        assert!(with_field_ids(&schema, &missing).is_err());
+    }
+}
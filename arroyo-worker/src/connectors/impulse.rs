@@ -10,7 +10,7 @@ use arroyo_types::*;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, SystemTime};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use typify::import_types;
 
 use super::OperatorConfig;
@@ -167,11 +167,20 @@ impl<K: Data, T: Data> ImpulseSourceFunc<K, T> {
                         StopMode::Immediate => {
                             return SourceFinishType::Immediate;
                         }
+                        StopMode::Drain => {
+                            return SourceFinishType::Final;
+                        }
                     }
                 }
                 Ok(ControlMessage::Commit { epoch: _ }) => {
                     unreachable!("sources shouldn't receive commit messages");
                 }
+                Ok(ControlMessage::SetPaused { .. }) => {
+                    warn!("watermark alignment is not supported by the impulse source");
+                }
+                Ok(ControlMessage::InjectProbe { probe_id }) => {
+                    ctx.handle_probe(probe_id).await;
+                }
                 Err(_) => {
                     // no messages
                 }
@@ -0,0 +1,673 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+use arroyo_macro::process_fn;
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use chrono::{DateTime, Utc};
+use mysql_async::prelude::Queryable;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio_postgres::NoTls;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::connectors::batching::{BatchPolicy, Batcher, FlushCause};
+use crate::connectors::connection_pool::ConnectionPermit;
+use crate::connectors::{
+    check_postgres_egress, ConnectionPoolConfig, OperatorConfig, OperatorConfigSerializationMode,
+};
+use crate::engine::{Context, StreamNode};
+
+import_types!(schema = "../connector-schemas/jdbc/connection.json");
+import_types!(schema = "../connector-schemas/jdbc/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+// the most placeholders MySQL allows in a statement
+const MAX_MYSQL_PARAMS: usize = 65_535;
+
+type Row = Map<String, Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dialect {
+    Postgres,
+    MySql,
+}
+
+impl Dialect {
+    fn for_connection_string(connection_string: &str) -> Self {
+        if connection_string.starts_with("mysql://") {
+            Dialect::MySql
+        } else {
+            Dialect::Postgres
+        }
+    }
+
+    fn quote(&self, ident: &str) -> String {
+        match self {
+            Dialect::Postgres => format!("\"{}\"", ident.replace('"', "\"\"")),
+            Dialect::MySql => format!("`{}`", ident.replace('`', "``")),
+        }
+    }
+
+    /// Quotes each part of a table name that may be qualified with its schema
+    fn quote_table(&self, table: &str) -> String {
+        table
+            .split('.')
+            .map(|part| self.quote(part))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn quote_all(&self, columns: &[String]) -> String {
+        columns
+            .iter()
+            .map(|c| self.quote(c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Formats a timestamp as a string the database will parse for timestamp columns; MySQL
+    /// doesn't accept time zones in datetime literals, so it gets a UTC time without one
+    fn timestamp(&self, time: DateTime<Utc>) -> String {
+        match self {
+            Dialect::Postgres => time.to_rfc3339(),
+            Dialect::MySql => time.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+        }
+    }
+
+    /// The statement that inserts `rows` rows with the given columns. With a primary key, rows
+    /// that already exist are updated if `upsert` is set and skipped otherwise.
+    ///
+    /// Postgres statements take all of the rows as a single JSON array, which Postgres converts
+    /// to the table's column types, while MySQL statements have a placeholder for each value.
+    fn insert(
+        &self,
+        table: &str,
+        columns: &[String],
+        rows: usize,
+        primary_key: &[String],
+        upsert: bool,
+    ) -> String {
+        let table = self.quote_table(table);
+        let column_list = self.quote_all(columns);
+        let updates: Vec<_> = columns
+            .iter()
+            .filter(|c| !primary_key.contains(c))
+            .map(|c| self.quote(c))
+            .collect();
+        let upsert = upsert && !updates.is_empty();
+
+        match self {
+            Dialect::Postgres => {
+                let conflict = if primary_key.is_empty() {
+                    String::new()
+                } else if upsert {
+                    format!(
+                        " ON CONFLICT ({}) DO UPDATE SET {}",
+                        self.quote_all(primary_key),
+                        updates
+                            .iter()
+                            .map(|c| format!("{} = EXCLUDED.{}", c, c))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                } else {
+                    " ON CONFLICT DO NOTHING".to_string()
+                };
+
+                format!(
+                    "INSERT INTO {} ({}) SELECT {} \
+                    FROM json_populate_recordset(NULL::{}, $1::text::json){}",
+                    table, column_list, column_list, table, conflict
+                )
+            }
+            Dialect::MySql => {
+                let row = format!("({})", vec!["?"; columns.len()].join(", "));
+                let values = vec![row; rows].join(", ");
+
+                if primary_key.is_empty() {
+                    format!("INSERT INTO {} ({}) VALUES {}", table, column_list, values)
+                } else if upsert {
+                    format!(
+                        "INSERT INTO {} ({}) VALUES {} ON DUPLICATE KEY UPDATE {}",
+                        table,
+                        column_list,
+                        values,
+                        updates
+                            .iter()
+                            .map(|c| format!("{} = VALUES({})", c, c))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                } else {
+                    format!(
+                        "INSERT IGNORE INTO {} ({}) VALUES {}",
+                        table, column_list, values
+                    )
+                }
+            }
+        }
+    }
+
+    /// The statement that deletes `rows` rows by their primary keys
+    fn delete(&self, table: &str, primary_key: &[String], rows: usize) -> String {
+        let table = self.quote_table(table);
+
+        match self {
+            Dialect::Postgres => format!(
+                "DELETE FROM {} AS t USING json_populate_recordset(NULL::{}, $1::text::json) \
+                AS d WHERE {}",
+                table,
+                table,
+                primary_key
+                    .iter()
+                    .map(|c| format!("t.{} = d.{}", self.quote(c), self.quote(c)))
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
+            ),
+            Dialect::MySql => {
+                let row = format!("({})", vec!["?"; primary_key.len()].join(", "));
+                format!(
+                    "DELETE FROM {} WHERE ({}) IN ({})",
+                    table,
+                    self.quote_all(primary_key),
+                    vec![row; rows].join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Converts the serialized record into values the database can write; timestamps serialized as
+/// `SystemTime`s are formatted as strings, and nested structs are written as JSON
+fn normalize(value: Value, dialect: Dialect) -> Value {
+    match value {
+        Value::Object(fields) => match system_time(&fields) {
+            Some(time) => Value::String(dialect.timestamp(time)),
+            None => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, normalize(v, dialect)))
+                    .collect(),
+            ),
+        },
+        Value::Array(values) => {
+            Value::Array(values.into_iter().map(|v| normalize(v, dialect)).collect())
+        }
+        other => other,
+    }
+}
+
+fn system_time(fields: &Map<String, Value>) -> Option<DateTime<Utc>> {
+    if fields.len() != 2 {
+        return None;
+    }
+    let secs = fields.get("secs_since_epoch")?.as_u64()?;
+    let nanos = fields.get("nanos_since_epoch")?.as_u64()?;
+    Some(DateTime::<Utc>::from(
+        SystemTime::UNIX_EPOCH + Duration::new(secs, nanos as u32),
+    ))
+}
+
+fn to_mysql_value(value: Option<&Value>) -> mysql_async::Value {
+    match value {
+        None | Some(Value::Null) => mysql_async::Value::NULL,
+        Some(Value::Bool(b)) => mysql_async::Value::Int(*b as i64),
+        Some(Value::Number(n)) => {
+            if let Some(i) = n.as_i64() {
+                mysql_async::Value::Int(i)
+            } else if let Some(u) = n.as_u64() {
+                mysql_async::Value::UInt(u)
+            } else {
+                mysql_async::Value::Double(n.as_f64().unwrap_or_default())
+            }
+        }
+        Some(Value::String(s)) => mysql_async::Value::Bytes(s.clone().into_bytes()),
+        Some(other) => mysql_async::Value::Bytes(other.to_string().into_bytes()),
+    }
+}
+
+enum Client {
+    Postgres(tokio_postgres::Client),
+    MySql(mysql_async::Conn),
+}
+
+impl Client {
+    async fn connect(connection_string: &str, dialect: Dialect) -> Result<Self, String> {
+        match dialect {
+            Dialect::Postgres => {
+                check_postgres_egress(connection_string)?;
+                let (client, connection) = tokio_postgres::connect(connection_string, NoTls)
+                    .await
+                    .map_err(|e| e.to_string())?;
+
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        warn!("Postgres connection closed: {}", e);
+                    }
+                });
+
+                Ok(Client::Postgres(client))
+            }
+            Dialect::MySql => {
+                let host = url::Url::parse(connection_string)
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_string()))
+                    .unwrap_or_default();
+                check_egress(&host)?;
+
+                mysql_async::Conn::from_url(connection_string)
+                    .await
+                    .map(Client::MySql)
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Executes the statement with the values of the given columns of the rows
+    async fn execute(
+        &mut self,
+        statement: &str,
+        rows: &[Row],
+        columns: &[String],
+    ) -> Result<(), String> {
+        match self {
+            Client::Postgres(client) => {
+                let json = Value::Array(
+                    rows.iter()
+                        .map(|row| {
+                            Value::Object(
+                                columns
+                                    .iter()
+                                    .map(|c| {
+                                        (c.clone(), row.get(c).cloned().unwrap_or(Value::Null))
+                                    })
+                                    .collect(),
+                            )
+                        })
+                        .collect(),
+                )
+                .to_string();
+
+                client
+                    .execute(statement, &[&json])
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }
+            Client::MySql(conn) => {
+                let params: Vec<_> = rows
+                    .iter()
+                    .flat_map(|row| columns.iter().map(|c| to_mysql_value(row.get(c))))
+                    .collect();
+
+                conn.exec_drop(statement, params)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+enum Write {
+    Upsert(Row),
+    Delete(Row),
+}
+
+#[derive(StreamNode)]
+pub struct JdbcSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    connection_string: String,
+    connection_pool: Option<ConnectionPoolConfig>,
+    permit: Option<ConnectionPermit>,
+    dialect: Dialect,
+    table: String,
+    primary_key: Vec<String>,
+    updating: bool,
+    batcher: Batcher,
+    client: Option<Client>,
+    // rows to append, in order
+    appends: Vec<Row>,
+    // pending upserts and deletes, keyed by the row's primary key; later writes to a key replace
+    // earlier ones, so each key is written once per batch
+    pending: HashMap<String, Write>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> JdbcSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for JdbcSink");
+        let connection: JdbcConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for JdbcSink");
+        let table: JdbcTable =
+            serde_json::from_value(config.table).expect("Invalid table config for JdbcSink");
+
+        let primary_key = table
+            .primary_key
+            .iter()
+            .flat_map(|k| k.split(','))
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+
+        Self {
+            dialect: Dialect::for_connection_string(&connection.connection_string),
+            connection_string: connection.connection_string.to_string(),
+            connection_pool: config.connection_pool,
+            permit: None,
+            table: table.table_name,
+            primary_key,
+            updating: matches!(
+                config.serialization_mode,
+                Some(OperatorConfigSerializationMode::DebeziumJson)
+            ),
+            batcher: Batcher::new(BatchPolicy::from_config(
+                config.batching,
+                table
+                    .batch_size
+                    .map(|s| s.max(1) as usize)
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+            )),
+            client: None,
+            appends: vec![],
+            pending: HashMap::new(),
+            _t: PhantomData,
+        }
+    }
+
+    /// Builds the write for the record. For updating streams, creates and updates are upserted
+    /// and deletes remove the row with the retracted row's primary key.
+    fn to_write(&self, record: &Record<K, T>) -> Result<Write, String> {
+        let mut value = serde_json::to_value(&record.value).map_err(|e| e.to_string())?;
+
+        let (fields, delete) = if self.updating {
+            let delete = value.get("op").and_then(|op| op.as_str()) == Some("d");
+            let field = if delete { "before" } else { "after" };
+            (
+                value.get_mut(field).map(Value::take).unwrap_or(Value::Null),
+                delete,
+            )
+        } else {
+            (value, false)
+        };
+
+        let fields = match normalize(fields, self.dialect) {
+            Value::Object(fields) => fields,
+            other => {
+                return Err(format!(
+                    "expected a struct to write to {}, found {}",
+                    self.table, other
+                ))
+            }
+        };
+
+        if self.updating {
+            for key in &self.primary_key {
+                match fields.get(key) {
+                    None | Some(Value::Null) => {
+                        return Err(format!("record is missing primary key column '{}'", key));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(if delete {
+            Write::Delete(fields)
+        } else {
+            Write::Upsert(fields)
+        })
+    }
+
+    fn pending_key(&self, row: &Row) -> String {
+        self.primary_key
+            .iter()
+            .map(|k| row.get(k).map(|v| v.to_string()).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("\u{0}")
+    }
+
+    async fn write(&mut self, write: Write, value: &T) {
+        match write {
+            Write::Upsert(row) if !self.updating => self.appends.push(row),
+            write => {
+                let key = match &write {
+                    Write::Upsert(row) | Write::Delete(row) => self.pending_key(row),
+                };
+                self.pending.insert(key, write);
+            }
+        }
+
+        if let Some(cause) = self.batcher.add(value) {
+            self.flush(cause).await;
+        }
+    }
+
+    /// Executes a statement for each chunk of the rows, retrying with exponential backoff and
+    /// reconnecting after failures
+    async fn execute(&mut self, op: &str, rows: &[Row], columns: &[String], upsert: bool) {
+        let chunk_size = match self.dialect {
+            Dialect::Postgres => rows.len(),
+            Dialect::MySql => MAX_MYSQL_PARAMS / columns.len().max(1),
+        };
+
+        for chunk in rows.chunks(chunk_size.max(1)) {
+            let statement = if op == "delete" {
+                self.dialect.delete(&self.table, columns, chunk.len())
+            } else {
+                self.dialect
+                    .insert(&self.table, columns, chunk.len(), &self.primary_key, upsert)
+            };
+
+            let mut attempt = 0;
+            loop {
+                let result = match &mut self.client {
+                    Some(client) => client.execute(&statement, chunk, columns).await,
+                    None => match Client::connect(&self.connection_string, self.dialect).await {
+                        Ok(client) => {
+                            self.client
+                                .insert(client)
+                                .execute(&statement, chunk, columns)
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    },
+                };
+
+                let Err(e) = result else {
+                    break;
+                };
+
+                // the connection may have been lost, so reconnect before retrying
+                self.client = None;
+                attempt += 1;
+                if attempt >= MAX_ATTEMPTS {
+                    panic!(
+                        "{} to {} failed after {} attempts: {}",
+                        op, self.table, attempt, e
+                    );
+                }
+
+                warn!("{} to {} failed, retrying: {}", op, self.table, e);
+                tokio::time::sleep(backoff(attempt)).await;
+            }
+        }
+    }
+
+    async fn flush(&mut self, cause: FlushCause) {
+        if self.appends.is_empty() && self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let appends = std::mem::take(&mut self.appends);
+        if let Some(first) = appends.first() {
+            let columns: Vec<_> = first.keys().cloned().collect();
+            self.execute("insert", &appends, &columns, false).await;
+        }
+
+        let mut upserts = vec![];
+        let mut deletes = vec![];
+        for (_, write) in self.pending.drain() {
+            match write {
+                Write::Upsert(row) => upserts.push(row),
+                Write::Delete(row) => deletes.push(row),
+            }
+        }
+
+        // each key has a single write, so the order of upserts and deletes doesn't matter
+        if let Some(first) = upserts.first() {
+            let columns: Vec<_> = first.keys().cloned().collect();
+            self.execute("upsert", &upserts, &columns, true).await;
+        }
+
+        if !deletes.is_empty() {
+            let primary_key = self.primary_key.clone();
+            self.execute("delete", &deletes, &primary_key, false).await;
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> JdbcSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("jdbc-sink-{}", self.table)
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        info!("Connecting to {:?} table {}", self.dialect, self.table);
+
+        self.permit = ConnectionPermit::acquire(&self.connection_pool, 1, &ctx.task_info).await;
+        match Client::connect(&self.connection_string, self.dialect).await {
+            Ok(client) => self.client = Some(client),
+            Err(e) => {
+                ctx.report_disconnected(e.clone()).await;
+                panic!("Failed to connect to {}: {}", self.table, e);
+            }
+        }
+        ctx.report_connected().await;
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match self.to_write(record) {
+            Ok(write) => self.write(write, &record.value).await,
+            Err(e) => {
+                ctx.report_error(format!("Could not write record to {}", self.table), e)
+                    .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, _: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint).await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close).await;
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    (Duration::from_millis(50) * 2u32.pow(attempt.min(10))).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{normalize, Dialect};
+
+    fn columns(columns: &[&str]) -> Vec<String> {
+        columns.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_postgres_statements() {
+        let d = Dialect::Postgres;
+        assert_eq!(
+            "INSERT INTO \"public\".\"orders\" (\"id\", \"total\") SELECT \"id\", \"total\" \
+            FROM json_populate_recordset(NULL::\"public\".\"orders\", $1::text::json) \
+            ON CONFLICT (\"id\") DO UPDATE SET \"total\" = EXCLUDED.\"total\"",
+            d.insert(
+                "public.orders",
+                &columns(&["id", "total"]),
+                3,
+                &columns(&["id"]),
+                true
+            )
+        );
+
+        assert_eq!(
+            "INSERT INTO \"orders\" (\"id\") SELECT \"id\" \
+            FROM json_populate_recordset(NULL::\"orders\", $1::text::json)",
+            d.insert("orders", &columns(&["id"]), 3, &[], false)
+        );
+
+        assert_eq!(
+            "DELETE FROM \"orders\" AS t USING json_populate_recordset(NULL::\"orders\", \
+            $1::text::json) AS d WHERE t.\"a\" = d.\"a\" AND t.\"b\" = d.\"b\"",
+            d.delete("orders", &columns(&["a", "b"]), 2)
+        );
+    }
+
+    #[test]
+    fn test_mysql_statements() {
+        let d = Dialect::MySql;
+        assert_eq!(
+            "INSERT INTO `orders` (`id`, `total`) VALUES (?, ?), (?, ?) \
+            ON DUPLICATE KEY UPDATE `total` = VALUES(`total`)",
+            d.insert(
+                "orders",
+                &columns(&["id", "total"]),
+                2,
+                &columns(&["id"]),
+                true
+            )
+        );
+
+        // rows with nothing but their key have nothing to update, so existing rows are skipped
+        assert_eq!(
+            "INSERT IGNORE INTO `orders` (`id`) VALUES (?)",
+            d.insert("orders", &columns(&["id"]), 1, &columns(&["id"]), true)
+        );
+
+        assert_eq!(
+            "DELETE FROM `orders` WHERE (`id`) IN ((?), (?))",
+            d.delete("orders", &columns(&["id"]), 2)
+        );
+    }
+
+    #[test]
+    fn test_normalize_timestamps() {
+        let value = json!({
+            "id": 1,
+            "ts": {"secs_since_epoch": 1_685_622_600, "nanos_since_epoch": 500_000_000},
+        });
+
+        assert_eq!(
+            json!({"id": 1, "ts": "2023-06-01T12:30:00.500+00:00"}),
+            normalize(value.clone(), Dialect::Postgres)
+        );
+        assert_eq!(
+            json!({"id": 1, "ts": "2023-06-01 12:30:00.500000"}),
+            normalize(value, Dialect::MySql)
+        );
+    }
+}
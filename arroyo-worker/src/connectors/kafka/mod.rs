@@ -1,9 +1,15 @@
 use std::collections::HashMap;
 
+use arroyo_rpc::tls;
+use arroyo_types::string_to_map;
+use rdkafka::message::{Header, Headers, OwnedHeaders};
 use rdkafka::Offset;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use typify::import_types;
 
+use crate::operators::UserError;
+
 pub mod sink;
 pub mod source;
 
@@ -37,5 +43,194 @@ pub fn client_configs(connection: &KafkaConfig) -> HashMap<String, String> {
         }
     };
 
+    if let Some(t) = &connection.tls {
+        // with SASL the protocol (SASL_SSL) has been set above
+        client_configs
+            .entry("security.protocol".to_string())
+            .or_insert_with(|| "ssl".to_string());
+        client_configs.extend(tls::TlsConfig::from_generated(t).kafka_configs());
+    }
+
     client_configs
 }
+
+/// A column that a source reads a record header into, or that a sink writes a header from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderColumn {
+    pub column: String,
+    pub header: String,
+    /// whether the column is BYTEA, rather than TEXT
+    pub binary: bool,
+}
+
+pub fn header_columns(table: &KafkaTable) -> Vec<HeaderColumn> {
+    let text = table.header_columns.as_ref().map(|c| (&c.0, false));
+    let binary = table.binary_header_columns.as_ref().map(|c| (&c.0, true));
+
+    let mut columns: Vec<_> = text
+        .into_iter()
+        .chain(binary)
+        .flat_map(|(pairs, binary)| {
+            string_to_map(pairs)
+                .expect("invalid header columns")
+                .into_iter()
+                .map(move |(column, header)| HeaderColumn {
+                    column,
+                    header,
+                    binary,
+                })
+        })
+        .collect();
+    columns.sort_by(|a, b| a.column.cmp(&b.column));
+    columns
+}
+
+/// The values of a source's header columns for a record with the given headers: strings (with
+/// invalid UTF-8 replaced) for TEXT columns, bytes for BYTEA columns, and null for headers that the
+/// record doesn't have. If a header appears more than once, its last value is used.
+pub fn header_fields<H: Headers>(
+    columns: &[HeaderColumn],
+    headers: Option<&H>,
+) -> Map<String, Value> {
+    let mut fields: Map<String, Value> = columns
+        .iter()
+        .map(|c| (c.column.clone(), Value::Null))
+        .collect();
+
+    for header in headers.into_iter().flat_map(|h| h.iter()) {
+        let Some(value) = header.value else {
+            continue;
+        };
+
+        for column in columns.iter().filter(|c| c.header == header.key) {
+            let value = if column.binary {
+                Value::from(value.to_vec())
+            } else {
+                Value::String(String::from_utf8_lossy(value).to_string())
+            };
+            fields.insert(column.column.clone(), value);
+        }
+    }
+
+    fields
+}
+
+/// Removes a sink's header columns from the json of a record, returning the headers to write for
+/// them. Strings are written as UTF-8, byte arrays as-is, and other values as their json; null
+/// columns aren't written.
+pub fn take_headers(
+    columns: &[HeaderColumn],
+    record: &mut Value,
+) -> Result<OwnedHeaders, UserError> {
+    let mut headers = OwnedHeaders::new_with_capacity(columns.len());
+    let Some(record) = record.as_object_mut() else {
+        return Ok(headers);
+    };
+
+    for column in columns {
+        let value = match record.remove(&column.column) {
+            None | Some(Value::Null) => continue,
+            Some(Value::String(s)) => s.into_bytes(),
+            Some(v @ Value::Array(_)) => serde_json::from_value(v).map_err(|e| {
+                UserError::new(
+                    "Serialization error",
+                    format!(
+                        "Could not write column '{}' as bytes to header '{}': {:?}",
+                        column.column, column.header, e
+                    ),
+                )
+            })?,
+            Some(v) => v.to_string().into_bytes(),
+        };
+
+        headers = headers.insert(Header {
+            key: &column.header,
+            value: Some(value.as_slice()),
+        });
+    }
+
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod test {
+    use rdkafka::message::{Header, Headers, OwnedHeaders};
+    use serde_json::json;
+
+    use super::{header_fields, take_headers, HeaderColumn};
+
+    fn columns() -> Vec<HeaderColumn> {
+        vec![
+            HeaderColumn {
+                column: "tenant_id".to_string(),
+                header: "x-tenant-id".to_string(),
+                binary: false,
+            },
+            HeaderColumn {
+                column: "trace_id".to_string(),
+                header: "x-trace-id".to_string(),
+                binary: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_header_fields() {
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "x-tenant-id",
+                value: Some("acme"),
+            })
+            .insert(Header {
+                key: "x-other",
+                value: Some("ignored"),
+            });
+
+        let fields = header_fields(&columns(), Some(&headers));
+        assert_eq!(
+            serde_json::Value::Object(fields),
+            json!({"tenant_id": "acme", "trace_id": null})
+        );
+
+        let headers = OwnedHeaders::new().insert(Header {
+            key: "x-trace-id",
+            value: Some(&[0xff_u8, 0][..]),
+        });
+        let fields = header_fields(&columns(), Some(&headers));
+        assert_eq!(
+            serde_json::Value::Object(fields),
+            json!({"tenant_id": null, "trace_id": [255, 0]})
+        );
+
+        let fields = header_fields::<OwnedHeaders>(&columns(), None);
+        assert_eq!(
+            serde_json::Value::Object(fields),
+            json!({"tenant_id": null, "trace_id": null})
+        );
+    }
+
+    #[test]
+    fn test_take_headers() {
+        let mut record = json!({"id": 1, "tenant_id": "acme", "trace_id": [1, 2]});
+        let headers = take_headers(&columns(), &mut record).unwrap();
+
+        assert_eq!(record, json!({"id": 1}));
+        let headers: Vec<_> = headers
+            .iter()
+            .map(|h| (h.key.to_string(), h.value.unwrap().to_vec()))
+            .collect();
+        assert_eq!(
+            headers,
+            vec![
+                ("x-tenant-id".to_string(), b"acme".to_vec()),
+                ("x-trace-id".to_string(), vec![1, 2]),
+            ]
+        );
+
+        // null columns aren't written
+        let mut record = json!({"id": 1, "tenant_id": null});
+        let headers = take_headers(&columns(), &mut record).unwrap();
+        assert_eq!(headers.count(), 0);
+        assert_eq!(record, json!({"id": 1}));
+    }
+}
@@ -1,13 +1,26 @@
-use crate::connectors::OperatorConfig;
+use crate::connectors::{OperatorConfig, OperatorConfigSerializationMode};
 use crate::engine::{Context, StreamNode};
+use crate::formats::avro::AvroFormat;
+use crate::formats::csv::CsvOptions;
+use crate::formats::schema_registry::SchemaRegistryClient;
+use crate::operators::{SerializationMode, UserError};
 use arroyo_macro::process_fn;
+use arroyo_metrics::counter_for_task;
+use arroyo_rpc::grpc::{
+    TableDeleteBehavior, TableDescriptor, TableType as StateTableType, TableWriteBehavior,
+};
+use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp};
+use arroyo_state::tables::GlobalKeyedState;
 use arroyo_types::*;
+use prometheus::IntCounter;
 use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::time::SystemTime;
 
-use tracing::info;
+use tracing::{debug, error, info, warn};
 
-use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord};
+use rdkafka::message::OwnedHeaders;
+use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 
 use rdkafka::ClientConfig;
@@ -18,7 +31,10 @@ use rdkafka_sys::RDKafkaErrorCode;
 use serde::Serialize;
 use std::time::Duration;
 
-use super::{client_configs, KafkaConfig, KafkaTable, TableType};
+use super::{
+    client_configs, header_columns, take_headers, CommitMode, HeaderColumn, KafkaConfig,
+    KafkaTable, TableType,
+};
 
 #[cfg(test)]
 mod test;
@@ -28,22 +44,54 @@ pub struct KafkaSinkFunc<K: Key + Serialize, T: Data + Serialize> {
     topic: String,
     bootstrap_servers: String,
     producer: Option<FutureProducer>,
-    write_futures: Vec<DeliveryFuture>,
+    consistency_mode: ConsistencyMode,
+    write_futures: Vec<(String, DeliveryFuture)>,
     client_config: HashMap<String, String>,
+    serialization_mode: SerializationMode,
+    // set when records are written as avro, with the latest schema of the topic's value subject;
+    // records routed to other topics are written with that schema as well
+    avro: Option<AvroFormat>,
+    // the columns that record headers are written from, rather than to the record's value
+    headers: Vec<HeaderColumn>,
+    router: Option<fn(&T) -> Option<String>>,
+    destinations: HashMap<String, DestinationMetrics>,
     _t: PhantomData<(K, T)>,
 }
 
+enum ConsistencyMode {
+    AtLeastOnce,
+    /// Records are written in a transaction per checkpoint, which is committed once the
+    /// checkpoint completes. Each transaction uses its own producer, as the next one has to be
+    /// started before the previous one is committed.
+    ExactlyOnce {
+        next_transaction_index: usize,
+        // the producer of the last checkpoint's transaction, with that checkpoint's epoch
+        producer_to_complete: Option<(u32, FutureProducer)>,
+    },
+}
+
+struct DestinationMetrics {
+    messages: Option<IntCounter>,
+    errors: Option<IntCounter>,
+}
+
 impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
     pub fn new(servers: &str, topic: &str, client_config: Vec<(&str, &str)>) -> Self {
         KafkaSinkFunc {
             topic: topic.to_string(),
             bootstrap_servers: servers.to_string(),
             producer: None,
+            consistency_mode: ConsistencyMode::AtLeastOnce,
             write_futures: vec![],
             client_config: client_config
                 .iter()
                 .map(|(key, value)| (key.to_string(), value.to_string()))
                 .collect(),
+            serialization_mode: SerializationMode::Json,
+            avro: None,
+            headers: vec![],
+            router: None,
+            destinations: HashMap::new(),
             _t: PhantomData,
         }
     }
@@ -55,28 +103,108 @@ impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
             .expect("Invalid connection config for KafkaSink");
         let table: KafkaTable =
             serde_json::from_value(config.table).expect("Invalid table config for KafkaSource");
-        let TableType::Sink{ .. } = &table.type_ else {
+        let TableType::Sink { commit_mode } = &table.type_ else {
             panic!("found non-sink kafka config in sink operator");
         };
 
+        let mut client_config = client_configs(&connection);
+
+        let avro = matches!(
+            config.serialization_mode,
+            Some(OperatorConfigSerializationMode::Avro)
+        )
+        .then(|| {
+            let registry = connection
+                .schema_registry
+                .as_ref()
+                .expect("avro kafka sink without a schema registry");
+            AvroFormat::new(
+                SchemaRegistryClient::new(registry).expect("Invalid schema registry config"),
+            )
+        });
+
+        // the producer batches records itself, so the batching options map to its settings
+        if let Some(batching) = config.batching {
+            if let Some(max_records) = batching.max_records {
+                client_config.insert("batch.num.messages".to_string(), max_records.to_string());
+            }
+            if let Some(max_bytes) = batching.max_bytes {
+                client_config.insert("batch.size".to_string(), max_bytes.to_string());
+            }
+            if let Some(linger) = batching.max_linger_micros {
+                client_config.insert(
+                    "linger.ms".to_string(),
+                    (linger as f64 / 1000.0).to_string(),
+                );
+            }
+        }
+
         Self {
+            headers: header_columns(&table),
             topic: table.topic,
             bootstrap_servers: connection.bootstrap_servers.to_string(),
             producer: None,
+            consistency_mode: match commit_mode {
+                Some(CommitMode::ExactlyOnce) => ConsistencyMode::ExactlyOnce {
+                    next_transaction_index: 0,
+                    producer_to_complete: None,
+                },
+                None | Some(CommitMode::AtLeastOnce) => ConsistencyMode::AtLeastOnce,
+            },
             write_futures: vec![],
-            client_config: client_configs(&connection),
+            client_config,
+            serialization_mode: match config.serialization_mode {
+                Some(OperatorConfigSerializationMode::RawBytes) => SerializationMode::RawBytes,
+                Some(OperatorConfigSerializationMode::Avro) => SerializationMode::Avro,
+                Some(OperatorConfigSerializationMode::Csv) => {
+                    SerializationMode::Csv(CsvOptions::from_config(config.csv.as_ref()))
+                }
+                _ => SerializationMode::Json,
+            },
+            avro,
+            router: None,
+            destinations: HashMap::new(),
             _t: PhantomData,
         }
     }
-}
 
-#[process_fn(in_k = K, in_t = T)]
-impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
-    fn name(&self) -> String {
-        format!("kafka-producer-{}", self.topic)
+    /// Routes each record to the topic computed by `router`, or to the sink's topic if it
+    /// returns `None`. Failures to write to a routed topic are reported and the records dropped,
+    /// so that a bad destination doesn't stop records from being written to the others.
+    pub fn with_router(mut self, router: fn(&T) -> Option<String>) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// Writes records in transactions that are committed when checkpoints complete
+    pub fn exactly_once(mut self) -> Self {
+        self.consistency_mode = ConsistencyMode::ExactlyOnce {
+            next_transaction_index: 0,
+            producer_to_complete: None,
+        };
+        self
+    }
+
+    fn serialize<V: Serialize>(&self, value: &V) -> Result<Vec<u8>, UserError> {
+        match &self.avro {
+            Some(avro) => avro.serialize(value),
+            None => self.serialization_mode.serialize(value),
+        }
+    }
+
+    /// The payload and headers to write for the record
+    fn message(&self, value: &T) -> Result<(Vec<u8>, Option<OwnedHeaders>), UserError> {
+        if self.headers.is_empty() {
+            return Ok((self.serialize(value)?, None));
+        }
+
+        let mut value = serde_json::to_value(value)
+            .map_err(|e| UserError::new("Serialization error", format!("{:?}", e)))?;
+        let headers = take_headers(&self.headers, &mut value)?;
+        Ok((self.serialize(&value)?, Some(headers)))
     }
 
-    async fn on_start(&mut self, _ctx: &mut Context<(), ()>) {
+    fn init_producer(&mut self, task_info: &TaskInfo) -> Result<(), KafkaError> {
         info!("Creating kafka producer for {}", self.bootstrap_servers);
         let mut client_config = ClientConfig::new();
 
@@ -86,14 +214,266 @@ impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
             client_config.set(key, value);
         }
 
-        self.producer = Some(client_config.create().expect("Producer creation failed"));
+        match &mut self.consistency_mode {
+            ConsistencyMode::AtLeastOnce => {
+                self.producer = Some(client_config.create()?);
+            }
+            ConsistencyMode::ExactlyOnce {
+                next_transaction_index,
+                ..
+            } => {
+                // initializing a producer with a transactional id aborts any transaction left
+                // open by an earlier producer with that id, so restoring the index from the
+                // checkpoint fences off writes made after it
+                client_config.set(
+                    "transactional.id",
+                    format!(
+                        "arroyo-id-{}-{}-{}-{}",
+                        task_info.job_id,
+                        task_info.operator_id,
+                        task_info.task_index,
+                        next_transaction_index
+                    ),
+                );
+                let producer: FutureProducer = client_config.create()?;
+                producer.init_transactions(Timeout::After(Duration::from_secs(30)))?;
+                producer.begin_transaction()?;
+                *next_transaction_index += 1;
+                self.producer = Some(producer);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("kafka-producer-{}", self.topic)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        match self.consistency_mode {
+            ConsistencyMode::AtLeastOnce => vec![],
+            // writes to this table are committed, which has the controller send the commit message
+            // that completes the transaction
+            ConsistencyMode::ExactlyOnce { .. } => vec![TableDescriptor {
+                name: "i".into(),
+                description: "kafka transaction index".into(),
+                table_type: StateTableType::Global as i32,
+                delete_behavior: TableDeleteBehavior::None as i32,
+                write_behavior: TableWriteBehavior::CommitWrites as i32,
+                retention_micros: 0,
+            }],
+        }
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        for server in self.bootstrap_servers.split(',') {
+            let server = server.trim();
+            let host = server.rsplit_once(':').map(|(h, _)| h).unwrap_or(server);
+            if let Err(e) = check_egress(host) {
+                ctx.report_error("Connection not allowed".to_string(), e.clone())
+                    .await;
+                panic!("{}", e);
+            }
+        }
+
+        if let ConsistencyMode::ExactlyOnce {
+            next_transaction_index,
+            ..
+        } = &mut self.consistency_mode
+        {
+            let mut index_state: GlobalKeyedState<usize, usize, _> =
+                ctx.state.get_global_keyed_state('i').await;
+            *next_transaction_index = index_state
+                .get(&ctx.task_info.task_index)
+                .copied()
+                .unwrap_or_default();
+        }
+
+        if let Some(avro) = &mut self.avro {
+            let subject = format!("{}-value", self.topic);
+            if let Err(e) = avro.load_writer_schema(&subject).await {
+                ctx.report_disconnected(e.to_string()).await;
+                panic!("Failed to load avro schema: {:?}", e);
+            }
+        }
+
+        match self.init_producer(&ctx.task_info) {
+            Ok(()) => {
+                ctx.report_connected().await;
+            }
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                panic!("Producer creation failed: {:?}", e);
+            }
+        }
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        if !matches!(self.consistency_mode, ConsistencyMode::ExactlyOnce { .. }) {
+            return;
+        }
+
+        // the final checkpoint's transaction is committed once the controller has finished it,
+        // which may come after other control messages
+        while let ConsistencyMode::ExactlyOnce {
+            producer_to_complete: Some(_),
+            ..
+        } = &self.consistency_mode
+        {
+            match ctx.control_rx.recv().await {
+                Some(ControlMessage::Commit { epoch }) => {
+                    self.handle_commit(epoch, ctx).await;
+                }
+                Some(other) => {
+                    debug!("ignoring {:?} while waiting for the final commit", other);
+                }
+                None => {
+                    warn!("control channel closed before the final commit, not committing");
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle_checkpoint(
+        &mut self,
+        checkpoint_barrier: &CheckpointBarrier,
+        ctx: &mut Context<(), ()>,
+    ) {
+        self.flush(ctx).await;
+
+        if let ConsistencyMode::ExactlyOnce {
+            next_transaction_index,
+            producer_to_complete,
+        } = &mut self.consistency_mode
+        {
+            *producer_to_complete = self
+                .producer
+                .take()
+                .map(|producer| (checkpoint_barrier.epoch, producer));
+
+            let mut index_state: GlobalKeyedState<usize, usize, _> =
+                ctx.state.get_global_keyed_state('i').await;
+            index_state
+                .insert(ctx.task_info.task_index, *next_transaction_index)
+                .await;
+
+            if !checkpoint_barrier.then_stop {
+                if let Err(e) = self.init_producer(&ctx.task_info) {
+                    ctx.report_disconnected(e.to_string()).await;
+                    panic!("Producer creation failed: {:?}", e);
+                }
+            }
+        }
+    }
+
+    async fn handle_commit(&mut self, epoch: u32, ctx: &mut Context<(), ()>) {
+        let ConsistencyMode::ExactlyOnce {
+            producer_to_complete,
+            ..
+        } = &mut self.consistency_mode
+        else {
+            warn!("received commit for kafka sink that isn't exactly-once");
+            return;
+        };
+
+        match producer_to_complete.take() {
+            Some((producer_epoch, producer)) if producer_epoch <= epoch => {
+                let mut attempts = 0;
+                while let Err(e) =
+                    producer.commit_transaction(Timeout::After(Duration::from_secs(10)))
+                {
+                    attempts += 1;
+                    if attempts == 5 {
+                        panic!(
+                            "Failed to commit kafka transaction after {} attempts: {:?}",
+                            attempts, e
+                        );
+                    }
+                    error!(
+                        "Failed to commit kafka transaction ({} attempts), retrying: {:?}",
+                        attempts, e
+                    );
+                }
+            }
+            pending => {
+                // after a restore the controller re-sends the commit for the restored epoch. The
+                // transaction from before the restart can't be resumed by a new producer, so it
+                // will have been aborted when the producer for the restored index was initialized
+                info!("no kafka transaction to commit for epoch {}", epoch);
+                *producer_to_complete = pending;
+            }
+        }
+
+        ctx.control_tx
+            .send(ControlResp::CheckpointEvent(CheckpointEvent {
+                checkpoint_epoch: epoch,
+                operator_id: ctx.task_info.operator_id.clone(),
+                subtask_index: ctx.task_info.task_index as u32,
+                time: SystemTime::now(),
+                event_type: arroyo_rpc::grpc::TaskCheckpointEventType::FinishedCommit.into(),
+            }))
+            .await
+            .expect("sent commit event");
     }
 
-    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
-        self.flush().await;
+    async fn handle_raw_control_message(
+        &mut self,
+        control_message: ControlMessage,
+        ctx: &mut Context<(), ()>,
+    ) {
+        match control_message {
+            ControlMessage::Commit { epoch } => {
+                self.handle_commit(epoch, ctx).await;
+            }
+            other => warn!("default handling of control message {:?}", other),
+        }
+    }
+
+    fn destination(&mut self, topic: &str, ctx: &Context<(), ()>) -> &DestinationMetrics {
+        self.destinations
+            .entry(topic.to_string())
+            .or_insert_with(|| {
+                let labels = HashMap::from([("destination".to_string(), topic.to_string())]);
+                DestinationMetrics {
+                    messages: counter_for_task(
+                        &ctx.task_info,
+                        SINK_ROUTED_MESSAGES,
+                        "Count of messages written to each destination of a routed sink",
+                        labels.clone(),
+                    ),
+                    errors: counter_for_task(
+                        &ctx.task_info,
+                        SINK_ROUTING_ERRORS,
+                        "Count of messages that failed to be written to each destination of a routed sink",
+                        labels,
+                    ),
+                }
+            })
     }
 
-    async fn flush(&mut self) {
+    /// Handles a failure to write to `topic`, which fails the sink unless the topic was chosen by
+    /// the router
+    async fn write_failed(&mut self, topic: &str, e: KafkaError, ctx: &mut Context<(), ()>) {
+        if topic == self.topic || self.router.is_none() {
+            panic!("Unhandled kafka error: {:?}", e);
+        }
+
+        if let Some(errors) = &self.destination(topic, ctx).errors {
+            errors.inc();
+        }
+        ctx.report_error(
+            format!("Failed to write to topic {}", topic),
+            format!("{:?}", e),
+        )
+        .await;
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
         self.producer
             .as_ref()
             .unwrap()
@@ -103,36 +483,48 @@ impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
             .poll(Timeout::After(Duration::ZERO));
 
         // ensure all messages were delivered before finishing the checkpoint
-        for future in self.write_futures.drain(..) {
+        let futures: Vec<_> = self.write_futures.drain(..).collect();
+        for (topic, future) in futures {
             match future.await.expect("Kafka producer shut down") {
                 Ok(_) => {}
                 Err((e, _)) => {
-                    panic!("Unhandled kafka error: {:?}", e);
+                    self.write_failed(&topic, e, ctx).await;
                 }
             }
         }
     }
 
-    async fn publish(&mut self, k: Option<String>, v: String) {
+    async fn publish(
+        &mut self,
+        topic: String,
+        k: Option<String>,
+        v: Vec<u8>,
+        headers: Option<OwnedHeaders>,
+        ctx: &mut Context<(), ()>,
+    ) {
         let mut rec = {
             if let Some(k) = k.as_ref() {
-                FutureRecord::to(&self.topic).key(k).payload(&v)
+                FutureRecord::to(&topic).key(k).payload(&v)
             } else {
-                FutureRecord::to(&self.topic).payload(&v)
+                FutureRecord::to(&topic).payload(&v)
             }
         };
+        if let Some(headers) = headers {
+            rec = rec.headers(headers);
+        }
 
         loop {
             match self.producer.as_mut().unwrap().send_result(rec) {
                 Ok(future) => {
-                    self.write_futures.push(future);
+                    self.write_futures.push((topic.clone(), future));
                     return;
                 }
                 Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), f)) => {
                     rec = f;
                 }
                 Err((e, _)) => {
-                    panic!("Unhandled kafka error: {:?}", e);
+                    self.write_failed(&topic, e, ctx).await;
+                    return;
                 }
             }
 
@@ -141,13 +533,32 @@ impl<K: Key + Serialize, T: Data + Serialize> KafkaSinkFunc<K, T> {
         }
     }
 
-    async fn process_element(&mut self, record: &Record<K, T>, _ctx: &mut Context<(), ()>) {
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
         let k = record
             .key
             .as_ref()
             .map(|k| serde_json::to_string(k).unwrap());
-        let v = serde_json::to_string(&record.value).unwrap();
+        let (v, headers) = match self.message(&record.value) {
+            Ok(message) => message,
+            Err(e) => {
+                ctx.report_error(e.name, e.details).await;
+                return;
+            }
+        };
+
+        let topic = match self.router {
+            Some(router) => {
+                let topic = router(&record.value).unwrap_or_else(|| self.topic.clone());
+                if let Some(messages) = &self.destination(&topic, ctx).messages {
+                    messages.inc();
+                }
+                topic
+            }
+            None => self.topic.clone(),
+        };
 
-        self.publish(k, v).await;
+        self.publish(topic, k, v, headers, ctx).await;
     }
 }
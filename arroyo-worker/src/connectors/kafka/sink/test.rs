@@ -2,13 +2,15 @@
 use std::time::{Duration, SystemTime};
 
 use crate::engine::{Context, OutQueue};
+use arroyo_rpc::grpc::TaskCheckpointEventType;
+use arroyo_rpc::{ControlMessage, ControlResp};
 use arroyo_types::CheckpointBarrier;
 use arroyo_types::*;
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
 use rdkafka::consumer::{Consumer, StreamConsumer};
 use rdkafka::producer::Producer;
 use rdkafka::{ClientConfig, Message};
-use tokio::sync::mpsc::channel;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 use super::KafkaSinkFunc;
 
@@ -45,9 +47,13 @@ impl KafkaTopicTester {
     }
 
     async fn get_sink_with_writes(&self) -> KafkaSinkWithWrites {
-        let mut kafka = KafkaSinkFunc::new(&self.server, &self.topic, vec![]);
-        let (_, control_rx) = channel(128);
-        let (command_tx, _) = channel(128);
+        self.start_sink(KafkaSinkFunc::new(&self.server, &self.topic, vec![]))
+            .await
+    }
+
+    async fn start_sink(&self, mut kafka: KafkaSinkFunc<String, String>) -> KafkaSinkWithWrites {
+        let (control_tx, control_rx) = channel(128);
+        let (command_tx, command_rx) = channel(128);
         let (data_tx, _recv) = channel(128);
 
         let task_info = arroyo_types::get_test_task_info();
@@ -59,12 +65,17 @@ impl KafkaTopicTester {
             command_tx,
             1,
             vec![vec![OutQueue::new(data_tx, false)]],
-            vec![],
+            kafka.tables(),
         )
         .await;
         kafka.on_start(&mut ctx).await;
 
-        KafkaSinkWithWrites { sink: kafka, ctx }
+        KafkaSinkWithWrites {
+            sink: kafka,
+            ctx,
+            control_tx,
+            command_rx,
+        }
     }
 
     fn get_consumer(&mut self, job_id: &str) -> StreamConsumer {
@@ -102,6 +113,22 @@ async fn get_data(consumer: &mut StreamConsumer) -> Record<String, String> {
 struct KafkaSinkWithWrites {
     sink: KafkaSinkFunc<String, String>,
     ctx: Context<(), ()>,
+    control_tx: Sender<ControlMessage>,
+    command_rx: Receiver<ControlResp>,
+}
+
+impl KafkaSinkWithWrites {
+    // the epoch of the next commit the sink reports having finished
+    async fn finished_commit(&mut self) -> u32 {
+        while let Some(resp) = self.command_rx.recv().await {
+            if let ControlResp::CheckpointEvent(event) = resp {
+                if event.event_type == TaskCheckpointEventType::FinishedCommit {
+                    return event.checkpoint_epoch;
+                }
+            }
+        }
+        panic!("sink stopped reporting");
+    }
 }
 
 #[tokio::test]
@@ -181,3 +208,218 @@ async fn test_kafka() {
         assert_eq!(record.value, result);
     }
 }
+
+#[tokio::test]
+async fn test_kafka_routing() {
+    let mut routed_tester = KafkaTopicTester {
+        topic: "arroyo-sink-routed-odd".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+    let mut default_tester = KafkaTopicTester {
+        topic: "arroyo-sink-routed".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+
+    routed_tester.create_topic("routed", 1).await;
+    default_tester.create_topic("routed", 1).await;
+
+    let mut sink_with_writes = default_tester.get_sink_with_writes().await;
+    sink_with_writes.sink.router = Some(|v: &String| {
+        (v.parse::<u32>().unwrap() % 2 == 1).then(|| "arroyo-sink-routed-odd".to_string())
+    });
+
+    let mut routed_consumer = routed_tester.get_consumer("routed-odd");
+    let mut default_consumer = default_tester.get_consumer("routed");
+
+    for message in 1u32..10 {
+        let mut record = Record {
+            timestamp: SystemTime::now(),
+            key: None,
+            value: message.to_string(),
+        };
+
+        sink_with_writes
+            .sink
+            .process_element(&mut record, &mut sink_with_writes.ctx)
+            .await;
+    }
+    let barrier = &CheckpointBarrier {
+        epoch: (2),
+        min_epoch: 0,
+        timestamp: (SystemTime::now()),
+        then_stop: false,
+    };
+    sink_with_writes
+        .sink
+        .handle_checkpoint(barrier, &mut sink_with_writes.ctx)
+        .await;
+
+    for message in (1u32..10).step_by(2) {
+        let result: String =
+            serde_json::from_str(&get_data(&mut routed_consumer).await.value).unwrap();
+        assert_eq!(message.to_string(), result);
+    }
+    for message in (2u32..10).step_by(2) {
+        let result: String =
+            serde_json::from_str(&get_data(&mut default_consumer).await.value).unwrap();
+        assert_eq!(message.to_string(), result);
+    }
+}
+
+#[tokio::test]
+async fn test_kafka_exactly_once() {
+    let mut kafka_topic_tester = KafkaTopicTester {
+        topic: "arroyo-sink-exactly-once".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+
+    kafka_topic_tester.create_topic("exactly-once", 1).await;
+    let mut sink_with_writes = kafka_topic_tester
+        .start_sink(
+            KafkaSinkFunc::new(
+                &kafka_topic_tester.server,
+                &kafka_topic_tester.topic,
+                vec![],
+            )
+            .exactly_once(),
+        )
+        .await;
+    // consumers default to read_committed, so only see committed transactions
+    let mut consumer = kafka_topic_tester.get_consumer("exactly-once");
+
+    for message in 1u32..20 {
+        let mut record = Record {
+            timestamp: SystemTime::now(),
+            key: None,
+            value: message.to_string(),
+        };
+
+        sink_with_writes
+            .sink
+            .process_element(&mut record, &mut sink_with_writes.ctx)
+            .await;
+    }
+    let barrier = &CheckpointBarrier {
+        epoch: (2),
+        min_epoch: 0,
+        timestamp: (SystemTime::now()),
+        then_stop: false,
+    };
+    sink_with_writes
+        .sink
+        .handle_checkpoint(barrier, &mut sink_with_writes.ctx)
+        .await;
+
+    assert!(
+        tokio::time::timeout(Duration::from_secs(2), get_data(&mut consumer))
+            .await
+            .is_err(),
+        "records should not be visible before the commit"
+    );
+
+    sink_with_writes
+        .sink
+        .handle_commit(2, &mut sink_with_writes.ctx)
+        .await;
+
+    for message in 1u32..20 {
+        let result: String = serde_json::from_str(&get_data(&mut consumer).await.value).unwrap();
+        assert_eq!(message.to_string(), result);
+    }
+}
+
+#[tokio::test]
+async fn test_kafka_exactly_once_commits_on_close() {
+    let mut kafka_topic_tester = KafkaTopicTester {
+        topic: "arroyo-sink-exactly-once-close".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+
+    kafka_topic_tester
+        .create_topic("exactly-once-close", 1)
+        .await;
+    let mut sink_with_writes = kafka_topic_tester
+        .start_sink(
+            KafkaSinkFunc::new(
+                &kafka_topic_tester.server,
+                &kafka_topic_tester.topic,
+                vec![],
+            )
+            .exactly_once(),
+        )
+        .await;
+    let mut consumer = kafka_topic_tester.get_consumer("exactly-once-close");
+
+    for message in 1u32..10 {
+        let mut record = Record {
+            timestamp: SystemTime::now(),
+            key: None,
+            value: message.to_string(),
+        };
+
+        sink_with_writes
+            .sink
+            .process_element(&mut record, &mut sink_with_writes.ctx)
+            .await;
+    }
+    let barrier = &CheckpointBarrier {
+        epoch: (3),
+        min_epoch: 0,
+        timestamp: (SystemTime::now()),
+        then_stop: true,
+    };
+    sink_with_writes
+        .sink
+        .handle_checkpoint(barrier, &mut sink_with_writes.ctx)
+        .await;
+
+    // a commit for an earlier epoch and other messages can arrive before the final commit
+    for message in [
+        ControlMessage::Commit { epoch: 2 },
+        ControlMessage::SetPaused { paused: false },
+        ControlMessage::Commit { epoch: 3 },
+    ] {
+        sink_with_writes.control_tx.send(message).await.unwrap();
+    }
+    sink_with_writes
+        .sink
+        .on_close(&mut sink_with_writes.ctx)
+        .await;
+
+    assert_eq!(sink_with_writes.finished_commit().await, 2);
+    assert_eq!(sink_with_writes.finished_commit().await, 3);
+    for message in 1u32..10 {
+        let result: String = serde_json::from_str(&get_data(&mut consumer).await.value).unwrap();
+        assert_eq!(message.to_string(), result);
+    }
+}
+
+#[tokio::test]
+async fn test_kafka_commit_after_restore() {
+    let mut kafka_topic_tester = KafkaTopicTester {
+        topic: "arroyo-sink-commit-after-restore".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+
+    kafka_topic_tester
+        .create_topic("commit-after-restore", 1)
+        .await;
+    let mut sink_with_writes = kafka_topic_tester
+        .start_sink(
+            KafkaSinkFunc::new(
+                &kafka_topic_tester.server,
+                &kafka_topic_tester.topic,
+                vec![],
+            )
+            .exactly_once(),
+        )
+        .await;
+
+    // the controller re-sends the commit for the restored epoch, which has no transaction here
+    sink_with_writes
+        .sink
+        .handle_commit(4, &mut sink_with_writes.ctx)
+        .await;
+
+    assert_eq!(sink_with_writes.finished_commit().await, 4);
+}
@@ -9,8 +9,10 @@ use arroyo_types::*;
 use bincode::{Decode, Encode};
 use governor::{Quota, RateLimiter};
 use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::BorrowedMessage;
 use rdkafka::{ClientConfig, Message as KMessage, Offset, TopicPartitionList};
 use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::num::NonZeroU32;
@@ -18,9 +20,15 @@ use std::time::Duration;
 use tokio::select;
 use tracing::{debug, error, info, warn};
 
-use crate::operators::{SerializationMode, UserError};
+use crate::formats::avro::AvroFormat;
+use crate::formats::csv::CsvOptions;
+use crate::formats::protobuf::ProtobufFormat;
+use crate::formats::schema_registry::SchemaRegistryClient;
+use crate::operators::{BadData, Lineage, SerializationMode, UserError};
 
-use super::{client_configs, KafkaConfig, KafkaTable, TableType};
+use super::{
+    client_configs, header_columns, header_fields, HeaderColumn, KafkaConfig, KafkaTable, TableType,
+};
 
 #[cfg(test)]
 mod test;
@@ -35,6 +43,16 @@ where
     bootstrap_servers: String,
     offset_mode: super::SourceOffset,
     serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    // set when the topic is read as avro, with the schemas from the schema registry
+    avro: Option<AvroFormat>,
+    // set when the topic is read as protobuf, with the message descriptor from the config
+    protobuf: Option<ProtobufFormat>,
+    bad_data: BadData,
+    // whether records are read with the topic, partition and offset they came from
+    lineage: bool,
+    // the columns that record headers are read into
+    headers: Vec<HeaderColumn>,
     client_configs: HashMap<String, String>,
     messages_per_second: NonZeroU32,
     _t: PhantomData<(K, T)>,
@@ -69,6 +87,12 @@ where
             bootstrap_servers: servers.to_string(),
             offset_mode,
             serialization_mode,
+            format_error: None,
+            avro: None,
+            protobuf: None,
+            bad_data: BadData::default(),
+            lineage: false,
+            headers: vec![],
             client_configs: client_configs
                 .iter()
                 .map(|(key, value)| (key.to_string(), value.to_string()))
@@ -89,21 +113,63 @@ where
             panic!("found non-source kafka config in source operator");
         };
 
+        let avro = matches!(
+            config.serialization_mode,
+            Some(OperatorConfigSerializationMode::Avro)
+        )
+        .then(|| {
+            let registry = connection
+                .schema_registry
+                .as_ref()
+                .expect("avro kafka source without a schema registry");
+            AvroFormat::new(
+                SchemaRegistryClient::new(registry).expect("Invalid schema registry config"),
+            )
+        });
+
+        let protobuf = matches!(
+            config.serialization_mode,
+            Some(OperatorConfigSerializationMode::Protobuf)
+        )
+        .then(|| {
+            let protobuf = config
+                .protobuf
+                .as_ref()
+                .expect("protobuf kafka source without a message descriptor");
+            ProtobufFormat::new(protobuf).expect("Invalid protobuf config")
+        });
+
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => {
+                Ok(SerializationMode::JsonSchemaRegistry)
+            }
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "kafka"))
+            }
+            OperatorConfigSerializationMode::Avro => Ok(SerializationMode::Avro),
+            OperatorConfigSerializationMode::Protobuf => Ok(SerializationMode::Protobuf),
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
         Self {
+            headers: header_columns(&table),
             topic: table.topic,
             bootstrap_servers: connection.bootstrap_servers.to_string(),
             offset_mode: *offset,
-            serialization_mode: match config.serialization_mode.unwrap() {
-                OperatorConfigSerializationMode::Json => SerializationMode::Json,
-                OperatorConfigSerializationMode::JsonSchemaRegistry => {
-                    SerializationMode::JsonSchemaRegistry
-                }
-                OperatorConfigSerializationMode::RawJson => SerializationMode::RawJson,
-                OperatorConfigSerializationMode::DebeziumJson => SerializationMode::Json,
-                OperatorConfigSerializationMode::Parquet => {
-                    unimplemented!("parquet out of kafka source doesn't make sense")
-                }
-            },
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            avro,
+            protobuf,
+            bad_data: config.bad_data.into(),
+            lineage: config.lineage.unwrap_or(false),
             client_configs: client_configs(&connection),
             messages_per_second: NonZeroU32::new(
                 config
@@ -124,8 +190,33 @@ where
         tables()
     }
 
+    /// The fields of the record that come from the message's metadata rather than its payload
+    fn message_fields(&self, msg: &BorrowedMessage) -> Map<String, Value> {
+        let mut fields = Map::new();
+        if self.lineage {
+            Lineage {
+                source: &self.topic,
+                partition: Some(msg.partition()),
+                offset: msg.offset(),
+            }
+            .add_to(&mut fields);
+        }
+
+        if !self.headers.is_empty() {
+            fields.extend(header_fields(&self.headers, msg.headers()));
+        }
+
+        fields
+    }
+
     async fn get_consumer(&mut self, ctx: &mut Context<(), T>) -> anyhow::Result<StreamConsumer> {
         info!("Creating kafka consumer for {}", self.bootstrap_servers);
+        for server in self.bootstrap_servers.split(',') {
+            let server = server.trim();
+            let host = server.rsplit_once(':').map(|(h, _)| h).unwrap_or(server);
+            check_egress(host).map_err(|e| anyhow::anyhow!(e))?;
+        }
+
         let mut client_config = ClientConfig::new();
 
         for (key, value) in &self.client_configs {
@@ -152,6 +243,17 @@ where
         let has_state = !state.is_empty();
 
         let state: HashMap<i32, KafkaState> = state.iter().map(|s| (s.partition, **s)).collect();
+
+        // if the job is being restored with an override for this source, the checkpointed
+        // offsets are ignored
+        let offset_override = restore_override(&ctx.task_info.operator_id);
+        if let Some(o) = offset_override {
+            info!(
+                "Overriding restored offsets for topic {}; starting from {}",
+                self.topic, o
+            );
+        }
+
         let metadata = consumer.fetch_metadata(Some(&self.topic), Duration::from_secs(30))?;
 
         info!("Fetched metadata for topic {}", self.topic);
@@ -163,25 +265,39 @@ where
                 .enumerate()
                 .filter(|(i, _)| i % ctx.task_info.parallelism == ctx.task_info.task_index)
                 .map(|(_, p)| {
-                    let offset = state
-                        .get(&p.id())
-                        .map(|s| Offset::Offset(s.offset))
-                        .unwrap_or_else(|| {
-                            if has_state {
-                                // if we've restored partitions and we don't know about this one, that means it's
-                                // new, and we want to start from the beginning so we don't drop data
-                                Offset::Beginning
-                            } else {
-                                self.offset_mode.get_offset()
-                            }
-                        });
+                    let offset = match offset_override {
+                        Some(SourceOffsetOverride::Earliest) => Offset::Beginning,
+                        Some(SourceOffsetOverride::Latest) => Offset::End,
+                        // resolved to offsets below
+                        Some(SourceOffsetOverride::Timestamp { micros }) => {
+                            Offset::Offset((micros / 1000) as i64)
+                        }
+                        None => state
+                            .get(&p.id())
+                            .map(|s| Offset::Offset(s.offset))
+                            .unwrap_or_else(|| {
+                                if has_state {
+                                    // if we've restored partitions and we don't know about this one, that means it's
+                                    // new, and we want to start from the beginning so we don't drop data
+                                    Offset::Beginning
+                                } else {
+                                    self.offset_mode.get_offset()
+                                }
+                            }),
+                    };
 
                     ((self.topic.clone(), p.id()), offset)
                 })
                 .collect()
         };
 
-        let topic_partitions = TopicPartitionList::from_topic_map(&our_partitions)?;
+        let mut topic_partitions = TopicPartitionList::from_topic_map(&our_partitions)?;
+
+        if let Some(SourceOffsetOverride::Timestamp { .. }) = offset_override {
+            // partitions without any messages at or after the timestamp start from the end
+            topic_partitions =
+                consumer.offsets_for_times(topic_partitions, Duration::from_secs(30))?;
+        }
 
         consumer.assign(&topic_partitions)?;
 
@@ -189,6 +305,11 @@ where
     }
 
     async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
@@ -208,34 +329,65 @@ where
     }
 
     async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
-        let consumer = self
-            .get_consumer(ctx)
-            .await
-            .map_err(|e| UserError::new("Could not create Kafka consumer", format!("{:?}", e)))?;
+        let consumer = match self.get_consumer(ctx).await {
+            Ok(consumer) => consumer,
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                return Err(UserError::new(
+                    "Could not create Kafka consumer",
+                    format!("{:?}", e),
+                ));
+            }
+        };
+        ctx.report_connected().await;
+        let mut connected = true;
 
         let rate_limiter = RateLimiter::direct(Quota::per_second(self.messages_per_second));
         let mut offsets = HashMap::new();
+        // set while watermark alignment is holding this source back
+        let mut paused = false;
         loop {
             select! {
-                message = consumer.recv() => {
+                message = consumer.recv(), if !paused => {
                     match message {
                         Ok(msg) => {
+                            if !connected {
+                                ctx.report_connected().await;
+                                connected = true;
+                            }
                             if let Some(v) = msg.payload() {
                                 let timestamp = msg.timestamp().to_millis()
                                     .ok_or_else(|| UserError::new("Failed to read timestamp from Kafka record",
                                         "The message read from Kafka did not contain a message timestamp"))?;
 
+                                ctx.profile_source_record(v);
+                                let fields = self.message_fields(&msg);
+                                let value = if let Some(avro) = &mut self.avro {
+                                    avro.deserialize(v, &fields).await?
+                                } else if let Some(protobuf) = &self.protobuf {
+                                    protobuf.deserialize(v, &fields)?
+                                } else if !fields.is_empty() {
+                                    self.serialization_mode.deserialize_slice_with_fields(v, self.bad_data, &fields)?
+                                } else {
+                                    self.serialization_mode.deserialize_slice(v, self.bad_data)?
+                                };
+
+                                ctx.report_source_lag(from_millis(timestamp as u64));
                                 ctx.collector.collect(Record {
                                     timestamp: from_millis(timestamp as u64),
                                     key: None,
-                                    value: self.serialization_mode.deserialize_slice(v)?,
+                                    value,
                                 }).await;
                                 offsets.insert(msg.partition(), msg.offset());
                                 rate_limiter.until_ready().await;
                             }
                         },
                         Err(err) => {
-                            error!("encountered error {}", err)
+                            error!("encountered error {}", err);
+                            if connected {
+                                ctx.report_disconnected(err.to_string()).await;
+                                connected = false;
+                            }
                         }
                     }
                 }
@@ -274,11 +426,21 @@ where
                                 StopMode::Immediate => {
                                     return Ok(SourceFinishType::Immediate);
                                 }
+                                StopMode::Drain => {
+                                    return Ok(SourceFinishType::Final);
+                                }
                             }
                         }
                         Some(ControlMessage::Commit { epoch: _ }) => {
                             unreachable!("sources shouldn't receive commit messages");
                         }
+                        Some(ControlMessage::SetPaused { paused: p }) => {
+                            debug!("kafka source {} paused: {}", ctx.task_info.task_index, p);
+                            paused = p;
+                        }
+                        Some(ControlMessage::InjectProbe { probe_id }) => {
+                            ctx.handle_probe(probe_id).await;
+                        }
                         None => {
 
                         }
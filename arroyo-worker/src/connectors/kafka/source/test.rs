@@ -6,7 +6,7 @@ use crate::connectors::kafka::source;
 use crate::engine::{Context, OutQueue, QueueItem};
 use arroyo_rpc::grpc::{CheckpointMetadata, OperatorCheckpointMetadata};
 use arroyo_rpc::{CheckpointCompleted, ControlMessage, ControlResp};
-use arroyo_types::{to_micros, CheckpointBarrier, Message, TaskInfo};
+use arroyo_types::{to_micros, CheckpointBarrier, Message, TaskInfo, STATE_VERSION};
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
 use rdkafka::producer::{BaseProducer, BaseRecord};
 use rdkafka::ClientConfig;
@@ -78,6 +78,7 @@ impl KafkaTopicTester {
             start_time: to_micros(SystemTime::now()),
             finish_time: to_micros(SystemTime::now()),
             operator_ids: vec![task_info.operator_id.clone()],
+            state_version: STATE_VERSION,
         });
 
         let mut ctx: Context<(), TestData> = Context::new(
@@ -231,6 +232,7 @@ async fn test_kafka() {
         tables: source::tables(),
         backend_data: checkpoint_completed.subtask_metadata.backend_data,
         bytes: checkpoint_completed.subtask_metadata.bytes,
+        table_schemas: checkpoint_completed.subtask_metadata.table_schemas,
     })
     .await;
 
@@ -241,6 +243,7 @@ async fn test_kafka() {
         start_time: 0,
         finish_time: 0,
         operator_ids: vec![task_info.operator_id.clone()],
+        state_version: STATE_VERSION,
     })
     .await;
 
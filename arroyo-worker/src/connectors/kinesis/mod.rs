@@ -0,0 +1,21 @@
+use std::str::FromStr;
+
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/kinesis/connection.json");
+import_types!(schema = "../connector-schemas/kinesis/table.json");
+
+fn region(connection: KinesisConfig) -> Region {
+    match connection.endpoint {
+        Some(endpoint) => Region::Custom {
+            name: connection.region,
+            endpoint,
+        },
+        None => Region::from_str(&connection.region).expect("Invalid AWS region"),
+    }
+}
@@ -0,0 +1,273 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use bytes::Bytes;
+use rusoto_core::{Region, RusotoError};
+use rusoto_kinesis::{
+    Kinesis, KinesisClient, PutRecordsError, PutRecordsInput, PutRecordsRequestEntry,
+};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use crate::connectors::batching::{BatchPolicy, Batcher, FlushCause};
+use crate::connectors::{aws_host, OperatorConfig, OperatorConfigSerializationMode};
+use crate::engine::{Context, StreamNode};
+use crate::formats::csv::CsvOptions;
+use crate::operators::SerializationMode;
+
+use super::{region, KinesisConfig, KinesisTable, TableType};
+
+// PutRecords accepts at most 500 records and 5 MiB per call
+const MAX_BATCH_SIZE: usize = 500;
+const MAX_BATCH_BYTES: usize = 5 * 1024 * 1024;
+const MAX_PARTITION_KEY_LENGTH: usize = 256;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+const THROUGHPUT_EXCEEDED: &str = "ProvisionedThroughputExceededException";
+
+#[derive(StreamNode)]
+pub struct KinesisSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    stream_name: String,
+    region: Region,
+    serialization_mode: SerializationMode,
+    batcher: Batcher,
+    client: Option<KinesisClient>,
+    pending: Vec<PutRecordsRequestEntry>,
+    pending_bytes: usize,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> KinesisSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for KinesisSink");
+        let connection: KinesisConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for KinesisSink");
+        let table: KinesisTable =
+            serde_json::from_value(config.table).expect("Invalid table config for KinesisSink");
+        let TableType::Sink { batch_size } = table.type_ else {
+            panic!("found non-sink Kinesis config in sink operator");
+        };
+
+        let mut batch_policy = BatchPolicy::from_config(
+            config.batching,
+            batch_size.map(|s| s as usize).unwrap_or(MAX_BATCH_SIZE),
+        );
+        batch_policy.max_records = batch_policy.max_records.min(MAX_BATCH_SIZE);
+
+        Self {
+            stream_name: table.stream_name,
+            region: region(connection),
+            serialization_mode: match config.serialization_mode {
+                Some(OperatorConfigSerializationMode::RawBytes) => SerializationMode::RawBytes,
+                Some(OperatorConfigSerializationMode::Csv) => {
+                    SerializationMode::Csv(CsvOptions::from_config(config.csv.as_ref()))
+                }
+                _ => SerializationMode::Json,
+            },
+            batcher: Batcher::new(batch_policy),
+            client: None,
+            pending: vec![],
+            pending_bytes: 0,
+            _t: PhantomData,
+        }
+    }
+
+    /// Writes all pending records with PutRecords, retrying those that fail until they have all
+    /// been accepted. While the stream is throttling writes we keep retrying (which blocks the
+    /// operator and backpressures the pipeline); other failures are retried a limited number of
+    /// times. Retried records may be written after later records with the same partition key.
+    async fn flush(&mut self, cause: FlushCause) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let mut records = std::mem::take(&mut self.pending);
+        self.pending_bytes = 0;
+
+        let client = self.client.as_ref().unwrap();
+        let mut attempt = 0;
+        let mut throttled = 0;
+
+        while !records.is_empty() {
+            let result = client
+                .put_records(PutRecordsInput {
+                    records: records.clone(),
+                    stream_name: self.stream_name.clone(),
+                })
+                .await;
+
+            match result {
+                Ok(output) if output.failed_record_count.unwrap_or(0) == 0 => return,
+                Ok(output) => {
+                    let mut all_throttled = true;
+                    records = records
+                        .into_iter()
+                        .zip(output.records)
+                        .filter(|(_, result)| {
+                            let Some(code) = &result.error_code else {
+                                return false;
+                            };
+                            all_throttled &= code == THROUGHPUT_EXCEEDED;
+                            true
+                        })
+                        .map(|(record, _)| record)
+                        .collect();
+
+                    debug!(
+                        "{} records were rejected by Kinesis stream {}, retrying",
+                        records.len(),
+                        self.stream_name
+                    );
+
+                    if all_throttled {
+                        throttled += 1;
+                        tokio::time::sleep(backoff(throttled)).await;
+                    } else {
+                        attempt += 1;
+                        if attempt >= MAX_ATTEMPTS {
+                            panic!(
+                                "Failed to write {} records to Kinesis after {} attempts",
+                                records.len(),
+                                attempt
+                            );
+                        }
+                        tokio::time::sleep(backoff(attempt)).await;
+                    }
+                }
+                Err(RusotoError::Service(PutRecordsError::ProvisionedThroughputExceeded(_))) => {
+                    throttled += 1;
+                    debug!(
+                        "Writes to Kinesis stream {} are being throttled, retrying",
+                        self.stream_name
+                    );
+                    tokio::time::sleep(backoff(throttled)).await;
+                }
+                Err(e) => {
+                    attempt += 1;
+                    if attempt >= MAX_ATTEMPTS {
+                        panic!(
+                            "PutRecords to Kinesis failed after {} attempts: {}",
+                            attempt, e
+                        );
+                    }
+
+                    warn!("PutRecords to Kinesis failed, retrying: {}", e);
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+            }
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> KinesisSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("kinesis-sink-{}", self.stream_name)
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        if let Err(e) = check_egress(&aws_host("kinesis", &self.region)) {
+            ctx.report_error("Connection not allowed".to_string(), e.clone())
+                .await;
+            panic!("{}", e);
+        }
+
+        info!("Creating Kinesis client for {:?}", self.region);
+        self.client = Some(KinesisClient::new(self.region.clone()));
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        let data = match self.serialization_mode.serialize(&record.value) {
+            Ok(data) => data,
+            Err(e) => {
+                ctx.report_error(e.name, e.details).await;
+                return;
+            }
+        };
+
+        let partition_key = partition_key(record.key.as_ref());
+        let size = data.len() + partition_key.len();
+        if self.pending_bytes + size > MAX_BATCH_BYTES {
+            self.flush(FlushCause::MaxBytes).await;
+        }
+
+        self.pending.push(PutRecordsRequestEntry {
+            data: Bytes::from(data),
+            explicit_hash_key: None,
+            partition_key,
+        });
+        self.pending_bytes += size;
+
+        if let Some(cause) = self.batcher.add(&record.value) {
+            self.flush(cause).await;
+        }
+    }
+
+    async fn handle_tick(&mut self, _: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint).await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close).await;
+    }
+}
+
+/// The partition key for a record, which determines its shard. Records with the same key go to
+/// the same shard; unkeyed records are spread randomly. Keys that Kinesis wouldn't accept (empty
+/// or longer than 256 characters) are replaced by their hash.
+fn partition_key<K: Serialize>(key: Option<&K>) -> String {
+    let key = match key.map(serde_json::to_value) {
+        Some(Ok(Value::String(s))) => s,
+        Some(Ok(v)) if !v.is_null() => v.to_string(),
+        _ => return rand::random::<u64>().to_string(),
+    };
+
+    if key.is_empty() || key.chars().count() > MAX_PARTITION_KEY_LENGTH {
+        hex::encode(Sha256::digest(key.as_bytes()))
+    } else {
+        key
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    (Duration::from_millis(50) * 2u32.pow(attempt.min(10))).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::partition_key;
+
+    #[test]
+    fn test_partition_key() {
+        assert_eq!("user-1", partition_key(Some(&"user-1".to_string())));
+        assert_eq!("5", partition_key(Some(&5u64)));
+
+        let long = "a".repeat(300);
+        assert_eq!(64, partition_key(Some(&long)).len());
+        assert_eq!(partition_key(Some(&long)), partition_key(Some(&long)));
+
+        assert!(!partition_key::<()>(None).is_empty());
+        assert!(!partition_key(Some(&())).is_empty());
+    }
+}
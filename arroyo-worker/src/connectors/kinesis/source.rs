@@ -0,0 +1,499 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+use arroyo_macro::{source_fn, StreamNode};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::ControlMessage;
+use arroyo_state::tables::GlobalKeyedState;
+use arroyo_types::{check_egress, from_millis, Data, Record};
+use bincode::{Decode, Encode};
+use rusoto_core::{Region, RusotoError};
+use rusoto_kinesis::{
+    GetRecordsError, GetRecordsInput, GetShardIteratorInput, Kinesis, KinesisClient,
+    ListShardsInput,
+};
+use serde::de::DeserializeOwned;
+use tokio::select;
+use tokio::time::{interval, interval_at, Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::engine::Context;
+use crate::formats::csv::CsvOptions;
+use crate::operators::{BadData, SerializationMode, UserError};
+use crate::SourceFinishType;
+
+use crate::connectors::{aws_host, OperatorConfig, OperatorConfigSerializationMode};
+
+use super::{region, KinesisConfig, KinesisTable, SourceOffset, TableType};
+
+// Kinesis allows 5 GetRecords calls per second per shard
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SHARD_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_RECORDS_PER_CALL: i64 = 10_000;
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct KinesisState {
+    shard_id: String,
+    // the sequence number of the last record read from the shard
+    sequence_number: Option<String>,
+    // the shard was closed by resharding and has been read to its end
+    finished: bool,
+}
+
+#[derive(Clone, Debug)]
+struct ShardState {
+    parents: Vec<String>,
+    // where to start reading if no records have been read from the shard yet
+    initial_position: &'static str,
+    sequence_number: Option<String>,
+    iterator: Option<String>,
+    finished: bool,
+}
+
+/// Shards that can be read now. After resharding, a shard's records continue in its children,
+/// so to preserve per-key order a child isn't read until its parents have been read to their
+/// ends. Parents that belong to other subtasks (or have expired) can't be waited for.
+fn readable_shards(shards: &HashMap<String, ShardState>) -> Vec<String> {
+    let mut readable: Vec<_> = shards
+        .iter()
+        .filter(|(_, shard)| !shard.finished)
+        .filter(|(_, shard)| {
+            shard
+                .parents
+                .iter()
+                .all(|p| shards.get(p).map(|p| p.finished).unwrap_or(true))
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    readable.sort();
+    readable
+}
+
+#[derive(StreamNode, Clone)]
+pub struct KinesisSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    stream_name: String,
+    region: Region,
+    offset: SourceOffset,
+    serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
+    client: Option<KinesisClient>,
+    connected: bool,
+    shards: HashMap<String, ShardState>,
+    // shard positions restored from the last checkpoint, used as shards are discovered
+    restored: HashMap<String, KinesisState>,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> KinesisSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for KinesisSource");
+        let connection: KinesisConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for KinesisSource");
+        let table: KinesisTable =
+            serde_json::from_value(config.table).expect("Invalid table config for KinesisSource");
+
+        let TableType::Source { offset } = table.type_ else {
+            panic!("found non-source Kinesis config in source operator");
+        };
+
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => {
+                Ok(SerializationMode::JsonSchemaRegistry)
+            }
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "Kinesis"))
+            }
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "Kinesis"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "Kinesis"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
+        Self {
+            stream_name: table.stream_name,
+            region: region(connection),
+            offset,
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
+            client: None,
+            connected: false,
+            shards: HashMap::new(),
+            restored: HashMap::new(),
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("kinesis-{}", self.stream_name)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("k", "kinesis source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let mut s: GlobalKeyedState<String, KinesisState, _> =
+            ctx.state.get_global_keyed_state('k').await;
+
+        self.restored = s
+            .get_all()
+            .into_iter()
+            .map(|state| (state.shard_id.clone(), state.clone()))
+            .collect();
+    }
+
+    /// Shards are distributed across the source's subtasks by a hash of their id
+    fn owns(&self, ctx: &Context<(), T>, shard_id: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        shard_id.hash(&mut hasher);
+        hasher.finish() % ctx.task_info.parallelism as u64 == ctx.task_info.task_index as u64
+    }
+
+    /// Starts tracking any new shards of the stream that belong to this subtask; `initial` is set
+    /// for the discovery when the source starts
+    async fn discover_shards(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        initial: bool,
+    ) -> anyhow::Result<()> {
+        let client = self.client.as_ref().unwrap();
+
+        let mut shards = vec![];
+        let mut next_token = None;
+        loop {
+            // the stream name can't be passed along with a continuation token
+            let output = client
+                .list_shards(ListShardsInput {
+                    stream_name: next_token.is_none().then(|| self.stream_name.clone()),
+                    next_token: next_token.take(),
+                    ..Default::default()
+                })
+                .await?;
+
+            shards.extend(output.shards.unwrap_or_default());
+            next_token = output.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        for shard in shards {
+            if self.shards.contains_key(&shard.shard_id) || !self.owns(ctx, &shard.shard_id) {
+                continue;
+            }
+
+            let restored = self.restored.get(&shard.shard_id);
+            // shards that weren't known at the last checkpoint, or that were created while we're
+            // running, are new since we started reading and must be read in full
+            let initial_position = match self.offset {
+                SourceOffset::Latest if initial && self.restored.is_empty() => "LATEST",
+                _ => "TRIM_HORIZON",
+            };
+
+            info!(
+                "Discovered shard {} of stream {}",
+                shard.shard_id, self.stream_name
+            );
+            self.shards.insert(
+                shard.shard_id.clone(),
+                ShardState {
+                    parents: shard
+                        .parent_shard_id
+                        .into_iter()
+                        .chain(shard.adjacent_parent_shard_id)
+                        .collect(),
+                    initial_position,
+                    sequence_number: restored.and_then(|r| r.sequence_number.clone()),
+                    iterator: None,
+                    finished: restored.map(|r| r.finished).unwrap_or(false),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn shard_iterator(&self, shard_id: &str) -> anyhow::Result<Option<String>> {
+        let shard = &self.shards[shard_id];
+        let (iterator_type, starting_sequence_number) = match &shard.sequence_number {
+            Some(sequence_number) => ("AFTER_SEQUENCE_NUMBER", Some(sequence_number.clone())),
+            None => (shard.initial_position, None),
+        };
+
+        Ok(self
+            .client
+            .as_ref()
+            .unwrap()
+            .get_shard_iterator(GetShardIteratorInput {
+                stream_name: self.stream_name.clone(),
+                shard_id: shard_id.to_string(),
+                shard_iterator_type: iterator_type.to_string(),
+                starting_sequence_number,
+                ..Default::default()
+            })
+            .await?
+            .shard_iterator)
+    }
+
+    /// Reads the next batch of records from each readable shard
+    async fn poll_shards(&mut self, ctx: &mut Context<(), T>) -> Result<(), UserError> {
+        for shard_id in readable_shards(&self.shards) {
+            let iterator = match self.shards[&shard_id].iterator.clone() {
+                Some(iterator) => iterator,
+                None => match self.shard_iterator(&shard_id).await {
+                    Ok(Some(iterator)) => iterator,
+                    Ok(None) => {
+                        self.shard_finished(&shard_id);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Failed to get iterator for shard {}: {:?}", shard_id, e);
+                        self.disconnected(ctx, e.to_string()).await;
+                        continue;
+                    }
+                },
+            };
+
+            let output = match self
+                .client
+                .as_ref()
+                .unwrap()
+                .get_records(GetRecordsInput {
+                    shard_iterator: iterator,
+                    limit: Some(MAX_RECORDS_PER_CALL),
+                })
+                .await
+            {
+                Ok(output) => output,
+                Err(RusotoError::Service(GetRecordsError::ExpiredIterator(_))) => {
+                    debug!("Iterator for shard {} expired", shard_id);
+                    self.shards.get_mut(&shard_id).unwrap().iterator = None;
+                    continue;
+                }
+                Err(RusotoError::Service(GetRecordsError::ProvisionedThroughputExceeded(_))) => {
+                    debug!("Throughput exceeded reading shard {}", shard_id);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to read shard {}: {:?}", shard_id, e);
+                    self.disconnected(ctx, e.to_string()).await;
+                    continue;
+                }
+            };
+
+            if !self.connected {
+                ctx.report_connected().await;
+                self.connected = true;
+            }
+
+            for record in output.records {
+                let timestamp = record
+                    .approximate_arrival_timestamp
+                    .map(|t| from_millis((t * 1000.0) as u64))
+                    .unwrap_or_else(SystemTime::now);
+                ctx.report_source_lag(timestamp);
+                ctx.profile_source_record(&record.data);
+                ctx.collector
+                    .collect(Record {
+                        timestamp,
+                        key: None,
+                        value: self
+                            .serialization_mode
+                            .deserialize_slice(&record.data, self.bad_data)?,
+                    })
+                    .await;
+                self.shards.get_mut(&shard_id).unwrap().sequence_number =
+                    Some(record.sequence_number);
+            }
+
+            match output.next_shard_iterator {
+                Some(next) => {
+                    self.shards.get_mut(&shard_id).unwrap().iterator = Some(next);
+                }
+                None => self.shard_finished(&shard_id),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn disconnected(&mut self, ctx: &mut Context<(), T>, error: String) {
+        if self.connected {
+            ctx.report_disconnected(error).await;
+            self.connected = false;
+        }
+    }
+
+    fn shard_finished(&mut self, shard_id: &str) {
+        info!(
+            "Finished reading shard {}, which was closed by resharding",
+            shard_id
+        );
+        let shard = self.shards.get_mut(shard_id).unwrap();
+        shard.finished = true;
+        shard.iterator = None;
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        check_egress(&aws_host("kinesis", &self.region))
+            .map_err(|e| UserError::new("Connection not allowed", e))?;
+        self.client = Some(KinesisClient::new(self.region.clone()));
+        if let Err(e) = self.discover_shards(ctx, true).await {
+            ctx.report_disconnected(e.to_string()).await;
+            return Err(UserError::new(
+                "Could not list Kinesis shards",
+                format!("{:?}", e),
+            ));
+        }
+        ctx.report_connected().await;
+        self.connected = true;
+
+        let mut poll = interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut discovery = interval_at(
+            Instant::now() + SHARD_DISCOVERY_INTERVAL,
+            SHARD_DISCOVERY_INTERVAL,
+        );
+
+        // set while watermark alignment is holding this source back
+        let mut paused = false;
+        loop {
+            select! {
+                _ = poll.tick(), if !paused => {
+                    self.poll_shards(ctx).await?;
+                }
+                _ = discovery.tick() => {
+                    if let Err(e) = self.discover_shards(ctx, false).await {
+                        warn!("Failed to discover shards of stream {}: {:?}", self.stream_name, e);
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    match control_message {
+                        Some(ControlMessage::Checkpoint(c)) => {
+                            debug!("starting checkpointing {}", ctx.task_info.task_index);
+                            let mut s = ctx.state.get_global_keyed_state('k').await;
+                            for (shard_id, shard) in &self.shards {
+                                s.insert(shard_id.clone(), KinesisState {
+                                    shard_id: shard_id.clone(),
+                                    sequence_number: shard.sequence_number.clone(),
+                                    finished: shard.finished,
+                                }).await;
+                            }
+
+                            if self.checkpoint(c, ctx).await {
+                                return Ok(SourceFinishType::Immediate);
+                            }
+                        },
+                        Some(ControlMessage::Stop { mode }) => {
+                            info!("Stopping Kinesis source: {:?}", mode);
+
+                            match mode {
+                                StopMode::Graceful => {
+                                    return Ok(SourceFinishType::Graceful);
+                                }
+                                StopMode::Immediate => {
+                                    return Ok(SourceFinishType::Immediate);
+                                }
+                                StopMode::Drain => {
+                                    return Ok(SourceFinishType::Final);
+                                }
+                            }
+                        }
+                        Some(ControlMessage::Commit{..}) => {
+                            return Err(UserError::new("Kinesis source does not support committing", ""));
+                        }
+                        Some(ControlMessage::SetPaused { paused: p }) => {
+                            debug!("kinesis source {} paused: {}", ctx.task_info.task_index, p);
+                            paused = p;
+                        }
+                        Some(ControlMessage::InjectProbe { probe_id }) => {
+                            ctx.handle_probe(probe_id).await;
+                        }
+                        None => {
+
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{readable_shards, ShardState};
+
+    fn shard(parents: &[&str], finished: bool) -> ShardState {
+        ShardState {
+            parents: parents.iter().map(|p| p.to_string()).collect(),
+            initial_position: "TRIM_HORIZON",
+            sequence_number: None,
+            iterator: None,
+            finished,
+        }
+    }
+
+    #[test]
+    fn test_children_wait_for_parents() {
+        let mut shards = HashMap::new();
+        shards.insert("a".to_string(), shard(&[], false));
+        shards.insert("b".to_string(), shard(&[], false));
+        // merged from a and b
+        shards.insert("c".to_string(), shard(&["a", "b"], false));
+        // split from a shard owned by another subtask
+        shards.insert("d".to_string(), shard(&["other"], false));
+
+        assert_eq!(readable_shards(&shards), vec!["a", "b", "d"]);
+
+        shards.get_mut("a").unwrap().finished = true;
+        assert_eq!(readable_shards(&shards), vec!["b", "d"]);
+
+        shards.get_mut("b").unwrap().finished = true;
+        assert_eq!(readable_shards(&shards), vec!["c", "d"]);
+    }
+}
@@ -1,14 +1,116 @@
 use serde::{Deserialize, Serialize};
 use typify::import_types;
 
+pub mod batching;
 pub mod blackhole;
+pub mod cassandra;
+pub mod connection_pool;
+pub mod dynamodb;
+pub mod elasticsearch;
 pub mod filesystem;
 pub mod fluvio;
+pub mod grpc_sink;
+pub mod iceberg;
 pub mod impulse;
+pub mod jdbc;
 pub mod kafka;
+pub mod kinesis;
+pub mod mongodb;
+pub mod nats;
 pub mod nexmark;
+pub mod object_store;
+pub mod postgres_cdc;
+pub mod sftp;
+pub mod smtp;
 pub mod sse;
 pub mod two_phase_committer;
+pub mod webhook;
 pub mod websocket;
 
 import_types!(schema = "../connector-schemas/common.json",);
+
+/// The host that an AWS client for `service` connects to in `region`, for checking egress
+pub fn aws_host(service: &str, region: &rusoto_core::Region) -> String {
+    match region {
+        rusoto_core::Region::Custom { endpoint, .. } => url::Url::parse(endpoint)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            // endpoints may be given without a scheme, like localhost:4566
+            .unwrap_or_else(|| {
+                endpoint
+                    .rsplit_once(':')
+                    .map(|(h, _)| h)
+                    .unwrap_or(endpoint)
+                    .to_string()
+            }),
+        region => format!("{}.{}.amazonaws.com", service, region.name()),
+    }
+}
+
+/// Checks that this worker may connect to the hosts of a Postgres connection string, which may be
+/// either a URL or a list of `key=value` pairs
+pub fn check_postgres_egress(connection_string: &str) -> Result<(), String> {
+    let config: tokio_postgres::Config = connection_string.parse().map_err(|e| format!("{}", e))?;
+    for host in config.get_hosts() {
+        if let tokio_postgres::config::Host::Tcp(host) = host {
+            arroyo_types::check_egress(host)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    // connectors that don't connect out of the worker
+    const NO_EGRESS: [&str; 8] = [
+        "batching.rs",
+        "blackhole.rs",
+        "connection_pool.rs",
+        "grpc_sink.rs",
+        "impulse.rs",
+        "mod.rs",
+        "nexmark",
+        "two_phase_committer.rs",
+    ];
+
+    // the ways a connector checks that it's allowed to connect to a host
+    const EGRESS_CHECKS: [&str; 4] = [
+        "check_egress(",
+        "aws_host(",
+        "check_postgres_egress(",
+        "proxy::tcp_connect(",
+    ];
+
+    fn checks_egress(path: &Path) -> bool {
+        if path.is_dir() {
+            return std::fs::read_dir(path)
+                .unwrap()
+                .any(|entry| checks_egress(&entry.unwrap().path()));
+        }
+
+        let source = std::fs::read_to_string(path).unwrap();
+        EGRESS_CHECKS.iter().any(|check| source.contains(check))
+    }
+
+    #[test]
+    fn test_connectors_check_egress() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/connectors");
+        let mut missing: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| !checks_egress(path))
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .filter(|name| !NO_EGRESS.contains(&name.as_str()))
+            .collect();
+        missing.sort();
+
+        assert!(
+            missing.is_empty(),
+            "connectors that connect without checking egress: {:?}",
+            missing
+        );
+    }
+}
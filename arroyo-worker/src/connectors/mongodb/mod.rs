@@ -0,0 +1,171 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use arroyo_rpc::tls;
+use arroyo_types::check_egress;
+use mongodb::bson::{oid::ObjectId, Bson, DateTime, Document};
+use mongodb::options::{ClientOptions, ServerAddress, Tls, TlsOptions};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/mongodb/connection.json");
+import_types!(schema = "../connector-schemas/mongodb/table.json");
+
+pub async fn connect(config: &MongoDbConfig) -> anyhow::Result<Client> {
+    let mut options = ClientOptions::parse(&*config.connection_string).await?;
+    for address in &options.hosts {
+        if let ServerAddress::Tcp { host, .. } = address {
+            check_egress(host).map_err(|e| anyhow!(e))?;
+        }
+    }
+
+    if let Some(t) = &config.tls {
+        let t = tls::TlsConfig::from_generated(t);
+        let (ca, client) = t.write_pem_files(&std::env::temp_dir().join("arroyo-mongodb-tls"))?;
+        options.tls = Some(Tls::Enabled(
+            TlsOptions::builder()
+                .ca_file_path(ca)
+                .cert_key_file_path(client)
+                .allow_invalid_certificates(t.insecure_skip_verify)
+                .build(),
+        ));
+    }
+
+    Ok(Client::with_options(options)?)
+}
+
+/// Converts a BSON value into the JSON representation expected by our deserializers. ObjectIds
+/// become their hex strings and dates become RFC3339 timestamps.
+pub fn bson_to_json(value: Bson) -> Value {
+    match value {
+        Bson::Null | Bson::Undefined => Value::Null,
+        Bson::Boolean(b) => Value::Bool(b),
+        Bson::Int32(i) => Value::Number(i.into()),
+        Bson::Int64(i) => Value::Number(i.into()),
+        Bson::Double(f) => Number::from_f64(f)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        Bson::String(s) | Bson::Symbol(s) => Value::String(s),
+        Bson::ObjectId(oid) => Value::String(oid.to_hex()),
+        Bson::DateTime(dt) => match dt.try_to_rfc3339_string() {
+            Ok(s) => Value::String(s),
+            Err(_) => Value::Number(dt.timestamp_millis().into()),
+        },
+        Bson::Decimal128(d) => Value::String(d.to_string()),
+        Bson::Array(values) => Value::Array(values.into_iter().map(bson_to_json).collect()),
+        Bson::Document(doc) => document_to_json(doc),
+        other => other.into_relaxed_extjson(),
+    }
+}
+
+pub fn document_to_json(doc: Document) -> Value {
+    Value::Object(
+        doc.into_iter()
+            .map(|(k, v)| (k, bson_to_json(v)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+/// Converts a serialized record into BSON. Serialized `SystemTime`s become BSON dates, and
+/// string `_id`s that are valid ObjectIds are converted back to ObjectIds so that documents
+/// read by the change stream source can be written back unchanged.
+pub fn json_to_document(fields: Map<String, Value>) -> Document {
+    fields
+        .into_iter()
+        .map(|(k, v)| {
+            let v = match (k.as_str(), v) {
+                ("_id", Value::String(s)) => match ObjectId::parse_str(&s) {
+                    Ok(oid) => Bson::ObjectId(oid),
+                    Err(_) => Bson::String(s),
+                },
+                (_, v) => json_to_bson(v),
+            };
+            (k, v)
+        })
+        .collect()
+}
+
+fn json_to_bson(value: Value) -> Bson {
+    match value {
+        Value::Null => Bson::Null,
+        Value::Bool(b) => Bson::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Bson::Int64(i),
+            None => Bson::Double(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => Bson::String(s),
+        Value::Array(values) => Bson::Array(values.into_iter().map(json_to_bson).collect()),
+        Value::Object(fields) => {
+            match (
+                fields.get("secs_since_epoch"),
+                fields.get("nanos_since_epoch"),
+            ) {
+                (Some(Value::Number(secs)), Some(Value::Number(nanos))) if fields.len() == 2 => {
+                    let time = UNIX_EPOCH
+                        + Duration::new(
+                            secs.as_u64().unwrap_or_default(),
+                            nanos.as_u64().unwrap_or_default() as u32,
+                        );
+                    Bson::DateTime(DateTime::from_system_time(time))
+                }
+                _ => Bson::Document(
+                    fields
+                        .into_iter()
+                        .map(|(k, v)| (k, json_to_bson(v)))
+                        .collect(),
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use mongodb::bson::{doc, oid::ObjectId, Bson, DateTime};
+    use serde_json::json;
+
+    use super::{document_to_json, json_to_document};
+
+    #[test]
+    fn test_round_trip() {
+        let oid = ObjectId::new();
+        let time = DateTime::from_system_time(UNIX_EPOCH + Duration::from_secs(2));
+
+        let json = document_to_json(doc! {
+            "_id": oid,
+            "count": 5i32,
+            "created": time,
+            "tags": ["a", "b"],
+        });
+
+        assert_eq!(
+            json!({
+                "_id": oid.to_hex(),
+                "count": 5,
+                "created": "1970-01-01T00:00:02Z",
+                "tags": ["a", "b"],
+            }),
+            json
+        );
+
+        let doc = json_to_document(
+            json!({
+                "_id": oid.to_hex(),
+                "created": {"secs_since_epoch": 2, "nanos_since_epoch": 0},
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        );
+
+        assert_eq!(Some(&Bson::ObjectId(oid)), doc.get("_id"));
+        assert_eq!(Some(&Bson::DateTime(time)), doc.get("created"));
+    }
+}
@@ -0,0 +1,304 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_types::{CheckpointBarrier, Data, Key, Record};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{InsertManyOptions, ReplaceOptions};
+use mongodb::Collection;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::connectors::batching::{BatchPolicy, Batcher, FlushCause};
+use crate::connectors::connection_pool::ConnectionPermit;
+use crate::connectors::{ConnectionPoolConfig, OperatorConfig, OperatorConfigSerializationMode};
+use crate::engine::{Context, StreamNode};
+
+use super::{connect, json_to_document, MongoDbConfig, MongoDbTable, TableType, WriteMode};
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+const DUPLICATE_KEY: i32 = 11000;
+
+enum Write {
+    Insert(Document),
+    Replace {
+        id: Bson,
+        doc: Document,
+        upsert: bool,
+    },
+    Delete(Bson),
+}
+
+#[derive(StreamNode)]
+pub struct MongoDbSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    connection: MongoDbConfig,
+    connection_pool: Option<ConnectionPoolConfig>,
+    permit: Option<ConnectionPermit>,
+    database: String,
+    collection_name: String,
+    write_mode: WriteMode,
+    updating: bool,
+    batcher: Batcher,
+    collection: Option<Collection<Document>>,
+    pending: Vec<Write>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> MongoDbSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for MongoDbSink");
+        let connection: MongoDbConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for MongoDbSink");
+        let table: MongoDbTable =
+            serde_json::from_value(config.table).expect("Invalid table config for MongoDbSink");
+        let TableType::Sink {
+            write_mode,
+            batch_size,
+        } = table.type_
+        else {
+            panic!("found non-sink MongoDB config in sink operator");
+        };
+
+        Self {
+            connection,
+            connection_pool: config.connection_pool,
+            permit: None,
+            database: table.database,
+            collection_name: table.collection,
+            write_mode,
+            updating: matches!(
+                config.serialization_mode,
+                Some(OperatorConfigSerializationMode::DebeziumJson)
+            ),
+            batcher: Batcher::new(BatchPolicy::from_config(
+                config.batching,
+                batch_size
+                    .map(|s| s.max(1) as usize)
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+            )),
+            collection: None,
+            pending: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    /// Builds the write for the record. For updating streams, creates and updates are upserted
+    /// and deletes remove the document with the retracted row's _id, regardless of write mode.
+    fn to_write(&self, record: &Record<K, T>) -> Result<Write, String> {
+        let mut value = serde_json::to_value(&record.value).map_err(|e| e.to_string())?;
+
+        let (fields, delete) = if self.updating {
+            let delete = value.get("op").and_then(|op| op.as_str()) == Some("d");
+            let field = if delete { "before" } else { "after" };
+            (
+                value.get_mut(field).map(Value::take).unwrap_or(Value::Null),
+                delete,
+            )
+        } else {
+            (value, false)
+        };
+
+        let mut fields = match fields {
+            Value::Object(fields) => fields,
+            other => {
+                return Err(format!(
+                    "expected a struct to write to MongoDB, found {}",
+                    other
+                ))
+            }
+        };
+
+        if let Some(key) = &record.key {
+            if let Ok(Value::Object(key_fields)) = serde_json::to_value(key) {
+                fields.extend(key_fields);
+            }
+        }
+
+        let doc = json_to_document(fields);
+
+        if !self.updating && self.write_mode == WriteMode::Insert {
+            return Ok(Write::Insert(doc));
+        }
+
+        let id = match doc.get("_id") {
+            None | Some(Bson::Null) => {
+                return Err("record is missing an _id field".to_string());
+            }
+            Some(id) => id.clone(),
+        };
+
+        Ok(if delete {
+            Write::Delete(id)
+        } else {
+            Write::Replace {
+                id,
+                doc,
+                upsert: self.updating || self.write_mode == WriteMode::Upsert,
+            }
+        })
+    }
+
+    async fn flush(&mut self, cause: FlushCause) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let collection = self.collection.as_ref().unwrap();
+        let mut inserts = vec![];
+
+        for write in std::mem::take(&mut self.pending) {
+            match write {
+                Write::Insert(doc) => inserts.push(doc),
+                Write::Replace { id, doc, upsert } => {
+                    let options = ReplaceOptions::builder().upsert(upsert).build();
+                    retry("replace", || {
+                        collection.replace_one(
+                            doc! { "_id": id.clone() },
+                            doc.clone(),
+                            options.clone(),
+                        )
+                    })
+                    .await;
+                }
+                Write::Delete(id) => {
+                    retry("delete", || {
+                        collection.delete_one(doc! { "_id": id.clone() }, None)
+                    })
+                    .await;
+                }
+            }
+        }
+
+        if !inserts.is_empty() {
+            let options = InsertManyOptions::builder().ordered(false).build();
+            let mut attempt = 0;
+            loop {
+                match collection
+                    .insert_many(inserts.iter(), options.clone())
+                    .await
+                {
+                    Ok(_) => break,
+                    // on replay after a restart we may re-insert documents that were already
+                    // written; these fail with duplicate key errors, which are safe to ignore
+                    Err(e) if is_only_duplicate_keys(&e) => break,
+                    Err(e) => {
+                        attempt += 1;
+                        if attempt >= MAX_ATTEMPTS {
+                            panic!("insert to MongoDB failed after {} attempts: {}", attempt, e);
+                        }
+                        warn!("insert to MongoDB failed, retrying: {}", e);
+                        tokio::time::sleep(backoff(attempt)).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> MongoDbSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("mongodb-sink-{}.{}", self.database, self.collection_name)
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        info!(
+            "Connecting to MongoDB collection {}.{}",
+            self.database, self.collection_name
+        );
+
+        self.permit = ConnectionPermit::acquire(&self.connection_pool, 1, &ctx.task_info).await;
+        let client = match connect(&self.connection).await {
+            Ok(client) => client,
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                panic!("Failed to create MongoDB client: {}", e);
+            }
+        };
+        ctx.report_connected().await;
+        self.collection = Some(
+            client
+                .database(&self.database)
+                .collection(&self.collection_name),
+        );
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match self.to_write(record) {
+            Ok(write) => {
+                self.pending.push(write);
+                if let Some(cause) = self.batcher.add(&record.value) {
+                    self.flush(cause).await;
+                }
+            }
+            Err(e) => {
+                ctx.report_error("Could not write record to MongoDB".to_string(), e)
+                    .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, _: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint).await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close).await;
+    }
+}
+
+fn is_only_duplicate_keys(e: &mongodb::error::Error) -> bool {
+    match &*e.kind {
+        ErrorKind::BulkWrite(failure) => {
+            failure.write_concern_error.is_none()
+                && failure
+                    .write_errors
+                    .as_ref()
+                    .map(|errors| errors.iter().all(|e| e.code == DUPLICATE_KEY))
+                    .unwrap_or(false)
+        }
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    (Duration::from_millis(50) * 2u32.pow(attempt.min(10))).min(MAX_BACKOFF)
+}
+
+async fn retry<F, Fut, R>(op: &str, f: F)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = mongodb::error::Result<R>>,
+{
+    let mut attempt = 0;
+    while let Err(e) = f().await {
+        attempt += 1;
+        if attempt >= MAX_ATTEMPTS {
+            panic!("{} to MongoDB failed after {} attempts: {}", op, attempt, e);
+        }
+
+        warn!("{} to MongoDB failed, retrying: {}", op, e);
+        tokio::time::sleep(backoff(attempt)).await;
+    }
+}
@@ -0,0 +1,313 @@
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use arroyo_macro::{source_fn, StreamNode};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_state::tables::GlobalKeyedState;
+use arroyo_types::{Data, Record};
+use bincode::{Decode, Encode};
+use futures::StreamExt;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken};
+use mongodb::options::{ChangeStreamOptions, FullDocumentBeforeChangeType, FullDocumentType};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use crate::connectors::connection_pool::ConnectionPermit;
+use crate::connectors::{ConnectionPoolConfig, OperatorConfig, OperatorConfigSerializationMode};
+use crate::engine::Context;
+use crate::operators::UserError;
+use crate::SourceFinishType;
+
+use super::{connect, document_to_json, MongoDbConfig, MongoDbTable};
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
+pub struct MongoDbSourceState {
+    // the BSON-encoded resume token of the last change we emitted
+    resume_token: Option<Vec<u8>>,
+}
+
+#[derive(StreamNode, Clone)]
+pub struct MongoDbSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    connection: MongoDbConfig,
+    connection_pool: Option<ConnectionPoolConfig>,
+    database: String,
+    collection: String,
+    // emit Debezium-style changes rather than just the current documents
+    updating: bool,
+    format_error: Option<UserError>,
+    state: MongoDbSourceState,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> MongoDbSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for MongoDbSource");
+        let connection: MongoDbConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for MongoDbSource");
+        let table: MongoDbTable =
+            serde_json::from_value(config.table).expect("Invalid table config for MongoDbSource");
+
+        let updating = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(false),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(true),
+            other => Err(UserError::unsupported_format(
+                &format!("{:?}", other),
+                "MongoDB",
+            )),
+        };
+
+        Self {
+            connection,
+            connection_pool: config.connection_pool,
+            database: table.database,
+            collection: table.collection,
+            updating: *updating.as_ref().unwrap_or(&false),
+            format_error: updating.err(),
+            state: MongoDbSourceState::default(),
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("mongodb-{}.{}", self.database, self.collection)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("m", "mongodb source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let s: GlobalKeyedState<(), MongoDbSourceState, _> =
+            ctx.state.get_global_keyed_state('m').await;
+
+        if let Some(state) = s.get(&()) {
+            self.state = state.clone();
+        }
+    }
+
+    async fn our_handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                let mut s: GlobalKeyedState<(), MongoDbSourceState, _> =
+                    ctx.state.get_global_keyed_state('m').await;
+                s.insert((), self.state.clone()).await;
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping MongoDB source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
+                }
+            }
+            ControlMessage::Commit { epoch: _ } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::SetPaused { .. } => {
+                warn!("watermark alignment is not supported by the MongoDB source");
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
+        }
+        None
+    }
+
+    /// Converts a change event into the JSON for a record, returning None for events that
+    /// don't produce data (like drops or, in append mode, deletes)
+    fn event_to_json(
+        &self,
+        event: ChangeStreamEvent<Document>,
+    ) -> Result<Option<Value>, UserError> {
+        let missing = |field: &str| {
+            UserError::new(
+                "Missing document in change event",
+                format!(
+                    "{:?} event on {}.{} did not include the {}; pre- and post-images must be \
+                    enabled on the collection to read updates and deletes",
+                    event.operation_type, self.database, self.collection, field
+                ),
+            )
+        };
+
+        let after = event.full_document.clone().map(document_to_json);
+        let before = event
+            .full_document_before_change
+            .clone()
+            .map(document_to_json);
+
+        Ok(match (&event.operation_type, self.updating) {
+            (OperationType::Insert, false)
+            | (OperationType::Update, false)
+            | (OperationType::Replace, false) => {
+                Some(after.ok_or_else(|| missing("full document"))?)
+            }
+            (OperationType::Insert, true) => Some(json!({
+                "before": null,
+                "after": after.ok_or_else(|| missing("full document"))?,
+                "op": "c",
+            })),
+            (OperationType::Update, true) | (OperationType::Replace, true) => Some(json!({
+                "before": before.ok_or_else(|| missing("document pre-image"))?,
+                "after": after.ok_or_else(|| missing("full document"))?,
+                "op": "u",
+            })),
+            (OperationType::Delete, true) => Some(json!({
+                "before": before.ok_or_else(|| missing("document pre-image"))?,
+                "after": null,
+                "op": "d",
+            })),
+            _ => None,
+        })
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        // change streams can't be partitioned, so only read on the first task
+        if ctx.task_info.task_index != 0 {
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.our_handle_control_message(ctx, msg).await {
+                    return r;
+                }
+            }
+        }
+
+        let _permit = ConnectionPermit::acquire(&self.connection_pool, 1, &ctx.task_info).await;
+        let client = connect(&self.connection)
+            .await
+            .expect("Failed to create MongoDB client");
+        let collection = client
+            .database(&self.database)
+            .collection::<Document>(&self.collection);
+
+        let resume_after = self.state.resume_token.as_ref().map(|bytes| {
+            let doc = Document::from_reader(&mut bytes.as_slice())
+                .expect("Invalid resume token in state");
+            bson::from_bson::<ResumeToken>(doc.get("t").cloned().unwrap_or(Bson::Null))
+                .expect("Invalid resume token in state")
+        });
+
+        if resume_after.is_some() {
+            info!(
+                "Resuming change stream for {}.{}",
+                self.database, self.collection
+            );
+        }
+
+        let options = ChangeStreamOptions::builder()
+            .full_document(Some(FullDocumentType::UpdateLookup))
+            .full_document_before_change(
+                self.updating
+                    .then_some(FullDocumentBeforeChangeType::WhenAvailable),
+            )
+            .resume_after(resume_after)
+            .build();
+
+        let mut stream = match collection.watch(None, options).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                panic!("Failed to open change stream: {}", e);
+            }
+        };
+        ctx.report_connected().await;
+
+        loop {
+            select! {
+                event = stream.next() => {
+                    match event {
+                        Some(Ok(event)) => {
+                            let timestamp = event
+                                .cluster_time
+                                .map(|t| UNIX_EPOCH + Duration::from_secs(t.time as u64))
+                                .unwrap_or_else(SystemTime::now);
+
+                            match self.event_to_json(event).and_then(|json| {
+                                json.map(|json| serde_json::from_value::<T>(json).map_err(|e| {
+                                    UserError::new("Deserialization error", format!(
+                                        "Failed to deserialize change event: {}", e))
+                                })).transpose()
+                            }) {
+                                Ok(Some(value)) => {
+                                    ctx.collector.collect(Record {
+                                        timestamp,
+                                        key: None,
+                                        value,
+                                    }).await;
+                                }
+                                Ok(None) => {}
+                                Err(e) => {
+                                    ctx.report_error(e.name, e.details).await;
+                                }
+                            }
+
+                            if let Some(token) = stream.resume_token() {
+                                let mut bytes = vec![];
+                                doc! { "t": bson::to_bson(&token).unwrap() }
+                                    .to_writer(&mut bytes)
+                                    .unwrap();
+                                self.state.resume_token = Some(bytes);
+                            }
+                        }
+                        Some(Err(e)) => {
+                            ctx.report_disconnected(e.to_string()).await;
+                            ctx.control_tx.send(
+                                ControlResp::Error {
+                                    operator_id: ctx.task_info.operator_id.clone(),
+                                    task_index: ctx.task_info.task_index,
+                                    message: "Error while reading from MongoDB change stream".to_string(),
+                                    details: e.to_string(),
+                                }
+                            ).await.unwrap();
+                            panic!("Error while reading from MongoDB change stream: {}", e);
+                        }
+                        None => {
+                            warn!("Change stream for {}.{} was closed", self.database, self.collection);
+                            return SourceFinishType::Final;
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                        return r;
+                    }
+                }
+            }
+        }
+    }
+}
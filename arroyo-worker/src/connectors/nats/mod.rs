@@ -0,0 +1,55 @@
+use async_nats::{Client, ConnectOptions};
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/nats/connection.json");
+import_types!(schema = "../connector-schemas/nats/table.json");
+
+impl NatsConfig {
+    /// The hosts of the configured servers, for checking against the allowed egress
+    pub fn hosts(&self) -> Vec<&str> {
+        self.servers
+            .split(',')
+            .map(|server| {
+                let server = server.trim();
+                let server = server.split_once("://").map(|(_, s)| s).unwrap_or(server);
+                server.rsplit_once(':').map(|(h, _)| h).unwrap_or(server)
+            })
+            .collect()
+    }
+}
+
+pub async fn connect(config: &NatsConfig) -> Result<Client, async_nats::ConnectError> {
+    let options = match &config.authentication {
+        NatsConfigAuthentication::None {} => ConnectOptions::new(),
+        NatsConfigAuthentication::Credentials { username, password } => {
+            ConnectOptions::with_user_and_password(username.clone(), password.clone())
+        }
+        NatsConfigAuthentication::Token { token } => ConnectOptions::with_token(token.clone()),
+    };
+
+    options.connect(config.servers.as_str()).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NatsConfig, NatsConfigAuthentication, Servers};
+
+    #[test]
+    fn test_hosts() {
+        let config = NatsConfig {
+            servers: Servers(
+                "nats://nats-1:4222, tls://nats-2.example.com:4443,localhost".to_string(),
+            ),
+            authentication: NatsConfigAuthentication::None {},
+        };
+
+        assert_eq!(
+            config.hosts(),
+            vec!["nats-1", "nats-2.example.com", "localhost"]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::marker::PhantomData;
+
+use arroyo_macro::process_fn;
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use async_nats::jetstream::context::PublishAckFuture;
+use async_nats::jetstream::Context as JetStreamContext;
+use serde::Serialize;
+use tracing::info;
+
+use crate::connectors::{OperatorConfig, OperatorConfigSerializationMode};
+use crate::engine::{Context, StreamNode};
+use crate::formats::csv::CsvOptions;
+use crate::operators::SerializationMode;
+
+use super::{connect, NatsConfig, NatsTable, TableType};
+
+// the number of publishes we allow to be awaiting acknowledgement from the server before waiting
+// for them to complete
+const MAX_PENDING_ACKS: usize = 1000;
+
+#[derive(StreamNode)]
+pub struct NatsSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    connection: NatsConfig,
+    stream: String,
+    subject: String,
+    serialization_mode: SerializationMode,
+    jetstream: Option<JetStreamContext>,
+    pending_acks: Vec<PublishAckFuture>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> NatsSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for NatsSink");
+        let connection: NatsConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for NatsSink");
+        let table: NatsTable =
+            serde_json::from_value(config.table).expect("Invalid table config for NatsSink");
+        let TableType::Sink {} = table.type_ else {
+            panic!("found non-sink NATS config in sink operator");
+        };
+
+        Self {
+            connection,
+            stream: table.stream,
+            subject: table.subject.expect("NATS sinks require a subject"),
+            serialization_mode: match config.serialization_mode {
+                Some(OperatorConfigSerializationMode::RawBytes) => SerializationMode::RawBytes,
+                Some(OperatorConfigSerializationMode::Csv) => {
+                    SerializationMode::Csv(CsvOptions::from_config(config.csv.as_ref()))
+                }
+                _ => SerializationMode::Json,
+            },
+            jetstream: None,
+            pending_acks: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    /// Waits for the server to acknowledge everything we've published, so that it's been stored in
+    /// the stream
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        for ack in self.pending_acks.drain(..) {
+            if let Err(e) = ack.await {
+                ctx.report_error(
+                    format!("Failed to publish to {}", self.subject),
+                    e.to_string(),
+                )
+                .await;
+                panic!("Failed to publish to {}: {}", self.subject, e);
+            }
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> NatsSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("nats-sink-{}", self.subject)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        for host in self.connection.hosts() {
+            if let Err(e) = check_egress(host) {
+                ctx.report_error("Connection not allowed".to_string(), e.clone())
+                    .await;
+                panic!("{}", e);
+            }
+        }
+
+        match connect(&self.connection).await {
+            Ok(client) => {
+                info!(
+                    "Publishing to subject {} of stream {}",
+                    self.subject, self.stream
+                );
+                self.jetstream = Some(async_nats::jetstream::new(client));
+                ctx.report_connected().await;
+            }
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                ctx.report_error("Failed to connect to NATS".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to connect to NATS: {:?}", e);
+            }
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        let v = match self.serialization_mode.serialize(&record.value) {
+            Ok(v) => v,
+            Err(e) => {
+                ctx.report_error(e.name, e.details).await;
+                return;
+            }
+        };
+
+        let ack = self
+            .jetstream
+            .as_ref()
+            .unwrap()
+            .publish(self.subject.clone(), v.into())
+            .await;
+
+        match ack {
+            Ok(ack) => self.pending_acks.push(ack),
+            Err(e) => {
+                ctx.report_error(
+                    format!("Failed to publish to {}", self.subject),
+                    e.to_string(),
+                )
+                .await;
+                panic!("Failed to publish to {}: {}", self.subject, e);
+            }
+        }
+
+        if self.pending_acks.len() >= MAX_PENDING_ACKS {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+}
@@ -0,0 +1,328 @@
+use std::marker::PhantomData;
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use arroyo_macro::{source_fn, StreamNode};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::ControlMessage;
+use arroyo_state::tables::GlobalKeyedState;
+use arroyo_types::{check_egress, restore_override, Data, Record, SourceOffsetOverride};
+use async_nats::jetstream::consumer::{pull, AckPolicy, DeliverPolicy};
+use bincode::{Decode, Encode};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use time::OffsetDateTime;
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use crate::connectors::{OperatorConfig, OperatorConfigSerializationMode};
+use crate::engine::Context;
+use crate::formats::csv::CsvOptions;
+use crate::operators::{BadData, SerializationMode, UserError};
+use crate::SourceFinishType;
+
+use super::{connect, NatsConfig, NatsTable, SourceOffset, TableType};
+
+#[derive(Copy, Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+pub struct NatsSourceState {
+    // the stream sequence of the last message we emitted
+    stream_sequence: u64,
+}
+
+#[derive(StreamNode, Clone)]
+pub struct NatsSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    connection: NatsConfig,
+    stream: String,
+    subject: Option<String>,
+    offset_mode: SourceOffset,
+    serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
+    state: Option<NatsSourceState>,
+    paused: bool,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> NatsSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for NatsSource");
+        let connection: NatsConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for NatsSource");
+        let table: NatsTable =
+            serde_json::from_value(config.table).expect("Invalid table config for NatsSource");
+        let TableType::Source { offset } = table.type_ else {
+            panic!("found non-source NATS config in source operator");
+        };
+
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => {
+                Ok(SerializationMode::JsonSchemaRegistry)
+            }
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "NATS"))
+            }
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "NATS"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "NATS"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
+        Self {
+            connection,
+            stream: table.stream,
+            subject: table.subject,
+            offset_mode: offset,
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
+            state: None,
+            paused: false,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("nats-{}", self.stream)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("n", "NATS source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let s: GlobalKeyedState<(), NatsSourceState, _> =
+            ctx.state.get_global_keyed_state('n').await;
+
+        self.state = s.get(&()).copied();
+    }
+
+    /// Where the consumer should start reading the stream: a restore override takes precedence over
+    /// the checkpointed position, which in turn takes precedence over the configured offset
+    fn deliver_policy(&self, operator_id: &str) -> DeliverPolicy {
+        match restore_override(operator_id) {
+            Some(o) => {
+                info!(
+                    "Overriding restored position for stream {}; starting from {}",
+                    self.stream, o
+                );
+                match o {
+                    SourceOffsetOverride::Earliest => DeliverPolicy::All,
+                    SourceOffsetOverride::Latest => DeliverPolicy::New,
+                    SourceOffsetOverride::Timestamp { micros } => DeliverPolicy::ByStartTime {
+                        start_time: OffsetDateTime::from_unix_timestamp_nanos(
+                            micros as i128 * 1000,
+                        )
+                        .expect("invalid restore timestamp"),
+                    },
+                }
+            }
+            None => match (self.state, self.offset_mode) {
+                (Some(state), _) => DeliverPolicy::ByStartSequence {
+                    start_sequence: state.stream_sequence + 1,
+                },
+                (None, SourceOffset::Earliest) => DeliverPolicy::All,
+                (None, SourceOffset::Latest) => DeliverPolicy::New,
+            },
+        }
+    }
+
+    async fn our_handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if let Some(state) = self.state {
+                    let mut s: GlobalKeyedState<(), NatsSourceState, _> =
+                        ctx.state.get_global_keyed_state('n').await;
+                    s.insert((), state).await;
+                }
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping NATS source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
+                }
+            }
+            ControlMessage::Commit { epoch: _ } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::SetPaused { paused } => {
+                debug!(
+                    "NATS source {} paused: {}",
+                    ctx.task_info.task_index, paused
+                );
+                self.paused = paused;
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
+        }
+        None
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        // a stream is read in order through a single consumer, so only read on the first task
+        if ctx.task_info.task_index != 0 {
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.our_handle_control_message(ctx, msg).await {
+                    return r;
+                }
+            }
+        }
+
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn get_consumer(&self, ctx: &mut Context<(), T>) -> anyhow::Result<pull::Stream> {
+        for host in self.connection.hosts() {
+            check_egress(host).map_err(|e| anyhow!(e))?;
+        }
+
+        let client = connect(&self.connection).await?;
+        let stream = async_nats::jetstream::new(client)
+            .get_stream(&self.stream)
+            .await
+            .map_err(|e| anyhow!("Failed to fetch stream '{}': {}", self.stream, e))?;
+
+        // the consumer is recreated on every start so that it begins from our checkpointed
+        // position rather than from wherever the server last delivered to
+        let name = format!(
+            "arroyo-{}-{}",
+            ctx.task_info.job_id, ctx.task_info.operator_id
+        );
+        if stream.delete_consumer(&name).await.is_ok() {
+            debug!("Deleted existing consumer {}", name);
+        }
+
+        let deliver_policy = self.deliver_policy(&ctx.task_info.operator_id);
+        info!(
+            "Creating consumer {} for stream {} with {:?}",
+            name, self.stream, deliver_policy
+        );
+
+        let consumer = stream
+            .create_consumer(pull::Config {
+                durable_name: Some(name),
+                deliver_policy,
+                ack_policy: AckPolicy::None,
+                filter_subject: self.subject.clone().unwrap_or_default(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow!("Failed to create consumer: {}", e))?;
+
+        Ok(consumer.messages().await?)
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let mut messages = match self.get_consumer(ctx).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                return Err(UserError::new(
+                    "Could not create NATS consumer",
+                    format!("{:?}", e),
+                ));
+            }
+        };
+        ctx.report_connected().await;
+        let mut connected = true;
+
+        loop {
+            select! {
+                message = messages.next(), if !self.paused => {
+                    match message {
+                        Some(Ok(msg)) => {
+                            if !connected {
+                                ctx.report_connected().await;
+                                connected = true;
+                            }
+                            let (timestamp, stream_sequence) = match msg.info() {
+                                Ok(info) => (SystemTime::from(info.published), info.stream_sequence),
+                                Err(e) => {
+                                    return Err(UserError::new(
+                                        "Invalid JetStream message",
+                                        format!("could not read message metadata: {}", e),
+                                    ));
+                                }
+                            };
+                            ctx.report_source_lag(timestamp);
+                            ctx.profile_source_record(&msg.payload);
+                            ctx.collector.collect(Record {
+                                timestamp,
+                                key: None,
+                                value: self.serialization_mode.deserialize_slice(&msg.payload, self.bad_data)?,
+                            }).await;
+                            self.state = Some(NatsSourceState { stream_sequence });
+                        }
+                        Some(Err(e)) => {
+                            warn!("encountered error while reading stream {}: {}", self.stream, e);
+                            if connected {
+                                ctx.report_disconnected(e.to_string()).await;
+                                connected = false;
+                            }
+                        }
+                        None => {
+                            panic!("Consumer for stream {} closed", self.stream);
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -197,8 +197,14 @@ impl<K: Data, T: Data> NexmarkSourceFunc<K, T> {
                             StopMode::Immediate => {
                                 return SourceFinishType::Immediate;
                             }
+                            StopMode::Drain => {
+                                return SourceFinishType::Final;
+                            }
                         }
                     }
+                    Ok(ControlMessage::InjectProbe { probe_id }) => {
+                        ctx.handle_probe(probe_id).await;
+                    }
                     Err(TryRecvError::Empty) => {}
                     x => {
                         warn!("{:?}", x);
@@ -0,0 +1,472 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::anyhow;
+use arrow::json::writer::record_batches_to_json_rows;
+use arroyo_macro::{source_fn, StreamNode};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_state::tables::GlobalKeyedState;
+use arroyo_types::{check_egress, Data, Record};
+use bincode::{Decode, Encode};
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use futures::TryStreamExt;
+use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, path::Path, ObjectStore};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::select;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{debug, info, warn};
+use typify::import_types;
+
+use crate::engine::Context;
+use crate::formats::csv::CsvOptions;
+use crate::operators::{BadData, Lineage, SerializationMode, UserError};
+use crate::SourceFinishType;
+
+use super::filesystem::S3Credentialing;
+use super::{OperatorConfig, OperatorConfigSerializationMode};
+
+import_types!(schema = "../connector-schemas/object_store/table.json");
+
+// how many records to emit between checks for control messages while reading a file
+const RECORDS_PER_CONTROL_CHECK: usize = 1024;
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
+pub struct ObjectStoreFileState {
+    file: String,
+    // number of records from the file that have been emitted
+    records_read: u64,
+    finished: bool,
+}
+
+#[derive(StreamNode, Clone)]
+pub struct ObjectStoreSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    table: ObjectStoreTable,
+    serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
+    // whether records are read with the file and row they came from
+    lineage: bool,
+    files: HashMap<String, ObjectStoreFileState>,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> ObjectStoreSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for ObjectStoreSource");
+        let table: ObjectStoreTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for ObjectStoreSource");
+
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => Err(
+                UserError::unsupported_format("schema registry json", "object store"),
+            ),
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            // parquet rows are read as JSON
+            OperatorConfigSerializationMode::Parquet => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "object store"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "object store"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
+        Self {
+            table,
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
+            lineage: config.lineage.unwrap_or(false),
+            files: HashMap::new(),
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("object-store-{}", self.table.path)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("f", "object store source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let mut s: GlobalKeyedState<String, ObjectStoreFileState, _> =
+            ctx.state.get_global_keyed_state('f').await;
+
+        self.files = s
+            .get_all()
+            .into_iter()
+            .map(|state| (state.file.clone(), state.clone()))
+            .collect();
+    }
+
+    async fn our_handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                let owned: Vec<_> = self
+                    .files
+                    .iter()
+                    .filter(|(file, _)| self.owns(ctx, file))
+                    .map(|(file, state)| (file.clone(), state.clone()))
+                    .collect();
+
+                let mut s: GlobalKeyedState<String, ObjectStoreFileState, _> =
+                    ctx.state.get_global_keyed_state('f').await;
+                for (file, state) in owned {
+                    s.insert(file, state).await;
+                }
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping object store source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
+                }
+            }
+            ControlMessage::Commit { epoch: _ } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::SetPaused { .. } => {
+                warn!("watermark alignment is not supported by the object store source");
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
+        }
+        None
+    }
+
+    /// Files are distributed across the source's subtasks by a hash of their path
+    fn owns(&self, ctx: &Context<(), T>, file: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        file.hash(&mut hasher);
+        hasher.finish() % ctx.task_info.parallelism as u64 == ctx.task_info.task_index as u64
+    }
+
+    fn file_state(&mut self, file: &str) -> &mut ObjectStoreFileState {
+        self.files
+            .entry(file.to_string())
+            .or_insert_with(|| ObjectStoreFileState {
+                file: file.to_string(),
+                ..Default::default()
+            })
+    }
+
+    /// The store that the table's files are in, and the prefix they're under within it
+    fn store(&self) -> anyhow::Result<(Arc<dyn ObjectStore>, Path)> {
+        let path = &self.table.path;
+        if let Some(location) = path.strip_prefix("s3://") {
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            let host = match &self.table.region {
+                Some(region) => format!("{}.s3.{}.amazonaws.com", bucket, region),
+                None => format!("{}.s3.amazonaws.com", bucket),
+            };
+            check_egress(&host).map_err(|e| anyhow!(e))?;
+
+            // use default credentials
+            let mut builder = AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .with_credentials(Arc::new(S3Credentialing::try_new()?));
+            if let Some(region) = &self.table.region {
+                builder = builder.with_region(region);
+            }
+
+            let store: Arc<dyn ObjectStore> = Arc::new(builder.build()?);
+            Ok((store, Path::from(prefix)))
+        } else {
+            let directory = path.strip_prefix("file://").unwrap_or(path);
+            let store: Arc<dyn ObjectStore> = Arc::new(LocalFileSystem::new());
+            Ok((store, Path::from_filesystem_path(directory)?))
+        }
+    }
+
+    async fn list_files(
+        &self,
+        store: &dyn ObjectStore,
+        prefix: &Path,
+        pattern: &Option<Regex>,
+    ) -> anyhow::Result<Vec<String>> {
+        let objects: Vec<_> = store.list(Some(prefix)).await?.try_collect().await?;
+
+        let mut files: Vec<String> = objects
+            .into_iter()
+            .map(|object| object.location)
+            .filter(|location| {
+                let name = location.filename().unwrap_or_default();
+                pattern.as_ref().map(|p| p.is_match(name)).unwrap_or(true)
+            })
+            .map(|location| location.to_string())
+            .collect();
+
+        files.sort();
+        Ok(files)
+    }
+
+    async fn read_file(&self, store: &dyn ObjectStore, file: &str) -> anyhow::Result<Vec<u8>> {
+        let mut contents = store.get(&Path::from(file)).await?.bytes().await?.to_vec();
+
+        if file.ends_with(".gz") {
+            let mut decompressed = vec![];
+            GzDecoder::new(contents.as_slice()).read_to_end(&mut decompressed)?;
+            contents = decompressed;
+        }
+
+        Ok(contents)
+    }
+
+    /// Parses the records of the file after the first `skip`, profiling them as they're parsed
+    fn parse_records(
+        &self,
+        ctx: &mut Context<(), T>,
+        file: &str,
+        contents: Vec<u8>,
+        skip: usize,
+    ) -> anyhow::Result<Vec<Result<T, UserError>>> {
+        let rows: Vec<Vec<u8>> = match self.table.file_format {
+            FileFormat::Json => contents
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.iter().all(|b| b.is_ascii_whitespace()))
+                .map(|line| line.to_vec())
+                .collect(),
+            FileFormat::Parquet => parquet_rows(contents)?,
+            FileFormat::Csv => {
+                return Ok(csv::ReaderBuilder::new()
+                    .has_headers(true)
+                    .from_reader(contents.as_slice())
+                    .deserialize()
+                    .skip(skip)
+                    .map(|r| {
+                        r.map_err(|e| {
+                            UserError::new(
+                                "Deserialization error",
+                                format!("Invalid CSV row: {}", e),
+                            )
+                        })
+                    })
+                    .collect());
+            }
+        };
+
+        Ok(rows
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(i, row)| {
+                ctx.profile_source_record(row);
+                if self.lineage {
+                    let lineage = Lineage {
+                        source: file,
+                        partition: None,
+                        offset: i as i64 + 1,
+                    };
+                    self.serialization_mode.deserialize_slice_with_lineage(
+                        row,
+                        self.bad_data,
+                        &lineage,
+                    )
+                } else {
+                    self.serialization_mode
+                        .deserialize_slice(row, self.bad_data)
+                }
+            })
+            .collect())
+    }
+
+    /// Emits the unread records of the file, returning early if a control message asks us to stop
+    async fn process_file(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        store: &dyn ObjectStore,
+        file: &str,
+    ) -> Result<(), SourceFinishType> {
+        info!("Reading {}", file);
+        let skip = self.files.get(file).map(|s| s.records_read).unwrap_or(0) as usize;
+
+        let records = match self
+            .read_file(store, file)
+            .await
+            .and_then(|contents| self.parse_records(ctx, file, contents, skip))
+        {
+            Ok(records) => records,
+            Err(e) => {
+                ctx.report_error(format!("Failed to read {}", file), format!("{:?}", e))
+                    .await;
+                return Ok(());
+            }
+        };
+
+        for (i, record) in records.into_iter().enumerate() {
+            match record {
+                Ok(value) => {
+                    ctx.collector
+                        .collect(Record {
+                            timestamp: SystemTime::now(),
+                            key: None,
+                            value,
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    ctx.report_error(e.name, format!("{} (in {})", e.details, file))
+                        .await;
+                }
+            }
+
+            self.file_state(file).records_read = (skip + i) as u64 + 1;
+
+            if (i + 1) % RECORDS_PER_CONTROL_CHECK == 0 {
+                match ctx.control_rx.try_recv() {
+                    Ok(msg) => {
+                        if let Some(r) = self.our_handle_control_message(ctx, Some(msg)).await {
+                            return Err(r);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => {
+                        return Err(SourceFinishType::Immediate);
+                    }
+                }
+            }
+        }
+
+        self.file_state(file).finished = true;
+        Ok(())
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        let pattern = match &self.table.file_pattern {
+            Some(p) => Some(Regex::new(&format!("^(?:{})$", p)).expect("invalid file pattern")),
+            None => None,
+        };
+
+        let (store, prefix) = match self.store() {
+            Ok(store) => {
+                ctx.report_connected().await;
+                store
+            }
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                ctx.control_tx
+                    .send(ControlResp::Error {
+                        operator_id: ctx.task_info.operator_id.clone(),
+                        task_index: ctx.task_info.task_index,
+                        message: format!("Failed to open {}", self.table.path),
+                        details: format!("{:?}", e),
+                    })
+                    .await
+                    .unwrap();
+                panic!("Failed to open {}: {:?}", self.table.path, e);
+            }
+        };
+
+        loop {
+            let files = match self.list_files(store.as_ref(), &prefix, &pattern).await {
+                Ok(files) => files,
+                Err(e) => {
+                    ctx.report_error(
+                        format!("Failed to list {}", self.table.path),
+                        format!("{:?}", e),
+                    )
+                    .await;
+                    vec![]
+                }
+            };
+
+            for file in files {
+                if !self.owns(ctx, &file)
+                    || self.files.get(&file).map(|s| s.finished).unwrap_or(false)
+                {
+                    continue;
+                }
+
+                if let Err(r) = self.process_file(ctx, store.as_ref(), &file).await {
+                    return r;
+                }
+            }
+
+            // without a poll interval, we're backfilling the files that were there at the start
+            let Some(poll_interval) = self.table.poll_interval_seconds else {
+                info!("Finished reading {}", self.table.path);
+                return SourceFinishType::Final;
+            };
+
+            let sleep = tokio::time::sleep(Duration::from_secs(poll_interval as u64));
+            tokio::pin!(sleep);
+            loop {
+                select! {
+                    _ = &mut sleep => break,
+                    control_message = ctx.control_rx.recv() => {
+                        if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
+                            return r;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The rows of a parquet file, as JSON
+fn parquet_rows(contents: Vec<u8>) -> anyhow::Result<Vec<Vec<u8>>> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(contents))?.build()?;
+
+    let mut rows = vec![];
+    for batch in reader {
+        for row in record_batches_to_json_rows(&[&batch?])? {
+            rows.push(serde_json::to_vec(&row)?);
+        }
+    }
+
+    Ok(rows)
+}
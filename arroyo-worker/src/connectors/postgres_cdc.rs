@@ -0,0 +1,820 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+use arroyo_macro::{source_fn, StreamNode};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::ControlMessage;
+use arroyo_state::tables::GlobalKeyedState;
+use arroyo_types::{from_micros, Data, Record};
+use bincode::{Decode, Encode};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Number, Value};
+use tokio::select;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_postgres::{Client, NoTls};
+use tracing::{debug, info, warn};
+use typify::import_types;
+
+use crate::connectors::{check_postgres_egress, OperatorConfig, OperatorConfigSerializationMode};
+use crate::engine::Context;
+use crate::operators::UserError;
+use crate::SourceFinishType;
+
+import_types!(schema = "../connector-schemas/postgres_cdc/connection.json");
+import_types!(schema = "../connector-schemas/postgres_cdc/table.json");
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// the most changes read by each poll beyond those that have already been read
+const MAX_CHANGES_PER_POLL: i32 = 10_000;
+
+// changes are peeked rather than consumed, as the slot is only advanced past them once they've been
+// checkpointed
+const WAL2JSON_QUERY: &str =
+    "SELECT lsn::text, data FROM pg_logical_slot_peek_changes($1, NULL, $2, \
+    'format-version', '2', 'include-timestamp', '1', 'add-tables', $3)";
+const PGOUTPUT_QUERY: &str = "SELECT lsn::text, data FROM pg_logical_slot_peek_binary_changes($1, \
+    NULL, $2, 'proto_version', '1', 'publication_names', $3)";
+
+// microseconds between the unix and Postgres (2000-01-01) epochs
+const POSTGRES_EPOCH_MICROS: u64 = 946_684_800_000_000;
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
+pub struct PostgresCdcState {
+    // the end LSN of the last transaction we emitted
+    lsn: Option<u64>,
+}
+
+/// Parses an LSN in Postgres's text format, like 16/B374D848
+fn parse_lsn(lsn: &str) -> Option<u64> {
+    let (high, low) = lsn.split_once('/')?;
+    Some(u64::from_str_radix(high, 16).ok()? << 32 | u64::from_str_radix(low, 16).ok()?)
+}
+
+fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+}
+
+/// The name of a table with its schema, which defaults to public
+fn qualified_table(table: &str) -> String {
+    if table.contains('.') {
+        table.to_string()
+    } else {
+        format!("public.{}", table)
+    }
+}
+
+/// Converts a timestamp in Postgres's text format (like 2023-06-01 12:30:00.25+02) to RFC3339,
+/// which is what our deserializers expect. Timestamps without a time zone are taken to be UTC.
+fn timestamp_to_rfc3339(timestamp: &str) -> Option<String> {
+    if let Ok(t) = DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%#z") {
+        return Some(t.to_rfc3339());
+    }
+
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|t| Utc.from_utc_datetime(&t).to_rfc3339())
+}
+
+// the oids of the types whose text representations are converted to other kinds of JSON values
+const BOOL_OID: u32 = 16;
+const INT8_OID: u32 = 20;
+const INT2_OID: u32 = 21;
+const INT4_OID: u32 = 23;
+const OID_OID: u32 = 26;
+const FLOAT4_OID: u32 = 700;
+const FLOAT8_OID: u32 = 701;
+const TIMESTAMP_OID: u32 = 1114;
+const TIMESTAMPTZ_OID: u32 = 1184;
+const NUMERIC_OID: u32 = 1700;
+
+/// Converts a value in its Postgres text representation into JSON; values of types that aren't
+/// booleans, numbers or timestamps (or that can't be parsed as them) are kept as strings
+fn text_to_json(type_oid: u32, text: &str) -> Value {
+    let parsed = match type_oid {
+        BOOL_OID => Some(Value::Bool(text == "t")),
+        INT2_OID | INT4_OID | INT8_OID | OID_OID => text.parse::<i64>().ok().map(Value::from),
+        FLOAT4_OID | FLOAT8_OID | NUMERIC_OID => text
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number),
+        TIMESTAMP_OID | TIMESTAMPTZ_OID => timestamp_to_rfc3339(text).map(Value::String),
+        _ => None,
+    };
+
+    parsed.unwrap_or_else(|| Value::String(text.to_string()))
+}
+
+/// A message decoded from the replication slot
+#[derive(Debug, PartialEq)]
+enum Message {
+    Begin,
+    Commit {
+        timestamp: Option<SystemTime>,
+    },
+    /// A change to a row of `table` (qualified with its schema); `before` is only set if the
+    /// complete old row is known, which requires the table's replica identity to be FULL
+    Change {
+        table: String,
+        before: Option<Map<String, Value>>,
+        after: Option<Map<String, Value>>,
+    },
+    Other,
+}
+
+/// Decodes a message in wal2json's format-version 2, which has one JSON object per message
+fn decode_wal2json(data: &str) -> Result<Message, String> {
+    let message: Value =
+        serde_json::from_str(data).map_err(|e| format!("invalid wal2json message: {}", e))?;
+
+    // wal2json writes values of numeric and boolean types as JSON, and the rest as strings
+    let columns = |key: &str| -> Option<Map<String, Value>> {
+        let columns = message.get(key)?.as_array()?;
+        Some(
+            columns
+                .iter()
+                .filter_map(|column| {
+                    let name = column.get("name")?.as_str()?.to_string();
+                    let value = column.get("value").cloned().unwrap_or(Value::Null);
+                    let value = match (column.get("type").and_then(Value::as_str), value) {
+                        (Some(t), Value::String(s)) if t.starts_with("timestamp") => {
+                            Value::String(timestamp_to_rfc3339(&s).unwrap_or(s))
+                        }
+                        (_, value) => value,
+                    };
+                    Some((name, value))
+                })
+                .collect(),
+        )
+    };
+
+    let action = message.get("action").and_then(Value::as_str);
+    Ok(match action {
+        Some("B") => Message::Begin,
+        Some("C") => Message::Commit {
+            timestamp: message
+                .get("timestamp")
+                .and_then(Value::as_str)
+                .and_then(timestamp_to_rfc3339)
+                .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                .map(|t| SystemTime::from(t.with_timezone(&Utc))),
+        },
+        Some(action @ ("I" | "U" | "D")) => {
+            let name = |key: &str| message.get(key).and_then(Value::as_str).unwrap_or("");
+            Message::Change {
+                table: format!("{}.{}", name("schema"), name("table")),
+                before: (action != "I").then(|| columns("identity")).flatten(),
+                after: (action != "D").then(|| columns("columns")).flatten(),
+            }
+        }
+        _ => Message::Other,
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Relation {
+    table: String,
+    // the names and type oids of its columns
+    columns: Vec<(String, u32)>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < n {
+            return Err("truncated pgoutput message".to_string());
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let end = self
+            .data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| "unterminated string in pgoutput message".to_string())?;
+        let s = String::from_utf8_lossy(self.bytes(end)?).to_string();
+        self.bytes(1)?;
+        Ok(s)
+    }
+}
+
+/// Decodes messages of version 1 of the pgoutput protocol. Changes refer to their tables by oid,
+/// so the relation messages that describe the tables (which precede their first change in each
+/// session) are kept.
+#[derive(Default)]
+struct PgOutputDecoder {
+    relations: HashMap<u32, Relation>,
+}
+
+impl PgOutputDecoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Message, String> {
+        let mut reader = Reader { data };
+        Ok(match reader.u8()? {
+            b'B' => Message::Begin,
+            b'C' => {
+                // flags, commit LSN and end LSN
+                reader.bytes(17)?;
+                let micros = reader.i64()?;
+                Message::Commit {
+                    timestamp: Some(from_micros(
+                        (POSTGRES_EPOCH_MICROS as i64 + micros).max(0) as u64
+                    )),
+                }
+            }
+            b'R' => {
+                let oid = reader.u32()?;
+                let namespace = reader.string()?;
+                let name = reader.string()?;
+                // replica identity
+                reader.u8()?;
+                let columns = (0..reader.i16()?)
+                    .map(|_| -> Result<(String, u32), String> {
+                        // flags
+                        reader.u8()?;
+                        let name = reader.string()?;
+                        let type_oid = reader.u32()?;
+                        // type modifier
+                        reader.u32()?;
+                        Ok((name, type_oid))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                self.relations.insert(
+                    oid,
+                    Relation {
+                        table: format!("{}.{}", namespace, name),
+                        columns,
+                    },
+                );
+                Message::Other
+            }
+            kind @ (b'I' | b'U' | b'D') => {
+                let oid = reader.u32()?;
+                let relation = self
+                    .relations
+                    .get(&oid)
+                    .ok_or_else(|| format!("change to unknown relation {}", oid))?;
+
+                let mut before = None;
+                let mut after = None;
+                while !reader.data.is_empty() {
+                    match reader.u8()? {
+                        // the complete old row
+                        b'O' => before = Some(Self::tuple(&mut reader, relation)?),
+                        // just the old key, which doesn't make a complete row
+                        b'K' => {
+                            Self::tuple(&mut reader, relation)?;
+                        }
+                        b'N' => after = Some(Self::tuple(&mut reader, relation)?),
+                        other => return Err(format!("unexpected tuple type {}", other as char)),
+                    }
+                }
+
+                if kind == b'I' && after.is_none() {
+                    return Err("insert without a new row".to_string());
+                }
+
+                Message::Change {
+                    table: relation.table.clone(),
+                    before,
+                    after,
+                }
+            }
+            _ => Message::Other,
+        })
+    }
+
+    fn tuple(reader: &mut Reader, relation: &Relation) -> Result<Map<String, Value>, String> {
+        let count = reader.i16()? as usize;
+        let mut row = Map::new();
+        for i in 0..count {
+            let (name, type_oid) = relation
+                .columns
+                .get(i)
+                .ok_or_else(|| format!("row has more columns than {}", relation.table))?;
+
+            let value = match reader.u8()? {
+                b'n' => Value::Null,
+                // an unchanged TOASTed value, which isn't sent
+                b'u' => continue,
+                b't' => {
+                    let len = reader.u32()? as usize;
+                    text_to_json(*type_oid, &String::from_utf8_lossy(reader.bytes(len)?))
+                }
+                other => return Err(format!("unexpected column type {}", other as char)),
+            };
+            row.insert(name.clone(), value);
+        }
+        Ok(row)
+    }
+}
+
+enum Decoder {
+    Wal2Json,
+    PgOutput(PgOutputDecoder),
+}
+
+#[derive(StreamNode, Clone)]
+pub struct PostgresCdcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    connection_string: String,
+    table: String,
+    slot: String,
+    plugin: Plugin,
+    publication: Option<String>,
+    // emit Debezium-style changes rather than just the current rows
+    updating: bool,
+    format_error: Option<UserError>,
+    state: PostgresCdcState,
+    // the LSN of the last checkpoint, which the slot is advanced to once the next checkpoint
+    // starts (and so that one has completed)
+    checkpointed_lsn: Option<u64>,
+    // the number of changes read since the slot was last advanced, all of which are read again
+    // by each poll
+    read_changes: i32,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> PostgresCdcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PostgresCdcSource");
+        let connection: PostgresCdcConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PostgresCdcSource");
+        let table: PostgresCdcTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for PostgresCdcSource");
+
+        let updating = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(false),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(true),
+            other => Err(UserError::unsupported_format(
+                &format!("{:?}", other),
+                "Postgres CDC",
+            )),
+        };
+
+        Self {
+            connection_string: connection.connection_string.to_string(),
+            table: qualified_table(&table.table),
+            slot: table.slot,
+            plugin: table.plugin,
+            publication: table.publication,
+            updating: *updating.as_ref().unwrap_or(&false),
+            format_error: updating.err(),
+            state: PostgresCdcState::default(),
+            checkpointed_lsn: None,
+            read_changes: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("postgres-cdc-{}", self.table)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("p", "postgres cdc source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let s: GlobalKeyedState<(), PostgresCdcState, _> =
+            ctx.state.get_global_keyed_state('p').await;
+
+        if let Some(state) = s.get(&()) {
+            self.state = state.clone();
+            // restored state is from a completed checkpoint
+            self.checkpointed_lsn = state.lsn;
+        }
+    }
+
+    async fn our_handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        client: Option<&Client>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                // checkpoints run one at a time, so the last one has completed and the changes
+                // it covered won't be read again
+                if let (Some(client), Some(lsn)) = (client, self.checkpointed_lsn) {
+                    self.advance_slot(client, lsn).await;
+                }
+                self.checkpointed_lsn = self.state.lsn;
+
+                let mut s: GlobalKeyedState<(), PostgresCdcState, _> =
+                    ctx.state.get_global_keyed_state('p').await;
+                s.insert((), self.state.clone()).await;
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping Postgres CDC source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
+                }
+            }
+            ControlMessage::Commit { epoch: _ } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::SetPaused { .. } => {
+                warn!("watermark alignment is not supported by the Postgres CDC source");
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
+        }
+        None
+    }
+
+    async fn advance_slot(&mut self, client: &Client, lsn: u64) {
+        match client
+            .execute(
+                "SELECT pg_replication_slot_advance($1, $2::text::pg_lsn)",
+                &[&self.slot, &format_lsn(lsn)],
+            )
+            .await
+        {
+            Ok(_) => self.read_changes = 0,
+            // the changes will be skipped when they're read again
+            Err(e) => warn!("Failed to advance replication slot {}: {}", self.slot, e),
+        }
+    }
+
+    async fn connect(&self) -> Result<Client, UserError> {
+        check_postgres_egress(&self.connection_string)
+            .map_err(|e| UserError::new("Connection not allowed", e))?;
+        let (client, connection) = tokio_postgres::connect(&self.connection_string, NoTls)
+            .await
+            .map_err(|e| UserError::new("Failed to connect to Postgres", e.to_string()))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Postgres connection closed: {}", e);
+            }
+        });
+
+        let exists = client
+            .query_opt(
+                "SELECT 1 FROM pg_replication_slots WHERE slot_name = $1",
+                &[&self.slot],
+            )
+            .await
+            .map_err(|e| UserError::new("Failed to list replication slots", e.to_string()))?
+            .is_some();
+
+        if !exists {
+            info!(
+                "Creating replication slot {} with plugin {}",
+                self.slot,
+                self.plugin.to_string()
+            );
+            client
+                .execute(
+                    "SELECT pg_create_logical_replication_slot($1, $2)",
+                    &[&self.slot, &self.plugin.to_string()],
+                )
+                .await
+                .map_err(|e| UserError::new("Failed to create replication slot", e.to_string()))?;
+        }
+
+        Ok(client)
+    }
+
+    /// Reads the changes in the slot, emitting those of transactions that haven't been emitted
+    /// yet. The slot is only advanced once a checkpoint has completed, so each poll reads the
+    /// changes from the last checkpoint again.
+    async fn poll(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        client: &Client,
+        decoder: &mut Decoder,
+    ) -> Result<(), UserError> {
+        let limit = self.read_changes + MAX_CHANGES_PER_POLL;
+        let rows = match decoder {
+            Decoder::Wal2Json => {
+                client
+                    .query(WAL2JSON_QUERY, &[&self.slot, &limit, &self.table])
+                    .await
+            }
+            Decoder::PgOutput(_) => {
+                let publication = self.publication.clone().unwrap_or_default();
+                client
+                    .query(PGOUTPUT_QUERY, &[&self.slot, &limit, &publication])
+                    .await
+            }
+        }
+        .map_err(|e| UserError::new("Failed to read replication slot", e.to_string()))?;
+
+        self.read_changes = rows.len() as i32;
+
+        // the changes of the current transaction, which are emitted when it commits
+        let mut transaction = vec![];
+        for row in rows {
+            let lsn: String = row.get(0);
+            let lsn = parse_lsn(&lsn)
+                .ok_or_else(|| UserError::new("Invalid LSN", format!("could not parse {}", lsn)))?;
+
+            let message = match decoder {
+                Decoder::Wal2Json => decode_wal2json(row.get(1)),
+                Decoder::PgOutput(decoder) => decoder.decode(row.get(1)),
+            }
+            .map_err(|e| UserError::new("Failed to decode change", e))?;
+
+            match message {
+                Message::Begin => transaction.clear(),
+                Message::Change {
+                    table,
+                    before,
+                    after,
+                } if table == self.table => {
+                    transaction.push((before, after));
+                }
+                // the row of a commit has the transaction's end LSN
+                Message::Commit { timestamp } => {
+                    if self.state.lsn.map(|l| lsn > l).unwrap_or(true) {
+                        let timestamp = timestamp.unwrap_or_else(SystemTime::now);
+                        for (before, after) in transaction.drain(..) {
+                            self.emit(ctx, timestamp, before, after).await;
+                        }
+                        self.state.lsn = Some(lsn);
+                    }
+                    transaction.clear();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn emit(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        timestamp: SystemTime,
+        before: Option<Map<String, Value>>,
+        after: Option<Map<String, Value>>,
+    ) {
+        let missing = || {
+            UserError::new(
+                "Missing old row in change",
+                format!(
+                    "a change to {} did not include the complete old row; the table's replica \
+                    identity must be FULL to read updates and deletes",
+                    self.table
+                ),
+            )
+        };
+
+        let json = match (before, after, self.updating) {
+            (_, Some(after), false) => Ok(Value::Object(after)),
+            // deletes
+            (_, None, false) => return,
+            (None, Some(after), true) => Ok(json!({"before": null, "after": after, "op": "c"})),
+            (Some(before), Some(after), true) => {
+                Ok(json!({"before": before, "after": after, "op": "u"}))
+            }
+            (Some(before), None, true) => Ok(json!({"before": before, "after": null, "op": "d"})),
+            (None, None, true) => Err(missing()),
+        };
+
+        let value = json.and_then(|json| {
+            serde_json::from_value::<T>(json).map_err(|e| {
+                UserError::new(
+                    "Deserialization error",
+                    format!("Failed to deserialize change to {}: {}", self.table, e),
+                )
+            })
+        });
+
+        match value {
+            Ok(value) => {
+                ctx.collector
+                    .collect(Record {
+                        timestamp,
+                        key: None,
+                        value,
+                    })
+                    .await;
+            }
+            Err(e) => {
+                ctx.report_error(e.name, e.details).await;
+            }
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        // a replication slot can only be read by one client, so only read on the first task
+        if ctx.task_info.task_index != 0 {
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.our_handle_control_message(ctx, None, msg).await {
+                    return r;
+                }
+            }
+        }
+
+        let client = match self.connect().await {
+            Ok(client) => client,
+            Err(e) => {
+                ctx.report_disconnected(e.details.clone()).await;
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                panic!("{}: {}", e.name, e.details);
+            }
+        };
+        ctx.report_connected().await;
+
+        if let Some(lsn) = self.state.lsn {
+            info!(
+                "Resuming replication slot {} after {}",
+                self.slot,
+                format_lsn(lsn)
+            );
+            self.advance_slot(&client, lsn).await;
+        }
+
+        let mut decoder = match self.plugin {
+            Plugin::Wal2json => Decoder::Wal2Json,
+            Plugin::Pgoutput => Decoder::PgOutput(PgOutputDecoder::default()),
+        };
+
+        let mut poll = interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            select! {
+                _ = poll.tick() => {
+                    if let Err(e) = self.poll(ctx, &client, &mut decoder).await {
+                        ctx.report_disconnected(e.details.clone()).await;
+                        ctx.report_error(e.name.clone(), e.details.clone()).await;
+                        panic!("{}: {}", e.name, e.details);
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.our_handle_control_message(ctx, Some(&client), control_message).await {
+                        return r;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lsn() {
+        assert_eq!(Some(0x16_B374_D848), parse_lsn("16/B374D848"));
+        assert_eq!("16/B374D848", format_lsn(0x16_B374_D848));
+        assert_eq!("0/0", format_lsn(parse_lsn("0/0").unwrap()));
+        assert_eq!(None, parse_lsn("B374D848"));
+    }
+
+    #[test]
+    fn test_decode_wal2json() {
+        assert_eq!(
+            Message::Change {
+                table: "public.orders".to_string(),
+                before: Some(Map::from_iter([("id".to_string(), json!(1))])),
+                after: Some(Map::from_iter([
+                    ("id".to_string(), json!(1)),
+                    (
+                        "created_at".to_string(),
+                        json!("2023-06-01T12:30:00.250+02:00")
+                    ),
+                ])),
+            },
+            decode_wal2json(
+                r#"{"action":"U","schema":"public","table":"orders",
+                "columns":[{"name":"id","type":"integer","value":1},
+                    {"name":"created_at","type":"timestamp with time zone","value":"2023-06-01 12:30:00.25+02"}],
+                "identity":[{"name":"id","type":"integer","value":1}]}"#
+            )
+            .unwrap()
+        );
+
+        assert_eq!(
+            Message::Commit {
+                timestamp: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_577_595_514))
+            },
+            decode_wal2json(r#"{"action":"C","timestamp":"2019-12-29 04:58:34+00"}"#).unwrap()
+        );
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        bytes
+    }
+
+    #[test]
+    fn test_decode_pgoutput() {
+        let mut decoder = PgOutputDecoder::default();
+
+        let mut relation = vec![b'R'];
+        relation.extend(42u32.to_be_bytes());
+        relation.extend(string("public"));
+        relation.extend(string("orders"));
+        relation.push(b'f');
+        relation.extend(2i16.to_be_bytes());
+        for (name, oid) in [("id", INT8_OID), ("created_at", TIMESTAMP_OID)] {
+            relation.push(1);
+            relation.extend(string(name));
+            relation.extend(oid.to_be_bytes());
+            relation.extend((-1i32).to_be_bytes());
+        }
+        assert_eq!(Message::Other, decoder.decode(&relation).unwrap());
+
+        let mut delete = vec![b'D'];
+        delete.extend(42u32.to_be_bytes());
+        delete.push(b'O');
+        delete.extend(2i16.to_be_bytes());
+        delete.push(b't');
+        delete.extend(2u32.to_be_bytes());
+        delete.extend(b"17");
+        delete.push(b'n');
+        assert_eq!(
+            Message::Change {
+                table: "public.orders".to_string(),
+                before: Some(Map::from_iter([
+                    ("id".to_string(), json!(17)),
+                    ("created_at".to_string(), Value::Null),
+                ])),
+                after: None,
+            },
+            decoder.decode(&delete).unwrap()
+        );
+
+        // changes can't be decoded without their relation
+        let mut insert = vec![b'I'];
+        insert.extend(7u32.to_be_bytes());
+        insert.push(b'N');
+        insert.extend(0i16.to_be_bytes());
+        assert!(decoder.decode(&insert).is_err());
+    }
+
+    #[test]
+    fn test_text_to_json() {
+        assert_eq!(json!(true), text_to_json(BOOL_OID, "t"));
+        assert_eq!(json!(-3), text_to_json(INT4_OID, "-3"));
+        assert_eq!(json!(1.5), text_to_json(NUMERIC_OID, "1.5"));
+        assert_eq!(json!("NaN"), text_to_json(NUMERIC_OID, "NaN"));
+        assert_eq!(
+            json!("2023-06-01T12:30:00+00:00"),
+            text_to_json(TIMESTAMP_OID, "2023-06-01 12:30:00")
+        );
+        assert_eq!(json!("hello"), text_to_json(25, "hello"));
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::marker::PhantomData;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail};
+use arroyo_macro::{source_fn, StreamNode};
+use arroyo_rpc::grpc::{
+    StopMode, TableDeleteBehavior, TableDescriptor, TableType as StateTableType,
+    TableWriteBehavior, TaskCheckpointEventType,
+};
+use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp};
+use arroyo_state::tables::GlobalKeyedState;
+use arroyo_types::{check_egress, Data, Record};
+use bincode::{Decode, Encode};
+use flate2::read::GzDecoder;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use ssh2::{Session, Sftp};
+use tokio::select;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{debug, info, warn};
+use typify::import_types;
+
+use crate::engine::Context;
+use crate::formats::csv::CsvOptions;
+use crate::operators::{BadData, Lineage, SerializationMode, UserError};
+use crate::SourceFinishType;
+
+use super::{OperatorConfig, OperatorConfigSerializationMode};
+
+import_types!(schema = "../connector-schemas/sftp/connection.json");
+import_types!(schema = "../connector-schemas/sftp/table.json");
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+// how many records to emit between checks for control messages while reading a file
+const RECORDS_PER_CONTROL_CHECK: usize = 1024;
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
+pub struct SftpFileState {
+    file: String,
+    // number of records from the file that have been emitted
+    records_read: u64,
+    finished: bool,
+}
+
+#[derive(StreamNode, Clone)]
+pub struct SftpSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    connection: SftpConfig,
+    table: SftpTable,
+    serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
+    // whether records are read with the file and line they came from
+    lineage: bool,
+    files: HashMap<String, SftpFileState>,
+    // files that have been fully read since the last checkpoint
+    pending_post_processing: Vec<String>,
+    // files recorded as finished by the last checkpoint, which are post-processed once it has
+    // been committed
+    awaiting_commit: Vec<String>,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> SftpSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: DeserializeOwned + Data,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for SftpSource");
+        let connection: SftpConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for SftpSource");
+        let table: SftpTable =
+            serde_json::from_value(config.table).expect("Invalid table config for SftpSource");
+
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => Err(
+                UserError::unsupported_format("schema registry json", "SFTP"),
+            ),
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "SFTP"))
+            }
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "SFTP"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "SFTP"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
+        Self {
+            connection,
+            table,
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
+            lineage: config.lineage.unwrap_or(false),
+            files: HashMap::new(),
+            pending_post_processing: vec![],
+            awaiting_commit: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("sftp-{}", self.table.directory)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![
+            arroyo_state::global_table("f", "sftp source state"),
+            // this table is written on every checkpoint, and as its writes are committed, the
+            // controller sends a commit once the checkpoint has completed
+            TableDescriptor {
+                name: "p".into(),
+                description: "sftp files awaiting post-processing".into(),
+                table_type: StateTableType::Global as i32,
+                delete_behavior: TableDeleteBehavior::None as i32,
+                write_behavior: TableWriteBehavior::CommitWrites as i32,
+                retention_micros: 0,
+            },
+        ]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let mut s: GlobalKeyedState<String, SftpFileState, _> =
+            ctx.state.get_global_keyed_state('f').await;
+
+        self.files = s
+            .get_all()
+            .into_iter()
+            .map(|state| (state.file.clone(), state.clone()))
+            .collect();
+    }
+
+    async fn our_handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        sftp: &Sftp,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                let owned: Vec<_> = self
+                    .files
+                    .iter()
+                    .filter(|(file, _)| self.owns(ctx, file))
+                    .map(|(file, state)| (file.clone(), state.clone()))
+                    .collect();
+
+                let mut s: GlobalKeyedState<String, SftpFileState, _> =
+                    ctx.state.get_global_keyed_state('f').await;
+                for (file, state) in owned {
+                    s.insert(file, state).await;
+                }
+
+                // files that were read after the previous checkpoint was taken are post-processed
+                // with this one
+                self.awaiting_commit
+                    .append(&mut self.pending_post_processing);
+                let mut p: GlobalKeyedState<usize, Vec<String>, _> =
+                    ctx.state.get_global_keyed_state('p').await;
+                p.insert(ctx.task_info.task_index, self.awaiting_commit.clone())
+                    .await;
+
+                if self.checkpoint(c, ctx).await {
+                    // the files finished by the final checkpoint are post-processed once it's
+                    // been committed
+                    match ctx.control_rx.recv().await {
+                        Some(ControlMessage::Commit { epoch }) => {
+                            self.handle_commit(epoch, ctx, sftp).await;
+                        }
+                        _ => warn!("no commit message received, not post-processing files"),
+                    }
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping SFTP source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
+                }
+            }
+            ControlMessage::Commit { epoch } => {
+                self.handle_commit(epoch, ctx, sftp).await;
+            }
+            ControlMessage::SetPaused { .. } => {
+                warn!("watermark alignment is not supported by the SFTP source");
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
+        }
+        None
+    }
+
+    /// Post-processes the files recorded as finished by the checkpoint that has been committed.
+    /// If the checkpoint fails instead, the job is restored from an earlier one that still
+    /// reads those files, so they can't be deleted or moved before this point.
+    async fn handle_commit(&mut self, epoch: u32, ctx: &mut Context<(), T>, sftp: &Sftp) {
+        for file in std::mem::take(&mut self.awaiting_commit) {
+            if let Err(e) = tokio::task::block_in_place(|| self.post_process(sftp, &file)) {
+                warn!("Failed to post-process {}: {:?}", file, e);
+            }
+        }
+
+        ctx.control_tx
+            .send(ControlResp::CheckpointEvent(CheckpointEvent {
+                checkpoint_epoch: epoch,
+                operator_id: ctx.task_info.operator_id.clone(),
+                subtask_index: ctx.task_info.task_index as u32,
+                time: SystemTime::now(),
+                event_type: TaskCheckpointEventType::FinishedCommit.into(),
+            }))
+            .await
+            .expect("sent commit event");
+    }
+
+    /// Files are distributed across the source's subtasks by a hash of their name
+    fn owns(&self, ctx: &Context<(), T>, file: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        file.hash(&mut hasher);
+        hasher.finish() % ctx.task_info.parallelism as u64 == ctx.task_info.task_index as u64
+    }
+
+    fn file_state(&mut self, file: &str) -> &mut SftpFileState {
+        self.files
+            .entry(file.to_string())
+            .or_insert_with(|| SftpFileState {
+                file: file.to_string(),
+                ..Default::default()
+            })
+    }
+
+    fn connect(&self) -> anyhow::Result<Sftp> {
+        check_egress(&self.connection.host).map_err(|e| anyhow!(e))?;
+
+        let tcp = TcpStream::connect((
+            self.connection.host.as_str(),
+            self.connection.port.unwrap_or(22) as u16,
+        ))?;
+
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+
+        match &self.connection.authentication {
+            Authentication::Password { password } => {
+                session.userauth_password(&self.connection.username, password)?
+            }
+            Authentication::PrivateKey {
+                private_key,
+                passphrase,
+            } => session.userauth_pubkey_memory(
+                &self.connection.username,
+                None,
+                private_key,
+                passphrase.as_deref(),
+            )?,
+        }
+
+        if !session.authenticated() {
+            bail!("failed to authenticate as {}", self.connection.username);
+        }
+
+        Ok(session.sftp()?)
+    }
+
+    fn list_files(&self, sftp: &Sftp, pattern: &Option<Regex>) -> anyhow::Result<Vec<String>> {
+        let mut files: Vec<String> = sftp
+            .readdir(Path::new(&self.table.directory))?
+            .into_iter()
+            .filter(|(_, stat)| stat.is_file())
+            .filter_map(|(path, _)| Some(path.file_name()?.to_str()?.to_string()))
+            .filter(|name| pattern.as_ref().map(|p| p.is_match(name)).unwrap_or(true))
+            .collect();
+
+        files.sort();
+        Ok(files)
+    }
+
+    fn read_file(&self, sftp: &Sftp, file: &str) -> anyhow::Result<Vec<u8>> {
+        let mut contents = vec![];
+        sftp.open(&Path::new(&self.table.directory).join(file))?
+            .read_to_end(&mut contents)?;
+
+        if file.ends_with(".gz") {
+            let mut decompressed = vec![];
+            GzDecoder::new(contents.as_slice()).read_to_end(&mut decompressed)?;
+            contents = decompressed;
+        }
+
+        Ok(contents)
+    }
+
+    fn parse_records(&self, file: &str, contents: &[u8]) -> Vec<Result<T, UserError>> {
+        match self.table.file_format {
+            FileFormat::Lines => contents
+                .split(|b| *b == b'\n')
+                .enumerate()
+                .filter(|(_, line)| !line.iter().all(|b| b.is_ascii_whitespace()))
+                .map(|(i, line)| {
+                    if self.lineage {
+                        let lineage = Lineage {
+                            source: file,
+                            partition: None,
+                            offset: i as i64 + 1,
+                        };
+                        self.serialization_mode.deserialize_slice_with_lineage(
+                            line,
+                            self.bad_data,
+                            &lineage,
+                        )
+                    } else {
+                        self.serialization_mode
+                            .deserialize_slice(line, self.bad_data)
+                    }
+                })
+                .collect(),
+            FileFormat::Csv => csv::ReaderBuilder::new()
+                .has_headers(true)
+                .from_reader(contents)
+                .deserialize()
+                .map(|r| {
+                    r.map_err(|e| {
+                        UserError::new("Deserialization error", format!("Invalid CSV row: {}", e))
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn post_process(&self, sftp: &Sftp, file: &str) -> anyhow::Result<()> {
+        let path = Path::new(&self.table.directory).join(file);
+        match &self.table.post_processing {
+            PostProcessing::None {} => Ok(()),
+            PostProcessing::Delete {} => {
+                info!("Deleting processed file {:?}", path);
+                sftp.unlink(&path)
+                    .map_err(|e| anyhow!("failed to delete {:?}: {}", path, e))
+            }
+            PostProcessing::Move { destination } => {
+                let to: PathBuf = Path::new(destination).join(file);
+                info!("Moving processed file {:?} to {:?}", path, to);
+                sftp.rename(&path, &to, None)
+                    .map_err(|e| anyhow!("failed to move {:?} to {:?}: {}", path, to, e))
+            }
+        }
+    }
+
+    /// Emits the unread records of the file, returning early if a control message asks us to stop
+    async fn process_file(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        sftp: &Sftp,
+        file: &str,
+    ) -> Result<(), SourceFinishType> {
+        info!("Reading {}", file);
+        let contents = match tokio::task::block_in_place(|| self.read_file(sftp, file)) {
+            Ok(contents) => contents,
+            Err(e) => {
+                ctx.report_error(format!("Failed to read {}", file), format!("{:?}", e))
+                    .await;
+                return Ok(());
+            }
+        };
+
+        let records = self.parse_records(file, &contents);
+        let skip = self.files.get(file).map(|s| s.records_read).unwrap_or(0) as usize;
+
+        if let FileFormat::Lines = self.table.file_format {
+            for line in contents
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.iter().all(|b| b.is_ascii_whitespace()))
+                .skip(skip)
+            {
+                ctx.profile_source_record(line);
+            }
+        }
+
+        for (i, record) in records.into_iter().enumerate().skip(skip) {
+            match record {
+                Ok(value) => {
+                    ctx.collector
+                        .collect(Record {
+                            timestamp: SystemTime::now(),
+                            key: None,
+                            value,
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    ctx.report_error(e.name, format!("{} (in {})", e.details, file))
+                        .await;
+                }
+            }
+
+            self.file_state(file).records_read = i as u64 + 1;
+
+            if (i + 1) % RECORDS_PER_CONTROL_CHECK == 0 {
+                match ctx.control_rx.try_recv() {
+                    Ok(msg) => {
+                        if let Some(r) = self.our_handle_control_message(ctx, sftp, Some(msg)).await
+                        {
+                            return Err(r);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => {
+                        return Err(SourceFinishType::Immediate);
+                    }
+                }
+            }
+        }
+
+        self.file_state(file).finished = true;
+        self.pending_post_processing.push(file.to_string());
+        Ok(())
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        let pattern = match &self.table.file_pattern {
+            Some(p) => Some(Regex::new(&format!("^(?:{})$", p)).expect("invalid file pattern")),
+            None => None,
+        };
+
+        let poll_interval = self
+            .table
+            .poll_interval_seconds
+            .map(|s| Duration::from_secs(s as u64))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+        let sftp = match tokio::task::block_in_place(|| self.connect()) {
+            Ok(sftp) => {
+                ctx.report_connected().await;
+                sftp
+            }
+            Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
+                ctx.control_tx
+                    .send(ControlResp::Error {
+                        operator_id: ctx.task_info.operator_id.clone(),
+                        task_index: ctx.task_info.task_index,
+                        message: "Failed to connect to SFTP server".to_string(),
+                        details: format!("{:?}", e),
+                    })
+                    .await
+                    .unwrap();
+                panic!("Failed to connect to SFTP server: {:?}", e);
+            }
+        };
+
+        loop {
+            let files = match tokio::task::block_in_place(|| self.list_files(&sftp, &pattern)) {
+                Ok(files) => files,
+                Err(e) => {
+                    ctx.report_error(
+                        format!("Failed to list {}", self.table.directory),
+                        format!("{:?}", e),
+                    )
+                    .await;
+                    vec![]
+                }
+            };
+
+            for file in files {
+                if !self.owns(ctx, &file) {
+                    continue;
+                }
+
+                match self.files.get(&file) {
+                    Some(state) if state.finished => {
+                        // we may have stopped before post-processing a finished file, which was
+                        // committed by the checkpoint we restored from
+                        if !matches!(self.table.post_processing, PostProcessing::None {})
+                            && !self.pending_post_processing.contains(&file)
+                            && !self.awaiting_commit.contains(&file)
+                        {
+                            self.pending_post_processing.push(file);
+                        }
+                    }
+                    _ => {
+                        if let Err(r) = self.process_file(ctx, &sftp, &file).await {
+                            return r;
+                        }
+                    }
+                }
+            }
+
+            let sleep = tokio::time::sleep(poll_interval);
+            tokio::pin!(sleep);
+            loop {
+                select! {
+                    _ = &mut sleep => break,
+                    control_message = ctx.control_rx.recv() => {
+                        if let Some(r) = self.our_handle_control_message(ctx, &sftp, control_message).await {
+                            return r;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_types::{check_egress, CheckpointBarrier, Data, Key, Record};
+use lazy_static::lazy_static;
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, Mailboxes};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+
+use super::OperatorConfig;
+
+import_types!(schema = "../connector-schemas/smtp/connection.json");
+import_types!(schema = "../connector-schemas/smtp/table.json");
+
+const MAX_ATTEMPTS: u32 = 5;
+
+lazy_static! {
+    static ref TEMPLATE_FIELD: Regex = Regex::new(r"\{\{\s*([A-Za-z0-9_.]+)\s*\}\}").unwrap();
+}
+
+#[derive(StreamNode)]
+pub struct SmtpSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    connection: SmtpConfig,
+    table: SmtpTable,
+    from: Mailbox,
+    to: Mailboxes,
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    // (subject, body) of the records collected for the next digest
+    digest: Vec<(String, String)>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> SmtpSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for SmtpSink");
+        let connection: SmtpConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for SmtpSink");
+        let table: SmtpTable =
+            serde_json::from_value(config.table).expect("Invalid table config for SmtpSink");
+
+        Self {
+            from: connection.from.parse().expect("Invalid from address"),
+            to: table.to.parse().expect("Invalid recipients"),
+            connection,
+            table,
+            transport: None,
+            digest: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    fn build_transport(&self) -> AsyncSmtpTransport<Tokio1Executor> {
+        let host = &self.connection.host;
+        let transport = match self.connection.tls {
+            TlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .expect("Failed to configure SMTP transport"),
+            TlsMode::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .expect("Failed to configure SMTP transport"),
+            TlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+
+        let transport = match self.connection.port {
+            Some(port) => transport.port(port as u16),
+            None => transport,
+        };
+
+        match (&self.connection.username, &self.connection.password) {
+            (Some(username), Some(password)) => transport
+                .credentials(Credentials::new(username.clone(), password.clone()))
+                .build(),
+            _ => transport.build(),
+        }
+    }
+
+    async fn send(&mut self, subject: String, body: String) {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+
+        for to in self.to.iter() {
+            message = message.to(to.clone());
+        }
+
+        let message = message.body(body).expect("Failed to build email");
+
+        let transport = self.transport.as_ref().unwrap();
+        let mut attempt = 0;
+        while let Err(e) = transport.send(message.clone()).await {
+            attempt += 1;
+            if attempt >= MAX_ATTEMPTS || e.is_permanent() {
+                panic!("Failed to send email after {} attempts: {}", attempt, e);
+            }
+
+            warn!("Failed to send email, retrying: {}", e);
+            tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+        }
+    }
+
+    async fn send_digest(&mut self) {
+        if self.digest.is_empty() {
+            return;
+        }
+
+        let digest = std::mem::take(&mut self.digest);
+        let subject = if digest.len() == 1 {
+            digest[0].0.clone()
+        } else {
+            format!("{} (and {} more)", digest[0].0, digest.len() - 1)
+        };
+
+        let body = digest
+            .into_iter()
+            .map(|(_, body)| body)
+            .collect::<Vec<_>>()
+            .join("\n\n----\n\n");
+
+        self.send(subject, body).await;
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> SmtpSinkFunc<K, T> {
+    fn name(&self) -> String {
+        "SmtpSink".to_string()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        if let Err(e) = check_egress(&self.connection.host) {
+            ctx.report_error("Connection not allowed".to_string(), e.clone())
+                .await;
+            panic!("{}", e);
+        }
+
+        self.transport = Some(self.build_transport());
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        let value = match serde_json::to_value(&record.value) {
+            Ok(value) => value,
+            Err(e) => {
+                ctx.report_error("Could not serialize record".to_string(), e.to_string())
+                    .await;
+                return;
+            }
+        };
+
+        // the subject is a header, so it can't span lines
+        let subject = render(&self.table.subject, &value).replace(['\r', '\n'], " ");
+        let body = render(&self.table.body, &value);
+
+        match self.table.digest_size {
+            Some(size) => {
+                self.digest.push((subject, body));
+                if self.digest.len() >= size as usize {
+                    self.send_digest().await;
+                }
+            }
+            None => self.send(subject, body).await,
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.send_digest().await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.send_digest().await;
+    }
+}
+
+/// Replaces `{{field}}` (or `{{field.nested}}`) in the template with the value of that field
+/// of the record; missing and null fields render as empty strings
+fn render(template: &str, value: &Value) -> String {
+    TEMPLATE_FIELD
+        .replace_all(template, |c: &Captures| {
+            match c[1].split('.').try_fold(value, |v, f| v.get(f)) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::render;
+
+    #[test]
+    fn test_render() {
+        let value = json!({
+            "service": "api",
+            "errors": 17,
+            "region": null,
+            "host": {"name": "api-1"},
+        });
+
+        assert_eq!(
+            "api had 17 errors on api-1 in ",
+            render(
+                "{{service}} had {{ errors }} errors on {{host.name}} in {{region}}{{missing}}",
+                &value
+            )
+        );
+    }
+}
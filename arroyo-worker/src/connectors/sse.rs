@@ -1,13 +1,17 @@
 use crate::engine::Context;
-use crate::operators::SerializationMode;
+use crate::formats::csv::CsvOptions;
+use crate::operators::{BadData, SerializationMode, UserError};
 use crate::SourceFinishType;
 use arroyo_macro::{source_fn, StreamNode};
 use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::proxy::{self, ProxyConfig};
+use arroyo_rpc::tls;
 use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_server_common::http::{CircuitBreaker, RetryPolicy};
 use arroyo_state::tables::GlobalKeyedState;
-use arroyo_types::{string_to_map, Data, Record};
+use arroyo_types::{check_egress, string_to_map, Data, Record};
 use bincode::{Decode, Encode};
-use eventsource_client::{Client, SSE};
+use eventsource_client::{Client, ReconnectOptions, SSE};
 use futures::StreamExt;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
@@ -15,13 +19,15 @@ use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::time::{Duration, Instant, SystemTime};
 use tokio::select;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use typify::import_types;
 
 use super::{OperatorConfig, OperatorConfigSerializationMode};
 
 import_types!(schema = "../connector-schemas/sse/table.json");
 
+const MAX_CONSECUTIVE_ERRORS: u32 = 5;
+
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
 pub struct SSESourceState {
     last_id: Option<String>,
@@ -36,7 +42,11 @@ where
     url: String,
     headers: Vec<(String, String)>,
     events: Vec<String>,
+    tls: Option<tls::TlsConfig>,
+    proxy: Option<ProxyConfig>,
     serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
     state: SSESourceState,
     _t: PhantomData<(K, T)>,
 }
@@ -60,7 +70,11 @@ where
                 .map(|(k, v)| (k.to_string(), v.to_string()))
                 .collect(),
             events: events.into_iter().map(|s| s.to_string()).collect(),
+            tls: None,
+            proxy: ProxyConfig::resolve(None),
             serialization_mode,
+            format_error: None,
+            bad_data: BadData::default(),
             state: SSESourceState::default(),
             _t: PhantomData,
         }
@@ -72,6 +86,28 @@ where
         let table: SseTable =
             serde_json::from_value(config.table).expect("Invalid table config for SSESource");
 
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => {
+                Ok(SerializationMode::JsonSchemaRegistry)
+            }
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::DebeziumJson => todo!(),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "SSE"))
+            }
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "SSE"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "SSE"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
         Self {
             url: table.endpoint,
             headers: string_to_map(table.headers.as_ref().map(|t| t.0.as_str()).unwrap_or(""))
@@ -82,17 +118,13 @@ where
                 .events
                 .map(|e| e.split(',').map(|e| e.to_string()).collect())
                 .unwrap_or_else(std::vec::Vec::new),
-            serialization_mode: match config.serialization_mode.unwrap() {
-                OperatorConfigSerializationMode::Json => SerializationMode::Json,
-                OperatorConfigSerializationMode::JsonSchemaRegistry => {
-                    SerializationMode::JsonSchemaRegistry
-                }
-                OperatorConfigSerializationMode::RawJson => SerializationMode::RawJson,
-                OperatorConfigSerializationMode::DebeziumJson => todo!(),
-                OperatorConfigSerializationMode::Parquet => {
-                    unimplemented!("parquet out of SSE source doesn't make sense")
-                }
-            },
+            tls: table.tls.as_ref().map(tls::TlsConfig::from_generated),
+            proxy: ProxyConfig::resolve(table.proxy.as_ref().map(ProxyConfig::from_generated)),
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
             state: SSESourceState::default(),
             _t: PhantomData,
         }
@@ -141,16 +173,41 @@ where
                     StopMode::Immediate => {
                         return Some(SourceFinishType::Immediate);
                     }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
                 }
             }
             ControlMessage::Commit { epoch: _ } => {
                 unreachable!("sources shouldn't receive commit messages");
             }
+            ControlMessage::SetPaused { .. } => {
+                warn!("watermark alignment is not supported by the SSE source");
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
         }
         None
     }
 
     async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        let host = url::Url::parse(&self.url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+        if let Err(e) = check_egress(&host) {
+            ctx.report_disconnected(e.clone()).await;
+            ctx.report_error("Connection not allowed".to_string(), e.clone())
+                .await;
+            panic!("{}", e);
+        }
+
         let mut client = eventsource_client::ClientBuilder::for_url(&self.url).unwrap();
 
         if let Some(id) = &self.state.last_id {
@@ -161,7 +218,28 @@ where
             client = client.header(k, v).unwrap();
         }
 
-        let mut stream = client.build().stream();
+        // the client reconnects on its own after errors; the breaker fails the task (so that the
+        // job is restarted) only once the endpoint has failed repeatedly
+        let retry = RetryPolicy::default();
+        client = client.reconnect(
+            ReconnectOptions::reconnect(true)
+                .delay(retry.initial_backoff)
+                .delay_max(retry.max_backoff)
+                .backoff_factor(2)
+                .build(),
+        );
+        let breaker = CircuitBreaker::new(MAX_CONSECUTIVE_ERRORS, retry.max_backoff);
+
+        let mut stream = if self.tls.is_some() || self.proxy.is_some() {
+            client
+                .build_with_conn(
+                    proxy::https_connector(self.proxy.as_ref(), self.tls.as_ref())
+                        .expect("Invalid TLS or proxy config"),
+                )
+                .stream()
+        } else {
+            client.build().stream()
+        };
         let events: HashSet<_> = self.events.iter().cloned().collect();
 
         let mut last_reported_error = Instant::now();
@@ -169,11 +247,18 @@ where
 
         // since there's no way to partition across an event source, only read on the first task
         if ctx.task_info.task_index == 0 {
+            // the client connects lazily, so the source is connected once it receives a message
+            let mut connected = false;
             loop {
                 select! {
                     message = stream.next()  => {
                         match message {
                             Some(Ok(msg)) => {
+                                if !connected {
+                                    ctx.report_connected().await;
+                                    connected = true;
+                                    breaker.record_success();
+                                }
                                 match msg {
                                     SSE::Event(event) => {
                                         if let Some(id) = event.id {
@@ -181,7 +266,8 @@ where
                                         }
 
                                         if events.is_empty() || events.contains(&event.event_type) {
-                                            match self.serialization_mode.deserialize_str(&event.data) {
+                                            ctx.profile_source_record(event.data.as_bytes());
+                                            match self.serialization_mode.deserialize_str(&event.data, self.bad_data) {
                                                 Ok(value) => {
                                                     ctx.collector.collect(Record {
                                                         timestamp: SystemTime::now(),
@@ -213,6 +299,13 @@ where
                                 }
                             }
                             Some(Err(e)) => {
+                                ctx.report_disconnected(format!("{:?}", e)).await;
+                                connected = false;
+                                if !breaker.record_failure() {
+                                    warn!("Error while reading from EventSource, reconnecting: {:?}", e);
+                                    continue;
+                                }
+
                                 ctx.control_tx.send(
                                     ControlResp::Error {
                                         operator_id: ctx.task_info.operator_id.clone(),
@@ -186,6 +186,8 @@ impl<K: Key, T: Data + Sync, TPC: TwoPhaseCommitter<K, T>> TwoPhaseCommitterOper
         match control_message {
             arroyo_rpc::ControlMessage::Checkpoint(_) => warn!("shouldn't receive checkpoint"),
             arroyo_rpc::ControlMessage::Stop { mode: _ } => warn!("shouldn't receive stop"),
+            arroyo_rpc::ControlMessage::SetPaused { .. } => warn!("shouldn't receive pause"),
+            arroyo_rpc::ControlMessage::InjectProbe { .. } => warn!("shouldn't receive probe"),
             arroyo_rpc::ControlMessage::Commit { epoch } => {
                 self.handle_commit(epoch, ctx).await;
             }
@@ -0,0 +1,336 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_server_common::http::{HttpClient, RetryPolicy};
+use arroyo_types::{check_egress, string_to_map, CheckpointBarrier, Data, Key, Record};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+
+use super::batching::{BatchPolicy, Batcher, FlushCause};
+use super::OperatorConfig;
+
+import_types!(schema = "../connector-schemas/webhook/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// A request that couldn't be delivered
+#[derive(Debug)]
+struct DeliveryError {
+    status: Option<u16>,
+    message: String,
+    // whether the request failed with an error that retrying may resolve (a connection error or
+    // a 5xx or 429 response), as opposed to being rejected by the endpoint
+    retryable: bool,
+}
+
+#[derive(StreamNode)]
+pub struct WebhookSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+    endpoint: String,
+    dead_letter_endpoint: Option<String>,
+    headers: HeaderMap,
+    body_format: BodyFormat,
+    batcher: Batcher,
+    max_concurrency: usize,
+    client: HttpClient,
+    dead_letter_client: HttpClient,
+    pending: Vec<Value>,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: Data + Serialize> WebhookSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for WebhookSink");
+        let table: WebhookTable =
+            serde_json::from_value(config.table).expect("Invalid table config for WebhookSink");
+
+        let mut headers: HeaderMap =
+            string_to_map(table.headers.as_ref().map(|h| h.0.as_str()).unwrap_or(""))
+                .expect("Invalid header map")
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        HeaderName::try_from(k.as_str()).expect("Invalid header name"),
+                        HeaderValue::try_from(v.as_str()).expect("Invalid header value"),
+                    )
+                })
+                .collect();
+        headers
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static("application/json"));
+
+        let timeout = table
+            .timeout_ms
+            .map(|t| Duration::from_millis(t as u64))
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let retry = RetryPolicy {
+            max_attempts: table
+                .max_attempts
+                .map(|a| a as u32)
+                .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        };
+
+        // the endpoints get their own clients, so that failures of one don't open the circuit
+        // breaker of the other
+        let client = |name: &str| {
+            HttpClient::with_client(
+                name,
+                reqwest::Client::builder().timeout(timeout).build().unwrap(),
+            )
+            .retry_policy(retry.clone())
+        };
+
+        Self {
+            endpoint: table.endpoint,
+            dead_letter_endpoint: table.dead_letter_endpoint,
+            headers,
+            body_format: table.body_format.unwrap_or(BodyFormat::Single),
+            batcher: Batcher::new(BatchPolicy::from_config(
+                config.batching,
+                table
+                    .batch_size
+                    .map(|s| s as usize)
+                    .unwrap_or(DEFAULT_BATCH_SIZE),
+            )),
+            max_concurrency: table
+                .max_concurrency
+                .map(|c| c as usize)
+                .unwrap_or(DEFAULT_CONCURRENCY),
+            client: client("webhook_sink"),
+            dead_letter_client: client("webhook_sink_dead_letter"),
+            pending: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    /// Sends all pending records, dead-lettering or reporting those that can't be delivered
+    async fn flush(&mut self, cause: FlushCause, ctx: &mut Context<(), ()>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.batcher.flushed(cause);
+
+        let bodies = request_bodies(&self.body_format, std::mem::take(&mut self.pending));
+        let (client, endpoint, headers) = (&self.client, &self.endpoint, &self.headers);
+
+        let failures: Vec<(Value, DeliveryError)> = futures::stream::iter(bodies)
+            .map(|body| async move {
+                post(client, endpoint, headers, &body)
+                    .await
+                    .err()
+                    .map(|e| (body, e))
+            })
+            .buffer_unordered(self.max_concurrency)
+            .filter_map(|r| async move { r })
+            .collect()
+            .await;
+
+        for (body, error) in failures {
+            self.handle_failure(body, error, ctx).await;
+        }
+    }
+
+    async fn handle_failure(&self, body: Value, error: DeliveryError, ctx: &mut Context<(), ()>) {
+        if let Some(dead_letter_endpoint) = &self.dead_letter_endpoint {
+            warn!(
+                "Request to {} failed, sending it to the dead letter endpoint: {}",
+                self.endpoint, error.message
+            );
+
+            let dead_letter = dead_letter_body(&self.endpoint, &error, body);
+            if let Err(e) = post(
+                &self.dead_letter_client,
+                dead_letter_endpoint,
+                &self.headers,
+                &dead_letter,
+            )
+            .await
+            {
+                panic!(
+                    "Request to {} failed ({}), and could not be sent to the dead letter endpoint {}: {}",
+                    self.endpoint, error.message, dead_letter_endpoint, e.message
+                );
+            }
+            return;
+        }
+
+        if error.retryable {
+            // the pipeline is restarted from its last checkpoint, which resends the records
+            panic!(
+                "Request to {} failed after retrying: {}",
+                self.endpoint, error.message
+            );
+        }
+
+        ctx.report_error(
+            format!("Request to {} was rejected", self.endpoint),
+            error.message,
+        )
+        .await;
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: Data + Serialize> WebhookSinkFunc<K, T> {
+    fn name(&self) -> String {
+        "webhook-sink".to_string()
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batcher.tick_interval()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        self.batcher.register_metrics(&ctx.task_info);
+
+        for endpoint in std::iter::once(&self.endpoint).chain(&self.dead_letter_endpoint) {
+            let host = url::Url::parse(endpoint)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_string()))
+                .unwrap_or_default();
+            if let Err(e) = check_egress(&host) {
+                ctx.report_error("Connection not allowed".to_string(), e.clone())
+                    .await;
+                panic!("{}", e);
+            }
+        }
+
+        info!("Sending records to webhook at {}", self.endpoint);
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        ctx.sample_output(record);
+
+        match serde_json::to_value(&record.value) {
+            Ok(value) => {
+                self.pending.push(value);
+                if let Some(cause) = self.batcher.add(&record.value) {
+                    self.flush(cause, ctx).await;
+                }
+            }
+            Err(e) => {
+                ctx.report_error(
+                    "Could not serialize record to JSON".to_string(),
+                    e.to_string(),
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, ctx: &mut Context<(), ()>) {
+        if self.batcher.linger_expired() {
+            self.flush(FlushCause::Linger, ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(FlushCause::Checkpoint, ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(FlushCause::Close, ctx).await;
+    }
+}
+
+/// The bodies of the requests to send for a batch of records: one per record in the single format,
+/// or a single array of all of them
+fn request_bodies(format: &BodyFormat, records: Vec<Value>) -> Vec<Value> {
+    match format {
+        BodyFormat::Single => records,
+        BodyFormat::Array => vec![Value::Array(records)],
+    }
+}
+
+/// Wraps a request that couldn't be delivered with the details of the failure
+fn dead_letter_body(endpoint: &str, error: &DeliveryError, body: Value) -> Value {
+    json!({
+        "endpoint": endpoint,
+        "status": error.status,
+        "error": error.message,
+        "body": body,
+    })
+}
+
+/// POSTs `body` as JSON, retrying failures according to the client's retry policy
+async fn post(
+    client: &HttpClient,
+    url: &str,
+    headers: &HeaderMap,
+    body: &Value,
+) -> Result<(), DeliveryError> {
+    let request = client
+        .post(url)
+        .headers(headers.clone())
+        .body(serde_json::to_vec(body).unwrap());
+
+    match client.send(request).await {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            Err(DeliveryError {
+                status: Some(status.as_u16()),
+                message: format!("{}: {}", status, text),
+                retryable: status.is_server_error() || status.as_u16() == 429,
+            })
+        }
+        Err(e) => Err(DeliveryError {
+            status: None,
+            message: e.to_string(),
+            retryable: true,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{dead_letter_body, request_bodies, BodyFormat, DeliveryError};
+
+    #[test]
+    fn test_request_bodies() {
+        let records = vec![json!({"id": 1}), json!({"id": 2})];
+
+        assert_eq!(
+            request_bodies(&BodyFormat::Single, records.clone()),
+            records.clone()
+        );
+        assert_eq!(
+            request_bodies(&BodyFormat::Array, records),
+            vec![json!([{"id": 1}, {"id": 2}])]
+        );
+    }
+
+    #[test]
+    fn test_dead_letter_body() {
+        let error = DeliveryError {
+            status: Some(400),
+            message: "400 Bad Request: missing field".to_string(),
+            retryable: false,
+        };
+
+        assert_eq!(
+            dead_letter_body("http://localhost/ingest", &error, json!({"id": 1})),
+            json!({
+                "endpoint": "http://localhost/ingest",
+                "status": 400,
+                "error": "400 Bad Request: missing field",
+                "body": {"id": 1},
+            })
+        );
+    }
+}
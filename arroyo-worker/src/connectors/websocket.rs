@@ -6,7 +6,8 @@ use std::{
 use arroyo_macro::source_fn;
 use arroyo_rpc::{
     grpc::{StopMode, TableDescriptor},
-    ControlMessage,
+    proxy::{self, ProxyConfig},
+    tls, ControlMessage,
 };
 use arroyo_state::tables::GlobalKeyedState;
 use arroyo_types::{Data, Record};
@@ -15,13 +16,14 @@ use futures::{SinkExt, StreamExt};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::select;
-use tokio_tungstenite::{connect_async, tungstenite};
-use tracing::{debug, info};
+use tokio_tungstenite::{client_async_tls_with_config, tungstenite, Connector};
+use tracing::{debug, info, warn};
 use typify::import_types;
 
 use crate::{
     engine::{Context, StreamNode},
-    operators::{SerializationMode, UserError},
+    formats::csv::CsvOptions,
+    operators::{BadData, SerializationMode, UserError},
     SourceFinishType,
 };
 
@@ -40,7 +42,11 @@ where
 {
     url: String,
     subscription_message: Option<String>,
+    tls: Option<tls::TlsConfig>,
+    proxy: Option<ProxyConfig>,
     serialization_mode: SerializationMode,
+    format_error: Option<UserError>,
+    bad_data: BadData,
     state: WebsocketSourceState,
     _t: PhantomData<(K, T)>,
 }
@@ -57,20 +63,38 @@ where
         let table: WebsocketTable =
             serde_json::from_value(config.table).expect("Invalid table config for WebsocketSource");
 
+        let serialization_mode = match config.serialization_mode.unwrap() {
+            OperatorConfigSerializationMode::Json
+            | OperatorConfigSerializationMode::DebeziumJson => Ok(SerializationMode::Json),
+            OperatorConfigSerializationMode::JsonSchemaRegistry => {
+                Ok(SerializationMode::JsonSchemaRegistry)
+            }
+            OperatorConfigSerializationMode::RawJson => Ok(SerializationMode::RawJson),
+            OperatorConfigSerializationMode::RawBytes => Ok(SerializationMode::RawBytes),
+            OperatorConfigSerializationMode::Parquet => {
+                Err(UserError::unsupported_format("parquet", "websocket"))
+            }
+            OperatorConfigSerializationMode::Avro => {
+                Err(UserError::unsupported_format("avro", "websocket"))
+            }
+            OperatorConfigSerializationMode::Protobuf => {
+                Err(UserError::unsupported_format("protobuf", "websocket"))
+            }
+            OperatorConfigSerializationMode::Csv => Ok(SerializationMode::Csv(
+                CsvOptions::from_config(config.csv.as_ref()),
+            )),
+        };
+
         Self {
             url: table.endpoint,
             subscription_message: table.subscription_message.map(|s| s.into()),
-            serialization_mode: match config.serialization_mode.unwrap() {
-                OperatorConfigSerializationMode::Json
-                | OperatorConfigSerializationMode::DebeziumJson => SerializationMode::Json,
-                OperatorConfigSerializationMode::JsonSchemaRegistry => {
-                    SerializationMode::JsonSchemaRegistry
-                }
-                OperatorConfigSerializationMode::RawJson => SerializationMode::RawJson,
-                OperatorConfigSerializationMode::Parquet => {
-                    unimplemented!("parquet out of websocket source doesn't make sense")
-                }
-            },
+            tls: table.tls.as_ref().map(tls::TlsConfig::from_generated),
+            proxy: ProxyConfig::resolve(table.proxy.as_ref().map(ProxyConfig::from_generated)),
+            serialization_mode: *serialization_mode
+                .as_ref()
+                .unwrap_or(&SerializationMode::Json),
+            format_error: serialization_mode.err(),
+            bad_data: config.bad_data.into(),
             state: WebsocketSourceState::default(),
             _t: PhantomData,
         }
@@ -119,19 +143,49 @@ where
                     StopMode::Immediate => {
                         return Some(SourceFinishType::Immediate);
                     }
+                    StopMode::Drain => {
+                        return Some(SourceFinishType::Final);
+                    }
                 }
             }
             ControlMessage::Commit { epoch: _ } => {
                 unreachable!("sources shouldn't receive commit messages");
             }
+            ControlMessage::SetPaused { .. } => {
+                warn!("watermark alignment is not supported by the websocket source");
+            }
+            ControlMessage::InjectProbe { probe_id } => {
+                ctx.handle_probe(probe_id).await;
+            }
         }
         None
     }
 
     async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
-        let ws_stream = match connect_async(&self.url).await {
-            Ok((ws_stream, _)) => ws_stream,
+        if let Some(e) = &self.format_error {
+            ctx.report_error(e.name.clone(), e.details.clone()).await;
+            return SourceFinishType::Final;
+        }
+
+        let connector = self
+            .tls
+            .as_ref()
+            .map(|t| Connector::NativeTls(t.native_tls_connector().expect("Invalid TLS config")));
+
+        let result = match proxy::tcp_connect(&self.url, self.proxy.as_ref()).await {
+            Ok(stream) => {
+                client_async_tls_with_config(self.url.as_str(), stream, None, connector).await
+            }
+            Err(e) => Err(tungstenite::Error::Io(e)),
+        };
+
+        let ws_stream = match result {
+            Ok((ws_stream, _)) => {
+                ctx.report_connected().await;
+                ws_stream
+            }
             Err(e) => {
+                ctx.report_disconnected(e.to_string()).await;
                 ctx.report_error(
                     "Failed to connect to websocket server".to_string(),
                     e.to_string(),
@@ -169,10 +223,12 @@ where
                             Some(Ok(msg)) => {
                                 let data = match msg {
                                     tungstenite::Message::Text(t) => {
-                                        self.serialization_mode.deserialize_str(&t).map(|t| Some(t))
+                                        ctx.profile_source_record(t.as_bytes());
+                                        self.serialization_mode.deserialize_str(&t, self.bad_data).map(|t| Some(t))
                                     },
                                     tungstenite::Message::Binary(bs) => {
-                                        self.serialization_mode.deserialize_slice(&bs).map(|t| Some(t))
+                                        ctx.profile_source_record(&bs);
+                                        self.serialization_mode.deserialize_slice(&bs, self.bad_data).map(|t| Some(t))
                                     },
                                     tungstenite::Message::Ping(d) => {
                                         tx.send(tungstenite::Message::Pong(d)).await
@@ -184,6 +240,7 @@ where
                                         Ok(None)
                                     },
                                     tungstenite::Message::Close(_) => {
+                                        ctx.report_disconnected("Received close frame from server".to_string()).await;
                                         ctx.report_error("Received close frame from server".to_string(), "".to_string()).await;
                                         return SourceFinishType::Final;
                                     },
@@ -214,6 +271,7 @@ where
                                 };
                             }
                         Some(Err(e)) => {
+                            ctx.report_disconnected(e.to_string()).await;
                             ctx.report_error("Error while reading from websocket".to_string(), format!("{:?}", e)).await;
                             panic!("Error while reading from websocket: {:?}", e);
                         }
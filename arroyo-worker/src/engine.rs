@@ -10,7 +10,6 @@ use std::{mem, thread};
 
 use std::time::{Duration, SystemTime};
 
-use arroyo_metrics::{counter_for_task, gauge_for_task};
 use arroyo_state::tables::TimeKeyMap;
 use bincode::{config, Decode, Encode};
 
@@ -19,32 +18,39 @@ use tracing::{debug, error, info, warn};
 pub use arroyo_macro::StreamNode;
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::{
-    CheckpointMetadata, HeartbeatReq, TableDeleteBehavior, TableDescriptor, TableType,
-    TableWriteBehavior, TaskAssignment, TaskCheckpointCompletedReq, TaskCheckpointEventReq,
-    TaskFailedReq, TaskFinishedReq, TaskStartedReq, WorkerErrorReq,
+    CheckpointMetadata, ConnectorHealthReq, HeartbeatReq, TableDeleteBehavior, TableDescriptor,
+    TableType, TableWriteBehavior, TaskAssignment, TaskCheckpointCompletedReq,
+    TaskCheckpointEventReq, TaskFailedReq, TaskFinishedReq, TaskProbeReq, TaskStartedReq,
+    TaskWatermarkReq, WorkerErrorReq,
 };
 use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_server_common::tls;
 use arroyo_types::{
-    from_micros, to_micros, CheckpointBarrier, Data, Key, Message, Record, TaskInfo, WorkerId,
-    BYTES_RECV, BYTES_SENT, MESSAGES_RECV, MESSAGES_SENT,
+    from_micros, skip_failing_records, to_micros, to_millis, CheckpointBarrier, Data, Key, Message,
+    QueueConfig, Record, TaskInfo, WorkerId,
 };
 use petgraph::graph::DiGraph;
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
-use prometheus::{labels, IntCounter, IntGauge};
+use prometheus::{labels, IntCounter};
 use rand::Rng;
+use serde::Serialize;
 use tokio::select;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::task::JoinHandle;
 use tonic::Request;
 
+use crate::column_stats::{self, ColumnProfiler};
+use crate::metrics::{
+    record_state_bytes, task_progress, track_progress, OutputMetrics, TaskMetrics,
+};
 use crate::network_manager::{NetworkManager, Quad, Senders};
-use crate::TIMER_TABLE;
+use crate::output_samples::OutputSampler;
+use crate::udf_cache::UdfCaches;
 use crate::{LogicalEdge, LogicalNode, METRICS_PUSH_INTERVAL, PROMETHEUS_PUSH_GATEWAY};
+use crate::{TIMER_TABLE, UDF_CACHE_TABLE};
 use arroyo_state::{hash_key, BackingStore, StateBackend, StateStore};
 
-const QUEUE_SIZE: usize = 4 * 1024;
-
 #[derive(Debug)]
 pub enum QueueItem {
     Data(Box<dyn Any + Send>),
@@ -75,8 +81,28 @@ fn range_for_server(i: usize, n: usize) -> RangeInclusive<u64> {
 }
 
 fn server_for_hash(x: u64, n: usize) -> usize {
-    let range_size = u64::MAX / (n as u64);
-    (n - 1).min((x / range_size) as usize)
+    Router::new(n).route(x)
+}
+
+/// Routes records to the subtasks of a downstream operator by the hashes of their keys, which
+/// are split into equal ranges (see [`range_for_server`])
+#[derive(Clone, Debug)]
+pub struct Router {
+    parallelism: usize,
+    range_size: u64,
+}
+
+impl Router {
+    pub fn new(parallelism: usize) -> Self {
+        Self {
+            parallelism,
+            range_size: u64::MAX / (parallelism.max(1) as u64),
+        }
+    }
+
+    pub fn route(&self, hash: u64) -> usize {
+        (self.parallelism.max(1) - 1).min((hash / self.range_size) as usize)
+    }
 }
 
 #[cfg(test)]
@@ -115,6 +141,71 @@ mod tests {
             "u64::MAX is not in the correct range"
         );
     }
+
+    fn collector(parallelisms: &[usize]) -> (Collector<u64, u64>, Vec<Vec<Receiver<QueueItem>>>) {
+        let (out_qs, rxs): (Vec<_>, Vec<_>) = parallelisms
+            .iter()
+            .map(|n| {
+                (0..*n)
+                    .map(|_| {
+                        let (tx, rx) = channel(1024);
+                        (OutQueue::new(tx, false), rx)
+                    })
+                    .unzip::<_, _, Vec<_>, Vec<_>>()
+            })
+            .unzip();
+
+        let metrics = OutputMetrics::new(&TaskInfo::for_test("job", "collector"), &out_qs);
+        (Collector::new(out_qs, metrics), rxs)
+    }
+
+    #[test]
+    fn test_collector_routing() {
+        let (mut collector, mut rxs) = collector(&[3, 1, 4]);
+
+        for key in 0..100u64 {
+            futures::executor::block_on(collector.collect(Record {
+                timestamp: SystemTime::UNIX_EPOCH,
+                key: Some(key),
+                value: key,
+            }));
+
+            let hash = hash_key(&key);
+            for (node, qs) in rxs.iter_mut().enumerate() {
+                let expected = server_for_hash(hash, qs.len());
+                for (idx, rx) in qs.iter_mut().enumerate() {
+                    let received = rx.try_recv().ok().map(Message::<u64, u64>::from);
+                    if idx == expected {
+                        assert!(
+                            matches!(received, Some(Message::Record(r)) if r.key == Some(key)),
+                            "key {} was not routed to {}-{}",
+                            key,
+                            node,
+                            idx
+                        );
+                    } else {
+                        assert!(
+                            received.is_none(),
+                            "key {} was routed to {}-{}",
+                            key,
+                            node,
+                            idx
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_hash_cache() {
+        let (mut collector, _rxs) = collector(&[2]);
+
+        assert_eq!(collector.key_hash(&Some(5)), hash_key(&5u64));
+        assert_eq!(collector.key_hash(&Some(5)), hash_key(&5u64));
+        assert_eq!(collector.key_hash(&Some(6)), hash_key(&6u64));
+        assert_eq!(collector.last_key, Some((6, hash_key(&6u64))));
+    }
 }
 
 pub trait StreamNode: Send {
@@ -137,7 +228,19 @@ pub struct Context<K: Key, T: Data, S: BackingStore = StateBackend> {
     pub watermarks: Vec<Option<SystemTime>>,
     pub state: StateStore<S>,
     pub collector: Collector<K, T>,
-    pub counters: HashMap<&'static str, IntCounter>,
+    pub metrics: TaskMetrics,
+    // set by the controller when this operator repeatedly failed on the same input; records that
+    // cause the operator to panic are reported and skipped rather than failing the task
+    pub skip_failing_records: bool,
+    // recent records written by a sink, created when the sink first samples its output
+    output_sampler: Option<OutputSampler>,
+    // column stats of the records read by a source or written by a sink, created when the first
+    // record is profiled
+    column_profiler: Option<ColumnProfiler>,
+    // the id of the most recent watermark probe this subtask has seen
+    last_probe: u64,
+    // the entries of the caches used by UDFs that this subtask runs
+    pub udf_caches: UdfCaches,
     _ts: PhantomData<(K, T)>,
 }
 
@@ -178,58 +281,86 @@ impl OutQueue {
 #[derive(Clone)]
 pub struct Collector<K: Key, T: Data> {
     out_qs: Vec<Vec<OutQueue>>,
+    // one per downstream operator, in the same order as out_qs
+    routers: Vec<Router>,
+    // the most recently routed key and its hash; operators often emit runs of records with the
+    // same key (like the results of a window), which can then skip hashing it again
+    last_key: Option<(K, u64)>,
     _ts: PhantomData<(K, T)>,
-    sent_bytes: Option<IntCounter>,
-    sent_messages: Option<IntCounter>,
-    tx_queue_rem_gauges: Vec<Vec<Option<IntGauge>>>,
-    tx_queue_size_gauges: Vec<Vec<Option<IntGauge>>>,
+    metrics: OutputMetrics,
 }
 
 impl<K: Key, T: Data> Collector<K, T> {
-    pub async fn collect(&mut self, record: Record<K, T>) {
-        fn out_idx<K: Key>(key: &Option<K>, qs: usize) -> usize {
-            let hash = if let Some(key) = &key {
-                hash_key(key)
-            } else {
-                // TODO: do we want this be random or deterministic?
-                rand::thread_rng().gen()
-            };
+    pub fn new(out_qs: Vec<Vec<OutQueue>>, metrics: OutputMetrics) -> Self {
+        Self {
+            routers: out_qs.iter().map(|qs| Router::new(qs.len())).collect(),
+            out_qs,
+            last_key: None,
+            _ts: PhantomData,
+            metrics,
+        }
+    }
+
+    /// The hash that the record's key is routed by. It isn't carried in the `Record` to the next
+    /// operator: records are re-keyed before each shuffle, so a downstream operator routes by a
+    /// different key than the one hashed here, and the hash would be serialized with every record
+    /// sent between workers, keyed or not.
+    fn key_hash(&mut self, key: &Option<K>) -> u64 {
+        let Some(key) = key else {
+            // TODO: do we want this be random or deterministic?
+            return rand::thread_rng().gen();
+        };
+
+        match &mut self.last_key {
+            Some((last, hash)) if *last == *key => *hash,
+            Some((last, hash)) => {
+                // reuses the last key's allocations, where the key type supports it
+                last.clone_from(key);
+                *hash = hash_key(key);
+                *hash
+            }
+            None => {
+                let hash = hash_key(key);
+                self.last_key = Some((key.clone(), hash));
+                hash
+            }
+        }
+    }
 
-            server_for_hash(hash, qs)
+    pub async fn collect(&mut self, record: Record<K, T>) {
+        if let Some(c) = &self.metrics.messages_sent {
+            c.inc();
         }
 
-        self.sent_messages.iter().for_each(|c| c.inc());
+        // the hash is computed once for all downstream operators, and not at all if every edge
+        // is a forward edge with a single queue
+        let hash = if self.routers.iter().any(|r| r.parallelism > 1) {
+            self.key_hash(&record.key)
+        } else {
+            0
+        };
 
         if self.out_qs.len() == 1 {
-            let idx = out_idx(&record.key, self.out_qs[0].len());
-
-            self.tx_queue_rem_gauges[0][idx]
-                .iter()
-                .for_each(|g| g.set(self.out_qs[0][idx].tx.capacity() as i64));
+            let idx = self.routers[0].route(hash);
 
-            self.tx_queue_size_gauges[0][idx]
-                .iter()
-                .for_each(|g| g.set(QUEUE_SIZE as i64));
+            let tx = &self.out_qs[0][idx].tx;
+            self.metrics
+                .update_queue(0, idx, tx.capacity(), tx.max_capacity());
 
             self.out_qs[0][idx]
-                .send(Message::Record(record), &self.sent_bytes)
+                .send(Message::Record(record), &self.metrics.bytes_sent)
                 .await;
         } else {
-            let key = record.key.clone();
             let message = Message::Record(record);
 
-            for (i, out_node_qs) in self.out_qs.iter().enumerate() {
-                let idx = out_idx(&key, out_node_qs.len());
-                self.tx_queue_rem_gauges[i][idx]
-                    .iter()
-                    .for_each(|c| c.set(self.out_qs[i][idx].tx.capacity() as i64));
-
-                self.tx_queue_size_gauges[i][idx]
-                    .iter()
-                    .for_each(|c| c.set(QUEUE_SIZE as i64));
+            for (i, (out_node_qs, router)) in self.out_qs.iter().zip(&self.routers).enumerate() {
+                let idx = router.route(hash);
+                let tx = &out_node_qs[idx].tx;
+                self.metrics
+                    .update_queue(i, idx, tx.capacity(), tx.max_capacity());
 
                 out_node_qs[idx]
-                    .send(message.clone(), &self.sent_bytes)
+                    .send(message.clone(), &self.metrics.bytes_sent)
                     .await;
             }
         }
@@ -238,7 +369,7 @@ impl<K: Key, T: Data> Collector<K, T> {
     pub async fn broadcast(&mut self, message: Message<K, T>) {
         for out_node in &self.out_qs {
             for q in out_node {
-                q.send(message.clone(), &self.sent_bytes).await;
+                q.send(message.clone(), &self.metrics.bytes_sent).await;
             }
         }
     }
@@ -262,8 +393,12 @@ impl<K: Key, T: Data> Context<K, T> {
             write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
             retention_micros: 0,
         });
+        tables.push(arroyo_state::global_table(
+            UDF_CACHE_TABLE.to_string(),
+            "UDF cache state",
+        ));
 
-        let (state, watermark) = if let Some(metadata) = restore_from {
+        let (mut state, watermark) = if let Some(metadata) = restore_from {
             let watermark = {
                 let metadata = StateBackend::load_operator_metadata(
                     &task_info.job_id,
@@ -292,101 +427,25 @@ impl<K: Key, T: Data> Context<K, T> {
             )
         };
 
-        let mut counters = HashMap::new();
-
-        if let Some(c) = counter_for_task(
-            &task_info,
-            MESSAGES_RECV,
-            "Count of messages received by this subtask",
-            HashMap::new(),
-        ) {
-            counters.insert(MESSAGES_RECV, c);
-        }
-
-        if let Some(c) = counter_for_task(
-            &task_info,
-            MESSAGES_SENT,
-            "Count of messages sent by this subtask",
-            HashMap::new(),
-        ) {
-            counters.insert(MESSAGES_SENT, c);
-        }
-
-        if let Some(c) = counter_for_task(
-            &task_info,
-            BYTES_RECV,
-            "Count of bytes received by this subtask",
-            HashMap::new(),
-        ) {
-            counters.insert(BYTES_RECV, c);
-        }
-
-        if let Some(c) = counter_for_task(
-            &task_info,
-            BYTES_SENT,
-            "Count of bytes sent by this subtask",
-            HashMap::new(),
-        ) {
-            counters.insert(BYTES_SENT, c);
-        }
+        let udf_caches = UdfCaches::restore(&mut state, task_info.task_index).await;
 
-        let tx_queue_size_gauges = out_qs
-            .iter()
-            .enumerate()
-            .map(|(i, qs)| {
-                qs.iter()
-                    .enumerate()
-                    .map(|(j, _)| {
-                        gauge_for_task(
-                            &task_info,
-                            "arroyo_worker_tx_queue_size",
-                            "Size of a tx queue",
-                            labels! {
-                                "next_node".to_string() => format!("{}", i),
-                                "next_node_idx".to_string() => format!("{}", j)
-                            },
-                        )
-                    })
-                    .collect()
-            })
-            .collect();
-
-        let tx_queue_rem_gauges = out_qs
-            .iter()
-            .enumerate()
-            .map(|(i, qs)| {
-                qs.iter()
-                    .enumerate()
-                    .map(|(j, _)| {
-                        gauge_for_task(
-                            &task_info,
-                            "arroyo_worker_tx_queue_rem",
-                            "Remaining space in a tx queue",
-                            labels! {
-                                "next_node".to_string() => format!("{}", i),
-                                "next_node_idx".to_string() => format!("{}", j)
-                            },
-                        )
-                    })
-                    .collect()
-            })
-            .collect();
+        let metrics = TaskMetrics::new(&task_info, input_partitions);
+        let output_metrics = OutputMetrics::new(&task_info, &out_qs);
+        track_progress(&task_info, &metrics, &output_metrics);
 
         Context {
+            skip_failing_records: skip_failing_records(&task_info.operator_id),
             task_info,
             control_rx,
             control_tx,
             watermarks: vec![watermark; input_partitions],
-            collector: Collector::<K, T> {
-                out_qs,
-                sent_messages: counters.remove(MESSAGES_SENT),
-                sent_bytes: counters.remove(BYTES_SENT),
-                tx_queue_rem_gauges,
-                tx_queue_size_gauges,
-                _ts: PhantomData,
-            },
+            collector: Collector::new(out_qs, output_metrics),
             state,
-            counters,
+            metrics,
+            output_sampler: None,
+            column_profiler: None,
+            last_probe: 0,
+            udf_caches,
             _ts: PhantomData,
         }
     }
@@ -431,6 +490,25 @@ impl<K: Key, T: Data> Context<K, T> {
             .flatten()
     }
 
+    /// Called once all inputs have passed `watermark`
+    pub fn handle_watermark(&mut self, watermark: SystemTime) {
+        self.state.handle_watermark(watermark);
+        if let Some(g) = &self.metrics.watermark {
+            g.set(to_millis(watermark) as i64);
+        }
+    }
+
+    /// Records how far behind a source is, given the time that the record it just read was
+    /// written to the external system
+    pub fn report_source_lag(&self, written_at: SystemTime) {
+        if let Some(g) = &self.metrics.source_lag {
+            let lag = SystemTime::now()
+                .duration_since(written_at)
+                .unwrap_or_default();
+            g.set(lag.as_millis() as i64);
+        }
+    }
+
     pub async fn schedule_timer<D: Data + PartialEq + Eq>(
         &mut self,
         key: &mut K,
@@ -460,6 +538,13 @@ impl<K: Key, T: Data> Context<K, T> {
         timer_state.insert(event_time, key.clone(), value);
     }
 
+    /// Writes the entries of the subtask's UDF caches to its state, as part of a checkpoint
+    pub async fn checkpoint_udf_caches(&mut self) {
+        self.udf_caches
+            .checkpoint(&mut self.state, self.task_info.task_index)
+            .await;
+    }
+
     pub async fn collect(&mut self, record: Record<K, T>) {
         self.collector.collect(record).await;
     }
@@ -479,6 +564,94 @@ impl<K: Key, T: Data> Context<K, T> {
             .await
             .unwrap();
     }
+
+    /// Reports that the connector has (re)connected to its external system
+    pub async fn report_connected(&mut self) {
+        self.report_connector_health(true, None).await;
+    }
+
+    /// Reports that the connector lost, or failed to establish, its connection to its external
+    /// system
+    pub async fn report_disconnected(&mut self, error: String) {
+        self.report_connector_health(false, Some(error)).await;
+    }
+
+    /// Reports a watermark probe to the controller and passes it on downstream. A probe arrives
+    /// over each of the subtask's inputs, but is only handled the first time it's seen.
+    pub async fn handle_probe(&mut self, probe_id: u64) {
+        // probe ids increase, so a probe that isn't newer than the last one has already been seen
+        if probe_id <= self.last_probe {
+            return;
+        }
+        self.last_probe = probe_id;
+
+        // probes are for debugging, so reports are dropped if nothing is listening for them
+        let _ = self
+            .control_tx
+            .send(ControlResp::ProbeObserved {
+                operator_id: self.task_info.operator_id.clone(),
+                task_index: self.task_info.task_index,
+                probe_id,
+                time: SystemTime::now(),
+                watermark: self.watermark(),
+            })
+            .await;
+
+        self.broadcast(Message::Probe(probe_id)).await;
+    }
+
+    /// Keeps a record written by a sink, so that its recent output can be sampled, and profiles
+    /// its columns if column stats are enabled
+    pub fn sample_output<RK: Key, RT: Data + Serialize>(&mut self, record: &Record<RK, RT>) {
+        self.output_sampler
+            .get_or_insert_with(|| OutputSampler::register(&self.task_info))
+            .add(record);
+
+        if let Some(profiler) = self.column_profiler() {
+            if profiler.sample() {
+                if let Ok(value) = serde_json::to_value(&record.value) {
+                    profiler.add(&value);
+                }
+            }
+        }
+    }
+
+    /// Profiles the columns of a record read by a source, given as the bytes it was read as, if
+    /// column stats are enabled. Records are profiled before they're deserialized, so fields that
+    /// the table doesn't declare are profiled too; records that aren't JSON objects are ignored.
+    pub fn profile_source_record(&mut self, data: &[u8]) {
+        if let Some(profiler) = self.column_profiler() {
+            if profiler.sample() {
+                if let Ok(value) = serde_json::from_slice(data) {
+                    profiler.add(&value);
+                }
+            }
+        }
+    }
+
+    fn column_profiler(&mut self) -> Option<&mut ColumnProfiler> {
+        if !column_stats::enabled() {
+            return None;
+        }
+
+        Some(
+            self.column_profiler
+                .get_or_insert_with(|| ColumnProfiler::register(&self.task_info)),
+        )
+    }
+
+    async fn report_connector_health(&mut self, connected: bool, error: Option<String>) {
+        // health is informational, so reports are dropped if nothing is listening for them
+        let _ = self
+            .control_tx
+            .send(ControlResp::ConnectorHealth {
+                operator_id: self.task_info.operator_id.clone(),
+                task_index: self.task_info.task_index,
+                connected,
+                error,
+            })
+            .await;
+    }
 }
 
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
@@ -661,6 +834,7 @@ impl Program {
         assignments: &Vec<TaskAssignment>,
     ) -> Program {
         let mut physical = DiGraph::new();
+        let queue_config = QueueConfig::from_env();
 
         let mut parallelism_map = HashMap::new();
         for task in assignments {
@@ -704,7 +878,7 @@ impl Program {
                         panic!("cannot create a forward connection between nodes of different parallelism");
                     }
                     for (f, t) in from_nodes.iter().zip(&to_nodes) {
-                        let (tx, rx) = channel(QUEUE_SIZE);
+                        let (tx, rx) = channel(queue_config.forward_queue_size());
                         let edge = PhysicalGraphEdge {
                             edge_idx: 0,
                             in_logical_idx: logical_in_node_idx.index(),
@@ -719,7 +893,7 @@ impl Program {
                 LogicalEdge::Shuffle | LogicalEdge::ShuffleJoin(_) => {
                     for f in &from_nodes {
                         for (idx, t) in to_nodes.iter().enumerate() {
-                            let (tx, rx) = channel(QUEUE_SIZE);
+                            let (tx, rx) = channel(queue_config.shuffle_queue_size());
                             let edge = PhysicalGraphEdge {
                                 edge_idx: idx,
                                 in_logical_idx: logical_in_node_idx.index(),
@@ -764,7 +938,16 @@ pub struct RunningEngine {
 }
 
 impl RunningEngine {
-    pub fn source_controls(&self) -> Vec<Sender<ControlMessage>> {
+    /// Whether any subtask of the operator runs on this worker
+    pub fn runs_operator(&self, operator_id: &str) -> bool {
+        self.assignments
+            .iter()
+            .any(|((id, _), a)| id == operator_id && a.worker_id == self.worker_id.0)
+    }
+
+    /// The control queues of the source subtasks running on this worker, by operator id and
+    /// subtask index
+    pub fn source_controls(&self) -> HashMap<(String, usize), Sender<ControlMessage>> {
         self.program
             .graph
             .externals(Direction::Incoming)
@@ -777,13 +960,11 @@ impl RunningEngine {
                     == self.worker_id.0
             })
             .map(|idx| {
-                self.program
-                    .graph
-                    .node_weight(idx)
-                    .unwrap()
-                    .as_queue()
-                    .tx
-                    .clone()
+                let w = self.program.graph.node_weight(idx).unwrap();
+                (
+                    (w.id().to_string(), w.subtask_idx()),
+                    w.as_queue().tx.clone(),
+                )
             })
             .collect()
     }
@@ -933,7 +1114,9 @@ impl Engine {
         let worker_id = self.worker_id;
         let job_id = self.job_id.clone();
         let mut controller = if let Some(addr) = self.controller_addr.clone() {
-            Some(ControllerGrpcClient::connect(addr).await.unwrap())
+            Some(ControllerGrpcClient::new(
+                tls::connect_addr(addr).await.unwrap(),
+            ))
         } else {
             None
         };
@@ -1127,6 +1310,11 @@ impl Engine {
                                 }
                             }
                             Some(ControlResp::CheckpointCompleted(c)) => {
+                                record_state_bytes(
+                                    &c.operator_id,
+                                    c.subtask_metadata.subtask_index as usize,
+                                    c.subtask_metadata.bytes,
+                                );
                                 if let Some(controller) = controller.as_mut() {
                                     controller.task_checkpoint_completed(Request::new(
                                         TaskCheckpointCompletedReq {
@@ -1191,6 +1379,59 @@ impl Engine {
                                     None
                                 }
                             }
+                            Some(ControlResp::Watermark { operator_id, task_index, watermark }) => {
+                                if let Some(controller) = controller.as_mut() {
+                                    // watermark reports only affect alignment, so failures aren't fatal
+                                    if let Err(e) = controller.task_watermark(Request::new(
+                                        TaskWatermarkReq {
+                                            worker_id: worker_id.0,
+                                            job_id: job_id.clone(),
+                                            operator_id,
+                                            operator_subtask: task_index as u64,
+                                            watermark_micros: to_micros(watermark),
+                                        }
+                                    )).await {
+                                        warn!("failed to report watermark to controller: {:?}", e);
+                                    }
+                                }
+                                None
+                            }
+                            Some(ControlResp::ConnectorHealth { operator_id, task_index, connected, error }) => {
+                                if let Some(controller) = controller.as_mut() {
+                                    // health reports are informational, so failures aren't fatal
+                                    if let Err(e) = controller.connector_health(Request::new(
+                                        ConnectorHealthReq {
+                                            job_id: job_id.clone(),
+                                            operator_id,
+                                            task_index: task_index as u32,
+                                            time: to_micros(SystemTime::now()),
+                                            connected,
+                                            error,
+                                        }
+                                    )).await {
+                                        warn!("failed to report connector health to controller: {:?}", e);
+                                    }
+                                }
+                                None
+                            }
+                            Some(ControlResp::ProbeObserved { operator_id, task_index, probe_id, time, watermark }) => {
+                                if let Some(controller) = controller.as_mut() {
+                                    // probes are for debugging, so failures aren't fatal
+                                    if let Err(e) = controller.task_probe(Request::new(
+                                        TaskProbeReq {
+                                            job_id: job_id.clone(),
+                                            operator_id,
+                                            task_index: task_index as u32,
+                                            probe_id,
+                                            time: to_micros(time),
+                                            watermark_micros: watermark.map(to_micros),
+                                        }
+                                    )).await {
+                                        warn!("failed to report watermark probe to controller: {:?}", e);
+                                    }
+                                }
+                                None
+                            }
                             None => {
                                 // TODO: remove the control queue from the select at this point
                                 tokio::time::sleep(Duration::from_millis(50)).await;
@@ -1208,6 +1449,7 @@ impl Engine {
                                 job_id: job_id.clone(),
                                 time: to_micros(SystemTime::now()),
                                 worker_id: worker_id.0,
+                                tasks: task_progress(),
                             })).await;
                             if let Err(err) = result {
                                 error!("heartbeat failed {:?}", err);
@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use apache_avro::types::Value as AvroValue;
+use apache_avro::Schema;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::operators::UserError;
+
+use super::schema_registry::{header, split_header, RegisteredSchema, SchemaRegistryClient};
+
+/// Reads and writes avro records in the schema registry wire format. Records are read with the
+/// schema they were written with, which is fetched from the registry the first time its id is
+/// seen, and written with the latest schema of the sink's subject.
+///
+/// Values pass through json on their way to and from the generated structs, so that they're
+/// deserialized the same way as json records are (for example, timestamps as RFC3339 strings).
+#[derive(Clone)]
+pub struct AvroFormat {
+    registry: SchemaRegistryClient,
+    schemas: HashMap<u32, Arc<Schema>>,
+    writer: Option<Arc<WriterSchema>>,
+}
+
+/// A schema that records are written with, along with its json definition which guides the
+/// conversion of values to avro (for example, of timestamps to the schema's unit)
+struct WriterSchema {
+    id: u32,
+    schema: Schema,
+    json: JsonValue,
+    // named types, by both their full and short names
+    names: HashMap<String, JsonValue>,
+}
+
+impl WriterSchema {
+    fn new(id: u32, definition: &str) -> Result<Self, String> {
+        let schema = Schema::parse_str(definition).map_err(|e| e.to_string())?;
+        let json: JsonValue = serde_json::from_str(definition).map_err(|e| e.to_string())?;
+        let mut names = HashMap::new();
+        collect_names(&json, None, &mut names);
+
+        Ok(Self {
+            id,
+            schema,
+            json,
+            names,
+        })
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        let value = json_to_avro(&self.json, &self.names, value)?;
+        let datum = apache_avro::to_avro_datum(&self.schema, value).map_err(|e| e.to_string())?;
+
+        let mut msg = header(self.id);
+        msg.extend_from_slice(&datum);
+        Ok(msg)
+    }
+}
+
+fn parse_schema(registered: &RegisteredSchema) -> Result<Schema, UserError> {
+    if let Some(typ) = registered.schema_type.as_ref().filter(|t| *t != "AVRO") {
+        return Err(UserError::new(
+            "Unsupported schema",
+            format!("schema {} is a {} schema, not avro", registered.id, typ),
+        ));
+    }
+
+    Schema::parse_str(&registered.schema).map_err(|e| {
+        UserError::new(
+            "Invalid schema",
+            format!("schema {} is not a valid avro schema: {}", registered.id, e),
+        )
+    })
+}
+
+fn decode<T: DeserializeOwned>(
+    schema: &Schema,
+    mut payload: &[u8],
+    fields: &Map<String, JsonValue>,
+) -> Result<T, String> {
+    let value = apache_avro::from_avro_datum(schema, &mut payload, None)
+        .map_err(|e| format!("could not read avro record: {}", e))?;
+    let mut value = avro_to_json(value)?;
+    if let Some(record) = value.as_object_mut() {
+        record.extend(fields.clone());
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+impl AvroFormat {
+    pub fn new(registry: SchemaRegistryClient) -> Self {
+        Self {
+            registry,
+            schemas: HashMap::new(),
+            writer: None,
+        }
+    }
+
+    /// Reads a record, filling in `fields` as `SerializationMode::deserialize_slice_with_fields`
+    /// does
+    pub async fn deserialize<T: DeserializeOwned>(
+        &mut self,
+        msg: &[u8],
+        fields: &Map<String, JsonValue>,
+    ) -> Result<T, UserError> {
+        let (id, payload) =
+            split_header(msg).map_err(|e| UserError::new("Deserialization error", e))?;
+
+        let schema = match self.schemas.get(&id) {
+            Some(schema) => schema.clone(),
+            None => {
+                let registered =
+                    self.registry.get_schema_for_id(id).await.map_err(|e| {
+                        UserError::new("Failed to fetch schema", format!("{:?}", e))
+                    })?;
+                let schema = Arc::new(parse_schema(&registered)?);
+                self.schemas.insert(id, schema.clone());
+                schema
+            }
+        };
+
+        decode(&schema, payload, fields).map_err(|e| {
+            UserError::new(
+                "Deserialization error",
+                format!(
+                    "Failed to deserialize message with schema {} from avro, with error {}",
+                    id, e
+                ),
+            )
+        })
+    }
+
+    /// Loads the latest schema of the subject, which records are then written with
+    pub async fn load_writer_schema(&mut self, subject: &str) -> anyhow::Result<()> {
+        let registered = self.registry.get_latest_schema(subject).await?;
+        if let Some(typ) = registered.schema_type.as_ref().filter(|t| *t != "AVRO") {
+            anyhow::bail!(
+                "the latest schema for {} is a {} schema, not avro",
+                subject,
+                typ
+            );
+        }
+
+        let writer = WriterSchema::new(registered.id, &registered.schema).map_err(|e| {
+            anyhow::anyhow!("the latest schema for {} is not valid avro: {}", subject, e)
+        })?;
+        self.writer = Some(Arc::new(writer));
+        Ok(())
+    }
+
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, UserError> {
+        let writer = self
+            .writer
+            .as_ref()
+            .expect("avro records written before the writer schema was loaded");
+
+        writer.encode(value).map_err(|e| {
+            UserError::new(
+                "Serialization error",
+                format!(
+                    "Failed to serialize record with schema {} to avro: {}",
+                    writer.id, e
+                ),
+            )
+        })
+    }
+}
+
+fn rfc3339(micros: i64) -> Result<JsonValue, String> {
+    Utc.timestamp_opt(
+        micros.div_euclid(1_000_000),
+        (micros.rem_euclid(1_000_000) * 1000) as u32,
+    )
+    .single()
+    .map(|t| JsonValue::String(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+    .ok_or_else(|| format!("timestamp {} is out of range", micros))
+}
+
+/// Converts an avro value to the json that its struct deserializes from. Unions are replaced by
+/// their values and timestamps become RFC3339 strings.
+fn avro_to_json(value: AvroValue) -> Result<JsonValue, String> {
+    Ok(match value {
+        AvroValue::Null => JsonValue::Null,
+        AvroValue::Boolean(b) => b.into(),
+        AvroValue::Int(i) | AvroValue::Date(i) | AvroValue::TimeMillis(i) => i.into(),
+        AvroValue::Long(l) | AvroValue::TimeMicros(l) => l.into(),
+        AvroValue::Float(f) => f.into(),
+        AvroValue::Double(f) => f.into(),
+        AvroValue::Bytes(b) | AvroValue::Fixed(_, b) => b.into(),
+        AvroValue::String(s) | AvroValue::Enum(_, s) => s.into(),
+        AvroValue::Uuid(u) => u.to_string().into(),
+        AvroValue::Union(_, v) => avro_to_json(*v)?,
+        AvroValue::Array(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .map(avro_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        AvroValue::Map(entries) => JsonValue::Object(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((k, avro_to_json(v)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        AvroValue::Record(fields) => JsonValue::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| Ok((k, avro_to_json(v)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        AvroValue::TimestampMillis(millis) => rfc3339(
+            millis
+                .checked_mul(1000)
+                .ok_or_else(|| format!("timestamp {} is out of range", millis))?,
+        )?,
+        AvroValue::TimestampMicros(micros) => rfc3339(micros)?,
+        other => return Err(format!("unsupported avro value {:?}", other)),
+    })
+}
+
+fn collect_names(
+    schema: &JsonValue,
+    namespace: Option<&str>,
+    names: &mut HashMap<String, JsonValue>,
+) {
+    match schema {
+        JsonValue::Array(branches) => {
+            for branch in branches {
+                collect_names(branch, namespace, names);
+            }
+        }
+        JsonValue::Object(o) => {
+            let namespace = o.get("namespace").and_then(|n| n.as_str()).or(namespace);
+            let named = matches!(
+                o.get("type").and_then(|t| t.as_str()),
+                Some("record" | "error" | "enum" | "fixed")
+            );
+
+            if let (true, Some(name)) = (named, o.get("name").and_then(|n| n.as_str())) {
+                names.insert(name.to_string(), schema.clone());
+                match (name.rsplit_once('.'), namespace) {
+                    (Some((_, short)), _) => {
+                        names.insert(short.to_string(), schema.clone());
+                    }
+                    (None, Some(namespace)) => {
+                        names.insert(format!("{}.{}", namespace, name), schema.clone());
+                    }
+                    (None, None) => {}
+                }
+            }
+
+            for child in ["type", "items", "values"] {
+                if let Some(child) = o.get(child) {
+                    collect_names(child, namespace, names);
+                }
+            }
+
+            for field in o
+                .get("fields")
+                .and_then(|f| f.as_array())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(typ) = field.get("type") {
+                    collect_names(typ, namespace, names);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads a timestamp in the given number of units per second from the json of a `SystemTime`,
+/// an RFC3339 string, or a number that's already in those units
+fn json_timestamp(value: &JsonValue, units_per_second: i64) -> Result<i64, String> {
+    match value {
+        JsonValue::Object(o) => {
+            let secs = o.get("secs_since_epoch").and_then(|s| s.as_i64());
+            let nanos = o.get("nanos_since_epoch").and_then(|n| n.as_i64());
+            match (secs, nanos) {
+                (Some(secs), Some(nanos)) => {
+                    Ok(secs * units_per_second + nanos / (1_000_000_000 / units_per_second))
+                }
+                _ => Err(format!("expected a timestamp, found {}", value)),
+            }
+        }
+        JsonValue::String(s) => {
+            let t = DateTime::parse_from_rfc3339(s)
+                .map_err(|e| format!("invalid timestamp '{}': {}", s, e))?;
+            Ok(t.timestamp() * units_per_second
+                + t.timestamp_subsec_nanos() as i64 / (1_000_000_000 / units_per_second))
+        }
+        JsonValue::Number(n) => n
+            .as_i64()
+            .ok_or_else(|| format!("expected a timestamp, found {}", n)),
+        _ => Err(format!("expected a timestamp, found {}", value)),
+    }
+}
+
+fn mismatch(schema: &JsonValue, value: &JsonValue) -> String {
+    format!("{} does not match avro type {}", value, schema)
+}
+
+/// Converts the json of a record to an avro value of the schema
+fn json_to_avro(
+    schema: &JsonValue,
+    names: &HashMap<String, JsonValue>,
+    value: JsonValue,
+) -> Result<AvroValue, String> {
+    match schema {
+        JsonValue::String(typ) => match (typ.as_str(), value) {
+            ("null", JsonValue::Null) => Ok(AvroValue::Null),
+            ("boolean", JsonValue::Bool(b)) => Ok(AvroValue::Boolean(b)),
+            ("int", JsonValue::Number(n)) => n
+                .as_i64()
+                .and_then(|i| i32::try_from(i).ok())
+                .map(AvroValue::Int)
+                .ok_or_else(|| format!("{} is not an int", n)),
+            ("long", JsonValue::Number(n)) => n
+                .as_i64()
+                .map(AvroValue::Long)
+                .ok_or_else(|| format!("{} is not a long", n)),
+            ("float", JsonValue::Number(n)) => Ok(AvroValue::Float(n.as_f64().unwrap() as f32)),
+            ("double", JsonValue::Number(n)) => Ok(AvroValue::Double(n.as_f64().unwrap())),
+            ("string", JsonValue::String(s)) => Ok(AvroValue::String(s)),
+            ("bytes", JsonValue::String(s)) => Ok(AvroValue::Bytes(s.into_bytes())),
+            ("bytes", v @ JsonValue::Array(_)) => serde_json::from_value(v)
+                .map(AvroValue::Bytes)
+                .map_err(|e| e.to_string()),
+            (name, value) => match names
+                .get(name)
+                .or_else(|| names.get(name.rsplit('.').next().unwrap()))
+            {
+                Some(named) => json_to_avro(named, names, value),
+                None => Err(mismatch(schema, &value)),
+            },
+        },
+        JsonValue::Array(branches) => {
+            if value.is_null() {
+                return branches
+                    .iter()
+                    .position(|b| b.as_str() == Some("null"))
+                    .map(|i| AvroValue::Union(i as u32, Box::new(AvroValue::Null)))
+                    .ok_or_else(|| mismatch(schema, &value));
+            }
+
+            branches
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| b.as_str() != Some("null"))
+                .find_map(|(i, b)| {
+                    json_to_avro(b, names, value.clone())
+                        .ok()
+                        .map(|v| AvroValue::Union(i as u32, Box::new(v)))
+                })
+                .ok_or_else(|| mismatch(schema, &value))
+        }
+        JsonValue::Object(o) => {
+            let typ = o.get("type").ok_or_else(|| mismatch(schema, &value))?;
+            match (
+                typ.as_str(),
+                o.get("logicalType").and_then(|t| t.as_str()),
+                value,
+            ) {
+                (Some("long"), Some("timestamp-millis"), v) => {
+                    json_timestamp(&v, 1_000).map(AvroValue::TimestampMillis)
+                }
+                (Some("long"), Some("timestamp-micros"), v) => {
+                    json_timestamp(&v, 1_000_000).map(AvroValue::TimestampMicros)
+                }
+                (Some("record" | "error"), _, JsonValue::Object(mut record)) => {
+                    let fields = o
+                        .get("fields")
+                        .and_then(|f| f.as_array())
+                        .ok_or_else(|| format!("record schema {} has no fields", schema))?;
+
+                    let mut values = Vec::with_capacity(fields.len());
+                    for field in fields {
+                        let name = field
+                            .get("name")
+                            .and_then(|n| n.as_str())
+                            .ok_or_else(|| format!("record field {} has no name", field))?;
+                        let value = record
+                            .remove(name)
+                            .or_else(|| field.get("default").cloned())
+                            .unwrap_or(JsonValue::Null);
+                        let value = json_to_avro(&field["type"], names, value)
+                            .map_err(|e| format!("field '{}': {}", name, e))?;
+                        values.push((name.to_string(), value));
+                    }
+                    Ok(AvroValue::Record(values))
+                }
+                (Some("enum"), _, JsonValue::String(symbol)) => o
+                    .get("symbols")
+                    .and_then(|s| s.as_array())
+                    .and_then(|symbols| {
+                        symbols
+                            .iter()
+                            .position(|s| s.as_str() == Some(symbol.as_str()))
+                    })
+                    .map(|i| AvroValue::Enum(i as u32, symbol.clone()))
+                    .ok_or_else(|| format!("'{}' is not a symbol of {}", symbol, schema)),
+                (Some("fixed"), _, v) => {
+                    let AvroValue::Bytes(bytes) =
+                        json_to_avro(&JsonValue::from("bytes"), names, v)?
+                    else {
+                        unreachable!()
+                    };
+                    Ok(AvroValue::Fixed(bytes.len(), bytes))
+                }
+                (Some("array"), _, JsonValue::Array(items)) => Ok(AvroValue::Array(
+                    items
+                        .into_iter()
+                        .map(|item| json_to_avro(&o["items"], names, item))
+                        .collect::<Result<_, _>>()?,
+                )),
+                (Some("map"), _, JsonValue::Object(entries)) => Ok(AvroValue::Map(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| Ok((k, json_to_avro(&o["values"], names, v)?)))
+                        .collect::<Result<_, String>>()?,
+                )),
+                // a primitive with a logical type we don't handle, or a nested type definition
+                (_, _, value) => json_to_avro(typ, names, value),
+            }
+        }
+        _ => Err(format!("invalid avro schema {}", schema)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use apache_avro::Schema;
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Map};
+
+    use super::{decode, WriterSchema};
+    use crate::formats::schema_registry::split_header;
+
+    const SCHEMA: &str = r#"
+    {
+        "type": "record",
+        "name": "Order",
+        "namespace": "com.example",
+        "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "customer", "type": ["null", "string"]},
+            {"name": "price", "type": "double"},
+            {"name": "created", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+            {"name": "status", "type": {"type": "enum", "name": "Status", "symbols": ["NEW", "PAID"]}},
+            {"name": "previous_status", "type": ["null", "Status"], "default": null},
+            {"name": "tags", "type": {"type": "array", "items": "string"}}
+        ]
+    }
+    "#;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: i64,
+        customer: Option<String>,
+        price: f64,
+        #[serde(deserialize_with = "crate::deserialize_rfc3339_datetime")]
+        created: SystemTime,
+        status: String,
+        previous_status: Option<String>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let writer = WriterSchema::new(7, SCHEMA).unwrap();
+        let order = Order {
+            id: 1,
+            customer: None,
+            price: 12.5,
+            created: UNIX_EPOCH + Duration::from_millis(1_690_000_000_123),
+            status: "PAID".to_string(),
+            previous_status: Some("NEW".to_string()),
+            tags: vec!["a".to_string(), "b".to_string()],
+        };
+
+        let msg = writer.encode(&order).unwrap();
+        let (id, payload) = split_header(&msg).unwrap();
+        assert_eq!(id, 7);
+
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        assert_eq!(
+            decode::<Order>(&schema, payload, &Map::new()).unwrap(),
+            order
+        );
+
+        // fields from outside of the payload replace the record's
+        let fields = json!({"customer": "acme"}).as_object().unwrap().clone();
+        let decoded: Order = decode(&schema, payload, &fields).unwrap();
+        assert_eq!(decoded.customer.as_deref(), Some("acme"));
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let writer = WriterSchema::new(7, SCHEMA).unwrap();
+
+        #[derive(Serialize)]
+        struct BadStatus {
+            id: i64,
+            price: f64,
+            created: &'static str,
+            status: &'static str,
+            tags: Vec<String>,
+        }
+
+        let err = writer
+            .encode(&BadStatus {
+                id: 1,
+                price: 1.0,
+                created: "2023-07-22T04:26:40Z",
+                status: "SHIPPED",
+                tags: vec![],
+            })
+            .unwrap_err();
+        assert!(err.contains("field 'status'"), "{}", err);
+    }
+}
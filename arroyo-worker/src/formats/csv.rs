@@ -0,0 +1,266 @@
+use std::fmt::{self, Display};
+
+use serde::de::{DeserializeOwned, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::connectors::grpc_sink::timestamps_to_rfc3339;
+use crate::connectors::{CsvConfig, CsvQuoting};
+use crate::operators::UserError;
+
+/// How csv records are read and written. A message (or a line of a file) holds a single record;
+/// with `has_header`, messages start with a header row and fields are matched to columns by name,
+/// and otherwise they're matched by position.
+#[derive(Clone, Copy, Debug)]
+pub struct CsvOptions {
+    delimiter: u8,
+    quote: u8,
+    has_header: bool,
+    quote_style: csv::QuoteStyle,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self::from_config(None)
+    }
+}
+
+impl CsvOptions {
+    pub fn from_config(config: Option<&CsvConfig>) -> Self {
+        // the connector has checked that these are single ASCII characters
+        let byte =
+            |c: Option<&String>, default| c.and_then(|c| c.bytes().next()).unwrap_or(default);
+
+        Self {
+            delimiter: byte(config.and_then(|c| c.delimiter.as_ref()), b','),
+            quote: byte(config.and_then(|c| c.quote.as_ref()), b'"'),
+            has_header: config.and_then(|c| c.has_header).unwrap_or(false),
+            quote_style: match config.and_then(|c| c.quoting.as_ref()) {
+                Some(CsvQuoting::Always) => csv::QuoteStyle::Always,
+                Some(CsvQuoting::NonNumeric) => csv::QuoteStyle::NonNumeric,
+                Some(CsvQuoting::Never) => csv::QuoteStyle::Never,
+                Some(CsvQuoting::Necessary) | None => csv::QuoteStyle::Necessary,
+            },
+        }
+    }
+
+    pub fn has_header(&self) -> bool {
+        self.has_header
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self, msg: &[u8]) -> Result<T, UserError> {
+        let record: Option<Result<T, csv::Error>> = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(self.has_header)
+            .from_reader(msg)
+            .deserialize()
+            .next();
+
+        record
+            .ok_or_else(|| "the message has no records".to_string())
+            .and_then(|r| r.map_err(|e| e.to_string()))
+            .map_err(|e| {
+                UserError::new(
+                    "Deserialization error",
+                    format!(
+                        "Failed to deserialize message '{}' from csv, with error {}",
+                        String::from_utf8_lossy(msg),
+                        e
+                    ),
+                )
+            })
+    }
+
+    /// The message for the value: its record, preceded by its header row if the options have one
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, UserError> {
+        let (mut message, mut record) = self.header_and_record(value)?;
+        if !self.has_header {
+            return Ok(record);
+        }
+
+        message.push(b'\n');
+        message.append(&mut record);
+        Ok(message)
+    }
+
+    /// The header row and record for the value, without line terminators. Timestamps are written
+    /// as RFC3339 strings, nulls as empty fields and nested values as json.
+    pub fn header_and_record<T: Serialize>(
+        &self,
+        value: &T,
+    ) -> Result<(Vec<u8>, Vec<u8>), UserError> {
+        let fields = record_fields(value)?;
+
+        Ok((
+            self.write_row(fields.iter().map(|(name, _)| name))?,
+            self.write_row(fields.iter().map(|(_, text)| text))?,
+        ))
+    }
+
+    fn write_row<I: IntoIterator<Item = S>, S: AsRef<[u8]>>(
+        &self,
+        row: I,
+    ) -> Result<Vec<u8>, UserError> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .quote_style(self.quote_style)
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(vec![]);
+
+        writer.write_record(row).map_err(serialization_error)?;
+        let mut row = writer
+            .into_inner()
+            .map_err(|e| serialization_error(e.error()))?;
+        row.pop();
+        Ok(row)
+    }
+}
+
+fn serialization_error(e: impl Display) -> UserError {
+    UserError::new(
+        "Serialization error",
+        format!("Failed to serialize record as csv: {}", e),
+    )
+}
+
+/// The fields of the serialized value as text, in the order they're declared in
+fn record_fields<T: Serialize>(value: &T) -> Result<Vec<(String, String)>, UserError> {
+    // serde_json's maps are sorted by key, so the fields are read back from the json text to keep
+    // their order
+    let json = serde_json::to_string(value).map_err(serialization_error)?;
+    let OrderedFields(fields) = serde_json::from_str(&json).map_err(serialization_error)?;
+
+    Ok(fields
+        .into_iter()
+        .map(|(name, mut value)| {
+            timestamps_to_rfc3339(&mut value);
+            let text = match value {
+                Value::Null => String::new(),
+                Value::String(s) => s,
+                // numbers and booleans, and nested values as json
+                value => value.to_string(),
+            };
+            (name, text)
+        })
+        .collect())
+}
+
+struct OrderedFields(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for OrderedFields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = OrderedFields;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a record")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedFields, A::Error> {
+                let mut fields = vec![];
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(OrderedFields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::CsvOptions;
+    use crate::connectors::{CsvConfig, CsvQuoting};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: i64,
+        customer: Option<String>,
+        #[serde(deserialize_with = "crate::deserialize_rfc3339_datetime")]
+        created: SystemTime,
+        amount: f64,
+    }
+
+    fn order() -> Order {
+        Order {
+            id: 1,
+            customer: Some("acme, inc".to_string()),
+            created: UNIX_EPOCH + Duration::from_millis(1_690_000_000_123),
+            amount: 9.5,
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let options = CsvOptions::default();
+        let order: Order = options
+            .deserialize(b"1,\"acme, inc\",2023-07-22T04:26:40.123Z,9.5\n")
+            .unwrap();
+        assert_eq!(order, self::order());
+
+        let order: Order = options
+            .deserialize(b"2,,2023-07-22T04:26:40.123Z,1")
+            .unwrap();
+        assert_eq!(order.customer, None);
+
+        assert!(options.deserialize::<Order>(b"").is_err());
+        assert!(options.deserialize::<Order>(b"x,y,z,w").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_with_header() {
+        let options = CsvOptions::from_config(Some(&CsvConfig {
+            delimiter: Some(";".to_string()),
+            quote: Some("'".to_string()),
+            has_header: Some(true),
+            quoting: None,
+        }));
+
+        // fields are matched by name
+        let order: Order = options
+            .deserialize(b"amount;created;customer;id\n9.5;2023-07-22T04:26:40.123Z;'acme, inc';1")
+            .unwrap();
+        assert_eq!(order, self::order());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let options = CsvOptions::default();
+        assert_eq!(
+            String::from_utf8(options.serialize(&order()).unwrap()).unwrap(),
+            "1,\"acme, inc\",2023-07-22T04:26:40.123Z,9.5"
+        );
+
+        let options = CsvOptions::from_config(Some(&CsvConfig {
+            delimiter: Some("|".to_string()),
+            quote: None,
+            has_header: Some(true),
+            quoting: Some(CsvQuoting::NonNumeric),
+        }));
+        let mut order = order();
+        order.customer = None;
+        assert_eq!(
+            String::from_utf8(options.serialize(&order).unwrap()).unwrap(),
+            "\"id\"|\"customer\"|\"created\"|\"amount\"\n1|\"\"|\"2023-07-22T04:26:40.123Z\"|9.5"
+        );
+
+        // records round trip
+        let order = self::order();
+        assert_eq!(
+            options
+                .deserialize::<Order>(&options.serialize(&order).unwrap())
+                .unwrap(),
+            order
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod avro;
+pub mod csv;
+pub mod protobuf;
+pub mod schema_registry;
@@ -0,0 +1,310 @@
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use prost_reflect::{DescriptorPool, DynamicMessage, Kind, MessageDescriptor, Value};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::connectors::ProtobufConfig;
+use crate::operators::UserError;
+
+const TIMESTAMP_MESSAGE: &str = "google.protobuf.Timestamp";
+
+/// Reads protobuf messages of the type configured for the source.
+///
+/// Messages pass through json on their way to the generated structs, as avro records do, so that
+/// they're deserialized the same way as json records are (for example, timestamps as RFC3339
+/// strings and enums as their names).
+#[derive(Clone)]
+pub struct ProtobufFormat {
+    descriptor: MessageDescriptor,
+}
+
+impl ProtobufFormat {
+    pub fn new(config: &ProtobufConfig) -> anyhow::Result<Self> {
+        let bytes = STANDARD
+            .decode(&config.descriptor_set)
+            .map_err(|e| anyhow!("descriptor set is not valid base64: {}", e))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .map_err(|e| anyhow!("descriptor set is not a valid FileDescriptorSet: {}", e))?;
+        let descriptor = pool
+            .get_message_by_name(&config.message)
+            .ok_or_else(|| anyhow!("message '{}' not found in descriptor set", config.message))?;
+
+        Ok(Self { descriptor })
+    }
+
+    /// Reads a record, filling in `fields` as `SerializationMode::deserialize_slice_with_fields`
+    /// does
+    pub fn deserialize<T: DeserializeOwned>(
+        &self,
+        msg: &[u8],
+        fields: &Map<String, JsonValue>,
+    ) -> Result<T, UserError> {
+        decode(&self.descriptor, msg, fields).map_err(|e| {
+            UserError::new(
+                "Deserialization error",
+                format!(
+                    "Failed to deserialize message as {} from protobuf, with error {}",
+                    self.descriptor.full_name(),
+                    e
+                ),
+            )
+        })
+    }
+}
+
+fn decode<T: DeserializeOwned>(
+    descriptor: &MessageDescriptor,
+    msg: &[u8],
+    fields: &Map<String, JsonValue>,
+) -> Result<T, String> {
+    let message = DynamicMessage::decode(descriptor.clone(), msg)
+        .map_err(|e| format!("could not read protobuf message: {}", e))?;
+    let mut value = message_to_json(&message)?;
+    if let Some(record) = value.as_object_mut() {
+        record.extend(fields.clone());
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+/// Converts a message to the json that its struct deserializes from. Fields with explicit
+/// presence that aren't set become nulls, while other fields take their default values; repeated
+/// and map fields are left out, as tables can't represent them.
+fn message_to_json(message: &DynamicMessage) -> Result<JsonValue, String> {
+    if message.descriptor().full_name() == TIMESTAMP_MESSAGE {
+        return timestamp_to_json(message);
+    }
+
+    let mut record = Map::new();
+    for field in message.descriptor().fields() {
+        if field.is_list() || field.is_map() {
+            continue;
+        }
+
+        let value = if field.supports_presence() && !message.has_field(&field) {
+            JsonValue::Null
+        } else {
+            value_to_json(&message.get_field(&field), &field.kind())?
+        };
+        record.insert(field.name().to_string(), value);
+    }
+
+    Ok(JsonValue::Object(record))
+}
+
+fn value_to_json(value: &Value, kind: &Kind) -> Result<JsonValue, String> {
+    Ok(match value {
+        Value::Bool(b) => (*b).into(),
+        Value::I32(i) => (*i).into(),
+        Value::I64(i) => (*i).into(),
+        Value::U32(u) => (*u).into(),
+        Value::U64(u) => (*u).into(),
+        Value::F32(f) => (*f).into(),
+        Value::F64(f) => (*f).into(),
+        Value::String(s) => s.clone().into(),
+        Value::Bytes(b) => b.to_vec().into(),
+        Value::EnumNumber(n) => match kind.as_enum().and_then(|e| e.get_value(*n)) {
+            Some(v) => v.name().into(),
+            // a value added to the enum after the schema was given to us
+            None => n.to_string().into(),
+        },
+        Value::Message(m) => message_to_json(m)?,
+        Value::List(_) | Value::Map(_) => {
+            return Err(format!("unexpected repeated value {:?}", value));
+        }
+    })
+}
+
+fn timestamp_to_json(message: &DynamicMessage) -> Result<JsonValue, String> {
+    let seconds = message
+        .get_field_by_name("seconds")
+        .and_then(|s| s.as_i64())
+        .unwrap_or_default();
+    let nanos = message
+        .get_field_by_name("nanos")
+        .and_then(|n| n.as_i32())
+        .unwrap_or_default();
+
+    Utc.timestamp_opt(seconds, nanos as u32)
+        .single()
+        .map(|t| JsonValue::String(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+        .ok_or_else(|| format!("timestamp {}s {}ns is out of range", seconds, nanos))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use prost::Message;
+    use prost_reflect::{DynamicMessage, Value};
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+        FileDescriptorProto, FileDescriptorSet,
+    };
+    use serde::Deserialize;
+    use serde_json::{json, Map};
+
+    use super::ProtobufFormat;
+    use crate::connectors::ProtobufConfig;
+
+    fn field(name: &str, number: i32, typ: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(typ as i32),
+            type_name: type_name.map(|t| t.to_string()),
+            ..Default::default()
+        }
+    }
+
+    // the descriptor set that `protoc --include_imports` produces for
+    //
+    //   syntax = "proto2";
+    //   package com.example;
+    //   import "google/protobuf/timestamp.proto";
+    //
+    //   message Order {
+    //     enum Status { NEW = 0; PAID = 1; }
+    //     optional int64 id = 1;
+    //     optional string customer = 2;
+    //     optional google.protobuf.Timestamp created = 3;
+    //     optional Status status = 4;
+    //     optional uint32 quantity = 5;
+    //     repeated string tags = 6;
+    //   }
+    fn descriptor_set() -> String {
+        let timestamp = FileDescriptorProto {
+            name: Some("google/protobuf/timestamp.proto".to_string()),
+            package: Some("google.protobuf".to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Timestamp".to_string()),
+                field: vec![
+                    field("seconds", 1, Type::Int64, None),
+                    field("nanos", 2, Type::Int32, None),
+                ],
+                ..Default::default()
+            }],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        };
+
+        let mut tags = field("tags", 6, Type::String, None);
+        tags.label = Some(Label::Repeated as i32);
+
+        let order = FileDescriptorProto {
+            name: Some("order.proto".to_string()),
+            package: Some("com.example".to_string()),
+            dependency: vec!["google/protobuf/timestamp.proto".to_string()],
+            message_type: vec![DescriptorProto {
+                name: Some("Order".to_string()),
+                field: vec![
+                    field("id", 1, Type::Int64, None),
+                    field("customer", 2, Type::String, None),
+                    field(
+                        "created",
+                        3,
+                        Type::Message,
+                        Some(".google.protobuf.Timestamp"),
+                    ),
+                    field("status", 4, Type::Enum, Some(".com.example.Order.Status")),
+                    field("quantity", 5, Type::Uint32, None),
+                    tags,
+                ],
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Status".to_string()),
+                    value: ["NEW", "PAID"]
+                        .iter()
+                        .enumerate()
+                        .map(|(i, name)| EnumValueDescriptorProto {
+                            name: Some(name.to_string()),
+                            number: Some(i as i32),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            syntax: Some("proto2".to_string()),
+            ..Default::default()
+        };
+
+        STANDARD.encode(
+            FileDescriptorSet {
+                file: vec![timestamp, order],
+            }
+            .encode_to_vec(),
+        )
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Order {
+        id: i64,
+        customer: Option<String>,
+        #[serde(deserialize_with = "crate::deserialize_rfc3339_datetime")]
+        created: SystemTime,
+        status: String,
+        quantity: Option<u32>,
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let format = ProtobufFormat::new(&ProtobufConfig {
+            descriptor_set: descriptor_set(),
+            message: "com.example.Order".to_string(),
+        })
+        .unwrap();
+
+        let pool = format.descriptor.parent_pool();
+        let mut created = DynamicMessage::new(
+            pool.get_message_by_name("google.protobuf.Timestamp")
+                .unwrap(),
+        );
+        created.set_field_by_name("seconds", Value::I64(1_690_000_000));
+        created.set_field_by_name("nanos", Value::I32(123_000_000));
+
+        let mut message = DynamicMessage::new(format.descriptor.clone());
+        message.set_field_by_name("id", Value::I64(1));
+        message.set_field_by_name("created", Value::Message(created));
+        message.set_field_by_name("status", Value::EnumNumber(1));
+        message.set_field_by_name("tags", Value::List(vec![Value::String("a".to_string())]));
+        let msg = message.encode_to_vec();
+
+        let order: Order = format.deserialize(&msg, &Map::new()).unwrap();
+        assert_eq!(
+            order,
+            Order {
+                id: 1,
+                customer: None,
+                created: UNIX_EPOCH + Duration::from_millis(1_690_000_000_123),
+                status: "PAID".to_string(),
+                quantity: None,
+            }
+        );
+
+        // fields from outside of the payload replace the message's
+        let fields = json!({"customer": "acme"}).as_object().unwrap().clone();
+        let order: Order = format.deserialize(&msg, &fields).unwrap();
+        assert_eq!(order.customer.as_deref(), Some("acme"));
+
+        assert!(format
+            .deserialize::<Order>(&[0xff, 0xff], &Map::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_missing_message() {
+        let err = ProtobufFormat::new(&ProtobufConfig {
+            descriptor_set: descriptor_set(),
+            message: "com.example.Missing".to_string(),
+        })
+        .err()
+        .unwrap();
+
+        assert!(err.to_string().contains("not found"), "{}", err);
+    }
+}
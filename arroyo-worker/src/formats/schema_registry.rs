@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_types::check_egress;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+
+use crate::connectors::kafka::SchemaRegistry as SchemaRegistryConfig;
+
+// https://docs.confluent.io/platform/current/schema-registry/serdes-develop/index.html#wire-format
+const MAGIC_BYTE: u8 = 0;
+const HEADER_LEN: usize = 5;
+
+/// Splits a message in the schema registry wire format into the id of the schema it was written
+/// with and its payload
+pub fn split_header(msg: &[u8]) -> Result<(u32, &[u8]), String> {
+    if msg.len() < HEADER_LEN {
+        return Err(format!(
+            "message is {} bytes long, which is too short to contain a schema id",
+            msg.len()
+        ));
+    }
+
+    if msg[0] != MAGIC_BYTE {
+        return Err(format!(
+            "message starts with byte {}, rather than the schema registry magic byte {}",
+            msg[0], MAGIC_BYTE
+        ));
+    }
+
+    let id = u32::from_be_bytes(msg[1..HEADER_LEN].try_into().unwrap());
+    Ok((id, &msg[HEADER_LEN..]))
+}
+
+/// The header that precedes a payload written with the schema `id`
+pub fn header(id: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.push(MAGIC_BYTE);
+    header.extend_from_slice(&id.to_be_bytes());
+    header
+}
+
+/// A schema as stored in the registry, before it's parsed
+#[derive(Debug, Deserialize)]
+pub struct RegisteredSchema {
+    // not returned when the schema is looked up by its id
+    #[serde(default)]
+    pub id: u32,
+    pub schema: String,
+    // absent for avro schemas
+    #[serde(rename = "schemaType")]
+    pub schema_type: Option<String>,
+}
+
+/// A client for a Confluent Schema Registry. Schemas are immutable once registered, so callers
+/// cache them by id rather than this client.
+#[derive(Clone)]
+pub struct SchemaRegistryClient {
+    client: reqwest::Client,
+    endpoint: Url,
+    api_key: Option<String>,
+    api_secret: Option<String>,
+}
+
+impl SchemaRegistryClient {
+    pub fn new(config: &SchemaRegistryConfig) -> Result<Self> {
+        let endpoint = Url::parse(&config.endpoint)
+            .map_err(|e| anyhow!("invalid schema registry URL {}: {}", config.endpoint, e))?;
+        let host = endpoint
+            .host_str()
+            .ok_or_else(|| anyhow!("invalid schema registry URL {}", config.endpoint))?;
+        check_egress(host).map_err(|e| anyhow!(e))?;
+
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap(),
+            endpoint,
+            api_key: config.api_key.clone(),
+            api_secret: config.api_secret.clone(),
+        })
+    }
+
+    pub async fn get_schema_for_id(&self, id: u32) -> Result<RegisteredSchema> {
+        let mut schema: RegisteredSchema = self
+            .get(&["schemas", "ids", &id.to_string()])
+            .await
+            .map_err(|e| anyhow!("failed to fetch schema {}: {}", id, e))?;
+        schema.id = id;
+        Ok(schema)
+    }
+
+    /// The latest version of the subject's schema, which is what records are written with
+    pub async fn get_latest_schema(&self, subject: &str) -> Result<RegisteredSchema> {
+        self.get(&["subjects", subject, "versions", "latest"])
+            .await
+            .map_err(|e| anyhow!("failed to fetch the latest schema for {}: {}", subject, e))
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, segments: &[&str]) -> Result<T> {
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("invalid schema registry URL {}", self.endpoint))?
+            .pop_if_empty()
+            .extend(segments);
+
+        let mut req = self.client.get(url);
+        if let Some(api_key) = &self.api_key {
+            req = req.basic_auth(api_key, self.api_secret.as_ref());
+        }
+
+        let resp = req.send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            bail!("not found in the schema registry at {}", self.endpoint);
+        }
+
+        Ok(resp.error_for_status()?.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{header, split_header};
+
+    #[test]
+    fn test_wire_format() {
+        let mut msg = header(258);
+        assert_eq!(msg, vec![0, 0, 0, 1, 2]);
+        msg.extend_from_slice(b"payload");
+
+        assert_eq!(split_header(&msg), Ok((258, &b"payload"[..])));
+        assert!(split_header(&msg[..4]).is_err());
+        assert!(split_header(b"{\"a\": 1}").is_err());
+    }
+}
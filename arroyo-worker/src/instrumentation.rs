@@ -0,0 +1,42 @@
+//! Debug instrumentation that is only compiled into pipelines created with instrumentation
+//! enabled. The generated `main` calls [`configure`] before starting the worker.
+
+use arroyo_types::{Data, Key, Record, TaskInfo};
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::info;
+
+// the f64 sample rate, stored as its bit pattern
+static RECORD_SAMPLE_RATE: AtomicU64 = AtomicU64::new(0);
+
+pub fn configure(record_sample_rate: f64, trace_state: bool) {
+    RECORD_SAMPLE_RATE.store(
+        record_sample_rate.clamp(0.0, 1.0).to_bits(),
+        Ordering::Relaxed,
+    );
+    arroyo_state::instrumentation::set_trace_state(trace_state);
+
+    info!(
+        "Running with instrumentation (record sample rate = {}, state tracing = {})",
+        record_sample_rate, trace_state
+    );
+}
+
+fn should_sample() -> bool {
+    let rate = f64::from_bits(RECORD_SAMPLE_RATE.load(Ordering::Relaxed));
+    rate > 0.0 && (rate >= 1.0 || rand::thread_rng().gen_bool(rate))
+}
+
+/// Logs a sample of the records received by an operator
+pub fn log_record<K: Key, T: Data>(task_info: &TaskInfo, input: usize, record: &Record<K, T>) {
+    if should_sample() {
+        info!(
+            target: "arroyo_worker::records",
+            operator_id = task_info.operator_id,
+            subtask_idx = task_info.task_index,
+            input,
+            "{:?}",
+            record
+        );
+    }
+}
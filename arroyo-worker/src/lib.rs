@@ -8,23 +8,28 @@ use crate::network_manager::NetworkManager;
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::worker_grpc_server::{WorkerGrpc, WorkerGrpcServer};
 use arroyo_rpc::grpc::{
-    CheckpointReq, CheckpointResp, JobFinishedReq, JobFinishedResp, RegisterWorkerReq,
-    StartExecutionReq, StartExecutionResp, StopExecutionReq, StopExecutionResp, WorkerResources,
+    AlignSourcesReq, AlignSourcesResp, AssignWorkerReq, AssignWorkerResp, CheckpointReq,
+    CheckpointResp, ColumnStatsReq, ColumnStatsResp, InjectProbeReq, InjectProbeResp,
+    JobFinishedReq, JobFinishedResp, LoadCompactedStateReq, LoadCompactedStateResp,
+    RegisterWorkerReq, SampleOutputReq, SampleOutputResp, SetLogFilterReq, SetLogFilterResp,
+    StartExecutionReq, StartExecutionResp, StopExecutionReq, StopExecutionResp, WorkerIdleReq,
+    WorkerResources,
 };
 use arroyo_rpc::ControlMessage;
-use arroyo_server_common::start_admin_server;
+use arroyo_server_common::{advertise_ip, set_log_filter, start_admin_server, tls};
 use arroyo_types::{
-    from_millis, from_nanos, grpc_port, ports, CheckpointBarrier, NodeId, WorkerId, JOB_ID_ENV,
-    RUN_ID_ENV,
+    bind_addr, from_millis, from_nanos, grpc_port, ports, CheckpointBarrier, NodeId, WorkerId,
+    JOB_ID_ENV, RUN_ID_ENV, WORKER_PROTOCOL_VERSION, WORKER_REUSE_ENV,
 };
 use chrono::{DateTime, Utc};
 use engine::RunningEngine;
 use lazy_static::lazy_static;
-use local_ip_address::local_ip;
 use petgraph::graph::DiGraph;
 use rand::Rng;
 use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
+use std::net::SocketAddr;
 use std::process::exit;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -32,18 +37,26 @@ use std::time::{Duration, SystemTime};
 use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
-use tokio_stream::wrappers::TcpListenerStream;
 use tonic::{Request, Response, Status};
-use tracing::info;
+use tracing::{error, info, warn};
 
 pub use ordered_float::OrderedFloat;
 
+pub mod column_stats;
 pub mod connectors;
 pub mod engine;
+pub mod formats;
 mod inq_reader;
-mod network_manager;
+#[cfg(feature = "instrumentation")]
+pub mod instrumentation;
+pub mod metrics;
+pub mod network_manager;
 pub mod operators;
+pub mod output_samples;
 mod process_fn;
+mod sandbox;
+pub mod udf_cache;
+pub mod udfs;
 
 pub const PROMETHEUS_PUSH_GATEWAY: &str = "localhost:9091";
 pub const METRICS_PUSH_INTERVAL: Duration = Duration::from_secs(1);
@@ -92,6 +105,7 @@ where
 }
 
 pub static TIMER_TABLE: char = '[';
+pub static UDF_CACHE_TABLE: char = ']';
 
 pub enum SourceFinishType {
     // stop messages should be propagated through the dataflow
@@ -146,7 +160,7 @@ impl Debug for LogicalNode {
 }
 
 struct EngineState {
-    sources: Vec<Sender<ControlMessage>>,
+    sources: HashMap<(String, usize), Sender<ControlMessage>>,
     sinks: Vec<Sender<ControlMessage>>,
     running_engine: RunningEngine,
 }
@@ -177,14 +191,17 @@ impl LocalRunner {
 
 pub struct WorkerServer {
     id: WorkerId,
-    job_id: String,
-    run_id: String,
     name: &'static str,
     hash: &'static str,
     controller_addr: String,
     logical: DiGraph<LogicalNode, LogicalEdge>,
     state: Arc<Mutex<Option<EngineState>>>,
     network: Arc<Mutex<Option<NetworkManager>>>,
+    // how the worker registers with the controller, including the job run it's assigned to; the
+    // addresses are filled in once the worker's servers are started
+    registration: Arc<Mutex<RegisterWorkerReq>>,
+    // whether the worker waits to be assigned another run once its job finishes, rather than exiting
+    reusable: bool,
 }
 
 impl WorkerServer {
@@ -206,19 +223,31 @@ impl WorkerServer {
         Self {
             id,
             name,
-            job_id,
-            run_id,
             hash,
             controller_addr,
             logical,
             state: Arc::new(Mutex::new(None)),
             network: Arc::new(Mutex::new(None)),
+            registration: Arc::new(Mutex::new(RegisterWorkerReq {
+                worker_id: id.0,
+                job_id,
+                job_hash: hash.to_string(),
+                run_id: run_id.parse().unwrap_or_default(),
+                protocol_version: WORKER_PROTOCOL_VERSION,
+                ..Default::default()
+            })),
+            reusable: std::env::var(WORKER_REUSE_ENV)
+                .map(|v| v == "true")
+                .unwrap_or(false),
         }
     }
 
     pub async fn start_async(self) -> Result<(), Box<dyn std::error::Error>> {
+        let job_id = self.registration.lock().unwrap().job_id.clone();
         let _guard =
-            arroyo_server_common::init_logging(&format!("worker-{}-{}", self.id.0, self.job_id));
+            arroyo_server_common::init_logging(&format!("worker-{}-{}", self.id.0, job_id));
+
+        sandbox::apply_limits();
 
         let slots = std::env::var(arroyo_types::TASK_SLOTS_ENV)
             .map(|s| usize::from_str(&s).unwrap())
@@ -228,11 +257,12 @@ impl WorkerServer {
 
         let grpc_port = grpc_port("worker", 0);
 
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", grpc_port)).await?;
+        let listener = TcpListener::bind(bind_addr("worker", grpc_port)).await?;
         let local_addr = listener.local_addr()?;
 
         info!("Started worker-rpc for {} on {}", self.name, local_addr);
-        let mut client = ControllerGrpcClient::connect(self.controller_addr.clone()).await?;
+        let mut client =
+            ControllerGrpcClient::new(tls::connect_addr(self.controller_addr.clone()).await?);
 
         let mut network = NetworkManager::new(0);
         let data_port = network.open_listener().await;
@@ -240,17 +270,27 @@ impl WorkerServer {
         (*self.network.lock().unwrap()) = Some(network);
 
         info!(
-            "Started worker data for {} on 0.0.0.0:{}",
-            self.name, data_port
+            "Started worker data for {} on {}",
+            self.name,
+            bind_addr("worker", data_port)
         );
 
-        let id = self.id;
-        let local_ip = local_ip().unwrap();
+        let advertise_ip = advertise_ip("worker");
 
-        let rpc_address = format!("http://{}:{}", local_ip, local_addr.port());
-        let data_address = format!("{}:{}", local_ip, data_port);
-        let hash = self.hash;
-        let job_id = self.job_id.clone();
+        {
+            let mut registration = self.registration.lock().unwrap();
+            registration.node_id = node_id.0;
+            registration.rpc_address = format!(
+                "http://{}",
+                SocketAddr::new(advertise_ip, local_addr.port())
+            );
+            registration.data_address = SocketAddr::new(advertise_ip, data_port).to_string();
+            registration.resources = Some(WorkerResources {
+                slots: std::thread::available_parallelism().unwrap().get() as u64,
+            });
+            registration.slots = slots as u64;
+        }
+        let registration = self.registration.clone();
 
         let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
 
@@ -260,26 +300,13 @@ impl WorkerServer {
             // ideally, get a signal when the server is started...
             tokio::time::sleep(Duration::from_secs(2)).await;
 
-            client
-                .register_worker(Request::new(RegisterWorkerReq {
-                    worker_id: id.0,
-                    node_id: node_id.0,
-                    job_id,
-                    rpc_address,
-                    data_address,
-                    resources: Some(WorkerResources {
-                        slots: std::thread::available_parallelism().unwrap().get() as u64,
-                    }),
-                    job_hash: hash.to_string(),
-                    slots: slots as u64,
-                }))
-                .await
-                .unwrap();
+            let req = registration.lock().unwrap().clone();
+            client.register_worker(Request::new(req)).await.unwrap();
         });
 
         arroyo_server_common::grpc_server()
             .add_service(WorkerGrpcServer::new(self))
-            .serve_with_incoming(TcpListenerStream::new(listener))
+            .serve_with_incoming(tls::incoming(listener))
             .await?;
 
         shutdown_tx.send(0).unwrap();
@@ -291,6 +318,43 @@ impl WorkerServer {
     pub async fn start(self) -> Result<(), Box<dyn std::error::Error>> {
         self.start_async().await
     }
+
+    /// Tears down the engine of the finished job and tells the controller that this worker is
+    /// ready to be assigned another run of the pipeline
+    async fn become_idle(&self) -> Result<(), Status> {
+        if let Some(mut state) = self.state.lock().unwrap().take() {
+            state.running_engine.stop();
+        }
+
+        // the previous run's network manager was handed to its engine, so the next run needs a
+        // new one
+        let mut network = NetworkManager::new(0);
+        let data_port = network.open_listener().await;
+        *self.network.lock().unwrap() = Some(network);
+
+        let req = {
+            let mut registration = self.registration.lock().unwrap();
+            registration.data_address =
+                SocketAddr::new(advertise_ip("worker"), data_port).to_string();
+            WorkerIdleReq {
+                worker_id: self.id.0,
+                job_id: registration.job_id.clone(),
+                rpc_address: registration.rpc_address.clone(),
+            }
+        };
+
+        ControllerGrpcClient::new(
+            tls::connect_addr(self.controller_addr.clone())
+                .await
+                .map_err(|e| {
+                    Status::unavailable(format!("failed to connect to controller: {}", e))
+                })?,
+        )
+        .worker_idle(Request::new(req))
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[tonic::async_trait]
@@ -313,14 +377,22 @@ impl WorkerGrpc for WorkerServer {
 
         let program = Program::from_logical(self.name.to_string(), &self.logical, &req.tasks);
 
+        let (job_id, run_id) = {
+            let registration = self.registration.lock().unwrap();
+            (registration.job_id.clone(), registration.run_id.to_string())
+        };
+
+        // compacted state sent to a reused worker during an earlier run doesn't apply to this one
+        arroyo_state::parquet::clear_compacted_state(&job_id);
+
         let engine = {
             let network = { self.network.lock().unwrap().take().unwrap() };
 
             let engine = Engine::new(
                 program,
                 self.id,
-                self.job_id.clone(),
-                self.run_id.clone(),
+                job_id,
+                run_id,
                 self.controller_addr.clone(),
                 network,
                 req.tasks,
@@ -359,7 +431,19 @@ impl WorkerGrpc for WorkerServer {
                 let state = self.state.lock().unwrap();
 
                 if let Some(state) = state.as_ref() {
-                    state.sinks.clone()
+                    // sources that commit, like those that clean up files they've read, only
+                    // receive commits for the operators the controller is waiting on
+                    let mut senders = state.sinks.clone();
+                    senders.extend(
+                        state
+                            .sources
+                            .iter()
+                            .filter(|((operator_id, _), _)| {
+                                req.committing_operators.contains(operator_id)
+                            })
+                            .map(|(_, tx)| tx.clone()),
+                    );
+                    senders
                 } else {
                     return Err(Status::failed_precondition(
                         "Worker has not yet started execution",
@@ -379,7 +463,7 @@ impl WorkerGrpc for WorkerServer {
             let state = self.state.lock().unwrap();
 
             if let Some(state) = state.as_ref() {
-                state.sources.clone()
+                state.sources.values().cloned().collect::<Vec<_>>()
             } else {
                 return Err(Status::failed_precondition(
                     "Worker has not yet started execution",
@@ -407,7 +491,13 @@ impl WorkerGrpc for WorkerServer {
     ) -> Result<Response<StopExecutionResp>, Status> {
         let sources = {
             let state = self.state.lock().unwrap();
-            state.as_ref().unwrap().sources.clone()
+            state
+                .as_ref()
+                .unwrap()
+                .sources
+                .values()
+                .cloned()
+                .collect::<Vec<_>>()
         };
 
         let req = request.into_inner();
@@ -422,10 +512,56 @@ impl WorkerGrpc for WorkerServer {
         Ok(Response::new(StopExecutionResp {}))
     }
 
+    async fn align_sources(
+        &self,
+        request: Request<AlignSourcesReq>,
+    ) -> Result<Response<AlignSourcesResp>, Status> {
+        let req = request.into_inner();
+
+        // the controller sends every change to every worker; only apply the ones for our tasks
+        let senders: Vec<_> = {
+            let state = self.state.lock().unwrap();
+            let Some(state) = state.as_ref() else {
+                return Err(Status::failed_precondition(
+                    "Worker has not yet started execution",
+                ));
+            };
+
+            req.sources
+                .into_iter()
+                .filter_map(|s| {
+                    state
+                        .sources
+                        .get(&(s.operator_id, s.operator_subtask as usize))
+                        .map(|tx| (tx.clone(), s.paused))
+                })
+                .collect()
+        };
+
+        for (tx, paused) in senders {
+            // the task may have already finished
+            let _ = tx.send(ControlMessage::SetPaused { paused }).await;
+        }
+
+        Ok(Response::new(AlignSourcesResp {}))
+    }
+
     async fn job_finished(
         &self,
         _request: Request<JobFinishedReq>,
     ) -> Result<Response<JobFinishedResp>, Status> {
+        if self.reusable {
+            match self.become_idle().await {
+                Ok(()) => {
+                    info!("Job finished; waiting to be assigned another run");
+                    return Ok(Response::new(JobFinishedResp {}));
+                }
+                Err(e) => {
+                    warn!("Failed to make worker available for reuse, exiting: {}", e);
+                }
+            }
+        }
+
         let mut state = self.state.lock().unwrap();
         if let Some(engine) = state.as_mut() {
             engine.running_engine.stop();
@@ -438,4 +574,163 @@ impl WorkerGrpc for WorkerServer {
 
         Ok(Response::new(JobFinishedResp {}))
     }
+
+    async fn set_log_filter(
+        &self,
+        request: Request<SetLogFilterReq>,
+    ) -> Result<Response<SetLogFilterResp>, Status> {
+        let req = request.into_inner();
+
+        if let Some(operator_id) = &req.operator_id {
+            let runs_operator = self
+                .state
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|state| state.running_engine.runs_operator(operator_id))
+                .unwrap_or(false);
+
+            if !runs_operator {
+                return Ok(Response::new(SetLogFilterResp { applied: false }));
+            }
+        }
+
+        set_log_filter(&req.directives).map_err(Status::invalid_argument)?;
+
+        Ok(Response::new(SetLogFilterResp { applied: true }))
+    }
+
+    async fn sample_output(
+        &self,
+        request: Request<SampleOutputReq>,
+    ) -> Result<Response<SampleOutputResp>, Status> {
+        let req = request.into_inner();
+
+        Ok(Response::new(SampleOutputResp {
+            samples: output_samples::sample(&req.operator_id, req.count as usize),
+        }))
+    }
+
+    async fn get_column_stats(
+        &self,
+        _: Request<ColumnStatsReq>,
+    ) -> Result<Response<ColumnStatsResp>, Status> {
+        Ok(Response::new(ColumnStatsResp {
+            operators: column_stats::stats(),
+        }))
+    }
+
+    async fn assign_worker(
+        &self,
+        request: Request<AssignWorkerReq>,
+    ) -> Result<Response<AssignWorkerResp>, Status> {
+        if !self.reusable {
+            return Err(Status::failed_precondition("Worker is not reusable"));
+        }
+
+        if self.state.lock().unwrap().is_some() || self.network.lock().unwrap().is_none() {
+            return Err(Status::failed_precondition(
+                "Job is already running on this worker",
+            ));
+        }
+
+        let req = request.into_inner();
+        let registration = {
+            let mut registration = self.registration.lock().unwrap();
+            registration.job_id = req.job_id;
+            registration.run_id = req.run_id;
+            registration.clone()
+        };
+
+        info!(
+            "Assigned to run {} of job {}",
+            registration.run_id, registration.job_id
+        );
+
+        sandbox::apply_limits();
+
+        // register for the new run as a newly started worker would, once the controller has its
+        // response
+        let controller_addr = self.controller_addr.clone();
+        tokio::spawn(async move {
+            let result = match tls::connect_addr(controller_addr).await {
+                Ok(channel) => ControllerGrpcClient::new(channel)
+                    .register_worker(Request::new(registration))
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+
+            if let Err(e) = result {
+                error!("Failed to register with controller for new run: {}", e);
+                exit(1);
+            }
+        });
+
+        Ok(Response::new(AssignWorkerResp {}))
+    }
+
+    async fn inject_probe(
+        &self,
+        request: Request<InjectProbeReq>,
+    ) -> Result<Response<InjectProbeResp>, Status> {
+        let req = request.into_inner();
+
+        let senders: Vec<_> = {
+            let state = self.state.lock().unwrap();
+            let Some(state) = state.as_ref() else {
+                return Err(Status::failed_precondition(
+                    "Worker has not yet started execution",
+                ));
+            };
+
+            state
+                .sources
+                .iter()
+                .filter(|((operator_id, _), _)| {
+                    req.operator_id
+                        .as_ref()
+                        .map(|id| id == operator_id)
+                        .unwrap_or(true)
+                })
+                .map(|(_, tx)| tx.clone())
+                .collect()
+        };
+
+        let mut sources = 0;
+        for tx in senders {
+            // the task may have already finished
+            if tx
+                .send(ControlMessage::InjectProbe {
+                    probe_id: req.probe_id,
+                })
+                .await
+                .is_ok()
+            {
+                sources += 1;
+            }
+        }
+
+        Ok(Response::new(InjectProbeResp { sources }))
+    }
+
+    async fn load_compacted_state(
+        &self,
+        request: Request<LoadCompactedStateReq>,
+    ) -> Result<Response<LoadCompactedStateResp>, Status> {
+        let req = request.into_inner();
+        let job_id = self.registration.lock().unwrap().job_id.clone();
+
+        info!(
+            message = "received compacted state",
+            operator_id = req.operator_id,
+            epoch = req.epoch,
+            apply_epoch = req.apply_epoch,
+            files = req.files.len()
+        );
+        arroyo_state::parquet::load_compacted_state(&job_id, req);
+
+        Ok(Response::new(LoadCompactedStateResp {}))
+    }
 }
@@ -0,0 +1,250 @@
+//! Metrics reported by every subtask. They are registered once when the subtask starts and held
+//! by its [`Context`](crate::engine::Context) and [`Collector`](crate::engine::Collector), so
+//! updating them on the hot path doesn't require looking them up.
+//!
+//! The counters and watermark of each subtask are also tracked here so that the worker can report
+//! the progress of its subtasks to the controller with each heartbeat.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use arroyo_metrics::{counter_for_task, gauge_for_task, unregister_task_metrics};
+use arroyo_rpc::grpc::TaskProgress;
+use arroyo_types::{
+    TaskInfo, BYTES_RECV, BYTES_SENT, MESSAGES_RECV, MESSAGES_SENT, SOURCE_LAG, TX_QUEUE_REM,
+    TX_QUEUE_SIZE, WATERMARK,
+};
+use prometheus::{labels, IntCounter, IntGauge};
+
+use crate::engine::OutQueue;
+
+// handles to the metrics of each subtask running in this worker, keyed by operator id and subtask
+// index
+static TASK_PROGRESS: Mutex<BTreeMap<(String, usize), ProgressHandles>> =
+    Mutex::new(BTreeMap::new());
+
+struct ProgressHandles {
+    records_in: Option<IntCounter>,
+    records_out: Option<IntCounter>,
+    watermark: Option<IntGauge>,
+    source_lag: Option<IntGauge>,
+    // size of the subtask's state as of its last checkpoint
+    state_bytes: u64,
+}
+
+/// Metrics for the subtask's inputs and progress. When dropped (as the subtask finishes), all
+/// metrics registered for the subtask are unregistered.
+pub struct TaskMetrics {
+    task_info: TaskInfo,
+    pub messages_recv: Option<IntCounter>,
+    pub bytes_recv: Option<IntCounter>,
+    pub watermark: Option<IntGauge>,
+    /// Only registered for sources
+    pub source_lag: Option<IntGauge>,
+}
+
+impl TaskMetrics {
+    pub fn new(task_info: &TaskInfo, input_partitions: usize) -> Self {
+        Self {
+            task_info: task_info.clone(),
+            messages_recv: counter_for_task(
+                task_info,
+                MESSAGES_RECV,
+                "Count of messages received by this subtask",
+                HashMap::new(),
+            ),
+            bytes_recv: counter_for_task(
+                task_info,
+                BYTES_RECV,
+                "Count of bytes received by this subtask",
+                HashMap::new(),
+            ),
+            watermark: gauge_for_task(
+                task_info,
+                WATERMARK,
+                "Current watermark of this subtask, in milliseconds since the epoch",
+                HashMap::new(),
+            ),
+            source_lag: (input_partitions == 0)
+                .then(|| {
+                    gauge_for_task(
+                        task_info,
+                        SOURCE_LAG,
+                        "Milliseconds between when the last record read by this source was written and read",
+                        HashMap::new(),
+                    )
+                })
+                .flatten(),
+        }
+    }
+}
+
+impl Drop for TaskMetrics {
+    fn drop(&mut self) {
+        unregister_task_metrics(&self.task_info);
+        TASK_PROGRESS.lock().unwrap().remove(&(
+            self.task_info.operator_id.clone(),
+            self.task_info.task_index,
+        ));
+    }
+}
+
+/// Starts reporting the progress of the subtask; it is reported until its [`TaskMetrics`] are
+/// dropped
+pub fn track_progress(task_info: &TaskInfo, metrics: &TaskMetrics, output: &OutputMetrics) {
+    TASK_PROGRESS.lock().unwrap().insert(
+        (task_info.operator_id.clone(), task_info.task_index),
+        ProgressHandles {
+            records_in: metrics.messages_recv.clone(),
+            records_out: output.messages_sent.clone(),
+            watermark: metrics.watermark.clone(),
+            source_lag: metrics.source_lag.clone(),
+            state_bytes: 0,
+        },
+    );
+}
+
+/// Records the size of the subtask's state after it completes a checkpoint
+pub fn record_state_bytes(operator_id: &str, task_index: usize, bytes: u64) {
+    if let Some(handles) = TASK_PROGRESS
+        .lock()
+        .unwrap()
+        .get_mut(&(operator_id.to_string(), task_index))
+    {
+        handles.state_bytes = bytes;
+    }
+}
+
+/// The current progress of every subtask running in this worker
+pub fn task_progress() -> Vec<TaskProgress> {
+    TASK_PROGRESS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((operator_id, task_index), handles)| TaskProgress {
+            operator_id: operator_id.clone(),
+            task_index: *task_index as u32,
+            records_in: handles.records_in.as_ref().map(|c| c.get()).unwrap_or(0),
+            records_out: handles.records_out.as_ref().map(|c| c.get()).unwrap_or(0),
+            // the watermark gauge is in millis, and is 0 until the subtask has a watermark
+            watermark_micros: handles
+                .watermark
+                .as_ref()
+                .map(|g| g.get())
+                .filter(|millis| *millis > 0)
+                .map(|millis| millis as u64 * 1000),
+            state_bytes: handles.state_bytes,
+            source_lag_millis: handles.source_lag.as_ref().map(|g| g.get().max(0) as u64),
+        })
+        .collect()
+}
+
+/// Metrics for the subtask's outputs, indexed like its output queues
+#[derive(Clone, Default)]
+pub struct OutputMetrics {
+    pub messages_sent: Option<IntCounter>,
+    pub bytes_sent: Option<IntCounter>,
+    pub tx_queue_size: Vec<Vec<Option<IntGauge>>>,
+    pub tx_queue_rem: Vec<Vec<Option<IntGauge>>>,
+}
+
+impl OutputMetrics {
+    pub fn new(task_info: &TaskInfo, out_qs: &[Vec<OutQueue>]) -> Self {
+        let queue_gauges = |name: &'static str, help: &'static str| {
+            out_qs
+                .iter()
+                .enumerate()
+                .map(|(i, qs)| {
+                    (0..qs.len())
+                        .map(|j| {
+                            gauge_for_task(
+                                task_info,
+                                name,
+                                help,
+                                labels! {
+                                    "next_node".to_string() => format!("{}", i),
+                                    "next_node_idx".to_string() => format!("{}", j)
+                                },
+                            )
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        Self {
+            messages_sent: counter_for_task(
+                task_info,
+                MESSAGES_SENT,
+                "Count of messages sent by this subtask",
+                HashMap::new(),
+            ),
+            bytes_sent: counter_for_task(
+                task_info,
+                BYTES_SENT,
+                "Count of bytes sent by this subtask",
+                HashMap::new(),
+            ),
+            tx_queue_size: queue_gauges(TX_QUEUE_SIZE, "Size of a tx queue"),
+            tx_queue_rem: queue_gauges(TX_QUEUE_REM, "Remaining space in a tx queue"),
+        }
+    }
+
+    /// Records the current occupancy of output queue `idx` of node `i`
+    pub fn update_queue(&self, i: usize, idx: usize, capacity: usize, size: usize) {
+        if let Some(g) = &self.tx_queue_rem[i][idx] {
+            g.set(capacity as i64);
+        }
+        if let Some(g) = &self.tx_queue_size[i][idx] {
+            g.set(size as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_metrics_reregistered_after_drop() {
+        let task_info = TaskInfo::for_test("job-1", "metrics-test-operator");
+
+        let metrics = TaskMetrics::new(&task_info, 0);
+        assert!(metrics.messages_recv.is_some());
+        assert!(metrics.source_lag.is_some());
+        drop(metrics);
+
+        // the subtask is restarted in the same worker, this time with inputs
+        let metrics = TaskMetrics::new(&task_info, 1);
+        assert!(metrics.messages_recv.is_some());
+        assert!(metrics.source_lag.is_none());
+    }
+
+    #[test]
+    fn test_task_progress() {
+        let task_info = TaskInfo::for_test("job-1", "progress-test-operator");
+        let key = |p: &TaskProgress| p.operator_id == task_info.operator_id;
+
+        let metrics = TaskMetrics::new(&task_info, 1);
+        let output = OutputMetrics::new(&task_info, &[]);
+        track_progress(&task_info, &metrics, &output);
+
+        metrics.messages_recv.as_ref().unwrap().inc_by(10);
+        output.messages_sent.as_ref().unwrap().inc_by(4);
+        record_state_bytes(&task_info.operator_id, task_info.task_index, 1024);
+
+        let progress = task_progress().into_iter().find(key).unwrap();
+        assert_eq!(progress.records_in, 10);
+        assert_eq!(progress.records_out, 4);
+        assert_eq!(progress.watermark_micros, None);
+        assert_eq!(progress.state_bytes, 1024);
+        assert_eq!(progress.source_lag_millis, None);
+
+        metrics.watermark.as_ref().unwrap().set(5);
+        let progress = task_progress().into_iter().find(key).unwrap();
+        assert_eq!(progress.watermark_micros, Some(5000));
+
+        drop(metrics);
+        assert!(task_progress().into_iter().find(key).is_none());
+    }
+}
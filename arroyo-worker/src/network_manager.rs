@@ -1,7 +1,8 @@
 #![allow(clippy::redundant_slicing)]
-use arroyo_types::Message;
+use arroyo_server_common::tls::{self, ClusterStream};
+use arroyo_types::{bind_addr, Message, QueueConfig};
 use bincode::config;
-use std::{collections::HashMap, mem::size_of, pin::Pin, sync::Arc, time::Duration};
+use std::{collections::HashMap, mem::size_of, pin::Pin, sync::Arc};
 use tokio::{
     io::{self, BufReader, BufWriter},
     select,
@@ -12,7 +13,7 @@ use tracing::warn;
 use bytes::{Buf, BufMut};
 use tokio::{
     io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    net::TcpListener,
     sync::mpsc::{Receiver, Sender},
 };
 
@@ -22,7 +23,7 @@ use tokio_stream::StreamExt;
 
 use crate::inq_reader::InQReader;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Senders {
     senders: HashMap<Quad, Sender<QueueItem>>,
 }
@@ -62,7 +63,7 @@ impl Senders {
 
 pub struct InNetworkLink {
     _source: String,
-    stream: BufReader<TcpStream>,
+    stream: BufReader<ClusterStream>,
     senders: Senders,
 }
 
@@ -119,7 +120,7 @@ impl Header {
 }
 
 impl InNetworkLink {
-    pub fn new(source: String, stream: TcpStream, senders: Senders) -> Self {
+    pub fn new(source: String, stream: ClusterStream, senders: Senders) -> Self {
         InNetworkLink {
             _source: source,
             stream: BufReader::new(stream),
@@ -161,13 +162,13 @@ pub struct Quad {
 
 struct OutNetworkLink {
     _dest: String,
-    stream: BufWriter<TcpStream>,
+    stream: BufWriter<ClusterStream>,
     receivers: Vec<(Quad, Receiver<QueueItem>)>,
 }
 
 impl OutNetworkLink {
     pub async fn connect(dest: String) -> Self {
-        let stream = TcpStream::connect(&dest).await.unwrap();
+        let stream = tls::connect(&dest).await.unwrap();
 
         Self {
             _dest: dest,
@@ -191,7 +192,8 @@ impl OutNetworkLink {
                 };
                 sel.push(Box::pin(stream));
             }
-            let mut flush_interval: Interval = interval(Duration::from_millis(100));
+            let mut flush_interval: Interval =
+                interval(QueueConfig::from_env().network_flush_interval());
 
             loop {
                 select! {
@@ -214,7 +216,7 @@ impl OutNetworkLink {
 }
 
 enum InStreamsOrSenders {
-    InStreams(Vec<TcpStream>),
+    InStreams(Vec<ClusterStream>),
     Senders(Senders),
 }
 
@@ -235,9 +237,7 @@ impl NetworkManager {
 
     pub async fn open_listener(&mut self) -> u16 {
         let port = self.port;
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))
-            .await
-            .unwrap();
+        let listener = TcpListener::bind(bind_addr("worker", port)).await.unwrap();
         let port = listener.local_addr().unwrap().port();
 
         let streams = Arc::clone(&self.in_streams);
@@ -245,22 +245,30 @@ impl NetworkManager {
             loop {
                 let (stream, _) = listener.accept().await.unwrap();
 
-                let mut s = streams.lock().await;
-
-                match &mut *s {
-                    InStreamsOrSenders::InStreams(streams) => streams.push(stream),
-                    InStreamsOrSenders::Senders(ref senders) => {
-                        let senders = senders.clone();
-                        tokio::spawn(async move {
+                let streams = Arc::clone(&streams);
+                tokio::spawn(async move {
+                    let stream = match tls::accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            warn!("Failed to accept data connection: {:?}", e);
+                            return;
+                        }
+                    };
+
+                    let mut s = streams.lock().await;
+
+                    match &mut *s {
+                        InStreamsOrSenders::InStreams(streams) => streams.push(stream),
+                        InStreamsOrSenders::Senders(ref senders) => {
                             InNetworkLink::new(
                                 stream.local_addr().unwrap().to_string(),
                                 stream,
-                                senders,
+                                senders.clone(),
                             )
                             .start();
-                        });
+                        }
                     }
-                }
+                });
             }
         });
 
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use arroyo_macro::{process_fn, StreamNode};
+use arroyo_metrics::counter_for_task;
+use arroyo_types::*;
+use prometheus::IntCounter;
+use tracing::warn;
+
+use crate::engine::Context;
+
+/// Checks the records read from a source against a data quality assertion declared in SQL with
+/// ASSERT. Records that pass are forwarded, and violations are counted (labelled by the
+/// assertion) and then dropped, or if `fail` is set, fail the pipeline.
+///
+/// Violations routed to a dead-letter sink are also dropped here; the sink reads them from before
+/// this operator.
+#[derive(StreamNode)]
+pub struct Assertion<K: Key, T: Data> {
+    assertion: String,
+    fail: bool,
+    predicate_fn: Box<dyn Fn(&Record<K, T>) -> bool + Send>,
+    violations: Option<IntCounter>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = T)]
+impl<K: Key, T: Data> Assertion<K, T> {
+    fn name(&self) -> String {
+        "Assertion".to_string()
+    }
+
+    pub fn new(
+        assertion: &str,
+        fail: bool,
+        predicate_fn: impl Fn(&Record<K, T>) -> bool + Send + 'static,
+    ) -> Self {
+        Assertion {
+            assertion: assertion.to_string(),
+            fail,
+            predicate_fn: Box::new(predicate_fn),
+            violations: None,
+        }
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<K, T>) {
+        self.violations = counter_for_task(
+            &ctx.task_info,
+            ASSERTION_VIOLATIONS,
+            "Count of records that violated a data quality assertion",
+            HashMap::from([("assertion".to_string(), self.assertion.clone())]),
+        );
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, T>) {
+        if (self.predicate_fn)(record) {
+            ctx.collect(record.clone()).await;
+            return;
+        }
+
+        let first = self.violations.as_ref().map_or(true, |v| v.get() == 0);
+        if let Some(violations) = &self.violations {
+            violations.inc();
+        }
+
+        if self.fail {
+            ctx.report_error(
+                "Data quality assertion failed".to_string(),
+                format!("A record violated the assertion {}", self.assertion),
+            )
+            .await;
+            panic!("record violated assertion {}", self.assertion);
+        }
+
+        if first {
+            warn!(
+                "[{}] dropping records that violate assertion {}",
+                ctx.task_info.operator_name, self.assertion
+            );
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Keyed hashing and encryption, with keys read from the pipeline's secrets (see
+//! [`arroyo_types::pipeline_secret`]) so that they appear in neither the query nor the job's
+//! configuration. HMAC keys are used as-is, while AES-GCM keys must be 32 bytes, base64-encoded.
+//!
+//! Each subtask loads a key the first time it's used and keeps it for the rest of its run. A key
+//! that is missing or invalid is reported as an error for the operator, which then fails.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use arroyo_rpc::ControlResp;
+use arroyo_types::{pipeline_secret, TaskInfo, PIPELINE_SECRETS_DIR_ENV};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::mpsc::Sender;
+
+use crate::operators::UserError;
+
+const NONCE_SIZE: usize = 12;
+
+tokio::task_local! {
+    /// The keys of the subtask whose operator is running on the current task
+    pub static CRYPTO_KEYS: CryptoKeys;
+}
+
+#[derive(Default)]
+struct LoadedKeys {
+    // keys that failed to load keep their error, so that it's only reported once
+    hmac: HashMap<String, Result<Hmac<Sha256>, String>>,
+    aes: HashMap<String, Result<Aes256Gcm, String>>,
+}
+
+/// The keys that a subtask has loaded, by the name of the secret holding them
+#[derive(Clone)]
+pub struct CryptoKeys {
+    task_info: TaskInfo,
+    control_tx: Sender<ControlResp>,
+    keys: Arc<Mutex<LoadedKeys>>,
+}
+
+impl CryptoKeys {
+    pub fn new(task_info: TaskInfo, control_tx: Sender<ControlResp>) -> Self {
+        Self {
+            task_info,
+            control_tx,
+            keys: Arc::new(Mutex::new(LoadedKeys::default())),
+        }
+    }
+
+    fn get<K: Clone>(
+        &self,
+        name: &str,
+        keys: impl FnOnce(&mut LoadedKeys) -> &mut HashMap<String, Result<K, String>>,
+        load: impl FnOnce(&str) -> Result<K, UserError>,
+    ) -> K {
+        let result = {
+            let mut loaded = self.keys.lock().unwrap();
+            let loaded = keys(&mut *loaded);
+            if let Some(result) = loaded.get(name) {
+                result.clone()
+            } else {
+                let result = load(name).map_err(|e| {
+                    self.control_tx
+                        .try_send(ControlResp::Error {
+                            operator_id: self.task_info.operator_id.clone(),
+                            task_index: self.task_info.task_index,
+                            message: e.name.clone(),
+                            details: e.details.clone(),
+                        })
+                        .ok();
+                    format!("{}: {}", e.name, e.details)
+                });
+                loaded.insert(name.to_string(), result.clone());
+                result
+            }
+        };
+
+        result.unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+// loads a key with the subtask's keys, or on its own outside of an operator (e.g., in tests)
+fn key<K: Clone>(
+    name: &str,
+    keys: impl FnOnce(&mut LoadedKeys) -> &mut HashMap<String, Result<K, String>>,
+    load: impl FnOnce(&str) -> Result<K, UserError> + Copy,
+) -> K {
+    CRYPTO_KEYS
+        .try_with(|crypto_keys| crypto_keys.get(name, keys, load))
+        .unwrap_or_else(|_| load(name).unwrap_or_else(|e| panic!("{}: {}", e.name, e.details)))
+}
+
+fn secret(name: &str) -> Result<Vec<u8>, UserError> {
+    pipeline_secret(name).ok_or_else(|| {
+        UserError::new(
+            "Missing encryption key",
+            format!(
+                "the secret '{}' is not set; secrets are read from the directory given by {}",
+                name, PIPELINE_SECRETS_DIR_ENV
+            ),
+        )
+    })
+}
+
+fn hmac_key(key: &[u8]) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length
+    Hmac::<Sha256>::new_from_slice(key).unwrap()
+}
+
+fn aes_key(key: &[u8]) -> Option<Aes256Gcm> {
+    let key = STANDARD
+        .decode(std::str::from_utf8(key).ok()?.trim())
+        .ok()?;
+    Aes256Gcm::new_from_slice(&key).ok()
+}
+
+fn load_hmac_key(name: &str) -> Result<Hmac<Sha256>, UserError> {
+    Ok(hmac_key(&secret(name)?))
+}
+
+fn load_aes_key(name: &str) -> Result<Aes256Gcm, UserError> {
+    aes_key(&secret(name)?).ok_or_else(|| {
+        UserError::new(
+            "Invalid encryption key",
+            format!(
+                "the secret '{}' must hold a base64-encoded 32-byte AES-GCM key",
+                name
+            ),
+        )
+    })
+}
+
+fn mac(mut mac: Hmac<Sha256>, value: &[u8]) -> Vec<u8> {
+    mac.update(value);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn encrypt(cipher: &Aes256Gcm, value: &[u8]) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, value)
+        .expect("AES-GCM encryption failed");
+
+    let mut result = nonce.to_vec();
+    result.extend(ciphertext);
+    result
+}
+
+fn decrypt(cipher: &Aes256Gcm, value: &[u8]) -> Option<Vec<u8>> {
+    if value.len() < NONCE_SIZE {
+        return None;
+    }
+
+    let (nonce, ciphertext) = value.split_at(NONCE_SIZE);
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+pub fn base64_encode(value: Vec<u8>) -> String {
+    STANDARD.encode(value)
+}
+
+pub fn base64_decode(value: String) -> Option<Vec<u8>> {
+    STANDARD.decode(value).ok()
+}
+
+pub fn hex_encode(value: Vec<u8>) -> String {
+    hex::encode(value)
+}
+
+pub fn hex_decode(value: String) -> Option<Vec<u8>> {
+    hex::decode(value).ok()
+}
+
+pub fn hmac_sha256(value: Vec<u8>, key_name: &str) -> Vec<u8> {
+    mac(key(key_name, |k| &mut k.hmac, load_hmac_key), &value)
+}
+
+/// Encrypts `value` with a random nonce, which is prepended to the ciphertext
+pub fn aes_gcm_encrypt(value: Vec<u8>, key_name: &str) -> Vec<u8> {
+    encrypt(&key(key_name, |k| &mut k.aes, load_aes_key), &value)
+}
+
+/// Decrypts a value produced by [`aes_gcm_encrypt`], returning None if it was not encrypted with
+/// the same key or has been modified
+pub fn aes_gcm_decrypt(value: Vec<u8>, key_name: &str) -> Option<Vec<u8>> {
+    decrypt(&key(key_name, |k| &mut k.aes, load_aes_key), &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aes_gcm_round_trip() {
+        let cipher = aes_key(STANDARD.encode([7u8; 32]).as_bytes()).unwrap();
+
+        let encrypted = encrypt(&cipher, b"secret value");
+        assert_ne!(&encrypted[NONCE_SIZE..], b"secret value");
+        assert_eq!(decrypt(&cipher, &encrypted), Some(b"secret value".to_vec()));
+
+        let mut tampered = encrypted;
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(decrypt(&cipher, &tampered), None);
+    }
+
+    #[test]
+    fn test_invalid_aes_key() {
+        assert!(aes_key(STANDARD.encode([7u8; 16]).as_bytes()).is_none());
+        assert!(aes_key(b"not base64!").is_none());
+    }
+
+    #[test]
+    fn test_missing_key_is_reported_once() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let keys = CryptoKeys::new(TaskInfo::for_test("job", "operator"), tx);
+
+        for _ in 0..2 {
+            let result = CRYPTO_KEYS.sync_scope(keys.clone(), || {
+                std::panic::catch_unwind(|| hmac_sha256(b"value".to_vec(), "missing_key"))
+            });
+            assert!(result.is_err());
+        }
+
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ControlResp::Error { message, .. }) if message == "Missing encryption key"
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(base64_encode(b"arroyo".to_vec()), "YXJyb3lv");
+        assert_eq!(
+            base64_decode("YXJyb3lv".to_string()),
+            Some(b"arroyo".to_vec())
+        );
+        assert_eq!(hex_encode(vec![0xde, 0xad]), "dead");
+        assert_eq!(hex_decode("not hex".to_string()), None);
+    }
+}
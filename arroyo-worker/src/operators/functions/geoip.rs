@@ -0,0 +1,106 @@
+//! Lookups against MaxMind GeoIP databases. The databases are read from the paths in the
+//! pipeline's `GEOIP_DATABASES` environment variable when first used and reloaded periodically,
+//! so that updated databases are picked up without restarting the pipeline.
+
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use arroyo_types::{pipeline_env, GEOIP_DATABASES_VAR};
+use maxminddb::{geoip2, Reader};
+use once_cell::sync::Lazy;
+use tracing::{info, warn};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Default)]
+struct Databases {
+    country: Option<Arc<Reader<Vec<u8>>>>,
+    asn: Option<Arc<Reader<Vec<u8>>>>,
+}
+
+impl Databases {
+    // databases that fail to load are left as they were, so a bad update doesn't disable lookups
+    fn load(&mut self, paths: &[String]) {
+        for path in paths {
+            let reader = match Reader::open_readfile(path) {
+                Ok(reader) => Arc::new(reader),
+                Err(e) => {
+                    warn!("failed to load GeoIP database {}: {}", path, e);
+                    continue;
+                }
+            };
+
+            let database_type = &reader.metadata.database_type;
+            if database_type.contains("ASN") {
+                self.asn = Some(reader);
+            } else if database_type.contains("Country") || database_type.contains("City") {
+                self.country = Some(reader);
+            } else {
+                warn!(
+                    "GeoIP database {} has unsupported type {}",
+                    path, database_type
+                );
+                continue;
+            }
+
+            info!("loaded GeoIP database {}", path);
+        }
+    }
+}
+
+static DATABASES: Lazy<RwLock<Databases>> = Lazy::new(|| {
+    let paths: Vec<String> = pipeline_env(GEOIP_DATABASES_VAR)
+        .map(|paths| {
+            paths
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if paths.is_empty() {
+        warn!(
+            "GeoIP functions are used, but {} is not set for this pipeline",
+            GEOIP_DATABASES_VAR
+        );
+        return RwLock::new(Databases::default());
+    }
+
+    let mut databases = Databases::default();
+    databases.load(&paths);
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(RELOAD_INTERVAL);
+        let mut databases = DATABASES.read().unwrap().clone();
+        databases.load(&paths);
+        *DATABASES.write().unwrap() = databases;
+    });
+
+    RwLock::new(databases)
+});
+
+/// The ISO code of the country the address is located in
+pub fn geoip_country(ip: String) -> Option<String> {
+    let ip: IpAddr = ip.parse().ok()?;
+    let reader = DATABASES.read().unwrap().country.clone()?;
+    let country: geoip2::Country = reader.lookup(ip).ok()?;
+    country.country?.iso_code.map(|c| c.to_string())
+}
+
+/// The number of the autonomous system the address belongs to
+pub fn geoip_asn(ip: String) -> Option<i64> {
+    let ip: IpAddr = ip.parse().ok()?;
+    let reader = DATABASES.read().unwrap().asn.clone()?;
+    let asn: geoip2::Asn = reader.lookup(ip).ok()?;
+    asn.autonomous_system_number.map(|n| n as i64)
+}
+
+/// The organization that operates the autonomous system the address belongs to
+pub fn geoip_asn_org(ip: String) -> Option<String> {
+    let ip: IpAddr = ip.parse().ok()?;
+    let reader = DATABASES.read().unwrap().asn.clone()?;
+    let asn: geoip2::Asn = reader.lookup(ip).ok()?;
+    asn.autonomous_system_organization.map(|o| o.to_string())
+}
@@ -0,0 +1,90 @@
+use std::net::IpAddr;
+
+pub fn ip_version(ip: String) -> Option<i32> {
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(_) => Some(4),
+        IpAddr::V6(_) => Some(6),
+    }
+}
+
+pub fn normalize_ip(ip: String) -> Option<String> {
+    ip.parse::<IpAddr>().ok().map(|ip| ip.to_string())
+}
+
+// parses `address/prefix`, treating a bare address as a network containing only itself
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u32)> {
+    let (network, prefix) = match cidr.split_once('/') {
+        Some((network, prefix)) => (network.parse().ok()?, prefix.parse().ok()?),
+        None => {
+            let network: IpAddr = cidr.parse().ok()?;
+            (network, width(&network))
+        }
+    };
+
+    (prefix <= width(&network)).then_some((network, prefix))
+}
+
+fn width(ip: &IpAddr) -> u32 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn bits(ip: &IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(*ip) as u128,
+        IpAddr::V6(ip) => u128::from(*ip),
+    }
+}
+
+/// Whether `ip` is in the network `cidr` (e.g., `10.0.0.0/8`); addresses are never in networks
+/// of the other IP version
+pub fn cidr_contains(cidr: String, ip: String) -> Option<bool> {
+    let (network, prefix) = parse_cidr(&cidr)?;
+    let ip: IpAddr = ip.parse().ok()?;
+
+    if network.is_ipv4() != ip.is_ipv4() {
+        return Some(false);
+    }
+
+    // compare only the network bits
+    let host_bits = width(&ip) - prefix;
+    Some(
+        bits(&network).checked_shr(host_bits).unwrap_or(0)
+            == bits(&ip).checked_shr(host_bits).unwrap_or(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_parsing() {
+        assert_eq!(ip_version("192.168.0.1".to_string()), Some(4));
+        assert_eq!(ip_version("::1".to_string()), Some(6));
+        assert_eq!(ip_version("not an ip".to_string()), None);
+        assert_eq!(
+            normalize_ip("2001:0db8:0000:0000:0000:0000:0000:0001".to_string()),
+            Some("2001:db8::1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let contains = |cidr: &str, ip: &str| cidr_contains(cidr.to_string(), ip.to_string());
+
+        assert_eq!(contains("10.0.0.0/8", "10.20.30.40"), Some(true));
+        assert_eq!(contains("10.0.0.0/8", "11.0.0.1"), Some(false));
+        assert_eq!(contains("192.168.1.0/24", "192.168.1.255"), Some(true));
+        assert_eq!(contains("192.168.1.7", "192.168.1.7"), Some(true));
+        assert_eq!(contains("0.0.0.0/0", "8.8.8.8"), Some(true));
+        assert_eq!(contains("::/0", "2001:db8::1"), Some(true));
+        assert_eq!(contains("2001:db8::/32", "2001:db8:ffff::1"), Some(true));
+        assert_eq!(contains("2001:db8::/32", "2001:db9::1"), Some(false));
+        assert_eq!(contains("10.0.0.0/8", "::1"), Some(false));
+        assert_eq!(contains("10.0.0.0/33", "10.0.0.1"), None);
+        assert_eq!(contains("10.0.0.0/8", "not an ip"), None);
+    }
+}
@@ -1,5 +1,8 @@
+pub mod crypto;
 pub mod datetime;
+pub mod geoip;
 pub mod hash;
+pub mod ip;
 pub mod json;
 pub mod regexp;
 pub mod strings;
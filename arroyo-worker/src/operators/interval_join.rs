@@ -0,0 +1,212 @@
+use std::{
+    marker::PhantomData,
+    time::{Duration, SystemTime},
+};
+
+use arroyo_macro::{co_process_fn, StreamNode};
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::{KeyTimeMultiMap, TimeKeyMap};
+use arroyo_types::*;
+
+use crate::engine::Context;
+
+/// Matches each record from the left input with the first record (by event time) from the right
+/// input with the same key whose timestamp is within `window` after it, emitting the left record
+/// with no match once the window has passed without one.
+///
+/// Left records are buffered until the watermark passes the end of their window, at which point
+/// every right record that could match has arrived and the first one can be chosen, so each left
+/// record is emitted exactly once. Matches are emitted at the timestamp of the right record, and
+/// unmatched records at the end of their window. Right records are retained for `window`, as they
+/// may match any left record up to that far before them. Records from either side that arrive
+/// after the watermark has passed them are dropped.
+#[derive(StreamNode)]
+pub struct IntervalJoin<K: Key, T1: Data, T2: Data> {
+    window: Duration,
+    _t: PhantomData<(K, T1, T2)>,
+}
+
+/// The first of `right` with a timestamp in `[left_timestamp, left_timestamp + window]`, where
+/// `right` is in timestamp order
+fn first_match<'a, T: 'a>(
+    left_timestamp: SystemTime,
+    window: Duration,
+    right: impl Iterator<Item = (SystemTime, &'a T)>,
+) -> Option<(SystemTime, &'a T)> {
+    right
+        .skip_while(|(timestamp, _)| *timestamp < left_timestamp)
+        .take_while(|(timestamp, _)| *timestamp <= left_timestamp + window)
+        .next()
+}
+
+#[co_process_fn(in_k1=K, in_t1=T1, in_k2=K, in_t2=T2, out_k=K, out_t=(T1, Option<T2>))]
+impl<K: Key, T1: Data, T2: Data> IntervalJoin<K, T1, T2> {
+    fn name(&self) -> String {
+        "IntervalJoin".to_string()
+    }
+
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![
+            TableDescriptor {
+                name: "l".to_string(),
+                description: "interval join left records, by the end of their window".to_string(),
+                table_type: TableType::TimeKeyMap as i32,
+                delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+                write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+                retention_micros: 0,
+            },
+            TableDescriptor {
+                name: "r".to_string(),
+                description: "interval join right records".to_string(),
+                table_type: TableType::KeyTimeMultiMap as i32,
+                delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+                write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+                retention_micros: self.window.as_micros() as u64,
+            },
+        ]
+    }
+
+    async fn process_left(
+        &mut self,
+        record: &Record<K, T1>,
+        ctx: &mut Context<K, (T1, Option<T2>)>,
+    ) {
+        if let Some(watermark) = ctx.watermark() {
+            if record.timestamp < watermark {
+                return;
+            }
+        };
+
+        let mut key = record.key.clone().unwrap();
+        let end = record.timestamp + self.window;
+
+        let mut left_state: TimeKeyMap<K, Vec<T1>, _> =
+            ctx.state.get_time_key_map('l', ctx.watermark()).await;
+        let mut values = left_state.get(end, &mut key).cloned().unwrap_or_default();
+        values.push(record.value.clone());
+        left_state.insert(end, key, values);
+    }
+
+    async fn process_right(
+        &mut self,
+        record: &Record<K, T2>,
+        ctx: &mut Context<K, (T1, Option<T2>)>,
+    ) {
+        if let Some(watermark) = ctx.watermark() {
+            if record.timestamp < watermark {
+                return;
+            }
+        };
+
+        let mut right_state: KeyTimeMultiMap<K, T2, _> =
+            ctx.state.get_key_time_multi_map('r').await;
+        right_state
+            .insert(
+                record.timestamp,
+                record.key.clone().unwrap(),
+                record.value.clone(),
+            )
+            .await;
+    }
+
+    async fn handle_watermark(
+        &mut self,
+        _watermark: SystemTime,
+        ctx: &mut Context<K, (T1, Option<T2>)>,
+    ) {
+        let Some(watermark) = ctx.watermark() else {
+            return;
+        };
+
+        let mut expired = vec![];
+        {
+            let mut left_state: TimeKeyMap<K, Vec<T1>, _> =
+                ctx.state.get_time_key_map('l', Some(watermark)).await;
+            while let Some(end) = left_state.get_min_time() {
+                if end > watermark {
+                    break;
+                }
+                expired.extend(
+                    left_state
+                        .evict_for_timestamp(end)
+                        .into_iter()
+                        .map(|(key, values)| (end, key, values)),
+                );
+            }
+        }
+
+        let mut records = vec![];
+        {
+            let mut right_state: KeyTimeMultiMap<K, T2, _> =
+                ctx.state.get_key_time_multi_map('r').await;
+            for (end, mut key, values) in expired {
+                let start = end - self.window;
+                for value in values {
+                    let (timestamp, right) = match right_state
+                        .get_all_values_with_timestamps(&mut key)
+                        .await
+                        .and_then(|right| first_match(start, self.window, right))
+                    {
+                        Some((timestamp, right)) => (timestamp, Some(right.clone())),
+                        None => (end, None),
+                    };
+
+                    records.push(Record {
+                        timestamp,
+                        key: Some(key.clone()),
+                        value: (value, right),
+                    });
+                }
+            }
+
+            // unemitted left records all end after the watermark, so they can't match earlier
+            right_state.expire_entries_before(watermark - self.window);
+        }
+
+        for record in records {
+            ctx.collect(record).await;
+        }
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
+    async fn handle_checkpoint(
+        &mut self,
+        _: &CheckpointBarrier,
+        ctx: &mut Context<K, (T1, Option<T2>)>,
+    ) {
+        let mut left_state: TimeKeyMap<K, Vec<T1>, _> =
+            ctx.state.get_time_key_map('l', ctx.watermark()).await;
+        left_state.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_match() {
+        let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let window = Duration::from_secs(10);
+        let right = vec![(t(5), "a"), (t(12), "b"), (t(15), "c"), (t(30), "d")];
+        let first = |left| {
+            first_match(t(left), window, right.iter().map(|(time, v)| (*time, v))).map(|(_, v)| *v)
+        };
+
+        assert_eq!(first(0), Some("a"));
+        assert_eq!(first(5), Some("a"));
+        assert_eq!(first(6), Some("b"));
+        assert_eq!(first(13), Some("c"));
+        assert_eq!(first(20), Some("d"));
+        assert_eq!(first(16), None);
+        assert_eq!(first(31), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use arroyo_metrics::{counter_for_task, gauge_for_task};
+use arroyo_types::{
+    TaskInfo, JOIN_BUFFERED_KEYS, JOIN_BUFFERED_ROWS, JOIN_EXPIRED_KEYS, JOIN_MATCHED_RECORDS,
+    JOIN_ORPHANED_KEYS, JOIN_RECORDS,
+};
+use prometheus::{IntCounter, IntGauge};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JoinSide {
+    Left,
+    Right,
+}
+
+impl JoinSide {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JoinSide::Left => "left",
+            JoinSide::Right => "right",
+        }
+    }
+}
+
+struct SideMetrics {
+    records: Option<IntCounter>,
+    matched_records: Option<IntCounter>,
+    buffered_rows: Option<IntGauge>,
+    buffered_keys: Option<IntGauge>,
+    expired_keys: Option<IntCounter>,
+    orphaned_keys: Option<IntCounter>,
+}
+
+impl SideMetrics {
+    fn new(task_info: &TaskInfo, side: JoinSide) -> Self {
+        let labels = || HashMap::from([("side".to_string(), side.as_str().to_string())]);
+
+        SideMetrics {
+            records: counter_for_task(
+                task_info,
+                JOIN_RECORDS,
+                "Count of records received by this join on each side",
+                labels(),
+            ),
+            matched_records: counter_for_task(
+                task_info,
+                JOIN_MATCHED_RECORDS,
+                "Count of records that matched at least one buffered row from the other side \
+                of this join",
+                labels(),
+            ),
+            buffered_rows: gauge_for_task(
+                task_info,
+                JOIN_BUFFERED_ROWS,
+                "Number of rows this join holds in state for each side",
+                labels(),
+            ),
+            buffered_keys: gauge_for_task(
+                task_info,
+                JOIN_BUFFERED_KEYS,
+                "Number of distinct keys this join holds rows for on each side",
+                labels(),
+            ),
+            expired_keys: counter_for_task(
+                task_info,
+                JOIN_EXPIRED_KEYS,
+                "Count of keys whose rows were all expired from this join's state",
+                labels(),
+            ),
+            orphaned_keys: counter_for_task(
+                task_info,
+                JOIN_ORPHANED_KEYS,
+                "Count of keys whose rows expired from this join's state without ever matching a \
+                row from the other side",
+                labels(),
+            ),
+        }
+    }
+}
+
+/// Metrics for each side of a join, describing how much state it buffers and how well its inputs
+/// match, which are registered once the operator starts
+#[derive(Default)]
+pub struct JoinMetrics {
+    sides: Option<(SideMetrics, SideMetrics)>,
+}
+
+impl JoinMetrics {
+    pub fn register(&mut self, task_info: &TaskInfo) {
+        self.sides = Some((
+            SideMetrics::new(task_info, JoinSide::Left),
+            SideMetrics::new(task_info, JoinSide::Right),
+        ));
+    }
+
+    fn side(&self, side: JoinSide) -> Option<&SideMetrics> {
+        self.sides.as_ref().map(|(left, right)| match side {
+            JoinSide::Left => left,
+            JoinSide::Right => right,
+        })
+    }
+
+    pub fn received(&self, side: JoinSide) {
+        if let Some(c) = self.side(side).and_then(|m| m.records.as_ref()) {
+            c.inc();
+        }
+    }
+
+    /// Records that `records` of those received on `side` matched rows of the other side
+    pub fn matched(&self, side: JoinSide, records: usize) {
+        if let Some(c) = self.side(side).and_then(|m| m.matched_records.as_ref()) {
+            c.inc_by(records as u64);
+        }
+    }
+
+    /// Sets the number of keys and rows currently held in state for `side`
+    pub fn buffered(&self, side: JoinSide, keys: usize, rows: usize) {
+        let Some(metrics) = self.side(side) else {
+            return;
+        };
+        if let Some(g) = &metrics.buffered_keys {
+            g.set(keys as i64);
+        }
+        if let Some(g) = &metrics.buffered_rows {
+            g.set(rows as i64);
+        }
+    }
+
+    /// Records keys of `side` that were removed from state, `orphaned` of which never matched
+    pub fn expired(&self, side: JoinSide, keys: usize, orphaned: usize) {
+        let Some(metrics) = self.side(side) else {
+            return;
+        };
+        if let Some(c) = &metrics.expired_keys {
+            c.inc_by(keys as u64);
+        }
+        if let Some(c) = &metrics.orphaned_keys {
+            c.inc_by(orphaned as u64);
+        }
+    }
+}
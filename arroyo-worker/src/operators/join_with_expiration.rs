@@ -1,4 +1,5 @@
 use std::{
+    collections::HashSet,
     marker::PhantomData,
     time::{Duration, SystemTime},
 };
@@ -10,6 +11,8 @@ use arroyo_types::*;
 
 use crate::engine::Context;
 
+use super::join_metrics::{JoinMetrics, JoinSide};
+
 #[derive(StreamNode)]
 pub struct JoinWithExpiration<
     K: Key,
@@ -21,6 +24,10 @@ pub struct JoinWithExpiration<
     left_expiration: Duration,
     right_expiration: Duration,
     processor: P,
+    // keys that have produced a match since the operator started, so that keys whose rows expire
+    // without ever matching can be reported
+    matched: HashSet<K>,
+    metrics: JoinMetrics,
     _t: PhantomData<(K, T1, T2, Output)>,
 }
 
@@ -328,6 +335,8 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
             left_expiration,
             right_expiration,
             processor,
+            matched: HashSet::new(),
+            metrics: JoinMetrics::default(),
             _t: PhantomData,
         }
     }
@@ -353,6 +362,10 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
         ]
     }
 
+    async fn on_start(&mut self, ctx: &mut Context<K, Output>) {
+        self.metrics.register(&ctx.task_info);
+    }
+
     async fn process_left(&mut self, record: &Record<K, T1>, ctx: &mut Context<K, Output>) {
         if let Some(watermark) = ctx.watermark() {
             if record.timestamp < watermark {
@@ -369,6 +382,7 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
             .is_none();
         let mut right_state: KeyTimeMultiMap<K, T2, _> =
             ctx.state.get_key_time_multi_map('r').await;
+        let matched = right_state.contains_key(&key);
         let records = {
             let mut records = vec![];
             if let Some(right_rows) = right_state.get_all_values_with_timestamps(&mut key).await {
@@ -402,8 +416,19 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
         for record in records {
             ctx.collect(record).await;
         }
-        let mut left_state = ctx.state.get_key_time_multi_map('l').await;
+        if matched {
+            self.matched.insert(key.clone());
+        }
+        self.metrics.received(JoinSide::Left);
+        self.metrics.matched(JoinSide::Left, matched as usize);
+
+        let mut left_state: KeyTimeMultiMap<K, T1, _> = ctx.state.get_key_time_multi_map('l').await;
         left_state.insert(record.timestamp, key, value).await;
+        self.metrics.buffered(
+            JoinSide::Left,
+            left_state.key_count(),
+            left_state.row_count(),
+        );
     }
 
     async fn process_right(&mut self, record: &Record<K, T2>, ctx: &mut Context<K, Output>) {
@@ -424,8 +449,14 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
         right_state
             .insert(record.timestamp, key_to_insert, value_to_insert)
             .await;
+        self.metrics.buffered(
+            JoinSide::Right,
+            right_state.key_count(),
+            right_state.row_count(),
+        );
 
         let mut left_state: KeyTimeMultiMap<K, T1, _> = ctx.state.get_key_time_multi_map('l').await;
+        let matched = left_state.contains_key(&key);
         let records = {
             let mut records = vec![];
             if let Some(left_rows) = left_state.get_all_values_with_timestamps(&mut key).await {
@@ -459,6 +490,11 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
         for record in records {
             ctx.collect(record).await;
         }
+        if matched {
+            self.matched.insert(key);
+        }
+        self.metrics.received(JoinSide::Right);
+        self.metrics.matched(JoinSide::Right, matched as usize);
     }
 
     async fn handle_watermark(
@@ -468,10 +504,49 @@ impl<K: Key, T1: Data, T2: Data, Output: Data, P: JoinProcessor<K, T1, T2, Outpu
     ) {
         let Some(watermark) = ctx.watermark() else {return};
         let mut left_state: KeyTimeMultiMap<K, T1, _> = ctx.state.get_key_time_multi_map('l').await;
-        left_state.expire_entries_before(watermark - self.left_expiration);
+        let expired_left = left_state.expire_entries_before(watermark - self.left_expiration);
+        self.metrics.buffered(
+            JoinSide::Left,
+            left_state.key_count(),
+            left_state.row_count(),
+        );
+
         let mut right_state: KeyTimeMultiMap<K, T2, _> =
             ctx.state.get_key_time_multi_map('r').await;
-        right_state.expire_entries_before(watermark - self.right_expiration);
+        // rows restored from a checkpoint aren't in `matched`, but any that are still buffered
+        // on the other side will have matched them
+        let orphaned = expired_left
+            .iter()
+            .filter(|k| !self.matched.contains(*k) && !right_state.contains_key(k))
+            .count();
+        self.metrics
+            .expired(JoinSide::Left, expired_left.len(), orphaned);
+        for key in &expired_left {
+            if !right_state.contains_key(key) {
+                self.matched.remove(key);
+            }
+        }
+
+        let expired_right = right_state.expire_entries_before(watermark - self.right_expiration);
+        self.metrics.buffered(
+            JoinSide::Right,
+            right_state.key_count(),
+            right_state.row_count(),
+        );
+
+        let left_state: KeyTimeMultiMap<K, T1, _> = ctx.state.get_key_time_multi_map('l').await;
+        let orphaned = expired_right
+            .iter()
+            .filter(|k| !self.matched.contains(*k) && !left_state.contains_key(k))
+            .count();
+        self.metrics
+            .expired(JoinSide::Right, expired_right.len(), orphaned);
+        for key in &expired_right {
+            if !left_state.contains_key(key) {
+                self.matched.remove(key);
+            }
+        }
+
         ctx.broadcast(arroyo_types::Message::Watermark(watermark))
             .await;
     }
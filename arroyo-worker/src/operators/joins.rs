@@ -7,8 +7,10 @@ use arroyo_types::*;
 
 use crate::engine::Context;
 
+use super::join_metrics::{JoinMetrics, JoinSide};
 use super::{
-    InstantWindowAssigner, SlidingWindowAssigner, TimeWindowAssigner, TumblingWindowAssigner,
+    CalendarWindowAssigner, InstantWindowAssigner, SlidingWindowAssigner, TimeWindowAssigner,
+    TumblingWindowAssigner,
 };
 
 #[derive(StreamNode)]
@@ -21,6 +23,7 @@ pub struct WindowedHashJoin<
 > {
     assigner1: W1,
     assigner2: W2,
+    metrics: JoinMetrics,
     _t: PhantomData<(K, T1, T2)>,
 }
 
@@ -30,10 +33,12 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
 {
     pub fn tumbling_window(
         size: Duration,
+        offset: Duration,
     ) -> WindowedHashJoin<K, T1, T2, TumblingWindowAssigner, TumblingWindowAssigner> {
         WindowedHashJoin {
-            assigner1: TumblingWindowAssigner { size },
-            assigner2: TumblingWindowAssigner { size },
+            assigner1: TumblingWindowAssigner { size, offset },
+            assigner2: TumblingWindowAssigner { size, offset },
+            metrics: JoinMetrics::default(),
             _t: PhantomData,
         }
     }
@@ -41,10 +46,32 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
     pub fn sliding_window(
         size: Duration,
         slide: Duration,
+        offset: Duration,
     ) -> WindowedHashJoin<K, T1, T2, SlidingWindowAssigner, SlidingWindowAssigner> {
         WindowedHashJoin {
-            assigner1: SlidingWindowAssigner { size, slide },
-            assigner2: SlidingWindowAssigner { size, slide },
+            assigner1: SlidingWindowAssigner {
+                size,
+                slide,
+                offset,
+            },
+            assigner2: SlidingWindowAssigner {
+                size,
+                slide,
+                offset,
+            },
+            metrics: JoinMetrics::default(),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn calendar_window(
+        unit: CalendarUnit,
+        timezone: &str,
+    ) -> WindowedHashJoin<K, T1, T2, CalendarWindowAssigner, CalendarWindowAssigner> {
+        WindowedHashJoin {
+            assigner1: CalendarWindowAssigner::new(unit, timezone),
+            assigner2: CalendarWindowAssigner::new(unit, timezone),
+            metrics: JoinMetrics::default(),
             _t: PhantomData,
         }
     }
@@ -54,6 +81,7 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
         WindowedHashJoin {
             assigner1: InstantWindowAssigner {},
             assigner2: InstantWindowAssigner {},
+            metrics: JoinMetrics::default(),
             _t: PhantomData,
         }
     }
@@ -119,6 +147,19 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
         }
     }
 
+    async fn update_buffered(&self, ctx: &mut Context<K, (Vec<T1>, Vec<T2>)>) {
+        let left = ctx.state.get_key_time_multi_map::<K, T1>('l').await;
+        self.metrics
+            .buffered(JoinSide::Left, left.key_count(), left.row_count());
+        let right = ctx.state.get_key_time_multi_map::<K, T2>('r').await;
+        self.metrics
+            .buffered(JoinSide::Right, right.key_count(), right.row_count());
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<K, (Vec<T1>, Vec<T2>)>) {
+        self.metrics.register(&ctx.task_info);
+    }
+
     async fn handle_timer(
         &mut self,
         mut key: K,
@@ -158,6 +199,23 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
             }
         };
 
+        // the key's rows for the window are released once it closes; those from one side only
+        // never matched
+        let (left, right) = &record.value;
+        for (side, rows, other_rows) in [
+            (JoinSide::Left, left.len(), right.len()),
+            (JoinSide::Right, right.len(), left.len()),
+        ] {
+            if rows > 0 {
+                let orphaned = other_rows == 0;
+                self.metrics.expired(side, 1, orphaned as usize);
+                if !orphaned {
+                    self.metrics.matched(side, rows);
+                }
+            }
+        }
+        self.update_buffered(ctx).await;
+
         ctx.collector.collect(record).await;
     }
 
@@ -166,7 +224,9 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
         record: &Record<K, T1>,
         ctx: &mut Context<K, (Vec<T1>, Vec<T2>)>,
     ) {
+        self.metrics.received(JoinSide::Left);
         Self::store(record, self.assigner1, 'l', ctx).await;
+        self.update_buffered(ctx).await;
     }
 
     async fn process_right(
@@ -174,6 +234,8 @@ impl<K: Key, T1: Data, T2: Data, W1: TimeWindowAssigner<K, T1>, W2: TimeWindowAs
         record: &Record<K, T2>,
         ctx: &mut Context<K, (Vec<T1>, Vec<T2>)>,
     ) {
+        self.metrics.received(JoinSide::Right);
         Self::store(record, self.assigner2, 'r', ctx).await;
+        self.update_buffered(ctx).await;
     }
 }
@@ -0,0 +1,272 @@
+//! Joins a stream against a reference table: a small table of newline-delimited JSON rows that
+//! every subtask loads from a url and reloads periodically, so that the stream can be joined
+//! against it without being shuffled. Each reload replaces the whole table at once, so records
+//! are never joined against a partially-loaded version of it.
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context as AnyhowContext};
+use arroyo_macro::{process_fn, StreamNode};
+use arroyo_metrics::{counter_for_task, gauge_for_task};
+use arroyo_types::*;
+use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore};
+use prometheus::{IntCounter, IntGauge};
+use serde::de::DeserializeOwned;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::engine::Context;
+
+type Rows<K, R> = Arc<HashMap<K, Vec<R>>>;
+
+#[derive(StreamNode)]
+pub struct LookupJoin<K: Key + Sync, T: Data, R: Data + DeserializeOwned + Sync, OutT: Data> {
+    location: String,
+    refresh_interval: Duration,
+    inner: bool,
+    row_key: fn(&R) -> K,
+    merge: fn(&T, Option<&R>) -> OutT,
+    rows: Rows<K, R>,
+    updates: Option<watch::Receiver<Rows<K, R>>>,
+    _t: PhantomData<T>,
+}
+
+/// Reads the rows of a reference table, grouped by their keys
+fn parse_rows<K: Key, R: DeserializeOwned>(
+    data: &[u8],
+    row_key: fn(&R) -> K,
+) -> anyhow::Result<HashMap<K, Vec<R>>> {
+    let mut rows: HashMap<K, Vec<R>> = HashMap::new();
+    for (i, line) in data.split(|b| *b == b'\n').enumerate() {
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+
+        let row: R = serde_json::from_slice(line)
+            .with_context(|| format!("invalid row on line {}", i + 1))?;
+        rows.entry(row_key(&row)).or_default().push(row);
+    }
+
+    Ok(rows)
+}
+
+async fn fetch(location: &str) -> anyhow::Result<Vec<u8>> {
+    let url = url::Url::parse(location)?;
+    // the host of a bucket url is the bucket rather than the service that's connected to
+    let host = match url.scheme() {
+        "s3" => url.host_str().map(|b| format!("{}.s3.amazonaws.com", b)),
+        "gs" => Some("storage.googleapis.com".to_string()),
+        _ => url.host_str().map(|h| h.to_string()),
+    };
+    if let Some(host) = host {
+        check_egress(&host).map_err(|e| anyhow!(e))?;
+    }
+
+    let (store, path): (Box<dyn ObjectStore>, Path) = if url.scheme() == "s3" {
+        // use the default credentials, as we do for state
+        let store = AmazonS3Builder::from_env().with_url(location).build()?;
+        (Box::new(store), Path::from_url_path(url.path())?)
+    } else {
+        object_store::parse_url(&url)?
+    };
+
+    Ok(store.get(&path).await?.bytes().await?.to_vec())
+}
+
+struct RefreshMetrics {
+    refreshed: Option<IntGauge>,
+    rows: Option<IntGauge>,
+    failures: Option<IntCounter>,
+}
+
+impl RefreshMetrics {
+    fn loaded(&self, rows: usize) {
+        if let Some(refreshed) = &self.refreshed {
+            refreshed.set(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+            );
+        }
+        if let Some(gauge) = &self.rows {
+            gauge.set(rows as i64);
+        }
+    }
+
+    fn failed(&self) {
+        if let Some(failures) = &self.failures {
+            failures.inc();
+        }
+    }
+}
+
+async fn load<K: Key, R: DeserializeOwned>(
+    location: &str,
+    row_key: fn(&R) -> K,
+    metrics: &RefreshMetrics,
+) -> anyhow::Result<HashMap<K, Vec<R>>> {
+    let result = fetch(location)
+        .await
+        .and_then(|data| parse_rows(&data, row_key));
+
+    match &result {
+        Ok(rows) => metrics.loaded(rows.values().map(|v| v.len()).sum()),
+        Err(_) => metrics.failed(),
+    }
+
+    result
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = (), out_t = OutT)]
+impl<K: Key + Sync, T: Data, R: Data + DeserializeOwned + Sync, OutT: Data>
+    LookupJoin<K, T, R, OutT>
+{
+    fn name(&self) -> String {
+        "LookupJoin".to_string()
+    }
+
+    pub fn new(
+        location: String,
+        refresh_interval: Duration,
+        inner: bool,
+        row_key: fn(&R) -> K,
+        merge: fn(&T, Option<&R>) -> OutT,
+    ) -> Self {
+        LookupJoin {
+            location,
+            refresh_interval,
+            inner,
+            row_key,
+            merge,
+            rows: Arc::new(HashMap::new()),
+            updates: None,
+            _t: PhantomData,
+        }
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), OutT>) {
+        let metrics = RefreshMetrics {
+            refreshed: gauge_for_task(
+                &ctx.task_info,
+                REFERENCE_TABLE_REFRESHED,
+                "Time (in seconds since the epoch) that the reference table was last loaded",
+                HashMap::new(),
+            ),
+            rows: gauge_for_task(
+                &ctx.task_info,
+                REFERENCE_TABLE_ROWS,
+                "Number of rows in the loaded version of the reference table",
+                HashMap::new(),
+            ),
+            failures: counter_for_task(
+                &ctx.task_info,
+                REFERENCE_TABLE_REFRESH_FAILURES,
+                "Count of failed attempts to reload the reference table",
+                HashMap::new(),
+            ),
+        };
+
+        // records can't be joined until the table has been loaded once, so failing to do so fails
+        // the task
+        let rows = load(&self.location, self.row_key, &metrics)
+            .await
+            .map_err(|e| anyhow!("failed to load reference table {}: {:?}", self.location, e))
+            .unwrap();
+        info!(
+            "[{}] loaded reference table {}",
+            ctx.task_info.operator_name, self.location
+        );
+        self.rows = Arc::new(rows);
+
+        let (tx, rx) = watch::channel(self.rows.clone());
+        self.updates = Some(rx);
+
+        let location = self.location.clone();
+        let refresh_interval = self.refresh_interval;
+        let row_key = self.row_key;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(refresh_interval).await;
+                if tx.is_closed() {
+                    return;
+                }
+
+                // a table that fails to load is left as it was, so a bad update doesn't
+                // interrupt the join
+                match load(&location, row_key, &metrics).await {
+                    Ok(rows) => {
+                        if tx.send(Arc::new(rows)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("failed to reload reference table {}: {:?}", location, e);
+                    }
+                }
+            }
+        });
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), OutT>) {
+        if let Some(updates) = &mut self.updates {
+            if updates.has_changed().unwrap_or(false) {
+                self.rows = updates.borrow_and_update().clone();
+            }
+        }
+
+        let matches = record.key.as_ref().and_then(|key| self.rows.get(key));
+        let values: Vec<OutT> = match matches {
+            Some(rows) => rows
+                .iter()
+                .map(|row| (self.merge)(&record.value, Some(row)))
+                .collect(),
+            None if self.inner => vec![],
+            None => vec![(self.merge)(&record.value, None)],
+        };
+
+        for value in values {
+            ctx.collect(Record {
+                timestamp: record.timestamp,
+                key: None,
+                value,
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Rate {
+        currency: String,
+        rate: f64,
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        let data = b"{\"currency\": \"EUR\", \"rate\": 1.1}\n\n{\"currency\": \"GBP\", \"rate\": 1.3}\n{\"currency\": \"EUR\", \"rate\": 1.2, \"source\": \"ecb\"}\n";
+        let rows: HashMap<String, Vec<Rate>> =
+            parse_rows(data, |r: &Rate| r.currency.clone()).unwrap();
+
+        assert_eq!(2, rows.len());
+        assert_eq!(
+            vec![1.1, 1.2],
+            rows["EUR"].iter().map(|r| r.rate).collect::<Vec<_>>()
+        );
+        assert_eq!(1.3, rows["GBP"][0].rate);
+
+        let err = parse_rows::<String, Rate>(b"{\"currency\": \"EUR\"}", |r| r.currency.clone())
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("line 1"));
+    }
+}
@@ -5,16 +5,22 @@ use std::{fmt::Debug, path::PathBuf};
 use std::marker::PhantomData;
 use std::ops::Add;
 
+use crate::connectors::OperatorConfigBadData;
 use crate::engine::{Collector, Context, StreamNode};
+use crate::formats::csv::CsvOptions;
 use arroyo_macro::process_fn;
 use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::ControlResp;
 use arroyo_types::{
-    from_millis, to_millis, CheckpointBarrier, Data, GlobalKey, Key, Message, Record, TaskInfo,
-    UpdatingData, Window,
+    from_millis, report_watermarks, stop_at_event_time, to_millis, CalendarUnit, CheckpointBarrier,
+    Data, GlobalKey, Key, Message, Record, TaskInfo, Tz, UpdatingData, Window,
+    CORRUPT_RECORD_FIELD, LINEAGE_OFFSET_FIELD, LINEAGE_PARTITION_FIELD, LINEAGE_SOURCE_FIELD,
 };
 use bincode::{config, Decode, Encode};
-use serde::de::DeserializeOwned;
-use serde_json::json;
+use serde::de::value::MapDeserializer;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Serialize;
+use serde_json::{Map, Value};
 use std::time::{Duration, SystemTime};
 use tracing::debug;
 use wasmtime::{
@@ -22,11 +28,20 @@ use wasmtime::{
     TypedFunc,
 };
 pub mod aggregating_window;
+pub mod assertion;
 pub mod functions;
+pub mod interval_join;
+pub mod join_metrics;
 pub mod join_with_expiration;
 pub mod joins;
+pub mod lookup_join;
+pub mod process;
+pub mod reorder_buffer;
 pub mod sinks;
 pub mod sliding_top_n_aggregating_window;
+pub mod tenant_limit;
+pub mod timestamp_bounds;
+pub mod top_n;
 pub mod tumbling_aggregating_window;
 pub mod tumbling_top_n_window;
 pub mod updating_aggregate;
@@ -44,6 +59,15 @@ impl UserError {
             details: details.into(),
         }
     }
+
+    /// A format that a source can't read. Tables are checked for this when they're created, so
+    /// it's only seen for tables that were created before that check.
+    pub fn unsupported_format(format: &str, source: &str) -> UserError {
+        UserError::new(
+            "Unsupported format",
+            format!("{} is not supported for {} sources", format, source),
+        )
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -52,10 +76,203 @@ pub enum SerializationMode {
     // https://docs.confluent.io/platform/current/schema-registry/serdes-develop/index.html#wire-format
     JsonSchemaRegistry,
     RawJson,
+    // the message bytes are passed through untouched as arroyo_types::RawBytes
+    RawBytes,
+    // avro in the schema registry wire format; as the schemas have to be fetched from the
+    // registry, records are read and written with a formats::avro::AvroFormat
+    Avro,
+    // protobuf messages, which are read with a formats::protobuf::ProtobufFormat holding the
+    // message descriptor from the operator's config
+    Protobuf,
+    // a csv record per message, laid out as the options from the operator's config describe
+    Csv(CsvOptions),
+}
+
+/// How a source handles records that don't match its schema
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BadData {
+    /// The record fails the subtask
+    #[default]
+    Fail,
+    /// The record is read with nulls for the fields that couldn't be deserialized and its raw
+    /// payload in the `_corrupt_record` column, as in Spark's permissive mode. Only applies to
+    /// json; all fields of the record type must be nullable.
+    Permissive,
+}
+
+impl From<Option<OperatorConfigBadData>> for BadData {
+    fn from(value: Option<OperatorConfigBadData>) -> Self {
+        match value {
+            Some(OperatorConfigBadData::Permissive) => BadData::Permissive,
+            Some(OperatorConfigBadData::Fail) | None => BadData::Fail,
+        }
+    }
+}
+
+/// Where a record was read from, which sources with lineage enabled put in its `_lineage_*` fields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lineage<'a> {
+    /// The topic or file the record was read from
+    pub source: &'a str,
+    pub partition: Option<i32>,
+    /// The offset of the record within its partition, or its line number within its file
+    pub offset: i64,
+}
+
+impl Lineage<'_> {
+    pub fn add_to(&self, record: &mut Map<String, Value>) {
+        record.insert(
+            LINEAGE_SOURCE_FIELD.to_string(),
+            Value::String(self.source.to_string()),
+        );
+        record.insert(
+            LINEAGE_PARTITION_FIELD.to_string(),
+            self.partition.map(Value::from).unwrap_or(Value::Null),
+        );
+        record.insert(LINEAGE_OFFSET_FIELD.to_string(), Value::from(self.offset));
+    }
+}
+
+/// Deserializes a record whose only field is `value`, as RawJson and RawBytes records are, directly
+/// from the message rather than from a json object built around it
+fn deserialize_value<'a, T: DeserializeOwned>(
+    value: impl IntoDeserializer<'a, serde::de::value::Error>,
+) -> Result<T, serde::de::value::Error> {
+    T::deserialize(MapDeserializer::new(std::iter::once(("value", value))))
+}
+
+/// Deserializes as much as possible of a json record that failed to deserialize: each field that
+/// can't be deserialized is left null, and the raw record is put in its `_corrupt_record` field.
+/// `fields` are filled in as with `deserialize_slice_with_fields`.
+fn deserialize_permissive<T: DeserializeOwned>(
+    msg: &[u8],
+    fields: &Map<String, Value>,
+) -> Result<T, UserError> {
+    let raw = String::from_utf8_lossy(msg).to_string();
+    let fields = match serde_json::from_slice(msg) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+
+    let mut record = Map::new();
+    record.insert(CORRUPT_RECORD_FIELD.to_string(), Value::String(raw.clone()));
+    record.extend(fields.clone());
+    let mut record = Value::Object(record);
+
+    // serde doesn't report which field it failed on, so fields are added one at a time and kept
+    // only if the record can still be deserialized; fields that were filled in above take
+    // precedence over those in the record
+    for (name, value) in fields {
+        if record.as_object().unwrap().contains_key(&name) {
+            continue;
+        }
+
+        record.as_object_mut().unwrap().insert(name.clone(), value);
+        if T::deserialize(&record).is_err() {
+            record.as_object_mut().unwrap().remove(&name);
+        }
+    }
+
+    T::deserialize(&record).map_err(|e| {
+        UserError::new(
+            "Deserialization error",
+            format!(
+                "Failed to deserialize message '{}' from json in permissive mode, with error {}",
+                raw, e
+            ),
+        )
+    })
+}
+
+fn avro_requires_registry() -> UserError {
+    UserError::new(
+        "Unsupported format",
+        "avro records can only be read and written by connectors with a schema registry",
+    )
+}
+
+fn protobuf_requires_descriptor() -> UserError {
+    UserError::new(
+        "Unsupported format",
+        "protobuf records can only be read by connectors configured with a message descriptor",
+    )
 }
 
 impl SerializationMode {
-    pub fn deserialize_slice<T: DeserializeOwned>(&self, msg: &[u8]) -> Result<T, UserError> {
+    pub fn deserialize_slice<T: DeserializeOwned>(
+        &self,
+        msg: &[u8],
+        bad_data: BadData,
+    ) -> Result<T, UserError> {
+        let result = self.deserialize_slice_strict(msg);
+        if result.is_err() && bad_data == BadData::Permissive {
+            match self {
+                SerializationMode::Json => return deserialize_permissive(msg, &Map::new()),
+                SerializationMode::JsonSchemaRegistry if msg.len() >= 5 => {
+                    return deserialize_permissive(&msg[5..], &Map::new())
+                }
+                _ => {}
+            }
+        }
+
+        result
+    }
+
+    /// Deserializes a json record, filling in its `_lineage_*` fields from `lineage`. Other modes
+    /// don't have lineage fields, so their records are deserialized as with `deserialize_slice`.
+    pub fn deserialize_slice_with_lineage<T: DeserializeOwned>(
+        &self,
+        msg: &[u8],
+        bad_data: BadData,
+        lineage: &Lineage,
+    ) -> Result<T, UserError> {
+        let mut fields = Map::new();
+        lineage.add_to(&mut fields);
+        self.deserialize_slice_with_fields(msg, bad_data, &fields)
+    }
+
+    /// Deserializes a json record, filling in fields that the source provides rather than the
+    /// payload (like lineage or Kafka headers), which replace any fields of the same names in the
+    /// record. Other modes don't have such fields, so their records are deserialized as with
+    /// `deserialize_slice`.
+    pub fn deserialize_slice_with_fields<T: DeserializeOwned>(
+        &self,
+        msg: &[u8],
+        bad_data: BadData,
+        fields: &Map<String, Value>,
+    ) -> Result<T, UserError> {
+        let msg = match self {
+            SerializationMode::Json => msg,
+            SerializationMode::JsonSchemaRegistry if msg.len() >= 5 => &msg[5..],
+            _ => return self.deserialize_slice(msg, bad_data),
+        };
+
+        let result = match serde_json::from_slice(msg) {
+            Ok(Value::Object(mut record)) => {
+                record.extend(fields.clone());
+                T::deserialize(&Value::Object(record)).map_err(|e| e.to_string())
+            }
+            Ok(_) => Err("expected a json object".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        result.or_else(|err| {
+            if bad_data == BadData::Permissive {
+                return deserialize_permissive(msg, fields);
+            }
+
+            Err(UserError::new(
+                "Deserialization error",
+                format!(
+                    "Failed to deserialize message '{}' from json, with error {}",
+                    String::from_utf8_lossy(msg),
+                    err
+                ),
+            ))
+        })
+    }
+
+    fn deserialize_slice_strict<T: DeserializeOwned>(&self, msg: &[u8]) -> Result<T, UserError> {
         match self {
             SerializationMode::Json => serde_json::from_slice(msg)
                 .map_err(|err|
@@ -65,50 +282,82 @@ impl SerializationMode {
                 .map_err(|err|
                     UserError::new("Deserialization error", format!("Failed to deserialize message '{}' from confluent schema registry json, with error {}",
                         String::from_utf8_lossy(msg), err))),
-            SerializationMode::RawJson => {
-                let s = String::from_utf8_lossy(msg);
-                let j = json! {
-                    { "value": s }
-                };
-
-                // TODO: this is inefficient, because we know that T is RawJson in this case and can much more directly
-                //  produce that value. However, without specialization I don't know how to get the compiler to emit
-                //  the optimized code that case.
-                serde_json::from_value(j)
-                    .map_err(|e| UserError::new("Deserialization error", format!("Could not represent data as RawJson: {:?}", e)))
-            },
+            SerializationMode::RawJson => deserialize_value(&*String::from_utf8_lossy(msg))
+                .map_err(|e| UserError::new("Deserialization error", format!("Could not represent data as RawJson: {:?}", e))),
+            SerializationMode::RawBytes => deserialize_value(msg)
+                .map_err(|e| UserError::new("Deserialization error", format!("Could not represent data as RawBytes: {:?}", e))),
+            SerializationMode::Avro => Err(avro_requires_registry()),
+            SerializationMode::Protobuf => Err(protobuf_requires_descriptor()),
+            SerializationMode::Csv(options) => options.deserialize(msg),
         }
     }
 
-    pub fn deserialize_str<T: DeserializeOwned>(&self, msg: &str) -> Result<T, UserError> {
+    pub fn deserialize_str<T: DeserializeOwned>(
+        &self,
+        msg: &str,
+        bad_data: BadData,
+    ) -> Result<T, UserError> {
         match self {
-            SerializationMode::Json => serde_json::from_str(msg).map_err(|err| {
-                UserError::new(
+            SerializationMode::Json => serde_json::from_str(msg).or_else(|err| {
+                if bad_data == BadData::Permissive {
+                    return deserialize_permissive(msg.as_bytes(), &Map::new());
+                }
+
+                Err(UserError::new(
                     "Deserialization error",
                     format!(
                         "Failed to deserialize message '{}' from json, with error {}",
                         msg, err
                     ),
-                )
+                ))
             }),
             SerializationMode::JsonSchemaRegistry => {
                 panic!("cannot read json schema registry data from str")
             }
-            SerializationMode::RawJson => {
-                let j = json! {
-                    { "value": msg }
-                };
-
-                // TODO: this is inefficient, because we know that T is RawJson in this case and can much more directly
-                //  produce that value. However, without specialization I don't know how to get the compiler to emit
-                //  the optimized code that case.
-                serde_json::from_value(j).map_err(|e| {
-                    UserError::new(
-                        "Deserialization error",
-                        format!("Could not represent data as RawJson: {:?}", e),
-                    )
-                })
+            SerializationMode::RawJson => deserialize_value(msg).map_err(|e| {
+                UserError::new(
+                    "Deserialization error",
+                    format!("Could not represent data as RawJson: {:?}", e),
+                )
+            }),
+            SerializationMode::RawBytes => self.deserialize_slice_strict(msg.as_bytes()),
+            SerializationMode::Avro => panic!("cannot read avro data from str"),
+            SerializationMode::Protobuf => panic!("cannot read protobuf data from str"),
+            SerializationMode::Csv(options) => options.deserialize(msg.as_bytes()),
+        }
+    }
+
+    /// Produces the bytes that a sink should write for the value. In RawBytes mode, the record
+    /// must have a single `value` column (either bytes or a string) which is written as-is; in Csv
+    /// mode it's written as a csv record; otherwise the value is written as json.
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, UserError> {
+        match self {
+            SerializationMode::RawBytes => {
+                let value = serde_json::to_value(value)
+                    .map_err(|e| UserError::new("Serialization error", format!("{:?}", e)))?;
+
+                match value.get("value") {
+                    Some(Value::String(s)) => Ok(s.as_bytes().to_vec()),
+                    Some(v @ Value::Array(_)) => serde_json::from_value(v.clone()).map_err(|e| {
+                        UserError::new(
+                            "Serialization error",
+                            format!("Could not represent 'value' as bytes: {:?}", e),
+                        )
+                    }),
+                    Some(Value::Null) => Ok(vec![]),
+                    _ => Err(UserError::new(
+                        "Serialization error",
+                        "raw_bytes sinks require a single 'value' column of type BYTEA or TEXT",
+                    )),
+                }
             }
+            SerializationMode::Avro => Err(avro_requires_registry()),
+            SerializationMode::Protobuf => Err(UserError::new(
+                "Unsupported format",
+                "protobuf records can't be written by sinks",
+            )),
+            SerializationMode::Csv(options) => options.serialize(value),
+            _ => Ok(serde_json::to_vec(value).unwrap()),
         }
     }
 }
@@ -117,10 +366,12 @@ impl SerializationMode {
 mod test {
     use crate::operators::WasmOperator;
     use crate::{engine::Context, operators::TimeWindowAssigner};
-    use arroyo_types::{from_millis, to_millis, Message, Record};
+    use arroyo_types::{from_millis, to_millis, CalendarUnit, Message, RawBytes, RawJson, Record};
     use std::time::{Duration, SystemTime};
 
-    use super::SlidingWindowAssigner;
+    use super::{
+        BadData, CalendarWindowAssigner, Lineage, SerializationMode, SlidingWindowAssigner,
+    };
 
     #[tokio::test]
     #[ignore]
@@ -165,6 +416,7 @@ mod test {
         let assigner = SlidingWindowAssigner {
             size: Duration::from_secs(5),
             slide: Duration::from_secs(5),
+            offset: Duration::ZERO,
         };
         let start_millis = to_millis(SystemTime::now());
         let truncated_start_millis =
@@ -175,6 +427,171 @@ mod test {
             <SlidingWindowAssigner as TimeWindowAssigner<(), ()>>::windows(&assigner, start).len()
         );
     }
+
+    #[test]
+    fn test_calendar_window_assignment() {
+        let assigner = CalendarWindowAssigner::new(CalendarUnit::Day, "America/New_York");
+
+        // 2023-03-12 12:00 EDT, the day DST started
+        let window = <CalendarWindowAssigner as TimeWindowAssigner<(), ()>>::windows(
+            &assigner,
+            from_millis(1678636800000),
+        )[0];
+        assert_eq!(from_millis(1678597200000), window.start_time);
+        assert_eq!(from_millis(1678680000000), window.end_time);
+        assert_eq!(
+            Duration::from_secs(23 * 60 * 60),
+            window.end_time.duration_since(window.start_time).unwrap()
+        );
+
+        let next = <CalendarWindowAssigner as TimeWindowAssigner<(), ()>>::next(&assigner, window);
+        assert_eq!(window.end_time, next.start_time);
+        assert_eq!(
+            Duration::from_secs(24 * 60 * 60),
+            next.end_time.duration_since(next.start_time).unwrap()
+        );
+
+        let assigner = CalendarWindowAssigner::new(CalendarUnit::Month, "Asia/Tokyo");
+        // 2023-02-28 23:00 JST
+        let window = <CalendarWindowAssigner as TimeWindowAssigner<(), ()>>::windows(
+            &assigner,
+            from_millis(1677592800000),
+        )[0];
+        // 2023-02-01 00:00 JST to 2023-03-01 00:00 JST
+        assert_eq!(from_millis(1675177200000), window.start_time);
+        assert_eq!(from_millis(1677596400000), window.end_time);
+    }
+
+    #[test]
+    fn test_raw_deserialization() {
+        let msg = b"\x00\xffnot utf-8";
+
+        let record: RawBytes = SerializationMode::RawBytes
+            .deserialize_slice(msg, BadData::Fail)
+            .unwrap();
+        assert_eq!(msg.to_vec(), record.value);
+
+        let record: RawJson = SerializationMode::RawJson
+            .deserialize_slice(br#"{"id": 1}"#, BadData::Fail)
+            .unwrap();
+        assert_eq!(r#"{"id": 1}"#, record.value);
+
+        // RawBytes still deserializes from the json it's serialized as
+        let record: RawBytes = serde_json::from_str(r#"{"value": [0, 255]}"#).unwrap();
+        assert_eq!(vec![0, 255], record.value);
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct PermissiveRecord {
+        id: Option<i64>,
+        name: Option<String>,
+        _corrupt_record: Option<String>,
+    }
+
+    #[test]
+    fn test_permissive_deserialization() {
+        let mode = SerializationMode::Json;
+
+        let record: PermissiveRecord = mode
+            .deserialize_slice(br#"{"id": 1, "name": "a"}"#, BadData::Permissive)
+            .unwrap();
+        assert_eq!(
+            PermissiveRecord {
+                id: Some(1),
+                name: Some("a".to_string()),
+                _corrupt_record: None,
+            },
+            record
+        );
+
+        let msg = r#"{"id": "one", "name": "a"}"#;
+        assert!(mode
+            .deserialize_str::<PermissiveRecord>(msg, BadData::Fail)
+            .is_err());
+
+        let record: PermissiveRecord = mode.deserialize_str(msg, BadData::Permissive).unwrap();
+        assert_eq!(
+            PermissiveRecord {
+                id: None,
+                name: Some("a".to_string()),
+                _corrupt_record: Some(msg.to_string()),
+            },
+            record
+        );
+
+        let record: PermissiveRecord = mode
+            .deserialize_slice(b"not json", BadData::Permissive)
+            .unwrap();
+        assert_eq!(
+            PermissiveRecord {
+                id: None,
+                name: None,
+                _corrupt_record: Some("not json".to_string()),
+            },
+            record
+        );
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct LineageRecord {
+        id: Option<i64>,
+        _corrupt_record: Option<String>,
+        _lineage_source: String,
+        _lineage_partition: Option<i32>,
+        _lineage_offset: i64,
+    }
+
+    #[test]
+    fn test_lineage_deserialization() {
+        let mode = SerializationMode::Json;
+        let lineage = Lineage {
+            source: "orders",
+            partition: Some(3),
+            offset: 17,
+        };
+
+        // lineage from the source replaces any lineage fields in the record itself
+        let record: LineageRecord = mode
+            .deserialize_slice_with_lineage(
+                br#"{"id": 1, "_lineage_offset": 5}"#,
+                BadData::Fail,
+                &lineage,
+            )
+            .unwrap();
+        assert_eq!(
+            LineageRecord {
+                id: Some(1),
+                _corrupt_record: None,
+                _lineage_source: "orders".to_string(),
+                _lineage_partition: Some(3),
+                _lineage_offset: 17,
+            },
+            record
+        );
+
+        assert!(mode
+            .deserialize_slice_with_lineage::<LineageRecord>(b"not json", BadData::Fail, &lineage)
+            .is_err());
+
+        let lineage = Lineage {
+            source: "/data/orders.json",
+            partition: None,
+            offset: 2,
+        };
+        let record: LineageRecord = mode
+            .deserialize_slice_with_lineage(br#"{"id": "one"}"#, BadData::Permissive, &lineage)
+            .unwrap();
+        assert_eq!(
+            LineageRecord {
+                id: None,
+                _corrupt_record: Some(r#"{"id": "one"}"#.to_string()),
+                _lineage_source: "/data/orders.json".to_string(),
+                _lineage_partition: None,
+                _lineage_offset: 2,
+            },
+            record
+        );
+    }
 }
 
 #[derive(Encode, Decode, Copy, Clone, Debug, PartialEq)]
@@ -188,6 +605,14 @@ pub struct PeriodicWatermarkGenerator<K: Key, D: Data> {
     interval: Duration,
     watermark_function: Box<dyn Fn(&Record<K, D>) -> SystemTime + Send>,
     state_cache: PeriodicWatermarkGeneratorState,
+    // whether the watermark is part of an alignment group, in which case emitted watermarks are
+    // reported to the controller so that it can pause sources that are too far ahead
+    aligned: bool,
+    // the job's stop time; records after it are dropped and watermarks that pass it are reported
+    // to the controller, which drains the job once every watermark has
+    stop_at: Option<SystemTime>,
+    // set when the job has a recovery throttle, which paces sources by their watermarks
+    report_all: bool,
     _t: PhantomData<(K, D)>,
 }
 
@@ -204,6 +629,9 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
                 last_watermark_emitted_at: SystemTime::UNIX_EPOCH,
                 max_watermark: SystemTime::UNIX_EPOCH,
             },
+            aligned: false,
+            stop_at: None,
+            report_all: false,
             _t: PhantomData,
         }
     }
@@ -219,10 +647,32 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
                 last_watermark_emitted_at: SystemTime::UNIX_EPOCH,
                 max_watermark: SystemTime::UNIX_EPOCH,
             },
+            aligned: false,
+            stop_at: None,
+            report_all: false,
             _t: PhantomData,
         }
     }
 
+    pub fn aligned(mut self, aligned: bool) -> Self {
+        self.aligned = aligned;
+        self
+    }
+
+    async fn report_watermark(&self, watermark: SystemTime, ctx: &mut Context<K, D>) {
+        if self.aligned || self.report_all || self.stop_at.map(|t| watermark >= t).unwrap_or(false)
+        {
+            ctx.control_tx
+                .send(ControlResp::Watermark {
+                    operator_id: ctx.task_info.operator_id.clone(),
+                    task_index: ctx.task_info.task_index,
+                    watermark,
+                })
+                .await
+                .unwrap();
+        }
+    }
+
     fn tables(&self) -> Vec<TableDescriptor> {
         vec![arroyo_state::global_table(
             "s",
@@ -245,6 +695,8 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
                 }));
 
         self.state_cache = state;
+        self.stop_at = stop_at_event_time();
+        self.report_all = report_watermarks();
     }
 
     async fn on_close(&mut self, ctx: &mut Context<K, D>) {
@@ -255,7 +707,9 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
     }
 
     async fn process_element(&mut self, record: &Record<K, D>, ctx: &mut Context<K, D>) {
-        ctx.collector.collect(record.clone()).await;
+        if self.stop_at.map(|t| record.timestamp <= t).unwrap_or(true) {
+            ctx.collector.collect(record.clone()).await;
+        }
 
         let watermark = (self.watermark_function)(record);
 
@@ -272,6 +726,7 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
                 to_millis(watermark)
             );
             ctx.collector.broadcast(Message::Watermark(watermark)).await;
+            self.report_watermark(watermark, ctx).await;
             self.state_cache.last_watermark_emitted_at = record.timestamp;
         }
     }
@@ -294,14 +749,18 @@ pub trait TimeWindowAssigner<K: Key, T: Data>: Copy + Clone + Send + 'static {
 #[derive(Clone, Copy)]
 pub struct TumblingWindowAssigner {
     size: Duration,
+    // windows start at `offset` past each multiple of `size`
+    offset: Duration,
 }
 
 impl<K: Key, T: Data> TimeWindowAssigner<K, T> for TumblingWindowAssigner {
     fn windows(&self, ts: SystemTime) -> Vec<Window> {
-        let key = to_millis(ts) / (self.size.as_millis() as u64);
+        let size = self.size.as_millis() as u64;
+        let offset = self.offset.as_millis() as u64 % size;
+        let start = to_millis(ts) - (to_millis(ts) + size - offset) % size;
         vec![Window {
-            start_time: from_millis(key * self.size.as_millis() as u64),
-            end_time: from_millis((key + 1) * (self.size.as_millis() as u64)),
+            start_time: from_millis(start),
+            end_time: from_millis(start + size),
         }]
     }
 
@@ -316,6 +775,37 @@ impl<K: Key, T: Data> TimeWindowAssigner<K, T> for TumblingWindowAssigner {
         Some(self.size)
     }
 }
+#[derive(Clone, Copy)]
+pub struct CalendarWindowAssigner {
+    unit: CalendarUnit,
+    timezone: Tz,
+}
+
+impl CalendarWindowAssigner {
+    pub fn new(unit: CalendarUnit, timezone: &str) -> Self {
+        CalendarWindowAssigner {
+            unit,
+            timezone: timezone
+                .parse()
+                .unwrap_or_else(|e| panic!("invalid timezone '{}': {}", timezone, e)),
+        }
+    }
+}
+
+impl<K: Key, T: Data> TimeWindowAssigner<K, T> for CalendarWindowAssigner {
+    fn windows(&self, ts: SystemTime) -> Vec<Window> {
+        vec![self.unit.window(ts, self.timezone)]
+    }
+
+    fn next(&self, window: Window) -> Window {
+        self.unit.window(window.end_time, self.timezone)
+    }
+
+    fn safe_retention_duration(&self) -> Option<Duration> {
+        Some(self.unit.max_width())
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct InstantWindowAssigner {}
 
@@ -343,6 +833,8 @@ impl<K: Key, T: Data> TimeWindowAssigner<K, T> for InstantWindowAssigner {
 pub struct SlidingWindowAssigner {
     size: Duration,
     slide: Duration,
+    // windows start at `offset` past each multiple of `slide`
+    offset: Duration,
 }
 //  012345678
 //  --x------
@@ -356,7 +848,9 @@ impl SlidingWindowAssigner {
         let ts_millis = to_millis(ts);
         let earliest_window_start = ts_millis - self.size.as_millis() as u64;
 
-        let remainder = earliest_window_start % (self.slide.as_millis() as u64);
+        let slide = self.slide.as_millis() as u64;
+        let offset = self.offset.as_millis() as u64 % slide;
+        let remainder = (earliest_window_start + slide - offset) % slide;
 
         from_millis(earliest_window_start - remainder + self.slide.as_millis() as u64)
     }
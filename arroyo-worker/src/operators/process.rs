@@ -0,0 +1,117 @@
+use std::{marker::PhantomData, time::SystemTime};
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_types::*;
+use serde_json::Value;
+
+/// A custom stateful operator that can be called from SQL as `PROCESS(name, TABLE input, 'config')`.
+/// Process functions are defined along with a pipeline's UDFs, as a type that implements this
+/// trait; `Input` and `Output` must be structs defined there too. The fields of `Input` are read
+/// from the columns of the input table with the same names, and the fields of `Output` become the
+/// columns of the table that PROCESS returns.
+///
+/// A process function sees all of its input in a single subtask. Its state is whatever it keeps
+/// in itself: `snapshot` is saved with each checkpoint and passed to `restore` when the job
+/// restarts.
+pub trait ProcessFunction: Send + 'static {
+    type Input;
+    type Output;
+
+    /// Creates the function from the config given in the query (which is often JSON)
+    fn new(config: &str) -> Self;
+
+    /// Called for each input row, returning the rows to emit, which get the row's timestamp
+    fn process(&mut self, input: Self::Input, timestamp: SystemTime) -> Vec<Self::Output>;
+
+    /// Called as event time advances; rows returned here get the watermark as their timestamp
+    fn on_watermark(&mut self, _watermark: SystemTime) -> Vec<Self::Output> {
+        vec![]
+    }
+
+    fn snapshot(&self) -> Value {
+        Value::Null
+    }
+
+    fn restore(&mut self, _state: Value) {}
+}
+
+#[derive(StreamNode)]
+pub struct ProcessOperator<K: Key, T: Data, OutT: Data, F: ProcessFunction> {
+    config: String,
+    function: Option<F>,
+    input: fn(&T) -> F::Input,
+    output: fn(F::Output) -> OutT,
+    _t: PhantomData<K>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = (), out_t = OutT)]
+impl<K: Key, T: Data, OutT: Data, F: ProcessFunction> ProcessOperator<K, T, OutT, F> {
+    fn name(&self) -> String {
+        "Process".to_string()
+    }
+
+    pub fn new(config: &str, input: fn(&T) -> F::Input, output: fn(F::Output) -> OutT) -> Self {
+        Self {
+            config: config.to_string(),
+            function: None,
+            input,
+            output,
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("p", "process function state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), OutT>) {
+        let mut function = F::new(&self.config);
+
+        let gs = ctx.state.get_global_keyed_state::<usize, String>('p').await;
+        if let Some(state) = gs.get(&ctx.task_info.task_index) {
+            function.restore(serde_json::from_str(state).expect("invalid process function state"));
+        }
+
+        self.function = Some(function);
+    }
+
+    async fn emit(
+        &mut self,
+        rows: Vec<F::Output>,
+        timestamp: SystemTime,
+        ctx: &mut Context<(), OutT>,
+    ) {
+        for row in rows {
+            ctx.collect(Record {
+                timestamp,
+                key: None,
+                value: (self.output)(row),
+            })
+            .await;
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), OutT>) {
+        let rows = self
+            .function
+            .as_mut()
+            .unwrap()
+            .process((self.input)(&record.value), record.timestamp);
+        self.emit(rows, record.timestamp, ctx).await;
+    }
+
+    async fn handle_watermark(&mut self, watermark: SystemTime, ctx: &mut Context<(), OutT>) {
+        let rows = self.function.as_mut().unwrap().on_watermark(watermark);
+        self.emit(rows, watermark, ctx).await;
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), OutT>) {
+        let state = self.function.as_ref().unwrap().snapshot();
+        let mut gs = ctx.state.get_global_keyed_state('p').await;
+        gs.insert(ctx.task_info.task_index, state.to_string()).await;
+    }
+}
@@ -0,0 +1,98 @@
+use std::marker::PhantomData;
+use std::time::SystemTime;
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::TimeKeyMap;
+use arroyo_types::*;
+
+/// Buffers records until the watermark passes them and then emits them in timestamp order.
+/// This is placed after shuffles in pipelines that require per-key ordering, as records for a
+/// key may arrive interleaved from several upstream subtasks. Records for the same key and
+/// timestamp are emitted in the order they arrived; records without a key, or that arrive after
+/// the watermark has passed them, are forwarded immediately.
+#[derive(StreamNode)]
+pub struct ReorderBuffer<K: Key, T: Data> {
+    _t: PhantomData<(K, T)>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = T)]
+impl<K: Key, T: Data> ReorderBuffer<K, T> {
+    fn name(&self) -> String {
+        "ReorderBuffer".to_string()
+    }
+
+    pub fn new() -> Self {
+        ReorderBuffer { _t: PhantomData }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![TableDescriptor {
+            name: "r".to_string(),
+            description: "reorder buffer".to_string(),
+            table_type: TableType::TimeKeyMap as i32,
+            delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+            write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+            retention_micros: 0,
+        }]
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, T>) {
+        let late = ctx
+            .watermark()
+            .map(|watermark| record.timestamp <= watermark)
+            .unwrap_or(false);
+
+        let (Some(key), false) = (&record.key, late) else {
+            ctx.collect(record.clone()).await;
+            return;
+        };
+
+        let mut buffer: TimeKeyMap<K, Vec<T>, _> =
+            ctx.state.get_time_key_map('r', ctx.watermark()).await;
+        let mut key = key.clone();
+        let mut values = buffer
+            .get(record.timestamp, &mut key)
+            .cloned()
+            .unwrap_or_default();
+        values.push(record.value.clone());
+        buffer.insert(record.timestamp, key, values);
+    }
+
+    async fn handle_watermark(&mut self, _watermark: SystemTime, ctx: &mut Context<K, T>) {
+        let Some(watermark) = ctx.watermark() else {
+            return;
+        };
+
+        let mut records = vec![];
+        {
+            let mut buffer: TimeKeyMap<K, Vec<T>, _> =
+                ctx.state.get_time_key_map('r', Some(watermark)).await;
+            while let Some(timestamp) = buffer.get_min_time() {
+                if timestamp > watermark {
+                    break;
+                }
+                for (key, values) in buffer.evict_for_timestamp(timestamp) {
+                    records.extend(values.into_iter().map(|value| Record {
+                        timestamp,
+                        key: Some(key.clone()),
+                        value,
+                    }));
+                }
+            }
+        }
+
+        for record in records {
+            ctx.collect(record).await;
+        }
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<K, T>) {
+        let mut buffer: TimeKeyMap<K, Vec<T>, _> =
+            ctx.state.get_time_key_map('r', ctx.watermark()).await;
+        buffer.flush().await;
+    }
+}
@@ -4,14 +4,25 @@ use crate::engine::{Context, StreamNode};
 use arroyo_macro::process_fn;
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::SinkDataReq;
+use arroyo_server_common::tls;
 use arroyo_types::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tonic::transport::Channel;
 
+#[derive(Deserialize)]
+struct GrpcSinkConfig {
+    tag: Option<String>,
+}
+
 #[derive(StreamNode)]
 pub struct GrpcSink<K: Key, T: Data + Serialize> {
     _ts: PhantomData<(K, T)>,
     client: Option<ControllerGrpcClient<Channel>>,
+    // set for sandboxed runs; output beyond it is dropped
+    max_records: Option<u64>,
+    records_sent: u64,
+    // identifies this sink's output among the outputs of the job; defaults to the operator id
+    tag: Option<String>,
 }
 
 #[process_fn(in_k=K, in_t=T)]
@@ -20,29 +31,59 @@ impl<K: Key, T: Data + Serialize> GrpcSink<K, T> {
         GrpcSink {
             _ts: PhantomData,
             client: None,
+            max_records: SandboxLimits::from_env().max_output_records,
+            records_sent: 0,
+            tag: None,
         }
     }
 
-    pub fn from_config(_config: &str) -> Self {
-        Self::new()
+    pub fn from_config(config: &str) -> Self {
+        let config: GrpcSinkConfig =
+            serde_json::from_str(config).expect("Invalid config for GrpcSink");
+
+        Self {
+            tag: config.tag,
+            ..Self::new()
+        }
     }
 
     fn name(&self) -> String {
         "GrpcSink".to_string()
     }
 
-    async fn on_start(&mut self, _: &mut Context<(), ()>) {
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        if self.tag.is_none() {
+            self.tag = Some(ctx.task_info.operator_id.clone());
+        }
+
         let controller_addr = std::env::var(arroyo_types::CONTROLLER_ADDR_ENV)
             .unwrap_or_else(|_| crate::LOCAL_CONTROLLER_ADDR.to_string());
 
-        self.client = Some(
-            ControllerGrpcClient::connect(controller_addr)
-                .await
-                .unwrap(),
-        );
+        self.client = Some(ControllerGrpcClient::new(
+            tls::connect_addr(controller_addr).await.unwrap(),
+        ));
     }
 
     async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        if let Some(max) = self.max_records {
+            if self.records_sent >= max {
+                if self.records_sent == max {
+                    ctx.report_error(
+                        "Preview output limit reached".to_string(),
+                        format!(
+                            "Only the first {} records output by this preview are shown",
+                            max
+                        ),
+                    )
+                    .await;
+                    self.send_done(ctx).await;
+                    self.records_sent += 1;
+                }
+                return;
+            }
+            self.records_sent += 1;
+        }
+
         let value = serde_json::to_string(&record.value).unwrap();
         self.client
             .as_mut()
@@ -55,12 +96,20 @@ impl<K: Key, T: Data + Serialize> GrpcSink<K, T> {
                 key: format!("{:?}", record.key),
                 value,
                 done: false,
+                tag: self.tag.clone().unwrap_or_default(),
             })
             .await
             .unwrap();
     }
 
     async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        // the output was already marked done if it reached the limit
+        if self.max_records.map(|max| self.records_sent > max) != Some(true) {
+            self.send_done(ctx).await;
+        }
+    }
+
+    async fn send_done(&mut self, ctx: &mut Context<(), ()>) {
         self.client
             .as_mut()
             .unwrap()
@@ -72,6 +121,7 @@ impl<K: Key, T: Data + Serialize> GrpcSink<K, T> {
                 key: "".to_string(),
                 value: "".to_string(),
                 done: true,
+                tag: self.tag.clone().unwrap_or_default(),
             })
             .await
             .unwrap();
@@ -0,0 +1,403 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant, SystemTime},
+};
+
+use arroyo_macro::{process_fn, StreamNode};
+use arroyo_metrics::counter_for_task;
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_state::hash_key;
+use arroyo_types::*;
+use prometheus::IntCounter;
+use tracing::warn;
+
+use crate::engine::Context;
+
+// tenants past this many on a subtask share the metrics labelled "other", so that a job with many
+// tenants doesn't report an unbounded number of series
+const MAX_TENANT_LABELS: usize = 1000;
+
+// how often the queued records of tenants are released as their buckets refill
+const RELEASE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Limits the rate of the records read from a source for each tenant, so that one noisy tenant of
+/// a shared pipeline can't starve the others. Records are partitioned by their tenant key before
+/// this operator, and each tenant has a token bucket that refills at `max_rate` records per second
+/// up to `burst` records.
+///
+/// Records of a tenant that's over its rate are dropped (shed), or if `max_queued` is set, queued
+/// and released in order as its bucket refills, with those arriving once its queue is full shed.
+/// Queued records keep their timestamps, so those released after the watermark has passed them
+/// may be dropped as late by windows downstream.
+#[derive(StreamNode)]
+pub struct TenantLimit<K: Key, T: Data> {
+    max_rate: f64,
+    burst: f64,
+    max_queued: Option<usize>,
+    tenant_fn: Box<dyn Fn(&T) -> String + Send>,
+    buckets: HashMap<K, TokenBucket>,
+    queues: HashMap<K, VecDeque<(SystemTime, T)>>,
+    metrics: HashMap<K, TenantMetrics>,
+    other_metrics: Option<TenantMetrics>,
+    warned: bool,
+}
+
+/// Tokens that refill at a fixed rate up to a capacity; each record forwarded takes one
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(capacity: f64, now: Instant) -> Self {
+        TokenBucket {
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant, rate: f64, capacity: f64) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(capacity);
+        self.refilled_at = now;
+    }
+
+    fn try_take(&mut self, now: Instant, rate: f64, capacity: f64) -> bool {
+        self.refill(now, rate, capacity);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Admission {
+    Forward,
+    Queue,
+    Shed,
+}
+
+#[derive(Clone)]
+struct TenantMetrics {
+    records: Option<IntCounter>,
+    queued: Option<IntCounter>,
+    shed: Option<IntCounter>,
+}
+
+impl TenantMetrics {
+    fn new(task_info: &TaskInfo, tenant: &str) -> Self {
+        let labels = || HashMap::from([("tenant".to_string(), tenant.to_string())]);
+        TenantMetrics {
+            records: counter_for_task(
+                task_info,
+                TENANT_RECORDS,
+                "Count of records of a tenant forwarded within its rate limit",
+                labels(),
+            ),
+            queued: counter_for_task(
+                task_info,
+                TENANT_RECORDS_QUEUED,
+                "Count of records of a tenant queued for being over its rate limit",
+                labels(),
+            ),
+            shed: counter_for_task(
+                task_info,
+                TENANT_RECORDS_SHED,
+                "Count of records of a tenant dropped for being over its rate limit",
+                labels(),
+            ),
+        }
+    }
+}
+
+fn inc(counter: &Option<IntCounter>) {
+    if let Some(counter) = counter {
+        counter.inc();
+    }
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = (), out_t = T)]
+impl<K: Key, T: Data> TenantLimit<K, T> {
+    fn name(&self) -> String {
+        "TenantLimit".to_string()
+    }
+
+    pub fn new(
+        max_rate: u32,
+        burst: u32,
+        max_queued: Option<usize>,
+        tenant_fn: impl Fn(&T) -> String + Send + 'static,
+    ) -> Self {
+        TenantLimit {
+            max_rate: max_rate as f64,
+            burst: burst.max(1) as f64,
+            max_queued,
+            tenant_fn: Box::new(tenant_fn),
+            buckets: HashMap::new(),
+            queues: HashMap::new(),
+            metrics: HashMap::new(),
+            other_metrics: None,
+            warned: false,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("q", "tenant limit queues")]
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.max_queued.map(|_| RELEASE_INTERVAL)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let key_range = ctx.task_info.key_range.clone();
+        let mut gs = ctx
+            .state
+            .get_global_keyed_state::<usize, Vec<(K, Vec<(SystemTime, T)>)>>('q')
+            .await;
+
+        // queues are restored by the subtask that now owns their tenant, which may not be the one
+        // that checkpointed them if the parallelism has changed
+        for (key, queue) in gs.get_all().into_iter().flatten() {
+            if key_range.contains(&hash_key(key)) {
+                self.queues
+                    .entry(key.clone())
+                    .or_default()
+                    .extend(queue.iter().cloned());
+            }
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), T>) {
+        let key = record
+            .key
+            .as_ref()
+            .expect("records are keyed by tenant before being limited");
+        let metrics = self.metrics(key, &record.value, &ctx.task_info);
+
+        match self.admit(key, record.timestamp, &record.value, Instant::now()) {
+            Admission::Forward => {
+                inc(&metrics.records);
+                ctx.collect(Record {
+                    timestamp: record.timestamp,
+                    key: None,
+                    value: record.value.clone(),
+                })
+                .await;
+            }
+            Admission::Queue => {
+                inc(&metrics.queued);
+            }
+            Admission::Shed => {
+                if !self.warned {
+                    warn!(
+                        "[{}] tenant {} is over its limit of {} records/sec; dropping its excess \
+                        records",
+                        ctx.task_info.operator_name,
+                        (self.tenant_fn)(&record.value),
+                        self.max_rate
+                    );
+                    self.warned = true;
+                }
+                inc(&metrics.shed);
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, ctx: &mut Context<(), T>) {
+        for (key, timestamp, value) in self.release(Instant::now()) {
+            let metrics = self.metrics(&key, &value, &ctx.task_info);
+            inc(&metrics.records);
+            ctx.collect(Record {
+                timestamp,
+                key: None,
+                value,
+            })
+            .await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), T>) {
+        let queues: Vec<(K, Vec<(SystemTime, T)>)> = self
+            .queues
+            .iter()
+            .map(|(key, queue)| (key.clone(), queue.iter().cloned().collect()))
+            .collect();
+
+        let mut gs = ctx.state.get_global_keyed_state('q').await;
+        gs.insert(ctx.task_info.task_index, queues).await;
+    }
+
+    /// Decides what happens to a record of the tenant `key` arriving at `now`, queueing it if
+    /// that's the decision
+    fn admit(&mut self, key: &K, timestamp: SystemTime, value: &T, now: Instant) -> Admission {
+        let (rate, burst) = (self.max_rate, self.burst);
+        let queued = self.queues.get(key).map_or(0, VecDeque::len);
+        let bucket = self
+            .buckets
+            .entry(key.clone())
+            .or_insert_with(|| TokenBucket::full(burst, now));
+
+        // a tenant's records are forwarded in order, so none may skip ahead of its queue
+        if queued == 0 && bucket.try_take(now, rate, burst) {
+            return Admission::Forward;
+        }
+
+        match self.max_queued {
+            Some(max_queued) if queued < max_queued => {
+                self.queues
+                    .entry(key.clone())
+                    .or_default()
+                    .push_back((timestamp, value.clone()));
+                Admission::Queue
+            }
+            _ => Admission::Shed,
+        }
+    }
+
+    /// Takes the queued records that the tenants' buckets have refilled enough for by `now`
+    fn release(&mut self, now: Instant) -> Vec<(K, SystemTime, T)> {
+        let (rate, burst) = (self.max_rate, self.burst);
+        let mut released = vec![];
+        for (key, queue) in self.queues.iter_mut() {
+            let bucket = self
+                .buckets
+                .entry(key.clone())
+                .or_insert_with(|| TokenBucket::full(burst, now));
+            while !queue.is_empty() && bucket.try_take(now, rate, burst) {
+                let (timestamp, value) = queue.pop_front().unwrap();
+                released.push((key.clone(), timestamp, value));
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+
+        // a full bucket is the same as a new one, so those of idle tenants needn't be kept
+        let queues = &self.queues;
+        self.buckets.retain(|key, bucket| {
+            bucket.refill(now, rate, burst);
+            bucket.tokens < burst || queues.contains_key(key)
+        });
+
+        released
+    }
+
+    fn metrics(&mut self, key: &K, value: &T, task_info: &TaskInfo) -> TenantMetrics {
+        if let Some(metrics) = self.metrics.get(key) {
+            return metrics.clone();
+        }
+
+        if self.metrics.len() >= MAX_TENANT_LABELS {
+            return self
+                .other_metrics
+                .get_or_insert_with(|| TenantMetrics::new(task_info, "other"))
+                .clone();
+        }
+
+        let metrics = TenantMetrics::new(task_info, &(self.tenant_fn)(value));
+        self.metrics.insert(key.clone(), metrics.clone());
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(max_rate: u32, burst: u32, max_queued: Option<usize>) -> TenantLimit<String, u32> {
+        TenantLimit::new(max_rate, burst, max_queued, |v: &u32| v.to_string())
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(2.0, start);
+
+        assert!(bucket.try_take(start, 10.0, 2.0));
+        assert!(bucket.try_take(start, 10.0, 2.0));
+        assert!(!bucket.try_take(start, 10.0, 2.0));
+
+        // refills at 10 tokens per second, up to its capacity
+        assert!(bucket.try_take(start + Duration::from_millis(100), 10.0, 2.0));
+        assert!(!bucket.try_take(start + Duration::from_millis(100), 10.0, 2.0));
+        bucket.refill(start + Duration::from_secs(10), 10.0, 2.0);
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn test_shed() {
+        let mut limit = limit(1, 2, None);
+        let now = Instant::now();
+        let (noisy, quiet) = ("noisy".to_string(), "quiet".to_string());
+
+        let admitted: Vec<_> = (0..4)
+            .map(|i| limit.admit(&noisy, SystemTime::UNIX_EPOCH, &i, now))
+            .collect();
+        assert_eq!(
+            admitted,
+            vec![
+                Admission::Forward,
+                Admission::Forward,
+                Admission::Shed,
+                Admission::Shed
+            ]
+        );
+
+        // other tenants aren't affected by one that's over its limit
+        assert_eq!(
+            limit.admit(&quiet, SystemTime::UNIX_EPOCH, &0, now),
+            Admission::Forward
+        );
+        assert!(limit.queues.is_empty());
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut limit = limit(10, 1, Some(2));
+        let now = Instant::now();
+        let tenant = "tenant".to_string();
+        let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        let admitted: Vec<_> = (0..4)
+            .map(|i| limit.admit(&tenant, t(i), &(i as u32), now))
+            .collect();
+        assert_eq!(
+            admitted,
+            vec![
+                Admission::Forward,
+                Admission::Queue,
+                Admission::Queue,
+                Admission::Shed
+            ]
+        );
+
+        // nothing is released until the bucket has refilled
+        assert!(limit.release(now).is_empty());
+        assert_eq!(
+            limit.release(now + Duration::from_millis(100)),
+            vec![(tenant.clone(), t(1), 1)]
+        );
+
+        // records can't skip ahead of the queue, even once there are tokens for them
+        assert_eq!(
+            limit.admit(&tenant, t(4), &4, now + Duration::from_millis(300)),
+            Admission::Queue
+        );
+        assert_eq!(
+            limit.release(now + Duration::from_millis(300)),
+            vec![(tenant.clone(), t(2), 2)]
+        );
+        assert_eq!(
+            limit.release(now + Duration::from_millis(400)),
+            vec![(tenant.clone(), t(4), 4)]
+        );
+        assert!(limit.queues.is_empty());
+
+        // once the bucket is full again it's dropped
+        limit.release(now + Duration::from_secs(1));
+        assert!(limit.buckets.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    time::{Duration, SystemTime},
+};
+
+use arroyo_macro::{process_fn, StreamNode};
+use arroyo_metrics::counter_for_task;
+use arroyo_types::*;
+use prometheus::IntCounter;
+use tracing::warn;
+
+use crate::engine::Context;
+
+/// Checks the timestamps of records read from a source against the current time, so that a
+/// producer with a broken clock can't push the watermark far into the future (dropping every
+/// other record as late) or hold windows open with records from long ago.
+///
+/// Records more than `max_future` ahead of now or more than `max_age` behind it are either
+/// dropped or have their timestamp clamped to the bound, depending on `clamp`.
+#[derive(StreamNode)]
+pub struct TimestampBounds<K: Key, T: Data> {
+    max_future: Option<Duration>,
+    max_age: Option<Duration>,
+    clamp: bool,
+    adjusted: Option<IntCounter>,
+    rejected: Option<IntCounter>,
+    _t: PhantomData<(K, T)>,
+}
+
+/// The timestamp a record at `timestamp` should have at `now`, which is `None` if it's out of
+/// bounds and shouldn't be clamped
+fn bounded(
+    timestamp: SystemTime,
+    now: SystemTime,
+    max_future: Option<Duration>,
+    max_age: Option<Duration>,
+    clamp: bool,
+) -> Option<SystemTime> {
+    let latest = max_future.map(|d| now + d);
+    let earliest = max_age.and_then(|d| now.checked_sub(d));
+
+    let bound = match (latest, earliest) {
+        (Some(latest), _) if timestamp > latest => latest,
+        (_, Some(earliest)) if timestamp < earliest => earliest,
+        _ => return Some(timestamp),
+    };
+
+    clamp.then_some(bound)
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = T)]
+impl<K: Key, T: Data> TimestampBounds<K, T> {
+    fn name(&self) -> String {
+        "TimestampBounds".to_string()
+    }
+
+    pub fn new(max_future: Option<Duration>, max_age: Option<Duration>, clamp: bool) -> Self {
+        TimestampBounds {
+            max_future,
+            max_age,
+            clamp,
+            adjusted: None,
+            rejected: None,
+            _t: PhantomData,
+        }
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<K, T>) {
+        self.adjusted = counter_for_task(
+            &ctx.task_info,
+            TIMESTAMPS_ADJUSTED,
+            "Count of records whose timestamps were clamped to the source's timestamp bounds",
+            HashMap::new(),
+        );
+        self.rejected = counter_for_task(
+            &ctx.task_info,
+            TIMESTAMPS_REJECTED,
+            "Count of records dropped for timestamps outside the source's timestamp bounds",
+            HashMap::new(),
+        );
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, T>) {
+        let timestamp = bounded(
+            record.timestamp,
+            SystemTime::now(),
+            self.max_future,
+            self.max_age,
+            self.clamp,
+        );
+
+        match timestamp {
+            Some(timestamp) if timestamp == record.timestamp => {
+                ctx.collect(record.clone()).await;
+            }
+            Some(timestamp) => {
+                if let Some(adjusted) = &self.adjusted {
+                    adjusted.inc();
+                }
+                ctx.collect(Record {
+                    timestamp,
+                    key: record.key.clone(),
+                    value: record.value.clone(),
+                })
+                .await;
+            }
+            None => {
+                if let Some(rejected) = &self.rejected {
+                    if rejected.get() == 0 {
+                        warn!(
+                            "[{}] dropping record with out-of-bounds timestamp {}",
+                            ctx.task_info.operator_name,
+                            to_millis(record.timestamp)
+                        );
+                    }
+                    rejected.inc();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded() {
+        let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let now = t(1000);
+        let future = Some(Duration::from_secs(10));
+        let age = Some(Duration::from_secs(100));
+
+        assert_eq!(bounded(t(1005), now, future, age, false), Some(t(1005)));
+        assert_eq!(bounded(t(950), now, future, age, false), Some(t(950)));
+        assert_eq!(bounded(t(1011), now, future, age, false), None);
+        assert_eq!(bounded(t(899), now, future, age, false), None);
+        assert_eq!(bounded(t(1011), now, future, age, true), Some(t(1010)));
+        assert_eq!(bounded(t(899), now, future, age, true), Some(t(900)));
+
+        // only the bounds that are set are applied
+        assert_eq!(bounded(t(5000), now, None, age, false), Some(t(5000)));
+        assert_eq!(bounded(t(0), now, future, None, false), Some(t(0)));
+    }
+}
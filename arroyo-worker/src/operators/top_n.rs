@@ -0,0 +1,291 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_types::*;
+
+/// Records grouped by their sort key, in sort order.
+struct SortedRows<SK, T> {
+    rows: BTreeMap<SK, Vec<T>>,
+    len: usize,
+}
+
+impl<SK: Ord + Clone, T: PartialEq> SortedRows<SK, T> {
+    fn new() -> Self {
+        Self {
+            rows: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    fn insert(&mut self, key: SK, value: T) {
+        self.rows.entry(key).or_default().push(value);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, key: &SK, value: &T) -> bool {
+        let Some(values) = self.rows.get_mut(key) else {
+            return false;
+        };
+
+        let Some(position) = values.iter().position(|v| v == value) else {
+            return false;
+        };
+
+        values.remove(position);
+        if values.is_empty() {
+            self.rows.remove(key);
+        }
+        self.len -= 1;
+        true
+    }
+
+    fn last_key(&self) -> Option<&SK> {
+        self.rows.keys().next_back()
+    }
+
+    fn pop_first(&mut self) -> Option<(SK, T)> {
+        let mut entry = self.rows.first_entry()?;
+        let key = entry.key().clone();
+        let value = entry.get_mut().remove(0);
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        self.len -= 1;
+        Some((key, value))
+    }
+
+    fn pop_last(&mut self) -> Option<(SK, T)> {
+        let mut entry = self.rows.last_entry()?;
+        let key = entry.key().clone();
+        let value = entry.get_mut().pop().unwrap();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        self.len -= 1;
+        Some((key, value))
+    }
+
+    fn values(&self) -> impl Iterator<Item = &T> {
+        self.rows.values().flatten()
+    }
+}
+
+/// Maintains the first `max_elements` records by sort key, returning the changes to that set
+/// as each record is added or retracted.
+struct TopNState<SK, T> {
+    max_elements: usize,
+    // when set, records that fall out of the top are kept so they can be promoted if a record
+    // in the top is retracted
+    retain_all: bool,
+    top: SortedRows<SK, T>,
+    rest: SortedRows<SK, T>,
+}
+
+impl<SK: Ord + Clone, T: Clone + PartialEq> TopNState<SK, T> {
+    fn new(max_elements: usize, retain_all: bool) -> Self {
+        Self {
+            max_elements,
+            retain_all,
+            top: SortedRows::new(),
+            rest: SortedRows::new(),
+        }
+    }
+
+    fn add(&mut self, key: SK, value: T, changes: &mut Vec<UpdatingData<T>>) {
+        if self.top.len < self.max_elements {
+            self.top.insert(key, value.clone());
+            changes.push(UpdatingData::Append(value));
+        } else if self.top.last_key().map(|last| key < *last).unwrap_or(false) {
+            let (last_key, last) = self.top.pop_last().unwrap();
+            self.top.insert(key, value.clone());
+            changes.push(UpdatingData::Retract(last.clone()));
+            changes.push(UpdatingData::Append(value));
+            if self.retain_all {
+                self.rest.insert(last_key, last);
+            }
+        } else if self.retain_all {
+            self.rest.insert(key, value);
+        }
+    }
+
+    fn retract(&mut self, key: SK, value: T, changes: &mut Vec<UpdatingData<T>>) {
+        if self.top.remove(&key, &value) {
+            changes.push(UpdatingData::Retract(value));
+            if let Some((key, promoted)) = self.rest.pop_first() {
+                self.top.insert(key, promoted.clone());
+                changes.push(UpdatingData::Append(promoted));
+            }
+        } else {
+            self.rest.remove(&key, &value);
+        }
+    }
+
+    fn values(&self) -> Vec<T> {
+        self.top
+            .values()
+            .chain(self.rest.values())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Emits the first `max_elements` records of its input (in the order given by `extractor`) as an
+/// updating stream. Used both as a partial top-N ahead of a shuffle and as the final merge, which
+/// runs with `retain_all` set so that retractions from upstream can be backfilled.
+#[derive(StreamNode)]
+pub struct TopN<K: Key, InT: Data, T: Data, SK: Ord + Clone + Send + 'static> {
+    extractor: fn(&T) -> SK,
+    updates: fn(&InT) -> UpdatingData<T>,
+    state: TopNState<SK, T>,
+    _t: PhantomData<K>,
+}
+
+#[process_fn(in_k = K, in_t = InT, out_k = (), out_t = UpdatingData<T>)]
+impl<K: Key, InT: Data, T: Data, SK: Ord + Clone + Send + 'static> TopN<K, InT, T, SK> {
+    fn name(&self) -> String {
+        "TopN".to_string()
+    }
+
+    pub fn new(
+        max_elements: usize,
+        retain_all: bool,
+        extractor: fn(&T) -> SK,
+        updates: fn(&InT) -> UpdatingData<T>,
+    ) -> Self {
+        Self {
+            extractor,
+            updates,
+            state: TopNState::new(max_elements, retain_all),
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("s", "top n state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), UpdatingData<T>>) {
+        let mut gs = ctx
+            .state
+            .get_global_keyed_state::<usize, (usize, Vec<T>)>('s')
+            .await;
+
+        // records restored here were already emitted before the checkpoint, so the changes from
+        // re-adding them are dropped
+        let mut changes = vec![];
+        let restored: Vec<T> = gs
+            .get_all()
+            .into_iter()
+            .filter(|(i, _)| *i % ctx.task_info.parallelism == ctx.task_info.task_index)
+            .flat_map(|(_, values)| values.clone())
+            .collect();
+
+        for value in restored {
+            self.state
+                .add((self.extractor)(&value), value, &mut changes);
+        }
+    }
+
+    async fn process_element(
+        &mut self,
+        record: &Record<K, InT>,
+        ctx: &mut Context<(), UpdatingData<T>>,
+    ) {
+        let mut changes = vec![];
+        match (self.updates)(&record.value) {
+            UpdatingData::Append(value) => {
+                self.state
+                    .add((self.extractor)(&value), value, &mut changes);
+            }
+            UpdatingData::Retract(value) => {
+                self.state
+                    .retract((self.extractor)(&value), value, &mut changes);
+            }
+            UpdatingData::Update { old, new } => {
+                self.state
+                    .retract((self.extractor)(&old), old, &mut changes);
+                self.state.add((self.extractor)(&new), new, &mut changes);
+            }
+        }
+
+        for change in changes {
+            ctx.collect(Record {
+                timestamp: record.timestamp,
+                key: None,
+                value: change,
+            })
+            .await;
+        }
+    }
+
+    async fn handle_checkpoint(
+        &mut self,
+        _: &CheckpointBarrier,
+        ctx: &mut Context<(), UpdatingData<T>>,
+    ) {
+        let mut gs = ctx.state.get_global_keyed_state('s').await;
+        gs.insert(
+            ctx.task_info.task_index,
+            (ctx.task_info.task_index, self.state.values()),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TopNState;
+    use arroyo_types::UpdatingData;
+
+    fn add(state: &mut TopNState<i64, i64>, value: i64) -> Vec<UpdatingData<i64>> {
+        let mut changes = vec![];
+        state.add(value, value, &mut changes);
+        changes
+    }
+
+    fn retract(state: &mut TopNState<i64, i64>, value: i64) -> Vec<UpdatingData<i64>> {
+        let mut changes = vec![];
+        state.retract(value, value, &mut changes);
+        changes
+    }
+
+    #[test]
+    fn test_top_n_displaces_largest() {
+        let mut state = TopNState::new(2, false);
+
+        assert_eq!(add(&mut state, 5), vec![UpdatingData::Append(5)]);
+        assert_eq!(add(&mut state, 3), vec![UpdatingData::Append(3)]);
+        assert_eq!(add(&mut state, 7), vec![]);
+        assert_eq!(
+            add(&mut state, 1),
+            vec![UpdatingData::Retract(5), UpdatingData::Append(1)]
+        );
+        assert_eq!(add(&mut state, 3), vec![]);
+
+        let mut values = state.values();
+        values.sort();
+        assert_eq!(values, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_top_n_promotes_on_retract() {
+        let mut state = TopNState::new(2, true);
+
+        add(&mut state, 5);
+        add(&mut state, 3);
+        add(&mut state, 7);
+        add(&mut state, 1);
+
+        assert_eq!(
+            retract(&mut state, 1),
+            vec![UpdatingData::Retract(1), UpdatingData::Append(5)]
+        );
+        assert_eq!(retract(&mut state, 7), vec![]);
+        assert_eq!(retract(&mut state, 3), vec![UpdatingData::Retract(3)]);
+        assert_eq!(retract(&mut state, 100), vec![]);
+
+        assert_eq!(state.values(), vec![5]);
+    }
+}
@@ -10,7 +10,8 @@ use tracing::debug;
 #[derive(StreamNode)]
 pub struct TumblingAggregatingWindowFunc<K: Key, T: Data, BinA: Data, OutT: Data> {
     width: Duration,
-    aggregator: fn(&BinA) -> OutT,
+    // returns None for bins that have no rows left (e.g., when they were all retracted)
+    aggregator: fn(&BinA) -> Option<OutT>,
     bin_merger: fn(&T, Option<&BinA>) -> BinA,
     state: TumblingWindowState,
     _t: PhantomData<K>,
@@ -33,7 +34,7 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
     pub fn new(
         width: Duration,
         // TODO: this can consume the bin, as we drop it right after.
-        aggregator: fn(&BinA) -> OutT,
+        aggregator: fn(&BinA) -> Option<OutT>,
         bin_merger: fn(&T, Option<&BinA>) -> BinA,
     ) -> Self {
         TumblingAggregatingWindowFunc {
@@ -136,11 +137,13 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
         let window_end = self.window_end(bin_start);
         let mut records = vec![];
         for (key, value) in aggregating_map.evict_for_timestamp(bin_start) {
-            records.push(Record {
-                timestamp: window_end,
-                key: Some(key.clone()),
-                value: (self.aggregator)(&value),
-            });
+            if let Some(value) = (self.aggregator)(&value) {
+                records.push(Record {
+                    timestamp: window_end,
+                    key: Some(key.clone()),
+                    value,
+                });
+            }
         }
         self.state = match aggregating_map.get_min_time() {
             Some(min_time) => TumblingWindowState::BufferedData {
@@ -9,7 +9,8 @@ use rand::{rngs::SmallRng, RngCore, SeedableRng};
 use std::time::Duration;
 
 use super::{
-    InstantWindowAssigner, SlidingWindowAssigner, TimeWindowAssigner, TumblingWindowAssigner,
+    CalendarWindowAssigner, InstantWindowAssigner, SlidingWindowAssigner, TimeWindowAssigner,
+    TumblingWindowAssigner,
 };
 
 pub mod aggregators {
@@ -59,10 +60,11 @@ pub struct KeyedWindowFunc<K: Key, T: Data, OutT: Data, W: TimeWindowAssigner<K,
 impl<K: Key, T: Data, OutT: Data, W: TimeWindowAssigner<K, T>> KeyedWindowFunc<K, T, OutT, W> {
     pub fn tumbling_window(
         size: Duration,
+        offset: Duration,
         operation: WindowOperation<T, OutT>,
     ) -> KeyedWindowFunc<K, T, OutT, TumblingWindowAssigner> {
         KeyedWindowFunc {
-            assigner: TumblingWindowAssigner { size },
+            assigner: TumblingWindowAssigner { size, offset },
             operation,
             salt: SmallRng::from_entropy().next_u64(),
             _phantom: PhantomData,
@@ -72,15 +74,33 @@ impl<K: Key, T: Data, OutT: Data, W: TimeWindowAssigner<K, T>> KeyedWindowFunc<K
     pub fn sliding_window(
         size: Duration,
         slide: Duration,
+        offset: Duration,
         operation: WindowOperation<T, OutT>,
     ) -> KeyedWindowFunc<K, T, OutT, SlidingWindowAssigner> {
         KeyedWindowFunc {
-            assigner: SlidingWindowAssigner { size, slide },
+            assigner: SlidingWindowAssigner {
+                size,
+                slide,
+                offset,
+            },
             operation,
             salt: SmallRng::from_entropy().next_u64(),
             _phantom: PhantomData,
         }
     }
+    pub fn calendar_window(
+        unit: CalendarUnit,
+        timezone: &str,
+        operation: WindowOperation<T, OutT>,
+    ) -> KeyedWindowFunc<K, T, OutT, CalendarWindowAssigner> {
+        KeyedWindowFunc {
+            assigner: CalendarWindowAssigner::new(unit, timezone),
+            operation,
+            salt: SmallRng::from_entropy().next_u64(),
+            _phantom: PhantomData,
+        }
+    }
+
     pub fn instant_window(
         operation: WindowOperation<T, OutT>,
     ) -> KeyedWindowFunc<K, T, OutT, InstantWindowAssigner> {
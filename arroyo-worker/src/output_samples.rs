@@ -0,0 +1,111 @@
+//! Keeps the most recent records written by each sink subtask, so that the output of a running
+//! pipeline can be inspected without access to the system it writes to. Records are only
+//! serialized (as JSON) when they're sampled, so keeping them costs a clone per record.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use arroyo_rpc::grpc::SinkOutputSample;
+use arroyo_types::{to_micros, Data, Key, Record, TaskInfo};
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+/// Number of records retained for each sink subtask
+pub const SAMPLES_PER_SUBTASK: usize = 100;
+
+struct Sample {
+    timestamp: SystemTime,
+    value: Box<dyn Fn() -> serde_json::Result<String> + Send>,
+}
+
+type Buffer = Arc<Mutex<VecDeque<Sample>>>;
+
+lazy_static! {
+    // (operator id, subtask index) -> recent records, oldest first
+    static ref BUFFERS: Mutex<HashMap<(String, u32), Buffer>> = Mutex::new(HashMap::new());
+}
+
+pub struct OutputSampler {
+    buffer: Buffer,
+}
+
+impl OutputSampler {
+    /// Creates the buffer for a sink subtask, replacing that of any previous run of it
+    pub fn register(task_info: &TaskInfo) -> Self {
+        let buffer = Buffer::default();
+        BUFFERS.lock().unwrap().insert(
+            (task_info.operator_id.clone(), task_info.task_index as u32),
+            buffer.clone(),
+        );
+        Self { buffer }
+    }
+
+    pub fn add<K: Key, T: Data + Serialize>(&self, record: &Record<K, T>) {
+        let value = record.value.clone();
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() == SAMPLES_PER_SUBTASK {
+            buffer.pop_front();
+        }
+        buffer.push_back(Sample {
+            timestamp: record.timestamp,
+            value: Box::new(move || serde_json::to_string(&value)),
+        });
+    }
+}
+
+/// The most recent `count` records written by the subtasks of `operator_id` that run on this
+/// worker, newest first
+pub fn sample(operator_id: &str, count: usize) -> Vec<SinkOutputSample> {
+    let buffers: Vec<_> = BUFFERS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|((op, _), _)| op == operator_id)
+        .map(|((_, task_index), buffer)| (*task_index, buffer.clone()))
+        .collect();
+
+    let mut samples = vec![];
+    for (task_index, buffer) in buffers {
+        let buffer = buffer.lock().unwrap();
+        samples.extend(buffer.iter().rev().take(count).map(|s| SinkOutputSample {
+            task_index,
+            timestamp: to_micros(s.timestamp),
+            value: (s.value)().unwrap_or_else(|e| format!("<failed to serialize: {}>", e)),
+        }));
+    }
+
+    samples.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    samples.truncate(count);
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_types::{from_micros, get_test_task_info};
+
+    #[test]
+    fn test_sample_keeps_most_recent() {
+        let mut task_info = get_test_task_info();
+        task_info.operator_id = "sample_sink".to_string();
+        let sampler = OutputSampler::register(&task_info);
+
+        for i in 0..(SAMPLES_PER_SUBTASK as u64 + 10) {
+            sampler.add(&Record {
+                timestamp: from_micros(i),
+                key: None::<()>,
+                value: format!("record-{}", i),
+            });
+        }
+
+        let samples = sample("sample_sink", 3);
+        assert_eq!(
+            vec!["\"record-109\"", "\"record-108\"", "\"record-107\""],
+            samples.iter().map(|s| s.value.as_str()).collect::<Vec<_>>()
+        );
+
+        assert_eq!(SAMPLES_PER_SUBTASK, sample("sample_sink", 1000).len());
+        assert!(sample("other_sink", 10).is_empty());
+    }
+}
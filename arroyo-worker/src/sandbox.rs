@@ -0,0 +1,67 @@
+//! Applies the [`SandboxLimits`] that the controller sets on the workers of preview runs.
+//!
+//! Memory and CPU are limited with soft rlimits on the worker process: a worker that exceeds its
+//! memory limit fails to allocate and aborts, and one that exceeds its CPU time is killed with
+//! SIGXCPU, either of which fails the preview. The output limit is enforced by the preview sink,
+//! and the egress limit by connectors and lookup joins as they connect (see
+//! [`arroyo_types::check_egress`]).
+
+use arroyo_types::SandboxLimits;
+use tracing::{info, warn};
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+/// Applies the worker's memory and CPU limits. The CPU limit covers a single run, so this is
+/// called again when a reused worker is assigned a new run.
+pub fn apply_limits() {
+    let limits = SandboxLimits::from_env();
+
+    if let Some(bytes) = limits.memory_bytes {
+        set_soft_limit(libc::RLIMIT_DATA, bytes, "memory");
+    }
+
+    if let Some(seconds) = limits.cpu_seconds {
+        set_soft_limit(libc::RLIMIT_CPU, cpu_seconds_used() + seconds, "CPU time");
+    }
+}
+
+fn set_soft_limit(resource: Resource, value: u64, name: &str) {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        warn!(
+            "failed to read {} limit: {}",
+            name,
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+
+    // the soft limit can't be raised above the hard limit
+    limit.rlim_cur = (value as libc::rlim_t).min(limit.rlim_max);
+
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        warn!(
+            "failed to limit {} of sandboxed worker: {}",
+            name,
+            std::io::Error::last_os_error()
+        );
+    } else {
+        info!("limited {} of sandboxed worker to {}", name, limit.rlim_cur);
+    }
+}
+
+fn cpu_seconds_used() -> u64 {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return 0;
+    }
+
+    (usage.ru_utime.tv_sec + usage.ru_stime.tv_sec) as u64
+}
@@ -0,0 +1,299 @@
+//! Caches for UDFs that need to remember values between calls, e.g., to memoize an expensive
+//! parse of a value that repeats across records. A cache is declared as a constant in the UDF
+//! definitions and used from UDF bodies:
+//!
+//! ```ignore
+//! use arroyo_worker::udf_cache::UdfCache;
+//!
+//! const PARSED: UdfCache<String, Vec<String>> =
+//!     UdfCache::new("parsed", std::time::Duration::from_secs(600), 10_000);
+//!
+//! pub fn parse_path(path: String) -> Vec<String> {
+//!     PARSED.get_or_insert_with(&path, || path.split('/').map(|s| s.to_string()).collect())
+//! }
+//! ```
+//!
+//! Each subtask of an operator has its own entries, which are written to the operator's state
+//! when it checkpoints and restored with it; unlike state kept in a static, they're neither shared
+//! between the operators that run on a worker nor lost (or left stale) when a job restarts.
+//! Entries expire a fixed time after they were inserted, by processing time, and when a cache is
+//! full the entry that expires soonest is evicted. Keys and values are stored as json.
+//!
+//! Caches can't be declared when UDFs are sandboxed. Outside of an operator (e.g., when a UDF is
+//! called from a test) every lookup misses and inserts are dropped.
+
+use std::collections::{BTreeSet, HashMap};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use arroyo_state::{BackingStore, StateStore};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::UDF_CACHE_TABLE;
+
+tokio::task_local! {
+    /// The caches of the subtask whose operator is running on the current task
+    pub static UDF_CACHES: UdfCaches;
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CacheEntries {
+    // serialized key -> (expiration, serialized value)
+    entries: HashMap<String, (SystemTime, String)>,
+    #[serde(skip)]
+    expirations: BTreeSet<(SystemTime, String)>,
+}
+
+impl CacheEntries {
+    fn get(&mut self, key: &str, now: SystemTime) -> Option<&str> {
+        if matches!(self.entries.get(key), Some((expires_at, _)) if *expires_at <= now) {
+            self.remove(key);
+            return None;
+        }
+
+        self.entries.get(key).map(|(_, value)| value.as_str())
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((expires_at, _)) = self.entries.remove(key) {
+            self.expirations.remove(&(expires_at, key.to_string()));
+        }
+    }
+
+    fn expire(&mut self, now: SystemTime) {
+        while let Some((expires_at, key)) = self.expirations.first().cloned() {
+            if expires_at > now {
+                break;
+            }
+            self.remove(&key);
+        }
+    }
+
+    fn insert(&mut self, key: String, value: String, expires_at: SystemTime, max_entries: usize) {
+        self.remove(&key);
+        self.expire(SystemTime::now());
+
+        while self.entries.len() >= max_entries {
+            let Some((_, evicted)) = self.expirations.first().cloned() else {
+                return;
+            };
+            self.remove(&evicted);
+        }
+
+        self.expirations.insert((expires_at, key.clone()));
+        self.entries.insert(key, (expires_at, value));
+    }
+
+    fn rebuild_expirations(&mut self) {
+        self.expirations = self
+            .entries
+            .iter()
+            .map(|(key, (expires_at, _))| (*expires_at, key.clone()))
+            .collect();
+    }
+}
+
+/// The entries of all of the caches of a subtask, by cache name
+#[derive(Clone, Default)]
+pub struct UdfCaches {
+    caches: Arc<Mutex<HashMap<String, CacheEntries>>>,
+}
+
+impl UdfCaches {
+    /// Restores the subtask's entries from the operator's state
+    pub async fn restore<S: BackingStore>(state: &mut StateStore<S>, task_index: usize) -> Self {
+        let caches = Self::default();
+
+        let gs = state
+            .get_global_keyed_state::<usize, String>(UDF_CACHE_TABLE)
+            .await;
+        if let Some(snapshot) = gs.get(&task_index) {
+            let mut restored: HashMap<String, CacheEntries> =
+                serde_json::from_str(snapshot).expect("invalid UDF cache state");
+            for entries in restored.values_mut() {
+                entries.rebuild_expirations();
+            }
+            *caches.caches.lock().unwrap() = restored;
+        }
+
+        caches
+    }
+
+    /// Writes the subtask's unexpired entries to the operator's state
+    pub async fn checkpoint<S: BackingStore>(&self, state: &mut StateStore<S>, task_index: usize) {
+        let snapshot = {
+            let mut caches = self.caches.lock().unwrap();
+            // most operators don't run UDFs with caches, and so don't need to write anything
+            if caches.is_empty() {
+                return;
+            }
+
+            let now = SystemTime::now();
+            for entries in caches.values_mut() {
+                entries.expire(now);
+            }
+            serde_json::to_string(&*caches).unwrap()
+        };
+
+        let mut gs = state.get_global_keyed_state(UDF_CACHE_TABLE).await;
+        gs.insert(task_index, snapshot).await;
+    }
+}
+
+/// A cache of values computed by UDFs, which expire `ttl` after they're inserted; at most
+/// `max_entries` are kept by each subtask. Caches are identified by their names, so each cache in
+/// a pipeline must have a different name.
+pub struct UdfCache<K, V> {
+    name: &'static str,
+    ttl: Duration,
+    max_entries: usize,
+    _t: PhantomData<fn(&K) -> V>,
+}
+
+impl<K: Serialize, V: Serialize + DeserializeOwned> UdfCache<K, V> {
+    pub const fn new(name: &'static str, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            name,
+            ttl,
+            max_entries,
+            _t: PhantomData,
+        }
+    }
+
+    fn with_entries<R>(&self, f: impl FnOnce(&mut CacheEntries) -> R) -> Option<R> {
+        UDF_CACHES
+            .try_with(|caches| {
+                let mut caches = caches.caches.lock().unwrap();
+                f(caches.entry(self.name.to_string()).or_default())
+            })
+            .ok()
+    }
+
+    fn serialize_key(key: &K) -> Option<String> {
+        serde_json::to_string(key)
+            .map_err(|e| warn!("failed to serialize UDF cache key: {:?}", e))
+            .ok()
+    }
+
+    /// The cached value for the key, if there is one that hasn't expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let key = Self::serialize_key(key)?;
+        let value = self
+            .with_entries(|entries| entries.get(&key, SystemTime::now()).map(|v| v.to_string()))
+            .flatten()?;
+
+        match serde_json::from_str(&value) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                // e.g., the value type changed since the entry was checkpointed
+                warn!(
+                    "discarding invalid entry of UDF cache {}: {:?}",
+                    self.name, e
+                );
+                self.with_entries(|entries| entries.remove(&key));
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, key: &K, value: &V) {
+        let Some(key) = Self::serialize_key(key) else {
+            return;
+        };
+        let value = match serde_json::to_string(value) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!(
+                    "failed to serialize value for UDF cache {}: {:?}",
+                    self.name, e
+                );
+                return;
+            }
+        };
+
+        let expires_at = SystemTime::now() + self.ttl;
+        self.with_entries(|entries| entries.insert(key, value, expires_at, self.max_entries));
+    }
+
+    /// The cached value for the key, or else the value computed by `f`, which is then cached
+    pub fn get_or_insert_with(&self, key: &K, f: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.get(key) {
+            return value;
+        }
+
+        let value = f();
+        self.insert(key, &value);
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::{CacheEntries, UdfCache, UdfCaches, UDF_CACHES};
+
+    const CACHE: UdfCache<String, u64> = UdfCache::new("lengths", Duration::from_secs(60), 2);
+
+    #[tokio::test]
+    async fn test_get_or_insert() {
+        UDF_CACHES
+            .scope(UdfCaches::default(), async {
+                let mut calls = 0;
+                let mut len = |s: &str| {
+                    CACHE.get_or_insert_with(&s.to_string(), || {
+                        calls += 1;
+                        s.len() as u64
+                    })
+                };
+
+                assert_eq!(len("a"), 1);
+                assert_eq!(len("a"), 1);
+                assert_eq!(len("bb"), 2);
+                // evicts "a", which expires first
+                assert_eq!(len("ccc"), 3);
+                assert_eq!(len("a"), 1);
+                drop(len);
+
+                assert_eq!(calls, 4);
+            })
+            .await;
+    }
+
+    #[test]
+    fn test_outside_operator() {
+        CACHE.insert(&"a".to_string(), &1);
+        assert_eq!(CACHE.get(&"a".to_string()), None);
+    }
+
+    #[test]
+    fn test_expiration() {
+        let now = SystemTime::now();
+        let mut entries = CacheEntries::default();
+        entries.insert(
+            "a".to_string(),
+            "1".to_string(),
+            now + Duration::from_secs(1),
+            10,
+        );
+        entries.insert(
+            "b".to_string(),
+            "2".to_string(),
+            now + Duration::from_secs(10),
+            10,
+        );
+
+        assert_eq!(entries.get("a", now), Some("1"));
+        assert_eq!(entries.get("a", now + Duration::from_secs(2)), None);
+        assert_eq!(entries.get("b", now + Duration::from_secs(2)), Some("2"));
+
+        let mut restored: CacheEntries =
+            serde_json::from_str(&serde_json::to_string(&entries).unwrap()).unwrap();
+        restored.rebuild_expirations();
+        restored.expire(now + Duration::from_secs(20));
+        assert!(restored.entries.is_empty());
+    }
+}
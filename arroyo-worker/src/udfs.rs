@@ -0,0 +1,300 @@
+//! Runs SQL UDFs in a WebAssembly sandbox, for deployments that run untrusted users' UDFs (see
+//! [`UdfSandbox`]).
+//!
+//! Sandboxed UDFs are compiled into the pipeline's WebAssembly module, which is run with wasmtime.
+//! The module is only given the two functions it uses to exchange data with the worker, so UDFs
+//! have no access to the filesystem, network, clock, or any other I/O; its memory is limited by
+//! the sandbox's memory limit, and each call by its fuel. A call that traps, including by running
+//! out of fuel or memory, panics, which fails the job as a panicking native UDF would.
+//!
+//! Each thread that calls UDFs instantiates the module once and reuses the instance for every
+//! call, so state that a UDF leaves in its memory (e.g., in a static) persists between calls on
+//! the same thread but isn't shared between threads.
+//!
+//! ## Performance
+//!
+//! Sandboxing trades throughput for isolation. Compared to a native UDF, each call additionally
+//!  * encodes its arguments and decodes its result with bincode,
+//!  * copies them in and out of the sandbox's memory, and
+//!  * enters and exits the sandbox, which includes resetting its fuel;
+//!
+//! and the UDF itself runs as WebAssembly compiled by Cranelift, which is slower than natively
+//! compiled code, further slowed by fuel metering. The fixed costs dominate for cheap UDFs (e.g.,
+//! arithmetic or short string manipulation), which are slowed the most, while the relative
+//! overhead of UDFs that do a lot of work per call is smaller. Instantiating the module is a
+//! one-time cost per thread.
+//!
+//! `bench_sandboxed_call` measures the fixed per-call overhead against a native call; run it on
+//! the hardware of interest with
+//!
+//! ```text
+//! cargo test --release -p arroyo-worker bench_sandboxed_call -- --ignored --nocapture
+//! ```
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_types::UdfSandbox;
+use bincode::{config, Decode, Encode};
+use once_cell::sync::OnceCell;
+use tracing::info;
+use wasmtime::{
+    Caller, Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+const WASM_PATH: &str = "wasm_fns_bg.wasm";
+
+static MODULE: OnceCell<(Engine, Module)> = OnceCell::new();
+
+thread_local! {
+    static SANDBOX: RefCell<Option<Sandbox>> = RefCell::new(None);
+}
+
+struct SandboxState {
+    input: Option<Vec<u8>>,
+    output: Option<Vec<u8>>,
+    limits: StoreLimits,
+}
+
+struct Sandbox {
+    store: Store<SandboxState>,
+    instance: Instance,
+    fuel: u64,
+}
+
+fn engine() -> Result<Engine> {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    Engine::new(&config)
+}
+
+fn memory(caller: &mut Caller<'_, SandboxState>) -> Result<wasmtime::Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|e| e.into_memory())
+        .ok_or_else(|| anyhow!("UDF module does not export its memory"))
+}
+
+impl Sandbox {
+    fn new(engine: &Engine, module: &Module, limits: &UdfSandbox) -> Result<Self> {
+        let state = SandboxState {
+            input: None,
+            output: None,
+            limits: StoreLimitsBuilder::new()
+                .memory_size(limits.memory_bytes as usize)
+                .instances(1)
+                .build(),
+        };
+
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+
+        let mut linker = Linker::new(engine);
+        linker.func_wrap(
+            "env",
+            "fetch_data",
+            |mut caller: Caller<'_, SandboxState>, ptr: u32| -> Result<i32> {
+                let memory = memory(&mut caller)?;
+                let input = caller
+                    .data_mut()
+                    .input
+                    .take()
+                    .ok_or_else(|| anyhow!("UDF fetched its arguments more than once"))?;
+                memory.write(&mut caller, ptr as usize, &input)?;
+                Ok(0)
+            },
+        )?;
+
+        linker.func_wrap(
+            "env",
+            "send",
+            |mut caller: Caller<'_, SandboxState>, ptr: u32, len: u32| -> Result<i32> {
+                let memory = memory(&mut caller)?;
+                let mut output = vec![0; len as usize];
+                memory.read(&caller, ptr as usize, &mut output)?;
+                caller.data_mut().output = Some(output);
+                Ok(0)
+            },
+        )?;
+
+        // anything else the module imports is unavailable in the sandbox
+        linker.define_unknown_imports_as_traps(module)?;
+
+        let instance = linker.instantiate(&mut store, module)?;
+
+        Ok(Self {
+            store,
+            instance,
+            fuel: limits.fuel,
+        })
+    }
+
+    fn call(&mut self, name: &str, input: Vec<u8>) -> Result<Vec<u8>> {
+        // each call gets the full amount of fuel, regardless of what earlier calls used
+        let remaining = self.store.consume_fuel(0)?;
+        self.store.add_fuel(self.fuel.saturating_sub(remaining))?;
+
+        let len = input.len() as u32;
+        self.store.data_mut().input = Some(input);
+        self.store.data_mut().output = None;
+
+        self.instance
+            .get_typed_func::<u32, ()>(&mut self.store, name)?
+            .call(&mut self.store, len)?;
+
+        match self.store.data_mut().output.take() {
+            Some(output) => Ok(output),
+            None => bail!("UDF did not return a value"),
+        }
+    }
+}
+
+/// Calls the export `name` of the pipeline's WebAssembly module in this thread's sandbox with the
+/// encoded `args`, returning its decoded result
+pub fn call<Args: Encode, Ret: Decode>(limits: &UdfSandbox, name: &str, args: Args) -> Ret {
+    let input = bincode::encode_to_vec(args, config::standard()).unwrap();
+
+    let output = SANDBOX.with(|sandbox| {
+        let mut sandbox = sandbox.borrow_mut();
+        if sandbox.is_none() {
+            let (engine, module) = MODULE
+                .get_or_try_init(|| {
+                    info!("Loading sandboxed UDFs from {}", WASM_PATH);
+                    let engine = engine()?;
+                    let module = Module::from_file(&engine, WASM_PATH)?;
+                    Ok::<_, anyhow::Error>((engine, module))
+                })
+                .unwrap_or_else(|e| panic!("Failed to load sandboxed UDFs: {:?}", e));
+
+            *sandbox = Some(
+                Sandbox::new(engine, module, limits)
+                    .unwrap_or_else(|e| panic!("Failed to create UDF sandbox: {:?}", e)),
+            );
+        }
+
+        let result = sandbox.as_mut().unwrap().call(name, input);
+        if result.is_err() {
+            // the instance may have been left in an inconsistent state
+            *sandbox = None;
+        }
+        result
+    });
+
+    let output = output.unwrap_or_else(|e| {
+        let name = name.trim_start_matches("__udf_");
+        panic!("UDF {} failed in its sandbox: {:?}", name, e)
+    });
+
+    bincode::decode_from_slice(&output, config::standard())
+        .unwrap()
+        .0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // a module with the same imports as a pipeline's, exporting a UDF that returns its (single)
+    // argument, and one that never returns
+    const MODULE_WAT: &str = r#"
+        (module
+          (import "env" "fetch_data" (func $fetch_data (param i32) (result i32)))
+          (import "env" "send" (func $send (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "__udf_identity") (param $len i32)
+            (drop (call $fetch_data (i32.const 0)))
+            (drop (call $send (i32.const 0) (local.get $len))))
+          (func (export "__udf_spin") (param $len i32)
+            (loop $l (br $l))))
+    "#;
+
+    fn sandbox(fuel: u64) -> Sandbox {
+        let engine = engine().unwrap();
+        let module = Module::new(&engine, MODULE_WAT).unwrap();
+        let limits = UdfSandbox {
+            memory_bytes: 1 << 20,
+            fuel,
+        };
+        Sandbox::new(&engine, &module, &limits).unwrap()
+    }
+
+    fn call_in<Args: Encode, Ret: Decode>(sandbox: &mut Sandbox, name: &str, args: Args) -> Ret {
+        let input = bincode::encode_to_vec(args, config::standard()).unwrap();
+        let output = sandbox.call(name, input).unwrap();
+        bincode::decode_from_slice(&output, config::standard())
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_sandboxed_call() {
+        let mut sandbox = sandbox(10_000);
+
+        let result: String = call_in(&mut sandbox, "__udf_identity", ("hello".to_string(),));
+        assert_eq!(result, "hello");
+
+        // fuel is reset for each call
+        for i in 0..1_000i64 {
+            let result: i64 = call_in(&mut sandbox, "__udf_identity", (i,));
+            assert_eq!(result, i);
+        }
+    }
+
+    #[test]
+    fn test_sandbox_fuel_limit() {
+        let mut sandbox = sandbox(10_000);
+
+        let input = bincode::encode_to_vec((1i64,), config::standard()).unwrap();
+        assert!(sandbox.call("__udf_spin", input).is_err());
+    }
+
+    #[test]
+    fn test_sandbox_memory_limit() {
+        let engine = engine().unwrap();
+        // asks for more memory than the sandbox allows
+        let module = Module::new(&engine, r#"(module (memory (export "memory") 32))"#).unwrap();
+        let limits = UdfSandbox {
+            memory_bytes: 1 << 20,
+            fuel: 10_000,
+        };
+        assert!(Sandbox::new(&engine, &module, &limits).is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_sandboxed_call() {
+        const CALLS: i64 = 1_000_000;
+
+        #[inline(never)]
+        fn identity(x: i64) -> i64 {
+            x
+        }
+
+        let start = Instant::now();
+        let mut sum = 0i64;
+        for i in 0..CALLS {
+            sum = sum.wrapping_add(identity(std::hint::black_box(i)));
+        }
+        let native = start.elapsed();
+        std::hint::black_box(sum);
+
+        let mut sandbox = sandbox(10_000);
+        let start = Instant::now();
+        for i in 0..CALLS {
+            let result: i64 = call_in(&mut sandbox, "__udf_identity", (i,));
+            sum = sum.wrapping_add(result);
+        }
+        let sandboxed = start.elapsed();
+        std::hint::black_box(sum);
+
+        println!(
+            "{} calls: native {:?} ({:?}/call), sandboxed {:?} ({:?}/call)",
+            CALLS,
+            native,
+            native / CALLS as u32,
+            sandboxed,
+            sandboxed / CALLS as u32
+        );
+    }
+}
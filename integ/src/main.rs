@@ -176,6 +176,13 @@ pub async fn main() {
                     parallelism: 1,
                     udfs: vec![],
                     preview: false,
+                    env: None,
+                    ordered: false,
+                    preserve_identifier_case: false,
+                    dependencies: vec![],
+                    instrumentation: None,
+                    environment: None,
+                    stop_at_event_time_micros: None,
                 },
             )),
         })
@@ -192,6 +199,9 @@ pub async fn main() {
             pipeline_id: pipeline_id.clone(),
             checkpoint_interval_micros: 2_000_000,
             preview: false,
+            env: None,
+            dependencies: vec![],
+            stop_at_event_time_micros: None,
         })
         .await
         .unwrap()
@@ -232,6 +242,14 @@ pub async fn main() {
             checkpoint_interval_micros: None,
             stop: Some(StopType::Checkpoint as i32),
             parallelism: None,
+            env: None,
+            source_overrides: vec![],
+            slo: None,
+            failure_policy: None,
+            queue_config: None,
+            stop_at_event_time_micros: None,
+            recovery_throttle: None,
+            state_storage: None,
         })
         .await
         .unwrap();